@@ -10,6 +10,12 @@ cfg_alloc! {
     pub fn ax_dealloc(ptr: NonNull<u8>, layout: Layout) {
         axalloc::global_allocator().dealloc(ptr, layout)
     }
+
+    pub use axalloc::MallocStats as AxMallocStats;
+
+    pub fn ax_malloc_stats() -> AxMallocStats {
+        axalloc::global_allocator().malloc_stats()
+    }
 }
 
 cfg_dma! {
@@ -16,6 +16,11 @@ cfg_display! {
     pub use display::*;
 }
 
+cfg_sound! {
+    mod sound;
+    pub use sound::*;
+}
+
 mod stdio {
     use core::fmt;
 
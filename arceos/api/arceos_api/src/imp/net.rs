@@ -56,6 +56,13 @@ pub fn ax_tcp_recv(socket: &AxTcpSocketHandle, buf: &mut [u8]) -> AxResult<usize
     socket.0.recv(buf)
 }
 
+pub fn ax_tcp_recv_with(
+    socket: &AxTcpSocketHandle,
+    f: &mut dyn FnMut(&[u8]) -> usize,
+) -> AxResult<Option<usize>> {
+    socket.0.recv_with(f)
+}
+
 pub fn ax_tcp_poll(socket: &AxTcpSocketHandle) -> AxResult<AxPollState> {
     socket.0.poll()
 }
@@ -0,0 +1,23 @@
+use axerrno::AxResult;
+use axsound::PcmStream;
+
+pub use axsound::{PcmFormat as AxPcmFormat, PcmParams as AxPcmParams};
+
+/// A handle to a PCM playback stream.
+pub struct AxPcmHandle(PcmStream);
+
+pub fn ax_sound_output_streams() -> usize {
+    axsound::num_output_streams()
+}
+
+pub fn ax_pcm_open(stream: usize, params: AxPcmParams) -> AxResult<AxPcmHandle> {
+    PcmStream::open(stream, params).map(AxPcmHandle)
+}
+
+pub fn ax_pcm_write(handle: &mut AxPcmHandle, buf: &[u8]) -> AxResult<usize> {
+    handle.0.write(buf)
+}
+
+pub fn ax_pcm_drain(handle: &mut AxPcmHandle) -> AxResult {
+    handle.0.drain()
+}
@@ -39,6 +39,20 @@ cfg_task! {
         }
     }
 
+    /// The information of a task, see [`ax_task_list`].
+    #[derive(Debug, Clone)]
+    pub struct AxTaskInfo {
+        /// The task ID.
+        pub id: u64,
+        /// The name of the task, unique among the tasks.
+        pub name: alloc::string::String,
+        /// The tag of the task.
+        pub tag: Option<alloc::string::String>,
+        /// The state of the task: `"running"`, `"ready"`, `"blocked"` or
+        /// `"exited"`.
+        pub state: &'static str,
+    }
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -82,6 +96,57 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_foreground(foreground: bool) {
+        let curr = axtask::current();
+        if foreground {
+            axtask::set_foreground(Some(curr.as_task_ref()));
+        } else if axtask::foreground()
+            .is_some_and(|t| axtask::AxTaskRef::ptr_eq(curr.as_task_ref(), &t))
+        {
+            axtask::set_foreground(None);
+        }
+    }
+
+    pub fn ax_take_cancel_request() -> bool {
+        axtask::current().take_cancel_request()
+    }
+
+    pub fn ax_sched_policies() -> alloc::vec::Vec<&'static str> {
+        axtask::sched_policies()
+    }
+
+    pub fn ax_sched_policy() -> &'static str {
+        axtask::sched_policy()
+    }
+
+    pub fn ax_set_sched_policy(name: &str) -> crate::AxResult {
+        if axtask::set_sched_policy(name) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(NotFound, "ax_set_sched_policy: unknown policy")
+        }
+    }
+
+    pub fn ax_set_current_name(name: alloc::string::String) -> alloc::string::String {
+        axtask::current().set_name(name)
+    }
+
+    pub fn ax_set_current_tag(tag: Option<alloc::string::String>) {
+        axtask::current().set_tag(tag);
+    }
+
+    pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo> {
+        axtask::tasks()
+            .iter()
+            .map(|t| AxTaskInfo {
+                id: t.id().as_u64(),
+                name: t.name(),
+                tag: t.tag(),
+                state: t.state_name(),
+            })
+            .collect()
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
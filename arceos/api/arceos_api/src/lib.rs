@@ -75,6 +75,18 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api! {
+        @cfg "alloc";
+        /// Returns the statistics of the byte allocations by size class, with
+        /// the fragmentation of the heap. It is printed like `malloc_stats()`.
+        pub fn ax_malloc_stats() -> AxMallocStats;
+    }
+
+    define_api_type! {
+        @cfg "alloc";
+        pub type AxMallocStats;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
@@ -120,6 +132,7 @@ pub mod task {
     define_api_type! {
         @cfg "multitask";
         pub type AxTaskHandle;
+        pub type AxTaskInfo;
         pub type AxWaitQueueHandle;
     }
 
@@ -155,6 +168,27 @@ pub mod task {
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Renames the current task, returns the name given to it, with a
+        /// suffix if another task has the same name.
+        pub fn ax_set_current_name(name: alloc::string::String) -> alloc::string::String;
+        /// Sets or clears the tag of the current task, a free text describing it.
+        pub fn ax_set_current_tag(tag: Option<alloc::string::String>);
+        /// Returns the information of the tasks alive, ordered by their IDs.
+        pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo>;
+        /// Makes the current task the foreground one, that Ctrl-C on the
+        /// console requests to cancel, or no longer the foreground one.
+        pub fn ax_set_current_foreground(foreground: bool);
+        /// Returns whether Ctrl-C requested to cancel the current task since
+        /// the last call.
+        pub fn ax_take_cancel_request() -> bool;
+        /// Returns the names of the scheduling policies, in the order they
+        /// were registered.
+        pub fn ax_sched_policies() -> alloc::vec::Vec<&'static str>;
+        /// Returns the name of the active scheduling policy.
+        pub fn ax_sched_policy() -> &'static str;
+        /// Switches to another scheduling policy, the ready tasks moving to
+        /// it.
+        pub fn ax_set_sched_policy(name: &str) -> crate::AxResult;
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -299,6 +333,13 @@ pub mod net {
         /// Receives data on the TCP socket, and stores it in the given buffer.
         /// On success, returns the number of bytes read.
         pub fn ax_tcp_recv(socket: &AxTcpSocketHandle, buf: &mut [u8]) -> AxResult<usize>;
+        /// Receives data on the TCP socket without copying, by passing the
+        /// readable part of the receive buffer to `f`, which returns how many
+        /// bytes it consumed. On success, returns the number of bytes consumed,
+        /// or `None` if the connection is closed.
+        ///
+        /// `f` runs with the sockets locked, and must not use any of them.
+        pub fn ax_tcp_recv_with(socket: &AxTcpSocketHandle, f: &mut dyn FnMut(&[u8]) -> usize) -> AxResult<Option<usize>>;
         /// Returns whether the TCP socket is readable or writable.
         pub fn ax_tcp_poll(socket: &AxTcpSocketHandle) -> AxResult<AxPollState>;
         /// Closes the connection on the TCP socket.
@@ -368,6 +409,35 @@ pub mod display {
     }
 }
 
+/// PCM playback on the sound device.
+pub mod sound {
+    use crate::AxResult;
+
+    define_api_type! {
+        @cfg "sound";
+        pub type AxPcmFormat;
+        pub type AxPcmParams;
+        pub type AxPcmHandle;
+    }
+
+    define_api! {
+        @cfg "sound";
+        /// Returns the number of output streams of the sound device.
+        pub fn ax_sound_output_streams() -> usize;
+        /// Opens the output stream `stream` with the given parameters.
+        ///
+        /// The stream is closed when the handle is dropped, without waiting
+        /// for the queued frames.
+        pub fn ax_pcm_open(stream: usize, params: AxPcmParams) -> AxResult<AxPcmHandle>;
+        /// Queues the frames in the given buffer for the playback, waiting
+        /// for room for them. Returns the number of bytes queued, the length
+        /// of the buffer without the last partial frame.
+        pub fn ax_pcm_write(handle: &mut AxPcmHandle, buf: &[u8]) -> AxResult<usize>;
+        /// Waits until all the queued frames have been played.
+        pub fn ax_pcm_drain(handle: &mut AxPcmHandle) -> AxResult;
+    }
+}
+
 /// Input/output operations.
 pub mod io {
     define_api_type! {
@@ -392,7 +462,12 @@ pub mod modules {
     pub use axdisplay;
     #[cfg(feature = "dma")]
     pub use axdma;
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "sound"
+    ))]
     pub use axdriver;
     #[cfg(feature = "fs")]
     pub use axfs;
@@ -400,6 +475,8 @@ pub mod modules {
     pub use axmm;
     #[cfg(feature = "net")]
     pub use axnet;
+    #[cfg(feature = "sound")]
+    pub use axsound;
     #[cfg(feature = "multitask")]
     pub use axtask;
 }
@@ -44,6 +44,12 @@ typedef struct {{
             "sock.*",
             "fd_set",
             "timeval",
+            "itimerspec",
+            "sigset_t",
+            "signalfd_siginfo",
+            "sigevent",
+            "timer_t",
+            "tcp_info",
             "pthread_t",
             "pthread_attr_t",
             "pthread_mutex_t",
@@ -53,21 +59,47 @@ typedef struct {{
             "clockid_t",
             "rlimit",
             "aibuf",
+            "ifreq",
+            "ethtool_.*",
         ];
         let allow_vars = [
             "CLOCK_.*",
             "O_.*",
+            "AT_.*",
+            "UTIME_.*",
             "AF_.*",
             "SOCK_.*",
             "IPPROTO_.*",
+            "TCP_.*",
             "FD_.*",
             "F_.*",
+            "FIO.*",
             "_SC_.*",
             "EPOLL_CTL_.*",
             "EPOLL.*",
+            "EFD_.*",
+            "TFD_.*",
+            "SFD_.*",
+            "SIG[A-Z]+",
+            "SI_KERNEL",
+            "SI_TIMER",
+            "SIGEV_.*",
+            "TIMER_ABSTIME",
             "RLIMIT_.*",
             "EAI_.*",
             "MAXADDRS",
+            "VMADDR_.*",
+            "SIOC.*",
+            "IFF_.*",
+            "IFNAMSIZ",
+            "ETHTOOL_.*",
+            "ETH_GSTRING_LEN",
+            "ETH_SS_.*",
+            "SPEED_.*",
+            "DUPLEX_.*",
+            "PORT_.*",
+            "XCVR_.*",
+            "AUTONEG_.*",
         ];
 
         #[derive(Debug)]
@@ -0,0 +1,239 @@
+//! `eventfd` implementation.
+//!
+//! An [`EventFd`] is a 64-bit counter shared by the writers signaling events
+//! and the readers waiting for them. Kernel tasks and drivers can hold the
+//! same object as the C apps through an [`Arc`], signal it even in the
+//! interrupt context, and the apps wait on its file descriptor with `read`,
+//! `select` or `epoll`. The kernel tasks running an async executor await it
+//! with [`EventFd::wait_async`] instead of blocking.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ffi::{c_int, c_uint};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+
+use super::fd_ops::{add_file_like, get_file_like, FileLike};
+use crate::ctypes;
+
+/// The largest value of the counter.
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+pub struct EventFd {
+    count: AtomicU64,
+    semaphore: bool,
+    nonblocking: AtomicBool,
+    /// Where the readers wait for the counter to be non-zero, and the
+    /// writers for room in it.
+    wq: WaitQueue,
+    /// The wakers of the futures of [`EventFd::wait_async`].
+    wakers: SpinNoIrq<Vec<Waker>>,
+}
+
+impl EventFd {
+    /// Creates an event object with the counter set to `initval`.
+    ///
+    /// In the semaphore mode, a read takes 1 from the counter instead of
+    /// resetting it.
+    pub const fn new(initval: u64, semaphore: bool) -> Self {
+        Self {
+            count: AtomicU64::new(initval),
+            semaphore,
+            nonblocking: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+            wakers: SpinNoIrq::new(Vec::new()),
+        }
+    }
+
+    /// Gets the event object of the file descriptor `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<EventFd>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Adds the event object to the file descriptor table, returns the new
+    /// file descriptor.
+    pub fn add_to_fd_table(self: &Arc<Self>) -> LinuxResult<c_int> {
+        add_file_like(self.clone())
+    }
+
+    /// Adds `n` to the counter without blocking.
+    ///
+    /// Returns [`LinuxError::EAGAIN`] if the counter would exceed its
+    /// maximum. It never blocks, so it can be called in the interrupt context.
+    /// The tasks and the futures waiting for the counter are woken.
+    pub fn signal(&self, n: u64) -> LinuxResult {
+        if n == u64::MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_add(n).filter(|&c| c <= MAX_COUNT)
+            })
+            .map_err(|_| LinuxError::EAGAIN)?;
+        let wakers = core::mem::take(&mut *self.wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+        self.wq.notify_all(false);
+        Ok(())
+    }
+
+    /// Takes the value of the counter without blocking, or 1 in the
+    /// semaphore mode.
+    ///
+    /// Returns [`LinuxError::EAGAIN`] if the counter is zero.
+    pub fn try_wait(&self) -> LinuxResult<u64> {
+        let count = self
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| match count {
+                0 => None,
+                _ if self.semaphore => Some(count - 1),
+                _ => Some(0),
+            })
+            .map_err(|_| LinuxError::EAGAIN)?;
+        // Room for the writers waiting.
+        self.wq.notify_all(false);
+        Ok(if self.semaphore { 1 } else { count })
+    }
+
+    /// Waits until the counter is non-zero, then takes it as
+    /// [`EventFd::try_wait`].
+    pub fn wait(&self) -> u64 {
+        loop {
+            if let Ok(value) = self.try_wait() {
+                return value;
+            }
+            self.wq
+                .wait_until(|| self.count.load(Ordering::Acquire) != 0);
+        }
+    }
+
+    /// Takes the counter as [`EventFd::try_wait`] if it is non-zero, or
+    /// registers the waker of `cx` to be woken by the next signal.
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<u64> {
+        if let Ok(value) = self.try_wait() {
+            return Poll::Ready(value);
+        }
+        {
+            let mut wakers = self.wakers.lock();
+            if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        // Signaled before the waker was registered.
+        match self.try_wait() {
+            Ok(value) => Poll::Ready(value),
+            Err(_) => Poll::Pending,
+        }
+    }
+
+    /// Waits as [`EventFd::wait`] in a future, polled by
+    /// [`EventFd::poll_wait`].
+    pub fn wait_async(&self) -> EventWait<'_> {
+        EventWait { event: self }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+}
+
+/// The future of [`EventFd::wait_async`], resolving to the value taken from
+/// the counter.
+pub struct EventWait<'a> {
+    event: &'a EventFd,
+}
+
+impl Future for EventWait<'_> {
+    type Output = u64;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        self.event.poll_wait(cx)
+    }
+}
+
+impl FileLike for EventFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = if self.is_nonblocking() {
+            self.try_wait()?
+        } else {
+            self.wait()
+        };
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LinuxError::EINVAL);
+        }
+        let n = u64::from_ne_bytes(buf[..8].try_into().unwrap());
+        loop {
+            match self.signal(n) {
+                Err(LinuxError::EAGAIN) if !self.is_nonblocking() => {
+                    // Counter is full, wait for readers to take it
+                    self.wq
+                        .wait_until(|| self.count.load(Ordering::Acquire) <= MAX_COUNT - n);
+                }
+                res => return res.map(|_| 8),
+            }
+        }
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600u32, // rw-------
+            st_uid: 1000,
+            st_gid: 1000,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let count = self.count.load(Ordering::Acquire);
+        Ok(PollState {
+            readable: count > 0,
+            writable: count < MAX_COUNT,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Create a file descriptor for event notification
+///
+/// Return the new file descriptor if succeed
+pub fn sys_eventfd(initval: c_uint, flags: c_int) -> c_int {
+    debug!("sys_eventfd <= {} {:#x}", initval, flags);
+    syscall_body!(sys_eventfd, {
+        let known = ctypes::EFD_SEMAPHORE | ctypes::EFD_NONBLOCK | ctypes::EFD_CLOEXEC;
+        if flags as u32 & !known != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let event = EventFd::new(initval as u64, flags as u32 & ctypes::EFD_SEMAPHORE != 0);
+        event.set_nonblocking(flags as u32 & ctypes::EFD_NONBLOCK != 0)?;
+        add_file_like(Arc::new(event))
+    })
+}
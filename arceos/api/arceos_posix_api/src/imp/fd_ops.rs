@@ -19,6 +19,15 @@ pub trait FileLike: Send + Sync {
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync>;
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
+
+    /// Performs the ioctl `cmd`, only device files have commands.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be valid for the argument of the command.
+    unsafe fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<usize> {
+        Err(LinuxError::ENOTTY)
+    }
 }
 
 lazy_static::lazy_static! {
@@ -127,3 +136,22 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
         }
     })
 }
+
+/// Manipulate the underlying device parameters of special files.
+///
+/// `FIONBIO` is handled for all the files, the other commands by the handlers
+/// of the device files, see [`axfs::ioctl`], and the ones of the network
+/// interfaces by the sockets.
+pub fn sys_ioctl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
+    debug!("sys_ioctl <= fd: {} cmd: {:#x} arg: {:#x}", fd, cmd, arg);
+    syscall_body!(sys_ioctl, {
+        let f = get_file_like(fd)?;
+        if cmd as u32 == ctypes::FIONBIO {
+            crate::utils::check_null_ptr(arg as *const c_int)?;
+            f.set_nonblocking(unsafe { *(arg as *const c_int) } != 0)?;
+            return Ok(0);
+        }
+        let ret = unsafe { f.ioctl(cmd as u32, arg)? };
+        Ok(ret as c_int)
+    })
+}
@@ -1,14 +1,43 @@
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::ffi::{c_char, c_int};
+use core::time::Duration;
 
-use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axerror::ResultExt;
+use axfs::fops::{Advice, FileAttr, FileTimes, OpenOptions, XattrSet};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
 use super::fd_ops::{get_file_like, FileLike};
 use crate::{ctypes, utils::char_ptr_to_str};
 
+/// Don't overwrite the target of [`sys_renameat2`].
+const RENAME_NOREPLACE: u32 = 1;
+
+/// Don't change the file size in [`sys_fallocate`].
+const FALLOC_FL_KEEP_SIZE: c_int = 1;
+/// Deallocate the range in [`sys_fallocate`].
+const FALLOC_FL_PUNCH_HOLE: c_int = 2;
+
+/// The advices of [`sys_posix_fadvise`].
+const POSIX_FADV_NORMAL: c_int = 0;
+const POSIX_FADV_RANDOM: c_int = 1;
+const POSIX_FADV_SEQUENTIAL: c_int = 2;
+const POSIX_FADV_WILLNEED: c_int = 3;
+const POSIX_FADV_DONTNEED: c_int = 4;
+const POSIX_FADV_NOREUSE: c_int = 5;
+
+/// Fail if the extended attribute exists.
+const XATTR_CREATE: c_int = 1;
+/// Fail if the extended attribute does not exist.
+const XATTR_REPLACE: c_int = 2;
+/// Maximum length of an extended attribute name.
+const XATTR_NAME_MAX: usize = 255;
+/// Maximum size of an extended attribute value.
+const XATTR_SIZE_MAX: usize = 65536;
+
 pub struct File {
     inner: Mutex<axfs::fops::File>,
 }
@@ -24,12 +53,24 @@ impl File {
         super::fd_ops::add_file_like(Arc::new(self))
     }
 
-    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+    pub(crate) fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
         let f = super::fd_ops::get_file_like(fd)?;
         f.into_any()
             .downcast::<Self>()
             .map_err(|_| LinuxError::EINVAL)
     }
+
+    /// Reads at `offset`, without changing the file position.
+    #[cfg(feature = "net")]
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> axerrno::AxResult<usize> {
+        self.inner.lock().read_at(offset, buf)
+    }
+
+    /// Moves the file position, returns the new one.
+    #[cfg(feature = "net")]
+    pub(crate) fn seek(&self, pos: SeekFrom) -> axerrno::AxResult<u64> {
+        self.inner.lock().seek(pos)
+    }
 }
 
 impl FileLike for File {
@@ -42,21 +83,8 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        let inner = self.inner.lock();
+        Ok(attr_to_stat(inner.get_attr()?, inner.get_times()?))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -73,12 +101,149 @@ impl FileLike for File {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    unsafe fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<usize> {
+        Ok(self
+            .inner
+            .lock()
+            .ioctl(cmd, arg)
+            .errno_for(AxError::Unsupported, LinuxError::ENOTTY)?)
+    }
+}
+
+/// A directory opened as a file descriptor, used as the base of the `*at()`
+/// family of syscalls.
+pub struct Directory {
+    inner: Mutex<axfs::fops::Directory>,
+    /// Canonical absolute path of the directory, ending with `/`.
+    path: String,
+}
+
+impl Directory {
+    fn open(path: String) -> LinuxResult<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        let inner = axfs::fops::Directory::open_dir(&path, &options)?;
+        let path = if path.ends_with('/') {
+            path
+        } else {
+            path + "/"
+        };
+        Ok(Self {
+            inner: Mutex::new(inner),
+            path,
+        })
+    }
+
+    fn add_to_fd_table(self) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like(Arc::new(self))
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        let f = super::fd_ops::get_file_like(fd)?;
+        f.into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::ENOTDIR)
+    }
+
+    /// Returns the absolute path of the directory.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl FileLike for Directory {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let inner = self.inner.lock();
+        Ok(attr_to_stat(inner.get_attr()?, inner.get_times()?))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+fn attr_to_stat(metadata: FileAttr, times: FileTimes) -> ctypes::stat {
+    let ty = metadata.file_type() as u8;
+    let perm = metadata.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        st_ino: 1,
+        st_nlink: 1,
+        st_mode,
+        st_uid: 1000,
+        st_gid: 1000,
+        st_size: metadata.size() as _,
+        st_blocks: metadata.blocks() as _,
+        st_blksize: 512,
+        st_atim: times.atime.into(),
+        st_mtim: times.mtime.into(),
+        st_ctim: times.ctime.into(),
+        ..Default::default()
+    }
+}
+
+/// Resolves `path` relative to the directory referred to by `dirfd` (or the
+/// current directory if it is `AT_FDCWD`) to a canonical absolute path.
+///
+/// `.` and `..` are resolved lexically from the absolute path of the
+/// directory, so `..` can never escape above the root, and works the same
+/// across mount points.
+fn resolve_at(dirfd: c_int, path: &str) -> LinuxResult<String> {
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    if path.starts_with('/') || dirfd == ctypes::AT_FDCWD {
+        return Ok(axfs::api::canonicalize(path)?);
+    }
+    let dir = Directory::from_fd(dirfd)?;
+    Ok(axfs::api::canonicalize(&(String::from(dir.path()) + path))?)
+}
+
+fn stat_path(path: &str) -> LinuxResult<ctypes::stat> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    let file = axfs::fops::File::open(path, &options)?;
+    Ok(attr_to_stat(file.get_attr()?, file.get_times()?))
+}
+
+/// Converts a time of `utimensat`, `None` if to be left unchanged.
+fn utime_to_duration(ts: &ctypes::timespec) -> LinuxResult<Option<Duration>> {
+    if ts.tv_nsec == ctypes::UTIME_OMIT as _ {
+        Ok(None)
+    } else if ts.tv_nsec == ctypes::UTIME_NOW as _ {
+        Ok(Some(axhal::time::wall_time()))
+    } else if (0..1_000_000_000).contains(&ts.tv_nsec) && ts.tv_sec >= 0 {
+        Ok(Some((*ts).into()))
+    } else {
+        Err(LinuxError::EINVAL)
+    }
 }
 
 /// Convert open flags to [`OpenOptions`].
-fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
+fn flags_to_options(flags: c_int, mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
     let mut options = OpenOptions::new();
+    options.mode(mode as _);
     match flags & 0b11 {
         ctypes::O_RDONLY => options.read(true),
         ctypes::O_WRONLY => options.write(true),
@@ -99,6 +264,9 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     if flags & ctypes::O_EXEC != 0 {
         options.create_new(true);
     }
+    if flags & ctypes::O_DIRECT != 0 {
+        options.direct(true);
+    }
     options
 }
 
@@ -107,11 +275,40 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
 /// Return its index in the file table (`fd`). Return `EMFILE` if it already
 /// has the maximum number of files open.
 pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) -> c_int {
+    sys_openat(ctypes::AT_FDCWD, filename, flags, mode)
+}
+
+/// Open a file by `filename` relative to the directory `dirfd` and insert it
+/// into the file descriptor table.
+///
+/// Directories are opened as directory file descriptors, which can be used as
+/// `dirfd` of the `*at()` syscalls. `O_DIRECTORY` requires `filename` to be a
+/// directory.
+///
+/// Return its index in the file table (`fd`).
+pub fn sys_openat(
+    dirfd: c_int,
+    filename: *const c_char,
+    flags: c_int,
+    mode: ctypes::mode_t,
+) -> c_int {
     let filename = char_ptr_to_str(filename);
-    debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
-    syscall_body!(sys_open, {
+    debug!(
+        "sys_openat <= {} {:?} {:#o} {:#o}",
+        dirfd, filename, flags, mode
+    );
+    syscall_body!(sys_openat, {
+        let path = resolve_at(dirfd, filename?)?;
+        let uflags = flags as u32;
+        let is_dir = axfs::api::metadata(&path).is_ok_and(|m| m.is_dir());
+        if is_dir || uflags & ctypes::O_DIRECTORY != 0 {
+            if is_dir && (uflags & 0b11 != ctypes::O_RDONLY || uflags & ctypes::O_CREAT != 0) {
+                return Err(LinuxError::EISDIR);
+            }
+            return Directory::open(path)?.add_to_fd_table();
+        }
         let options = flags_to_options(flags, mode);
-        let file = axfs::fops::File::open(filename?, &options)?;
+        let file = axfs::fops::File::open(&path, &options)?;
         File::new(file).add_to_fd_table()
     })
 }
@@ -122,17 +319,89 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
 pub fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> ctypes::off_t {
     debug!("sys_lseek <= {} {} {}", fd, offset, whence);
     syscall_body!(sys_lseek, {
+        let file = File::from_fd(fd)?;
+        let mut file = file.inner.lock();
         let pos = match whence {
             0 => SeekFrom::Start(offset as _),
             1 => SeekFrom::Current(offset as _),
             2 => SeekFrom::End(offset as _),
+            // SEEK_DATA, SEEK_HOLE
+            3 | 4 => {
+                if offset < 0 {
+                    return Err(LinuxError::ENXIO);
+                }
+                let off = if whence == 3 {
+                    file.seek_data(offset as _)?
+                } else {
+                    file.seek_hole(offset as _)?
+                };
+                return off.ok_or(LinuxError::ENXIO);
+            }
             _ => return Err(LinuxError::EINVAL),
         };
-        let off = File::from_fd(fd)?.inner.lock().seek(pos)?;
+        let off = file.seek(pos)?;
         Ok(off)
     })
 }
 
+/// Manipulate the storage of `len` bytes from `offset` of the file `fd`.
+///
+/// With no `mode`, the storage is allocated and the file extended if needed.
+/// `FALLOC_FL_KEEP_SIZE` leaves the size unchanged, and
+/// `FALLOC_FL_PUNCH_HOLE` (only with `FALLOC_FL_KEEP_SIZE`) deallocates
+/// the range, which then reads as zeros.
+///
+/// Return 0 if success.
+pub fn sys_fallocate(fd: c_int, mode: c_int, offset: ctypes::off_t, len: ctypes::off_t) -> c_int {
+    debug!("sys_fallocate <= {} {:#x} {} {}", fd, mode, offset, len);
+    syscall_body!(sys_fallocate, {
+        if offset < 0 || len <= 0 || offset.checked_add(len).is_none() {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = File::from_fd(fd)?;
+        let file = file.inner.lock();
+        let keep_size = mode & FALLOC_FL_KEEP_SIZE != 0;
+        match mode & !FALLOC_FL_KEEP_SIZE {
+            0 => file.allocate(offset as _, len as _, keep_size)?,
+            FALLOC_FL_PUNCH_HOLE if keep_size => file.punch_hole(offset as _, len as _)?,
+            _ => return Err(LinuxError::EOPNOTSUPP),
+        }
+        Ok(0)
+    })
+}
+
+/// Give the access pattern of `len` bytes from `offset` of the file `fd`, to
+/// the end of the file if `len` is 0, to tune its readahead and caching.
+///
+/// `POSIX_FADV_NOREUSE` is accepted and does nothing.
+///
+/// Return 0 if success.
+pub fn sys_posix_fadvise(
+    fd: c_int,
+    offset: ctypes::off_t,
+    len: ctypes::off_t,
+    advice: c_int,
+) -> c_int {
+    debug!("sys_posix_fadvise <= {} {} {} {}", fd, offset, len, advice);
+    syscall_body!(sys_posix_fadvise, {
+        if offset < 0 || len < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let advice = match advice {
+            POSIX_FADV_NORMAL => Advice::Normal,
+            POSIX_FADV_RANDOM => Advice::Random,
+            POSIX_FADV_SEQUENTIAL => Advice::Sequential,
+            POSIX_FADV_WILLNEED => Advice::WillNeed,
+            POSIX_FADV_DONTNEED => Advice::DontNeed,
+            POSIX_FADV_NOREUSE => return Ok(0),
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let file = File::from_fd(fd)?;
+        file.inner.lock().advise(offset as _, len as _, advice)?;
+        Ok(0)
+    })
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -143,11 +412,7 @@ pub unsafe fn sys_stat(path: *const c_char, buf: *mut ctypes::stat) -> c_int {
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        let mut options = OpenOptions::new();
-        options.read(true);
-        let file = axfs::fops::File::open(path?, &options)?;
-        let st = File::new(file).stat()?;
-        unsafe { *buf = st };
+        unsafe { *buf = stat_path(path?)? };
         Ok(0)
     })
 }
@@ -215,3 +480,495 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         Ok(0)
     })
 }
+
+/// Get the file metadata by `path` relative to the directory `dirfd` and
+/// write into `buf`.
+///
+/// With `AT_EMPTY_PATH` and an empty `path`, get the metadata of `dirfd`
+/// itself. There are no symbolic links, so `AT_SYMLINK_NOFOLLOW` is ignored.
+///
+/// Return 0 if success.
+pub unsafe fn sys_fstatat(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut ctypes::stat,
+    flags: c_int,
+) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!(
+        "sys_fstatat <= {} {:?} {:#x} {:#x}",
+        dirfd, path, buf as usize, flags
+    );
+    syscall_body!(sys_fstatat, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let flags = flags as u32;
+        if flags & !(ctypes::AT_EMPTY_PATH | ctypes::AT_SYMLINK_NOFOLLOW) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = path?;
+        let st = if path.is_empty() && flags & ctypes::AT_EMPTY_PATH != 0 {
+            if dirfd == ctypes::AT_FDCWD {
+                stat_path(&axfs::api::current_dir()?)?
+            } else {
+                get_file_like(dirfd)?.stat()?
+            }
+        } else {
+            stat_path(&resolve_at(dirfd, path)?)?
+        };
+        unsafe { *buf = st };
+        Ok(0)
+    })
+}
+
+/// Create a directory by `path` relative to the directory `dirfd`.
+///
+/// Return 0 if success.
+pub fn sys_mkdirat(dirfd: c_int, path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_mkdirat <= {} {:?} {:#o}", dirfd, path, mode);
+    syscall_body!(sys_mkdirat, {
+        axfs::api::DirBuilder::new()
+            .mode(mode as _)
+            .create(&resolve_at(dirfd, path?)?)?;
+        Ok(0)
+    })
+}
+
+/// Remove the file, or the empty directory with `AT_REMOVEDIR`, by `path`
+/// relative to the directory `dirfd`.
+///
+/// Return 0 if success.
+pub fn sys_unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_unlinkat <= {} {:?} {:#x}", dirfd, path, flags);
+    syscall_body!(sys_unlinkat, {
+        let flags = flags as u32;
+        if flags & !ctypes::AT_REMOVEDIR != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = resolve_at(dirfd, path?)?;
+        if flags & ctypes::AT_REMOVEDIR != 0 {
+            axfs::api::remove_dir(&path)?;
+        } else {
+            axfs::api::remove_file(&path)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Rename `old` relative to the directory `olddirfd` to `new` relative to the
+/// directory `newdirfd`.
+///
+/// Return 0 if success.
+pub fn sys_renameat(
+    olddirfd: c_int,
+    old: *const c_char,
+    newdirfd: c_int,
+    new: *const c_char,
+) -> c_int {
+    sys_renameat2(olddirfd, old, newdirfd, new, 0)
+}
+
+/// Like [`sys_renameat`], with `flags`. Only `RENAME_NOREPLACE` is supported.
+///
+/// Return 0 if success.
+pub fn sys_renameat2(
+    olddirfd: c_int,
+    old: *const c_char,
+    newdirfd: c_int,
+    new: *const c_char,
+    flags: c_int,
+) -> c_int {
+    syscall_body!(sys_renameat2, {
+        let old_path = resolve_at(olddirfd, char_ptr_to_str(old)?)?;
+        let new_path = resolve_at(newdirfd, char_ptr_to_str(new)?)?;
+        debug!(
+            "sys_renameat2 <= old: {:?}, new: {:?}, flags: {:#x}",
+            old_path, new_path, flags
+        );
+        let flags = flags as u32;
+        if flags & !RENAME_NOREPLACE != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags & RENAME_NOREPLACE != 0 && axfs::api::metadata(&new_path).is_ok() {
+            return Err(LinuxError::EEXIST);
+        }
+        axfs::api::rename(&old_path, &new_path)?;
+        Ok(0)
+    })
+}
+
+/// Create a hard link `new` relative to the directory `newdirfd` to `old`
+/// relative to the directory `olddirfd`.
+///
+/// The paths are checked, but the filesystems do not support hard links, so
+/// it always fails with `EPERM` if they are valid.
+pub fn sys_linkat(
+    olddirfd: c_int,
+    old: *const c_char,
+    newdirfd: c_int,
+    new: *const c_char,
+    flags: c_int,
+) -> c_int {
+    syscall_body!(sys_linkat, {
+        let old_path = resolve_at(olddirfd, char_ptr_to_str(old)?)?;
+        let new_path = resolve_at(newdirfd, char_ptr_to_str(new)?)?;
+        debug!(
+            "sys_linkat <= old: {:?}, new: {:?}, flags: {:#x}",
+            old_path, new_path, flags
+        );
+        if flags as u32 & !ctypes::AT_SYMLINK_FOLLOW != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        axfs::api::metadata(&old_path)?;
+        if axfs::api::metadata(&new_path).is_ok() {
+            return Err(LinuxError::EEXIST);
+        }
+        Err::<c_int, _>(LinuxError::EPERM)
+    })
+}
+
+/// Change the current directory to the directory referred to by `fd`.
+///
+/// Return 0 if success.
+pub fn sys_fchdir(fd: c_int) -> c_int {
+    debug!("sys_fchdir <= {}", fd);
+    syscall_body!(sys_fchdir, {
+        axfs::api::set_current_dir(Directory::from_fd(fd)?.path())?;
+        Ok(0)
+    })
+}
+
+/// Set the file mode creation mask of the current task to `mask & 0o777`.
+///
+/// Return the previous mask.
+pub fn sys_umask(mask: ctypes::mode_t) -> ctypes::mode_t {
+    debug!("sys_umask <= {:#o}", mask);
+    axfs::api::set_umask(mask as _) as _
+}
+
+/// Set the access and modification times of the file by `path` relative to
+/// the directory `dirfd`, or of `dirfd` itself if `path` is null.
+///
+/// `times` points to the access and modification times, either can be
+/// `UTIME_NOW` or `UTIME_OMIT`. Both are set to now if `times` is null.
+///
+/// Return 0 if success.
+pub unsafe fn sys_utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const ctypes::timespec,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_utimensat <= {} {:#x} {:#x} {:#x}",
+        dirfd, path as usize, times as usize, flags
+    );
+    syscall_body!(sys_utimensat, {
+        if flags as u32 & !ctypes::AT_SYMLINK_NOFOLLOW != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let (atime, mtime) = if times.is_null() {
+            let now = Some(axhal::time::wall_time());
+            (now, now)
+        } else {
+            let times = unsafe { core::slice::from_raw_parts(times, 2) };
+            (utime_to_duration(&times[0])?, utime_to_duration(&times[1])?)
+        };
+        if path.is_null() {
+            let f = get_file_like(dirfd)?.into_any();
+            if let Some(file) = f.downcast_ref::<File>() {
+                file.inner.lock().set_times(atime, mtime)?;
+            } else if let Some(dir) = f.downcast_ref::<Directory>() {
+                dir.inner.lock().set_times(atime, mtime)?;
+            } else {
+                return Err(LinuxError::EINVAL);
+            }
+        } else {
+            let path = resolve_at(dirfd, char_ptr_to_str(path)?)?;
+            axfs::api::set_times(&path, atime, mtime)?;
+        }
+        Ok(0)
+    })
+}
+
+/// The file or directory an extended attribute syscall operates on.
+enum XattrNode {
+    Path(String),
+    File(Arc<File>),
+    Dir(Arc<Directory>),
+}
+
+impl XattrNode {
+    fn from_path(path: *const c_char) -> LinuxResult<Self> {
+        let path = resolve_at(ctypes::AT_FDCWD, char_ptr_to_str(path)?)?;
+        // Fail with `ENOENT` here, `NotFound` then means a missing attribute.
+        axfs::api::metadata(&path)?;
+        Ok(Self::Path(path))
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Self> {
+        let f = get_file_like(fd)?.into_any();
+        match f.clone().downcast::<File>() {
+            Ok(file) => Ok(Self::File(file)),
+            Err(_) => match f.downcast::<Directory>() {
+                Ok(dir) => Ok(Self::Dir(dir)),
+                Err(_) => Err(LinuxError::EOPNOTSUPP),
+            },
+        }
+    }
+
+    fn get(&self, name: &str) -> axerror::Result<Vec<u8>> {
+        let value = match self {
+            Self::Path(path) => axfs::api::get_xattr(path, name),
+            Self::File(f) => f.inner.lock().get_xattr(name),
+            Self::Dir(d) => d.inner.lock().get_xattr(name),
+        }
+        .errno_for(AxError::NotFound, LinuxError::ENODATA)
+        .context("get xattr")?;
+        value.ok_or_else(|| axerror::Error::from_errno(LinuxError::ENODATA))
+    }
+
+    fn set(&self, name: &str, value: &[u8], flags: c_int) -> axerror::Result {
+        let mode = match flags {
+            0 => XattrSet::Any,
+            XATTR_CREATE => XattrSet::Create,
+            XATTR_REPLACE => XattrSet::Replace,
+            _ => return Err(axerror::Error::from_errno(LinuxError::EINVAL)),
+        };
+        if value.len() > XATTR_SIZE_MAX {
+            return Err(axerror::Error::from_errno(LinuxError::E2BIG));
+        }
+        match self {
+            Self::Path(path) => axfs::api::set_xattr(path, name, value, mode),
+            Self::File(f) => f.inner.lock().set_xattr(name, value, mode),
+            Self::Dir(d) => d.inner.lock().set_xattr(name, value, mode),
+        }
+        .errno_for(AxError::NotFound, LinuxError::ENODATA)
+        .context("set xattr")
+    }
+
+    fn remove(&self, name: &str) -> axerror::Result {
+        match self {
+            Self::Path(path) => axfs::api::remove_xattr(path, name),
+            Self::File(f) => f.inner.lock().remove_xattr(name),
+            Self::Dir(d) => d.inner.lock().remove_xattr(name),
+        }
+        .errno_for(AxError::NotFound, LinuxError::ENODATA)
+        .context("remove xattr")
+    }
+
+    fn list(&self) -> axerror::Result<Vec<String>> {
+        match self {
+            Self::Path(path) => axfs::api::list_xattr(path),
+            Self::File(f) => f.inner.lock().list_xattr(),
+            Self::Dir(d) => d.inner.lock().list_xattr(),
+        }
+        .context("list xattr")
+    }
+}
+
+fn xattr_name<'a>(name: *const c_char) -> LinuxResult<&'a str> {
+    let name = char_ptr_to_str(name)?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(LinuxError::ERANGE);
+    }
+    Ok(name)
+}
+
+/// Copies `data` to the user buffer `buf` of `size` bytes. With size 0, only
+/// returns the size needed.
+fn copy_to_user_buf(data: &[u8], buf: *mut u8, size: usize) -> LinuxResult<usize> {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if buf.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if size < data.len() {
+        return Err(LinuxError::ERANGE);
+    }
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len()) };
+    Ok(data.len())
+}
+
+fn getxattr(
+    node: LinuxResult<XattrNode>,
+    name: *const c_char,
+    value: *mut u8,
+    size: usize,
+) -> LinuxResult<usize> {
+    let value_data = node?.get(xattr_name(name)?)?;
+    copy_to_user_buf(&value_data, value, size)
+}
+
+fn setxattr(
+    node: LinuxResult<XattrNode>,
+    name: *const c_char,
+    value: *const u8,
+    size: usize,
+    flags: c_int,
+) -> LinuxResult<c_int> {
+    let name = xattr_name(name)?;
+    let value = if size == 0 {
+        &[][..]
+    } else if value.is_null() {
+        return Err(LinuxError::EFAULT);
+    } else {
+        unsafe { core::slice::from_raw_parts(value, size) }
+    };
+    node?.set(name, value, flags)?;
+    Ok(0)
+}
+
+fn listxattr(node: LinuxResult<XattrNode>, list: *mut u8, size: usize) -> LinuxResult<usize> {
+    let mut names = Vec::new();
+    for name in node?.list()? {
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+    copy_to_user_buf(&names, list, size)
+}
+
+/// Get the value of the extended attribute `name` of the file by `path`.
+///
+/// Return the size of the value, which is not copied if `size` is 0.
+pub fn sys_getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut u8,
+    size: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_getxattr <= {:?} {:?} {}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name),
+        size
+    );
+    syscall_body!(
+        sys_getxattr,
+        getxattr(XattrNode::from_path(path), name, value, size)
+    )
+}
+
+/// Get the value of the extended attribute `name` of the file indicated by
+/// `fd`.
+///
+/// Return the size of the value, which is not copied if `size` is 0.
+pub fn sys_fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut u8,
+    size: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_fgetxattr <= {} {:?} {}",
+        fd,
+        char_ptr_to_str(name),
+        size
+    );
+    syscall_body!(
+        sys_fgetxattr,
+        getxattr(XattrNode::from_fd(fd), name, value, size)
+    )
+}
+
+/// Set the extended attribute `name` of the file by `path`.
+///
+/// `flags` can be `XATTR_CREATE` or `XATTR_REPLACE`. Return 0 if success.
+pub fn sys_setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const u8,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_setxattr <= {:?} {:?} {} {:#x}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name),
+        size,
+        flags
+    );
+    syscall_body!(
+        sys_setxattr,
+        setxattr(XattrNode::from_path(path), name, value, size, flags)
+    )
+}
+
+/// Set the extended attribute `name` of the file indicated by `fd`.
+///
+/// `flags` can be `XATTR_CREATE` or `XATTR_REPLACE`. Return 0 if success.
+pub fn sys_fsetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *const u8,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_fsetxattr <= {} {:?} {} {:#x}",
+        fd,
+        char_ptr_to_str(name),
+        size,
+        flags
+    );
+    syscall_body!(
+        sys_fsetxattr,
+        setxattr(XattrNode::from_fd(fd), name, value, size, flags)
+    )
+}
+
+/// List the extended attribute names of the file by `path`, each terminated
+/// by a NUL byte.
+///
+/// Return the size of the list, which is not copied if `size` is 0.
+pub fn sys_listxattr(path: *const c_char, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    debug!("sys_listxattr <= {:?} {}", char_ptr_to_str(path), size);
+    syscall_body!(
+        sys_listxattr,
+        listxattr(XattrNode::from_path(path), list as _, size)
+    )
+}
+
+/// List the extended attribute names of the file indicated by `fd`, each
+/// terminated by a NUL byte.
+///
+/// Return the size of the list, which is not copied if `size` is 0.
+pub fn sys_flistxattr(fd: c_int, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    debug!("sys_flistxattr <= {} {}", fd, size);
+    syscall_body!(
+        sys_flistxattr,
+        listxattr(XattrNode::from_fd(fd), list as _, size)
+    )
+}
+
+/// Remove the extended attribute `name` of the file by `path`.
+///
+/// Return 0 if success.
+pub fn sys_removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    debug!(
+        "sys_removexattr <= {:?} {:?}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name)
+    );
+    syscall_body!(sys_removexattr, {
+        let node = XattrNode::from_path(path)?;
+        node.remove(xattr_name(name)?)?;
+        Ok(0)
+    })
+}
+
+/// Remove the extended attribute `name` of the file indicated by `fd`.
+///
+/// Return 0 if success.
+pub fn sys_fremovexattr(fd: c_int, name: *const c_char) -> c_int {
+    debug!("sys_fremovexattr <= {} {:?}", fd, char_ptr_to_str(name));
+    syscall_body!(sys_fremovexattr, {
+        let node = XattrNode::from_fd(fd)?;
+        node.remove(xattr_name(name)?)?;
+        Ok(0)
+    })
+}
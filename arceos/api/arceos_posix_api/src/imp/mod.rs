@@ -6,15 +6,25 @@ pub mod sys;
 pub mod task;
 pub mod time;
 
+#[cfg(feature = "eventfd")]
+pub mod eventfd;
 #[cfg(feature = "fd")]
 pub mod fd_ops;
 #[cfg(feature = "fs")]
 pub mod fs;
 #[cfg(any(feature = "select", feature = "epoll"))]
 pub mod io_mpx;
-#[cfg(feature = "net")]
+#[cfg(any(feature = "net", feature = "vsock"))]
 pub mod net;
+#[cfg(feature = "net")]
+mod netdev;
 #[cfg(feature = "pipe")]
 pub mod pipe;
 #[cfg(feature = "multitask")]
 pub mod pthread;
+#[cfg(feature = "signalfd")]
+pub mod signalfd;
+#[cfg(feature = "timer")]
+pub mod timer;
+#[cfg(feature = "timerfd")]
+pub mod timerfd;
@@ -1,20 +1,60 @@
-use alloc::{sync::Arc, vec, vec::Vec};
-use core::ffi::{c_char, c_int, c_void};
+use alloc::sync::Arc;
+#[cfg(feature = "net")]
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "net")]
+use core::ffi::c_char;
+use core::ffi::{c_int, c_void};
+use core::fmt;
 use core::mem::size_of;
+#[cfg(feature = "net")]
 use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
+#[cfg(all(feature = "fs", feature = "net"))]
+use axio::SeekFrom;
+#[cfg(feature = "net")]
 use axnet::{TcpSocket, UdpSocket};
 use axsync::Mutex;
+#[cfg(feature = "vsock")]
+use axvsock::{VsockAddr, VsockSocket};
 
 use super::fd_ops::FileLike;
 use crate::ctypes;
+#[cfg(feature = "net")]
 use crate::utils::char_ptr_to_str;
 
+/// The bytes read from the file at a time by [`sys_sendfile`].
+#[cfg(all(feature = "fs", feature = "net"))]
+const SENDFILE_CHUNK_SIZE: usize = 16 * 1024;
+
 pub enum Socket {
+    #[cfg(feature = "net")]
     Udp(Mutex<UdpSocket>),
+    #[cfg(feature = "net")]
     Tcp(Mutex<TcpSocket>),
+    #[cfg(feature = "vsock")]
+    Vsock(Mutex<VsockSocket>),
+}
+
+/// The address of a socket, in one of the supported address families.
+#[derive(Debug, Clone, Copy)]
+enum SockAddr {
+    #[cfg(feature = "net")]
+    Inet(SocketAddr),
+    #[cfg(feature = "vsock")]
+    Vsock(VsockAddr),
+}
+
+impl fmt::Display for SockAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "net")]
+            Self::Inet(addr) => write!(f, "{}", addr),
+            #[cfg(feature = "vsock")]
+            Self::Vsock(addr) => write!(f, "vsock:{}:{}", addr.cid, addr.port),
+        }
+    }
 }
 
 impl Socket {
@@ -31,88 +71,139 @@ impl Socket {
 
     fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().send(buf)?),
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().send(buf)?),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().send(buf)?),
         }
     }
 
     fn recv(&self, buf: &mut [u8]) -> LinuxResult<usize> {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().recv_from(buf).map(|e| e.0)?),
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf)?),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().recv(buf)?),
         }
     }
 
     pub fn poll(&self) -> LinuxResult<PollState> {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().poll()?),
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().poll()?),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().poll()?),
         }
     }
 
-    fn local_addr(&self) -> LinuxResult<SocketAddr> {
+    fn local_addr(&self) -> LinuxResult<SockAddr> {
         match self {
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().local_addr()?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().local_addr()?),
+            #[cfg(feature = "net")]
+            Socket::Udp(udpsocket) => Ok(SockAddr::Inet(udpsocket.lock().local_addr()?)),
+            #[cfg(feature = "net")]
+            Socket::Tcp(tcpsocket) => Ok(SockAddr::Inet(tcpsocket.lock().local_addr()?)),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(SockAddr::Vsock(vsocket.lock().local_addr()?)),
         }
     }
 
-    fn peer_addr(&self) -> LinuxResult<SocketAddr> {
+    fn peer_addr(&self) -> LinuxResult<SockAddr> {
         match self {
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().peer_addr()?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().peer_addr()?),
+            #[cfg(feature = "net")]
+            Socket::Udp(udpsocket) => Ok(SockAddr::Inet(udpsocket.lock().peer_addr()?)),
+            #[cfg(feature = "net")]
+            Socket::Tcp(tcpsocket) => Ok(SockAddr::Inet(tcpsocket.lock().peer_addr()?)),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(SockAddr::Vsock(vsocket.lock().peer_addr()?)),
         }
     }
 
-    fn bind(&self, addr: SocketAddr) -> LinuxResult {
-        match self {
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().bind(addr)?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().bind(addr)?),
+    #[allow(unreachable_patterns)]
+    fn bind(&self, addr: SockAddr) -> LinuxResult {
+        match (self, addr) {
+            #[cfg(feature = "net")]
+            (Socket::Udp(udpsocket), SockAddr::Inet(addr)) => Ok(udpsocket.lock().bind(addr)?),
+            #[cfg(feature = "net")]
+            (Socket::Tcp(tcpsocket), SockAddr::Inet(addr)) => Ok(tcpsocket.lock().bind(addr)?),
+            #[cfg(feature = "vsock")]
+            (Socket::Vsock(vsocket), SockAddr::Vsock(addr)) => Ok(vsocket.lock().bind(addr)?),
+            _ => Err(LinuxError::EAFNOSUPPORT),
         }
     }
 
-    fn connect(&self, addr: SocketAddr) -> LinuxResult {
-        match self {
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().connect(addr)?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().connect(addr)?),
+    #[allow(unreachable_patterns)]
+    fn connect(&self, addr: SockAddr) -> LinuxResult {
+        match (self, addr) {
+            #[cfg(feature = "net")]
+            (Socket::Udp(udpsocket), SockAddr::Inet(addr)) => Ok(udpsocket.lock().connect(addr)?),
+            #[cfg(feature = "net")]
+            (Socket::Tcp(tcpsocket), SockAddr::Inet(addr)) => Ok(tcpsocket.lock().connect(addr)?),
+            #[cfg(feature = "vsock")]
+            (Socket::Vsock(vsocket), SockAddr::Vsock(addr)) => Ok(vsocket.lock().connect(addr)?),
+            _ => Err(LinuxError::EAFNOSUPPORT),
         }
     }
 
-    fn sendto(&self, buf: &[u8], addr: SocketAddr) -> LinuxResult<usize> {
-        match self {
+    #[allow(unreachable_patterns)]
+    fn sendto(&self, buf: &[u8], addr: SockAddr) -> LinuxResult<usize> {
+        match (self, addr) {
             // diff: must bind before sendto
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().send_to(buf, addr)?),
-            Socket::Tcp(_) => Err(LinuxError::EISCONN),
+            #[cfg(feature = "net")]
+            (Socket::Udp(udpsocket), SockAddr::Inet(addr)) => {
+                Ok(udpsocket.lock().send_to(buf, addr)?)
+            }
+            #[cfg(feature = "net")]
+            (Socket::Udp(_), _) => Err(LinuxError::EAFNOSUPPORT),
+            _ => Err(LinuxError::EISCONN),
         }
     }
 
-    fn recvfrom(&self, buf: &mut [u8]) -> LinuxResult<(usize, Option<SocketAddr>)> {
+    fn recvfrom(&self, buf: &mut [u8]) -> LinuxResult<(usize, Option<SockAddr>)> {
         match self {
             // diff: must bind before recvfrom
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => Ok(udpsocket
                 .lock()
                 .recv_from(buf)
-                .map(|res| (res.0, Some(res.1)))?),
+                .map(|res| (res.0, Some(SockAddr::Inet(res.1))))?),
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf).map(|res| (res, None))?),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().recv(buf).map(|res| (res, None))?),
         }
     }
 
-    fn listen(&self) -> LinuxResult {
+    fn listen(&self, backlog: usize) -> LinuxResult {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
+            #[cfg(feature = "net")]
+            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen_with_backlog(backlog)?),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().listen()?),
         }
     }
 
-    fn accept(&self) -> LinuxResult<TcpSocket> {
+    fn accept(&self) -> LinuxResult<Socket> {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().accept()?),
+            #[cfg(feature = "net")]
+            Socket::Tcp(tcpsocket) => Ok(Socket::Tcp(Mutex::new(tcpsocket.lock().accept()?))),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(Socket::Vsock(Mutex::new(vsocket.lock().accept()?))),
         }
     }
 
     fn shutdown(&self) -> LinuxResult {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => {
                 let udpsocket = udpsocket.lock();
                 udpsocket.peer_addr()?;
@@ -120,12 +211,16 @@ impl Socket {
                 Ok(())
             }
 
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => {
                 let tcpsocket = tcpsocket.lock();
                 tcpsocket.peer_addr()?;
                 tcpsocket.shutdown()?;
                 Ok(())
             }
+
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => Ok(vsocket.lock().shutdown()?),
         }
     }
 }
@@ -163,13 +258,30 @@ impl FileLike for Socket {
 
     fn set_nonblocking(&self, nonblock: bool) -> LinuxResult {
         match self {
+            #[cfg(feature = "net")]
             Socket::Udp(udpsocket) => udpsocket.lock().set_nonblocking(nonblock),
+            #[cfg(feature = "net")]
             Socket::Tcp(tcpsocket) => tcpsocket.lock().set_nonblocking(nonblock),
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(vsocket) => vsocket.lock().set_nonblocking(nonblock),
         }
         Ok(())
     }
+
+    /// Performs the ioctls of the network interfaces, see [`netdev`].
+    ///
+    /// [`netdev`]: super::netdev
+    unsafe fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<usize> {
+        match self {
+            #[cfg(feature = "net")]
+            Socket::Udp(_) | Socket::Tcp(_) => unsafe { super::netdev::ioctl(cmd, arg) },
+            #[cfg(feature = "vsock")]
+            Socket::Vsock(_) => Err(LinuxError::ENOTTY),
+        }
+    }
 }
 
+#[cfg(feature = "net")]
 impl From<SocketAddrV4> for ctypes::sockaddr_in {
     fn from(addr: SocketAddrV4) -> ctypes::sockaddr_in {
         ctypes::sockaddr_in {
@@ -185,6 +297,7 @@ impl From<SocketAddrV4> for ctypes::sockaddr_in {
     }
 }
 
+#[cfg(feature = "net")]
 impl From<ctypes::sockaddr_in> for SocketAddrV4 {
     fn from(addr: ctypes::sockaddr_in) -> SocketAddrV4 {
         SocketAddrV4::new(
@@ -194,21 +307,50 @@ impl From<ctypes::sockaddr_in> for SocketAddrV4 {
     }
 }
 
-fn into_sockaddr(addr: SocketAddr) -> (ctypes::sockaddr, ctypes::socklen_t) {
+#[cfg(feature = "vsock")]
+impl From<VsockAddr> for ctypes::sockaddr_vm {
+    fn from(addr: VsockAddr) -> ctypes::sockaddr_vm {
+        ctypes::sockaddr_vm {
+            svm_family: ctypes::AF_VSOCK as u16,
+            svm_port: addr.port,
+            svm_cid: addr.cid as u32,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "vsock")]
+impl From<ctypes::sockaddr_vm> for VsockAddr {
+    fn from(addr: ctypes::sockaddr_vm) -> VsockAddr {
+        VsockAddr {
+            cid: addr.svm_cid as u64,
+            port: addr.svm_port,
+        }
+    }
+}
+
+fn into_sockaddr(addr: SockAddr) -> (ctypes::sockaddr, ctypes::socklen_t) {
     debug!("    Sockaddr: {}", addr);
     match addr {
-        SocketAddr::V4(addr) => (
+        #[cfg(feature = "net")]
+        SockAddr::Inet(SocketAddr::V4(addr)) => (
             unsafe { *(&ctypes::sockaddr_in::from(addr) as *const _ as *const ctypes::sockaddr) },
             size_of::<ctypes::sockaddr>() as _,
         ),
-        SocketAddr::V6(_) => panic!("IPv6 is not supported"),
+        #[cfg(feature = "net")]
+        SockAddr::Inet(SocketAddr::V6(_)) => panic!("IPv6 is not supported"),
+        #[cfg(feature = "vsock")]
+        SockAddr::Vsock(addr) => (
+            unsafe { *(&ctypes::sockaddr_vm::from(addr) as *const _ as *const ctypes::sockaddr) },
+            size_of::<ctypes::sockaddr>() as _,
+        ),
     }
 }
 
 fn from_sockaddr(
     addr: *const ctypes::sockaddr,
     addrlen: ctypes::socklen_t,
-) -> LinuxResult<SocketAddr> {
+) -> LinuxResult<SockAddr> {
     if addr.is_null() {
         return Err(LinuxError::EFAULT);
     }
@@ -216,12 +358,17 @@ fn from_sockaddr(
         return Err(LinuxError::EINVAL);
     }
 
-    let mid = unsafe { *(addr as *const ctypes::sockaddr_in) };
-    if mid.sin_family != ctypes::AF_INET as u16 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    let res = SocketAddr::V4(mid.into());
+    let res = match unsafe { (*addr).sa_family } as u32 {
+        #[cfg(feature = "net")]
+        ctypes::AF_INET => SockAddr::Inet(SocketAddr::V4(
+            unsafe { *(addr as *const ctypes::sockaddr_in) }.into(),
+        )),
+        #[cfg(feature = "vsock")]
+        ctypes::AF_VSOCK => {
+            SockAddr::Vsock(unsafe { *(addr as *const ctypes::sockaddr_vm) }.into())
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
     debug!("    load sockaddr:{:#x} => {:?}", addr as usize, res);
     Ok(res)
 }
@@ -234,14 +381,20 @@ pub fn sys_socket(domain: c_int, socktype: c_int, protocol: c_int) -> c_int {
     let (domain, socktype, protocol) = (domain as u32, socktype as u32, protocol as u32);
     syscall_body!(sys_socket, {
         match (domain, socktype, protocol) {
+            #[cfg(feature = "net")]
             (ctypes::AF_INET, ctypes::SOCK_STREAM, ctypes::IPPROTO_TCP)
             | (ctypes::AF_INET, ctypes::SOCK_STREAM, 0) => {
                 Socket::Tcp(Mutex::new(TcpSocket::new())).add_to_fd_table()
             }
+            #[cfg(feature = "net")]
             (ctypes::AF_INET, ctypes::SOCK_DGRAM, ctypes::IPPROTO_UDP)
             | (ctypes::AF_INET, ctypes::SOCK_DGRAM, 0) => {
                 Socket::Udp(Mutex::new(UdpSocket::new())).add_to_fd_table()
             }
+            #[cfg(feature = "vsock")]
+            (ctypes::AF_VSOCK, ctypes::SOCK_STREAM, 0) => {
+                Socket::Vsock(Mutex::new(VsockSocket::new())).add_to_fd_table()
+            }
             _ => Err(LinuxError::EINVAL),
         }
     })
@@ -262,6 +415,11 @@ pub fn sys_bind(
     syscall_body!(sys_bind, {
         let addr = from_sockaddr(socket_addr, addrlen)?;
         Socket::from_fd(socket_fd)?.bind(addr)?;
+        #[cfg(all(feature = "audit", feature = "net"))]
+        #[allow(irrefutable_let_patterns)]
+        if let SockAddr::Inet(addr) = addr {
+            axaudit::audit(axaudit::AuditEvent::SocketBind { addr });
+        }
         Ok(0)
     })
 }
@@ -332,6 +490,84 @@ pub fn sys_send(
     })
 }
 
+/// Transfer data from a file to a TCP socket, without copying it through a
+/// user buffer.
+///
+/// The file is read by chunks of [`SENDFILE_CHUNK_SIZE`] bytes outside the
+/// lock of the socket set, so that the disk I/O does not stall the other
+/// sockets, each chunk being copied into the transmit buffer of the socket.
+///
+/// If `offset` is not null, the file is read from `*offset`, which is updated,
+/// and the file position is not changed. Either is moved past the bytes sent
+/// only.
+///
+/// Return the number of bytes sent if success.
+#[cfg(all(feature = "fs", feature = "net"))]
+pub unsafe fn sys_sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: ctypes::size_t,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_sendfile <= {} {} {:#x} {}",
+        out_fd, in_fd, offset as usize, count
+    );
+    syscall_body!(sys_sendfile, {
+        let socket = Socket::from_fd(out_fd)?;
+        let Socket::Tcp(socket) = socket.as_ref() else {
+            return Err(LinuxError::EINVAL);
+        };
+        let file = super::fs::File::from_fd(in_fd)?;
+        // The position of the next byte to send.
+        let mut pos = match unsafe { offset.as_ref() } {
+            Some(&off) if off < 0 => return Err(LinuxError::EINVAL),
+            Some(&off) => off as u64,
+            None => file.seek(SeekFrom::Current(0))?,
+        };
+        let mut chunk = vec![0; SENDFILE_CHUNK_SIZE.min(count)];
+        // The bytes read and not sent yet.
+        let (mut start, mut end) = (0, 0);
+        let mut sent = 0;
+        while sent < count {
+            if start == end {
+                let len = chunk.len().min(count - sent);
+                match file.read_at(pos, &mut chunk[..len]) {
+                    Ok(0) => break, // end of file
+                    Ok(n) => (start, end) = (0, n),
+                    Err(e) if sent > 0 => {
+                        // Report the bytes sent, the error comes again at
+                        // the next call.
+                        warn!("sys_sendfile: read failed after {} bytes: {:?}", sent, e);
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let res = socket.lock().send_with(|buf| {
+                let len = buf.len().min(end - start);
+                buf[..len].copy_from_slice(&chunk[start..start + len]);
+                Ok(len)
+            });
+            match res {
+                Ok(n) => {
+                    start += n;
+                    sent += n;
+                    pos += n as u64;
+                }
+                Err(_) if sent > 0 => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if offset.is_null() {
+            file.seek(SeekFrom::Start(pos))?;
+        } else {
+            unsafe { *offset = pos as ctypes::off_t };
+        }
+        Ok(sent)
+    })
+}
+
 /// Receive a message on a socket and get its source address.
 ///
 /// Return the number of bytes received if success.
@@ -391,11 +627,12 @@ pub fn sys_recv(
 /// Return 0 if success.
 pub fn sys_listen(
     socket_fd: c_int,
-    backlog: c_int, // currently not used
+    backlog: c_int, // not used by vsock
 ) -> c_int {
     debug!("sys_listen <= {} {}", socket_fd, backlog);
     syscall_body!(sys_listen, {
-        Socket::from_fd(socket_fd)?.listen()?;
+        // A negative backlog is the largest one, as on Linux.
+        Socket::from_fd(socket_fd)?.listen(backlog as u32 as usize)?;
         Ok(0)
     })
 }
@@ -419,7 +656,7 @@ pub unsafe fn sys_accept(
         let socket = Socket::from_fd(socket_fd)?;
         let new_socket = socket.accept()?;
         let addr = new_socket.peer_addr()?;
-        let new_fd = Socket::add_to_fd_table(Socket::Tcp(Mutex::new(new_socket)))?;
+        let new_fd = Socket::add_to_fd_table(new_socket)?;
         unsafe {
             (*socket_addr, *socket_len) = into_sockaddr(addr);
         }
@@ -447,6 +684,7 @@ pub fn sys_shutdown(
 /// Results' ai_flags and ai_canonname are 0 or NULL.
 ///
 /// Return address number if success.
+#[cfg(feature = "net")]
 pub unsafe fn sys_getaddrinfo(
     nodename: *const c_char,
     servname: *const c_char,
@@ -520,6 +758,7 @@ pub unsafe fn sys_getaddrinfo(
 }
 
 /// Free queried `addrinfo` struct
+#[cfg(feature = "net")]
 pub unsafe fn sys_freeaddrinfo(res: *mut ctypes::addrinfo) {
     if res.is_null() {
         return;
@@ -579,3 +818,63 @@ pub unsafe fn sys_getpeername(
         Ok(0)
     })
 }
+
+/// Get options on a socket
+///
+/// Only `TCP_INFO` is supported, with the RTT, retransmission and pacing
+/// metrics of a TCP connection.
+///
+/// Return 0 if success.
+#[cfg(feature = "net")]
+pub unsafe fn sys_getsockopt(
+    sock_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+) -> c_int {
+    debug!(
+        "sys_getsockopt <= {} {} {} {:#x} {:#x}",
+        sock_fd, level, optname, optval as usize, optlen as usize
+    );
+    syscall_body!(sys_getsockopt, {
+        if optval.is_null() || optlen.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        match (level as u32, optname as u32) {
+            (ctypes::IPPROTO_TCP, ctypes::TCP_INFO) => {
+                let info = match &*Socket::from_fd(sock_fd)? {
+                    Socket::Tcp(tcpsocket) => tcpsocket.lock().tcp_info(),
+                    _ => return Err(LinuxError::EOPNOTSUPP),
+                };
+                let info = ctypes::tcp_info {
+                    tcpi_rtt: info.rtt_us,
+                    tcpi_rttvar: info.rttvar_us,
+                    tcpi_min_rtt: info.min_rtt_us,
+                    tcpi_total_retrans: info.total_retrans,
+                    tcpi_segs_out: info.segs_out,
+                    tcpi_segs_in: info.segs_in,
+                    tcpi_bytes_sent: info.bytes_sent,
+                    tcpi_bytes_retrans: info.bytes_retrans,
+                    tcpi_bytes_acked: info.bytes_acked,
+                    tcpi_delivery_rate: info.delivery_rate,
+                    tcpi_pacing_rate: info.pacing_rate,
+                    tcpi_max_pacing_rate: u64::MAX,
+                    ..Default::default()
+                };
+                // truncated to the buffer of the caller, as Linux does
+                let len = (unsafe { *optlen } as usize).min(size_of::<ctypes::tcp_info>());
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        &info as *const _ as *const u8,
+                        optval as *mut u8,
+                        len,
+                    );
+                    *optlen = len as _;
+                }
+                Ok(0)
+            }
+            _ => Err(LinuxError::ENOPROTOOPT),
+        }
+    })
+}
@@ -0,0 +1,155 @@
+//! The ioctls of the network interfaces on the sockets: the `SIOCGIF*` ones
+//! and the `SIOCETHTOOL` commands read by the monitoring tools, e.g.
+//! `ethtool -i` and `ethtool -S`.
+
+use core::ffi::{c_char, c_int};
+
+use axerrno::{LinuxError, LinuxResult};
+use axnet::NetIfInfo;
+
+use crate::ctypes;
+use crate::utils::check_null_mut_ptr;
+
+/// The hardware type of the Ethernet devices, in `ifr_hwaddr`.
+const ARPHRD_ETHER: u16 = 1;
+
+/// Copies `s` into `dst` ended by a zero, truncated to fit.
+fn copy_str(dst: &mut [u8], s: &str) {
+    let len = s.len().min(dst.len() - 1);
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+    dst[len] = 0;
+}
+
+/// Returns the bytes of the C string buffer `s`.
+fn c_bytes(s: &mut [c_char]) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(s.as_mut_ptr() as *mut u8, s.len()) }
+}
+
+fn c_char_str(s: &[c_char]) -> LinuxResult<&str> {
+    let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    let bytes = unsafe { core::slice::from_raw_parts(s.as_ptr() as *const u8, len) };
+    core::str::from_utf8(bytes).map_err(|_| LinuxError::ENODEV)
+}
+
+/// Performs the interface ioctl `cmd` on the `ifreq` at `arg`.
+///
+/// # Safety
+///
+/// `arg` must point to a valid `ifreq`, and its `ifr_data` to a valid
+/// ethtool command for `SIOCETHTOOL`.
+pub unsafe fn ioctl(cmd: u32, arg: usize) -> LinuxResult<usize> {
+    let ifr = arg as *mut ctypes::ifreq;
+    if !matches!(
+        cmd,
+        ctypes::SIOCGIFNAME
+            | ctypes::SIOCGIFFLAGS
+            | ctypes::SIOCGIFMTU
+            | ctypes::SIOCGIFHWADDR
+            | ctypes::SIOCGIFINDEX
+            | ctypes::SIOCETHTOOL
+    ) {
+        return Err(LinuxError::ENOTTY);
+    }
+    check_null_mut_ptr(ifr)?;
+    let ifr = unsafe { &mut *ifr };
+    if cmd == ctypes::SIOCGIFNAME {
+        let index = unsafe { ifr.ifr_ifru.ifru_ivalue };
+        let iface = axnet::interfaces()
+            .into_iter()
+            .find(|i| i.index as c_int == index)
+            .ok_or(LinuxError::ENODEV)?;
+        copy_str(c_bytes(unsafe { &mut ifr.ifr_ifrn.ifrn_name }), iface.name);
+        return Ok(0);
+    }
+
+    let name = c_char_str(unsafe { &ifr.ifr_ifrn.ifrn_name })?;
+    let iface = axnet::interface(name).ok_or(LinuxError::ENODEV)?;
+    match cmd {
+        ctypes::SIOCGIFFLAGS => {
+            let flags = ctypes::IFF_UP
+                | ctypes::IFF_BROADCAST
+                | ctypes::IFF_RUNNING
+                | ctypes::IFF_MULTICAST;
+            ifr.ifr_ifru.ifru_flags = flags as i16;
+        }
+        ctypes::SIOCGIFMTU => ifr.ifr_ifru.ifru_mtu = iface.mtu as c_int,
+        ctypes::SIOCGIFHWADDR => {
+            let mut addr = ctypes::sockaddr {
+                sa_family: ARPHRD_ETHER,
+                ..Default::default()
+            };
+            for (d, b) in addr.sa_data.iter_mut().zip(iface.mac) {
+                *d = b as c_char;
+            }
+            ifr.ifr_ifru.ifru_hwaddr = addr;
+        }
+        ctypes::SIOCGIFINDEX => ifr.ifr_ifru.ifru_ivalue = iface.index as c_int,
+        _ => unsafe { ethtool(&iface, ifr.ifr_ifru.ifru_data as usize)? },
+    }
+    Ok(0)
+}
+
+/// Performs the ethtool command at `data` on `iface`.
+unsafe fn ethtool(iface: &NetIfInfo, data: usize) -> LinuxResult {
+    let cmd = data as *mut u32;
+    check_null_mut_ptr(cmd)?;
+    let counters = iface.stats.counters();
+    match unsafe { *cmd } {
+        ctypes::ETHTOOL_GSET => {
+            let (speed, duplex) = match iface.speed {
+                Some(speed) => (speed, ctypes::DUPLEX_FULL),
+                None => (ctypes::SPEED_UNKNOWN as u32, ctypes::DUPLEX_UNKNOWN),
+            };
+            let ecmd = ctypes::ethtool_cmd {
+                cmd: ctypes::ETHTOOL_GSET,
+                speed: speed as u16,
+                speed_hi: (speed >> 16) as u16,
+                duplex: duplex as u8,
+                port: ctypes::PORT_OTHER as u8,
+                transceiver: ctypes::XCVR_INTERNAL as u8,
+                autoneg: ctypes::AUTONEG_DISABLE as u8,
+                ..Default::default()
+            };
+            unsafe { *(data as *mut ctypes::ethtool_cmd) = ecmd };
+        }
+        ctypes::ETHTOOL_GDRVINFO => {
+            let mut info = ctypes::ethtool_drvinfo {
+                cmd: ctypes::ETHTOOL_GDRVINFO,
+                n_stats: counters.len() as u32,
+                ..Default::default()
+            };
+            copy_str(c_bytes(&mut info.driver), &iface.driver);
+            copy_str(c_bytes(&mut info.version), env!("CARGO_PKG_VERSION"));
+            unsafe { *(data as *mut ctypes::ethtool_drvinfo) = info };
+        }
+        ctypes::ETHTOOL_GLINK => {
+            let value = ctypes::ethtool_value {
+                cmd: ctypes::ETHTOOL_GLINK,
+                data: 1,
+            };
+            unsafe { *(data as *mut ctypes::ethtool_value) = value };
+        }
+        ctypes::ETHTOOL_GSTRINGS => {
+            let strings = unsafe { &mut *(data as *mut ctypes::ethtool_gstrings) };
+            if strings.string_set != ctypes::ETH_SS_STATS {
+                return Err(LinuxError::EOPNOTSUPP);
+            }
+            strings.len = counters.len() as u32;
+            let len = ctypes::ETH_GSTRING_LEN as usize;
+            let buf = unsafe { strings.data.as_mut_slice(counters.len() * len) };
+            for (dst, (name, _)) in buf.chunks_exact_mut(len).zip(counters) {
+                copy_str(dst, name);
+            }
+        }
+        ctypes::ETHTOOL_GSTATS => {
+            let stats = unsafe { &mut *(data as *mut ctypes::ethtool_stats) };
+            stats.n_stats = counters.len() as u32;
+            let buf = unsafe { stats.data.as_mut_slice(counters.len()) };
+            for (dst, (_, value)) in buf.iter_mut().zip(counters) {
+                *dst = value;
+            }
+        }
+        _ => return Err(LinuxError::EOPNOTSUPP),
+    }
+    Ok(())
+}
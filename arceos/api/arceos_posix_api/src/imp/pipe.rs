@@ -3,7 +3,7 @@ use core::ffi::c_int;
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
-use axsync::Mutex;
+use axsync::{spin_wait_until, Mutex};
 
 use super::fd_ops::{add_file_like, close_file_like, FileLike};
 use crate::ctypes;
@@ -122,7 +122,9 @@ impl FileLike for Pipe {
                 }
                 drop(ring_buffer);
                 // Data not ready, wait for write end
-                crate::sys_sched_yield(); // TODO: use synconize primitive
+                spin_wait_until(|| {
+                    self.buffer.lock().available_read() != 0 || self.write_end_close()
+                });
                 continue;
             }
             for _ in 0..loop_read {
@@ -147,7 +149,7 @@ impl FileLike for Pipe {
             if loop_write == 0 {
                 drop(ring_buffer);
                 // Buffer is full, wait for read end to consume
-                crate::sys_sched_yield(); // TODO: use synconize primitive
+                spin_wait_until(|| self.buffer.lock().available_write() != 0);
                 continue;
             }
             for _ in 0..loop_write {
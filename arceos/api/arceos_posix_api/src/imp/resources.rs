@@ -45,6 +45,15 @@ pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit
             crate::ctypes::RLIMIT_NOFILE => {}
             _ => return Err(LinuxError::EINVAL),
         }
+        #[cfg(feature = "audit")]
+        if !rlimits.is_null() {
+            let rlimits = unsafe { *rlimits };
+            axaudit::audit(axaudit::AuditEvent::SetRlimit {
+                resource,
+                cur: rlimits.rlim_cur as _,
+                max: rlimits.rlim_max as _,
+            });
+        }
         // Currently do not support set resources
         Ok(0)
     })
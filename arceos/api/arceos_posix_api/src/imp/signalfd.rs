@@ -0,0 +1,217 @@
+//! `signalfd` implementation.
+//!
+//! There are no signal handlers in ArceOS, the signals are only delivered
+//! through the signal file descriptors. `SIGINT` is sent on the console break
+//! (Ctrl-C): the task creating a signal file descriptor with `SIGINT` in its
+//! mask becomes the foreground task, and the cancellation the break requests
+//! on it is read as a `SIGINT`. The other signals are sent by the POSIX
+//! timers, and stay pending for the whole system until a signal file
+//! descriptor with them in its mask reads them. A signal pending twice is
+//! only read once, the timers count the lost ones as overruns.
+
+use alloc::sync::Arc;
+use core::ffi::c_int;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axtask::{AxTaskRef, WaitQueue};
+
+use super::fd_ops::{add_file_like, get_file_like, FileLike};
+use crate::ctypes;
+use crate::ctypes::SIGINT;
+
+/// The signals sent by [`send_signal`] and not read yet.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+/// Where the readers wait for the signals sent.
+static SIGNAL_WQ: WaitQueue = WaitQueue::new();
+
+const fn sig_bit(sig: u32) -> u64 {
+    1 << (sig - 1)
+}
+
+const fn lowest_bit(bits: u64) -> u64 {
+    bits & bits.wrapping_neg()
+}
+
+/// Makes the signal `sig` pending, to be read by a signal file descriptor.
+///
+/// It can be called in the interrupt handlers.
+#[cfg_attr(not(feature = "timer"), allow(dead_code))]
+pub(crate) fn send_signal(sig: u32) {
+    PENDING.fetch_or(sig_bit(sig), Ordering::AcqRel);
+    SIGNAL_WQ.notify_all(false);
+}
+
+/// Returns whether the signal `sig` is sent and not read yet.
+#[cfg_attr(not(feature = "timer"), allow(dead_code))]
+pub(crate) fn is_signal_pending(sig: u32) -> bool {
+    PENDING.load(Ordering::Acquire) & sig_bit(sig) != 0
+}
+
+pub struct SignalFd {
+    mask: AtomicU64,
+    owner: AxTaskRef,
+    nonblocking: AtomicBool,
+}
+
+impl SignalFd {
+    fn new(mask: u64) -> Self {
+        let signal = Self {
+            mask: AtomicU64::new(0),
+            owner: axtask::current().as_task_ref().clone(),
+            nonblocking: AtomicBool::new(false),
+        };
+        signal.set_mask(mask);
+        signal
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<SignalFd>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    fn set_mask(&self, mask: u64) {
+        self.mask.store(mask, Ordering::Release);
+        if mask & sig_bit(SIGINT) != 0 {
+            axtask::set_foreground(Some(&self.owner));
+        }
+    }
+
+    fn accepts(&self, sig: u32) -> bool {
+        self.mask.load(Ordering::Acquire) & sig_bit(sig) != 0
+    }
+
+    /// Takes a pending signal in the mask, with its `ssi_code`.
+    fn take_signal(&self) -> Option<(u32, i32)> {
+        if self.accepts(SIGINT) && self.owner.take_cancel_request() {
+            return Some((SIGINT, ctypes::SI_KERNEL as i32));
+        }
+        let mask = self.mask.load(Ordering::Acquire);
+        let pending = PENDING
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |pending| {
+                (pending & mask != 0).then(|| pending & !lowest_bit(pending & mask))
+            })
+            .ok()?;
+        let sig = (pending & mask).trailing_zeros() + 1;
+        Some((sig, ctypes::SI_TIMER))
+    }
+
+    /// Whether a signal in the mask is pending.
+    fn has_signal(&self) -> bool {
+        (self.accepts(SIGINT) && self.owner.is_cancel_requested())
+            || PENDING.load(Ordering::Acquire) & self.mask.load(Ordering::Acquire) != 0
+    }
+
+    /// Blocks until a signal in the mask is pending: sent by a timer, or the
+    /// console break of the owner with `SIGINT` in the mask.
+    fn wait(&self) {
+        // The token of the owner is cancelled for good on its first break,
+        // the next breaks do not wake the reader.
+        let token = Some(self.owner.cancel_token())
+            .filter(|token| self.accepts(SIGINT) && !token.is_cancelled());
+        match token {
+            Some(token) => {
+                SIGNAL_WQ
+                    .wait_until_cancellable(token, || self.has_signal())
+                    .ok();
+            }
+            None => SIGNAL_WQ.wait_until(|| self.has_signal()),
+        }
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        if axtask::foreground().is_some_and(|t| AxTaskRef::ptr_eq(&t, &self.owner)) {
+            axtask::set_foreground(None);
+        }
+    }
+}
+
+impl FileLike for SignalFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        const INFO_SIZE: usize = size_of::<ctypes::signalfd_siginfo>();
+        if buf.len() < INFO_SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let (sig, code) = loop {
+            if let Some(signal) = self.take_signal() {
+                break signal;
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            // No signal pending, wait for the console break or a timer
+            self.wait();
+        };
+        let info = ctypes::signalfd_siginfo {
+            ssi_signo: sig,
+            ssi_code: code,
+            ..Default::default()
+        };
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&info as *const _ as *const u8, INFO_SIZE) };
+        buf[..INFO_SIZE].copy_from_slice(bytes);
+        Ok(INFO_SIZE)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600u32, // rw-------
+            st_uid: 1000,
+            st_gid: 1000,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.has_signal(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Create a file descriptor for accepting signals, or change the mask of the
+/// signal file descriptor `fd` if it is not -1
+///
+/// Return the file descriptor if succeed
+pub unsafe fn sys_signalfd(fd: c_int, mask: *const ctypes::sigset_t, flags: c_int) -> c_int {
+    debug!("sys_signalfd <= {} {:#x} {:#x}", fd, mask as usize, flags);
+    syscall_body!(sys_signalfd, {
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if flags as u32 & !(ctypes::SFD_NONBLOCK | ctypes::SFD_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mask = unsafe { (*mask).__bits[0] as u64 };
+        if fd != -1 {
+            SignalFd::from_fd(fd)?.set_mask(mask);
+            return Ok(fd);
+        }
+        let signal = SignalFd::new(mask);
+        signal.set_nonblocking(flags as u32 & ctypes::SFD_NONBLOCK != 0)?;
+        add_file_like(Arc::new(signal))
+    })
+}
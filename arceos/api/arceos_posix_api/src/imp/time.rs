@@ -90,3 +90,93 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
         Ok(0)
     })
 }
+
+/// The setting of an interval timer on a clock, shared by `timerfd` and the
+/// POSIX timers.
+#[cfg(any(feature = "timerfd", feature = "timer"))]
+pub(crate) struct TimerState {
+    /// The next expiration on the clock, `None` if the timer is disarmed.
+    pub deadline: Option<Duration>,
+    /// The period of the timer, zero for a one-shot timer.
+    pub interval: Duration,
+}
+
+#[cfg(any(feature = "timerfd", feature = "timer"))]
+impl TimerState {
+    pub const fn new() -> Self {
+        Self {
+            deadline: None,
+            interval: Duration::ZERO,
+        }
+    }
+
+    /// Arms the timer to expire after `value` (or at `value` on the clock if
+    /// `absolute`), then every `interval` if it is not zero. A zero `value`
+    /// disarms the timer.
+    ///
+    /// Returns the previous setting, as [`TimerState::to_itimerspec`].
+    pub fn set(
+        &mut self,
+        now: Duration,
+        value: Duration,
+        interval: Duration,
+        absolute: bool,
+    ) -> ctypes::itimerspec {
+        let old = self.to_itimerspec(now);
+        self.deadline = match (value.is_zero(), absolute) {
+            (true, _) => None,
+            (false, true) => Some(value),
+            (false, false) => Some(now + value),
+        };
+        self.interval = interval;
+        old
+    }
+
+    /// Takes the number of expirations until `now`, and advances the deadline
+    /// past it.
+    pub fn take_expirations(&mut self, now: Duration) -> u64 {
+        let Some(deadline) = self.deadline.filter(|&d| d <= now) else {
+            return 0;
+        };
+        if self.interval.is_zero() {
+            self.deadline = None;
+            return 1;
+        }
+        let interval = self.interval.as_nanos();
+        let periods = (now - deadline).as_nanos() / interval + 1;
+        self.deadline = Some(deadline + Duration::from_nanos((periods * interval) as u64));
+        periods as u64
+    }
+
+    /// Returns the time until the next expiration and the interval.
+    pub fn to_itimerspec(&self, now: Duration) -> ctypes::itimerspec {
+        let value = self
+            .deadline
+            .map_or(Duration::ZERO, |d| d.saturating_sub(now));
+        ctypes::itimerspec {
+            it_interval: self.interval.into(),
+            // A zero value means disarmed, report an expired timer as due in 1ns.
+            it_value: match (self.deadline, value.is_zero()) {
+                (Some(_), true) => Duration::from_nanos(1).into(),
+                _ => value.into(),
+            },
+        }
+    }
+}
+
+/// The current time on `clock`, `CLOCK_REALTIME` or `CLOCK_MONOTONIC`.
+#[cfg(any(feature = "timerfd", feature = "timer"))]
+pub(crate) fn clock_now(clock: u32) -> Duration {
+    match clock {
+        CLOCK_REALTIME => axhal::time::wall_time(),
+        _ => axhal::time::monotonic_time(),
+    }
+}
+
+#[cfg(any(feature = "timerfd", feature = "timer"))]
+pub(crate) fn timespec_to_duration(ts: &ctypes::timespec) -> axerrno::LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok((*ts).into())
+}
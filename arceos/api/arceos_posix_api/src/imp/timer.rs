@@ -0,0 +1,369 @@
+//! POSIX timers (`timer_create`) implementation.
+//!
+//! An armed timer sets a timer callback of [`axtask`] at its next expiration,
+//! which counts the expirations since and re-arms a periodic timer. They are
+//! notified as the `sigev_notify` of the timer asks:
+//!
+//! - `SIGEV_NONE`: not notified, the timer is only read by `timer_gettime`;
+//! - `SIGEV_SIGNAL`: `sigev_signo` is sent, to be read by a signal file
+//!   descriptor as there are no signal handlers (see [`super::signalfd`]);
+//! - `SIGEV_THREAD`: `sigev_notify_function` is called with `sigev_value` by
+//!   a task of the timer, the callbacks of the unikernel apps.
+//!
+//! The expirations while the previous notification is pending (the signal
+//! not read yet, or the function not called yet) are not notified but
+//! counted as the overrun of that notification, which `timer_getoverrun`
+//! returns once it is delivered.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::ffi::{c_int, c_void};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{TimerId, WaitQueue};
+use kspin::SpinNoIrq;
+
+use super::signalfd::{is_signal_pending, send_signal};
+use super::time::{clock_now, timespec_to_duration, TimerState};
+use crate::ctypes;
+use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+/// The largest overrun reported, as `DELAYTIMER_MAX`.
+const DELAYTIMER_MAX: u64 = c_int::MAX as u64;
+
+static TIMERS: Mutex<BTreeMap<usize, Arc<PosixTimer>>> = Mutex::new(BTreeMap::new());
+static NEXT_TIMER_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// How the expirations are notified.
+enum Notify {
+    None,
+    Signal(u32),
+    Thread {
+        function: unsafe extern "C" fn(ctypes::sigval),
+        /// The `sival_ptr` of `sigev_value`.
+        value: usize,
+    },
+}
+
+struct TimerInner {
+    timer: TimerState,
+    /// The timer callback set at the next expiration.
+    event: Option<TimerId>,
+    /// Incremented on each arming, to ignore the callbacks of the previous
+    /// ones which already left the timer list.
+    generation: u64,
+    /// Whether a notification is pending.
+    queued: bool,
+    /// The expirations since the pending notification.
+    pending_overrun: u64,
+    /// The overrun of the last notification delivered.
+    overrun: u64,
+    deleted: bool,
+}
+
+impl TimerInner {
+    /// Records the delivery of the pending notification.
+    fn deliver(&mut self) {
+        self.overrun = self.pending_overrun;
+        self.pending_overrun = 0;
+        self.queued = false;
+    }
+}
+
+pub struct PosixTimer {
+    clock: u32,
+    notify: Notify,
+    inner: SpinNoIrq<TimerInner>,
+    /// Where the task of a `SIGEV_THREAD` timer waits for the notifications.
+    wq: WaitQueue,
+}
+
+impl PosixTimer {
+    fn new(clock: u32, notify: Notify) -> LinuxResult<Self> {
+        if clock != CLOCK_REALTIME && clock != CLOCK_MONOTONIC {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Self {
+            clock,
+            notify,
+            inner: SpinNoIrq::new(TimerInner {
+                timer: TimerState::new(),
+                event: None,
+                generation: 0,
+                queued: false,
+                pending_overrun: 0,
+                overrun: 0,
+                deleted: false,
+            }),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    fn from_id(id: ctypes::timer_t) -> LinuxResult<Arc<Self>> {
+        TIMERS
+            .lock()
+            .get(&(id as usize))
+            .cloned()
+            .ok_or(LinuxError::EINVAL)
+    }
+
+    /// Records the delivery of the pending signal once it is read.
+    fn sync(&self, inner: &mut TimerInner) {
+        if let Notify::Signal(sig) = self.notify {
+            if inner.queued && !is_signal_pending(sig) {
+                inner.deliver();
+            }
+        }
+    }
+
+    /// Sets the timer callback at the next expiration, in place of the
+    /// previous one.
+    fn arm(self: &Arc<Self>, inner: &mut TimerInner) {
+        if let Some(event) = inner.event.take() {
+            axtask::cancel_timer(event);
+        }
+        inner.generation += 1;
+        let Some(deadline) = inner.timer.deadline else {
+            return;
+        };
+        // The timer callbacks are on the wall time.
+        let deadline = match self.clock {
+            CLOCK_REALTIME => deadline,
+            _ => deadline + Duration::from_nanos(axhal::time::epochoffset_nanos()),
+        };
+        let (timer, generation) = (self.clone(), inner.generation);
+        inner.event = Some(axtask::set_timer(deadline, move |_| {
+            timer.expire(generation)
+        }));
+    }
+
+    /// The timer callback, in the timer interrupt handler.
+    fn expire(self: &Arc<Self>, generation: u64) {
+        let mut inner = self.inner.lock();
+        if inner.generation != generation {
+            return;
+        }
+        inner.event = None;
+        // 0 if the wall time was stepped back, it is armed again
+        let expirations = inner.timer.take_expirations(clock_now(self.clock));
+        let wake = expirations > 0 && self.notify_expirations(&mut inner, expirations);
+        self.arm(&mut inner);
+        drop(inner);
+        // The wait queue checks the condition under the run queue lock, so it
+        // is notified without the timer locked.
+        if wake {
+            self.wq.notify_one(false);
+        }
+    }
+
+    /// Notifies the `expirations`, or counts them as overruns of the pending
+    /// notification. Returns whether the task of the timer is to be woken.
+    fn notify_expirations(&self, inner: &mut TimerInner, expirations: u64) -> bool {
+        self.sync(inner);
+        if inner.queued {
+            inner.pending_overrun += expirations;
+            return false;
+        }
+        inner.pending_overrun = expirations - 1;
+        let wake = match self.notify {
+            Notify::None => return false,
+            Notify::Signal(sig) => {
+                send_signal(sig);
+                false
+            }
+            Notify::Thread { .. } => true,
+        };
+        inner.queued = true;
+        wake
+    }
+
+    /// The task of a `SIGEV_THREAD` timer, calling `function` on each
+    /// notification until the timer is deleted.
+    fn run_notifier(&self, function: unsafe extern "C" fn(ctypes::sigval), value: usize) {
+        loop {
+            self.wq.wait_until(|| {
+                let inner = self.inner.lock();
+                inner.queued || inner.deleted
+            });
+            let mut inner = self.inner.lock();
+            if inner.deleted {
+                break;
+            }
+            inner.deliver();
+            drop(inner);
+            unsafe {
+                function(ctypes::sigval {
+                    sival_ptr: value as *mut c_void,
+                })
+            };
+        }
+    }
+
+    /// Arms the timer, as [`TimerState::set`].
+    pub fn set(
+        self: &Arc<Self>,
+        value: Duration,
+        interval: Duration,
+        absolute: bool,
+    ) -> ctypes::itimerspec {
+        let now = clock_now(self.clock);
+        let mut inner = self.inner.lock();
+        let old = inner.timer.set(now, value, interval, absolute);
+        self.arm(&mut inner);
+        old
+    }
+
+    /// Returns the time until the next expiration and the interval.
+    pub fn get(&self) -> ctypes::itimerspec {
+        self.inner.lock().timer.to_itimerspec(clock_now(self.clock))
+    }
+
+    /// Returns the overrun of the last notification delivered.
+    pub fn overrun(&self) -> c_int {
+        let mut inner = self.inner.lock();
+        self.sync(&mut inner);
+        inner.overrun.min(DELAYTIMER_MAX) as c_int
+    }
+
+    fn delete(&self) {
+        let mut inner = self.inner.lock();
+        if let Some(event) = inner.event.take() {
+            axtask::cancel_timer(event);
+        }
+        inner.generation += 1;
+        inner.deleted = true;
+        drop(inner);
+        self.wq.notify_all(false);
+    }
+}
+
+/// Create a timer on the clock `clockid`, notifying its expirations as
+/// `sevp` asks (`SIGEV_SIGNAL` with `SIGALRM` if it is null)
+///
+/// Return 0 if succeed, and the new timer in `timerid`
+pub unsafe fn sys_timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *mut ctypes::sigevent,
+    timerid: *mut ctypes::timer_t,
+) -> c_int {
+    debug!(
+        "sys_timer_create <= {} {:#x} {:#x}",
+        clockid, sevp as usize, timerid as usize
+    );
+    syscall_body!(sys_timer_create, {
+        if timerid.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let notify = match unsafe { sevp.as_ref() } {
+            None => Notify::Signal(ctypes::SIGALRM),
+            Some(sev) => match sev.sigev_notify as u32 {
+                ctypes::SIGEV_NONE => Notify::None,
+                ctypes::SIGEV_SIGNAL => {
+                    let sig = sev.sigev_signo as u32;
+                    if !(1..=64).contains(&sig) {
+                        return Err(LinuxError::EINVAL);
+                    }
+                    Notify::Signal(sig)
+                }
+                ctypes::SIGEV_THREAD => {
+                    let function = unsafe { sev.__sev_fields.__sev_thread.sigev_notify_function }
+                        .ok_or(LinuxError::EINVAL)?;
+                    let value = unsafe { sev.sigev_value.sival_ptr } as usize;
+                    Notify::Thread { function, value }
+                }
+                _ => return Err(LinuxError::EINVAL),
+            },
+        };
+        let timer = Arc::new(PosixTimer::new(clockid as u32, notify)?);
+        if let Notify::Thread { function, value } = timer.notify {
+            let timer = timer.clone();
+            axtask::spawn(move || timer.run_notifier(function, value));
+        }
+        let id = NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed);
+        TIMERS.lock().insert(id, timer);
+        unsafe { *timerid = id as ctypes::timer_t };
+        Ok(0)
+    })
+}
+
+/// Arm or disarm a timer
+///
+/// Return 0 if succeed
+pub unsafe fn sys_timer_settime(
+    timerid: ctypes::timer_t,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timer_settime <= {:#x} {:#x} {:#x} {:#x}",
+        timerid as usize, flags, new_value as usize, old_value as usize
+    );
+    syscall_body!(sys_timer_settime, {
+        if new_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if flags as u32 & !ctypes::TIMER_ABSTIME != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let new_value = unsafe { &*new_value };
+        let value = timespec_to_duration(&new_value.it_value)?;
+        let interval = timespec_to_duration(&new_value.it_interval)?;
+        let absolute = flags as u32 & ctypes::TIMER_ABSTIME != 0;
+        let old = PosixTimer::from_id(timerid)?.set(value, interval, absolute);
+        if !old_value.is_null() {
+            unsafe { *old_value = old };
+        }
+        Ok(0)
+    })
+}
+
+/// Get the time until the next expiration of a timer
+///
+/// Return 0 if succeed
+pub unsafe fn sys_timer_gettime(
+    timerid: ctypes::timer_t,
+    curr_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timer_gettime <= {:#x} {:#x}",
+        timerid as usize, curr_value as usize
+    );
+    syscall_body!(sys_timer_gettime, {
+        if curr_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let curr = PosixTimer::from_id(timerid)?.get();
+        unsafe { *curr_value = curr };
+        Ok(0)
+    })
+}
+
+/// Get the overrun count of the last notification delivered by a timer
+///
+/// Return the overrun count if succeed
+pub fn sys_timer_getoverrun(timerid: ctypes::timer_t) -> c_int {
+    debug!("sys_timer_getoverrun <= {:#x}", timerid as usize);
+    syscall_body!(sys_timer_getoverrun, {
+        Ok(PosixTimer::from_id(timerid)?.overrun())
+    })
+}
+
+/// Delete a timer, the function of a `SIGEV_THREAD` timer is not called for
+/// the pending notification
+///
+/// Return 0 if succeed
+pub fn sys_timer_delete(timerid: ctypes::timer_t) -> c_int {
+    debug!("sys_timer_delete <= {:#x}", timerid as usize);
+    syscall_body!(sys_timer_delete, {
+        let timer = TIMERS
+            .lock()
+            .remove(&(timerid as usize))
+            .ok_or(LinuxError::EINVAL)?;
+        timer.delete();
+        Ok(0)
+    })
+}
@@ -0,0 +1,224 @@
+//! `timerfd` implementation.
+//!
+//! The expirations are counted from the clock when the timer is read or
+//! polled, so an armed timer needs no timer event of its own. A blocking read
+//! sleeps until the next expiration or until the timer is set again, and is
+//! interrupted with `EINTR` by the console break (`SIGINT`, see `signalfd`)
+//! of its task.
+
+use alloc::sync::Arc;
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use super::fd_ops::{add_file_like, get_file_like, FileLike};
+use super::time::{clock_now, timespec_to_duration, TimerState};
+use crate::ctypes;
+use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+pub struct TimerFd {
+    clock: u32,
+    state: Mutex<TimerState>,
+    nonblocking: AtomicBool,
+    /// Where the readers wait for the next expiration.
+    wq: WaitQueue,
+    /// The number of settings, the readers wake up on a new one.
+    generation: AtomicU64,
+}
+
+impl TimerFd {
+    /// Creates a disarmed timer on the clock `clock`.
+    pub fn new(clock: u32) -> LinuxResult<Self> {
+        if clock != CLOCK_REALTIME && clock != CLOCK_MONOTONIC {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Self {
+            clock,
+            state: Mutex::new(TimerState::new()),
+            nonblocking: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<TimerFd>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Arms the timer to expire after `value` (or at `value` on the clock if
+    /// `absolute`), then every `interval` if it is not zero. A zero `value`
+    /// disarms the timer.
+    ///
+    /// Returns the previous setting, as [`TimerFd::get`].
+    pub fn set(&self, value: Duration, interval: Duration, absolute: bool) -> ctypes::itimerspec {
+        let now = clock_now(self.clock);
+        let old = self.state.lock().set(now, value, interval, absolute);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(true);
+        old
+    }
+
+    /// Returns the time until the next expiration and the interval.
+    pub fn get(&self) -> ctypes::itimerspec {
+        self.state.lock().to_itimerspec(clock_now(self.clock))
+    }
+
+    /// Takes the number of expirations, with the next one.
+    fn take_expirations(&self) -> (u64, Option<Duration>) {
+        let mut state = self.state.lock();
+        (
+            state.take_expirations(clock_now(self.clock)),
+            state.deadline,
+        )
+    }
+
+    /// Blocks until `deadline` on the clock, the setting after `generation`,
+    /// or the console break of the current task.
+    fn wait(&self, generation: u64, deadline: Option<Duration>) {
+        let curr = axtask::current();
+        let set = || self.generation.load(Ordering::Acquire) != generation;
+        // The token of the task is cancelled for good on its first break,
+        // the next breaks do not wake the reader.
+        let token = Some(curr.cancel_token()).filter(|token| !token.is_cancelled());
+        let timeout = deadline.map(|d| d.saturating_sub(clock_now(self.clock)));
+        match (timeout, token) {
+            (Some(dur), Some(token)) => {
+                self.wq.wait_timeout_until_cancellable(dur, token, set).ok();
+            }
+            (Some(dur), None) => {
+                self.wq.wait_timeout_until(dur, set);
+            }
+            (None, Some(token)) => {
+                self.wq.wait_until_cancellable(token, set).ok();
+            }
+            (None, None) => self.wq.wait_until(set),
+        }
+    }
+}
+
+impl FileLike for TimerFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LinuxError::EINVAL);
+        }
+        let expirations = loop {
+            let generation = self.generation.load(Ordering::Acquire);
+            match self.take_expirations() {
+                (0, _) if self.nonblocking.load(Ordering::Acquire) => {
+                    return Err(LinuxError::EAGAIN);
+                }
+                (0, _) if axtask::current().is_cancel_requested() => {
+                    return Err(LinuxError::EINTR);
+                }
+                // Timer not expired, wait for the clock
+                (0, deadline) => self.wait(generation, deadline),
+                (n, _) => break n,
+            }
+        };
+        buf[..8].copy_from_slice(&expirations.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600u32, // rw-------
+            st_uid: 1000,
+            st_gid: 1000,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let now = clock_now(self.clock);
+        Ok(PollState {
+            readable: self.state.lock().deadline.is_some_and(|d| d <= now),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Create a timer that notifies via a file descriptor
+///
+/// Return the new file descriptor if succeed
+pub fn sys_timerfd_create(clockid: ctypes::clockid_t, flags: c_int) -> c_int {
+    debug!("sys_timerfd_create <= {} {:#x}", clockid, flags);
+    syscall_body!(sys_timerfd_create, {
+        if flags as u32 & !(ctypes::TFD_NONBLOCK | ctypes::TFD_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let timer = TimerFd::new(clockid as u32)?;
+        timer.set_nonblocking(flags as u32 & ctypes::TFD_NONBLOCK != 0)?;
+        add_file_like(Arc::new(timer))
+    })
+}
+
+/// Arm or disarm the timer of a file descriptor
+///
+/// Return 0 if succeed
+pub unsafe fn sys_timerfd_settime(
+    fd: c_int,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timerfd_settime <= {} {:#x} {:#x} {:#x}",
+        fd, flags, new_value as usize, old_value as usize
+    );
+    syscall_body!(sys_timerfd_settime, {
+        if new_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if flags as u32 & !ctypes::TFD_TIMER_ABSTIME != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let new_value = unsafe { &*new_value };
+        let value = timespec_to_duration(&new_value.it_value)?;
+        let interval = timespec_to_duration(&new_value.it_interval)?;
+        let absolute = flags as u32 & ctypes::TFD_TIMER_ABSTIME != 0;
+        let old = TimerFd::from_fd(fd)?.set(value, interval, absolute);
+        if !old_value.is_null() {
+            unsafe { *old_value = old };
+        }
+        Ok(0)
+    })
+}
+
+/// Get the time until the next expiration of the timer of a file descriptor
+///
+/// Return 0 if succeed
+pub unsafe fn sys_timerfd_gettime(fd: c_int, curr_value: *mut ctypes::itimerspec) -> c_int {
+    debug!("sys_timerfd_gettime <= {} {:#x}", fd, curr_value as usize);
+    syscall_body!(sys_timerfd_gettime, {
+        if curr_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let curr = TimerFd::from_fd(fd)?.get();
+        unsafe { *curr_value = curr };
+        Ok(0)
+    })
+}
@@ -36,19 +36,33 @@ pub use imp::sys::sys_sysconf;
 pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
 pub use imp::time::{sys_clock_gettime, sys_nanosleep};
 
+#[cfg(feature = "eventfd")]
+pub use imp::eventfd::{sys_eventfd, EventFd, EventWait};
 #[cfg(feature = "fd")]
-pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl, get_file_like};
+pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl, sys_ioctl, get_file_like};
 #[cfg(feature = "fs")]
-pub use imp::fs::{sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat};
+pub use imp::fs::{
+    sys_fallocate, sys_fchdir, sys_fstat, sys_fstatat, sys_getcwd, sys_linkat, sys_lseek,
+    sys_lstat, sys_mkdirat, sys_open, sys_openat, sys_posix_fadvise, sys_rename, sys_renameat,
+    sys_renameat2, sys_stat, sys_umask, sys_unlinkat, sys_utimensat,
+};
+#[cfg(feature = "fs")]
+pub use imp::fs::{
+    sys_fgetxattr, sys_flistxattr, sys_fremovexattr, sys_fsetxattr, sys_getxattr, sys_listxattr,
+    sys_removexattr, sys_setxattr,
+};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
 pub use imp::io_mpx::{sys_epoll_create, sys_epoll_ctl, sys_epoll_wait};
+#[cfg(all(feature = "fs", feature = "net"))]
+pub use imp::net::sys_sendfile;
 #[cfg(feature = "net")]
+pub use imp::net::{sys_freeaddrinfo, sys_getaddrinfo, sys_getsockopt};
+#[cfg(any(feature = "net", feature = "vsock"))]
 pub use imp::net::{
-    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getpeername,
-    sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
-    sys_socket,
+    sys_accept, sys_bind, sys_connect, sys_getpeername, sys_getsockname, sys_listen, sys_recv,
+    sys_recvfrom, sys_send, sys_sendto, sys_shutdown, sys_socket,
 };
 #[cfg(feature = "pipe")]
 pub use imp::pipe::sys_pipe;
@@ -58,3 +72,11 @@ pub use imp::pthread::mutex::{
 };
 #[cfg(feature = "multitask")]
 pub use imp::pthread::{sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_self};
+#[cfg(feature = "signalfd")]
+pub use imp::signalfd::sys_signalfd;
+#[cfg(feature = "timer")]
+pub use imp::timer::{
+    sys_timer_create, sys_timer_delete, sys_timer_getoverrun, sys_timer_gettime, sys_timer_settime,
+};
+#[cfg(feature = "timerfd")]
+pub use imp::timerfd::{sys_timerfd_create, sys_timerfd_gettime, sys_timerfd_settime, TimerFd};
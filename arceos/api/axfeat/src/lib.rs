@@ -7,33 +7,68 @@
 //!     - `fp_simd`: Enable floating point and SIMD support.
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support.
+//!     - `irq-stats`: Record the durations and latencies of the IRQ handlers.
+//!     - `timer-broadcast`: Wake the idle CPUs by the timer of the primary CPU, for the boards
+//!       whose local timers stop in idle.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
 //!     - `alloc-tlsf`: Use the TLSF allocator.
 //!     - `alloc-slab`: Use the slab allocator.
 //!     - `alloc-buddy`: Use the buddy system allocator.
+//!     - `alloc-page-buddy`: Use the buddy page allocator, which coalesces the freed pages.
+//!     - `page-color`: Allocate the pages of each task from its cache colors, to partition the
+//!       last level cache.
+//!     - `heap-quota`: Limit the heap allocations of the tasks and of their jobs by quotas, the
+//!       allocations over them failing for the task only.
+//!     - `page-scrub`: Zero the freed pages in the background, for the user and guest memory.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
-//!     - `sched_fifo`: Use the FIFO cooperative scheduler.
-//!     - `sched_rr`: Use the Round-robin preemptive scheduler.
-//!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
-//! - Upperlayer stacks (fs, net, display)
+//!     - `sched_fifo`: Boot with the FIFO cooperative scheduler.
+//!     - `sched_rr`: Boot with the Round-robin preemptive scheduler.
+//!     - `sched_cfs`: Boot with the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_boost`: Run the tasks waking up from I/O waits first, for a lower latency.
+//!     - `sched_smt`: Spread the tasks across the physical cores before the SMT siblings.
+//!     - `sched_debug`: Check the invariants of the scheduler on every operation.
+//!     - `sched_profile`: Sample the profiled tasks on the timer ticks, for per-task flamegraphs.
+//!     - `irq-guard`: Report blocking in the IRQ handlers with a backtrace, for debugging.
+//!     - `nohz`: Allow isolating CPUs from the timer tick and the kernel work.
+//! - Upperlayer stacks (fs, net, display, sound, vsock)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `fs-procmaps`: List the memory maps of the processes in `/proc/[pid]/maps`.
+//!     - `fs-iscsi`: Mount the root filesystem on an iSCSI LUN, configured by the `AX_ISCSI_*` variables.
+//!     - `fs-http`: Mount the root filesystem read-only on a disk image at the URL `AX_HTTP_DISK_URL`.
+//!     - `fs-netboot`: Fetch the application bundle at the TFTP or HTTP URL `AX_NETBOOT_URL` into the filesystem at boot.
+//!     - `fs-snapshot`: Restore the state saved by `axruntime::snapshot::checkpoint` at boot, for warm starts.
 //!     - `net`: Enable networking support.
+//!     - `net-vlan`: Put the network interface on the 802.1Q VLAN given by `AX_VLAN`.
+//!     - `net-bridge`: Join all the NICs in a software bridge with MAC learning.
+//!     - `net-ptp`: Discipline the wall clock to a PTP (IEEE 1588) master.
+//!     - `net-wireguard`: Enable the WireGuard tunnels, configured by the `AX_WG_*` variables.
+//!     - `net-xdp`: Enable the AF_XDP-style sockets, sending and receiving raw frames through shared rings.
+//!     - `net-ip-frag`: Enable the IPv4 fragmentation and reassembly.
+//!     - `net-trace`: Export the spans of the W3C traces to the OTLP/HTTP collector at `AX_OTLP_ENDPOINT`.
 //!     - `display`: Enable graphics support.
+//!     - `display-terminal`: Show the console output on the display.
+//!     - `sound`: Enable sound support.
+//!     - `vsock`: Enable the VM sockets to talk to the host (virtio-vsock).
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
 //!     - `driver-ramdisk`: Use the RAM disk to emulate the block device.
 //!     - `driver-ixgbe`: Enable the Intel 82599 10Gbit NIC driver.
 //!     - `driver-bcm2835-sdhci`: Enable the BCM2835 SDHCI driver (Raspberry Pi SD card).
+//!     - `driver-sdhci`: Enable the driver of the standard SD host controllers.
+//!     - `iommu`: Confine the DMA of the PCI devices with the IOMMU (RISC-V IOMMU only).
+//!     - `sriov`: Enable the virtual functions of the SR-IOV capable PCI devices.
 //! - Logging
 //!     - `log-level-off`: Disable all logging.
 //!     - `log-level-error`, `log-level-warn`, `log-level-info`, `log-level-debug`,
 //!       `log-level-trace`: Keep logging only at the specified level or higher.
+//!     - `log-ring`: Write the log records into a lock-free ring, printed by a flusher task.
+//!     - `pstore`: Keep the log in RAM across warm reboots, to read the log of the previous boot.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 
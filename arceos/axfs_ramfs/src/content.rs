@@ -0,0 +1,154 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+
+/// Size of the pages backing the file content.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Sparse file content: only written pages are allocated, the holes read as
+/// zeros.
+#[derive(Default)]
+pub(crate) struct Content {
+    pages: BTreeMap<u64, Box<[u8]>>,
+    size: u64,
+}
+
+fn page_of(offset: u64) -> u64 {
+    offset / PAGE_SIZE as u64
+}
+
+fn page_start(page: u64) -> u64 {
+    page * PAGE_SIZE as u64
+}
+
+impl Content {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Number of allocated pages.
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn page_mut(&mut self, page: u64) -> &mut [u8] {
+        self.pages
+            .entry(page)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice())
+    }
+
+    /// Calls `f` on each `(page, offset in page, range in buffer)` covering
+    /// `len` bytes from `offset`.
+    fn for_each_chunk(offset: u64, len: usize, mut f: impl FnMut(u64, usize, usize, usize)) {
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let in_page = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - in_page).min(len - done);
+            f(page_of(pos), in_page, done, done + n);
+            done += n;
+        }
+    }
+
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        let len = buf.len().min((self.size - offset) as usize);
+        Self::for_each_chunk(offset, len, |page, in_page, start, end| {
+            let dst = &mut buf[start..end];
+            match self.pages.get(&page) {
+                Some(data) => dst.copy_from_slice(&data[in_page..in_page + dst.len()]),
+                None => dst.fill(0),
+            }
+        });
+        len
+    }
+
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> usize {
+        Self::for_each_chunk(offset, buf.len(), |page, in_page, start, end| {
+            let src = &buf[start..end];
+            self.page_mut(page)[in_page..in_page + src.len()].copy_from_slice(src);
+        });
+        self.size = self.size.max(offset + buf.len() as u64);
+        buf.len()
+    }
+
+    /// Zeroes `len` bytes from `offset`, freeing the pages entirely covered.
+    fn zero_range(&mut self, offset: u64, len: u64) {
+        let end = offset + len;
+        let mut pos = offset;
+        while pos < end {
+            let page = page_of(pos);
+            let in_page = (pos - page_start(page)) as usize;
+            let n = ((PAGE_SIZE - in_page) as u64).min(end - pos) as usize;
+            if n == PAGE_SIZE {
+                self.pages.remove(&page);
+            } else if let Some(data) = self.pages.get_mut(&page) {
+                data[in_page..in_page + n].fill(0);
+            }
+            pos += n as u64;
+        }
+    }
+
+    pub fn truncate(&mut self, size: u64) {
+        if size < self.size {
+            // Zero the tail of the last page, it must read as zeros if the
+            // file is extended again.
+            let tail = page_start(page_of(size) + 1).min(self.size);
+            self.zero_range(size, tail - size);
+            self.pages.split_off(&page_of(size + PAGE_SIZE as u64 - 1));
+        }
+        self.size = size;
+    }
+
+    /// Allocates the pages covering `len` bytes from `offset`, extending the
+    /// file unless `keep_size`.
+    pub fn allocate(&mut self, offset: u64, len: u64, keep_size: bool) {
+        if len == 0 {
+            return;
+        }
+        for page in page_of(offset)..=page_of(offset + len - 1) {
+            self.page_mut(page);
+        }
+        if !keep_size {
+            self.size = self.size.max(offset + len);
+        }
+    }
+
+    /// Deallocates `len` bytes from `offset`, which then read as zeros. The
+    /// size is unchanged.
+    pub fn punch_hole(&mut self, offset: u64, len: u64) {
+        // Past the end everything reads as zeros, the last page can be freed.
+        let end = offset
+            .saturating_add(len)
+            .min(page_start(page_of(self.size + PAGE_SIZE as u64 - 1)));
+        if offset < end {
+            self.zero_range(offset, end - offset);
+        }
+    }
+
+    /// Returns the first offset at or after `offset` that is in data, `None`
+    /// if there is no more data.
+    pub fn seek_data(&self, offset: u64) -> Option<u64> {
+        if offset >= self.size {
+            return None;
+        }
+        let (&page, _) = self.pages.range(page_of(offset)..).next()?;
+        let pos = offset.max(page_start(page));
+        (pos < self.size).then_some(pos)
+    }
+
+    /// Returns the first offset at or after `offset` that is in a hole, the
+    /// end of the file being a hole. `None` if `offset` is past the end.
+    pub fn seek_hole(&self, offset: u64) -> Option<u64> {
+        if offset >= self.size {
+            return None;
+        }
+        let mut page = page_of(offset);
+        while self.pages.contains_key(&page) {
+            page += 1;
+        }
+        Some(offset.max(page_start(page)).min(self.size))
+    }
+}
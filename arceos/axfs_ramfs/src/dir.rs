@@ -3,7 +3,9 @@ use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 
 use crate::file::FileNode;
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use crate::xattr::Xattrs;
+use crate::{Clock, NodeTimes};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
 use log::*;
 use spin::RwLock;
@@ -15,17 +17,78 @@ pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    perm: RwLock<VfsNodePerm>,
+    times: RwLock<NodeTimes>,
+    xattrs: Xattrs,
+    clock: Clock,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>, clock: Clock) -> Arc<Self> {
         Arc::new_cyclic(|this| Self {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
+            perm: RwLock::new(VfsNodePerm::default_dir()),
+            times: RwLock::new(NodeTimes::new(clock())),
+            xattrs: Xattrs::default(),
+            clock,
         })
     }
 
+    /// Returns the permission bits of the directory.
+    pub fn perm(&self) -> VfsNodePerm {
+        *self.perm.read()
+    }
+
+    /// Sets the permission bits of the directory.
+    pub fn set_perm(&self, perm: VfsNodePerm) {
+        *self.perm.write() = perm;
+        self.times.write().ctime = (self.clock)();
+    }
+
+    /// Returns the timestamps of the directory.
+    ///
+    /// The modification and change times are updated when entries are
+    /// added or removed, the access time is left to the caller.
+    pub fn times(&self) -> NodeTimes {
+        *self.times.read()
+    }
+
+    /// Sets the timestamps of the directory.
+    pub fn set_times(&self, times: NodeTimes) {
+        *self.times.write() = times;
+    }
+
+    /// Returns the value of the extended attribute `name`.
+    pub fn get_xattr(&self, name: &str) -> Option<Vec<u8>> {
+        self.xattrs.get(name)
+    }
+
+    /// Sets the extended attribute `name`, returns whether it existed.
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> bool {
+        self.times.write().ctime = (self.clock)();
+        self.xattrs.set(name, value)
+    }
+
+    /// Removes the extended attribute `name`, returns whether it existed.
+    pub fn remove_xattr(&self, name: &str) -> bool {
+        let existed = self.xattrs.remove(name);
+        if existed {
+            self.times.write().ctime = (self.clock)();
+        }
+        existed
+    }
+
+    /// Returns the names of all extended attributes.
+    pub fn list_xattr(&self) -> Vec<String> {
+        self.xattrs.list()
+    }
+
+    fn touch(&self) {
+        self.times.write().modified((self.clock)());
+    }
+
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
@@ -47,11 +110,12 @@ impl DirNode {
             return Err(VfsError::AlreadyExists);
         }
         let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::File => Arc::new(FileNode::new(self.clock)),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), self.clock),
             _ => return Err(VfsError::Unsupported),
         };
         self.children.write().insert(name.into(), node);
+        self.touch();
         Ok(())
     }
 
@@ -65,13 +129,14 @@ impl DirNode {
             }
         }
         children.remove(name);
+        self.touch();
         Ok(())
     }
 }
 
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        Ok(VfsNodeAttr::new(self.perm(), VfsNodeType::Dir, 4096, 0))
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -198,13 +263,15 @@ impl VfsNodeOps for DirNode {
         let dst_dir_node = this.clone().lookup(dst_dir_name)?;
 
         // 将原节点插入目标目录
-        dst_dir_node
+        let dst_dir = dst_dir_node
             .as_any()
             .downcast_ref::<DirNode>()
-            .ok_or(VfsError::NotFound)?
+            .ok_or(VfsError::NotFound)?;
+        dst_dir
             .children
             .write()
             .insert(String::from(dst_name), src_node.clone());
+        dst_dir.touch();
 
         // 当src_node为目录节点时，重置父节点
         if let Some(dir_node) = src_node.as_any().downcast_ref::<DirNode>() {
@@ -212,13 +279,23 @@ impl VfsNodeOps for DirNode {
         }
 
         // 删除原节点
-        src_dir_node
+        let src_dir = src_dir_node
             .as_any()
             .downcast_ref::<DirNode>()
-            .ok_or(VfsError::NotFound)?
-            .children
-            .write()
-            .remove(src_name);
+            .ok_or(VfsError::NotFound)?;
+        src_dir.children.write().remove(src_name);
+        src_dir.touch();
+
+        // 重命名修改了节点的元数据
+        let now = (self.clock)();
+        if let Some(file) = src_node.as_any().downcast_ref::<FileNode>() {
+            file.set_times(NodeTimes {
+                ctime: now,
+                ..file.times()
+            });
+        } else if let Some(dir) = src_node.as_any().downcast_ref::<DirNode>() {
+            dir.times.write().ctime = now;
+        }
 
         Ok(())
     }
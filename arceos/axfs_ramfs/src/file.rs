@@ -1,55 +1,143 @@
-use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use alloc::{string::String, vec::Vec};
+use axfs_vfs::VfsResult;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
 use spin::RwLock;
 
+use crate::content::{Content, PAGE_SIZE};
+use crate::xattr::Xattrs;
+use crate::{Clock, NodeTimes};
+
 /// The file node in the RAM filesystem.
 ///
-/// It implements [`axfs_vfs::VfsNodeOps`].
+/// It implements [`axfs_vfs::VfsNodeOps`]. Files are sparse: the pages never
+/// written are holes, they read as zeros and take no memory.
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    content: RwLock<Content>,
+    perm: RwLock<VfsNodePerm>,
+    times: RwLock<NodeTimes>,
+    xattrs: Xattrs,
+    clock: Clock,
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
+    pub(super) fn new(clock: Clock) -> Self {
         Self {
-            content: RwLock::new(Vec::new()),
+            content: RwLock::new(Content::default()),
+            perm: RwLock::new(VfsNodePerm::default_file()),
+            times: RwLock::new(NodeTimes::new(clock())),
+            xattrs: Xattrs::default(),
+            clock,
         }
     }
+
+    /// Returns the permission bits of the file.
+    pub fn perm(&self) -> VfsNodePerm {
+        *self.perm.read()
+    }
+
+    /// Sets the permission bits of the file.
+    pub fn set_perm(&self, perm: VfsNodePerm) {
+        *self.perm.write() = perm;
+        self.times.write().ctime = (self.clock)();
+    }
+
+    /// Returns the timestamps of the file.
+    ///
+    /// The modification and change times are updated by writes, the access
+    /// time is left to the caller.
+    pub fn times(&self) -> NodeTimes {
+        *self.times.read()
+    }
+
+    /// Sets the timestamps of the file.
+    pub fn set_times(&self, times: NodeTimes) {
+        *self.times.write() = times;
+    }
+
+    /// Returns the value of the extended attribute `name`.
+    pub fn get_xattr(&self, name: &str) -> Option<Vec<u8>> {
+        self.xattrs.get(name)
+    }
+
+    /// Sets the extended attribute `name`, returns whether it existed.
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> bool {
+        self.times.write().ctime = (self.clock)();
+        self.xattrs.set(name, value)
+    }
+
+    /// Removes the extended attribute `name`, returns whether it existed.
+    pub fn remove_xattr(&self, name: &str) -> bool {
+        let existed = self.xattrs.remove(name);
+        if existed {
+            self.times.write().ctime = (self.clock)();
+        }
+        existed
+    }
+
+    /// Returns the names of all extended attributes.
+    pub fn list_xattr(&self) -> Vec<String> {
+        self.xattrs.list()
+    }
+
+    /// Returns the first offset at or after `offset` that is in data, or
+    /// `None` if there is no data past `offset`.
+    pub fn seek_data(&self, offset: u64) -> Option<u64> {
+        self.content.read().seek_data(offset)
+    }
+
+    /// Returns the first offset at or after `offset` that is in a hole, the
+    /// end of the file counting as a hole, or `None` if `offset` is past the
+    /// end of the file.
+    pub fn seek_hole(&self, offset: u64) -> Option<u64> {
+        self.content.read().seek_hole(offset)
+    }
+
+    /// Allocates memory for `len` bytes from `offset`, growing the file if
+    /// needed unless `keep_size` is set.
+    pub fn allocate(&self, offset: u64, len: u64, keep_size: bool) {
+        self.content.write().allocate(offset, len, keep_size);
+        self.times.write().modified((self.clock)());
+    }
+
+    /// Frees the memory of `len` bytes from `offset`, which then read as
+    /// zeros. The file size is not changed.
+    pub fn punch_hole(&self, offset: u64, len: u64) {
+        self.content.write().punch_hole(offset, len);
+        self.times.write().modified((self.clock)());
+    }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        let blocks = content.allocated_pages() * PAGE_SIZE / 512;
+        Ok(VfsNodeAttr::new(
+            self.perm(),
+            VfsNodeType::File,
+            content.size(),
+            blocks as _,
+        ))
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
-        let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
-        }
+        self.content.write().truncate(size);
+        self.times.write().modified((self.clock)());
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        Ok(self.content.read().read_at(offset, buf))
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
-        let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
-        }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        let n = self.content.write().write_at(offset, buf);
+        self.times.write().modified((self.clock)());
+        Ok(n)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        // Nothing to write back.
+        Ok(())
     }
 
     impl_vfs_non_dir_default! {}
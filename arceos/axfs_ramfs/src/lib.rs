@@ -6,8 +6,10 @@
 
 extern crate alloc;
 
+mod content;
 mod dir;
 mod file;
+mod xattr;
 
 #[cfg(test)]
 mod tests;
@@ -17,8 +19,42 @@ pub use self::file::FileNode;
 
 use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use core::time::Duration;
 use spin::once::Once;
 
+/// A function returning the current time, used to timestamp the nodes.
+pub type Clock = fn() -> Duration;
+
+/// Timestamps of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeTimes {
+    /// Time of last access.
+    pub atime: Duration,
+    /// Time of last modification of the content.
+    pub mtime: Duration,
+    /// Time of last change of the content or the metadata.
+    pub ctime: Duration,
+}
+
+impl NodeTimes {
+    fn new(now: Duration) -> Self {
+        Self {
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    fn modified(&mut self, now: Duration) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+fn zero_clock() -> Duration {
+    Duration::ZERO
+}
+
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
@@ -26,11 +62,16 @@ pub struct RamFileSystem {
 }
 
 impl RamFileSystem {
-    /// Create a new instance.
+    /// Create a new instance, all timestamps are zero.
     pub fn new() -> Self {
+        Self::with_clock(zero_clock)
+    }
+
+    /// Create a new instance, timestamping the nodes with `clock`.
+    pub fn with_clock(clock: Clock) -> Self {
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root: DirNode::new(None, clock),
         }
     }
 
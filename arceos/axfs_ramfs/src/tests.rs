@@ -134,3 +134,102 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_ramfs_times() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    use axfs_vfs::VfsNodeOps;
+
+    static NOW: AtomicU64 = AtomicU64::new(1);
+    fn clock() -> Duration {
+        Duration::from_secs(NOW.load(Ordering::Relaxed))
+    }
+
+    let ramfs = RamFileSystem::with_clock(clock);
+    let root = ramfs.root_dir_node();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f1").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.times(), NodeTimes::new(Duration::from_secs(1)));
+
+    NOW.store(2, Ordering::Relaxed);
+    node.read_at(0, &mut [0; 4]).unwrap();
+    assert_eq!(file.times().mtime, Duration::from_secs(1));
+    node.write_at(0, b"data").unwrap();
+    assert_eq!(file.times().atime, Duration::from_secs(1));
+    assert_eq!(file.times().mtime, Duration::from_secs(2));
+    assert_eq!(file.times().ctime, Duration::from_secs(2));
+    assert_eq!(root.times().mtime, Duration::from_secs(1));
+
+    NOW.store(3, Ordering::Relaxed);
+    root.create("f2", VfsNodeType::File).unwrap();
+    assert_eq!(root.times().mtime, Duration::from_secs(3));
+    root.rename("f1", "f3").unwrap();
+    assert_eq!(file.times().mtime, Duration::from_secs(2));
+    assert_eq!(file.times().ctime, Duration::from_secs(3));
+}
+
+#[test]
+fn test_ramfs_xattr() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert_eq!(root.get_xattr("user.a"), None);
+    assert!(!root.set_xattr("user.a", b"1"));
+    assert!(root.set_xattr("user.a", b"2"));
+    assert!(!root.set_xattr("user.b", b""));
+    assert_eq!(root.get_xattr("user.a").as_deref(), Some(&b"2"[..]));
+    assert_eq!(root.list_xattr(), ["user.a", "user.b"]);
+    assert!(root.remove_xattr("user.a"));
+    assert!(!root.remove_xattr("user.a"));
+    assert_eq!(root.list_xattr(), ["user.b"]);
+}
+
+#[test]
+fn test_ramfs_sparse() {
+    use crate::content::PAGE_SIZE;
+    use axfs_vfs::VfsNodeOps;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    let page = PAGE_SIZE as u64;
+
+    // One page of data after a two-page hole.
+    node.write_at(2 * page + 10, b"data").unwrap();
+    let attr = node.get_attr().unwrap();
+    assert_eq!(attr.size(), 2 * page + 14);
+    assert_eq!(attr.blocks(), page / 512);
+    let mut buf = [1; 8];
+    assert_eq!(node.read_at(page, &mut buf).unwrap(), 8);
+    assert_eq!(buf, [0; 8]);
+    assert_eq!(file.seek_data(0), Some(2 * page));
+    assert_eq!(file.seek_data(2 * page + 12), Some(2 * page + 12));
+    assert_eq!(file.seek_hole(0), Some(0));
+    assert_eq!(file.seek_hole(2 * page), Some(2 * page + 14));
+    assert_eq!(file.seek_data(2 * page + 14), None);
+
+    file.punch_hole(2 * page + 11, 2);
+    assert_eq!(node.read_at(2 * page + 10, &mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"d\0\0a");
+    file.punch_hole(2 * page, page);
+    assert_eq!(node.get_attr().unwrap().blocks(), 0);
+    assert_eq!(file.seek_data(0), None);
+
+    file.allocate(0, page + 1, true);
+    assert_eq!(node.get_attr().unwrap().blocks(), 2 * page / 512);
+    assert_eq!(node.get_attr().unwrap().size(), 2 * page + 14);
+    file.allocate(3 * page, 1, false);
+    assert_eq!(node.get_attr().unwrap().size(), 3 * page + 1);
+
+    // Shrinking clears the tail of the last page.
+    node.write_at(0, b"abcd").unwrap();
+    node.truncate(2).unwrap();
+    node.truncate(4).unwrap();
+    assert_eq!(node.read_at(0, &mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"ab\0\0");
+    assert_eq!(node.get_attr().unwrap().blocks(), page / 512);
+}
@@ -0,0 +1,27 @@
+use alloc::collections::BTreeMap;
+use alloc::{string::String, vec::Vec};
+use spin::RwLock;
+
+/// Extended attributes of a node.
+#[derive(Default)]
+pub(crate) struct Xattrs(RwLock<BTreeMap<String, Vec<u8>>>);
+
+impl Xattrs {
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.0.read().get(name).cloned()
+    }
+
+    /// Inserts or replaces an attribute, returns whether it existed.
+    pub fn set(&self, name: &str, value: &[u8]) -> bool {
+        self.0.write().insert(name.into(), value.into()).is_some()
+    }
+
+    /// Removes an attribute, returns whether it existed.
+    pub fn remove(&self, name: &str) -> bool {
+        self.0.write().remove(name).is_some()
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.0.read().keys().cloned().collect()
+    }
+}
@@ -0,0 +1,87 @@
+//! Compares the allocators of ArceOS on the workloads of `alloc_bench`.
+//!
+//! Each allocator manages the same static arena in turn, and the reports are
+//! printed as a table. It runs both on the host (`cargo run --release`) and
+//! as an ArceOS app (`make A=examples/alloc_bench run`), where the harness
+//! keeps its bookkeeping in the global allocator.
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+use std::time::Instant;
+use std::vec::Vec;
+
+use alloc_bench::{run_bytes, run_pages, Report, Table, Workload};
+use allocator::BaseAllocator;
+use buddy_allocator::BuddyPageAllocator;
+use bump_allocator::EarlyAllocator;
+use slab_allocator::SlabByteAllocator;
+use tlsf_allocator::TlsfByteAllocator;
+
+const ARENA_SIZE: usize = 16 << 20;
+
+#[repr(align(4096))]
+struct Arena([u8; ARENA_SIZE]);
+
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+
+/// Gives the whole arena to `alloc`, which takes it over from the previous
+/// allocator.
+fn init(alloc: &mut impl BaseAllocator) {
+    let start = unsafe { core::ptr::addr_of_mut!(ARENA.0) } as usize;
+    alloc.init(start, ARENA_SIZE);
+}
+
+fn bench_bytes(workload: &Workload, now: impl Fn() -> u64 + Copy, reports: &mut Vec<Report>) {
+    let mut bump = EarlyAllocator::<4096>::new();
+    init(&mut bump);
+    reports.push(run_bytes("bump", &mut bump, workload, now));
+
+    let mut slab = SlabByteAllocator::new(BuddyPageAllocator::<4096>::new());
+    init(&mut slab);
+    reports.push(run_bytes("slab+buddy", &mut slab, workload, now));
+
+    let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+    init(&mut tlsf);
+    reports.push(run_bytes("tlsf", &mut tlsf, workload, now));
+}
+
+fn bench_pages(workload: &Workload, now: impl Fn() -> u64 + Copy, reports: &mut Vec<Report>) {
+    let mut bump = EarlyAllocator::<4096>::new();
+    init(&mut bump);
+    reports.push(run_pages("bump", &mut bump, 0, workload, now));
+
+    let mut buddy = BuddyPageAllocator::<4096>::new();
+    init(&mut buddy);
+    reports.push(run_pages("buddy", &mut buddy, 4096, workload, now));
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    let clock = Instant::now();
+    let now = || clock.elapsed().as_nanos() as u64;
+
+    let mut reports = Vec::new();
+    for workload in [
+        Workload::random(),
+        Workload::producer_consumer(),
+        Workload::fragmentation(),
+    ] {
+        bench_bytes(&workload, now, &mut reports);
+    }
+    for workload in [Workload::random(), Workload::producer_consumer()] {
+        bench_pages(
+            &Workload {
+                max_live: 512,
+                ..workload.pages(8)
+            },
+            now,
+            &mut reports,
+        );
+    }
+    println!("{}", Table(&reports));
+}
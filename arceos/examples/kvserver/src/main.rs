@@ -0,0 +1,137 @@
+//! In-kernel key/value cache server, speaking the memcached text protocol.
+//!
+//! With `axstd`, requests are parsed straight out of the socket receive
+//! buffer ([`TcpStream::recv_with`]); only partial requests split across
+//! segments are staged in a per-connection buffer. The parsed requests are
+//! served once the buffer is given back, as the network stack is locked
+//! meanwhile.
+//!
+//! Benchmark with [memtier_benchmark](https://github.com/RedisLabs/memtier_benchmark):
+//!
+//! ```
+//! memtier_benchmark -s X.X.X.X -p 11211 -P memcache_text --hide-histogram
+//! ```
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+mod protocol;
+mod store;
+
+use std::io::{self, prelude::*};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::vec::Vec;
+
+use self::store::Store;
+
+const LOCAL_IP: &str = "0.0.0.0";
+const LOCAL_PORT: u16 = 11211;
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        match option_env!("LOG") {
+            Some("info") | Some("debug") | Some("trace") => {
+                print!("[INFO] {}\n", format_args!($($arg)*));
+            }
+            _ => {}
+        }
+    };
+}
+
+/// Feeds the received bytes to `f`, which must not use any socket. Returns
+/// `false` at EOF.
+#[cfg(feature = "axstd")]
+fn recv_with(stream: &mut TcpStream, mut f: impl FnMut(&[u8])) -> io::Result<bool> {
+    let n = stream.recv_with(|buf| {
+        f(buf);
+        buf.len()
+    })?;
+    Ok(n.is_some())
+}
+
+/// Feeds the received bytes to `f`, returns `false` at EOF.
+#[cfg(not(feature = "axstd"))]
+fn recv_with(stream: &mut TcpStream, mut f: impl FnMut(&[u8])) -> io::Result<bool> {
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf)?;
+    f(&buf[..n]);
+    Ok(n > 0)
+}
+
+fn kv_client(mut stream: TcpStream, store: &Store) -> io::Result<()> {
+    let mut pending = Vec::new();
+    let mut requests = Vec::new();
+    let mut out = Vec::new();
+    let mut close = false;
+    while !close {
+        // Only parse with the receive buffer borrowed, the store is not
+        // touched until it is given back.
+        let open = recv_with(&mut stream, |data| {
+            if pending.is_empty() {
+                // Fast path: parse in place, keep only the partial tail.
+                let (used, c) = protocol::parse_all(data, &mut requests);
+                pending.extend_from_slice(&data[used..]);
+                close = c;
+            } else {
+                pending.extend_from_slice(data);
+                let (used, c) = protocol::parse_all(&pending, &mut requests);
+                pending.drain(..used);
+                close = c;
+            }
+        })?;
+        if !open {
+            break;
+        }
+        for req in requests.drain(..) {
+            if protocol::execute(req, store, &mut out) {
+                close = true;
+                break;
+            }
+        }
+        if !out.is_empty() {
+            stream.write_all(&out)?;
+            out.clear();
+        }
+    }
+    Ok(())
+}
+
+fn accept_loop() -> io::Result<()> {
+    let listener = TcpListener::bind((LOCAL_IP, LOCAL_PORT))?;
+    println!("listen on: {}", listener.local_addr().unwrap());
+
+    let store = Arc::new(Store::new());
+    let mut i = 0;
+    loop {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                info!("new client {}: {}", i, addr);
+                let store = store.clone();
+                thread::spawn(move || {
+                    let conns = &store.stats.curr_connections;
+                    conns.fetch_add(1, Ordering::Relaxed);
+                    match kv_client(stream, &store) {
+                        Err(e) => info!("client connection error: {:?}", e),
+                        Ok(()) => info!("client {} closed successfully", i),
+                    }
+                    conns.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            Err(e) => return Err(e),
+        }
+        i += 1;
+    }
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Hello, ArceOS key/value cache server!");
+    accept_loop().expect("test key/value server failed");
+}
@@ -0,0 +1,384 @@
+//! The memcached text protocol.
+//!
+//! See <https://github.com/memcached/memcached/blob/master/doc/protocol.txt>.
+//! Supported commands: `get`, `gets`, `set`, `add`, `replace`, `append`,
+//! `prepend`, `delete`, `incr`, `decr`, `touch`, `flush_all`, `stats`,
+//! `version` and `quit`.
+
+use std::str;
+use std::sync::atomic::Ordering;
+use std::vec::Vec;
+
+use crate::store::{Item, Store};
+
+/// Longest command line accepted before the client is considered broken.
+pub const MAX_LINE_LEN: usize = 2048;
+/// Largest value accepted by storage commands (1 MiB, as memcached).
+pub const MAX_VALUE_LEN: usize = 1 << 20;
+
+/// A parsed request, owning its keys and data.
+pub enum Request {
+    Get {
+        keys: Vec<Vec<u8>>,
+        with_cas: bool,
+    },
+    Store {
+        cmd: StoreCmd,
+        key: Vec<u8>,
+        flags: u32,
+        exptime: u32,
+        data: Vec<u8>,
+        noreply: bool,
+    },
+    Delete {
+        key: Vec<u8>,
+        noreply: bool,
+    },
+    Arith {
+        incr: bool,
+        key: Vec<u8>,
+        delta: u64,
+        noreply: bool,
+    },
+    Touch {
+        key: Vec<u8>,
+        exptime: u32,
+        noreply: bool,
+    },
+    FlushAll {
+        noreply: bool,
+    },
+    Stats,
+    Version,
+    /// A malformed request, answered with the given line.
+    Error(&'static [u8]),
+    /// A request that cannot be served, answered with the given line before
+    /// closing the connection.
+    Fatal(&'static [u8]),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StoreCmd {
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+}
+
+pub enum Outcome {
+    /// One request of the given length was parsed.
+    Done(usize, Request),
+    /// The buffer does not contain a full request yet.
+    Incomplete,
+    /// The client asked to close the connection, or sent garbage.
+    Close,
+}
+
+fn find_crlf(buf: &[u8]) -> Option<usize> {
+    buf.windows(2).position(|w| w == b"\r\n")
+}
+
+fn parse_num<T: str::FromStr>(tok: Option<&[u8]>) -> Option<T> {
+    str::from_utf8(tok?).ok()?.parse().ok()
+}
+
+/// Parses all complete requests at the beginning of `buf` into `requests`.
+/// Returns the number of bytes consumed and whether the connection should be
+/// closed after serving them.
+///
+/// It does not touch the store, so that it can run while the receive buffer
+/// is borrowed from the network stack.
+pub fn parse_all(buf: &[u8], requests: &mut Vec<Request>) -> (usize, bool) {
+    let mut pos = 0;
+    loop {
+        match parse(&buf[pos..]) {
+            Outcome::Done(n, req) => {
+                pos += n;
+                let fatal = matches!(req, Request::Fatal(_));
+                requests.push(req);
+                if fatal {
+                    return (pos, true);
+                }
+            }
+            Outcome::Incomplete => return (pos, buf.len() - pos > MAX_LINE_LEN + MAX_VALUE_LEN),
+            Outcome::Close => return (pos, true),
+        }
+    }
+}
+
+/// Parses one request from the beginning of `buf`.
+pub fn parse(buf: &[u8]) -> Outcome {
+    let line_len = match find_crlf(buf) {
+        Some(n) => n,
+        None if buf.len() > MAX_LINE_LEN => return Outcome::Close,
+        None => return Outcome::Incomplete,
+    };
+    let mut tokens = buf[..line_len]
+        .split(|&b| b == b' ')
+        .filter(|t| !t.is_empty());
+    let consumed = line_len + 2;
+    let Some(cmd) = tokens.next() else {
+        return Outcome::Done(consumed, Request::Error(b"ERROR\r\n"));
+    };
+
+    let req = match cmd {
+        b"get" | b"gets" => Request::Get {
+            keys: tokens.map(<[u8]>::to_vec).collect(),
+            with_cas: cmd == b"gets",
+        },
+        b"set" | b"add" | b"replace" | b"append" | b"prepend" => {
+            let cmd = match cmd {
+                b"set" => StoreCmd::Set,
+                b"add" => StoreCmd::Add,
+                b"replace" => StoreCmd::Replace,
+                b"append" => StoreCmd::Append,
+                _ => StoreCmd::Prepend,
+            };
+            let key = tokens.next();
+            let flags = parse_num::<u32>(tokens.next());
+            let exptime = parse_num::<u32>(tokens.next());
+            let bytes = parse_num::<usize>(tokens.next());
+            let noreply = tokens.next() == Some(&b"noreply"[..]);
+            let (Some(key), Some(flags), Some(exptime), Some(bytes)) = (key, flags, exptime, bytes)
+            else {
+                let req = Request::Error(b"CLIENT_ERROR bad command line format\r\n");
+                return Outcome::Done(consumed, req);
+            };
+            if bytes > MAX_VALUE_LEN {
+                let req = Request::Fatal(b"SERVER_ERROR object too large for cache\r\n");
+                return Outcome::Done(consumed, req);
+            }
+            let total = consumed + bytes + 2;
+            if buf.len() < total {
+                return Outcome::Incomplete;
+            }
+            if &buf[consumed + bytes..total] != b"\r\n" {
+                return Outcome::Done(total, Request::Error(b"CLIENT_ERROR bad data chunk\r\n"));
+            }
+            let req = Request::Store {
+                cmd,
+                key: key.to_vec(),
+                flags,
+                exptime,
+                data: buf[consumed..consumed + bytes].to_vec(),
+                noreply,
+            };
+            return Outcome::Done(total, req);
+        }
+        b"delete" => match tokens.next() {
+            Some(key) => Request::Delete {
+                key: key.to_vec(),
+                noreply: tokens.next() == Some(&b"noreply"[..]),
+            },
+            None => Request::Error(b"ERROR\r\n"),
+        },
+        b"incr" | b"decr" => {
+            let key = tokens.next();
+            let delta = parse_num::<u64>(tokens.next());
+            let noreply = tokens.next() == Some(&b"noreply"[..]);
+            match (key, delta) {
+                (Some(key), Some(delta)) => Request::Arith {
+                    incr: cmd == b"incr",
+                    key: key.to_vec(),
+                    delta,
+                    noreply,
+                },
+                _ => Request::Error(b"CLIENT_ERROR invalid numeric delta argument\r\n"),
+            }
+        }
+        b"touch" => {
+            let key = tokens.next();
+            let exptime = parse_num::<u32>(tokens.next());
+            let noreply = tokens.next() == Some(&b"noreply"[..]);
+            match (key, exptime) {
+                (Some(key), Some(exptime)) => Request::Touch {
+                    key: key.to_vec(),
+                    exptime,
+                    noreply,
+                },
+                _ => Request::Error(b"ERROR\r\n"),
+            }
+        }
+        b"flush_all" => Request::FlushAll {
+            noreply: tokens.any(|t| t == b"noreply"),
+        },
+        b"stats" => Request::Stats,
+        b"version" => Request::Version,
+        b"quit" => return Outcome::Close,
+        _ => Request::Error(b"ERROR\r\n"),
+    };
+    Outcome::Done(consumed, req)
+}
+
+/// Serves one parsed request, appending the response to `out`. Returns
+/// whether the connection should be closed.
+pub fn execute(req: Request, store: &Store, out: &mut Vec<u8>) -> bool {
+    match req {
+        Request::Get { keys, with_cas } => {
+            for key in &keys {
+                store.with(key, |item| match item {
+                    Some(item) => {
+                        store.stats.get_hits.fetch_add(1, Ordering::Relaxed);
+                        out.extend_from_slice(b"VALUE ");
+                        out.extend_from_slice(key);
+                        out.extend_from_slice(
+                            format!(" {} {}", item.flags, item.data.len()).as_bytes(),
+                        );
+                        if with_cas {
+                            out.extend_from_slice(b" 0");
+                        }
+                        out.extend_from_slice(b"\r\n");
+                        out.extend_from_slice(&item.data);
+                        out.extend_from_slice(b"\r\n");
+                    }
+                    None => {
+                        store.stats.get_misses.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+            out.extend_from_slice(b"END\r\n");
+        }
+        Request::Store {
+            cmd,
+            key,
+            flags,
+            exptime,
+            data,
+            noreply,
+        } => {
+            let stored = store_cmd(store, cmd, &key, flags, exptime, data);
+            if !noreply {
+                let reply: &[u8] = if stored {
+                    b"STORED\r\n"
+                } else {
+                    b"NOT_STORED\r\n"
+                };
+                out.extend_from_slice(reply);
+            }
+        }
+        Request::Delete { key, noreply } => {
+            let deleted = store.delete(&key);
+            if !noreply {
+                let reply: &[u8] = if deleted {
+                    b"DELETED\r\n"
+                } else {
+                    b"NOT_FOUND\r\n"
+                };
+                out.extend_from_slice(reply);
+            }
+        }
+        Request::Arith {
+            incr,
+            key,
+            delta,
+            noreply,
+        } => {
+            let mut reply = Vec::new();
+            store.with(&key, |item| match item {
+                Some(item) => match parse_num::<u64>(Some(&item.data)) {
+                    Some(val) => {
+                        let val = if incr {
+                            val.wrapping_add(delta)
+                        } else {
+                            val.saturating_sub(delta)
+                        };
+                        item.data = format!("{}", val).into_bytes();
+                        reply.extend_from_slice(format!("{}\r\n", val).as_bytes());
+                    }
+                    None => reply.extend_from_slice(
+                        b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+                    ),
+                },
+                None => reply.extend_from_slice(b"NOT_FOUND\r\n"),
+            });
+            if !noreply {
+                out.extend_from_slice(&reply);
+            }
+        }
+        Request::Touch {
+            key,
+            exptime,
+            noreply,
+        } => {
+            let touched = store.with(&key, |item| match item {
+                Some(item) => {
+                    let data = core::mem::take(&mut item.data);
+                    *item = Item::new(item.flags, data, exptime);
+                    true
+                }
+                None => false,
+            });
+            if !noreply {
+                let reply: &[u8] = if touched {
+                    b"TOUCHED\r\n"
+                } else {
+                    b"NOT_FOUND\r\n"
+                };
+                out.extend_from_slice(reply);
+            }
+        }
+        Request::FlushAll { noreply } => {
+            store.flush_all();
+            if !noreply {
+                out.extend_from_slice(b"OK\r\n");
+            }
+        }
+        Request::Stats => {
+            let stats = &store.stats;
+            let reply = format!(
+                "STAT curr_items {}\r\n\
+                 STAT curr_connections {}\r\n\
+                 STAT cmd_set {}\r\n\
+                 STAT get_hits {}\r\n\
+                 STAT get_misses {}\r\n\
+                 END\r\n",
+                store.len(),
+                stats.curr_connections.load(Ordering::Relaxed),
+                stats.cmd_set.load(Ordering::Relaxed),
+                stats.get_hits.load(Ordering::Relaxed),
+                stats.get_misses.load(Ordering::Relaxed),
+            );
+            out.extend_from_slice(reply.as_bytes());
+        }
+        Request::Version => out.extend_from_slice(b"VERSION 1.6.0-arceos\r\n"),
+        Request::Error(reply) => out.extend_from_slice(reply),
+        Request::Fatal(reply) => {
+            out.extend_from_slice(reply);
+            return true;
+        }
+    }
+    false
+}
+
+fn store_cmd(
+    store: &Store,
+    cmd: StoreCmd,
+    key: &[u8],
+    flags: u32,
+    exptime: u32,
+    data: Vec<u8>,
+) -> bool {
+    match cmd {
+        StoreCmd::Set => {
+            store.set(key, Item::new(flags, data, exptime));
+            true
+        }
+        StoreCmd::Add | StoreCmd::Replace => store.set_if(
+            key,
+            Item::new(flags, data, exptime),
+            cmd == StoreCmd::Replace,
+        ),
+        _ => store.with(key, |item| match item {
+            Some(item) => {
+                if cmd == StoreCmd::Append {
+                    item.data.extend_from_slice(&data);
+                } else {
+                    item.data.splice(0..0, data);
+                }
+                true
+            }
+            None => false,
+        }),
+    }
+}
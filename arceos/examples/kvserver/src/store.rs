@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+/// Number of independently locked shards, to keep concurrent clients from
+/// serializing on a single lock.
+const SHARDS: usize = 16;
+
+pub struct Item {
+    pub flags: u32,
+    pub data: Vec<u8>,
+    expire_at: Option<Instant>,
+}
+
+impl Item {
+    pub fn new(flags: u32, data: Vec<u8>, exptime: u32) -> Self {
+        let expire_at = match exptime {
+            0 => None,
+            secs => Some(Instant::now() + Duration::from_secs(secs as u64)),
+        };
+        Self {
+            flags,
+            data,
+            expire_at,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expire_at.is_some_and(|t| !t.elapsed().is_zero())
+    }
+}
+
+#[derive(Default)]
+pub struct Stats {
+    pub get_hits: AtomicUsize,
+    pub get_misses: AtomicUsize,
+    pub cmd_set: AtomicUsize,
+    pub curr_connections: AtomicUsize,
+}
+
+pub struct Store {
+    shards: Vec<Mutex<HashMap<Vec<u8>, Item>>>,
+    pub stats: Stats,
+}
+
+#[cfg(feature = "axstd")]
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock()
+}
+
+#[cfg(not(feature = "axstd"))]
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap()
+}
+
+fn shard_of(key: &[u8]) -> usize {
+    // FNV-1a
+    let mut hash: u32 = 0x811c_9dc5;
+    for &b in key {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash as usize % SHARDS
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Calls `f` with the item of `key`, if present and not expired.
+    pub fn with<R>(&self, key: &[u8], f: impl FnOnce(Option<&mut Item>) -> R) -> R {
+        let mut shard = lock(&self.shards[shard_of(key)]);
+        if shard.get(key).is_some_and(Item::is_expired) {
+            shard.remove(key);
+        }
+        f(shard.get_mut(key))
+    }
+
+    pub fn set(&self, key: &[u8], item: Item) {
+        self.stats.cmd_set.fetch_add(1, Ordering::Relaxed);
+        lock(&self.shards[shard_of(key)]).insert(key.to_vec(), item);
+    }
+
+    /// Stores `item` only if `key` is present (`must_exist`) or absent,
+    /// checked under the same lock. Returns whether it was stored.
+    pub fn set_if(&self, key: &[u8], item: Item, must_exist: bool) -> bool {
+        let mut shard = lock(&self.shards[shard_of(key)]);
+        let exists = shard.get(key).is_some_and(|item| !item.is_expired());
+        if exists != must_exist {
+            return false;
+        }
+        self.stats.cmd_set.fetch_add(1, Ordering::Relaxed);
+        shard.insert(key.to_vec(), item);
+        true
+    }
+
+    pub fn delete(&self, key: &[u8]) -> bool {
+        let mut shard = lock(&self.shards[shard_of(key)]);
+        shard.remove(key).is_some_and(|item| !item.is_expired())
+    }
+
+    pub fn flush_all(&self) {
+        for shard in &self.shards {
+            lock(shard).clear();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| lock(s).len()).sum()
+    }
+}
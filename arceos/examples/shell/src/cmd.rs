@@ -16,6 +16,16 @@ macro_rules! print_err {
 
 type CmdHandler = fn(&str);
 
+/// The standard output, for the commands registered by the other crates.
+struct Stdout;
+
+impl core::fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        print!("{}", s);
+        Ok(())
+    }
+}
+
 const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("cat", do_cat),
     ("cd", do_cd),
@@ -23,9 +33,15 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("exit", do_exit),
     ("help", do_help),
     ("ls", do_ls),
+    #[cfg(feature = "axstd")]
+    ("malloc_stats", do_malloc_stats),
     ("mkdir", do_mkdir),
+    #[cfg(feature = "multitask")]
+    ("ps", do_ps),
     ("pwd", do_pwd),
     ("rm", do_rm),
+    #[cfg(feature = "multitask")]
+    ("sched", do_sched),
     ("uname", do_uname),
 ];
 
@@ -260,11 +276,57 @@ fn do_uname(_args: &str) {
     );
 }
 
+#[cfg(feature = "multitask")]
+fn do_ps(_args: &str) {
+    use std::os::arceos::api::task::ax_task_list;
+
+    println!("{:>5} {:<8} {:<16} TAG", "ID", "STATE", "NAME");
+    for task in ax_task_list() {
+        println!(
+            "{:>5} {:<8} {:<16} {}",
+            task.id,
+            task.state,
+            task.name,
+            task.tag.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+#[cfg(feature = "multitask")]
+fn do_sched(args: &str) {
+    use std::os::arceos::api::task::{ax_sched_policies, ax_sched_policy, ax_set_sched_policy};
+
+    let name = args.trim();
+    if name.is_empty() {
+        let active = ax_sched_policy();
+        for policy in ax_sched_policies() {
+            let mark = if policy == active { '*' } else { ' ' };
+            println!("{} {}", mark, policy);
+        }
+    } else if let Err(e) = ax_set_sched_policy(name) {
+        print_err!("sched", name, e);
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_malloc_stats(_args: &str) {
+    use std::os::arceos::api::mem::ax_malloc_stats;
+
+    print!("{}", ax_malloc_stats());
+}
+
 fn do_help(_args: &str) {
     println!("Available commands:");
     for (name, _) in CMD_TABLE {
         println!("  {}", name);
     }
+    for cmd in axcmd::SHELL_CMDS.iter() {
+        if cmd.help.is_empty() {
+            println!("  {}", cmd.name);
+        } else {
+            println!("  {:<16}{}", cmd.name, cmd.help);
+        }
+    }
 }
 
 fn do_exit(_args: &str) {
@@ -282,10 +344,51 @@ pub fn run_cmd(line: &[u8]) {
                 return;
             }
         }
-        println!("{}: command not found", cmd);
+        match axcmd::run_cmd(cmd, args, &mut Stdout) {
+            Some(Ok(())) => {}
+            Some(Err(_)) => println!("{}: failed to write the output", cmd),
+            None => println!("{}: command not found", cmd),
+        }
     }
 }
 
+/// Completes the command names, then the paths in their arguments.
+pub fn complete(before: &str, word: &str) -> Vec<String> {
+    if before.trim().is_empty() {
+        return CMD_TABLE
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(axcmd::SHELL_CMDS.iter().map(|cmd| cmd.name))
+            .filter(|name| name.starts_with(word))
+            .map(String::from)
+            .collect();
+    }
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..i + 1], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut names = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name();
+            let name = path_to_str!(name);
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            let path = String::from(dir) + name;
+            match fs::metadata(&path) {
+                Ok(meta) if meta.is_dir() => Some(path + "/"),
+                _ => Some(path),
+            }
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
 fn split_whitespace(str: &str) -> (&str, &str) {
     let str = str.trim();
     str.find(char::is_whitespace)
@@ -0,0 +1,356 @@
+//! Line editing and history of the command line.
+//!
+//! The keys are the ones of readline (emacs mode), with the escape sequences
+//! of VT100 terminals:
+//!
+//! - Left/Right, `^B`/`^F`: move the cursor by a character.
+//! - Home/End, `^A`/`^E`: move the cursor to the start/end of the line.
+//! - Backspace, Delete, `^D`: delete the character before/under the cursor.
+//! - `^K`/`^U`/`^W`: cut to the end of the line, to its start, or the word
+//!   before the cursor; `^Y` pastes the last cut text.
+//! - Up/Down, `^P`/`^N`: previous/next line of the history.
+//! - Tab: complete the word before the cursor, see [`LineEditor::set_completer`].
+//! - `^C`: abandon the line, `^L`: clear the screen.
+
+use std::io::{self, prelude::*};
+use std::{string::String, vec::Vec};
+
+const MAX_HISTORY: usize = 100;
+
+const CTRL_A: u8 = 0x01;
+const CTRL_B: u8 = 0x02;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_F: u8 = 0x06;
+const BS: u8 = 0x08;
+const TAB: u8 = 0x09;
+const LF: u8 = b'\n';
+const CTRL_K: u8 = 0x0b;
+const CTRL_L: u8 = 0x0c;
+const CR: u8 = b'\r';
+const CTRL_N: u8 = 0x0e;
+const CTRL_P: u8 = 0x10;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+const CTRL_Y: u8 = 0x19;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// Returns the completions of the word `word` of the line, after `before`.
+///
+/// A completion replaces the word. A space is added after it when it is the
+/// only one, unless it ends with `/`.
+pub type Completer = fn(before: &str, word: &str) -> Vec<String>;
+
+enum Key {
+    Char(char),
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Delete,
+    Other,
+}
+
+/// A line editor, with the history of the lines read.
+pub struct LineEditor {
+    history: Vec<String>,
+    completer: Option<Completer>,
+    /// The last cut text.
+    yank: Vec<char>,
+    max_len: usize,
+}
+
+/// The line being edited.
+struct Line<'a, W: Write> {
+    out: &'a mut W,
+    prompt: &'a str,
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl<W: Write> Line<'_, W> {
+    /// Redraws the line and puts the cursor at its place.
+    fn refresh(&mut self) -> io::Result<()> {
+        let line: String = self.chars.iter().collect();
+        write!(self.out, "\r{}{}\x1b[K", self.prompt, line)?;
+        let back = self.chars.len() - self.cursor;
+        if back > 0 {
+            write!(self.out, "\x1b[{}D", back)?;
+        }
+        self.out.flush()
+    }
+
+    fn set(&mut self, chars: Vec<char>) -> io::Result<()> {
+        self.cursor = chars.len();
+        self.chars = chars;
+        self.refresh()
+    }
+
+    fn insert(&mut self, s: &[char]) -> io::Result<()> {
+        self.chars
+            .splice(self.cursor..self.cursor, s.iter().copied());
+        self.cursor += s.len();
+        self.refresh()
+    }
+
+    /// Removes the characters in `start..end`, returns them.
+    fn remove(&mut self, start: usize, end: usize) -> io::Result<Vec<char>> {
+        let removed = self.chars.drain(start..end).collect();
+        self.cursor = start;
+        self.refresh()?;
+        Ok(removed)
+    }
+
+    fn move_to(&mut self, cursor: usize) -> io::Result<()> {
+        self.cursor = cursor;
+        self.refresh()
+    }
+
+    /// Returns the start of the word before the cursor.
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && self.chars[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.chars[start - 1] != ' ' {
+            start -= 1;
+        }
+        start
+    }
+}
+
+impl LineEditor {
+    /// Creates an editor of lines of at most `max_len` bytes.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            history: Vec::new(),
+            completer: None,
+            yank: Vec::new(),
+            max_len,
+        }
+    }
+
+    /// Sets the function completing the words on Tab.
+    pub fn set_completer(&mut self, completer: Completer) {
+        self.completer = Some(completer);
+    }
+
+    /// Reads a line after `prompt`, without the line break.
+    ///
+    /// The non-empty lines are added to the history.
+    pub fn read_line<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        prompt: &str,
+    ) -> io::Result<String> {
+        let mut line = Line {
+            out: output,
+            prompt,
+            chars: Vec::new(),
+            cursor: 0,
+        };
+        line.refresh()?;
+        // Index in the history of the line shown, with the line being typed
+        // saved when going up from it.
+        let mut index = self.history.len();
+        let mut typed = Vec::new();
+        loop {
+            match self.read_key(input)? {
+                Key::Char('\n') => break,
+                Key::Char('\t') => self.complete(&mut line)?,
+                Key::Char(c) if !c.is_control() => {
+                    let len = line.chars.iter().map(|c| c.len_utf8()).sum::<usize>();
+                    if len + c.len_utf8() <= self.max_len {
+                        line.insert(&[c])?;
+                    }
+                }
+                Key::Char(c) => match c as u8 {
+                    CTRL_A => line.move_to(0)?,
+                    CTRL_E => line.move_to(line.chars.len())?,
+                    CTRL_B if line.cursor > 0 => line.move_to(line.cursor - 1)?,
+                    CTRL_F if line.cursor < line.chars.len() => line.move_to(line.cursor + 1)?,
+                    BS | DEL if line.cursor > 0 => {
+                        line.remove(line.cursor - 1, line.cursor)?;
+                    }
+                    CTRL_D if line.cursor < line.chars.len() => {
+                        line.remove(line.cursor, line.cursor + 1)?;
+                    }
+                    CTRL_K => self.yank = line.remove(line.cursor, line.chars.len())?,
+                    CTRL_U => self.yank = line.remove(0, line.cursor)?,
+                    CTRL_W => self.yank = line.remove(line.word_start(), line.cursor)?,
+                    CTRL_Y => line.insert(&self.yank.clone())?,
+                    CTRL_P => self.history_move(&mut line, &mut index, &mut typed, true)?,
+                    CTRL_N => self.history_move(&mut line, &mut index, &mut typed, false)?,
+                    CTRL_C => {
+                        writeln!(line.out, "^C")?;
+                        line.chars.clear();
+                        line.cursor = 0;
+                        index = self.history.len();
+                        line.refresh()?;
+                    }
+                    CTRL_L => {
+                        write!(line.out, "\x1b[H\x1b[2J")?;
+                        line.refresh()?;
+                    }
+                    _ => {}
+                },
+                Key::Left if line.cursor > 0 => line.move_to(line.cursor - 1)?,
+                Key::Right if line.cursor < line.chars.len() => line.move_to(line.cursor + 1)?,
+                Key::Home => line.move_to(0)?,
+                Key::End => line.move_to(line.chars.len())?,
+                Key::Up => self.history_move(&mut line, &mut index, &mut typed, true)?,
+                Key::Down => self.history_move(&mut line, &mut index, &mut typed, false)?,
+                Key::Delete if line.cursor < line.chars.len() => {
+                    line.remove(line.cursor, line.cursor + 1)?;
+                }
+                _ => {}
+            }
+        }
+        writeln!(line.out)?;
+        line.out.flush()?;
+
+        let line: String = line.chars.into_iter().collect();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        Ok(line)
+    }
+
+    fn history_move<W: Write>(
+        &self,
+        line: &mut Line<W>,
+        index: &mut usize,
+        typed: &mut Vec<char>,
+        up: bool,
+    ) -> io::Result<()> {
+        if up && *index > 0 {
+            if *index == self.history.len() {
+                *typed = line.chars.clone();
+            }
+            *index -= 1;
+            line.set(self.history[*index].chars().collect())
+        } else if !up && *index < self.history.len() {
+            *index += 1;
+            match self.history.get(*index) {
+                Some(entry) => line.set(entry.chars().collect()),
+                None => line.set(core::mem::take(typed)),
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    fn complete<W: Write>(&self, line: &mut Line<W>) -> io::Result<()> {
+        let Some(completer) = self.completer else {
+            return Ok(());
+        };
+        let start = line.word_start();
+        let before: String = line.chars[..start].iter().collect();
+        let word: String = line.chars[start..line.cursor].iter().collect();
+        let word = word.trim_end();
+        let candidates = completer(&before, word);
+        match candidates.as_slice() {
+            [] => Ok(()),
+            [only] => {
+                let mut insert: Vec<char> = only.chars().skip(word.chars().count()).collect();
+                if !only.ends_with('/') {
+                    insert.push(' ');
+                }
+                line.insert(&insert)
+            }
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, c| {
+                    first
+                        .char_indices()
+                        .zip(c.chars())
+                        .take_while(|((i, a), b)| *i < len && a == b)
+                        .count()
+                });
+                let insert: Vec<char> = first
+                    .chars()
+                    .take(common)
+                    .skip(word.chars().count())
+                    .collect();
+                if !insert.is_empty() {
+                    return line.insert(&insert);
+                }
+                // Nothing more in common, shows them.
+                writeln!(line.out)?;
+                for c in candidates.iter() {
+                    write!(line.out, "{}  ", c)?;
+                }
+                writeln!(line.out)?;
+                line.refresh()
+            }
+        }
+    }
+
+    /// Reads a key, a UTF-8 character or an escape sequence.
+    fn read_key<R: Read>(&self, input: &mut R) -> io::Result<Key> {
+        let b = read_byte(input)?;
+        match b {
+            CR | LF => Ok(Key::Char('\n')),
+            TAB => Ok(Key::Char('\t')),
+            ESC => {
+                let intro = read_byte(input)?;
+                if intro != b'[' && intro != b'O' {
+                    return Ok(Key::Other);
+                }
+                // Parameters, then the final byte.
+                let mut param = 0;
+                let last = loop {
+                    match read_byte(input)? {
+                        b @ b'0'..=b'9' => param = param * 10 + (b - b'0') as u32,
+                        b';' => {}
+                        b => break b,
+                    }
+                };
+                Ok(match (last, param) {
+                    (b'A', _) => Key::Up,
+                    (b'B', _) => Key::Down,
+                    (b'C', _) => Key::Right,
+                    (b'D', _) => Key::Left,
+                    (b'H', _) | (b'~', 1 | 7) => Key::Home,
+                    (b'F', _) | (b'~', 4 | 8) => Key::End,
+                    (b'~', 3) => Key::Delete,
+                    _ => Key::Other,
+                })
+            }
+            0x00..=0x7f => Ok(Key::Char(b as char)),
+            _ => {
+                // The continuation bytes of a UTF-8 character.
+                let len = match b {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => return Ok(Key::Other),
+                };
+                let mut buf = [b, 0, 0, 0];
+                for byte in buf.iter_mut().take(len).skip(1) {
+                    *byte = read_byte(input)?;
+                }
+                Ok(core::str::from_utf8(&buf[..len])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map_or(Key::Other, Key::Char))
+            }
+        }
+    }
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut b = [0];
+    loop {
+        if input.read(&mut b)? == 1 {
+            return Ok(b[0]);
+        }
+    }
+}
@@ -19,26 +19,20 @@ macro_rules! path_to_str {
 }
 
 mod cmd;
+mod line;
 
 #[cfg(feature = "use-ramfs")]
 mod ramfs;
 
-use std::io::prelude::*;
-
-const LF: u8 = b'\n';
-const CR: u8 = b'\r';
-const DL: u8 = b'\x7f';
-const BS: u8 = b'\x08';
-const SPACE: u8 = b' ';
+use std::string::String;
 
 const MAX_CMD_LEN: usize = 256;
 
-fn print_prompt() {
-    print!(
+fn prompt() -> String {
+    format!(
         "arceos:{}$ ",
         path_to_str!(std::env::current_dir().unwrap())
-    );
-    std::io::stdout().flush().unwrap();
+    )
 }
 
 #[cfg_attr(feature = "axstd", no_mangle)]
@@ -46,40 +40,14 @@ fn main() {
     let mut stdin = std::io::stdin();
     let mut stdout = std::io::stdout();
 
-    let mut buf = [0; MAX_CMD_LEN];
-    let mut cursor = 0;
+    let mut editor = line::LineEditor::new(MAX_CMD_LEN - 1);
+    editor.set_completer(cmd::complete);
     cmd::run_cmd("help".as_bytes());
-    print_prompt();
 
     loop {
-        if stdin.read(&mut buf[cursor..cursor + 1]).ok() != Some(1) {
-            continue;
-        }
-        if buf[cursor] == b'\x1b' {
-            buf[cursor] = b'^';
-        }
-        match buf[cursor] {
-            CR | LF => {
-                println!();
-                if cursor > 0 {
-                    cmd::run_cmd(&buf[..cursor]);
-                    cursor = 0;
-                }
-                print_prompt();
-            }
-            BS | DL => {
-                if cursor > 0 {
-                    stdout.write_all(&[BS, SPACE, BS]).unwrap();
-                    cursor -= 1;
-                }
-            }
-            0..=31 => {}
-            c => {
-                if cursor < MAX_CMD_LEN - 1 {
-                    stdout.write_all(&[c]).unwrap();
-                    cursor += 1;
-                }
-            }
+        match editor.read_line(&mut stdin, &mut stdout, &prompt()) {
+            Ok(line) => cmd::run_cmd(line.as_bytes()),
+            Err(_) => continue,
         }
     }
 }
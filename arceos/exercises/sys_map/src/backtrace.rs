@@ -0,0 +1,207 @@
+//! Symbolicated backtraces of the user tasks killed by a fatal trap.
+//!
+//! The stack is walked by the frame pointers (the user code must be built
+//! with `-fno-omit-frame-pointer`), and each return address is resolved
+//! against the images loaded in the address space: the function from the
+//! symbol table, and the source line from the DWARF line tables if the image
+//! has them.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use axhal::arch::TrapFrame;
+use axhal::mem::VirtAddr;
+use axmm::AddrSpace;
+use axtask::{current, TaskExtRef};
+
+use elf::abi::{PT_LOAD, STT_FUNC};
+use elf::endian::AnyEndian;
+use elf::ElfBytes;
+
+use crate::dwarf::{LineTable, StrSections};
+
+/// The most frames printed.
+const MAX_FRAMES: usize = 32;
+
+/// A function of the symbol table.
+struct Symbol {
+    addr: u64,
+    size: u64,
+    name: String,
+}
+
+/// The debug info of an ELF image loaded in a user address space: the
+/// executable, or a shared object.
+pub struct DebugImage {
+    path: String,
+    /// The load bias, added to the addresses of the ELF file.
+    bias: usize,
+    /// The addresses of its loaded segments.
+    range: Range<usize>,
+    /// The functions, ordered by their addresses.
+    symbols: Vec<Symbol>,
+    lines: LineTable,
+}
+
+impl DebugImage {
+    /// Reads the symbols and the line tables of the ELF file `path`, loaded
+    /// at `bias`. An image which can not be read has no symbols.
+    pub fn load(path: &str, bias: usize) -> Self {
+        let mut image = Self {
+            path: path.into(),
+            bias,
+            range: 0..0,
+            symbols: Vec::new(),
+            lines: LineTable::default(),
+        };
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("no debug info for {}: {:?}", path, e);
+                return image;
+            }
+        };
+        match ElfBytes::<AnyEndian>::minimal_parse(&data) {
+            Ok(file) => image.parse(&file),
+            Err(e) => warn!("no debug info for {}: {:?}", path, e),
+        }
+        image
+    }
+
+    fn parse(&mut self, file: &ElfBytes<AnyEndian>) {
+        let loads = file.segments().into_iter().flatten().filter(|ph| ph.p_type == PT_LOAD);
+        let (start, end) = loads.fold((u64::MAX, 0), |(start, end), ph| {
+            (start.min(ph.p_vaddr), end.max(ph.p_vaddr + ph.p_memsz))
+        });
+        if start < end {
+            self.range = self.bias + start as usize..self.bias + end as usize;
+        }
+
+        if let Ok(Some((symtab, strtab))) = file.symbol_table() {
+            self.symbols = symtab
+                .iter()
+                .filter(|sym| sym.st_symtype() == STT_FUNC && sym.st_value != 0)
+                .filter_map(|sym| {
+                    Some(Symbol {
+                        addr: sym.st_value,
+                        size: sym.st_size,
+                        name: strtab.get(sym.st_name as usize).ok()?.into(),
+                    })
+                })
+                .collect();
+            self.symbols.sort_by_key(|sym| sym.addr);
+        }
+
+        let debug_line = section_data(file, ".debug_line");
+        if !debug_line.is_empty() {
+            let strs = StrSections {
+                str: section_data(file, ".debug_str"),
+                line_str: section_data(file, ".debug_line_str"),
+            };
+            self.lines = LineTable::parse(debug_line, strs);
+        }
+        info!(
+            "{}: {} functions, {}line table",
+            self.path,
+            self.symbols.len(),
+            if self.lines.is_empty() { "no " } else { "" }
+        );
+    }
+
+    /// Returns the function containing `addr` of the ELF file, with the
+    /// offset of `addr` in it.
+    fn function(&self, addr: u64) -> Option<(&str, u64)> {
+        let idx = self.symbols.partition_point(|sym| sym.addr <= addr);
+        let sym = self.symbols[..idx].last()?;
+        if sym.size != 0 && addr >= sym.addr + sym.size {
+            return None;
+        }
+        Some((&sym.name, addr - sym.addr))
+    }
+
+    /// Formats the location of the user address `pc`.
+    fn describe(&self, pc: usize) -> String {
+        let addr = (pc - self.bias) as u64;
+        let name = self.path.rsplit('/').next().unwrap_or("");
+        let mut desc = match self.function(addr) {
+            Some((func, offset)) => alloc::format!("{}: {}+{:#x}", name, func, offset),
+            None => alloc::format!("{}+{:#x}", name, addr),
+        };
+        if let Some((file, line)) = self.lines.lookup(addr) {
+            desc += &alloc::format!(" at {}:{}", file, line);
+        }
+        desc
+    }
+}
+
+/// Returns the data of the section `name`, empty if there is none or it is
+/// compressed.
+fn section_data<'a>(file: &ElfBytes<'a, AnyEndian>, name: &str) -> &'a [u8] {
+    match file.section_header_by_name(name) {
+        Ok(Some(shdr)) => match file.section_data(&shdr) {
+            Ok((data, None)) => data,
+            _ => &[],
+        },
+        _ => &[],
+    }
+}
+
+fn read_word(aspace: &AddrSpace, addr: usize) -> Option<usize> {
+    if addr % 8 != 0 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    aspace.read(VirtAddr::from(addr), &mut buf).ok()?;
+    Some(usize::from_le_bytes(buf))
+}
+
+/// Walks the frames of the user stack from the trap, returns the program
+/// counters, the faulting one first.
+///
+/// With the frame pointer, the return address of a frame is saved at
+/// `fp - 8` and the former frame pointer at `fp - 16`, the way of the riscv
+/// Linux unwinder.
+fn unwind(aspace: &AddrSpace, tf: &TrapFrame) -> Vec<usize> {
+    let mut pcs = Vec::from([tf.sepc]);
+    let (mut fp, mut sp) = (tf.regs.s0, tf.regs.sp);
+    // a frame pointer is on the stack, above the former ones
+    let valid_fp = |fp: usize, sp: usize| fp > sp && fp % 8 == 0;
+    while pcs.len() < MAX_FRAMES && valid_fp(fp, sp) {
+        let ra = read_word(aspace, fp - 8);
+        let prev_fp = read_word(aspace, fp - 16);
+        let (Some(ra), Some(prev_fp)) = (ra, prev_fp) else {
+            break;
+        };
+        let (pc, next_fp) = if pcs.len() == 1 && valid_fp(ra, fp) {
+            // a leaf function which saved the frame pointer only, at fp - 8,
+            // its return address is still in ra
+            (tf.regs.ra, ra)
+        } else {
+            (ra, prev_fp)
+        };
+        if pc == 0 {
+            break;
+        }
+        pcs.push(pc);
+        sp = fp;
+        fp = next_fp;
+    }
+    pcs
+}
+
+/// Prints the backtrace of the current user task from the trap.
+pub fn print_backtrace(tf: &TrapFrame) {
+    let curr = current();
+    let ext = curr.task_ext();
+    let pcs = unwind(&ext.aspace.lock(), tf);
+    let images = ext.images.lock();
+    ax_println!("backtrace:");
+    for (i, &pc) in pcs.iter().enumerate() {
+        // a return address is after the call, look up the call instead
+        let lookup = if i == 0 { pc } else { pc - 1 };
+        match images.iter().find(|image| image.range.contains(&lookup)) {
+            Some(image) => ax_println!("  #{:<2} {:#x} {}", i, pc, image.describe(lookup)),
+            None => ax_println!("  #{:<2} {:#x} ??", i, pc),
+        }
+    }
+}
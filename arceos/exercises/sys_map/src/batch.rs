@@ -0,0 +1,116 @@
+//! Batched syscalls: an array of I/O operations run in one kernel entry.
+//!
+//! A user process fills an array of [`BatchOp`] and submits it with
+//! `SYS_BATCH`, which runs the operations in order and writes the result of
+//! each, as the syscall it stands for would return it, in its `result` field.
+//! Every operation goes through the syscall filter and the BPF hooks as that
+//! syscall, so a batch cannot do what the process is not allowed to. The
+//! batch returns the number of operations run, all of them unless
+//! [`BATCH_STOP_ON_ERROR`] is given and one failed.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::LinuxError;
+use axhal::arch::TrapFrame;
+use axtask::{current, TaskExtRef};
+use core::ffi::c_void;
+use memory_addr::VirtAddr;
+
+use crate::syscall::{check_syscall, SYS_READ, SYS_RECVFROM, SYS_SENDTO, SYS_WRITE};
+
+/// `read(fd, buf, len)`.
+pub const BATCH_OP_READ: u32 = 0;
+/// `write(fd, buf, len)`.
+pub const BATCH_OP_WRITE: u32 = 1;
+/// `send(fd, buf, len, flags)`.
+pub const BATCH_OP_SEND: u32 = 2;
+/// `recv(fd, buf, len, flags)`.
+pub const BATCH_OP_RECV: u32 = 3;
+
+/// Stops the batch after the first operation failing.
+pub const BATCH_STOP_ON_ERROR: u32 = 1 << 0;
+
+/// The most operations of a batch.
+pub const MAX_BATCH_OPS: usize = 256;
+
+/// An operation of a batch, shared with the user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchOp {
+    /// One of the `BATCH_OP_*`.
+    pub opcode: u32,
+    /// The `flags` of `send` and `recv`, zero for the others.
+    pub flags: u32,
+    /// The file descriptor.
+    pub fd: i32,
+    _reserved: u32,
+    /// The user buffer.
+    pub buf: usize,
+    /// The bytes of the buffer.
+    pub len: usize,
+    /// The result, written by the kernel.
+    pub result: isize,
+}
+
+fn as_bytes_mut(ops: &mut [BatchOp]) -> &mut [u8] {
+    // SAFETY: `BatchOp` is plain data, any bytes are a valid one.
+    unsafe {
+        core::slice::from_raw_parts_mut(ops.as_mut_ptr() as *mut u8, core::mem::size_of_val(ops))
+    }
+}
+
+/// Runs the operation, as the syscall of its opcode.
+fn run_op(tf: &TrapFrame, op: &BatchOp) -> isize {
+    let syscall_num = match op.opcode {
+        BATCH_OP_READ => SYS_READ,
+        BATCH_OP_WRITE => SYS_WRITE,
+        BATCH_OP_SEND => SYS_SENDTO,
+        BATCH_OP_RECV => SYS_RECVFROM,
+        _ => return -LinuxError::EINVAL.code() as _,
+    };
+    let args = [op.fd as usize, op.buf, op.len, op.flags as usize, 0, 0];
+    if let Some(ret) = check_syscall(tf, syscall_num, &args) {
+        return ret;
+    }
+    let (fd, buf, len) = (op.fd, op.buf as *mut c_void, op.len);
+    match op.opcode {
+        BATCH_OP_READ => arceos_posix_api::sys_read(fd, buf, len),
+        BATCH_OP_WRITE => arceos_posix_api::sys_write(fd, buf, len),
+        #[cfg(feature = "net")]
+        BATCH_OP_SEND => arceos_posix_api::sys_send(fd, buf, len, op.flags as _),
+        #[cfg(feature = "net")]
+        BATCH_OP_RECV => arceos_posix_api::sys_recv(fd, buf, len, op.flags as _),
+        _ => -LinuxError::ENOSYS.code() as _,
+    }
+}
+
+/// Runs the `count` operations at `ops`, and returns the number run.
+pub fn sys_batch(tf: &TrapFrame, ops: usize, count: usize, flags: u32) -> isize {
+    crate::syscall_body!(sys_batch, {
+        if count > MAX_BATCH_OPS || flags & !BATCH_STOP_ON_ERROR != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut batch: Vec<BatchOp> = vec![BatchOp::default(); count];
+        let aspace = current().task_ext().aspace.clone();
+        // The address space is not locked while the operations run, they
+        // may sleep.
+        aspace
+            .lock()
+            .read(VirtAddr::from(ops), as_bytes_mut(&mut batch))
+            .map_err(|_| LinuxError::EFAULT)?;
+
+        let mut done = 0;
+        for op in batch.iter_mut() {
+            op.result = run_op(tf, op);
+            done += 1;
+            if op.result < 0 && flags & BATCH_STOP_ON_ERROR != 0 {
+                break;
+            }
+        }
+        aspace
+            .lock()
+            .write(VirtAddr::from(ops), as_bytes_mut(&mut batch[..done]))
+            .map_err(|_| LinuxError::EFAULT)?;
+        Ok(done)
+    })
+}
@@ -0,0 +1,235 @@
+//! Core dumps of the user tasks killed by a fatal trap.
+//!
+//! The core file is an ELF file (`ET_CORE`) like the ones of Linux, to be
+//! read by the host gdb with the executable (`gdb mapfile core.mapfile.233`):
+//! a `PT_NOTE` segment with the registers (`NT_PRSTATUS`) and the process
+//! info (`NT_PRPSINFO`), and a `PT_LOAD` segment with the contents of each
+//! area of the address space.
+
+use std::fs::File;
+use std::io::{self, Write};
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::vec;
+use axhal::arch::TrapFrame;
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axhal::trap::{register_trap_handler, USER_FAULT};
+use axmm::AreaInfo;
+use axtask::{current, TaskExtRef};
+
+/// The path of the core files, with `%e` replaced by the executable name,
+/// `%p` by the PID and `%s` by the signal.
+const DEFAULT_CORE_PATTERN: &str = "/core.%e.%p";
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+/// `sizeof(struct elf_prstatus)` of riscv64.
+const PRSTATUS_SIZE: usize = 376;
+/// Offset of `pr_reg` in `struct elf_prstatus`.
+const PRSTATUS_REG_OFFSET: usize = 112;
+/// `sizeof(struct elf_prpsinfo)` of 64-bit targets.
+const PRPSINFO_SIZE: usize = 136;
+
+#[register_trap_handler(USER_FAULT)]
+fn handle_user_fault(tf: &TrapFrame, signal: i32, addr: VirtAddr) -> bool {
+    // The trap comes from the user space, no lock of the kernel is held.
+    axhal::arch::enable_irqs();
+    let Some(signal) = crate::ptrace::report_fault(signal, addr) else {
+        // handled by the tracer
+        return true;
+    };
+    ax_println!(
+        "user task killed by signal {} @ {:#x}, fault address {:#x}",
+        signal, tf.sepc, addr
+    );
+    crate::backtrace::print_backtrace(tf);
+    match write_core(tf, signal) {
+        Ok(path) => ax_println!("core dumped to {}", path),
+        Err(e) => warn!("failed to write the core file: {:?}", e),
+    }
+    crate::task::exit(128 + signal)
+}
+
+fn core_path(signal: i32) -> String {
+    // The empty variables are unset ones exported by the Makefile.
+    let pattern = option_env!("AX_CORE_PATTERN")
+        .filter(|v| !v.is_empty())
+        .unwrap_or(DEFAULT_CORE_PATTERN);
+    let curr = current();
+    let exe_path = curr.task_ext().exe_path.lock();
+    let exe = exe_path.rsplit('/').next().unwrap_or("");
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('%', Some(spec @ ('e' | 'p' | 's' | '%'))) => {
+                chars.next();
+                match spec {
+                    'e' => path.push_str(exe),
+                    'p' => path += &alloc::format!("{}", curr.task_ext().proc_id),
+                    's' => path += &alloc::format!("{}", signal),
+                    _ => path.push('%'),
+                }
+            }
+            _ => path.push(c),
+        }
+    }
+    path
+}
+
+fn push_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_note(buf: &mut Vec<u8>, ty: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0\0\0\0";
+    push_u32(buf, 5);
+    push_u32(buf, desc.len() as u32);
+    push_u32(buf, ty);
+    buf.extend_from_slice(NAME);
+    buf.extend_from_slice(desc);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+fn prstatus(tf: &TrapFrame, signal: i32, pid: u32) -> Vec<u8> {
+    let mut desc = vec![0; PRSTATUS_SIZE];
+    // pr_info.si_signo, pr_cursig
+    desc[0..4].copy_from_slice(&signal.to_le_bytes());
+    desc[12..14].copy_from_slice(&(signal as u16).to_le_bytes());
+    // pr_pid
+    desc[32..36].copy_from_slice(&pid.to_le_bytes());
+    // pr_reg: pc, then x1..x31 in the order of `GeneralRegisters`
+    let regs: [usize; 31] = unsafe { core::mem::transmute(tf.regs) };
+    let reg = &mut desc[PRSTATUS_REG_OFFSET..];
+    for (i, v) in core::iter::once(tf.sepc).chain(regs).enumerate() {
+        reg[i * 8..i * 8 + 8].copy_from_slice(&(v as u64).to_le_bytes());
+    }
+    desc
+}
+
+fn prpsinfo(pid: u32, exe: &str) -> Vec<u8> {
+    let mut desc = vec![0; PRPSINFO_SIZE];
+    // pr_sname: running
+    desc[1] = b'R';
+    // pr_pid
+    desc[24..28].copy_from_slice(&pid.to_le_bytes());
+    // pr_fname, pr_psargs
+    let name = exe.rsplit('/').next().unwrap_or("").as_bytes();
+    let len = name.len().min(15);
+    desc[40..40 + len].copy_from_slice(&name[..len]);
+    let len = exe.len().min(79);
+    desc[56..56 + len].copy_from_slice(&exe.as_bytes()[..len]);
+    desc
+}
+
+fn segment_flags(flags: MappingFlags) -> u32 {
+    let mut pf = 0;
+    if flags.contains(MappingFlags::READ) {
+        pf |= PF_R;
+    }
+    if flags.contains(MappingFlags::WRITE) {
+        pf |= PF_W;
+    }
+    if flags.contains(MappingFlags::EXECUTE) {
+        pf |= PF_X;
+    }
+    pf
+}
+
+/// Builds the ELF header, the program headers and the notes, padded to a
+/// page boundary where the contents of the areas follow.
+fn core_headers(areas: &[AreaInfo], notes: &[u8]) -> Vec<u8> {
+    let phnum = areas.len() + 1;
+    let notes_off = EHDR_SIZE + phnum * PHDR_SIZE;
+    let data_off = memory_addr::align_up_4k(notes_off + notes.len());
+
+    let mut buf = Vec::with_capacity(data_off);
+    // e_ident: ELFCLASS64, ELFDATA2LSB, EV_CURRENT
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    buf.resize(16, 0);
+    push_u16(&mut buf, ET_CORE);
+    push_u16(&mut buf, EM_RISCV);
+    push_u32(&mut buf, 1); // e_version
+    push_u64(&mut buf, 0); // e_entry
+    push_u64(&mut buf, EHDR_SIZE as u64); // e_phoff
+    push_u64(&mut buf, 0); // e_shoff
+    push_u32(&mut buf, 0); // e_flags
+    push_u16(&mut buf, EHDR_SIZE as u16);
+    push_u16(&mut buf, PHDR_SIZE as u16);
+    push_u16(&mut buf, phnum as u16);
+    push_u16(&mut buf, 0); // e_shentsize
+    push_u16(&mut buf, 0); // e_shnum
+    push_u16(&mut buf, 0); // e_shstrndx
+
+    let mut phdr = |ty: u32, flags: u32, off: usize, vaddr: usize, size: usize, align: u64| {
+        push_u32(&mut buf, ty);
+        push_u32(&mut buf, flags);
+        push_u64(&mut buf, off as u64);
+        push_u64(&mut buf, vaddr as u64);
+        push_u64(&mut buf, 0); // p_paddr
+        push_u64(&mut buf, size as u64); // p_filesz
+        push_u64(&mut buf, size as u64); // p_memsz
+        push_u64(&mut buf, align);
+    };
+    phdr(PT_NOTE, 0, notes_off, 0, notes.len(), 4);
+    let mut off = data_off;
+    for area in areas {
+        let flags = segment_flags(area.flags);
+        phdr(PT_LOAD, flags, off, area.start.as_usize(), area.size(), PAGE_SIZE_4K as u64);
+        off += area.size();
+    }
+
+    buf.extend_from_slice(notes);
+    buf.resize(data_off, 0);
+    buf
+}
+
+/// Writes the core file of the current task, returns its path.
+fn write_core(tf: &TrapFrame, signal: i32) -> io::Result<String> {
+    let curr = current();
+    let ext = curr.task_ext();
+    let pid = ext.proc_id as u32;
+    let aspace = ext.aspace.lock();
+    let areas = aspace.memory_map();
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, &prstatus(tf, signal, pid));
+    push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid, &ext.exe_path.lock()));
+    let headers = core_headers(&areas, &notes);
+
+    let path = core_path(signal);
+    let mut file = File::create(&path)?;
+    file.write_all(&headers)?;
+    let mut page = [0u8; PAGE_SIZE_4K];
+    for area in &areas {
+        let mut vaddr = area.start;
+        while vaddr < area.end {
+            // The pages not populated yet are zeros.
+            if aspace.read(vaddr, &mut page).is_err() {
+                page.fill(0);
+            }
+            file.write_all(&page)?;
+            vaddr += PAGE_SIZE_4K;
+        }
+    }
+    Ok(path)
+}
@@ -0,0 +1,429 @@
+//! A minimal reader of the DWARF line tables (`.debug_line`, versions 2 to
+//! 5), to map the addresses of the user code to their source lines.
+//!
+//! Only the rows are kept: the address, the file and the line. The columns,
+//! the ISA and the VLIW operations are ignored.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+/// The string sections referred to by the version 5 line tables.
+#[derive(Default, Clone, Copy)]
+pub struct StrSections<'a> {
+    /// `.debug_str`
+    pub str: &'a [u8],
+    /// `.debug_line_str`
+    pub line_str: &'a [u8],
+}
+
+/// A row of a line table.
+#[derive(Clone, Copy)]
+struct Row {
+    addr: u64,
+    /// The index in [`LineTable::files`].
+    file: u32,
+    line: u32,
+    /// The first address after a sequence, which has no line.
+    end: bool,
+}
+
+/// The line tables of all the compilation units of an executable.
+#[derive(Default)]
+pub struct LineTable {
+    rows: Vec<Row>,
+    files: Vec<String>,
+}
+
+/// Reads the little-endian data of the line tables. All the reads return
+/// `None` past the end.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.bytes(len)?;
+        Some(bytes.iter().rev().fold(0, |v, &b| v << 8 | b as u64))
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Option<&'a str> {
+        let rest = self.data.get(self.pos..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        self.pos += len + 1;
+        core::str::from_utf8(&rest[..len]).ok()
+    }
+}
+
+/// Reads the NUL-terminated string at `offset` of a string section.
+fn str_at(section: &[u8], offset: u64) -> Option<&str> {
+    let mut reader = Reader::new(section);
+    reader.pos = usize::try_from(offset).ok()?;
+    reader.cstr()
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() || name.starts_with('/') {
+        name.into()
+    } else {
+        alloc::format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+/// The header of the line table of a compilation unit.
+struct UnitHeader<'a> {
+    version: u16,
+    offset_size: usize,
+    min_inst_len: u8,
+    line_base: i8,
+    line_range: u8,
+    opcode_base: u8,
+    std_opcode_lengths: &'a [u8],
+    /// The index in [`LineTable::files`] of the first file of the unit.
+    file_base: u32,
+}
+
+impl LineTable {
+    /// Parses the line tables of `.debug_line`, and with the version 5
+    /// tables the string sections. The units which can not be parsed are
+    /// skipped.
+    pub fn parse(debug_line: &[u8], strs: StrSections) -> Self {
+        let mut table = Self::default();
+        let mut reader = Reader::new(debug_line);
+        while reader.pos < debug_line.len() {
+            let Some(unit) = table.parse_unit(&mut reader, strs) else {
+                break;
+            };
+            reader.pos = unit;
+        }
+        // the start of a sequence comes after the end of the former one at
+        // the same address
+        table.rows.sort_by_key(|row| (row.addr, !row.end));
+        table
+    }
+
+    /// Parses the unit at the position of `reader`, returns the offset of
+    /// the next unit.
+    fn parse_unit(&mut self, reader: &mut Reader, strs: StrSections) -> Option<usize> {
+        let (unit_len, offset_size) = match reader.uint(4)? {
+            0xffff_ffff => (reader.uint(8)?, 8),
+            len => (len, 4),
+        };
+        let unit_end = reader.pos.checked_add(usize::try_from(unit_len).ok()?)?;
+        let mut unit = Reader::new(reader.data.get(..unit_end)?);
+        unit.pos = reader.pos;
+        if self.parse_program(&mut unit, offset_size, strs).is_none() {
+            warn!("skipped a malformed DWARF line table");
+        }
+        Some(unit_end)
+    }
+
+    fn parse_program(
+        &mut self,
+        unit: &mut Reader,
+        offset_size: usize,
+        strs: StrSections,
+    ) -> Option<()> {
+        let version = unit.uint(2)? as u16;
+        if !(2..=5).contains(&version) {
+            return None;
+        }
+        if version >= 5 {
+            // address_size, segment_selector_size
+            unit.bytes(2)?;
+        }
+        let header_len = usize::try_from(unit.uint(offset_size)?).ok()?;
+        let program = unit.pos.checked_add(header_len)?;
+        let min_inst_len = unit.u8()?;
+        if version >= 4 {
+            // maximum_operations_per_instruction
+            unit.u8()?;
+        }
+        // default_is_stmt
+        unit.u8()?;
+        let line_base = unit.u8()? as i8;
+        let line_range = unit.u8()?;
+        let opcode_base = unit.u8()?;
+        if line_range == 0 || opcode_base == 0 {
+            return None;
+        }
+        let std_opcode_lengths = unit.bytes(opcode_base as usize - 1)?;
+        let mut header = UnitHeader {
+            version,
+            offset_size,
+            min_inst_len,
+            line_base,
+            line_range,
+            opcode_base,
+            std_opcode_lengths,
+            file_base: self.files.len() as u32,
+        };
+        if version >= 5 {
+            self.parse_entries_v5(unit, &header, strs)?;
+        } else {
+            self.parse_entries_v4(unit)?;
+            // the file numbers of the former versions start at 1
+            header.file_base = header.file_base.wrapping_sub(1);
+        }
+        unit.pos = program;
+        self.run_program(unit, &header)
+    }
+
+    /// Reads the directories and the files of a unit before version 5.
+    fn parse_entries_v4(&mut self, unit: &mut Reader) -> Option<()> {
+        let mut dirs = Vec::new();
+        loop {
+            match unit.cstr()? {
+                "" => break,
+                dir => dirs.push(dir),
+            }
+        }
+        loop {
+            let name = unit.cstr()?;
+            if name.is_empty() {
+                return Some(());
+            }
+            let dir = unit.uleb()? as usize;
+            // modification time, length
+            unit.uleb()?;
+            unit.uleb()?;
+            // the directory 0 is the one of the compilation, unknown here
+            let dir = dir.checked_sub(1).and_then(|i| dirs.get(i)).copied();
+            self.files.push(join_path(dir.unwrap_or(""), name));
+        }
+    }
+
+    /// Reads the directories and the files of a version 5 unit, described
+    /// by their entry formats.
+    fn parse_entries_v5(
+        &mut self,
+        unit: &mut Reader,
+        header: &UnitHeader,
+        strs: StrSections,
+    ) -> Option<()> {
+        let mut dirs = Vec::new();
+        for (path, _) in Self::parse_entry_list(unit, header, strs)? {
+            dirs.push(path);
+        }
+        for (path, dir) in Self::parse_entry_list(unit, header, strs)? {
+            let dir = dirs.get(dir as usize).map_or("", String::as_str);
+            self.files.push(join_path(dir, &path));
+        }
+        Some(())
+    }
+
+    /// Reads a list of entries, returns their paths and directory indices.
+    fn parse_entry_list(
+        unit: &mut Reader,
+        header: &UnitHeader,
+        strs: StrSections,
+    ) -> Option<Vec<(String, u64)>> {
+        let format_count = unit.u8()?;
+        let mut formats = Vec::new();
+        for _ in 0..format_count {
+            formats.push((unit.uleb()?, unit.uleb()?));
+        }
+        let count = unit.uleb()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let (mut path, mut dir) = (String::new(), 0);
+            for &(content, form) in &formats {
+                let offset_size = header.offset_size;
+                let value = match form {
+                    DW_FORM_STRING => Some(unit.cstr()?),
+                    DW_FORM_LINE_STRP => Some(str_at(strs.line_str, unit.uint(offset_size)?)?),
+                    DW_FORM_STRP => Some(str_at(strs.str, unit.uint(offset_size)?)?),
+                    _ => {
+                        let value = match form {
+                            DW_FORM_UDATA => unit.uleb()?,
+                            DW_FORM_DATA1 => unit.uint(1)?,
+                            DW_FORM_DATA2 => unit.uint(2)?,
+                            DW_FORM_DATA4 => unit.uint(4)?,
+                            DW_FORM_DATA8 => unit.uint(8)?,
+                            DW_FORM_DATA16 => {
+                                unit.bytes(16)?;
+                                0
+                            }
+                            DW_FORM_BLOCK => {
+                                let len = unit.uleb()?;
+                                unit.bytes(usize::try_from(len).ok()?)?;
+                                0
+                            }
+                            // the other forms (`strx`...) need the other
+                            // sections, give up the unit
+                            _ => return None,
+                        };
+                        if content == DW_LNCT_DIRECTORY_INDEX {
+                            dir = value;
+                        }
+                        None
+                    }
+                };
+                if let (DW_LNCT_PATH, Some(value)) = (content, value) {
+                    path = value.into();
+                }
+            }
+            entries.push((path, dir));
+        }
+        Some(entries)
+    }
+
+    /// Runs the line number program of a unit, adding its rows.
+    fn run_program(&mut self, unit: &mut Reader, header: &UnitHeader) -> Option<()> {
+        let min_inst_len = header.min_inst_len as u64;
+        let line_range = header.line_range as u64;
+        let mut addr = 0u64;
+        let mut file = 1u64;
+        let mut line = 1i64;
+        let row = |table: &mut Self, addr: u64, file: u64, line: i64, end: bool| {
+            table.rows.push(Row {
+                addr,
+                file: header.file_base.wrapping_add(file as u32),
+                line: line as u32,
+                end,
+            });
+        };
+        while unit.pos < unit.data.len() {
+            let opcode = unit.u8()?;
+            if opcode >= header.opcode_base {
+                let adjusted = (opcode - header.opcode_base) as u64;
+                addr = addr.wrapping_add(adjusted / line_range * min_inst_len);
+                line += header.line_base as i64 + (adjusted % line_range) as i64;
+                row(self, addr, file, line, false);
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = usize::try_from(unit.uleb()?).ok()?;
+                    let end = unit.pos.checked_add(len)?;
+                    match unit.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            row(self, addr, file, line, true);
+                            addr = 0;
+                            file = 1;
+                            line = 1;
+                        }
+                        DW_LNE_SET_ADDRESS => addr = unit.uint(len.checked_sub(1)?)?,
+                        DW_LNE_DEFINE_FILE if header.version < 5 => {
+                            let name = unit.cstr()?;
+                            self.files.push(name.into());
+                        }
+                        _ => {}
+                    }
+                    unit.pos = end;
+                }
+                DW_LNS_COPY => row(self, addr, file, line, false),
+                DW_LNS_ADVANCE_PC => addr = addr.wrapping_add(unit.uleb()? * min_inst_len),
+                DW_LNS_ADVANCE_LINE => line += unit.sleb()?,
+                DW_LNS_SET_FILE => file = unit.uleb()?,
+                DW_LNS_SET_COLUMN => {
+                    unit.uleb()?;
+                }
+                DW_LNS_NEGATE_STMT | DW_LNS_BASIC_BLOCK => {}
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted = (255 - header.opcode_base) as u64;
+                    addr = addr.wrapping_add(adjusted / line_range * min_inst_len);
+                }
+                DW_LNS_FIXED_ADVANCE_PC => addr = addr.wrapping_add(unit.uint(2)?),
+                // the other standard opcodes (prologue_end, set_isa...),
+                // with the number of their arguments
+                _ => {
+                    for _ in 0..header.std_opcode_lengths[opcode as usize - 1] {
+                        unit.uleb()?;
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the file and the line of the code at `addr`.
+    pub fn lookup(&self, addr: u64) -> Option<(&str, u32)> {
+        let idx = self.rows.partition_point(|row| row.addr <= addr);
+        let row = self.rows[..idx].last()?;
+        if row.end {
+            return None;
+        }
+        let file = self.files.get(row.file as usize)?;
+        Some((file, row.line))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
@@ -0,0 +1,344 @@
+//! seccomp-like syscall filtering.
+//!
+//! A [`SyscallFilter`] is attached to a user task with its app, at spawn and
+//! at exec, and is consulted by the syscall dispatcher before every syscall.
+//! Rules are checked in insertion order, the first matching rule decides the
+//! action, otherwise the default action applies. So an allowlist is a filter
+//! with a denying default, and a denylist is one with [`FilterAction::Allow`].
+//!
+//! As seccomp filters, the filter of a task is kept across exec: the one of
+//! the new app is stacked on it (see [`exec_filter`]), the most restrictive
+//! action of both applies, so a sandboxed task cannot exec its way out.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::LinuxError;
+
+/// What to do with a filtered syscall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Run the syscall.
+    Allow,
+    /// Fail the syscall with the given error, without running it.
+    Errno(LinuxError),
+    /// Kill the calling task.
+    #[allow(dead_code)] // not used by the sandbox of the app
+    Kill,
+}
+
+impl FilterAction {
+    /// The more restrictive of `self` and `other`, `self` on a tie.
+    fn stricter(self, other: Self) -> Self {
+        let rank = |action| match action {
+            Self::Allow => 0,
+            Self::Errno(_) => 1,
+            Self::Kill => 2,
+        };
+        if rank(other) > rank(self) {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// A comparison applied to one syscall argument.
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)] // not all used by the sandbox of the app
+pub enum ArgCmp {
+    /// `arg == value`
+    Eq(usize),
+    /// `arg != value`
+    Ne(usize),
+    /// `arg < value`
+    Lt(usize),
+    /// `arg <= value`
+    Le(usize),
+    /// `arg > value`
+    Gt(usize),
+    /// `arg >= value`
+    Ge(usize),
+    /// `arg & mask == value`
+    MaskedEq { mask: usize, value: usize },
+}
+
+impl ArgCmp {
+    fn matches(self, arg: usize) -> bool {
+        match self {
+            Self::Eq(v) => arg == v,
+            Self::Ne(v) => arg != v,
+            Self::Lt(v) => arg < v,
+            Self::Le(v) => arg <= v,
+            Self::Gt(v) => arg > v,
+            Self::Ge(v) => arg >= v,
+            Self::MaskedEq { mask, value } => arg & mask == value,
+        }
+    }
+}
+
+/// A constraint on the `index`-th (0 to 5) syscall argument.
+#[derive(Debug, Clone, Copy)]
+pub struct ArgConstraint {
+    pub index: usize,
+    pub cmp: ArgCmp,
+}
+
+impl ArgConstraint {
+    pub const fn new(index: usize, cmp: ArgCmp) -> Self {
+        assert!(index < 6);
+        Self { index, cmp }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    sysno: usize,
+    args: Vec<ArgConstraint>,
+    action: FilterAction,
+}
+
+/// A per-task syscall filter.
+#[derive(Debug)]
+pub struct SyscallFilter {
+    default: FilterAction,
+    rules: Vec<Rule>,
+    /// The filter of the previous app, which also applies.
+    parent: Option<Arc<SyscallFilter>>,
+}
+
+impl SyscallFilter {
+    /// Creates a filter that applies `default` to syscalls matching no rule.
+    pub const fn new(default: FilterAction) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+            parent: None,
+        }
+    }
+
+    /// Creates an allowlist, which fails every syscall not explicitly
+    /// allowed with `EPERM`.
+    #[allow(dead_code)]
+    pub const fn allowlist() -> Self {
+        Self::new(FilterAction::Errno(LinuxError::EPERM))
+    }
+
+    /// Creates a denylist, which allows every syscall not explicitly denied.
+    #[allow(dead_code)]
+    pub const fn denylist() -> Self {
+        Self::new(FilterAction::Allow)
+    }
+
+    /// Adds a rule applying `action` to syscall `sysno` when all `args`
+    /// constraints hold.
+    pub fn rule(mut self, sysno: usize, args: &[ArgConstraint], action: FilterAction) -> Self {
+        self.rules.push(Rule {
+            sysno,
+            args: args.to_vec(),
+            action,
+        });
+        self
+    }
+
+    /// Allows syscall `sysno` unconditionally.
+    pub fn allow(self, sysno: usize) -> Self {
+        self.rule(sysno, &[], FilterAction::Allow)
+    }
+
+    /// Fails syscall `sysno` with `err` unconditionally.
+    #[allow(dead_code)]
+    pub fn deny(self, sysno: usize, err: LinuxError) -> Self {
+        self.rule(sysno, &[], FilterAction::Errno(err))
+    }
+
+    /// Decides what to do with syscall `sysno` called with `args`, by this
+    /// filter and the ones it is stacked on.
+    pub fn check(&self, sysno: usize, args: &[usize; 6]) -> FilterAction {
+        let action = self
+            .rules
+            .iter()
+            .find(|r| r.sysno == sysno && r.args.iter().all(|c| c.cmp.matches(args[c.index])))
+            .map_or(self.default, |r| r.action);
+        match &self.parent {
+            Some(parent) => parent.check(sysno, args).stricter(action),
+            None => action,
+        }
+    }
+}
+
+/// The filter of a task after the exec of an app with the filter `app`, if
+/// the task ran in `current`: the filter of the app is stacked on the current
+/// one, which is kept if the app has none.
+pub fn exec_filter(
+    current: Option<Arc<SyscallFilter>>,
+    app: Option<SyscallFilter>,
+) -> Option<Arc<SyscallFilter>> {
+    match (current, app) {
+        (Some(current), Some(mut app)) => {
+            app.parent = Some(current);
+            Some(Arc::new(app))
+        }
+        (current, None) => current,
+        (None, app) => app.map(Arc::new),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYS_READ: usize = 63;
+    const SYS_WRITE: usize = 64;
+    const SYS_MMAP: usize = 222;
+    const SYS_OTHER: usize = 0x2000;
+
+    fn args(args: &[usize]) -> [usize; 6] {
+        let mut all = [0; 6];
+        all[..args.len()].copy_from_slice(args);
+        all
+    }
+
+    #[test]
+    fn test_defaults() {
+        let allowlist = SyscallFilter::allowlist().allow(SYS_READ);
+        assert_eq!(allowlist.check(SYS_READ, &args(&[])), FilterAction::Allow);
+        assert_eq!(
+            allowlist.check(SYS_WRITE, &args(&[])),
+            FilterAction::Errno(LinuxError::EPERM)
+        );
+
+        let denylist = SyscallFilter::denylist().deny(SYS_WRITE, LinuxError::EACCES);
+        assert_eq!(denylist.check(SYS_READ, &args(&[])), FilterAction::Allow);
+        assert_eq!(
+            denylist.check(SYS_WRITE, &args(&[])),
+            FilterAction::Errno(LinuxError::EACCES)
+        );
+
+        let kill = SyscallFilter::new(FilterAction::Kill);
+        assert_eq!(kill.check(SYS_READ, &args(&[])), FilterAction::Kill);
+    }
+
+    #[test]
+    fn test_first_match() {
+        // The denying rule comes first, so it wins over the allowing one.
+        let filter = SyscallFilter::allowlist()
+            .deny(SYS_WRITE, LinuxError::EBADF)
+            .allow(SYS_WRITE);
+        assert_eq!(
+            filter.check(SYS_WRITE, &args(&[])),
+            FilterAction::Errno(LinuxError::EBADF)
+        );
+
+        let filter = SyscallFilter::allowlist()
+            .allow(SYS_WRITE)
+            .deny(SYS_WRITE, LinuxError::EBADF);
+        assert_eq!(filter.check(SYS_WRITE, &args(&[])), FilterAction::Allow);
+    }
+
+    #[test]
+    fn test_exec_cannot_loosen() {
+        let sandbox = Arc::new(
+            SyscallFilter::allowlist()
+                .allow(SYS_READ)
+                .allow(SYS_MMAP)
+                .deny(SYS_WRITE, LinuxError::EBADF),
+        );
+
+        // The app allows all, the sandbox still applies.
+        let filter = exec_filter(Some(sandbox.clone()), Some(SyscallFilter::denylist())).unwrap();
+        assert_eq!(filter.check(SYS_READ, &args(&[])), FilterAction::Allow);
+        assert_eq!(
+            filter.check(SYS_WRITE, &args(&[])),
+            FilterAction::Errno(LinuxError::EBADF)
+        );
+        assert_eq!(
+            filter.check(SYS_OTHER, &args(&[])),
+            FilterAction::Errno(LinuxError::EPERM)
+        );
+
+        // The app has no filter, the sandbox is kept.
+        let filter = exec_filter(Some(sandbox.clone()), None).unwrap();
+        assert!(Arc::ptr_eq(&filter, &sandbox));
+
+        // The app restricts the sandbox further, the stricter action wins.
+        let app = SyscallFilter::denylist()
+            .deny(SYS_READ, LinuxError::EACCES)
+            .rule(SYS_MMAP, &[], FilterAction::Kill);
+        let filter = exec_filter(Some(sandbox.clone()), Some(app)).unwrap();
+        assert_eq!(
+            filter.check(SYS_READ, &args(&[])),
+            FilterAction::Errno(LinuxError::EACCES)
+        );
+        assert_eq!(filter.check(SYS_MMAP, &args(&[])), FilterAction::Kill);
+
+        // Stacks again at the next exec.
+        let filter = exec_filter(Some(filter), Some(SyscallFilter::denylist())).unwrap();
+        assert_eq!(filter.check(SYS_MMAP, &args(&[])), FilterAction::Kill);
+        assert_eq!(
+            filter.check(SYS_WRITE, &args(&[])),
+            FilterAction::Errno(LinuxError::EBADF)
+        );
+
+        // Not sandboxed, the filter of the app is attached.
+        let filter = exec_filter(None, Some(SyscallFilter::allowlist())).unwrap();
+        assert_eq!(
+            filter.check(SYS_READ, &args(&[])),
+            FilterAction::Errno(LinuxError::EPERM)
+        );
+        assert!(exec_filter(None, None).is_none());
+    }
+
+    #[test]
+    fn test_arg_constraints() {
+        // Writes to stdout and stderr only.
+        let filter = SyscallFilter::allowlist()
+            .rule(
+                SYS_WRITE,
+                &[
+                    ArgConstraint::new(0, ArgCmp::Ge(1)),
+                    ArgConstraint::new(0, ArgCmp::Le(2)),
+                ],
+                FilterAction::Allow,
+            )
+            .rule(SYS_WRITE, &[], FilterAction::Kill);
+        assert_eq!(filter.check(SYS_WRITE, &args(&[0])), FilterAction::Kill);
+        assert_eq!(filter.check(SYS_WRITE, &args(&[1])), FilterAction::Allow);
+        assert_eq!(filter.check(SYS_WRITE, &args(&[2])), FilterAction::Allow);
+        assert_eq!(filter.check(SYS_WRITE, &args(&[3])), FilterAction::Kill);
+
+        // No writable and executable mappings, as the sandbox of the app.
+        const PROT_WX: usize = 0x2 | 0x4;
+        let wx = ArgCmp::MaskedEq {
+            mask: PROT_WX,
+            value: PROT_WX,
+        };
+        let filter = SyscallFilter::denylist().rule(
+            SYS_MMAP,
+            &[ArgConstraint::new(2, wx)],
+            FilterAction::Errno(LinuxError::EACCES),
+        );
+        let (allowed, denied) = (FilterAction::Allow, FilterAction::Errno(LinuxError::EACCES));
+        assert_eq!(filter.check(SYS_MMAP, &args(&[0, 4096, 0x3])), allowed);
+        assert_eq!(filter.check(SYS_MMAP, &args(&[0, 4096, 0x5])), allowed);
+        assert_eq!(filter.check(SYS_MMAP, &args(&[0, 4096, 0x6])), denied);
+        assert_eq!(filter.check(SYS_MMAP, &args(&[0, 4096, 0x7])), denied);
+
+        let cmps = [
+            (ArgCmp::Eq(5), [false, true, false]),
+            (ArgCmp::Ne(5), [true, false, true]),
+            (ArgCmp::Lt(5), [true, false, false]),
+            (ArgCmp::Gt(5), [false, false, true]),
+        ];
+        for (cmp, expected) in cmps {
+            let filter = SyscallFilter::allowlist().rule(
+                SYS_READ,
+                &[ArgConstraint::new(5, cmp)],
+                FilterAction::Allow,
+            );
+            for (arg, allowed) in [4, 5, 6].into_iter().zip(expected) {
+                let action = filter.check(SYS_READ, &args(&[0, 0, 0, 0, 0, arg]));
+                assert_eq!(action == FilterAction::Allow, allowed, "{:?} {}", cmp, arg);
+            }
+        }
+    }
+}
@@ -4,11 +4,15 @@ use std::io::Seek;
 use std::fs::File;
 use alloc::vec::Vec;
 use alloc::vec;
+use axhal::arch::UspaceContext;
 use axhal::paging::MappingFlags;
 use axhal::mem::{PAGE_SIZE_4K, VirtAddr, MemoryAddr};
-use axmm::AddrSpace;
+use axmm::{AddrSpace, UserLayout};
 
-use elf::abi::{PT_INTERP, PT_LOAD};
+use crate::backtrace::DebugImage;
+use crate::filter::SyscallFilter;
+
+use elf::abi::{ET_DYN, PT_INTERP, PT_LOAD};
 use elf::endian::AnyEndian;
 use elf::parse::ParseAt;
 use elf::segment::ProgramHeader;
@@ -17,9 +21,33 @@ use elf::ElfBytes;
 
 const ELF_HEAD_BUF_SIZE: usize = 256;
 
-pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
+/// A user app loaded in an address space, ready to run in a new task or in
+/// the current one after `execve`.
+pub struct LoadedApp {
+    /// The layout the app was loaded with.
+    pub layout: UserLayout,
+    /// The context at the entry of the app, on its stack.
+    pub uctx: UspaceContext,
+    /// The start of the heap, after the image at a random offset.
+    pub heap_start: VirtAddr,
+    /// The debug info of the executable.
+    pub image: DebugImage,
+    /// The syscall filter the app runs in, if sandboxed.
+    pub filter: Option<SyscallFilter>,
+}
+
+/// Loads the ELF file `fname`, returns its entry, the end of its image and its
+/// debug info.
+pub fn load_user_app(
+    fname: &str,
+    uspace: &mut AddrSpace,
+    layout: &UserLayout,
+) -> io::Result<(usize, VirtAddr, DebugImage)> {
     let mut file = File::open(fname)?;
-    let (phdrs, entry, _, _) = load_elf_phdrs(&mut file)?;
+    let (phdrs, entry, _, _, is_pie) = load_elf_phdrs(&mut file)?;
+    // Position-independent executables are loaded at the randomized base.
+    let bias = if is_pie { layout.pie_base.as_usize() } else { 0 };
+    let mut image_end = VirtAddr::from(bias);
 
     for phdr in &phdrs {
         ax_println!(
@@ -27,11 +55,12 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             phdr.p_offset, phdr.p_vaddr, phdr.p_filesz, phdr.p_memsz
         );
 
-        let vaddr = VirtAddr::from(phdr.p_vaddr as usize).align_down_4k();
-        let vaddr_end = VirtAddr::from((phdr.p_vaddr+phdr.p_memsz) as usize)
+        let vaddr = VirtAddr::from(bias + phdr.p_vaddr as usize).align_down_4k();
+        let vaddr_end = VirtAddr::from(bias + (phdr.p_vaddr+phdr.p_memsz) as usize)
             .align_up_4k();
 
         ax_println!("{:#x} - {:#x}", vaddr, vaddr_end);
+        image_end = image_end.max(vaddr_end);
         uspace.map_alloc(vaddr, vaddr_end-vaddr, MappingFlags::READ|MappingFlags::WRITE|MappingFlags::EXECUTE|MappingFlags::USER, true)?;
 
         let mut data = vec![0u8; phdr.p_memsz as usize];
@@ -44,13 +73,13 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             index += n;
         }
         assert_eq!(index, filesz);
-        uspace.write(VirtAddr::from(phdr.p_vaddr as usize), &data)?;
+        uspace.write(VirtAddr::from(bias + phdr.p_vaddr as usize), &data)?;
     }
 
-    Ok(entry)
+    Ok((bias + entry, image_end, DebugImage::load(fname, bias)))
 }
 
-fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usize, usize)> {
+fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usize, usize, bool)> {
     let mut buf: [u8; ELF_HEAD_BUF_SIZE] = [0; ELF_HEAD_BUF_SIZE];
     file.read(&mut buf)?;
 
@@ -72,5 +101,6 @@ fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usi
         .iter()
         .filter(|phdr| phdr.p_type == PT_LOAD || phdr.p_type == PT_INTERP)
         .collect();
-    Ok((phdrs, ehdr.e_entry as usize, ehdr.e_phoff as usize, ehdr.e_phnum as usize))
+    let is_pie = ehdr.e_type == ET_DYN;
+    Ok((phdrs, ehdr.e_entry as usize, ehdr.e_phoff as usize, ehdr.e_phnum as usize, is_pie))
 }
@@ -11,6 +11,12 @@ extern crate axlog;
 mod task;
 mod syscall;
 mod loader;
+mod filter;
+mod coredump;
+mod backtrace;
+mod dwarf;
+mod ptrace;
+mod batch;
 
 use axstd::io;
 use axhal::paging::MappingFlags;
@@ -20,41 +26,111 @@ use axsync::Mutex;
 use alloc::sync::Arc;
 use alloc::string::String;
 use alloc::collections::BTreeMap;
-use axmm::AddrSpace;
-use loader::load_user_app;
+use axmm::{AddrSpace, UserLayout};
+use loader::{load_user_app, LoadedApp};
+use filter::{ArgCmp, ArgConstraint, FilterAction, SyscallFilter};
+use axerrno::LinuxError;
 
+const APP_PATH: &str = "/sbin/mapfile";
 const USER_STACK_SIZE: usize = 0x10000;
 const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
 
+/// Whether the user layouts are randomized if there is no `aslr=` on the
+/// command line.
+const DEFAULT_ASLR: bool = !matches!(option_env!("AX_ASLR"), Some("n"));
+
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
+    init_aslr();
     // A new address space for user app.
     let mut uspace = axmm::new_user_aspace().unwrap();
 
     // Load user app binary file into address space.
-    let entry = match load_user_app("/sbin/mapfile", &mut uspace) {
-        Ok(e) => e,
+    let app = match load_app(APP_PATH, &mut uspace) {
+        Ok(app) => app,
         Err(err) => panic!("Cannot load app! {:?}", err),
     };
-    ax_println!("entry: {:#x}", entry);
-
-    // Init user stack.
-    let ustack_top = init_user_stack(&mut uspace, true).unwrap();
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
-    let user_task = task::spawn_user_task(
-        Arc::new(Mutex::new(uspace)),
-        UspaceContext::new(entry, ustack_top),
-    );
+    let user_task = task::spawn_user_task(Arc::new(Mutex::new(uspace)), APP_PATH.into(), app);
 
-    // Wait for user process to exit ...
-    let exit_code = user_task.join();
+    // Wait for user process to exit, traced like `strace` if asked.
+    let exit_code = if option_env!("AX_STRACE") == Some("y") {
+        ptrace::strace(&user_task).ok()
+    } else {
+        user_task.join()
+    };
     ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
 }
 
-fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
-    let ustack_top = uspace.end();
+/// Loads the app `path` in `uspace` with its stack, at spawn and at exec.
+fn load_app(path: &str, uspace: &mut AddrSpace) -> io::Result<LoadedApp> {
+    // Randomized (unless ASLR is disabled) bases of stack, mmap and PIE.
+    let layout = UserLayout::new(uspace);
+    let (entry, image_end, image) = load_user_app(path, uspace, &layout)?;
+    axaudit::audit(axaudit::AuditEvent::Exec { path: path.into() });
+    ax_println!("entry: {:#x}", entry);
+
+    // Init user stack.
+    let ustack_top = init_user_stack(uspace, &layout, true)?;
+    Ok(LoadedApp {
+        layout,
+        uctx: UspaceContext::new(entry, ustack_top),
+        heap_start: layout.heap_start(image_end),
+        image,
+        filter: Some(app_syscall_filter()),
+    })
+}
+
+/// Sandbox for the user app: only the implemented syscalls are allowed (the
+/// others fail with `ENOSYS` as before), and mappings may not be both
+/// writable and executable.
+fn app_syscall_filter() -> SyscallFilter {
+    use syscall::*;
+    const PROT_WX: usize = 0x2 | 0x4;
+    SyscallFilter::new(FilterAction::Errno(LinuxError::ENOSYS))
+        .rule(
+            SYS_MMAP,
+            &[ArgConstraint::new(2, ArgCmp::MaskedEq { mask: PROT_WX, value: PROT_WX })],
+            FilterAction::Errno(LinuxError::EACCES),
+        )
+        .allow(SYS_MMAP)
+        .allow(SYS_IOCTL)
+        .allow(SYS_OPENAT)
+        .allow(SYS_CLOSE)
+        .allow(SYS_READ)
+        .allow(SYS_WRITE)
+        .allow(SYS_WRITEV)
+        .allow(SYS_SET_TID_ADDRESS)
+        .allow(SYS_BRK)
+        .allow(SYS_EXIT)
+        .allow(SYS_EXIT_GROUP)
+        .allow(SYS_EXECVE)
+        .allow(SYS_RING_CREATE)
+        .allow(SYS_RING_MAP)
+        .allow(SYS_RING_WAIT)
+        .allow(SYS_RING_WAKE)
+        .allow(SYS_BATCH)
+}
+
+/// Switches ASLR by `aslr=on` or `aslr=off` on the command line, or by
+/// `ASLR` at build time.
+fn init_aslr() {
+    let enabled = match axhal::cmdline::args().find_map(|arg| arg.strip_prefix("aslr=")) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => DEFAULT_ASLR,
+    };
+    axmm::set_aslr_enabled(enabled);
+}
+
+fn init_user_stack(
+    uspace: &mut AddrSpace,
+    layout: &UserLayout,
+    populating: bool,
+) -> io::Result<VirtAddr> {
+    let ustack_top = layout.stack_top;
     let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
     ax_println!(
         "Mapping user stack: {:#x?} -> {:#x?}",
@@ -0,0 +1,381 @@
+//! ptrace-like debugging of the user tasks.
+//!
+//! A supervisor, a kernel task like an on-target debugger, attaches to a user
+//! task with [`Tracee::attach`]. The tracee then stops in the kernel, where
+//! [`Tracee::wait`] reports why:
+//!
+//! - at its next syscall once [`Tracee::interrupt`] is called (a task which
+//!   never makes a syscall can not be interrupted);
+//! - at the entry and the exit of each syscall, with
+//!   [`Tracee::set_trace_syscalls`];
+//! - at a fatal trap or a breakpoint (`ebreak`), before it is killed.
+//!
+//! While it is stopped, its user registers (the trap frame at the top of its
+//! kernel stack) and its memory can be read and written, until it is resumed
+//! by [`Tracee::cont`]. The exit of the tracee is reported too.
+//!
+//! At a syscall entry stop, the syscall number (`a7`) and the arguments can
+//! be changed before it is run, or the syscall is skipped if the number is
+//! set to `-1`, returning `a0`. At a syscall exit stop, `a0` is the return
+//! value. The `sepc` of both is the one of the `ecall`, 4 is added once the
+//! syscall returns.
+//!
+//! At a fault stop, the tracee is killed by the signal if it is resumed with
+//! it, otherwise the trapping instruction is run again: the tracer has fixed
+//! it, e.g. restored the instruction of a breakpoint.
+
+#![allow(dead_code)]
+
+use core::mem::size_of;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axhal::mem::VirtAddr;
+use axsync::spin::SpinNoIrq;
+use axtask::{current, AxTaskRef, TaskExtRef, WaitQueue};
+
+/// Why a tracee is stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// At a syscall, by [`Tracee::interrupt`].
+    Interrupted,
+    /// At the entry of the syscall, before it is checked by the filters.
+    SyscallEnter(usize),
+    /// At the exit of the syscall.
+    SyscallExit(usize),
+    /// At a fatal trap or a breakpoint of the user space, with the signal
+    /// killing the tracee and the faulting address.
+    Fault { signal: i32, addr: VirtAddr },
+}
+
+/// What [`Tracee::wait`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// The tracee is stopped.
+    Stopped(StopReason),
+    /// The tracee exited with the exit code.
+    Exited(i32),
+}
+
+#[derive(Default)]
+struct TraceInner {
+    attached: bool,
+    trace_syscalls: bool,
+    interrupt: bool,
+    stop: Option<StopReason>,
+    exit_code: Option<i32>,
+    /// The signal a fault stop is resumed with.
+    resume_signal: Option<i32>,
+    /// Whether the memory was written while stopped, the code may be.
+    mem_written: bool,
+}
+
+/// The trace state of a user task, in its [`TaskExt`](crate::task::TaskExt).
+pub struct TraceState {
+    inner: SpinNoIrq<TraceInner>,
+    /// The tracee waits here to be resumed.
+    resume_wq: WaitQueue,
+    /// The tracer waits here for the tracee to stop or exit.
+    event_wq: WaitQueue,
+}
+
+impl TraceState {
+    pub const fn new() -> Self {
+        Self {
+            inner: SpinNoIrq::new(TraceInner {
+                attached: false,
+                trace_syscalls: false,
+                interrupt: false,
+                stop: None,
+                exit_code: None,
+                resume_signal: None,
+                mem_written: false,
+            }),
+            resume_wq: WaitQueue::new(),
+            event_wq: WaitQueue::new(),
+        }
+    }
+
+    /// Stops the current task, the tracee, until it is resumed or detached.
+    /// Returns the signal it is resumed with.
+    fn stop(&self, reason: StopReason) -> Option<i32> {
+        // The tracee stops in a trap from the user space, no lock of the
+        // kernel is held.
+        axhal::arch::enable_irqs();
+        {
+            let mut inner = self.inner.lock();
+            if !inner.attached {
+                return None;
+            }
+            inner.stop = Some(reason);
+            inner.resume_signal = None;
+        }
+        self.event_wq.notify_all(false);
+        self.resume_wq
+            .wait_until(|| self.inner.lock().stop.is_none());
+        let mut inner = self.inner.lock();
+        if core::mem::take(&mut inner.mem_written) {
+            axhal::arch::flush_icache_all();
+        }
+        inner.resume_signal.take()
+    }
+}
+
+/// The user registers of `task`, saved at the top of its kernel stack when it
+/// trapped from the user space.
+pub(crate) fn user_trap_frame(task: &AxTaskRef) -> *mut TrapFrame {
+    let kstack_top = task.kernel_stack_top().unwrap();
+    (kstack_top.as_usize() - size_of::<TrapFrame>()) as *mut TrapFrame
+}
+
+/// Whether the syscalls of the current task are traced, or it must stop at
+/// its next syscall.
+pub fn syscall_traced() -> bool {
+    let curr = current();
+    let inner = curr.task_ext().trace.inner.lock();
+    inner.attached && (inner.trace_syscalls || inner.interrupt)
+}
+
+/// Runs the syscall of the current task by `dispatch` with the stops asked
+/// by its tracer, returns its return value.
+pub fn trace_syscall(dispatch: impl FnOnce(&TrapFrame, usize) -> isize) -> isize {
+    let curr = current();
+    let state = &curr.task_ext().trace;
+    let frame = user_trap_frame(curr.as_task_ref());
+    let interrupt = core::mem::take(&mut state.inner.lock().interrupt);
+    if interrupt {
+        state.stop(StopReason::Interrupted);
+    }
+    // SAFETY: the frame is only written by the tracer while it is stopped.
+    let tf = unsafe { frame.read_volatile() };
+    if state.inner.lock().trace_syscalls {
+        state.stop(StopReason::SyscallEnter(tf.regs.a7));
+    }
+    let tf = unsafe { frame.read_volatile() };
+    let sysno = tf.regs.a7;
+    let ret = if sysno == usize::MAX {
+        tf.regs.a0 as isize
+    } else {
+        dispatch(&tf, sysno)
+    };
+    if !state.inner.lock().trace_syscalls {
+        return ret;
+    }
+    unsafe { (*frame).regs.a0 = ret as usize };
+    state.stop(StopReason::SyscallExit(sysno));
+    unsafe { (*frame).regs.a0 as isize }
+}
+
+/// Reports the fatal trap of the current task to its tracer, returns the
+/// signal it must be killed with, or `None` to run the instruction again.
+pub fn report_fault(signal: i32, addr: VirtAddr) -> Option<i32> {
+    let curr = current();
+    let state = &curr.task_ext().trace;
+    if !state.inner.lock().attached {
+        return Some(signal);
+    }
+    state.stop(StopReason::Fault { signal, addr })
+}
+
+/// Reports the exit of the current task to its tracer.
+pub fn report_exit(exit_code: i32) {
+    let curr = current();
+    let state = &curr.task_ext().trace;
+    let mut inner = state.inner.lock();
+    if inner.attached {
+        inner.exit_code = Some(exit_code);
+        drop(inner);
+        state.event_wq.notify_all(false);
+    }
+}
+
+/// A user task traced by the current task.
+///
+/// It is detached when dropped, and resumed if it was stopped.
+pub struct Tracee {
+    task: AxTaskRef,
+}
+
+impl Tracee {
+    /// Attaches to the user task `task`, which keeps running.
+    ///
+    /// Fails with `EPERM` if it is the current task or it is traced already,
+    /// and with `ESRCH` if it exited.
+    pub fn attach(task: &AxTaskRef) -> LinuxResult<Self> {
+        if task.id() == current().id() {
+            return Err(LinuxError::EPERM);
+        }
+        let mut inner = task.task_ext().trace.inner.lock();
+        if inner.attached {
+            return Err(LinuxError::EPERM);
+        }
+        if task.state_name() == "exited" {
+            return Err(LinuxError::ESRCH);
+        }
+        *inner = TraceInner {
+            attached: true,
+            ..Default::default()
+        };
+        Ok(Self { task: task.clone() })
+    }
+
+    fn state(&self) -> &TraceState {
+        &self.task.task_ext().trace
+    }
+
+    /// The task traced.
+    pub fn task(&self) -> &AxTaskRef {
+        &self.task
+    }
+
+    /// Sets whether the tracee stops at the entry and the exit of each
+    /// syscall.
+    pub fn set_trace_syscalls(&self, enabled: bool) {
+        self.state().inner.lock().trace_syscalls = enabled;
+    }
+
+    /// Asks the tracee to stop at its next syscall.
+    pub fn interrupt(&self) {
+        self.state().inner.lock().interrupt = true;
+    }
+
+    /// Blocks until the tracee is stopped or exited.
+    pub fn wait(&self) -> TraceEvent {
+        let state = self.state();
+        state.event_wq.wait_until(|| {
+            let inner = state.inner.lock();
+            inner.stop.is_some() || inner.exit_code.is_some()
+        });
+        let inner = state.inner.lock();
+        match inner.exit_code {
+            Some(code) => TraceEvent::Exited(code),
+            None => TraceEvent::Stopped(inner.stop.unwrap()),
+        }
+    }
+
+    /// Returns why the tracee is stopped, fails with `ESRCH` if it is not.
+    pub fn stop_reason(&self) -> LinuxResult<StopReason> {
+        self.state().inner.lock().stop.ok_or(LinuxError::ESRCH)
+    }
+
+    /// Resumes the stopped tracee, with the signal killing it at a fault
+    /// stop (it is ignored at the other stops).
+    pub fn cont(&self, signal: Option<i32>) -> LinuxResult {
+        let state = self.state();
+        let mut inner = state.inner.lock();
+        if inner.stop.take().is_none() {
+            return Err(LinuxError::ESRCH);
+        }
+        inner.resume_signal = signal;
+        drop(inner);
+        state.resume_wq.notify_all(false);
+        Ok(())
+    }
+
+    /// Reads the user registers of the stopped tracee.
+    pub fn regs(&self) -> LinuxResult<TrapFrame> {
+        self.stop_reason()?;
+        // SAFETY: the tracee does not run while it is stopped.
+        Ok(unsafe { user_trap_frame(&self.task).read_volatile() })
+    }
+
+    /// Writes the user registers of the stopped tracee, `sstatus` is not
+    /// changed.
+    pub fn set_regs(&self, regs: &TrapFrame) -> LinuxResult {
+        self.stop_reason()?;
+        let frame = user_trap_frame(&self.task);
+        // SAFETY: the tracee does not run while it is stopped.
+        unsafe {
+            let sstatus = (*frame).sstatus;
+            frame.write_volatile(TrapFrame { sstatus, ..*regs });
+        }
+        Ok(())
+    }
+
+    /// Reads the memory of the stopped tracee at `addr`.
+    pub fn read_mem(&self, addr: VirtAddr, buf: &mut [u8]) -> LinuxResult {
+        self.stop_reason()?;
+        let aspace = self.task.task_ext().aspace.lock();
+        aspace.read(addr, buf).map_err(|_| LinuxError::EFAULT)
+    }
+
+    /// Writes the memory of the stopped tracee at `addr`, even its code (e.g.
+    /// to set breakpoints).
+    pub fn write_mem(&self, addr: VirtAddr, data: &[u8]) -> LinuxResult {
+        self.stop_reason()?;
+        let aspace = self.task.task_ext().aspace.lock();
+        aspace.write(addr, data).map_err(|_| LinuxError::EFAULT)?;
+        self.state().inner.lock().mem_written = true;
+        Ok(())
+    }
+}
+
+impl Drop for Tracee {
+    fn drop(&mut self) {
+        let state = self.state();
+        let mut inner = state.inner.lock();
+        let (stop, mem_written) = (inner.stop, inner.mem_written);
+        *inner = TraceInner {
+            // resumed as by `cont(None)`, except at a fault stop
+            resume_signal: match stop {
+                Some(StopReason::Fault { signal, .. }) => Some(signal),
+                _ => None,
+            },
+            mem_written,
+            ..Default::default()
+        };
+        drop(inner);
+        state.resume_wq.notify_all(false);
+    }
+}
+
+/// Traces the syscalls and the faults of `task` like `strace`, until it
+/// exits, returns its exit code.
+///
+/// The faults are printed with the registers, and kill the task.
+pub fn strace(task: &AxTaskRef) -> LinuxResult<i32> {
+    let tracee = Tracee::attach(task)?;
+    tracee.set_trace_syscalls(true);
+    loop {
+        let reason = match tracee.wait() {
+            TraceEvent::Exited(code) => {
+                ax_println!("[strace] exited with {}", code);
+                return Ok(code);
+            }
+            TraceEvent::Stopped(reason) => reason,
+        };
+        let regs = tracee.regs()?;
+        let signal = match reason {
+            StopReason::Interrupted => None,
+            StopReason::SyscallEnter(sysno) => {
+                ax_println!(
+                    "[strace] syscall {}({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}) @ {:#x}",
+                    sysno,
+                    regs.arg0(),
+                    regs.arg1(),
+                    regs.arg2(),
+                    regs.arg3(),
+                    regs.arg4(),
+                    regs.arg5(),
+                    regs.sepc
+                );
+                None
+            }
+            StopReason::SyscallExit(sysno) => {
+                ax_println!("[strace] syscall {} = {}", sysno, regs.regs.a0 as isize);
+                None
+            }
+            StopReason::Fault { signal, addr } => {
+                ax_println!(
+                    "[strace] signal {} @ {:#x}, fault address {:#x}:\n{:#x?}",
+                    signal,
+                    regs.sepc,
+                    addr,
+                    regs
+                );
+                Some(signal)
+            }
+        };
+        tracee.cont(signal)?;
+    }
+}
@@ -1,28 +1,44 @@
 #![allow(dead_code)]
 
+use alloc::string::String;
 use arceos_posix_api as api;
 use arceos_posix_api::get_file_like;
 use axerrno::LinuxError;
 use axhal::arch::TrapFrame;
 use axhal::paging::MappingFlags;
 use axhal::trap::{register_trap_handler, SYSCALL};
+use axmm::ring::{self, RingEvent};
 use axtask::current;
 use axtask::TaskExtRef;
-use core::ffi::{c_char, c_int, c_void};
+use core::ffi::{c_char, c_int, c_void, CStr};
 use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange};
 
-const SYS_IOCTL: usize = 29;
-const SYS_OPENAT: usize = 56;
-const SYS_CLOSE: usize = 57;
-const SYS_READ: usize = 63;
-const SYS_WRITE: usize = 64;
-const SYS_WRITEV: usize = 66;
-const SYS_EXIT: usize = 93;
-const SYS_EXIT_GROUP: usize = 94;
-const SYS_SET_TID_ADDRESS: usize = 96;
-const SYS_MMAP: usize = 222;
+use crate::filter::FilterAction;
 
-const AT_FDCWD: i32 = -100;
+pub const SYS_IOCTL: usize = 29;
+pub const SYS_OPENAT: usize = 56;
+pub const SYS_CLOSE: usize = 57;
+pub const SYS_READ: usize = 63;
+pub const SYS_WRITE: usize = 64;
+pub const SYS_WRITEV: usize = 66;
+pub const SYS_EXIT: usize = 93;
+pub const SYS_EXIT_GROUP: usize = 94;
+pub const SYS_SET_TID_ADDRESS: usize = 96;
+pub const SYS_SENDTO: usize = 206;
+pub const SYS_RECVFROM: usize = 207;
+pub const SYS_BRK: usize = 214;
+pub const SYS_EXECVE: usize = 221;
+pub const SYS_MMAP: usize = 222;
+
+// ArceOS specific syscalls, above the range of Linux.
+pub const SYS_RING_CREATE: usize = 0x1000;
+pub const SYS_RING_MAP: usize = 0x1001;
+pub const SYS_RING_WAIT: usize = 0x1002;
+pub const SYS_RING_WAKE: usize = 0x1003;
+pub const SYS_BATCH: usize = 0x1004;
+
+/// Exit code of tasks killed by the syscall filter, as if by `SIGSYS`.
+const SIGSYS_EXIT_CODE: i32 = 128 + 31;
 
 /// Macro to generate syscall body
 ///
@@ -98,9 +114,96 @@ bitflags::bitflags! {
     }
 }
 
+/// `seccomp_data.arch` of RISC-V 64-bit syscalls.
+const AUDIT_ARCH_RISCV64: u32 = 0xc000_00f3;
+
+// Return values of seccomp BPF programs. Actions are ordered from the most
+// to the least restrictive, the low 16 bits are the action data.
+const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_ACTION: u32 = 0xffff_0000;
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// Runs the BPF programs attached to the `sys_enter` tracepoint and the
+/// seccomp hook, returns the syscall result if it must not be run.
+fn run_bpf_hooks(tf: &TrapFrame, syscall_num: usize, args: &[usize; 6]) -> Option<isize> {
+    let mut data = axbpf::SeccompData {
+        nr: syscall_num as _,
+        arch: AUDIT_ARCH_RISCV64,
+        instruction_pointer: tf.sepc as _,
+        args: args.map(|a| a as u64),
+    };
+    axbpf::run_hook(axbpf::HOOK_SYS_ENTER, data.as_bytes_mut());
+    let ret = axbpf::run_hook(axbpf::HOOK_SECCOMP, data.as_bytes_mut())? as u32;
+    match ret & SECCOMP_RET_ACTION {
+        SECCOMP_RET_ALLOW => None,
+        SECCOMP_RET_LOG => {
+            ax_println!("syscall {} logged by seccomp", syscall_num);
+            None
+        }
+        SECCOMP_RET_ERRNO => {
+            let errno = ret & SECCOMP_RET_DATA;
+            ax_println!("syscall {} denied by seccomp: errno {}", syscall_num, errno);
+            Some(-(errno as isize))
+        }
+        action => {
+            if action != SECCOMP_RET_KILL_THREAD {
+                warn!("unsupported seccomp action {:#x}, killing task", action);
+            }
+            ax_println!("syscall {} denied by seccomp, killing task", syscall_num);
+            crate::task::exit(SIGSYS_EXIT_CODE)
+        }
+    }
+}
+
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
+    if crate::ptrace::syscall_traced() {
+        return crate::ptrace::trace_syscall(dispatch_syscall);
+    }
+    dispatch_syscall(tf, syscall_num)
+}
+
+/// Checks a syscall against the filter of the task and the BPF hooks,
+/// returns its result if it must not be run.
+pub(crate) fn check_syscall(
+    tf: &TrapFrame,
+    syscall_num: usize,
+    args: &[usize; 6],
+) -> Option<isize> {
+    // Cloned so that the filter may be replaced by the syscall.
+    let filter = current().task_ext().filter.lock().clone();
+    if let Some(filter) = filter {
+        match filter.check(syscall_num, args) {
+            FilterAction::Allow => {}
+            FilterAction::Errno(err) => {
+                ax_println!("syscall {} denied by filter: {:?}", syscall_num, err);
+                return Some(-err.code() as _);
+            }
+            FilterAction::Kill => {
+                ax_println!("syscall {} denied by filter, killing task", syscall_num);
+                crate::task::exit(SIGSYS_EXIT_CODE)
+            }
+        }
+    }
+    run_bpf_hooks(tf, syscall_num, args)
+}
+
+fn dispatch_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ax_println!("handle_syscall [{}] ...", syscall_num);
+    let args = [
+        tf.arg0(),
+        tf.arg1(),
+        tf.arg2(),
+        tf.arg3(),
+        tf.arg4(),
+        tf.arg5(),
+    ];
+    if let Some(ret) = check_syscall(tf, syscall_num, &args) {
+        return ret;
+    }
     let ret = match syscall_num {
         SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
         SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
@@ -116,12 +219,14 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_EXIT_GROUP => {
             ax_println!("[SYS_EXIT_GROUP]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            crate::task::exit(tf.arg0() as _)
         }
         SYS_EXIT => {
             ax_println!("[SYS_EXIT]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            crate::task::exit(tf.arg0() as _)
         }
+        SYS_BRK => sys_brk(tf.arg0() as _),
+        SYS_EXECVE => sys_execve(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_MMAP => sys_mmap(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -130,6 +235,11 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        SYS_RING_CREATE => sys_ring_create(tf.arg0() as _),
+        SYS_RING_MAP => sys_ring_map(tf.arg0() as _),
+        SYS_RING_WAIT => sys_ring_wait(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_RING_WAKE => sys_ring_wake(tf.arg0() as _, tf.arg1() as _),
+        SYS_BATCH => crate::batch::sys_batch(tf, tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
@@ -167,13 +277,17 @@ fn sys_mmap(
             // 使用指定的地址
             VirtAddr::from(addr as usize)
         } else {
-            // 寻找空闲区域
+            // 寻找空闲区域，未指定地址时从 (随机化的) mmap 基址开始
+            let size = length.align_up_4k();
+            let limit = VirtAddrRange::new(uspace.base(), uspace.end());
+            let hint = if addr.is_null() {
+                current.task_ext().layout.lock().mmap_base - size
+            } else {
+                VirtAddr::from(addr as usize)
+            };
             uspace
-                .find_free_area(
-                    VirtAddr::from(addr as usize),
-                    length.align_up_4k(),
-                    VirtAddrRange::new(uspace.base(), uspace.end()),
-                )
+                .find_free_area(hint, size, limit)
+                .or_else(|| uspace.find_free_area(uspace.base(), size, limit))
                 .ok_or(LinuxError::ENOMEM)?
         };
 
@@ -215,9 +329,120 @@ fn sys_mmap(
     })
 }
 
+/// Moves the program break to `addr`, returns the new break, which is the
+/// current one if `addr` is null or below the heap start, or if the memory is
+/// exhausted.
+///
+/// The heap starts a random number of pages after the image, see
+/// [`axmm::UserLayout::heap_start`].
+fn sys_brk(addr: usize) -> isize {
+    let current = current();
+    let mut brk = current.task_ext().brk.lock();
+    let new_end = VirtAddr::from(addr);
+    if new_end < brk.start {
+        return brk.end.as_usize() as isize;
+    }
+    let mut uspace = current.task_ext().aspace.lock();
+    let mapped = new_end.align_up_4k();
+    if mapped > brk.mapped {
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+        if uspace
+            .map_alloc(brk.mapped, mapped - brk.mapped, flags, true)
+            .is_err()
+        {
+            return brk.end.as_usize() as isize;
+        }
+    }
+    // The bytes kept mapped when the heap shrank are zeroed as new ones.
+    let stale_end = new_end.min(brk.mapped);
+    if stale_end > brk.end {
+        let zeros = alloc::vec![0u8; stale_end - brk.end];
+        uspace.write(brk.end, &zeros).ok();
+    }
+    brk.mapped = brk.mapped.max(mapped);
+    brk.end = new_end;
+    new_end.as_usize() as isize
+}
+
+/// Replaces the image of the current task by the app `path`, see
+/// [`crate::task::exec`]. The arguments and the environment are not passed to
+/// the app yet (it gets the ones of the first app).
+fn sys_execve(path: *const c_char, _argv: usize, _envp: usize) -> isize {
+    syscall_body!(sys_execve, {
+        if path.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        // Copied, the user memory is gone once the image is replaced.
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_str()
+            .map_err(|_| LinuxError::EINVAL)?;
+        let path = String::from(path);
+        crate::task::exec(&path)?;
+        Ok(0)
+    })
+}
+
+/// Creates a shared ring of `capacity` bytes of data, and returns its ID.
+///
+/// The ring lives while it is mapped by a process: it must be mapped by
+/// [`sys_ring_map`] before the creator exits.
+fn sys_ring_create(capacity: usize) -> isize {
+    syscall_body!(sys_ring_create, {
+        let ring = ring::create(capacity).map_err(LinuxError::from)?;
+        let id = ring.id();
+        current().task_ext().rings.lock().push(ring);
+        Ok(id as isize)
+    })
+}
+
+/// Maps the shared ring `id` in a free area from the mmap base, and returns
+/// the address of its header.
+fn sys_ring_map(id: u32) -> isize {
+    syscall_body!(sys_ring_map, {
+        let ring = ring::get(id).ok_or(LinuxError::ENOENT)?;
+        let current = current();
+        let mut uspace = current.task_ext().aspace.lock();
+        let size = ring.size();
+        let limit = VirtAddrRange::new(uspace.base(), uspace.end());
+        let vaddr = uspace
+            .find_free_area(
+                current.task_ext().layout.lock().mmap_base - size,
+                size,
+                limit,
+            )
+            .or_else(|| uspace.find_free_area(uspace.base(), size, limit))
+            .ok_or(LinuxError::ENOMEM)?;
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+        ring.map_into(&mut uspace, vaddr, flags)
+            .map_err(LinuxError::from)?;
+        current.task_ext().rings.lock().push(ring);
+        Ok(vaddr.as_usize() as isize)
+    })
+}
+
+/// Sleeps while the counter of `event` in the ring `id` is `expected`, the
+/// `futex(FUTEX_WAIT)` of the rings.
+fn sys_ring_wait(id: u32, event: usize, expected: u32) -> isize {
+    syscall_body!(sys_ring_wait, {
+        let ring = ring::get(id).ok_or(LinuxError::ENOENT)?;
+        let event = RingEvent::try_from(event).map_err(|_| LinuxError::EINVAL)?;
+        ring.wait(event, expected);
+        Ok(0)
+    })
+}
+
+/// Wakes the side waiting for `event` in the ring `id`.
+fn sys_ring_wake(id: u32, event: usize) -> isize {
+    syscall_body!(sys_ring_wake, {
+        let ring = ring::get(id).ok_or(LinuxError::ENOENT)?;
+        let event = RingEvent::try_from(event).map_err(|_| LinuxError::EINVAL)?;
+        ring.wake(event);
+        Ok(0)
+    })
+}
+
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
-    assert_eq!(dfd, AT_FDCWD);
-    api::sys_open(fname, flags, mode) as isize
+    api::sys_openat(dfd, fname, flags, mode) as isize
 }
 
 fn sys_close(fd: i32) -> isize {
@@ -2,12 +2,43 @@
 
 use core::sync::atomic::AtomicU64;
 
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::fs::File;
 
-use axhal::arch::UspaceContext;
-use axmm::AddrSpace;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::{GeneralRegisters, TrapFrame, UspaceContext};
+use axmm::ring::SharedRing;
+use axmm::{AddrSpace, UserLayout};
 use axsync::Mutex;
 use axtask::{AxTaskRef, TaskExtRef, TaskInner};
+use memory_addr::VirtAddr;
+
+use crate::backtrace::DebugImage;
+use crate::filter::{exec_filter, SyscallFilter};
+use crate::loader::LoadedApp;
+use crate::ptrace::TraceState;
+
+/// The program break of a task.
+pub struct Brk {
+    /// The start of the heap.
+    pub start: VirtAddr,
+    /// The current break.
+    pub end: VirtAddr,
+    /// The end of the pages mapped for the heap, kept when it shrinks.
+    pub mapped: VirtAddr,
+}
+
+impl Brk {
+    const fn new(start: VirtAddr) -> Self {
+        Self {
+            start,
+            end: start,
+            mapped: start,
+        }
+    }
+}
 
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
@@ -19,19 +50,43 @@ pub struct TaskExt {
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
-    /// The user space context.
+    /// The user space context the task enters first.
     pub uctx: UspaceContext,
     /// The virtual memory address space.
     pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The shared rings mapped in the address space, kept until it is
+    /// dropped (after `aspace`, in the order of the fields).
+    pub rings: Mutex<Vec<Arc<SharedRing>>>,
+    /// The (randomized) layout of the address space.
+    pub layout: Mutex<UserLayout>,
+    /// The heap grown by `brk`.
+    pub brk: Mutex<Brk>,
+    /// The syscall filter of the task, attached at spawn and stacked with the
+    /// one of the app at [`exec`], if sandboxed.
+    pub filter: Mutex<Option<Arc<SyscallFilter>>>,
+    /// The path of the executable, for the core dumps.
+    pub exe_path: Mutex<String>,
+    /// The debug info of the images loaded in the address space, for the
+    /// backtraces.
+    pub images: Mutex<Vec<DebugImage>>,
+    /// The state of the debugging by a tracer.
+    pub trace: TraceState,
 }
 
 impl TaskExt {
-    pub const fn new(uctx: UspaceContext, aspace: Arc<Mutex<AddrSpace>>) -> Self {
+    pub fn new(aspace: Arc<Mutex<AddrSpace>>, exe_path: String, app: LoadedApp) -> Self {
         Self {
             proc_id: 233,
-            uctx,
+            uctx: app.uctx,
             clear_child_tid: AtomicU64::new(0),
             aspace,
+            rings: Mutex::new(Vec::new()),
+            layout: Mutex::new(app.layout),
+            brk: Mutex::new(Brk::new(app.heap_start)),
+            filter: Mutex::new(app.filter.map(Arc::new)),
+            exe_path: Mutex::new(exe_path),
+            images: Mutex::new(alloc::vec![app.image]),
+            trace: TraceState::new(),
         }
     }
 
@@ -48,7 +103,66 @@ impl TaskExt {
 
 axtask::def_task_ext!(TaskExt);
 
-pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> AxTaskRef {
+/// Exits the current user task, reporting it to its tracer.
+pub fn exit(exit_code: i32) -> ! {
+    crate::ptrace::report_exit(exit_code);
+    axtask::exit(exit_code)
+}
+
+/// Replaces the image of the current user task by the app `path`: the
+/// mappings and the shared rings are dropped, the app is loaded with a new
+/// layout and its syscall filter is stacked on the current one, which still
+/// applies (see [`exec_filter`]). The task returns
+/// from the syscall to the entry of the app.
+///
+/// Fails without touching the task if `path` cannot be opened, but kills it
+/// if the app cannot be loaded once the old image is gone.
+pub fn exec(path: &str) -> LinuxResult {
+    File::open(path).map_err(LinuxError::from)?;
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    let mut uspace = ext.aspace.lock();
+    uspace.clear()?;
+    ext.rings.lock().clear();
+    let app = match crate::load_app(path, &mut uspace) {
+        Ok(app) => app,
+        Err(err) => {
+            warn!("cannot load {} at exec: {:?}", path, err);
+            drop(uspace);
+            exit(128 + 11) // as if by `SIGSEGV`
+        }
+    };
+    drop(uspace);
+    axhal::arch::flush_icache_all();
+
+    *ext.layout.lock() = app.layout;
+    *ext.brk.lock() = Brk::new(app.heap_start);
+    let mut filter = ext.filter.lock();
+    *filter = exec_filter(filter.take(), app.filter);
+    drop(filter);
+    *ext.exe_path.lock() = path.into();
+    *ext.images.lock() = alloc::vec![app.image];
+
+    let frame = crate::ptrace::user_trap_frame(curr.as_task_ref());
+    // The trap handler steps over the `ecall` and puts the result in `a0`.
+    unsafe {
+        *frame = TrapFrame {
+            regs: GeneralRegisters {
+                sp: app.uctx.get_sp(),
+                ..Default::default()
+            },
+            sepc: app.uctx.get_ip() - 4,
+            sstatus: (*frame).sstatus,
+        };
+    }
+    Ok(())
+}
+
+pub fn spawn_user_task(
+    aspace: Arc<Mutex<AddrSpace>>,
+    exe_path: String,
+    app: LoadedApp,
+) -> AxTaskRef {
     let mut task = TaskInner::new(
         || {
             let curr = axtask::current();
@@ -66,6 +180,6 @@ pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> Ax
     );
     task.ctx_mut()
         .set_page_table_root(aspace.lock().page_table_root());
-    task.init_task_ext(TaskExt::new(uctx, aspace));
+    task.init_task_ext(TaskExt::new(aspace, exe_path, app));
     axtask::spawn_task(task)
 }
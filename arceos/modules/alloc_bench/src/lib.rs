@@ -0,0 +1,453 @@
+//! Benchmark and stress harness for the allocators.
+//!
+//! It drives any [`ByteAllocator`] with [`run_bytes`], or [`PageAllocator`]
+//! with [`run_pages`], through a [`Workload`]: one of the [`Pattern`]s with
+//! random sizes from a seeded generator, so that the allocators are compared
+//! on exactly the same sequence of requests. Each run returns a [`Report`] of
+//! its throughput, peak usage and fragmentation, and a [`Table`] of them
+//! prints the comparison:
+//!
+//! ```ignore
+//! let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+//! tlsf.init(start, size);
+//! let report = run_bytes("tlsf", &mut tlsf, &Workload::random(), now_ns);
+//! println!("{}", Table(&[report]));
+//! ```
+//!
+//! The harness works in `no_std`, to run both in the hosted tests and in an
+//! ArceOS app (see `examples/alloc_bench`), the clock being given by the
+//! caller. It keeps its own bookkeeping in the global allocator, which must
+//! not be the allocator under test.
+//!
+//! Each allocation gets a tag written into its first word, checked when it is
+//! freed, so that the overlapping allocations are reported as corruptions.
+
+#![no_std]
+
+extern crate alloc;
+
+mod report;
+
+pub use self::report::{Report, Table, Unit};
+
+use alloc::collections::VecDeque;
+use allocator::{ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::NonNull;
+
+/// 工作负载的分配与释放模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// 随机地分配或释放一个随机的存活分配
+    Random,
+    /// 生产者分配一批，消费者按先进先出释放一批，批的大小在 `1..=batch` 之间
+    ProducerConsumer {
+        /// 一批最多的分配个数
+        batch: usize,
+    },
+    /// 碎片化压力：以小的分配填满，隔一个释放一个，再分配大的，之后全部释放，
+    /// 如此反复
+    Fragmentation,
+}
+
+impl Pattern {
+    /// 模式的名称，用于报告
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::ProducerConsumer { .. } => "prod-cons",
+            Self::Fragmentation => "fragment",
+        }
+    }
+}
+
+/// 一个工作负载
+///
+/// 大小对 [`run_bytes`] 是字节数，对 [`run_pages`] 是页数。
+#[derive(Debug, Clone, Copy)]
+pub struct Workload {
+    /// 分配与释放的模式
+    pub pattern: Pattern,
+    /// 操作（分配或释放）的次数
+    pub ops: usize,
+    /// 最小的分配大小
+    pub min_size: usize,
+    /// 最大的分配大小，碎片化压力中大的分配使用它
+    pub max_size: usize,
+    /// 字节分配的最大对齐，2 的幂，对齐在 `1..=max_align` 的 2 的幂中随机选取
+    pub max_align: usize,
+    /// 同时存活的分配个数的上限
+    pub max_live: usize,
+    /// 随机数生成器的种子
+    pub seed: u64,
+}
+
+impl Workload {
+    /// 随机模式的默认字节负载
+    pub const fn random() -> Self {
+        Self {
+            pattern: Pattern::Random,
+            ops: 100_000,
+            min_size: 8,
+            max_size: 1024,
+            max_align: 16,
+            max_live: 1024,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// 生产者消费者模式的默认字节负载
+    pub const fn producer_consumer() -> Self {
+        Self {
+            pattern: Pattern::ProducerConsumer { batch: 64 },
+            ..Self::random()
+        }
+    }
+
+    /// 碎片化压力的默认字节负载
+    pub const fn fragmentation() -> Self {
+        Self {
+            pattern: Pattern::Fragmentation,
+            min_size: 16,
+            max_size: 4096,
+            ..Self::random()
+        }
+    }
+
+    /// 页数在 `1..=max_pages` 之间的页负载
+    pub const fn pages(self, max_pages: usize) -> Self {
+        Self {
+            min_size: 1,
+            max_size: max_pages,
+            max_align: 1,
+            ..self
+        }
+    }
+}
+
+/// 以 `workload` 驱动字节分配器 `alloc`，`now` 返回纳秒数
+pub fn run_bytes<A: ByteAllocator>(
+    name: &'static str,
+    alloc: &mut A,
+    workload: &Workload,
+    now: impl FnMut() -> u64,
+) -> Report {
+    run(name, &mut Bytes(alloc), workload, now)
+}
+
+/// 以 `workload` 驱动页分配器 `alloc`，`now` 返回纳秒数
+///
+/// 页都按 1 页对齐，`align_pow2` 是 `alloc` 表示 1 页对齐的参数：各个页分配器
+/// 的含义不同，早期分配器为 0（对齐页数的幂），`allocator` 中的和伙伴分配器
+/// 为 `A::PAGE_SIZE`（对齐的字节数）。
+pub fn run_pages<A: PageAllocator>(
+    name: &'static str,
+    alloc: &mut A,
+    align_pow2: usize,
+    workload: &Workload,
+    now: impl FnMut() -> u64,
+) -> Report {
+    run(name, &mut Pages(alloc, align_pow2), workload, now)
+}
+
+/// 被驱动的分配器，大小以其单位计，地址和用量以字节计
+trait Target {
+    const UNIT: Unit;
+
+    /// 单位的字节数
+    fn unit_bytes(&self) -> usize;
+    fn alloc(&mut self, size: usize, align: usize) -> Option<usize>;
+    fn dealloc(&mut self, pos: usize, size: usize, align: usize);
+    fn used_bytes(&self) -> usize;
+    fn available_bytes(&self) -> usize;
+}
+
+struct Bytes<'a, A>(&'a mut A);
+
+impl<A: ByteAllocator> Target for Bytes<'_, A> {
+    const UNIT: Unit = Unit::Bytes;
+
+    fn unit_bytes(&self) -> usize {
+        1
+    }
+
+    fn alloc(&mut self, size: usize, align: usize) -> Option<usize> {
+        let layout = Layout::from_size_align(size, align).ok()?;
+        self.0.alloc(layout).ok().map(|ptr| ptr.as_ptr() as usize)
+    }
+
+    fn dealloc(&mut self, pos: usize, size: usize, align: usize) {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        self.0
+            .dealloc(NonNull::new(pos as *mut u8).unwrap(), layout);
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.0.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.0.available_bytes()
+    }
+}
+
+struct Pages<'a, A>(&'a mut A, usize);
+
+impl<A: PageAllocator> Target for Pages<'_, A> {
+    const UNIT: Unit = Unit::Pages;
+
+    fn unit_bytes(&self) -> usize {
+        A::PAGE_SIZE
+    }
+
+    fn alloc(&mut self, size: usize, _align: usize) -> Option<usize> {
+        self.0.alloc_pages(size, self.1).ok()
+    }
+
+    fn dealloc(&mut self, pos: usize, size: usize, _align: usize) {
+        self.0.dealloc_pages(pos, size);
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.0.used_pages() * A::PAGE_SIZE
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.0.available_pages() * A::PAGE_SIZE
+    }
+}
+
+/// xorshift64* 伪随机数生成器
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // 种子不能为 0
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// `[low, high]` 中的随机数
+    fn range(&mut self, low: usize, high: usize) -> usize {
+        if high <= low {
+            return low;
+        }
+        low + (self.next() % (high - low + 1) as u64) as usize
+    }
+}
+
+/// 一个存活的分配
+#[derive(Clone, Copy)]
+struct Live {
+    pos: usize,
+    size: usize,
+    align: usize,
+    tag: usize,
+}
+
+/// 一次运行的状态
+struct Runner<'a, T> {
+    target: &'a mut T,
+    workload: &'a Workload,
+    rng: Rng,
+    live: VecDeque<Live>,
+    report: Report,
+    // 存活分配请求的字节数
+    requested: usize,
+    // 下一个分配的标记
+    next_tag: usize,
+}
+
+impl<T: Target> Runner<'_, T> {
+    fn ops_left(&self) -> bool {
+        self.report.ops < self.workload.ops
+    }
+
+    fn is_full(&self) -> bool {
+        self.live.len() >= self.workload.max_live
+    }
+
+    /// 分配 `size` 个单位，记入存活的分配，失败时返回 `false`
+    fn alloc(&mut self, size: usize) -> bool {
+        let align = if T::UNIT == Unit::Bytes {
+            let max_shift = self.workload.max_align.max(1).trailing_zeros() as usize;
+            1 << self.rng.range(0, max_shift)
+        } else {
+            1
+        };
+        let bytes = size * self.target.unit_bytes();
+        self.report.ops += 1;
+        let Some(pos) = self.target.alloc(size, align) else {
+            self.report.failures += 1;
+            if self.target.available_bytes() >= bytes {
+                self.report.fragmented_failures += 1;
+            }
+            return false;
+        };
+        self.report.allocs += 1;
+        let tag = self.next_tag;
+        self.next_tag += 1;
+        if bytes >= size_of::<usize>() {
+            // SAFETY: the allocation is at least a word, and is ours.
+            unsafe { (pos as *mut usize).write_unaligned(tag) };
+        }
+        self.live.push_back(Live {
+            pos,
+            size,
+            align,
+            tag,
+        });
+        self.requested += bytes;
+        self.sample();
+        true
+    }
+
+    /// 释放第 `idx` 个存活的分配
+    fn free(&mut self, idx: usize) {
+        let Some(live) = self.live.swap_remove_back(idx) else {
+            return;
+        };
+        self.free_live(live);
+    }
+
+    /// 释放最早的存活分配
+    fn free_oldest(&mut self) {
+        if let Some(live) = self.live.pop_front() {
+            self.free_live(live);
+        }
+    }
+
+    fn free_live(&mut self, live: Live) {
+        let bytes = live.size * self.target.unit_bytes();
+        if bytes >= size_of::<usize>() {
+            // SAFETY: the allocation is at least a word, and is still ours.
+            let tag = unsafe { (live.pos as *const usize).read_unaligned() };
+            if tag != live.tag {
+                self.report.corruptions += 1;
+            }
+        }
+        self.report.ops += 1;
+        self.report.frees += 1;
+        self.target.dealloc(live.pos, live.size, live.align);
+        self.requested -= bytes;
+    }
+
+    fn free_all(&mut self) {
+        while !self.live.is_empty() {
+            self.free_oldest();
+        }
+    }
+
+    /// 记录用量的峰值
+    fn sample(&mut self) {
+        let used = self.target.used_bytes();
+        if used > self.report.peak_used {
+            self.report.peak_used = used;
+            self.report.requested_at_peak = self.requested.min(used);
+        }
+        self.report.peak_requested = self.report.peak_requested.max(self.requested);
+    }
+
+    fn random_size(&mut self) -> usize {
+        self.rng
+            .range(self.workload.min_size.max(1), self.workload.max_size)
+    }
+
+    fn run_random(&mut self) {
+        while self.ops_left() {
+            let alloc = self.live.is_empty() || (!self.is_full() && self.rng.next() & 1 == 0);
+            if alloc {
+                let size = self.random_size();
+                self.alloc(size);
+            } else {
+                let idx = self.rng.range(0, self.live.len() - 1);
+                self.free(idx);
+            }
+        }
+    }
+
+    fn run_producer_consumer(&mut self, batch: usize) {
+        while self.ops_left() {
+            for _ in 0..self.rng.range(1, batch) {
+                if self.is_full() || !self.ops_left() {
+                    break;
+                }
+                let size = self.random_size();
+                self.alloc(size);
+            }
+            for _ in 0..self.rng.range(1, batch) {
+                if self.live.is_empty() || !self.ops_left() {
+                    break;
+                }
+                self.free_oldest();
+            }
+        }
+    }
+
+    fn run_fragmentation(&mut self) {
+        let small = self.workload.min_size.max(1);
+        let large = self.workload.max_size.max(small);
+        while self.ops_left() {
+            // 以小的分配填满
+            while !self.is_full() && self.ops_left() {
+                let size = self.rng.range(small, small * 2);
+                if !self.alloc(size) {
+                    break;
+                }
+            }
+            // 隔一个释放一个，留下小的空洞
+            for i in 0..self.live.len() {
+                let live = self.live.pop_front().unwrap();
+                if i % 2 == 0 && self.ops_left() {
+                    self.free_live(live);
+                } else {
+                    self.live.push_back(live);
+                }
+            }
+            // 空洞放不下大的分配
+            while !self.is_full() && self.ops_left() {
+                if !self.alloc(large) {
+                    break;
+                }
+            }
+            self.free_all();
+        }
+    }
+}
+
+fn run<T: Target>(
+    name: &'static str,
+    target: &mut T,
+    workload: &Workload,
+    mut now: impl FnMut() -> u64,
+) -> Report {
+    let mut runner = Runner {
+        target,
+        workload,
+        rng: Rng::new(workload.seed),
+        live: VecDeque::with_capacity(workload.max_live),
+        report: Report::new(name, workload.pattern.name(), T::UNIT),
+        requested: 0,
+        next_tag: 0,
+    };
+    let start = now();
+    match workload.pattern {
+        Pattern::Random => runner.run_random(),
+        Pattern::ProducerConsumer { batch } => runner.run_producer_consumer(batch.max(1)),
+        Pattern::Fragmentation => runner.run_fragmentation(),
+    }
+    let elapsed = now().saturating_sub(start);
+    let mut report = runner.report;
+    report.elapsed_ns = elapsed;
+    // 最后的释放不计入报告，只检查标记，分配器回到初始的状态
+    runner.free_all();
+    report.corruptions = runner.report.corruptions;
+    report
+}
@@ -0,0 +1,128 @@
+//! 一次运行的报告，以及多个报告的对比表
+
+use core::fmt;
+
+/// 分配大小的单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// 字节分配
+    Bytes,
+    /// 页分配
+    Pages,
+}
+
+/// 一次运行的报告，由 [`run_bytes`] 或 [`run_pages`] 返回
+///
+/// 用量都以字节计。
+///
+/// [`run_bytes`]: crate::run_bytes
+/// [`run_pages`]: crate::run_pages
+#[derive(Debug, Clone, Copy)]
+pub struct Report {
+    /// 分配器的名称
+    pub allocator: &'static str,
+    /// 工作负载的名称
+    pub workload: &'static str,
+    /// 分配大小的单位
+    pub unit: Unit,
+    /// 分配与释放的次数，包括失败的分配
+    pub ops: usize,
+    /// 成功的分配次数
+    pub allocs: usize,
+    /// 释放次数
+    pub frees: usize,
+    /// 失败的分配次数
+    pub failures: usize,
+    /// 分配器报告的可用内存足够时仍失败的分配次数，即碎片导致的失败
+    pub fragmented_failures: usize,
+    /// 释放时标记被改写的分配个数，不为 0 说明有重叠的分配
+    pub corruptions: usize,
+    /// 耗时的纳秒数
+    pub elapsed_ns: u64,
+    /// 存活分配请求的字节数的峰值
+    pub peak_requested: usize,
+    /// 分配器报告的已用字节数的峰值
+    pub peak_used: usize,
+    /// 已用字节数达到峰值时，存活分配请求的字节数
+    pub requested_at_peak: usize,
+}
+
+impl Report {
+    pub(crate) const fn new(allocator: &'static str, workload: &'static str, unit: Unit) -> Self {
+        Self {
+            allocator,
+            workload,
+            unit,
+            ops: 0,
+            allocs: 0,
+            frees: 0,
+            failures: 0,
+            fragmented_failures: 0,
+            corruptions: 0,
+            elapsed_ns: 0,
+            peak_requested: 0,
+            peak_used: 0,
+            requested_at_peak: 0,
+        }
+    }
+
+    /// 每秒的操作次数
+    pub fn ops_per_sec(&self) -> u64 {
+        if self.elapsed_ns == 0 {
+            return 0;
+        }
+        (self.ops as u128 * 1_000_000_000 / self.elapsed_ns as u128) as u64
+    }
+
+    /// 已用字节数达到峰值时，不属于任何请求的部分所占的百分比：对齐、元数据、
+    /// 大小类的取整和碎片
+    pub fn overhead_percent(&self) -> u64 {
+        if self.peak_used == 0 {
+            return 0;
+        }
+        ((self.peak_used - self.requested_at_peak) as u128 * 100 / self.peak_used as u128) as u64
+    }
+}
+
+/// 多个报告的对比表，每个报告一行
+pub struct Table<'a>(pub &'a [Report]);
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:<10} {:<5} {:>9} {:>10} {:>10} {:>9} {:>7} {:>7} {:>7}",
+            "allocator",
+            "workload",
+            "unit",
+            "ops",
+            "kops/s",
+            "peak KiB",
+            "overhead",
+            "fails",
+            "frag",
+            "corrupt"
+        )?;
+        for report in self.0 {
+            let unit = match report.unit {
+                Unit::Bytes => "bytes",
+                Unit::Pages => "pages",
+            };
+            writeln!(
+                f,
+                "{:<12} {:<10} {:<5} {:>9} {:>10} {:>10} {:>8}% {:>7} {:>7} {:>7}",
+                report.allocator,
+                report.workload,
+                unit,
+                report.ops,
+                report.ops_per_sec() / 1000,
+                report.peak_used / 1024,
+                report.overhead_percent(),
+                report.failures,
+                report.fragmented_failures,
+                report.corruptions
+            )?;
+        }
+        Ok(())
+    }
+}
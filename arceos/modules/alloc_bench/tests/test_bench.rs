@@ -0,0 +1,124 @@
+use std::time::Instant;
+
+use alloc_bench::{run_bytes, run_pages, Report, Table, Workload};
+use allocator::BaseAllocator;
+use buddy_allocator::BuddyPageAllocator;
+use bump_allocator::EarlyAllocator;
+use slab_allocator::SlabByteAllocator;
+use tlsf_allocator::TlsfByteAllocator;
+
+const ARENA_SIZE: usize = 8 << 20;
+const OPS: usize = 20_000;
+
+/// A page aligned region of the host memory for an allocator under test.
+struct Arena(Vec<u8>);
+
+impl Arena {
+    fn new() -> Self {
+        Self(vec![0; ARENA_SIZE + 4096])
+    }
+
+    fn init(&mut self, alloc: &mut impl BaseAllocator) {
+        let start = (self.0.as_mut_ptr() as usize + 4095) & !4095;
+        alloc.init(start, ARENA_SIZE);
+    }
+}
+
+fn bench_bytes(workload: &Workload) -> Vec<Report> {
+    let clock = Instant::now();
+    let now = || clock.elapsed().as_nanos() as u64;
+    let mut arena = Arena::new();
+    let mut reports = Vec::new();
+
+    let mut bump = EarlyAllocator::<4096>::new();
+    arena.init(&mut bump);
+    reports.push(run_bytes("bump", &mut bump, workload, now));
+
+    let mut slab = SlabByteAllocator::new(BuddyPageAllocator::<4096>::new());
+    arena.init(&mut slab);
+    reports.push(run_bytes("slab+buddy", &mut slab, workload, now));
+
+    let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+    arena.init(&mut tlsf);
+    reports.push(run_bytes("tlsf", &mut tlsf, workload, now));
+    reports
+}
+
+fn bench_pages(workload: &Workload) -> Vec<Report> {
+    let clock = Instant::now();
+    let now = || clock.elapsed().as_nanos() as u64;
+    let mut arena = Arena::new();
+    let mut reports = Vec::new();
+
+    let mut bump = EarlyAllocator::<4096>::new();
+    arena.init(&mut bump);
+    reports.push(run_pages("bump", &mut bump, 0, workload, now));
+
+    let mut buddy = BuddyPageAllocator::<4096>::new();
+    arena.init(&mut buddy);
+    reports.push(run_pages("buddy", &mut buddy, 4096, workload, now));
+    reports
+}
+
+fn check(reports: &[Report], workload: &Workload) {
+    println!("{}", Table(reports));
+    for report in reports {
+        assert_eq!(report.corruptions, 0, "{}", report.allocator);
+        assert!(report.ops >= workload.ops, "{}", report.allocator);
+        assert!(report.allocs > 0, "{}", report.allocator);
+        assert!(report.peak_used >= report.requested_at_peak);
+    }
+}
+
+#[test]
+fn test_bytes() {
+    for workload in [
+        Workload::random(),
+        Workload::producer_consumer(),
+        Workload::fragmentation(),
+    ] {
+        let workload = Workload {
+            ops: OPS,
+            ..workload
+        };
+        let reports = bench_bytes(&workload);
+        check(&reports, &workload);
+        // The live allocations always fit in the arena of the allocators
+        // which reuse the freed memory: they see the same requests.
+        let (slab, tlsf) = (&reports[1], &reports[2]);
+        assert_eq!(slab.failures, 0);
+        assert_eq!(tlsf.failures, 0);
+        assert_eq!((slab.allocs, slab.frees), (tlsf.allocs, tlsf.frees));
+        assert_eq!(slab.peak_requested, tlsf.peak_requested);
+    }
+}
+
+#[test]
+fn test_pages() {
+    for workload in [Workload::random(), Workload::producer_consumer()] {
+        let workload = Workload {
+            ops: OPS,
+            max_live: 256,
+            ..workload.pages(4)
+        };
+        let reports = bench_pages(&workload);
+        check(&reports, &workload);
+        assert_eq!(reports[1].failures, 0);
+    }
+}
+
+#[test]
+fn test_fragmentation() {
+    // Large allocations in the holes of the small ones, with less memory
+    // than they all need.
+    let workload = Workload {
+        ops: OPS,
+        max_live: 4096,
+        ..Workload::fragmentation()
+    };
+    let reports = bench_bytes(&workload);
+    check(&reports, &workload);
+    for report in &reports {
+        assert!(report.failures > 0, "{}", report.allocator);
+    }
+}
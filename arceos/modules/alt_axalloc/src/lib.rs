@@ -5,11 +5,16 @@ extern crate log;
 extern crate alloc;
 
 use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
-use bump_allocator::EarlyAllocator;
+use bump_allocator::{ConstrainedPageAllocator, EarlyAllocator, HugePageAllocator, MAX_NODES};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
 
+pub use bump_allocator::{
+    AllocConstraints, AllocDomain, AllocStats, BumpMark, DomainStats, ExtendHook, Handoff,
+    LiveKind, LiveRange, NodeStats, ObjectPool, RegionLayout, Snapshot, Watermark, WatermarkHook,
+};
+
 const PAGE_SIZE: usize = 0x1000;
 
 /// The global allocator used by ArceOS.
@@ -36,32 +41,302 @@ impl GlobalAllocator {
     }
 
     /// Add the given region to the allocator.
-    pub fn add_memory(&self, _start_vaddr: usize, _size: usize) -> AllocResult {
-        unimplemented!()
+    pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory(start_vaddr, size)
+    }
+
+    /// Add the given region of the NUMA node `node` to the allocator.
+    pub fn add_memory_on(&self, node: usize, start_vaddr: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory_on(node, start_vaddr, size)
+    }
+
+    /// Reserve the given region, it is never allocated, e.g. for the firmware
+    /// or the initrd. It can be called before the initialization.
+    pub fn reserve(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.inner.lock().reserve(start_vaddr, size)
+    }
+
+    /// Sets the hook asked for another free region when the heap is
+    /// exhausted, so it grows instead of failing.
+    ///
+    /// It is called with the lock held, and must not allocate.
+    pub fn set_extend_hook(&self, hook: ExtendHook) {
+        self.inner.lock().set_extend_hook(hook)
+    }
+
+    /// Sets the low-memory watermark: once an allocation leaves less free
+    /// memory than `mark`, `hook` is called and [`take_low_watermark`]
+    /// returns `true`, before the heap is actually exhausted.
+    ///
+    /// The hook is called with the lock held, and must not allocate.
+    ///
+    /// [`take_low_watermark`]: GlobalAllocator::take_low_watermark
+    pub fn set_low_watermark(&self, mark: Watermark, hook: Option<WatermarkHook>) -> AllocResult {
+        self.inner.lock().set_low_watermark(mark, hook)
+    }
+
+    /// Returns whether the free memory dropped below the watermark since the
+    /// last call, e.g. to hand off to the formal allocators earlier.
+    pub fn take_low_watermark(&self) -> bool {
+        self.inner.lock().take_low_watermark()
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
+    #[cfg_attr(feature = "tracking", track_caller)]
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         self.inner.lock().alloc(layout)
     }
 
+    /// Allocate arbitrary number of bytes, from the NUMA node `node` if it
+    /// has enough memory, from the other nodes otherwise.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_on(&self, node: usize, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.inner.lock().alloc_on(node, layout)
+    }
+
     /// Gives back the allocated region to the byte allocator.
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
         self.inner.lock().dealloc(pos, layout)
     }
 
+    /// Resizes the allocated region `pos` to `new_size` bytes, in place if it
+    /// is the most recent allocation, returns its new left bound.
+    ///
+    /// # Safety
+    ///
+    /// `pos` must be a live allocation of `layout` from this allocator.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub unsafe fn realloc(
+        &self,
+        pos: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> AllocResult<NonNull<u8>> {
+        self.inner.lock().realloc(pos, layout, new_size)
+    }
+
+    /// Allocates arbitrary number of bytes on behalf of the subsystem
+    /// `domain`, accounted in its [`DomainStats`]. Fails with `NoMemory` if
+    /// it would exceed the budget of the domain.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_for(&self, domain: AllocDomain, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.inner.lock().alloc_for(domain, layout)
+    }
+
+    /// Gives back the region allocated by [`alloc_for`] with the same
+    /// `domain`.
+    ///
+    /// [`alloc_for`]: GlobalAllocator::alloc_for
+    pub fn dealloc_for(&self, domain: AllocDomain, pos: NonNull<u8>, layout: Layout) {
+        self.inner.lock().dealloc_for(domain, pos, layout)
+    }
+
+    /// Allocates contiguous pages on behalf of the subsystem `domain`, like
+    /// [`alloc_for`].
+    ///
+    /// [`alloc_for`]: GlobalAllocator::alloc_for
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_for(
+        &self,
+        domain: AllocDomain,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_for(domain, num_pages, align_pow2)
+    }
+
+    /// Gives back the pages allocated by [`alloc_pages_for`] with the same
+    /// `domain`.
+    ///
+    /// [`alloc_pages_for`]: GlobalAllocator::alloc_pages_for
+    pub fn dealloc_pages_for(&self, domain: AllocDomain, pos: usize, num_pages: usize) {
+        self.inner.lock().dealloc_pages_for(domain, pos, num_pages)
+    }
+
+    /// Caps the memory held by the subsystem `domain` to `budget` bytes, its
+    /// pages counted as 4 KiB each, or removes the cap with `None`.
+    pub fn set_domain_budget(&self, domain: AllocDomain, budget: Option<usize>) {
+        self.inner.lock().set_domain_budget(domain, budget)
+    }
+
     /// Allocates contiguous pages.
+    #[cfg_attr(feature = "tracking", track_caller)]
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
         self.inner.lock().alloc_pages(num_pages, align_pow2)
     }
 
+    /// Allocates contiguous pages, from the NUMA node `node` if it has enough
+    /// memory, from the other nodes otherwise.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_on(
+        &self,
+        node: usize,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_on(node, num_pages, align_pow2)
+    }
+
+    /// Allocates contiguous pages meeting `constraints`, e.g. below the
+    /// address limit of a DMA device. Fails with `NoMemory` if no free pages
+    /// meet them.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_constrained(
+        &self,
+        num_pages: usize,
+        constraints: AllocConstraints,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_constrained(num_pages, constraints)
+    }
+
+    /// Allocates contiguous zeroed pages, e.g. for the page tables.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_zeroed(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_zeroed(num_pages, align_pow2)
+    }
+
+    /// Allocates the `num_pages` pages at `base`, e.g. for the page tables or
+    /// the firmware handoff structures living at fixed addresses. Fails with
+    /// `MemoryOverlap` if some of them are already used.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_at(&self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_at(base, num_pages)
+    }
+
     /// Gives back the allocated pages starts from `pos` to the page allocator.
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
         self.inner.lock().dealloc_pages(pos, num_pages)
     }
 
+    /// Allocates `num` contiguous pages of `page_size`, aligned to it, e.g.
+    /// the 2 MiB or 1 GiB blocks of the huge page mappings.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_sized(&self, num: usize, page_size: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_sized(num, page_size)
+    }
+
+    /// Gives back the pages allocated by [`alloc_pages_sized`].
+    ///
+    /// [`alloc_pages_sized`]: GlobalAllocator::alloc_pages_sized
+    pub fn dealloc_pages_sized(&self, pos: usize, num: usize, page_size: usize) {
+        self.inner.lock().dealloc_pages_sized(pos, num, page_size)
+    }
+
+    /// Sets whether the freed memory is zeroed, so the secrets of a boot phase
+    /// do not leak into the later allocations.
+    pub fn set_zero_on_free(&self, enabled: bool) {
+        self.inner.lock().set_zero_on_free(enabled)
+    }
+
+    /// Records the state of the byte allocator, to discard the allocations
+    /// made after it at once by [`rollback`].
+    ///
+    /// [`rollback`]: GlobalAllocator::rollback
+    pub fn checkpoint(&self) -> BumpMark {
+        self.inner.lock().checkpoint()
+    }
+
+    /// Restores the byte allocator to `mark`, freeing all the allocations
+    /// made after it.
+    ///
+    /// # Safety
+    ///
+    /// None of the allocations made after `mark` may be used any more, also
+    /// by the other tasks.
+    pub unsafe fn rollback(&self, mark: BumpMark) {
+        self.inner.lock().rollback(mark)
+    }
+
+    /// Seals the early allocator and returns its free memory, to be added to
+    /// the allocators taking over.
+    ///
+    /// All the later allocations fail. The returned ranges are not used by
+    /// the early allocator any more, the allocations still live in it (as
+    /// summarized by the [`Handoff`]) stay valid and may still be freed.
+    pub fn take_remaining(&self) -> Handoff {
+        let handoff = self.inner.lock().take_remaining();
+        info!(
+            "early allocator sealed: {} bytes handed off, {} byte allocations and {} pages live",
+            handoff.free_bytes(),
+            handoff.live_byte_allocs,
+            handoff.live_pages
+        );
+        log_stats(&self.stats());
+        for node in 0..MAX_NODES {
+            log_node_stats(node, &self.node_stats(node));
+        }
+        for domain in AllocDomain::ALL {
+            log_domain_stats(domain, &self.domain_stats(domain));
+        }
+        #[cfg(feature = "tracking")]
+        self.report_leaks();
+        handoff
+    }
+
+    /// Freezes the early allocator: all the later allocations fail, the frees
+    /// are still honored.
+    ///
+    /// Unlike [`take_remaining`], the free memory is not handed off: the
+    /// allocators taking over get the whole memory, with the ranges listed by
+    /// [`for_each_live_range`] marked as allocated.
+    ///
+    /// [`take_remaining`]: GlobalAllocator::take_remaining
+    /// [`for_each_live_range`]: GlobalAllocator::for_each_live_range
+    pub fn freeze(&self) {
+        self.inner.lock().freeze();
+        info!("early allocator frozen");
+    }
+
+    /// Calls `f` on each page-aligned range still in use by the early
+    /// allocator, see [`LiveRange`].
+    pub fn for_each_live_range(&self, f: impl FnMut(LiveRange)) {
+        self.inner.lock().live_ranges().for_each(f)
+    }
+
+    /// Logs the allocations still live in the early allocator, with their
+    /// call sites.
+    #[cfg(feature = "tracking")]
+    pub fn report_leaks(&self) {
+        let inner = self.inner.lock();
+        for live in inner.report_leaks() {
+            warn!("early allocator: live {}", live);
+        }
+        let untracked = inner.untracked_allocs();
+        if untracked > 0 {
+            warn!("early allocator: {} allocations not tracked", untracked);
+        }
+    }
+
+    /// Returns the allocation statistics of the early allocator.
+    pub fn stats(&self) -> AllocStats {
+        self.inner.lock().stats()
+    }
+
+    /// Returns the usage of the NUMA node `node`.
+    pub fn node_stats(&self, node: usize) -> NodeStats {
+        self.inner.lock().node_stats(node)
+    }
+
+    /// Returns the usage of the subsystem `domain`.
+    pub fn domain_stats(&self, domain: AllocDomain) -> DomainStats {
+        self.inner.lock().domain_stats(domain)
+    }
+
+    /// Returns a snapshot of the layout of the regions, printed as their
+    /// diagram, e.g. to dump the state of the allocator when an allocation
+    /// fails.
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.lock().snapshot()
+    }
+
     /// Returns the number of allocated bytes in the byte allocator.
     pub fn used_bytes(&self) -> usize {
         self.inner.lock().used_bytes()
@@ -81,6 +356,12 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.inner.lock().available_pages()
     }
+
+    /// Returns the number of allocated pages of `page_size`, not counted in
+    /// the pages of the other sizes.
+    pub fn used_pages_sized(&self, page_size: usize) -> usize {
+        self.inner.lock().used_pages_sized(page_size)
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
@@ -95,6 +376,14 @@ unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout)
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let pos = NonNull::new(ptr).expect("realloc null ptr");
+        match GlobalAllocator::realloc(self, pos, layout, new_size) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
 }
 
 #[cfg_attr(all(target_os = "none", not(test)), global_allocator)]
@@ -119,3 +408,63 @@ pub fn global_init(start_vaddr: usize, size: usize) {
 pub fn global_add_memory(_start_vaddr: usize, _size: usize) -> AllocResult {
     unimplemented!()
 }
+
+fn log_stats(stats: &AllocStats) {
+    info!(
+        "early allocator peak usage: {} bytes, {} pages",
+        stats.peak_bytes, stats.peak_pages
+    );
+    info!(
+        "early allocator allocations: {} byte, {} page, failed {} byte, {} page",
+        stats.byte_allocs, stats.page_allocs, stats.failed_byte_allocs, stats.failed_page_allocs
+    );
+    if stats.invalid_frees > 0 {
+        warn!(
+            "early allocator rejected {} invalid frees",
+            stats.invalid_frees
+        );
+    }
+    for (class, &count) in stats.size_classes.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        match AllocStats::size_class_limit(class) {
+            Some(limit) => info!("  <= {:>6} bytes: {}", limit, count),
+            None => info!("  larger: {}", count),
+        }
+    }
+}
+
+fn log_domain_stats(domain: AllocDomain, stats: &DomainStats) {
+    if stats.allocs == 0 && stats.over_budget == 0 {
+        return;
+    }
+    info!(
+        "early allocator domain {}: {} live bytes, {} live pages, peak {} bytes",
+        domain, stats.live_bytes, stats.live_pages, stats.peak_bytes
+    );
+    if stats.over_budget > 0 {
+        warn!(
+            "early allocator domain {}: {} allocations over the budget of {} bytes",
+            domain,
+            stats.over_budget,
+            stats.budget.unwrap_or(0)
+        );
+    }
+}
+
+fn log_node_stats(node: usize, stats: &NodeStats) {
+    if stats.regions == 0 {
+        return;
+    }
+    info!(
+        "early allocator node {}: {} regions, {} bytes, {} used bytes, {} pages",
+        node, stats.regions, stats.total_bytes, stats.used_bytes, stats.used_pages
+    );
+    if stats.fallbacks > 0 {
+        warn!(
+            "early allocator node {}: {} allocations fell back to other nodes",
+            node, stats.fallbacks
+        );
+    }
+}
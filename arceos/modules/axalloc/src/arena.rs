@@ -0,0 +1,159 @@
+//! Heap arenas.
+//!
+//! Besides the global heap, up to [`MAX_ARENAS`] - 1 independent arenas can
+//! be added by [`add_arena`], each with its own memory region and allocators,
+//! e.g. one per NUMA node or one of DMA-coherent memory. They are allocated
+//! from by [`GlobalAllocator::alloc_in`] and
+//! [`GlobalAllocator::alloc_pages_in`], and freed by the usual
+//! [`GlobalAllocator::dealloc`] and [`GlobalAllocator::dealloc_pages`],
+//! which find the arena of the address.
+//!
+//! The arenas never borrow memory from each other: an allocation fails when
+//! its arena is exhausted, after calling the [`Shrinker`]s.
+//!
+//! [`GlobalAllocator::alloc_in`]: crate::GlobalAllocator::alloc_in
+//! [`GlobalAllocator::alloc_pages_in`]: crate::GlobalAllocator::alloc_pages_in
+//! [`GlobalAllocator::dealloc`]: crate::GlobalAllocator::dealloc
+//! [`GlobalAllocator::dealloc_pages`]: crate::GlobalAllocator::dealloc_pages
+//! [`Shrinker`]: crate::Shrinker
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kspin::SpinNoIrq;
+
+use crate::{DefaultByteAllocator, DefaultPageAllocator, MIN_HEAP_SIZE, PAGE_SIZE};
+
+/// The most arenas, including the global heap.
+pub const MAX_ARENAS: usize = 8;
+
+/// The identifier of a heap arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArenaId(usize);
+
+impl ArenaId {
+    /// The global heap, given the memory of [`global_init`] and
+    /// [`global_add_memory`].
+    ///
+    /// [`global_init`]: crate::global_init
+    /// [`global_add_memory`]: crate::global_add_memory
+    pub const GLOBAL: Self = Self(0);
+
+    /// Returns the index of the arena, 0 for the global heap.
+    pub const fn as_usize(self) -> usize {
+        self.0
+    }
+
+    /// Returns the name of the arena.
+    pub fn name(self) -> &'static str {
+        match get(self) {
+            Some(arena) => *arena.name.lock(),
+            None => "global",
+        }
+    }
+}
+
+pub(crate) struct Arena {
+    name: SpinNoIrq<&'static str>,
+    start: AtomicUsize,
+    end: AtomicUsize,
+    pub(crate) balloc: SpinNoIrq<DefaultByteAllocator>,
+    pub(crate) palloc: SpinNoIrq<DefaultPageAllocator>,
+}
+
+impl Arena {
+    const fn new() -> Self {
+        Self {
+            name: SpinNoIrq::new(""),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
+            palloc: SpinNoIrq::new(DefaultPageAllocator::new()),
+        }
+    }
+
+    fn contains(&self, vaddr: usize) -> bool {
+        (self.start.load(Ordering::Relaxed)..self.end.load(Ordering::Relaxed)).contains(&vaddr)
+    }
+}
+
+static ARENAS: [Arena; MAX_ARENAS - 1] = [const { Arena::new() }; MAX_ARENAS - 1];
+
+/// The number of added arenas, which are the first ones of [`ARENAS`].
+static NR_ARENAS: AtomicUsize = AtomicUsize::new(0);
+
+/// Serializes the additions of the arenas.
+static ADD_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+fn added() -> &'static [Arena] {
+    &ARENAS[..NR_ARENAS.load(Ordering::Acquire)]
+}
+
+/// Returns the added arena `id`, [`None`] for the global heap.
+pub(crate) fn get(id: ArenaId) -> Option<&'static Arena> {
+    id.0.checked_sub(1).map(|i| &ARENAS[i])
+}
+
+/// Returns the added arena containing `vaddr`, [`None`] for the global heap.
+pub(crate) fn containing(vaddr: usize) -> Option<&'static Arena> {
+    added().iter().find(|arena| arena.contains(vaddr))
+}
+
+/// Adds an arena named `name` with the memory region
+/// `[start_vaddr, start_vaddr + size)`, which must not be given to the global
+/// heap.
+///
+/// Like the global heap, the region must be larger than 32 KB. Fails with
+/// [`AllocError::MemoryOverlap`] if it overlaps another arena, with
+/// [`AllocError::InvalidParam`] if the name is taken, and with
+/// [`AllocError::NoMemory`] if there are already [`MAX_ARENAS`].
+pub fn add_arena(name: &'static str, start_vaddr: usize, size: usize) -> AllocResult<ArenaId> {
+    if size <= MIN_HEAP_SIZE {
+        return Err(AllocError::InvalidParam);
+    }
+    let end = start_vaddr
+        .checked_add(size)
+        .ok_or(AllocError::InvalidParam)?;
+    let _guard = ADD_LOCK.lock();
+    if name == ArenaId::GLOBAL.name() || added().iter().any(|arena| *arena.name.lock() == name) {
+        return Err(AllocError::InvalidParam);
+    }
+    if added().iter().any(|arena| {
+        start_vaddr < arena.end.load(Ordering::Relaxed) && arena.start.load(Ordering::Relaxed) < end
+    }) {
+        return Err(AllocError::MemoryOverlap);
+    }
+    let index = NR_ARENAS.load(Ordering::Relaxed);
+    let Some(arena) = ARENAS.get(index) else {
+        return Err(AllocError::NoMemory);
+    };
+    *arena.name.lock() = name;
+    let mut palloc = arena.palloc.lock();
+    palloc.init(start_vaddr, size);
+    let heap_ptr = palloc.alloc_pages(MIN_HEAP_SIZE / PAGE_SIZE, PAGE_SIZE)?;
+    drop(palloc);
+    arena.balloc.lock().init(heap_ptr, MIN_HEAP_SIZE);
+    arena.start.store(start_vaddr, Ordering::Relaxed);
+    arena.end.store(end, Ordering::Relaxed);
+    NR_ARENAS.store(index + 1, Ordering::Release);
+    debug!(
+        "add heap arena {:?}: [{:#x}, {:#x})",
+        name, start_vaddr, end
+    );
+    Ok(ArenaId(index + 1))
+}
+
+/// Returns the arena named `name`, "global" for the global heap.
+pub fn find_arena(name: &str) -> Option<ArenaId> {
+    if name == ArenaId::GLOBAL.name() {
+        return Some(ArenaId::GLOBAL);
+    }
+    added()
+        .iter()
+        .position(|arena| *arena.name.lock() == name)
+        .map(|i| ArenaId(i + 1))
+}
+
+/// Returns the added arenas, not including the global heap.
+pub fn arenas() -> impl Iterator<Item = ArenaId> {
+    (1..=added().len()).map(ArenaId)
+}
@@ -0,0 +1,176 @@
+//! Page coloring, with the `page-color` feature.
+//!
+//! The pages whose addresses are congruent modulo the way size of the last
+//! level cache (its size divided by its associativity) map to the same cache
+//! sets: they have the same *color*. Giving the pages of different tasks or
+//! subsystems disjoint sets of colors partitions the cache between them, so a
+//! latency-critical task is not slowed by the cache misses others cause.
+//!
+//! The colors allowed for an allocation are a mask, given by the caller of
+//! [`GlobalAllocator::alloc_colored_page`] or by the [color hook] for the
+//! single pages of [`GlobalAllocator::alloc_pages`], e.g. from the current
+//! task. The pages of the other colors met while looking for one are kept in
+//! per-color caches for the later allocations, and given back by a
+//! [`Shrinker`] under memory pressure. Coloring is best effort: if no page of
+//! the allowed colors is found, any page is returned.
+//!
+//! The number of colors is set by `AX_PAGE_COLORS` at build time (16 by
+//! default, up to 64). For Intel CAT, which partitions the cache ways
+//! instead, the class of service is not managed here.
+//!
+//! [`GlobalAllocator::alloc_colored_page`]: crate::GlobalAllocator::alloc_colored_page
+//! [`GlobalAllocator::alloc_pages`]: crate::GlobalAllocator::alloc_pages
+//! [color hook]: set_color_hook
+
+use allocator::PageAllocator;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kspin::SpinNoIrq;
+
+use crate::{Shrinker, PAGE_SIZE};
+
+/// The most colors, the bits of a mask.
+pub(crate) const MAX_COLORS: usize = 64;
+const DEFAULT_COLORS: usize = 16;
+/// The most cached pages of each color.
+const CACHE_PER_COLOR: usize = 16;
+
+/// A function returning the mask of the colors allowed for the current
+/// allocation, 0 for any.
+pub type ColorHook = fn() -> u64;
+
+static COLOR_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function giving the colors of the single pages allocated by
+/// [`GlobalAllocator::alloc_pages`], e.g. the colors of the current task.
+///
+/// [`GlobalAllocator::alloc_pages`]: crate::GlobalAllocator::alloc_pages
+pub fn set_color_hook(hook: ColorHook) {
+    COLOR_HOOK.store(hook as usize, Ordering::Release);
+}
+
+/// Returns the mask of the colors allowed by the color hook, 0 for any.
+pub(crate) fn current_colors() -> u64 {
+    match COLOR_HOOK.load(Ordering::Acquire) {
+        0 => 0,
+        f => {
+            let hook = unsafe { core::mem::transmute::<usize, ColorHook>(f) };
+            hook()
+        }
+    }
+}
+
+/// Returns the number of page colors.
+pub fn nr_colors() -> usize {
+    // The empty variables are unset ones exported by the Makefile.
+    option_env!("AX_PAGE_COLORS")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .map_or(DEFAULT_COLORS, |n| n.min(MAX_COLORS))
+}
+
+/// Returns the color of the page at `vaddr`.
+///
+/// The linear mapping keeps the offsets within the cache ways, so the color
+/// of the virtual address is the one of the physical address.
+pub fn page_color(vaddr: usize) -> usize {
+    (vaddr / PAGE_SIZE) % nr_colors()
+}
+
+/// Returns the mask restricted to the existing colors, all of them if it
+/// allows none.
+pub(crate) fn effective_mask(mask: u64) -> u64 {
+    let all = match nr_colors() {
+        MAX_COLORS => u64::MAX,
+        n => (1 << n) - 1,
+    };
+    match mask & all {
+        0 => all,
+        mask => mask,
+    }
+}
+
+/// The free pages put aside, by color.
+struct Caches {
+    pages: [[usize; CACHE_PER_COLOR]; MAX_COLORS],
+    lens: [usize; MAX_COLORS],
+    total: usize,
+    /// The next color to look at first, to spread the allocations over the
+    /// allowed colors.
+    next: usize,
+}
+
+pub(crate) struct ColorCache {
+    caches: SpinNoIrq<Caches>,
+}
+
+pub(crate) static COLOR_CACHE: ColorCache = ColorCache {
+    caches: SpinNoIrq::new(Caches {
+        pages: [[0; CACHE_PER_COLOR]; MAX_COLORS],
+        lens: [0; MAX_COLORS],
+        total: 0,
+        next: 0,
+    }),
+};
+
+impl ColorCache {
+    /// Takes a cached page of a color of `mask`.
+    pub fn take(&self, mask: u64) -> Option<usize> {
+        let mut caches = self.caches.lock();
+        if caches.total == 0 {
+            return None;
+        }
+        let n = nr_colors();
+        let color = (0..n)
+            .map(|i| (caches.next + i) % n)
+            .find(|&c| mask & (1 << c) != 0 && caches.lens[c] > 0)?;
+        caches.next = (color + 1) % n;
+        caches.lens[color] -= 1;
+        caches.total -= 1;
+        Some(caches.pages[color][caches.lens[color]])
+    }
+
+    /// Puts aside the free page at `vaddr`, returns `false` if the cache of
+    /// its color is full.
+    pub fn put(&self, vaddr: usize) -> bool {
+        let color = page_color(vaddr);
+        let mut caches = self.caches.lock();
+        let len = caches.lens[color];
+        if len == CACHE_PER_COLOR {
+            return false;
+        }
+        caches.pages[color][len] = vaddr;
+        caches.lens[color] += 1;
+        caches.total += 1;
+        true
+    }
+
+    /// Returns the number of cached pages.
+    pub fn len(&self) -> usize {
+        self.caches.lock().total
+    }
+}
+
+impl Shrinker for ColorCache {
+    fn name(&self) -> &'static str {
+        "page-color"
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn scan(&self, nr_to_scan: usize) -> usize {
+        let mut freed = 0;
+        while freed < nr_to_scan {
+            let Some(vaddr) = self.take(u64::MAX) else {
+                break;
+            };
+            crate::global_allocator()
+                .palloc
+                .lock()
+                .dealloc_pages(vaddr, 1);
+            freed += 1;
+        }
+        freed
+    }
+}
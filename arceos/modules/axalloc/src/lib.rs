@@ -4,6 +4,27 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! Independent heap arenas can be added besides the global heap, see
+//! [`add_arena`].
+//!
+//! With the `page-color` feature, the single pages can be allocated from
+//! given cache colors, see [`color`].
+//!
+//! With the `page-buddy` feature, it can take the memory of the early
+//! allocator over with its live allocations, see
+//! [`GlobalAllocator::init_reserved`].
+//!
+//! With the `heap-quota` feature, the heap allocations of the tasks are
+//! charged to their quotas, see [`quota`]. Each allocation of the
+//! `GlobalAlloc` path then takes up to two more words, for its quota.
+//!
+//! With the `page-scrub` feature, the freed pages are zeroed in the
+//! background into a pool of zeroed pages, see [`scrub`].
+//!
+//! The byte allocations are counted by size class, and
+//! [`GlobalAllocator::malloc_stats`] dumps them with the fragmentation of the
+//! heap.
 
 #![no_std]
 
@@ -11,17 +32,32 @@
 extern crate log;
 extern crate alloc;
 
+mod arena;
+#[cfg(feature = "page-color")]
+pub mod color;
 mod page;
+mod pool;
+#[cfg(feature = "heap-quota")]
+pub mod quota;
+#[cfg(feature = "page-scrub")]
+pub mod scrub;
+mod shrink;
+mod stats;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use kspin::SpinNoIrq;
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+pub use arena::{add_arena, arenas, find_arena, ArenaId, MAX_ARENAS};
 pub use page::GlobalPage;
+pub use pool::IrqPool;
+pub use shrink::{register_shrinker, shrink_all, unregister_shrinker, Shrinker};
+pub use stats::{MallocStats, SizeClassStats, NR_SIZE_CLASSES};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
@@ -36,6 +72,98 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "page-buddy")] {
+        /// The default page allocator.
+        pub type DefaultPageAllocator = buddy_allocator::BuddyPageAllocator<PAGE_SIZE>;
+    } else {
+        /// The default page allocator.
+        pub type DefaultPageAllocator = allocator::BitmapPageAllocator<PAGE_SIZE>;
+    }
+}
+
+/// A function moving allocated pages to make room for `num_pages` contiguous
+/// pages aligned to `align_pow2`, returns whether it moved any.
+pub type CompactionHook = fn(num_pages: usize, align_pow2: usize) -> bool;
+
+static COMPACTION_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function called when an allocation of contiguous pages fails,
+/// before retrying it once.
+pub fn set_compaction_hook(hook: CompactionHook) {
+    COMPACTION_HOOK.store(hook as usize, Ordering::Release);
+}
+
+fn compaction_hook() -> Option<CompactionHook> {
+    match COMPACTION_HOOK.load(Ordering::Acquire) {
+        0 => None,
+        f => Some(unsafe { core::mem::transmute::<usize, CompactionHook>(f) }),
+    }
+}
+
+/// A function returning whether the caller may block for `what`, after
+/// reporting it if not, e.g. in the IRQ handlers.
+pub type MayBlockHook = fn(what: &str) -> bool;
+
+static MAY_BLOCK_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function checking that an allocation may block before shrinking
+/// the caches or compacting the pages, which are skipped if it may not.
+pub fn set_may_block_hook(hook: MayBlockHook) {
+    MAY_BLOCK_HOOK.store(hook as usize, Ordering::Release);
+}
+
+/// A function freeing a byte allocation the global allocator did not make,
+/// e.g. one of the early allocator it took the memory over from, returns
+/// whether it was one.
+pub type ForeignDeallocHook = fn(pos: NonNull<u8>, layout: Layout) -> bool;
+
+static FOREIGN_DEALLOC_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function called first to free the byte allocations, see
+/// [`GlobalAllocator::init_reserved`].
+pub fn set_foreign_dealloc_hook(hook: ForeignDeallocHook) {
+    FOREIGN_DEALLOC_HOOK.store(hook as usize, Ordering::Release);
+}
+
+fn dealloc_foreign(pos: NonNull<u8>, layout: Layout) -> bool {
+    match FOREIGN_DEALLOC_HOOK.load(Ordering::Acquire) {
+        0 => false,
+        f => {
+            let hook = unsafe { core::mem::transmute::<usize, ForeignDeallocHook>(f) };
+            hook(pos, layout)
+        }
+    }
+}
+
+fn may_block(what: &str) -> bool {
+    match MAY_BLOCK_HOOK.load(Ordering::Acquire) {
+        0 => true,
+        f => {
+            let hook = unsafe { core::mem::transmute::<usize, MayBlockHook>(f) };
+            hook(what)
+        }
+    }
+}
+
+/// Runs `alloc`, and again after each round of shrinking freeing objects,
+/// until there is memory.
+fn with_shrinking<T>(alloc: impl Fn() -> AllocResult<T>) -> AllocResult<T> {
+    let mut res = alloc();
+    if matches!(res, Err(AllocError::NoMemory)) && !may_block("shrinking the caches") {
+        return res;
+    }
+    for priority in (0..=shrink::DEF_PRIORITY).rev() {
+        if !matches!(res, Err(AllocError::NoMemory)) {
+            break;
+        }
+        if shrink_all(priority) > 0 {
+            res = alloc();
+        }
+    }
+    res
+}
+
 /// The global allocator used by ArceOS.
 ///
 /// It combines a [`ByteAllocator`] and a [`PageAllocator`] into a simple
@@ -44,12 +172,14 @@ cfg_if::cfg_if! {
 /// the byte allocator.
 ///
 /// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
-/// [`BitmapPageAllocator`] is used as the page allocator.
+/// [`BitmapPageAllocator`] is used as the page allocator, or the buddy
+/// allocator with the `page-buddy` feature.
 ///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+/// [`BitmapPageAllocator`]: allocator::BitmapPageAllocator
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
-    palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    palloc: SpinNoIrq<DefaultPageAllocator>,
 }
 
 impl GlobalAllocator {
@@ -57,7 +187,7 @@ impl GlobalAllocator {
     pub const fn new() -> Self {
         Self {
             balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
-            palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            palloc: SpinNoIrq::new(DefaultPageAllocator::new()),
         }
     }
 
@@ -81,8 +211,69 @@ impl GlobalAllocator {
     /// the given region must be larger than 32 KB.
     pub fn init(&self, start_vaddr: usize, size: usize) {
         assert!(size > MIN_HEAP_SIZE);
-        let init_heap_size = MIN_HEAP_SIZE;
         self.palloc.lock().init(start_vaddr, size);
+        self.init_heap();
+    }
+
+    /// Initializes the allocator with the given region, whose pages in the
+    /// `reserved` ranges `(start, size)` are already allocated, with the
+    /// `page-buddy` feature.
+    ///
+    /// It takes the whole memory of the early allocator over without losing
+    /// its live allocations, in two phases:
+    /// 1. the early allocator is frozen, it only frees from then on;
+    /// 2. its memory is given to this function (and to
+    ///    [`add_memory_reserved`] for the other regions), with its live
+    ///    ranges as `reserved`.
+    ///
+    /// The live pages are then freed by [`dealloc_pages`]. The live byte
+    /// allocations are still freed by the early allocator, through the
+    /// [foreign dealloc hook](set_foreign_dealloc_hook), which gives their
+    /// pages back by [`dealloc_pages`] once they are all freed.
+    ///
+    /// [`add_memory_reserved`]: GlobalAllocator::add_memory_reserved
+    /// [`dealloc_pages`]: GlobalAllocator::dealloc_pages
+    #[cfg(feature = "page-buddy")]
+    pub fn init_reserved<I>(&self, start_vaddr: usize, size: usize, reserved: I) -> AllocResult
+    where
+        I: IntoIterator<Item = (usize, usize)> + Clone,
+    {
+        let mut palloc = self.palloc.lock();
+        *palloc = DefaultPageAllocator::new();
+        palloc.add_memory_reserved(start_vaddr, size, reserved)?;
+        drop(palloc);
+        self.init_heap();
+        Ok(())
+    }
+
+    /// Adds the given region to the page allocator, whose pages in the
+    /// `reserved` ranges `(start, size)` are already allocated, with the
+    /// `page-buddy` feature. See [`init_reserved`].
+    ///
+    /// [`init_reserved`]: GlobalAllocator::init_reserved
+    #[cfg(feature = "page-buddy")]
+    pub fn add_memory_reserved<I>(
+        &self,
+        start_vaddr: usize,
+        size: usize,
+        reserved: I,
+    ) -> AllocResult
+    where
+        I: IntoIterator<Item = (usize, usize)> + Clone,
+    {
+        self.palloc
+            .lock()
+            .add_memory_reserved(start_vaddr, size, reserved)
+    }
+
+    /// Initializes the byte allocator with a small region (32 KB) of the page
+    /// allocator.
+    fn init_heap(&self) {
+        let init_heap_size = MIN_HEAP_SIZE;
+        #[cfg(feature = "page-color")]
+        let _ = register_shrinker(&color::COLOR_CACHE);
+        #[cfg(feature = "page-scrub")]
+        let _ = register_shrinker(&scrub::SCRUB_CACHE);
         let heap_ptr = self
             .alloc_pages(init_heap_size / PAGE_SIZE, PAGE_SIZE)
             .unwrap();
@@ -91,9 +282,15 @@ impl GlobalAllocator {
 
     /// Add the given region to the allocator.
     ///
-    /// It will add the whole region to the byte allocator.
+    /// It will add the whole region to the byte allocator, or to the page
+    /// allocator with the `page-buddy` feature, which manages several
+    /// regions.
     pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
-        self.balloc.lock().add_memory(start_vaddr, size)
+        if cfg!(feature = "page-buddy") {
+            self.palloc.lock().add_memory(start_vaddr, size)
+        } else {
+            self.balloc.lock().add_memory(start_vaddr, size)
+        }
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
@@ -101,28 +298,57 @@ impl GlobalAllocator {
     ///
     /// It firstly tries to allocate from the byte allocator. If there is no
     /// memory, it asks the page allocator for more memory and adds it to the
-    /// byte allocator.
+    /// byte allocator. If there are no free pages either, the registered
+    /// [`Shrinker`]s are called, unless the caller may not block (see
+    /// [`set_may_block_hook`]).
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
-        // simple two-level allocator: if no heap memory, allocate from the page allocator.
-        let mut balloc = self.balloc.lock();
-        loop {
-            if let Ok(ptr) = balloc.alloc(layout) {
-                return Ok(ptr);
-            } else {
-                let old_size = balloc.total_bytes();
-                let expand_size = old_size
-                    .max(layout.size())
-                    .next_power_of_two()
-                    .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
-                debug!(
-                    "expand heap memory: [{:#x}, {:#x})",
-                    heap_ptr,
-                    heap_ptr + expand_size
-                );
-                balloc.add_memory(heap_ptr, expand_size)?;
-            }
+        let res = with_shrinking(|| self.alloc_bytes(layout));
+        count_alloc(&res, layout);
+        #[cfg(feature = "event")]
+        if res.is_err() {
+            axevent::publish(axevent::KernelEvent::Oom {
+                size: layout.size(),
+                align: layout.align(),
+            });
         }
+        res
+    }
+
+    fn alloc_bytes(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        alloc_bytes_from(&self.balloc, &self.palloc, layout)
+    }
+
+    /// Allocates bytes from `arena`, see [`add_arena`]. It is [`alloc`] for
+    /// [`ArenaId::GLOBAL`].
+    ///
+    /// The region is freed by [`dealloc`].
+    ///
+    /// [`alloc`]: GlobalAllocator::alloc
+    /// [`dealloc`]: GlobalAllocator::dealloc
+    pub fn alloc_in(&self, arena: ArenaId, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let Some(arena) = arena::get(arena) else {
+            return self.alloc(layout);
+        };
+        let res = with_shrinking(|| alloc_bytes_from(&arena.balloc, &arena.palloc, layout));
+        count_alloc(&res, layout);
+        #[cfg(feature = "event")]
+        if res.is_err() {
+            axevent::publish(axevent::KernelEvent::Oom {
+                size: layout.size(),
+                align: layout.align(),
+            });
+        }
+        res
+    }
+
+    /// Allocates bytes like [`alloc`] without blocking: the [`Shrinker`]s
+    /// are not called. For the IRQ handlers, see also [`IrqPool`].
+    ///
+    /// [`alloc`]: GlobalAllocator::alloc
+    pub fn try_alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let res = self.alloc_bytes(layout);
+        count_alloc(&res, layout);
+        res
     }
 
     /// Gives back the allocated region to the byte allocator.
@@ -131,9 +357,19 @@ impl GlobalAllocator {
     /// the same as the one used in [`alloc`]. Otherwise, the behavior is
     /// undefined.
     ///
+    /// The allocations it did not make are freed by the
+    /// [foreign dealloc hook](set_foreign_dealloc_hook).
+    ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
-        self.balloc.lock().dealloc(pos, layout)
+        if dealloc_foreign(pos, layout) {
+            return;
+        }
+        stats::on_dealloc(layout.size());
+        match arena::containing(pos.as_ptr() as usize) {
+            Some(arena) => arena.balloc.lock().dealloc(pos, layout),
+            None => self.balloc.lock().dealloc(pos, layout),
+        }
     }
 
     /// Allocates contiguous pages.
@@ -142,7 +378,117 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// If there are not enough free pages, the registered [`Shrinker`]s are
+    /// called. If several pages are requested and there are not enough
+    /// contiguous free ones, the [compaction hook](set_compaction_hook) is
+    /// called. Neither is called if the caller may not block (see
+    /// [`set_may_block_hook`]).
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        #[cfg(feature = "page-color")]
+        if num_pages == 1 && align_pow2 <= PAGE_SIZE {
+            return match color::current_colors() {
+                0 => match color::COLOR_CACHE.take(u64::MAX) {
+                    Some(vaddr) => Ok(vaddr),
+                    None => self.alloc_pages_any(num_pages, align_pow2),
+                },
+                colors => self.alloc_colored_page(colors),
+            };
+        }
+        self.alloc_pages_any(num_pages, align_pow2)
+    }
+
+    /// Allocates a page of one of the cache colors of the mask `colors`,
+    /// see [`color`]. It is any page if there is none of these colors.
+    #[cfg(feature = "page-color")]
+    pub fn alloc_colored_page(&self, colors: u64) -> AllocResult<usize> {
+        let mask = color::effective_mask(colors);
+        if let Some(vaddr) = color::COLOR_CACHE.take(mask) {
+            return Ok(vaddr);
+        }
+        // The free pages are taken in order, one of each color at most is
+        // met before finding an allowed one.
+        let mut others = [0; color::MAX_COLORS];
+        let mut nr_others = 0;
+        let mut found = None;
+        while nr_others < color::nr_colors() {
+            let Ok(vaddr) = self.palloc.lock().alloc_pages(1, PAGE_SIZE) else {
+                break;
+            };
+            if mask & (1 << color::page_color(vaddr)) != 0 {
+                found = Some(vaddr);
+                break;
+            }
+            others[nr_others] = vaddr;
+            nr_others += 1;
+        }
+        if found.is_none() && nr_others > 0 {
+            nr_others -= 1;
+            found = Some(others[nr_others]);
+        }
+        for &vaddr in &others[..nr_others] {
+            if !color::COLOR_CACHE.put(vaddr) {
+                self.palloc.lock().dealloc_pages(vaddr, 1);
+            }
+        }
+        match found {
+            Some(vaddr) => Ok(vaddr),
+            None => self.alloc_pages_any(1, PAGE_SIZE),
+        }
+    }
+
+    fn alloc_pages_any(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let alloc = || self.palloc.lock().alloc_pages(num_pages, align_pow2);
+        let mut res = with_shrinking(alloc);
+        if res.is_err() && num_pages > 1 {
+            if let Some(hook) = compaction_hook().filter(|_| may_block("compacting the pages")) {
+                if hook(num_pages, align_pow2) {
+                    res = alloc();
+                }
+            }
+        }
+        #[cfg(feature = "event")]
+        if res.is_err() {
+            axevent::publish(axevent::KernelEvent::Oom {
+                size: num_pages * PAGE_SIZE,
+                align: align_pow2,
+            });
+        }
+        res
+    }
+
+    /// Allocates contiguous pages from `arena`, see [`add_arena`]. It is
+    /// [`alloc_pages`] for [`ArenaId::GLOBAL`].
+    ///
+    /// The pages are not colored, and are freed by [`dealloc_pages`].
+    ///
+    /// [`alloc_pages`]: GlobalAllocator::alloc_pages
+    /// [`dealloc_pages`]: GlobalAllocator::dealloc_pages
+    pub fn alloc_pages_in(
+        &self,
+        arena: ArenaId,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        let Some(arena) = arena::get(arena) else {
+            return self.alloc_pages(num_pages, align_pow2);
+        };
+        let res = with_shrinking(|| arena.palloc.lock().alloc_pages(num_pages, align_pow2));
+        #[cfg(feature = "event")]
+        if res.is_err() {
+            axevent::publish(axevent::KernelEvent::Oom {
+                size: num_pages * PAGE_SIZE,
+                align: align_pow2,
+            });
+        }
+        res
+    }
+
+    /// Allocates contiguous pages like [`alloc_pages`] without blocking:
+    /// neither the [`Shrinker`]s nor the compaction hook are called.
+    ///
+    /// [`alloc_pages`]: GlobalAllocator::alloc_pages
+    pub fn try_alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
         self.palloc.lock().alloc_pages(num_pages, align_pow2)
     }
 
@@ -152,9 +498,17 @@ impl GlobalAllocator {
     /// should be the same as the one used in [`alloc_pages`]. Otherwise, the
     /// behavior is undefined.
     ///
+    /// With the `page-scrub` feature, the single pages of the global heap are
+    /// queued to be zeroed in the background, see [`scrub`].
+    ///
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
-        self.palloc.lock().dealloc_pages(pos, num_pages)
+        match arena::containing(pos) {
+            Some(arena) => arena.palloc.lock().dealloc_pages(pos, num_pages),
+            #[cfg(feature = "page-scrub")]
+            None if num_pages == 1 && scrub::defer_free(pos) => {}
+            None => self.palloc.lock().dealloc_pages(pos, num_pages),
+        }
     }
 
     /// Returns the number of allocated bytes in the byte allocator.
@@ -167,7 +521,28 @@ impl GlobalAllocator {
         self.balloc.lock().available_bytes()
     }
 
+    /// Returns a snapshot of the byte allocations, with the usage and the
+    /// fragmentation of the global heap.
+    ///
+    /// The largest free block is found by trying allocations with the heap
+    /// locked, it is for the diagnostics only.
+    pub fn malloc_stats(&self) -> MallocStats {
+        let mut balloc = self.balloc.lock();
+        let free_bytes = balloc.available_bytes();
+        let largest_free = stats::largest_free(&mut *balloc, free_bytes);
+        MallocStats::new(
+            self.name(),
+            balloc.total_bytes(),
+            balloc.used_bytes(),
+            free_bytes,
+            largest_free,
+        )
+    }
+
     /// Returns the number of allocated pages in the page allocator.
+    ///
+    /// The free pages cached by color, or queued to be zeroed, are counted as
+    /// allocated.
     pub fn used_pages(&self) -> usize {
         self.palloc.lock().used_pages()
     }
@@ -176,19 +551,89 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.palloc.lock().available_pages()
     }
+
+    /// Returns the number of allocated pages in `arena`, including the ones
+    /// of its byte allocator.
+    pub fn used_pages_in(&self, arena: ArenaId) -> usize {
+        match arena::get(arena) {
+            Some(arena) => arena.palloc.lock().used_pages(),
+            None => self.used_pages(),
+        }
+    }
+
+    /// Returns the number of available pages in `arena`.
+    pub fn available_pages_in(&self, arena: ArenaId) -> usize {
+        match arena::get(arena) {
+            Some(arena) => arena.palloc.lock().available_pages(),
+            None => self.available_pages(),
+        }
+    }
+}
+
+fn count_alloc(res: &AllocResult<NonNull<u8>>, layout: Layout) {
+    match res {
+        Ok(_) => stats::on_alloc(layout.size()),
+        Err(_) => stats::on_failure(),
+    }
+}
+
+/// The simple two-level allocation: if there is no memory in `balloc`, adds
+/// pages from `palloc` to it.
+fn alloc_bytes_from(
+    balloc: &SpinNoIrq<DefaultByteAllocator>,
+    palloc: &SpinNoIrq<DefaultPageAllocator>,
+    layout: Layout,
+) -> AllocResult<NonNull<u8>> {
+    let mut balloc = balloc.lock();
+    loop {
+        if let Ok(ptr) = balloc.alloc(layout) {
+            return Ok(ptr);
+        } else {
+            let old_size = balloc.total_bytes();
+            let expand_size = old_size
+                .max(layout.size())
+                .next_power_of_two()
+                .max(PAGE_SIZE);
+            // Neither shrinking nor compacting, which allocate with the
+            // heap locked.
+            let heap_ptr = palloc
+                .lock()
+                .alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+            debug!(
+                "expand heap memory: [{:#x}, {:#x})",
+                heap_ptr,
+                heap_ptr + expand_size
+            );
+            balloc.add_memory(heap_ptr, expand_size)?;
+        }
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
-            ptr.as_ptr()
-        } else {
-            alloc::alloc::handle_alloc_error(layout)
+        #[cfg(feature = "heap-quota")]
+        {
+            quota::alloc(self, layout)
+        }
+        #[cfg(not(feature = "heap-quota"))]
+        {
+            if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
+                ptr.as_ptr()
+            } else {
+                alloc::alloc::handle_alloc_error(layout)
+            }
         }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout)
+        let pos = NonNull::new(ptr).expect("dealloc null ptr");
+        // The foreign allocations have no quota trailer.
+        #[cfg(feature = "heap-quota")]
+        if !dealloc_foreign(pos, layout) {
+            quota::dealloc(self, ptr, layout);
+        }
+        #[cfg(not(feature = "heap-quota"))]
+        GlobalAllocator::dealloc(self, pos, layout);
     }
 }
 
@@ -203,9 +648,10 @@ pub fn global_allocator() -> &'static GlobalAllocator {
 /// Initializes the global allocator with the given memory region.
 ///
 /// Note that the memory region bounds are just numbers, and the allocator
-/// does not actually access the region. Users should ensure that the region
-/// is valid and not being used by others, so that the allocated memory is also
-/// valid.
+/// does not actually access the region, except the buddy page allocator of
+/// the `page-buddy` feature, which links its free blocks. Users should ensure
+/// that the region is valid and not being used by others, so that the
+/// allocated memory is also valid.
 ///
 /// This function should be called only once, and before any allocation.
 pub fn global_init(start_vaddr: usize, size: usize) {
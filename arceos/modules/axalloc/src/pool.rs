@@ -0,0 +1,119 @@
+//! Pools of pre-allocated blocks, for the allocations of the IRQ handlers.
+
+use allocator::{AllocError, AllocResult};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use kspin::SpinNoIrq;
+
+use crate::global_allocator;
+
+/// A pool of up to `N` pre-allocated blocks of a layout, for the allocations
+/// of the IRQ handlers, which must not block.
+///
+/// It is filled in the task context by [`fill`], and [`try_alloc`] takes a
+/// block from it, or from the global allocator without blocking (see
+/// [`GlobalAllocator::try_alloc`]) once it is empty.
+///
+/// [`fill`]: IrqPool::fill
+/// [`try_alloc`]: IrqPool::try_alloc
+/// [`GlobalAllocator::try_alloc`]: crate::GlobalAllocator::try_alloc
+pub struct IrqPool<const N: usize> {
+    layout: Layout,
+    /// The free blocks, and their number.
+    blocks: SpinNoIrq<([usize; N], usize)>,
+}
+
+impl<const N: usize> IrqPool<N> {
+    /// Creates an empty pool of the blocks of `layout`.
+    pub const fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            blocks: SpinNoIrq::new(([0; N], 0)),
+        }
+    }
+
+    /// Returns the layout of the blocks.
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the number of free blocks in the pool.
+    pub fn len(&self) -> usize {
+        self.blocks.lock().1
+    }
+
+    /// Returns whether there is no free block in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fills the pool up with blocks of the global allocator.
+    ///
+    /// It may block, and is to be called in the task context, e.g. at the
+    /// initialization of a driver, and after its IRQ handler used the pool.
+    /// If an allocation fails, the pool keeps the blocks allocated so far.
+    pub fn fill(&self) -> AllocResult {
+        while self.len() < N {
+            // not allocated with the pool locked, as it may block
+            let ptr = global_allocator().alloc(self.layout)?;
+            let mut blocks = self.blocks.lock();
+            let (slots, len) = &mut *blocks;
+            if *len == N {
+                drop(blocks);
+                global_allocator().dealloc(ptr, self.layout);
+                break;
+            }
+            slots[*len] = ptr.as_ptr() as usize;
+            *len += 1;
+        }
+        Ok(())
+    }
+
+    /// Allocates a block without blocking, from the pool, or from the global
+    /// allocator if the pool is empty.
+    pub fn try_alloc(&self) -> AllocResult<NonNull<u8>> {
+        let popped = {
+            let mut blocks = self.blocks.lock();
+            let (slots, len) = &mut *blocks;
+            (*len > 0).then(|| {
+                *len -= 1;
+                slots[*len]
+            })
+        };
+        match popped {
+            Some(pos) => NonNull::new(pos as *mut u8).ok_or(AllocError::InvalidParam),
+            None => global_allocator().try_alloc(self.layout),
+        }
+    }
+
+    /// Gives a block back to the pool, or to the global allocator if the
+    /// pool is full.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a block allocated by [`try_alloc`] of this pool.
+    ///
+    /// [`try_alloc`]: IrqPool::try_alloc
+    pub unsafe fn free(&self, ptr: NonNull<u8>) {
+        let mut blocks = self.blocks.lock();
+        let (slots, len) = &mut *blocks;
+        if *len < N {
+            slots[*len] = ptr.as_ptr() as usize;
+            *len += 1;
+        } else {
+            drop(blocks);
+            global_allocator().dealloc(ptr, self.layout);
+        }
+    }
+}
+
+impl<const N: usize> Drop for IrqPool<N> {
+    fn drop(&mut self) {
+        let (slots, len) = self.blocks.get_mut();
+        for &pos in &slots[..*len] {
+            if let Some(ptr) = NonNull::new(pos as *mut u8) {
+                global_allocator().dealloc(ptr, self.layout);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Heap quotas, with the `heap-quota` feature.
+//!
+//! A [`HeapQuota`] limits the bytes of the heap allocated by the tasks
+//! charged to it, given by the [quota hook] for each allocation, e.g. the
+//! quota of the current task. A quota may be nested in another, which is
+//! charged too. An allocation over the quota fails as if the heap were
+//! exhausted, only for the task over it: its fallible allocations
+//! (`Vec::try_reserve`, `Box::try_new`) get the error, the ones of the other
+//! tasks go on.
+//!
+//! The allocations of the [`GlobalAlloc`](core::alloc::GlobalAlloc) path are
+//! followed by a trailer word telling the quota they were charged to,
+//! released when they are freed, even by another task or after the one which
+//! allocated them exited. It costs at most two words per allocation,
+//! whatever its alignment: a page-aligned allocation takes no extra page.
+//! The direct calls to the methods of [`GlobalAllocator`] are not charged.
+//!
+//! [quota hook]: set_quota_hook
+
+use alloc::sync::Arc;
+use core::alloc::Layout;
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::GlobalAllocator;
+
+/// A limit of the bytes of the heap allocated by some tasks.
+pub struct HeapQuota {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    failures: AtomicUsize,
+    parent: Option<Arc<HeapQuota>>,
+}
+
+impl HeapQuota {
+    /// Creates a quota of `limit` bytes, nested in `parent` if any.
+    pub fn new(limit: usize, parent: Option<Arc<HeapQuota>>) -> Arc<Self> {
+        Arc::new(Self {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            parent,
+        })
+    }
+
+    /// Gets the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Sets the limit in bytes. A limit lower than the bytes in use only
+    /// fails the next allocations.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Gets the bytes in use, with the trailers and their padding.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Gets the most bytes in use so far.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Gets the allocations which failed for the quota.
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Gets the quota this one is nested in.
+    pub fn parent(&self) -> Option<&Arc<HeapQuota>> {
+        self.parent.as_ref()
+    }
+
+    /// The quota and the ones it is nested in.
+    fn ancestors(&self) -> impl Iterator<Item = &HeapQuota> {
+        core::iter::successors(Some(self), |quota| quota.parent.as_deref())
+    }
+
+    /// Charges `size` bytes to the quota and its ancestors, or to none of
+    /// them if one would be over its limit.
+    fn try_charge(&self, size: usize) -> bool {
+        for (i, quota) in self.ancestors().enumerate() {
+            let limit = quota.limit();
+            let charged = quota
+                .used
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                    used.checked_add(size).filter(|&total| total <= limit)
+                });
+            match charged {
+                Ok(used) => {
+                    quota.peak.fetch_max(used + size, Ordering::Relaxed);
+                }
+                Err(_) => {
+                    quota.failures.fetch_add(1, Ordering::Relaxed);
+                    for quota in self.ancestors().take(i) {
+                        quota.used.fetch_sub(size, Ordering::Relaxed);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn uncharge(&self, size: usize) {
+        for quota in self.ancestors() {
+            quota.used.fetch_sub(size, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A function returning the quota the current allocation is charged to, if
+/// any.
+pub type QuotaHook = fn() -> Option<Arc<HeapQuota>>;
+
+static QUOTA_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function giving the quota of the allocations of the
+/// `GlobalAlloc` path, e.g. the quota of the current task.
+pub fn set_quota_hook(hook: QuotaHook) {
+    QUOTA_HOOK.store(hook as usize, Ordering::Release);
+}
+
+fn current_quota() -> Option<Arc<HeapQuota>> {
+    match QUOTA_HOOK.load(Ordering::Acquire) {
+        0 => None,
+        f => {
+            let hook = unsafe { core::mem::transmute::<usize, QuotaHook>(f) };
+            hook()
+        }
+    }
+}
+
+/// The layout of the allocation holding the region of `layout` and the
+/// trailer after it, and the offset of the trailer.
+fn with_trailer(layout: Layout) -> Option<(Layout, usize)> {
+    let word = core::mem::size_of::<usize>();
+    let offset = layout.size().checked_next_multiple_of(word)?;
+    let outer =
+        Layout::from_size_align(offset.checked_add(word)?, layout.align().max(word)).ok()?;
+    Some((outer, offset))
+}
+
+/// Allocates a region of `layout` charged to the current quota, with the
+/// pointer to the quota in the word after it.
+pub(crate) unsafe fn alloc(allocator: &GlobalAllocator, layout: Layout) -> *mut u8 {
+    let Some((outer, offset)) = with_trailer(layout) else {
+        return null_mut();
+    };
+    let quota = current_quota();
+    if let Some(quota) = &quota {
+        if !quota.try_charge(outer.size()) {
+            return null_mut();
+        }
+    }
+    match allocator.alloc(outer) {
+        Ok(ptr) => {
+            let region = ptr.as_ptr();
+            let trailer = quota.map_or(0, |quota| Arc::into_raw(quota) as usize);
+            (region.add(offset) as *mut usize).write(trailer);
+            region
+        }
+        Err(_) => {
+            if let Some(quota) = quota {
+                quota.uncharge(outer.size());
+            }
+            alloc::alloc::handle_alloc_error(layout)
+        }
+    }
+}
+
+/// Frees a region allocated by [`alloc`], and releases it from its quota.
+pub(crate) unsafe fn dealloc(allocator: &GlobalAllocator, region: *mut u8, layout: Layout) {
+    let (outer, offset) = with_trailer(layout).unwrap();
+    let trailer = (region.add(offset) as *const usize).read();
+    allocator.dealloc(NonNull::new(region).unwrap(), outer);
+    if trailer != 0 {
+        // The quota may be dropped here, freeing it after the region.
+        let quota = Arc::from_raw(trailer as *const HeapQuota);
+        quota.uncharge(outer.size());
+    }
+}
@@ -0,0 +1,170 @@
+//! Background zeroing of the freed pages, with the `page-scrub` feature.
+//!
+//! The single pages freed to the global heap by
+//! [`GlobalAllocator::dealloc_pages`] are queued as dirty instead of going
+//! back to the page allocator. A background task of the lowest priority calls
+//! [`scrub_step`] to zero them into a pool of zeroed pages, and tops the pool
+//! up with fresh pages when no freed one is waiting.
+//!
+//! [`alloc_zeroed_page`] takes a page of the pool, for the allocations that
+//! must not see the data of the previous owner of the page (the user and the
+//! guest memory), so they do not zero it on the allocation path. It only
+//! zeroes a page itself when the pool is empty.
+//!
+//! The pages of both queues are given back by a [`Shrinker`] under memory
+//! pressure, the dirty ones first.
+//!
+//! [`GlobalAllocator::dealloc_pages`]: crate::GlobalAllocator::dealloc_pages
+
+use allocator::{AllocResult, PageAllocator};
+use kspin::SpinNoIrq;
+
+use crate::{global_allocator, Shrinker, PAGE_SIZE};
+
+/// The most freed pages waiting to be zeroed.
+const MAX_DIRTY_PAGES: usize = 64;
+/// The most zeroed pages in the pool.
+const MAX_ZEROED_PAGES: usize = 64;
+
+/// A stack of up to `N` free pages.
+struct PageStack<const N: usize> {
+    pages: SpinNoIrq<([usize; N], usize)>,
+}
+
+impl<const N: usize> PageStack<N> {
+    const fn new() -> Self {
+        Self {
+            pages: SpinNoIrq::new(([0; N], 0)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.pages.lock().1
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Pushes the page at `vaddr`, returns `false` if the stack is full.
+    fn push(&self, vaddr: usize) -> bool {
+        let mut pages = self.pages.lock();
+        let (slots, len) = &mut *pages;
+        if *len == N {
+            return false;
+        }
+        slots[*len] = vaddr;
+        *len += 1;
+        true
+    }
+
+    fn pop(&self) -> Option<usize> {
+        let mut pages = self.pages.lock();
+        let (slots, len) = &mut *pages;
+        (*len > 0).then(|| {
+            *len -= 1;
+            slots[*len]
+        })
+    }
+}
+
+static DIRTY: PageStack<MAX_DIRTY_PAGES> = PageStack::new();
+static ZEROED: PageStack<MAX_ZEROED_PAGES> = PageStack::new();
+
+pub(crate) static SCRUB_CACHE: ScrubCache = ScrubCache;
+
+fn zero_page(vaddr: usize) {
+    unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, PAGE_SIZE) };
+}
+
+fn free_page(vaddr: usize) {
+    global_allocator().palloc.lock().dealloc_pages(vaddr, 1);
+}
+
+/// Queues the freed page at `vaddr` to be zeroed, returns `false` if the
+/// queue is full.
+pub(crate) fn defer_free(vaddr: usize) -> bool {
+    DIRTY.push(vaddr)
+}
+
+/// Allocates a zeroed page, from the pool of the zeroed pages, or from the
+/// global allocator and zeroed now if the pool is empty.
+///
+/// It is freed by [`GlobalAllocator::dealloc_pages`].
+///
+/// [`GlobalAllocator::dealloc_pages`]: crate::GlobalAllocator::dealloc_pages
+pub fn alloc_zeroed_page() -> AllocResult<usize> {
+    if let Some(vaddr) = ZEROED.pop() {
+        return Ok(vaddr);
+    }
+    let vaddr = global_allocator().alloc_pages(1, PAGE_SIZE)?;
+    zero_page(vaddr);
+    Ok(vaddr)
+}
+
+/// Zeroes a page into the pool: a freed one, or a fresh one if none is
+/// waiting. Returns `false` if there was nothing to do.
+///
+/// It is called in a loop by the scrubber task, which sleeps once it
+/// returns `false`. A freed page is given back without being zeroed if the
+/// pool is full.
+pub fn scrub_step() -> bool {
+    if let Some(vaddr) = DIRTY.pop() {
+        if ZEROED.is_full() {
+            free_page(vaddr);
+        } else {
+            zero_page(vaddr);
+            if !ZEROED.push(vaddr) {
+                free_page(vaddr);
+            }
+        }
+        return true;
+    }
+    if ZEROED.is_full() {
+        return false;
+    }
+    // a fresh page only if it does not have to make room for it
+    let Ok(vaddr) = global_allocator().try_alloc_pages(1, PAGE_SIZE) else {
+        return false;
+    };
+    zero_page(vaddr);
+    if !ZEROED.push(vaddr) {
+        free_page(vaddr);
+    }
+    true
+}
+
+/// Returns the number of the freed pages waiting to be zeroed.
+pub fn dirty_pages() -> usize {
+    DIRTY.len()
+}
+
+/// Returns the number of zeroed pages in the pool.
+pub fn zeroed_pages() -> usize {
+    ZEROED.len()
+}
+
+/// The [`Shrinker`] giving back the pages of both queues.
+pub(crate) struct ScrubCache;
+
+impl Shrinker for ScrubCache {
+    fn name(&self) -> &'static str {
+        "page-scrub"
+    }
+
+    fn count(&self) -> usize {
+        DIRTY.len() + ZEROED.len()
+    }
+
+    fn scan(&self, nr_to_scan: usize) -> usize {
+        let mut freed = 0;
+        while freed < nr_to_scan {
+            let Some(vaddr) = DIRTY.pop().or_else(|| ZEROED.pop()) else {
+                break;
+            };
+            free_page(vaddr);
+            freed += 1;
+        }
+        freed
+    }
+}
@@ -0,0 +1,95 @@
+//! Shrinkers of the caches, called under memory pressure.
+//!
+//! A cache (of directory entries, inodes, pages or network buffers) that can
+//! drop some of its objects registers a [`Shrinker`]. When an allocation
+//! fails, the global allocator asks all shrinkers to free a part of their
+//! objects and retries, with a larger part each time, before giving up.
+
+use allocator::{AllocError, AllocResult};
+use core::sync::atomic::{AtomicBool, Ordering};
+use kspin::SpinNoIrq;
+
+/// The first round asks the shrinkers to free `1 / 2^DEF_PRIORITY` of their
+/// objects, the last one all of them.
+pub(crate) const DEF_PRIORITY: u32 = 4;
+
+/// Maximum number of registered shrinkers, registering does not allocate.
+const MAX_SHRINKERS: usize = 16;
+
+/// A cache whose objects can be freed on demand.
+pub trait Shrinker: Sync {
+    /// Returns the name of the cache.
+    fn name(&self) -> &'static str;
+
+    /// Returns the number of objects that could be freed now.
+    fn count(&self) -> usize;
+
+    /// Tries to free `nr_to_scan` objects, returns the number freed.
+    ///
+    /// It is called with no lock of the allocator held, but maybe in the
+    /// middle of an allocation of the caller: it must not wait for a lock held
+    /// while allocating.
+    fn scan(&self, nr_to_scan: usize) -> usize;
+}
+
+static SHRINKERS: SpinNoIrq<[Option<&'static dyn Shrinker>; MAX_SHRINKERS]> =
+    SpinNoIrq::new([None; MAX_SHRINKERS]);
+
+static SHRINKING: AtomicBool = AtomicBool::new(false);
+
+/// Registers a shrinker, called on allocation failures from now on.
+///
+/// Returns [`AllocError::NoMemory`] if there are too many shrinkers.
+pub fn register_shrinker(shrinker: &'static dyn Shrinker) -> AllocResult {
+    let mut shrinkers = SHRINKERS.lock();
+    let slot = shrinkers
+        .iter_mut()
+        .find(|s| s.is_none())
+        .ok_or(AllocError::NoMemory)?;
+    *slot = Some(shrinker);
+    info!("register shrinker: {}", shrinker.name());
+    Ok(())
+}
+
+/// Unregisters a shrinker, returns whether it was registered.
+pub fn unregister_shrinker(shrinker: &'static dyn Shrinker) -> bool {
+    let mut shrinkers = SHRINKERS.lock();
+    for slot in shrinkers.iter_mut() {
+        if slot.is_some_and(|s| core::ptr::addr_eq(s, shrinker)) {
+            *slot = None;
+            return true;
+        }
+    }
+    false
+}
+
+/// Asks all shrinkers to free `1 / 2^priority` of their objects, returns the
+/// number of objects freed.
+///
+/// The shrinkers run one at a time, allocations failing while they run do
+/// not shrink again.
+pub fn shrink_all(priority: u32) -> usize {
+    if SHRINKING.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    // Not called with the lock held, they may block.
+    let shrinkers = *SHRINKERS.lock();
+    let mut freed = 0;
+    for shrinker in shrinkers.iter().flatten() {
+        let count = shrinker.count();
+        if count == 0 {
+            continue;
+        }
+        let nr_to_scan = (count >> priority).max(1);
+        let n = shrinker.scan(nr_to_scan);
+        debug!(
+            "shrinker {}: {}/{} objects freed",
+            shrinker.name(),
+            n,
+            nr_to_scan
+        );
+        freed += n;
+    }
+    SHRINKING.store(false, Ordering::Release);
+    freed
+}
@@ -0,0 +1,235 @@
+//! Statistics of the byte allocations, dumped like `malloc_stats()`.
+//!
+//! The allocations and the frees are counted by size class, of all the
+//! heaps. [`MallocStats`] is a snapshot of them with the usage of the global
+//! heap, and two fragmentation estimates: the external one, the part of the
+//! free bytes out of the largest free block, and the internal one, the part
+//! of the used bytes not requested (the rounding and the headers of the
+//! byte allocator).
+//!
+//! The counters are per-CPU [`axcounter`] ones, the CPUs allocating at once
+//! do not contend on them.
+
+use allocator::ByteAllocator;
+use axcounter::{Counter, Gauge};
+use core::alloc::Layout;
+use core::fmt;
+
+/// The number of size classes.
+pub const NR_SIZE_CLASSES: usize = 16;
+
+/// The upper bound of the first class, doubled for each next class, the last
+/// one is unbounded.
+const MIN_CLASS_SIZE: usize = 8;
+
+/// The granularity of the sizes tried for the largest free block.
+const PROBE_ALIGN: usize = 8;
+
+struct ClassCounters {
+    allocs: Counter,
+    frees: Counter,
+    live_bytes: Gauge,
+}
+
+impl ClassCounters {
+    const fn new() -> Self {
+        Self {
+            allocs: Counter::new(),
+            frees: Counter::new(),
+            live_bytes: Gauge::new(),
+        }
+    }
+}
+
+static CLASSES: [ClassCounters; NR_SIZE_CLASSES] =
+    [const { ClassCounters::new() }; NR_SIZE_CLASSES];
+static FAILED_ALLOCS: Counter = Counter::new();
+
+fn size_class(size: usize) -> usize {
+    let class = size
+        .div_ceil(MIN_CLASS_SIZE)
+        .next_power_of_two()
+        .trailing_zeros();
+    (class as usize).min(NR_SIZE_CLASSES - 1)
+}
+
+/// Counts an allocation of `size` bytes.
+pub(crate) fn on_alloc(size: usize) {
+    let class = &CLASSES[size_class(size)];
+    class.allocs.inc();
+    class.live_bytes.add(size);
+}
+
+/// Counts a free of `size` bytes.
+pub(crate) fn on_dealloc(size: usize) {
+    let class = &CLASSES[size_class(size)];
+    class.frees.inc();
+    class.live_bytes.sub(size);
+}
+
+/// Counts an allocation failed for lack of memory.
+pub(crate) fn on_failure() {
+    FAILED_ALLOCS.inc();
+}
+
+/// The largest block `balloc` can allocate without adding memory to it,
+/// found by trying allocations up to the `free` bytes.
+pub(crate) fn largest_free(balloc: &mut impl ByteAllocator, free: usize) -> usize {
+    let (mut lo, mut hi) = (0, free / PROBE_ALIGN);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        let layout = Layout::from_size_align(mid * PROBE_ALIGN, PROBE_ALIGN).unwrap();
+        match balloc.alloc(layout) {
+            Ok(ptr) => {
+                balloc.dealloc(ptr, layout);
+                lo = mid;
+            }
+            Err(_) => hi = mid - 1,
+        }
+    }
+    lo * PROBE_ALIGN
+}
+
+/// The allocations of a size class.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeClassStats {
+    /// The upper bound (inclusive) of the sizes of the class, `None` for the
+    /// last one.
+    pub limit: Option<usize>,
+    /// The number of allocations.
+    pub allocs: usize,
+    /// The number of frees.
+    pub frees: usize,
+    /// The bytes requested by the live allocations.
+    pub live_bytes: usize,
+}
+
+impl SizeClassStats {
+    /// The number of live allocations.
+    pub fn live(&self) -> usize {
+        self.allocs.saturating_sub(self.frees)
+    }
+}
+
+/// A snapshot of the byte allocations, see
+/// [`GlobalAllocator::malloc_stats`](crate::GlobalAllocator::malloc_stats).
+///
+/// It is printed as a table by [`Display`](fmt::Display).
+#[derive(Debug, Clone)]
+pub struct MallocStats {
+    /// The name of the byte allocator.
+    pub allocator: &'static str,
+    /// The allocations by size class, of all the heaps.
+    pub classes: [SizeClassStats; NR_SIZE_CLASSES],
+    /// The bytes of the global heap.
+    pub heap_bytes: usize,
+    /// The bytes used in the global heap, by the byte allocator.
+    pub used_bytes: usize,
+    /// The bytes free in the global heap.
+    pub free_bytes: usize,
+    /// The largest block which can be allocated in the global heap without
+    /// expanding it.
+    pub largest_free: usize,
+    /// The number of allocations failed for lack of memory.
+    pub failed_allocs: usize,
+}
+
+impl MallocStats {
+    /// Takes the counters, with the given usage of the global heap.
+    pub(crate) fn new(
+        allocator: &'static str,
+        heap_bytes: usize,
+        used_bytes: usize,
+        free_bytes: usize,
+        largest_free: usize,
+    ) -> Self {
+        let mut classes = [SizeClassStats::default(); NR_SIZE_CLASSES];
+        for (i, (stats, counters)) in classes.iter_mut().zip(&CLASSES).enumerate() {
+            *stats = SizeClassStats {
+                limit: (i + 1 < NR_SIZE_CLASSES).then(|| MIN_CLASS_SIZE << i),
+                allocs: counters.allocs.get() as usize,
+                frees: counters.frees.get() as usize,
+                live_bytes: counters.live_bytes.get(),
+            };
+        }
+        Self {
+            allocator,
+            classes,
+            heap_bytes,
+            used_bytes,
+            free_bytes,
+            largest_free,
+            failed_allocs: FAILED_ALLOCS.get() as usize,
+        }
+    }
+
+    /// The bytes requested by the live allocations.
+    pub fn requested_bytes(&self) -> usize {
+        self.classes.iter().map(|class| class.live_bytes).sum()
+    }
+
+    /// The percentage of the free bytes out of the largest free block.
+    pub fn external_fragmentation(&self) -> usize {
+        if self.free_bytes == 0 {
+            return 0;
+        }
+        100 - self.largest_free.min(self.free_bytes) * 100 / self.free_bytes
+    }
+
+    /// The percentage of the used bytes not requested.
+    ///
+    /// The requested bytes count the allocations of all the heaps, so it is
+    /// only an estimate when other arenas are used.
+    pub fn internal_fragmentation(&self) -> usize {
+        if self.used_bytes == 0 {
+            return 0;
+        }
+        self.used_bytes.saturating_sub(self.requested_bytes()) * 100 / self.used_bytes
+    }
+}
+
+impl fmt::Display for MallocStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Allocator: {}", self.allocator)?;
+        writeln!(
+            f,
+            "heap: {} bytes, used {} (requested {}), free {}, largest free block {}",
+            self.heap_bytes,
+            self.used_bytes,
+            self.requested_bytes(),
+            self.free_bytes,
+            self.largest_free
+        )?;
+        writeln!(
+            f,
+            "fragmentation: external {}%, internal {}%",
+            self.external_fragmentation(),
+            self.internal_fragmentation()
+        )?;
+        writeln!(f, "failed allocations: {}", self.failed_allocs)?;
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10} {:>10} {:>12}",
+            "size", "allocs", "frees", "live", "live bytes"
+        )?;
+        let mut lower = 0;
+        for class in self.classes.iter() {
+            if class.allocs > 0 {
+                match class.limit {
+                    Some(limit) => write!(f, "{:>10}", limit)?,
+                    None => write!(f, "{:>9}+", lower + 1)?,
+                }
+                writeln!(
+                    f,
+                    " {:>10} {:>10} {:>10} {:>12}",
+                    class.allocs,
+                    class.frees,
+                    class.live(),
+                    class.live_bytes
+                )?;
+            }
+            lower = class.limit.unwrap_or(lower);
+        }
+        Ok(())
+    }
+}
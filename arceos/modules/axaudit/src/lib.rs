@@ -0,0 +1,168 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) audit log.
+//!
+//! Security-relevant events (exec, mount, setrlimit, socket bind, and
+//! capability denials) are recorded by the modules that observe them into a
+//! dedicated in-memory ring buffer, separate from the kernel log. Operators
+//! can then [`export`] the records to reconstruct what a workload did.
+//!
+//! The ring holds the latest [`CAPACITY`] records. When it is full, the
+//! oldest records are overwritten and counted by [`dropped`].
+//!
+//! # Cargo Features
+//!
+//! - `multitask`: Record the ID of the current task in every record.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+
+/// Maximum number of records kept in the ring buffer.
+pub const CAPACITY: usize = 1024;
+
+/// A security-relevant event.
+#[derive(Debug, Clone)]
+pub enum AuditEvent {
+    /// A program was executed.
+    Exec {
+        /// Path of the executable.
+        path: String,
+    },
+    /// A filesystem was mounted.
+    Mount {
+        /// The mount point.
+        path: String,
+    },
+    /// A resource limit was changed.
+    SetRlimit {
+        /// The `RLIMIT_*` resource number.
+        resource: i32,
+        /// New soft limit.
+        cur: u64,
+        /// New hard limit.
+        max: u64,
+    },
+    /// A socket was bound to a local address.
+    SocketBind {
+        /// The local address.
+        addr: SocketAddr,
+    },
+    /// An operation was denied for lack of capabilities or permissions.
+    CapabilityDenied {
+        /// The operation that was denied.
+        op: &'static str,
+        /// The object of the operation, e.g. a path.
+        target: String,
+    },
+}
+
+/// An audit record: an [`AuditEvent`] with its context.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    /// Sequence number, strictly increasing from 0 since boot.
+    pub seq: u64,
+    /// Monotonic time when the event happened.
+    pub time: Duration,
+    /// ID of the task that caused the event, 0 if unknown.
+    pub task_id: u64,
+    /// The event.
+    pub event: AuditEvent,
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "seq={} time={}.{:06} task={} ",
+            self.seq,
+            self.time.as_secs(),
+            self.time.subsec_micros(),
+            self.task_id,
+        )?;
+        match &self.event {
+            AuditEvent::Exec { path } => write!(f, "type=EXEC path={:?}", path),
+            AuditEvent::Mount { path } => write!(f, "type=MOUNT path={:?}", path),
+            AuditEvent::SetRlimit { resource, cur, max } => write!(
+                f,
+                "type=SETRLIMIT resource={} cur={} max={}",
+                resource, cur, max
+            ),
+            AuditEvent::SocketBind { addr } => write!(f, "type=BIND addr={}", addr),
+            AuditEvent::CapabilityDenied { op, target } => {
+                write!(f, "type=CAP_DENIED op={} target={:?}", op, target)
+            }
+        }
+    }
+}
+
+struct AuditLog {
+    records: VecDeque<AuditRecord>,
+    next_seq: u64,
+    dropped: u64,
+}
+
+static AUDIT_LOG: SpinNoIrq<AuditLog> = SpinNoIrq::new(AuditLog {
+    records: VecDeque::new(),
+    next_seq: 0,
+    dropped: 0,
+});
+
+fn current_task_id() -> u64 {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.id().as_u64();
+    }
+    0
+}
+
+/// Records an event.
+pub fn audit(event: AuditEvent) {
+    let time = axhal::time::monotonic_time();
+    let task_id = current_task_id();
+    let mut log = AUDIT_LOG.lock();
+    if log.records.len() >= CAPACITY {
+        log.records.pop_front();
+        log.dropped += 1;
+    }
+    let seq = log.next_seq;
+    log.next_seq += 1;
+    let record = AuditRecord {
+        seq,
+        time,
+        task_id,
+        event,
+    };
+    debug!("audit: {}", record);
+    log.records.push_back(record);
+}
+
+/// Appends all records with a sequence number not less than `since` to
+/// `out`, oldest first.
+///
+/// Returns the sequence number to pass as `since` in the next call, to
+/// export only the newer records.
+pub fn export(since: u64, out: &mut Vec<AuditRecord>) -> u64 {
+    let log = AUDIT_LOG.lock();
+    out.extend(log.records.iter().filter(|r| r.seq >= since).cloned());
+    log.next_seq
+}
+
+/// Removes and returns all records in the ring buffer, oldest first.
+pub fn drain() -> Vec<AuditRecord> {
+    AUDIT_LOG.lock().records.drain(..).collect()
+}
+
+/// Returns the number of records overwritten because the ring was full.
+pub fn dropped() -> u64 {
+    AUDIT_LOG.lock().dropped
+}
@@ -0,0 +1,23 @@
+//! Kernel functions callable by programs, with the eBPF helper numbering.
+//!
+//! Arguments are passed in `r1` to `r5` and the result is returned in `r0`.
+//! Failing helpers return a negative Linux error number.
+
+/// `void *map_lookup_elem(map, const void *key)`, returns null if absent.
+pub const MAP_LOOKUP_ELEM: u32 = 1;
+/// `long map_update_elem(map, const void *key, const void *value, u64 flags)`
+pub const MAP_UPDATE_ELEM: u32 = 2;
+/// `long map_delete_elem(map, const void *key)`
+pub const MAP_DELETE_ELEM: u32 = 3;
+/// `u64 ktime_get_ns(void)`, the monotonic time in nanoseconds.
+pub const KTIME_GET_NS: u32 = 5;
+/// `u32 get_prandom_u32(void)`
+pub const GET_PRANDOM_U32: u32 = 7;
+
+/// Whether `id` is a supported helper.
+pub const fn is_known(id: u32) -> bool {
+    matches!(
+        id,
+        MAP_LOOKUP_ELEM | MAP_UPDATE_ELEM | MAP_DELETE_ELEM | KTIME_GET_NS | GET_PRANDOM_U32
+    )
+}
@@ -0,0 +1,141 @@
+//! Instruction encoding, a subset of the [eBPF instruction set].
+//!
+//! [eBPF instruction set]: https://www.kernel.org/doc/html/latest/bpf/standardization/instruction-set.html
+
+// Instruction classes.
+pub const BPF_LD: u8 = 0x00;
+pub const BPF_LDX: u8 = 0x01;
+pub const BPF_ST: u8 = 0x02;
+pub const BPF_STX: u8 = 0x03;
+pub const BPF_ALU: u8 = 0x04;
+pub const BPF_JMP: u8 = 0x05;
+pub const BPF_JMP32: u8 = 0x06;
+pub const BPF_ALU64: u8 = 0x07;
+
+// Size modifiers of load and store instructions.
+pub const BPF_W: u8 = 0x00;
+pub const BPF_H: u8 = 0x08;
+pub const BPF_B: u8 = 0x10;
+pub const BPF_DW: u8 = 0x18;
+
+// Mode modifiers of load and store instructions.
+pub const BPF_IMM: u8 = 0x00;
+pub const BPF_MEM: u8 = 0x60;
+
+// Source operand of ALU and jump instructions.
+pub const BPF_K: u8 = 0x00;
+pub const BPF_X: u8 = 0x08;
+
+// ALU operations.
+pub const BPF_ADD: u8 = 0x00;
+pub const BPF_SUB: u8 = 0x10;
+pub const BPF_MUL: u8 = 0x20;
+pub const BPF_DIV: u8 = 0x30;
+pub const BPF_OR: u8 = 0x40;
+pub const BPF_AND: u8 = 0x50;
+pub const BPF_LSH: u8 = 0x60;
+pub const BPF_RSH: u8 = 0x70;
+pub const BPF_NEG: u8 = 0x80;
+pub const BPF_MOD: u8 = 0x90;
+pub const BPF_XOR: u8 = 0xa0;
+pub const BPF_MOV: u8 = 0xb0;
+pub const BPF_ARSH: u8 = 0xc0;
+
+// Jump operations.
+pub const BPF_JA: u8 = 0x00;
+pub const BPF_JEQ: u8 = 0x10;
+pub const BPF_JGT: u8 = 0x20;
+pub const BPF_JGE: u8 = 0x30;
+pub const BPF_JSET: u8 = 0x40;
+pub const BPF_JNE: u8 = 0x50;
+pub const BPF_JSGT: u8 = 0x60;
+pub const BPF_JSGE: u8 = 0x70;
+pub const BPF_CALL: u8 = 0x80;
+pub const BPF_EXIT: u8 = 0x90;
+pub const BPF_JLT: u8 = 0xa0;
+pub const BPF_JLE: u8 = 0xb0;
+pub const BPF_JSLT: u8 = 0xc0;
+pub const BPF_JSLE: u8 = 0xd0;
+
+/// `src` of a 64-bit immediate load referring to a map of the program.
+pub const BPF_PSEUDO_MAP_FD: u8 = 1;
+
+/// The frame pointer register, read-only.
+pub const REG_FP: u8 = 10;
+/// Number of registers.
+pub const NUM_REGS: u8 = 11;
+
+/// A decoded instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Insn {
+    /// Operation code.
+    pub op: u8,
+    /// Destination register.
+    pub dst: u8,
+    /// Source register.
+    pub src: u8,
+    /// Signed offset.
+    pub off: i16,
+    /// Signed immediate.
+    pub imm: i32,
+}
+
+impl Insn {
+    /// Builds an instruction from its fields.
+    pub const fn new(op: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self {
+            op,
+            dst,
+            src,
+            off,
+            imm,
+        }
+    }
+
+    /// Decodes an instruction from its 8-byte little-endian encoding.
+    pub fn decode(raw: [u8; 8]) -> Self {
+        Self {
+            op: raw[0],
+            dst: raw[1] & 0xf,
+            src: raw[1] >> 4,
+            off: i16::from_le_bytes([raw[2], raw[3]]),
+            imm: i32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]),
+        }
+    }
+
+    /// Encodes the instruction to 8 little-endian bytes.
+    pub fn encode(&self) -> [u8; 8] {
+        let off = self.off.to_le_bytes();
+        let imm = self.imm.to_le_bytes();
+        [
+            self.op,
+            (self.src << 4) | (self.dst & 0xf),
+            off[0],
+            off[1],
+            imm[0],
+            imm[1],
+            imm[2],
+            imm[3],
+        ]
+    }
+
+    /// The instruction class.
+    pub const fn class(&self) -> u8 {
+        self.op & 0x07
+    }
+
+    /// Whether this is the first slot of a 64-bit immediate load.
+    pub const fn is_ld_imm64(&self) -> bool {
+        self.op == BPF_LD | BPF_IMM | BPF_DW
+    }
+}
+
+/// Size in bytes of a load or store of the given opcode.
+pub const fn mem_size(op: u8) -> usize {
+    match op & 0x18 {
+        BPF_B => 1,
+        BPF_H => 2,
+        BPF_W => 4,
+        _ => 8,
+    }
+}
@@ -0,0 +1,224 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) eBPF-like programmable
+//! filters.
+//!
+//! A [`BpfProgram`] is a sequence of instructions of (a subset of) the eBPF
+//! instruction set, checked by a [verifier](verifier::verify) when it is
+//! loaded and then run by an interpreter. Programs are attached to named
+//! hook points, which the kernel runs with a context buffer describing the
+//! event, e.g. a received packet or a syscall. Programs share data with the
+//! kernel and each other through [maps](map).
+//!
+//! Execution is bounded: programs can only jump forward, so they run at most
+//! [`MAX_INSNS`](verifier::MAX_INSNS) instructions, and every memory access
+//! is checked against the context, the stack and the map values.
+//!
+//! # Hook points
+//!
+//! - [`HOOK_NET_RX`]: run on every received packet, with the raw frame as
+//!   context. The packet is dropped if a program returns 0.
+//! - [`HOOK_SECCOMP`]: run before every syscall of user tasks, with a
+//!   [`SeccompData`] as context. The return value is a `SECCOMP_RET_*`
+//!   action.
+//! - [`HOOK_SYS_ENTER`]: a tracepoint run before every syscall of user
+//!   tasks, with the same context. Its return value is ignored.
+//! - [`HOOK_SCHED_ENQUEUE`], [`HOOK_SCHED_DEQUEUE`] and [`HOOK_SCHED_PICK`]:
+//!   tracepoints run by the scheduler when a task becomes ready, stops being
+//!   ready or running, and is picked to run, with a [`SchedData`] as
+//!   context. Their return values are ignored.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod vm;
+
+pub mod helpers;
+pub mod insn;
+pub mod map;
+pub mod verifier;
+
+#[cfg(test)]
+mod tests;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+
+pub use self::insn::Insn;
+pub use self::map::{ArrayMap, BpfMap, HashMap};
+pub use self::vm::STACK_SIZE;
+
+/// Hook point run on received packets.
+pub const HOOK_NET_RX: &str = "net/rx";
+/// Hook point filtering syscalls of user tasks.
+pub const HOOK_SECCOMP: &str = "seccomp";
+/// Tracepoint run on syscall entry of user tasks.
+pub const HOOK_SYS_ENTER: &str = "tracepoint/sys_enter";
+/// Tracepoint run when a task is put in a run queue.
+pub const HOOK_SCHED_ENQUEUE: &str = "tracepoint/sched_enqueue";
+/// Tracepoint run when the current task blocks or exits.
+pub const HOOK_SCHED_DEQUEUE: &str = "tracepoint/sched_dequeue";
+/// Tracepoint run when a task is picked to run next.
+pub const HOOK_SCHED_PICK: &str = "tracepoint/sched_pick";
+
+/// Context of the [`HOOK_SECCOMP`] and [`HOOK_SYS_ENTER`] hooks, laid out as
+/// Linux's `struct seccomp_data`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SeccompData {
+    /// Syscall number.
+    pub nr: i32,
+    /// `AUDIT_ARCH_*` value of the calling convention.
+    pub arch: u32,
+    /// User instruction pointer.
+    pub instruction_pointer: u64,
+    /// Syscall arguments.
+    pub args: [u64; 6],
+}
+
+impl SeccompData {
+    /// Views the context as bytes, to be passed to [`run_hook`].
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `SeccompData` is plain old data without padding.
+        unsafe {
+            core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, core::mem::size_of::<Self>())
+        }
+    }
+}
+
+/// Context of the scheduler tracepoints.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchedData {
+    /// CPU running the scheduler.
+    pub cpu: u32,
+    /// CPU the task last ran on, `u32::MAX` if it never ran.
+    pub task_cpu: u32,
+    /// ID of the task.
+    pub task_id: u64,
+    /// Number of ready tasks accounted to `cpu`, after the event.
+    pub nr_ready: u64,
+    /// Monotonic time of the event, in nanoseconds.
+    pub time_ns: u64,
+    /// ID of the distributed trace the task works for, high half first, 0
+    /// if none.
+    pub trace_id: [u64; 2],
+    /// ID of the span of the trace the task works for.
+    pub span_id: u64,
+}
+
+impl SchedData {
+    /// Views the context as bytes, to be passed to [`run_hook`].
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `SchedData` is plain old data without padding.
+        unsafe {
+            core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, core::mem::size_of::<Self>())
+        }
+    }
+}
+
+/// A verified program.
+pub struct BpfProgram {
+    name: String,
+    insns: Vec<Insn>,
+    maps: Vec<Arc<dyn BpfMap>>,
+}
+
+impl BpfProgram {
+    /// Verifies and loads a program.
+    ///
+    /// The program refers to `maps` by their index, through 64-bit immediate
+    /// loads with `src` set to [`BPF_PSEUDO_MAP_FD`](insn::BPF_PSEUDO_MAP_FD).
+    pub fn new(name: &str, insns: Vec<Insn>, maps: Vec<Arc<dyn BpfMap>>) -> AxResult<Self> {
+        verifier::verify(&insns, maps.len()).inspect_err(|e| {
+            warn!("BPF program {:?} rejected: {:?}", name, e);
+        })?;
+        Ok(Self {
+            name: String::from(name),
+            insns,
+            maps,
+        })
+    }
+
+    /// Verifies and loads a program from its binary encoding.
+    pub fn from_bytes(name: &str, code: &[u8], maps: Vec<Arc<dyn BpfMap>>) -> AxResult<Self> {
+        if code.len() % 8 != 0 {
+            return ax_err!(InvalidInput, "BPF code size not a multiple of 8");
+        }
+        let insns = code
+            .chunks_exact(8)
+            .map(|raw| Insn::decode(raw.try_into().unwrap()))
+            .collect();
+        Self::new(name, insns, maps)
+    }
+
+    /// The name of the program.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runs the program on `ctx`.
+    ///
+    /// Returns an error if the program accessed memory out of bounds or
+    /// passed invalid arguments to a helper.
+    pub fn run(&self, ctx: &mut [u8]) -> AxResult<u64> {
+        vm::execute(self, ctx)
+    }
+}
+
+static HOOKS: SpinNoIrq<BTreeMap<String, Vec<Arc<BpfProgram>>>> = SpinNoIrq::new(BTreeMap::new());
+static NUM_ATTACHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Attaches a program to the hook point `point`.
+pub fn attach(point: &str, prog: Arc<BpfProgram>) {
+    info!("BPF program {:?} attached to {:?}", prog.name(), point);
+    HOOKS
+        .lock()
+        .entry(String::from(point))
+        .or_default()
+        .push(prog);
+    NUM_ATTACHED.fetch_add(1, Ordering::Release);
+}
+
+/// Detaches the program named `name` from the hook point `point`.
+pub fn detach(point: &str, name: &str) -> AxResult {
+    let mut hooks = HOOKS.lock();
+    let Some(progs) = hooks.get_mut(point) else {
+        return ax_err!(NotFound);
+    };
+    let Some(idx) = progs.iter().position(|p| p.name() == name) else {
+        return ax_err!(NotFound);
+    };
+    progs.remove(idx);
+    NUM_ATTACHED.fetch_sub(1, Ordering::Release);
+    info!("BPF program {:?} detached from {:?}", name, point);
+    Ok(())
+}
+
+/// Runs all programs attached to `point` on `ctx`.
+///
+/// Returns the smallest result, so the most restrictive program wins, or
+/// `None` if no program is attached. A program failing at runtime counts as
+/// returning 0.
+pub fn run_hook(point: &str, ctx: &mut [u8]) -> Option<u64> {
+    if NUM_ATTACHED.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    let progs = HOOKS.lock().get(point)?.clone();
+    progs
+        .iter()
+        .map(|prog| {
+            prog.run(ctx).unwrap_or_else(|e| {
+                warn!("BPF program {:?} failed: {:?}", prog.name(), e);
+                0
+            })
+        })
+        .min()
+}
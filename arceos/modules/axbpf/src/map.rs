@@ -0,0 +1,219 @@
+//! Map data structures shared between programs and the kernel.
+//!
+//! All values of a map live in one buffer allocated at creation, so the
+//! value pointers handed to programs stay valid as long as the map lives,
+//! even if the entry is deleted meanwhile.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use alloc::{boxed::Box, vec};
+use core::cell::UnsafeCell;
+
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+
+/// A map usable by programs through the `map_*` helpers.
+pub trait BpfMap: Send + Sync {
+    /// Size of keys in bytes.
+    fn key_size(&self) -> usize;
+
+    /// Size of values in bytes.
+    fn value_size(&self) -> usize;
+
+    /// Returns a pointer to the value of `key`.
+    fn lookup(&self, key: &[u8]) -> Option<*mut u8>;
+
+    /// Inserts or overwrites the value of `key`.
+    fn update(&self, key: &[u8], value: &[u8]) -> AxResult;
+
+    /// Removes `key` from the map.
+    fn delete(&self, key: &[u8]) -> AxResult;
+
+    /// The buffer holding all values, as `(start, len)`.
+    fn value_region(&self) -> (usize, usize);
+
+    /// Returns a copy of the value of `key`.
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let ptr = self.lookup(key)?;
+        // SAFETY: `lookup` returns a pointer to `value_size` bytes.
+        Some(unsafe { core::slice::from_raw_parts(ptr, self.value_size()) }.to_vec())
+    }
+}
+
+struct Values {
+    buf: UnsafeCell<Box<[u8]>>,
+    value_size: usize,
+}
+
+// Values are plain bytes that programs may race on, like in eBPF.
+unsafe impl Sync for Values {}
+unsafe impl Send for Values {}
+
+impl Values {
+    fn new(max_entries: usize, value_size: usize) -> Self {
+        Self {
+            buf: UnsafeCell::new(vec![0; max_entries * value_size].into_boxed_slice()),
+            value_size,
+        }
+    }
+
+    fn slot(&self, idx: usize) -> *mut u8 {
+        // SAFETY: the buffer is never reallocated, `idx` is in bounds.
+        unsafe { (*self.buf.get()).as_mut_ptr().add(idx * self.value_size) }
+    }
+
+    fn write(&self, idx: usize, value: &[u8]) {
+        // SAFETY: `value` has exactly `value_size` bytes, checked by callers.
+        unsafe { core::ptr::copy_nonoverlapping(value.as_ptr(), self.slot(idx), self.value_size) };
+    }
+
+    fn region(&self) -> (usize, usize) {
+        // SAFETY: only the address and length are read.
+        let buf = unsafe { &*self.buf.get() };
+        (buf.as_ptr() as usize, buf.len())
+    }
+}
+
+/// An array indexed by `u32` keys, with all entries always present.
+pub struct ArrayMap {
+    values: Values,
+    max_entries: usize,
+}
+
+impl ArrayMap {
+    /// Creates a zero-filled array of `max_entries` values.
+    pub fn new(max_entries: usize, value_size: usize) -> Self {
+        Self {
+            values: Values::new(max_entries, value_size),
+            max_entries,
+        }
+    }
+
+    fn index(&self, key: &[u8]) -> Option<usize> {
+        let idx = u32::from_ne_bytes(key.try_into().ok()?) as usize;
+        (idx < self.max_entries).then_some(idx)
+    }
+}
+
+impl BpfMap for ArrayMap {
+    fn key_size(&self) -> usize {
+        4
+    }
+
+    fn value_size(&self) -> usize {
+        self.values.value_size
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<*mut u8> {
+        self.index(key).map(|idx| self.values.slot(idx))
+    }
+
+    fn update(&self, key: &[u8], value: &[u8]) -> AxResult {
+        match self.index(key) {
+            Some(idx) if value.len() == self.value_size() => {
+                self.values.write(idx, value);
+                Ok(())
+            }
+            _ => ax_err!(InvalidInput),
+        }
+    }
+
+    fn delete(&self, _key: &[u8]) -> AxResult {
+        ax_err!(InvalidInput, "cannot delete array map entries")
+    }
+
+    fn value_region(&self) -> (usize, usize) {
+        self.values.region()
+    }
+}
+
+struct HashIndex {
+    slots: BTreeMap<Vec<u8>, usize>,
+    free: Vec<usize>,
+}
+
+/// A map from fixed-size keys to fixed-size values, with a maximum number
+/// of entries preallocated.
+pub struct HashMap {
+    values: Values,
+    key_size: usize,
+    index: SpinNoIrq<HashIndex>,
+}
+
+impl HashMap {
+    /// Creates an empty map.
+    pub fn new(max_entries: usize, key_size: usize, value_size: usize) -> Self {
+        Self {
+            values: Values::new(max_entries, value_size),
+            key_size,
+            index: SpinNoIrq::new(HashIndex {
+                slots: BTreeMap::new(),
+                free: (0..max_entries).rev().collect(),
+            }),
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.index.lock().slots.len()
+    }
+
+    /// Returns `true` if there is no entry.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns copies of all keys.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        self.index.lock().slots.keys().cloned().collect()
+    }
+}
+
+impl BpfMap for HashMap {
+    fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    fn value_size(&self) -> usize {
+        self.values.value_size
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<*mut u8> {
+        let idx = *self.index.lock().slots.get(key)?;
+        Some(self.values.slot(idx))
+    }
+
+    fn update(&self, key: &[u8], value: &[u8]) -> AxResult {
+        if key.len() != self.key_size || value.len() != self.value_size() {
+            return ax_err!(InvalidInput);
+        }
+        let mut index = self.index.lock();
+        let idx = match index.slots.get(key) {
+            Some(&idx) => idx,
+            None => {
+                let Some(idx) = index.free.pop() else {
+                    return ax_err!(NoMemory, "BPF hash map full");
+                };
+                index.slots.insert(key.to_vec(), idx);
+                idx
+            }
+        };
+        self.values.write(idx, value);
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> AxResult {
+        let mut index = self.index.lock();
+        match index.slots.remove(key) {
+            Some(idx) => {
+                index.free.push(idx);
+                Ok(())
+            }
+            None => ax_err!(NotFound),
+        }
+    }
+
+    fn value_region(&self) -> (usize, usize) {
+        self.values.region()
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::sync::Arc;
+use alloc::vec;
+
+use crate::helpers::*;
+use crate::insn::*;
+use crate::*;
+
+const fn exit() -> Insn {
+    Insn::new(BPF_JMP | BPF_EXIT, 0, 0, 0, 0)
+}
+
+const fn mov64_imm(dst: u8, imm: i32) -> Insn {
+    Insn::new(BPF_ALU64 | BPF_MOV | BPF_K, dst, 0, 0, imm)
+}
+
+#[test]
+fn test_verifier() {
+    assert!(BpfProgram::new("empty", vec![], vec![]).is_err());
+    assert!(BpfProgram::new("no_exit", vec![mov64_imm(0, 1)], vec![]).is_err());
+    // Backward jump.
+    let insns = vec![
+        mov64_imm(0, 1),
+        Insn::new(BPF_JMP | BPF_JA, 0, 0, -2, 0),
+        exit(),
+    ];
+    assert!(BpfProgram::new("loop", insns, vec![]).is_err());
+    // Writing the frame pointer.
+    let insns = vec![mov64_imm(REG_FP, 0), exit()];
+    assert!(BpfProgram::new("fp", insns, vec![]).is_err());
+    // Unknown helper.
+    let insns = vec![Insn::new(BPF_JMP | BPF_CALL, 0, 0, 0, 1000), exit()];
+    assert!(BpfProgram::new("call", insns, vec![]).is_err());
+    // Unknown map.
+    let insns = vec![
+        Insn::new(BPF_LD | BPF_IMM | BPF_DW, 1, BPF_PSEUDO_MAP_FD, 0, 0),
+        Insn::default(),
+        exit(),
+    ];
+    assert!(BpfProgram::new("map", insns, vec![]).is_err());
+}
+
+#[test]
+fn test_ctx_filter() {
+    // r0 = ctx[0] == 0x45 ? 1 : 0
+    let insns = vec![
+        Insn::new(BPF_LDX | BPF_MEM | BPF_B, 2, 1, 0, 0),
+        mov64_imm(0, 0),
+        Insn::new(BPF_JMP | BPF_JNE | BPF_K, 2, 0, 1, 0x45),
+        mov64_imm(0, 1),
+        exit(),
+    ];
+    let prog = BpfProgram::new("filter", insns, vec![]).unwrap();
+    assert_eq!(prog.run(&mut [0x45, 0]).unwrap(), 1);
+    assert_eq!(prog.run(&mut [0x60, 0]).unwrap(), 0);
+    // Out of bounds.
+    let insns = vec![Insn::new(BPF_LDX | BPF_MEM | BPF_W, 0, 1, 8, 0), exit()];
+    let prog = BpfProgram::new("oob", insns, vec![]).unwrap();
+    assert!(prog.run(&mut [0; 8]).is_err());
+}
+
+#[test]
+fn test_map_counter() {
+    // key = 0; v = lookup(map, &key); *v += 1
+    let insns = vec![
+        Insn::new(BPF_ST | BPF_MEM | BPF_W, REG_FP, 0, -4, 0),
+        Insn::new(BPF_LD | BPF_IMM | BPF_DW, 1, BPF_PSEUDO_MAP_FD, 0, 0),
+        Insn::default(),
+        Insn::new(BPF_ALU64 | BPF_MOV | BPF_X, 2, REG_FP, 0, 0),
+        Insn::new(BPF_ALU64 | BPF_ADD | BPF_K, 2, 0, 0, -4),
+        Insn::new(BPF_JMP | BPF_CALL, 0, 0, 0, MAP_LOOKUP_ELEM as i32),
+        Insn::new(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 3, 0),
+        Insn::new(BPF_LDX | BPF_MEM | BPF_DW, 1, 0, 0, 0),
+        Insn::new(BPF_ALU64 | BPF_ADD | BPF_K, 1, 0, 0, 1),
+        Insn::new(BPF_STX | BPF_MEM | BPF_DW, 0, 1, 0, 0),
+        mov64_imm(0, 0),
+        exit(),
+    ];
+    let map = Arc::new(ArrayMap::new(4, 8));
+    let prog = BpfProgram::new("counter", insns, vec![map.clone()]).unwrap();
+    for _ in 0..3 {
+        assert_eq!(prog.run(&mut []).unwrap(), 0);
+    }
+    assert_eq!(map.get(&0u32.to_ne_bytes()).unwrap(), 3u64.to_ne_bytes());
+
+    let hash = HashMap::new(1, 4, 4);
+    hash.update(&[1; 4], &[2; 4]).unwrap();
+    assert!(hash.update(&[2; 4], &[2; 4]).is_err());
+    hash.delete(&[1; 4]).unwrap();
+    hash.update(&[2; 4], &[3; 4]).unwrap();
+    assert_eq!(hash.get(&[2; 4]).unwrap(), [3; 4]);
+}
@@ -0,0 +1,115 @@
+//! Static checks done when a program is loaded.
+//!
+//! Only forward jumps are accepted, so every program terminates after at
+//! most [`MAX_INSNS`] instructions. Memory accesses cannot be checked
+//! statically without tracking pointer types, so they are bounds-checked by
+//! the interpreter instead.
+
+use axerrno::{ax_err, AxResult};
+
+use crate::helpers;
+use crate::insn::*;
+
+/// Maximum number of instructions of a program.
+pub const MAX_INSNS: usize = 4096;
+
+fn check_alu(insn: &Insn) -> bool {
+    let op = insn.op & 0xf0;
+    match op {
+        BPF_NEG => insn.op & BPF_X == 0,
+        BPF_ADD | BPF_SUB | BPF_MUL | BPF_DIV | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH | BPF_MOD
+        | BPF_XOR | BPF_MOV | BPF_ARSH => true,
+        _ => false,
+    }
+}
+
+fn check_jmp(insn: &Insn) -> bool {
+    matches!(
+        insn.op & 0xf0,
+        BPF_JA
+            | BPF_JEQ
+            | BPF_JGT
+            | BPF_JGE
+            | BPF_JSET
+            | BPF_JNE
+            | BPF_JSGT
+            | BPF_JSGE
+            | BPF_JLT
+            | BPF_JLE
+            | BPF_JSLT
+            | BPF_JSLE
+            | BPF_CALL
+            | BPF_EXIT
+    )
+}
+
+/// Checks that `insns` is a valid program referring to `num_maps` maps.
+pub fn verify(insns: &[Insn], num_maps: usize) -> AxResult {
+    if insns.is_empty() || insns.len() > MAX_INSNS {
+        return ax_err!(InvalidInput, "BPF program too large or empty");
+    }
+    // Second slots of 64-bit immediate loads, which cannot be jumped to.
+    let mut is_imm_hi = alloc::vec![false; insns.len()];
+    let mut pc = 0;
+    while pc < insns.len() {
+        let insn = &insns[pc];
+        if insn.dst >= NUM_REGS || insn.src >= NUM_REGS && !insn.is_ld_imm64() {
+            return ax_err!(InvalidInput, "BPF invalid register");
+        }
+        let writes_dst = matches!(insn.class(), BPF_LD | BPF_LDX | BPF_ALU | BPF_ALU64);
+        if writes_dst && insn.dst == REG_FP {
+            return ax_err!(InvalidInput, "BPF frame pointer is read-only");
+        }
+        let ok = match insn.class() {
+            BPF_LD if insn.is_ld_imm64() => {
+                if pc + 1 >= insns.len() || insns[pc + 1].op != 0 {
+                    return ax_err!(InvalidInput, "BPF incomplete 64-bit immediate load");
+                }
+                match insn.src {
+                    0 => {}
+                    BPF_PSEUDO_MAP_FD if (insn.imm as u32 as usize) < num_maps => {}
+                    _ => return ax_err!(InvalidInput, "BPF invalid map reference"),
+                }
+                is_imm_hi[pc + 1] = true;
+                pc += 2;
+                continue;
+            }
+            BPF_LDX | BPF_STX => insn.op & 0xe0 == BPF_MEM,
+            BPF_ST => insn.op & 0xe0 == BPF_MEM,
+            BPF_ALU | BPF_ALU64 => check_alu(insn),
+            BPF_JMP => check_jmp(insn),
+            BPF_JMP32 => check_jmp(insn) && !matches!(insn.op & 0xf0, BPF_JA | BPF_CALL | BPF_EXIT),
+            _ => false,
+        };
+        if !ok {
+            return ax_err!(InvalidInput, "BPF unknown opcode");
+        }
+        if insn.class() == BPF_JMP
+            && insn.op & 0xf0 == BPF_CALL
+            && !helpers::is_known(insn.imm as u32)
+        {
+            return ax_err!(InvalidInput, "BPF unknown helper");
+        }
+        pc += 1;
+    }
+
+    for (pc, insn) in insns.iter().enumerate() {
+        if is_imm_hi[pc] || !matches!(insn.class(), BPF_JMP | BPF_JMP32) {
+            continue;
+        }
+        if matches!(insn.op & 0xf0, BPF_CALL | BPF_EXIT) {
+            continue;
+        }
+        // Backward jumps and jumps to self could loop forever.
+        let target = pc as isize + 1 + insn.off as isize;
+        if insn.off < 0 || target as usize >= insns.len() || is_imm_hi[target as usize] {
+            return ax_err!(InvalidInput, "BPF invalid jump");
+        }
+    }
+
+    let last = insns.last().unwrap();
+    if is_imm_hi[insns.len() - 1] || last.op != BPF_JMP | BPF_EXIT {
+        return ax_err!(InvalidInput, "BPF program does not end with exit");
+    }
+    Ok(())
+}
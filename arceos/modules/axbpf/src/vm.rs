@@ -0,0 +1,253 @@
+//! The interpreter.
+
+use axerrno::{ax_err, AxResult, LinuxError};
+
+use crate::helpers::*;
+use crate::insn::*;
+use crate::BpfProgram;
+
+/// Size of the stack of a program, addressed downwards from `r10`.
+pub const STACK_SIZE: usize = 512;
+
+/// Tag of the values loaded by map references, followed by the map index.
+const MAP_REF_TAG: u64 = 0xbbf0_0000_0000_0000;
+
+/// The memory a program is allowed to access.
+struct Memory<'a> {
+    ctx: (usize, usize),
+    stack: (usize, usize),
+    prog: &'a BpfProgram,
+}
+
+impl Memory<'_> {
+    /// Checks that `[addr, addr + size)` lies in the context, the stack, or
+    /// the values of a map.
+    fn check(&self, addr: u64, size: usize) -> AxResult<usize> {
+        let addr = addr as usize;
+        let Some(end) = addr.checked_add(size) else {
+            return ax_err!(BadAddress);
+        };
+        let within = |(start, len): (usize, usize)| start <= addr && end <= start + len;
+        if within(self.ctx)
+            || within(self.stack)
+            || self.prog.maps.iter().any(|m| within(m.value_region()))
+        {
+            Ok(addr)
+        } else {
+            warn!("BPF out of bounds access: {:#x}..{:#x}", addr, end);
+            ax_err!(BadAddress)
+        }
+    }
+
+    fn slice(&self, addr: u64, size: usize) -> AxResult<&[u8]> {
+        let addr = self.check(addr, size)?;
+        // SAFETY: the range was checked above.
+        Ok(unsafe { core::slice::from_raw_parts(addr as *const u8, size) })
+    }
+
+    fn map(&self, reg: u64) -> AxResult<&dyn crate::BpfMap> {
+        let idx = reg ^ MAP_REF_TAG;
+        match self.prog.maps.get(idx as usize) {
+            Some(map) if reg & MAP_REF_TAG == MAP_REF_TAG => Ok(map.as_ref()),
+            _ => ax_err!(InvalidInput, "BPF invalid map argument"),
+        }
+    }
+}
+
+fn load(addr: usize, size: usize) -> u64 {
+    // SAFETY: the range was checked by `Memory::check`.
+    unsafe {
+        match size {
+            1 => (addr as *const u8).read() as u64,
+            2 => (addr as *const u16).read_unaligned() as u64,
+            4 => (addr as *const u32).read_unaligned() as u64,
+            _ => (addr as *const u64).read_unaligned(),
+        }
+    }
+}
+
+fn store(addr: usize, size: usize, val: u64) {
+    // SAFETY: the range was checked by `Memory::check`.
+    unsafe {
+        match size {
+            1 => (addr as *mut u8).write(val as u8),
+            2 => (addr as *mut u16).write_unaligned(val as u16),
+            4 => (addr as *mut u32).write_unaligned(val as u32),
+            _ => (addr as *mut u64).write_unaligned(val),
+        }
+    }
+}
+
+fn alu64(op: u8, dst: u64, src: u64) -> u64 {
+    match op & 0xf0 {
+        BPF_ADD => dst.wrapping_add(src),
+        BPF_SUB => dst.wrapping_sub(src),
+        BPF_MUL => dst.wrapping_mul(src),
+        BPF_DIV => dst.checked_div(src).unwrap_or(0),
+        BPF_OR => dst | src,
+        BPF_AND => dst & src,
+        BPF_LSH => dst.wrapping_shl(src as u32),
+        BPF_RSH => dst.wrapping_shr(src as u32),
+        BPF_NEG => (dst as i64).wrapping_neg() as u64,
+        BPF_MOD => dst.checked_rem(src).unwrap_or(dst),
+        BPF_XOR => dst ^ src,
+        BPF_MOV => src,
+        _ => (dst as i64).wrapping_shr(src as u32) as u64, // BPF_ARSH
+    }
+}
+
+fn alu32(op: u8, dst: u32, src: u32) -> u32 {
+    match op & 0xf0 {
+        BPF_ADD => dst.wrapping_add(src),
+        BPF_SUB => dst.wrapping_sub(src),
+        BPF_MUL => dst.wrapping_mul(src),
+        BPF_DIV => dst.checked_div(src).unwrap_or(0),
+        BPF_OR => dst | src,
+        BPF_AND => dst & src,
+        BPF_LSH => dst.wrapping_shl(src),
+        BPF_RSH => dst.wrapping_shr(src),
+        BPF_NEG => (dst as i32).wrapping_neg() as u32,
+        BPF_MOD => dst.checked_rem(src).unwrap_or(dst),
+        BPF_XOR => dst ^ src,
+        BPF_MOV => src,
+        _ => (dst as i32).wrapping_shr(src) as u32, // BPF_ARSH
+    }
+}
+
+fn cond(op: u8, a: u64, b: u64, wide: bool) -> bool {
+    let (a, b) = if wide {
+        (a, b)
+    } else {
+        (a as u32 as u64, b as u32 as u64)
+    };
+    let (sa, sb) = if wide {
+        (a as i64, b as i64)
+    } else {
+        (a as u32 as i32 as i64, b as u32 as i32 as i64)
+    };
+    match op & 0xf0 {
+        BPF_JA => true,
+        BPF_JEQ => a == b,
+        BPF_JGT => a > b,
+        BPF_JGE => a >= b,
+        BPF_JSET => a & b != 0,
+        BPF_JNE => a != b,
+        BPF_JSGT => sa > sb,
+        BPF_JSGE => sa >= sb,
+        BPF_JLT => a < b,
+        BPF_JLE => a <= b,
+        BPF_JSLT => sa < sb,
+        _ => sa <= sb, // BPF_JSLE
+    }
+}
+
+fn errno(res: AxResult) -> u64 {
+    match res {
+        Ok(()) => 0,
+        Err(e) => (-(LinuxError::from(e).code() as i64)) as u64,
+    }
+}
+
+fn call(id: u32, regs: &[u64; NUM_REGS as usize], mem: &Memory) -> AxResult<u64> {
+    Ok(match id {
+        MAP_LOOKUP_ELEM => {
+            let map = mem.map(regs[1])?;
+            let key = mem.slice(regs[2], map.key_size())?;
+            map.lookup(key).map_or(0, |ptr| ptr as u64)
+        }
+        MAP_UPDATE_ELEM => {
+            let map = mem.map(regs[1])?;
+            let key = mem.slice(regs[2], map.key_size())?;
+            let value = mem.slice(regs[3], map.value_size())?;
+            errno(map.update(key, value))
+        }
+        MAP_DELETE_ELEM => {
+            let map = mem.map(regs[1])?;
+            let key = mem.slice(regs[2], map.key_size())?;
+            errno(map.delete(key))
+        }
+        KTIME_GET_NS => axhal::time::monotonic_time_nanos(),
+        GET_PRANDOM_U32 => axhal::misc::random() as u32 as u64,
+        _ => unreachable!(), // rejected by the verifier
+    })
+}
+
+/// Runs a verified program on `ctx`, which is passed in `r1`.
+pub(crate) fn execute(prog: &BpfProgram, ctx: &mut [u8]) -> AxResult<u64> {
+    let mut stack = [0u8; STACK_SIZE];
+    let mut regs = [0u64; NUM_REGS as usize];
+    regs[1] = ctx.as_mut_ptr() as u64;
+    regs[REG_FP as usize] = stack.as_mut_ptr() as u64 + STACK_SIZE as u64;
+    let mem = Memory {
+        ctx: (ctx.as_ptr() as usize, ctx.len()),
+        stack: (stack.as_ptr() as usize, STACK_SIZE),
+        prog,
+    };
+
+    // The verifier ensures `pc` stays in bounds and only moves forward.
+    let insns = &prog.insns;
+    let mut pc = 0;
+    loop {
+        let insn = insns[pc];
+        let (dst, src) = (insn.dst as usize, insn.src as usize);
+        let off = insn.off as i64 as u64;
+        pc += 1;
+        match insn.class() {
+            BPF_LD => {
+                let lo = insn.imm as u32 as u64;
+                regs[dst] = if insn.src == BPF_PSEUDO_MAP_FD {
+                    MAP_REF_TAG | lo
+                } else {
+                    ((insns[pc].imm as u32 as u64) << 32) | lo
+                };
+                pc += 1;
+            }
+            BPF_LDX => {
+                let size = mem_size(insn.op);
+                let addr = mem.check(regs[src].wrapping_add(off), size)?;
+                regs[dst] = load(addr, size);
+            }
+            BPF_ST | BPF_STX => {
+                let size = mem_size(insn.op);
+                let addr = mem.check(regs[dst].wrapping_add(off), size)?;
+                let val = if insn.class() == BPF_ST {
+                    insn.imm as i64 as u64
+                } else {
+                    regs[src]
+                };
+                store(addr, size, val);
+            }
+            BPF_ALU64 => {
+                let val = if insn.op & BPF_X != 0 {
+                    regs[src]
+                } else {
+                    insn.imm as i64 as u64
+                };
+                regs[dst] = alu64(insn.op, regs[dst], val);
+            }
+            BPF_ALU => {
+                let val = if insn.op & BPF_X != 0 {
+                    regs[src] as u32
+                } else {
+                    insn.imm as u32
+                };
+                regs[dst] = alu32(insn.op, regs[dst] as u32, val) as u64;
+            }
+            _ => match insn.op & 0xf0 {
+                // BPF_JMP or BPF_JMP32
+                BPF_EXIT => return Ok(regs[0]),
+                BPF_CALL => regs[0] = call(insn.imm as u32, &regs, &mem)?,
+                _ => {
+                    let val = if insn.op & BPF_X != 0 {
+                        regs[src]
+                    } else {
+                        insn.imm as i64 as u64
+                    };
+                    if cond(insn.op, regs[dst], val, insn.class() == BPF_JMP) {
+                        pc = pc.wrapping_add(off as usize);
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) shell commands registered
+//! by any crate.
+//!
+//! A driver or an application adds a command to the shell, e.g. for its
+//! diagnostics, with [`register_cmd!`], without the shell depending on it:
+//!
+//! ```ignore
+//! fn do_ifconfig(args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+//!     writeln!(out, "eth0: ...")
+//! }
+//!
+//! axcmd::register_cmd!("ifconfig", do_ifconfig, "show the network interfaces");
+//! ```
+//!
+//! The commands of all the crates linked in the kernel are collected in
+//! [`SHELL_CMDS`] at link time. The shell looks them up by name with
+//! [`find_cmd`] after its own ones, and lists them in its help.
+
+#![no_std]
+
+use core::fmt;
+
+#[doc(hidden)]
+pub use linkme;
+
+/// The function of a command, given the arguments after its name and the
+/// output to write to.
+pub type CmdFn = fn(args: &str, out: &mut dyn fmt::Write) -> fmt::Result;
+
+/// A command of the shell, see [`register_cmd!`].
+pub struct ShellCmd {
+    /// The name typed to run it.
+    pub name: &'static str,
+    /// The one-line help.
+    pub help: &'static str,
+    /// The function run.
+    pub func: CmdFn,
+}
+
+/// All the commands registered by the crates.
+#[linkme::distributed_slice]
+pub static SHELL_CMDS: [ShellCmd];
+
+/// Registers the command `name` running `func`, with an optional one-line
+/// help.
+#[macro_export]
+macro_rules! register_cmd {
+    ($name:literal, $func:path) => {
+        $crate::register_cmd!($name, $func, "");
+    };
+    ($name:literal, $func:path, $help:literal) => {
+        const _: () = {
+            #[$crate::linkme::distributed_slice($crate::SHELL_CMDS)]
+            #[linkme(crate = $crate::linkme)]
+            static CMD: $crate::ShellCmd = $crate::ShellCmd {
+                name: $name,
+                help: $help,
+                func: $func,
+            };
+        };
+    };
+}
+
+/// Returns the registered command `name`.
+///
+/// If several crates register the same name, the first one linked is
+/// returned.
+pub fn find_cmd(name: &str) -> Option<&'static ShellCmd> {
+    SHELL_CMDS.iter().find(|cmd| cmd.name == name)
+}
+
+/// Runs the registered command `name` with `args`, returns `None` if there
+/// is no such command.
+pub fn run_cmd(name: &str, args: &str, out: &mut dyn fmt::Write) -> Option<fmt::Result> {
+    find_cmd(name).map(|cmd| (cmd.func)(args, out))
+}
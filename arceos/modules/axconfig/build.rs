@@ -2,9 +2,20 @@ use std::io::{Result, Write};
 use std::path::{Path, PathBuf};
 use toml_edit::{Decor, DocumentMut, Item, Table, Value};
 
-fn resolve_config_path(platform: Option<&str>) -> Result<PathBuf> {
+/// Profile keys consumed by the build script, not turned into constants.
+const PROFILE_KEYS: &[&str] = &["base", "overlays"];
+
+/// Bound on the chain of `base` profiles, against cycles.
+const MAX_BASE_DEPTH: usize = 8;
+
+fn root_dir() -> PathBuf {
     let mut root_dir = PathBuf::from(std::env!("CARGO_MANIFEST_DIR"));
     root_dir.extend(["..", ".."]);
+    root_dir
+}
+
+fn resolve_config_path(platform: Option<&str>) -> Result<PathBuf> {
+    let root_dir = root_dir();
     let config_dir = root_dir.join("platforms");
 
     let builtin_platforms = std::fs::read_dir(&config_dir)?
@@ -62,7 +73,89 @@ fn load_config_toml(config_path: &Path) -> Result<Table> {
     Ok(toml)
 }
 
-fn gen_config_rs(config_path: &Path) -> Result<Vec<u8>> {
+/// Resolves an overlay: a name of `platforms/overlays/<name>.toml`, or a path
+/// relative to the root of ArceOS.
+fn resolve_overlay_path(overlay: &str) -> PathBuf {
+    let root_dir = root_dir();
+    let path = PathBuf::from(overlay);
+    if path.is_absolute() {
+        path
+    } else if overlay.contains('/') || overlay.ends_with(".toml") {
+        root_dir.join(path)
+    } else {
+        root_dir
+            .join("platforms/overlays")
+            .join(format!("{overlay}.toml"))
+    }
+}
+
+/// Loads a board profile, on top of the profile named by its `base` key if
+/// it has one.
+fn load_profile(config_path: &Path, depth: usize, deps: &mut Vec<PathBuf>) -> Result<Table> {
+    deps.push(config_path.into());
+    let mut config = load_config_toml(config_path)?;
+    let Some(base) = config.remove("base") else {
+        return Ok(config);
+    };
+    assert!(
+        depth < MAX_BASE_DEPTH,
+        "too deep `base` chain at {config_path:?}"
+    );
+    let base = base
+        .as_str()
+        .expect("`base` must be a platform name or path");
+    let mut merged = load_profile(&resolve_config_path(Some(base))?, depth + 1, deps)?;
+    for (key, item) in config.iter() {
+        add_config(&mut merged, key, item.clone(), get_comments(&config, key));
+    }
+    Ok(merged)
+}
+
+/// Applies a device overlay: its keys replace the ones of the profile, and
+/// the arrays of its `[append]` table are appended to the ones of the
+/// profile, e.g. to add MMIO regions.
+fn apply_overlay(config: &mut Table, overlay: &Table) {
+    for (key, item) in overlay.iter() {
+        if key != "append" {
+            add_config(config, key, item.clone(), get_comments(overlay, key));
+            continue;
+        }
+        let appended = item.as_table().expect("`append` must be a table");
+        for (key, item) in appended.iter() {
+            let items = item.as_array().expect("appended items must be arrays");
+            match config.get_mut(key).and_then(|item| item.as_array_mut()) {
+                Some(array) => array.extend(items.iter().cloned()),
+                None => add_config(config, key, item.clone(), None),
+            }
+        }
+    }
+}
+
+/// Returns the overlays of the profile, followed by the ones of
+/// `AX_OVERLAYS`, a comma-separated list.
+fn overlay_names(config: &mut Table) -> Vec<String> {
+    let mut names: Vec<String> = config
+        .remove("overlays")
+        .and_then(|item| item.as_array().cloned())
+        .map(|array| {
+            let names = array
+                .iter()
+                .map(|v| v.as_str().expect("overlay names must be strings"));
+            names.map(String::from).collect()
+        })
+        .unwrap_or_default();
+    if let Ok(env) = std::env::var("AX_OVERLAYS") {
+        names.extend(
+            env.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from),
+        );
+    }
+    names
+}
+
+fn gen_config_rs(config_path: &Path, deps: &mut Vec<PathBuf>) -> Result<Vec<u8>> {
     fn is_num(s: &str) -> bool {
         let s = s.replace('_', "");
         if s.parse::<usize>().is_ok() {
@@ -80,7 +173,12 @@ fn gen_config_rs(config_path: &Path) -> Result<Vec<u8>> {
     } else {
         // Set default values for missing items
         let defconfig = load_config_toml(Path::new("defconfig.toml"))?;
-        let mut config = load_config_toml(config_path)?;
+        let mut config = load_profile(config_path, 0, deps)?;
+        for overlay in overlay_names(&mut config) {
+            let overlay_path = resolve_overlay_path(&overlay);
+            apply_overlay(&mut config, &load_config_toml(&overlay_path)?);
+            deps.push(overlay_path);
+        }
 
         for (key, item) in defconfig.iter() {
             if !config.contains_key(key) {
@@ -112,6 +210,9 @@ fn gen_config_rs(config_path: &Path) -> Result<Vec<u8>> {
     writeln!(output, "// Generated by build.rs, DO NOT edit!\n")?;
 
     for (key, item) in config.iter() {
+        if PROFILE_KEYS.contains(&key) {
+            continue;
+        }
         let var_name = key.to_uppercase().replace('-', "_");
         if let Item::Value(value) = item {
             let comments = get_comments(&config, key)
@@ -127,6 +228,14 @@ fn gen_config_rs(config_path: &Path) -> Result<Vec<u8>> {
                         writeln!(output, "pub const {var_name}: &str = \"{s}\";")?;
                     }
                 }
+                Value::Array(names) if key == "drivers" => {
+                    writeln!(output, "{comments}")?;
+                    writeln!(output, "pub const {var_name}: &[&str] = &[")?;
+                    for name in names.iter() {
+                        writeln!(output, "    {:?},", name.as_str().unwrap())?;
+                    }
+                    writeln!(output, "];")?;
+                }
                 Value::Array(regions) => {
                     if key != "mmio-regions" && key != "virtio-mmio-regions" && key != "pci-ranges"
                     {
@@ -158,7 +267,8 @@ fn main() -> Result<()> {
     let config_path = resolve_config_path(platform)?;
 
     println!("Reading config file: {:?}", config_path);
-    let config_rs = gen_config_rs(&config_path)?;
+    let mut deps = Vec::new();
+    let config_rs = gen_config_rs(&config_path, &mut deps)?;
 
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let out_path = Path::new(&out_dir).join("config.rs");
@@ -166,7 +276,11 @@ fn main() -> Result<()> {
     std::fs::write(out_path, config_rs)?;
 
     println!("cargo:rerun-if-changed={}", config_path.display());
+    for dep in deps {
+        println!("cargo:rerun-if-changed={}", dep.display());
+    }
     println!("cargo:rerun-if-env-changed=AX_PLATFORM");
+    println!("cargo:rerun-if-env-changed=AX_OVERLAYS");
     println!("cargo:rerun-if-env-changed=AX_SMP");
     Ok(())
 }
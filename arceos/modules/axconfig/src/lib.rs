@@ -3,6 +3,31 @@
 //! Currently supported platforms can be found in the [platforms] directory of
 //! the [ArceOS] root.
 //!
+//! # Board profiles
+//!
+//! A platform file is a board profile, selected by `AX_PLATFORM`. A board
+//! derived from another one only describes what differs: with a `base`
+//! key naming a platform (or a path), the profile is loaded on top of it.
+//! The keys missing in both are taken from `defconfig.toml`.
+//!
+//! The devices of a board variant are described by overlays, merged at
+//! build time in order: the ones of the `overlays` key of the profile, then
+//! the ones of `AX_OVERLAYS` (comma-separated). An overlay is a name of
+//! `platforms/overlays/<name>.toml`, or a path. Its keys replace the ones of
+//! the profile, and the arrays of its `[append]` table are appended, e.g.:
+//!
+//! ```toml
+//! # The SD card controller of the board.
+//! drivers = ["sdhci"]
+//!
+//! [append]
+//! mmio-regions = [["0x1600_0000", "0x1000"]]
+//! ```
+//!
+//! `drivers` lists the drivers enabled on the board, by their features of
+//! `axdriver`: the other drivers built in are not probed, see
+//! [`driver_enabled`].
+//!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [platforms]: https://github.com/arceos-org/arceos/tree/main/platforms
 
@@ -17,3 +42,9 @@ pub use config::*;
 
 /// End address of the whole physical memory.
 pub const PHYS_MEMORY_END: usize = PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE;
+
+/// Whether the driver `name` (its feature of `axdriver`) is enabled on the
+/// board: it is in [`DRIVERS`], or [`DRIVERS`] is empty.
+pub fn driver_enabled(name: &str) -> bool {
+    DRIVERS.is_empty() || DRIVERS.contains(&name)
+}
@@ -0,0 +1,160 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) lock-free statistics
+//! counters.
+//!
+//! A [`Counter`] only goes up, a [`Gauge`] goes up and down. Both keep a slot
+//! per CPU, on a cache line of its own, so that the CPUs updating them on
+//! their hot paths do not bounce the line between them. The updates are
+//! relaxed atomic additions to the slot of the current CPU, which stay
+//! correct if the caller migrates to another CPU in between.
+//!
+//! The value is the sum of the slots. A sum taken while the CPUs update them
+//! mixes old and new slots, so it is taken again until two sums in a row
+//! agree. A gauge incremented on a CPU and decremented on another has
+//! negative slots, the sum is never read below zero.
+//!
+//! The CPU of the caller is given by the function set with
+//! [`set_cpu_id_hook`] at boot, before that all the updates go to the first
+//! slot.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use core::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+
+/// The number of slots of a counter, the CPUs beyond share them.
+pub const MAX_CPUS: usize = 16;
+
+/// The number of sums taken at most for a read, the last one is returned if
+/// no two in a row agree.
+const MAX_READS: usize = 4;
+
+/// A function returning the ID of the current CPU.
+pub type CpuIdHook = fn() -> usize;
+
+static CPU_ID_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function giving the CPU of the caller, to pick its slot.
+pub fn set_cpu_id_hook(hook: CpuIdHook) {
+    CPU_ID_HOOK.store(hook as usize, Ordering::Release);
+}
+
+fn this_slot() -> usize {
+    match CPU_ID_HOOK.load(Ordering::Acquire) {
+        0 => 0,
+        f => unsafe { core::mem::transmute::<usize, CpuIdHook>(f)() % MAX_CPUS },
+    }
+}
+
+#[repr(align(64))]
+struct Slot<T>(T);
+
+/// Sums `slot` over `slots`, until two sums in a row agree.
+fn consistent_sum<T>(slots: &[Slot<T>], slot: impl Fn(&T) -> i64) -> i64 {
+    let sum = || {
+        slots
+            .iter()
+            .fold(0i64, |sum, s| sum.wrapping_add(slot(&s.0)))
+    };
+    let mut last = sum();
+    for _ in 1..MAX_READS {
+        let next = sum();
+        if next == last {
+            break;
+        }
+        last = next;
+    }
+    last
+}
+
+/// A monotonic counter, e.g. of events.
+pub struct Counter {
+    slots: [Slot<AtomicU64>; MAX_CPUS],
+}
+
+impl Counter {
+    /// Creates a counter at zero.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { Slot(AtomicU64::new(0)) }; MAX_CPUS],
+        }
+    }
+
+    /// Adds one.
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Adds `n`.
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.slots[this_slot()].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the count.
+    pub fn get(&self) -> u64 {
+        consistent_sum(&self.slots, |s| s.load(Ordering::Relaxed) as i64) as u64
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A gauge, e.g. of the live objects, going up and down.
+pub struct Gauge {
+    slots: [Slot<AtomicI64>; MAX_CPUS],
+}
+
+impl Gauge {
+    /// Creates a gauge at zero.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { Slot(AtomicI64::new(0)) }; MAX_CPUS],
+        }
+    }
+
+    /// Adds one.
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Subtracts one.
+    #[inline]
+    pub fn dec(&self) {
+        self.sub(1);
+    }
+
+    /// Adds `n`.
+    #[inline]
+    pub fn add(&self, n: usize) {
+        self.slots[this_slot()]
+            .0
+            .fetch_add(n as i64, Ordering::Relaxed);
+    }
+
+    /// Subtracts `n`.
+    #[inline]
+    pub fn sub(&self, n: usize) {
+        self.slots[this_slot()]
+            .0
+            .fetch_sub(n as i64, Ordering::Relaxed);
+    }
+
+    /// Returns the value, zero if the subtractions seen outnumber the
+    /// additions.
+    pub fn get(&self) -> usize {
+        consistent_sum(&self.slots, |s| s.load(Ordering::Relaxed)).max(0) as usize
+    }
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::*;
+
+fn thread_cpu_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static ID: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+    ID.with(|id| {
+        *id.get()
+            .get_or_insert_with(|| NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    })
+}
+
+#[test]
+fn test_counter() {
+    set_cpu_id_hook(thread_cpu_id);
+    static COUNTER: Counter = Counter::new();
+    thread::scope(|s| {
+        for _ in 0..MAX_CPUS + 4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    COUNTER.inc();
+                }
+                COUNTER.add(10);
+            });
+        }
+    });
+    assert_eq!(COUNTER.get(), (MAX_CPUS as u64 + 4) * 1010);
+}
+
+#[test]
+fn test_gauge_across_cpus() {
+    set_cpu_id_hook(thread_cpu_id);
+    static GAUGE: Gauge = Gauge::new();
+    // Up on a CPU, down on another.
+    thread::spawn(|| GAUGE.add(100)).join().unwrap();
+    thread::spawn(|| GAUGE.sub(40)).join().unwrap();
+    assert_eq!(GAUGE.get(), 60);
+    thread::spawn(|| GAUGE.sub(60)).join().unwrap();
+    assert_eq!(GAUGE.get(), 0);
+    // Never negative.
+    thread::spawn(|| GAUGE.dec()).join().unwrap();
+    assert_eq!(GAUGE.get(), 0);
+    GAUGE.inc();
+    assert_eq!(GAUGE.get(), 0);
+    GAUGE.inc();
+    assert_eq!(GAUGE.get(), 1);
+}
+
+#[test]
+fn test_concurrent_reads() {
+    set_cpu_id_hook(thread_cpu_id);
+    static GAUGE: Gauge = Gauge::new();
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..10000 {
+                    GAUGE.inc();
+                    GAUGE.dec();
+                }
+            });
+        }
+        s.spawn(|| {
+            for _ in 0..1000 {
+                assert!(GAUGE.get() <= 4);
+            }
+        });
+    });
+    assert_eq!(GAUGE.get(), 0);
+}
@@ -0,0 +1,217 @@
+//! The BLAKE2s hash function (RFC 7693).
+
+/// The block size in bytes.
+pub const BLOCK_LEN: usize = 64;
+/// The maximum (and default) digest size in bytes.
+pub const OUT_LEN: usize = 32;
+/// The maximum key size in bytes.
+pub const KEY_LEN: usize = 32;
+
+const IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// An incremental BLAKE2s hasher.
+#[derive(Clone)]
+pub struct Blake2s {
+    h: [u32; 8],
+    /// The number of bytes compressed.
+    t: u64,
+    buf: [u8; BLOCK_LEN],
+    buf_len: usize,
+    out_len: usize,
+}
+
+impl Blake2s {
+    /// Creates a hasher with a digest of `out_len` bytes.
+    pub fn new(out_len: usize) -> Self {
+        Self::new_keyed(&[], out_len)
+    }
+
+    /// Creates a hasher in the keyed mode (a MAC), with a digest of
+    /// `out_len` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is longer than [`KEY_LEN`], or `out_len` is 0 or
+    /// greater than [`OUT_LEN`].
+    pub fn new_keyed(key: &[u8], out_len: usize) -> Self {
+        assert!(key.len() <= KEY_LEN);
+        assert!((1..=OUT_LEN).contains(&out_len));
+        let mut h = IV;
+        h[0] ^= 0x0101_0000 ^ ((key.len() as u32) << 8) ^ out_len as u32;
+        let mut hasher = Self {
+            h,
+            t: 0,
+            buf: [0; BLOCK_LEN],
+            buf_len: 0,
+            out_len,
+        };
+        if !key.is_empty() {
+            // the key is padded to a full block
+            hasher.buf[..key.len()].copy_from_slice(key);
+            hasher.buf_len = BLOCK_LEN;
+        }
+        hasher
+    }
+
+    /// Feeds `data` to the hasher.
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block is compressed differently, so a full buffer is
+            // only compressed when more data comes.
+            if self.buf_len == BLOCK_LEN {
+                self.t += BLOCK_LEN as u64;
+                compress(&mut self.h, &self.buf, self.t, false);
+                self.buf_len = 0;
+            }
+            let n = data.len().min(BLOCK_LEN - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+        }
+    }
+
+    /// Writes the digest to `out`, which must have the length given at the
+    /// creation.
+    pub fn finalize_into(mut self, out: &mut [u8]) {
+        assert_eq!(out.len(), self.out_len);
+        self.t += self.buf_len as u64;
+        self.buf[self.buf_len..].fill(0);
+        compress(&mut self.h, &self.buf, self.t, true);
+        let mut bytes = [0; OUT_LEN];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(self.h) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out.copy_from_slice(&bytes[..self.out_len]);
+    }
+
+    /// Returns the digest of [`OUT_LEN`] bytes.
+    pub fn finalize(self) -> [u8; OUT_LEN] {
+        let mut out = [0; OUT_LEN];
+        self.finalize_into(&mut out);
+        out
+    }
+}
+
+#[inline(always)]
+fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(12);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(8);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(7);
+}
+
+fn compress(h: &mut [u32; 8], block: &[u8; BLOCK_LEN], t: u64, last: bool) {
+    let mut m = [0u32; 16];
+    for (word, chunk) in m.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    let mut v = [0u32; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&IV);
+    v[12] ^= t as u32;
+    v[13] ^= (t >> 32) as u32;
+    if last {
+        v[14] = !v[14];
+    }
+    for s in SIGMA.iter() {
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+/// Returns the 32-byte BLAKE2s digest of the concatenation of `parts`.
+pub fn hash(parts: &[&[u8]]) -> [u8; OUT_LEN] {
+    let mut hasher = Blake2s::new(OUT_LEN);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+/// Returns the 16-byte keyed BLAKE2s digest of `data`, the `MAC` function of
+/// WireGuard.
+pub fn mac(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut hasher = Blake2s::new_keyed(key, 16);
+    hasher.update(data);
+    let mut out = [0; 16];
+    hasher.finalize_into(&mut out);
+    out
+}
+
+/// Returns the HMAC-BLAKE2s of the concatenation of `parts` (RFC 2104).
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; OUT_LEN] {
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..OUT_LEN].copy_from_slice(&hash(&[key]));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut pad = [0u8; BLOCK_LEN];
+    for (p, k) in pad.iter_mut().zip(block) {
+        *p = k ^ 0x36;
+    }
+    let mut inner = Blake2s::new(OUT_LEN);
+    inner.update(&pad);
+    for part in parts {
+        inner.update(part);
+    }
+    let inner = inner.finalize();
+
+    for (p, k) in pad.iter_mut().zip(block) {
+        *p = k ^ 0x5c;
+    }
+    hash(&[&pad, &inner])
+}
+
+/// Derives `N` keys from the chaining key `key` and `input`, the `KDF`
+/// function of WireGuard (HKDF over HMAC-BLAKE2s).
+///
+/// # Panics
+///
+/// Panics if `N` is 0 or greater than 3.
+pub fn kdf<const N: usize>(key: &[u8; OUT_LEN], input: &[u8]) -> [[u8; OUT_LEN]; N] {
+    assert!((1..=3).contains(&N));
+    let prk = hmac(key, &[input]);
+    let mut out = [[0; OUT_LEN]; N];
+    for i in 0..N {
+        let prev: &[u8] = if i == 0 { &[] } else { &out[i - 1] };
+        out[i] = hmac(&prk, &[prev, &[i as u8 + 1]]);
+    }
+    out
+}
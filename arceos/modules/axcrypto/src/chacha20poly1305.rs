@@ -0,0 +1,268 @@
+//! The ChaCha20-Poly1305 AEAD (RFC 8439).
+
+/// The key size in bytes.
+pub const KEY_LEN: usize = 32;
+/// The nonce size in bytes.
+pub const NONCE_LEN: usize = 12;
+/// The authentication tag size in bytes.
+pub const TAG_LEN: usize = 16;
+
+/// The authentication tag of a sealed message does not match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagMismatch;
+
+#[inline(always)]
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// Returns the ChaCha20 keystream block `counter`.
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    // "expand 32-byte k"
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for i in 0..8 {
+        state[4 + i] = le32(&key[4 * i..]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = le32(&nonce[4 * i..]);
+    }
+
+    let mut s = state;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0; 64];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&s[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+/// XORs `data` with the ChaCha20 keystream starting at the block `counter`.
+pub fn chacha20(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (b, k) in chunk.iter_mut().zip(block) {
+            *b ^= k;
+        }
+    }
+}
+
+/// An incremental Poly1305 one-time authenticator.
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+impl Poly1305 {
+    /// Creates the authenticator with the one-time key `key`.
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            r: [
+                le32(&key[0..]) & 0x3ff_ffff,
+                (le32(&key[3..]) >> 2) & 0x3ff_ff03,
+                (le32(&key[6..]) >> 4) & 0x3ff_c0ff,
+                (le32(&key[9..]) >> 6) & 0x3f0_3fff,
+                (le32(&key[12..]) >> 8) & 0x00f_ffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+            buf: [0; 16],
+            buf_len: 0,
+        }
+    }
+
+    /// Processes a block, `hibit` is the bit after the message in it.
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        const MASK: u32 = 0x3ff_ffff;
+        let [r0, r1, r2, r3, r4] = self.r.map(|r| r as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h = &mut self.h;
+        h[0] += le32(&m[0..]) & MASK;
+        h[1] += (le32(&m[3..]) >> 2) & MASK;
+        h[2] += (le32(&m[6..]) >> 4) & MASK;
+        h[3] += (le32(&m[9..]) >> 6) & MASK;
+        h[4] += (le32(&m[12..]) >> 8) | hibit;
+        let [h0, h1, h2, h3, h4] = h.map(|h| h as u64);
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        h[0] = d0 as u32 & MASK;
+        d2 += d1 >> 26;
+        h[1] = d1 as u32 & MASK;
+        d3 += d2 >> 26;
+        h[2] = d2 as u32 & MASK;
+        d4 += d3 >> 26;
+        h[3] = d3 as u32 & MASK;
+        h[4] = d4 as u32 & MASK;
+        let d0 = (d4 >> 26) * 5 + h[0] as u64;
+        h[0] = d0 as u32 & MASK;
+        h[1] += (d0 >> 26) as u32;
+    }
+
+    /// Feeds `data` to the authenticator.
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len > 0 {
+            let n = data.len().min(16 - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+            if self.buf_len < 16 {
+                return;
+            }
+            let buf = self.buf;
+            self.block(&buf, 1 << 24);
+            self.buf_len = 0;
+        }
+        let mut chunks = data.chunks_exact(16);
+        for chunk in &mut chunks {
+            self.block(chunk.try_into().unwrap(), 1 << 24);
+        }
+        let rest = chunks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    /// Feeds zeros to align the data fed so far to 16 bytes.
+    fn pad16(&mut self) {
+        if self.buf_len > 0 {
+            self.update(&[0; 16][self.buf_len..]);
+        }
+    }
+
+    /// Returns the authentication tag.
+    pub fn finalize(mut self) -> [u8; TAG_LEN] {
+        const MASK: u32 = 0x3ff_ffff;
+        if self.buf_len > 0 {
+            let mut last = [0; 16];
+            last[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            last[self.buf_len] = 1;
+            self.block(&last, 0);
+        }
+
+        // fully carry h
+        let mut h = self.h;
+        for _ in 0..2 {
+            for i in 1..5 {
+                h[i] += h[i - 1] >> 26;
+                h[i - 1] &= MASK;
+            }
+            h[0] += (h[4] >> 26) * 5;
+            h[4] &= MASK;
+        }
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+
+        // compute h - p, select it if it does not underflow
+        let mut g = [0u32; 5];
+        let mut carry = 5;
+        for i in 0..4 {
+            g[i] = h[i] + carry;
+            carry = g[i] >> 26;
+            g[i] &= MASK;
+        }
+        g[4] = h[4].wrapping_add(carry).wrapping_sub(1 << 26);
+        let select_g = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !select_g) | (g[i] & select_g);
+        }
+
+        // h = (h + pad) % 2^128
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; TAG_LEN];
+        let mut f = 0u64;
+        for i in 0..4 {
+            f = words[i] as u64 + self.pad[i] as u64 + (f >> 32);
+            tag[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        tag
+    }
+}
+
+/// Returns the Poly1305 tag of the AEAD construction over `aad` and
+/// `ciphertext`.
+fn aead_tag(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut poly = Poly1305::new(block[..32].try_into().unwrap());
+    poly.update(aad);
+    poly.pad16();
+    poly.update(ciphertext);
+    poly.pad16();
+    poly.update(&(aad.len() as u64).to_le_bytes());
+    poly.update(&(ciphertext.len() as u64).to_le_bytes());
+    poly.finalize()
+}
+
+/// Encrypts `buf` in place, and returns the authentication tag of it and the
+/// additional data `aad`.
+pub fn seal(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    buf: &mut [u8],
+) -> [u8; TAG_LEN] {
+    chacha20(key, nonce, 1, buf);
+    aead_tag(key, nonce, aad, buf)
+}
+
+/// Checks the authentication tag `tag` of `buf` and the additional data
+/// `aad`, and decrypts `buf` in place if it matches.
+///
+/// `buf` is left untouched if the tag does not match.
+pub fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    buf: &mut [u8],
+    tag: &[u8; TAG_LEN],
+) -> Result<(), TagMismatch> {
+    if !crate::ct_eq(&aead_tag(key, nonce, aad, buf), tag) {
+        return Err(TagMismatch);
+    }
+    chacha20(key, nonce, 1, buf);
+    Ok(())
+}
@@ -0,0 +1,34 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) cryptographic primitives.
+//!
+//! Portable implementations of the primitives of the WireGuard protocol,
+//! without heap allocation:
+//!
+//! - [`x25519`]: the Diffie-Hellman function on Curve25519 (RFC 7748).
+//! - [`chacha20poly1305`]: the ChaCha20-Poly1305 AEAD (RFC 8439).
+//! - [`blake2s`]: the BLAKE2s hash function (RFC 7693), with its keyed mode,
+//!   HMAC and HKDF.
+//!
+//! And MD5 ([`md5`], RFC 1321), for the legacy protocols requiring it.
+//!
+//! The secret-dependent code runs in constant time, but nothing is done
+//! against the other side channels, and the secrets are not zeroized.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod blake2s;
+pub mod chacha20poly1305;
+pub mod md5;
+pub mod x25519;
+
+#[cfg(test)]
+mod tests;
+
+/// Compares two byte strings in constant time.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    // Keep the comparison from being short-circuited.
+    core::hint::black_box(diff) == 0
+}
@@ -0,0 +1,130 @@
+use crate::*;
+
+fn hex<const N: usize>(s: &str) -> [u8; N] {
+    let mut out = [0; N];
+    assert_eq!(s.len(), 2 * N);
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+    }
+    out
+}
+
+#[test]
+fn test_x25519() {
+    // RFC 7748, section 5.2
+    let scalar = hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+    let u = hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+    let expected = hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552");
+    assert_eq!(x25519::x25519(&scalar, &u), expected);
+
+    // RFC 7748, section 6.1
+    let alice = hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    let bob = hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+    let alice_pub = x25519::public_key(&alice);
+    let bob_pub = x25519::public_key(&bob);
+    assert_eq!(
+        alice_pub,
+        hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+    );
+    assert_eq!(
+        bob_pub,
+        hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+    );
+    let shared = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    assert_eq!(x25519::x25519(&alice, &bob_pub), shared);
+    assert_eq!(x25519::x25519(&bob, &alice_pub), shared);
+}
+
+#[test]
+fn test_poly1305() {
+    // RFC 8439, section 2.5.2
+    let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+    let msg = b"Cryptographic Forum Research Group";
+    let expected: [u8; 16] = hex("a8061dc1305136c6c22b8baf0c0127a9");
+    let mut poly = chacha20poly1305::Poly1305::new(&key);
+    poly.update(msg);
+    assert_eq!(poly.finalize(), expected);
+    // fed in pieces
+    let mut poly = chacha20poly1305::Poly1305::new(&key);
+    for chunk in msg.chunks(5) {
+        poly.update(chunk);
+    }
+    assert_eq!(poly.finalize(), expected);
+}
+
+#[test]
+fn test_chacha20poly1305() {
+    // RFC 8439, section 2.8.2
+    let mut key = [0; 32];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = 0x80 + i as u8;
+    }
+    let nonce = hex("070000004041424344454647");
+    let aad = hex::<12>("50515253c0c1c2c3c4c5c6c7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+
+    let mut buf = plaintext.to_vec();
+    let tag = chacha20poly1305::seal(&key, &nonce, &aad, &mut buf);
+    assert_eq!(tag, hex("1ae10b594f09e26a7e902ecbd0600691"));
+    assert_eq!(buf[..16], hex::<16>("d31a8d34648e60db7b86afbc53ef7ec2"));
+
+    let mut bad_tag = tag;
+    bad_tag[0] ^= 1;
+    let sealed = buf.clone();
+    assert_eq!(
+        chacha20poly1305::open(&key, &nonce, &aad, &mut buf, &bad_tag),
+        Err(chacha20poly1305::TagMismatch)
+    );
+    assert_eq!(buf, sealed);
+    assert!(chacha20poly1305::open(&key, &nonce, &aad, &mut buf, &tag).is_ok());
+    assert_eq!(buf, plaintext);
+}
+
+#[test]
+fn test_blake2s() {
+    assert_eq!(
+        blake2s::hash(&[]),
+        hex("69217a3079908094e11121d042354a7c1f55b6482ca1a51e1b250dfd1ed0eef9")
+    );
+    assert_eq!(
+        blake2s::hash(&[b"a", b"bc"]),
+        hex("508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982")
+    );
+    assert_eq!(
+        blake2s::mac(&[b'k'; 32], b"hello"),
+        hex("1c58b50d7347bf4effbdf24df827a767")
+    );
+    assert_eq!(
+        blake2s::hmac(b"key", &[b"The quick brown fox"]),
+        hex("8717096fc5e7ac53d11d990acd4380c9ddd7bd8da5d8b5bc29855e6c98a866a4")
+    );
+    let [t1, t2] = blake2s::kdf(&[0; 32], b"input");
+    assert_eq!(
+        t1,
+        hex("fbff6119f265251560576e82518f61aeb420cff55b5dcf649475b758a01f3951")
+    );
+    assert_eq!(
+        t2,
+        hex("a534dd2f7d3e7e614508dba7c675d5cf1008b383670cd8fd46231aa38bbfb618")
+    );
+}
+
+#[test]
+fn test_md5() {
+    // RFC 1321, appendix A.5
+    assert_eq!(md5::hash(&[]), hex("d41d8cd98f00b204e9800998ecf8427e"));
+    assert_eq!(
+        md5::hash(&[b"abc"]),
+        hex("900150983cd24fb0d6963f7d28e17f72")
+    );
+    assert_eq!(
+        md5::hash(&[b"abcdefghijklmnopqrstuvwxyz"]),
+        hex("c3fcd3d76192e4007dfb496cca67e13b")
+    );
+    let digits = b"1234567890".repeat(8);
+    assert_eq!(
+        md5::hash(&[&digits[..7], &digits[7..]]),
+        hex("57edf4a22be3c955ac49da2e2107b67a")
+    );
+}
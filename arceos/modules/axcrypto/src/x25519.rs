@@ -0,0 +1,213 @@
+//! The X25519 Diffie-Hellman function (RFC 7748).
+
+/// The size of the keys and of the shared secret in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// An element of GF(2^255 - 19), in 5 limbs of 51 bits.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const MASK: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        // the top bit is ignored
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().0;
+        // h < 2^255 + small, compute h - p and keep it if it does not
+        // underflow
+        let mut q = (h[0] + 19) >> 51;
+        q = (h[1] + q) >> 51;
+        q = (h[2] + q) >> 51;
+        q = (h[3] + q) >> 51;
+        q = (h[4] + q) >> 51;
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12),
+        ];
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reduces the limbs to 51 bits (plus a tiny excess in the first one).
+    fn carry(self) -> Self {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        Fe(h)
+    }
+
+    fn add(self, rhs: Fe) -> Fe {
+        let mut h = self.0;
+        for (a, b) in h.iter_mut().zip(rhs.0) {
+            *a += b;
+        }
+        Fe(h)
+    }
+
+    fn sub(self, rhs: Fe) -> Fe {
+        // add 2p so the limbs do not underflow
+        const TWO_P: [u64; 5] = [
+            0xf_ffff_ffff_ffda,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+        ];
+        let rhs = rhs.carry().0;
+        let mut h = self.0;
+        for i in 0..5 {
+            h[i] = h[i] + TWO_P[i] - rhs[i];
+        }
+        Fe(h).carry()
+    }
+
+    fn mul(self, rhs: Fe) -> Fe {
+        let [a0, a1, a2, a3, a4] = self.0.map(|a| a as u128);
+        let [b0, b1, b2, b3, b4] = rhs.0.map(|b| b as u128);
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+
+        let t = [
+            a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19,
+            a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19,
+            a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19,
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19,
+            a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0,
+        ];
+        Self::reduce_wide(t)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn mul_small(self, n: u64) -> Fe {
+        Self::reduce_wide(self.0.map(|a| a as u128 * n as u128))
+    }
+
+    fn reduce_wide(mut t: [u128; 5]) -> Fe {
+        for i in 0..4 {
+            t[i + 1] += t[i] >> 51;
+            t[i] &= MASK as u128;
+        }
+        let mut h = t.map(|t| t as u64);
+        h[4] &= MASK;
+        h[0] += 19 * (t[4] >> 51) as u64;
+        h[1] += h[0] >> 51;
+        h[0] &= MASK;
+        Fe(h)
+    }
+
+    /// Squares `n` times.
+    fn pow2k(self, n: usize) -> Fe {
+        let mut x = self;
+        for _ in 0..n {
+            x = x.square();
+        }
+        x
+    }
+
+    /// Returns the inverse, as `self^(p - 2)`.
+    fn invert(self) -> Fe {
+        let z2 = self.square();
+        let z9 = z2.pow2k(2).mul(self);
+        let z11 = z9.mul(z2);
+        let z2_5_0 = z11.square().mul(z9);
+        let z2_10_0 = z2_5_0.pow2k(5).mul(z2_5_0);
+        let z2_20_0 = z2_10_0.pow2k(10).mul(z2_10_0);
+        let z2_40_0 = z2_20_0.pow2k(20).mul(z2_20_0);
+        let z2_50_0 = z2_40_0.pow2k(10).mul(z2_10_0);
+        let z2_100_0 = z2_50_0.pow2k(50).mul(z2_50_0);
+        let z2_200_0 = z2_100_0.pow2k(100).mul(z2_100_0);
+        let z2_250_0 = z2_200_0.pow2k(50).mul(z2_50_0);
+        z2_250_0.pow2k(5).mul(z11)
+    }
+
+    /// Swaps `a` and `b` if `swap` is 1, in constant time.
+    fn cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = 0u64.wrapping_sub(swap);
+        for (x, y) in a.0.iter_mut().zip(b.0.iter_mut()) {
+            let t = mask & (*x ^ *y);
+            *x ^= t;
+            *y ^= t;
+        }
+    }
+}
+
+/// Returns the X25519 function of the scalar `scalar` and the u-coordinate
+/// `u`.
+///
+/// The scalar is clamped as RFC 7748 says, so any 32 random bytes are a valid
+/// private key.
+pub fn x25519(scalar: &[u8; KEY_LEN], u: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = Fe::from_bytes(u);
+    let (mut x2, mut z2) = (Fe::ONE, Fe::ZERO);
+    let (mut x3, mut z3) = (x1, Fe::ONE);
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let k_t = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= k_t;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = k_t;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// Returns the public key of the private key `secret`.
+pub fn public_key(secret: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut base = [0; KEY_LEN];
+    base[0] = 9;
+    x25519(secret, &base)
+}
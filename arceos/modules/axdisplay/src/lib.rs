@@ -1,11 +1,20 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) graphics module.
 //!
 //! Currently only supports direct writing to the framebuffer.
+//!
+//! # Cargo Features
+//!
+//! - `terminal`: Show the console output on the display, with a terminal
+//!   emulator understanding the common ANSI escape sequences.
 
 #![no_std]
 
 #[macro_use]
 extern crate log;
+extern crate alloc;
+
+#[cfg(feature = "terminal")]
+mod terminal;
 
 #[doc(no_inline)]
 pub use axdriver_display::DisplayInfo;
@@ -22,6 +31,8 @@ pub fn init_display(mut display_devs: AxDeviceContainer<AxDisplayDevice>) {
 
     let dev = display_devs.take_one().expect("No graphics device found!");
     info!("  use graphics device 0: {:?}", dev.device_name());
+    #[cfg(feature = "terminal")]
+    terminal::init(&dev.info());
     MAIN_DISPLAY.init_once(Mutex::new(dev));
 }
 
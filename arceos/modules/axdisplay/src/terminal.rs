@@ -0,0 +1,479 @@
+//! A terminal emulator on the framebuffer, showing the console output.
+//!
+//! Besides the control characters, it understands a subset of the ANSI/VT100
+//! escape sequences:
+//!
+//! - `CSI n A/B/C/D/E/F/G`, `CSI y;x H/f`, `CSI y d`: cursor movements and
+//!   addressing.
+//! - `CSI n J`, `CSI n K`, `CSI n X`: erasing the screen, the line or
+//!   characters.
+//! - `CSI ... m`: the attributes, bold, reverse, and the 16, 256 and 24-bit
+//!   colors.
+//! - `CSI s/u`, `ESC 7/8`: saving and restoring the cursor.
+//! - `CSI ?25 h/l`: showing and hiding the cursor.
+//!
+//! The characters are drawn with an 8x8 bitmap font, doubled in height.
+
+use alloc::{vec, vec::Vec};
+
+use axdriver_display::DisplayInfo;
+use font8x8::{UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+
+const CHAR_WIDTH: usize = 8;
+const CHAR_HEIGHT: usize = 16;
+const TAB_WIDTH: usize = 8;
+const MAX_PARAMS: usize = 16;
+
+/// The 16 colors of xterm, in `0xRRGGBB`.
+const PALETTE: [u32; 16] = [
+    0x000000, 0xcd0000, 0x00cd00, 0xcdcd00, 0x0000ee, 0xcd00cd, 0x00cdcd, 0xe5e5e5, //
+    0x7f7f7f, 0xff0000, 0x00ff00, 0xffff00, 0x5c5cff, 0xff00ff, 0x00ffff, 0xffffff,
+];
+const DEFAULT_FG: u32 = PALETTE[7];
+const DEFAULT_BG: u32 = PALETTE[0];
+
+static TERMINAL: LazyInit<SpinNoIrq<Terminal>> = LazyInit::new();
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Default,
+    /// An index in the 256 colors.
+    Indexed(u8),
+    Rgb(u32),
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    ch: char,
+    fg: u32,
+    bg: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+struct Terminal {
+    fb: *mut u32,
+    /// Pixels per line of the framebuffer.
+    stride: usize,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    x: usize,
+    y: usize,
+    /// The cursor is past the last column, the next character goes to the
+    /// next line.
+    wrap_pending: bool,
+    saved: (usize, usize),
+    cursor_visible: bool,
+    fg: Color,
+    bg: Color,
+    bold: bool,
+    reverse: bool,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    num_params: usize,
+    /// A `?` after the CSI.
+    private: bool,
+    /// The UTF-8 character being decoded, and its missing bytes.
+    utf8: (u32, usize),
+}
+
+// The framebuffer is only accessed with the lock held.
+unsafe impl Send for Terminal {}
+
+/// Returns the color of `index` in the 256 colors of xterm.
+fn color256(index: u8) -> u32 {
+    match index {
+        0..=15 => PALETTE[index as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v as u32 * 40 };
+            let i = index - 16;
+            level(i / 36) << 16 | level(i / 6 % 6) << 8 | level(i % 6)
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) as u32 * 10;
+            gray << 16 | gray << 8 | gray
+        }
+    }
+}
+
+fn glyph(ch: char) -> [u8; 8] {
+    BASIC_FONTS
+        .get(ch)
+        .or_else(|| LATIN_FONTS.get(ch))
+        .or_else(|| BOX_FONTS.get(ch))
+        .or_else(|| BLOCK_FONTS.get(ch))
+        .or_else(|| GREEK_FONTS.get(ch))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap()
+}
+
+impl Terminal {
+    fn new(info: &DisplayInfo) -> Self {
+        let (width, height) = (info.width as usize, info.height as usize);
+        let cols = width / CHAR_WIDTH;
+        let rows = height / CHAR_HEIGHT;
+        let blank = Cell {
+            ch: ' ',
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+        };
+        Self {
+            fb: info.fb_base_vaddr as *mut u32,
+            stride: width,
+            cols,
+            rows,
+            cells: vec![blank; cols * rows],
+            x: 0,
+            y: 0,
+            wrap_pending: false,
+            saved: (0, 0),
+            cursor_visible: true,
+            fg: Color::Default,
+            bg: Color::Default,
+            bold: false,
+            reverse: false,
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            num_params: 0,
+            private: false,
+            utf8: (0, 0),
+        }
+    }
+
+    fn colors(&self) -> (u32, u32) {
+        let fg = match self.fg {
+            Color::Default if self.bold => PALETTE[15],
+            Color::Default => DEFAULT_FG,
+            // Bold brightens the 8 basic colors.
+            Color::Indexed(i) if self.bold && i < 8 => PALETTE[i as usize + 8],
+            Color::Indexed(i) => color256(i),
+            Color::Rgb(rgb) => rgb,
+        };
+        let bg = match self.bg {
+            Color::Default => DEFAULT_BG,
+            Color::Indexed(i) => color256(i),
+            Color::Rgb(rgb) => rgb,
+        };
+        if self.reverse {
+            (bg, fg)
+        } else {
+            (fg, bg)
+        }
+    }
+
+    fn draw_cell(&mut self, x: usize, y: usize, inverted: bool) {
+        let cell = self.cells[y * self.cols + x];
+        let (fg, bg) = if inverted {
+            (cell.bg, cell.fg)
+        } else {
+            (cell.fg, cell.bg)
+        };
+        let (fg, bg) = (0xff00_0000 | fg, 0xff00_0000 | bg);
+        let glyph = glyph(cell.ch);
+        for row in 0..CHAR_HEIGHT {
+            let bits = glyph[row / 2];
+            let line = (y * CHAR_HEIGHT + row) * self.stride + x * CHAR_WIDTH;
+            for col in 0..CHAR_WIDTH {
+                let pixel = if bits & (1 << col) != 0 { fg } else { bg };
+                unsafe { self.fb.add(line + col).write_volatile(pixel) };
+            }
+        }
+    }
+
+    fn draw_cursor(&mut self, shown: bool) {
+        if self.cursor_visible {
+            self.draw_cell(self.x, self.y, shown);
+        }
+    }
+
+    /// Sets the cells of `start..end` (indexes in the cells) to spaces.
+    fn erase(&mut self, start: usize, end: usize) {
+        let (_, bg) = self.colors();
+        for i in start..end {
+            self.cells[i] = Cell {
+                ch: ' ',
+                fg: DEFAULT_FG,
+                bg,
+            };
+            self.draw_cell(i % self.cols, i / self.cols, false);
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        let cols = self.cols;
+        self.cells.copy_within(cols.., 0);
+        let line_pixels = CHAR_HEIGHT * self.stride;
+        unsafe {
+            core::ptr::copy(
+                self.fb.add(line_pixels),
+                self.fb,
+                (self.rows - 1) * line_pixels,
+            );
+        }
+        let len = self.cells.len();
+        self.erase(len - cols, len);
+    }
+
+    fn line_feed(&mut self) {
+        if self.y + 1 == self.rows {
+            self.scroll_up();
+        } else {
+            self.y += 1;
+        }
+    }
+
+    fn move_to(&mut self, x: usize, y: usize) {
+        self.x = x.min(self.cols - 1);
+        self.y = y.min(self.rows - 1);
+        self.wrap_pending = false;
+    }
+
+    fn print(&mut self, ch: char) {
+        if self.wrap_pending {
+            self.x = 0;
+            self.line_feed();
+            self.wrap_pending = false;
+        }
+        let (fg, bg) = self.colors();
+        self.cells[self.y * self.cols + self.x] = Cell { ch, fg, bg };
+        self.draw_cell(self.x, self.y, false);
+        if self.x + 1 == self.cols {
+            self.wrap_pending = true;
+        } else {
+            self.x += 1;
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.draw_cursor(false);
+        for &b in bytes {
+            self.write_byte(b);
+        }
+        self.draw_cursor(true);
+    }
+
+    fn write_byte(&mut self, b: u8) {
+        match self.state {
+            State::Escape => return self.escape(b),
+            State::Csi => return self.csi(b),
+            State::Ground => {}
+        }
+        match b {
+            0x1b => self.state = State::Escape,
+            // The console output only has `\n` for new lines.
+            b'\n' => {
+                self.x = 0;
+                self.wrap_pending = false;
+                self.line_feed();
+            }
+            b'\r' => self.move_to(0, self.y),
+            0x08 => self.move_to(self.x.saturating_sub(1), self.y),
+            b'\t' => self.move_to((self.x / TAB_WIDTH + 1) * TAB_WIDTH, self.y),
+            0x00..=0x1f | 0x7f => {}
+            0x20..=0x7e => self.print(b as char),
+            _ => self.utf8_byte(b),
+        }
+    }
+
+    fn utf8_byte(&mut self, b: u8) {
+        let (code, missing) = self.utf8;
+        if b & 0xc0 == 0x80 {
+            if missing == 0 {
+                return;
+            }
+            let code = code << 6 | (b & 0x3f) as u32;
+            self.utf8 = (code, missing - 1);
+            if missing == 1 {
+                self.print(char::from_u32(code).unwrap_or('?'));
+            }
+            return;
+        }
+        self.utf8 = match b {
+            0xc0..=0xdf => ((b & 0x1f) as u32, 1),
+            0xe0..=0xef => ((b & 0x0f) as u32, 2),
+            0xf0..=0xf7 => ((b & 0x07) as u32, 3),
+            _ => (0, 0),
+        };
+    }
+
+    fn escape(&mut self, b: u8) {
+        self.state = State::Ground;
+        match b {
+            b'[' => {
+                self.state = State::Csi;
+                self.params = [0; MAX_PARAMS];
+                self.num_params = 0;
+                self.private = false;
+            }
+            b'7' => self.saved = (self.x, self.y),
+            b'8' => self.move_to(self.saved.0, self.saved.1),
+            b'c' => {
+                self.reset_attributes();
+                self.move_to(0, 0);
+                let len = self.cells.len();
+                self.erase(0, len);
+            }
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, b: u8) {
+        match b {
+            b'0'..=b'9' => {
+                let i = self.num_params.max(1) - 1;
+                self.num_params = i + 1;
+                self.params[i] = self.params[i].saturating_mul(10) + (b - b'0') as u16;
+            }
+            b';' => {
+                // An empty parameter is 0.
+                self.num_params = (self.num_params.max(1) + 1).min(MAX_PARAMS);
+            }
+            b'?' => self.private = true,
+            0x40..=0x7e => {
+                self.state = State::Ground;
+                self.dispatch(b);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the parameter `i`, or `default` if it is missing or 0.
+    fn param(&self, i: usize, default: usize) -> usize {
+        match self.params[i] {
+            0 => default,
+            n => n as usize,
+        }
+    }
+
+    fn dispatch(&mut self, b: u8) {
+        let n = self.param(0, 1);
+        let (x, y) = (self.x, self.y);
+        match b {
+            b'A' => self.move_to(x, y.saturating_sub(n)),
+            b'B' => self.move_to(x, y + n),
+            b'C' => self.move_to(x + n, y),
+            b'D' => self.move_to(x.saturating_sub(n), y),
+            b'E' => self.move_to(0, y + n),
+            b'F' => self.move_to(0, y.saturating_sub(n)),
+            b'G' => self.move_to(n - 1, y),
+            b'd' => self.move_to(x, n - 1),
+            b'H' | b'f' => self.move_to(self.param(1, 1) - 1, n - 1),
+            b'J' => {
+                let cursor = y * self.cols + x;
+                let len = self.cells.len();
+                match self.params[0] {
+                    0 => self.erase(cursor, len),
+                    1 => self.erase(0, cursor + 1),
+                    _ => self.erase(0, len),
+                }
+            }
+            b'K' => {
+                let line = y * self.cols;
+                match self.params[0] {
+                    0 => self.erase(line + x, line + self.cols),
+                    1 => self.erase(line, line + x + 1),
+                    _ => self.erase(line, line + self.cols),
+                }
+            }
+            b'X' => {
+                let line = y * self.cols;
+                self.erase(line + x, line + (x + n).min(self.cols));
+            }
+            b'm' => self.set_attributes(),
+            b's' => self.saved = (x, y),
+            b'u' => self.move_to(self.saved.0, self.saved.1),
+            b'h' | b'l' if self.private && self.params[0] == 25 => {
+                self.cursor_visible = b == b'h';
+            }
+            _ => {}
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.fg = Color::Default;
+        self.bg = Color::Default;
+        self.bold = false;
+        self.reverse = false;
+    }
+
+    /// Reads the color of `38;5;n` or `38;2;r;g;b` after `params[i]`, returns
+    /// it with the number of parameters read.
+    fn extended_color(&self, i: usize) -> (Option<Color>, usize) {
+        let p = |j: usize| self.params.get(i + j).copied().unwrap_or(0);
+        match p(1) {
+            5 => (Some(Color::Indexed(p(2).min(255) as u8)), 2),
+            2 => {
+                let c = |j| p(j).min(255) as u32;
+                (Some(Color::Rgb(c(2) << 16 | c(3) << 8 | c(4))), 4)
+            }
+            _ => (None, 1),
+        }
+    }
+
+    fn set_attributes(&mut self) {
+        let num = self.num_params.max(1);
+        let mut i = 0;
+        while i < num {
+            match self.params[i] {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                27 => self.reverse = false,
+                p @ 30..=37 => self.fg = Color::Indexed((p - 30) as u8),
+                39 => self.fg = Color::Default,
+                p @ 40..=47 => self.bg = Color::Indexed((p - 40) as u8),
+                49 => self.bg = Color::Default,
+                p @ 90..=97 => self.fg = Color::Indexed((p - 90 + 8) as u8),
+                p @ 100..=107 => self.bg = Color::Indexed((p - 100 + 8) as u8),
+                p @ (38 | 48) => {
+                    let (color, skip) = self.extended_color(i);
+                    if let Some(color) = color {
+                        if p == 38 {
+                            self.fg = color;
+                        } else {
+                            self.bg = color;
+                        }
+                    }
+                    i += skip;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Writes the bytes to the terminal, then flushes the display.
+fn write(bytes: &[u8]) {
+    TERMINAL.lock().write(bytes);
+    // The display may be flushing already, with the console output of the
+    // driver written here.
+    if let Some(mut dev) = crate::MAIN_DISPLAY.try_lock() {
+        if dev.need_flush() {
+            dev.flush().ok();
+        }
+    }
+}
+
+/// Clears the display and shows the console output on it.
+pub(crate) fn init(info: &DisplayInfo) {
+    let mut term = Terminal::new(info);
+    let (cols, rows) = (term.cols, term.rows);
+    if cols == 0 || rows == 0 {
+        warn!("  display too small for a terminal");
+        return;
+    }
+    term.write(b"\x1bc");
+    TERMINAL.init_once(SpinNoIrq::new(term));
+    axhal::console::set_mirror(write);
+    info!("  terminal of {}x{} characters", cols, rows);
+}
@@ -7,7 +7,7 @@ use kspin::SpinNoIrq;
 use log::{debug, error};
 use memory_addr::{va, VirtAddr, PAGE_SIZE_4K};
 
-use crate::{phys_to_bus, BusAddr, DMAInfo};
+use crate::{iommu, phys_to_bus, BusAddr, DMAInfo, DmaDirection};
 
 pub(crate) static ALLOCATOR: SpinNoIrq<DmaAllocator> = SpinNoIrq::new(DmaAllocator::new());
 
@@ -41,6 +41,10 @@ impl DmaAllocator {
         loop {
             if let Ok(data) = self.alloc.alloc(layout) {
                 let cpu_addr = va!(data.as_ptr() as usize);
+                if let Err(e) = map_for_devices(cpu_addr, layout.size()) {
+                    self.alloc.dealloc(data, layout);
+                    return Err(e);
+                }
                 return Ok(DMAInfo {
                     cpu_addr: data,
                     bus_addr: virt_to_bus(cpu_addr),
@@ -79,6 +83,11 @@ impl DmaAllocator {
             num_pages,
             MappingFlags::READ | MappingFlags::WRITE | MappingFlags::UNCACHED,
         )?;
+        if let Err(e) = map_for_devices(vaddr, num_pages * PAGE_SIZE_4K) {
+            global_allocator().dealloc_pages(vaddr_raw, num_pages);
+            let _ = self.update_flags(vaddr, num_pages, MappingFlags::READ | MappingFlags::WRITE);
+            return Err(e);
+        }
         Ok(DMAInfo {
             cpu_addr: unsafe { NonNull::new_unchecked(vaddr_raw as *mut u8) },
             bus_addr: virt_to_bus(vaddr),
@@ -103,6 +112,10 @@ impl DmaAllocator {
 
     /// Gives back the allocated region to the byte allocator.
     pub unsafe fn dealloc_coherent(&mut self, dma: DMAInfo, layout: Layout) {
+        iommu::unmap_range(
+            virt_to_phys(va!(dma.cpu_addr.as_ptr() as usize)),
+            layout.size(),
+        );
         if layout.size() >= PAGE_SIZE_4K {
             let num_pages = layout_pages(&layout);
             let virt_raw = dma.cpu_addr.as_ptr() as usize;
@@ -118,6 +131,14 @@ impl DmaAllocator {
     }
 }
 
+/// Lets the devices access the coherent memory through the IOMMU, if any.
+fn map_for_devices(vaddr: VirtAddr, size: usize) -> AllocResult {
+    iommu::map_range(virt_to_phys(vaddr), size, DmaDirection::Bidirectional).map_err(|e| {
+        error!("IOMMU map fail: {e:?}");
+        AllocError::NoMemory
+    })
+}
+
 const fn virt_to_bus(addr: VirtAddr) -> BusAddr {
     let paddr = virt_to_phys(addr);
     phys_to_bus(paddr)
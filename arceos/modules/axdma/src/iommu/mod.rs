@@ -0,0 +1,168 @@
+//! IOMMU support.
+//!
+//! When an IOMMU is registered with [`register_iommu`], devices attached to
+//! it can only reach the memory that is currently mapped for them: the
+//! coherent memory allocated by [`alloc_coherent`](crate::alloc_coherent)
+//! and the buffers passed to [`map_single`](crate::map_single) or
+//! [`map_sg`](crate::map_sg), until they are unmapped. Without an IOMMU,
+//! mapping is only a matter of address translation and cache maintenance.
+//!
+//! The I/O virtual address of a page is its bus address, so the addresses
+//! handed to the devices are the same whether an IOMMU is present or not.
+//! Pages are reference counted, as several buffers may share a page.
+//!
+//! Currently only the RISC-V IOMMU has a driver ([`riscv::RiscvIommu`]).
+//! Drivers for other IOMMUs (Arm SMMUv3, Intel VT-d) can be plugged in by
+//! implementing the [`Iommu`] trait.
+
+#[cfg(target_arch = "riscv64")]
+pub mod riscv;
+
+use alloc::collections::BTreeMap;
+
+use axerrno::AxResult;
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use log::{info, warn};
+use memory_addr::{PhysAddr, PAGE_SIZE_4K};
+
+use crate::{phys_to_bus, BusAddr, DmaDirection};
+
+/// Operations of an IOMMU.
+///
+/// All the devices attached to the IOMMU share one I/O address space.
+pub trait Iommu: Send + Sync {
+    /// Returns the name of the IOMMU.
+    fn name(&self) -> &'static str;
+
+    /// Puts the DMA of the device `device_id` under the control of the IOMMU.
+    ///
+    /// For PCI devices, the device ID is the requester ID
+    /// (`bus << 8 | device << 3 | function`).
+    fn attach_device(&self, device_id: u32) -> AxResult;
+
+    /// Maps the 4K page at `iova` to the physical page `paddr`, allowing the
+    /// accesses implied by `dir`.
+    ///
+    /// The page may be mapped already, in which case its permissions are
+    /// updated.
+    fn map_page(&self, iova: BusAddr, paddr: PhysAddr, dir: DmaDirection) -> AxResult;
+
+    /// Unmaps the 4K page at `iova`.
+    ///
+    /// The devices may still access the page until [`Iommu::flush`] is called.
+    fn unmap_page(&self, iova: BusAddr) -> AxResult;
+
+    /// Waits for all previous unmappings to take effect.
+    fn flush(&self);
+}
+
+struct PageRef {
+    count: usize,
+    dir: DmaDirection,
+}
+
+static IOMMU: LazyInit<&'static dyn Iommu> = LazyInit::new();
+static MAPPED_PAGES: SpinNoIrq<BTreeMap<PhysAddr, PageRef>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Registers the IOMMU that confines the device DMA.
+///
+/// It must be called before any DMA memory is allocated or mapped, and the
+/// devices must then be attached with [`attach_device`].
+///
+/// # Panics
+///
+/// Panics if an IOMMU is already registered.
+pub fn register_iommu(iommu: &'static dyn Iommu) {
+    info!("Using IOMMU: {}", iommu.name());
+    IOMMU.init_once(iommu);
+}
+
+/// Returns the registered IOMMU, if any.
+pub fn iommu() -> Option<&'static dyn Iommu> {
+    IOMMU.get().copied()
+}
+
+/// Puts the DMA of the device `device_id` under the control of the
+/// registered IOMMU.
+///
+/// It does nothing if there is no IOMMU.
+pub fn attach_device(device_id: u32) -> AxResult {
+    match iommu() {
+        Some(iommu) => iommu.attach_device(device_id),
+        None => Ok(()),
+    }
+}
+
+fn pages(paddr: PhysAddr, size: usize) -> impl Iterator<Item = PhysAddr> {
+    let start = paddr.align_down_4k();
+    let end = (paddr + size.max(1)).align_up_4k();
+    (start.as_usize()..end.as_usize())
+        .step_by(PAGE_SIZE_4K)
+        .map(PhysAddr::from)
+}
+
+/// Makes the physical range `[paddr, paddr + size)` accessible to the
+/// devices.
+pub(crate) fn map_range(paddr: PhysAddr, size: usize, dir: DmaDirection) -> AxResult {
+    let Some(iommu) = iommu() else {
+        return Ok(());
+    };
+    let mut mapped = MAPPED_PAGES.lock();
+    for (i, page) in pages(paddr, size).enumerate() {
+        let res = match mapped.get_mut(&page) {
+            Some(r) => {
+                let merged = r.dir.merge(dir);
+                let res = if merged != r.dir {
+                    iommu.map_page(phys_to_bus(page), page, merged)
+                } else {
+                    Ok(())
+                };
+                if res.is_ok() {
+                    r.count += 1;
+                    r.dir = merged;
+                }
+                res
+            }
+            None => iommu.map_page(phys_to_bus(page), page, dir).map(|_| {
+                mapped.insert(page, PageRef { count: 1, dir });
+            }),
+        };
+        if let Err(e) = res {
+            warn!("IOMMU: failed to map page {:#x}: {:?}", page, e);
+            unmap_pages(iommu, &mut mapped, pages(paddr, size).take(i));
+            iommu.flush();
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Revokes the device access to `[paddr, paddr + size)` that was granted by
+/// [`map_range`].
+pub(crate) fn unmap_range(paddr: PhysAddr, size: usize) {
+    if let Some(iommu) = iommu() {
+        unmap_pages(iommu, &mut MAPPED_PAGES.lock(), pages(paddr, size));
+        iommu.flush();
+    }
+}
+
+fn unmap_pages(
+    iommu: &dyn Iommu,
+    mapped: &mut BTreeMap<PhysAddr, PageRef>,
+    pages: impl Iterator<Item = PhysAddr>,
+) {
+    for page in pages {
+        let Some(r) = mapped.get_mut(&page) else {
+            warn!("IOMMU: unmapping page {:#x} that is not mapped", page);
+            continue;
+        };
+        r.count -= 1;
+        if r.count == 0 {
+            mapped.remove(&page);
+            if let Err(e) = iommu.unmap_page(phys_to_bus(page)) {
+                warn!("IOMMU: failed to unmap page {:#x}: {:?}", page, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Driver for the RISC-V IOMMU (version 1.0 of the specification).
+//!
+//! The devices are translated by the first stage, with one Sv39 page table
+//! shared by all of them, and the second stage is left bare. Device contexts
+//! are kept in a two-level device directory table, which covers the 16-bit
+//! PCI requester IDs.
+
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::boxed::Box;
+use axalloc::global_allocator;
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
+use kspin::SpinNoIrq;
+use log::{info, warn};
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::Iommu;
+use crate::{BusAddr, DmaDirection};
+
+/// The PCI vendor ID of the RISC-V IOMMU emulated by QEMU.
+pub const PCI_VENDOR_ID: u16 = 0x1efd;
+/// The PCI device ID of the RISC-V IOMMU emulated by QEMU.
+pub const PCI_DEVICE_ID: u16 = 0xedf1;
+
+const REG_CAPABILITIES: usize = 0x00;
+const REG_DDTP: usize = 0x10;
+const REG_CQB: usize = 0x18;
+const REG_CQH: usize = 0x20;
+const REG_CQT: usize = 0x24;
+const REG_FQB: usize = 0x28;
+const REG_FQH: usize = 0x30;
+const REG_FQT: usize = 0x34;
+const REG_CQCSR: usize = 0x48;
+const REG_FQCSR: usize = 0x4c;
+
+const CAP_SV39: u64 = 1 << 8;
+const CAP_MSI_FLAT: u64 = 1 << 22;
+
+const DDTP_MODE_OFF: u64 = 0;
+const DDTP_MODE_2LVL: u64 = 3;
+const DDTP_BUSY: u64 = 1 << 4;
+
+const QCSR_EN: u32 = 1 << 0;
+const QCSR_ERRORS: u32 = 0b111 << 8;
+const QCSR_ON: u32 = 1 << 16;
+
+/// Command queue entries of 16 bytes, one page.
+const CQ_LEN: u32 = (PAGE_SIZE_4K / 16) as u32;
+/// Fault queue records of 32 bytes, one page.
+const FQ_LEN: u32 = (PAGE_SIZE_4K / 32) as u32;
+
+const CMD_IOTINVAL_VMA: u64 = 1;
+const CMD_IOFENCE_C: u64 = 2;
+const CMD_IODIR_INVAL_DDT: u64 = 3;
+const IOTINVAL_AV: u64 = 1 << 10;
+const IOTINVAL_PSCV: u64 = 1 << 32;
+const IOFENCE_AV: u64 = 1 << 10;
+const IOFENCE_PR_PW: u64 = 0b11 << 12;
+const IODIR_DV: u64 = 1 << 33;
+
+const DC_TC_V: u64 = 1 << 0;
+const IOSATP_MODE_SV39: u64 = 8 << 60;
+/// The process soft-context ID used for the shared address space.
+const PSCID: u64 = 1;
+
+const TIMEOUT_SPINS: usize = 10_000_000;
+
+struct Inner {
+    pt: PageTable,
+    cq_tail: u32,
+    fence_seq: u32,
+}
+
+/// A RISC-V IOMMU.
+pub struct RiscvIommu {
+    base: VirtAddr,
+    /// The root of the device directory table.
+    ddt: VirtAddr,
+    cq: VirtAddr,
+    fq: VirtAddr,
+    /// The size in bytes of a device context.
+    dc_size: usize,
+    /// Written by the IOMMU when an `IOFENCE.C` command completes.
+    fence_word: Box<AtomicU32>,
+    inner: SpinNoIrq<Inner>,
+}
+
+fn alloc_zeroed_page() -> AxResult<VirtAddr> {
+    let vaddr = global_allocator()
+        .alloc_pages(1, PAGE_SIZE_4K)
+        .map_err(|_| AxError::NoMemory)?;
+    unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, PAGE_SIZE_4K) };
+    Ok(vaddr.into())
+}
+
+/// The PPN field used by the IOMMU registers and the directory entries.
+fn ppn_field(vaddr: VirtAddr) -> u64 {
+    ((virt_to_phys(vaddr).as_usize() >> 12) as u64) << 10
+}
+
+fn wait_for(mut cond: impl FnMut() -> bool) -> AxResult {
+    for _ in 0..TIMEOUT_SPINS {
+        if cond() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    ax_err!(TimedOut)
+}
+
+impl RiscvIommu {
+    /// Initializes the IOMMU whose registers are mapped at `base`.
+    ///
+    /// On success, all the DMA of the devices behind the IOMMU is blocked
+    /// until they are attached with [`Iommu::attach_device`].
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to the register space of a RISC-V IOMMU that is not
+    /// in use by anyone else.
+    pub unsafe fn new(base: NonNull<u8>) -> AxResult<Self> {
+        let caps = (base.as_ptr().add(REG_CAPABILITIES) as *const u64).read_volatile();
+        info!(
+            "RISC-V IOMMU version {}.{}, capabilities {:#x}",
+            (caps >> 4) & 0xf,
+            caps & 0xf,
+            caps
+        );
+        if caps & CAP_SV39 == 0 {
+            warn!("RISC-V IOMMU does not support Sv39");
+            return ax_err!(Unsupported);
+        }
+
+        let inner = Inner {
+            pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            cq_tail: 0,
+            fence_seq: 0,
+        };
+        let iommu = Self {
+            base: VirtAddr::from(base.as_ptr() as usize),
+            ddt: alloc_zeroed_page()?,
+            cq: alloc_zeroed_page()?,
+            fq: alloc_zeroed_page()?,
+            dc_size: if caps & CAP_MSI_FLAT != 0 { 64 } else { 32 },
+            fence_word: Box::new(AtomicU32::new(0)),
+            inner: SpinNoIrq::new(inner),
+        };
+        iommu.enable()?;
+        Ok(iommu)
+    }
+
+    fn read32(&self, off: usize) -> u32 {
+        unsafe { ((self.base + off).as_ptr() as *const u32).read_volatile() }
+    }
+
+    fn write32(&self, off: usize, val: u32) {
+        unsafe { ((self.base + off).as_mut_ptr() as *mut u32).write_volatile(val) }
+    }
+
+    fn read64(&self, off: usize) -> u64 {
+        unsafe { ((self.base + off).as_ptr() as *const u64).read_volatile() }
+    }
+
+    fn write64(&self, off: usize, val: u64) {
+        unsafe { ((self.base + off).as_mut_ptr() as *mut u64).write_volatile(val) }
+    }
+
+    fn enable_queue(&self, csr: usize) -> AxResult {
+        self.write32(csr, QCSR_EN);
+        wait_for(|| self.read32(csr) & QCSR_ON != 0).inspect_err(|_| {
+            warn!("RISC-V IOMMU: queue {:#x} does not turn on", csr);
+        })
+    }
+
+    fn enable(&self) -> AxResult {
+        wait_for(|| self.read64(REG_DDTP) & DDTP_BUSY == 0)?;
+        self.write64(REG_DDTP, DDTP_MODE_OFF);
+
+        // LOG2SZ-1 in the low bits.
+        self.write64(
+            REG_CQB,
+            ppn_field(self.cq) | (CQ_LEN.trailing_zeros() - 1) as u64,
+        );
+        self.write32(REG_CQT, 0);
+        self.enable_queue(REG_CQCSR)?;
+        self.write64(
+            REG_FQB,
+            ppn_field(self.fq) | (FQ_LEN.trailing_zeros() - 1) as u64,
+        );
+        self.write32(REG_FQH, 0);
+        self.enable_queue(REG_FQCSR)?;
+
+        self.write64(REG_DDTP, ppn_field(self.ddt) | DDTP_MODE_2LVL);
+        wait_for(|| self.read64(REG_DDTP) & DDTP_BUSY == 0)
+    }
+
+    fn submit(&self, inner: &mut Inner, cmd: [u64; 2]) -> AxResult {
+        let next = (inner.cq_tail + 1) % CQ_LEN;
+        wait_for(|| self.read32(REG_CQH) != next)?;
+        let slot = (self.cq + inner.cq_tail as usize * 16).as_mut_ptr() as *mut [u64; 2];
+        unsafe { slot.write_volatile(cmd) };
+        inner.cq_tail = next;
+        self.write32(REG_CQT, next);
+        Ok(())
+    }
+
+    /// Waits for all the submitted commands to complete.
+    fn fence(&self, inner: &mut Inner) -> AxResult {
+        inner.fence_seq = inner.fence_seq.wrapping_add(1);
+        let seq = inner.fence_seq;
+        let addr = virt_to_phys(VirtAddr::from(self.fence_word.as_ptr() as usize)).as_usize();
+        self.submit(
+            inner,
+            [
+                CMD_IOFENCE_C | IOFENCE_AV | IOFENCE_PR_PW | ((seq as u64) << 32),
+                addr as u64,
+            ],
+        )?;
+        wait_for(|| self.fence_word.load(Ordering::Acquire) == seq).inspect_err(|_| {
+            warn!(
+                "RISC-V IOMMU: command queue timed out, cqcsr = {:#x}",
+                self.read32(REG_CQCSR)
+            );
+            if self.read32(REG_CQCSR) & QCSR_ERRORS != 0 {
+                // clear the errors (write 1 to clear) so the queue restarts
+                self.write32(REG_CQCSR, QCSR_EN | QCSR_ERRORS);
+            }
+        })
+    }
+
+    /// Returns the device context for `device_id`, allocating the leaf
+    /// directory table on the way if needed.
+    fn device_context(&self, device_id: u32) -> AxResult<*mut [u64; 4]> {
+        let leaf_bits = if self.dc_size == 64 { 6 } else { 7 };
+        let l1_index = (device_id >> leaf_bits) as usize;
+        if l1_index >= 512 {
+            return ax_err!(InvalidInput);
+        }
+        let entry = (self.ddt + l1_index * 8).as_mut_ptr() as *mut u64;
+        let mut val = unsafe { entry.read_volatile() };
+        if val & 1 == 0 {
+            val = ppn_field(alloc_zeroed_page()?) | 1;
+            unsafe { entry.write_volatile(val) };
+        }
+        let leaf = phys_to_virt(PhysAddr::from(((val >> 10) << 12) as usize));
+        let index = (device_id & ((1 << leaf_bits) - 1)) as usize;
+        Ok((leaf + index * self.dc_size).as_mut_ptr() as *mut [u64; 4])
+    }
+
+    fn report_faults(&self) {
+        let mut head = self.read32(REG_FQH);
+        while head != self.read32(REG_FQT) {
+            let record = unsafe {
+                ((self.fq + head as usize * 32).as_ptr() as *const [u64; 4]).read_volatile()
+            };
+            warn!(
+                "RISC-V IOMMU fault: cause {}, device {:#x}, iova {:#x}",
+                record[0] & 0xfff,
+                record[0] >> 40,
+                record[2],
+            );
+            head = (head + 1) % FQ_LEN;
+            self.write32(REG_FQH, head);
+        }
+    }
+}
+
+impl Iommu for RiscvIommu {
+    fn name(&self) -> &'static str {
+        "riscv-iommu"
+    }
+
+    fn attach_device(&self, device_id: u32) -> AxResult {
+        let mut inner = self.inner.lock();
+        let dc = self.device_context(device_id)?;
+        let root = inner.pt.root_paddr().as_usize() as u64;
+        unsafe {
+            // translation attributes, and the first stage page table
+            (*dc)[2] = PSCID << 12;
+            (*dc)[3] = IOSATP_MODE_SV39 | (root >> 12);
+            // second stage is bare
+            (*dc)[1] = 0;
+            core::sync::atomic::fence(Ordering::SeqCst);
+            (*dc)[0] = DC_TC_V;
+        }
+        self.submit(
+            &mut inner,
+            [
+                CMD_IODIR_INVAL_DDT | IODIR_DV | ((device_id as u64) << 40),
+                0,
+            ],
+        )?;
+        self.fence(&mut inner)
+    }
+
+    fn map_page(&self, iova: BusAddr, paddr: PhysAddr, dir: DmaDirection) -> AxResult {
+        // The accesses do not come from privileged mode, so the pages must
+        // be user accessible. A writable page must be readable as well.
+        let flags = match dir {
+            DmaDirection::ToDevice => MappingFlags::READ | MappingFlags::USER,
+            _ => MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        };
+        let vaddr = VirtAddr::from(iova.as_u64() as usize);
+        let mut inner = self.inner.lock();
+        match inner.pt.map(vaddr, paddr, PageSize::Size4K, flags) {
+            Ok(tlb) => {
+                tlb.ignore();
+                Ok(())
+            }
+            Err(PagingError::AlreadyMapped) => {
+                let tlb = inner
+                    .pt
+                    .remap(vaddr, paddr, flags)
+                    .map_err(|_| AxError::BadState)?
+                    .1;
+                tlb.ignore();
+                // the old permissions may be cached
+                self.submit(
+                    &mut inner,
+                    [
+                        CMD_IOTINVAL_VMA | IOTINVAL_AV | IOTINVAL_PSCV | (PSCID << 12),
+                        (iova.as_u64() >> 12) << 10,
+                    ],
+                )
+            }
+            Err(PagingError::NoMemory) => ax_err!(NoMemory),
+            Err(_) => ax_err!(InvalidInput),
+        }
+    }
+
+    fn unmap_page(&self, iova: BusAddr) -> AxResult {
+        let mut inner = self.inner.lock();
+        let (_, _, tlb) = inner
+            .pt
+            .unmap(VirtAddr::from(iova.as_u64() as usize))
+            .map_err(|_| AxError::NotFound)?;
+        tlb.ignore();
+        self.submit(
+            &mut inner,
+            [
+                CMD_IOTINVAL_VMA | IOTINVAL_AV | IOTINVAL_PSCV | (PSCID << 12),
+                (iova.as_u64() >> 12) << 10,
+            ],
+        )
+    }
+
+    fn flush(&self) {
+        if let Err(e) = self.fence(&mut self.inner.lock()) {
+            warn!("RISC-V IOMMU: flush failed: {:?}", e);
+        }
+        self.report_faults();
+    }
+}
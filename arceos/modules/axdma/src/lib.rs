@@ -1,10 +1,17 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) global DMA allocator.
+//!
+//! Besides the coherent memory allocator, it provides the streaming DMA
+//! mapping API ([`map_single`], [`map_sg`] and the sync operations), which
+//! programs the [`iommu`] when there is one.
 
 #![no_std]
 
 extern crate alloc;
 
 mod dma;
+mod map;
+
+pub mod iommu;
 
 use core::{alloc::Layout, ptr::NonNull};
 
@@ -13,6 +20,11 @@ use memory_addr::PhysAddr;
 
 use self::dma::ALLOCATOR;
 
+pub use self::map::{
+    map_sg, map_single, sync_sg_for_cpu, sync_sg_for_device, sync_single_for_cpu,
+    sync_single_for_device, unmap_sg, unmap_single, DmaDirection, SgEntry,
+};
+
 /// Converts a physical address to a bus address.
 ///
 /// It assumes that there is a linear mapping with the offset
@@ -24,6 +36,14 @@ pub const fn phys_to_bus(paddr: PhysAddr) -> BusAddr {
     BusAddr::new((paddr.as_usize() + axconfig::PHYS_BUS_OFFSET) as u64)
 }
 
+/// Converts a bus address to a physical address.
+///
+/// It is the reverse of [`phys_to_bus`].
+#[inline]
+pub const fn bus_to_phys(baddr: BusAddr) -> PhysAddr {
+    PhysAddr::from_usize(baddr.as_u64() as usize - axconfig::PHYS_BUS_OFFSET)
+}
+
 /// Allocates **coherent** memory that meets Direct Memory Access (DMA) requirements.
 ///
 /// This function allocates a block of memory through the global allocator. The memory pages must be contiguous, undivided, and have consistent read and write access.
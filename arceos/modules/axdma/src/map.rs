@@ -0,0 +1,157 @@
+use axerrno::AxResult;
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use memory_addr::VirtAddr;
+
+use crate::{bus_to_phys, iommu, phys_to_bus, BusAddr};
+
+/// The direction of a streaming DMA transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// The device reads the buffer.
+    ToDevice,
+    /// The device writes the buffer.
+    FromDevice,
+    /// The device both reads and writes the buffer.
+    Bidirectional,
+}
+
+impl DmaDirection {
+    /// Returns the direction that allows the accesses of both `self` and
+    /// `other`.
+    pub const fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::ToDevice, Self::ToDevice) => Self::ToDevice,
+            (Self::FromDevice, Self::FromDevice) => Self::FromDevice,
+            _ => Self::Bidirectional,
+        }
+    }
+}
+
+/// An entry of a scatter-gather list.
+///
+/// It describes one physically contiguous piece of a buffer, its bus
+/// address is filled in by [`map_sg`].
+#[derive(Debug, Clone, Copy)]
+pub struct SgEntry {
+    /// The virtual address of the piece, in the linear mapping.
+    pub vaddr: VirtAddr,
+    /// The length of the piece in bytes.
+    pub len: usize,
+    /// The address of the piece that the device uses.
+    pub bus_addr: BusAddr,
+}
+
+impl SgEntry {
+    /// Creates an unmapped entry for `len` bytes at `vaddr`.
+    pub const fn new(vaddr: VirtAddr, len: usize) -> Self {
+        Self {
+            vaddr,
+            len,
+            bus_addr: BusAddr::new(0),
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn for_each_cache_line(vaddr: VirtAddr, len: usize, f: fn(VirtAddr)) {
+    const CACHE_LINE_SIZE: usize = 64;
+    let start = vaddr.as_usize() & !(CACHE_LINE_SIZE - 1);
+    for line in (start..vaddr.as_usize() + len).step_by(CACHE_LINE_SIZE) {
+        f(line.into());
+    }
+}
+
+fn sync_for_device(vaddr: VirtAddr, len: usize, dir: DmaDirection) {
+    #[cfg(target_arch = "aarch64")]
+    match dir {
+        // write back the data for the device
+        DmaDirection::ToDevice => for_each_cache_line(vaddr, len, axhal::arch::clean_dcache_line),
+        // also drop the lines, so no dirty line is written back over the
+        // data from the device
+        _ => for_each_cache_line(vaddr, len, axhal::arch::clean_flush_dcache_line),
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    let _ = (vaddr, len, dir);
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+fn sync_for_cpu(vaddr: VirtAddr, len: usize, dir: DmaDirection) {
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    #[cfg(target_arch = "aarch64")]
+    if dir != DmaDirection::ToDevice {
+        // drop the lines speculatively loaded during the transfer
+        for_each_cache_line(vaddr, len, axhal::arch::flush_dcache_line);
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    let _ = (vaddr, len, dir);
+}
+
+/// Maps a buffer for a streaming DMA transfer, and returns its bus address.
+///
+/// The buffer must be in the linear mapping, so that it is physically
+/// contiguous. The CPU must not touch the buffer until it is unmapped with
+/// [`unmap_single`] or synchronized with [`sync_single_for_cpu`].
+pub fn map_single(vaddr: VirtAddr, len: usize, dir: DmaDirection) -> AxResult<BusAddr> {
+    let paddr = virt_to_phys(vaddr);
+    sync_for_device(vaddr, len, dir);
+    iommu::map_range(paddr, len, dir)?;
+    Ok(phys_to_bus(paddr))
+}
+
+/// Unmaps a buffer mapped by [`map_single`], giving it back to the CPU.
+pub fn unmap_single(bus_addr: BusAddr, len: usize, dir: DmaDirection) {
+    let paddr = bus_to_phys(bus_addr);
+    iommu::unmap_range(paddr, len);
+    sync_for_cpu(phys_to_virt(paddr), len, dir);
+}
+
+/// Makes the data written by the device to a mapped buffer visible to the
+/// CPU, without unmapping it.
+pub fn sync_single_for_cpu(bus_addr: BusAddr, len: usize, dir: DmaDirection) {
+    sync_for_cpu(phys_to_virt(bus_to_phys(bus_addr)), len, dir);
+}
+
+/// Makes the data written by the CPU to a mapped buffer visible to the
+/// device, after a [`sync_single_for_cpu`].
+pub fn sync_single_for_device(bus_addr: BusAddr, len: usize, dir: DmaDirection) {
+    sync_for_device(phys_to_virt(bus_to_phys(bus_addr)), len, dir);
+}
+
+/// Maps all the entries of a scatter-gather list for a streaming DMA
+/// transfer, filling in their bus addresses.
+///
+/// If an entry fails to map, the entries before it are unmapped.
+pub fn map_sg(sg: &mut [SgEntry], dir: DmaDirection) -> AxResult {
+    let mut mapped = 0;
+    let res = sg.iter_mut().try_for_each(|entry| {
+        entry.bus_addr = map_single(entry.vaddr, entry.len, dir)?;
+        mapped += 1;
+        Ok(())
+    });
+    if res.is_err() {
+        unmap_sg(&sg[..mapped], dir);
+    }
+    res
+}
+
+/// Unmaps a scatter-gather list mapped by [`map_sg`].
+pub fn unmap_sg(sg: &[SgEntry], dir: DmaDirection) {
+    for entry in sg {
+        unmap_single(entry.bus_addr, entry.len, dir);
+    }
+}
+
+/// Calls [`sync_single_for_cpu`] on all the entries of a scatter-gather list.
+pub fn sync_sg_for_cpu(sg: &[SgEntry], dir: DmaDirection) {
+    for entry in sg {
+        sync_for_cpu(entry.vaddr, entry.len, dir);
+    }
+}
+
+/// Calls [`sync_single_for_device`] on all the entries of a scatter-gather
+/// list.
+pub fn sync_sg_for_device(sg: &[SgEntry], dir: DmaDirection) {
+    for entry in sg {
+        sync_for_device(entry.vaddr, entry.len, dir);
+    }
+}
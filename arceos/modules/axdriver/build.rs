@@ -1,6 +1,8 @@
 const NET_DEV_FEATURES: &[&str] = &["ixgbe", "virtio-net"];
-const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "bcm2835-sdhci", "virtio-blk"];
+const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "sdhci", "bcm2835-sdhci", "virtio-blk"];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
+const SOUND_DEV_FEATURES: &[&str] = &["virtio-snd"];
+const VSOCK_DEV_FEATURES: &[&str] = &["virtio-vsock"];
 
 fn make_cfg_values(str_list: &[&str]) -> String {
     str_list
@@ -36,14 +38,17 @@ fn main() {
         ("net", NET_DEV_FEATURES),
         ("block", BLOCK_DEV_FEATURES),
         ("display", DISPLAY_DEV_FEATURES),
+        ("sound", SOUND_DEV_FEATURES),
+        ("vsock", VSOCK_DEV_FEATURES),
     ] {
         if !has_feature(dev_kind) {
             continue;
         }
 
         let mut selected = false;
+        // The drivers built in but not enabled on the board are left out.
         for feat in feat_list {
-            if has_feature(feat) {
+            if has_feature(feat) && axconfig::driver_enabled(feat) {
                 enable_cfg(&format!("{dev_kind}_dev"), feat);
                 selected = true;
                 if !is_dyn {
@@ -72,4 +77,12 @@ fn main() {
         "cargo::rustc-check-cfg=cfg(display_dev, values({}, \"dummy\"))",
         make_cfg_values(DISPLAY_DEV_FEATURES)
     );
+    println!(
+        "cargo::rustc-check-cfg=cfg(sound_dev, values({}, \"dummy\"))",
+        make_cfg_values(SOUND_DEV_FEATURES)
+    );
+    println!(
+        "cargo::rustc-check-cfg=cfg(vsock_dev, values({}, \"dummy\"))",
+        make_cfg_values(VSOCK_DEV_FEATURES)
+    );
 }
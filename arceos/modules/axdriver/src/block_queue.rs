@@ -0,0 +1,300 @@
+//! Asynchronous submission of the block requests, with completion callbacks.
+//!
+//! [`BlockDriverOps`] reads and writes synchronously, the caller waits for
+//! each request. [`AsyncBlockDriverOps`] queues the requests instead, up to
+//! the queue depth of the device, and calls the [`Completion`] of each one
+//! once done, so that the caller computes meanwhile and keeps several
+//! requests in flight. The completions run when the driver is polled, e.g.
+//! from its IRQ handler or from a task of the filesystem. A [`CompletionSlot`]
+//! keeps the result for the submitter, and wakes its [`Waker`] if it waits
+//! in a future.
+//!
+//! A driver with a hardware queue implements the trait itself. [`BlockQueue`]
+//! implements it over any [`AxBlockDevice`]: the requests are run in order
+//! when it is polled, and the adjacent ones of the same kind are merged into
+//! one request to the device.
+//!
+//! [`BlockDriverOps`]: axdriver_block::BlockDriverOps
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec, vec::Vec};
+use core::task::Waker;
+
+use axdriver_base::{DevError, DevResult};
+use axdriver_block::BlockDriverOps;
+use kspin::SpinNoIrq;
+
+use crate::AxBlockDevice;
+
+/// The most blocks of a request merged from the adjacent ones.
+const MAX_MERGED_BLOCKS: usize = 256;
+
+/// The kind of a [`BlockRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockOp {
+    /// Reads the blocks into the buffer.
+    Read,
+    /// Writes the buffer to the blocks.
+    Write,
+    /// Makes the blocks written before persistent, the buffer is empty.
+    Flush,
+}
+
+/// A request to a block device.
+#[derive(Debug)]
+pub struct BlockRequest {
+    /// The kind of the request.
+    pub op: BlockOp,
+    /// The first block.
+    pub block_id: u64,
+    /// The data, of a multiple of the block size.
+    pub buf: Vec<u8>,
+}
+
+impl BlockRequest {
+    /// A request reading `len` bytes from the block `block_id`.
+    pub fn read(block_id: u64, len: usize) -> Self {
+        Self {
+            op: BlockOp::Read,
+            block_id,
+            buf: vec![0; len],
+        }
+    }
+
+    /// A request writing `buf` to the block `block_id`.
+    pub fn write(block_id: u64, buf: Vec<u8>) -> Self {
+        Self {
+            op: BlockOp::Write,
+            block_id,
+            buf,
+        }
+    }
+
+    /// A flush request.
+    pub fn flush() -> Self {
+        Self {
+            op: BlockOp::Flush,
+            block_id: 0,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// The function called with a request and its result once it is done.
+pub type Completion = Box<dyn FnOnce(BlockRequest, DevResult) + Send>;
+
+/// Operations of the block devices accepting the requests asynchronously.
+pub trait AsyncBlockDriverOps: Send {
+    /// The number of blocks of the device.
+    fn num_blocks(&self) -> u64;
+
+    /// The size of a block, in bytes.
+    fn block_size(&self) -> usize;
+
+    /// The most requests in flight at once.
+    fn queue_depth(&self) -> usize;
+
+    /// The number of the requests submitted and not completed yet.
+    fn in_flight(&self) -> usize;
+
+    /// Submits `req`, `done` is called with it once it is done.
+    ///
+    /// Returns them back if the queue is full, to be submitted again after
+    /// some requests are completed.
+    fn submit(
+        &mut self,
+        req: BlockRequest,
+        done: Completion,
+    ) -> Result<(), (BlockRequest, Completion)>;
+
+    /// Completes the requests done by the device, calling their completions,
+    /// returns their number.
+    fn poll(&mut self) -> usize;
+}
+
+/// The result of a request for its submitter, see [`CompletionSlot::completion`].
+pub struct CompletionSlot {
+    result: SpinNoIrq<Option<(BlockRequest, DevResult)>>,
+    waker: SpinNoIrq<Option<Waker>>,
+}
+
+impl CompletionSlot {
+    /// Creates an empty slot.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            result: SpinNoIrq::new(None),
+            waker: SpinNoIrq::new(None),
+        })
+    }
+
+    /// The completion keeping the request and its result in the slot, and
+    /// waking the registered waker.
+    pub fn completion(self: &Arc<Self>) -> Completion {
+        let slot = self.clone();
+        Box::new(move |req, res| {
+            *slot.result.lock() = Some((req, res));
+            if let Some(waker) = slot.waker.lock().take() {
+                waker.wake();
+            }
+        })
+    }
+
+    /// Whether the request is completed and its result not taken yet.
+    pub fn is_done(&self) -> bool {
+        self.result.lock().is_some()
+    }
+
+    /// Takes the request and its result, if it is completed.
+    pub fn take(&self) -> Option<(BlockRequest, DevResult)> {
+        self.result.lock().take()
+    }
+
+    /// Wakes `waker` once the request is completed, at once if it already
+    /// is.
+    pub fn register(&self, waker: &Waker) {
+        *self.waker.lock() = Some(waker.clone());
+        if self.is_done() {
+            if let Some(waker) = self.waker.lock().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A queue of requests to a synchronous block device, see the
+/// [module documentation](self).
+pub struct BlockQueue {
+    dev: AxBlockDevice,
+    depth: usize,
+    queue: VecDeque<(BlockRequest, Completion)>,
+}
+
+impl BlockQueue {
+    /// Creates a queue of `depth` requests at most to `dev`.
+    pub fn new(dev: AxBlockDevice, depth: usize) -> Self {
+        Self {
+            dev,
+            depth: depth.max(1),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the device, after completing the requests in flight.
+    pub fn into_inner(mut self) -> AxBlockDevice {
+        self.poll();
+        self.dev
+    }
+
+    fn blocks(&self, req: &BlockRequest) -> usize {
+        req.buf.len() / self.dev.block_size()
+    }
+
+    /// Whether `next` continues `batch`, of reads or of writes.
+    fn can_merge(&self, batch: &[(BlockRequest, Completion)], next: &BlockRequest) -> bool {
+        let last = &batch.last().unwrap().0;
+        let blocks: usize = batch.iter().map(|(req, _)| self.blocks(req)).sum();
+        last.op == next.op
+            && last.op != BlockOp::Flush
+            && next.block_id == last.block_id + self.blocks(last) as u64
+            && blocks + self.blocks(next) <= MAX_MERGED_BLOCKS
+    }
+
+    fn run_one(&mut self, (mut req, done): (BlockRequest, Completion)) {
+        let res = match req.op {
+            BlockOp::Read => self.dev.read_block(req.block_id, &mut req.buf),
+            BlockOp::Write => self.dev.write_block(req.block_id, &req.buf),
+            BlockOp::Flush => self.dev.flush(),
+        };
+        done(req, res);
+    }
+
+    /// Runs the adjacent requests of `batch` as one. If it fails, they are
+    /// run again one by one, so that each one gets its own result.
+    fn run_merged(&mut self, batch: Vec<(BlockRequest, Completion)>) {
+        let op = batch[0].0.op;
+        let block_id = batch[0].0.block_id;
+        let len = batch.iter().map(|(req, _)| req.buf.len()).sum();
+        let res = match op {
+            BlockOp::Read => {
+                let mut buf = vec![0; len];
+                self.dev.read_block(block_id, &mut buf).map(|_| buf)
+            }
+            _ => {
+                let mut buf = Vec::with_capacity(len);
+                for (req, _) in batch.iter() {
+                    buf.extend_from_slice(&req.buf);
+                }
+                self.dev.write_block(block_id, &buf).map(|_| buf)
+            }
+        };
+        let Ok(buf) = res else {
+            for request in batch {
+                self.run_one(request);
+            }
+            return;
+        };
+        let mut offset = 0;
+        for (mut req, done) in batch {
+            let len = req.buf.len();
+            if op == BlockOp::Read {
+                req.buf.copy_from_slice(&buf[offset..offset + len]);
+            }
+            offset += len;
+            done(req, Ok(()));
+        }
+    }
+}
+
+impl AsyncBlockDriverOps for BlockQueue {
+    fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks()
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.depth
+    }
+
+    fn in_flight(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn submit(
+        &mut self,
+        req: BlockRequest,
+        done: Completion,
+    ) -> Result<(), (BlockRequest, Completion)> {
+        if self.queue.len() >= self.depth {
+            return Err((req, done));
+        }
+        if req.buf.len() % self.dev.block_size() != 0 {
+            done(req, Err(DevError::InvalidParam));
+            return Ok(());
+        }
+        self.queue.push_back((req, done));
+        Ok(())
+    }
+
+    fn poll(&mut self) -> usize {
+        let mut completed = 0;
+        while let Some(first) = self.queue.pop_front() {
+            let mut batch = vec![first];
+            while self
+                .queue
+                .front()
+                .is_some_and(|(next, _)| self.can_merge(&batch, next))
+            {
+                batch.push(self.queue.pop_front().unwrap());
+            }
+            completed += batch.len();
+            if batch.len() == 1 {
+                self.run_one(batch.pop().unwrap());
+            } else {
+                self.run_merged(batch);
+            }
+        }
+        completed
+    }
+}
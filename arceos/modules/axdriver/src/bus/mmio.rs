@@ -1,11 +1,15 @@
 #[allow(unused_imports)]
-use crate::{prelude::*, AllDevices};
+use crate::{model, prelude::*, AllDevices};
 
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
+    #[cfg_attr(not(feature = "virtio"), allow(unused_variables))]
+    pub(crate) fn probe_bus_devices(&mut self, platform: model::DeviceId) {
         // TODO: parse device tree
         #[cfg(feature = "virtio")]
         for reg in axconfig::VIRTIO_MMIO_REGIONS {
+            let name = alloc::format!("{:x}.virtio_mmio", reg.0);
+            let node =
+                model::register_device(name, model::Subsystem::Bus("mmio"), Some(platform)).ok();
             for_each_drivers!(type Driver, {
                 if let Some(dev) = Driver::probe_mmio(reg.0, reg.1) {
                     info!(
@@ -14,10 +18,14 @@ impl AllDevices {
                         reg.0, reg.0 + reg.1,
                         dev.device_name(),
                     );
-                    self.add_device(dev);
+                    self.add_device(dev, node);
                     continue; // skip to the next device
                 }
             });
+            // nothing there
+            if let Some(node) = node {
+                let _ = model::remove_device(node);
+            }
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::{prelude::*, AllDevices};
+use crate::{model, prelude::*, AllDevices};
 use axdriver_pci::{
     BarInfo, Cam, Command, DeviceFunction, HeaderType, MemoryBarType, PciRangeAllocator, PciRoot,
 };
@@ -82,8 +82,56 @@ fn config_pci_device(
     Ok(())
 }
 
+/// The requester ID of the device, that identifies it to the IOMMU.
+#[cfg(feature = "iommu")]
+const fn requester_id(bdf: DeviceFunction) -> u32 {
+    ((bdf.bus as u32) << 8) | ((bdf.device as u32) << 3) | bdf.function as u32
+}
+
+/// Finds and enables the IOMMU that sits on the PCI bus, before any other
+/// device issues DMA.
+#[cfg(all(feature = "iommu", target_arch = "riscv64"))]
+fn probe_iommu(root: &mut PciRoot, allocator: &mut Option<PciRangeAllocator>) -> Option<u32> {
+    for bus in 0..=axconfig::PCI_BUS_END as u8 {
+        for (bdf, dev_info) in root.enumerate_bus(bus) {
+            if dev_info.vendor_id == axdma::iommu::riscv::PCI_VENDOR_ID
+                && dev_info.device_id == axdma::iommu::riscv::PCI_DEVICE_ID
+            {
+                if let Err(e) = config_pci_device(root, bdf, allocator) {
+                    warn!("failed to enable the IOMMU at {}: {:?}", bdf, e);
+                    return None;
+                }
+                let BarInfo::Memory { address, .. } = root.bar_info(bdf, 0).unwrap() else {
+                    return None;
+                };
+                let base = phys_to_virt((address as usize).into());
+                let base = core::ptr::NonNull::new(base.as_mut_ptr()).unwrap();
+                match unsafe { axdma::iommu::riscv::RiscvIommu::new(base) } {
+                    Ok(iommu) => {
+                        axdma::iommu::register_iommu(alloc::boxed::Box::leak(
+                            alloc::boxed::Box::new(iommu),
+                        ));
+                        return Some(requester_id(bdf));
+                    }
+                    Err(e) => {
+                        warn!("failed to initialize the IOMMU at {}: {:?}", bdf, e);
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(all(feature = "iommu", not(target_arch = "riscv64")))]
+fn probe_iommu(_root: &mut PciRoot, _allocator: &mut Option<PciRangeAllocator>) -> Option<u32> {
+    warn!("no IOMMU driver for this architecture");
+    None
+}
+
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
+    pub(crate) fn probe_bus_devices(&mut self, _platform: model::DeviceId) {
         let base_vaddr = phys_to_virt(axconfig::PCI_ECAM_BASE.into());
         let mut root = unsafe { PciRoot::new(base_vaddr.as_mut_ptr(), Cam::Ecam) };
 
@@ -92,13 +140,40 @@ impl AllDevices {
             .get(1)
             .map(|range| PciRangeAllocator::new(range.0 as u64, range.1 as u64));
 
+        #[cfg(feature = "iommu")]
+        let iommu_id = probe_iommu(&mut root, &mut allocator);
+
+        let host = model::register_device("pci0000:00", model::Subsystem::Bus("pci"), None).ok();
+
         for bus in 0..=axconfig::PCI_BUS_END as u8 {
             for (bdf, dev_info) in root.enumerate_bus(bus) {
                 debug!("PCI {}: {}", bdf, dev_info);
                 if dev_info.header_type != HeaderType::Standard {
                     continue;
                 }
-                match config_pci_device(&mut root, bdf, &mut allocator) {
+                let name =
+                    alloc::format!("0000:{:02x}:{:02x}.{}", bdf.bus, bdf.device, bdf.function);
+                let node = model::register_device(name, model::Subsystem::Bus("pci"), host).ok();
+                #[cfg(feature = "iommu")]
+                if iommu_id.is_some() {
+                    if iommu_id == Some(requester_id(bdf)) {
+                        continue;
+                    }
+                    if let Err(e) = axdma::iommu::attach_device(requester_id(bdf)) {
+                        warn!(
+                            "failed to attach PCI device at {} to the IOMMU: {:?}",
+                            bdf, e
+                        );
+                        continue;
+                    }
+                }
+                let res = config_pci_device(&mut root, bdf, &mut allocator);
+                #[cfg(feature = "sriov")]
+                if res.is_ok() {
+                    let vfs = crate::sriov::enable_vfs(bdf, dev_info.vendor_id, &mut allocator);
+                    self.add_vfs(vfs, node);
+                }
+                match res {
                     Ok(_) => for_each_drivers!(type Driver, {
                         if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
                             info!(
@@ -107,7 +182,7 @@ impl AllDevices {
                                 bdf,
                                 dev.device_name(),
                             );
-                            self.add_device(dev);
+                            self.add_device(dev, node);
                             continue; // skip to the next device
                         }
                     }),
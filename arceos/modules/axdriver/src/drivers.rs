@@ -31,6 +31,12 @@ pub trait DriverProbe {
     ) -> Option<AxDeviceEnum> {
         None
     }
+
+    /// Probes an SR-IOV virtual function assigned to the host.
+    #[cfg(feature = "sriov")]
+    fn probe_vf(_vf: &crate::sriov::VirtualFunction) -> Option<AxDeviceEnum> {
+        None
+    }
 }
 
 #[cfg(net_dev = "virtio-net")]
@@ -51,6 +57,12 @@ register_display_driver!(
     <virtio::VirtIoGpu as VirtIoDevMeta>::Device
 );
 
+#[cfg(sound_dev = "virtio-snd")]
+register_sound_driver!(virtio::VirtIoSndDriver, virtio::VirtIoSndDev);
+
+#[cfg(vsock_dev = "virtio-vsock")]
+register_vsock_driver!(virtio::VirtIoVsockDriver, virtio::VirtIoVsockDev);
+
 cfg_if::cfg_if! {
     if #[cfg(block_dev = "ramdisk")] {
         pub struct RamDiskDriver;
@@ -81,6 +93,27 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(block_dev = "sdhci")] {
+        pub struct SdhciDriver;
+        register_block_driver!(SdhciDriver, crate::sdhci::SdhciBlk);
+
+        impl DriverProbe for SdhciDriver {
+            fn probe_global() -> Option<AxDeviceEnum> {
+                let paddr = crate::sdhci::base_paddr()?;
+                let base = axhal::mem::phys_to_virt(paddr.into()).as_usize();
+                match crate::sdhci::SdhciBlk::new(base) {
+                    Ok(dev) => Some(AxDeviceEnum::from_block(dev)),
+                    Err(err) => {
+                        warn!("sdhci: failed to set up the controller at {:#x}: {:?}", paddr, err);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
 cfg_if::cfg_if! {
     if #[cfg(net_dev = "ixgbe")] {
         use crate::ixgbe::IxgbeHalImpl;
@@ -100,3 +100,88 @@ cfg_if! {
         }
     }
 }
+
+cfg_if! {
+    if #[cfg(sound_dev = "dummy")] {
+        use crate::sound::{PcmParams, SoundDriverOps};
+
+        pub struct DummySoundDev;
+        pub struct DummySoundDriver;
+        register_sound_driver!(DummySoundDriver, DummySoundDev);
+
+        impl BaseDriverOps for DummySoundDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Char
+            }
+            fn device_name(&self) -> &str {
+                "dummy-sound"
+            }
+        }
+
+        impl SoundDriverOps for DummySoundDev {
+            fn num_output_streams(&self) -> usize {
+                0
+            }
+            fn open(&mut self, _: usize, _: &PcmParams) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn write(&mut self, _: usize, _: &[u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn pending_bytes(&mut self, _: usize) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn close(&mut self, _: usize) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(vsock_dev = "dummy")] {
+        use crate::vsock::{VsockAddr, VsockConnId, VsockDriverOps, VsockEvent};
+
+        pub struct DummyVsockDev;
+        pub struct DummyVsockDriver;
+        register_vsock_driver!(DummyVsockDriver, DummyVsockDev);
+
+        impl BaseDriverOps for DummyVsockDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Char
+            }
+            fn device_name(&self) -> &str {
+                "dummy-vsock"
+            }
+        }
+
+        impl VsockDriverOps for DummyVsockDev {
+            fn guest_cid(&self) -> u64 {
+                0
+            }
+            fn listen(&mut self, _: u32) {}
+            fn unlisten(&mut self, _: u32) {}
+            fn connect(&mut self, _: VsockAddr, _: u32) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn send(&mut self, _: VsockConnId, _: &[u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn recv(&mut self, _: VsockConnId, _: &mut [u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn recv_avail(&mut self, _: VsockConnId) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn shutdown(&mut self, _: VsockConnId) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn abort(&mut self, _: VsockConnId) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn poll_event(&mut self) -> DevResult<Option<VsockEvent>> {
+                Ok(None)
+            }
+        }
+    }
+}
@@ -8,8 +8,17 @@
 //! driver they want.
 //!
 //! For each device category (i.e., net, block, display, etc.), an unified type
-//! is used to represent all devices in that category. Currently, there are 3
-//! categories: [`AxNetDevice`], [`AxBlockDevice`], and [`AxDisplayDevice`].
+//! is used to represent all devices in that category. Currently, there are 5
+//! categories: [`AxNetDevice`], [`AxBlockDevice`], [`AxDisplayDevice`],
+//! [`AxSoundDevice`], and [`AxVsockDevice`].
+//!
+//! Besides, all the devices, including the bus devices they are found on, are
+//! registered in the [`model`], which keeps the parent-child relations between
+//! them and drives their power management. The `i`-th device of a category is
+//! named after it (e.g., `net0`) there.
+//!
+//! The drivers read their tunables from the [`params`], set on the command
+//! line of the kernel or at runtime.
 //!
 //! # Concepts
 //!
@@ -32,8 +41,11 @@
 //! |-|-|-|
 //! | Block | `ramdisk` | A RAM disk that stores data in a vector |
 //! | Block | `virtio-blk` | VirtIO block device |
-//! | Network | `virtio-net` | VirtIO network device |
+//! | Block | `sdhci` | SD card behind a standard SD host controller, with ADMA2 and UHS-I |
+//! | Network | `virtio-net` | VirtIO network device, with the jumbo frames |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
+//! | Sound | `virtio-snd` | VirtIO sound device, for the PCM playback |
+//! | Vsock | `virtio-vsock` | VirtIO socket device, for the VM sockets |
 //!
 //! # Other Cargo Features
 //!
@@ -42,12 +54,23 @@
 //! - `bus-pci`: use PCI bus to probe all PCI devices. This feature is
 //!    enabeld by default.
 //! - `virtio`: use VirtIO devices. This is enabled if any of `virtio-blk`,
-//!   `virtio-net` or `virtio-gpu` is enabled.
+//!   `virtio-net`, `virtio-gpu`, `virtio-snd` or `virtio-vsock` is enabled.
 //! - `net`: use network devices. This is enabled if any feature of network
 //!    devices is selected. If this feature is enabled without any network device
-//!    features, a dummy struct is used for [`AxNetDevice`].
-//! - `block`: use block storage devices. Similar to the `net` feature.
+//!    features, a dummy struct is used for [`AxNetDevice`]. Their MTU is
+//!    given by [`net::max_mtu`].
+//! - `block`: use block storage devices. Similar to the `net` feature. They
+//!   also accept the requests asynchronously through [`block_queue`].
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `sound`: use sound devices, see [`sound::SoundDriverOps`]. Similar to the
+//!   `net` feature.
+//! - `vsock`: use socket devices of the VM sockets, see
+//!   [`vsock::VsockDriverOps`]. Similar to the `net` feature.
+//! - `sriov`: enable the virtual functions of the SR-IOV capable PCI devices,
+//!   see [`sriov`].
+//! - `iommu`: probe the IOMMU on the PCI bus (only the RISC-V IOMMU for now),
+//!   and confine the DMA of the PCI devices to the buffers mapped by
+//!   [axdma].
 //!
 //! [`VirtioNetDev`]: axdriver_virtio::VirtIoNetDev
 //! [`Box<dyn NetDriverOps>`]: axdriver_net::NetDriverOps
@@ -61,7 +84,6 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
 extern crate alloc;
 
 #[macro_use]
@@ -74,11 +96,31 @@ mod structs;
 
 #[cfg(feature = "virtio")]
 mod virtio;
+#[cfg(net_dev = "virtio-net")]
+mod virtio_net;
+#[cfg(feature = "virtio-snd")]
+mod virtio_snd;
+#[cfg(feature = "virtio-vsock")]
+mod virtio_vsock;
 
 #[cfg(feature = "ixgbe")]
 mod ixgbe;
+#[cfg(feature = "sdhci")]
+mod sdhci;
 
+#[cfg(feature = "block")]
+pub mod block_queue;
+pub mod model;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod params;
 pub mod prelude;
+#[cfg(feature = "sound")]
+pub mod sound;
+#[cfg(feature = "sriov")]
+pub mod sriov;
+#[cfg(feature = "vsock")]
+pub mod vsock;
 
 #[allow(unused_imports)]
 use self::prelude::*;
@@ -90,6 +132,10 @@ pub use self::structs::AxBlockDevice;
 pub use self::structs::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use self::structs::AxNetDevice;
+#[cfg(feature = "sound")]
+pub use self::structs::AxSoundDevice;
+#[cfg(feature = "vsock")]
+pub use self::structs::AxVsockDevice;
 
 /// A structure that contains all device drivers, organized by their category.
 #[derive(Default)]
@@ -103,6 +149,15 @@ pub struct AllDevices {
     /// All graphics device drivers.
     #[cfg(feature = "display")]
     pub display: AxDeviceContainer<AxDisplayDevice>,
+    /// All sound device drivers.
+    #[cfg(feature = "sound")]
+    pub sound: AxDeviceContainer<AxSoundDevice>,
+    /// All vsock device drivers.
+    #[cfg(feature = "vsock")]
+    pub vsock: AxDeviceContainer<AxVsockDevice>,
+    /// All SR-IOV virtual functions, including those for the guests.
+    #[cfg(feature = "sriov")]
+    pub vfs: alloc::vec::Vec<sriov::VirtualFunction>,
 }
 
 impl AllDevices {
@@ -119,6 +174,8 @@ impl AllDevices {
 
     /// Probes all supported devices.
     fn probe(&mut self) {
+        let platform = model::register_device("platform", model::Subsystem::Bus("platform"), None)
+            .expect("failed to register the platform bus");
         for_each_drivers!(type Driver, {
             if let Some(dev) = Driver::probe_global() {
                 info!(
@@ -126,16 +183,48 @@ impl AllDevices {
                     dev.device_type(),
                     dev.device_name(),
                 );
-                self.add_device(dev);
+                self.add_device(dev, Some(platform));
             }
         });
 
-        self.probe_bus_devices();
+        self.probe_bus_devices(platform);
     }
 
-    /// Adds one device into the corresponding container, according to its device category.
+    /// Adds one device into the corresponding container, according to its device category,
+    /// and registers it in the [`model`] under the bus device `parent`.
     #[allow(dead_code)]
-    fn add_device(&mut self, dev: AxDeviceEnum) {
+    fn add_device(&mut self, dev: AxDeviceEnum, parent: Option<model::DeviceId>) {
+        #[allow(unreachable_patterns)]
+        let class = match dev {
+            #[cfg(feature = "net")]
+            AxDeviceEnum::Net(_) => "net",
+            #[cfg(feature = "block")]
+            AxDeviceEnum::Block(_) => "block",
+            #[cfg(feature = "display")]
+            AxDeviceEnum::Display(_) => "display",
+            #[cfg(feature = "sound")]
+            AxDeviceEnum::Sound(_) => "sound",
+            #[cfg(feature = "vsock")]
+            AxDeviceEnum::Vsock(_) => "vsock",
+            _ => unreachable!(),
+        };
+        if let Some(parent) = parent {
+            let _ = model::bind_driver(parent, dev.device_name());
+        }
+        if let Err(e) = model::register_class_device(class, dev.device_name(), parent) {
+            warn!("failed to register the {} device: {:?}", class, e);
+        }
+
+        #[cfg(feature = "event")]
+        axevent::publish(axevent::KernelEvent::DeviceAdded {
+            kind: match dev.device_type() {
+                DeviceType::Net => "net",
+                DeviceType::Block => "block",
+                DeviceType::Display => "display",
+                _ => "unknown",
+            },
+            name: dev.device_name(),
+        });
         match dev {
             #[cfg(feature = "net")]
             AxDeviceEnum::Net(dev) => self.net.push(dev),
@@ -143,6 +232,10 @@ impl AllDevices {
             AxDeviceEnum::Block(dev) => self.block.push(dev),
             #[cfg(feature = "display")]
             AxDeviceEnum::Display(dev) => self.display.push(dev),
+            #[cfg(feature = "sound")]
+            AxDeviceEnum::Sound(dev) => self.sound.push(dev),
+            #[cfg(feature = "vsock")]
+            AxDeviceEnum::Vsock(dev) => self.vsock.push(dev),
         }
     }
 }
@@ -179,6 +272,20 @@ pub fn init_drivers() -> AllDevices {
             debug!("  graphics device {}: {:?}", i, dev.device_name());
         }
     }
+    #[cfg(feature = "sound")]
+    {
+        debug!("number of sound devices: {}", all_devs.sound.len());
+        for (i, dev) in all_devs.sound.iter().enumerate() {
+            debug!("  sound device {}: {:?}", i, dev.device_name());
+        }
+    }
+    #[cfg(feature = "vsock")]
+    {
+        debug!("number of vsock devices: {}", all_devs.vsock.len());
+        for (i, dev) in all_devs.vsock.iter().enumerate() {
+            debug!("  vsock device {}: {:?}", i, dev.device_name());
+        }
+    }
 
     all_devs
 }
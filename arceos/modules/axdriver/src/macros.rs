@@ -26,6 +26,22 @@ macro_rules! register_display_driver {
     };
 }
 
+macro_rules! register_sound_driver {
+    ($driver_type:ty, $device_type:ty) => {
+        /// The unified type of the sound devices.
+        #[cfg(not(feature = "dyn"))]
+        pub type AxSoundDevice = $device_type;
+    };
+}
+
+macro_rules! register_vsock_driver {
+    ($driver_type:ty, $device_type:ty) => {
+        /// The unified type of the vsock devices.
+        #[cfg(not(feature = "dyn"))]
+        pub type AxVsockDevice = $device_type;
+    };
+}
+
 macro_rules! for_each_drivers {
     (type $drv_type:ident, $code:block) => {{
         #[allow(unused_imports)]
@@ -49,6 +65,16 @@ macro_rules! for_each_drivers {
             type $drv_type = <virtio::VirtIoGpu as VirtIoDevMeta>::Driver;
             $code
         }
+        #[cfg(sound_dev = "virtio-snd")]
+        {
+            type $drv_type = virtio::VirtIoSndDriver;
+            $code
+        }
+        #[cfg(vsock_dev = "virtio-vsock")]
+        {
+            type $drv_type = virtio::VirtIoVsockDriver;
+            $code
+        }
         #[cfg(block_dev = "ramdisk")]
         {
             type $drv_type = crate::drivers::RamDiskDriver;
@@ -59,6 +85,11 @@ macro_rules! for_each_drivers {
             type $drv_type = crate::drivers::BcmSdhciDriver;
             $code
         }
+        #[cfg(block_dev = "sdhci")]
+        {
+            type $drv_type = crate::drivers::SdhciDriver;
+            $code
+        }
         #[cfg(net_dev = "ixgbe")]
         {
             type $drv_type = crate::drivers::IxgbeDriver;
@@ -0,0 +1,430 @@
+//! The device model.
+//!
+//! Every device found during the probing is registered as a node in a tree:
+//! the bus controllers (e.g., the PCI host bridge) at the roots, the
+//! functions on the buses under them, and the devices created by the drivers
+//! (e.g., `net0`) at the leaves. Each node belongs to a [`Subsystem`], either
+//! the bus it sits on or the class of the driver device, and records the
+//! driver bound to it.
+//!
+//! The subsystems that take the devices from [`AllDevices`](crate::AllDevices)
+//! can give their nodes [`DevicePm`] callbacks. The power management then
+//! follows the tree: [`suspend_all`] suspends the children before their
+//! parents, [`resume_all`] resumes the parents first, and a device in use
+//! (see [`runtime_get`]) keeps all its ancestors active.
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+
+use axdriver_base::{DevError, DevResult};
+use kspin::SpinNoIrq;
+
+/// The identifier of a device in the model.
+pub type DeviceId = usize;
+
+/// What kind of device a node is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// A device on the named bus (`platform`, `pci`, `mmio`).
+    Bus(&'static str),
+    /// A device created by a driver, of the named class (`net`, `block`,
+    /// `display`, `sound`, `vsock`).
+    Class(&'static str),
+}
+
+/// The power state of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// The device is working.
+    Active,
+    /// The device is suspended because it is idle (runtime PM).
+    RuntimeSuspended,
+    /// The device is suspended with the whole system.
+    Suspended,
+}
+
+/// Power management and removal callbacks of a device.
+///
+/// All of them are called without any lock of the model held.
+pub trait DevicePm: Send + Sync {
+    /// Puts the device to sleep, for a system suspend.
+    fn suspend(&self) -> DevResult {
+        Ok(())
+    }
+
+    /// Wakes the device up after [`DevicePm::suspend`].
+    fn resume(&self) -> DevResult {
+        Ok(())
+    }
+
+    /// Puts the device to sleep when it is idle.
+    fn runtime_suspend(&self) -> DevResult {
+        self.suspend()
+    }
+
+    /// Wakes the device up after [`DevicePm::runtime_suspend`], before it is
+    /// used again.
+    fn runtime_resume(&self) -> DevResult {
+        self.resume()
+    }
+
+    /// Releases the device, which is removed from the model.
+    fn remove(&self) {}
+}
+
+/// A snapshot of a device node.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    /// The identifier of the device.
+    pub id: DeviceId,
+    /// The name of the device, unique among its siblings.
+    pub name: String,
+    /// The path of the device from the root of the tree, such as
+    /// `pci0000:00/0000:00:01.0/net0`.
+    pub path: String,
+    /// The kind of the device.
+    pub subsystem: Subsystem,
+    /// The driver bound to the device.
+    pub driver: Option<String>,
+    /// The parent of the device.
+    pub parent: Option<DeviceId>,
+    /// The power state of the device.
+    pub state: PowerState,
+    /// The number of [`runtime_get`] not yet balanced by [`runtime_put`].
+    pub usage: usize,
+}
+
+struct Node {
+    name: String,
+    subsystem: Subsystem,
+    driver: Option<String>,
+    parent: Option<DeviceId>,
+    children: Vec<DeviceId>,
+    pm: Option<Arc<dyn DevicePm>>,
+    state: PowerState,
+    usage: usize,
+}
+
+struct Model {
+    nodes: BTreeMap<DeviceId, Node>,
+    next_id: DeviceId,
+}
+
+static MODEL: SpinNoIrq<Model> = SpinNoIrq::new(Model {
+    nodes: BTreeMap::new(),
+    next_id: 0,
+});
+
+impl Model {
+    fn node(&self, id: DeviceId) -> DevResult<&Node> {
+        self.nodes.get(&id).ok_or(DevError::InvalidParam)
+    }
+
+    fn node_mut(&mut self, id: DeviceId) -> DevResult<&mut Node> {
+        self.nodes.get_mut(&id).ok_or(DevError::InvalidParam)
+    }
+
+    fn path(&self, id: DeviceId) -> String {
+        let node = &self.nodes[&id];
+        match node.parent {
+            Some(parent) => format!("{}/{}", self.path(parent), node.name),
+            None => node.name.clone(),
+        }
+    }
+
+    /// Returns the subtree of `id` in post-order (children first).
+    fn subtree(&self, id: DeviceId, out: &mut Vec<DeviceId>) {
+        for &child in &self.nodes[&id].children {
+            self.subtree(child, out);
+        }
+        out.push(id);
+    }
+
+    /// Returns all the nodes in post-order.
+    fn post_order(&self) -> Vec<DeviceId> {
+        let mut out = Vec::new();
+        for (&id, node) in &self.nodes {
+            if node.parent.is_none() {
+                self.subtree(id, &mut out);
+            }
+        }
+        out
+    }
+
+    fn info(&self, id: DeviceId) -> DeviceInfo {
+        let node = &self.nodes[&id];
+        DeviceInfo {
+            id,
+            name: node.name.clone(),
+            path: self.path(id),
+            subsystem: node.subsystem,
+            driver: node.driver.clone(),
+            parent: node.parent,
+            state: node.state,
+            usage: node.usage,
+        }
+    }
+}
+
+/// Registers a new device under `parent`, and returns its identifier.
+pub fn register_device(
+    name: impl Into<String>,
+    subsystem: Subsystem,
+    parent: Option<DeviceId>,
+) -> DevResult<DeviceId> {
+    let name = name.into();
+    let mut model = MODEL.lock();
+    if let Some(parent) = parent {
+        let siblings = &model.node(parent)?.children;
+        if siblings.iter().any(|c| model.nodes[c].name == name) {
+            return Err(DevError::AlreadyExists);
+        }
+    }
+    let id = model.next_id;
+    model.next_id += 1;
+    model.nodes.insert(
+        id,
+        Node {
+            name,
+            subsystem,
+            driver: None,
+            parent,
+            children: Vec::new(),
+            pm: None,
+            state: PowerState::Active,
+            usage: 0,
+        },
+    );
+    if let Some(parent) = parent {
+        model.node_mut(parent)?.children.push(id);
+    }
+    Ok(id)
+}
+
+/// Registers a device of the class `class`, named after the class and its
+/// number of devices (e.g., `net0`).
+pub fn register_class_device(
+    class: &'static str,
+    driver: &str,
+    parent: Option<DeviceId>,
+) -> DevResult<DeviceId> {
+    let index = MODEL
+        .lock()
+        .nodes
+        .values()
+        .filter(|n| n.subsystem == Subsystem::Class(class))
+        .count();
+    let id = register_device(
+        format!("{}{}", class, index),
+        Subsystem::Class(class),
+        parent,
+    )?;
+    bind_driver(id, driver)?;
+    Ok(id)
+}
+
+/// Records that the driver `driver` is bound to the device `id`.
+pub fn bind_driver(id: DeviceId, driver: &str) -> DevResult {
+    MODEL.lock().node_mut(id)?.driver = Some(driver.into());
+    Ok(())
+}
+
+/// Sets the power management callbacks of the device `id`.
+pub fn set_pm_ops(id: DeviceId, ops: Arc<dyn DevicePm>) -> DevResult {
+    MODEL.lock().node_mut(id)?.pm = Some(ops);
+    Ok(())
+}
+
+/// Finds a device by its path (see [`DeviceInfo::path`]) or, if there is no
+/// `/` in it, by its name.
+pub fn find_device(path: &str) -> Option<DeviceId> {
+    let model = MODEL.lock();
+    for (&id, node) in &model.nodes {
+        let found = if path.contains('/') {
+            model.path(id) == path
+        } else {
+            node.name == path
+        };
+        if found {
+            return Some(id);
+        }
+    }
+    None
+}
+
+/// Returns the information of the device `id`.
+pub fn device_info(id: DeviceId) -> Option<DeviceInfo> {
+    let model = MODEL.lock();
+    model.nodes.contains_key(&id).then(|| model.info(id))
+}
+
+/// Returns the information of all the devices, the parents before their
+/// children.
+pub fn devices() -> Vec<DeviceInfo> {
+    let model = MODEL.lock();
+    model
+        .post_order()
+        .into_iter()
+        .rev()
+        .map(|id| model.info(id))
+        .collect()
+}
+
+/// Returns the PM callbacks of `ids`, in order, with those that are not in
+/// the state `from`.
+fn pm_ops(ids: &[DeviceId], from: PowerState) -> Vec<(DeviceId, Option<Arc<dyn DevicePm>>)> {
+    let model = MODEL.lock();
+    ids.iter()
+        .filter(|id| model.nodes.get(id).is_some_and(|n| n.state == from))
+        .map(|&id| (id, model.nodes[&id].pm.clone()))
+        .collect()
+}
+
+fn set_state(id: DeviceId, state: PowerState) {
+    if let Some(node) = MODEL.lock().nodes.get_mut(&id) {
+        node.state = state;
+    }
+}
+
+/// Removes the device `id` and all its descendants, the children first.
+pub fn remove_device(id: DeviceId) -> DevResult {
+    let ids = {
+        let model = MODEL.lock();
+        model.node(id)?;
+        let mut ids = Vec::new();
+        model.subtree(id, &mut ids);
+        ids
+    };
+    for id in ids {
+        let node = {
+            let mut model = MODEL.lock();
+            let Some(node) = model.nodes.remove(&id) else {
+                continue;
+            };
+            if let Some(parent) = node.parent.and_then(|p| model.nodes.get_mut(&p)) {
+                parent.children.retain(|&c| c != id);
+            }
+            node
+        };
+        if let Some(pm) = node.pm {
+            pm.remove();
+        }
+    }
+    Ok(())
+}
+
+/// Suspends all the devices for a system suspend, the children before their
+/// parents.
+///
+/// If a device fails to suspend, the devices already suspended are resumed,
+/// and the error is returned.
+pub fn suspend_all() -> DevResult {
+    let order = MODEL.lock().post_order();
+    // the runtime suspended devices are already asleep
+    let mut suspended = Vec::new();
+    for (id, pm) in pm_ops(&order, PowerState::Active) {
+        if let Some(pm) = &pm {
+            if let Err(e) = pm.suspend() {
+                warn!("failed to suspend device {}: {:?}", id, e);
+                for (id, pm) in suspended.into_iter().rev() {
+                    resume_one(id, pm);
+                }
+                return Err(e);
+            }
+        }
+        set_state(id, PowerState::Suspended);
+        suspended.push((id, pm));
+    }
+    Ok(())
+}
+
+fn resume_one(id: DeviceId, pm: Option<Arc<dyn DevicePm>>) {
+    if let Some(pm) = pm {
+        if let Err(e) = pm.resume() {
+            warn!("failed to resume device {}: {:?}", id, e);
+            return;
+        }
+    }
+    set_state(id, PowerState::Active);
+}
+
+/// Resumes all the devices suspended by [`suspend_all`], the parents before
+/// their children.
+pub fn resume_all() {
+    let mut order = MODEL.lock().post_order();
+    order.reverse();
+    for (id, pm) in pm_ops(&order, PowerState::Suspended) {
+        resume_one(id, pm);
+    }
+}
+
+/// Marks the device `id` as in use, resuming it and its ancestors (the
+/// parents first) if they are runtime suspended.
+pub fn runtime_get(id: DeviceId) -> DevResult {
+    let mut chain = Vec::new();
+    {
+        let mut model = MODEL.lock();
+        let mut cur = Some(id);
+        while let Some(c) = cur {
+            let node = model.node(c)?;
+            if node.state == PowerState::Suspended {
+                return Err(DevError::BadState);
+            }
+            chain.push(c);
+            cur = node.parent;
+        }
+        model.node_mut(id)?.usage += 1;
+    }
+    chain.reverse();
+    for (c, pm) in pm_ops(&chain, PowerState::RuntimeSuspended) {
+        if let Some(pm) = pm {
+            if let Err(e) = pm.runtime_resume() {
+                warn!("failed to resume device {}: {:?}", c, e);
+                MODEL.lock().node_mut(id)?.usage -= 1;
+                return Err(e);
+            }
+        }
+        set_state(c, PowerState::Active);
+    }
+    Ok(())
+}
+
+/// Marks the device `id` as no longer in use by one user.
+///
+/// When it is idle (no users, and all its children suspended), it is runtime
+/// suspended, and so are its ancestors that become idle in turn.
+pub fn runtime_put(id: DeviceId) -> DevResult {
+    {
+        let mut model = MODEL.lock();
+        let node = model.node_mut(id)?;
+        if node.usage == 0 {
+            return Err(DevError::BadState);
+        }
+        node.usage -= 1;
+    }
+    let mut cur = Some(id);
+    while let Some(c) = cur {
+        let (idle, pm, parent) = {
+            let model = MODEL.lock();
+            let node = model.node(c)?;
+            let idle = node.state == PowerState::Active
+                && node.usage == 0
+                && node
+                    .children
+                    .iter()
+                    .all(|ch| model.nodes[ch].state != PowerState::Active);
+            (idle, node.pm.clone(), node.parent)
+        };
+        if !idle {
+            break;
+        }
+        if let Some(pm) = pm {
+            if let Err(e) = pm.runtime_suspend() {
+                warn!("failed to runtime suspend device {}: {:?}", c, e);
+                break;
+            }
+        }
+        set_state(c, PowerState::RuntimeSuspended);
+        cur = parent;
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! The MTU and the link speed of the network devices.
+//!
+//! The network stack sends IP packets of its MTU at most, and receives the
+//! ones of the MTU of the link. [`NetDriverOps`] has no MTU: the largest one
+//! of a device is the one its buffers are sized for, given by [`max_mtu`].
+//! Neither has it the link speed, the nominal one of a device is given by
+//! [`link_speed`].
+//!
+//! [`NetDriverOps`]: axdriver_net::NetDriverOps
+
+/// The smallest MTU of IPv4.
+pub const MIN_MTU: usize = 68;
+
+/// The MTU of Ethernet.
+pub const STANDARD_MTU: usize = 1500;
+
+/// The MTU of the jumbo frames.
+pub const JUMBO_MTU: usize = 9000;
+
+/// Returns the largest MTU of the network device named `device_name`.
+pub fn max_mtu(device_name: &str) -> usize {
+    match device_name {
+        #[cfg(net_dev = "virtio-net")]
+        "virtio-net" => crate::virtio_net::MTU.get(),
+        _ => STANDARD_MTU,
+    }
+}
+
+/// Returns the link speed of the network device named `device_name`, in
+/// Mb/s, `None` if it is unknown, as for the virtual devices.
+pub fn link_speed(device_name: &str) -> Option<u32> {
+    match device_name {
+        #[cfg(net_dev = "ixgbe")]
+        "ixgbe" => Some(10_000),
+        _ => None,
+    }
+}
@@ -0,0 +1,257 @@
+//! Parameters of the drivers, tunable without rebuilding the kernel.
+//!
+//! A driver declares its parameters (queue depths, offload toggles, debug
+//! levels, ...) as `static` [`DriverParam`]s registered in
+//! [`DRIVER_PARAMS`], and reads them where it used a constant:
+//!
+//! ```ignore
+//! use axdriver::params::{register_driver_param, DriverParam, DRIVER_PARAMS};
+//!
+//! #[register_driver_param(DRIVER_PARAMS)]
+//! static QUEUE_DEPTH: DriverParam =
+//!     DriverParam::uint("foo", "queue_depth", 64, 1, 1024, "the depth of the queues");
+//! ```
+//!
+//! The parameters are set on the command line of the kernel by arguments
+//! `<driver>.<name>=<value>`, like the ones of the Linux modules, given to
+//! [`parse_cmdline`] before [`init_drivers`](crate::init_drivers) probes the
+//! devices. A boolean may be set by its name alone, as `foo.offload`. The
+//! ones not declared [`boot_only`](DriverParam::boot_only) may also be
+//! changed later, e.g. through the files
+//! `/sys/module/<driver>/parameters/<name>` of the sysfs: the driver sees
+//! the new value the next time it reads it.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axdriver_base::{DevError, DevResult};
+
+pub use linkme::distributed_slice as register_driver_param;
+
+/// All the parameters declared by the drivers.
+#[linkme::distributed_slice]
+pub static DRIVER_PARAMS: [DriverParam];
+
+/// The type of the value of a [`DriverParam`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// `0`/`1`, also set by `n`/`y`, `false`/`true` or `off`/`on`.
+    Bool,
+    /// An unsigned integer in `min..=max`, in decimal or in hexadecimal with
+    /// a `0x`.
+    Uint {
+        /// The smallest value.
+        min: usize,
+        /// The largest value.
+        max: usize,
+    },
+}
+
+/// A parameter of a driver, see the [module documentation](self).
+pub struct DriverParam {
+    driver: &'static str,
+    name: &'static str,
+    desc: &'static str,
+    kind: ParamKind,
+    boot_only: bool,
+    default: usize,
+    value: AtomicUsize,
+}
+
+impl DriverParam {
+    /// Creates the boolean parameter `name` of `driver`.
+    pub const fn bool(
+        driver: &'static str,
+        name: &'static str,
+        default: bool,
+        desc: &'static str,
+    ) -> Self {
+        Self::new(driver, name, ParamKind::Bool, default as usize, desc)
+    }
+
+    /// Creates the integer parameter `name` of `driver`, in `min..=max`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `default` is not in `min..=max`.
+    pub const fn uint(
+        driver: &'static str,
+        name: &'static str,
+        default: usize,
+        min: usize,
+        max: usize,
+        desc: &'static str,
+    ) -> Self {
+        assert!(min <= default && default <= max, "default out of range");
+        Self::new(driver, name, ParamKind::Uint { min, max }, default, desc)
+    }
+
+    const fn new(
+        driver: &'static str,
+        name: &'static str,
+        kind: ParamKind,
+        default: usize,
+        desc: &'static str,
+    ) -> Self {
+        Self {
+            driver,
+            name,
+            desc,
+            kind,
+            boot_only: false,
+            default,
+            value: AtomicUsize::new(default),
+        }
+    }
+
+    /// Makes the parameter settable on the command line only, for the ones
+    /// read once when the devices are probed.
+    pub const fn boot_only(mut self) -> Self {
+        self.boot_only = true;
+        self
+    }
+
+    /// Returns the name of the driver.
+    pub const fn driver(&self) -> &'static str {
+        self.driver
+    }
+
+    /// Returns the name of the parameter.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the description of the parameter.
+    pub const fn desc(&self) -> &'static str {
+        self.desc
+    }
+
+    /// Returns the type of the value.
+    pub const fn kind(&self) -> ParamKind {
+        self.kind
+    }
+
+    /// Returns whether the parameter is settable on the command line only.
+    pub const fn is_boot_only(&self) -> bool {
+        self.boot_only
+    }
+
+    /// Returns the default value.
+    pub const fn default_value(&self) -> usize {
+        self.default
+    }
+
+    /// Returns the current value, 0 or 1 for a boolean.
+    pub fn get(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether a boolean is set, or an integer is not 0.
+    pub fn enabled(&self) -> bool {
+        self.get() != 0
+    }
+
+    /// Sets the value, parsed from `s`.
+    ///
+    /// Returns [`DevError::InvalidParam`] if `s` is not a value of the type
+    /// of the parameter, and [`DevError::Unsupported`] if the parameter is
+    /// [`boot_only`](Self::boot_only).
+    pub fn set_str(&self, s: &str) -> DevResult {
+        if self.boot_only {
+            return Err(DevError::Unsupported);
+        }
+        self.parse_and_store(s)
+    }
+
+    fn parse_and_store(&self, s: &str) -> DevResult {
+        let s = s.trim();
+        let value = match self.kind {
+            ParamKind::Bool => match s {
+                "1" | "y" | "Y" | "true" | "on" => 1,
+                "0" | "n" | "N" | "false" | "off" => 0,
+                _ => return Err(DevError::InvalidParam),
+            },
+            ParamKind::Uint { min, max } => {
+                let value = match s.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16),
+                    None => s.parse(),
+                }
+                .map_err(|_| DevError::InvalidParam)?;
+                if !(min..=max).contains(&value) {
+                    return Err(DevError::InvalidParam);
+                }
+                value
+            }
+        };
+        self.value.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Formats the current value, as read from the sysfs.
+impl fmt::Display for DriverParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParamKind::Bool => f.write_str(if self.enabled() { "Y" } else { "N" }),
+            ParamKind::Uint { .. } => write!(f, "{}", self.get()),
+        }
+    }
+}
+
+impl fmt::Debug for DriverParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}={}", self.driver, self.name, self)
+    }
+}
+
+/// Returns all the parameters.
+pub fn params() -> impl Iterator<Item = &'static DriverParam> {
+    DRIVER_PARAMS.iter()
+}
+
+/// Returns the parameters of `driver`.
+pub fn driver_params(driver: &str) -> impl Iterator<Item = &'static DriverParam> + '_ {
+    params().filter(move |p| p.driver == driver)
+}
+
+/// Returns the parameter `name` of `driver`.
+pub fn find(driver: &str, name: &str) -> Option<&'static DriverParam> {
+    driver_params(driver).find(|p| p.name == name)
+}
+
+/// Returns the names of the drivers having parameters, each once.
+pub fn drivers() -> impl Iterator<Item = &'static str> {
+    params()
+        .enumerate()
+        .filter(|(i, p)| !DRIVER_PARAMS[..*i].iter().any(|q| q.driver == p.driver))
+        .map(|(_, p)| p.driver)
+}
+
+/// Sets the parameters given by the arguments `<driver>.<name>[=<value>]` of
+/// the command line `cmdline`, the boot only ones included.
+///
+/// The other arguments are ignored, as well as the ones of unknown
+/// parameters, which may be of a driver not built in. The invalid values are
+/// reported and ignored.
+pub fn parse_cmdline(cmdline: &str) {
+    for arg in cmdline.split_ascii_whitespace() {
+        let (key, value) = arg
+            .split_once('=')
+            .map_or((arg, None), |(k, v)| (k, Some(v)));
+        let Some((driver, name)) = key.split_once('.') else {
+            continue;
+        };
+        let Some(param) = find(driver, name) else {
+            continue;
+        };
+        let res = match (value, param.kind) {
+            (Some(value), _) => param.parse_and_store(value),
+            (None, ParamKind::Bool) => param.parse_and_store("1"),
+            (None, _) => Err(DevError::InvalidParam),
+        };
+        match res {
+            Ok(()) => info!("driver parameter {:?}", param),
+            Err(_) => warn!("invalid driver parameter {:?}", arg),
+        }
+    }
+}
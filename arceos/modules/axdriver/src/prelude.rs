@@ -2,9 +2,13 @@
 
 pub use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
 
+#[cfg(feature = "sound")]
+pub use {crate::sound::SoundDriverOps, crate::structs::AxSoundDevice};
 #[cfg(feature = "block")]
 pub use {crate::structs::AxBlockDevice, axdriver_block::BlockDriverOps};
 #[cfg(feature = "display")]
 pub use {crate::structs::AxDisplayDevice, axdriver_display::DisplayDriverOps};
 #[cfg(feature = "net")]
 pub use {crate::structs::AxNetDevice, axdriver_net::NetDriverOps};
+#[cfg(feature = "vsock")]
+pub use {crate::structs::AxVsockDevice, crate::vsock::VsockDriverOps};
@@ -0,0 +1,889 @@
+//! Driver of the standard SD Host Controllers (SDHCI), for the SD cards, e.g.
+//! the EMMC2 controller of the Raspberry Pi 4.
+//!
+//! The data is moved by the ADMA2 engine of the controller, from a table of
+//! descriptors, and a buffer of several blocks is read or written by a single
+//! multi-block command (CMD18 or CMD25, stopped by the Auto CMD12) instead of
+//! one command and one PIO copy per block. The buffers aligned to the cache
+//! lines are mapped for the DMA directly, the others go through a bounce
+//! buffer.
+//!
+//! The card runs at the fastest bus speed that both support, with a 4-bit
+//! bus: one of the UHS-I modes (SDR104, SDR50 or DDR50) once the signaling
+//! is switched to 1.8V, tuning the sampling clock if the mode requires it,
+//! otherwise the high speed mode. If the switch to 1.8V fails, e.g. without
+//! a regulator for it, the card is power cycled and set up again at 3.3V.
+//!
+//! The controller is polled. Its registers are at the physical address
+//! `AX_SDHCI_BASE`, and its base clock is read from its capabilities, or
+//! given in MHz by `AX_SDHCI_CLOCK_MHZ` if it has none.
+//!
+//! Parameters (see [`params`](crate::params)): `sdhci.uhs=0` keeps the card
+//! at 3.3V, and `sdhci.data_timeout_ms` bounds the data transfers.
+
+use core::alloc::Layout;
+use core::ptr;
+
+use axdma::{map_single, unmap_single, DMAInfo, DmaDirection};
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_block::BlockDriverOps;
+use axhal::time::{busy_wait, monotonic_time_nanos, Duration, NANOS_PER_MILLIS};
+
+use crate::params::{register_driver_param, DriverParam, DRIVER_PARAMS};
+
+const BLOCK_SIZE: usize = 512;
+/// The alignment of the buffers mapped for the DMA directly.
+const CACHE_LINE_SIZE: usize = 64;
+
+// Registers
+const REG_BLOCK_SIZE: usize = 0x04; // and the block count
+const REG_ARGUMENT: usize = 0x08;
+const REG_TRANSFER_MODE: usize = 0x0c; // and the command
+const REG_RESPONSE: usize = 0x10;
+const REG_PRESENT_STATE: usize = 0x24;
+const REG_HOST_CONTROL1: usize = 0x28;
+const REG_POWER_CONTROL: usize = 0x29;
+const REG_CLOCK_CONTROL: usize = 0x2c;
+const REG_TIMEOUT_CONTROL: usize = 0x2e;
+const REG_SOFTWARE_RESET: usize = 0x2f;
+const REG_INT_STATUS: usize = 0x30; // normal and error
+const REG_INT_ENABLE: usize = 0x34;
+const REG_INT_SIGNAL: usize = 0x38;
+const REG_HOST_CONTROL2: usize = 0x3e;
+const REG_CAPABILITIES: usize = 0x40;
+const REG_CAPABILITIES_HI: usize = 0x44;
+const REG_ADMA_ADDRESS: usize = 0x58;
+const REG_HOST_VERSION: usize = 0xfe;
+
+const SPEC_VERSION_3: u16 = 2;
+
+// Transfer mode
+const TM_DMA: u32 = 1 << 0;
+const TM_BLOCK_COUNT: u32 = 1 << 1;
+const TM_AUTO_CMD12: u32 = 1 << 2;
+const TM_READ: u32 = 1 << 4;
+const TM_MULTI_BLOCK: u32 = 1 << 5;
+
+// Command
+const CMD_RESP_136: u32 = 0b01;
+const CMD_RESP_48: u32 = 0b10;
+const CMD_RESP_48_BUSY: u32 = 0b11;
+const CMD_CRC_CHECK: u32 = 1 << 3;
+const CMD_INDEX_CHECK: u32 = 1 << 4;
+const CMD_DATA: u32 = 1 << 5;
+
+// Present state
+const PS_CMD_INHIBIT: u32 = 1 << 0;
+const PS_DAT_INHIBIT: u32 = 1 << 1;
+const PS_CARD_INSERTED: u32 = 1 << 16;
+const PS_DAT_LEVEL: u32 = 0xf << 20;
+
+// Host control 1
+const HC1_4BIT: u8 = 1 << 1;
+const HC1_HIGH_SPEED: u8 = 1 << 2;
+const HC1_ADMA2_32: u8 = 0b10 << 3;
+const HC1_ADMA2_64: u8 = 0b11 << 3;
+
+// Power control
+const POWER_ON: u8 = 1 << 0;
+const POWER_330: u8 = 0b111 << 1;
+
+// Clock control
+const CLK_INT_ENABLE: u16 = 1 << 0;
+const CLK_INT_STABLE: u16 = 1 << 1;
+const CLK_SD_ENABLE: u16 = 1 << 2;
+
+// Software reset
+const RESET_ALL: u8 = 1 << 0;
+const RESET_CMD: u8 = 1 << 1;
+const RESET_DAT: u8 = 1 << 2;
+
+// Interrupt status
+const INT_CMD_COMPLETE: u32 = 1 << 0;
+const INT_TRANSFER_COMPLETE: u32 = 1 << 1;
+const INT_BUFFER_READ_READY: u32 = 1 << 5;
+const INT_ERROR: u32 = 1 << 15;
+const INT_ALL: u32 = 0xffff_ffff;
+
+// Host control 2
+const HC2_UHS_MASK: u16 = 0b111;
+const HC2_1V8: u16 = 1 << 3;
+const HC2_EXECUTE_TUNING: u16 = 1 << 6;
+const HC2_TUNED_CLOCK: u16 = 1 << 7;
+
+// Capabilities
+const CAP_BASE_CLOCK_SHIFT: u32 = 8;
+const CAP_ADMA2: u32 = 1 << 19;
+const CAP_HIGH_SPEED: u32 = 1 << 21;
+const CAP_1V8: u32 = 1 << 26;
+const CAP_64BIT: u32 = 1 << 28;
+const CAP_HI_SDR50: u32 = 1 << 0;
+const CAP_HI_SDR104: u32 = 1 << 1;
+const CAP_HI_DDR50: u32 = 1 << 2;
+const CAP_HI_TUNE_SDR50: u32 = 1 << 13;
+
+// ADMA2 descriptor attributes
+const ADMA_VALID: u16 = 1 << 0;
+const ADMA_END: u16 = 1 << 1;
+const ADMA_TRAN: u16 = 0b10 << 4;
+/// The bytes of a descriptor, at most.
+const ADMA_DESC_MAX: usize = 12;
+/// The number of descriptors of the table.
+const ADMA_DESCS: usize = 64;
+/// The bytes of the data of a descriptor, a multiple of the block size.
+const ADMA_SEG_SIZE: usize = 0x8000;
+/// The most blocks moved by a command.
+const MAX_BLOCKS: usize = ADMA_DESCS * ADMA_SEG_SIZE / BLOCK_SIZE;
+/// The blocks of the bounce buffer.
+const BOUNCE_BLOCKS: usize = 128;
+
+// Card registers
+const OCR_BUSY: u32 = 1 << 31;
+const OCR_CCS: u32 = 1 << 30;
+const OCR_S18: u32 = 1 << 24;
+const OCR_VOLTAGES: u32 = 0x00ff_8000;
+const CMD8_CHECK: u32 = 0x1aa;
+
+const CMD_TIMEOUT_MS: u64 = 1000;
+
+#[register_driver_param(DRIVER_PARAMS)]
+static UHS: DriverParam =
+    DriverParam::bool("sdhci", "uhs", true, "use the UHS-I modes at 1.8V").boot_only();
+
+#[register_driver_param(DRIVER_PARAMS)]
+static DATA_TIMEOUT_MS: DriverParam = DriverParam::uint(
+    "sdhci",
+    "data_timeout_ms",
+    5000,
+    100,
+    60_000,
+    "the timeout of the data transfers, in milliseconds",
+);
+const TUNING_LOOPS: usize = 40;
+
+/// The response of a command.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Resp {
+    None,
+    /// R1, R6 and R7.
+    R1,
+    /// R1 with the busy signal.
+    R1b,
+    /// R2, the CID or the CSD.
+    R2,
+    /// R3, the OCR.
+    R3,
+}
+
+impl Resp {
+    const fn flags(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::R1 => CMD_RESP_48 | CMD_CRC_CHECK | CMD_INDEX_CHECK,
+            Self::R1b => CMD_RESP_48_BUSY | CMD_CRC_CHECK | CMD_INDEX_CHECK,
+            Self::R2 => CMD_RESP_136 | CMD_CRC_CHECK,
+            Self::R3 => CMD_RESP_48,
+        }
+    }
+}
+
+/// A bus speed mode, the function of the group 1 of CMD6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BusMode {
+    Default,
+    HighSpeed,
+    Sdr50,
+    Sdr104,
+    Ddr50,
+}
+
+impl BusMode {
+    /// The function number of CMD6.
+    const fn function(self) -> u32 {
+        match self {
+            Self::Default => 0,
+            Self::HighSpeed => 1,
+            Self::Sdr50 => 2,
+            Self::Sdr104 => 3,
+            Self::Ddr50 => 4,
+        }
+    }
+
+    /// The UHS mode select of the host control 2.
+    const fn uhs_select(self) -> u16 {
+        match self {
+            Self::Default => 0b000,
+            Self::HighSpeed => 0b001,
+            Self::Sdr50 => 0b010,
+            Self::Sdr104 => 0b011,
+            Self::Ddr50 => 0b100,
+        }
+    }
+
+    const fn clock_hz(self) -> u32 {
+        match self {
+            Self::Default => 25_000_000,
+            Self::HighSpeed | Self::Ddr50 => 50_000_000,
+            Self::Sdr50 => 100_000_000,
+            Self::Sdr104 => 208_000_000,
+        }
+    }
+}
+
+/// A buffer of coherent DMA memory.
+struct DmaBuf {
+    info: DMAInfo,
+    layout: Layout,
+}
+
+impl DmaBuf {
+    fn new(size: usize, align: usize) -> DevResult<Self> {
+        let layout = Layout::from_size_align(size, align).map_err(|_| DevError::InvalidParam)?;
+        // SAFETY: the buffer is freed with the same layout when dropped.
+        let info = unsafe { axdma::alloc_coherent(layout) }.map_err(|_| DevError::NoMemory)?;
+        Ok(Self { info, layout })
+    }
+
+    fn bus_addr(&self) -> u64 {
+        self.info.bus_addr.as_u64()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the buffer is `layout.size()` bytes of coherent memory.
+        unsafe { core::slice::from_raw_parts_mut(self.info.cpu_addr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for DmaBuf {
+    fn drop(&mut self) {
+        // SAFETY: it was allocated with this layout.
+        unsafe { axdma::dealloc_coherent(self.info, self.layout) };
+    }
+}
+
+/// The memory of a data transfer, seen by the DMA engine.
+#[derive(Clone, Copy)]
+struct DataBuf {
+    bus_addr: u64,
+    len: usize,
+    block_size: usize,
+    read: bool,
+}
+
+/// The SD card behind a standard SD host controller.
+pub struct SdhciBlk {
+    base: usize,
+    /// The spec version of the controller, 2 for the version 3.00.
+    version: u16,
+    caps: u32,
+    caps_hi: u32,
+    /// The base clock in Hz.
+    base_clock: u32,
+    /// Whether the descriptors are the ones of the 64-bit ADMA2.
+    adma64: bool,
+    descs: DmaBuf,
+    bounce: DmaBuf,
+    rca: u32,
+    /// Whether the card is addressed by blocks (SDHC and SDXC) rather than
+    /// by bytes (SDSC).
+    block_addressing: bool,
+    num_blocks: u64,
+    mode: BusMode,
+}
+
+impl SdhciBlk {
+    /// Sets up the controller whose registers are mapped at `base`, and the
+    /// card in its slot.
+    pub fn new(base: usize) -> DevResult<Self> {
+        let mut dev = Self {
+            base,
+            version: 0,
+            caps: 0,
+            caps_hi: 0,
+            base_clock: 0,
+            adma64: false,
+            descs: DmaBuf::new(ADMA_DESCS * ADMA_DESC_MAX, 8)?,
+            bounce: DmaBuf::new(BOUNCE_BLOCKS * BLOCK_SIZE, CACHE_LINE_SIZE)?,
+            rca: 0,
+            block_addressing: false,
+            num_blocks: 0,
+            mode: BusMode::Default,
+        };
+        dev.version = dev.read16(REG_HOST_VERSION) & 0xff;
+        dev.caps = dev.read32(REG_CAPABILITIES);
+        if dev.version >= SPEC_VERSION_3 {
+            dev.caps_hi = dev.read32(REG_CAPABILITIES_HI);
+        }
+        if dev.caps & CAP_ADMA2 == 0 {
+            warn!("sdhci: no ADMA2 support");
+            return Err(DevError::Unsupported);
+        }
+        dev.adma64 = dev.caps & CAP_64BIT != 0;
+        let clock_mask = if dev.version >= SPEC_VERSION_3 {
+            0xff
+        } else {
+            0x3f
+        };
+        dev.base_clock = match (dev.caps >> CAP_BASE_CLOCK_SHIFT) & clock_mask {
+            0 => option_env!("AX_SDHCI_CLOCK_MHZ")
+                .and_then(|mhz| mhz.parse::<u32>().ok())
+                .ok_or(DevError::Unsupported)?,
+            mhz => mhz,
+        } * 1_000_000;
+
+        let uhs = UHS.enabled()
+            && dev.version >= SPEC_VERSION_3
+            && dev.caps & CAP_1V8 != 0
+            && dev.caps_hi & (CAP_HI_SDR50 | CAP_HI_SDR104 | CAP_HI_DDR50) != 0;
+        if let Err(err) = dev.init_card(uhs) {
+            if !uhs {
+                return Err(err);
+            }
+            warn!("sdhci: UHS-I setup failed ({:?}), retrying at 3.3V", err);
+            dev.init_card(false)?;
+        }
+        info!(
+            "sdhci: {} blocks, {:?} mode at {} MHz, ADMA2 {}-bit",
+            dev.num_blocks,
+            dev.mode,
+            dev.mode.clock_hz() / 1_000_000,
+            if dev.adma64 { 64 } else { 32 },
+        );
+        Ok(dev)
+    }
+
+    fn read32(&self, reg: usize) -> u32 {
+        // SAFETY: the registers are mapped at `base`.
+        unsafe { ((self.base + reg) as *const u32).read_volatile() }
+    }
+
+    fn write32(&mut self, reg: usize, val: u32) {
+        // SAFETY: the registers are mapped at `base`.
+        unsafe { ((self.base + reg) as *mut u32).write_volatile(val) }
+    }
+
+    // The narrower registers are accessed by their 32-bit words, as some
+    // controllers only support the 32-bit accesses.
+
+    fn read16(&self, reg: usize) -> u16 {
+        (self.read32(reg & !3) >> ((reg & 2) * 8)) as u16
+    }
+
+    fn write16(&mut self, reg: usize, val: u16) {
+        let shift = (reg & 2) * 8;
+        let word = self.read32(reg & !3) & !(0xffff << shift);
+        self.write32(reg & !3, word | (val as u32) << shift);
+    }
+
+    fn read8(&self, reg: usize) -> u8 {
+        (self.read32(reg & !3) >> ((reg & 3) * 8)) as u8
+    }
+
+    fn write8(&mut self, reg: usize, val: u8) {
+        let shift = (reg & 3) * 8;
+        let word = self.read32(reg & !3) & !(0xff << shift);
+        self.write32(reg & !3, word | (val as u32) << shift);
+    }
+
+    /// Waits for `done` for `timeout_ms` milliseconds.
+    fn wait(&self, timeout_ms: u64, mut done: impl FnMut(&Self) -> bool) -> DevResult {
+        let deadline = monotonic_time_nanos() + timeout_ms * NANOS_PER_MILLIS;
+        while !done(self) {
+            if monotonic_time_nanos() > deadline {
+                return Err(DevError::Io);
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self, mask: u8) -> DevResult {
+        self.write8(REG_SOFTWARE_RESET, mask);
+        self.wait(CMD_TIMEOUT_MS, |dev| {
+            dev.read8(REG_SOFTWARE_RESET) & mask == 0
+        })
+    }
+
+    /// Waits for the interrupts of `mask`, and clears them. On an error, the
+    /// command and data lines are reset.
+    fn wait_int(&mut self, mask: u32, timeout_ms: u64) -> DevResult {
+        let mut status = 0;
+        let res = self.wait(timeout_ms, |dev| {
+            status = dev.read32(REG_INT_STATUS);
+            status & (mask | INT_ERROR) != 0 && (status & INT_ERROR != 0 || status & mask == mask)
+        });
+        if res.is_ok() && status & INT_ERROR == 0 {
+            self.write32(REG_INT_STATUS, mask);
+            return Ok(());
+        }
+        debug!("sdhci: interrupt status {:#x}", status);
+        self.write32(REG_INT_STATUS, INT_ALL);
+        self.reset(RESET_CMD | RESET_DAT)?;
+        Err(DevError::Io)
+    }
+
+    /// Sends a command, with the data of `data` if any, and returns its
+    /// response.
+    fn command(
+        &mut self,
+        index: u32,
+        arg: u32,
+        resp: Resp,
+        data: Option<DataBuf>,
+    ) -> DevResult<[u32; 4]> {
+        let inhibit = if data.is_some() || resp == Resp::R1b {
+            PS_CMD_INHIBIT | PS_DAT_INHIBIT
+        } else {
+            PS_CMD_INHIBIT
+        };
+        self.wait(CMD_TIMEOUT_MS, |dev| {
+            dev.read32(REG_PRESENT_STATE) & inhibit == 0
+        })?;
+
+        let mut mode = 0;
+        let mut flags = resp.flags();
+        if let Some(data) = data {
+            let blocks = data.len / data.block_size;
+            self.write_descs(data.bus_addr, data.len);
+            self.write32(
+                REG_BLOCK_SIZE,
+                (blocks as u32) << 16 | data.block_size as u32,
+            );
+            mode = TM_DMA | TM_BLOCK_COUNT;
+            if blocks > 1 {
+                mode |= TM_MULTI_BLOCK | TM_AUTO_CMD12;
+            }
+            if data.read {
+                mode |= TM_READ;
+            }
+            flags |= CMD_DATA;
+        }
+        self.write32(REG_INT_STATUS, INT_ALL);
+        self.write32(REG_ARGUMENT, arg);
+        self.write32(REG_TRANSFER_MODE, (index << 8 | flags) << 16 | mode);
+        self.wait_int(INT_CMD_COMPLETE, CMD_TIMEOUT_MS)?;
+
+        let mut response = [0; 4];
+        if resp != Resp::None {
+            for (i, word) in response.iter_mut().enumerate() {
+                *word = self.read32(REG_RESPONSE + i * 4);
+            }
+        }
+        if data.is_some() || resp == Resp::R1b {
+            self.wait_int(INT_TRANSFER_COMPLETE, DATA_TIMEOUT_MS.get() as u64)?;
+        }
+        Ok(response)
+    }
+
+    /// Sends an application command, preceded by CMD55.
+    fn app_command(
+        &mut self,
+        index: u32,
+        arg: u32,
+        resp: Resp,
+        data: Option<DataBuf>,
+    ) -> DevResult<[u32; 4]> {
+        self.command(55, self.rca << 16, Resp::R1, None)?;
+        self.command(index, arg, resp, data)
+    }
+
+    /// Fills the descriptor table for the `len` bytes at `bus_addr`.
+    fn write_descs(&mut self, bus_addr: u64, len: usize) {
+        let desc_size = if self.adma64 { 12 } else { 8 };
+        let segs = len.div_ceil(ADMA_SEG_SIZE);
+        let table = self.descs.as_mut_slice();
+        for i in 0..segs {
+            let seg_len = (len - i * ADMA_SEG_SIZE).min(ADMA_SEG_SIZE);
+            let addr = bus_addr + (i * ADMA_SEG_SIZE) as u64;
+            let mut attr = ADMA_VALID | ADMA_TRAN;
+            if i == segs - 1 {
+                attr |= ADMA_END;
+            }
+            let desc = &mut table[i * desc_size..(i + 1) * desc_size];
+            desc[0..2].copy_from_slice(&attr.to_le_bytes());
+            desc[2..4].copy_from_slice(&(seg_len as u16).to_le_bytes());
+            if self.adma64 {
+                desc[4..12].copy_from_slice(&addr.to_le_bytes());
+            } else {
+                desc[4..8].copy_from_slice(&(addr as u32).to_le_bytes());
+            }
+        }
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        let table = self.descs.bus_addr();
+        self.write32(REG_ADMA_ADDRESS, table as u32);
+        self.write32(REG_ADMA_ADDRESS + 4, (table >> 32) as u32);
+    }
+
+    /// Sets the SD clock to at most `hz`.
+    fn set_clock(&mut self, hz: u32) -> DevResult {
+        self.wait(CMD_TIMEOUT_MS, |dev| {
+            dev.read32(REG_PRESENT_STATE) & (PS_CMD_INHIBIT | PS_DAT_INHIBIT) == 0
+        })?;
+        self.write16(REG_CLOCK_CONTROL, 0);
+        // The clock is `base_clock / (2 * div)`, or `base_clock` if `div`
+        // is 0: any 10-bit divider from the version 3.00, a power of 2 of 8
+        // bits before it.
+        let div = if hz >= self.base_clock {
+            0
+        } else if self.version >= SPEC_VERSION_3 {
+            self.base_clock.div_ceil(2 * hz).min(0x3ff)
+        } else {
+            self.base_clock
+                .div_ceil(2 * hz)
+                .next_power_of_two()
+                .min(0x80)
+        };
+        let clock = ((div & 0xff) << 8 | (div >> 8) << 6) as u16;
+        self.write16(REG_CLOCK_CONTROL, clock | CLK_INT_ENABLE);
+        self.wait(CMD_TIMEOUT_MS, |dev| {
+            dev.read16(REG_CLOCK_CONTROL) & CLK_INT_STABLE != 0
+        })?;
+        self.write16(REG_CLOCK_CONTROL, clock | CLK_INT_ENABLE | CLK_SD_ENABLE);
+        Ok(())
+    }
+
+    /// Resets the controller, powers the card on at 3.3V and sets it up,
+    /// asking for the 1.8V signaling of the UHS-I modes if `uhs`.
+    fn init_card(&mut self, uhs: bool) -> DevResult {
+        self.reset(RESET_ALL)?;
+        if self.read32(REG_PRESENT_STATE) & PS_CARD_INSERTED == 0 {
+            warn!("sdhci: no card");
+            return Err(DevError::Io);
+        }
+        // power cycle, e.g. to leave the 1.8V signaling of a failed switch
+        self.write8(REG_POWER_CONTROL, 0);
+        busy_wait(Duration::from_millis(1));
+        self.write8(REG_POWER_CONTROL, POWER_330 | POWER_ON);
+        busy_wait(Duration::from_millis(10));
+        self.write32(REG_INT_ENABLE, INT_ALL);
+        self.write32(REG_INT_SIGNAL, 0);
+        self.write8(REG_TIMEOUT_CONTROL, 0xe);
+        let dma = if self.adma64 {
+            HC1_ADMA2_64
+        } else {
+            HC1_ADMA2_32
+        };
+        self.write8(REG_HOST_CONTROL1, dma);
+        if self.version >= SPEC_VERSION_3 {
+            self.write16(REG_HOST_CONTROL2, 0);
+        }
+        self.set_clock(400_000)?;
+        busy_wait(Duration::from_millis(1));
+
+        self.rca = 0;
+        self.command(0, 0, Resp::None, None)?;
+        // CMD8: only answered by the cards of the version 2.00 or later
+        let v2 = self
+            .command(8, CMD8_CHECK, Resp::R1, None)
+            .is_ok_and(|r| r[0] & 0xfff == CMD8_CHECK);
+        let mut arg = OCR_VOLTAGES;
+        if v2 {
+            arg |= OCR_CCS;
+            if uhs {
+                arg |= OCR_S18;
+            }
+        }
+        let mut ocr = 0;
+        let deadline = monotonic_time_nanos() + CMD_TIMEOUT_MS * NANOS_PER_MILLIS;
+        while ocr & OCR_BUSY == 0 {
+            if monotonic_time_nanos() > deadline {
+                return Err(DevError::Io);
+            }
+            ocr = self.app_command(41, arg, Resp::R3, None)?[0];
+            busy_wait(Duration::from_millis(1));
+        }
+        self.block_addressing = ocr & OCR_CCS != 0;
+        let uhs = uhs && ocr & OCR_S18 != 0;
+        if uhs {
+            self.switch_to_1v8()?;
+        }
+
+        self.command(2, 0, Resp::R2, None)?;
+        self.rca = self.command(3, 0, Resp::R1, None)?[0] >> 16;
+        let csd = self.command(9, self.rca << 16, Resp::R2, None)?;
+        self.num_blocks = csd_blocks(&csd).ok_or(DevError::Unsupported)?;
+        self.command(7, self.rca << 16, Resp::R1b, None)?;
+        self.set_clock(BusMode::Default.clock_hz())?;
+        if !self.block_addressing {
+            self.command(16, BLOCK_SIZE as u32, Resp::R1, None)?;
+        }
+
+        let scr = self.read_scr()?;
+        if scr[1] & 0x4 != 0 {
+            // ACMD6: 4-bit bus
+            self.app_command(6, 2, Resp::R1, None)?;
+            let hc1 = self.read8(REG_HOST_CONTROL1);
+            self.write8(REG_HOST_CONTROL1, hc1 | HC1_4BIT);
+        }
+        // CMD6 is supported from the version 1.10
+        self.mode = BusMode::Default;
+        if scr[0] & 0xf >= 1 {
+            self.set_bus_mode(uhs)?;
+        }
+        Ok(())
+    }
+
+    /// Switches the signaling to 1.8V after the card accepted it.
+    fn switch_to_1v8(&mut self) -> DevResult {
+        self.command(11, 0, Resp::R1, None)?;
+        let clock = self.read16(REG_CLOCK_CONTROL);
+        self.write16(REG_CLOCK_CONTROL, clock & !CLK_SD_ENABLE);
+        if self.read32(REG_PRESENT_STATE) & PS_DAT_LEVEL != 0 {
+            return Err(DevError::Io);
+        }
+        let hc2 = self.read16(REG_HOST_CONTROL2);
+        self.write16(REG_HOST_CONTROL2, hc2 | HC2_1V8);
+        busy_wait(Duration::from_millis(5));
+        if self.read16(REG_HOST_CONTROL2) & HC2_1V8 == 0 {
+            return Err(DevError::Unsupported);
+        }
+        self.write16(REG_CLOCK_CONTROL, clock | CLK_SD_ENABLE);
+        busy_wait(Duration::from_millis(1));
+        if self.read32(REG_PRESENT_STATE) & PS_DAT_LEVEL != PS_DAT_LEVEL {
+            return Err(DevError::Io);
+        }
+        Ok(())
+    }
+
+    /// Reads the SCR, by ACMD51.
+    fn read_scr(&mut self) -> DevResult<[u8; 8]> {
+        let data = self.bounce_data(8, true);
+        self.app_command(51, 0, Resp::R1, Some(data))?;
+        let mut scr = [0; 8];
+        scr.copy_from_slice(&self.bounce.as_mut_slice()[..8]);
+        Ok(scr)
+    }
+
+    /// Reads the 64-byte status of CMD6 for `arg`.
+    fn switch_function(&mut self, arg: u32) -> DevResult<[u8; 64]> {
+        let data = self.bounce_data(64, true);
+        self.command(6, arg, Resp::R1, Some(data))?;
+        let mut status = [0; 64];
+        status.copy_from_slice(&self.bounce.as_mut_slice()[..64]);
+        Ok(status)
+    }
+
+    /// Switches the card and the controller to the fastest bus mode both
+    /// support, among the UHS-I ones if `uhs`.
+    fn set_bus_mode(&mut self, uhs: bool) -> DevResult {
+        let status = self.switch_function(0x00ff_fff0 | BusMode::Default.function())?;
+        // the functions of the group 1 supported, bits 415:400
+        let supported = u16::from_be_bytes([status[12], status[13]]) as u32;
+        let card = |mode: BusMode| supported & (1 << mode.function()) != 0;
+        let mode = if uhs {
+            [
+                (BusMode::Sdr104, CAP_HI_SDR104),
+                (BusMode::Sdr50, CAP_HI_SDR50),
+                (BusMode::Ddr50, CAP_HI_DDR50),
+            ]
+            .into_iter()
+            .find(|&(mode, cap)| card(mode) && self.caps_hi & cap != 0)
+            .map_or(BusMode::HighSpeed, |(mode, _)| mode)
+        } else if card(BusMode::HighSpeed) && self.caps & CAP_HIGH_SPEED != 0 {
+            BusMode::HighSpeed
+        } else {
+            return Ok(());
+        };
+        let status = self.switch_function(0x80ff_fff0 | mode.function())?;
+        // the function of the group 1 switched to, bits 379:376
+        if (status[16] & 0xf) as u32 != mode.function() {
+            warn!("sdhci: the card did not switch to {:?}", mode);
+            return Ok(());
+        }
+
+        let hc1 = self.read8(REG_HOST_CONTROL1);
+        self.write8(REG_HOST_CONTROL1, hc1 | HC1_HIGH_SPEED);
+        if uhs {
+            let hc2 = self.read16(REG_HOST_CONTROL2) & !HC2_UHS_MASK;
+            self.write16(REG_HOST_CONTROL2, hc2 | mode.uhs_select());
+        }
+        self.set_clock(mode.clock_hz())?;
+        self.mode = mode;
+        let tuning = mode == BusMode::Sdr104
+            || (mode == BusMode::Sdr50 && self.caps_hi & CAP_HI_TUNE_SDR50 != 0);
+        if tuning {
+            self.tune()?;
+        }
+        Ok(())
+    }
+
+    /// Tunes the sampling clock with the tuning blocks of CMD19.
+    fn tune(&mut self) -> DevResult {
+        let hc2 = self.read16(REG_HOST_CONTROL2);
+        self.write16(REG_HOST_CONTROL2, hc2 | HC2_EXECUTE_TUNING);
+        for _ in 0..TUNING_LOOPS {
+            self.wait(CMD_TIMEOUT_MS, |dev| {
+                dev.read32(REG_PRESENT_STATE) & (PS_CMD_INHIBIT | PS_DAT_INHIBIT) == 0
+            })?;
+            // a block of 64 bytes read by the controller itself, no DMA
+            self.write32(REG_BLOCK_SIZE, 1 << 16 | 64);
+            self.write32(REG_INT_STATUS, INT_ALL);
+            self.write32(REG_ARGUMENT, 0);
+            let flags = Resp::R1.flags() | CMD_DATA;
+            self.write32(REG_TRANSFER_MODE, (19 << 8 | flags) << 16 | TM_READ);
+            self.wait_int(INT_BUFFER_READ_READY, CMD_TIMEOUT_MS)?;
+            let hc2 = self.read16(REG_HOST_CONTROL2);
+            if hc2 & HC2_EXECUTE_TUNING == 0 {
+                return if hc2 & HC2_TUNED_CLOCK != 0 {
+                    Ok(())
+                } else {
+                    Err(DevError::Io)
+                };
+            }
+        }
+        let hc2 = self.read16(REG_HOST_CONTROL2);
+        self.write16(
+            REG_HOST_CONTROL2,
+            hc2 & !(HC2_EXECUTE_TUNING | HC2_TUNED_CLOCK),
+        );
+        Err(DevError::Io)
+    }
+
+    /// The first `len` bytes of the bounce buffer, for a transfer.
+    fn bounce_data(&self, len: usize, read: bool) -> DataBuf {
+        DataBuf {
+            bus_addr: self.bounce.bus_addr(),
+            len,
+            block_size: len.min(BLOCK_SIZE),
+            read,
+        }
+    }
+
+    /// Whether the buffer at `vaddr` can be mapped for the DMA directly.
+    fn direct(&self, vaddr: usize, len: usize) -> bool {
+        let fits = |bus: u64| self.adma64 || bus + len as u64 <= 1 << 32;
+        vaddr % CACHE_LINE_SIZE == 0
+            && vaddr >= axconfig::PHYS_VIRT_OFFSET
+            && fits(axdma::phys_to_bus(axhal::mem::virt_to_phys(vaddr.into())).as_u64())
+    }
+
+    /// Reads or writes the blocks from `block_id` into or from the DMA
+    /// memory at `bus_addr`, by a single command.
+    fn transfer(&mut self, block_id: u64, bus_addr: u64, len: usize, read: bool) -> DevResult {
+        let blocks = len / BLOCK_SIZE;
+        let index = match (read, blocks > 1) {
+            (true, false) => 17,
+            (true, true) => 18,
+            (false, false) => 24,
+            (false, true) => 25,
+        };
+        let addr = if self.block_addressing {
+            block_id
+        } else {
+            block_id * BLOCK_SIZE as u64
+        };
+        let data = DataBuf {
+            bus_addr,
+            len,
+            block_size: BLOCK_SIZE,
+            read,
+        };
+        self.command(index, addr as u32, Resp::R1, Some(data))?;
+        Ok(())
+    }
+
+    /// Reads or writes the `len` bytes at `vaddr` from `block_id`, by
+    /// commands of [`MAX_BLOCKS`] at most, or of [`BOUNCE_BLOCKS`] through
+    /// the bounce buffer.
+    ///
+    /// The bytes are only written if `read`, they are only read otherwise.
+    fn rw_blocks(&mut self, block_id: u64, vaddr: usize, len: usize, read: bool) -> DevResult {
+        if len % BLOCK_SIZE != 0 || block_id + (len / BLOCK_SIZE) as u64 > self.num_blocks {
+            return Err(DevError::InvalidParam);
+        }
+        let dir = if read {
+            DmaDirection::FromDevice
+        } else {
+            DmaDirection::ToDevice
+        };
+        let direct = self.direct(vaddr, len);
+        let chunk_size = BLOCK_SIZE * if direct { MAX_BLOCKS } else { BOUNCE_BLOCKS };
+        for offset in (0..len).step_by(chunk_size) {
+            let chunk = (len - offset).min(chunk_size);
+            let block = block_id + (offset / BLOCK_SIZE) as u64;
+            let addr = vaddr + offset;
+            if direct {
+                let bus = map_single(addr.into(), chunk, dir).map_err(|_| DevError::Io)?;
+                let res = self.transfer(block, bus.as_u64(), chunk, read);
+                unmap_single(bus, chunk, dir);
+                res?;
+            } else {
+                // SAFETY: the bytes are in the buffer of the caller and in
+                // the bounce buffer, written only if they are read.
+                let bounce = self.bounce.as_mut_slice().as_mut_ptr();
+                if !read {
+                    unsafe { ptr::copy_nonoverlapping(addr as *const u8, bounce, chunk) };
+                }
+                self.transfer(block, self.bounce.bus_addr(), chunk, read)?;
+                if read {
+                    unsafe { ptr::copy_nonoverlapping(bounce, addr as *mut u8, chunk) };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of blocks of the card from its CSD, as stored in the
+/// response registers: without the CRC, the bit `n` of the CSD at `n - 8`.
+fn csd_blocks(csd: &[u32; 4]) -> Option<u64> {
+    let bits = |hi: usize, lo: usize| -> u64 {
+        let raw = (csd[0] as u128)
+            | (csd[1] as u128) << 32
+            | (csd[2] as u128) << 64
+            | (csd[3] as u128) << 96;
+        ((raw >> (lo - 8)) & ((1 << (hi - lo + 1)) - 1)) as u64
+    };
+    match bits(127, 126) {
+        // CSD version 1.0, SDSC
+        0 => {
+            let c_size = bits(73, 62);
+            let c_size_mult = bits(49, 47);
+            let read_bl_len = bits(83, 80);
+            Some((c_size + 1) << (c_size_mult + 2 + read_bl_len) >> 9)
+        }
+        // CSD version 2.0, SDHC and SDXC: the size is in units of 512 KiB
+        1 => Some((bits(69, 48) + 1) * 1024),
+        _ => None,
+    }
+}
+
+impl BaseDriverOps for SdhciBlk {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn device_name(&self) -> &str {
+        "sdhci"
+    }
+}
+
+impl BlockDriverOps for SdhciBlk {
+    fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.rw_blocks(block_id, buf.as_mut_ptr() as usize, buf.len(), true)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.rw_blocks(block_id, buf.as_ptr() as usize, buf.len(), false)
+    }
+
+    fn flush(&mut self) -> DevResult {
+        Ok(())
+    }
+}
+
+/// Returns the physical address of the registers of the controller, from
+/// `AX_SDHCI_BASE`.
+pub(crate) fn base_paddr() -> Option<usize> {
+    let base = option_env!("AX_SDHCI_BASE")?;
+    let hex = base.strip_prefix("0x").unwrap_or(base);
+    usize::from_str_radix(hex, 16).ok()
+}
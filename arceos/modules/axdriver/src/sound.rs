@@ -0,0 +1,74 @@
+//! Common traits and types for sound device drivers.
+
+use axdriver_base::{BaseDriverOps, DevResult};
+
+/// Format of the samples of a PCM stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Unsigned 8-bit.
+    U8,
+    /// Signed 16-bit, little-endian.
+    S16Le,
+    /// Signed 32-bit, little-endian.
+    S32Le,
+    /// 32-bit floating point, little-endian.
+    FloatLe,
+}
+
+impl PcmFormat {
+    /// Returns the size of a sample in bytes.
+    pub const fn sample_bytes(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::S16Le => 2,
+            Self::S32Le | Self::FloatLe => 4,
+        }
+    }
+}
+
+/// Parameters of a PCM stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmParams {
+    /// Format of the samples.
+    pub format: PcmFormat,
+    /// Number of channels, the samples of a frame are interleaved.
+    pub channels: u8,
+    /// Number of frames per second.
+    pub rate: u32,
+}
+
+impl PcmParams {
+    /// Returns the size of a frame in bytes.
+    pub const fn frame_bytes(&self) -> usize {
+        self.format.sample_bytes() * self.channels as usize
+    }
+}
+
+/// Operations that require a sound device driver to implement, for the PCM
+/// playback.
+///
+/// The output streams of the device are numbered from 0.
+pub trait SoundDriverOps: BaseDriverOps {
+    /// The number of output streams.
+    fn num_output_streams(&self) -> usize;
+
+    /// Sets up the output stream `stream` with `params`, and prepares it for
+    /// the playback.
+    fn open(&mut self, stream: usize, params: &PcmParams) -> DevResult;
+
+    /// Queues the frames in `data` for the playback on an opened stream,
+    /// returns the number of bytes queued.
+    ///
+    /// The playback starts with the first frames queued. Returns
+    /// [`DevError::Again`](axdriver_base::DevError::Again) if no frame can be
+    /// queued until some are played.
+    fn write(&mut self, stream: usize, data: &[u8]) -> DevResult<usize>;
+
+    /// Returns the number of bytes queued on an opened stream and not
+    /// played yet.
+    fn pending_bytes(&mut self, stream: usize) -> DevResult<usize>;
+
+    /// Stops the playback of an opened stream, drops the queued frames and
+    /// releases the stream.
+    fn close(&mut self, stream: usize) -> DevResult;
+}
@@ -0,0 +1,465 @@
+//! PCIe Single Root I/O Virtualization (SR-IOV).
+//!
+//! When the `sriov` feature is enabled, the PCI bus probing enables
+//! `AX_SRIOV_VFS` virtual functions (VFs) on each physical function (PF) that
+//! has the SR-IOV capability. The memory BARs of the VFs are assigned from the
+//! PCI memory ranges, and the VFs are listed in [`AllDevices::vfs`].
+//!
+//! The last `AX_SRIOV_GUEST_VFS` VFs of each PF are kept for the guests: a
+//! hypervisor can map their BARs (and [MSI-X](Msix) table) into the guest
+//! physical space. The other VFs are offered to the host drivers through
+//! [`DriverProbe::probe_vf`], and are attached to the IOMMU if there is one.
+//! VFs do not answer to the bus enumeration, so they never reach
+//! `probe_pci`.
+//!
+//! [`AllDevices::vfs`]: crate::AllDevices::vfs
+//! [`DriverProbe::probe_vf`]: crate::drivers::DriverProbe::probe_vf
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use axdriver_pci::{DeviceFunction, PciRangeAllocator};
+use axhal::mem::{phys_to_virt, VirtAddr};
+
+use crate::{model, AllDevices};
+
+const EXT_CAP_START: u16 = 0x100;
+const EXT_CAP_ID_SRIOV: u16 = 0x0010;
+const CAP_ID_MSIX: u8 = 0x11;
+
+const SRIOV_CTRL: u16 = 0x08;
+const SRIOV_TOTAL_VFS: u16 = 0x0e;
+const SRIOV_NUM_VFS: u16 = 0x10;
+const SRIOV_VF_OFFSET: u16 = 0x14;
+const SRIOV_VF_STRIDE: u16 = 0x16;
+const SRIOV_VF_DEVICE_ID: u16 = 0x1a;
+const SRIOV_VF_BAR0: u16 = 0x24;
+
+const SRIOV_CTRL_VF_ENABLE: u16 = 1 << 0;
+const SRIOV_CTRL_VF_MSE: u16 = 1 << 3;
+
+const MSIX_CTRL_ENABLE: u16 = 1 << 15;
+const MSIX_CTRL_FUNCTION_MASK: u16 = 1 << 14;
+
+const NUM_BARS: usize = 6;
+
+macro_rules! env_or_zero {
+    ($key:literal) => {
+        match option_env!($key) {
+            Some(val) => val,
+            None => "0",
+        }
+    };
+}
+
+/// The number of VFs to enable on each PF.
+fn num_vfs_wanted() -> u16 {
+    env_or_zero!("AX_SRIOV_VFS").parse().unwrap_or(0)
+}
+
+/// The number of VFs of each PF kept for the guests.
+fn num_guest_vfs() -> u16 {
+    env_or_zero!("AX_SRIOV_GUEST_VFS").parse().unwrap_or(0)
+}
+
+/// Accessor of the PCIe extended configuration space through ECAM.
+#[derive(Clone, Copy)]
+struct ConfigSpace {
+    base: VirtAddr,
+}
+
+impl ConfigSpace {
+    fn new() -> Self {
+        Self {
+            base: phys_to_virt(axconfig::PCI_ECAM_BASE.into()),
+        }
+    }
+
+    fn ptr(&self, bdf: DeviceFunction, offset: u16) -> *mut u32 {
+        let addr = ((bdf.bus as usize) << 20)
+            | ((bdf.device as usize) << 15)
+            | ((bdf.function as usize) << 12)
+            | (offset as usize & 0xffc);
+        (self.base + addr).as_mut_ptr() as *mut u32
+    }
+
+    fn read32(&self, bdf: DeviceFunction, offset: u16) -> u32 {
+        unsafe { self.ptr(bdf, offset).read_volatile() }
+    }
+
+    fn write32(&self, bdf: DeviceFunction, offset: u16, val: u32) {
+        unsafe { self.ptr(bdf, offset).write_volatile(val) }
+    }
+
+    fn read16(&self, bdf: DeviceFunction, offset: u16) -> u16 {
+        (self.read32(bdf, offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    fn write16(&self, bdf: DeviceFunction, offset: u16, val: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read32(bdf, offset) & !(0xffff << shift);
+        self.write32(bdf, offset, old | ((val as u32) << shift));
+    }
+
+    /// Finds the capability `id` in the capability list.
+    fn find_capability(&self, bdf: DeviceFunction, id: u8) -> Option<u16> {
+        // the capability list bit of the status register
+        if self.read16(bdf, 0x06) & (1 << 4) == 0 {
+            return None;
+        }
+        let mut ptr = (self.read32(bdf, 0x34) & 0xfc) as u16;
+        // bounded, in case of a malformed list
+        for _ in 0..48 {
+            if ptr == 0 {
+                break;
+            }
+            let header = self.read32(bdf, ptr);
+            if header as u8 == id {
+                return Some(ptr);
+            }
+            ptr = ((header >> 8) & 0xfc) as u16;
+        }
+        None
+    }
+
+    /// Finds the extended capability `id` in the extended configuration
+    /// space.
+    fn find_ext_capability(&self, bdf: DeviceFunction, id: u16) -> Option<u16> {
+        let mut ptr = EXT_CAP_START;
+        for _ in 0..(4096 - 256) / 4 {
+            let header = self.read32(bdf, ptr);
+            if header == 0 || header == u32::MAX {
+                break;
+            }
+            if header as u16 == id {
+                return Some(ptr);
+            }
+            ptr = (header >> 20) as u16 & 0xffc;
+            if ptr < EXT_CAP_START {
+                break;
+            }
+        }
+        None
+    }
+}
+
+/// A memory BAR of a virtual function.
+#[derive(Debug, Clone, Copy)]
+pub struct VfBar {
+    /// The physical address of the BAR.
+    pub address: u64,
+    /// The size of the BAR in bytes.
+    pub size: u64,
+    /// Whether the BAR is prefetchable.
+    pub prefetchable: bool,
+}
+
+/// Who a virtual function is assigned to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfOwner {
+    /// The VF is driven by the host.
+    Host,
+    /// The VF is kept for passing through to a guest.
+    Guest,
+}
+
+/// A virtual function of an SR-IOV capable device.
+#[derive(Debug, Clone)]
+pub struct VirtualFunction {
+    /// The physical function the VF belongs to.
+    pub pf: DeviceFunction,
+    /// The address of the VF.
+    pub bdf: DeviceFunction,
+    /// The index of the VF in its PF, from 0.
+    pub index: u16,
+    /// The vendor ID, the same as the PF.
+    pub vendor_id: u16,
+    /// The device ID of the VFs of the PF.
+    pub device_id: u16,
+    /// The memory BARs.
+    pub bars: [Option<VfBar>; NUM_BARS],
+    /// Who the VF is assigned to.
+    pub owner: VfOwner,
+}
+
+impl VirtualFunction {
+    /// Returns the requester ID of the VF, which identifies it to the IOMMU.
+    pub const fn requester_id(&self) -> u16 {
+        routing_id(self.bdf)
+    }
+
+    /// Returns the MSI-X capability of the VF, if it has one.
+    pub fn msix(&self) -> Option<Msix> {
+        Msix::new(ConfigSpace::new(), self.bdf, |bir| {
+            self.bars
+                .get(bir as usize)
+                .copied()
+                .flatten()
+                .map(|bar| bar.address)
+        })
+    }
+}
+
+const fn routing_id(bdf: DeviceFunction) -> u16 {
+    ((bdf.bus as u16) << 8) | ((bdf.device as u16) << 3) | bdf.function as u16
+}
+
+const fn from_routing_id(rid: u16) -> DeviceFunction {
+    DeviceFunction {
+        bus: (rid >> 8) as u8,
+        device: ((rid >> 3) & 0x1f) as u8,
+        function: (rid & 0x7) as u8,
+    }
+}
+
+/// The MSI-X capability of a PCI function.
+///
+/// There is no MSI controller support in the interrupt layer yet, so the
+/// message address and data of each vector are given by the caller.
+pub struct Msix {
+    cfg: ConfigSpace,
+    bdf: DeviceFunction,
+    cap: u16,
+    table: VirtAddr,
+    table_size: u16,
+}
+
+impl Msix {
+    /// Locates the MSI-X capability and table of `bdf`, `bar_address`
+    /// returning the address of a BAR of the function.
+    fn new(
+        cfg: ConfigSpace,
+        bdf: DeviceFunction,
+        bar_address: impl Fn(u8) -> Option<u64>,
+    ) -> Option<Self> {
+        let cap = cfg.find_capability(bdf, CAP_ID_MSIX)?;
+        let table_size = (cfg.read16(bdf, cap + 2) & 0x7ff) + 1;
+        let table_reg = cfg.read32(bdf, cap + 4);
+        let bar = bar_address((table_reg & 0x7) as u8)?;
+        let table = phys_to_virt((bar as usize + (table_reg & !0x7) as usize).into());
+        Some(Self {
+            cfg,
+            bdf,
+            cap,
+            table,
+            table_size,
+        })
+    }
+
+    /// Returns the number of vectors.
+    pub const fn table_size(&self) -> u16 {
+        self.table_size
+    }
+
+    fn entry(&self, index: u16) -> *mut u32 {
+        assert!(index < self.table_size, "MSI-X vector out of range");
+        (self.table + index as usize * 16).as_mut_ptr() as *mut u32
+    }
+
+    /// Sets the message of vector `index`.
+    pub fn set_entry(&self, index: u16, addr: u64, data: u32) {
+        let entry = self.entry(index);
+        unsafe {
+            entry.write_volatile(addr as u32);
+            entry.add(1).write_volatile((addr >> 32) as u32);
+            entry.add(2).write_volatile(data);
+        }
+    }
+
+    /// Masks or unmasks vector `index`.
+    pub fn set_masked(&self, index: u16, masked: bool) {
+        let ctrl = unsafe { self.entry(index).add(3) };
+        unsafe {
+            let val = ctrl.read_volatile();
+            ctrl.write_volatile(if masked { val | 1 } else { val & !1 });
+        }
+    }
+
+    /// Enables MSI-X, which replaces the INTx interrupts of the function.
+    pub fn enable(&self) {
+        let ctrl = self.cfg.read16(self.bdf, self.cap + 2);
+        self.cfg.write16(
+            self.bdf,
+            self.cap + 2,
+            (ctrl | MSIX_CTRL_ENABLE) & !MSIX_CTRL_FUNCTION_MASK,
+        );
+    }
+
+    /// Disables MSI-X.
+    pub fn disable(&self) {
+        let ctrl = self.cfg.read16(self.bdf, self.cap + 2);
+        self.cfg
+            .write16(self.bdf, self.cap + 2, ctrl & !MSIX_CTRL_ENABLE);
+    }
+}
+
+/// Sizes the VF BARs of the SR-IOV capability at `cap`, and assigns them
+/// for `num_vfs` VFs.
+fn assign_vf_bars(
+    cfg: &ConfigSpace,
+    pf: DeviceFunction,
+    cap: u16,
+    num_vfs: u16,
+    allocator: &mut Option<PciRangeAllocator>,
+) -> Option<[Option<VfBar>; NUM_BARS]> {
+    let mut bars = [None; NUM_BARS];
+    let mut i = 0;
+    while i < NUM_BARS {
+        let reg = cap + SRIOV_VF_BAR0 + 4 * i as u16;
+        let orig = cfg.read32(pf, reg);
+        let is_64bit = orig & 0b110 == 0b100;
+        cfg.write32(pf, reg, u32::MAX);
+        let mut mask = (cfg.read32(pf, reg) & !0xf) as u64;
+        if is_64bit {
+            cfg.write32(pf, reg + 4, u32::MAX);
+            mask |= (cfg.read32(pf, reg + 4) as u64) << 32;
+        } else if mask != 0 {
+            mask |= 0xffff_ffff << 32;
+        }
+        let size = (!mask).wrapping_add(1);
+        if mask != 0 && size != 0 {
+            let total = (size * num_vfs as u64).next_power_of_two();
+            let Some(address) = allocator.as_mut().and_then(|a| a.alloc(total)) else {
+                warn!("no memory range for the VF BAR {} of PCI {}", i, pf);
+                return None;
+            };
+            cfg.write32(pf, reg, address as u32 | (orig & 0xf));
+            if is_64bit {
+                cfg.write32(pf, reg + 4, (address >> 32) as u32);
+            }
+            bars[i] = Some(VfBar {
+                address,
+                size,
+                prefetchable: orig & 0b1000 != 0,
+            });
+        } else {
+            cfg.write32(pf, reg, orig);
+        }
+        i += if is_64bit { 2 } else { 1 };
+    }
+    Some(bars)
+}
+
+/// Enables the VFs of `pf` if it is SR-IOV capable, and returns them.
+pub(crate) fn enable_vfs(
+    pf: DeviceFunction,
+    vendor_id: u16,
+    allocator: &mut Option<PciRangeAllocator>,
+) -> Vec<VirtualFunction> {
+    let cfg = ConfigSpace::new();
+    let wanted = num_vfs_wanted();
+    let Some(cap) = cfg.find_ext_capability(pf, EXT_CAP_ID_SRIOV) else {
+        return Vec::new();
+    };
+    let total = cfg.read16(pf, cap + SRIOV_TOTAL_VFS);
+    let num_vfs = wanted.min(total);
+    info!("PCI {}: SR-IOV capable, {} VFs at most", pf, total);
+    if num_vfs == 0 {
+        return Vec::new();
+    }
+
+    let ctrl = cfg.read16(pf, cap + SRIOV_CTRL);
+    cfg.write16(
+        pf,
+        cap + SRIOV_CTRL,
+        ctrl & !(SRIOV_CTRL_VF_ENABLE | SRIOV_CTRL_VF_MSE),
+    );
+    // the offset and stride depend on the number of VFs
+    cfg.write16(pf, cap + SRIOV_NUM_VFS, num_vfs);
+    let offset = cfg.read16(pf, cap + SRIOV_VF_OFFSET);
+    let stride = cfg.read16(pf, cap + SRIOV_VF_STRIDE);
+    let device_id = cfg.read16(pf, cap + SRIOV_VF_DEVICE_ID);
+    let Some(bars) = assign_vf_bars(&cfg, pf, cap, num_vfs, allocator) else {
+        cfg.write16(pf, cap + SRIOV_NUM_VFS, 0);
+        return Vec::new();
+    };
+    cfg.write16(
+        pf,
+        cap + SRIOV_CTRL,
+        ctrl | SRIOV_CTRL_VF_ENABLE | SRIOV_CTRL_VF_MSE,
+    );
+    // the VFs are not ready until 100ms after they are enabled
+    axhal::time::busy_wait(Duration::from_millis(100));
+
+    let num_guest_vfs = num_guest_vfs().min(num_vfs);
+    let vfs: Vec<_> = (0..num_vfs)
+        .map(|index| {
+            let rid = routing_id(pf)
+                .wrapping_add(offset)
+                .wrapping_add(index.wrapping_mul(stride));
+            let mut vf_bars = bars;
+            for bar in vf_bars.iter_mut().flatten() {
+                bar.address += bar.size * index as u64;
+            }
+            VirtualFunction {
+                pf,
+                bdf: from_routing_id(rid),
+                index,
+                vendor_id,
+                device_id,
+                bars: vf_bars,
+                owner: if index >= num_vfs - num_guest_vfs {
+                    VfOwner::Guest
+                } else {
+                    VfOwner::Host
+                },
+            }
+        })
+        .collect();
+    info!(
+        "PCI {}: enabled {} VFs ({:04x}:{:04x}), {} for the guests",
+        pf, num_vfs, vendor_id, device_id, num_guest_vfs
+    );
+    vfs
+}
+
+impl AllDevices {
+    /// Offers the host VFs to the drivers, and records all the VFs, under
+    /// the node of their PF in the [`model`].
+    pub(crate) fn add_vfs(&mut self, vfs: Vec<VirtualFunction>, pf_node: Option<model::DeviceId>) {
+        for vf in vfs {
+            let name = alloc::format!(
+                "0000:{:02x}:{:02x}.{}",
+                vf.bdf.bus,
+                vf.bdf.device,
+                vf.bdf.function
+            );
+            let node = pf_node.and_then(|pf| {
+                model::register_device(name, model::Subsystem::Bus("pci"), Some(pf)).ok()
+            });
+            if vf.owner == VfOwner::Host {
+                self.probe_vf(&vf, node);
+            }
+            self.vfs.push(vf);
+        }
+    }
+
+    fn probe_vf(&mut self, vf: &VirtualFunction, node: Option<model::DeviceId>) {
+        #[cfg(feature = "iommu")]
+        if let Err(e) = axdma::iommu::attach_device(vf.requester_id() as u32) {
+            warn!("failed to attach VF {} to the IOMMU: {:?}", vf.bdf, e);
+            return;
+        }
+        let mut claimed = false;
+        for_each_drivers!(type Driver, {
+            if !claimed {
+                if let Some(dev) = Driver::probe_vf(vf) {
+                    info!(
+                        "registered a new {:?} device at VF {}: {:?}",
+                        dev.device_type(),
+                        vf.bdf,
+                        dev.device_name(),
+                    );
+                    self.add_device(dev, node);
+                    claimed = true;
+                }
+            }
+        });
+        if !claimed {
+            debug!(
+                "no driver for VF {} ({:04x}:{:04x})",
+                vf.bdf, vf.vendor_id, vf.device_id
+            );
+        }
+    }
+}
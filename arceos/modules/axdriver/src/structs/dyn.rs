@@ -12,6 +12,12 @@ pub type AxBlockDevice = Box<dyn BlockDriverOps>;
 /// The unified type of the graphics display devices.
 #[cfg(feature = "display")]
 pub type AxDisplayDevice = Box<dyn DisplayDriverOps>;
+/// The unified type of the sound devices.
+#[cfg(feature = "sound")]
+pub type AxSoundDevice = Box<dyn SoundDriverOps>;
+/// The unified type of the vsock devices.
+#[cfg(feature = "vsock")]
+pub type AxVsockDevice = Box<dyn VsockDriverOps>;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -31,6 +37,18 @@ impl super::AxDeviceEnum {
     pub fn from_display(dev: impl DisplayDriverOps + 'static) -> Self {
         Self::Display(Box::new(dev))
     }
+
+    /// Constructs a sound device.
+    #[cfg(feature = "sound")]
+    pub fn from_sound(dev: impl SoundDriverOps + 'static) -> Self {
+        Self::Sound(Box::new(dev))
+    }
+
+    /// Constructs a vsock device.
+    #[cfg(feature = "vsock")]
+    pub fn from_vsock(dev: impl VsockDriverOps + 'static) -> Self {
+        Self::Vsock(Box::new(dev))
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
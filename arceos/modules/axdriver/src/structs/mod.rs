@@ -18,6 +18,12 @@ pub enum AxDeviceEnum {
     /// Graphic display device.
     #[cfg(feature = "display")]
     Display(AxDisplayDevice),
+    /// Sound device.
+    #[cfg(feature = "sound")]
+    Sound(AxSoundDevice),
+    /// Socket device of the VM sockets.
+    #[cfg(feature = "vsock")]
+    Vsock(AxVsockDevice),
 }
 
 impl BaseDriverOps for AxDeviceEnum {
@@ -31,6 +37,10 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Block(_) => DeviceType::Block,
             #[cfg(feature = "display")]
             Self::Display(_) => DeviceType::Display,
+            #[cfg(feature = "sound")]
+            Self::Sound(dev) => dev.device_type(),
+            #[cfg(feature = "vsock")]
+            Self::Vsock(dev) => dev.device_type(),
             _ => unreachable!(),
         }
     }
@@ -45,6 +55,10 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Block(dev) => dev.device_name(),
             #[cfg(feature = "display")]
             Self::Display(dev) => dev.device_name(),
+            #[cfg(feature = "sound")]
+            Self::Sound(dev) => dev.device_name(),
+            #[cfg(feature = "vsock")]
+            Self::Vsock(dev) => dev.device_name(),
             _ => unreachable!(),
         }
     }
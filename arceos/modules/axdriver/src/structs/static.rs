@@ -4,6 +4,10 @@ pub use crate::drivers::AxBlockDevice;
 pub use crate::drivers::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use crate::drivers::AxNetDevice;
+#[cfg(feature = "sound")]
+pub use crate::drivers::AxSoundDevice;
+#[cfg(feature = "vsock")]
+pub use crate::drivers::AxVsockDevice;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -23,6 +27,18 @@ impl super::AxDeviceEnum {
     pub const fn from_display(dev: AxDisplayDevice) -> Self {
         Self::Display(dev)
     }
+
+    /// Constructs a sound device.
+    #[cfg(feature = "sound")]
+    pub const fn from_sound(dev: AxSoundDevice) -> Self {
+        Self::Sound(dev)
+    }
+
+    /// Constructs a vsock device.
+    #[cfg(feature = "vsock")]
+    pub const fn from_vsock(dev: AxVsockDevice) -> Self {
+        Self::Vsock(dev)
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
@@ -1,10 +1,13 @@
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 
+#[cfg(not(feature = "iommu"))]
 use axalloc::global_allocator;
 use axdriver_base::{BaseDriverOps, DevResult, DeviceType};
 use axdriver_virtio::{BufferDirection, PhysAddr, VirtIoHal};
-use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::mem::phys_to_virt;
+#[cfg(not(feature = "iommu"))]
+use axhal::mem::virt_to_phys;
 use cfg_if::cfg_if;
 
 use crate::{drivers::DriverProbe, AxDeviceEnum};
@@ -34,7 +37,7 @@ cfg_if! {
 
         impl VirtIoDevMeta for VirtIoNet {
             const DEVICE_TYPE: DeviceType = DeviceType::Net;
-            type Device = axdriver_virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>;
+            type Device = crate::virtio_net::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>;
 
             fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
                 Ok(AxDeviceEnum::from_net(Self::Device::try_new(transport)?))
@@ -73,6 +76,134 @@ cfg_if! {
     }
 }
 
+cfg_if! {
+    if #[cfg(sound_dev = "virtio-snd")] {
+        /// The VirtIO sound device, which is not probed by `axdriver_virtio`.
+        pub type VirtIoSndDev = crate::virtio_snd::VirtIoSoundDev<VirtIoHalImpl, VirtIoTransport>;
+
+        const VIRTIO_ID_SOUND: u32 = 25;
+
+        pub struct VirtIoSndDriver;
+
+        impl DriverProbe for VirtIoSndDriver {
+            #[cfg(bus = "mmio")]
+            fn probe_mmio(mmio_base: usize, mmio_size: usize) -> Option<AxDeviceEnum> {
+                use virtio_drivers::transport::mmio::VirtIOHeader;
+
+                let base_vaddr = phys_to_virt(mmio_base.into());
+                // The device ID register.
+                let device_id =
+                    unsafe { (base_vaddr.as_ptr().add(8) as *const u32).read_volatile() };
+                if device_id != VIRTIO_ID_SOUND {
+                    return None;
+                }
+                let header = NonNull::new(base_vaddr.as_mut_ptr() as *mut VirtIOHeader)?;
+                let transport = unsafe { VirtIoTransport::new(header) }.ok()?;
+                match VirtIoSndDev::try_new(transport) {
+                    Ok(dev) => Some(AxDeviceEnum::from_sound(dev)),
+                    Err(e) => {
+                        warn!(
+                            "failed to initialize MMIO device at [PA:{:#x}, PA:{:#x}): {:?}",
+                            mmio_base,
+                            mmio_base + mmio_size,
+                            e
+                        );
+                        None
+                    }
+                }
+            }
+
+            #[cfg(bus = "pci")]
+            fn probe_pci(
+                root: &mut PciRoot,
+                bdf: DeviceFunction,
+                dev_info: &DeviceFunctionInfo,
+            ) -> Option<AxDeviceEnum> {
+                if dev_info.vendor_id != 0x1af4
+                    || dev_info.device_id != 0x1040 + VIRTIO_ID_SOUND as u16
+                {
+                    return None;
+                }
+                let transport = VirtIoTransport::new::<VirtIoHalImpl>(root, bdf).ok()?;
+                match VirtIoSndDev::try_new(transport) {
+                    Ok(dev) => Some(AxDeviceEnum::from_sound(dev)),
+                    Err(e) => {
+                        warn!(
+                            "failed to initialize PCI device at {}({}): {:?}",
+                            bdf, dev_info, e
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(vsock_dev = "virtio-vsock")] {
+        /// The VirtIO socket device, which is not probed by `axdriver_virtio`.
+        pub type VirtIoVsockDev = crate::virtio_vsock::VirtIoVsockDev<VirtIoHalImpl, VirtIoTransport>;
+
+        const VIRTIO_ID_SOCKET: u32 = 19;
+
+        pub struct VirtIoVsockDriver;
+
+        impl DriverProbe for VirtIoVsockDriver {
+            #[cfg(bus = "mmio")]
+            fn probe_mmio(mmio_base: usize, mmio_size: usize) -> Option<AxDeviceEnum> {
+                use virtio_drivers::transport::mmio::VirtIOHeader;
+
+                let base_vaddr = phys_to_virt(mmio_base.into());
+                // The device ID register.
+                let device_id =
+                    unsafe { (base_vaddr.as_ptr().add(8) as *const u32).read_volatile() };
+                if device_id != VIRTIO_ID_SOCKET {
+                    return None;
+                }
+                let header = NonNull::new(base_vaddr.as_mut_ptr() as *mut VirtIOHeader)?;
+                let transport = unsafe { VirtIoTransport::new(header) }.ok()?;
+                match VirtIoVsockDev::try_new(transport) {
+                    Ok(dev) => Some(AxDeviceEnum::from_vsock(dev)),
+                    Err(e) => {
+                        warn!(
+                            "failed to initialize MMIO device at [PA:{:#x}, PA:{:#x}): {:?}",
+                            mmio_base,
+                            mmio_base + mmio_size,
+                            e
+                        );
+                        None
+                    }
+                }
+            }
+
+            #[cfg(bus = "pci")]
+            fn probe_pci(
+                root: &mut PciRoot,
+                bdf: DeviceFunction,
+                dev_info: &DeviceFunctionInfo,
+            ) -> Option<AxDeviceEnum> {
+                if dev_info.vendor_id != 0x1af4
+                    || dev_info.device_id != 0x1040 + VIRTIO_ID_SOCKET as u16
+                {
+                    return None;
+                }
+                let transport = VirtIoTransport::new::<VirtIoHalImpl>(root, bdf).ok()?;
+                match VirtIoVsockDev::try_new(transport) {
+                    Ok(dev) => Some(AxDeviceEnum::from_vsock(dev)),
+                    Err(e) => {
+                        warn!(
+                            "failed to initialize PCI device at {}({}): {:?}",
+                            bdf, dev_info, e
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// A common driver for all VirtIO devices that implements [`DriverProbe`].
 pub struct VirtIoDriver<D: VirtIoDevMeta + ?Sized>(PhantomData<D>);
 
@@ -139,6 +270,57 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
 
 pub struct VirtIoHalImpl;
 
+#[cfg(feature = "iommu")]
+const fn dma_direction(direction: BufferDirection) -> axdma::DmaDirection {
+    match direction {
+        BufferDirection::DriverToDevice => axdma::DmaDirection::ToDevice,
+        BufferDirection::DeviceToDriver => axdma::DmaDirection::FromDevice,
+        BufferDirection::Both => axdma::DmaDirection::Bidirectional,
+    }
+}
+
+/// With an IOMMU, the DMA memory and the shared buffers have to be mapped
+/// for the devices.
+#[cfg(feature = "iommu")]
+unsafe impl VirtIoHal for VirtIoHalImpl {
+    fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
+        let layout = core::alloc::Layout::from_size_align(pages * 0x1000, 0x1000).unwrap();
+        match unsafe { axdma::alloc_coherent(layout) } {
+            Ok(dma) => (dma.bus_addr.as_u64() as usize, dma.cpu_addr),
+            Err(_) => (0, NonNull::dangling()),
+        }
+    }
+
+    unsafe fn dma_dealloc(paddr: PhysAddr, vaddr: NonNull<u8>, pages: usize) -> i32 {
+        let layout = core::alloc::Layout::from_size_align(pages * 0x1000, 0x1000).unwrap();
+        let dma = axdma::DMAInfo {
+            cpu_addr: vaddr,
+            bus_addr: axdma::BusAddr::new(paddr as u64),
+        };
+        unsafe { axdma::dealloc_coherent(dma, layout) };
+        0
+    }
+
+    #[inline]
+    unsafe fn mmio_phys_to_virt(paddr: PhysAddr, _size: usize) -> NonNull<u8> {
+        NonNull::new(phys_to_virt(paddr.into()).as_mut_ptr()).unwrap()
+    }
+
+    unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
+        let vaddr = buffer.as_ptr() as *mut u8 as usize;
+        match axdma::map_single(vaddr.into(), buffer.len(), dma_direction(direction)) {
+            Ok(bus_addr) => bus_addr.as_u64() as usize,
+            Err(e) => panic!("failed to map a virtio buffer for DMA: {:?}", e),
+        }
+    }
+
+    unsafe fn unshare(paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
+        let bus_addr = axdma::BusAddr::new(paddr as u64);
+        axdma::unmap_single(bus_addr, buffer.len(), dma_direction(direction));
+    }
+}
+
+#[cfg(not(feature = "iommu"))]
 unsafe impl VirtIoHal for VirtIoHalImpl {
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
         let vaddr = if let Ok(vaddr) = global_allocator().alloc_pages(pages, 0x1000) {
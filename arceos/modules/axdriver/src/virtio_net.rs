@@ -0,0 +1,183 @@
+//! Driver for the VirtIO network device, with the jumbo frames.
+//!
+//! The receive and transmit buffers are sized for the MTU given by the
+//! `virtio_net.mtu` parameter at boot, up to [`JUMBO_MTU`], which the network
+//! stack reads back with [`max_mtu`](crate::net::max_mtu). The device must
+//! deliver the frames of the MTU of its backend (e.g. the tap of the host)
+//! in them: the ones longer are dropped by the device.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_net::{EthernetAddress, NetBuf, NetBufBox, NetBufPool, NetBufPtr, NetDriverOps};
+use virtio_drivers::device::net::VirtIONetRaw;
+use virtio_drivers::transport::Transport;
+use virtio_drivers::{Error, Hal};
+
+use crate::net::{JUMBO_MTU, MIN_MTU, STANDARD_MTU};
+use crate::params::{register_driver_param, DriverParam, DRIVER_PARAMS};
+
+#[register_driver_param(DRIVER_PARAMS)]
+pub(crate) static MTU: DriverParam = DriverParam::uint(
+    "virtio_net",
+    "mtu",
+    STANDARD_MTU,
+    MIN_MTU,
+    JUMBO_MTU,
+    "the largest IP packet of the buffers",
+)
+.boot_only();
+
+/// The header of the VirtIO network device, with the ones of the Ethernet
+/// frame and of an 802.1Q tag.
+const HEADER_ROOM: usize = 12 + 14 + 4;
+
+const fn as_dev_err(e: Error) -> DevError {
+    match e {
+        Error::QueueFull | Error::NotReady => DevError::Again,
+        Error::AlreadyUsed => DevError::AlreadyExists,
+        Error::InvalidParam => DevError::InvalidParam,
+        Error::DmaError => DevError::NoMemory,
+        Error::Unsupported => DevError::Unsupported,
+        _ => DevError::BadState,
+    }
+}
+
+/// The VirtIO network device, with `QS` entries in each queue.
+pub struct VirtIoNetDev<H: Hal, T: Transport, const QS: usize> {
+    rx_buffers: [Option<NetBufBox>; QS],
+    tx_buffers: [Option<NetBufBox>; QS],
+    free_tx_bufs: Vec<NetBufBox>,
+    _buf_pool: Arc<NetBufPool>,
+    inner: VirtIONetRaw<H, T, QS>,
+}
+
+unsafe impl<H: Hal, T: Transport, const QS: usize> Send for VirtIoNetDev<H, T, QS> {}
+unsafe impl<H: Hal, T: Transport, const QS: usize> Sync for VirtIoNetDev<H, T, QS> {}
+
+impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    pub fn try_new(transport: T) -> DevResult<Self> {
+        let inner = VirtIONetRaw::new(transport).map_err(as_dev_err)?;
+        let buf_len = HEADER_ROOM + MTU.get();
+        let buf_pool = NetBufPool::new(2 * QS, buf_len)?;
+        let mut dev = Self {
+            rx_buffers: [const { None }; QS],
+            tx_buffers: [const { None }; QS],
+            free_tx_bufs: Vec::with_capacity(QS),
+            _buf_pool: buf_pool.clone(),
+            inner,
+        };
+
+        for (i, rx_buf_place) in dev.rx_buffers.iter_mut().enumerate() {
+            let mut rx_buf = buf_pool.alloc_boxed().ok_or(DevError::NoMemory)?;
+            let token =
+                unsafe { dev.inner.receive_begin(rx_buf.raw_buf_mut()) }.map_err(as_dev_err)?;
+            assert_eq!(token, i as u16);
+            *rx_buf_place = Some(rx_buf);
+        }
+        for _ in 0..QS {
+            let mut tx_buf = buf_pool.alloc_boxed().ok_or(DevError::NoMemory)?;
+            let hdr_len = dev
+                .inner
+                .fill_buffer_header(tx_buf.raw_buf_mut())
+                .map_err(as_dev_err)?;
+            tx_buf.set_header_len(hdr_len);
+            dev.free_tx_bufs.push(tx_buf);
+        }
+        info!("virtio-net: MTU {}, {} bytes buffers", MTU.get(), buf_len);
+        Ok(dev)
+    }
+}
+
+impl<H: Hal, T: Transport, const QS: usize> BaseDriverOps for VirtIoNetDev<H, T, QS> {
+    fn device_name(&self) -> &str {
+        "virtio-net"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+}
+
+impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T, QS> {
+    fn mac_address(&self) -> EthernetAddress {
+        EthernetAddress(self.inner.mac_address())
+    }
+
+    fn can_transmit(&self) -> bool {
+        !self.free_tx_bufs.is_empty() && self.inner.can_send()
+    }
+
+    fn can_receive(&self) -> bool {
+        self.inner.poll_receive().is_some()
+    }
+
+    fn rx_queue_size(&self) -> usize {
+        QS
+    }
+
+    fn tx_queue_size(&self) -> usize {
+        QS
+    }
+
+    fn recycle_rx_buffer(&mut self, rx_buf: NetBufPtr) -> DevResult {
+        let mut rx_buf = unsafe { NetBuf::from_buf_ptr(rx_buf) };
+        let token =
+            unsafe { self.inner.receive_begin(rx_buf.raw_buf_mut()) }.map_err(as_dev_err)? as usize;
+        if self.rx_buffers[token].is_some() {
+            return Err(DevError::BadState);
+        }
+        self.rx_buffers[token] = Some(rx_buf);
+        Ok(())
+    }
+
+    fn recycle_tx_buffers(&mut self) -> DevResult {
+        while let Some(token) = self.inner.poll_transmit() {
+            let tx_buf = self.tx_buffers[token as usize]
+                .take()
+                .ok_or(DevError::BadState)?;
+            unsafe {
+                self.inner
+                    .transmit_complete(token, tx_buf.packet_with_header())
+            }
+            .map_err(as_dev_err)?;
+            self.free_tx_bufs.push(tx_buf);
+        }
+        Ok(())
+    }
+
+    fn transmit(&mut self, tx_buf: NetBufPtr) -> DevResult {
+        let tx_buf = unsafe { NetBuf::from_buf_ptr(tx_buf) };
+        let token = unsafe { self.inner.transmit_begin(tx_buf.packet_with_header()) }
+            .map_err(as_dev_err)? as usize;
+        self.tx_buffers[token] = Some(tx_buf);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> DevResult<NetBufPtr> {
+        let Some(token) = self.inner.poll_receive() else {
+            return Err(DevError::Again);
+        };
+        let mut rx_buf = self.rx_buffers[token as usize]
+            .take()
+            .ok_or(DevError::BadState)?;
+        let (hdr_len, pkt_len) =
+            unsafe { self.inner.receive_complete(token, rx_buf.raw_buf_mut()) }
+                .map_err(as_dev_err)?;
+        rx_buf.set_header_len(hdr_len);
+        rx_buf.set_packet_len(pkt_len);
+        Ok(rx_buf.into_buf_ptr())
+    }
+
+    fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr> {
+        let mut tx_buf = self.free_tx_bufs.pop().ok_or(DevError::NoMemory)?;
+        if tx_buf.header_len() + size > tx_buf.capacity() {
+            self.free_tx_bufs.push(tx_buf);
+            return Err(DevError::InvalidParam);
+        }
+        tx_buf.set_packet_len(size);
+        Ok(tx_buf.into_buf_ptr())
+    }
+}
@@ -0,0 +1,575 @@
+//! Driver of the VirtIO sound devices, for the PCM playback.
+//!
+//! Only the output streams are used. The device is polled: the requests on
+//! the control queue are waited for, and the buffers of frames on the TX
+//! queue are reclaimed when writing to or querying a stream.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ptr::{addr_of, NonNull};
+use core::sync::atomic::{fence, Ordering};
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+use virtio_drivers::{BufferDirection, Hal, PhysAddr};
+
+use crate::sound::{PcmFormat, PcmParams, SoundDriverOps};
+
+const PAGE_SIZE: usize = 0x1000;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const CONTROL_QUEUE: u16 = 0;
+const EVENT_QUEUE: u16 = 1;
+const TX_QUEUE: u16 = 2;
+const QUEUE_SIZE: u16 = 32;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+// Request codes
+const R_PCM_INFO: u32 = 0x0100;
+const R_PCM_SET_PARAMS: u32 = 0x0101;
+const R_PCM_PREPARE: u32 = 0x0102;
+const R_PCM_RELEASE: u32 = 0x0103;
+const R_PCM_START: u32 = 0x0104;
+const R_PCM_STOP: u32 = 0x0105;
+
+// Status codes
+const S_OK: u32 = 0x8000;
+const S_BAD_MSG: u32 = 0x8001;
+const S_NOT_SUPP: u32 = 0x8002;
+
+const D_OUTPUT: u8 = 0;
+
+/// The rates of the bits of [`PcmInfo::rates`].
+const RATES: [u32; 14] = [
+    5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176400, 192000,
+    384000,
+];
+
+/// Offset of the response in the buffer of the control requests.
+const RESPONSE_OFFSET: usize = PAGE_SIZE / 2;
+/// At most the infos of this number of streams fit in the response.
+const MAX_STREAMS: u32 = ((PAGE_SIZE - RESPONSE_OFFSET - 4) / size_of::<PcmInfo>()) as u32;
+
+const EVENT_SIZE: usize = 8;
+const NUM_EVENT_BUFS: usize = 4;
+
+/// Number of the buffers of frames of a stream.
+const PERIODS: usize = 4;
+/// Duration of the frames of a buffer, in milliseconds.
+const PERIOD_MS: usize = 20;
+
+// Layout of a buffer of frames: the header, the status written by the
+// device, then the frames.
+const TX_STATUS_OFFSET: usize = 8;
+const TX_DATA_OFFSET: usize = 16;
+
+#[repr(C)]
+struct Config {
+    jacks: u32,
+    streams: u32,
+    chmaps: u32,
+}
+
+/// `struct virtio_snd_pcm_info`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PcmInfo {
+    hda_fn_nid: u32,
+    features: u32,
+    formats: u64,
+    rates: u64,
+    direction: u8,
+    channels_min: u8,
+    channels_max: u8,
+    _padding: [u8; 5],
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+const fn align_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+const fn format_code(format: PcmFormat) -> u8 {
+    match format {
+        PcmFormat::U8 => 4,
+        PcmFormat::S16Le => 5,
+        PcmFormat::S32Le => 17,
+        PcmFormat::FloatLe => 19,
+    }
+}
+
+/// Zeroed pages allocated for DMA.
+struct Dma<H: Hal> {
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    pages: usize,
+    _hal: PhantomData<H>,
+}
+
+impl<H: Hal> Dma<H> {
+    fn new(pages: usize) -> DevResult<Self> {
+        let (paddr, vaddr) = H::dma_alloc(pages, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        unsafe { vaddr.as_ptr().write_bytes(0, pages * PAGE_SIZE) };
+        Ok(Self {
+            paddr,
+            vaddr,
+            pages,
+            _hal: PhantomData,
+        })
+    }
+
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        unsafe { self.vaddr.as_ptr().add(offset) as *mut T }
+    }
+
+    fn paddr(&self, offset: usize) -> PhysAddr {
+        self.paddr + offset
+    }
+}
+
+impl<H: Hal> Drop for Dma<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, self.pages) };
+    }
+}
+
+/// A split virtqueue, in the legacy layout that all the transports accept.
+struct Queue<H: Hal> {
+    dma: Dma<H>,
+    size: u16,
+    /// Offsets of the available and used rings in `dma`.
+    avail: usize,
+    used: usize,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl<H: Hal> Queue<H> {
+    fn new<T: Transport>(transport: &mut T, index: u16) -> DevResult<Self> {
+        let size = transport.max_queue_size(index).min(QUEUE_SIZE as u32) as u16;
+        if size == 0 {
+            return Err(DevError::BadState);
+        }
+        let avail = size_of::<Descriptor>() * size as usize;
+        let used = align_up(avail + 2 * (size as usize + 3));
+        let dma = Dma::new((used + align_up(6 + 8 * size as usize)) / PAGE_SIZE)?;
+        let queue = Self {
+            dma,
+            size,
+            avail,
+            used,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        for i in 0..size - 1 {
+            unsafe { (*queue.desc(i)).next = i + 1 };
+        }
+        transport.queue_set(
+            index,
+            size as u32,
+            queue.dma.paddr(0),
+            queue.dma.paddr(avail),
+            queue.dma.paddr(used),
+        );
+        Ok(queue)
+    }
+
+    fn desc(&self, i: u16) -> *mut Descriptor {
+        self.dma.ptr::<Descriptor>(0).wrapping_add(i as usize)
+    }
+
+    /// Adds a chain of buffers, the ones with `true` being written by the
+    /// device, returns the index of its head.
+    fn add(&mut self, bufs: &[(PhysAddr, usize, bool)]) -> DevResult<u16> {
+        if bufs.len() > self.num_free as usize {
+            return Err(DevError::Again);
+        }
+        let head = self.free_head;
+        let mut i = head;
+        for (n, &(paddr, len, device_writes)) in bufs.iter().enumerate() {
+            let desc = self.desc(i);
+            let mut flags = if device_writes { DESC_F_WRITE } else { 0 };
+            if n + 1 < bufs.len() {
+                // Chained to the next free descriptor.
+                flags |= DESC_F_NEXT;
+            }
+            unsafe {
+                (*desc).addr = paddr as u64;
+                (*desc).len = len as u32;
+                (*desc).flags = flags;
+                i = (*desc).next;
+            }
+        }
+        self.free_head = i;
+        self.num_free -= bufs.len() as u16;
+
+        let ring = self.dma.ptr::<u16>(self.avail + 4);
+        unsafe {
+            ring.add((self.avail_idx % self.size) as usize)
+                .write_volatile(head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            self.dma
+                .ptr::<u16>(self.avail + 2)
+                .write_volatile(self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Takes a chain of buffers used by the device, returns its head.
+    fn pop_used(&mut self) -> Option<u16> {
+        fence(Ordering::SeqCst);
+        let used_idx = unsafe { self.dma.ptr::<u16>(self.used + 2).read_volatile() };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        let slot = (self.last_used_idx % self.size) as usize;
+        let head = unsafe {
+            self.dma
+                .ptr::<u32>(self.used + 4 + 8 * slot)
+                .read_volatile()
+        } as u16;
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let mut i = head;
+        loop {
+            self.num_free += 1;
+            let desc = self.desc(i);
+            unsafe {
+                if (*desc).flags & DESC_F_NEXT == 0 {
+                    (*desc).next = self.free_head;
+                    break;
+                }
+                i = (*desc).next;
+            }
+        }
+        self.free_head = head;
+        Some(head)
+    }
+}
+
+/// A buffer of frames for the TX queue.
+struct TxBuf<H: Hal> {
+    dma: Dma<H>,
+    /// The head of its descriptors, while it is queued.
+    token: Option<u16>,
+    len: usize,
+}
+
+struct Playback<H: Hal> {
+    period_bytes: usize,
+    frame_bytes: usize,
+    bufs: Vec<TxBuf<H>>,
+    started: bool,
+}
+
+struct Output<H: Hal> {
+    id: u32,
+    info: PcmInfo,
+    playback: Option<Playback<H>>,
+}
+
+/// The VirtIO sound device driver.
+pub struct VirtIoSoundDev<H: Hal, T: Transport> {
+    transport: T,
+    control: Queue<H>,
+    _event: Queue<H>,
+    tx: Queue<H>,
+    /// The control request, then the response at [`RESPONSE_OFFSET`].
+    control_buf: Dma<H>,
+    /// The buffers of the events, never read.
+    _event_bufs: Dma<H>,
+    outputs: Vec<Output<H>>,
+}
+
+unsafe impl<H: Hal, T: Transport + Send> Send for VirtIoSoundDev<H, T> {}
+unsafe impl<H: Hal, T: Transport + Sync> Sync for VirtIoSoundDev<H, T> {}
+
+impl<H: Hal, T: Transport> VirtIoSoundDev<H, T> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        let status = DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER;
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(status);
+        let features = transport.read_device_features() & VIRTIO_F_VERSION_1;
+        transport.write_driver_features(features);
+        transport.set_status(status | DeviceStatus::FEATURES_OK);
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let control = Queue::new(&mut transport, CONTROL_QUEUE)?;
+        let mut event = Queue::new(&mut transport, EVENT_QUEUE)?;
+        let tx = Queue::new(&mut transport, TX_QUEUE)?;
+        let event_bufs = Dma::<H>::new(1)?;
+        for i in 0..NUM_EVENT_BUFS {
+            event.add(&[(event_bufs.paddr(i * EVENT_SIZE), EVENT_SIZE, true)])?;
+        }
+        transport.finish_init();
+        transport.notify(EVENT_QUEUE);
+
+        let config = transport
+            .config_space::<Config>()
+            .map_err(|_| DevError::Unsupported)?;
+        let streams = unsafe { addr_of!((*config.as_ptr()).streams).read_volatile() };
+        let mut dev = Self {
+            transport,
+            control,
+            _event: event,
+            tx,
+            control_buf: Dma::new(1)?,
+            _event_bufs: event_bufs,
+            outputs: Vec::new(),
+        };
+        dev.query_outputs(streams.min(MAX_STREAMS))?;
+        Ok(dev)
+    }
+
+    /// Sends a control request, waits for the response of `resp_len` bytes
+    /// and returns its address.
+    fn request(&mut self, req: &[u32], resp_len: usize) -> DevResult<*const u8> {
+        let buf = &self.control_buf;
+        for (i, &word) in req.iter().enumerate() {
+            unsafe { buf.ptr::<u32>(4 * i).write_volatile(word.to_le()) };
+        }
+        let token = self.control.add(&[
+            (buf.paddr(0), 4 * req.len(), false),
+            (buf.paddr(RESPONSE_OFFSET), resp_len, true),
+        ])?;
+        self.transport.notify(CONTROL_QUEUE);
+        while self.control.pop_used() != Some(token) {
+            core::hint::spin_loop();
+        }
+        let status = unsafe { buf.ptr::<u32>(RESPONSE_OFFSET).read_volatile() };
+        match u32::from_le(status) {
+            S_OK => Ok(buf.ptr(RESPONSE_OFFSET)),
+            S_BAD_MSG => Err(DevError::InvalidParam),
+            S_NOT_SUPP => Err(DevError::Unsupported),
+            _ => Err(DevError::Io),
+        }
+    }
+
+    fn query_outputs(&mut self, streams: u32) -> DevResult {
+        if streams == 0 {
+            return Ok(());
+        }
+        let info_size = size_of::<PcmInfo>();
+        let resp = self.request(
+            &[R_PCM_INFO, 0, streams, info_size as u32],
+            4 + streams as usize * info_size,
+        )?;
+        for id in 0..streams {
+            let info = unsafe {
+                (resp.add(4 + id as usize * info_size) as *const PcmInfo).read_unaligned()
+            };
+            if info.direction == D_OUTPUT {
+                self.outputs.push(Output {
+                    id,
+                    info,
+                    playback: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn playback(&mut self, stream: usize) -> DevResult<&mut Playback<H>> {
+        self.outputs
+            .get_mut(stream)
+            .and_then(|out| out.playback.as_mut())
+            .ok_or(DevError::BadState)
+    }
+
+    /// Marks the buffers of frames played as free.
+    fn reclaim(&mut self) {
+        while let Some(token) = self.tx.pop_used() {
+            let bufs = self
+                .outputs
+                .iter_mut()
+                .filter_map(|out| out.playback.as_mut())
+                .flat_map(|playback| playback.bufs.iter_mut());
+            for buf in bufs.filter(|buf| buf.token == Some(token)) {
+                let status = unsafe { buf.dma.ptr::<u32>(TX_STATUS_OFFSET).read_volatile() };
+                if u32::from_le(status) != S_OK {
+                    warn!("virtio-snd: failed to play frames: {:#x}", status);
+                }
+                buf.token = None;
+            }
+        }
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIoSoundDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-snd"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        // There is no type for sound devices, which are character devices
+        // elsewhere.
+        DeviceType::Char
+    }
+}
+
+impl<H: Hal, T: Transport> SoundDriverOps for VirtIoSoundDev<H, T> {
+    fn num_output_streams(&self) -> usize {
+        self.outputs.len()
+    }
+
+    fn open(&mut self, stream: usize, params: &PcmParams) -> DevResult {
+        let out = self.outputs.get(stream).ok_or(DevError::InvalidParam)?;
+        if out.playback.is_some() {
+            return Err(DevError::ResourceBusy);
+        }
+        let (id, info) = (out.id, out.info);
+        let format = format_code(params.format);
+        let rate = RATES
+            .iter()
+            .position(|&r| r == params.rate)
+            .ok_or(DevError::Unsupported)?;
+        if info.formats & (1 << format) == 0
+            || info.rates & (1 << rate) == 0
+            || !(info.channels_min..=info.channels_max).contains(&params.channels)
+        {
+            return Err(DevError::Unsupported);
+        }
+
+        let frame_bytes = params.frame_bytes();
+        let period_bytes = (params.rate as usize * PERIOD_MS / 1000).max(1) * frame_bytes;
+        let pages = align_up(TX_DATA_OFFSET + period_bytes) / PAGE_SIZE;
+        let bufs = (0..PERIODS)
+            .map(|_| {
+                let dma = Dma::<H>::new(pages)?;
+                unsafe { dma.ptr::<u32>(0).write_volatile(id.to_le()) };
+                Ok(TxBuf {
+                    dma,
+                    token: None,
+                    len: 0,
+                })
+            })
+            .collect::<DevResult<Vec<_>>>()?;
+
+        self.request(
+            &[
+                R_PCM_SET_PARAMS,
+                id,
+                (period_bytes * PERIODS) as u32,
+                period_bytes as u32,
+                0,
+                params.channels as u32 | (format as u32) << 8 | (rate as u32) << 16,
+            ],
+            4,
+        )?;
+        self.request(&[R_PCM_PREPARE, id], 4)?;
+        self.outputs[stream].playback = Some(Playback {
+            period_bytes,
+            frame_bytes,
+            bufs,
+            started: false,
+        });
+        Ok(())
+    }
+
+    fn write(&mut self, stream: usize, data: &[u8]) -> DevResult<usize> {
+        self.reclaim();
+        let Self {
+            outputs,
+            tx,
+            transport,
+            ..
+        } = self;
+        let playback = outputs
+            .get_mut(stream)
+            .and_then(|out| out.playback.as_mut())
+            .ok_or(DevError::BadState)?;
+        if data.len() < playback.frame_bytes {
+            return Err(DevError::InvalidParam);
+        }
+
+        let mut written = 0;
+        for buf in playback.bufs.iter_mut().filter(|buf| buf.token.is_none()) {
+            let mut len = (data.len() - written).min(playback.period_bytes);
+            len -= len % playback.frame_bytes;
+            if len == 0 {
+                break;
+            }
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[written..].as_ptr(),
+                    buf.dma.ptr(TX_DATA_OFFSET),
+                    len,
+                );
+            }
+            buf.token = Some(tx.add(&[
+                (buf.dma.paddr(0), 4, false),
+                (buf.dma.paddr(TX_DATA_OFFSET), len, false),
+                (buf.dma.paddr(TX_STATUS_OFFSET), 8, true),
+            ])?);
+            buf.len = len;
+            written += len;
+        }
+        if written == 0 {
+            return Err(DevError::Again);
+        }
+        transport.notify(TX_QUEUE);
+
+        if !playback.started {
+            playback.started = true;
+            let id = outputs[stream].id;
+            self.request(&[R_PCM_START, id], 4)?;
+        }
+        Ok(written)
+    }
+
+    fn pending_bytes(&mut self, stream: usize) -> DevResult<usize> {
+        self.reclaim();
+        let playback = self.playback(stream)?;
+        Ok(playback
+            .bufs
+            .iter()
+            .filter(|buf| buf.token.is_some())
+            .map(|buf| buf.len)
+            .sum())
+    }
+
+    fn close(&mut self, stream: usize) -> DevResult {
+        let started = self.playback(stream)?.started;
+        let id = self.outputs[stream].id;
+        if started {
+            self.request(&[R_PCM_STOP, id], 4)?;
+        }
+        self.request(&[R_PCM_RELEASE, id], 4)?;
+        // The device completes the queued buffers on release.
+        while self.pending_bytes(stream)? > 0 {
+            core::hint::spin_loop();
+        }
+        self.outputs[stream].playback = None;
+        Ok(())
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoSoundDev<H, T> {
+    fn drop(&mut self) {
+        // Stops the device before freeing the queues and the buffers.
+        self.transport.set_status(DeviceStatus::empty());
+        for queue in [CONTROL_QUEUE, EVENT_QUEUE, TX_QUEUE] {
+            self.transport.queue_unset(queue);
+        }
+    }
+}
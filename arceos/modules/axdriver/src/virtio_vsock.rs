@@ -0,0 +1,149 @@
+//! Driver for the VirtIO socket device, built on the connection manager of
+//! `virtio-drivers`.
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::device::socket::{
+    self, SocketError, VirtIOSocket, VsockConnectionManager, VsockEventType,
+};
+use virtio_drivers::transport::Transport;
+use virtio_drivers::{Error, Hal};
+
+use crate::vsock::{VsockAddr, VsockConnId, VsockDriverOps, VsockEvent};
+
+const fn as_dev_err(e: Error) -> DevError {
+    match e {
+        Error::QueueFull | Error::NotReady => DevError::Again,
+        Error::InvalidParam => DevError::InvalidParam,
+        Error::DmaError => DevError::NoMemory,
+        Error::Unsupported => DevError::Unsupported,
+        Error::SocketDeviceError(SocketError::InsufficientBufferSpaceInPeer) => DevError::Again,
+        Error::SocketDeviceError(SocketError::ConnectionExists) => DevError::AlreadyExists,
+        Error::SocketDeviceError(SocketError::NotConnected)
+        | Error::SocketDeviceError(SocketError::PeerSocketShutdown)
+        | Error::SocketDeviceError(SocketError::ConnectionFailed) => DevError::BadState,
+        _ => DevError::Io,
+    }
+}
+
+const fn as_addr(addr: socket::VsockAddr) -> VsockAddr {
+    VsockAddr {
+        cid: addr.cid,
+        port: addr.port,
+    }
+}
+
+const fn as_virtio_addr(addr: VsockAddr) -> socket::VsockAddr {
+    socket::VsockAddr {
+        cid: addr.cid,
+        port: addr.port,
+    }
+}
+
+/// The VirtIO socket device.
+pub struct VirtIoVsockDev<H: Hal, T: Transport> {
+    inner: VsockConnectionManager<H, T>,
+}
+
+unsafe impl<H: Hal, T: Transport> Send for VirtIoVsockDev<H, T> {}
+unsafe impl<H: Hal, T: Transport> Sync for VirtIoVsockDev<H, T> {}
+
+impl<H: Hal, T: Transport> VirtIoVsockDev<H, T> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    pub fn try_new(transport: T) -> DevResult<Self> {
+        let socket = VirtIOSocket::<H, T>::new(transport).map_err(as_dev_err)?;
+        Ok(Self {
+            inner: VsockConnectionManager::new(socket),
+        })
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIoVsockDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-vsock"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+}
+
+impl<H: Hal, T: Transport> VsockDriverOps for VirtIoVsockDev<H, T> {
+    fn guest_cid(&self) -> u64 {
+        self.inner.guest_cid()
+    }
+
+    fn listen(&mut self, port: u32) {
+        self.inner.listen(port)
+    }
+
+    fn unlisten(&mut self, port: u32) {
+        self.inner.unlisten(port)
+    }
+
+    fn connect(&mut self, peer: VsockAddr, src_port: u32) -> DevResult {
+        self.inner
+            .connect(as_virtio_addr(peer), src_port)
+            .map_err(as_dev_err)
+    }
+
+    fn send(&mut self, conn: VsockConnId, buf: &[u8]) -> DevResult<usize> {
+        self.inner
+            .send(as_virtio_addr(conn.peer), conn.local_port, buf)
+            .map_err(as_dev_err)?;
+        Ok(buf.len())
+    }
+
+    fn recv(&mut self, conn: VsockConnId, buf: &mut [u8]) -> DevResult<usize> {
+        let peer = as_virtio_addr(conn.peer);
+        let len = self
+            .inner
+            .recv(peer, conn.local_port, buf)
+            .map_err(as_dev_err)?;
+        if len > 0 {
+            // tell the peer about the room freed, the connection may be gone
+            // if the peer has closed it
+            let _ = self.inner.update_credit(peer, conn.local_port);
+        }
+        Ok(len)
+    }
+
+    fn recv_avail(&mut self, conn: VsockConnId) -> DevResult<usize> {
+        self.inner
+            .recv_buffer_available_bytes(as_virtio_addr(conn.peer), conn.local_port)
+            .map_err(as_dev_err)
+    }
+
+    fn shutdown(&mut self, conn: VsockConnId) -> DevResult {
+        self.inner
+            .shutdown(as_virtio_addr(conn.peer), conn.local_port)
+            .map_err(as_dev_err)
+    }
+
+    fn abort(&mut self, conn: VsockConnId) -> DevResult {
+        self.inner
+            .force_close(as_virtio_addr(conn.peer), conn.local_port)
+            .map_err(as_dev_err)
+    }
+
+    fn poll_event(&mut self) -> DevResult<Option<VsockEvent>> {
+        loop {
+            let Some(event) = self.inner.poll().map_err(as_dev_err)? else {
+                return Ok(None);
+            };
+            let conn = VsockConnId {
+                peer: as_addr(event.source),
+                local_port: event.destination.port,
+            };
+            return Ok(Some(match event.event_type {
+                VsockEventType::ConnectionRequest => VsockEvent::ConnectionRequest(conn),
+                VsockEventType::Connected => VsockEvent::Connected(conn),
+                VsockEventType::Received { length } => VsockEvent::Received(conn, length),
+                VsockEventType::Disconnected { .. } => VsockEvent::Disconnected(conn),
+                VsockEventType::CreditUpdate => VsockEvent::CreditUpdate(conn),
+                // answered by the connection manager
+                VsockEventType::CreditRequest => continue,
+            }));
+        }
+    }
+}
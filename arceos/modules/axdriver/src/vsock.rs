@@ -0,0 +1,86 @@
+//! Common traits and types for socket devices of the VM sockets (vsock).
+
+use axdriver_base::{BaseDriverOps, DevResult};
+
+/// The address of a VM socket endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VsockAddr {
+    /// The context ID of the VM (or the host, which is always 2).
+    pub cid: u64,
+    /// The port number.
+    pub port: u32,
+}
+
+/// A connection of a socket device, identified by the address of the peer
+/// and the local port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VsockConnId {
+    /// The address of the peer.
+    pub peer: VsockAddr,
+    /// The local port.
+    pub local_port: u32,
+}
+
+/// An event that happened on a connection of a socket device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VsockEvent {
+    /// The peer connected to a listening port, the connection has been
+    /// accepted.
+    ConnectionRequest(VsockConnId),
+    /// The peer accepted the connection.
+    Connected(VsockConnId),
+    /// Some bytes have been received from the peer.
+    Received(VsockConnId, usize),
+    /// The peer closed or reset the connection, or rejected the connection
+    /// request.
+    Disconnected(VsockConnId),
+    /// The peer has more room for the bytes to send.
+    CreditUpdate(VsockConnId),
+}
+
+/// Operations that require a socket device driver to implement.
+///
+/// The driver keeps track of the connections and buffers the received bytes
+/// of each one. All the operations are non-blocking, the events are got by
+/// polling the device with [`VsockDriverOps::poll_event`].
+pub trait VsockDriverOps: BaseDriverOps {
+    /// The context ID of the guest.
+    fn guest_cid(&self) -> u64;
+
+    /// Accepts the connection requests to `port` from now on.
+    fn listen(&mut self, port: u32);
+
+    /// Rejects the connection requests to `port` from now on.
+    fn unlisten(&mut self, port: u32);
+
+    /// Requests a connection to `peer` from the local port `src_port`.
+    ///
+    /// The connection is established when the
+    /// [`VsockEvent::Connected`] event comes.
+    fn connect(&mut self, peer: VsockAddr, src_port: u32) -> DevResult;
+
+    /// Sends the bytes in `buf` on a connection, returns the number of bytes
+    /// sent.
+    ///
+    /// Returns [`DevError::Again`](axdriver_base::DevError::Again) if the
+    /// peer has no room for the bytes.
+    fn send(&mut self, conn: VsockConnId, buf: &[u8]) -> DevResult<usize>;
+
+    /// Takes the received bytes of a connection into `buf`, returns the
+    /// number of bytes taken.
+    fn recv(&mut self, conn: VsockConnId, buf: &mut [u8]) -> DevResult<usize>;
+
+    /// Returns the number of the received bytes of a connection not taken
+    /// yet.
+    fn recv_avail(&mut self, conn: VsockConnId) -> DevResult<usize>;
+
+    /// Tells the peer that no more bytes will be sent on a connection, and
+    /// forgets the connection after the peer closes it.
+    fn shutdown(&mut self, conn: VsockConnId) -> DevResult;
+
+    /// Resets a connection and forgets it at once.
+    fn abort(&mut self, conn: VsockConnId) -> DevResult;
+
+    /// Processes the packets from the device, returns the next event if any.
+    fn poll_event(&mut self) -> DevResult<Option<VsockEvent>>;
+}
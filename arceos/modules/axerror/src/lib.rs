@@ -0,0 +1,259 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) typed errors with context,
+//! and their errno mapping.
+//!
+//! An [`Error`] is an [`AxError`] kind, with:
+//! - the errno it maps to, if it is not the one of the kind, e.g. `ENODATA`
+//!   for a missing extended attribute instead of the `ENOENT` of
+//!   [`AxError::NotFound`];
+//! - in the debug builds, where it was created and the chain of the contexts
+//!   added by the callers with [`ResultExt::context`], to tell where an
+//!   error comes from.
+//!
+//! The subsystems return [`Result`], and `?` converts the [`AxResult`] and
+//! [`LinuxResult`] of the code below into it. At the API edge, `?` converts
+//! it back into [`LinuxError`], which is [`Error::errno`]: always the same
+//! errno for the same error, whatever the path it took. The context chain is
+//! logged there in the debug builds, as the errno cannot carry it.
+//!
+//! [`AxResult`]: axerrno::AxResult
+//! [`LinuxResult`]: axerrno::LinuxResult
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(debug_assertions)]
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+use core::fmt;
+#[cfg(debug_assertions)]
+use core::panic::Location;
+
+pub use axerrno::{AxError, LinuxError};
+
+/// A [`core::result::Result`] with the [`Error`] of this crate.
+pub type Result<T = ()> = core::result::Result<T, Error>;
+
+/// Where a context was added to an error, in the debug builds.
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// What was being done.
+    pub context: &'static str,
+    /// The caller which added the context.
+    pub location: &'static Location<'static>,
+}
+
+/// A typed error, with its errno and its context chain.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: AxError,
+    errno: Option<LinuxError>,
+    #[cfg(debug_assertions)]
+    origin: &'static Location<'static>,
+    #[cfg(debug_assertions)]
+    chain: alloc::vec::Vec<Frame>,
+}
+
+impl Error {
+    /// Creates an error of `kind`, it maps to the errno of the kind.
+    #[track_caller]
+    pub fn new(kind: AxError) -> Self {
+        Self {
+            kind,
+            errno: None,
+            #[cfg(debug_assertions)]
+            origin: Location::caller(),
+            #[cfg(debug_assertions)]
+            chain: alloc::vec::Vec::new(),
+        }
+    }
+
+    /// Creates an error mapping to `errno`, of the kind closest to it.
+    #[track_caller]
+    pub fn from_errno(errno: LinuxError) -> Self {
+        Self::new(kind_of(errno)).with_errno(errno)
+    }
+
+    /// The kind of the error.
+    pub fn kind(&self) -> AxError {
+        self.kind
+    }
+
+    /// The errno the error maps to at the API edge.
+    pub fn errno(&self) -> LinuxError {
+        self.errno.unwrap_or_else(|| errno_of(self.kind))
+    }
+
+    /// Maps the error to `errno` instead of the errno of its kind.
+    pub fn with_errno(mut self, errno: LinuxError) -> Self {
+        self.errno = Some(errno);
+        self
+    }
+
+    /// Adds what was being done when the error occurred, recorded with the
+    /// caller in the debug builds only.
+    #[track_caller]
+    pub fn context(self, _context: &'static str) -> Self {
+        #[cfg(debug_assertions)]
+        {
+            let mut err = self;
+            err.chain.push(Frame {
+                context: _context,
+                location: Location::caller(),
+            });
+            err
+        }
+        #[cfg(not(debug_assertions))]
+        self
+    }
+
+    /// Where the error was created.
+    #[cfg(debug_assertions)]
+    pub fn origin(&self) -> &'static Location<'static> {
+        self.origin
+    }
+
+    /// The contexts added to the error, the innermost first.
+    #[cfg(debug_assertions)]
+    pub fn chain(&self) -> &[Frame] {
+        &self.chain
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(debug_assertions)]
+        for frame in self.chain.iter().rev() {
+            write!(f, "{}: ", frame.context)?;
+        }
+        write!(f, "{:?}", self.kind)?;
+        if let Some(errno) = self.errno {
+            write!(f, " ({:?})", errno)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        if let Some(errno) = self.errno {
+            write!(f, " ({:?})", errno)?;
+        }
+        #[cfg(debug_assertions)]
+        {
+            write!(f, " at {}", self.origin)?;
+            for frame in &self.chain {
+                write!(f, ", in {} at {}", frame.context, frame.location)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<AxError> for Error {
+    #[track_caller]
+    fn from(kind: AxError) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<LinuxError> for Error {
+    #[track_caller]
+    fn from(errno: LinuxError) -> Self {
+        Self::from_errno(errno)
+    }
+}
+
+impl From<Error> for AxError {
+    fn from(err: Error) -> Self {
+        err.kind
+    }
+}
+
+impl From<Error> for LinuxError {
+    /// The API edge: the context chain is lost, it is logged in the debug
+    /// builds.
+    fn from(err: Error) -> Self {
+        #[cfg(debug_assertions)]
+        if !err.chain.is_empty() {
+            debug!("{:?}", err);
+        }
+        err.errno()
+    }
+}
+
+/// The errno of an error kind.
+pub fn errno_of(kind: AxError) -> LinuxError {
+    kind.into()
+}
+
+/// The error kind closest to an errno, [`AxError::Io`] if there is none.
+///
+/// The errnos of the kinds map back to them, except for `EINVAL` which maps
+/// to [`AxError::InvalidInput`].
+pub fn kind_of(errno: LinuxError) -> AxError {
+    use LinuxError::*;
+    match errno {
+        EADDRINUSE => AxError::AddrInUse,
+        EEXIST => AxError::AlreadyExists,
+        EFAULT => AxError::BadAddress,
+        ECONNREFUSED => AxError::ConnectionRefused,
+        ECONNRESET => AxError::ConnectionReset,
+        ENOTEMPTY => AxError::DirectoryNotEmpty,
+        EINVAL | E2BIG | ERANGE => AxError::InvalidInput,
+        EISDIR => AxError::IsADirectory,
+        ENOMEM => AxError::NoMemory,
+        ENOTDIR => AxError::NotADirectory,
+        ENOTCONN => AxError::NotConnected,
+        ENOENT | ENODATA => AxError::NotFound,
+        EACCES | EPERM => AxError::PermissionDenied,
+        EBUSY => AxError::ResourceBusy,
+        ENOSYS | EOPNOTSUPP => AxError::Unsupported,
+        EAGAIN => AxError::WouldBlock,
+        _ => AxError::Io,
+    }
+}
+
+/// Adds the context and the errno mappings to the results.
+pub trait ResultExt<T> {
+    /// Adds what was being done to the error, see [`Error::context`].
+    #[track_caller]
+    fn context(self, context: &'static str) -> Result<T>;
+
+    /// Maps the errors of `kind` to `errno` instead of the errno of the kind.
+    #[track_caller]
+    fn errno_for(self, kind: AxError, errno: LinuxError) -> Result<T>;
+}
+
+impl<T, E> ResultExt<T> for core::result::Result<T, E>
+where
+    Error: From<E>,
+{
+    #[track_caller]
+    fn context(self, context: &'static str) -> Result<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::from(err).context(context)),
+        }
+    }
+
+    #[track_caller]
+    fn errno_for(self, kind: AxError, errno: LinuxError) -> Result<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                let err = Error::from(err);
+                Err(if err.kind == kind && err.errno.is_none() {
+                    err.with_errno(errno)
+                } else {
+                    err
+                })
+            }
+        }
+    }
+}
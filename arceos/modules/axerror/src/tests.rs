@@ -0,0 +1,78 @@
+use crate::*;
+
+fn open(missing: bool) -> Result<u32> {
+    if missing {
+        return Err(Error::new(AxError::NotFound));
+    }
+    Ok(3)
+}
+
+fn get_xattr() -> Result<u32> {
+    open(true)
+        .context("open")
+        .errno_for(AxError::NotFound, LinuxError::ENODATA)
+}
+
+fn sys_getxattr() -> core::result::Result<u32, LinuxError> {
+    Ok(get_xattr().context("getxattr")?)
+}
+
+#[test]
+fn test_errno() {
+    assert_eq!(open(false), Ok(3));
+    let err = open(true).unwrap_err();
+    assert_eq!(err.kind(), AxError::NotFound);
+    assert_eq!(err.errno(), LinuxError::ENOENT);
+    assert_eq!(LinuxError::from(err), LinuxError::ENOENT);
+
+    // The errno of the kind is overridden, only for that kind.
+    assert_eq!(sys_getxattr(), Err(LinuxError::ENODATA));
+    let err = Err::<(), _>(AxError::InvalidInput)
+        .errno_for(AxError::NotFound, LinuxError::ENODATA)
+        .unwrap_err();
+    assert_eq!(err.errno(), LinuxError::EINVAL);
+
+    // An errno from below is kept as it is.
+    let err = Error::from(LinuxError::ENODATA);
+    assert_eq!(err.kind(), AxError::NotFound);
+    assert_eq!(err.errno(), LinuxError::ENODATA);
+    assert_eq!(AxError::from(err), AxError::NotFound);
+}
+
+#[test]
+fn test_kind_round_trip() {
+    for kind in [
+        AxError::AddrInUse,
+        AxError::AlreadyExists,
+        AxError::BadAddress,
+        AxError::ConnectionRefused,
+        AxError::ConnectionReset,
+        AxError::DirectoryNotEmpty,
+        AxError::InvalidInput,
+        AxError::IsADirectory,
+        AxError::NoMemory,
+        AxError::NotADirectory,
+        AxError::NotConnected,
+        AxError::NotFound,
+        AxError::PermissionDenied,
+        AxError::ResourceBusy,
+        AxError::Unsupported,
+        AxError::WouldBlock,
+    ] {
+        assert_eq!(kind_of(errno_of(kind)), kind, "{:?}", kind);
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_context_chain() {
+    let err = get_xattr().context("getxattr").unwrap_err();
+    let contexts: Vec<_> = err.chain().iter().map(|frame| frame.context).collect();
+    assert_eq!(contexts, ["open", "getxattr"]);
+    assert!(err
+        .chain()
+        .iter()
+        .all(|frame| frame.location.file().ends_with("tests.rs")));
+    assert_eq!(err.origin().file(), err.chain()[0].location.file());
+    assert_eq!(err.to_string(), "getxattr: open: NotFound (ENODATA)");
+}
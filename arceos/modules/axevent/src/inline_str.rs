@@ -0,0 +1,52 @@
+use core::fmt;
+
+/// Maximum length of an [`EventStr`] in bytes.
+const MAX_LEN: usize = 64;
+
+/// A short string stored inline in an event, so that publishing events does
+/// not allocate. Longer strings are truncated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EventStr {
+    buf: [u8; MAX_LEN],
+    len: u8,
+}
+
+impl EventStr {
+    /// Copies `s`, truncated to 64 bytes at a character boundary.
+    pub fn new(s: &str) -> Self {
+        let mut len = s.len().min(MAX_LEN);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut buf = [0; MAX_LEN];
+        buf[..len].copy_from_slice(&s.as_bytes()[..len]);
+        Self {
+            buf,
+            len: len as u8,
+        }
+    }
+
+    /// Returns the string.
+    pub fn as_str(&self) -> &str {
+        // SAFETY: copied from a `str` and cut at a character boundary.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len as usize]) }
+    }
+}
+
+impl From<&str> for EventStr {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl fmt::Debug for EventStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for EventStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
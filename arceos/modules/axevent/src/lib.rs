@@ -0,0 +1,216 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) kernel event bus.
+//!
+//! Modules [`publish`] typed [`KernelEvent`]s (device added, link change,
+//! out of memory, task exit, mount change), and any number of modules or
+//! applications [`subscribe`] to the kinds of events they are interested in,
+//! instead of modules calling each other's callbacks directly.
+//!
+//! Every subscriber has its own bounded queue. When it is full, new events
+//! are dropped for this subscriber only and counted by [`Subscriber::lost`],
+//! so a slow subscriber never blocks publishers or other subscribers.
+//!
+//! Publishing never allocates, so events can be published from the memory
+//! allocator itself, e.g. [`KernelEvent::Oom`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod inline_str;
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::ops::BitOr;
+
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+
+pub use self::inline_str::EventStr;
+
+/// Maximum number of subscribers at the same time.
+pub const MAX_SUBSCRIBERS: usize = 32;
+
+/// A kernel event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelEvent {
+    /// A device was probed and registered.
+    DeviceAdded {
+        /// Device category: `"net"`, `"block"` or `"display"`.
+        kind: &'static str,
+        /// Driver name of the device.
+        name: &'static str,
+    },
+    /// A network interface went up or down.
+    LinkChange {
+        /// Name of the interface.
+        iface: &'static str,
+        /// Whether the link is up.
+        up: bool,
+    },
+    /// A memory allocation failed.
+    Oom {
+        /// Requested size in bytes.
+        size: usize,
+        /// Requested alignment in bytes.
+        align: usize,
+    },
+    /// A task exited.
+    TaskExit {
+        /// ID of the task.
+        task_id: u64,
+        /// Exit code of the task.
+        exit_code: i32,
+    },
+    /// A filesystem was mounted or unmounted.
+    MountChange {
+        /// The mount point.
+        path: EventStr,
+        /// `true` if mounted, `false` if unmounted.
+        mounted: bool,
+    },
+}
+
+impl KernelEvent {
+    /// Returns the mask matching this kind of event.
+    pub const fn mask(&self) -> EventMask {
+        match self {
+            Self::DeviceAdded { .. } => EventMask::DEVICE,
+            Self::LinkChange { .. } => EventMask::LINK,
+            Self::Oom { .. } => EventMask::OOM,
+            Self::TaskExit { .. } => EventMask::TASK_EXIT,
+            Self::MountChange { .. } => EventMask::MOUNT,
+        }
+    }
+}
+
+/// A set of event kinds to subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventMask(u32);
+
+impl EventMask {
+    /// [`KernelEvent::DeviceAdded`] events.
+    pub const DEVICE: Self = Self(1 << 0);
+    /// [`KernelEvent::LinkChange`] events.
+    pub const LINK: Self = Self(1 << 1);
+    /// [`KernelEvent::Oom`] events.
+    pub const OOM: Self = Self(1 << 2);
+    /// [`KernelEvent::TaskExit`] events.
+    pub const TASK_EXIT: Self = Self(1 << 3);
+    /// [`KernelEvent::MountChange`] events.
+    pub const MOUNT: Self = Self(1 << 4);
+    /// All events.
+    pub const ALL: Self = Self((1 << 5) - 1);
+
+    /// Whether all kinds in `other` are in `self`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for EventMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+struct Queue {
+    mask: EventMask,
+    inner: SpinNoIrq<QueueInner>,
+}
+
+struct QueueInner {
+    events: VecDeque<KernelEvent>,
+    capacity: usize,
+    lost: u64,
+}
+
+impl Queue {
+    fn push(&self, event: KernelEvent) {
+        let mut inner = self.inner.lock();
+        // The capacity was reserved on creation, this never allocates.
+        if inner.events.len() < inner.capacity {
+            inner.events.push_back(event);
+        } else {
+            inner.lost += 1;
+        }
+    }
+}
+
+const EMPTY_SLOT: Option<Arc<Queue>> = None;
+
+static SUBSCRIBERS: SpinNoIrq<[Option<Arc<Queue>>; MAX_SUBSCRIBERS]> =
+    SpinNoIrq::new([EMPTY_SLOT; MAX_SUBSCRIBERS]);
+
+/// A subscription to kernel events, cancelled when dropped.
+pub struct Subscriber {
+    queue: Arc<Queue>,
+    slot: usize,
+}
+
+impl Subscriber {
+    /// Removes and returns the oldest pending event.
+    pub fn try_recv(&self) -> Option<KernelEvent> {
+        self.queue.inner.lock().events.pop_front()
+    }
+
+    /// Returns the number of pending events.
+    pub fn pending(&self) -> usize {
+        self.queue.inner.lock().events.len()
+    }
+
+    /// Returns the number of events dropped because the queue was full.
+    pub fn lost(&self) -> u64 {
+        self.queue.inner.lock().lost
+    }
+
+    /// The kinds of events received by this subscriber.
+    pub fn mask(&self) -> EventMask {
+        self.queue.mask
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        SUBSCRIBERS.lock()[self.slot] = None;
+    }
+}
+
+/// Subscribes to the events of the kinds in `mask`, queueing at most
+/// `capacity` pending events.
+///
+/// Returns [`NoMemory`](axerrno::AxError::NoMemory) if there are already
+/// [`MAX_SUBSCRIBERS`] subscribers.
+pub fn subscribe(mask: EventMask, capacity: usize) -> AxResult<Subscriber> {
+    if capacity == 0 {
+        return ax_err!(InvalidInput, "event queue capacity must be positive");
+    }
+    let queue = Arc::new(Queue {
+        mask,
+        inner: SpinNoIrq::new(QueueInner {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            lost: 0,
+        }),
+    });
+    let mut subscribers = SUBSCRIBERS.lock();
+    let Some(slot) = subscribers.iter().position(Option::is_none) else {
+        return ax_err!(NoMemory, "too many event subscribers");
+    };
+    subscribers[slot] = Some(queue.clone());
+    Ok(Subscriber { queue, slot })
+}
+
+/// Delivers `event` to all subscribers interested in its kind.
+pub fn publish(event: KernelEvent) {
+    trace!("publish event: {:?}", event);
+    let mask = event.mask();
+    for queue in SUBSCRIBERS.lock().iter().flatten() {
+        if queue.mask.contains(mask) {
+            queue.push(event);
+        }
+    }
+}
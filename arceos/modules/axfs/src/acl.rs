@@ -0,0 +1,136 @@
+//! A simple model of POSIX access control lists.
+//!
+//! An ACL is stored in the `system.posix_acl_access` extended attribute, in
+//! the Linux binary format, and is checked when opening a file after the
+//! permission bits: it can only restrict the access further.
+//!
+//! All files are owned by [`FILE_UID`] and [`FILE_GID`], and accessed with the
+//! credentials set by [`set_fs_ids`](crate::api::set_fs_ids), the owner by
+//! default.
+
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::VfsNodeRef;
+use cap_access::Cap;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The extended attribute storing the ACL of a file.
+pub const ACL_XATTR: &str = "system.posix_acl_access";
+
+/// The owner user ID of all files.
+pub const FILE_UID: u32 = 1000;
+/// The owner group ID of all files.
+pub const FILE_GID: u32 = 1000;
+
+const ACL_VERSION: u32 = 2;
+const HEADER_SIZE: usize = 4;
+const ENTRY_SIZE: usize = 8;
+
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+static FS_UID: AtomicU32 = AtomicU32::new(FILE_UID);
+static FS_GID: AtomicU32 = AtomicU32::new(FILE_GID);
+
+#[derive(Clone, Copy)]
+struct Entry {
+    tag: u16,
+    perm: u16,
+    id: u32,
+}
+
+fn entries(acl: &[u8]) -> impl Iterator<Item = Entry> + '_ {
+    let body = acl.get(HEADER_SIZE..).unwrap_or_default();
+    body.chunks_exact(ENTRY_SIZE).map(|e| Entry {
+        tag: u16::from_le_bytes([e[0], e[1]]),
+        perm: u16::from_le_bytes([e[2], e[3]]),
+        id: u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+    })
+}
+
+/// Checks that `acl` is a well-formed ACL: it has exactly one entry for the
+/// owner, the owner group and others, and a mask if it has named entries.
+pub(crate) fn validate(acl: &[u8]) -> AxResult {
+    if acl.len() < HEADER_SIZE || (acl.len() - HEADER_SIZE) % ENTRY_SIZE != 0 {
+        return ax_err!(InvalidInput, "malformed ACL");
+    }
+    if u32::from_le_bytes([acl[0], acl[1], acl[2], acl[3]]) != ACL_VERSION {
+        return ax_err!(InvalidInput, "unsupported ACL version");
+    }
+    let (mut user_obj, mut group_obj, mut other, mut mask, mut named) = (0, 0, 0, 0, 0);
+    for e in entries(acl) {
+        if e.perm & !0o7 != 0 {
+            return ax_err!(InvalidInput, "invalid ACL permissions");
+        }
+        match e.tag {
+            ACL_USER_OBJ => user_obj += 1,
+            ACL_GROUP_OBJ => group_obj += 1,
+            ACL_OTHER => other += 1,
+            ACL_MASK => mask += 1,
+            ACL_USER | ACL_GROUP => named += 1,
+            _ => return ax_err!(InvalidInput, "invalid ACL tag"),
+        }
+    }
+    if user_obj != 1 || group_obj != 1 || other != 1 || mask > 1 || (named > 0 && mask == 0) {
+        return ax_err!(InvalidInput, "incomplete ACL");
+    }
+    Ok(())
+}
+
+/// Returns the permissions granted by `acl` to `uid` and `gid`, following
+/// the POSIX access check algorithm.
+fn granted(acl: &[u8], uid: u32, gid: u32) -> u16 {
+    let mask = entries(acl)
+        .find(|e| e.tag == ACL_MASK)
+        .map_or(0o7, |e| e.perm);
+    let find = |tag| entries(acl).find(|e| e.tag == tag).map_or(0, |e| e.perm);
+    if uid == FILE_UID {
+        return find(ACL_USER_OBJ);
+    }
+    if let Some(e) = entries(acl).find(|e| e.tag == ACL_USER && e.id == uid) {
+        return e.perm & mask;
+    }
+    if gid == FILE_GID {
+        return find(ACL_GROUP_OBJ) & mask;
+    }
+    if let Some(e) = entries(acl).find(|e| e.tag == ACL_GROUP && e.id == gid) {
+        return e.perm & mask;
+    }
+    find(ACL_OTHER)
+}
+
+/// Checks that the ACL of `node`, if any, grants `cap` to the current
+/// credentials.
+pub(crate) fn check(node: &VfsNodeRef, cap: Cap) -> AxResult {
+    let Ok(Some(acl)) = crate::xattr::get(node, ACL_XATTR) else {
+        return Ok(());
+    };
+    let perm = granted(
+        &acl,
+        FS_UID.load(Ordering::Relaxed),
+        FS_GID.load(Ordering::Relaxed),
+    );
+    let mut allowed = Cap::empty();
+    if perm & 0o4 != 0 {
+        allowed |= Cap::READ;
+    }
+    if perm & 0o2 != 0 {
+        allowed |= Cap::WRITE;
+    }
+    if perm & 0o1 != 0 {
+        allowed |= Cap::EXECUTE;
+    }
+    if allowed.contains(cap) {
+        Ok(())
+    } else {
+        ax_err!(PermissionDenied)
+    }
+}
+
+pub(crate) fn set_fs_ids(uid: u32, gid: u32) {
+    FS_UID.store(uid, Ordering::Relaxed);
+    FS_GID.store(gid, Ordering::Relaxed);
+}
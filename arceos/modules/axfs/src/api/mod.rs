@@ -5,9 +5,14 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::fops::{AtimeMode, FileTimes, XattrSet};
+
+#[cfg(feature = "fsck")]
+pub use crate::fsck::FsckReport;
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -59,6 +64,17 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Returns the file mode creation mask of the current task.
+pub fn umask() -> u32 {
+    crate::root::umask()
+}
+
+/// Sets the file mode creation mask of the current task, returns the
+/// previous one.
+pub fn set_umask(mask: u32) -> u32 {
+    crate::root::set_umask(mask)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -87,3 +103,63 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
+
+/// Sets the access and modification times of a file or directory, `None`
+/// leaves a time unchanged.
+pub fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> io::Result<()> {
+    crate::times::set(&crate::root::lookup(None, path)?, atime, mtime)
+}
+
+/// Gets the access, modification and change times of a file or directory,
+/// all zero if its filesystem does not store them.
+pub fn times(path: &str) -> io::Result<FileTimes> {
+    Ok(crate::times::get(&crate::root::lookup(None, path)?))
+}
+
+/// Sets the permission bits of a file or directory, if its filesystem stores
+/// them.
+pub fn set_permissions(path: &str, perm: Permissions) -> io::Result<()> {
+    crate::perm::set_perm(&crate::root::lookup(None, path)?, perm.bits() as u32);
+    Ok(())
+}
+
+/// Sets when the access times of the filesystem mounted at `mount_point` (or
+/// `/` for the main filesystem) are updated, like the `noatime` and
+/// `relatime` mount options.
+pub fn set_atime_mode(mount_point: &str, mode: AtimeMode) -> io::Result<()> {
+    crate::root::set_atime_mode(mount_point, mode)
+}
+
+/// Returns the result of the consistency check of the main filesystem run
+/// before mounting it, `None` if it could not be checked.
+#[cfg(feature = "fsck")]
+pub fn fsck_report() -> Option<FsckReport> {
+    crate::root::FSCK_REPORT.get().copied()
+}
+
+/// Gets the value of the extended attribute `name` of a file or directory,
+/// `None` if it does not exist.
+pub fn get_xattr(path: &str, name: &str) -> io::Result<Option<Vec<u8>>> {
+    crate::xattr::get(&crate::root::lookup(None, path)?, name)
+}
+
+/// Sets the extended attribute `name` of a file or directory to `value`.
+pub fn set_xattr(path: &str, name: &str, value: &[u8], mode: XattrSet) -> io::Result<()> {
+    crate::xattr::set(&crate::root::lookup(None, path)?, name, value, mode)
+}
+
+/// Removes the extended attribute `name` of a file or directory.
+pub fn remove_xattr(path: &str, name: &str) -> io::Result<()> {
+    crate::xattr::remove(&crate::root::lookup(None, path)?, name)
+}
+
+/// Lists the names of the extended attributes of a file or directory.
+pub fn list_xattr(path: &str) -> io::Result<Vec<String>> {
+    crate::xattr::list(&crate::root::lookup(None, path)?)
+}
+
+/// Sets the user and group IDs used to check access control lists.
+#[cfg(feature = "acl")]
+pub fn set_fs_ids(uid: u32, gid: u32) {
+    crate::acl::set_fs_ids(uid, gid)
+}
@@ -1,12 +1,45 @@
 use axdriver::prelude::*;
+#[cfg(any(feature = "readahead", feature = "writeback"))]
+use core::sync::atomic::{AtomicBool, Ordering};
 
-const BLOCK_SIZE: usize = 512;
+use axsync::Mutex;
+
+pub(crate) const BLOCK_SIZE: usize = 512;
+
+#[cfg(not(feature = "writeback"))]
+pub(crate) type BlockDevice = AxBlockDevice;
+#[cfg(feature = "writeback")]
+pub(crate) type BlockDevice = crate::writeback::CachedDevice;
+
+/// Held while a file opened for direct I/O is read or written, the direct
+/// I/O being in `DIRECT`.
+static DIRECT_IO: Mutex<()> = Mutex::new(());
+#[cfg(any(feature = "readahead", feature = "writeback"))]
+static DIRECT: AtomicBool = AtomicBool::new(false);
+
+/// Runs `f`, a direct I/O of a file, with the caches of the disk bypassed:
+/// the blocks are read from the device and written through to it.
+pub(crate) fn direct<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = DIRECT_IO.lock();
+    #[cfg(any(feature = "readahead", feature = "writeback"))]
+    DIRECT.store(true, Ordering::Relaxed);
+    let ret = f();
+    #[cfg(any(feature = "readahead", feature = "writeback"))]
+    DIRECT.store(false, Ordering::Relaxed);
+    ret
+}
+
+/// Whether the disk is accessed by a direct I/O, see [`direct`].
+#[cfg(any(feature = "readahead", feature = "writeback"))]
+pub(crate) fn is_direct() -> bool {
+    DIRECT.load(Ordering::Relaxed)
+}
 
 /// A disk device with a cursor.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: BlockDevice,
 }
 
 impl Disk {
@@ -16,7 +49,7 @@ impl Disk {
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            dev: dev.into(),
         }
     }
 
@@ -40,8 +73,7 @@ impl Disk {
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
+            self.read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -50,7 +82,7 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.read_block(self.block_id, &mut data)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -67,7 +99,7 @@ impl Disk {
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
+            self.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -76,9 +108,9 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.read_block(self.block_id, &mut data)?;
             data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.write_block(self.block_id, &data)?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
@@ -89,4 +121,31 @@ impl Disk {
         };
         Ok(write_size)
     }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        #[cfg(feature = "readahead")]
+        if !is_direct() {
+            return crate::readahead::cache().read(&mut self.dev, block_id, buf);
+        }
+        self.dev.read_block(block_id, buf)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.dev.write_block(block_id, buf)?;
+        #[cfg(feature = "readahead")]
+        crate::readahead::cache().update(block_id, buf);
+        // logged when written back otherwise
+        #[cfg(all(feature = "power-fail", not(feature = "writeback")))]
+        crate::power_fail::log_write(block_id, buf);
+        Ok(())
+    }
+
+    /// Flushes the device, a write barrier: the blocks written before are
+    /// persistent once it returns.
+    pub fn flush(&mut self) -> DevResult {
+        self.dev.flush()?;
+        #[cfg(feature = "power-fail")]
+        crate::power_fail::log_barrier();
+        Ok(())
+    }
 }
@@ -1,15 +1,26 @@
 //! Low-level filesystem operations.
 
+use alloc::{string::String, vec::Vec};
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
+use core::time::Duration;
+
+pub use crate::times::{AtimeMode, FileTimes};
+pub use crate::xattr::XattrSet;
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
 pub use crate::fs::myfs::MyFileSystemIf;
+#[cfg(feature = "overlay")]
+pub use crate::fs::overlay::{OverlayFileSystem, OverlayNode};
+#[cfg(feature = "procfs")]
+pub use crate::fs::procfs::{set_pid_source, PidFileFn, PidSource, ProcFileSystem};
+#[cfg(feature = "sysfs")]
+pub use crate::fs::sysfs::{set_net_source, NetFileFn, NetSource};
 
 /// Alias of [`axfs_vfs::VfsNodeType`].
 pub type FileType = axfs_vfs::VfsNodeType;
@@ -20,11 +31,36 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// Alignment of the offsets, lengths and buffers of direct I/O, the block
+/// size of the devices.
+pub const DIRECT_IO_ALIGN: usize = 512;
+
+/// An access pattern of a file, given by [`File::advise`], the advices of
+/// `posix_fadvise`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// No particular pattern, the default.
+    Normal = 0,
+    /// The file is read sequentially, each byte once.
+    Sequential = 1,
+    /// The file is read at random offsets.
+    Random = 2,
+    /// The range will be read soon.
+    WillNeed = 3,
+    /// The range will not be read soon.
+    DontNeed = 4,
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
+    is_direct: bool,
     offset: u64,
+    atime: AtimeMode,
+    #[cfg(feature = "readahead")]
+    advice: Advice,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +68,7 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    atime: AtimeMode,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -44,9 +81,10 @@ pub struct OpenOptions {
     truncate: bool,
     create: bool,
     create_new: bool,
+    direct: bool,
     // system-specific
     _custom_flags: i32,
-    _mode: u32,
+    mode: u32,
 }
 
 impl OpenOptions {
@@ -60,9 +98,10 @@ impl OpenOptions {
             truncate: false,
             create: false,
             create_new: false,
+            direct: false,
             // system-specific
             _custom_flags: 0,
-            _mode: 0o666,
+            mode: 0o666,
         }
     }
     /// Sets the option for read access.
@@ -89,6 +128,15 @@ impl OpenOptions {
     pub fn create_new(&mut self, create_new: bool) {
         self.create_new = create_new;
     }
+    /// Sets the option for direct I/O, see [`File::is_direct`].
+    pub fn direct(&mut self, direct: bool) {
+        self.direct = direct;
+    }
+    /// Sets the permission bits of a new file, before applying the file mode
+    /// creation mask. The default is `0o666`.
+    pub fn mode(&mut self, mode: u32) {
+        self.mode = mode;
+    }
 
     const fn is_valid(&self) -> bool {
         if !self.read && !self.write && !self.append {
@@ -111,18 +159,77 @@ impl OpenOptions {
     }
 }
 
+#[cfg(feature = "audit")]
+fn audit_denied(op: &'static str, path: &str) {
+    axaudit::audit(axaudit::AuditEvent::CapabilityDenied {
+        op,
+        target: path.into(),
+    });
+}
+
 impl File {
     fn access_node(&self, cap: Cap) -> AxResult<&VfsNodeRef> {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    /// Checks the alignment of a direct I/O at `offset` with `buf`.
+    fn check_direct(&self, offset: u64, buf: &[u8]) -> AxResult {
+        let align = DIRECT_IO_ALIGN;
+        if self.is_direct
+            && (offset % align as u64 != 0
+                || buf.len() % align != 0
+                || buf.as_ptr() as usize % align != 0)
+        {
+            return ax_err!(InvalidInput, "unaligned direct I/O");
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at `offset` to the node, and through to the device, with
+    /// the metadata, for direct I/O.
+    fn write_node(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.check_direct(offset, buf)?;
+        let node = self.access_node(Cap::WRITE)?;
+        if !self.is_direct {
+            return node.write_at(offset, buf);
+        }
+        crate::dev::direct(|| {
+            let write_len = node.write_at(offset, buf)?;
+            node.fsync()?;
+            Ok(write_len)
+        })
+    }
+
+    /// Reads `buf` at `offset` of the node, with the advice of the file for
+    /// the readahead, from the device for direct I/O.
+    fn read_node(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.check_direct(offset, buf)?;
+        let node = self.access_node(Cap::READ)?;
+        #[cfg(feature = "readahead")]
+        let read_len = if self.is_direct {
+            crate::dev::direct(|| node.read_at(offset, buf))?
+        } else {
+            crate::readahead::advised(self.advice, || node.read_at(offset, buf))?
+        };
+        #[cfg(not(feature = "readahead"))]
+        let read_len = node.read_at(offset, buf)?;
+        crate::times::accessed(node, self.atime);
+        Ok(read_len)
+    }
+
+    fn _open_at(
+        dir: Option<&VfsNodeRef>,
+        path: &str,
+        opts: &OpenOptions,
+        atime: AtimeMode,
+    ) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
         }
 
         let node_option = crate::root::lookup(dir, path);
+        let mut created = false;
         let node = if opts.create || opts.create_new {
             match node_option {
                 Ok(node) => {
@@ -133,7 +240,10 @@ impl File {
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(VfsError::NotFound) => {
+                    created = true;
+                    crate::root::create_file(dir, path, opts.mode)?
+                }
                 Err(e) => return Err(e),
             }
         } else {
@@ -148,9 +258,14 @@ impl File {
             return ax_err!(IsADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
+        // The mode of a new file only applies to the later opens.
+        if !created && !perm_to_cap(attr.perm()).contains(access_cap) {
+            #[cfg(feature = "audit")]
+            audit_denied("open", path);
             return ax_err!(PermissionDenied);
         }
+        #[cfg(feature = "acl")]
+        crate::acl::check(&node, access_cap)?;
 
         node.open()?;
         if opts.truncate {
@@ -159,14 +274,18 @@ impl File {
         Ok(Self {
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
+            is_direct: opts.direct,
             offset: 0,
+            atime,
+            #[cfg(feature = "readahead")]
+            advice: Advice::Normal,
         })
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
     /// [`File`] object.
     pub fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_at(None, path, opts)
+        Self::_open_at(None, path, opts, crate::root::atime_mode(path)?)
     }
 
     /// Truncates the file to the specified size.
@@ -180,8 +299,7 @@ impl File {
     ///
     /// After the read, the cursor will be advanced by the number of bytes read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        let node = self.access_node(Cap::READ)?;
-        let read_len = node.read_at(self.offset, buf)?;
+        let read_len = self.read_node(self.offset, buf)?;
         self.offset += read_len as u64;
         Ok(read_len)
     }
@@ -190,9 +308,7 @@ impl File {
     ///
     /// It does not update the file cursor.
     pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
-        let node = self.access_node(Cap::READ)?;
-        let read_len = node.read_at(offset, buf)?;
-        Ok(read_len)
+        self.read_node(offset, buf)
     }
 
     /// Writes the file at the current position. Returns the number of bytes
@@ -206,8 +322,7 @@ impl File {
         } else {
             self.offset
         };
-        let node = self.access_node(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
+        let write_len = self.write_node(offset, buf)?;
         self.offset = offset + write_len as u64;
         Ok(write_len)
     }
@@ -217,9 +332,41 @@ impl File {
     ///
     /// It does not update the file cursor.
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
-        let node = self.access_node(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
-        Ok(write_len)
+        self.write_node(offset, buf)
+    }
+
+    /// Whether the file was opened for direct I/O.
+    ///
+    /// The reads and writes must then be aligned to [`DIRECT_IO_ALIGN`] (the
+    /// offset, the length and the buffer), so that the block devices transfer
+    /// whole blocks to and from the buffer without bounce buffers, and the
+    /// writes reach the device before returning, with the metadata. The
+    /// caches of the disk of the `writeback` and `readahead` features are
+    /// bypassed: the blocks are read from the device and written through to
+    /// it.
+    pub const fn is_direct(&self) -> bool {
+        self.is_direct
+    }
+
+    /// Gives the access pattern of the `len` bytes from `offset` of the file,
+    /// to the end of the file if `len` is 0, like `posix_fadvise`.
+    ///
+    /// With the `readahead` feature, it tunes the readahead and the caching
+    /// of the blocks read, see [`crate::readahead`]: [`Advice::Normal`],
+    /// [`Advice::Sequential`] and [`Advice::Random`] set how the file is read
+    /// from now on, whatever the range. It does nothing otherwise.
+    pub fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> AxResult {
+        let node = self.access_node(Cap::empty())?;
+        #[cfg(feature = "readahead")]
+        match advice {
+            Advice::WillNeed | Advice::DontNeed => {
+                crate::readahead::read_range(node, offset, len, advice)?
+            }
+            _ => self.advice = advice,
+        }
+        #[cfg(not(feature = "readahead"))]
+        let _ = (node, offset, len, advice);
+        Ok(())
     }
 
     /// Flushes the file, writes all buffered data to the underlying device.
@@ -242,10 +389,85 @@ impl File {
         Ok(new_offset)
     }
 
+    /// Moves the cursor to the first offset at or after `offset` that is in
+    /// data. Returns the new position, or `None` if there is no data past
+    /// `offset`.
+    pub fn seek_data(&mut self, offset: u64) -> AxResult<Option<u64>> {
+        let pos = crate::sparse::seek_data(self.access_node(Cap::empty())?, offset)?;
+        if let Some(pos) = pos {
+            self.offset = pos;
+        }
+        Ok(pos)
+    }
+
+    /// Moves the cursor to the first offset at or after `offset` that is in a
+    /// hole, the end of the file counting as a hole. Returns the new position,
+    /// or `None` if `offset` is past the end of the file.
+    pub fn seek_hole(&mut self, offset: u64) -> AxResult<Option<u64>> {
+        let pos = crate::sparse::seek_hole(self.access_node(Cap::empty())?, offset)?;
+        if let Some(pos) = pos {
+            self.offset = pos;
+        }
+        Ok(pos)
+    }
+
+    /// Allocates storage for `len` bytes from `offset`. The file is extended
+    /// if needed, unless `keep_size` is set.
+    pub fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> AxResult {
+        crate::sparse::allocate(self.access_node(Cap::WRITE)?, offset, len, keep_size)
+    }
+
+    /// Deallocates `len` bytes from `offset`, which then read as zeros. The
+    /// file size is not changed.
+    pub fn punch_hole(&self, offset: u64, len: u64) -> AxResult {
+        crate::sparse::punch_hole(self.access_node(Cap::WRITE)?, offset, len)
+    }
+
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Gets the file timestamps.
+    pub fn get_times(&self) -> AxResult<FileTimes> {
+        Ok(crate::times::get(self.access_node(Cap::empty())?))
+    }
+
+    /// Sets the access and modification times of the file, `None` leaves a
+    /// time unchanged. The change time is set to now.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        crate::times::set(self.access_node(Cap::empty())?, atime, mtime)
+    }
+
+    /// Gets the value of the extended attribute `name`, `None` if it does not
+    /// exist.
+    pub fn get_xattr(&self, name: &str) -> AxResult<Option<Vec<u8>>> {
+        crate::xattr::get(self.access_node(Cap::empty())?, name)
+    }
+
+    /// Sets the extended attribute `name` to `value`.
+    pub fn set_xattr(&self, name: &str, value: &[u8], mode: XattrSet) -> AxResult {
+        crate::xattr::set(self.access_node(Cap::empty())?, name, value, mode)
+    }
+
+    /// Removes the extended attribute `name`.
+    pub fn remove_xattr(&self, name: &str) -> AxResult {
+        crate::xattr::remove(self.access_node(Cap::empty())?, name)
+    }
+
+    /// Lists the names of the extended attributes.
+    pub fn list_xattr(&self) -> AxResult<Vec<String>> {
+        crate::xattr::list(self.access_node(Cap::empty())?)
+    }
+
+    /// Performs the ioctl `cmd` of a device file, see [`crate::ioctl`].
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be valid for the argument of the command.
+    pub unsafe fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+        crate::ioctl::dispatch(self.access_node(Cap::empty())?, cmd, arg)
+    }
 }
 
 impl Directory {
@@ -253,7 +475,12 @@ impl Directory {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(
+        dir: Option<&VfsNodeRef>,
+        path: &str,
+        opts: &OpenOptions,
+        atime: AtimeMode,
+    ) -> AxResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
             return ax_err!(InvalidInput);
@@ -269,13 +496,18 @@ impl Directory {
         }
         let access_cap = opts.into();
         if !perm_to_cap(attr.perm()).contains(access_cap) {
+            #[cfg(feature = "audit")]
+            audit_denied("opendir", path);
             return ax_err!(PermissionDenied);
         }
+        #[cfg(feature = "acl")]
+        crate::acl::check(&node, access_cap)?;
 
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            atime,
         })
     }
 
@@ -287,32 +519,47 @@ impl Directory {
         }
     }
 
+    /// Relative paths are assumed to stay in the same filesystem.
+    fn atime_at(&self, path: &str) -> AxResult<AtimeMode> {
+        if path.starts_with('/') {
+            crate::root::atime_mode(path)
+        } else {
+            Ok(self.atime)
+        }
+    }
+
     /// Opens a directory at the path relative to the current directory.
     /// Returns a [`Directory`] object.
     pub fn open_dir(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(None, path, opts)
+        Self::_open_dir_at(None, path, opts, crate::root::atime_mode(path)?)
     }
 
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        Self::_open_dir_at(self.access_at(path)?, path, opts, self.atime_at(path)?)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        File::_open_at(self.access_at(path)?, path, opts, self.atime_at(path)?)
     }
 
     /// Creates an empty file at the path relative to this directory.
     pub fn create_file(&self, path: &str) -> AxResult<VfsNodeRef> {
-        crate::root::create_file(self.access_at(path)?, path)
+        crate::root::create_file(self.access_at(path)?, path, 0o666)
     }
 
     /// Creates an empty directory at the path relative to this directory.
     pub fn create_dir(&self, path: &str) -> AxResult {
-        crate::root::create_dir(self.access_at(path)?, path)
+        self.create_dir_with_mode(path, 0o777)
+    }
+
+    /// Creates an empty directory at the path relative to this directory,
+    /// with the permission bits of `mode` not in the file mode creation mask.
+    pub fn create_dir_with_mode(&self, path: &str, mode: u32) -> AxResult {
+        crate::root::create_dir(self.access_at(path)?, path, mode)
     }
 
     /// Removes a file at the path relative to this directory.
@@ -331,13 +578,50 @@ impl Directory {
     /// After the read, the cursor will be advanced by the number of entries
     /// read.
     pub fn read_dir(&mut self, dirents: &mut [DirEntry]) -> AxResult<usize> {
-        let n = self
-            .access_node(Cap::READ)?
-            .read_dir(self.entry_idx, dirents)?;
+        let node = self.access_node(Cap::READ)?;
+        let n = node.read_dir(self.entry_idx, dirents)?;
+        crate::times::accessed(node, self.atime);
         self.entry_idx += n;
         Ok(n)
     }
 
+    /// Gets the directory attributes.
+    pub fn get_attr(&self) -> AxResult<FileAttr> {
+        self.access_node(Cap::empty())?.get_attr()
+    }
+
+    /// Gets the directory timestamps.
+    pub fn get_times(&self) -> AxResult<FileTimes> {
+        Ok(crate::times::get(self.access_node(Cap::empty())?))
+    }
+
+    /// Sets the access and modification times of the directory, `None`
+    /// leaves a time unchanged. The change time is set to now.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        crate::times::set(self.access_node(Cap::empty())?, atime, mtime)
+    }
+
+    /// Gets the value of the extended attribute `name`, `None` if it does not
+    /// exist.
+    pub fn get_xattr(&self, name: &str) -> AxResult<Option<Vec<u8>>> {
+        crate::xattr::get(self.access_node(Cap::empty())?, name)
+    }
+
+    /// Sets the extended attribute `name` to `value`.
+    pub fn set_xattr(&self, name: &str, value: &[u8], mode: XattrSet) -> AxResult {
+        crate::xattr::set(self.access_node(Cap::empty())?, name, value, mode)
+    }
+
+    /// Removes the extended attribute `name`.
+    pub fn remove_xattr(&self, name: &str) -> AxResult {
+        crate::xattr::remove(self.access_node(Cap::empty())?, name)
+    }
+
+    /// Lists the names of the extended attributes.
+    pub fn list_xattr(&self) -> AxResult<Vec<String>> {
+        crate::xattr::list(self.access_node(Cap::empty())?)
+    }
+
     /// Rename a file or directory to a new name.
     /// Delete the original file if `old` already exists.
     ///
@@ -380,6 +664,7 @@ impl fmt::Debug for OpenOptions {
         fmt_opt!(truncate, "TRUNC");
         fmt_opt!(create, "CREATE");
         fmt_opt!(create_new, "CREATE_NEW");
+        fmt_opt!(direct, "DIRECT");
         Ok(())
     }
 }
@@ -1,22 +1,42 @@
+use alloc::string::String;
 use alloc::sync::Arc;
 use core::cell::UnsafeCell;
+use core::time::Duration;
 
+use axerrno::AxResult;
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{Date, DateTime, TimeProvider};
+use fatfs::{Dir, File, LossyOemCpConverter, Read, Seek, SeekFrom, Write};
 
 use crate::dev::Disk;
+use crate::times::FileTimes;
 
 const BLOCK_SIZE: usize = 512;
 
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: fatfs::FileSystem<Disk, WallClock, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+type FatDir<'a> = Dir<'a, Disk, WallClock, LossyOemCpConverter>;
+
+/// The directory entry of a node, where FAT stores the timestamps.
+struct Entry<'a> {
+    dir: FatDir<'a>,
+    name: String,
+}
+
+pub struct FileWrapper<'a> {
+    file: Mutex<File<'a, Disk, WallClock, LossyOemCpConverter>>,
+    entry: Option<Entry<'a>>,
+}
+
+pub struct DirWrapper<'a> {
+    dir: FatDir<'a>,
+    entry: Option<Entry<'a>>,
+}
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -30,7 +50,7 @@ impl FatFileSystem {
     pub fn new(mut disk: Disk) -> Self {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let inner = fatfs::FileSystem::new(disk, fs_options())
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -40,7 +60,7 @@ impl FatFileSystem {
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let inner = fatfs::FileSystem::new(disk, fs_options())
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -50,23 +70,184 @@ impl FatFileSystem {
 
     pub fn init(&'static self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir(), None)) }
+    }
+
+    fn new_file<'a>(
+        file: File<'a, Disk, WallClock, LossyOemCpConverter>,
+        entry: Option<Entry<'a>>,
+    ) -> Arc<FileWrapper<'a>> {
+        Arc::new(FileWrapper {
+            file: Mutex::new(file),
+            entry,
+        })
+    }
+
+    fn new_dir<'a>(dir: FatDir<'a>, entry: Option<Entry<'a>>) -> Arc<DirWrapper<'a>> {
+        Arc::new(DirWrapper { dir, entry })
+    }
+}
+
+fn fs_options() -> fatfs::FsOptions<WallClock, LossyOemCpConverter> {
+    // The access date is updated by `axfs` according to the mount options.
+    fatfs::FsOptions::new()
+        .time_provider(WallClock)
+        .update_accessed_date(false)
+}
+
+/// Timestamps new and modified entries with the wall time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallClock;
+
+impl TimeProvider for WallClock {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        to_fat_time(crate::times::now())
+    }
+}
+
+impl<'a> Entry<'a> {
+    fn find(&self) -> Option<fatfs::DirEntry<'a, Disk, WallClock, LossyOemCpConverter>> {
+        self.dir
+            .iter()
+            .filter_map(Result::ok)
+            .find(|e| e.file_name().eq_ignore_ascii_case(&self.name))
+    }
+
+    fn times(&self) -> Option<FileTimes> {
+        let entry = self.find()?;
+        let mtime = from_fat_time(entry.modified());
+        Some(FileTimes {
+            atime: from_fat_date(entry.accessed()),
+            mtime,
+            // FAT has no change time.
+            ctime: mtime,
+        })
     }
+}
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+impl FileWrapper<'static> {
+    /// Returns the timestamps stored in the directory entry.
+    pub fn times(&self) -> Option<FileTimes> {
+        // The entry is written back on flush.
+        self.file.lock().flush().ok()?;
+        self.entry.as_ref()?.times()
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    /// Sets the access and modification times. FAT only stores the date of
+    /// the last access.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        let old = self.times();
+        let mut file = self.file.lock();
+        if let Some(atime) = atime {
+            let date = to_fat_time(atime).date;
+            if old.map(|t| to_fat_time(t.atime).date) != Some(date) {
+                file.set_accessed(date);
+            }
+        }
+        if let Some(mtime) = mtime {
+            file.set_modified(to_fat_time(mtime));
+        }
+        file.flush().map_err(as_vfs_err)
     }
 }
 
+impl DirWrapper<'static> {
+    /// Returns the timestamps stored in the directory entry, `None` for the
+    /// root directory.
+    pub fn times(&self) -> Option<FileTimes> {
+        self.entry.as_ref()?.times()
+    }
+}
+
+/// Splits `path` into the directory containing the last component and its
+/// name.
+fn split_entry<'a>(dir: &FatDir<'a>, path: &str) -> Option<Entry<'a>> {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((parent, name)) => (dir.open_dir(parent).ok()?, name),
+        None => (dir.clone(), path),
+    };
+    Some(Entry {
+        dir,
+        name: name.into(),
+    })
+}
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Converts days since the UNIX epoch to a civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Converts a civil date to days since the UNIX epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Converts a time since the UNIX epoch to a FAT timestamp, clamped to the
+/// range of FAT (1980 to 2107).
+fn to_fat_time(time: Duration) -> DateTime {
+    const MIN_SECS: u64 = 315532800; // 1980-01-01
+    const MAX_SECS: u64 = 4354819199; // 2107-12-31 23:59:59
+    let secs = time.as_secs().clamp(MIN_SECS, MAX_SECS);
+    let millis = if (MIN_SECS..MAX_SECS).contains(&time.as_secs()) {
+        time.subsec_millis() as u16
+    } else {
+        0
+    };
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let rem = secs % SECS_PER_DAY;
+    DateTime::new(
+        Date::new(year as u16, month as u16, day as u16),
+        fatfs::Time::new(
+            (rem / 3600) as u16,
+            (rem / 60 % 60) as u16,
+            (rem % 60) as u16,
+            millis,
+        ),
+    )
+}
+
+fn from_fat_date(date: Date) -> Duration {
+    let days = days_from_civil(date.year as i64, date.month as u32, date.day as u32);
+    Duration::from_secs(days.max(0) as u64 * SECS_PER_DAY)
+}
+
+fn from_fat_time(dt: DateTime) -> Duration {
+    let t = dt.time;
+    from_fat_date(dt.date)
+        + Duration::from_secs(t.hour as u64 * 3600 + t.min as u64 * 60 + t.sec as u64)
+        + Duration::from_millis(t.millis as u64)
+}
+
 impl VfsNodeOps for FileWrapper<'static> {
     axfs_vfs::impl_vfs_non_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        let size = self.0.lock().seek(SeekFrom::End(0)).map_err(as_vfs_err)?;
+        let size = self
+            .file
+            .lock()
+            .seek(SeekFrom::End(0))
+            .map_err(as_vfs_err)?;
         let blocks = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
         // FAT fs doesn't support permissions, we just set everything to 755
         let perm = VfsNodePerm::from_bits_truncate(0o755);
@@ -74,22 +255,26 @@ impl VfsNodeOps for FileWrapper<'static> {
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
         file.read(buf).map_err(as_vfs_err)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
         file.write(buf).map_err(as_vfs_err)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
         file.truncate().map_err(as_vfs_err)
     }
+
+    fn fsync(&self) -> VfsResult {
+        self.file.lock().flush().map_err(as_vfs_err)
+    }
 }
 
 impl VfsNodeOps for DirWrapper<'static> {
@@ -106,9 +291,9 @@ impl VfsNodeOps for DirWrapper<'static> {
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        self.0
+        self.dir
             .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir, None)))
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -122,10 +307,10 @@ impl VfsNodeOps for DirWrapper<'static> {
         }
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
-        if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
-        } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+        if let Ok(file) = self.dir.open_file(path) {
+            Ok(FatFileSystem::new_file(file, split_entry(&self.dir, path)))
+        } else if let Ok(dir) = self.dir.open_dir(path) {
+            Ok(FatFileSystem::new_dir(dir, split_entry(&self.dir, path)))
         } else {
             Err(VfsError::NotFound)
         }
@@ -143,11 +328,11 @@ impl VfsNodeOps for DirWrapper<'static> {
 
         match ty {
             VfsNodeType::File => {
-                self.0.create_file(path).map_err(as_vfs_err)?;
+                self.dir.create_file(path).map_err(as_vfs_err)?;
                 Ok(())
             }
             VfsNodeType::Dir => {
-                self.0.create_dir(path).map_err(as_vfs_err)?;
+                self.dir.create_dir(path).map_err(as_vfs_err)?;
                 Ok(())
             }
             _ => Err(VfsError::Unsupported),
@@ -161,11 +346,11 @@ impl VfsNodeOps for DirWrapper<'static> {
         if let Some(rest) = path.strip_prefix("./") {
             return self.remove(rest);
         }
-        self.0.remove(path).map_err(as_vfs_err)
+        self.dir.remove(path).map_err(as_vfs_err)
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
-        let mut iter = self.0.iter().skip(start_idx);
+        let mut iter = self.dir.iter().skip(start_idx);
         for (i, out_entry) in dirents.iter_mut().enumerate() {
             let x = iter.next();
             match x {
@@ -192,8 +377,8 @@ impl VfsNodeOps for DirWrapper<'static> {
             src_path, dst_path
         );
 
-        self.0
-            .rename(src_path, &self.0, dst_path)
+        self.dir
+            .rename(src_path, &self.dir, dst_path)
             .map_err(as_vfs_err)
     }
 }
@@ -243,7 +428,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
@@ -6,8 +6,26 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(feature = "overlay")]
+pub mod overlay;
+#[cfg(feature = "procfs")]
+pub mod procfs;
+#[cfg(feature = "devfs")]
+pub mod random;
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
+
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+/// Splits the first component of `path` from the rest.
+#[cfg(any(feature = "procfs", feature = "sysfs"))]
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
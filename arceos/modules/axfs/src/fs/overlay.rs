@@ -0,0 +1,423 @@
+//! Overlay filesystem: a read-only lower layer under a writable upper layer.
+//!
+//! Lookups see the upper layer first, then the lower one. Directories present
+//! in both layers are merged. The lower layer is never modified:
+//!
+//! - Writing to a lower file first copies it up to the upper layer, with its
+//!   parent directories.
+//! - Removing a lower entry creates a whiteout in the upper layer, a file
+//!   named `.wh.<name>` which hides the lower entry.
+//! - A directory created over a whiteout is made opaque by a `.wh..wh..opq`
+//!   file, which hides the content of the lower directory.
+//!
+//! Renaming a directory that has lower content is not supported.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE: &str = ".wh..wh..opq";
+const COPY_CHUNK: usize = 4096;
+
+/// An overlay of two filesystems, implementing [`axfs_vfs::VfsOps`].
+pub struct OverlayFileSystem {
+    layers: Arc<Layers>,
+}
+
+struct Layers {
+    lower_fs: Arc<dyn VfsOps>,
+    upper_fs: Arc<dyn VfsOps>,
+    lower: VfsNodeRef,
+    upper: VfsNodeRef,
+    parent: Mutex<Option<VfsNodeRef>>,
+}
+
+/// A file or directory of the overlay, identified by its path from the
+/// root: it is resolved in the layers on every operation, so it follows the
+/// copy-ups.
+pub struct OverlayNode {
+    layers: Arc<Layers>,
+    path: String,
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of `upper` on top of `lower`.
+    pub fn new(lower: Arc<dyn VfsOps>, upper: Arc<dyn VfsOps>) -> Self {
+        Self {
+            layers: Arc::new(Layers {
+                lower: lower.root_dir(),
+                upper: upper.root_dir(),
+                lower_fs: lower,
+                upper_fs: upper,
+                parent: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        *self.layers.parent.lock() = mount_point.parent();
+        Ok(())
+    }
+
+    fn umount(&self) -> VfsResult {
+        self.layers.upper_fs.umount()?;
+        self.layers.lower_fs.umount()
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.layers.node(String::new())
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Splits `path` into its parent and its last component.
+fn split_parent(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => ("", path),
+    }
+}
+
+fn whiteout(path: &str) -> String {
+    let (parent, name) = split_parent(path);
+    join(parent, &format!("{}{}", WHITEOUT_PREFIX, name))
+}
+
+/// Resolves `path` relative to `base`, without going above the root.
+fn normalize(base: &str, path: &str) -> String {
+    let mut comps: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+    for comp in path.split('/') {
+        match comp {
+            "" | "." => {}
+            ".." => {
+                comps.pop();
+            }
+            _ => comps.push(comp),
+        }
+    }
+    comps.join("/")
+}
+
+fn lookup(root: &VfsNodeRef, path: &str) -> Option<VfsNodeRef> {
+    if path.is_empty() {
+        Some(root.clone())
+    } else {
+        root.clone().lookup(path).ok()
+    }
+}
+
+fn is_dir(node: &VfsNodeRef) -> bool {
+    node.get_attr().is_ok_and(|attr| attr.is_dir())
+}
+
+fn list(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut buf = [EMPTY; 16];
+    let mut entries = Vec::new();
+    loop {
+        let n = dir.read_dir(entries.len(), &mut buf)?;
+        if n == 0 {
+            return Ok(entries);
+        }
+        for entry in &buf[..n] {
+            let name = String::from_utf8_lossy(entry.name_as_bytes()).into_owned();
+            entries.push((name, entry.entry_type()));
+        }
+    }
+}
+
+impl Layers {
+    fn node(self: &Arc<Self>, path: String) -> VfsNodeRef {
+        Arc::new(OverlayNode {
+            layers: self.clone(),
+            path,
+        })
+    }
+
+    /// Whether the lower entry at `path` is hidden by a whiteout, an opaque
+    /// directory or a non-directory in the upper layer.
+    fn lower_hidden(&self, path: &str) -> bool {
+        let mut dir = String::new();
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            if lookup(&self.upper, &whiteout(&join(&dir, name))).is_some() {
+                return true;
+            }
+            dir = join(&dir, name);
+            if let Some(node) = lookup(&self.upper, &dir) {
+                if !is_dir(&node) && dir.len() < path.len() {
+                    return true;
+                }
+                if lookup(&self.upper, &join(&dir, OPAQUE)).is_some() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the upper and the lower node at `path`, the lower one only if
+    /// it is visible.
+    fn resolve(&self, path: &str) -> (Option<VfsNodeRef>, Option<VfsNodeRef>) {
+        let upper = lookup(&self.upper, path);
+        if upper.as_ref().is_some_and(|n| !is_dir(n)) || self.lower_hidden(path) {
+            return (upper, None);
+        }
+        let lower = lookup(&self.lower, path);
+        match (&upper, &lower) {
+            // A lower file under an upper directory is hidden.
+            (Some(_), Some(l)) if !is_dir(l) => (upper, None),
+            _ => (upper, lower),
+        }
+    }
+
+    /// Whether the lower layer has an entry at `path` that must be whited
+    /// out once the upper one is gone.
+    fn needs_whiteout(&self, path: &str) -> bool {
+        !self.lower_hidden(path) && lookup(&self.lower, path).is_some()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let (upper, lower) = self.resolve(path);
+        upper.is_some() || lower.is_some()
+    }
+
+    /// Returns the upper node at `path`, copying it up from the lower layer
+    /// if needed.
+    fn copy_up(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        let (upper, lower) = self.resolve(path);
+        if let Some(upper) = upper {
+            return Ok(upper);
+        }
+        let lower = lower.ok_or(VfsError::NotFound)?;
+        let ty = lower.get_attr()?.file_type();
+        if !matches!(ty, VfsNodeType::File | VfsNodeType::Dir) {
+            return Err(VfsError::Unsupported);
+        }
+        self.copy_up(split_parent(path).0)?;
+        self.upper.create(path, ty)?;
+        let node = lookup(&self.upper, path).ok_or(VfsError::NotFound)?;
+        if ty == VfsNodeType::File {
+            let mut buf = [0; COPY_CHUNK];
+            let mut offset = 0;
+            loop {
+                let n = lower.read_at(offset, &mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                node.write_at(offset, &buf[..n])?;
+                offset += n as u64;
+            }
+        }
+        Ok(node)
+    }
+
+    /// Returns the entries of the merged directory at `path`.
+    fn entries(&self, path: &str) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let (upper, lower) = self.resolve(path);
+        let mut entries = Vec::new();
+        let mut whiteouts = Vec::new();
+        if let Some(upper) = upper {
+            for (name, ty) in list(&upper)? {
+                if name == OPAQUE {
+                    continue;
+                }
+                match name.strip_prefix(WHITEOUT_PREFIX) {
+                    Some(hidden) => whiteouts.push(String::from(hidden)),
+                    None => entries.push((name, ty)),
+                }
+            }
+        }
+        if let Some(lower) = lower {
+            for (name, ty) in list(&lower)? {
+                if !whiteouts.contains(&name) && !entries.iter().any(|(n, _)| *n == name) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Removes the whiteout of `path`, returns whether there was one.
+    fn remove_whiteout(&self, path: &str) -> VfsResult<bool> {
+        let wh = whiteout(path);
+        if lookup(&self.upper, &wh).is_some() {
+            self.upper.remove(&wh)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl OverlayNode {
+    fn node(&self) -> VfsResult<VfsNodeRef> {
+        let (upper, lower) = self.layers.resolve(&self.path);
+        upper.or(lower).ok_or(VfsError::NotFound)
+    }
+
+    fn check_name(path: &str) -> VfsResult {
+        let name = split_parent(path).1;
+        if name.is_empty() {
+            Err(VfsError::InvalidInput)
+        } else if name.starts_with(WHITEOUT_PREFIX) {
+            Err(VfsError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.node()?.get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.node()?.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.layers.copy_up(&self.path)?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match lookup(&self.layers.upper, &self.path) {
+            Some(upper) => upper.fsync(),
+            None => Ok(()),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.layers.copy_up(&self.path)?.truncate(size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.path.is_empty() {
+            self.layers.parent.lock().clone()
+        } else {
+            let parent = split_parent(&self.path).0;
+            Some(self.layers.node(String::from(parent)))
+        }
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = normalize(&self.path, path);
+        if !self.layers.exists(&path) {
+            return Err(VfsError::NotFound);
+        }
+        Ok(self.layers.node(path))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let path = normalize(&self.path, path);
+        Self::check_name(&path)?;
+        if self.layers.exists(&path) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let parent = self.layers.copy_up(split_parent(&path).0)?;
+        if !is_dir(&parent) {
+            return Err(VfsError::NotADirectory);
+        }
+        let had_whiteout = self.layers.remove_whiteout(&path)?;
+        self.layers.upper.create(&path, ty)?;
+        if had_whiteout && ty == VfsNodeType::Dir {
+            self.layers
+                .upper
+                .create(&join(&path, OPAQUE), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let path = normalize(&self.path, path);
+        Self::check_name(&path)?;
+        let (upper, lower) = self.layers.resolve(&path);
+        let node = upper
+            .as_ref()
+            .or(lower.as_ref())
+            .ok_or(VfsError::NotFound)?;
+        if is_dir(node) {
+            let entries = self.layers.entries(&path)?;
+            if entries.iter().any(|(n, _)| n != "." && n != "..") {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+        }
+        if let Some(upper) = upper {
+            if is_dir(&upper) {
+                // Only whiteouts and the opaque marker are left.
+                for (name, _) in list(&upper)? {
+                    if name.starts_with(WHITEOUT_PREFIX) {
+                        self.layers.upper.remove(&join(&path, &name))?;
+                    }
+                }
+            }
+            self.layers.upper.remove(&path)?;
+        }
+        if self.layers.needs_whiteout(&path) {
+            self.layers.copy_up(split_parent(&path).0)?;
+            self.layers
+                .upper
+                .create(&whiteout(&path), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.layers.entries(&self.path)?;
+        let mut n = 0;
+        for ((name, ty), dirent) in entries.iter().skip(start_idx).zip(dirents.iter_mut()) {
+            *dirent = VfsDirEntry::new(name, *ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let src = normalize(&self.path, src_path);
+        let dst = normalize(&self.path, dst_path);
+        Self::check_name(&src)?;
+        Self::check_name(&dst)?;
+        let (upper, lower) = self.layers.resolve(&src);
+        let node = upper
+            .as_ref()
+            .or(lower.as_ref())
+            .ok_or(VfsError::NotFound)?;
+        let src_is_dir = is_dir(node);
+        let dst_lower = self.layers.resolve(&dst).1;
+        if src_is_dir && (lower.is_some() || dst_lower.is_some()) {
+            // Would need to move the lower content as well.
+            return Err(VfsError::Unsupported);
+        }
+        let src_whiteout = self.layers.needs_whiteout(&src);
+        self.layers.copy_up(&src)?;
+        self.layers.copy_up(split_parent(&dst).0)?;
+        let had_whiteout = self.layers.remove_whiteout(&dst)?;
+        self.layers.upper.rename(&src, &dst)?;
+        if src_whiteout {
+            self.layers
+                .upper
+                .create(&whiteout(&src), VfsNodeType::File)?;
+        }
+        if had_whiteout && src_is_dir {
+            self.layers
+                .upper
+                .create(&join(&dst, OPAQUE), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
@@ -0,0 +1,220 @@
+//! The procfs: a RAM filesystem with the per-process directories
+//! `/proc/[pid]`, whose files are generated when they are read.
+//!
+//! This module knows nothing about the processes: the directories and their
+//! files come from the [`PidSource`] set by [`set_pid_source`].
+
+use alloc::{string::String, string::ToString, sync::Arc, vec::Vec};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use lazyinit::LazyInit;
+
+use axfs_ramfs::RamFileSystem;
+
+use super::split_path;
+
+/// Generates the content of a file of the process `pid`, `None` if the
+/// process is gone.
+pub type PidFileFn = fn(pid: u64) -> Option<String>;
+
+/// The source of the per-process directories of the procfs.
+#[derive(Clone, Copy)]
+pub struct PidSource {
+    /// Lists the IDs of the processes.
+    pub pids: fn() -> Vec<u64>,
+    /// The names of the files of a process directory, with the functions
+    /// generating their contents.
+    pub files: &'static [(&'static str, PidFileFn)],
+}
+
+static PID_SOURCE: LazyInit<PidSource> = LazyInit::new();
+
+/// Sets the source of the `/proc/[pid]` directories, it can be set once.
+///
+/// Without it, the procfs has no process directories.
+pub fn set_pid_source(source: PidSource) {
+    PID_SOURCE.init_once(source);
+}
+
+fn pids() -> Vec<u64> {
+    PID_SOURCE.get().map_or_else(Vec::new, |s| (s.pids)())
+}
+
+fn pid_files() -> &'static [(&'static str, PidFileFn)] {
+    PID_SOURCE.get().map_or(&[], |s| s.files)
+}
+
+/// A RAM filesystem with the process directories in its root.
+pub struct ProcFileSystem {
+    ram: RamFileSystem,
+    root: Arc<ProcRoot>,
+}
+
+impl ProcFileSystem {
+    /// Creates the procfs over the RAM filesystem `ram`, which holds the other
+    /// files and directories.
+    pub fn new(ram: RamFileSystem) -> Self {
+        let root = Arc::new(ProcRoot {
+            ram_root: ram.root_dir(),
+        });
+        Self { ram, root }
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn mount(&self, path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        self.ram.mount(path, mount_point)
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+struct ProcRoot {
+    ram_root: VfsNodeRef,
+}
+
+impl VfsNodeOps for ProcRoot {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.ram_root.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.ram_root.parent()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        if name.is_empty() || name == "." {
+            return match rest {
+                Some(rest) => self.lookup(rest),
+                None => Ok(self),
+            };
+        }
+        let Some(pid) = name.parse().ok().filter(|pid| pids().contains(pid)) else {
+            return self.ram_root.clone().lookup(path);
+        };
+        let dir: VfsNodeRef = Arc::new(PidDir {
+            pid,
+            parent: self.clone(),
+        });
+        match rest {
+            Some(rest) => dir.lookup(rest),
+            None => Ok(dir),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.ram_root.create(path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        self.ram_root.remove(path)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        // ".", "..", then the process directories, then the RAM entries.
+        let pids = pids();
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            let idx = start_idx + i;
+            if (2..2 + pids.len()).contains(&idx) {
+                *ent = VfsDirEntry::new(&pids[idx - 2].to_string(), VfsNodeType::Dir);
+                continue;
+            }
+            let ram_idx = if idx < 2 { idx } else { idx - pids.len() };
+            let ent = core::slice::from_mut(ent);
+            if self.ram_root.read_dir(ram_idx, ent)? == 0 {
+                return Ok(i);
+            }
+        }
+        Ok(dirents.len())
+    }
+}
+
+/// The directory `/proc/[pid]`.
+struct PidDir {
+    pid: u64,
+    parent: Arc<ProcRoot>,
+}
+
+impl VfsNodeOps for PidDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o555);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 0, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Some(self.parent.clone())
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self.clone(),
+            ".." => self.parent.clone(),
+            _ => {
+                let &(_, generate) = pid_files()
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .ok_or(VfsError::NotFound)?;
+                Arc::new(PidFile {
+                    pid: self.pid,
+                    generate,
+                })
+            }
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut files = pid_files().iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => match files.next() {
+                    Some((name, _)) => *ent = VfsDirEntry::new(name, VfsNodeType::File),
+                    None => return Ok(i),
+                },
+            }
+        }
+        Ok(dirents.len())
+    }
+}
+
+/// A file of `/proc/[pid]`, generated on every read.
+struct PidFile {
+    pid: u64,
+    generate: PidFileFn,
+}
+
+impl VfsNodeOps for PidFile {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        // The size is unknown until the file is generated, like in Linux.
+        let perm = VfsNodePerm::from_bits_truncate(0o444);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, 0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.generate)(self.pid).ok_or(VfsError::NotFound)?;
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let len = buf.len().min(content.len() - start);
+        buf[..len].copy_from_slice(&content[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+}
@@ -0,0 +1,36 @@
+//! The random devices `/dev/random` and `/dev/urandom`.
+
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+/// A device giving random bytes from [`axhal::misc::random_bytes`], the
+/// entropy pool with its `entropy` feature.
+///
+/// As in Linux, `/dev/random` and `/dev/urandom` are the same device, which
+/// never blocks. The data written to it is discarded.
+pub struct RandomDev;
+
+impl VfsNodeOps for RandomDev {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o666),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        axhal::misc::random_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+}
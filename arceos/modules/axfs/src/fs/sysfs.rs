@@ -0,0 +1,503 @@
+//! The sysfs: a RAM filesystem with the directory `/sys/module` of the
+//! [parameters of the drivers](axdriver::params), as in Linux.
+//!
+//! The file `/sys/module/<driver>/parameters/<name>` reads as the current
+//! value of the parameter, and a value written to it sets the parameter,
+//! unless it is a boot only one, whose file is read-only.
+//!
+//! The directories `/sys/class/net/<iface>` of the network interfaces, with
+//! their attribute files generated when they are read, come from the
+//! [`NetSource`] set by [`set_net_source`], this module knows nothing about
+//! the network.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use axdriver::params::{self, DriverParam};
+use axdriver::prelude::DevError;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use lazyinit::LazyInit;
+
+use axfs_ramfs::RamFileSystem;
+
+use super::split_path;
+
+/// Generates the content of an attribute file of the network interface
+/// `iface`, `None` if the interface is gone.
+pub type NetFileFn = fn(iface: &str) -> Option<String>;
+
+/// The source of the network interface directories of the sysfs.
+#[derive(Clone, Copy)]
+pub struct NetSource {
+    /// Lists the names of the interfaces.
+    pub ifaces: fn() -> Vec<String>,
+    /// The names of the attribute files of an interface directory, with the
+    /// functions generating their contents. A name `<dir>/<file>` is a file
+    /// of the subdirectory `<dir>`, e.g. `statistics/rx_bytes`.
+    pub files: &'static [(&'static str, NetFileFn)],
+}
+
+static NET_SOURCE: LazyInit<NetSource> = LazyInit::new();
+
+/// Sets the source of the `/sys/class/net/<iface>` directories, it can be
+/// set once.
+///
+/// Without it, `/sys/class/net` lists the network devices of the device tree
+/// only.
+pub fn set_net_source(source: NetSource) {
+    NET_SOURCE.init_once(source);
+}
+
+fn net_ifaces() -> Vec<String> {
+    NET_SOURCE.get().map_or_else(Vec::new, |s| (s.ifaces)())
+}
+
+fn net_files() -> &'static [(&'static str, NetFileFn)] {
+    NET_SOURCE.get().map_or(&[], |s| s.files)
+}
+
+/// A RAM filesystem with the directory `module` in its root.
+pub struct SysFileSystem {
+    ram: RamFileSystem,
+    root: Arc<SysRoot>,
+}
+
+impl SysFileSystem {
+    /// Creates the sysfs over the RAM filesystem `ram`, which holds the other
+    /// files and directories.
+    pub fn new(ram: RamFileSystem) -> Self {
+        let root = Arc::new(SysRoot {
+            ram_root: ram.root_dir(),
+        });
+        Self { ram, root }
+    }
+}
+
+impl VfsOps for SysFileSystem {
+    fn mount(&self, path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        self.ram.mount(path, mount_point)
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn dir_attr() -> VfsResult<VfsNodeAttr> {
+    let perm = VfsNodePerm::from_bits_truncate(0o555);
+    Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 0, 0))
+}
+
+/// Fills `dirents` from the entry `start_idx` of ".", ".." and `names`.
+fn read_names<'a>(
+    names: impl Iterator<Item = &'a str>,
+    ty: VfsNodeType,
+    start_idx: usize,
+    dirents: &mut [VfsDirEntry],
+) -> usize {
+    read_entries(names.map(|name| (name, ty)), start_idx, dirents)
+}
+
+/// Fills `dirents` from the entry `start_idx` of ".", ".." and `entries`.
+fn read_entries<'a>(
+    entries: impl Iterator<Item = (&'a str, VfsNodeType)>,
+    start_idx: usize,
+    dirents: &mut [VfsDirEntry],
+) -> usize {
+    let mut entries = entries.skip(start_idx.max(2) - 2);
+    for (i, ent) in dirents.iter_mut().enumerate() {
+        match i + start_idx {
+            0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+            1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+            _ => match entries.next() {
+                Some((name, ty)) => *ent = VfsDirEntry::new(name, ty),
+                None => return i,
+            },
+        }
+    }
+    dirents.len()
+}
+
+/// Looks `rest` up from `node`, if any.
+fn lookup_rest(node: VfsNodeRef, rest: Option<&str>) -> VfsResult<VfsNodeRef> {
+    match rest {
+        Some(rest) => node.lookup(rest),
+        None => Ok(node),
+    }
+}
+
+struct SysRoot {
+    ram_root: VfsNodeRef,
+}
+
+impl VfsNodeOps for SysRoot {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.ram_root.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.ram_root.parent()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        match name {
+            "" | "." => lookup_rest(self, rest),
+            "module" => lookup_rest(Arc::new(ModuleDir { parent: self }), rest),
+            "class" if NET_SOURCE.get().is_some() => match rest.map(split_path) {
+                Some(("net", rest)) => {
+                    let ram = self.ram_root.clone().lookup("class/net")?;
+                    lookup_rest(Arc::new(ClassNetDir { ram }), rest)
+                }
+                _ => self.ram_root.clone().lookup(path),
+            },
+            _ => self.ram_root.clone().lookup(path),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.ram_root.create(path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        self.ram_root.remove(path)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        // ".", "..", then "module", then the RAM entries.
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            let idx = start_idx + i;
+            if idx == 2 {
+                *ent = VfsDirEntry::new("module", VfsNodeType::Dir);
+                continue;
+            }
+            let ram_idx = if idx < 2 { idx } else { idx - 1 };
+            let ent = core::slice::from_mut(ent);
+            if self.ram_root.read_dir(ram_idx, ent)? == 0 {
+                return Ok(i);
+            }
+        }
+        Ok(dirents.len())
+    }
+}
+
+/// The directory `/sys/module`, with a directory per driver.
+struct ModuleDir {
+    parent: Arc<SysRoot>,
+}
+
+impl VfsNodeOps for ModuleDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        dir_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Some(self.parent.clone())
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self,
+            ".." => self.parent.clone(),
+            _ => {
+                let driver = params::drivers()
+                    .find(|d| *d == name)
+                    .ok_or(VfsError::NotFound)?;
+                Arc::new(DriverDir {
+                    driver,
+                    parent: self,
+                })
+            }
+        };
+        lookup_rest(node, rest)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let drivers = params::drivers();
+        Ok(read_names(drivers, VfsNodeType::Dir, start_idx, dirents))
+    }
+}
+
+/// The directory `/sys/module/<driver>`, with the `parameters` directory.
+struct DriverDir {
+    driver: &'static str,
+    parent: Arc<ModuleDir>,
+}
+
+impl VfsNodeOps for DriverDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        dir_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Some(self.parent.clone())
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self,
+            ".." => self.parent.clone(),
+            "parameters" => Arc::new(ParamsDir { parent: self }),
+            _ => return Err(VfsError::NotFound),
+        };
+        lookup_rest(node, rest)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let names = ["parameters"].into_iter();
+        Ok(read_names(names, VfsNodeType::Dir, start_idx, dirents))
+    }
+}
+
+/// The directory `/sys/module/<driver>/parameters`, with a file per
+/// parameter.
+struct ParamsDir {
+    parent: Arc<DriverDir>,
+}
+
+impl VfsNodeOps for ParamsDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        dir_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Some(self.parent.clone())
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self,
+            ".." => self.parent.clone(),
+            _ => {
+                let param = params::find(self.parent.driver, name).ok_or(VfsError::NotFound)?;
+                Arc::new(ParamFile { param })
+            }
+        };
+        lookup_rest(node, rest)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let names = params::driver_params(self.parent.driver).map(|p| p.name());
+        Ok(read_names(names, VfsNodeType::File, start_idx, dirents))
+    }
+}
+
+/// The file of a parameter, holding its value followed by a new line.
+struct ParamFile {
+    param: &'static DriverParam,
+}
+
+impl ParamFile {
+    fn content(&self) -> String {
+        format!("{}\n", self.param)
+    }
+}
+
+impl VfsNodeOps for ParamFile {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mode = if self.param.is_boot_only() {
+            0o444
+        } else {
+            0o644
+        };
+        let perm = VfsNodePerm::from_bits_truncate(mode);
+        let size = self.content().len() as u64;
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, size, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content();
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let len = buf.len().min(content.len() - start);
+        buf[..len].copy_from_slice(&content[start..start + len]);
+        Ok(len)
+    }
+
+    /// Sets the parameter to the value written, wherever it is written.
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidInput)?;
+        self.param.set_str(value).map_err(|err| match err {
+            DevError::Unsupported => VfsError::PermissionDenied,
+            _ => VfsError::InvalidInput,
+        })?;
+        Ok(buf.len())
+    }
+
+    /// Does nothing, the written values replace the current one: the file is
+    /// truncated when opened for writing, as by `echo 1 > <file>`.
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+}
+
+/// The directory `/sys/class/net`, with a directory per network interface,
+/// then the network devices of the RAM directory.
+struct ClassNetDir {
+    ram: VfsNodeRef,
+}
+
+impl VfsNodeOps for ClassNetDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.ram.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.ram.parent()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self,
+            ".." => self.parent().ok_or(VfsError::NotFound)?,
+            _ if net_ifaces().iter().any(|i| i == name) => Arc::new(IfaceDir {
+                iface: name.into(),
+                prefix: "",
+                parent: self,
+            }),
+            _ => return self.ram.clone().lookup(path),
+        };
+        lookup_rest(node, rest)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        // ".", "..", then the interfaces, then the RAM entries.
+        let ifaces = net_ifaces();
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            let idx = start_idx + i;
+            if let Some(iface) = idx.checked_sub(2).and_then(|j| ifaces.get(j)) {
+                *ent = VfsDirEntry::new(iface, VfsNodeType::Dir);
+                continue;
+            }
+            let ram_idx = if idx < 2 { idx } else { idx - ifaces.len() };
+            let ent = core::slice::from_mut(ent);
+            if self.ram.read_dir(ram_idx, ent)? == 0 {
+                return Ok(i);
+            }
+        }
+        Ok(dirents.len())
+    }
+}
+
+/// The directory `/sys/class/net/<iface>`, or a subdirectory of it, with the
+/// attribute files of the [`NetSource`].
+struct IfaceDir {
+    iface: String,
+    /// The path of the directory in the interface one, ended by `/` unless
+    /// empty.
+    prefix: &'static str,
+    parent: VfsNodeRef,
+}
+
+impl IfaceDir {
+    /// Returns the files and the subdirectories, by the first file in each.
+    fn entries(&self) -> impl Iterator<Item = (&'static str, VfsNodeType)> + '_ {
+        let files = net_files();
+        files.iter().enumerate().filter_map(move |(i, (path, _))| {
+            let rest = path.strip_prefix(self.prefix)?;
+            match rest.split_once('/') {
+                None => Some((rest, VfsNodeType::File)),
+                Some((dir, _)) => {
+                    let first = files[..i].iter().all(|(p, _)| {
+                        p.strip_prefix(self.prefix)
+                            .and_then(|r| r.split_once('/'))
+                            .map_or(true, |(d, _)| d != dir)
+                    });
+                    first.then_some((dir, VfsNodeType::Dir))
+                }
+            }
+        })
+    }
+}
+
+impl VfsNodeOps for IfaceDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        dir_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Some(self.parent.clone())
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self,
+            ".." => self.parent.clone(),
+            _ => {
+                let (path, generate) = net_files()
+                    .iter()
+                    .find(|(path, _)| {
+                        path.strip_prefix(self.prefix)
+                            .and_then(|r| r.strip_prefix(name))
+                            .is_some_and(|r| r.is_empty() || r.starts_with('/'))
+                    })
+                    .ok_or(VfsError::NotFound)?;
+                let len = self.prefix.len() + name.len();
+                if path.len() == len {
+                    Arc::new(NetFile {
+                        iface: self.iface.clone(),
+                        generate: *generate,
+                    })
+                } else {
+                    Arc::new(IfaceDir {
+                        iface: self.iface.clone(),
+                        prefix: &path[..len + 1],
+                        parent: self,
+                    })
+                }
+            }
+        };
+        lookup_rest(node, rest)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        Ok(read_entries(self.entries(), start_idx, dirents))
+    }
+}
+
+/// An attribute file of a network interface, generated on every read.
+struct NetFile {
+    iface: String,
+    generate: NetFileFn,
+}
+
+impl VfsNodeOps for NetFile {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        // The size is unknown until the file is generated, like in Linux.
+        let perm = VfsNodePerm::from_bits_truncate(0o444);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, 0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.generate)(&self.iface).ok_or(VfsError::NotFound)?;
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let len = buf.len().min(content.len() - start);
+        buf[..len].copy_from_slice(&content[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+}
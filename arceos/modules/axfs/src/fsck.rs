@@ -0,0 +1,493 @@
+//! Consistency checks of FAT volumes, run on the disk before mounting it.
+//!
+//! The check walks the directory tree from the root, follows the cluster
+//! chain of every file and directory, and compares the result with the
+//! allocation table. It finds:
+//!
+//! - chains running into a free, bad or out of range cluster,
+//! - clusters shared by several chains (cross-links) or chains looping,
+//! - files whose size does not match the length of their chain,
+//! - allocated clusters reachable from no entry (orphans, usually left by a
+//!   crash in the middle of a write).
+//!
+//! With the `fsck-repair` feature, the broken chains are terminated, the
+//! file sizes adjusted to their chains, the orphans freed, and the volume
+//! marked clean. FAT has no journal, so there is nothing to replay.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxError, AxResult};
+
+use crate::dev::Disk;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ENTRY_FREE: u8 = 0xe5;
+const ENTRY_END: u8 = 0x00;
+
+/// The result of a check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// The volume was not cleanly unmounted.
+    pub dirty: bool,
+    /// Chains running into a free, bad or out of range cluster.
+    pub broken_chains: u32,
+    /// Chains sharing a cluster with another chain, or looping.
+    pub cross_links: u32,
+    /// Files whose size does not match their chain.
+    pub size_mismatches: u32,
+    /// Allocated clusters not reachable from any entry.
+    pub orphan_clusters: u32,
+    /// The problems were repaired.
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /// Whether no problem was found, apart from the dirty flag.
+    pub const fn is_consistent(&self) -> bool {
+        self.broken_chains == 0
+            && self.cross_links == 0
+            && self.size_mismatches == 0
+            && self.orphan_clusters == 0
+    }
+}
+
+/// A volume the checker reads and repairs.
+pub(crate) trait Volume {
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> AxResult;
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> AxResult;
+}
+
+impl Volume for Disk {
+    fn read_exact_at(&mut self, pos: u64, mut buf: &mut [u8]) -> AxResult {
+        self.set_position(pos);
+        while !buf.is_empty() {
+            match self.read_one(buf) {
+                Ok(0) | Err(_) => return Err(AxError::Io),
+                Ok(n) => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, mut buf: &[u8]) -> AxResult {
+        self.set_position(pos);
+        while !buf.is_empty() {
+            match self.write_one(buf) {
+                Ok(0) | Err(_) => return Err(AxError::Io),
+                Ok(n) => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// The layout of a volume, from its boot sector.
+struct Layout {
+    fat_type: FatType,
+    cluster_size: u64,
+    fat_start: u64,
+    fat_size: u64,
+    num_fats: u64,
+    /// Start and size of the fixed root directory (FAT12/16).
+    root_start: u64,
+    root_size: u64,
+    root_cluster: u32,
+    data_start: u64,
+    /// Number of data clusters, numbered from 2.
+    clusters: u32,
+}
+
+fn le16(b: &[u8], off: usize) -> u32 {
+    u16::from_le_bytes([b[off], b[off + 1]]) as u32
+}
+
+fn le32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+impl Layout {
+    fn parse(boot: &[u8]) -> AxResult<Self> {
+        let sector_size = le16(boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = le16(boot, 14) as u64;
+        let num_fats = boot[16] as u64;
+        let root_entries = le16(boot, 17) as u64;
+        let total = match le16(boot, 19) {
+            0 => le32(boot, 32),
+            n => n,
+        } as u64;
+        let fat_sectors = match le16(boot, 22) {
+            0 => le32(boot, 36),
+            n => n,
+        } as u64;
+        if !sector_size.is_power_of_two()
+            || sector_size < 512
+            || !sectors_per_cluster.is_power_of_two()
+            || num_fats == 0
+            || le16(boot, 510) != 0xaa55
+        {
+            return ax_err!(InvalidData, "not a FAT volume");
+        }
+        let root_sectors = (root_entries * DIR_ENTRY_SIZE as u64).div_ceil(sector_size);
+        let data_sector = reserved + num_fats * fat_sectors + root_sectors;
+        let Some(data_sectors) = total.checked_sub(data_sector) else {
+            return ax_err!(InvalidData, "invalid FAT layout");
+        };
+        let clusters = (data_sectors / sectors_per_cluster) as u32;
+        let fat_type = match clusters {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        Ok(Self {
+            fat_type,
+            cluster_size: sector_size * sectors_per_cluster,
+            fat_start: reserved * sector_size,
+            fat_size: fat_sectors * sector_size,
+            num_fats,
+            root_start: (reserved + num_fats * fat_sectors) * sector_size,
+            root_size: root_sectors * sector_size,
+            root_cluster: le32(boot, 44),
+            data_start: data_sector * sector_size,
+            clusters,
+        })
+    }
+
+    fn cluster_pos(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    fn is_data(&self, cluster: u32) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+}
+
+/// The allocation table, loaded in memory.
+struct Fat {
+    fat_type: FatType,
+    data: Vec<u8>,
+    changed: bool,
+}
+
+impl Fat {
+    fn get(&self, cluster: u32) -> u32 {
+        let i = cluster as usize;
+        match self.fat_type {
+            FatType::Fat12 => {
+                let v = le16(&self.data, i + i / 2);
+                if i % 2 == 0 {
+                    v & 0xfff
+                } else {
+                    v >> 4
+                }
+            }
+            FatType::Fat16 => le16(&self.data, i * 2),
+            FatType::Fat32 => le32(&self.data, i * 4) & 0x0fff_ffff,
+        }
+    }
+
+    fn set(&mut self, cluster: u32, value: u32) {
+        let i = cluster as usize;
+        match self.fat_type {
+            FatType::Fat12 => {
+                let off = i + i / 2;
+                let old = le16(&self.data, off);
+                let new = if i % 2 == 0 {
+                    (old & 0xf000) | (value & 0xfff)
+                } else {
+                    (old & 0x000f) | ((value & 0xfff) << 4)
+                };
+                self.data[off..off + 2].copy_from_slice(&(new as u16).to_le_bytes());
+            }
+            FatType::Fat16 => {
+                self.data[i * 2..i * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes())
+            }
+            FatType::Fat32 => {
+                let old = le32(&self.data, i * 4);
+                let new = (old & 0xf000_0000) | (value & 0x0fff_ffff);
+                self.data[i * 4..i * 4 + 4].copy_from_slice(&new.to_le_bytes());
+            }
+        }
+        self.changed = true;
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xfff,
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    fn is_end(&self, value: u32) -> bool {
+        value >= self.end_of_chain() - 7
+    }
+
+    fn is_bad(&self, value: u32) -> bool {
+        value == self.end_of_chain() - 8
+    }
+
+    /// The clean shutdown bit in the second entry (FAT16/32 only).
+    fn clean_bit(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0,
+            FatType::Fat16 => 0x8000,
+            FatType::Fat32 => 0x0800_0000,
+        }
+    }
+}
+
+struct Checker<'a, V: Volume> {
+    vol: &'a mut V,
+    layout: Layout,
+    fat: Fat,
+    used: Vec<bool>,
+    report: FsckReport,
+    repair: bool,
+}
+
+impl<V: Volume> Checker<'_, V> {
+    /// Follows the chain starting at `start`, marking its clusters as used.
+    /// Returns the clusters, the chain being cut at the first problem.
+    fn walk_chain(&mut self, start: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = start;
+        loop {
+            if self.used[cluster as usize] {
+                self.report.cross_links += 1;
+                if let Some(&last) = chain.last() {
+                    self.terminate(last);
+                }
+                return chain;
+            }
+            self.used[cluster as usize] = true;
+            chain.push(cluster);
+            let next = self.fat.get(cluster);
+            if self.fat.is_end(next) {
+                return chain;
+            }
+            if !self.layout.is_data(next) || self.fat.is_bad(next) {
+                self.report.broken_chains += 1;
+                self.terminate(cluster);
+                return chain;
+            }
+            cluster = next;
+        }
+    }
+
+    fn terminate(&mut self, cluster: u32) {
+        if self.repair {
+            self.fat.set(cluster, self.fat.end_of_chain());
+        }
+    }
+
+    /// Checks the entries of a directory stored in the `extents`, and the
+    /// subdirectories.
+    fn check_dir(&mut self, extents: Vec<(u64, u64)>) -> AxResult {
+        let mut subdirs = Vec::new();
+        for (pos, len) in extents {
+            let mut data = vec![0; len as usize];
+            self.vol.read_exact_at(pos, &mut data)?;
+            for (i, entry) in data.chunks_exact_mut(DIR_ENTRY_SIZE).enumerate() {
+                match entry[0] {
+                    ENTRY_END => break,
+                    ENTRY_FREE => continue,
+                    _ => {}
+                }
+                let attr = entry[11];
+                if attr & ATTR_LONG_NAME == ATTR_LONG_NAME
+                    || attr & ATTR_VOLUME_ID != 0
+                    || entry[0] == b'.'
+                {
+                    continue;
+                }
+                let hi = match self.layout.fat_type {
+                    FatType::Fat32 => le16(entry, 20) << 16,
+                    _ => 0,
+                };
+                let first = hi | le16(entry, 26);
+                let entry_pos = pos + (i * DIR_ENTRY_SIZE) as u64;
+                if first == 0 {
+                    if attr & ATTR_DIRECTORY == 0 && le32(entry, 28) != 0 {
+                        self.report.size_mismatches += 1;
+                        self.fix_size(entry_pos, entry, 0)?;
+                    }
+                    continue;
+                }
+                if !self.layout.is_data(first) {
+                    self.report.broken_chains += 1;
+                    continue;
+                }
+                let chain = self.walk_chain(first);
+                if attr & ATTR_DIRECTORY != 0 {
+                    subdirs.push(chain);
+                } else {
+                    self.check_size(entry_pos, entry, &chain)?;
+                }
+            }
+        }
+        for chain in subdirs {
+            let extents = self.extents(&chain);
+            self.check_dir(extents)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the size of the file `entry` matches its `chain`.
+    fn check_size(&mut self, entry_pos: u64, entry: &mut [u8], chain: &[u32]) -> AxResult {
+        let size = le32(entry, 28) as u64;
+        let needed = size.div_ceil(self.layout.cluster_size) as usize;
+        if needed == chain.len() {
+            return Ok(());
+        }
+        self.report.size_mismatches += 1;
+        if needed > chain.len() {
+            // The data is lost, keep what the chain holds.
+            let size = chain.len() as u64 * self.layout.cluster_size;
+            self.fix_size(entry_pos, entry, size.min(u32::MAX as u64) as u32)
+        } else if self.repair {
+            // Free the clusters past the end of the file.
+            let (keep, free) = chain.split_at(needed);
+            for &cluster in free {
+                self.fat.set(cluster, 0);
+            }
+            match keep.last() {
+                Some(&last) => self.terminate(last),
+                None => {
+                    entry[20..22].fill(0);
+                    entry[26..28].fill(0);
+                    self.vol.write_all_at(entry_pos, entry)?;
+                }
+            }
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn fix_size(&mut self, entry_pos: u64, entry: &mut [u8], size: u32) -> AxResult {
+        if self.repair {
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+            self.vol.write_all_at(entry_pos, entry)?;
+        }
+        Ok(())
+    }
+
+    fn extents(&self, chain: &[u32]) -> Vec<(u64, u64)> {
+        chain
+            .iter()
+            .map(|&c| (self.layout.cluster_pos(c), self.layout.cluster_size))
+            .collect()
+    }
+
+    fn check_orphans(&mut self) {
+        for cluster in 2..self.layout.clusters + 2 {
+            let value = self.fat.get(cluster);
+            if value != 0 && !self.fat.is_bad(value) && !self.used[cluster as usize] {
+                self.report.orphan_clusters += 1;
+                if self.repair {
+                    self.fat.set(cluster, 0);
+                }
+            }
+        }
+    }
+
+    fn write_fat(&mut self) -> AxResult {
+        for i in 0..self.layout.num_fats {
+            let pos = self.layout.fat_start + i * self.layout.fat_size;
+            self.vol.write_all_at(pos, &self.fat.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the FAT volume `vol`, repairing it if `repair` is set.
+pub(crate) fn check<V: Volume>(vol: &mut V, repair: bool) -> AxResult<FsckReport> {
+    let mut boot = [0; 512];
+    vol.read_exact_at(0, &mut boot)?;
+    let layout = Layout::parse(&boot)?;
+    let fat_bytes = match layout.fat_type {
+        FatType::Fat12 => (layout.clusters as u64 + 2) * 3 / 2 + 1,
+        FatType::Fat16 => (layout.clusters as u64 + 2) * 2,
+        FatType::Fat32 => (layout.clusters as u64 + 2) * 4,
+    };
+    if fat_bytes > layout.fat_size {
+        return ax_err!(InvalidData, "FAT too small for the volume");
+    }
+    let mut data = vec![0; layout.fat_size as usize];
+    vol.read_exact_at(layout.fat_start, &mut data)?;
+    let fat = Fat {
+        fat_type: layout.fat_type,
+        data,
+        changed: false,
+    };
+    let clean_bit = fat.clean_bit();
+    let dirty = fat.get(1) & clean_bit != clean_bit;
+
+    let mut checker = Checker {
+        vol,
+        used: vec![false; layout.clusters as usize + 2],
+        layout,
+        fat,
+        report: FsckReport {
+            dirty,
+            ..Default::default()
+        },
+        repair,
+    };
+    let root = if checker.layout.fat_type == FatType::Fat32 {
+        let root_cluster = checker.layout.root_cluster;
+        if !checker.layout.is_data(root_cluster) {
+            return ax_err!(InvalidData, "invalid root directory cluster");
+        }
+        let chain = checker.walk_chain(root_cluster);
+        checker.extents(&chain)
+    } else {
+        vec![(checker.layout.root_start, checker.layout.root_size)]
+    };
+    checker.check_dir(root)?;
+    checker.check_orphans();
+
+    let mut report = checker.report;
+    if repair && (!report.is_consistent() || report.dirty) {
+        let value = checker.fat.get(1) | clean_bit;
+        checker.fat.set(1, value);
+        report.repaired = true;
+    }
+    if checker.fat.changed {
+        checker.write_fat()?;
+    }
+    Ok(report)
+}
+
+/// Checks the main disk before it is mounted, according to the features.
+pub(crate) fn check_disk(disk: &mut Disk) -> Option<FsckReport> {
+    let repair = cfg!(feature = "fsck-repair");
+    match check(disk, repair) {
+        Ok(report) => {
+            if report.repaired && disk.flush().is_err() {
+                warn!("fsck: cannot flush the repairs");
+            }
+            if report.is_consistent() {
+                info!("fsck: {:?}", report);
+            } else {
+                warn!("fsck: inconsistent volume: {:?}", report);
+            }
+            Some(report)
+        }
+        Err(e) => {
+            warn!("fsck: cannot check the volume: {:?}", e);
+            None
+        }
+    }
+}
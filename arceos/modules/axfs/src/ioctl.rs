@@ -0,0 +1,345 @@
+//! ioctl dispatch of the device files.
+//!
+//! The device nodes have no ioctl operation, so the drivers register an
+//! [`IoctlHandler`] for the node at a path with [`register`], and
+//! [`File::ioctl`] dispatches the commands to it.
+//!
+//! A command number is encoded like on Linux: the direction and the size of
+//! its argument, a type byte which is the namespace of the command, e.g.
+//! `b'T'` for the terminals, and its number in the namespace. A node can have
+//! a handler per namespace. The numbers predating the encoding (e.g.
+//! `TCGETS`, `0x5401`) have no direction nor size, the handler declares them
+//! in an [`IoctlSpec`]. The argument is checked against the spec before the
+//! handler is called, and copied in and out by [`IoctlArg`].
+//!
+//! The commands without a handler fail with
+//! [`Unsupported`](AxError::Unsupported), which is `ENOTTY` for ioctl.
+//!
+//! [`File::ioctl`]: crate::fops::File::ioctl
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+use core::mem::size_of;
+
+const NR_BITS: u32 = 8;
+const TYPE_BITS: u32 = 8;
+const SIZE_BITS: u32 = 14;
+const TYPE_SHIFT: u32 = NR_BITS;
+const SIZE_SHIFT: u32 = TYPE_SHIFT + TYPE_BITS;
+const DIR_SHIFT: u32 = SIZE_SHIFT + SIZE_BITS;
+
+/// Maximum size of an encoded argument.
+pub const MAX_ARG_SIZE: usize = (1 << SIZE_BITS) - 1;
+
+/// Which way the argument of a command is copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoctlDir {
+    /// No argument is copied, it is a plain value if any.
+    None = 0,
+    /// The caller passes the argument to the driver (`_IOC_WRITE`).
+    Write = 1,
+    /// The driver returns the argument to the caller (`_IOC_READ`).
+    Read = 2,
+    /// Both.
+    ReadWrite = 3,
+}
+
+impl IoctlDir {
+    const fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            0 => Self::None,
+            1 => Self::Write,
+            2 => Self::Read,
+            _ => Self::ReadWrite,
+        }
+    }
+
+    const fn writes(self) -> bool {
+        self as u32 & Self::Write as u32 != 0
+    }
+
+    const fn reads(self) -> bool {
+        self as u32 & Self::Read as u32 != 0
+    }
+}
+
+/// An ioctl command number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IoctlCmd(pub u32);
+
+impl IoctlCmd {
+    /// Encodes the command `nr` of the namespace `ty`, `size` bytes long
+    /// argument copied in `dir`.
+    pub const fn new(dir: IoctlDir, ty: u8, nr: u8, size: usize) -> Self {
+        assert!(size <= MAX_ARG_SIZE);
+        Self(
+            ((dir as u32) << DIR_SHIFT)
+                | ((size as u32) << SIZE_SHIFT)
+                | ((ty as u32) << TYPE_SHIFT)
+                | nr as u32,
+        )
+    }
+
+    /// A command without argument, or with a plain value (`_IO`).
+    pub const fn none(ty: u8, nr: u8) -> Self {
+        Self::new(IoctlDir::None, ty, nr, 0)
+    }
+
+    /// A command returning a `T` (`_IOR`).
+    pub const fn read<T>(ty: u8, nr: u8) -> Self {
+        Self::new(IoctlDir::Read, ty, nr, size_of::<T>())
+    }
+
+    /// A command taking a `T` (`_IOW`).
+    pub const fn write<T>(ty: u8, nr: u8) -> Self {
+        Self::new(IoctlDir::Write, ty, nr, size_of::<T>())
+    }
+
+    /// A command taking and returning a `T` (`_IOWR`).
+    pub const fn read_write<T>(ty: u8, nr: u8) -> Self {
+        Self::new(IoctlDir::ReadWrite, ty, nr, size_of::<T>())
+    }
+
+    /// The encoded direction of the argument.
+    pub const fn dir(self) -> IoctlDir {
+        IoctlDir::from_bits(self.0 >> DIR_SHIFT)
+    }
+
+    /// The encoded size of the argument.
+    pub const fn size(self) -> usize {
+        ((self.0 >> SIZE_SHIFT) & ((1 << SIZE_BITS) - 1)) as usize
+    }
+
+    /// The namespace of the command.
+    pub const fn ty(self) -> u8 {
+        (self.0 >> TYPE_SHIFT) as u8
+    }
+
+    /// The number of the command in its namespace.
+    pub const fn nr(self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Whether the direction and the size are encoded in the number.
+    const fn is_encoded(self) -> bool {
+        (self.0 >> SIZE_SHIFT) != 0
+    }
+}
+
+/// A command handled by an [`IoctlHandler`], with its argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlSpec {
+    /// The command number.
+    pub cmd: IoctlCmd,
+    /// Which way the argument is copied.
+    pub dir: IoctlDir,
+    /// The size of the argument, 0 if it is a plain value.
+    pub size: usize,
+}
+
+impl IoctlSpec {
+    /// A command with the argument encoded in its number.
+    pub const fn encoded(cmd: IoctlCmd) -> Self {
+        Self {
+            cmd,
+            dir: cmd.dir(),
+            size: cmd.size(),
+        }
+    }
+
+    /// A command numbered before the encoding, with an argument of `size`
+    /// bytes copied in `dir`.
+    pub const fn legacy(cmd: u32, dir: IoctlDir, size: usize) -> Self {
+        Self {
+            cmd: IoctlCmd(cmd),
+            dir,
+            size,
+        }
+    }
+
+    fn check(&self, namespace: u8) -> AxResult {
+        if self.cmd.ty() != namespace {
+            return ax_err!(InvalidInput, "ioctl command out of the namespace");
+        }
+        let encoded = self.cmd.is_encoded();
+        if (encoded && (self.dir != self.cmd.dir() || self.size != self.cmd.size()))
+            || (self.dir == IoctlDir::None) != (self.size == 0)
+        {
+            return ax_err!(InvalidInput, "ioctl argument not matching its command");
+        }
+        Ok(())
+    }
+}
+
+/// Plain data which can be copied in and out of an ioctl argument.
+///
+/// # Safety
+///
+/// Any bit pattern of the size of the type must be a valid value, e.g. the
+/// integers and the `#[repr(C)]` structures of them.
+pub unsafe trait IoctlData: Copy {}
+
+macro_rules! impl_ioctl_data {
+    ($($ty:ty),*) => {
+        $(unsafe impl IoctlData for $ty {})*
+    };
+}
+
+impl_ioctl_data!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl<T: IoctlData, const N: usize> IoctlData for [T; N] {}
+
+/// The argument of a command, checked against its [`IoctlSpec`].
+pub struct IoctlArg {
+    addr: usize,
+    spec: IoctlSpec,
+}
+
+impl IoctlArg {
+    /// The plain value passed, for the commands without argument to copy.
+    pub fn value(&self) -> usize {
+        self.addr
+    }
+
+    /// Copies the argument in.
+    pub fn read<T: IoctlData>(&self) -> AxResult<T> {
+        self.check::<T>(self.spec.dir.writes())?;
+        Ok(unsafe { (self.addr as *const T).read_unaligned() })
+    }
+
+    /// Copies `value` out to the argument.
+    pub fn write<T: IoctlData>(&mut self, value: &T) -> AxResult {
+        self.check::<T>(self.spec.dir.reads())?;
+        unsafe { (self.addr as *mut T).write_unaligned(*value) };
+        Ok(())
+    }
+
+    /// Copies the first `buf.len()` bytes of the argument in.
+    pub fn read_bytes(&self, buf: &mut [u8]) -> AxResult {
+        self.check_bytes(buf.len(), self.spec.dir.writes())?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.addr as *const u8, buf.as_mut_ptr(), buf.len())
+        };
+        Ok(())
+    }
+
+    /// Copies `buf` out to the first `buf.len()` bytes of the argument.
+    pub fn write_bytes(&mut self, buf: &[u8]) -> AxResult {
+        self.check_bytes(buf.len(), self.spec.dir.reads())?;
+        unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), self.addr as *mut u8, buf.len()) };
+        Ok(())
+    }
+
+    fn check<T>(&self, allowed: bool) -> AxResult {
+        if size_of::<T>() != self.spec.size {
+            return ax_err!(InvalidInput, "ioctl argument of a wrong type");
+        }
+        self.check_bytes(size_of::<T>(), allowed)
+    }
+
+    fn check_bytes(&self, len: usize, allowed: bool) -> AxResult {
+        if !allowed || len > self.spec.size {
+            return ax_err!(InvalidInput, "ioctl argument copied out of its spec");
+        }
+        Ok(())
+    }
+}
+
+/// A driver handling the commands of a namespace on a device node.
+pub trait IoctlHandler: Send + Sync {
+    /// The type byte of the commands handled.
+    fn namespace(&self) -> u8;
+
+    /// The commands handled, all in the namespace.
+    fn commands(&self) -> &[IoctlSpec];
+
+    /// Performs `cmd`, one of the [`commands`](Self::commands), and returns
+    /// the non-negative result of the ioctl.
+    fn ioctl(&self, cmd: IoctlCmd, arg: &mut IoctlArg) -> AxResult<usize>;
+}
+
+struct Handlers {
+    // Kept so that the address of the node is not reused.
+    _node: VfsNodeRef,
+    handlers: Vec<Arc<dyn IoctlHandler>>,
+}
+
+/// The handlers by the address of their node.
+static HANDLERS: Mutex<BTreeMap<usize, Handlers>> = Mutex::new(BTreeMap::new());
+
+fn key(node: &VfsNodeRef) -> usize {
+    Arc::as_ptr(node) as *const () as usize
+}
+
+/// Registers `handler` for the device node at `path`.
+///
+/// Fails with [`AlreadyExists`](AxError::AlreadyExists) if the node has a
+/// handler for the namespace, and with
+/// [`InvalidInput`](AxError::InvalidInput) if it is not a device or a
+/// command does not match its spec.
+pub fn register(path: &str, handler: Arc<dyn IoctlHandler>) -> AxResult {
+    let node = crate::root::lookup(None, path)?;
+    let ty = node.get_attr()?.file_type();
+    if !ty.is_char_device() && !ty.is_block_device() {
+        return ax_err!(InvalidInput, "ioctl handler for a non-device file");
+    }
+    let namespace = handler.namespace();
+    for spec in handler.commands() {
+        spec.check(namespace)?;
+    }
+    let mut all = HANDLERS.lock();
+    let entry = all.entry(key(&node)).or_insert_with(|| Handlers {
+        _node: node.clone(),
+        handlers: Vec::new(),
+    });
+    if entry.handlers.iter().any(|h| h.namespace() == namespace) {
+        return ax_err!(AlreadyExists, "ioctl namespace already registered");
+    }
+    entry.handlers.push(handler);
+    Ok(())
+}
+
+/// Unregisters the handler of `namespace` for the device node at `path`.
+pub fn unregister(path: &str, namespace: u8) -> AxResult {
+    let key = key(&crate::root::lookup(None, path)?);
+    let mut all = HANDLERS.lock();
+    let entry = all.get_mut(&key).ok_or(AxError::NotFound)?;
+    let idx = entry
+        .handlers
+        .iter()
+        .position(|h| h.namespace() == namespace)
+        .ok_or(AxError::NotFound)?;
+    entry.handlers.remove(idx);
+    if entry.handlers.is_empty() {
+        all.remove(&key);
+    }
+    Ok(())
+}
+
+/// Performs the command `cmd` on `node`.
+///
+/// # Safety
+///
+/// `arg` must be valid for the argument of the command.
+pub(crate) unsafe fn dispatch(node: &VfsNodeRef, cmd: u32, arg: usize) -> AxResult<usize> {
+    let cmd = IoctlCmd(cmd);
+    // The handler is called without the lock, it may block.
+    let handler = HANDLERS
+        .lock()
+        .get(&key(node))
+        .and_then(|entry| entry.handlers.iter().find(|h| h.namespace() == cmd.ty()))
+        .cloned()
+        .ok_or(AxError::Unsupported)?;
+    let spec = handler
+        .commands()
+        .iter()
+        .find(|spec| spec.cmd == cmd)
+        .copied()
+        .ok_or(AxError::Unsupported)?;
+    if spec.size != 0 && arg == 0 {
+        return ax_err!(BadAddress, "null ioctl argument");
+    }
+    handler.ioctl(cmd, &mut IoctlArg { addr: arg, spec })
+}
@@ -6,10 +6,44 @@
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
-//!    **enabled** by default.
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, with the
+//!    [random devices](fs::random). This feature is **enabled** by default.
 //! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
 //!    **enabled** by default.
+//! - `procfs`: Mount a [`ProcFileSystem`] on `/proc`, with the per-process
+//!    directories of [`set_pid_source`]. This feature is **enabled** by
+//!    default.
+//! - `sysfs`: Mount a RAM filesystem on `/sys`, with the device tree of
+//!    [`axdriver::model`] and the parameters of the drivers in
+//!    `/sys/module/<driver>/parameters`, see [`axdriver::params`], and the
+//!    network interfaces of [`set_net_source`] in `/sys/class/net`. This
+//!    feature is **enabled** by default.
+//! - `acl`: Check the access control lists stored in the
+//!    `system.posix_acl_access` extended attribute when opening files, after
+//!    the permission bits. This feature is **disabled** by default.
+//! - `fsck`: Check the consistency of the FAT volume before mounting it, and
+//!    log the problems found. This feature is **disabled** by default.
+//! - `fsck-repair`: Like `fsck`, and repair the problems found. This feature
+//!    is **disabled** by default.
+//! - `power-fail`: Log the blocks written and the write barriers, to simulate
+//!    power losses and check the crashed volumes with [`power_fail`]. For
+//!    testing only, this feature is **disabled** by default.
+//! - `overlay`: Use the main filesystem as the read-only lower layer of an
+//!    [`OverlayFileSystem`], with a RAM filesystem as the upper layer, so the
+//!    changes are not written to the disk. With `myfs`, it allows to build
+//!    overlays for the custom filesystem. This feature is **disabled** by
+//!    default.
+//! - `writeback`: Cache the blocks written in memory, and write them back to
+//!    the disk by a flusher task, when they expire or there are too many, with
+//!    the writers throttled; see [`writeback`]. It enables `multitask`, and
+//!    this feature is **disabled** by default.
+//! - `readahead`: Cache the blocks read from the disk, and read the blocks
+//!    after them when they are read sequentially, with the window and the
+//!    retention tuned per file by [`File::advise`]; see [`readahead`]. This
+//!    feature is **disabled** by default.
+//! - `multitask`: Keep the current directory and the file mode creation mask
+//!    per task, inherited by the spawned tasks, instead of globally. This
+//!    feature is **disabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -18,6 +52,11 @@
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
+//! [`OverlayFileSystem`]: fops::OverlayFileSystem
+//! [`ProcFileSystem`]: fops::ProcFileSystem
+//! [`File::advise`]: fops::File::advise
+//! [`set_pid_source`]: fops::set_pid_source
+//! [`set_net_source`]: fops::set_net_source
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
 #![feature(doc_auto_cfg)]
@@ -29,10 +68,26 @@ extern crate alloc;
 mod dev;
 mod fs;
 mod mounts;
+mod perm;
 mod root;
+mod sparse;
+mod times;
+mod xattr;
+
+#[cfg(feature = "acl")]
+mod acl;
+#[cfg(feature = "fsck")]
+mod fsck;
+#[cfg(feature = "power-fail")]
+pub mod power_fail;
+#[cfg(feature = "readahead")]
+pub mod readahead;
+#[cfg(feature = "writeback")]
+pub mod writeback;
 
 pub mod api;
 pub mod fops;
+pub mod ioctl;
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
@@ -1,4 +1,8 @@
+#[cfg(feature = "sysfs")]
+use alloc::format;
 use alloc::sync::Arc;
+#[cfg(feature = "sysfs")]
+use axfs_vfs::VfsNodeRef;
 use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
 
 use crate::fs;
@@ -12,18 +16,21 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let foo_dir = devfs.mkdir("foo");
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
+    let random = Arc::new(fs::random::RandomDev);
+    devfs.add("random", random.clone());
+    devfs.add("urandom", random);
     foo_dir.add("bar", Arc::new(bar));
     Arc::new(devfs)
 }
 
 #[cfg(feature = "ramfs")]
 pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
-    Arc::new(fs::ramfs::RamFileSystem::new())
+    Arc::new(fs::ramfs::RamFileSystem::with_clock(crate::times::now))
 }
 
 #[cfg(feature = "procfs")]
-pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let procfs = fs::ramfs::RamFileSystem::new();
+pub(crate) fn procfs() -> VfsResult<Arc<fs::procfs::ProcFileSystem>> {
+    let procfs = axfs_ramfs::RamFileSystem::with_clock(crate::times::now);
     let proc_root = procfs.root_dir();
 
     // Create /proc/sys/net/core/somaxconn
@@ -44,12 +51,12 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
 
-    Ok(Arc::new(procfs))
+    Ok(Arc::new(fs::procfs::ProcFileSystem::new(procfs)))
 }
 
 #[cfg(feature = "sysfs")]
-pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let sysfs = fs::ramfs::RamFileSystem::new();
+pub(crate) fn sysfs() -> VfsResult<Arc<fs::sysfs::SysFileSystem>> {
+    let sysfs = fs::ramfs::RamFileSystem::with_clock(crate::times::now);
     let sys_root = sysfs.root_dir();
 
     // Create /sys/kernel/mm/transparent_hugepage/enabled
@@ -76,5 +83,38 @@ pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
         .lookup("devices/system/clocksource/clocksource0/current_clocksource")?;
     file_cc.write_at(0, b"tsc\n")?;
 
-    Ok(Arc::new(sysfs))
+    // Create the device tree, e.g. /sys/devices/pci0000:00/0000:00:01.0/net0,
+    // listed by bus and class in /sys/bus/pci/devices and /sys/class/net
+    sys_root.create("bus", VfsNodeType::Dir)?;
+    sys_root.create("class", VfsNodeType::Dir)?;
+    sys_root.create("class/net", VfsNodeType::Dir)?;
+    for dev in axdriver::model::devices() {
+        let dir = format!("devices/{}", dev.path);
+        sys_root.create(&dir, VfsNodeType::Dir)?;
+        if let Some(driver) = &dev.driver {
+            write_sys_file(&sys_root, &format!("{dir}/driver"), &format!("{driver}\n"))?;
+        }
+        let list = match dev.subsystem {
+            axdriver::model::Subsystem::Bus(bus) => {
+                let _ = sys_root.create(&format!("bus/{bus}"), VfsNodeType::Dir);
+                format!("bus/{bus}/devices")
+            }
+            axdriver::model::Subsystem::Class(class) => format!("class/{class}"),
+        };
+        let _ = sys_root.create(&list, VfsNodeType::Dir);
+        write_sys_file(
+            &sys_root,
+            &format!("{list}/{}", dev.name),
+            &format!("/sys/{dir}\n"),
+        )?;
+    }
+
+    Ok(Arc::new(fs::sysfs::SysFileSystem::new(sysfs)))
+}
+
+#[cfg(feature = "sysfs")]
+fn write_sys_file(root: &VfsNodeRef, path: &str, content: &str) -> VfsResult {
+    root.create(path, VfsNodeType::File)?;
+    root.clone().lookup(path)?.write_at(0, content.as_bytes())?;
+    Ok(())
 }
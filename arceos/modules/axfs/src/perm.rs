@@ -0,0 +1,19 @@
+//! Permission bits of new files and directories.
+//!
+//! Only the RAM filesystem stores permission bits, other filesystems keep
+//! their fixed ones.
+
+use axfs_ramfs::{DirNode, FileNode};
+use axfs_vfs::{VfsNodePerm, VfsNodeRef};
+
+/// Sets the permission bits of `node` to `mode`, if the filesystem supports
+/// it.
+pub(crate) fn set_perm(node: &VfsNodeRef, mode: u32) {
+    let perm = VfsNodePerm::from_bits_truncate(mode as u16);
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        file.set_perm(perm);
+    } else if let Some(dir) = any.downcast_ref::<DirNode>() {
+        dir.set_perm(perm);
+    }
+}
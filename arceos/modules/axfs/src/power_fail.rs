@@ -0,0 +1,110 @@
+//! Power-fail testing mode of the block layer.
+//!
+//! The [`Disk`](crate::dev::Disk) logs every block written and every write
+//! barrier (a flush of the device, requested by the filesystem when it
+//! flushes a file or itself). A power loss is then simulated offline on a
+//! copy of the initial image with [`crash_image`], as on a device with a
+//! volatile write cache: the writes before the last barrier are persistent,
+//! the ones after it may or may not have reached the medium, in any order.
+//! [`check_image`] tells whether the metadata of the crashed image is
+//! consistent.
+
+use alloc::{boxed::Box, vec::Vec};
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+
+use crate::fsck::{FsckReport, Volume};
+
+/// Size of the blocks in the log.
+pub const BLOCK_SIZE: usize = 512;
+
+/// An operation on the block device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockOp {
+    /// A block was written.
+    Write {
+        /// Index of the block.
+        block_id: u64,
+        /// New content of the block.
+        data: Box<[u8]>,
+    },
+    /// The device was flushed, the previous writes are persistent.
+    Barrier,
+}
+
+static LOG: Mutex<Vec<BlockOp>> = Mutex::new(Vec::new());
+
+pub(crate) fn log_write(block_id: u64, data: &[u8]) {
+    LOG.lock().push(BlockOp::Write {
+        block_id,
+        data: data.into(),
+    });
+}
+
+pub(crate) fn log_barrier() {
+    LOG.lock().push(BlockOp::Barrier);
+}
+
+/// Takes the operations logged since the last call.
+pub fn take_log() -> Vec<BlockOp> {
+    core::mem::take(&mut *LOG.lock())
+}
+
+/// Returns `image` after applying the first `point` operations of `log`
+/// before a power loss.
+///
+/// The writes after the last barrier before `point` are lost or not
+/// depending on the bits of a pseudo-random sequence seeded by `seed`.
+pub fn crash_image(image: &[u8], log: &[BlockOp], point: usize, seed: u64) -> Vec<u8> {
+    let log = &log[..point.min(log.len())];
+    let synced = log
+        .iter()
+        .rposition(|op| matches!(op, BlockOp::Barrier))
+        .map_or(0, |i| i + 1);
+    let mut state = seed | 1;
+    let mut image = Vec::from(image);
+    for (i, op) in log.iter().enumerate() {
+        if let BlockOp::Write { block_id, data } = op {
+            if i >= synced {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if state & 1 == 0 {
+                    continue;
+                }
+            }
+            let start = *block_id as usize * BLOCK_SIZE;
+            if let Some(block) = image.get_mut(start..start + BLOCK_SIZE) {
+                block.copy_from_slice(data);
+            }
+        }
+    }
+    image
+}
+
+impl Volume for &mut [u8] {
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> AxResult {
+        let start = pos as usize;
+        match self.get(start..start + buf.len()) {
+            Some(src) => buf.copy_from_slice(src),
+            None => return ax_err!(UnexpectedEof),
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> AxResult {
+        let start = pos as usize;
+        match self.get_mut(start..start + buf.len()) {
+            Some(dst) => dst.copy_from_slice(buf),
+            None => return ax_err!(UnexpectedEof),
+        }
+        Ok(())
+    }
+}
+
+/// Checks the consistency of the FAT volume in `image`, repairing it if
+/// `repair`.
+pub fn check_image(mut image: &mut [u8], repair: bool) -> AxResult<FsckReport> {
+    crate::fsck::check(&mut image, repair)
+}
@@ -0,0 +1,431 @@
+//! Caching and readahead of the blocks read, with the `readahead` feature.
+//!
+//! The blocks read from the disk are cached, and the writes update the cached
+//! ones, so that the cache is always coherent. When the blocks are read
+//! sequentially, the next ones are read too, by a single read of the device,
+//! in a window which starts at [`ReadaheadConfig::min_blocks`] and doubles
+//! each time the reads reach the blocks read ahead, up to
+//! [`ReadaheadConfig::max_blocks`]. A few streams are followed at once, so
+//! the reads of the metadata in between do not stop the readahead of the
+//! files.
+//!
+//! [`File::advise`] adjusts this for the reads of a file, with the advices of
+//! `posix_fadvise`:
+//! - [`Advice::Sequential`]: the readahead starts at the first read, at twice
+//!   the maximum window, and the blocks read are evicted first, as they are
+//!   not read again;
+//! - [`Advice::Random`]: no readahead, and the blocks read are evicted last;
+//! - [`Advice::WillNeed`]: the range is read into the cache now;
+//! - [`Advice::DontNeed`]: the cached blocks of the range are dropped, as the
+//!   filesystem reads it a last time.
+//!
+//! The direct I/O bypasses the cache and does not read ahead. The cache holds
+//! [`ReadaheadConfig::cache_blocks`] at most: the least recently used blocks
+//! are evicted first, those read at most once before those read again. The
+//! allocator evicts them the same way under memory pressure.
+//!
+//! [`File::advise`]: crate::fops::File::advise
+
+use alloc::{boxed::Box, collections::BTreeMap, vec};
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use axalloc::Shrinker;
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axsync::{Mutex, MutexGuard};
+
+use crate::dev::{BlockDevice, BLOCK_SIZE};
+use crate::fops::Advice;
+
+/// The number of sequential streams followed at once.
+const STREAMS: usize = 4;
+
+/// The bytes read at once for [`Advice::WillNeed`] and [`Advice::DontNeed`].
+const ADVISE_CHUNK: usize = 0x10000;
+
+/// The sizes of the readahead window and of the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadaheadConfig {
+    /// The blocks read ahead when a sequential stream starts.
+    pub min_blocks: usize,
+    /// The most blocks read ahead at once, 0 to disable the readahead.
+    pub max_blocks: usize,
+    /// The most blocks cached.
+    pub cache_blocks: usize,
+}
+
+impl ReadaheadConfig {
+    /// The default configuration: from 4 KiB to 128 KiB read ahead, like
+    /// Linux, in a cache of 4 MiB.
+    pub const DEFAULT: Self = Self {
+        min_blocks: 8,
+        max_blocks: 256,
+        cache_blocks: 8192,
+    };
+}
+
+impl Default for ReadaheadConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The statistics of the cache, returned by [`stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadaheadStats {
+    /// The blocks cached now.
+    pub cached_blocks: usize,
+    /// The blocks read found in the cache.
+    pub hits: u64,
+    /// The blocks read from the device as they were asked for.
+    pub misses: u64,
+    /// The blocks read from the device ahead of the reads.
+    pub readahead: u64,
+    /// The blocks evicted to make room for others.
+    pub evicted: u64,
+}
+
+static CONFIG: Mutex<ReadaheadConfig> = Mutex::new(ReadaheadConfig::DEFAULT);
+
+static CACHED: AtomicUsize = AtomicUsize::new(0);
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static READAHEAD: AtomicU64 = AtomicU64::new(0);
+static EVICTED: AtomicU64 = AtomicU64::new(0);
+
+/// The cache of the blocks of the disk.
+static CACHE: Mutex<ReadCache> = Mutex::new(ReadCache::new());
+
+/// Evicts the cached blocks when the allocations fail.
+static SHRINKER: ReadCacheShrinker = ReadCacheShrinker;
+
+/// Held while a file with an advice is read, the advice being in `ADVICE`.
+static ADVISED: Mutex<()> = Mutex::new(());
+static ADVICE: AtomicU8 = AtomicU8::new(Advice::Normal as u8);
+
+/// Returns the configuration of the readahead.
+pub fn config() -> ReadaheadConfig {
+    *CONFIG.lock()
+}
+
+/// Sets the configuration of the readahead. The blocks above the new size of
+/// the cache are evicted at the next read.
+pub fn set_config(config: ReadaheadConfig) -> AxResult {
+    if config.min_blocks > config.max_blocks {
+        return ax_err!(InvalidInput, "readahead minimum above the maximum");
+    }
+    *CONFIG.lock() = config;
+    Ok(())
+}
+
+/// Returns the statistics of the cache.
+pub fn stats() -> ReadaheadStats {
+    ReadaheadStats {
+        cached_blocks: CACHED.load(Ordering::Relaxed),
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        readahead: READAHEAD.load(Ordering::Relaxed),
+        evicted: EVICTED.load(Ordering::Relaxed),
+    }
+}
+
+/// Returns the cache of the blocks of the disk, its shrinker registered on
+/// the first call.
+pub(crate) fn cache() -> MutexGuard<'static, ReadCache> {
+    static REGISTERED: AtomicBool = AtomicBool::new(false);
+    if !REGISTERED.swap(true, Ordering::Relaxed) {
+        if let Err(err) = axalloc::register_shrinker(&SHRINKER) {
+            warn!("readahead: failed to register the shrinker: {:?}", err);
+        }
+    }
+    CACHE.lock()
+}
+
+/// Runs `f`, a read of a file, with the blocks read under `advice`.
+pub(crate) fn advised<R>(advice: Advice, f: impl FnOnce() -> R) -> R {
+    if advice == Advice::Normal {
+        return f();
+    }
+    let _guard = ADVISED.lock();
+    ADVICE.store(advice as u8, Ordering::Relaxed);
+    let ret = f();
+    ADVICE.store(Advice::Normal as u8, Ordering::Relaxed);
+    ret
+}
+
+/// Applies [`Advice::WillNeed`] or [`Advice::DontNeed`] to the `len` bytes
+/// from `offset` of `node`, to the end of the file if `len` is 0, by reading
+/// them.
+pub(crate) fn read_range(node: &VfsNodeRef, offset: u64, len: u64, advice: Advice) -> AxResult {
+    let size = node.get_attr()?.size();
+    let mut end = if len == 0 {
+        size
+    } else {
+        offset.saturating_add(len).min(size)
+    };
+    if advice == Advice::WillNeed {
+        // more would evict the first blocks read
+        let cache_bytes = (config().cache_blocks * BLOCK_SIZE) as u64;
+        end = end.min(offset.saturating_add(cache_bytes));
+    }
+    let mut buf = vec![0; ADVISE_CHUNK];
+    advised(advice, || {
+        let mut pos = offset;
+        while pos < end {
+            let chunk = (end - pos).min(ADVISE_CHUNK as u64) as usize;
+            let n = node.read_at(pos, &mut buf[..chunk])?;
+            if n == 0 {
+                break;
+            }
+            pos += n as u64;
+        }
+        Ok(())
+    })
+}
+
+/// The order of eviction of a block: the blocks not read again first, then
+/// the least recently used.
+type LruKey = (bool, u64);
+
+struct CachedBlock {
+    data: Box<[u8; BLOCK_SIZE]>,
+    lru: LruKey,
+    /// Whether it was read, not only read ahead.
+    referenced: bool,
+}
+
+/// A sequential stream of reads.
+#[derive(Clone, Copy)]
+struct Stream {
+    /// The block its next read is expected at.
+    next: u64,
+    /// The blocks of the window, 0 until the stream is sequential.
+    window: usize,
+    /// The first block of the last window read ahead, the stream grows when
+    /// the reads reach it.
+    ahead: u64,
+    /// The end of the last window read ahead.
+    ahead_end: u64,
+    /// When it was read last, the least recent one is replaced.
+    used: u64,
+}
+
+impl Stream {
+    const NONE: Self = Self {
+        next: u64::MAX,
+        window: 0,
+        ahead: 0,
+        ahead_end: 0,
+        used: 0,
+    };
+}
+
+/// The cache of the blocks read, with their readahead.
+pub(crate) struct ReadCache {
+    blocks: BTreeMap<u64, CachedBlock>,
+    /// The blocks in the order of eviction.
+    lru: BTreeMap<LruKey, u64>,
+    streams: [Stream; STREAMS],
+    clock: u64,
+}
+
+impl ReadCache {
+    pub(crate) const fn new() -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            streams: [Stream::NONE; STREAMS],
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn insert(&mut self, block_id: u64, data: &[u8], hot: bool, referenced: bool) {
+        if self.blocks.contains_key(&block_id) {
+            return;
+        }
+        let lru = (hot, self.tick());
+        let mut block = Box::new([0; BLOCK_SIZE]);
+        block.copy_from_slice(data);
+        let block = CachedBlock {
+            data: block,
+            lru,
+            referenced,
+        };
+        self.blocks.insert(block_id, block);
+        self.lru.insert(lru, block_id);
+        let excess = self.blocks.len().saturating_sub(config().cache_blocks);
+        self.evict(excess);
+    }
+
+    /// Evicts `count` blocks at most, in the order of eviction, returns the
+    /// number evicted.
+    fn evict(&mut self, count: usize) -> usize {
+        let mut evicted = 0;
+        while evicted < count {
+            let Some((_, victim)) = self.lru.pop_first() else {
+                break;
+            };
+            self.blocks.remove(&victim);
+            evicted += 1;
+        }
+        EVICTED.fetch_add(evicted as u64, Ordering::Relaxed);
+        CACHED.store(self.blocks.len(), Ordering::Relaxed);
+        evicted
+    }
+
+    fn remove(&mut self, block_id: u64) {
+        if let Some(block) = self.blocks.remove(&block_id) {
+            self.lru.remove(&block.lru);
+            CACHED.store(self.blocks.len(), Ordering::Relaxed);
+        }
+    }
+
+    /// Reads the block `block_id` into `buf`, from the cache or from `dev`,
+    /// and the blocks after it if it continues a sequential stream.
+    pub(crate) fn read(
+        &mut self,
+        dev: &mut BlockDevice,
+        block_id: u64,
+        buf: &mut [u8],
+    ) -> DevResult {
+        let advice = advice_from_u8(ADVICE.load(Ordering::Relaxed));
+        let hot = advice == Advice::Random;
+        let clock = self.tick();
+        if let Some(block) = self.blocks.get_mut(&block_id) {
+            buf.copy_from_slice(&block.data[..]);
+            HITS.fetch_add(1, Ordering::Relaxed);
+            if advice == Advice::DontNeed {
+                self.remove(block_id);
+            } else {
+                // kept longer once read again, but by a sequential reader
+                let again = block.referenced && advice != Advice::Sequential;
+                let lru = (block.lru.0 || again, clock);
+                block.referenced = true;
+                let old = core::mem::replace(&mut block.lru, lru);
+                self.lru.remove(&old);
+                self.lru.insert(lru, block_id);
+            }
+        } else {
+            dev.read_block(block_id, buf)?;
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            if advice != Advice::DontNeed {
+                self.insert(block_id, buf, hot, true);
+            }
+        }
+        let ahead = self.on_read(block_id, advice);
+        let ahead = ahead.start..ahead.end.min(dev.num_blocks());
+        if !ahead.is_empty() {
+            // the block asked for is read, a failure of the readahead is not
+            // one of the read
+            if let Err(err) = self.fill(dev, ahead, hot) {
+                debug!("readahead failed: {:?}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the cached block `block_id`, once `buf` is written to it.
+    pub(crate) fn update(&mut self, block_id: u64, buf: &[u8]) {
+        if let Some(block) = self.blocks.get_mut(&block_id) {
+            block.data.copy_from_slice(buf);
+        }
+    }
+
+    /// Returns the blocks to read ahead after the block `block_id` is read.
+    fn on_read(&mut self, block_id: u64, advice: Advice) -> Range<u64> {
+        let config = config();
+        let (start_window, max) = match advice {
+            Advice::Normal => (config.min_blocks, config.max_blocks),
+            Advice::Sequential => (config.max_blocks * 2, config.max_blocks * 2),
+            Advice::Random | Advice::WillNeed | Advice::DontNeed => return 0..0,
+        };
+        if max == 0 {
+            return 0..0;
+        }
+        let used = self.tick();
+        let (stream, continued) = match self.streams.iter().position(|s| s.next == block_id) {
+            Some(i) => (&mut self.streams[i], true),
+            None => {
+                let lru = self.streams.iter_mut().min_by_key(|s| s.used).unwrap();
+                *lru = Stream::NONE;
+                (lru, false)
+            }
+        };
+        stream.next = block_id + 1;
+        stream.used = used;
+        let start = if stream.window == 0 && (continued || advice == Advice::Sequential) {
+            stream.window = start_window.max(1);
+            block_id + 1
+        } else if stream.window != 0 && block_id >= stream.ahead {
+            // the reads reached the blocks read ahead
+            stream.window = (stream.window * 2).min(max);
+            stream.ahead_end.max(block_id + 1)
+        } else {
+            return 0..0;
+        };
+        stream.ahead = start;
+        stream.ahead_end = start + stream.window as u64;
+        start..stream.ahead_end
+    }
+
+    /// Reads the blocks `range` not cached into the cache, each run of them
+    /// by a single read of `dev`.
+    fn fill(&mut self, dev: &mut BlockDevice, range: Range<u64>, hot: bool) -> DevResult {
+        let mut block_id = range.start;
+        while block_id < range.end {
+            if self.blocks.contains_key(&block_id) {
+                block_id += 1;
+                continue;
+            }
+            let run_end = (block_id..range.end)
+                .find(|id| self.blocks.contains_key(id))
+                .unwrap_or(range.end);
+            let mut data = vec![0; (run_end - block_id) as usize * BLOCK_SIZE];
+            dev.read_block(block_id, &mut data)?;
+            for (i, block) in data.chunks_exact(BLOCK_SIZE).enumerate() {
+                self.insert(block_id + i as u64, block, hot, false);
+            }
+            READAHEAD.fetch_add(run_end - block_id, Ordering::Relaxed);
+            block_id = run_end;
+        }
+        Ok(())
+    }
+}
+
+/// The shrinker of [`CACHE`], the blocks cached are clean and dropped at once.
+struct ReadCacheShrinker;
+
+impl Shrinker for ReadCacheShrinker {
+    fn name(&self) -> &'static str {
+        "readahead"
+    }
+
+    fn count(&self) -> usize {
+        CACHED.load(Ordering::Relaxed)
+    }
+
+    fn scan(&self, nr_to_scan: usize) -> usize {
+        // the cache allocates the blocks with its lock held
+        match CACHE.try_lock() {
+            Some(mut cache) => cache.evict(nr_to_scan),
+            None => 0,
+        }
+    }
+}
+
+const fn advice_from_u8(v: u8) -> Advice {
+    match v {
+        1 => Advice::Sequential,
+        2 => Advice::Random,
+        3 => Advice::WillNeed,
+        4 => Advice::DontNeed,
+        _ => Advice::Normal,
+    }
+}
@@ -2,25 +2,34 @@
 //!
 //! TODO: it doesn't work very well if the mount points have containment relationships.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{borrow::Cow, string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
 use axfs_ramfs::DirNode;
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 use lazyinit::LazyInit;
 
+use crate::times::AtimeMode;
 use crate::{api::FileType, fs, mounts};
 
+/// Default file mode creation mask.
+pub(crate) const DEFAULT_UMASK: u32 = 0o022;
+
+// Current directory and file mode creation mask used when there is no current
+// task, they are per task with the "multitask" feature.
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
-static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
+static UMASK: AtomicU32 = AtomicU32::new(DEFAULT_UMASK);
 
 struct MountPoint {
     path: &'static str,
     fs: Arc<dyn VfsOps>,
+    atime: AtomicU8,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
+    main_atime: AtomicU8,
     mounts: Vec<MountPoint>,
 }
 
@@ -28,7 +37,11 @@ static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
     pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+        Self {
+            path,
+            fs,
+            atime: AtomicU8::new(AtimeMode::Relatime as u8),
+        }
     }
 }
 
@@ -42,6 +55,7 @@ impl RootDirectory {
     pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
         Self {
             main_fs,
+            main_atime: AtomicU8::new(AtimeMode::Relatime as u8),
             mounts: Vec::new(),
         }
     }
@@ -60,17 +74,56 @@ impl RootDirectory {
         self.main_fs.root_dir().create(path, FileType::Dir)?;
         fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
         self.mounts.push(MountPoint::new(path, fs));
+        #[cfg(feature = "audit")]
+        axaudit::audit(axaudit::AuditEvent::Mount { path: path.into() });
+        #[cfg(feature = "event")]
+        axevent::publish(axevent::KernelEvent::MountChange {
+            path: path.into(),
+            mounted: true,
+        });
         Ok(())
     }
 
     pub fn _umount(&mut self, path: &str) {
         self.mounts.retain(|mp| mp.path != path);
+        #[cfg(feature = "event")]
+        axevent::publish(axevent::KernelEvent::MountChange {
+            path: path.into(),
+            mounted: false,
+        });
     }
 
     pub fn contains(&self, path: &str) -> bool {
         self.mounts.iter().any(|mp| mp.path == path)
     }
 
+    /// Returns the access time mode of the filesystem of `path`.
+    fn atime_mode(&self, path: &str) -> AtimeMode {
+        let path = path.trim_matches('/');
+        let atime = self
+            .mounts
+            .iter()
+            .filter(|mp| path.starts_with(&mp.path[1..]))
+            .max_by_key(|mp| mp.path.len())
+            .map_or(&self.main_atime, |mp| &mp.atime);
+        AtimeMode::from_u8(atime.load(Ordering::Relaxed))
+    }
+
+    /// Sets the access time mode of the filesystem mounted at `path`.
+    fn set_atime_mode(&self, path: &str, mode: AtimeMode) -> AxResult {
+        let atime = if path.trim_matches('/').is_empty() {
+            &self.main_atime
+        } else {
+            let path = path.trim_end_matches('/');
+            match self.mounts.iter().find(|mp| mp.path == path) {
+                Some(mp) => &mp.atime,
+                None => return ax_err!(InvalidInput, "not a mount point"),
+            }
+        };
+        atime.store(mode as u8, Ordering::Relaxed);
+        Ok(())
+    }
+
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
@@ -156,17 +209,33 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
+#[cfg(feature = "fsck")]
+pub(crate) static FSCK_REPORT: LazyInit<crate::fsck::FsckReport> = LazyInit::new();
+
 pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
         } else if #[cfg(feature = "fatfs")] {
+            #[cfg(all(feature = "fsck", not(feature = "use-ramdisk")))]
+            let disk = {
+                let mut disk = disk;
+                if let Some(report) = crate::fsck::check_disk(&mut disk) {
+                    FSCK_REPORT.init_once(report);
+                }
+                disk
+            };
             static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
             FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
             FAT_FS.init();
             let main_fs = FAT_FS.clone();
         }
     }
+    #[cfg(all(feature = "overlay", not(feature = "myfs")))]
+    let main_fs = Arc::new(fs::overlay::OverlayFileSystem::new(
+        main_fs,
+        mounts::ramfs(),
+    ));
 
     let mut root_dir = RootDirectory::new(main_fs);
 
@@ -193,15 +262,43 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
-    CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
-fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
+/// Returns a snapshot of the current directory, always ending with '/'.
+fn cwd() -> String {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.fs_context().lock().cwd.clone();
+    }
+    CURRENT_DIR_PATH.lock().clone()
+}
+
+fn set_cwd(path: String) {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        curr.fs_context().lock().cwd = path;
+        return;
+    }
+    *CURRENT_DIR_PATH.lock() = path;
+}
+
+/// Returns the node `path` is to be looked up from and the path to look up.
+///
+/// Paths relative to the current directory are made absolute from a single
+/// snapshot of it, so that a concurrent `chdir` cannot change the directory
+/// in the middle of the resolution.
+fn parent_node_of<'a>(dir: Option<&VfsNodeRef>, path: &'a str) -> (VfsNodeRef, Cow<'a, str>) {
     if path.starts_with('/') {
-        ROOT_DIR.clone()
+        (ROOT_DIR.clone(), Cow::Borrowed(path))
+    } else if let Some(dir) = dir {
+        (dir.clone(), Cow::Borrowed(path))
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        let mut abs_path = axfs_vfs::path::canonicalize(&(cwd() + path));
+        if path.ends_with('/') && !abs_path.ends_with('/') {
+            abs_path.push('/');
+        }
+        (ROOT_DIR.clone(), Cow::Owned(abs_path))
     }
 }
 
@@ -209,8 +306,7 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
-        Ok(axfs_vfs::path::canonicalize(&path))
+        Ok(axfs_vfs::path::canonicalize(&(cwd() + path)))
     }
 }
 
@@ -218,7 +314,8 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let (parent, path) = parent_node_of(dir, path);
+    let node = parent.lookup(&path)?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
@@ -226,21 +323,32 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     }
 }
 
-pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Creates the file `path` with the permission bits of `mode` not in the
+/// file mode creation mask.
+pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str, mode: u32) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let (parent, path) = parent_node_of(dir, path);
+    parent.create(&path, VfsNodeType::File)?;
+    let node = parent.lookup(&path)?;
+    crate::perm::set_perm(&node, mode & !umask());
+    Ok(node)
 }
 
-pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
+/// Creates the directory `path` with the permission bits of `mode` not in
+/// the file mode creation mask.
+pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str, mode: u32) -> AxResult {
     match lookup(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let (parent, path) = parent_node_of(dir, path);
+            parent.create(&path, VfsNodeType::Dir)?;
+            crate::perm::set_perm(&parent.lookup(&path)?, mode & !umask());
+            Ok(())
+        }
         Err(e) => Err(e),
     }
 }
@@ -253,7 +361,8 @@ pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, path) = parent_node_of(dir, path);
+        parent.remove(&path)
     }
 }
 
@@ -282,12 +391,18 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, path) = parent_node_of(dir, path);
+        parent.remove(&path)
     }
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    let cwd = cwd();
+    if cwd.len() > 1 {
+        Ok(cwd.trim_end_matches('/').into())
+    } else {
+        Ok(cwd)
+    }
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
@@ -295,32 +410,52 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     if !abs_path.ends_with('/') {
         abs_path += "/";
     }
-    if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
-        return Ok(());
+    if abs_path != "/" {
+        let attr = lookup(None, &abs_path)?.get_attr()?;
+        if !attr.is_dir() {
+            return ax_err!(NotADirectory);
+        } else if !attr.perm().owner_executable() {
+            return ax_err!(PermissionDenied);
+        }
     }
+    set_cwd(abs_path);
+    Ok(())
+}
 
-    let node = lookup(None, &abs_path)?;
-    let attr = node.get_attr()?;
-    if !attr.is_dir() {
-        ax_err!(NotADirectory)
-    } else if !attr.perm().owner_executable() {
-        ax_err!(PermissionDenied)
-    } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
-        Ok(())
+/// Returns the file mode creation mask.
+pub(crate) fn umask() -> u32 {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.fs_context().lock().umask;
+    }
+    UMASK.load(Ordering::Relaxed)
+}
+
+/// Sets the file mode creation mask, returns the previous one.
+pub(crate) fn set_umask(mask: u32) -> u32 {
+    let mask = mask & 0o777;
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return core::mem::replace(&mut curr.fs_context().lock().umask, mask);
     }
+    UMASK.swap(mask, Ordering::Relaxed)
+}
+
+pub(crate) fn atime_mode(path: &str) -> AxResult<AtimeMode> {
+    Ok(ROOT_DIR.atime_mode(&absolute_path(path)?))
+}
+
+pub(crate) fn set_atime_mode(mount_point: &str, mode: AtimeMode) -> AxResult {
+    ROOT_DIR.set_atime_mode(&absolute_path(mount_point)?, mode)
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    parent_node_of(None, old).lookup(old)?;
+    lookup(None, old)?;
 
     let old_abs = absolute_path(old)?;
     let new_abs = absolute_path(new)?;
     if old_abs == new_abs {
         return ax_err!(InvalidInput, "src and dst are the same");
     }
-    parent_node_of(None, old_abs.as_str()).rename(old_abs.as_str(), new_abs.as_str())
+    ROOT_DIR.rename(&old_abs, &new_abs)
 }
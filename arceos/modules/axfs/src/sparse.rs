@@ -0,0 +1,56 @@
+//! Sparse files: hole and data seeking, preallocation and hole punching.
+//!
+//! The RAM filesystem tracks its holes. Other filesystems are seen as having
+//! no holes, with data up to the end of the file, and do not support
+//! [`allocate`] nor [`punch_hole`].
+
+use axerrno::{ax_err, AxResult};
+use axfs_ramfs::FileNode;
+use axfs_vfs::VfsNodeRef;
+
+fn ramfs_file(node: &VfsNodeRef) -> Option<&FileNode> {
+    node.as_any().downcast_ref::<FileNode>()
+}
+
+/// Returns the first offset at or after `offset` that is in data, `None` if
+/// there is no data past `offset`.
+pub(crate) fn seek_data(node: &VfsNodeRef, offset: u64) -> AxResult<Option<u64>> {
+    if let Some(file) = ramfs_file(node) {
+        return Ok(file.seek_data(offset));
+    }
+    let size = node.get_attr()?.size();
+    Ok((offset < size).then_some(offset))
+}
+
+/// Returns the first offset at or after `offset` that is in a hole, the end
+/// of the file being a hole. `None` if `offset` is past the end of the file.
+pub(crate) fn seek_hole(node: &VfsNodeRef, offset: u64) -> AxResult<Option<u64>> {
+    if let Some(file) = ramfs_file(node) {
+        return Ok(file.seek_hole(offset));
+    }
+    let size = node.get_attr()?.size();
+    Ok((offset < size).then_some(size))
+}
+
+/// Allocates the storage of `len` bytes from `offset`, extending the file
+/// unless `keep_size`.
+pub(crate) fn allocate(node: &VfsNodeRef, offset: u64, len: u64, keep_size: bool) -> AxResult {
+    match ramfs_file(node) {
+        Some(file) => {
+            file.allocate(offset, len, keep_size);
+            Ok(())
+        }
+        None => ax_err!(Unsupported, "preallocation not supported by the filesystem"),
+    }
+}
+
+/// Deallocates `len` bytes from `offset`, which then read as zeros.
+pub(crate) fn punch_hole(node: &VfsNodeRef, offset: u64, len: u64) -> AxResult {
+    match ramfs_file(node) {
+        Some(file) => {
+            file.punch_hole(offset, len);
+            Ok(())
+        }
+        None => ax_err!(Unsupported, "hole punching not supported by the filesystem"),
+    }
+}
@@ -0,0 +1,158 @@
+//! File timestamps.
+//!
+//! The filesystems update the modification and change times themselves, the
+//! access time is updated here on reads, according to the [`AtimeMode`] of
+//! the mount point.
+
+use axerrno::{ax_err, AxResult};
+use axfs_ramfs::{DirNode, FileNode, NodeTimes};
+use axfs_vfs::VfsNodeRef;
+use core::time::Duration;
+
+/// Timestamps of a file or directory, since the UNIX epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    /// Time of last access.
+    pub atime: Duration,
+    /// Time of last modification of the content.
+    pub mtime: Duration,
+    /// Time of last change of the content or the metadata.
+    pub ctime: Duration,
+}
+
+/// When to update the access time on reads, set per mount point.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtimeMode {
+    /// Update on every access.
+    Strict = 0,
+    /// Update only if the access time is older than the modification or
+    /// change time, or older than one day (`relatime`). The default.
+    Relatime = 1,
+    /// Never update (`noatime`).
+    NoAtime = 2,
+}
+
+impl AtimeMode {
+    pub(crate) const fn from_u8(v: u8) -> Self {
+        match v {
+            0 => Self::Strict,
+            2 => Self::NoAtime,
+            _ => Self::Relatime,
+        }
+    }
+}
+
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returns the current wall time.
+pub(crate) fn now() -> Duration {
+    axhal::time::wall_time()
+}
+
+impl From<NodeTimes> for FileTimes {
+    fn from(t: NodeTimes) -> Self {
+        Self {
+            atime: t.atime,
+            mtime: t.mtime,
+            ctime: t.ctime,
+        }
+    }
+}
+
+impl From<FileTimes> for NodeTimes {
+    fn from(t: FileTimes) -> Self {
+        Self {
+            atime: t.atime,
+            mtime: t.mtime,
+            ctime: t.ctime,
+        }
+    }
+}
+
+/// Gets the timestamps of `node`, all zero if its filesystem does not
+/// support them.
+pub(crate) fn get(node: &VfsNodeRef) -> FileTimes {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        return file.times().into();
+    }
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        return dir.times().into();
+    }
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    {
+        use crate::fs::fatfs::{DirWrapper, FileWrapper};
+        if let Some(file) = any.downcast_ref::<FileWrapper<'static>>() {
+            return file.times().unwrap_or_default();
+        }
+        if let Some(dir) = any.downcast_ref::<DirWrapper<'static>>() {
+            return dir.times().unwrap_or_default();
+        }
+    }
+    FileTimes::default()
+}
+
+/// Sets the access and modification times of `node`, the change time is set
+/// to now. `None` leaves the time unchanged.
+pub(crate) fn set(node: &VfsNodeRef, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+    let now = now();
+    let update = |old: FileTimes| FileTimes {
+        atime: atime.unwrap_or(old.atime),
+        mtime: mtime.unwrap_or(old.mtime),
+        ctime: now,
+    };
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        file.set_times(update(file.times().into()).into());
+        return Ok(());
+    }
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        dir.set_times(update(dir.times().into()).into());
+        return Ok(());
+    }
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    if let Some(file) = any.downcast_ref::<crate::fs::fatfs::FileWrapper<'static>>() {
+        return file.set_times(atime, mtime);
+    }
+    ax_err!(Unsupported, "timestamps not supported by the filesystem")
+}
+
+/// Updates the access time of `node` after a read, according to `mode`.
+pub(crate) fn accessed(node: &VfsNodeRef, mode: AtimeMode) {
+    let now = now();
+    let update = |t: FileTimes| match mode {
+        AtimeMode::Strict => true,
+        AtimeMode::Relatime => {
+            t.atime <= t.mtime || t.atime <= t.ctime || t.atime + RELATIME_INTERVAL <= now
+        }
+        AtimeMode::NoAtime => false,
+    };
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        let times = file.times();
+        if update(times.into()) {
+            file.set_times(NodeTimes {
+                atime: now,
+                ..times
+            });
+        }
+        return;
+    }
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        let times = dir.times();
+        if update(times.into()) {
+            dir.set_times(NodeTimes {
+                atime: now,
+                ..times
+            });
+        }
+        return;
+    }
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    if let Some(file) = any.downcast_ref::<crate::fs::fatfs::FileWrapper<'static>>() {
+        if file.times().is_some_and(update) {
+            file.set_times(Some(now), None).ok();
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//! Write-back caching of the block device, with the `writeback` feature.
+//!
+//! The blocks written by the filesystem stay dirty in memory, and a flusher
+//! task writes them to the device:
+//! - the blocks dirty for longer than [`WritebackConfig::dirty_expire`]
+//!   first, checked every [`WritebackConfig::writeback_interval`];
+//! - from the lowest block, while the dirty blocks are above the background
+//!   threshold;
+//! - all of them when the filesystem flushes the device, on `fsync`.
+//!
+//! The direct I/O bypasses the cache: its blocks are written through to the
+//! device, their dirty copies dropped, and its flushes leave the blocks of
+//! the other files dirty.
+//!
+//! A task dirtying blocks faster than the device writes them is throttled,
+//! like `balance_dirty_pages` of Linux: halfway from the background
+//! threshold to the limit, it pauses after each block, at most
+//! [`MAX_PAUSE`] and the longer the closer to the limit, and at the limit it
+//! waits for the flusher to go below it. So the dirty blocks never take all
+//! the memory before a sync. Under memory pressure, the allocator has the
+//! lowest dirty blocks written back too.
+//!
+//! The thresholds are ratios of the free memory at boot, or sizes in bytes,
+//! set by [`set_config`].
+
+use alloc::{
+    boxed::Box, collections::btree_map::Entry, collections::BTreeMap, sync::Arc, vec::Vec,
+};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axalloc::Shrinker;
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxResult};
+use axhal::mem::MemRegionFlags;
+use axhal::time::monotonic_time;
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use crate::dev::BLOCK_SIZE;
+
+/// The longest pause of a throttled task after writing a block.
+pub const MAX_PAUSE: Duration = Duration::from_millis(200);
+
+/// The memory the ratios are of when the free memory is unknown, e.g. in the
+/// hosted tests.
+const DEFAULT_DIRTYABLE: usize = 64 << 20;
+
+/// The stack size of the flusher task.
+const FLUSHER_STACK_SIZE: usize = 0x10000;
+
+/// The throttled tasks are woken after this number of blocks written back.
+const WAKE_BATCH: usize = 32;
+
+/// The thresholds and the deadlines of the write-back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WritebackConfig {
+    /// The limit of the dirty blocks, in percents of the free memory.
+    pub dirty_ratio: u8,
+    /// The dirty blocks above which the flusher writes them back, in
+    /// percents of the free memory.
+    pub dirty_background_ratio: u8,
+    /// The limit in bytes, instead of `dirty_ratio` if it is not 0.
+    pub dirty_bytes: usize,
+    /// The background threshold in bytes, instead of
+    /// `dirty_background_ratio` if it is not 0.
+    pub dirty_background_bytes: usize,
+    /// How long a block may stay dirty.
+    pub dirty_expire: Duration,
+    /// How often the flusher looks for the expired blocks.
+    pub writeback_interval: Duration,
+}
+
+impl WritebackConfig {
+    /// The default configuration, the one of Linux.
+    pub const DEFAULT: Self = Self {
+        dirty_ratio: 20,
+        dirty_background_ratio: 10,
+        dirty_bytes: 0,
+        dirty_background_bytes: 0,
+        dirty_expire: Duration::from_secs(30),
+        writeback_interval: Duration::from_secs(5),
+    };
+}
+
+impl Default for WritebackConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The statistics of the write-back, returned by [`stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WritebackStats {
+    /// The dirty blocks now.
+    pub dirty_blocks: usize,
+    /// The background threshold, in blocks.
+    pub background_blocks: usize,
+    /// The limit, in blocks.
+    pub limit_blocks: usize,
+    /// The blocks written back to the device.
+    pub written_back: u64,
+    /// The blocks of them written back as they expired.
+    pub expired: u64,
+    /// The times a task was throttled.
+    pub throttled: u64,
+    /// How long the tasks were throttled in total.
+    pub throttled_time: Duration,
+}
+
+static CONFIG: Mutex<WritebackConfig> = Mutex::new(WritebackConfig::DEFAULT);
+
+/// The free memory at boot, the ratios are of it.
+static DIRTYABLE: AtomicUsize = AtomicUsize::new(0);
+
+static DIRTY: AtomicUsize = AtomicUsize::new(0);
+static WRITTEN_BACK: AtomicU64 = AtomicU64::new(0);
+static EXPIRED: AtomicU64 = AtomicU64::new(0);
+static THROTTLED: AtomicU64 = AtomicU64::new(0);
+static THROTTLED_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// The flusher and the throttled tasks, woken on changes of the config.
+static WAKERS: Mutex<Option<Arc<Shared>>> = Mutex::new(None);
+
+/// Writes back the dirty blocks when the allocations fail.
+static SHRINKER: WritebackShrinker = WritebackShrinker;
+
+/// Returns the configuration of the write-back.
+pub fn config() -> WritebackConfig {
+    *CONFIG.lock()
+}
+
+/// Sets the configuration of the write-back.
+///
+/// A background threshold not below the limit is taken as half the limit.
+pub fn set_config(config: WritebackConfig) -> AxResult {
+    if config.dirty_ratio > 100 || config.dirty_background_ratio > 100 {
+        return ax_err!(InvalidInput, "dirty ratio above 100");
+    }
+    if config.writeback_interval.is_zero() {
+        return ax_err!(InvalidInput, "zero writeback interval");
+    }
+    *CONFIG.lock() = config;
+    // the thresholds may have moved
+    if let Some(shared) = WAKERS.lock().as_ref() {
+        shared.flusher.notify_one(false);
+        shared.throttled.notify_all(false);
+    }
+    Ok(())
+}
+
+/// Returns the statistics of the write-back.
+pub fn stats() -> WritebackStats {
+    let thresholds = Thresholds::get();
+    WritebackStats {
+        dirty_blocks: DIRTY.load(Ordering::Relaxed),
+        background_blocks: thresholds.background,
+        limit_blocks: thresholds.limit,
+        written_back: WRITTEN_BACK.load(Ordering::Relaxed),
+        expired: EXPIRED.load(Ordering::Relaxed),
+        throttled: THROTTLED.load(Ordering::Relaxed),
+        throttled_time: Duration::from_nanos(THROTTLED_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+/// The thresholds in blocks.
+struct Thresholds {
+    background: usize,
+    limit: usize,
+}
+
+impl Thresholds {
+    fn get() -> Self {
+        let config = config();
+        let dirtyable = DIRTYABLE.load(Ordering::Relaxed);
+        let blocks = |bytes: usize, ratio: u8| {
+            let bytes = if bytes != 0 {
+                bytes
+            } else {
+                dirtyable / 100 * ratio as usize
+            };
+            bytes / BLOCK_SIZE
+        };
+        let limit = blocks(config.dirty_bytes, config.dirty_ratio).max(1);
+        let mut background = blocks(config.dirty_background_bytes, config.dirty_background_ratio);
+        if background >= limit {
+            background = limit / 2;
+        }
+        Self { background, limit }
+    }
+
+    /// Below it, the writers are not throttled.
+    fn freerun(&self) -> usize {
+        (self.background + self.limit) / 2
+    }
+}
+
+fn dirty_blocks() -> usize {
+    DIRTY.load(Ordering::Relaxed)
+}
+
+/// A dirty block.
+struct DirtyBlock {
+    data: Box<[u8; BLOCK_SIZE]>,
+    /// When it was dirtied, it is written back when it expires.
+    since: Duration,
+}
+
+struct Inner {
+    dev: AxBlockDevice,
+    dirty: BTreeMap<u64, DirtyBlock>,
+}
+
+impl Inner {
+    /// Writes the dirty block `block_id` back to the device, if it is still
+    /// dirty.
+    fn write_back(&mut self, block_id: u64) -> DevResult {
+        let Some(block) = self.dirty.remove(&block_id) else {
+            return Ok(());
+        };
+        if let Err(err) = self.dev.write_block(block_id, &block.data[..]) {
+            self.dirty.insert(block_id, block);
+            return Err(err);
+        }
+        #[cfg(feature = "power-fail")]
+        crate::power_fail::log_write(block_id, &block.data[..]);
+        DIRTY.store(self.dirty.len(), Ordering::Relaxed);
+        WRITTEN_BACK.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+struct Shared {
+    inner: Mutex<Inner>,
+    /// The flusher, woken above the background threshold.
+    flusher: WaitQueue,
+    /// The tasks throttled at the limit.
+    throttled: WaitQueue,
+}
+
+impl Shared {
+    /// Writes the dirty block `block_id` back to the device, if it is still
+    /// dirty.
+    fn write_back(&self, block_id: u64) -> DevResult {
+        self.inner.lock().write_back(block_id)
+    }
+
+    /// Writes back the expired blocks, and the lowest blocks while they are
+    /// above the background threshold.
+    fn write_back_pass(&self) -> DevResult {
+        let expire = config().dirty_expire;
+        let now = monotonic_time();
+        let expired: Vec<u64> = self
+            .inner
+            .lock()
+            .dirty
+            .iter()
+            .filter(|(_, block)| now.saturating_sub(block.since) >= expire)
+            .map(|(&block_id, _)| block_id)
+            .collect();
+        let mut written = 0;
+        for block_id in expired {
+            self.write_back(block_id)?;
+            EXPIRED.fetch_add(1, Ordering::Relaxed);
+            written += 1;
+            if written % WAKE_BATCH == 0 {
+                self.throttled.notify_all(false);
+            }
+        }
+        while dirty_blocks() > Thresholds::get().background {
+            let Some(block_id) = self.inner.lock().dirty.keys().next().copied() else {
+                break;
+            };
+            self.write_back(block_id)?;
+            written += 1;
+            if written % WAKE_BATCH == 0 {
+                self.throttled.notify_all(false);
+            }
+        }
+        self.throttled.notify_all(false);
+        Ok(())
+    }
+
+    /// Writes `buf` to the block `block_id` of the device at once, dropping
+    /// its dirty copy.
+    fn write_through(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        let mut inner = self.inner.lock();
+        inner.dev.write_block(block_id, buf)?;
+        #[cfg(feature = "power-fail")]
+        crate::power_fail::log_write(block_id, buf);
+        if inner.dirty.remove(&block_id).is_some() {
+            DIRTY.store(inner.dirty.len(), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Writes back all the dirty blocks.
+    fn write_back_all(&self) -> DevResult {
+        let result = loop {
+            let Some(block_id) = self.inner.lock().dirty.keys().next().copied() else {
+                break Ok(());
+            };
+            if let Err(err) = self.write_back(block_id) {
+                break Err(err);
+            }
+        };
+        self.throttled.notify_all(false);
+        result
+    }
+
+    fn flusher(&self) -> ! {
+        loop {
+            let interval = config().writeback_interval;
+            self.flusher
+                .wait_timeout_until(interval, || dirty_blocks() > Thresholds::get().background);
+            if let Err(err) = self.write_back_pass() {
+                warn!("writeback: failed to write a block: {:?}", err);
+                // not again right away
+                axtask::sleep(interval);
+            }
+        }
+    }
+
+    /// Throttles the current task which dirtied a block, `dirty` blocks are
+    /// dirty now.
+    fn balance_dirty(&self, dirty: usize) {
+        let thresholds = Thresholds::get();
+        if dirty > thresholds.background {
+            self.flusher.notify_one(false);
+        }
+        let freerun = thresholds.freerun();
+        if dirty <= freerun {
+            return;
+        }
+        let start = monotonic_time();
+        if dirty < thresholds.limit {
+            // the closer to the limit, the longer
+            let over = (dirty - freerun) as u32;
+            axtask::sleep(MAX_PAUSE * over / (thresholds.limit - freerun) as u32);
+        } else {
+            // until below the limit, as long as the flusher makes progress
+            loop {
+                let written = WRITTEN_BACK.load(Ordering::Relaxed);
+                let below = self
+                    .throttled
+                    .wait_timeout_until(MAX_PAUSE, || dirty_blocks() < Thresholds::get().limit);
+                if !below || WRITTEN_BACK.load(Ordering::Relaxed) == written {
+                    break;
+                }
+                self.flusher.notify_one(false);
+            }
+        }
+        let throttled_time = monotonic_time().saturating_sub(start);
+        THROTTLED.fetch_add(1, Ordering::Relaxed);
+        THROTTLED_NANOS.fetch_add(throttled_time.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// The shrinker of the dirty blocks, written back from the lowest one.
+struct WritebackShrinker;
+
+impl Shrinker for WritebackShrinker {
+    fn name(&self) -> &'static str {
+        "writeback"
+    }
+
+    fn count(&self) -> usize {
+        dirty_blocks()
+    }
+
+    fn scan(&self, nr_to_scan: usize) -> usize {
+        // the writers allocate the dirty blocks with the lock held
+        let Some(shared) = WAKERS.try_lock().and_then(|wakers| wakers.clone()) else {
+            return 0;
+        };
+        let mut freed = 0;
+        while freed < nr_to_scan {
+            let Some(mut inner) = shared.inner.try_lock() else {
+                break;
+            };
+            let Some(block_id) = inner.dirty.keys().next().copied() else {
+                break;
+            };
+            if let Err(err) = inner.write_back(block_id) {
+                warn!("writeback: failed to write a block: {:?}", err);
+                break;
+            }
+            freed += 1;
+        }
+        if freed > 0 {
+            shared.throttled.notify_all(false);
+        }
+        freed
+    }
+}
+
+/// A block device with the written blocks cached, and written back by a
+/// flusher task.
+pub(crate) struct CachedDevice {
+    shared: Arc<Shared>,
+    num_blocks: u64,
+}
+
+impl From<AxBlockDevice> for CachedDevice {
+    /// Caches the writes to `dev`, and spawns the flusher task.
+    fn from(dev: AxBlockDevice) -> Self {
+        let free: usize = axhal::mem::memory_regions()
+            .filter(|region| region.flags.contains(MemRegionFlags::FREE))
+            .map(|region| region.size)
+            .sum();
+        let dirtyable = if free == 0 { DEFAULT_DIRTYABLE } else { free };
+        DIRTYABLE.store(dirtyable, Ordering::Relaxed);
+
+        let num_blocks = dev.num_blocks();
+        let shared = Arc::new(Shared {
+            inner: Mutex::new(Inner {
+                dev,
+                dirty: BTreeMap::new(),
+            }),
+            flusher: WaitQueue::new(),
+            throttled: WaitQueue::new(),
+        });
+        *WAKERS.lock() = Some(shared.clone());
+        if let Err(err) = axalloc::register_shrinker(&SHRINKER) {
+            warn!("writeback: failed to register the shrinker: {:?}", err);
+        }
+        let flusher = shared.clone();
+        axtask::spawn_raw(
+            move || flusher.flusher(),
+            "writeback".into(),
+            FLUSHER_STACK_SIZE,
+        );
+        let thresholds = Thresholds::get();
+        info!(
+            "  writeback: background {} blocks, limit {} blocks",
+            thresholds.background, thresholds.limit
+        );
+        Self { shared, num_blocks }
+    }
+}
+
+impl CachedDevice {
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Reads the blocks from `block_id`, the dirty ones if they are not
+    /// written back.
+    pub fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let mut inner = self.shared.inner.lock();
+        if buf.len() == BLOCK_SIZE {
+            if let Some(block) = inner.dirty.get(&block_id) {
+                buf.copy_from_slice(&block.data[..]);
+                return Ok(());
+            }
+        }
+        inner.dev.read_block(block_id, buf)?;
+        let end = block_id + (buf.len() / BLOCK_SIZE) as u64;
+        for (&dirty_id, block) in inner.dirty.range(block_id..end) {
+            let start = (dirty_id - block_id) as usize * BLOCK_SIZE;
+            buf[start..start + BLOCK_SIZE].copy_from_slice(&block.data[..]);
+        }
+        Ok(())
+    }
+
+    /// Writes the block `block_id` into the cache, and throttles the current
+    /// task if there are too many dirty blocks. The direct I/O writes it to
+    /// the device instead.
+    pub fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        if crate::dev::is_direct() {
+            return self.shared.write_through(block_id, buf);
+        }
+        let dirty = {
+            let mut inner = self.shared.inner.lock();
+            match inner.dirty.entry(block_id) {
+                Entry::Occupied(mut entry) => entry.get_mut().data.copy_from_slice(buf),
+                Entry::Vacant(entry) => {
+                    let mut data = Box::new([0; BLOCK_SIZE]);
+                    data.copy_from_slice(buf);
+                    entry.insert(DirtyBlock {
+                        data,
+                        since: monotonic_time(),
+                    });
+                }
+            }
+            inner.dirty.len()
+        };
+        DIRTY.store(dirty, Ordering::Relaxed);
+        self.shared.balance_dirty(dirty);
+        Ok(())
+    }
+
+    /// Writes back all the dirty blocks and flushes the device, only the
+    /// device for the direct I/O.
+    pub fn flush(&mut self) -> DevResult {
+        if !crate::dev::is_direct() {
+            self.shared.write_back_all()?;
+        }
+        self.shared.inner.lock().dev.flush()
+    }
+}
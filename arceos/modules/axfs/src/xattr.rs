@@ -0,0 +1,112 @@
+//! Extended attributes.
+//!
+//! Only the RAM filesystem stores extended attributes, on other filesystems
+//! the operations return [`Unsupported`](AxError::Unsupported).
+
+use alloc::{string::String, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_ramfs::{DirNode, FileNode};
+use axfs_vfs::VfsNodeRef;
+
+/// Namespaces an attribute name must start with.
+const NAMESPACES: [&str; 4] = ["user.", "system.", "trusted.", "security."];
+
+/// How [`set`] behaves if the attribute exists or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrSet {
+    /// Create or replace the attribute.
+    Any,
+    /// Fail with [`AlreadyExists`](AxError::AlreadyExists) if the attribute
+    /// exists.
+    Create,
+    /// Fail with [`NotFound`](AxError::NotFound) if the attribute does not
+    /// exist.
+    Replace,
+}
+
+fn check_name(name: &str) -> AxResult {
+    if NAMESPACES
+        .iter()
+        .any(|ns| name.len() > ns.len() && name.starts_with(ns))
+    {
+        Ok(())
+    } else {
+        ax_err!(Unsupported, "unknown extended attribute namespace")
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    File(&'a FileNode),
+    Dir(&'a DirNode),
+}
+
+fn ramfs_node(node: &VfsNodeRef) -> AxResult<Node> {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        Ok(Node::File(file))
+    } else if let Some(dir) = any.downcast_ref::<DirNode>() {
+        Ok(Node::Dir(dir))
+    } else {
+        ax_err!(
+            Unsupported,
+            "extended attributes not supported by the filesystem"
+        )
+    }
+}
+
+/// Gets the value of the attribute `name` of `node`.
+pub(crate) fn get(node: &VfsNodeRef, name: &str) -> AxResult<Option<Vec<u8>>> {
+    check_name(name)?;
+    Ok(match ramfs_node(node)? {
+        Node::File(f) => f.get_xattr(name),
+        Node::Dir(d) => d.get_xattr(name),
+    })
+}
+
+/// Sets the attribute `name` of `node` to `value`.
+pub(crate) fn set(node: &VfsNodeRef, name: &str, value: &[u8], mode: XattrSet) -> AxResult {
+    check_name(name)?;
+    #[cfg(feature = "acl")]
+    if name == crate::acl::ACL_XATTR {
+        crate::acl::validate(value)?;
+    }
+    let node = ramfs_node(node)?;
+    let exists = match node {
+        Node::File(f) => f.get_xattr(name).is_some(),
+        Node::Dir(d) => d.get_xattr(name).is_some(),
+    };
+    match mode {
+        XattrSet::Create if exists => return Err(AxError::AlreadyExists),
+        XattrSet::Replace if !exists => return Err(AxError::NotFound),
+        _ => {}
+    }
+    match node {
+        Node::File(f) => f.set_xattr(name, value),
+        Node::Dir(d) => d.set_xattr(name, value),
+    };
+    Ok(())
+}
+
+/// Removes the attribute `name` of `node`, [`NotFound`](AxError::NotFound)
+/// if it does not exist.
+pub(crate) fn remove(node: &VfsNodeRef, name: &str) -> AxResult {
+    check_name(name)?;
+    let removed = match ramfs_node(node)? {
+        Node::File(f) => f.remove_xattr(name),
+        Node::Dir(d) => d.remove_xattr(name),
+    };
+    if removed {
+        Ok(())
+    } else {
+        Err(AxError::NotFound)
+    }
+}
+
+/// Lists the attribute names of `node`.
+pub(crate) fn list(node: &VfsNodeRef) -> AxResult<Vec<String>> {
+    Ok(match ramfs_node(node)? {
+        Node::File(f) => f.list_xattr(),
+        Node::Dir(d) => d.list_xattr(),
+    })
+}
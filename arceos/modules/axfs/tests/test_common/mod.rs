@@ -192,12 +192,17 @@ fn test_devfs_ramfs() -> Result<()> {
     assert!(file.write_all(&buf).is_ok());
     assert_eq!(buf, [0; N]);
 
+    // read /dev/urandom
+    let mut file = File::open("/dev/urandom")?;
+    assert_eq!(file.read(&mut buf)?, N);
+
     // list /dev
     let dirents = fs::read_dir("/dev")?
         .map(|e| e.unwrap().file_name())
         .collect::<Vec<_>>();
     assert!(dirents.contains(&"null".into()));
     assert!(dirents.contains(&"zero".into()));
+    assert!(dirents.contains(&"urandom".into()));
 
     // stat /dev
     let dname = "/dev";
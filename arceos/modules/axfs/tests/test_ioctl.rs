@@ -0,0 +1,112 @@
+#![cfg(all(feature = "devfs", not(feature = "myfs")))]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axerrno::AxError;
+use axfs::fops::{File, OpenOptions};
+use axfs::ioctl::{self, IoctlArg, IoctlCmd, IoctlDir, IoctlHandler, IoctlSpec};
+use axio::Result;
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+const GET_SPEED: IoctlCmd = IoctlCmd::read::<u32>(b'Z', 1);
+const SET_SPEED: IoctlCmd = IoctlCmd::write::<u32>(b'Z', 2);
+const RESET: IoctlCmd = IoctlCmd::none(b'Z', 3);
+const LEGACY_GET: u32 = 0x5a10;
+
+struct SpeedDev {
+    speed: AtomicU32,
+    commands: [IoctlSpec; 4],
+}
+
+impl IoctlHandler for SpeedDev {
+    fn namespace(&self) -> u8 {
+        b'Z'
+    }
+
+    fn commands(&self) -> &[IoctlSpec] {
+        &self.commands
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, arg: &mut IoctlArg) -> Result<usize> {
+        match cmd {
+            GET_SPEED => arg.write(&self.speed.load(Ordering::Relaxed))?,
+            SET_SPEED => self.speed.store(arg.read()?, Ordering::Relaxed),
+            RESET => self.speed.store(arg.value() as u32, Ordering::Relaxed),
+            // the type does not match the size of the spec
+            _ => arg.write(&0u64)?,
+        }
+        Ok(0)
+    }
+}
+
+fn open(path: &str) -> Result<File> {
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    File::open(path, &opts)
+}
+
+#[test]
+fn test_ioctl() {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    let image = std::fs::read(path).expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&image)));
+
+    assert_eq!(SET_SPEED.0, 0x4004_5a02);
+    assert_eq!((SET_SPEED.dir(), SET_SPEED.size()), (IoctlDir::Write, 4));
+    assert_eq!((SET_SPEED.ty(), SET_SPEED.nr()), (b'Z', 2));
+
+    let dev = Arc::new(SpeedDev {
+        speed: AtomicU32::new(9600),
+        commands: [
+            IoctlSpec::encoded(GET_SPEED),
+            IoctlSpec::encoded(SET_SPEED),
+            IoctlSpec::encoded(RESET),
+            IoctlSpec::legacy(LEGACY_GET, IoctlDir::Read, 4),
+        ],
+    });
+    ioctl::register("/dev/zero", dev.clone()).unwrap();
+    assert_eq!(
+        ioctl::register("/dev/zero", dev.clone()).err(),
+        Some(AxError::AlreadyExists)
+    );
+    assert_eq!(
+        ioctl::register("/dev/foo", dev.clone()).err(),
+        Some(AxError::InvalidInput)
+    );
+
+    let zero = open("/dev/zero").unwrap();
+    let mut speed = 0u32;
+    let ptr = &mut speed as *mut u32 as usize;
+    unsafe {
+        assert_eq!(zero.ioctl(GET_SPEED.0, ptr), Ok(0));
+        assert_eq!(speed, 9600);
+        speed = 115200;
+        zero.ioctl(SET_SPEED.0, ptr).unwrap();
+        assert_eq!(dev.speed.load(Ordering::Relaxed), 115200);
+        zero.ioctl(RESET.0, 300).unwrap();
+        assert_eq!(dev.speed.load(Ordering::Relaxed), 300);
+
+        // checked against the spec
+        assert_eq!(zero.ioctl(GET_SPEED.0, 0), Err(AxError::BadAddress));
+        assert_eq!(zero.ioctl(LEGACY_GET, ptr), Err(AxError::InvalidInput));
+        // unknown commands and namespaces, and other nodes
+        assert_eq!(zero.ioctl(0x5a04, ptr), Err(AxError::Unsupported));
+        assert_eq!(zero.ioctl(0x5401, ptr), Err(AxError::Unsupported));
+        let null = open("/dev/null").unwrap();
+        assert_eq!(null.ioctl(GET_SPEED.0, ptr), Err(AxError::Unsupported));
+    }
+
+    ioctl::unregister("/dev/zero", b'Z').unwrap();
+    unsafe {
+        assert_eq!(zero.ioctl(GET_SPEED.0, ptr), Err(AxError::Unsupported));
+    }
+    assert_eq!(
+        ioctl::unregister("/dev/zero", b'Z').err(),
+        Some(AxError::NotFound)
+    );
+}
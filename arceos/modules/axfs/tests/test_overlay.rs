@@ -0,0 +1,71 @@
+#![cfg(all(feature = "myfs", feature = "overlay"))]
+
+mod test_common;
+
+use std::sync::{Arc, OnceLock};
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::fops::{Disk, MyFileSystemIf, OverlayFileSystem};
+use axfs_ramfs::RamFileSystem;
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+
+static LOWER: OnceLock<Arc<RamFileSystem>> = OnceLock::new();
+
+struct MyFileSystemIfImpl;
+
+#[crate_interface::impl_interface]
+impl MyFileSystemIf for MyFileSystemIfImpl {
+    fn new_myfs(_disk: Disk) -> Arc<dyn VfsOps> {
+        let lower = LOWER.get().unwrap().clone();
+        Arc::new(OverlayFileSystem::new(
+            lower,
+            Arc::new(RamFileSystem::new()),
+        ))
+    }
+}
+
+/// Creates the initial files in the lower layer.
+fn create_init_files(lower: &RamFileSystem) -> VfsResult {
+    let root = lower.root_dir();
+    let write = |path: &str, content: &[u8]| -> VfsResult {
+        root.create(path, VfsNodeType::File)?;
+        root.clone().lookup(path)?.write_at(0, content)?;
+        Ok(())
+    };
+    write("short.txt", b"Rust is cool!\n")?;
+    write("long.txt", "Rust is cool!\n".repeat(100).as_bytes())?;
+    root.create("very-long-dir-name", VfsNodeType::Dir)?;
+    write(
+        "very-long-dir-name/very-long-file-name.txt",
+        b"Rust is cool!\n",
+    )?;
+    root.create("very", VfsNodeType::Dir)?;
+    root.create("very/long", VfsNodeType::Dir)?;
+    root.create("very/long/path", VfsNodeType::Dir)?;
+    write("very/long/path/test.txt", b"Rust is cool!\n")?;
+    Ok(())
+}
+
+#[test]
+fn test_overlay() {
+    println!("Testing overlay ...");
+
+    let lower = Arc::new(RamFileSystem::new());
+    create_init_files(&lower).expect("failed to create init files");
+    LOWER.set(lower.clone()).ok();
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::default())); // dummy disk, actually not used.
+
+    test_common::test_all();
+
+    // The lower layer is left untouched.
+    let mut buf = [0; 64];
+    let short = lower.root_dir().lookup("short.txt").unwrap();
+    let n = short.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"Rust is cool!\n");
+    let test = lower.root_dir().lookup("very/long/path/test.txt").unwrap();
+    let n = test.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"Rust is cool!\n");
+}
@@ -0,0 +1,64 @@
+#![cfg(all(
+    feature = "power-fail",
+    not(feature = "myfs"),
+    not(feature = "overlay")
+))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api::{self as fs, File};
+use axfs::power_fail::{self, BlockOp};
+use axio::{prelude::*, Result};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn workload() -> Result<()> {
+    fs::create_dir("/power-fail")?;
+    for i in 0..8 {
+        let mut file = File::create(&format!("/power-fail/file-{}", i))?;
+        file.write_all(&vec![i as u8; 3000 * (i + 1)])?;
+        file.flush()?;
+    }
+    fs::remove_file("/power-fail/file-3")?;
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open("/power-fail/file-5")?;
+    file.write_all(&[0xff; 5000])?;
+    file.flush()?;
+    fs::rename("/power-fail/file-6", "/power-fail/file-9")?;
+    Ok(())
+}
+
+#[test]
+fn test_power_fail() {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    let image = std::fs::read(path).expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&image)));
+
+    workload().expect("workload failed");
+    let log = power_fail::take_log();
+    let barriers = log.iter().filter(|op| **op == BlockOp::Barrier).count();
+    println!("{} block operations, {} barriers", log.len(), barriers);
+    assert!(barriers >= 9);
+
+    // Everything is persistent at the barriers.
+    for (i, _) in log
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| **op == BlockOp::Barrier)
+    {
+        let mut crashed = power_fail::crash_image(&image, &log, i + 1, 0);
+        let report = power_fail::check_image(&mut crashed, false).unwrap();
+        assert!(report.is_consistent(), "barrier {}: {:?}", i, report);
+    }
+
+    // Between them, the checker repairs what was lost.
+    for seed in 1..=64u64 {
+        let point = (seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % (log.len() + 1);
+        let mut crashed = power_fail::crash_image(&image, &log, point, seed);
+        power_fail::check_image(&mut crashed, true).unwrap();
+        let report = power_fail::check_image(&mut crashed, false).unwrap();
+        assert!(report.is_consistent(), "point {}: {:?}", point, report);
+    }
+}
@@ -0,0 +1,89 @@
+#![cfg(all(feature = "readahead", not(feature = "myfs"), not(feature = "overlay")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axfs::fops::{Advice, File, OpenOptions};
+use axfs::readahead::{self, ReadaheadConfig};
+
+const IMG_PATH: &str = "resources/fat16.img";
+const FILE_SIZE: usize = 256 * 1024;
+
+fn open(path: &str, write: bool) -> File {
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(write);
+    File::open(path, &opts).unwrap()
+}
+
+fn read_all(file: &mut File) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0; 1000];
+    loop {
+        let n = file.read(&mut buf).unwrap();
+        if n == 0 {
+            return data;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[test]
+fn test_readahead() {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    let image = std::fs::read(path).expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&image)));
+
+    assert!(readahead::set_config(ReadaheadConfig {
+        min_blocks: 16,
+        max_blocks: 8,
+        ..ReadaheadConfig::DEFAULT
+    })
+    .is_err());
+    readahead::set_config(ReadaheadConfig {
+        min_blocks: 4,
+        max_blocks: 64,
+        cache_blocks: 2048,
+    })
+    .unwrap();
+
+    let content: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    fs::write("/readahead.bin", &content).unwrap();
+
+    // A sequential read is served by the blocks read ahead.
+    let before = readahead::stats();
+    let mut file = open("/readahead.bin", false);
+    assert_eq!(read_all(&mut file), content);
+    let stats = readahead::stats();
+    println!("{:?}", stats);
+    let blocks = (FILE_SIZE / 512) as u64;
+    assert!(stats.readahead - before.readahead >= blocks / 2);
+    assert!(stats.misses - before.misses < blocks / 8);
+
+    // The blocks of the file are dropped.
+    file.advise(0, 0, Advice::DontNeed).unwrap();
+    assert!(readahead::stats().cached_blocks < stats.cached_blocks - blocks as usize / 2);
+
+    // No readahead for the random reads.
+    file.advise(0, 0, Advice::Random).unwrap();
+    let before = readahead::stats();
+    let mut buf = [0; 100];
+    for offset in [200_000, 3_000, 100_000, 50_000] {
+        file.read_at(offset as u64, &mut buf).unwrap();
+        assert_eq!(buf[..], content[offset..offset + 100]);
+    }
+    assert_eq!(readahead::stats().readahead, before.readahead);
+
+    // The range is read in advance.
+    file.advise(0, 8192, Advice::WillNeed).unwrap();
+    let before = readahead::stats();
+    file.read_at(4096, &mut buf).unwrap();
+    assert_eq!(readahead::stats().misses, before.misses);
+
+    // The writes update the cached blocks.
+    let writer = open("/readahead.bin", true);
+    writer.write_at(4096, b"changed").unwrap();
+    file.read_at(4096, &mut buf[..7]).unwrap();
+    assert_eq!(&buf[..7], b"changed");
+}
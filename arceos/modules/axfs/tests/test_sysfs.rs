@@ -0,0 +1,111 @@
+#![cfg(all(feature = "myfs", feature = "sysfs"))]
+
+use std::sync::Arc;
+
+use axdriver::params::{self, register_driver_param, DriverParam, DRIVER_PARAMS};
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axfs::fops::{Disk, MyFileSystemIf, NetFileFn, NetSource};
+use axfs_ramfs::RamFileSystem;
+use axfs_vfs::VfsOps;
+use axio::Error;
+
+struct MyFileSystemIfImpl;
+
+#[crate_interface::impl_interface]
+impl MyFileSystemIf for MyFileSystemIfImpl {
+    fn new_myfs(_disk: Disk) -> Arc<dyn VfsOps> {
+        Arc::new(RamFileSystem::new())
+    }
+}
+
+#[register_driver_param(DRIVER_PARAMS)]
+static DEPTH: DriverParam = DriverParam::uint("test", "depth", 64, 1, 1024, "queue depth");
+
+#[register_driver_param(DRIVER_PARAMS)]
+static OFFLOAD: DriverParam = DriverParam::bool("test", "offload", false, "offload").boot_only();
+
+const DIR: &str = "/sys/module/test/parameters";
+
+const NET_FILES: &[(&str, NetFileFn)] = &[
+    ("mtu", |_| Some("1500\n".into())),
+    ("statistics/rx_bytes", |iface| {
+        Some(format!("{}\n", iface.len()))
+    }),
+    ("statistics/tx_bytes", |_| None),
+];
+
+#[test]
+fn test_sysfs() {
+    println!("Testing sysfs ...");
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::default())); // dummy disk, actually not used.
+
+    // Set on the command line, the unknown and invalid arguments ignored.
+    params::parse_cmdline("console=ttyS0 test.offload test.depth=0 other.x=1");
+    assert!(OFFLOAD.enabled());
+    assert_eq!(DEPTH.get(), 64);
+    params::parse_cmdline("test.depth=0x80");
+    assert_eq!(DEPTH.get(), 128);
+
+    let list = |path: &str| -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        names
+    };
+    assert!(list("/sys").contains(&"module".into()));
+    assert_eq!(list("/sys/module"), ["test"]);
+    assert_eq!(list(DIR), ["depth", "offload"]);
+
+    let depth = format!("{DIR}/depth");
+    let offload = format!("{DIR}/offload");
+    assert_eq!(fs::read_to_string(&depth).unwrap(), "128\n");
+    assert_eq!(fs::read_to_string(&offload).unwrap(), "Y\n");
+
+    // Changed at runtime, within the range.
+    fs::write(&depth, "256\n").unwrap();
+    assert_eq!(DEPTH.get(), 256);
+    assert_eq!(fs::read_to_string(&depth).unwrap(), "256\n");
+    assert_eq!(fs::write(&depth, "2048").err(), Some(Error::InvalidInput));
+    assert_eq!(DEPTH.get(), 256);
+
+    // The boot only ones are read-only.
+    assert!(!fs::metadata(&offload)
+        .unwrap()
+        .permissions()
+        .owner_writable());
+    assert!(fs::write(&offload, "0").is_err());
+    assert!(OFFLOAD.enabled());
+
+    assert!(fs::metadata(&format!("{DIR}/nothing")).is_err());
+    assert!(fs::metadata("/sys/module/nothing").is_err());
+
+    // The network interfaces, from their source.
+    axfs::fops::set_net_source(NetSource {
+        ifaces: || vec!["eth0".into()],
+        files: NET_FILES,
+    });
+    assert!(list("/sys/class/net").contains(&"eth0".into()));
+    assert_eq!(list("/sys/class/net/eth0"), ["mtu", "statistics"]);
+    assert_eq!(
+        list("/sys/class/net/eth0/statistics"),
+        ["rx_bytes", "tx_bytes"]
+    );
+    assert_eq!(
+        fs::read_to_string("/sys/class/net/eth0/mtu").unwrap(),
+        "1500\n"
+    );
+    assert_eq!(
+        fs::read_to_string("/sys/class/net/eth0/statistics/rx_bytes").unwrap(),
+        "4\n"
+    );
+    assert!(fs::read_to_string("/sys/class/net/eth0/statistics/tx_bytes").is_err());
+    assert!(fs::write("/sys/class/net/eth0/mtu", "9000").is_err());
+    assert!(fs::metadata("/sys/class/net/eth0/speed").is_err());
+    assert!(fs::metadata("/sys/class/net/eth1").is_err());
+}
@@ -0,0 +1,61 @@
+#![cfg(all(feature = "writeback", not(feature = "myfs"), not(feature = "overlay")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api::{self as fs, File};
+use axfs::writeback::{self, WritebackConfig};
+use axio::{prelude::*, Result};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn workload() -> Result<()> {
+    fs::create_dir("/writeback")?;
+    for i in 0..8 {
+        let mut file = File::create(&format!("/writeback/file-{}", i))?;
+        file.write_all(&vec![i as u8; 6000 * (i + 1)])?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_writeback() {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    let image = std::fs::read(path).expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&image)));
+
+    assert!(writeback::set_config(WritebackConfig {
+        dirty_ratio: 101,
+        ..WritebackConfig::DEFAULT
+    })
+    .is_err());
+    writeback::set_config(WritebackConfig {
+        dirty_bytes: 32 * 512,
+        dirty_background_bytes: 8 * 512,
+        ..WritebackConfig::DEFAULT
+    })
+    .unwrap();
+    let stats = writeback::stats();
+    assert_eq!((stats.background_blocks, stats.limit_blocks), (8, 32));
+
+    // The writers are throttled to stay at the limit.
+    workload().expect("workload failed");
+    let stats = writeback::stats();
+    println!("{:?}", stats);
+    assert!(stats.dirty_blocks <= stats.limit_blocks);
+    assert!(stats.written_back > 0);
+    assert!(stats.throttled > 0);
+
+    // The dirty blocks are read back before they are written back.
+    for i in 0..8 {
+        let data = fs::read(&format!("/writeback/file-{}", i)).unwrap();
+        assert_eq!(data, vec![i as u8; 6000 * (i + 1)]);
+    }
+
+    // All of them are written back on the flush.
+    let mut file = File::create("/writeback/last").unwrap();
+    file.write_all(b"last").unwrap();
+    file.flush().unwrap();
+    assert_eq!(writeback::stats().dirty_blocks, 0);
+    assert_eq!(fs::read_to_string("/writeback/last").unwrap(), "last");
+}
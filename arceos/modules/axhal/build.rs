@@ -43,6 +43,13 @@ fn main() {
         "cargo::rustc-check-cfg=cfg(platform_family, values({}))",
         make_cfg_values(BUILTIN_PLATFORM_FAMILIES)
     );
+
+    // The FP/SIMD states are switched lazily by trapping their first use,
+    // which the dummy platform (in user space) cannot do.
+    if platform != "dummy" {
+        println!("cargo:rustc-cfg=lazy_fp");
+    }
+    println!("cargo::rustc-check-cfg=cfg(lazy_fp)");
 }
 
 fn gen_linker_script(arch: &str, platform: &str) -> Result<()> {
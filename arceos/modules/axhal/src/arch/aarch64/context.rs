@@ -25,12 +25,113 @@ pub struct FpState {
     pub fpcr: u32,
     /// Floating-point Status Register (FPSR)
     pub fpsr: u32,
+    /// SVE registers, if SVE is used.
+    pub sve: SveState,
+}
+
+/// The largest SVE vector length switched, in bytes (512 bits). Longer
+/// vectors are limited to it by `ZCR_EL1`.
+pub const MAX_SVE_VL: usize = 64;
+
+/// SVE registers.
+#[repr(C, align(16))]
+#[derive(Debug)]
+pub struct SveState {
+    /// Z0..Z31 of VL bytes each, then P0..P15 and FFR of VL / 8 bytes each,
+    /// for the vector length VL of the CPU.
+    pub regs: [u8; 32 * MAX_SVE_VL + 17 * MAX_SVE_VL / 8],
+}
+
+impl Default for SveState {
+    fn default() -> Self {
+        Self {
+            regs: [0; 32 * MAX_SVE_VL + 17 * MAX_SVE_VL / 8],
+        }
+    }
 }
 
 #[cfg(feature = "fp_simd")]
 impl FpState {
+    #[cfg(not(lazy_fp))]
     fn switch_to(&mut self, next_fpstate: &FpState) {
-        unsafe { fpstate_switch(self, next_fpstate) }
+        self.save();
+        next_fpstate.restore();
+    }
+
+    fn save(&mut self) {
+        unsafe { fpstate_save(self) }
+    }
+
+    fn restore(&self) {
+        unsafe { fpstate_restore(self) }
+    }
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+fn sve_used() -> bool {
+    crate::cpu::features().contains(crate::cpu::CpuFeatures::SVE)
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl super::super::lazy_fp::FpContext for FpState {
+    fn save(&mut self) {
+        FpState::save(self);
+        if sve_used() {
+            unsafe { sve_save(&mut self.sve) }
+        }
+    }
+
+    fn restore(&self) {
+        FpState::restore(self);
+        // The low 128 bits of Z0..Z31 are V0..V31.
+        if sve_used() {
+            unsafe { sve_restore(&self.sve) }
+        }
+    }
+
+    fn enable() {
+        set_fpen(0b11);
+        if sve_used() {
+            set_zen(0b11);
+        }
+    }
+
+    fn disable() {
+        set_fpen(0b00);
+        if sve_used() {
+            set_zen(0b00);
+        }
+    }
+
+    fn is_enabled() -> bool {
+        use aarch64_cpu::registers::CPACR_EL1;
+        use tock_registers::interfaces::Readable;
+        (CPACR_EL1.get() >> 20) & 0b11 == 0b11
+    }
+}
+
+/// Sets `CPACR_EL1.FPEN`, `0b00` traps FP/SIMD at EL0 and EL1, `0b11`
+/// traps nothing.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+fn set_fpen(fpen: u64) {
+    use aarch64_cpu::{asm::barrier, registers::CPACR_EL1};
+    use tock_registers::interfaces::{Readable, Writeable};
+    CPACR_EL1.set((CPACR_EL1.get() & !(0b11 << 20)) | (fpen << 20));
+    barrier::isb(barrier::SY);
+}
+
+/// Sets `CPACR_EL1.ZEN` like [`set_fpen`] for SVE, and limits the vector
+/// length to [`MAX_SVE_VL`] when enabled.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+fn set_zen(zen: u64) {
+    use aarch64_cpu::{asm::barrier, registers::CPACR_EL1};
+    use tock_registers::interfaces::{Readable, Writeable};
+    CPACR_EL1.set((CPACR_EL1.get() & !(0b11 << 16)) | (zen << 16));
+    barrier::isb(barrier::SY);
+    if zen != 0 {
+        // ZCR_EL1.LEN: the vector length is (LEN + 1) * 128 bits at most.
+        let len = (MAX_SVE_VL / 16 - 1) as u64;
+        unsafe { asm!("msr S3_0_C1_C2_0, {}", "isb", in(reg) len, options(nomem, nostack)) };
     }
 }
 
@@ -86,12 +187,21 @@ impl TaskContext {
     /// It first saves the current task's context from CPU to this place, and then
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
-        #[cfg(feature = "fp_simd")]
+        #[cfg(all(feature = "fp_simd", lazy_fp))]
+        super::super::lazy_fp::switch(&mut self.fp_state, &next_ctx.fp_state);
+        #[cfg(all(feature = "fp_simd", not(lazy_fp)))]
         self.fp_state.switch_to(&next_ctx.fp_state);
         unsafe { context_switch(self, next_ctx) }
     }
 }
 
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl Drop for TaskContext {
+    fn drop(&mut self) {
+        super::super::lazy_fp::release(&self.fp_state);
+    }
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task: &TaskContext) {
     asm!(
@@ -125,10 +235,9 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
 
 #[naked]
 #[cfg(feature = "fp_simd")]
-unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstate: &FpState) {
+unsafe extern "C" fn fpstate_save(_fpstate: &mut FpState) {
     asm!(
         "
-        // save fp/neon context
         mrs     x9, fpcr
         mrs     x10, fpsr
         stp     q0, q1, [x0, 0 * 16]
@@ -149,31 +258,165 @@ unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstat
         stp     q30, q31, [x0, 30 * 16]
         str     x9, [x0, 64 *  8]
         str     x10, [x0, 65 * 8]
+        ret",
+        options(noreturn),
+    )
+}
 
-        // restore fp/neon context
-        ldp     q0, q1, [x1, 0 * 16]
-        ldp     q2, q3, [x1, 2 * 16]
-        ldp     q4, q5, [x1, 4 * 16]
-        ldp     q6, q7, [x1, 6 * 16]
-        ldp     q8, q9, [x1, 8 * 16]
-        ldp     q10, q11, [x1, 10 * 16]
-        ldp     q12, q13, [x1, 12 * 16]
-        ldp     q14, q15, [x1, 14 * 16]
-        ldp     q16, q17, [x1, 16 * 16]
-        ldp     q18, q19, [x1, 18 * 16]
-        ldp     q20, q21, [x1, 20 * 16]
-        ldp     q22, q23, [x1, 22 * 16]
-        ldp     q24, q25, [x1, 24 * 16]
-        ldp     q26, q27, [x1, 26 * 16]
-        ldp     q28, q29, [x1, 28 * 16]
-        ldp     q30, q31, [x1, 30 * 16]
-        ldr     x9, [x1, 64 * 8]
-        ldr     x10, [x1, 65 * 8]
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fpstate_restore(_fpstate: &FpState) {
+    asm!(
+        "
+        ldp     q0, q1, [x0, 0 * 16]
+        ldp     q2, q3, [x0, 2 * 16]
+        ldp     q4, q5, [x0, 4 * 16]
+        ldp     q6, q7, [x0, 6 * 16]
+        ldp     q8, q9, [x0, 8 * 16]
+        ldp     q10, q11, [x0, 10 * 16]
+        ldp     q12, q13, [x0, 12 * 16]
+        ldp     q14, q15, [x0, 14 * 16]
+        ldp     q16, q17, [x0, 16 * 16]
+        ldp     q18, q19, [x0, 18 * 16]
+        ldp     q20, q21, [x0, 20 * 16]
+        ldp     q22, q23, [x0, 22 * 16]
+        ldp     q24, q25, [x0, 24 * 16]
+        ldp     q26, q27, [x0, 26 * 16]
+        ldp     q28, q29, [x0, 28 * 16]
+        ldp     q30, q31, [x0, 30 * 16]
+        ldr     x9, [x0, 64 * 8]
+        ldr     x10, [x0, 65 * 8]
         msr     fpcr, x9
         msr     fpsr, x10
-
         isb
         ret",
         options(noreturn),
     )
 }
+
+#[naked]
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+unsafe extern "C" fn sve_save(_sve: &mut SveState) {
+    asm!(
+        "
+        .arch_extension sve
+        str     z0, [x0, #0, mul vl]
+        str     z1, [x0, #1, mul vl]
+        str     z2, [x0, #2, mul vl]
+        str     z3, [x0, #3, mul vl]
+        str     z4, [x0, #4, mul vl]
+        str     z5, [x0, #5, mul vl]
+        str     z6, [x0, #6, mul vl]
+        str     z7, [x0, #7, mul vl]
+        str     z8, [x0, #8, mul vl]
+        str     z9, [x0, #9, mul vl]
+        str     z10, [x0, #10, mul vl]
+        str     z11, [x0, #11, mul vl]
+        str     z12, [x0, #12, mul vl]
+        str     z13, [x0, #13, mul vl]
+        str     z14, [x0, #14, mul vl]
+        str     z15, [x0, #15, mul vl]
+        str     z16, [x0, #16, mul vl]
+        str     z17, [x0, #17, mul vl]
+        str     z18, [x0, #18, mul vl]
+        str     z19, [x0, #19, mul vl]
+        str     z20, [x0, #20, mul vl]
+        str     z21, [x0, #21, mul vl]
+        str     z22, [x0, #22, mul vl]
+        str     z23, [x0, #23, mul vl]
+        str     z24, [x0, #24, mul vl]
+        str     z25, [x0, #25, mul vl]
+        str     z26, [x0, #26, mul vl]
+        str     z27, [x0, #27, mul vl]
+        str     z28, [x0, #28, mul vl]
+        str     z29, [x0, #29, mul vl]
+        str     z30, [x0, #30, mul vl]
+        str     z31, [x0, #31, mul vl]
+        addvl   x1, x0, #16
+        addvl   x1, x1, #16
+        str     p0, [x1, #0, mul vl]
+        str     p1, [x1, #1, mul vl]
+        str     p2, [x1, #2, mul vl]
+        str     p3, [x1, #3, mul vl]
+        str     p4, [x1, #4, mul vl]
+        str     p5, [x1, #5, mul vl]
+        str     p6, [x1, #6, mul vl]
+        str     p7, [x1, #7, mul vl]
+        str     p8, [x1, #8, mul vl]
+        str     p9, [x1, #9, mul vl]
+        str     p10, [x1, #10, mul vl]
+        str     p11, [x1, #11, mul vl]
+        str     p12, [x1, #12, mul vl]
+        str     p13, [x1, #13, mul vl]
+        str     p14, [x1, #14, mul vl]
+        str     p15, [x1, #15, mul vl]
+        rdffr   p0.b
+        str     p0, [x1, #16, mul vl]
+        ldr     p0, [x1, #0, mul vl]
+        ret",
+        options(noreturn),
+    )
+}
+
+#[naked]
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+unsafe extern "C" fn sve_restore(_sve: &SveState) {
+    asm!(
+        "
+        .arch_extension sve
+        addvl   x1, x0, #16
+        addvl   x1, x1, #16
+        ldr     p0, [x1, #16, mul vl]
+        wrffr   p0.b
+        ldr     p0, [x1, #0, mul vl]
+        ldr     p1, [x1, #1, mul vl]
+        ldr     p2, [x1, #2, mul vl]
+        ldr     p3, [x1, #3, mul vl]
+        ldr     p4, [x1, #4, mul vl]
+        ldr     p5, [x1, #5, mul vl]
+        ldr     p6, [x1, #6, mul vl]
+        ldr     p7, [x1, #7, mul vl]
+        ldr     p8, [x1, #8, mul vl]
+        ldr     p9, [x1, #9, mul vl]
+        ldr     p10, [x1, #10, mul vl]
+        ldr     p11, [x1, #11, mul vl]
+        ldr     p12, [x1, #12, mul vl]
+        ldr     p13, [x1, #13, mul vl]
+        ldr     p14, [x1, #14, mul vl]
+        ldr     p15, [x1, #15, mul vl]
+        ldr     z0, [x0, #0, mul vl]
+        ldr     z1, [x0, #1, mul vl]
+        ldr     z2, [x0, #2, mul vl]
+        ldr     z3, [x0, #3, mul vl]
+        ldr     z4, [x0, #4, mul vl]
+        ldr     z5, [x0, #5, mul vl]
+        ldr     z6, [x0, #6, mul vl]
+        ldr     z7, [x0, #7, mul vl]
+        ldr     z8, [x0, #8, mul vl]
+        ldr     z9, [x0, #9, mul vl]
+        ldr     z10, [x0, #10, mul vl]
+        ldr     z11, [x0, #11, mul vl]
+        ldr     z12, [x0, #12, mul vl]
+        ldr     z13, [x0, #13, mul vl]
+        ldr     z14, [x0, #14, mul vl]
+        ldr     z15, [x0, #15, mul vl]
+        ldr     z16, [x0, #16, mul vl]
+        ldr     z17, [x0, #17, mul vl]
+        ldr     z18, [x0, #18, mul vl]
+        ldr     z19, [x0, #19, mul vl]
+        ldr     z20, [x0, #20, mul vl]
+        ldr     z21, [x0, #21, mul vl]
+        ldr     z22, [x0, #22, mul vl]
+        ldr     z23, [x0, #23, mul vl]
+        ldr     z24, [x0, #24, mul vl]
+        ldr     z25, [x0, #25, mul vl]
+        ldr     z26, [x0, #26, mul vl]
+        ldr     z27, [x0, #27, mul vl]
+        ldr     z28, [x0, #28, mul vl]
+        ldr     z29, [x0, #29, mul vl]
+        ldr     z30, [x0, #30, mul vl]
+        ldr     z31, [x0, #31, mul vl]
+        ret",
+        options(noreturn),
+    )
+}
@@ -1,4 +1,10 @@
 mod context;
+#[cfg(feature = "pmu")]
+pub(crate) mod pmu;
+#[cfg(feature = "ras")]
+pub(crate) mod ras;
+#[cfg(feature = "steal-time")]
+pub(crate) mod steal;
 pub(crate) mod trap;
 
 use core::arch::asm;
@@ -116,6 +122,20 @@ pub fn flush_dcache_line(vaddr: VirtAddr) {
     unsafe { asm!("dc ivac, {0:x}; dsb sy; isb", in(reg) vaddr.as_usize()) };
 }
 
+/// Cleans (writes back) the data cache line (64 bytes) at the given virtual
+/// address
+#[inline]
+pub fn clean_dcache_line(vaddr: VirtAddr) {
+    unsafe { asm!("dc cvac, {0:x}; dsb sy; isb", in(reg) vaddr.as_usize()) };
+}
+
+/// Cleans and invalidates the data cache line (64 bytes) at the given virtual
+/// address
+#[inline]
+pub fn clean_flush_dcache_line(vaddr: VirtAddr) {
+    unsafe { asm!("dc civac, {0:x}; dsb sy; isb", in(reg) vaddr.as_usize()) };
+}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
@@ -0,0 +1,100 @@
+//! The Performance Monitors Extension, PMUv3.
+
+use core::arch::asm;
+
+use crate::pmu::{PmuEvent, NR_EVENTS};
+
+// `PMCR_EL0`: enabled, reset the counters, 64-bit cycle counter.
+const PMCR_E: u64 = 1 << 0;
+const PMCR_P: u64 = 1 << 1;
+const PMCR_C: u64 = 1 << 2;
+const PMCR_LC: u64 = 1 << 6;
+
+/// The cycle counter in `PMCNTENSET_EL0`.
+const CYCLE_COUNTER: u64 = 1 << 31;
+
+// Common events.
+const INST_RETIRED: u64 = 0x08;
+const L1D_CACHE_REFILL: u64 = 0x03;
+const BR_MIS_PRED: u64 = 0x10;
+const LL_CACHE_MISS_RD: u64 = 0x37;
+
+macro_rules! mrs {
+    ($reg:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("mrs {}, ", $reg), out(reg) value) };
+        value
+    }};
+}
+
+macro_rules! msr {
+    ($reg:literal, $value:expr) => {
+        unsafe { asm!(concat!("msr ", $reg, ", {}"), in(reg) $value as u64) }
+    };
+}
+
+/// Whether the common event `event` is implemented, by `PMCEID0_EL0` and
+/// `PMCEID1_EL0`.
+fn has_event(event: u64) -> bool {
+    match event {
+        0x00..=0x1f => mrs!("pmceid0_el0") & (1 << event) != 0,
+        0x20..=0x3f => mrs!("pmceid1_el0") & (1 << (event - 0x20)) != 0,
+        _ => false,
+    }
+}
+
+/// Programs the counters, returns their widths.
+///
+/// The cycles are on the cycle counter, the instructions, the cache misses
+/// and the branch misses on the event counters 0, 1 and 2.
+pub(crate) fn init_percpu() -> [u8; NR_EVENTS] {
+    let mut widths = [0; NR_EVENTS];
+    // `ID_AA64DFR0_EL1.PMUVer`: 0 for none, 0xf for an IMPLEMENTATION DEFINED PMU
+    let pmu_ver = (mrs!("id_aa64dfr0_el1") >> 8) & 0xf;
+    if pmu_ver == 0 || pmu_ver == 0xf {
+        return widths;
+    }
+    let nr_counters = (mrs!("pmcr_el0") >> 11) & 0x1f;
+    let cache_miss = if has_event(LL_CACHE_MISS_RD) {
+        LL_CACHE_MISS_RD
+    } else {
+        L1D_CACHE_REFILL
+    };
+
+    // count at EL0 and EL1
+    msr!("pmccfiltr_el0", 0);
+    let mut enabled = CYCLE_COUNTER;
+    widths[PmuEvent::Cycles as usize] = 64;
+    let events = [
+        (PmuEvent::Instructions, INST_RETIRED),
+        (PmuEvent::CacheMisses, cache_miss),
+        (PmuEvent::BranchMisses, BR_MIS_PRED),
+    ];
+    for (i, &(event, number)) in events.iter().enumerate() {
+        if i as u64 >= nr_counters || !has_event(number) {
+            continue;
+        }
+        match i {
+            0 => msr!("pmevtyper0_el0", number),
+            1 => msr!("pmevtyper1_el0", number),
+            _ => msr!("pmevtyper2_el0", number),
+        }
+        enabled |= 1 << i;
+        widths[event as usize] = 32;
+    }
+    msr!("pmcntenset_el0", enabled);
+    let pmcr = mrs!("pmcr_el0");
+    msr!("pmcr_el0", pmcr | PMCR_E | PMCR_P | PMCR_C | PMCR_LC);
+    unsafe { asm!("isb") };
+    widths
+}
+
+/// Reads the counter of `event`, which is counted.
+pub(crate) fn read(event: PmuEvent) -> u64 {
+    match event {
+        PmuEvent::Cycles => mrs!("pmccntr_el0"),
+        PmuEvent::Instructions => mrs!("pmevcntr0_el0"),
+        PmuEvent::CacheMisses => mrs!("pmevcntr1_el0"),
+        PmuEvent::BranchMisses => mrs!("pmevcntr2_el0"),
+    }
+}
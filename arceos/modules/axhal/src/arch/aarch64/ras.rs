@@ -0,0 +1,135 @@
+//! The SErrors and the external aborts, with the error records of the RAS
+//! extension if it is implemented.
+
+use core::arch::asm;
+
+use aarch64_cpu::registers::{ESR_EL1, FAR_EL1};
+use tock_registers::interfaces::Readable;
+
+use super::TrapFrame;
+use crate::ras::{self, HwError, HwErrorSeverity};
+
+macro_rules! mrs {
+    ($reg:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("mrs {}, ", $reg), out(reg) value) };
+        value
+    }};
+}
+
+macro_rules! msr {
+    ($reg:literal, $value:expr) => {
+        unsafe { asm!(concat!("msr ", $reg, ", {}"), in(reg) $value as u64) }
+    };
+}
+
+// `ERXSTATUS_EL1`
+const ERR_STATUS_AV: u64 = 1 << 31;
+const ERR_STATUS_V: u64 = 1 << 30;
+
+/// The fault status code of an asynchronous SError.
+const DFSC_ASYNC_SERROR: u64 = 0x11;
+
+/// Unmasks the SErrors.
+pub(crate) fn init_percpu() {
+    unsafe { asm!("msr daifclr, #4") };
+}
+
+/// Whether the RAS extension is implemented, by `ID_AA64PFR0_EL1.RAS`.
+fn has_ras() -> bool {
+    (mrs!("id_aa64pfr0_el1") >> 28) & 0xf != 0
+}
+
+/// Reads and clears the first valid error record, returns its index, its
+/// status and its address.
+fn take_error_record() -> Option<(usize, u64, Option<usize>)> {
+    if !has_ras() {
+        return None;
+    }
+    // the error record registers by their encodings, the names need `+ras`:
+    // `ERRIDR_EL1`, `ERRSELR_EL1`, `ERXSTATUS_EL1` and `ERXADDR_EL1`
+    let nr_records = mrs!("s3_0_c5_c3_0") & 0xffff;
+    for i in 0..nr_records {
+        msr!("s3_0_c5_c3_1", i);
+        unsafe { asm!("isb") };
+        let status = mrs!("s3_0_c5_c4_2");
+        if status & ERR_STATUS_V == 0 {
+            continue;
+        }
+        let addr = (status & ERR_STATUS_AV != 0)
+            .then(|| (mrs!("s3_0_c5_c4_3") & ((1 << 56) - 1)) as usize);
+        // the status bits are cleared by writing 1
+        msr!("s3_0_c5_c4_2", status);
+        return Some((i as usize, status, addr));
+    }
+    None
+}
+
+/// The severity of an SError by `ESR_EL1.ISS.AET`.
+fn serror_severity(iss: u64) -> (HwErrorSeverity, &'static str) {
+    // an IMPLEMENTATION DEFINED syndrome
+    if iss & (1 << 24) != 0 || iss & 0x3f != DFSC_ASYNC_SERROR {
+        return (HwErrorSeverity::Fatal, "SError");
+    }
+    match (iss >> 10) & 0b111 {
+        0b000 => (HwErrorSeverity::Fatal, "uncontainable SError"),
+        0b001 => (HwErrorSeverity::Fatal, "unrecoverable SError"),
+        0b010 => (HwErrorSeverity::Recoverable, "restartable SError"),
+        0b011 => (HwErrorSeverity::Recoverable, "recoverable SError"),
+        0b110 => (HwErrorSeverity::Corrected, "corrected SError"),
+        _ => (HwErrorSeverity::Fatal, "SError"),
+    }
+}
+
+/// Handles an SError.
+pub(crate) fn handle_serror(tf: &TrapFrame, from_user: bool) {
+    let esr = ESR_EL1.get();
+    let (severity, kind) = serror_severity(esr & 0x1ff_ffff);
+    let record = take_error_record();
+    let err = HwError {
+        severity,
+        kind,
+        source: record.map_or(0, |(i, ..)| i),
+        syndrome: esr,
+        addr: record.and_then(|(.., addr)| addr),
+        vaddr: None,
+        pc: tf.elr as usize,
+        from_user,
+    };
+    if let Some((i, status, _)) = record {
+        debug!("error record {}: status {:#x}", i, status);
+    }
+    let action = ras::report(&err);
+    ras::take_action(tf, action, &err);
+}
+
+/// Whether the fault status code of an abort is a synchronous external
+/// abort or a parity or ECC error, also on a translation table walk.
+pub(crate) fn is_external_abort(iss: u64) -> bool {
+    matches!(iss & 0x3f, 0x10 | 0x14..=0x18 | 0x1c..=0x1f)
+}
+
+/// Handles a synchronous external abort on an instruction fetch or a data
+/// access.
+pub(crate) fn handle_external_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+    // `ISS.SET` with the RAS extension, for the aborts not on a table walk
+    let uncontainable = has_ras() && iss & 0x3f == 0x10 && (iss >> 11) & 0b11 == 0b10;
+    let severity = if is_user && !uncontainable {
+        HwErrorSeverity::Recoverable
+    } else {
+        HwErrorSeverity::Fatal
+    };
+    let record = take_error_record();
+    let err = HwError {
+        severity,
+        kind: "synchronous external abort",
+        source: record.map_or(0, |(i, ..)| i),
+        syndrome: ESR_EL1.get(),
+        addr: record.and_then(|(.., addr)| addr),
+        vaddr: Some(FAR_EL1.get() as usize),
+        pc: tf.elr as usize,
+        from_user: is_user,
+    };
+    let action = ras::report(&err);
+    ras::take_action(tf, action, &err);
+}
@@ -30,6 +30,12 @@ enum TrapSource {
 
 #[no_mangle]
 fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
+    #[cfg(feature = "ras")]
+    if matches!(kind, TrapKind::SError) {
+        let from_user = matches!(source, TrapSource::LowerAArch64 | TrapSource::LowerAArch32);
+        super::ras::handle_serror(tf, from_user);
+        return;
+    }
     panic!(
         "Invalid exception {:?} from {:?}:\n{:#x?}",
         kind, source, tf
@@ -37,11 +43,15 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &TrapFrame) {
-    handle_trap!(IRQ, 0);
+fn handle_irq_exception(tf: &TrapFrame) {
+    crate::trap::handle_irq(tf, 0);
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+    #[cfg(feature = "ras")]
+    if super::ras::is_external_abort(iss) {
+        return super::ras::handle_external_abort(tf, iss, is_user);
+    }
     let mut access_flags = MappingFlags::EXECUTE;
     if is_user {
         access_flags |= MappingFlags::USER;
@@ -65,6 +75,10 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
 }
 
 fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+    #[cfg(feature = "ras")]
+    if super::ras::is_external_abort(iss) {
+        return super::ras::handle_external_abort(tf, iss, is_user);
+    }
     let wnr = (iss & (1 << 6)) != 0; // WnR: Write not Read
     let cm = (iss & (1 << 8)) != 0; // CM: Cache maintenance
     let mut access_flags = if wnr & !cm {
@@ -97,6 +111,13 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
 fn handle_sync_exception(tf: &mut TrapFrame) {
     let esr = ESR_EL1.extract();
     let iss = esr.read(ESR_EL1::ISS);
+    // Access to SIMD or floating-point registers trapped by CPACR_EL1.FPEN,
+    // or to SVE registers by CPACR_EL1.ZEN.
+    #[cfg(all(feature = "fp_simd", lazy_fp))]
+    if matches!(esr.read(ESR_EL1::EC), 0b00_0111 | 0b01_1001) {
+        super::super::lazy_fp::handle_trap::<super::FpState>();
+        return;
+    }
     match esr.read_as_enum(ESR_EL1::EC) {
         Some(ESR_EL1::EC::Value::SVC64) => {
             warn!("No syscall is supported currently!");
@@ -0,0 +1,86 @@
+//! Lazy switching of the FP/SIMD states.
+//!
+//! The FP/SIMD unit is disabled on context switches, and the first FP/SIMD
+//! instruction of the next task traps. Only then the registers are saved to
+//! the task that last used them (the owner) and the state of the current
+//! task is restored. Tasks not using FP/SIMD do not pay for it, and a task
+//! alone in using it keeps its state in the registers.
+//!
+//! With SMP, a task may migrate with its state in the registers of another
+//! CPU, so the owner saves its state when switched out instead.
+
+/// The FP/SIMD state of a task, and the control of the unit.
+pub(crate) trait FpContext {
+    /// Saves the registers to `self`.
+    fn save(&mut self);
+    /// Restores the registers from `self`.
+    fn restore(&self);
+    /// Enables the FP/SIMD unit.
+    fn enable();
+    /// Disables the FP/SIMD unit, its instructions then trap.
+    fn disable();
+    /// Whether the FP/SIMD unit is enabled.
+    fn is_enabled() -> bool;
+}
+
+/// The state whose content is in the registers, null if none.
+#[percpu::def_percpu]
+static FP_OWNER: usize = 0;
+
+/// The state of the task running on the CPU, null before the first switch.
+#[percpu::def_percpu]
+static FP_CURRENT: usize = 0;
+
+/// Switches from the task of `prev` to the task of `next`, with IRQs
+/// disabled.
+pub(crate) fn switch<S: FpContext>(prev: &mut S, next: &S) {
+    let prev_ptr = prev as *mut S as usize;
+    let next_ptr = next as *const S as usize;
+    unsafe {
+        // Enabled: `prev` used the unit since it was switched in.
+        if S::is_enabled() {
+            FP_OWNER.write_current_raw(prev_ptr);
+        }
+        if cfg!(feature = "smp") && FP_OWNER.read_current_raw() == prev_ptr {
+            prev.save();
+            FP_OWNER.write_current_raw(0);
+        }
+        FP_CURRENT.write_current_raw(next_ptr);
+        if FP_OWNER.read_current_raw() == next_ptr {
+            S::enable();
+        } else {
+            S::disable();
+        }
+    }
+}
+
+/// Handles the trap of an FP/SIMD instruction while the unit is disabled.
+pub(crate) fn handle_trap<S: FpContext>() {
+    S::enable();
+    unsafe {
+        let owner = FP_OWNER.read_current_raw();
+        let current = FP_CURRENT.read_current_raw();
+        if owner != current {
+            if owner != 0 {
+                (*(owner as *mut S)).save();
+            }
+            if current != 0 {
+                (*(current as *const S)).restore();
+            }
+            FP_OWNER.write_current_raw(current);
+        }
+    }
+}
+
+/// Forgets `state` before it is freed.
+pub(crate) fn release<S: FpContext>(state: &S) {
+    let ptr = state as *const S as usize;
+    unsafe {
+        if FP_OWNER.read_current_raw() == ptr {
+            FP_OWNER.write_current_raw(0);
+        }
+        if FP_CURRENT.read_current_raw() == ptr {
+            FP_CURRENT.write_current_raw(0);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 //! Architecture-specific types and operations.
 
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+mod lazy_fp;
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         mod x86_64;
@@ -3,6 +3,11 @@ use memory_addr::VirtAddr;
 #[cfg(feature = "uspace")]
 use memory_addr::PhysAddr;
 
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+use riscv::register::sstatus::{self, FS};
+
 include_asm_marcos!();
 
 /// General registers of RISC-V.
@@ -87,6 +92,268 @@ impl TrapFrame {
     }
 }
 
+/// The largest `vlenb` whose vector registers are switched, for a VLEN of
+/// 512 bits. The V extension of CPUs with longer vectors is left disabled.
+pub const MAX_VLENB: usize = 64;
+
+/// FP registers, of the F and D extensions, and the vector registers of the
+/// V extension.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FpState {
+    /// `f0`..`f31`.
+    pub fp: [u64; 32],
+    /// Floating-point control and status register (`fcsr`).
+    pub fcsr: usize,
+    /// The vector registers, if the V extension is used.
+    pub vector: VectorState,
+}
+
+/// Vector registers, of the V extension.
+#[repr(C, align(16))]
+#[derive(Debug)]
+pub struct VectorState {
+    /// `v0`..`v31`, `vlenb` bytes each.
+    pub v: [u8; 32 * MAX_VLENB],
+    /// Vector start index (`vstart`).
+    pub vstart: usize,
+    /// Vector data type (`vtype`).
+    pub vtype: usize,
+    /// Vector length (`vl`).
+    pub vl: usize,
+    /// Vector control and status register (`vcsr`).
+    pub vcsr: usize,
+}
+
+impl Default for VectorState {
+    fn default() -> Self {
+        Self {
+            v: [0; 32 * MAX_VLENB],
+            vstart: 0,
+            vtype: 0,
+            vl: 0,
+            vcsr: 0,
+        }
+    }
+}
+
+/// The VS field of `sstatus`, the state of the vector unit.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+const SSTATUS_VS: usize = 0b11 << 9;
+/// `Clean` in the VS field.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+const SSTATUS_VS_CLEAN: usize = 0b10 << 9;
+
+/// The `vlenb` of the CPU if its vector registers are switched, 0 if not.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+fn vlenb() -> usize {
+    static VLENB: AtomicUsize = AtomicUsize::new(usize::MAX);
+    let vlenb = VLENB.load(Ordering::Relaxed);
+    if vlenb != usize::MAX {
+        return vlenb;
+    }
+    let mut vlenb = 0;
+    if crate::cpu::features().contains(crate::cpu::CpuFeatures::RV_V) {
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrs sstatus, {vs}",
+                "csrr {vlenb}, vlenb",
+                "csrc sstatus, {vs}",
+                ".option pop",
+                vs = in(reg) SSTATUS_VS_CLEAN,
+                vlenb = out(reg) vlenb,
+                options(nomem, nostack),
+            )
+        };
+        if vlenb > MAX_VLENB {
+            warn!(
+                "VLEN of {} bits not supported, the V extension is disabled",
+                vlenb * 8
+            );
+            vlenb = 0;
+        }
+    }
+    VLENB.store(vlenb, Ordering::Relaxed);
+    vlenb
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl VectorState {
+    fn save(&mut self, vlenb: usize) {
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrr {vstart}, vstart",
+                "csrr {vtype}, vtype",
+                "csrr {vl}, vl",
+                "csrr {vcsr}, vcsr",
+                "csrw vstart, zero",
+                "vs8r.v v0, ({p})",
+                "add {p}, {p}, {stride}",
+                "vs8r.v v8, ({p})",
+                "add {p}, {p}, {stride}",
+                "vs8r.v v16, ({p})",
+                "add {p}, {p}, {stride}",
+                "vs8r.v v24, ({p})",
+                ".option pop",
+                p = inout(reg) self.v.as_mut_ptr() => _,
+                stride = in(reg) 8 * vlenb,
+                vstart = out(reg) self.vstart,
+                vtype = out(reg) self.vtype,
+                vl = out(reg) self.vl,
+                vcsr = out(reg) self.vcsr,
+                options(nostack),
+            );
+        }
+    }
+
+    fn restore(&self, vlenb: usize) {
+        // The whole register loads ignore `vtype` and `vl`, which are
+        // restored after them, and `vstart` last.
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrw vstart, zero",
+                "vl8re8.v v0, ({p})",
+                "add {p}, {p}, {stride}",
+                "vl8re8.v v8, ({p})",
+                "add {p}, {p}, {stride}",
+                "vl8re8.v v16, ({p})",
+                "add {p}, {p}, {stride}",
+                "vl8re8.v v24, ({p})",
+                "vsetvl zero, {vl}, {vtype}",
+                "csrw vcsr, {vcsr}",
+                "csrw vstart, {vstart}",
+                ".option pop",
+                p = inout(reg) self.v.as_ptr() => _,
+                stride = in(reg) 8 * vlenb,
+                vstart = in(reg) self.vstart,
+                vtype = in(reg) self.vtype,
+                vl = in(reg) self.vl,
+                vcsr = in(reg) self.vcsr,
+                options(nostack),
+            );
+        }
+    }
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl super::super::lazy_fp::FpContext for FpState {
+    fn save(&mut self) {
+        unsafe {
+            asm!(
+                "fsd f0, 0 * 8({0})",
+                "fsd f1, 1 * 8({0})",
+                "fsd f2, 2 * 8({0})",
+                "fsd f3, 3 * 8({0})",
+                "fsd f4, 4 * 8({0})",
+                "fsd f5, 5 * 8({0})",
+                "fsd f6, 6 * 8({0})",
+                "fsd f7, 7 * 8({0})",
+                "fsd f8, 8 * 8({0})",
+                "fsd f9, 9 * 8({0})",
+                "fsd f10, 10 * 8({0})",
+                "fsd f11, 11 * 8({0})",
+                "fsd f12, 12 * 8({0})",
+                "fsd f13, 13 * 8({0})",
+                "fsd f14, 14 * 8({0})",
+                "fsd f15, 15 * 8({0})",
+                "fsd f16, 16 * 8({0})",
+                "fsd f17, 17 * 8({0})",
+                "fsd f18, 18 * 8({0})",
+                "fsd f19, 19 * 8({0})",
+                "fsd f20, 20 * 8({0})",
+                "fsd f21, 21 * 8({0})",
+                "fsd f22, 22 * 8({0})",
+                "fsd f23, 23 * 8({0})",
+                "fsd f24, 24 * 8({0})",
+                "fsd f25, 25 * 8({0})",
+                "fsd f26, 26 * 8({0})",
+                "fsd f27, 27 * 8({0})",
+                "fsd f28, 28 * 8({0})",
+                "fsd f29, 29 * 8({0})",
+                "fsd f30, 30 * 8({0})",
+                "fsd f31, 31 * 8({0})",
+                in(reg) self.fp.as_mut_ptr(),
+                options(nostack),
+            );
+            asm!("frcsr {}", out(reg) self.fcsr, options(nomem, nostack));
+        }
+        let vlenb = vlenb();
+        if vlenb != 0 {
+            self.vector.save(vlenb);
+        }
+    }
+
+    fn restore(&self) {
+        unsafe {
+            asm!(
+                "fld f0, 0 * 8({0})",
+                "fld f1, 1 * 8({0})",
+                "fld f2, 2 * 8({0})",
+                "fld f3, 3 * 8({0})",
+                "fld f4, 4 * 8({0})",
+                "fld f5, 5 * 8({0})",
+                "fld f6, 6 * 8({0})",
+                "fld f7, 7 * 8({0})",
+                "fld f8, 8 * 8({0})",
+                "fld f9, 9 * 8({0})",
+                "fld f10, 10 * 8({0})",
+                "fld f11, 11 * 8({0})",
+                "fld f12, 12 * 8({0})",
+                "fld f13, 13 * 8({0})",
+                "fld f14, 14 * 8({0})",
+                "fld f15, 15 * 8({0})",
+                "fld f16, 16 * 8({0})",
+                "fld f17, 17 * 8({0})",
+                "fld f18, 18 * 8({0})",
+                "fld f19, 19 * 8({0})",
+                "fld f20, 20 * 8({0})",
+                "fld f21, 21 * 8({0})",
+                "fld f22, 22 * 8({0})",
+                "fld f23, 23 * 8({0})",
+                "fld f24, 24 * 8({0})",
+                "fld f25, 25 * 8({0})",
+                "fld f26, 26 * 8({0})",
+                "fld f27, 27 * 8({0})",
+                "fld f28, 28 * 8({0})",
+                "fld f29, 29 * 8({0})",
+                "fld f30, 30 * 8({0})",
+                "fld f31, 31 * 8({0})",
+                in(reg) self.fp.as_ptr(),
+                options(nostack),
+            );
+            asm!("fscsr {}", in(reg) self.fcsr, options(nomem, nostack));
+        }
+        let vlenb = vlenb();
+        if vlenb != 0 {
+            self.vector.restore(vlenb);
+        }
+    }
+
+    fn enable() {
+        unsafe { sstatus::set_fs(FS::Clean) }
+        if vlenb() != 0 {
+            unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_VS_CLEAN, options(nomem, nostack)) };
+        }
+    }
+
+    fn disable() {
+        unsafe {
+            sstatus::set_fs(FS::Off);
+            asm!("csrc sstatus, {}", in(reg) SSTATUS_VS, options(nomem, nostack));
+        }
+    }
+
+    fn is_enabled() -> bool {
+        sstatus::read().fs() != FS::Off
+    }
+}
+
 /// Saved hardware states of a task.
 ///
 /// The context usually includes:
@@ -123,7 +390,9 @@ pub struct TaskContext {
     /// The `satp` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub satp: PhysAddr,
-    // TODO: FP states
+    /// The FP states.
+    #[cfg(feature = "fp_simd")]
+    pub fp_state: FpState,
 }
 
 impl TaskContext {
@@ -177,10 +446,16 @@ impl TaskContext {
                 super::write_page_table_root(next_ctx.satp);
             }
         }
-        unsafe {
-            // TODO: switch FP states
-            context_switch(self, next_ctx)
-        }
+        #[cfg(all(feature = "fp_simd", lazy_fp))]
+        super::super::lazy_fp::switch(&mut self.fp_state, &next_ctx.fp_state);
+        unsafe { context_switch(self, next_ctx) }
+    }
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl Drop for TaskContext {
+    fn drop(&mut self) {
+        super::super::lazy_fp::release(&self.fp_state);
     }
 }
 
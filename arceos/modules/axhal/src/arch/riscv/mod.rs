@@ -4,6 +4,14 @@ mod macros;
 mod context;
 mod trap;
 
+#[cfg(feature = "pmu")]
+pub(crate) mod pmu;
+#[cfg(feature = "ras")]
+pub(crate) mod ras;
+#[cfg(feature = "steal-time")]
+pub(crate) mod steal;
+pub mod sbi;
+
 use memory_addr::{PhysAddr, VirtAddr};
 use riscv::asm;
 use riscv::register::{satp, sstatus, stvec};
@@ -82,6 +90,13 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the instruction cache of the current hart, after the code was
+/// written.
+#[inline]
+pub fn flush_icache_all() {
+    unsafe { core::arch::asm!("fence.i") };
+}
+
 /// Writes Supervisor Trap Vector Base Address Register (`stvec`).
 #[inline]
 pub fn set_trap_vector_base(stvec: usize) {
@@ -0,0 +1,91 @@
+//! The hardware performance counters, configured by the SBI PMU extension.
+
+use crate::arch::sbi::{self, pmu};
+use crate::cpu::{features, CpuFeatures};
+use crate::pmu::{PmuEvent, NR_EVENTS};
+
+const CSR_CYCLE: u16 = 0xc00;
+const CSR_INSTRET: u16 = 0xc02;
+
+/// The SBI PMU event of each event.
+const SBI_EVENTS: [pmu::HardwareEvent; NR_EVENTS] = [
+    pmu::HardwareEvent::CpuCycles,
+    pmu::HardwareEvent::Instructions,
+    pmu::HardwareEvent::CacheMisses,
+    pmu::HardwareEvent::BranchMisses,
+];
+
+/// The CSRs of the counters of the events on this CPU, 16 bits each, 0 if
+/// not counted.
+#[percpu::def_percpu]
+static COUNTER_CSRS: u64 = 0;
+
+/// Configures and starts the counters, returns their widths.
+///
+/// Without the SBI PMU extension, the cycles and the instructions are read
+/// from `cycle` and `instret`.
+pub(crate) fn init_percpu() -> [u8; NR_EVENTS] {
+    let mut widths = [0; NR_EVENTS];
+    let mut csrs = [0u16; NR_EVENTS];
+    let num_counters = if sbi::base::probe_extension(sbi::EID_PMU) {
+        pmu::num_counters().unwrap_or(0).min(usize::BITS as usize)
+    } else {
+        0
+    };
+    if num_counters == 0 {
+        csrs[PmuEvent::Cycles as usize] = CSR_CYCLE;
+        csrs[PmuEvent::Instructions as usize] = CSR_INSTRET;
+        widths[PmuEvent::Cycles as usize] = 64;
+        widths[PmuEvent::Instructions as usize] = 64;
+    } else {
+        let mask = usize::MAX >> (usize::BITS as usize - num_counters);
+        let mut flags = pmu::CFG_FLAG_CLEAR_VALUE | pmu::CFG_FLAG_AUTO_START;
+        if features().contains(CpuFeatures::RV_SSCOFPMF) {
+            flags |= pmu::CFG_FLAG_SET_MINH;
+        }
+        for event in PmuEvent::ALL {
+            let sbi_event = SBI_EVENTS[event as usize].event_idx();
+            let Ok(idx) = pmu::counter_config_matching(0, mask, flags, sbi_event, 0) else {
+                continue;
+            };
+            if let Ok(pmu::CounterInfo::Hardware { csr, width }) = pmu::counter_get_info(idx) {
+                csrs[event as usize] = csr;
+                widths[event as usize] = width;
+            }
+        }
+    }
+    let packed = csrs
+        .iter()
+        .enumerate()
+        .fold(0, |packed, (i, &csr)| packed | (csr as u64) << (i * 16));
+    unsafe { COUNTER_CSRS.write_current_raw(packed) };
+    widths
+}
+
+/// Reads the counter CSR `csr`, one of `cycle`, `time`, `instret` and
+/// `hpmcounter3` to `hpmcounter31`.
+fn read_counter_csr(csr: u16) -> u64 {
+    macro_rules! csrr {
+        ($($csr:literal)*) => {
+            match csr {
+                $($csr => {
+                    let value: usize;
+                    unsafe { core::arch::asm!(concat!("csrr {}, ", stringify!($csr)), out(reg) value) };
+                    value as u64
+                })*
+                _ => 0,
+            }
+        };
+    }
+    csrr!(
+        0xc00 0xc01 0xc02 0xc03 0xc04 0xc05 0xc06 0xc07 0xc08 0xc09 0xc0a 0xc0b 0xc0c 0xc0d 0xc0e
+        0xc0f 0xc10 0xc11 0xc12 0xc13 0xc14 0xc15 0xc16 0xc17 0xc18 0xc19 0xc1a 0xc1b 0xc1c 0xc1d
+        0xc1e 0xc1f
+    )
+}
+
+/// Reads the counter of `event`, which is counted.
+pub(crate) fn read(event: PmuEvent) -> u64 {
+    let packed = unsafe { COUNTER_CSRS.read_current_raw() };
+    read_counter_csr((packed >> (event as usize * 16)) as u16)
+}
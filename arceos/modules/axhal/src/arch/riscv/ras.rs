@@ -0,0 +1,38 @@
+//! The hardware error exception of the privileged specification.
+
+use riscv::register::{scause, stval};
+
+use super::TrapFrame;
+use crate::ras::{self, HwError, HwErrorSeverity};
+
+/// The exception code of a hardware error, from version 1.13 of the
+/// privileged specification.
+pub(crate) const HARDWARE_ERROR: usize = 19;
+
+/// The hardware errors are taken as the exceptions, nothing to enable in
+/// S-mode.
+pub(crate) fn init_percpu() {}
+
+/// Handles a hardware error exception.
+///
+/// Its cause is not reported in S-mode, it is recoverable by killing the
+/// task if it is in the user space, and fatal in the kernel.
+pub(crate) fn handle_hardware_error(tf: &TrapFrame, from_user: bool) {
+    let vaddr = stval::read();
+    let err = HwError {
+        severity: if from_user {
+            HwErrorSeverity::Recoverable
+        } else {
+            HwErrorSeverity::Fatal
+        },
+        kind: "hardware error exception",
+        source: 0,
+        syndrome: scause::read().bits() as u64,
+        addr: None,
+        vaddr: (vaddr != 0).then_some(vaddr),
+        pc: tf.sepc,
+        from_user,
+    };
+    let action = ras::report(&err);
+    ras::take_action(tf, action, &err);
+}
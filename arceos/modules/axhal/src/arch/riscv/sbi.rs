@@ -0,0 +1,554 @@
+//! Client of the RISC-V Supervisor Binary Interface (SBI), the calls to the
+//! firmware (SEE) in M-mode.
+//!
+//! Each SBI extension has its module with typed functions:
+//! - [`base`]: the version of the SBI and the probing of the extensions.
+//! - [`time`]: the timer.
+//! - [`ipi`]: the inter-processor interrupts.
+//! - [`hsm`]: Hart State Management, to start, stop and suspend the harts.
+//! - [`srst`]: System Reset, to shut down and reboot.
+//! - [`pmu`]: Performance Monitoring Unit, the hardware performance counters.
+//! - [`dbcn`]: Debug Console.
+//! - [`sta`]: Steal-time Accounting.
+//!
+//! The functions of an extension fail with [`SbiError::NotSupported`] if the
+//! firmware does not implement it, see [`base::probe_extension`].
+//!
+//! The legacy SBI 0.1 console is only used by [`console_putchar`] and
+//! [`console_getchar`] on the firmware without the Debug Console.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The error code of an SBI call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbiError {
+    /// Failed.
+    Failed,
+    /// Not supported.
+    NotSupported,
+    /// Invalid parameters.
+    InvalidParam,
+    /// Denied or not allowed.
+    Denied,
+    /// Invalid address.
+    InvalidAddress,
+    /// Already available.
+    AlreadyAvailable,
+    /// Already started.
+    AlreadyStarted,
+    /// Already stopped.
+    AlreadyStopped,
+    /// Shared memory not available.
+    NoShmem,
+    /// An error code not defined by the specification.
+    Unknown(isize),
+}
+
+impl SbiError {
+    fn from_code(code: isize) -> Self {
+        match code {
+            -1 => Self::Failed,
+            -2 => Self::NotSupported,
+            -3 => Self::InvalidParam,
+            -4 => Self::Denied,
+            -5 => Self::InvalidAddress,
+            -6 => Self::AlreadyAvailable,
+            -7 => Self::AlreadyStarted,
+            -8 => Self::AlreadyStopped,
+            -9 => Self::NoShmem,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// The result of an SBI call: its value, or its error code.
+pub type SbiResult<T = usize> = Result<T, SbiError>;
+
+const EID_LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+const EID_LEGACY_CONSOLE_GETCHAR: usize = 0x02;
+/// The ID of the Base extension.
+pub const EID_BASE: usize = 0x10;
+/// The ID of the Timer extension.
+pub const EID_TIME: usize = 0x5449_4d45;
+/// The ID of the IPI extension.
+pub const EID_IPI: usize = 0x73_5049;
+/// The ID of the Hart State Management extension.
+pub const EID_HSM: usize = 0x48_534d;
+/// The ID of the System Reset extension.
+pub const EID_SRST: usize = 0x5352_5354;
+/// The ID of the Performance Monitoring Unit extension.
+pub const EID_PMU: usize = 0x50_4d55;
+/// The ID of the Debug Console extension.
+pub const EID_DBCN: usize = 0x4442_434e;
+/// The ID of the Steal-time Accounting extension.
+pub const EID_STA: usize = 0x53_5441;
+
+#[inline(always)]
+fn sbi_call(eid: usize, fid: usize, args: [usize; 6]) -> SbiResult {
+    let (error, value): (isize, usize);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    match error {
+        0 => Ok(value),
+        code => Err(SbiError::from_code(code)),
+    }
+}
+
+/// Calls a legacy SBI 0.1 extension, which returns its value in `a0`.
+fn legacy_call(eid: usize, arg0: usize) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") arg0 => ret,
+            in("a7") eid,
+        );
+    }
+    ret
+}
+
+fn call0(eid: usize, fid: usize) -> SbiResult {
+    sbi_call(eid, fid, [0; 6])
+}
+
+fn call1(eid: usize, fid: usize, a0: usize) -> SbiResult {
+    sbi_call(eid, fid, [a0, 0, 0, 0, 0, 0])
+}
+
+fn call2(eid: usize, fid: usize, a0: usize, a1: usize) -> SbiResult {
+    sbi_call(eid, fid, [a0, a1, 0, 0, 0, 0])
+}
+
+fn call3(eid: usize, fid: usize, a0: usize, a1: usize, a2: usize) -> SbiResult {
+    sbi_call(eid, fid, [a0, a1, a2, 0, 0, 0])
+}
+
+/// Splits a 64-bit value into the two arguments of an SBI call on RV32, or
+/// the first one on RV64.
+fn split_u64(value: u64) -> (usize, usize) {
+    #[cfg(target_pointer_width = "32")]
+    return (value as usize, (value >> 32) as usize);
+    #[cfg(target_pointer_width = "64")]
+    return (value as usize, 0);
+}
+
+/// Splits the physical address of a buffer into its low and high parts.
+fn split_paddr(buf: *const u8) -> (usize, usize) {
+    let paddr = crate::mem::virt_to_phys((buf as usize).into()).as_usize();
+    split_u64(paddr as u64)
+}
+
+/// The Base extension.
+pub mod base {
+    use super::{call0, call1, EID_BASE};
+
+    /// Returns the version of the SBI specification, as `(major, minor)`.
+    pub fn spec_version() -> (usize, usize) {
+        // the Base extension is always available
+        let version = call0(EID_BASE, 0).unwrap_or(0);
+        ((version >> 24) & 0x7f, version & 0xff_ffff)
+    }
+
+    /// Returns the ID of the SBI implementation, e.g. 1 for OpenSBI.
+    pub fn impl_id() -> usize {
+        call0(EID_BASE, 1).unwrap_or(0)
+    }
+
+    /// Returns the version of the SBI implementation.
+    pub fn impl_version() -> usize {
+        call0(EID_BASE, 2).unwrap_or(0)
+    }
+
+    /// Returns whether the extension `eid` is available.
+    pub fn probe_extension(eid: usize) -> bool {
+        matches!(call1(EID_BASE, 3, eid), Ok(v) if v != 0)
+    }
+}
+
+/// The Timer extension.
+pub mod time {
+    use super::{sbi_call, split_u64, SbiResult, EID_TIME};
+
+    /// Programs the timer interrupt at the absolute time `stime_value`, in
+    /// ticks. It also clears the pending timer interrupt.
+    pub fn set_timer(stime_value: u64) -> SbiResult<()> {
+        let (lo, hi) = split_u64(stime_value);
+        sbi_call(EID_TIME, 0, [lo, hi, 0, 0, 0, 0]).map(|_| ())
+    }
+}
+
+/// The IPI extension.
+pub mod ipi {
+    use super::{call2, SbiResult, EID_IPI};
+
+    /// Raises the supervisor software interrupt of the harts in `hart_mask`,
+    /// whose bit `i` is the hart `hart_mask_base + i`.
+    pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiResult<()> {
+        call2(EID_IPI, 0, hart_mask, hart_mask_base).map(|_| ())
+    }
+}
+
+/// The Hart State Management extension.
+pub mod hsm {
+    use super::{call0, call1, call3, SbiError, SbiResult, EID_HSM};
+
+    /// The state of a hart.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(usize)]
+    pub enum HartState {
+        /// Running.
+        Started = 0,
+        /// Stopped, or not started yet.
+        Stopped = 1,
+        /// Requested to start.
+        StartPending = 2,
+        /// Requested to stop.
+        StopPending = 3,
+        /// Suspended.
+        Suspended = 4,
+        /// Requested to suspend.
+        SuspendPending = 5,
+        /// Requested to resume.
+        ResumePending = 6,
+    }
+
+    /// The retentive suspend of the platform: the hart resumes after the
+    /// call on an interrupt, with its state kept.
+    pub const SUSPEND_DEFAULT_RETENTIVE: u32 = 0;
+    /// The non-retentive suspend of the platform: the hart resumes at the
+    /// given address as if started, with its state lost.
+    pub const SUSPEND_DEFAULT_NON_RETENTIVE: u32 = 0x8000_0000;
+
+    /// Starts the hart `hartid` at the physical address `start_addr` in
+    /// S-mode, with its hart ID in `a0` and `opaque` in `a1`, and the MMU
+    /// disabled.
+    pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> SbiResult<()> {
+        call3(EID_HSM, 0, hartid, start_addr, opaque).map(|_| ())
+    }
+
+    /// Stops the calling hart, to be started again by [`hart_start`]. It
+    /// only returns on failure.
+    pub fn hart_stop() -> SbiError {
+        match call0(EID_HSM, 1) {
+            Ok(_) => SbiError::Failed,
+            Err(e) => e,
+        }
+    }
+
+    /// Returns the state of the hart `hartid`.
+    pub fn hart_get_status(hartid: usize) -> SbiResult<HartState> {
+        Ok(match call1(EID_HSM, 2, hartid)? {
+            0 => HartState::Started,
+            1 => HartState::Stopped,
+            2 => HartState::StartPending,
+            3 => HartState::StopPending,
+            4 => HartState::Suspended,
+            5 => HartState::SuspendPending,
+            6 => HartState::ResumePending,
+            _ => return Err(SbiError::Failed),
+        })
+    }
+
+    /// Suspends the calling hart, see [`SUSPEND_DEFAULT_RETENTIVE`] and
+    /// [`SUSPEND_DEFAULT_NON_RETENTIVE`].
+    ///
+    /// A non-retentive suspend resumes at the physical address `resume_addr`
+    /// as [`hart_start`] does, with `opaque` in `a1`.
+    pub fn hart_suspend(suspend_type: u32, resume_addr: usize, opaque: usize) -> SbiResult<()> {
+        call3(EID_HSM, 3, suspend_type as usize, resume_addr, opaque).map(|_| ())
+    }
+}
+
+/// The System Reset extension.
+pub mod srst {
+    use super::{call2, SbiError, EID_SRST};
+
+    /// The type of a system reset.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum ResetType {
+        /// Power off all the hardware.
+        Shutdown = 0,
+        /// Power cycle all the hardware and reboot.
+        ColdReboot = 1,
+        /// Reboot without power cycling the hardware.
+        WarmReboot = 2,
+    }
+
+    /// The reason of a system reset.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum ResetReason {
+        /// No reason.
+        NoReason = 0,
+        /// A system failure.
+        SystemFailure = 1,
+    }
+
+    /// Resets the system. It only returns on failure.
+    pub fn system_reset(reset_type: ResetType, reason: ResetReason) -> SbiError {
+        match call2(EID_SRST, 0, reset_type as usize, reason as usize) {
+            Ok(_) => SbiError::Failed,
+            Err(e) => e,
+        }
+    }
+}
+
+/// The Performance Monitoring Unit extension.
+///
+/// The counters are selected by a set of indices: `counter_idx_base` and the
+/// bit mask `counter_idx_mask` of the indices from it.
+pub mod pmu {
+    use super::{call0, call1, call3, sbi_call, split_u64, SbiResult, EID_PMU};
+
+    /// The type of the event of [`EventType::HardwareGeneral`].
+    pub const EVENT_TYPE_HW_GENERAL: usize = 0;
+    /// The type of the event of [`EventType::HardwareCache`].
+    pub const EVENT_TYPE_HW_CACHE: usize = 1;
+    /// The type of the raw hardware events.
+    pub const EVENT_TYPE_HW_RAW: usize = 2;
+    /// The type of the firmware events.
+    pub const EVENT_TYPE_FIRMWARE: usize = 15;
+
+    /// The general hardware events.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(usize)]
+    pub enum HardwareEvent {
+        /// CPU cycles.
+        CpuCycles = 1,
+        /// Retired instructions.
+        Instructions = 2,
+        /// Cache accesses.
+        CacheReferences = 3,
+        /// Cache misses.
+        CacheMisses = 4,
+        /// Retired branch instructions.
+        BranchInstructions = 5,
+        /// Mispredicted branches.
+        BranchMisses = 6,
+        /// Bus cycles.
+        BusCycles = 7,
+        /// Stalled cycles in the frontend.
+        StalledCyclesFrontend = 8,
+        /// Stalled cycles in the backend.
+        StalledCyclesBackend = 9,
+        /// Reference CPU cycles, not affected by frequency scaling.
+        RefCpuCycles = 10,
+    }
+
+    impl HardwareEvent {
+        /// The event index of the event, for [`counter_config_matching`].
+        pub const fn event_idx(self) -> usize {
+            EVENT_TYPE_HW_GENERAL << 16 | self as usize
+        }
+    }
+
+    /// Skips the matching, configures the given counter.
+    pub const CFG_FLAG_SKIP_MATCH: usize = 1 << 0;
+    /// Clears the value of the counter.
+    pub const CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+    /// Starts the counter after configured.
+    pub const CFG_FLAG_AUTO_START: usize = 1 << 2;
+    /// Inhibits the counting in VU-mode, with Sscofpmf.
+    pub const CFG_FLAG_SET_VUINH: usize = 1 << 3;
+    /// Inhibits the counting in VS-mode, with Sscofpmf.
+    pub const CFG_FLAG_SET_VSINH: usize = 1 << 4;
+    /// Inhibits the counting in U-mode, with Sscofpmf.
+    pub const CFG_FLAG_SET_UINH: usize = 1 << 5;
+    /// Inhibits the counting in S-mode, with Sscofpmf.
+    pub const CFG_FLAG_SET_SINH: usize = 1 << 6;
+    /// Inhibits the counting in M-mode, with Sscofpmf.
+    pub const CFG_FLAG_SET_MINH: usize = 1 << 7;
+    /// Sets the initial value of the counters when started.
+    pub const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+    /// Resets the mapping of the counters to the events when stopped.
+    pub const STOP_FLAG_RESET: usize = 1 << 0;
+
+    /// Information of a counter.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum CounterInfo {
+        /// A hardware counter, read by its CSR.
+        Hardware {
+            /// The number of the CSR.
+            csr: u16,
+            /// The width of the counter in bits.
+            width: u8,
+        },
+        /// A firmware counter, read by [`counter_fw_read`].
+        Firmware,
+    }
+
+    /// Returns the number of the counters, hardware and firmware.
+    pub fn num_counters() -> SbiResult {
+        call0(EID_PMU, 0)
+    }
+
+    /// Returns the information of the counter `counter_idx`.
+    pub fn counter_get_info(counter_idx: usize) -> SbiResult<CounterInfo> {
+        let info = call1(EID_PMU, 1, counter_idx)?;
+        Ok(if info >> (usize::BITS - 1) == 0 {
+            CounterInfo::Hardware {
+                csr: (info & 0xfff) as u16,
+                width: ((info >> 12) & 0x3f) as u8 + 1,
+            }
+        } else {
+            CounterInfo::Firmware
+        })
+    }
+
+    /// Finds a counter in the set that can count the event `event_idx`,
+    /// with `event_data` for the raw events, and configures it. Returns the
+    /// index of the counter.
+    pub fn counter_config_matching(
+        counter_idx_base: usize,
+        counter_idx_mask: usize,
+        config_flags: usize,
+        event_idx: usize,
+        event_data: u64,
+    ) -> SbiResult {
+        let (lo, hi) = split_u64(event_data);
+        sbi_call(
+            EID_PMU,
+            2,
+            [
+                counter_idx_base,
+                counter_idx_mask,
+                config_flags,
+                event_idx,
+                lo,
+                hi,
+            ],
+        )
+    }
+
+    /// Starts the counters of the set, from `initial_value` with
+    /// [`START_FLAG_SET_INIT_VALUE`].
+    pub fn counter_start(
+        counter_idx_base: usize,
+        counter_idx_mask: usize,
+        start_flags: usize,
+        initial_value: u64,
+    ) -> SbiResult<()> {
+        let (lo, hi) = split_u64(initial_value);
+        sbi_call(
+            EID_PMU,
+            3,
+            [counter_idx_base, counter_idx_mask, start_flags, lo, hi, 0],
+        )
+        .map(|_| ())
+    }
+
+    /// Stops the counters of the set.
+    pub fn counter_stop(
+        counter_idx_base: usize,
+        counter_idx_mask: usize,
+        stop_flags: usize,
+    ) -> SbiResult<()> {
+        call3(EID_PMU, 4, counter_idx_base, counter_idx_mask, stop_flags).map(|_| ())
+    }
+
+    /// Reads the firmware counter `counter_idx`.
+    pub fn counter_fw_read(counter_idx: usize) -> SbiResult {
+        call1(EID_PMU, 5, counter_idx)
+    }
+}
+
+/// The Debug Console extension.
+///
+/// The buffers must be in the linear mapping of the physical memory, whose
+/// physical addresses are given to the firmware.
+pub mod dbcn {
+    use super::{call1, call3, split_paddr, SbiResult, EID_DBCN};
+
+    /// Writes the bytes of `buf`, returns the number of the bytes written.
+    pub fn write(buf: &[u8]) -> SbiResult {
+        let (lo, hi) = split_paddr(buf.as_ptr());
+        call3(EID_DBCN, 0, buf.len(), lo, hi)
+    }
+
+    /// Reads the bytes to `buf` without blocking, returns the number of the
+    /// bytes read.
+    pub fn read(buf: &mut [u8]) -> SbiResult {
+        let (lo, hi) = split_paddr(buf.as_ptr());
+        call3(EID_DBCN, 1, buf.len(), lo, hi)
+    }
+
+    /// Writes a byte, blocking.
+    pub fn write_byte(byte: u8) -> SbiResult<()> {
+        call1(EID_DBCN, 2, byte as usize).map(|_| ())
+    }
+}
+
+/// The Steal-time Accounting extension.
+pub mod sta {
+    use super::{call3, split_paddr, SbiResult, EID_STA};
+
+    /// Sets the shared memory of the steal time of the current hart to the
+    /// 64 bytes at `shmem`, aligned to 64 bytes in the linear mapping of the
+    /// physical memory.
+    pub fn set_shmem(shmem: *const u8) -> SbiResult<()> {
+        let (lo, hi) = split_paddr(shmem);
+        call3(EID_STA, 0, lo, hi, 0).map(|_| ())
+    }
+}
+
+const DBCN_UNKNOWN: u8 = 0;
+const DBCN_AVAILABLE: u8 = 1;
+const DBCN_UNAVAILABLE: u8 = 2;
+
+/// Whether the Debug Console extension is available, probed on first use.
+static DBCN_STATE: AtomicU8 = AtomicU8::new(DBCN_UNKNOWN);
+
+fn dbcn_available() -> bool {
+    match DBCN_STATE.load(Ordering::Relaxed) {
+        DBCN_UNKNOWN => {
+            let available = base::probe_extension(EID_DBCN);
+            let state = if available {
+                DBCN_AVAILABLE
+            } else {
+                DBCN_UNAVAILABLE
+            };
+            DBCN_STATE.store(state, Ordering::Relaxed);
+            available
+        }
+        state => state == DBCN_AVAILABLE,
+    }
+}
+
+/// Writes a byte to the console of the firmware, by the Debug Console, or
+/// the legacy console without it.
+pub fn console_putchar(c: u8) {
+    if dbcn_available() {
+        dbcn::write_byte(c).ok();
+    } else {
+        legacy_call(EID_LEGACY_CONSOLE_PUTCHAR, c as usize);
+    }
+}
+
+/// Reads a byte from the console of the firmware, by the Debug Console, or
+/// the legacy console without it. Returns [`None`] if no input is available.
+pub fn console_getchar() -> Option<u8> {
+    if dbcn_available() {
+        let mut c = 0;
+        match dbcn::read(core::slice::from_mut(&mut c)) {
+            Ok(1) => Some(c),
+            _ => None,
+        }
+    } else {
+        match legacy_call(EID_LEGACY_CONSOLE_GETCHAR, 0) {
+            c @ 0..=0xff => Some(c as u8),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! The steal time of the SBI Steal-time Accounting extension.
+
+use crate::arch::sbi::{self, sta};
+use crate::steal::StealArea;
+
+/// The word of the sequence number, in its low 32 bits.
+pub(crate) const SEQUENCE: usize = 0;
+/// The word of the steal time.
+pub(crate) const STEAL: usize = 1;
+
+/// Sets the shared memory of the current hart to `area`, returns whether the
+/// firmware accepted it.
+pub(crate) fn register(area: &'static StealArea) -> bool {
+    sbi::base::probe_extension(sbi::EID_STA)
+        && sta::set_shmem(area as *const StealArea as *const u8).is_ok()
+}
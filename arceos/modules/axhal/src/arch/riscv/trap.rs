@@ -4,6 +4,14 @@ use riscv::register::stval;
 
 use super::TrapFrame;
 
+/// The FS field of `sstatus`, the state of the FP unit.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+const SSTATUS_FS: usize = 0b11 << 13;
+/// The VS field of `sstatus`, the state of the vector unit, enabled and
+/// disabled with the FP unit.
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+const SSTATUS_VS: usize = 0b11 << 9;
+
 include_asm_marcos!();
 
 core::arch::global_asm!(
@@ -16,12 +24,23 @@ fn handle_breakpoint(sepc: &mut usize) {
     *sepc += 2
 }
 
+/// Hands a fatal trap of the user space to the handler killing the task,
+/// returns `false` if there is none.
+#[cfg(feature = "uspace")]
+fn handle_user_fault(tf: &TrapFrame, signal: i32) -> bool {
+    handle_trap!(USER_FAULT, tf, signal, va!(stval::read()))
+}
+
 fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(stval::read());
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        #[cfg(feature = "uspace")]
+        if is_user && handle_user_fault(tf, crate::trap::signal::SIGSEGV) {
+            return;
+        }
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "User" } else { "Supervisor" },
@@ -33,6 +52,17 @@ fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bo
     }
 }
 
+/// The signal of a fatal exception of the user space.
+#[cfg(feature = "uspace")]
+fn user_fault_signal(e: E) -> i32 {
+    use crate::trap::signal::*;
+    match e {
+        E::InstructionMisaligned | E::LoadMisaligned | E::StoreMisaligned => SIGBUS,
+        E::IllegalInstruction => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
 #[no_mangle]
 fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let scause = scause::read();
@@ -47,10 +77,25 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         Trap::Exception(E::InstructionPageFault) => {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user)
         }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(E::Breakpoint)
+            if from_user && handle_user_fault(tf, crate::trap::signal::SIGTRAP) => {}
         Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+        #[cfg(feature = "ras")]
+        Trap::Exception(_) if scause.code() == super::ras::HARDWARE_ERROR => {
+            super::ras::handle_hardware_error(tf, from_user)
+        }
+        #[cfg(all(feature = "fp_simd", lazy_fp))]
+        Trap::Exception(E::IllegalInstruction) if tf.sstatus & SSTATUS_FS == 0 => {
+            // Most likely an FP or vector instruction with the units off,
+            // executed again.
+            super::super::lazy_fp::handle_trap::<super::context::FpState>();
+        }
         Trap::Interrupt(_) => {
-            handle_trap!(IRQ, scause.bits());
+            crate::trap::handle_irq(tf, scause.bits());
         }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(e) if from_user && handle_user_fault(tf, user_fault_signal(e)) => {}
         _ => {
             panic!(
                 "Unhandled trap {:?} @ {:#x}:\n{:#x?}",
@@ -60,4 +105,12 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
+    // `sret` restores `sstatus` from the frame, keep the FP and vector
+    // unit states of the task running now, which may not be the one
+    // trapped if it was preempted.
+    #[cfg(all(feature = "fp_simd", lazy_fp))]
+    {
+        let units = riscv::register::sstatus::read().bits() & (SSTATUS_FS | SSTATUS_VS);
+        tf.sstatus = (tf.sstatus & !(SSTATUS_FS | SSTATUS_VS)) | units;
+    }
 }
@@ -77,21 +77,59 @@ pub struct FxsaveArea {
 static_assertions::const_assert_eq!(core::mem::size_of::<FxsaveArea>(), 512);
 
 /// Extended state of a task, such as FP/SIMD states.
+///
+/// It is saved by XSAVE, with the AVX states, if enabled at boot, by FXSAVE
+/// otherwise.
+#[repr(C, align(64))]
 pub struct ExtendedState {
-    /// Memory region for the FXSAVE/FXRSTOR instruction.
+    /// Memory region for the FXSAVE/FXRSTOR instruction, also the legacy
+    /// region of XSAVE.
     pub fxsave_area: FxsaveArea,
+    xsave_header: [u64; 8],
+    ymm_hi: [u128; 16],
 }
 
+static_assertions::const_assert_eq!(core::mem::size_of::<ExtendedState>(), 832);
+
+/// Whether the extended states are saved by XSAVE.
+#[cfg(feature = "fp_simd")]
+pub(super) static XSAVE_ENABLED: core::sync::atomic::AtomicBool =
+    core::sync::atomic::AtomicBool::new(false);
+
 #[cfg(feature = "fp_simd")]
 impl ExtendedState {
     #[inline]
     fn save(&mut self) {
-        unsafe { core::arch::x86_64::_fxsave64(&mut self.fxsave_area as *mut _ as *mut u8) }
+        if XSAVE_ENABLED.load(core::sync::atomic::Ordering::Relaxed) {
+            unsafe {
+                asm!(
+                    "xsave64 [{}]",
+                    in(reg) self as *mut Self,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack),
+                )
+            }
+        } else {
+            unsafe { core::arch::x86_64::_fxsave64(&mut self.fxsave_area as *mut _ as *mut u8) }
+        }
     }
 
     #[inline]
     fn restore(&self) {
-        unsafe { core::arch::x86_64::_fxrstor64(&self.fxsave_area as *const _ as *const u8) }
+        if XSAVE_ENABLED.load(core::sync::atomic::Ordering::Relaxed) {
+            unsafe {
+                asm!(
+                    "xrstor64 [{}]",
+                    in(reg) self as *const Self,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack),
+                )
+            }
+        } else {
+            unsafe { core::arch::x86_64::_fxrstor64(&self.fxsave_area as *const _ as *const u8) }
+        }
     }
 
     const fn default() -> Self {
@@ -99,7 +137,36 @@ impl ExtendedState {
         area.fcw = 0x37f;
         area.ftw = 0xffff;
         area.mxcsr = 0x1f80;
-        Self { fxsave_area: area }
+        Self {
+            fxsave_area: area,
+            xsave_header: [0; 8],
+            ymm_hi: [0; 16],
+        }
+    }
+}
+
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl super::super::lazy_fp::FpContext for ExtendedState {
+    fn save(&mut self) {
+        ExtendedState::save(self)
+    }
+
+    fn restore(&self) {
+        ExtendedState::restore(self)
+    }
+
+    fn enable() {
+        unsafe { asm!("clts") }
+    }
+
+    fn disable() {
+        use x86_64::registers::control::{Cr0, Cr0Flags};
+        unsafe { Cr0::update(|cr0| cr0.insert(Cr0Flags::TASK_SWITCHED)) }
+    }
+
+    fn is_enabled() -> bool {
+        use x86_64::registers::control::{Cr0, Cr0Flags};
+        !Cr0::read().contains(Cr0Flags::TASK_SWITCHED)
     }
 }
 
@@ -182,7 +249,9 @@ impl TaskContext {
     /// It first saves the current task's context from CPU to this place, and then
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
-        #[cfg(feature = "fp_simd")]
+        #[cfg(all(feature = "fp_simd", lazy_fp))]
+        super::super::lazy_fp::switch(&mut self.ext_state, &next_ctx.ext_state);
+        #[cfg(all(feature = "fp_simd", not(lazy_fp)))]
         {
             self.ext_state.save();
             next_ctx.ext_state.restore();
@@ -196,6 +265,13 @@ impl TaskContext {
     }
 }
 
+#[cfg(all(feature = "fp_simd", lazy_fp))]
+impl Drop for TaskContext {
+    fn drop(&mut self) {
+        super::super::lazy_fp::release(&self.ext_state);
+    }
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_stack: &mut u64, _next_stack: &u64) {
     asm!(
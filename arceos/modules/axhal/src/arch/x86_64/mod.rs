@@ -1,6 +1,12 @@
 mod context;
 mod gdt;
 mod idt;
+#[cfg(feature = "pmu")]
+pub(crate) mod pmu;
+#[cfg(feature = "ras")]
+pub(crate) mod ras;
+#[cfg(feature = "steal-time")]
+pub(crate) mod steal;
 
 #[cfg(target_os = "none")]
 mod trap;
@@ -108,3 +114,24 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(fs_base: usize) {
     unsafe { msr::wrmsr(msr::IA32_FS_BASE, fs_base as u64) }
 }
+
+/// Enables the AVX states on the current CPU and their saving by XSAVE, if
+/// supported.
+#[cfg(all(feature = "fp_simd", target_os = "none"))]
+pub(crate) fn init_fp_simd() {
+    use core::sync::atomic::Ordering;
+    use x86_64::registers::control::{Cr4, Cr4Flags};
+
+    let cpuid = raw_cpuid::CpuId::new();
+    let Some(info) = cpuid.get_feature_info() else {
+        return;
+    };
+    if info.has_xsave() && info.has_avx() {
+        unsafe {
+            Cr4::update(|cr4| cr4.insert(Cr4Flags::OSXSAVE));
+            // XCR0: x87, SSE and AVX states.
+            asm!("xsetbv", in("ecx") 0, in("eax") 0b111, in("edx") 0);
+        }
+        self::context::XSAVE_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
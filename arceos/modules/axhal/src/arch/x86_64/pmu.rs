@@ -0,0 +1,87 @@
+//! The architectural performance monitoring of Intel.
+
+use core::arch::x86_64::__cpuid;
+use x86::msr::{rdmsr, wrmsr};
+
+use crate::pmu::{PmuEvent, NR_EVENTS};
+
+const IA32_PMC0: u32 = 0xc1;
+const IA32_PERFEVTSEL0: u32 = 0x186;
+const IA32_FIXED_CTR0: u32 = 0x309;
+const IA32_FIXED_CTR_CTRL: u32 = 0x38d;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+// `IA32_PERFEVTSELx`: count in the user space and the kernel, enabled.
+const EVTSEL_USR: u64 = 1 << 16;
+const EVTSEL_OS: u64 = 1 << 17;
+const EVTSEL_EN: u64 = 1 << 22;
+
+/// The architectural events on the general-purpose counters: the event, its
+/// `[event select, umask]`, and its bit in `CPUID.0AH:EBX`, set if it is not
+/// available.
+const GP_EVENTS: [(PmuEvent, [u8; 2], u32); 2] = [
+    (PmuEvent::CacheMisses, [0x2e, 0x41], 4),
+    (PmuEvent::BranchMisses, [0xc5, 0x00], 6),
+];
+
+/// Programs the counters, returns their widths.
+///
+/// The instructions and the cycles are on the fixed counters 0 and 1, the
+/// events of [`GP_EVENTS`] on the general-purpose counters from 0.
+pub(crate) fn init_percpu() -> [u8; NR_EVENTS] {
+    let mut widths = [0; NR_EVENTS];
+    if unsafe { __cpuid(0) }.eax < 0xa {
+        return widths;
+    }
+    let leaf = unsafe { __cpuid(0xa) };
+    let version = leaf.eax & 0xff;
+    // the global control of the counters is from version 2
+    if version < 2 {
+        return widths;
+    }
+    let nr_gp = (leaf.eax >> 8) & 0xff;
+    let gp_width = (leaf.eax >> 16) & 0xff;
+    let ebx_len = leaf.eax >> 24;
+    let nr_fixed = leaf.edx & 0x1f;
+    let fixed_width = (leaf.edx >> 5) & 0xff;
+
+    let mut global_ctrl = 0;
+    unsafe {
+        wrmsr(IA32_PERF_GLOBAL_CTRL, 0);
+        if nr_fixed >= 2 {
+            // the user space and the kernel on the fixed counters 0 and 1
+            wrmsr(IA32_FIXED_CTR_CTRL, 0x33);
+            wrmsr(IA32_FIXED_CTR0, 0);
+            wrmsr(IA32_FIXED_CTR0 + 1, 0);
+            global_ctrl |= 0b11 << 32;
+            widths[PmuEvent::Instructions as usize] = fixed_width as u8;
+            widths[PmuEvent::Cycles as usize] = fixed_width as u8;
+        }
+        for (i, &(event, [select, umask], bit)) in GP_EVENTS.iter().enumerate() {
+            let unavailable = bit >= ebx_len || leaf.ebx & (1 << bit) != 0;
+            if i as u32 >= nr_gp || unavailable {
+                continue;
+            }
+            let evtsel = select as u64 | (umask as u64) << 8 | EVTSEL_USR | EVTSEL_OS | EVTSEL_EN;
+            wrmsr(IA32_PERFEVTSEL0 + i as u32, evtsel);
+            wrmsr(IA32_PMC0 + i as u32, 0);
+            global_ctrl |= 1 << i;
+            widths[event as usize] = gp_width as u8;
+        }
+        wrmsr(IA32_PERF_GLOBAL_CTRL, global_ctrl);
+    }
+    widths
+}
+
+/// Reads the counter of `event`, which is counted.
+pub(crate) fn read(event: PmuEvent) -> u64 {
+    let msr = match event {
+        PmuEvent::Instructions => IA32_FIXED_CTR0,
+        PmuEvent::Cycles => IA32_FIXED_CTR0 + 1,
+        _ => {
+            let i = GP_EVENTS.iter().position(|&(e, ..)| e == event).unwrap();
+            IA32_PMC0 + i as u32
+        }
+    };
+    unsafe { rdmsr(msr) }
+}
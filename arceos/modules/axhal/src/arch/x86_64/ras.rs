@@ -0,0 +1,147 @@
+//! The machine check architecture.
+
+use core::arch::x86_64::__cpuid;
+use x86::msr::{rdmsr, wrmsr};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+use super::TrapFrame;
+use crate::ras::{self, HwError, HwErrorSeverity, RasAction};
+
+const IA32_MCG_CAP: u32 = 0x179;
+const IA32_MCG_STATUS: u32 = 0x17a;
+const IA32_MCG_CTL: u32 = 0x17b;
+const IA32_MC0_CTL: u32 = 0x400;
+
+// `IA32_MCG_CAP`
+const MCG_COUNT_MASK: u64 = 0xff;
+const MCG_CTL_P: u64 = 1 << 8;
+
+// `IA32_MCG_STATUS`: the interrupted code can be restarted.
+const MCG_STATUS_RIPV: u64 = 1 << 0;
+
+// `IA32_MCi_STATUS`
+const MCI_STATUS_VAL: u64 = 1 << 63;
+const MCI_STATUS_UC: u64 = 1 << 61;
+const MCI_STATUS_ADDRV: u64 = 1 << 58;
+const MCI_STATUS_PCC: u64 = 1 << 57;
+
+// `CPUID.01H:EDX`
+const CPUID_MCE: u32 = 1 << 7;
+const CPUID_MCA: u32 = 1 << 14;
+
+fn mci_ctl(bank: u32) -> u32 {
+    IA32_MC0_CTL + 4 * bank
+}
+
+fn mci_status(bank: u32) -> u32 {
+    IA32_MC0_CTL + 4 * bank + 1
+}
+
+fn mci_addr(bank: u32) -> u32 {
+    IA32_MC0_CTL + 4 * bank + 2
+}
+
+/// The number of the banks, 0 without the machine check architecture.
+fn nr_banks() -> u32 {
+    let edx = unsafe { __cpuid(1) }.edx;
+    if edx & CPUID_MCE == 0 || edx & CPUID_MCA == 0 {
+        return 0;
+    }
+    (unsafe { rdmsr(IA32_MCG_CAP) } & MCG_COUNT_MASK) as u32
+}
+
+/// Enables all the errors of all the banks, and the `#MC` exceptions.
+pub(crate) fn init_percpu() {
+    let banks = nr_banks();
+    if banks == 0 {
+        return;
+    }
+    unsafe {
+        if rdmsr(IA32_MCG_CAP) & MCG_CTL_P != 0 {
+            wrmsr(IA32_MCG_CTL, u64::MAX);
+        }
+        for bank in 0..banks {
+            wrmsr(mci_ctl(bank), u64::MAX);
+            // the errors logged before the reboot
+            wrmsr(mci_status(bank), 0);
+        }
+        Cr4::update(|cr4| cr4.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION));
+    }
+    if crate::cpu::this_cpu_is_bsp() {
+        info!("machine checks enabled, {} banks", banks);
+    }
+}
+
+/// The unit of the simple or compound MCA error code.
+fn error_kind(code: u16) -> &'static str {
+    // the correction report filtering bit of the compound codes
+    match code & !(1 << 12) {
+        0x0000 => "no error",
+        0x0001 => "unclassified",
+        0x0002 => "microcode ROM parity",
+        0x0003 => "external",
+        0x0004 => "FRC",
+        0x0005 => "internal parity",
+        0x0006 => "SMM handler code access violation",
+        0x0400 => "internal timer",
+        0x0401..=0x07ff => "internal",
+        0x0010..=0x001f => "TLB",
+        0x0080..=0x00ff => "memory controller",
+        0x0100..=0x01ff => "cache hierarchy",
+        0x0800..=0x0fff => "bus and interconnect",
+        _ => "unknown",
+    }
+}
+
+/// Handles a machine check: reports the error of each valid bank and takes
+/// the strongest of their actions.
+pub(crate) fn handle_machine_check(tf: &TrapFrame) {
+    let mcg_status = unsafe { rdmsr(IA32_MCG_STATUS) };
+    let restartable = mcg_status & MCG_STATUS_RIPV != 0;
+    let mut worst: Option<(RasAction, HwError)> = None;
+    for bank in 0..nr_banks() {
+        let status = unsafe { rdmsr(mci_status(bank)) };
+        if status & MCI_STATUS_VAL == 0 {
+            continue;
+        }
+        let severity = if status & MCI_STATUS_UC == 0 {
+            HwErrorSeverity::Corrected
+        } else if status & MCI_STATUS_PCC != 0 || !restartable {
+            HwErrorSeverity::Fatal
+        } else {
+            HwErrorSeverity::Recoverable
+        };
+        let err = HwError {
+            severity,
+            kind: error_kind(status as u16),
+            source: bank as usize,
+            syndrome: status,
+            addr: (status & MCI_STATUS_ADDRV != 0)
+                .then(|| unsafe { rdmsr(mci_addr(bank)) } as usize),
+            vaddr: None,
+            pc: tf.rip as usize,
+            from_user: tf.is_user(),
+        };
+        let action = ras::report(&err);
+        unsafe { wrmsr(mci_status(bank), 0) };
+        if worst.map_or(true, |(worst, _)| action > worst) {
+            worst = Some((action, err));
+        }
+    }
+    let (action, err) = worst.unwrap_or_else(|| {
+        let err = HwError {
+            severity: HwErrorSeverity::Fatal,
+            kind: "machine check without a valid bank",
+            source: 0,
+            syndrome: mcg_status,
+            addr: None,
+            vaddr: None,
+            pc: tf.rip as usize,
+            from_user: tf.is_user(),
+        };
+        (ras::report(&err), err)
+    });
+    // the next machine check would shut down the CPU while it is in progress
+    unsafe { wrmsr(IA32_MCG_STATUS, 0) };
+    ras::take_action(tf, action, &err);
+}
@@ -0,0 +1,44 @@
+//! The steal time of KVM.
+
+use core::arch::x86_64::__cpuid;
+use x86::msr::wrmsr;
+
+use crate::steal::StealArea;
+
+const CPUID_HYPERVISOR_BIT: u32 = 1 << 31;
+const KVM_CPUID_SIGNATURE: u32 = 0x4000_0000;
+const KVM_CPUID_FEATURES: u32 = 0x4000_0001;
+const KVM_FEATURE_STEAL_TIME: u32 = 1 << 5;
+const MSR_KVM_STEAL_TIME: u32 = 0x4b56_4d03;
+const KVM_MSR_ENABLED: u64 = 1;
+
+/// The word of the version, in its low 32 bits.
+pub(crate) const SEQUENCE: usize = 1;
+/// The word of the steal time.
+pub(crate) const STEAL: usize = 0;
+
+fn has_steal_time() -> bool {
+    if unsafe { __cpuid(1) }.ecx & CPUID_HYPERVISOR_BIT == 0 {
+        return false;
+    }
+    let leaf = unsafe { __cpuid(KVM_CPUID_SIGNATURE) };
+    let mut signature = [0; 12];
+    signature[..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&leaf.ecx.to_le_bytes());
+    signature[8..].copy_from_slice(&leaf.edx.to_le_bytes());
+    &signature == b"KVMKVMKVM\0\0\0"
+        && leaf.eax >= KVM_CPUID_FEATURES
+        && unsafe { __cpuid(KVM_CPUID_FEATURES) }.eax & KVM_FEATURE_STEAL_TIME != 0
+}
+
+/// Gives `area` to KVM by `MSR_KVM_STEAL_TIME`, returns whether KVM has the
+/// steal time.
+pub(crate) fn register(area: &'static StealArea) -> bool {
+    if !has_steal_time() {
+        return false;
+    }
+    let vaddr = (area as *const StealArea as usize).into();
+    let paddr = crate::mem::virt_to_phys(vaddr).as_usize() as u64;
+    unsafe { wrmsr(MSR_KVM_STEAL_TIME, paddr | KVM_MSR_ENABLED) };
+    true
+}
@@ -31,6 +31,10 @@ fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        #[cfg(feature = "ras")]
+        MACHINE_CHECK_VECTOR => super::ras::handle_machine_check(tf),
+        #[cfg(all(feature = "fp_simd", lazy_fp))]
+        DEVICE_NOT_AVAILABLE_VECTOR => super::super::lazy_fp::handle_trap::<super::ExtendedState>(),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -38,7 +42,7 @@ fn x86_trap_handler(tf: &TrapFrame) {
             );
         }
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
-            handle_trap!(IRQ, tf.vector as _);
+            crate::trap::handle_irq(tf, tf.vector as _);
         }
         _ => {
             panic!(
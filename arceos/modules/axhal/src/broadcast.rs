@@ -0,0 +1,97 @@
+//! Timer broadcast, for the CPUs whose local timer stops in idle.
+//!
+//! On some boards (ARM SoCs gating the clock of the idle cores, say) the
+//! local timer of a CPU does not fire while the CPU waits for interrupts, and
+//! an idle CPU would miss its next tick, and the timers it serves, until
+//! another interrupt comes. With the `timer-broadcast` feature, the idle loop
+//! waits in [`idle`] instead of [`wait_for_irqs`]: a secondary CPU hands the
+//! deadline of its local timer over to the primary CPU, whose timer is the
+//! always-on one as it never sleeps that deep, and the primary CPU wakes it
+//! by an IPI on its first timer interrupt after the deadline. The woken CPU
+//! programs its local timer again, which fires at once if the deadline
+//! passed. An idle CPU then wakes up to a tick of the primary CPU late.
+//!
+//! [`wait_for_irqs`]: crate::arch::wait_for_irqs
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::cpu::this_cpu_id;
+use crate::platform::irq::{send_wakeup_ipi, WAKEUP_IPI_NUM};
+use crate::time::{current_ticks, ticks_to_nanos};
+
+/// The CPU whose timer wakes the others.
+pub const BROADCAST_CPU: usize = 0;
+
+/// The deadline of the local timer of each CPU, in nanoseconds of the
+/// hardware clock.
+static DEADLINES: [AtomicU64; axconfig::SMP] = [const { AtomicU64::new(u64::MAX) }; axconfig::SMP];
+/// Whether each CPU idles with its timer handed over.
+static HANDED_OVER: [AtomicBool; axconfig::SMP] = [const { AtomicBool::new(false) }; axconfig::SMP];
+static WAKEUPS: AtomicUsize = AtomicUsize::new(0);
+
+fn hw_now_nanos() -> u64 {
+    ticks_to_nanos(current_ticks())
+}
+
+/// Records the deadline programmed in the local timer of this CPU.
+pub(crate) fn set_deadline(deadline_ns: u64) {
+    DEADLINES[this_cpu_id()].store(deadline_ns, Ordering::Release);
+}
+
+/// Relaxes the current CPU and waits for interrupts, with its local timer
+/// handed over to the broadcast CPU if it is another one.
+///
+/// It must be called with interrupts enabled, otherwise it will never return.
+pub fn idle() {
+    let cpu_id = this_cpu_id();
+    if cpu_id == BROADCAST_CPU || cfg!(target_arch = "x86_64") {
+        // The timer of the broadcast CPU is always on, as are the local APIC
+        // timers on x86 (see the ARAT feature).
+        crate::arch::wait_for_irqs();
+        return;
+    }
+    // The IPI may come at any time once handed over: it must stay pending
+    // until the CPU waits, which wakes up on a pending interrupt even masked.
+    crate::arch::disable_irqs();
+    HANDED_OVER[cpu_id].store(true, Ordering::SeqCst);
+    let deadline = DEADLINES[cpu_id].load(Ordering::Acquire);
+    if hw_now_nanos() < deadline {
+        crate::arch::wait_for_irqs();
+    }
+    HANDED_OVER[cpu_id].store(false, Ordering::SeqCst);
+    let deadline = DEADLINES[cpu_id].load(Ordering::Acquire);
+    // The local timer may have lost its deadline while stopped.
+    if deadline != u64::MAX {
+        crate::platform::time::set_oneshot_timer(deadline);
+    }
+    crate::arch::enable_irqs();
+}
+
+/// Wakes the idle CPUs whose deadline passed, on the timer interrupts of the
+/// broadcast CPU.
+pub(crate) fn on_timer_irq() {
+    if this_cpu_id() != BROADCAST_CPU {
+        return;
+    }
+    let now = hw_now_nanos();
+    for cpu_id in (0..axconfig::SMP).filter(|&c| c != BROADCAST_CPU) {
+        if DEADLINES[cpu_id].load(Ordering::Acquire) <= now
+            && HANDED_OVER[cpu_id].swap(false, Ordering::SeqCst)
+        {
+            trace!("timer broadcast: waking CPU {}", cpu_id);
+            WAKEUPS.fetch_add(1, Ordering::Relaxed);
+            send_wakeup_ipi(cpu_id);
+        }
+    }
+}
+
+/// Returns the number of the CPUs woken up by the broadcast since boot.
+pub fn wakeups() -> usize {
+    WAKEUPS.load(Ordering::Relaxed)
+}
+
+/// Sets up the wake-up IPI on the primary CPU.
+pub(crate) fn init() {
+    // Only breaks the wait of the CPU.
+    crate::irq::register_handler(WAKEUP_IPI_NUM, || {});
+}
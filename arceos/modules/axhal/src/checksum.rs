@@ -0,0 +1,211 @@
+//! Checksums of the storage and network protocols.
+//!
+//! The CRC32C (Castagnoli) sums, as used by the ext4 metadata, iSCSI and
+//! the key-value stores, are computed by the CRC instructions when the CPU
+//! has them, selected with [`Dispatch`]: SSE4.2 on x86_64, the CRC32
+//! extension on aarch64 and the carry-less multiplication (Zbc) on riscv64.
+//! Otherwise, or on the other architectures, a lookup table is used.
+//!
+//! Adler-32 has no such instructions, it is computed with the
+//! general-purpose registers only, deferring the modulo as long as the sums
+//! cannot overflow.
+
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+use core::arch::asm;
+
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+use crate::cpu::CpuFeatures;
+use crate::cpu::Dispatch;
+
+type CrcFn = unsafe fn(u32, &[u8]) -> u32;
+
+#[cfg(target_arch = "x86_64")]
+static CRC32C: Dispatch<CrcFn> =
+    Dispatch::new(&[(CpuFeatures::SSE4_2, crc32c_sse42)], crc32c_table);
+#[cfg(target_arch = "aarch64")]
+static CRC32C: Dispatch<CrcFn> = Dispatch::new(&[(CpuFeatures::CRC32, crc32c_armv8)], crc32c_table);
+#[cfg(target_arch = "riscv64")]
+static CRC32C: Dispatch<CrcFn> = Dispatch::new(&[(CpuFeatures::RV_ZBC, crc32c_zbc)], crc32c_table);
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+static CRC32C: Dispatch<CrcFn> = Dispatch::new(&[], crc32c_table);
+
+/// The CRC32C polynomial, bit-reversed.
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+/// The largest prime below 2^16.
+const ADLER_MOD: u32 = 65521;
+/// The most bytes summed before `b` of Adler-32 may overflow 32 bits.
+const ADLER_NMAX: usize = 5552;
+
+static CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Returns the CRC32C of `data`.
+///
+/// The check value, of `b"123456789"`, is `0xe3069283`.
+#[inline]
+pub fn crc32c(data: &[u8]) -> u32 {
+    !crc32c_update(!0, data)
+}
+
+/// Feeds `data` to the CRC register `crc`, without the initial and final
+/// inversions of [`crc32c`].
+///
+/// The sums over several buffers are chained through it, and some formats
+/// store the register as is, like ext4 whose seed is the checksum of the
+/// filesystem UUID.
+#[inline]
+pub fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    // SAFETY: the selected implementation runs on this CPU.
+    unsafe { (CRC32C.get())(crc, data) }
+}
+
+/// Updates the Adler-32 checksum `adler`, which is 1 for no bytes, with
+/// `data`.
+///
+/// The check value, of `b"123456789"` from 1, is `0x091e01de`.
+pub fn adler32(adler: u32, data: &[u8]) -> u32 {
+    let mut a = adler & 0xffff;
+    let mut b = adler >> 16;
+    for chunk in data.chunks(ADLER_NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_MOD;
+        b %= ADLER_MOD;
+    }
+    (b << 16) | a
+}
+
+unsafe fn crc32c_table(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+#[inline]
+fn split_words(data: &[u8]) -> (impl Iterator<Item = u64> + '_, &[u8]) {
+    let words = data.chunks_exact(8);
+    let rest = words.remainder();
+    (
+        words.map(|w| u64::from_le_bytes(w.try_into().unwrap())),
+        rest,
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn crc32c_sse42(crc: u32, data: &[u8]) -> u32 {
+    let (words, rest) = split_words(data);
+    let mut crc = crc as u64;
+    for word in words {
+        asm!(
+            "crc32 {crc}, {word}",
+            crc = inout(reg) crc,
+            word = in(reg) word,
+            options(pure, nomem, nostack),
+        );
+    }
+    let mut crc = crc as u32;
+    for &byte in rest {
+        asm!(
+            "crc32 {crc:e}, {byte}",
+            crc = inout(reg) crc,
+            byte = in(reg_byte) byte,
+            options(pure, nomem, nostack),
+        );
+    }
+    crc
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn crc32c_armv8(mut crc: u32, data: &[u8]) -> u32 {
+    let (words, rest) = split_words(data);
+    for word in words {
+        asm!(
+            ".arch_extension crc",
+            "crc32cx {crc:w}, {crc:w}, {word}",
+            crc = inout(reg) crc,
+            word = in(reg) word,
+            options(pure, nomem, nostack, preserves_flags),
+        );
+    }
+    for &byte in rest {
+        asm!(
+            ".arch_extension crc",
+            "crc32cb {crc:w}, {crc:w}, {byte:w}",
+            crc = inout(reg) crc,
+            byte = in(reg) byte as u32,
+            options(pure, nomem, nostack, preserves_flags),
+        );
+    }
+    crc
+}
+
+// A word `s` xored with the register is reduced by Barrett's method (see
+// https://www.corsix.org/content/barrett-reduction-polynomials), with the
+// bit-reversed operands: the quotient is `clmul(s, QT) << 1 ^ s`, of which
+// the remainder is the high half of `clmulr` by the polynomial.
+#[cfg(target_arch = "riscv64")]
+unsafe fn crc32c_zbc(crc: u32, data: &[u8]) -> u32 {
+    /// `x^96 / P` without its `x^64` bit, bit-reversed.
+    const QT: u64 = 0xa434_f61c_6f53_89f8;
+    const POLY: u64 = (CRC32C_POLY as u64) << 32;
+
+    let (words, rest) = split_words(data);
+    let mut crc = crc as u64;
+    for word in words {
+        asm!(
+            ".option push",
+            ".option arch, +zbc",
+            "xor    {s}, {s}, {crc}",
+            "clmul  {crc}, {s}, {qt}",
+            "slli   {crc}, {crc}, 1",
+            "xor    {crc}, {crc}, {s}",
+            "clmulr {crc}, {crc}, {poly}",
+            "srli   {crc}, {crc}, 32",
+            ".option pop",
+            crc = inout(reg) crc,
+            s = inout(reg) word => _,
+            qt = in(reg) QT,
+            poly = in(reg) POLY,
+            options(pure, nomem, nostack),
+        );
+    }
+    crc32c_table(crc as u32, rest)
+}
@@ -0,0 +1,39 @@
+//! The command line of the kernel.
+//!
+//! It is the `bootargs` property of the `/chosen` node of the device tree, or
+//! the one given by the boot loader on x86, see [`earlycon`](crate::earlycon).
+//! It is copied at the entry of the primary CPU, as the memory holding it may
+//! be given to the allocator later.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use lazyinit::LazyInit;
+
+/// The longest command line kept, the rest is cut.
+pub const MAX_LEN: usize = 2048;
+
+static mut BUF: [u8; MAX_LEN] = [0; MAX_LEN];
+static LEN: LazyInit<usize> = LazyInit::new();
+
+/// Keeps a copy of `cmdline`, on the primary CPU before the others start.
+pub(crate) fn set(cmdline: &str) {
+    let mut len = cmdline.len().min(MAX_LEN);
+    while !cmdline.is_char_boundary(len) {
+        len -= 1;
+    }
+    // SAFETY: written once, before any reader.
+    unsafe { (*addr_of_mut!(BUF))[..len].copy_from_slice(&cmdline.as_bytes()[..len]) };
+    LEN.init_once(len);
+}
+
+/// Returns the command line of the kernel, empty if there is none.
+pub fn get() -> &'static str {
+    let len = LEN.get().copied().unwrap_or(0);
+    // SAFETY: a valid UTF-8 prefix of the command line, no longer written.
+    unsafe { core::str::from_utf8_unchecked(&(*addr_of!(BUF))[..len]) }
+}
+
+/// Returns the arguments of the command line, separated by white spaces.
+pub fn args() -> impl Iterator<Item = &'static str> {
+    get().split_ascii_whitespace()
+}
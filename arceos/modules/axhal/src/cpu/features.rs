@@ -0,0 +1,363 @@
+//! CPU feature detection and selection of optimized routines.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+bitflags::bitflags! {
+    /// Features of the CPU, that the kernel can use.
+    ///
+    /// The flags of other architectures are never set. The floating-point
+    /// and SIMD features are only reported with the `fp_simd` feature, the
+    /// kernel cannot use them without it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct CpuFeatures: u64 {
+        // x86_64
+        /// SSE2 (x86_64).
+        const SSE2 = 1 << 0;
+        /// SSE3 (x86_64).
+        const SSE3 = 1 << 1;
+        /// Supplemental SSE3 (x86_64).
+        const SSSE3 = 1 << 2;
+        /// SSE4.1 (x86_64).
+        const SSE4_1 = 1 << 3;
+        /// SSE4.2, with the CRC32 instructions (x86_64).
+        const SSE4_2 = 1 << 4;
+        /// AVX, enabled by the OS in `XCR0` (x86_64).
+        const AVX = 1 << 5;
+        /// AVX2 (x86_64).
+        const AVX2 = 1 << 6;
+        /// AVX-512 Foundation, enabled by the OS in `XCR0` (x86_64).
+        const AVX512F = 1 << 7;
+        /// `POPCNT` (x86_64).
+        const POPCNT = 1 << 8;
+        /// Bit manipulation instructions 1 and 2 (x86_64).
+        const BMI = 1 << 9;
+        /// Enhanced `REP MOVSB`/`STOSB` (x86_64).
+        const ERMS = 1 << 10;
+        /// AES-NI (x86_64).
+        const AES_NI = 1 << 11;
+        /// `PCLMULQDQ` (x86_64).
+        const PCLMULQDQ = 1 << 12;
+        /// SHA extensions (x86_64).
+        const SHA_NI = 1 << 13;
+        /// `XSAVE` and the `XCR0` register (x86_64).
+        const XSAVE = 1 << 14;
+        /// `RDRAND` (x86_64).
+        const RDRAND = 1 << 15;
+        /// `RDSEED` (x86_64).
+        const RDSEED = 1 << 16;
+
+        // aarch64
+        /// Floating-point (aarch64).
+        const FP = 1 << 20;
+        /// Advanced SIMD, a.k.a. NEON (aarch64).
+        const NEON = 1 << 21;
+        /// Scalable Vector Extension (aarch64).
+        const SVE = 1 << 22;
+        /// AES instructions (aarch64).
+        const AES = 1 << 23;
+        /// Polynomial multiply long (aarch64).
+        const PMULL = 1 << 24;
+        /// SHA1 instructions (aarch64).
+        const SHA1 = 1 << 25;
+        /// SHA256 instructions (aarch64).
+        const SHA2 = 1 << 26;
+        /// CRC32 instructions (aarch64).
+        const CRC32 = 1 << 27;
+        /// Large System Extensions atomics (aarch64).
+        const LSE = 1 << 28;
+
+        // riscv
+        /// Single-precision floating-point, `F` (riscv).
+        const RV_F = 1 << 40;
+        /// Double-precision floating-point, `D` (riscv).
+        const RV_D = 1 << 41;
+        /// Compressed instructions, `C` (riscv).
+        const RV_C = 1 << 42;
+        /// Vector extension, `V` (riscv).
+        const RV_V = 1 << 43;
+        /// Address generation, `Zba` (riscv).
+        const RV_ZBA = 1 << 44;
+        /// Basic bit manipulation, `Zbb` (riscv).
+        const RV_ZBB = 1 << 45;
+        /// Carry-less multiplication, `Zbc` (riscv).
+        const RV_ZBC = 1 << 46;
+        /// Single-bit instructions, `Zbs` (riscv).
+        const RV_ZBS = 1 << 47;
+        /// Cache block zero, `Zicboz` (riscv).
+        const RV_ZICBOZ = 1 << 48;
+        /// Counter overflow and mode-based filtering, `Sscofpmf` (riscv).
+        const RV_SSCOFPMF = 1 << 49;
+    }
+}
+
+impl CpuFeatures {
+    /// The floating-point and SIMD features.
+    const FP_SIMD: Self = Self::from_bits_truncate(
+        Self::SSE2.bits()
+            | Self::SSE3.bits()
+            | Self::SSSE3.bits()
+            | Self::SSE4_1.bits()
+            | Self::SSE4_2.bits()
+            | Self::AVX.bits()
+            | Self::AVX2.bits()
+            | Self::AVX512F.bits()
+            | Self::AES_NI.bits()
+            | Self::PCLMULQDQ.bits()
+            | Self::SHA_NI.bits()
+            | Self::FP.bits()
+            | Self::NEON.bits()
+            | Self::SVE.bits()
+            | Self::AES.bits()
+            | Self::PMULL.bits()
+            | Self::SHA1.bits()
+            | Self::SHA2.bits()
+            | Self::RV_F.bits()
+            | Self::RV_D.bits()
+            | Self::RV_V.bits(),
+    );
+
+    fn usable(self) -> Self {
+        if cfg!(feature = "fp_simd") {
+            self
+        } else {
+            self - Self::FP_SIMD
+        }
+    }
+}
+
+/// Set in [`FEATURES`] once detected.
+const DETECTED: u64 = 1 << 63;
+
+static FEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the features of the CPU, detected on the first call.
+///
+/// On RISC-V, they are read from the ISA string of the device tree at boot,
+/// or are those the kernel is compiled for if there is none.
+pub fn features() -> CpuFeatures {
+    let bits = FEATURES.load(Ordering::Acquire);
+    if bits & DETECTED != 0 {
+        return CpuFeatures::from_bits_truncate(bits);
+    }
+    let features = detect().usable();
+    FEATURES.store(features.bits() | DETECTED, Ordering::Release);
+    features
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> CpuFeatures {
+    use raw_cpuid::CpuId;
+
+    let cpuid = CpuId::new();
+    let mut f = CpuFeatures::empty();
+    if let Some(info) = cpuid.get_feature_info() {
+        f.set(CpuFeatures::SSE2, info.has_sse2());
+        f.set(CpuFeatures::SSE3, info.has_sse3());
+        f.set(CpuFeatures::SSSE3, info.has_ssse3());
+        f.set(CpuFeatures::SSE4_1, info.has_sse41());
+        f.set(CpuFeatures::SSE4_2, info.has_sse42());
+        f.set(CpuFeatures::POPCNT, info.has_popcnt());
+        f.set(CpuFeatures::AES_NI, info.has_aesni());
+        f.set(CpuFeatures::PCLMULQDQ, info.has_pclmulqdq());
+        f.set(CpuFeatures::XSAVE, info.has_xsave());
+        f.set(CpuFeatures::RDRAND, info.has_rdrand());
+        // The AVX states must be enabled by the OS to use the instructions.
+        if info.has_oxsave() {
+            let xcr0 = xcr0();
+            f.set(CpuFeatures::AVX, info.has_avx() && xcr0 & 0b110 == 0b110);
+            f.set(CpuFeatures::AVX512F, xcr0 & 0b1110_0110 == 0b1110_0110);
+        }
+    }
+    if let Some(info) = cpuid.get_extended_feature_info() {
+        if f.contains(CpuFeatures::AVX) {
+            f.set(CpuFeatures::AVX2, info.has_avx2());
+        }
+        if !info.has_avx512f() {
+            f.remove(CpuFeatures::AVX512F);
+        }
+        f.set(CpuFeatures::BMI, info.has_bmi1() && info.has_bmi2());
+        f.set(CpuFeatures::ERMS, info.has_rep_movsb_stosb());
+        f.set(CpuFeatures::SHA_NI, info.has_sha());
+        f.set(CpuFeatures::RDSEED, info.has_rdseed());
+    } else {
+        f.remove(CpuFeatures::AVX512F);
+    }
+    f
+}
+
+#[cfg(target_arch = "x86_64")]
+fn xcr0() -> u64 {
+    let (lo, hi): (u32, u32);
+    unsafe {
+        core::arch::asm!(
+            "xgetbv",
+            in("ecx") 0,
+            out("eax") lo,
+            out("edx") hi,
+            options(nomem, nostack, preserves_flags)
+        )
+    };
+    ((hi as u64) << 32) | lo as u64
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detect() -> CpuFeatures {
+    fn field(reg: u64, shift: u32) -> u64 {
+        (reg >> shift) & 0xf
+    }
+    let (pfr0, isar0): (u64, u64);
+    unsafe {
+        core::arch::asm!("mrs {}, ID_AA64PFR0_EL1", out(reg) pfr0, options(nomem, nostack));
+        core::arch::asm!("mrs {}, ID_AA64ISAR0_EL1", out(reg) isar0, options(nomem, nostack));
+    }
+    let mut f = CpuFeatures::empty();
+    f.set(CpuFeatures::FP, field(pfr0, 16) != 0xf);
+    f.set(CpuFeatures::NEON, field(pfr0, 20) != 0xf);
+    // The SVE state is switched with the lazy FP/SIMD states only, SVE
+    // instructions trap otherwise.
+    f.set(CpuFeatures::SVE, field(pfr0, 32) != 0 && cfg!(lazy_fp));
+    f.set(CpuFeatures::AES, field(isar0, 4) >= 1);
+    f.set(CpuFeatures::PMULL, field(isar0, 4) >= 2);
+    f.set(CpuFeatures::SHA1, field(isar0, 8) >= 1);
+    f.set(CpuFeatures::SHA2, field(isar0, 12) >= 1);
+    f.set(CpuFeatures::CRC32, field(isar0, 16) >= 1);
+    f.set(CpuFeatures::LSE, field(isar0, 20) >= 2);
+    f
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn detect() -> CpuFeatures {
+    let mut f = CpuFeatures::empty();
+    f.set(CpuFeatures::RV_F, cfg!(target_feature = "f"));
+    f.set(CpuFeatures::RV_D, cfg!(target_feature = "d"));
+    f.set(CpuFeatures::RV_C, cfg!(target_feature = "c"));
+    f.set(CpuFeatures::RV_V, cfg!(target_feature = "v"));
+    f.set(CpuFeatures::RV_ZBA, cfg!(target_feature = "zba"));
+    f.set(CpuFeatures::RV_ZBB, cfg!(target_feature = "zbb"));
+    f.set(CpuFeatures::RV_ZBC, cfg!(target_feature = "zbc"));
+    f.set(CpuFeatures::RV_ZBS, cfg!(target_feature = "zbs"));
+    f
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+)))]
+fn detect() -> CpuFeatures {
+    CpuFeatures::empty()
+}
+
+/// Parses a RISC-V ISA string, like `rv64imafdc_zba_zbb`.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn parse_riscv_isa(isa: &str) -> CpuFeatures {
+    let Some(exts) = isa
+        .strip_prefix("rv64")
+        .or_else(|| isa.strip_prefix("rv32"))
+    else {
+        return CpuFeatures::empty();
+    };
+    let mut f = CpuFeatures::empty();
+    let (single, multi) = match exts.find(['z', 's', 'x', '_']) {
+        Some(i) => exts.split_at(i),
+        None => (exts, ""),
+    };
+    for c in single.chars() {
+        match c {
+            'f' => f |= CpuFeatures::RV_F,
+            'd' => f |= CpuFeatures::RV_D,
+            'g' => f |= CpuFeatures::RV_F | CpuFeatures::RV_D,
+            'c' => f |= CpuFeatures::RV_C,
+            'v' => f |= CpuFeatures::RV_V | CpuFeatures::RV_F | CpuFeatures::RV_D,
+            _ => {}
+        }
+    }
+    for ext in multi.split('_') {
+        match ext {
+            "zba" => f |= CpuFeatures::RV_ZBA,
+            "zbb" => f |= CpuFeatures::RV_ZBB,
+            "zbc" => f |= CpuFeatures::RV_ZBC,
+            "zbs" => f |= CpuFeatures::RV_ZBS,
+            "zicboz" => f |= CpuFeatures::RV_ZICBOZ,
+            "sscofpmf" => f |= CpuFeatures::RV_SSCOFPMF,
+            _ => {}
+        }
+    }
+    f
+}
+
+/// Detects the features from the `riscv,isa` property of the device tree
+/// passed by the bootloader, on the primary CPU.
+///
+/// # Safety
+///
+/// `dtb` must be 0 or the mapped address of a device tree blob.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub(crate) unsafe fn init_riscv_isa(dtb: usize) {
+    let isa = crate::fdt::Fdt::from_ptr(dtb)
+        .and_then(|fdt| fdt.find_property("riscv,isa"))
+        .and_then(|v| core::str::from_utf8(v).ok())
+        .map(|isa| isa.trim_end_matches('\0'));
+    if let Some(isa) = isa {
+        let features = parse_riscv_isa(isa).usable();
+        FEATURES.store(features.bits() | DETECTED, Ordering::Release);
+    }
+}
+
+/// A routine with several implementations, of which the first one whose
+/// required [`CpuFeatures`] are all available is selected on the first
+/// call.
+///
+/// # Examples
+///
+/// ```ignore
+/// static CHECKSUM: Dispatch<fn(&[u8]) -> u16> = Dispatch::new(
+///     &[(CpuFeatures::AVX2, checksum_avx2), (CpuFeatures::NEON, checksum_neon)],
+///     checksum_generic,
+/// );
+///
+/// let sum = (CHECKSUM.get())(data);
+/// ```
+pub struct Dispatch<F: 'static> {
+    impls: &'static [(CpuFeatures, F)],
+    fallback: F,
+    selected: AtomicUsize,
+}
+
+impl<F: Copy> Dispatch<F> {
+    const UNSELECTED: usize = usize::MAX;
+
+    /// Creates the routine from its optimized implementations, in order of
+    /// preference, and the one to use if no other can be.
+    pub const fn new(impls: &'static [(CpuFeatures, F)], fallback: F) -> Self {
+        Self {
+            impls,
+            fallback,
+            selected: AtomicUsize::new(Self::UNSELECTED),
+        }
+    }
+
+    /// Returns the selected implementation.
+    #[inline]
+    pub fn get(&self) -> F {
+        let idx = match self.selected.load(Ordering::Relaxed) {
+            Self::UNSELECTED => self.select(),
+            idx => idx,
+        };
+        self.impls.get(idx).map_or(self.fallback, |(_, f)| *f)
+    }
+
+    fn select(&self) -> usize {
+        // Calls made while detecting the features get the fallback.
+        self.selected.store(self.impls.len(), Ordering::Relaxed);
+        let features = features();
+        let idx = self
+            .impls
+            .iter()
+            .position(|(required, _)| features.contains(*required))
+            .unwrap_or(self.impls.len());
+        self.selected.store(idx, Ordering::Relaxed);
+        idx
+    }
+}
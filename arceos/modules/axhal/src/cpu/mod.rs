@@ -1,5 +1,17 @@
 //! CPU-related operations.
 
+mod features;
+#[cfg(feature = "smp")]
+mod topology;
+
+pub use self::features::{features, CpuFeatures, Dispatch};
+#[cfg(feature = "smp")]
+pub(crate) use self::topology::init_from_dtb as init_topology_from_dtb;
+#[cfg(feature = "smp")]
+pub use self::topology::{topology, CpuTopology};
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub(crate) use self::features::init_riscv_isa;
+
 #[percpu::def_percpu]
 static CPU_ID: usize = 0;
 
@@ -81,6 +93,8 @@ pub(crate) fn init_primary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(true);
     }
+    #[cfg(feature = "smp")]
+    self::topology::init_percpu(cpu_id);
 }
 
 #[allow(dead_code)]
@@ -90,4 +104,6 @@ pub(crate) fn init_secondary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(false);
     }
+    #[cfg(feature = "smp")]
+    self::topology::init_percpu(cpu_id);
 }
@@ -0,0 +1,265 @@
+//! The topology of the CPUs: the hardware threads of the cores, the cores of
+//! the clusters and the clusters of the packages.
+//!
+//! It is read from the `cpu-map` node of the device tree if there is one,
+//! otherwise from the registers of each CPU when it starts:
+//! - x86_64: the x2APIC ID and the levels of the CPUID leaf `0BH`.
+//! - aarch64: the affinity levels of `MPIDR_EL1`.
+//! - riscv: none, each hart is a core of its own.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// The place of a CPU in the topology.
+///
+/// The IDs of the packages, the clusters and the cores are unique in the
+/// system, not only in their parent, so that two CPUs are SMT siblings if
+/// their `core` are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTopology {
+    /// The ID of the package (socket).
+    pub package: u32,
+    /// The ID of the cluster, the package if there is no cluster level.
+    pub cluster: u32,
+    /// The ID of the physical core.
+    pub core: u32,
+    /// The index of the hardware thread in its core, 0 without SMT.
+    pub thread: u32,
+}
+
+impl CpuTopology {
+    /// The topology of the CPU `cpu_id` when it is unknown: a core of its
+    /// own, in a single package.
+    const fn flat(cpu_id: usize) -> Self {
+        Self {
+            package: 0,
+            cluster: 0,
+            core: cpu_id as u32,
+            thread: 0,
+        }
+    }
+
+    /// Whether `self` and `other` are hardware threads of the same core.
+    pub fn is_smt_sibling(&self, other: &Self) -> bool {
+        self.core == other.core
+    }
+}
+
+/// The package, the cluster, the core and the thread of each CPU, flat
+/// until known.
+static TOPOLOGY: [[AtomicU32; 4]; axconfig::SMP] = flat_topology();
+static FROM_DT: AtomicBool = AtomicBool::new(false);
+
+const fn flat_topology() -> [[AtomicU32; 4]; axconfig::SMP] {
+    let mut topology = [const { [const { AtomicU32::new(0) }; 4] }; axconfig::SMP];
+    let mut cpu_id = 0;
+    while cpu_id < axconfig::SMP {
+        topology[cpu_id][2] = AtomicU32::new(cpu_id as u32);
+        cpu_id += 1;
+    }
+    topology
+}
+
+fn set(cpu_id: usize, t: CpuTopology) {
+    let Some(slot) = TOPOLOGY.get(cpu_id) else {
+        return;
+    };
+    for (slot, value) in slot.iter().zip([t.package, t.cluster, t.core, t.thread]) {
+        slot.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Returns the topology of the CPU `cpu_id`, or `None` if there is no such
+/// CPU.
+pub fn topology(cpu_id: usize) -> Option<CpuTopology> {
+    let [package, cluster, core, thread] = TOPOLOGY
+        .get(cpu_id)?
+        .each_ref()
+        .map(|v| v.load(Ordering::Relaxed));
+    Some(CpuTopology {
+        package,
+        cluster,
+        core,
+        thread,
+    })
+}
+
+/// Records the topology of the current CPU `cpu_id` from its registers,
+/// unless it was read from the device tree.
+pub(crate) fn init_percpu(cpu_id: usize) {
+    if !FROM_DT.load(Ordering::Acquire) {
+        set(cpu_id, arch_topology(cpu_id));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn arch_topology(cpu_id: usize) -> CpuTopology {
+    use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+    const LEVEL_SMT: u32 = 1;
+    const LEVEL_CORE: u32 = 2;
+    if unsafe { __cpuid(0) }.eax < 0xb || unsafe { __cpuid_count(0xb, 0) }.ebx & 0xffff == 0 {
+        return CpuTopology::flat(cpu_id);
+    }
+    let x2apic_id = unsafe { __cpuid_count(0xb, 0) }.edx;
+    let (mut smt_shift, mut core_shift) = (0, 0);
+    for subleaf in 0..8 {
+        let leaf = unsafe { __cpuid_count(0xb, subleaf) };
+        match (leaf.ecx >> 8) & 0xff {
+            LEVEL_SMT => smt_shift = leaf.eax & 0x1f,
+            LEVEL_CORE => core_shift = leaf.eax & 0x1f,
+            0 => break,
+            _ => {}
+        }
+    }
+    let core_shift = core_shift.max(smt_shift);
+    let package = x2apic_id.checked_shr(core_shift).unwrap_or(0);
+    CpuTopology {
+        package,
+        cluster: package,
+        core: x2apic_id >> smt_shift,
+        thread: x2apic_id & ((1 << smt_shift) - 1),
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn arch_topology(_cpu_id: usize) -> CpuTopology {
+    use aarch64_cpu::registers::MPIDR_EL1;
+    use tock_registers::interfaces::Readable;
+
+    const MPIDR_MT: u64 = 1 << 24;
+    let mpidr = MPIDR_EL1.get();
+    let aff = |level: u32| {
+        let shift = if level == 3 { 32 } else { level * 8 };
+        ((mpidr >> shift) & 0xff) as u32
+    };
+    if mpidr & MPIDR_MT != 0 {
+        // Aff0 is the thread, Aff1 the core, Aff2 the cluster.
+        CpuTopology {
+            package: aff(3),
+            cluster: aff(2) | (aff(3) << 8),
+            core: aff(1) | (aff(2) << 8) | (aff(3) << 16),
+            thread: aff(0),
+        }
+    } else {
+        CpuTopology {
+            package: aff(2),
+            cluster: aff(1) | (aff(2) << 8),
+            core: aff(0) | (aff(1) << 8) | (aff(2) << 16),
+            thread: 0,
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn arch_topology(cpu_id: usize) -> CpuTopology {
+    CpuTopology::flat(cpu_id)
+}
+
+/// Reads the topology of all the CPUs from the `cpu-map` node of the device
+/// tree, on the primary CPU, if there is one.
+///
+/// The CPU of a `cpu` node is the low 24 bits of its `reg`, its hart ID or
+/// its affinity bits of `MPIDR_EL1`.
+///
+/// # Safety
+///
+/// `dtb` must be 0 or the mapped address of a device tree blob.
+#[allow(dead_code)]
+pub(crate) unsafe fn init_from_dtb(dtb: usize) {
+    use crate::fdt::{Fdt, Token};
+
+    let Some(fdt) = Fdt::from_ptr(dtb) else {
+        return;
+    };
+    let (Some(cpus), Some(map)) = (fdt.find_node("/cpus"), fdt.find_node("/cpus/cpu-map")) else {
+        return;
+    };
+
+    // The phandles of the `cpu` nodes and their CPU IDs.
+    let address_cells = cpus.property_u32("#address-cells").unwrap_or(1);
+    let mut phandles = [(0u32, 0usize); axconfig::SMP];
+    let mut nr_cpus = 0;
+    let mut depth = 0;
+    let mut phandle = None;
+    let mut reg = None;
+    cpus.walk(|token| match token {
+        Token::BeginNode(name) => {
+            depth += 1;
+            if depth == 1 && name.starts_with("cpu@") {
+                phandle = None;
+                reg = None;
+            }
+        }
+        Token::Prop("phandle", value) if depth == 1 => {
+            phandle = value
+                .get(..4)
+                .map(|v| u32::from_be_bytes(v.try_into().unwrap()));
+        }
+        Token::Prop("reg", value) if depth == 1 => {
+            // The low cell of the address.
+            let off = (address_cells.max(1) as usize - 1) * 4;
+            reg = value
+                .get(off..off + 4)
+                .map(|v| u32::from_be_bytes(v.try_into().unwrap()) as usize & 0xff_ffff);
+        }
+        Token::Prop(..) => {}
+        Token::EndNode => {
+            if depth == 1 {
+                if let (Some(phandle), Some(cpu_id)) = (phandle.take(), reg.take()) {
+                    if nr_cpus < phandles.len() && cpu_id < axconfig::SMP {
+                        phandles[nr_cpus] = (phandle, cpu_id);
+                        nr_cpus += 1;
+                    }
+                }
+            }
+            depth -= 1;
+        }
+    });
+    if nr_cpus == 0 {
+        return;
+    }
+
+    // `socketN`, `clusterN`, `coreN` and `threadN`, numbered in the system.
+    let (mut sockets, mut clusters, mut cores) = (0, 0, 0);
+    let mut current = CpuTopology::flat(0);
+    let mut mapped = [false; axconfig::SMP];
+    map.walk(|token| match token {
+        Token::BeginNode(name) => {
+            let index = name.trim_start_matches(|c: char| !c.is_ascii_digit());
+            if name.starts_with("socket") {
+                current.package = sockets;
+                sockets += 1;
+            } else if name.starts_with("cluster") {
+                current.cluster = clusters;
+                clusters += 1;
+            } else if name.starts_with("core") {
+                current.core = cores;
+                current.thread = 0;
+                cores += 1;
+            } else if name.starts_with("thread") {
+                current.thread = index.parse().unwrap_or(0);
+            }
+        }
+        Token::Prop("cpu", value) => {
+            let Some(target) = value.get(..4) else {
+                return;
+            };
+            let target = u32::from_be_bytes(target.try_into().unwrap());
+            if let Some(&(_, cpu_id)) = phandles[..nr_cpus].iter().find(|(p, _)| *p == target) {
+                set(cpu_id, current);
+                mapped[cpu_id] = true;
+            }
+        }
+        _ => {}
+    });
+    if !mapped.contains(&true) {
+        return;
+    }
+    // The CPUs out of the map are cores of their own.
+    for cpu_id in (0..axconfig::SMP).filter(|&id| !mapped[id]) {
+        let mut t = CpuTopology::flat(cpu_id);
+        t.core += cores;
+        set(cpu_id, t);
+    }
+    FROM_DT.store(true, Ordering::Release);
+}
@@ -0,0 +1,295 @@
+//! Early console.
+//!
+//! When the command line of the kernel has an `earlycon` parameter, the
+//! console output goes to the UART it describes from the entry of the
+//! primary CPU on, before the platform devices and the drivers are
+//! initialized, so that early failures are not silent. The command line is
+//! the `bootargs` property of the `/chosen` node of the device tree, or the
+//! one given by the boot loader on x86 (by Multiboot, Multiboot2 or the Linux
+//! boot protocol).
+//!
+//! The parameter follows the syntax of Linux:
+//!
+//! - `earlycon`: the UART of the `stdout-path` property of `/chosen`.
+//! - `earlycon=pl011,[mmio32,]<addr>`: an ARM PL011.
+//! - `earlycon=uart8250,<io|mmio|mmio32>,<addr>`: a 8250/16550 with I/O ports
+//!   (x86 only), or registers of 8 or 32 bits every 1 or 4 bytes. `uart`,
+//!   `ns16550` and `ns16550a` are aliases of `uart8250`.
+//! - `earlycon=sbi`: the console of the SBI (riscv).
+//!
+//! The UART is used as configured by the firmware, and must be mapped by the
+//! boot page table: in the first GiB of the physical memory, or in the
+//! device memory of the platform on aarch64. It is mapped by the kernel page
+//! table too (see [`mem::memory_regions`](crate::mem::memory_regions)).
+//!
+//! [`platform_init`](crate::platform_init) gives the output back to the
+//! platform console, unless the command line has `keep_bootcon`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+use memory_addr::{align_up_4k, MemoryAddr, PhysAddr};
+
+use crate::mem::{phys_to_virt, MemRegion, MemRegionFlags};
+
+/// A UART used as the early console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyConsole {
+    /// An ARM PL011 at the address.
+    Pl011(PhysAddr),
+    /// A 8250/16550 with memory-mapped registers.
+    Uart8250Mmio {
+        /// Address of the registers.
+        base: PhysAddr,
+        /// The registers are `1 << reg_shift` bytes apart.
+        reg_shift: u8,
+        /// Size in bytes of the accesses to the registers, 1 or 4.
+        io_width: u8,
+    },
+    /// A 8250/16550 with I/O ports from the one given.
+    #[cfg(target_arch = "x86_64")]
+    Uart8250Io(u16),
+    /// The console of the SBI.
+    #[cfg(target_arch = "riscv64")]
+    Sbi,
+}
+
+static EARLYCON: SpinNoIrq<Option<EarlyConsole>> = SpinNoIrq::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static KEEP: AtomicBool = AtomicBool::new(false);
+
+/// Returns the early console, if any is used.
+pub fn current() -> Option<EarlyConsole> {
+    if ACTIVE.load(Ordering::Acquire) {
+        *EARLYCON.lock()
+    } else {
+        None
+    }
+}
+
+/// Writes a byte to the early console, returns `false` if there is none.
+pub(crate) fn putchar(c: u8) -> bool {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return false;
+    }
+    let guard = EARLYCON.lock();
+    let Some(con) = *guard else {
+        return false;
+    };
+    if c == b'\n' {
+        con.putchar(b'\r');
+    }
+    con.putchar(c);
+    true
+}
+
+/// Stops using the early console for the platform one, unless asked to keep
+/// it.
+pub(crate) fn handover() {
+    if !KEEP.load(Ordering::Relaxed) && ACTIVE.swap(false, Ordering::AcqRel) {
+        *EARLYCON.lock() = None;
+    }
+}
+
+/// Returns the registers of the early console, if it is not in a MMIO region
+/// of the platform.
+pub(crate) fn mmio_region() -> Option<MemRegion> {
+    let (paddr, size) = match current()? {
+        EarlyConsole::Pl011(base) => (base, 0x1000),
+        EarlyConsole::Uart8250Mmio {
+            base, reg_shift, ..
+        } => (base, 8 << reg_shift),
+        #[allow(unreachable_patterns)]
+        _ => return None,
+    };
+    let paddr = paddr.align_down_4k();
+    let size = align_up_4k(size);
+    let start = paddr.as_usize();
+    if axconfig::MMIO_REGIONS
+        .iter()
+        .any(|&(base, len)| base <= start && start + size <= base + len)
+    {
+        return None;
+    }
+    Some(MemRegion {
+        paddr,
+        size,
+        flags: MemRegionFlags::RESERVED
+            | MemRegionFlags::DEVICE
+            | MemRegionFlags::READ
+            | MemRegionFlags::WRITE,
+        name: "earlycon",
+    })
+}
+
+impl EarlyConsole {
+    /// Parses the value of the `earlycon` parameter.
+    fn parse(spec: &str) -> Option<Self> {
+        let mut args = spec.split(',');
+        let name = args.next()?;
+        let parse_addr = |s: &str| match s.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        };
+        match name {
+            #[cfg(target_arch = "riscv64")]
+            "sbi" => Some(Self::Sbi),
+            "pl011" => {
+                let mut arg = args.next()?;
+                if arg == "mmio32" {
+                    arg = args.next()?;
+                }
+                Some(Self::Pl011(parse_addr(arg)?.into()))
+            }
+            "uart8250" | "uart" | "ns16550" | "ns16550a" => {
+                let (iotype, addr) = match args.next()? {
+                    iotype @ ("io" | "mmio" | "mmio32") => (iotype, args.next()?),
+                    addr => ("mmio", addr),
+                };
+                let addr = parse_addr(addr)?;
+                match iotype {
+                    #[cfg(target_arch = "x86_64")]
+                    "io" => Some(Self::Uart8250Io(addr.try_into().ok()?)),
+                    "mmio" => Some(Self::Uart8250Mmio {
+                        base: addr.into(),
+                        reg_shift: 0,
+                        io_width: 1,
+                    }),
+                    "mmio32" => Some(Self::Uart8250Mmio {
+                        base: addr.into(),
+                        reg_shift: 2,
+                        io_width: 4,
+                    }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Finds the UART of the `stdout-path` property of `/chosen`.
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    fn from_stdout_path(fdt: &crate::fdt::Fdt) -> Option<Self> {
+        let path = fdt.find_node("/chosen")?.property_str("stdout-path")?;
+        // Without the options, e.g. `serial0:115200n8`.
+        let path = path.split(':').next()?;
+        let path = if path.starts_with('/') {
+            path
+        } else {
+            fdt.find_node("/aliases")?.property_str(path)?
+        };
+        let node = fdt.find_node(path)?;
+        let base = node.reg_address()?.into();
+        if node.is_compatible("arm,pl011") {
+            return Some(Self::Pl011(base));
+        }
+        ["ns16550a", "ns16550", "ns8250", "snps,dw-apb-uart"]
+            .iter()
+            .any(|c| node.is_compatible(c))
+            .then(|| Self::Uart8250Mmio {
+                base,
+                reg_shift: node.property_u32("reg-shift").unwrap_or(0) as u8,
+                io_width: node.property_u32("reg-io-width").unwrap_or(1) as u8,
+            })
+    }
+
+    fn putchar(&self, c: u8) {
+        match *self {
+            Self::Pl011(base) => unsafe {
+                const DR: usize = 0x00;
+                const FR: usize = 0x18;
+                const FR_TXFF: u32 = 1 << 5;
+                let base = phys_to_virt(base).as_usize();
+                while ((base + FR) as *const u32).read_volatile() & FR_TXFF != 0 {
+                    core::hint::spin_loop();
+                }
+                ((base + DR) as *mut u32).write_volatile(c as u32);
+            },
+            Self::Uart8250Mmio {
+                base,
+                reg_shift,
+                io_width,
+            } => unsafe {
+                const THR: usize = 0;
+                const LSR: usize = 5;
+                const LSR_THRE: u32 = 1 << 5;
+                let base = phys_to_virt(base).as_usize();
+                let reg = |r: usize| base + (r << reg_shift);
+                let read = |r: usize| match io_width {
+                    4 => (reg(r) as *const u32).read_volatile(),
+                    _ => (reg(r) as *const u8).read_volatile() as u32,
+                };
+                while read(LSR) & LSR_THRE == 0 {
+                    core::hint::spin_loop();
+                }
+                match io_width {
+                    4 => (reg(THR) as *mut u32).write_volatile(c as u32),
+                    _ => (reg(THR) as *mut u8).write_volatile(c),
+                }
+            },
+            #[cfg(target_arch = "x86_64")]
+            Self::Uart8250Io(port) => unsafe {
+                use x86_64::instructions::port::Port;
+                const LSR_THRE: u8 = 1 << 5;
+                let mut lsr = Port::<u8>::new(port + 5);
+                while lsr.read() & LSR_THRE == 0 {
+                    core::hint::spin_loop();
+                }
+                Port::<u8>::new(port).write(c);
+            },
+            #[cfg(target_arch = "riscv64")]
+            Self::Sbi => crate::arch::sbi::console_putchar(c),
+        }
+    }
+}
+
+fn init(cmdline: &str, from_stdout_path: impl FnOnce() -> Option<EarlyConsole>) {
+    let mut args = cmdline.split_ascii_whitespace();
+    let Some(arg) = args
+        .clone()
+        .find(|a| *a == "earlycon" || a.starts_with("earlycon="))
+    else {
+        return;
+    };
+    let con = match arg.strip_prefix("earlycon=") {
+        Some(spec) => EarlyConsole::parse(spec),
+        None => from_stdout_path(),
+    };
+    if let Some(con) = con {
+        KEEP.store(args.any(|a| a == "keep_bootcon"), Ordering::Relaxed);
+        *EARLYCON.lock() = Some(con);
+        ACTIVE.store(true, Ordering::Release);
+    }
+}
+
+/// Sets up the early console from the device tree at the physical address
+/// `dtb`.
+///
+/// # Safety
+///
+/// `dtb` must be 0 or the address of a device tree blob in the memory mapped
+/// by the boot page table.
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+pub(crate) unsafe fn init_from_dtb(dtb: usize) {
+    if dtb == 0 {
+        return;
+    }
+    let Some(fdt) = crate::fdt::Fdt::from_ptr(phys_to_virt(dtb.into()).as_usize()) else {
+        return;
+    };
+    let cmdline = fdt
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property_str("bootargs"));
+    if let Some(cmdline) = cmdline {
+        crate::cmdline::set(cmdline);
+        init(cmdline, || EarlyConsole::from_stdout_path(&fdt));
+    }
+}
+
+/// Sets up the early console from the command line given by the boot loader
+/// on x86.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn init_from_cmdline(cmdline: &str) {
+    crate::cmdline::set(cmdline);
+    init(cmdline, || None);
+}
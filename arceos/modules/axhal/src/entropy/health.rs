@@ -0,0 +1,75 @@
+//! The continuous health tests of NIST SP 800-90B (section 4.4), run on the
+//! raw samples of every source.
+
+/// The window of the adaptive proportion test.
+const APT_WINDOW: u32 = 512;
+
+/// The cutoffs of the adaptive proportion test, by the min-entropy of a
+/// sample in bits: the critical values of the binomial distribution over the
+/// window for a false positive probability of 2^-20.
+const APT_CUTOFFS: [u32; 8] = [311, 177, 103, 62, 39, 25, 18, 13];
+
+/// The health of a source, from its samples so far.
+pub(crate) struct HealthTests {
+    rct_cutoff: u32,
+    apt_cutoff: u32,
+    last: Option<u64>,
+    /// The samples in a row equal to `last`.
+    repeats: u32,
+    /// The first sample of the window of the adaptive proportion test.
+    apt_first: u64,
+    apt_seen: u32,
+    apt_matches: u32,
+}
+
+impl HealthTests {
+    /// Creates the tests of a source of `entropy_bits` bits per sample.
+    pub const fn new(entropy_bits: u32) -> Self {
+        let bits = if entropy_bits == 0 {
+            1
+        } else if entropy_bits > 8 {
+            8
+        } else {
+            entropy_bits
+        };
+        Self {
+            // Twenty bits of the assessed entropy repeated, as likely as a
+            // false positive of 2^-20.
+            rct_cutoff: 1 + 20u32.div_ceil(bits),
+            apt_cutoff: APT_CUTOFFS[bits as usize - 1],
+            last: None,
+            repeats: 0,
+            apt_first: 0,
+            apt_seen: 0,
+            apt_matches: 0,
+        }
+    }
+
+    /// Tests a new sample, returns `false` as soon as a test fails.
+    pub fn check(&mut self, sample: u64) -> bool {
+        // Repetition count test: the same sample too many times in a row.
+        if self.last == Some(sample) {
+            self.repeats += 1;
+            if self.repeats >= self.rct_cutoff {
+                return false;
+            }
+        } else {
+            self.last = Some(sample);
+            self.repeats = 1;
+        }
+
+        // Adaptive proportion test: the first sample of a window too often
+        // in it.
+        if self.apt_seen == 0 {
+            self.apt_first = sample;
+            self.apt_matches = 1;
+        } else if sample == self.apt_first {
+            self.apt_matches += 1;
+            if self.apt_matches >= self.apt_cutoff {
+                return false;
+            }
+        }
+        self.apt_seen = (self.apt_seen + 1) % APT_WINDOW;
+        true
+    }
+}
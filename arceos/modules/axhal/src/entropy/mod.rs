@@ -0,0 +1,312 @@
+//! The entropy pool, fed by pluggable sources, with the `entropy` feature.
+//!
+//! The sources are polled for raw samples, which go through the continuous
+//! health tests of NIST SP 800-90B of their source: a source failing them,
+//! e.g. stuck on a value, is disabled. The samples passing are mixed in a
+//! BLAKE2s pool, credited with the min-entropy assessed by their source.
+//! The built-in sources, registered by [`init`], are:
+//!
+//! - `jitter`: the jitter of the execution time of memory accesses
+//!   ([`JitterEntropy`]), on all the platforms.
+//! - `rdseed` or `rdrand`: the RNG instructions of x86_64 ([`CpuRng`]).
+//! - `irq-timing`: the arrival times of the interrupts, with the `irq`
+//!   feature.
+//!
+//! Any other source, e.g. the RNG device of a driver, is added with
+//! [`register_source`].
+//!
+//! The random bytes are the output of a ChaCha20 generator, whose key is
+//! erased after each request, reseeded from the pool when 256 bits are
+//! credited to it, at most once a minute. Until the first 256 bits, the pool
+//! is not seeded but the generator still gives bytes, reseeded by whatever
+//! is collected as `/dev/urandom` of Linux does: [`is_seeded`] tells when
+//! they are fit for keys.
+
+mod health;
+mod sources;
+
+#[cfg(feature = "irq")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axcrypto::{blake2s, chacha20poly1305};
+use kspin::SpinNoIrq;
+
+use self::health::HealthTests;
+use crate::time::monotonic_time_nanos;
+
+#[cfg(target_arch = "x86_64")]
+pub use self::sources::CpuRng;
+pub use self::sources::JitterEntropy;
+
+/// A source of raw entropy samples.
+pub trait EntropySource: Send + Sync {
+    /// The name of the source.
+    fn name(&self) -> &'static str;
+
+    /// The min-entropy of a sample assessed, in bits, at most 64.
+    fn entropy_bits(&self) -> u32;
+
+    /// Returns a raw sample, or `None` if none is available now.
+    fn sample(&self) -> Option<u64>;
+}
+
+/// The maximum number of sources.
+pub const MAX_SOURCES: usize = 8;
+
+/// The bits credited to the pool to seed the generator.
+const SEED_BITS: u64 = 256;
+/// The minimum interval between two reseeds of a seeded generator.
+const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+/// The rounds of polls of the sources by [`init`] to seed the pool.
+const INIT_ROUNDS: usize = 64;
+/// The bytes generated with the pool locked.
+const CHUNK_LEN: usize = 4096;
+/// The interrupts folded in a sample of the `irq-timing` source.
+#[cfg(feature = "irq")]
+const IRQ_FOLD: u32 = 64;
+
+/// The statistics of a source.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceStats {
+    /// The name of the source.
+    pub name: &'static str,
+    /// The min-entropy of a sample assessed, in bits.
+    pub entropy_bits: u32,
+    /// The samples mixed in the pool.
+    pub samples: u64,
+    /// Whether the source failed its health tests, and is disabled.
+    pub failed: bool,
+}
+
+struct Slot {
+    /// The source polled, `None` for the interrupts, which push their samples.
+    source: Option<&'static dyn EntropySource>,
+    health: HealthTests,
+    stats: SourceStats,
+}
+
+struct Pool {
+    hasher: blake2s::Blake2s,
+    /// The bits credited since the last reseed.
+    credit: u64,
+    key: [u8; chacha20poly1305::KEY_LEN],
+    seeded: bool,
+    last_reseed: u64,
+}
+
+#[cfg(feature = "irq")]
+struct IrqTiming {
+    last: u64,
+    acc: u64,
+    count: u32,
+}
+
+static SOURCES: SpinNoIrq<[Option<Slot>; MAX_SOURCES]> =
+    SpinNoIrq::new([const { None }; MAX_SOURCES]);
+static POOL: SpinNoIrq<Option<Pool>> = SpinNoIrq::new(None);
+#[cfg(feature = "irq")]
+static IRQ_TIMING: SpinNoIrq<IrqTiming> = SpinNoIrq::new(IrqTiming {
+    last: 0,
+    acc: 0,
+    count: 0,
+});
+/// The slot of the `irq-timing` source, once registered.
+#[cfg(feature = "irq")]
+static IRQ_SLOT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+impl Pool {
+    fn new() -> Self {
+        Self {
+            hasher: blake2s::Blake2s::new(blake2s::OUT_LEN),
+            credit: 0,
+            key: [0; chacha20poly1305::KEY_LEN],
+            seeded: false,
+            last_reseed: 0,
+        }
+    }
+
+    fn mix(&mut self, sample: u64, bits: u32) {
+        self.hasher.update(&sample.to_le_bytes());
+        self.credit = self.credit.saturating_add(bits as u64);
+    }
+
+    fn reseed_due(&self, now: u64) -> bool {
+        !self.seeded || now.saturating_sub(self.last_reseed) >= RESEED_INTERVAL.as_nanos() as u64
+    }
+
+    /// Derives a new key from the pool, once enough is credited, or with
+    /// whatever is in it until seeded.
+    fn reseed(&mut self, now: u64) {
+        if self.credit < SEED_BITS && (self.seeded || self.credit == 0) {
+            return;
+        }
+        let hasher = core::mem::replace(&mut self.hasher, blake2s::Blake2s::new(blake2s::OUT_LEN));
+        self.key = blake2s::hash(&[&self.key, &hasher.finalize()]);
+        if self.credit >= SEED_BITS && !self.seeded {
+            info!("entropy: pool seeded");
+            self.seeded = true;
+        }
+        self.credit = 0;
+        self.last_reseed = now;
+    }
+
+    /// Fills `buf` with the keystream, then replaces the key by the next
+    /// block of it, so that the bytes given cannot be found back.
+    fn generate(&mut self, buf: &mut [u8]) {
+        const NONCE: [u8; chacha20poly1305::NONCE_LEN] = [0; chacha20poly1305::NONCE_LEN];
+        buf.fill(0);
+        chacha20poly1305::chacha20(&self.key, &NONCE, 1, buf);
+        let mut key = [0; chacha20poly1305::KEY_LEN];
+        chacha20poly1305::chacha20(&self.key, &NONCE, 0, &mut key);
+        self.key = key;
+    }
+}
+
+fn with_pool<T>(f: impl FnOnce(&mut Pool) -> T) -> T {
+    f(POOL.lock().get_or_insert_with(Pool::new))
+}
+
+/// Adds a source of entropy, polled from now on.
+///
+/// It returns `false` if there are already [`MAX_SOURCES`] sources.
+pub fn register_source(source: &'static dyn EntropySource) -> bool {
+    add_slot(source.name(), Some(source), source.entropy_bits()).is_some()
+}
+
+fn add_slot(
+    name: &'static str,
+    source: Option<&'static dyn EntropySource>,
+    entropy_bits: u32,
+) -> Option<usize> {
+    let mut sources = SOURCES.lock();
+    let Some(idx) = sources.iter().position(|s| s.is_none()) else {
+        warn!("entropy: no room for the source {}", name);
+        return None;
+    };
+    let entropy_bits = entropy_bits.min(64);
+    sources[idx] = Some(Slot {
+        source,
+        health: HealthTests::new(entropy_bits),
+        stats: SourceStats {
+            name,
+            entropy_bits,
+            samples: 0,
+            failed: false,
+        },
+    });
+    info!("entropy: source {} added", name);
+    Some(idx)
+}
+
+/// Runs the health tests of the source `idx` on `sample`, and mixes it in
+/// the pool if they pass.
+fn feed(idx: usize, sample: u64) {
+    let mut sources = SOURCES.lock();
+    let Some(slot) = sources[idx].as_mut() else {
+        return;
+    };
+    if slot.stats.failed {
+        return;
+    }
+    if !slot.health.check(sample) {
+        error!(
+            "entropy: source {} failed its health tests, disabled",
+            slot.stats.name
+        );
+        slot.stats.failed = true;
+        return;
+    }
+    slot.stats.samples += 1;
+    let bits = slot.stats.entropy_bits;
+    with_pool(|pool| pool.mix(sample, bits));
+}
+
+/// Polls every source `rounds` times, or until the pool is seeded.
+fn poll_sources(rounds: usize) {
+    for _ in 0..rounds {
+        for idx in 0..MAX_SOURCES {
+            // The sources are polled unlocked, they may be slow.
+            let source = match &SOURCES.lock()[idx] {
+                Some(slot) if !slot.stats.failed => slot.source,
+                _ => None,
+            };
+            if let Some(sample) = source.and_then(|s| s.sample()) {
+                feed(idx, sample);
+            }
+        }
+        if with_pool(|pool| pool.credit >= SEED_BITS) {
+            break;
+        }
+    }
+}
+
+/// Registers the built-in sources, and seeds the pool from them.
+pub fn init() {
+    static JITTER: JitterEntropy = JitterEntropy::new();
+
+    #[cfg(feature = "irq")]
+    if let Some(idx) = add_slot("irq-timing", None, 1) {
+        IRQ_SLOT.store(idx, Ordering::Relaxed);
+    }
+    register_source(&JITTER);
+    #[cfg(target_arch = "x86_64")]
+    if let Some(rng) = CpuRng::detect() {
+        register_source(rng);
+    }
+
+    poll_sources(INIT_ROUNDS);
+    with_pool(|pool| pool.reseed(monotonic_time_nanos()));
+    if !is_seeded() {
+        warn!("entropy: pool not seeded at boot, the random bytes are weak until it is");
+    }
+}
+
+/// Mixes the arrival time of an interrupt in the pool.
+///
+/// The time deltas of [`IRQ_FOLD`] interrupts are folded in a sample of one
+/// bit: the deltas of a periodic interrupt alone repeat, their jitter is what
+/// is collected.
+#[cfg(feature = "irq")]
+pub(crate) fn add_interrupt_timing(irq_num: usize) {
+    let now = crate::time::current_ticks();
+    let mut irq = IRQ_TIMING.lock();
+    let delta = now.wrapping_sub(irq.last);
+    irq.last = now;
+    irq.acc = irq.acc.rotate_left(7) ^ delta ^ ((irq_num as u64) << 56);
+    irq.count += 1;
+    if irq.count == IRQ_FOLD {
+        let sample = irq.acc;
+        irq.count = 0;
+        drop(irq);
+        let idx = IRQ_SLOT.load(Ordering::Relaxed);
+        if idx < MAX_SOURCES {
+            feed(idx, sample);
+        }
+    }
+}
+
+/// Fills `buf` with random bytes from the generator.
+pub fn fill_bytes(buf: &mut [u8]) {
+    let now = monotonic_time_nanos();
+    if with_pool(|pool| pool.reseed_due(now)) {
+        poll_sources(1);
+        with_pool(|pool| pool.reseed(now));
+    }
+    for chunk in buf.chunks_mut(CHUNK_LEN) {
+        with_pool(|pool| pool.generate(chunk));
+    }
+}
+
+/// Whether the pool was seeded with 256 bits, so that the random bytes are
+/// fit for keys.
+pub fn is_seeded() -> bool {
+    with_pool(|pool| pool.seeded)
+}
+
+/// Calls `f` with the statistics of every source.
+pub fn for_each_source(mut f: impl FnMut(&SourceStats)) {
+    for slot in SOURCES.lock().iter().flatten() {
+        f(&slot.stats);
+    }
+}
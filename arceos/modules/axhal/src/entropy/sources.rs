@@ -0,0 +1,162 @@
+//! The built-in sources: the jitter of the CPU timings, and the RNG
+//! instructions of the CPU.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::EntropySource;
+use crate::time::current_ticks;
+
+/// The deltas folded in a sample of [`JitterEntropy`].
+const JITTER_DELTAS: usize = 64;
+/// The attempts to get a sample, most of the deltas being stuck.
+const JITTER_ATTEMPTS: usize = 4 * JITTER_DELTAS;
+
+/// A collector of the jitter of the execution time of memory accesses, like
+/// the `jitterentropy` of Linux, available without any hardware RNG.
+///
+/// A sample folds the time deltas of 64 runs of a loop of memory accesses,
+/// whose duration varies with the state of the caches, the pipelines and the
+/// bus. The stuck deltas, whose first, second or third derivative is zero,
+/// are skipped: on a timer too coarse to see the jitter, no sample is given.
+pub struct JitterEntropy {
+    /// The memory accessed, its content is the state of the loop.
+    mem: [AtomicU8; 2048],
+}
+
+impl JitterEntropy {
+    /// Creates a collector.
+    pub const fn new() -> Self {
+        Self {
+            mem: [const { AtomicU8::new(0) }; 2048],
+        }
+    }
+
+    /// Runs the loop of memory accesses, a number of times given by the last
+    /// delta.
+    fn access_memory(&self, delta: u64) {
+        let rounds = 1 + (delta & 0xf) as usize;
+        let mut idx = delta as usize;
+        for _ in 0..rounds * 64 {
+            idx = idx.wrapping_add(67) % self.mem.len();
+            let b = self.mem[idx].load(Ordering::Relaxed);
+            self.mem[idx].store(b.wrapping_add(1).rotate_left(3), Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for JitterEntropy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntropySource for JitterEntropy {
+    fn name(&self) -> &'static str {
+        "jitter"
+    }
+
+    /// An eighth of a bit per delta folded.
+    fn entropy_bits(&self) -> u32 {
+        (JITTER_DELTAS / 8) as u32
+    }
+
+    fn sample(&self) -> Option<u64> {
+        let mut sample = 0u64;
+        let mut folded = 0;
+        let (mut last, mut delta, mut delta2) = (current_ticks(), 0u64, 0u64);
+        for _ in 0..JITTER_ATTEMPTS {
+            self.access_memory(delta);
+            let now = current_ticks();
+            let new_delta = now.wrapping_sub(last);
+            let new_delta2 = new_delta.wrapping_sub(delta);
+            let delta3 = new_delta2.wrapping_sub(delta2);
+            (last, delta, delta2) = (now, new_delta, new_delta2);
+            if new_delta == 0 || new_delta2 == 0 || delta3 == 0 {
+                continue;
+            }
+            sample = sample.rotate_left(7) ^ new_delta;
+            folded += 1;
+            if folded == JITTER_DELTAS {
+                return Some(sample);
+            }
+        }
+        None
+    }
+}
+
+/// The `RDSEED` instruction of x86_64, or `RDRAND` without it.
+#[cfg(target_arch = "x86_64")]
+pub struct CpuRng {
+    rdseed: bool,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl CpuRng {
+    /// Returns the RNG of the CPU, if it has the instructions.
+    pub fn detect() -> Option<&'static Self> {
+        use crate::cpu::{features, CpuFeatures};
+        static RDSEED: CpuRng = CpuRng { rdseed: true };
+        static RDRAND: CpuRng = CpuRng { rdseed: false };
+
+        let features = features();
+        if features.contains(CpuFeatures::RDSEED) {
+            Some(&RDSEED)
+        } else if features.contains(CpuFeatures::RDRAND) {
+            Some(&RDRAND)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl EntropySource for CpuRng {
+    fn name(&self) -> &'static str {
+        if self.rdseed {
+            "rdseed"
+        } else {
+            "rdrand"
+        }
+    }
+
+    /// Half of the bits of `RDSEED`, and a quarter of those of `RDRAND`, the
+    /// output of a DRBG: neither is trusted alone.
+    fn entropy_bits(&self) -> u32 {
+        if self.rdseed {
+            32
+        } else {
+            16
+        }
+    }
+
+    fn sample(&self) -> Option<u64> {
+        use core::arch::x86_64::{_rdrand64_step, _rdseed64_step};
+
+        #[target_feature(enable = "rdseed")]
+        unsafe fn rdseed(out: &mut u64) -> i32 {
+            _rdseed64_step(out)
+        }
+        #[target_feature(enable = "rdrand")]
+        unsafe fn rdrand(out: &mut u64) -> i32 {
+            _rdrand64_step(out)
+        }
+
+        // The instructions fail while the hardware is out of entropy, which
+        // Intel says is transient.
+        let mut value = 0;
+        for _ in 0..10 {
+            let ok = unsafe {
+                if self.rdseed {
+                    rdseed(&mut value)
+                } else {
+                    rdrand(&mut value)
+                }
+            };
+            if ok == 1 {
+                return Some(value);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+}
@@ -0,0 +1,218 @@
+//! A minimal reader of flattened device trees, for the boot code that runs
+//! before the allocator and the drivers.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// A device tree blob.
+#[derive(Clone, Copy)]
+pub(crate) struct Fdt {
+    data: &'static [u8],
+    strings: &'static [u8],
+    structs: usize,
+}
+
+/// A token of the structure block.
+#[derive(Clone, Copy)]
+pub(crate) enum Token {
+    BeginNode(&'static str),
+    EndNode,
+    Prop(&'static str, &'static [u8]),
+}
+
+/// A node of the tree.
+#[derive(Clone, Copy)]
+pub(crate) struct Node {
+    fdt: Fdt,
+    /// Offset of the first token after the node name.
+    off: usize,
+    /// `#address-cells` of the parent, the size of the addresses in `reg`.
+    address_cells: u32,
+}
+
+fn be32(bytes: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(off..off + 4)?.try_into().ok()?,
+    ))
+}
+
+fn cstr(bytes: &'static [u8]) -> Option<&'static str> {
+    let len = bytes.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+impl Fdt {
+    /// Reads the blob at `dtb`, returns `None` if there is none.
+    ///
+    /// # Safety
+    ///
+    /// `dtb` must be 0 or the mapped address of a device tree blob.
+    pub unsafe fn from_ptr(dtb: usize) -> Option<Self> {
+        if dtb == 0 {
+            return None;
+        }
+        let header = core::slice::from_raw_parts(dtb as *const u8, 40);
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let data = core::slice::from_raw_parts(dtb as *const u8, be32(header, 4)? as usize);
+        Some(Self {
+            data,
+            strings: data.get(be32(header, 12)? as usize..)?,
+            structs: be32(header, 8)? as usize,
+        })
+    }
+
+    /// Returns the token at `off` and the offset of the next one.
+    fn token(&self, mut off: usize) -> Option<(Token, usize)> {
+        let align = |off: usize| (off + 3) & !3;
+        loop {
+            match be32(self.data, off)? {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(self.data.get(off + 4..)?)?;
+                    return Some((Token::BeginNode(name), align(off + 4 + name.len() + 1)));
+                }
+                FDT_END_NODE => return Some((Token::EndNode, off + 4)),
+                FDT_PROP => {
+                    let len = be32(self.data, off + 4)? as usize;
+                    let name = cstr(self.strings.get(be32(self.data, off + 8)? as usize..)?)?;
+                    let value = self.data.get(off + 12..off + 12 + len)?;
+                    return Some((Token::Prop(name, value), align(off + 12 + len)));
+                }
+                FDT_NOP => off += 4,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the value of the first property `name` of any node.
+    pub fn find_property(&self, name: &str) -> Option<&'static [u8]> {
+        let mut off = self.structs;
+        loop {
+            let (token, next) = self.token(off)?;
+            match token {
+                Token::Prop(n, value) if n == name => return Some(value),
+                _ => off = next,
+            }
+        }
+    }
+
+    /// Returns the node at the absolute `path`, whose components may omit
+    /// the unit addresses (`/soc/serial` for `/soc/serial@10000000`).
+    pub fn find_node(&self, path: &str) -> Option<Node> {
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        // The next component to find, `None` for the root.
+        let mut want: Option<&str> = None;
+        let mut depth = 0;
+        let mut matched = 0;
+        let mut address_cells = 2;
+        let mut off = self.structs;
+        loop {
+            let (token, next) = self.token(off)?;
+            match token {
+                Token::BeginNode(name) => {
+                    depth += 1;
+                    let found = match want {
+                        None => true,
+                        Some(want) => {
+                            name == want
+                                || (!want.contains('@') && name.split('@').next() == Some(want))
+                        }
+                    };
+                    if depth == matched + 1 && found {
+                        matched = depth;
+                        match components.next() {
+                            Some(c) => {
+                                want = Some(c);
+                                // Until read in the properties of the node.
+                                address_cells = 2;
+                            }
+                            None => {
+                                return Some(Node {
+                                    fdt: *self,
+                                    off: next,
+                                    address_cells,
+                                })
+                            }
+                        }
+                    }
+                }
+                Token::EndNode => {
+                    if depth == matched {
+                        return None;
+                    }
+                    depth -= 1;
+                }
+                Token::Prop(name, value) => {
+                    if depth == matched && name == "#address-cells" {
+                        address_cells = be32(value, 0)?;
+                    }
+                }
+            }
+            off = next;
+        }
+    }
+}
+
+impl Node {
+    /// Returns the value of the property `name`.
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        let mut off = self.off;
+        loop {
+            match self.fdt.token(off)? {
+                (Token::Prop(n, value), _) if n == name => return Some(value),
+                (Token::Prop(..), next) => off = next,
+                // The properties come before the subnodes.
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the value of the string property `name`.
+    pub fn property_str(&self, name: &str) -> Option<&'static str> {
+        cstr(self.property(name)?)
+    }
+
+    /// Returns the value of the `u32` property `name`.
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        be32(self.property(name)?, 0)
+    }
+
+    /// Whether one of the strings of the `compatible` property is `compat`.
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        self.property("compatible")
+            .is_some_and(|v| v.split(|&b| b == 0).any(|s| s == compat.as_bytes()))
+    }
+
+    /// Calls `f` on the properties and the subnodes of the node in the order
+    /// of the blob, the subnodes as their [`Token::BeginNode`], properties
+    /// and [`Token::EndNode`].
+    #[allow(dead_code)]
+    pub fn walk(&self, mut f: impl FnMut(Token)) {
+        let mut depth = 0;
+        let mut off = self.off;
+        while let Some((token, next)) = self.fdt.token(off) {
+            match token {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode if depth == 0 => return,
+                Token::EndNode => depth -= 1,
+                Token::Prop(..) => {}
+            }
+            f(token);
+            off = next;
+        }
+    }
+
+    /// Returns the address of the first region of the `reg` property.
+    pub fn reg_address(&self) -> Option<usize> {
+        let reg = self.property("reg")?;
+        match self.address_cells {
+            1 => be32(reg, 0).map(|a| a as usize),
+            2 => Some(((be32(reg, 0)? as u64) << 32 | be32(reg, 4)? as u64) as usize),
+            _ => None,
+        }
+    }
+}
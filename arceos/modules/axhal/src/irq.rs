@@ -7,6 +7,12 @@ use crate::trap::{register_trap_handler, IRQ};
 
 pub use crate::platform::irq::{register_handler, set_enable};
 
+#[cfg(feature = "irq-stats")]
+pub use crate::irq_stats::{
+    for_each_irq_stats, irq_stats, reset_irq_stats, Histogram, IrqStats, MAX_RECORDED_IRQS,
+    NR_BUCKETS,
+};
+
 /// The type if an IRQ handler.
 pub type IrqHandler = handler_table::Handler;
 
@@ -16,7 +22,11 @@ static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
     trace!("IRQ {}", irq_num);
-    if !IRQ_HANDLER_TABLE.handle(irq_num) {
+    #[cfg(feature = "irq-stats")]
+    let handled = crate::irq_stats::measure(irq_num, || IRQ_HANDLER_TABLE.handle(irq_num));
+    #[cfg(not(feature = "irq-stats"))]
+    let handled = IRQ_HANDLER_TABLE.handle(irq_num);
+    if !handled {
         warn!("Unhandled IRQ {}", irq_num);
     }
 }
@@ -38,7 +48,15 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
     let guard = kernel_guard::NoPreempt::new();
+    #[cfg(feature = "replay")]
+    axreplay::on_irq(crate::cpu::this_cpu_id(), irq_num);
+    #[cfg(feature = "entropy")]
+    crate::entropy::add_interrupt_timing(irq_num);
     dispatch_irq(irq_num);
+    #[cfg(feature = "timer-broadcast")]
+    if irq_num == crate::platform::irq::TIMER_IRQ_NUM {
+        crate::broadcast::on_timer_irq();
+    }
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
 }
@@ -0,0 +1,218 @@
+//! Statistics of the interrupts, with the `irq-stats` feature.
+//!
+//! For every IRQ, the duration of its handler is recorded in a histogram.
+//! The latency of the timer IRQ, from the deadline of the one-shot timer to
+//! the handler, is recorded too; it is not measurable for the other devices.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Number of buckets of the histograms.
+pub const NR_BUCKETS: usize = 32;
+
+/// Maximum number of different IRQs recorded, the other ones are ignored.
+pub const MAX_RECORDED_IRQS: usize = 32;
+
+const NO_IRQ: usize = usize::MAX;
+
+/// A histogram of durations in nanoseconds.
+///
+/// The bucket `i` counts the durations from `2^i` to `2^(i+1) - 1`, except
+/// the first one which also counts 0, and the last one which also counts all
+/// the longer ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Histogram {
+    /// Counts of the buckets.
+    pub buckets: [u64; NR_BUCKETS],
+}
+
+impl Histogram {
+    /// Returns the bucket counting `nanos`.
+    pub const fn bucket_of(nanos: u64) -> usize {
+        let log2 = (u64::BITS - nanos.leading_zeros()).saturating_sub(1) as usize;
+        if log2 < NR_BUCKETS {
+            log2
+        } else {
+            NR_BUCKETS - 1
+        }
+    }
+
+    /// Returns the number of durations counted.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Returns an upper bound of the given percentile (from 0 to 100) of the
+    /// durations, in nanoseconds, or 0 if there is none.
+    pub fn percentile(&self, percent: u32) -> u64 {
+        let count = self.count();
+        if count == 0 {
+            return 0;
+        }
+        let rank = (count * percent.min(100) as u64).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return (1u64 << (i + 1)) - 1;
+            }
+        }
+        u64::MAX
+    }
+}
+
+/// Statistics of an IRQ, see [`irq_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqStats {
+    /// The IRQ number.
+    pub irq_num: usize,
+    /// Number of times it was handled.
+    pub count: u64,
+    /// Total duration of its handler, in nanoseconds.
+    pub total_nanos: u64,
+    /// Maximum duration of its handler, in nanoseconds.
+    pub max_nanos: u64,
+    /// Durations of its handler.
+    pub duration: Histogram,
+    /// Latencies from the trigger to the handler, empty if not measurable.
+    pub latency: Histogram,
+    /// Maximum latency, in nanoseconds.
+    pub max_latency_nanos: u64,
+}
+
+struct Record {
+    irq_num: AtomicUsize,
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    duration: [AtomicU64; NR_BUCKETS],
+    latency: [AtomicU64; NR_BUCKETS],
+    max_latency_nanos: AtomicU64,
+}
+
+impl Record {
+    const fn new() -> Self {
+        Self {
+            irq_num: AtomicUsize::new(NO_IRQ),
+            count: AtomicU64::new(0),
+            total_nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+            duration: [const { AtomicU64::new(0) }; NR_BUCKETS],
+            latency: [const { AtomicU64::new(0) }; NR_BUCKETS],
+            max_latency_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn stats(&self, irq_num: usize) -> IrqStats {
+        let load = |h: &[AtomicU64; NR_BUCKETS]| Histogram {
+            buckets: core::array::from_fn(|i| h[i].load(Ordering::Relaxed)),
+        };
+        IrqStats {
+            irq_num,
+            count: self.count.load(Ordering::Relaxed),
+            total_nanos: self.total_nanos.load(Ordering::Relaxed),
+            max_nanos: self.max_nanos.load(Ordering::Relaxed),
+            duration: load(&self.duration),
+            latency: load(&self.latency),
+            max_latency_nanos: self.max_latency_nanos.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.total_nanos.store(0, Ordering::Relaxed);
+        self.max_nanos.store(0, Ordering::Relaxed);
+        self.max_latency_nanos.store(0, Ordering::Relaxed);
+        for b in self.duration.iter().chain(self.latency.iter()) {
+            b.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+static RECORDS: [Record; MAX_RECORDED_IRQS] = [const { Record::new() }; MAX_RECORDED_IRQS];
+
+/// The deadline of the one-shot timer of the CPU, or 0 if it is not set.
+#[percpu::def_percpu]
+static TIMER_DEADLINE: u64 = 0;
+
+/// Returns the record of `irq_num`, allocating it the first time.
+fn record_of(irq_num: usize) -> Option<&'static Record> {
+    for r in RECORDS.iter() {
+        match r.irq_num.load(Ordering::Acquire) {
+            n if n == irq_num => return Some(r),
+            NO_IRQ => match r.irq_num.compare_exchange(
+                NO_IRQ,
+                irq_num,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(r),
+                Err(n) if n == irq_num => return Some(r),
+                Err(_) => {}
+            },
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(not(feature = "virtual-time"))]
+pub(crate) fn set_timer_deadline(deadline_ns: u64) {
+    TIMER_DEADLINE.write_current(deadline_ns);
+}
+
+/// Runs the handler of `irq_num` and records its statistics.
+pub(crate) fn measure<R>(irq_num: usize, handler: impl FnOnce() -> R) -> R {
+    let start = crate::time::monotonic_time_nanos();
+    // Taken before the handler sets the next one.
+    let deadline = if irq_num == crate::time::TIMER_IRQ_NUM {
+        TIMER_DEADLINE.read_current()
+    } else {
+        0
+    };
+    if deadline != 0 {
+        TIMER_DEADLINE.write_current(0);
+    }
+    let ret = handler();
+    let end = crate::time::monotonic_time_nanos();
+
+    let Some(r) = record_of(irq_num) else {
+        return ret;
+    };
+    let nanos = end.saturating_sub(start);
+    r.count.fetch_add(1, Ordering::Relaxed);
+    r.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    r.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    r.duration[Histogram::bucket_of(nanos)].fetch_add(1, Ordering::Relaxed);
+
+    if deadline != 0 && start >= deadline {
+        let latency = start - deadline;
+        r.max_latency_nanos.fetch_max(latency, Ordering::Relaxed);
+        r.latency[Histogram::bucket_of(latency)].fetch_add(1, Ordering::Relaxed);
+    }
+    ret
+}
+
+/// Returns the statistics of `irq_num`, or `None` if it was never handled.
+pub fn irq_stats(irq_num: usize) -> Option<IrqStats> {
+    RECORDS
+        .iter()
+        .find(|r| r.irq_num.load(Ordering::Acquire) == irq_num)
+        .map(|r| r.stats(irq_num))
+}
+
+/// Calls `f` with the statistics of every IRQ handled.
+pub fn for_each_irq_stats(mut f: impl FnMut(&IrqStats)) {
+    for r in RECORDS.iter() {
+        match r.irq_num.load(Ordering::Acquire) {
+            NO_IRQ => break,
+            n => f(&r.stats(n)),
+        }
+    }
+}
+
+/// Clears the statistics of all the IRQs.
+pub fn reset_irq_stats() {
+    for r in RECORDS.iter() {
+        r.reset();
+    }
+}
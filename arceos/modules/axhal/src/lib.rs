@@ -17,9 +17,30 @@
 //! # Cargo Features
 //!
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
-//! - `fp_simd`: Enable floating-point and SIMD support.
+//! - `fp_simd`: Enable floating-point and SIMD support. On real platforms, the
+//!   FP/SIMD states are switched lazily, on the first use by a task (riscv64
+//!   F/D and V up to a VLEN of 512 bits, aarch64 NEON and SVE with the vector
+//!   length limited to 512 bits, x86_64 SSE/AVX with XSAVE).
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `irq-stats`: Record the durations of the IRQ handlers and the latencies
+//!   of the timer IRQ as histograms, see [`irq::irq_stats`].
+//! - `virtual-time`: Drive the clocks by a controllable virtual time source,
+//!   see [`time`].
+//! - `replay`: Record or replay interrupt arrivals and random values with
+//!   [`axreplay`].
+//! - `pmu`: Count cycles, instructions, cache misses and branch misses by the
+//!   hardware performance counters, see [`pmu`].
+//! - `ras`: Decode and report the hardware errors (machine checks, SErrors),
+//!   with a policy hook choosing the action, see [`ras`].
+//! - `steal-time`: Read the steal time reported by the hypervisor (KVM, the
+//!   SBI STA extension), see [`steal`].
+//! - `entropy`: Collect entropy from pluggable sources (timer jitter, CPU
+//!   RNG instructions, interrupt timings) in a pool with health tests per
+//!   source, for [`misc::random`], see [`entropy`].
+//! - `timer-broadcast`: Wake the idle secondary CPUs by the timer of the
+//!   primary CPU, for the boards whose local timers stop in idle, see
+//!   [`broadcast`].
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -40,32 +61,136 @@ extern crate log;
 extern crate memory_addr;
 
 mod platform;
+#[allow(dead_code)] // not used by all platforms
+mod serial;
+
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+mod fdt;
 
 #[macro_use]
 pub mod trap;
 
 pub mod arch;
+pub mod checksum;
+pub mod cmdline;
 pub mod cpu;
+pub mod earlycon;
 pub mod mem;
 pub mod time;
 
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+pub mod string;
+
 #[cfg(feature = "tls")]
 pub mod tls;
 
 #[cfg(feature = "irq")]
 pub mod irq;
 
+#[cfg(feature = "timer-broadcast")]
+pub mod broadcast;
+#[cfg(feature = "irq-stats")]
+mod irq_stats;
+
 #[cfg(feature = "paging")]
 pub mod paging;
 
+#[cfg(feature = "pmu")]
+pub mod pmu;
+
+#[cfg(feature = "ras")]
+pub mod ras;
+
+#[cfg(feature = "steal-time")]
+pub mod steal;
+
+#[cfg(feature = "entropy")]
+pub mod entropy;
+
 /// Console input and output.
+///
+/// The input of the UART consoles is buffered, on interrupts with the `irq`
+/// feature (x86_64 and aarch64), see [`set_config`](console::set_config) for
+/// the flow control. The riscv64 console of the SBI is polled.
+///
+/// The break character (Ctrl-C) can be handled as soon as it is received
+/// instead of being read, see [`set_break_handler`](console::set_break_handler).
 pub mod console {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
     pub use super::platform::console::*;
+    pub use crate::serial::{FlowControl, Parity, SerialConfig};
+
+    /// Writes a byte to the console, or to the [early console] if it is
+    /// used, and to the [mirror](set_mirror).
+    ///
+    /// [early console]: crate::earlycon
+    pub fn putchar(c: u8) {
+        write_bytes(&[c]);
+    }
 
     /// Write a slice of bytes to the console.
     pub fn write_bytes(bytes: &[u8]) {
         for c in bytes {
-            putchar(*c);
+            write_byte(*c);
+        }
+        if let Some(mirror) = mirror() {
+            mirror(bytes);
+        }
+    }
+
+    fn write_byte(c: u8) {
+        if !crate::earlycon::putchar(c) {
+            super::platform::console::putchar(c);
+        }
+    }
+
+    /// A function the console output is also written to, e.g. a terminal on
+    /// a display.
+    pub type ConsoleMirror = fn(bytes: &[u8]);
+
+    static MIRROR: AtomicUsize = AtomicUsize::new(0);
+
+    /// Sets the function the console output is also written to.
+    pub fn set_mirror(mirror: ConsoleMirror) {
+        MIRROR.store(mirror as usize, Ordering::Release);
+    }
+
+    fn mirror() -> Option<ConsoleMirror> {
+        match MIRROR.load(Ordering::Acquire) {
+            0 => None,
+            f => Some(unsafe { core::mem::transmute::<usize, ConsoleMirror>(f) }),
+        }
+    }
+
+    /// The break character, Ctrl-C.
+    pub const BREAK_CHAR: u8 = 0x03;
+
+    /// A function called when the break character is received, possibly in
+    /// an IRQ handler. It returns `false` to leave the character in the input.
+    pub type BreakHandler = fn() -> bool;
+
+    static BREAK_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+    /// Sets the function called when the break character is received.
+    ///
+    /// On the polled consoles (riscv64), it is only received when reading the
+    /// input.
+    pub fn set_break_handler(handler: BreakHandler) {
+        BREAK_HANDLER.store(handler as usize, Ordering::Release);
+    }
+
+    /// Returns `true` if `c` is the break character and it was handled.
+    #[allow(dead_code)]
+    pub(crate) fn handle_break(c: u8) -> bool {
+        match BREAK_HANDLER.load(Ordering::Acquire) {
+            0 => false,
+            f if c == BREAK_CHAR => unsafe { core::mem::transmute::<usize, BreakHandler>(f)() },
+            _ => false,
         }
     }
 }
@@ -79,7 +204,16 @@ pub mod mp {
     pub use super::platform::mp::*;
 }
 
-pub use self::platform::platform_init;
+/// Initializes the platform devices for the primary CPU.
+///
+/// For example, the interrupt controller and the timer. The platform console
+/// then replaces the [early console](earlycon).
+pub fn platform_init() {
+    self::platform::platform_init();
+    #[cfg(feature = "timer-broadcast")]
+    broadcast::init();
+    earlycon::handover();
+}
 
 #[cfg(feature = "smp")]
 pub use self::platform::platform_init_secondary;
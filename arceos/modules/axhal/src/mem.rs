@@ -72,7 +72,9 @@ pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
 
 /// Returns an iterator over all physical memory regions.
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
-    kernel_image_regions().chain(crate::platform::mem::platform_regions())
+    kernel_image_regions()
+        .chain(crate::platform::mem::platform_regions())
+        .chain(crate::earlycon::mmio_region())
 }
 
 /// Returns the memory regions of the kernel image (code and data sections).
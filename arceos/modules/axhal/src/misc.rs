@@ -1,20 +1,81 @@
 pub use super::platform::misc::*;
 
-use kspin::SpinNoIrq;
+/// How the system goes down on a failure, e.g. a panic, see [`fail`].
+///
+/// The host is told the guest failed, by the pvpanic device on x86, the
+/// reason of the SBI system reset on RISC-V, or a PSCI `SYSTEM_RESET2` on
+/// AArch64, so that it can restart the failed instances.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FailureReset {
+    /// Power off the whole system.
+    PowerOff,
+    /// Stop the current CPU, for the host to inspect the guest.
+    Halt,
+    /// Reboot, the memory being cleared.
+    ColdReboot,
+    /// Reboot keeping the memory, e.g. the pstore region.
+    WarmReboot,
+}
+
+/// Stops the current CPU forever, with the interrupts disabled.
+#[allow(dead_code)]
+pub(crate) fn halt_forever() -> ! {
+    crate::arch::disable_irqs();
+    loop {
+        crate::arch::halt();
+    }
+}
+
+#[cfg(not(feature = "entropy"))]
 use crate::time;
+#[cfg(not(feature = "entropy"))]
+use kspin::SpinNoIrq;
 
+#[cfg(not(feature = "entropy"))]
 static PARK_MILLER_LEHMER_SEED: SpinNoIrq<u32> = SpinNoIrq::new(0);
+#[cfg(not(feature = "entropy"))]
 const RAND_MAX: u64 = 2_147_483_647;
 
 pub fn random() -> u128 {
-	let mut seed = PARK_MILLER_LEHMER_SEED.lock();
+    #[cfg(feature = "replay")]
+    {
+        axreplay::random(crate::cpu::this_cpu_id(), random_inner)
+    }
+    #[cfg(not(feature = "replay"))]
+    {
+        random_inner()
+    }
+}
+
+/// Fills `buf` with random bytes, from the entropy pool with the `entropy`
+/// feature.
+pub fn random_bytes(buf: &mut [u8]) {
+    #[cfg(all(feature = "entropy", not(feature = "replay")))]
+    crate::entropy::fill_bytes(buf);
+    // The values recorded or replayed are the ones of `random`.
+    #[cfg(any(not(feature = "entropy"), feature = "replay"))]
+    for chunk in buf.chunks_mut(16) {
+        chunk.copy_from_slice(&random().to_le_bytes()[..chunk.len()]);
+    }
+}
+
+#[cfg(feature = "entropy")]
+fn random_inner() -> u128 {
+    let mut buf = [0; 16];
+    crate::entropy::fill_bytes(&mut buf);
+    u128::from_le_bytes(buf)
+}
+
+#[cfg(not(feature = "entropy"))]
+fn random_inner() -> u128 {
+    let mut seed = PARK_MILLER_LEHMER_SEED.lock();
     if *seed == 0 {
         *seed = time::current_ticks() as u32;
     }
 
     let mut ret: u128 = 0;
     for _ in 0..4 {
-	    *seed = ((u64::from(*seed) * 48271) % RAND_MAX) as u32;
+        *seed = ((u64::from(*seed) * 48271) % RAND_MAX) as u32;
         ret = (ret << 32) | (*seed as u128);
     }
     ret
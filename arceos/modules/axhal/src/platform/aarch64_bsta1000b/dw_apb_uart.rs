@@ -5,9 +5,26 @@ use dw_apb_uart::DW8250;
 use kspin::SpinNoIrq;
 use memory_addr::PhysAddr;
 
+use crate::serial::{Serial, SerialConfig, Uart};
+
 const UART_BASE: PhysAddr = pa!(axconfig::UART_PADDR);
 
-static UART: SpinNoIrq<DW8250> = SpinNoIrq::new(DW8250::new(phys_to_virt(UART_BASE).as_usize()));
+static UART: SpinNoIrq<Serial<DW8250>> =
+    SpinNoIrq::new(Serial::new(DW8250::new(phys_to_virt(UART_BASE).as_usize())));
+
+impl Uart for DW8250 {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.getchar()
+    }
+
+    fn write_byte(&mut self, c: u8) {
+        self.putchar(c)
+    }
+
+    fn configure(&mut self, _config: &SerialConfig) -> bool {
+        false
+    }
+}
 
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
@@ -26,19 +43,25 @@ pub fn getchar() -> Option<u8> {
     UART.lock().getchar()
 }
 
+/// Sets the configuration of the console, not supported yet.
+pub fn set_config(config: &SerialConfig) -> bool {
+    UART.lock().set_config(config)
+}
+
 /// UART simply initialize
 pub fn init_early() {
-    UART.lock().init();
+    UART.lock().uart().init();
 }
 
 /// Set UART IRQ Enable
 #[cfg(feature = "irq")]
 pub fn init_irq() {
-    UART.lock().set_ier(true);
+    UART.lock().uart().set_ier(true);
     crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
 }
 
 /// UART IRQ Handler
 pub fn handle() {
     trace!("Uart IRQ Handler");
+    UART.lock().receive();
 }
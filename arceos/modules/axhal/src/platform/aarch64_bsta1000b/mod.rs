@@ -30,6 +30,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
     crate::cpu::init_primary(cpu_id);
+    crate::earlycon::init_from_dtb(dtb);
     dw_apb_uart::init_early();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
@@ -1,3 +1,6 @@
+#[cfg(feature = "timer-broadcast")]
+use core::sync::atomic::{AtomicU8, Ordering};
+
 use crate::{irq::IrqHandler, mem::phys_to_virt};
 use arm_gicv2::{translate_irq, GicCpuInterface, GicDistributor, InterruptType};
 use kspin::SpinNoIrq;
@@ -12,6 +15,10 @@ pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(axconfig::UART_IRQ, InterruptType::SPI).unwrap();
 
+/// The IRQ number of the wake-up IPIs (the SGI 1).
+#[cfg(feature = "timer-broadcast")]
+pub const WAKEUP_IPI_NUM: usize = translate_irq(1, InterruptType::SGI).unwrap();
+
 const GICD_BASE: PhysAddr = pa!(axconfig::GICD_PADDR);
 const GICC_BASE: PhysAddr = pa!(axconfig::GICC_PADDR);
 
@@ -21,6 +28,10 @@ static GICD: SpinNoIrq<GicDistributor> =
 // per-CPU, no lock
 static GICC: GicCpuInterface = GicCpuInterface::new(phys_to_virt(GICC_BASE).as_mut_ptr());
 
+/// The CPU interface of each CPU, as a target of the SGIs.
+#[cfg(feature = "timer-broadcast")]
+static CPU_TARGETS: [AtomicU8; axconfig::SMP] = [const { AtomicU8::new(0) }; axconfig::SMP];
+
 /// Enables or disables the given IRQ.
 pub fn set_enable(irq_num: usize, enabled: bool) {
     trace!("GICD set enable: {} {}", irq_num, enabled);
@@ -45,15 +56,41 @@ pub fn dispatch_irq(_unused: usize) {
     GICC.handle_irq(|irq_num| crate::irq::dispatch_irq_common(irq_num as _));
 }
 
+/// Sends the wake-up IPI to the CPU `cpu_id`.
+#[cfg(feature = "timer-broadcast")]
+pub(crate) fn send_wakeup_ipi(cpu_id: usize) {
+    const GICD_SGIR: usize = 0xf00;
+    let target = CPU_TARGETS[cpu_id].load(Ordering::Relaxed) as u32;
+    let sgir = phys_to_virt(GICD_BASE + GICD_SGIR).as_mut_ptr() as *mut u32;
+    // The target list filter 0: to the CPU interfaces of the target list.
+    unsafe { sgir.write_volatile(target << 16 | WAKEUP_IPI_NUM as u32) };
+}
+
+/// Records the CPU interface of this CPU and enables the wake-up IPI, which
+/// is banked per CPU.
+#[cfg(feature = "timer-broadcast")]
+fn init_wakeup_ipi() {
+    const GICD_ITARGETSR: usize = 0x800;
+    let itargetsr = phys_to_virt(GICD_BASE + GICD_ITARGETSR).as_ptr() as *const u32;
+    // The targets of the SGIs read as the CPU interface of the reading CPU.
+    let target = unsafe { itargetsr.read_volatile() } as u8;
+    CPU_TARGETS[crate::cpu::this_cpu_id()].store(target, Ordering::Relaxed);
+    set_enable(WAKEUP_IPI_NUM, true);
+}
+
 /// Initializes GICD, GICC on the primary CPU.
 pub(crate) fn init_primary() {
     info!("Initialize GICv2...");
     GICD.lock().init();
     GICC.init();
+    #[cfg(feature = "timer-broadcast")]
+    init_wakeup_ipi();
 }
 
 /// Initializes GICC on secondary CPUs.
 #[cfg(feature = "smp")]
 pub(crate) fn init_secondary() {
     GICC.init();
+    #[cfg(feature = "timer-broadcast")]
+    init_wakeup_ipi();
 }
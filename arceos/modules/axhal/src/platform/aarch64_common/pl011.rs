@@ -5,11 +5,72 @@ use kspin::SpinNoIrq;
 use memory_addr::PhysAddr;
 
 use crate::mem::phys_to_virt;
+use crate::serial::{Parity, Serial, SerialConfig, Uart};
 
 const UART_BASE: PhysAddr = pa!(axconfig::UART_PADDR);
 
-static UART: SpinNoIrq<Pl011Uart> =
-    SpinNoIrq::new(Pl011Uart::new(phys_to_virt(UART_BASE).as_mut_ptr()));
+static UART: SpinNoIrq<Serial<Pl011>> = SpinNoIrq::new(Serial::new(Pl011 {
+    inner: Pl011Uart::new(phys_to_virt(UART_BASE).as_mut_ptr()),
+    base: phys_to_virt(UART_BASE).as_usize(),
+}));
+
+struct Pl011 {
+    inner: Pl011Uart,
+    base: usize,
+}
+
+impl Pl011 {
+    const FR: usize = 0x18;
+    const LCR_H: usize = 0x2c;
+    const CR: usize = 0x30;
+
+    const FR_BUSY: u32 = 1 << 3;
+    const LCR_H_PEN: u32 = 1 << 1;
+    const LCR_H_EPS: u32 = 1 << 2;
+    const LCR_H_STP2: u32 = 1 << 3;
+    const LCR_H_FEN: u32 = 1 << 4;
+    const CR_UARTEN: u32 = 1 << 0;
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+}
+
+impl Uart for Pl011 {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.inner.getchar()
+    }
+
+    fn write_byte(&mut self, c: u8) {
+        self.inner.putchar(c)
+    }
+
+    fn configure(&mut self, config: &SerialConfig) -> bool {
+        // The clock of the UART is not known, the baud rate set by the
+        // firmware is kept.
+        if config.baud_rate.is_some() {
+            return false;
+        }
+        let mut lcr_h = Self::LCR_H_FEN | ((config.data_bits as u32 - 5) << 5);
+        if config.stop_bits == 2 {
+            lcr_h |= Self::LCR_H_STP2;
+        }
+        match config.parity {
+            Parity::None => {}
+            Parity::Odd => lcr_h |= Self::LCR_H_PEN,
+            Parity::Even => lcr_h |= Self::LCR_H_PEN | Self::LCR_H_EPS,
+        }
+        unsafe {
+            // The line control must not change while the UART is enabled.
+            while self.reg(Self::FR).read_volatile() & Self::FR_BUSY != 0 {}
+            let cr = self.reg(Self::CR).read_volatile();
+            self.reg(Self::CR).write_volatile(cr & !Self::CR_UARTEN);
+            self.reg(Self::LCR_H).write_volatile(lcr_h);
+            self.reg(Self::CR).write_volatile(cr);
+        }
+        true
+    }
+}
 
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
@@ -28,24 +89,31 @@ pub fn getchar() -> Option<u8> {
     UART.lock().getchar()
 }
 
+/// Sets the frame format and the flow control of the console, returns
+/// `false` if they are not supported.
+///
+/// The baud rate cannot be changed.
+pub fn set_config(config: &SerialConfig) -> bool {
+    UART.lock().set_config(config)
+}
+
 /// Initialize the UART
 pub fn init_early() {
-    UART.lock().init();
+    UART.lock().uart().inner.init();
 }
 
-/// Set UART IRQ Enable
+/// Receives the input into the buffer on interrupts.
 pub fn init() {
     #[cfg(feature = "irq")]
-    crate::irq::set_enable(crate::platform::irq::UART_IRQ_NUM, true);
+    crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
 }
 
 /// UART IRQ Handler
 pub fn handle() {
-    let is_receive_interrupt = UART.lock().is_receive_interrupt();
-    UART.lock().ack_interrupts();
+    let mut uart = UART.lock();
+    let is_receive_interrupt = uart.uart().inner.is_receive_interrupt();
+    uart.uart().inner.ack_interrupts();
     if is_receive_interrupt {
-        while let Some(c) = getchar() {
-            putchar(c);
-        }
+        uart.receive();
     }
 }
@@ -2,6 +2,8 @@
 
 #![allow(dead_code)]
 
+use crate::misc::FailureReset;
+
 pub const PSCI_0_2_FN_BASE: u32 = 0x84000000;
 pub const PSCI_0_2_64BIT: u32 = 0x40000000;
 pub const PSCI_0_2_FN_CPU_SUSPEND: u32 = PSCI_0_2_FN_BASE + 1;
@@ -13,6 +15,11 @@ pub const PSCI_0_2_FN_SYSTEM_RESET: u32 = PSCI_0_2_FN_BASE + 9;
 pub const PSCI_0_2_FN64_CPU_SUSPEND: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 1;
 pub const PSCI_0_2_FN64_CPU_ON: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 3;
 pub const PSCI_0_2_FN64_MIGRATE: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 5;
+pub const PSCI_1_0_FN_PSCI_FEATURES: u32 = PSCI_0_2_FN_BASE + 0xa;
+pub const PSCI_1_1_FN64_SYSTEM_RESET2: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 0x12;
+
+/// The architectural reset type of `SYSTEM_RESET2`, a warm reset.
+pub const PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET: u32 = 0;
 
 /// PSCI return values, inclusive of all PSCI versions.
 #[derive(PartialEq, Debug)]
@@ -78,12 +85,16 @@ fn psci_hvc_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
-fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), PsciError> {
-    let ret = match axconfig::PSCI_METHOD {
+fn psci_call_raw(func: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    match axconfig::PSCI_METHOD {
         "smc" => arm_smccc_smc(func, arg0, arg1, arg2),
         "hvc" => psci_hvc_call(func, arg0, arg1, arg2),
         _ => panic!("Unknown PSCI method: {}", axconfig::PSCI_METHOD),
-    };
+    }
+}
+
+fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), PsciError> {
+    let ret = psci_call_raw(func, arg0, arg1, arg2);
     if ret == 0 {
         Ok(())
     } else {
@@ -101,6 +112,34 @@ pub fn system_off() -> ! {
     }
 }
 
+/// Resets the whole system on a failure.
+///
+/// A reboot is a warm reset by `SYSTEM_RESET2` of PSCI 1.1 if the firmware
+/// implements it, which the hypervisors tell apart from the resets asked by
+/// `SYSTEM_RESET`, e.g. KVM flags the exits with
+/// `KVM_SYSTEM_EVENT_RESET_FLAG_PSCI_RESET2`.
+pub fn fail(reset: FailureReset) -> ! {
+    match reset {
+        FailureReset::PowerOff => system_off(),
+        FailureReset::Halt => crate::misc::halt_forever(),
+        FailureReset::ColdReboot | FailureReset::WarmReboot => {}
+    }
+    // PSCI_FEATURES returns a negative error if it is not implemented
+    let reset2 = psci_call_raw(
+        PSCI_1_0_FN_PSCI_FEATURES,
+        PSCI_1_1_FN64_SYSTEM_RESET2 as usize,
+        0,
+        0,
+    );
+    if (reset2 as i32) >= 0 {
+        let reset_type = PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET as usize;
+        psci_call(PSCI_1_1_FN64_SYSTEM_RESET2, reset_type, 0, 0).ok();
+    }
+    psci_call(PSCI_0_2_FN_SYSTEM_RESET, 0, 0, 0).ok();
+    warn!("It should reset!");
+    crate::misc::halt_forever()
+}
+
 /// Power up a core. This call is used to power up cores that either:
 ///
 /// * Have not yet been booted into the calling supervisory software.
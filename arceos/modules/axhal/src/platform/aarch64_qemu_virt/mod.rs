@@ -17,7 +17,7 @@ pub mod time {
 }
 
 pub mod misc {
-    pub use crate::platform::aarch64_common::psci::system_off as terminate;
+    pub use crate::platform::aarch64_common::psci::{fail, system_off as terminate};
 }
 
 extern "C" {
@@ -32,6 +32,11 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
     crate::arch::write_page_table_root0(0.into()); // disable low address access
     crate::cpu::init_primary(cpu_id);
+    crate::earlycon::init_from_dtb(dtb);
+    #[cfg(feature = "smp")]
+    if dtb != 0 {
+        crate::cpu::init_topology_from_dtb(crate::mem::phys_to_virt(dtb.into()).as_usize());
+    }
     super::aarch64_common::pl011::init_early();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
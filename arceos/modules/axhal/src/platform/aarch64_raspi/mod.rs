@@ -23,6 +23,10 @@ pub mod misc {
             crate::arch::halt();
         }
     }
+
+    pub fn fail(_reset: crate::misc::FailureReset) -> ! {
+        crate::misc::halt_forever()
+    }
 }
 
 extern "C" {
@@ -37,6 +41,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
     crate::arch::write_page_table_root0(0.into()); // disable low address access
     crate::cpu::init_primary(cpu_id);
+    crate::earlycon::init_from_dtb(dtb);
     super::aarch64_common::pl011::init_early();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
@@ -11,6 +11,12 @@ pub mod console {
     pub fn getchar() -> Option<u8> {
         unimplemented!()
     }
+
+    /// Sets the configuration of the console, returns `false` if it is not
+    /// supported.
+    pub fn set_config(config: &crate::serial::SerialConfig) -> bool {
+        unimplemented!()
+    }
 }
 
 pub mod misc {
@@ -18,6 +24,11 @@ pub mod misc {
     pub fn terminate() -> ! {
         unimplemented!()
     }
+
+    /// Resets the whole system on a failure.
+    pub fn fail(reset: crate::misc::FailureReset) -> ! {
+        unimplemented!()
+    }
 }
 
 #[cfg(feature = "smp")]
@@ -68,6 +79,10 @@ pub mod irq {
     /// The timer IRQ number.
     pub const TIMER_IRQ_NUM: usize = 0;
 
+    /// The IRQ number of the wake-up IPIs.
+    #[cfg(feature = "timer-broadcast")]
+    pub const WAKEUP_IPI_NUM: usize = 1;
+
     /// Enables or disables the given IRQ.
     pub fn set_enable(irq_num: usize, enabled: bool) {}
 
@@ -82,6 +97,10 @@ pub mod irq {
     /// up in the IRQ handler table and calls the corresponding handler. If
     /// necessary, it also acknowledges the interrupt controller after handling.
     pub fn dispatch_irq(irq_num: usize) {}
+
+    /// Sends the wake-up IPI to the CPU `cpu_id`.
+    #[cfg(feature = "timer-broadcast")]
+    pub(crate) fn send_wakeup_ipi(cpu_id: usize) {}
 }
 
 /// Initializes the platform devices for the primary CPU.
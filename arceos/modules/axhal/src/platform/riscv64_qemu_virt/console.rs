@@ -1,14 +1,19 @@
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
-    #[allow(deprecated)]
-    sbi_rt::legacy::console_putchar(c as usize);
+    crate::arch::sbi::console_putchar(c);
 }
 
 /// Reads a byte from the console, or returns [`None`] if no input is available.
 pub fn getchar() -> Option<u8> {
-    #[allow(deprecated)]
-    match sbi_rt::legacy::console_getchar() as isize {
-        -1 => None,
-        c => Some(c as u8),
+    loop {
+        match crate::arch::sbi::console_getchar() {
+            Some(c) if crate::console::handle_break(c) => continue,
+            c => return c,
+        }
     }
 }
+
+/// Sets the configuration of the console, not supported by the SBI.
+pub fn set_config(_config: &crate::serial::SerialConfig) -> bool {
+    false
+}
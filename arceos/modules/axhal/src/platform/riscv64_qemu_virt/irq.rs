@@ -8,7 +8,6 @@ use riscv::register::sie;
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -18,6 +17,7 @@ pub(super) const S_TIMER: usize = INTC_IRQ_BASE + 5;
 pub(super) const S_EXT: usize = INTC_IRQ_BASE + 9;
 
 static TIMER_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
+static SOFT_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
 
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 1024;
@@ -25,9 +25,20 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number (supervisor timer interrupt in `scause`).
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
+/// The IRQ number of the wake-up IPIs (supervisor software interrupt in
+/// `scause`).
+#[cfg(feature = "timer-broadcast")]
+pub const WAKEUP_IPI_NUM: usize = S_SOFT;
+
 macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
+    (
+        $cause: expr,
+        @SOFT => $soft_op: expr,
+        @TIMER => $timer_op: expr,
+        @EXT => $ext_op: expr $(,)?
+    ) => {
         match $cause {
+            S_SOFT => $soft_op,
             S_TIMER => $timer_op,
             S_EXT => $ext_op,
             _ => panic!("invalid trap cause: {:#x}", $cause),
@@ -49,6 +60,12 @@ pub fn set_enable(scause: usize, _enabled: bool) {
 pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
     with_cause!(
         scause,
+        @SOFT => if !SOFT_HANDLER.is_inited() {
+            SOFT_HANDLER.init_once(handler);
+            true
+        } else {
+            false
+        },
         @TIMER => if !TIMER_HANDLER.is_inited() {
             TIMER_HANDLER.init_once(handler);
             true
@@ -67,14 +84,32 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
 pub fn dispatch_irq(scause: usize) {
     with_cause!(
         scause,
+        @SOFT => {
+            trace!("IRQ: software");
+            unsafe { riscv::register::sip::clear_ssoft() };
+            if let Some(handler) = SOFT_HANDLER.get() {
+                handler();
+            }
+        },
         @TIMER => {
             trace!("IRQ: timer");
+            #[cfg(feature = "irq-stats")]
+            crate::irq_stats::measure(TIMER_IRQ_NUM, || TIMER_HANDLER());
+            #[cfg(not(feature = "irq-stats"))]
             TIMER_HANDLER();
         },
         @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
     );
 }
 
+/// Sends the wake-up IPI to the CPU `cpu_id`.
+#[cfg(feature = "timer-broadcast")]
+pub(crate) fn send_wakeup_ipi(cpu_id: usize) {
+    if let Err(err) = crate::arch::sbi::ipi::send_ipi(1, cpu_id) {
+        warn!("failed to send IPI to hart {} ({:?})", cpu_id, err);
+    }
+}
+
 pub(super) fn init_percpu() {
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
@@ -1,9 +1,26 @@
+use crate::arch::sbi::srst::{system_reset, ResetReason, ResetType};
+use crate::misc::FailureReset;
+
 /// Shutdown the whole system, including all CPUs.
 pub fn terminate() -> ! {
     info!("Shutting down...");
-    sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::NoReason);
-    warn!("It should shutdown!");
+    let err = system_reset(ResetType::Shutdown, ResetReason::NoReason);
+    warn!("It should shutdown! ({:?})", err);
     loop {
         crate::arch::halt();
     }
 }
+
+/// Resets the whole system on a failure, with the `SystemFailure` reason of
+/// the SBI system reset.
+pub fn fail(reset: FailureReset) -> ! {
+    let reset_type = match reset {
+        FailureReset::PowerOff => ResetType::Shutdown,
+        FailureReset::ColdReboot => ResetType::ColdReboot,
+        FailureReset::WarmReboot => ResetType::WarmReboot,
+        FailureReset::Halt => crate::misc::halt_forever(),
+    };
+    let err = system_reset(reset_type, ResetReason::SystemFailure);
+    warn!("It should reset! ({:?})", err);
+    crate::misc::halt_forever()
+}
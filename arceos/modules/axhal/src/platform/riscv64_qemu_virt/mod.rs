@@ -21,7 +21,15 @@ extern "C" {
 unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     crate::cpu::init_primary(cpu_id);
+    crate::earlycon::init_from_dtb(dtb);
+    #[cfg(feature = "smp")]
+    if dtb != 0 {
+        crate::cpu::init_topology_from_dtb(crate::mem::phys_to_virt(dtb.into()).as_usize());
+    }
     crate::arch::set_trap_vector_base(trap_vector_base as usize);
+    if dtb != 0 {
+        crate::cpu::init_riscv_isa(crate::mem::phys_to_virt(dtb.into()).as_usize());
+    }
     self::time::init_early();
     rust_main(cpu_id, dtb);
 }
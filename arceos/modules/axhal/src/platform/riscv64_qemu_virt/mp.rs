@@ -1,3 +1,4 @@
+use crate::arch::sbi::{base, hsm, EID_HSM};
 use crate::mem::{virt_to_phys, PhysAddr};
 
 /// Starts the given secondary CPU with its boot stack.
@@ -5,10 +6,12 @@ pub fn start_secondary_cpu(hartid: usize, stack_top: PhysAddr) {
     extern "C" {
         fn _start_secondary();
     }
-    if sbi_rt::probe_extension(sbi_rt::Hsm).is_unavailable() {
+    if !base::probe_extension(EID_HSM) {
         warn!("HSM SBI extension is not supported for current SEE.");
         return;
     }
     let entry = virt_to_phys(va!(_start_secondary as usize));
-    sbi_rt::hart_start(hartid, entry.as_usize(), stack_top.as_usize());
+    if let Err(e) = hsm::hart_start(hartid, entry.as_usize(), stack_top.as_usize()) {
+        error!("failed to start hart {} ({:?})", hartid, e);
+    }
 }
@@ -32,7 +32,7 @@ pub fn epochoffset_nanos() -> u64 {
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
 #[cfg(feature = "irq")]
 pub fn set_oneshot_timer(deadline_ns: u64) {
-    sbi_rt::set_timer(nanos_to_ticks(deadline_ns));
+    crate::arch::sbi::time::set_timer(nanos_to_ticks(deadline_ns)).ok();
 }
 
 pub(super) fn init_early() {
@@ -56,5 +56,5 @@ pub(super) fn init_early() {
 
 pub(super) fn init_percpu() {
     #[cfg(feature = "irq")]
-    sbi_rt::set_timer(0);
+    crate::arch::sbi::time::set_timer(0).ok();
 }
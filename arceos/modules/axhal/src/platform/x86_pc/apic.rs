@@ -11,9 +11,11 @@ use self::vectors::*;
 use crate::mem::phys_to_virt;
 
 pub(super) mod vectors {
+    pub const IO_APIC_VECTOR_BASE: u8 = 0x20;
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_WAKEUP_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -22,22 +24,32 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The IRQ number of the wake-up IPIs.
+#[cfg(feature = "timer-broadcast")]
+pub const WAKEUP_IPI_NUM: usize = APIC_WAKEUP_VECTOR as usize;
+
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
 static mut LOCAL_APIC: Option<LocalApic> = None;
 static mut IS_X2APIC: bool = false;
 static IO_APIC: LazyInit<SpinNoIrq<IoApic>> = LazyInit::new();
 
+/// Returns the vector of the IRQ `irq` of the IO APIC.
+pub(super) const fn io_apic_vector(irq: u8) -> usize {
+    (IO_APIC_VECTOR_BASE + irq) as usize
+}
+
 /// Enables or disables the given IRQ.
 #[cfg(feature = "irq")]
 pub fn set_enable(vector: usize, enabled: bool) {
     // should not affect LAPIC interrupts
-    if vector < APIC_TIMER_VECTOR as _ {
+    if (IO_APIC_VECTOR_BASE as usize..APIC_TIMER_VECTOR as usize).contains(&vector) {
+        let irq = (vector - IO_APIC_VECTOR_BASE as usize) as u8;
         unsafe {
             if enabled {
-                IO_APIC.lock().enable_irq(vector as u8);
+                IO_APIC.lock().enable_irq(irq);
             } else {
-                IO_APIC.lock().disable_irq(vector as u8);
+                IO_APIC.lock().disable_irq(irq);
             }
         }
     }
@@ -63,6 +75,12 @@ pub fn dispatch_irq(vector: usize) {
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Sends the wake-up IPI to the CPU `cpu_id`.
+#[cfg(feature = "timer-broadcast")]
+pub(crate) fn send_wakeup_ipi(cpu_id: usize) {
+    unsafe { local_apic().send_ipi(APIC_WAKEUP_VECTOR, raw_apic_id(cpu_id as u8)) };
+}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as LAPIC is per-cpu.
     unsafe { LOCAL_APIC.as_mut().unwrap() }
@@ -114,7 +132,9 @@ pub(super) fn init_primary() {
     }
 
     info!("Initialize IO APIC...");
-    let io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    let mut io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    // All IRQs masked, IRQ `i` raises the vector `IO_APIC_VECTOR_BASE + i`.
+    unsafe { io_apic.init(IO_APIC_VECTOR_BASE) };
     IO_APIC.init_once(SpinNoIrq::new(io_apic));
 }
 
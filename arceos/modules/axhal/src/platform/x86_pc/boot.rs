@@ -16,6 +16,20 @@ const MULTIBOOT_HEADER_MAGIC: usize = 0x1BADB002;
 /// This should be in EAX.
 pub(super) const MULTIBOOT_BOOTLOADER_MAGIC: usize = 0x2BADB002;
 
+/// The magic field of the Multiboot2 header.
+const MULTIBOOT2_HEADER_MAGIC: usize = 0xE85250D6;
+
+/// This should be in EAX with Multiboot2.
+pub(super) const MULTIBOOT2_BOOTLOADER_MAGIC: usize = 0x36D76289;
+
+/// Given to `rust_entry` by the entries of the Linux boot protocol, which
+/// has no magic in registers ("HdrS", the magic of the setup header).
+pub(super) const LINUX_BOOT_MAGIC: usize = 0x5372_6448;
+
+/// Size of the setup of the Linux boot protocol at the start of the image,
+/// before the protected-mode kernel.
+const LINUX_SETUP_SIZE: usize = 0x1000;
+
 const CR0: u64 = Cr0Flags::PROTECTED_MODE_ENABLE.bits()
     | Cr0Flags::MONITOR_COPROCESSOR.bits()
     | Cr0Flags::NUMERIC_ERROR.bits()
@@ -38,6 +52,9 @@ global_asm!(
     mb_magic = const MULTIBOOT_BOOTLOADER_MAGIC,
     mb_hdr_magic = const MULTIBOOT_HEADER_MAGIC,
     mb_hdr_flags = const MULTIBOOT_HEADER_FLAGS,
+    mb2_hdr_magic = const MULTIBOOT2_HEADER_MAGIC,
+    linux_magic = const LINUX_BOOT_MAGIC,
+    linux_setup_size = const LINUX_SETUP_SIZE,
     entry = sym super::rust_entry,
     entry_secondary = sym super::rust_entry_secondary,
 
@@ -0,0 +1,155 @@
+//! The information given by the boot loader: the command line and the memory
+//! map, in the Multiboot information, the Multiboot2 information, or the
+//! `boot_params` ("zero page") of the Linux boot protocol.
+
+use core::ffi::{c_char, CStr};
+
+use super::boot::{LINUX_BOOT_MAGIC, MULTIBOOT2_BOOTLOADER_MAGIC, MULTIBOOT_BOOTLOADER_MAGIC};
+use crate::mem::phys_to_virt;
+
+/// The type of the available RAM in the memory maps of all the protocols
+/// (the e820 type).
+const MEMORY_AVAILABLE: u32 = 1;
+
+// Multiboot information.
+const MULTIBOOT_INFO_MEMORY: u32 = 1 << 0;
+const MULTIBOOT_INFO_CMDLINE: u32 = 1 << 2;
+const MULTIBOOT_INFO_MEM_MAP: u32 = 1 << 6;
+
+// Multiboot2 information tags.
+const MULTIBOOT2_TAG_END: u32 = 0;
+const MULTIBOOT2_TAG_CMDLINE: u32 = 1;
+const MULTIBOOT2_TAG_MMAP: u32 = 6;
+
+// Offsets in `boot_params`.
+const BP_EXT_CMD_LINE_PTR: usize = 0x0c8;
+const BP_E820_ENTRIES: usize = 0x1e8;
+const BP_CMD_LINE_PTR: usize = 0x228;
+const BP_E820_TABLE: usize = 0x2d0;
+const BP_E820_MAX_ENTRIES: usize = 128;
+
+/// The boot information, at its physical address.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum BootInfo {
+    /// The Multiboot information.
+    Multiboot(usize),
+    /// The Multiboot2 information.
+    Multiboot2(usize),
+    /// The `boot_params` of the Linux boot protocol.
+    Linux(usize),
+}
+
+unsafe fn read<T: Copy>(paddr: usize) -> T {
+    (phys_to_virt(paddr.into()).as_ptr() as *const T).read_unaligned()
+}
+
+impl BootInfo {
+    /// The information at `paddr`, given with `magic` to `rust_entry`.
+    pub fn new(magic: usize, paddr: usize) -> Option<Self> {
+        match magic {
+            MULTIBOOT_BOOTLOADER_MAGIC => Some(Self::Multiboot(paddr)),
+            MULTIBOOT2_BOOTLOADER_MAGIC => Some(Self::Multiboot2(paddr)),
+            LINUX_BOOT_MAGIC => Some(Self::Linux(paddr)),
+            _ => None,
+        }
+    }
+
+    /// Returns the address of the first Multiboot2 tag of type `ty`.
+    unsafe fn multiboot2_tag(info: usize, ty: u32) -> Option<usize> {
+        let end = info + read::<u32>(info) as usize;
+        let mut tag = info + 8;
+        while tag + 8 <= end {
+            match read::<u32>(tag) {
+                MULTIBOOT2_TAG_END => break,
+                t if t == ty => return Some(tag),
+                _ => tag = (tag + (read::<u32>(tag + 4) as usize).max(8)).next_multiple_of(8),
+            }
+        }
+        None
+    }
+
+    /// Returns the command line of the kernel.
+    ///
+    /// # Safety
+    ///
+    /// The information and the command line must be in the memory mapped by
+    /// the boot page table.
+    pub unsafe fn cmdline(&self) -> Option<&'static str> {
+        let paddr = match *self {
+            Self::Multiboot(info) => {
+                if read::<u32>(info) & MULTIBOOT_INFO_CMDLINE == 0 {
+                    return None;
+                }
+                read::<u32>(info + 16) as usize
+            }
+            Self::Multiboot2(info) => Self::multiboot2_tag(info, MULTIBOOT2_TAG_CMDLINE)? + 8,
+            Self::Linux(params) => {
+                read::<u32>(params + BP_CMD_LINE_PTR) as usize
+                    | (read::<u32>(params + BP_EXT_CMD_LINE_PTR) as usize) << 32
+            }
+        };
+        if paddr == 0 {
+            return None;
+        }
+        let cmdline = phys_to_virt(paddr.into()).as_ptr() as *const c_char;
+        CStr::from_ptr(cmdline).to_str().ok()
+    }
+
+    /// Calls `f` with the start and the size of each region of available RAM
+    /// in the memory map, returns `false` if there is no memory map.
+    ///
+    /// # Safety
+    ///
+    /// The information and the memory map must be in the memory mapped by the
+    /// boot page table.
+    pub unsafe fn for_each_ram_region(&self, mut f: impl FnMut(usize, usize)) -> bool {
+        match *self {
+            Self::Multiboot(info) => {
+                let flags = read::<u32>(info);
+                if flags & MULTIBOOT_INFO_MEM_MAP != 0 {
+                    // each entry has its size before it, not included
+                    let mut entry = read::<u32>(info + 48) as usize;
+                    let end = entry + read::<u32>(info + 44) as usize;
+                    while entry < end {
+                        if read::<u32>(entry + 20) == MEMORY_AVAILABLE {
+                            f(read::<u64>(entry + 4) as _, read::<u64>(entry + 12) as _);
+                        }
+                        entry += read::<u32>(entry) as usize + 4;
+                    }
+                } else if flags & MULTIBOOT_INFO_MEMORY != 0 {
+                    // the upper memory from 1M, in KiB
+                    f(0x10_0000, read::<u32>(info + 8) as usize * 1024);
+                } else {
+                    return false;
+                }
+            }
+            Self::Multiboot2(info) => {
+                let Some(tag) = Self::multiboot2_tag(info, MULTIBOOT2_TAG_MMAP) else {
+                    return false;
+                };
+                let end = tag + read::<u32>(tag + 4) as usize;
+                let entry_size = (read::<u32>(tag + 8) as usize).max(24);
+                let mut entry = tag + 16;
+                while entry + entry_size <= end {
+                    if read::<u32>(entry + 16) == MEMORY_AVAILABLE {
+                        f(read::<u64>(entry) as _, read::<u64>(entry + 8) as _);
+                    }
+                    entry += entry_size;
+                }
+            }
+            Self::Linux(params) => {
+                let entries = read::<u8>(params + BP_E820_ENTRIES) as usize;
+                if entries == 0 {
+                    return false;
+                }
+                for i in 0..entries.min(BP_E820_MAX_ENTRIES) {
+                    let entry = params + BP_E820_TABLE + i * 20;
+                    if read::<u32>(entry + 16) == MEMORY_AVAILABLE {
+                        f(read::<u64>(entry) as _, read::<u64>(entry + 8) as _);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
@@ -1,15 +1,73 @@
-// TODO: get memory regions from multiboot info.
+use lazyinit::LazyInit;
 
-use crate::mem::{MemRegion, MemRegionFlags};
+use super::bootinfo::BootInfo;
+use crate::mem::{virt_to_phys, MemRegion, MemRegionFlags, MemoryAddr, PhysAddr};
+
+/// The most regions of free memory kept from the memory map.
+const MAX_FREE_REGIONS: usize = 32;
+
+/// The end of the memory mapped by the boot page table, which the memory
+/// allocator starts with before the kernel page table.
+const BOOT_MAPPED_END: usize = 0x1_0000_0000;
+
+struct FreeRegions {
+    regions: [(PhysAddr, usize); MAX_FREE_REGIONS],
+    len: usize,
+}
+
+static FREE_REGIONS: LazyInit<FreeRegions> = LazyInit::new();
+
+/// Keeps the free memory of the memory map given by the boot loader, which
+/// is in the memory handed out later: the available RAM above the kernel
+/// image, in the memory mapped by the boot page table. Without a memory map,
+/// it is from the kernel image to the end of the physical memory of the
+/// platform configuration.
+///
+/// # Safety
+///
+/// The boot information must be valid, see [`BootInfo::for_each_ram_region`].
+pub(super) unsafe fn init_early(boot_info: &BootInfo) {
+    extern "C" {
+        fn _ekernel();
+    }
+    let kernel_end = virt_to_phys((_ekernel as usize).into()).align_up_4k();
+    let mut free = FreeRegions {
+        regions: [(pa!(0), 0); MAX_FREE_REGIONS],
+        len: 0,
+    };
+    let has_map = boot_info.for_each_ram_region(|base, size| {
+        let start = pa!(base).max(kernel_end).align_up_4k();
+        let end = pa!(base.saturating_add(size))
+            .min(pa!(BOOT_MAPPED_END))
+            .align_down_4k();
+        if start < end && free.len < MAX_FREE_REGIONS {
+            free.regions[free.len] = (start, end.as_usize() - start.as_usize());
+            free.len += 1;
+        }
+    });
+    if !has_map {
+        for r in crate::mem::default_free_regions() {
+            free.regions[free.len] = (r.paddr, r.size);
+            free.len += 1;
+        }
+    }
+    FREE_REGIONS.init_once(free);
+}
 
 /// Returns platform-specific memory regions.
 pub(crate) fn platform_regions() -> impl Iterator<Item = MemRegion> {
+    let free = &FREE_REGIONS.regions[..FREE_REGIONS.len];
     core::iter::once(MemRegion {
         paddr: pa!(0x1000),
         size: 0x9e000,
         flags: MemRegionFlags::RESERVED | MemRegionFlags::READ | MemRegionFlags::WRITE,
         name: "low memory",
     })
-    .chain(crate::mem::default_free_regions())
+    .chain(free.iter().map(|&(paddr, size)| MemRegion {
+        paddr,
+        size,
+        flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+        name: "free memory",
+    }))
     .chain(crate::mem::default_mmio_regions())
 }
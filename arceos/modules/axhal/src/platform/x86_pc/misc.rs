@@ -1,5 +1,25 @@
 use x86_64::instructions::port::PortWriteOnly;
 
+use crate::misc::FailureReset;
+
+/// The I/O port of the pvpanic device of QEMU.
+#[cfg(platform = "x86_64-qemu-q35")]
+const PVPANIC_PORT: u16 = 0x505;
+/// The pvpanic event of a guest panicked, handled by the host.
+#[cfg(platform = "x86_64-qemu-q35")]
+const PVPANIC_PANICKED: u8 = 1 << 0;
+/// The pvpanic event of a guest panicked, handling the crash itself.
+#[cfg(platform = "x86_64-qemu-q35")]
+const PVPANIC_CRASH_LOADED: u8 = 1 << 1;
+
+/// The Reset Control Register of the chipset.
+const RESET_CONTROL_PORT: u16 = 0xcf9;
+/// The bits of the Reset Control Register: the system reset, the reset of
+/// the CPU, and the full reset, power cycling the system.
+const RCR_SYS_RST: u8 = 1 << 1;
+const RCR_RST_CPU: u8 = 1 << 2;
+const RCR_FULL_RST: u8 = 1 << 3;
+
 /// Shutdown the whole system (in QEMU), including all CPUs.
 ///
 /// See <https://wiki.osdev.org/Shutdown> for more information.
@@ -25,3 +45,37 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Resets the whole system on a failure, after signaling it to the pvpanic
+/// device of QEMU, if any.
+///
+/// A guest rebooting reports that it handles the crash itself, the host
+/// only logs it. Otherwise the host takes the action it is configured with
+/// for the panics, e.g. by `-action panic=...`.
+pub fn fail(reset: FailureReset) -> ! {
+    #[cfg(platform = "x86_64-qemu-q35")]
+    {
+        let event = match reset {
+            FailureReset::PowerOff | FailureReset::Halt => PVPANIC_PANICKED,
+            FailureReset::ColdReboot | FailureReset::WarmReboot => PVPANIC_CRASH_LOADED,
+        };
+        // ignored without the device
+        unsafe { PortWriteOnly::new(PVPANIC_PORT).write(event) };
+    }
+    let full = match reset {
+        FailureReset::PowerOff => terminate(),
+        FailureReset::Halt => crate::misc::halt_forever(),
+        FailureReset::ColdReboot => RCR_FULL_RST,
+        FailureReset::WarmReboot => 0,
+    };
+    // the reset happens on the transition of `RST_CPU` to 1
+    let mut rcr = PortWriteOnly::new(RESET_CONTROL_PORT);
+    unsafe {
+        rcr.write(RCR_SYS_RST | full);
+        rcr.write(RCR_SYS_RST | RCR_RST_CPU | full);
+        // by the keyboard controller otherwise
+        PortWriteOnly::new(0x64).write(0xfeu8);
+    }
+    warn!("It should reset!");
+    crate::misc::halt_forever()
+}
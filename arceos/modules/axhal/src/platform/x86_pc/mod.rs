@@ -1,5 +1,6 @@
 mod apic;
 mod boot;
+mod bootinfo;
 mod dtables;
 mod uart16550;
 
@@ -32,13 +33,18 @@ fn current_cpu_id() -> usize {
     }
 }
 
-unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
-    // TODO: handle multiboot info
-    if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
+unsafe extern "C" fn rust_entry(magic: usize, info: usize) {
+    if let Some(boot_info) = self::bootinfo::BootInfo::new(magic, info) {
         crate::mem::clear_bss();
         crate::cpu::init_primary(current_cpu_id());
+        if let Some(cmdline) = boot_info.cmdline() {
+            crate::earlycon::init_from_cmdline(cmdline);
+        }
+        self::mem::init_early(&boot_info);
         self::uart16550::init();
         self::dtables::init_primary();
+        #[cfg(feature = "fp_simd")]
+        crate::arch::init_fp_simd();
         self::time::init_early();
         rust_main(current_cpu_id(), 0);
     }
@@ -50,6 +56,8 @@ unsafe extern "C" fn rust_entry_secondary(magic: usize) {
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::cpu::init_secondary(current_cpu_id());
         self::dtables::init_secondary();
+        #[cfg(feature = "fp_simd")]
+        crate::arch::init_fp_simd();
         rust_main_secondary(current_cpu_id());
     }
 }
@@ -58,6 +66,8 @@ unsafe extern "C" fn rust_entry_secondary(magic: usize) {
 pub fn platform_init() {
     self::apic::init_primary();
     self::time::init_primary();
+    #[cfg(feature = "irq")]
+    self::uart16550::init_irq();
 }
 
 /// Initializes the platform devices for secondary CPUs.
@@ -3,10 +3,16 @@
 use kspin::SpinNoIrq;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
+use crate::serial::{Parity, Serial, SerialConfig, Uart};
+
 const UART_CLOCK_FACTOR: usize = 16;
 const OSC_FREQ: usize = 1_843_200;
 
-static COM1: SpinNoIrq<Uart16550> = SpinNoIrq::new(Uart16550::new(0x3f8));
+/// The IRQ of COM1 on the IO APIC.
+#[cfg(feature = "irq")]
+const COM1_IRQ: u8 = 4;
+
+static COM1: SpinNoIrq<Serial<Uart16550>> = SpinNoIrq::new(Serial::new(Uart16550::new(0x3f8)));
 
 bitflags::bitflags! {
     /// Line status flags
@@ -25,6 +31,8 @@ struct Uart16550 {
     line_ctrl: PortWriteOnly<u8>,
     modem_ctrl: PortWriteOnly<u8>,
     line_sts: PortReadOnly<u8>,
+    /// Last value written to `line_ctrl`, which cannot be read.
+    lcr: u8,
 }
 
 impl Uart16550 {
@@ -36,6 +44,7 @@ impl Uart16550 {
             line_ctrl: PortWriteOnly::new(port + 3),
             modem_ctrl: PortWriteOnly::new(port + 4),
             line_sts: PortReadOnly::new(port + 5),
+            lcr: 0x03,
         }
     }
 
@@ -44,16 +53,8 @@ impl Uart16550 {
             // Disable interrupts
             self.int_en.write(0x00);
 
-            // Enable DLAB
-            self.line_ctrl.write(0x80);
-
-            // Set maximum speed according the input baud rate by configuring DLL and DLM
-            let divisor = OSC_FREQ / (baud_rate * UART_CLOCK_FACTOR);
-            self.data.write((divisor & 0xff) as u8);
-            self.int_en.write((divisor >> 8) as u8);
-
-            // Disable DLAB and set data word length to 8 bits
-            self.line_ctrl.write(0x03);
+            // Enable DLAB, and set the baud rate and 8 data bits
+            self.set_divisor(OSC_FREQ / (baud_rate * UART_CLOCK_FACTOR));
 
             // Enable FIFO, clear TX/RX queues and
             // set interrupt watermark at 14 bytes
@@ -65,22 +66,60 @@ impl Uart16550 {
         }
     }
 
-    fn line_sts(&mut self) -> LineStsFlags {
-        unsafe { LineStsFlags::from_bits_truncate(self.line_sts.read()) }
+    /// Sets the divisor of the clock with DLAB enabled, then restores the
+    /// line control.
+    unsafe fn set_divisor(&mut self, divisor: usize) {
+        self.line_ctrl.write(0x80);
+        // Set maximum speed according the input baud rate by configuring DLL and DLM
+        self.data.write((divisor & 0xff) as u8);
+        self.int_en.write((divisor >> 8) as u8);
+        self.line_ctrl.write(self.lcr);
     }
 
-    fn putchar(&mut self, c: u8) {
-        while !self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY) {}
-        unsafe { self.data.write(c) };
+    fn line_sts(&mut self) -> LineStsFlags {
+        unsafe { LineStsFlags::from_bits_truncate(self.line_sts.read()) }
     }
+}
 
-    fn getchar(&mut self) -> Option<u8> {
+impl Uart for Uart16550 {
+    fn read_byte(&mut self) -> Option<u8> {
         if self.line_sts().contains(LineStsFlags::INPUT_FULL) {
             unsafe { Some(self.data.read()) }
         } else {
             None
         }
     }
+
+    fn write_byte(&mut self, c: u8) {
+        while !self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY) {}
+        unsafe { self.data.write(c) };
+    }
+
+    fn configure(&mut self, config: &SerialConfig) -> bool {
+        let divisor = match config.baud_rate {
+            Some(baud_rate) => {
+                let divisor = OSC_FREQ / (baud_rate as usize * UART_CLOCK_FACTOR);
+                if !(1..=0xffff).contains(&divisor) {
+                    return false;
+                }
+                Some(divisor)
+            }
+            None => None,
+        };
+        let parity = match config.parity {
+            Parity::None => 0,
+            Parity::Odd => 1 << 3,
+            Parity::Even => (1 << 3) | (1 << 4),
+        };
+        self.lcr = (config.data_bits - 5) | ((config.stop_bits - 1) << 2) | parity;
+        unsafe {
+            match divisor {
+                Some(divisor) => self.set_divisor(divisor),
+                None => self.line_ctrl.write(self.lcr),
+            }
+        }
+        true
+    }
 }
 
 /// Writes a byte to the console.
@@ -100,6 +139,27 @@ pub fn getchar() -> Option<u8> {
     COM1.lock().getchar()
 }
 
+/// Sets the baud rate, the frame format and the flow control of the console,
+/// returns `false` if they are not supported.
+pub fn set_config(config: &SerialConfig) -> bool {
+    COM1.lock().set_config(config)
+}
+
 pub(super) fn init() {
-    COM1.lock().init(115200);
+    COM1.lock().uart().init(115200);
+}
+
+/// Receives the input into the buffer on interrupts.
+#[cfg(feature = "irq")]
+pub(super) fn init_irq() {
+    let vector = super::apic::io_apic_vector(COM1_IRQ);
+    if crate::irq::register_handler(vector, handle) {
+        // Received data available
+        unsafe { COM1.lock().uart().int_en.write(0x01) };
+    }
+}
+
+#[cfg(feature = "irq")]
+fn handle() {
+    COM1.lock().receive();
 }
@@ -0,0 +1,143 @@
+//! Hardware performance counters, with the `pmu` feature.
+//!
+//! The counters of a few common [events](PmuEvent) are set up on each CPU by
+//! [`init_percpu`], and read by [`read_all`] without stopping them:
+//! - x86_64: the architectural performance monitoring of Intel, the fixed
+//!   counters for the cycles and the instructions, and two general-purpose
+//!   counters.
+//! - aarch64: the PMUv3 cycle counter and event counters.
+//! - riscv: the counters configured by the SBI PMU extension, which does not
+//!   count in M-mode with Sscofpmf. Without it, only the `cycle` and
+//!   `instret` counters.
+//!
+//! The events not counted by the hardware read as 0. The counters count in
+//! the kernel and the user space, and wrap around at their width: the
+//! differences of two reads are given by [`PmuCounters::since`].
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The number of the events of [`PmuEvent`].
+pub const NR_EVENTS: usize = 4;
+
+/// An event counted by the hardware performance counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum PmuEvent {
+    /// CPU cycles.
+    Cycles = 0,
+    /// Retired instructions.
+    Instructions = 1,
+    /// Cache misses, of the last-level cache if it is counted.
+    CacheMisses = 2,
+    /// Mispredicted branches.
+    BranchMisses = 3,
+}
+
+impl PmuEvent {
+    /// All the events, in the order of their index.
+    pub const ALL: [Self; NR_EVENTS] = [
+        Self::Cycles,
+        Self::Instructions,
+        Self::CacheMisses,
+        Self::BranchMisses,
+    ];
+
+    /// The name of the event, e.g. `cycles`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Cycles => "cycles",
+            Self::Instructions => "instructions",
+            Self::CacheMisses => "cache-misses",
+            Self::BranchMisses => "branch-misses",
+        }
+    }
+}
+
+/// The values of the counters of all the events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PmuCounters {
+    values: [u64; NR_EVENTS],
+}
+
+impl PmuCounters {
+    /// All the counters 0.
+    pub const ZERO: Self = Self {
+        values: [0; NR_EVENTS],
+    };
+
+    /// The value of the counter of `event`.
+    pub const fn get(&self, event: PmuEvent) -> u64 {
+        self.values[event as usize]
+    }
+
+    /// The counts from the read `earlier` to this one, on the same CPU,
+    /// taking the wrap-around of the counters into account.
+    pub fn since(&self, earlier: &Self) -> Self {
+        let mut delta = Self::ZERO;
+        for event in PmuEvent::ALL {
+            let i = event as usize;
+            delta.values[i] = self.values[i].wrapping_sub(earlier.values[i]) & mask(event);
+        }
+        delta
+    }
+
+    /// Adds the counts of `other`.
+    pub fn accumulate(&mut self, other: &Self) {
+        for (value, other) in self.values.iter_mut().zip(other.values) {
+            *value = value.wrapping_add(other);
+        }
+    }
+}
+
+/// The widths of the counters in bits, 0 if the event is not counted.
+static WIDTHS: [AtomicU8; NR_EVENTS] = [const { AtomicU8::new(0) }; NR_EVENTS];
+
+fn mask(event: PmuEvent) -> u64 {
+    match width(event) {
+        0 => 0,
+        64.. => u64::MAX,
+        w => (1 << w) - 1,
+    }
+}
+
+fn width(event: PmuEvent) -> u32 {
+    WIDTHS[event as usize].load(Ordering::Relaxed) as u32
+}
+
+/// Sets up and starts the counters of the current CPU.
+///
+/// It is called on every CPU during the initialization, before the other
+/// functions are used.
+pub fn init_percpu() {
+    let widths = crate::arch::pmu::init_percpu();
+    if crate::cpu::this_cpu_is_bsp() {
+        for (event, &w) in PmuEvent::ALL.iter().zip(&widths) {
+            if w != 0 {
+                info!("PMU: counting {} ({}-bit)", event.name(), w);
+            }
+        }
+    }
+    for (slot, w) in WIDTHS.iter().zip(widths) {
+        slot.store(w, Ordering::Relaxed);
+    }
+}
+
+/// Whether the hardware counts `event`.
+pub fn is_supported(event: PmuEvent) -> bool {
+    width(event) != 0
+}
+
+/// Reads the counter of `event` on the current CPU, or returns [`None`] if it
+/// is not counted.
+pub fn read(event: PmuEvent) -> Option<u64> {
+    is_supported(event).then(|| crate::arch::pmu::read(event) & mask(event))
+}
+
+/// Reads the counters of all the events on the current CPU.
+pub fn read_all() -> PmuCounters {
+    let mut counters = PmuCounters::ZERO;
+    for event in PmuEvent::ALL {
+        counters.values[event as usize] = read(event).unwrap_or(0);
+    }
+    counters
+}
@@ -0,0 +1,145 @@
+//! Hardware error reporting, with the `ras` feature.
+//!
+//! The hardware error exceptions are decoded into [`HwError`]s instead of
+//! going to the fatal trap path:
+//! - x86_64: the machine checks (`#MC`), one error per valid bank of the
+//!   machine check architecture, which is enabled by [`init_percpu`].
+//! - aarch64: the SErrors, decoded from `ESR_EL1` and the first valid error
+//!   record of the RAS extension, and the synchronous external aborts.
+//! - riscv: the hardware error exception (code 19) of the privileged
+//!   specification.
+//!
+//! Each error is logged, and the handler registered in [`HW_ERROR`] chooses
+//! the [`RasAction`], [`default_action`] if there is none. The fatal errors
+//! always panic, as the interrupted code cannot be resumed, and killing the
+//! task is only possible for an error in the user space, by the handler of
+//! [`USER_FAULT`] with `SIGBUS`.
+//!
+//! [`HW_ERROR`]: crate::trap::HW_ERROR
+//! [`USER_FAULT`]: crate::trap::USER_FAULT
+
+use core::fmt;
+
+use crate::arch::TrapFrame;
+
+/// How bad a hardware error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HwErrorSeverity {
+    /// Corrected by the hardware, nothing was lost.
+    Corrected,
+    /// Uncorrected, but contained: the interrupted code can be resumed if
+    /// it does not use the data any more, e.g. by killing the task.
+    Recoverable,
+    /// Uncorrected and not contained, or the interrupted code cannot be
+    /// resumed.
+    Fatal,
+}
+
+/// The action taken on a hardware error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RasAction {
+    /// Log the error and resume the interrupted code.
+    Log,
+    /// Kill the interrupted task, only possible in the user space.
+    KillTask,
+    /// Panic.
+    Panic,
+}
+
+/// A decoded hardware error.
+#[derive(Debug, Clone, Copy)]
+pub struct HwError {
+    /// How bad the error is.
+    pub severity: HwErrorSeverity,
+    /// The unit or the kind of the error, e.g. `memory controller`.
+    pub kind: &'static str,
+    /// Where the error is reported, e.g. the bank of a machine check.
+    pub source: usize,
+    /// The raw syndrome: `IA32_MCi_STATUS`, `ESR_EL1` or `scause`.
+    pub syndrome: u64,
+    /// The physical address of the error, if it is reported.
+    pub addr: Option<usize>,
+    /// The virtual address of the error, if it is reported.
+    pub vaddr: Option<usize>,
+    /// The program counter of the interrupted code.
+    pub pc: usize,
+    /// Whether the user space was interrupted.
+    pub from_user: bool,
+}
+
+impl fmt::Display for HwError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} hardware error: {} (source {}, syndrome {:#x})",
+            self.severity, self.kind, self.source, self.syndrome
+        )?;
+        if let Some(addr) = self.addr {
+            write!(f, " at paddr {:#x}", addr)?;
+        }
+        if let Some(vaddr) = self.vaddr {
+            write!(f, " at vaddr {:#x}", vaddr)?;
+        }
+        let space = if self.from_user {
+            "user space"
+        } else {
+            "kernel"
+        };
+        write!(f, " @ {:#x} in the {}", self.pc, space)
+    }
+}
+
+/// The action on `err` if no handler is registered in [`HW_ERROR`]: log the
+/// corrected errors, kill the task of the recoverable errors in the user
+/// space, and panic otherwise.
+///
+/// [`HW_ERROR`]: crate::trap::HW_ERROR
+pub fn default_action(err: &HwError) -> RasAction {
+    match err.severity {
+        HwErrorSeverity::Corrected => RasAction::Log,
+        HwErrorSeverity::Recoverable if err.from_user => RasAction::KillTask,
+        _ => RasAction::Panic,
+    }
+}
+
+/// Enables the reporting of the hardware errors on the current CPU.
+///
+/// It is called on every CPU during the initialization.
+pub fn init_percpu() {
+    crate::arch::ras::init_percpu();
+}
+
+/// Logs `err` and returns the action chosen for it.
+pub(crate) fn report(err: &HwError) -> RasAction {
+    match err.severity {
+        HwErrorSeverity::Corrected => warn!("{}", err),
+        _ => error!("{}", err),
+    }
+    let action = crate::trap::HW_ERROR
+        .iter()
+        .next()
+        .map_or_else(|| default_action(err), |handler| handler(err));
+    // the interrupted code cannot go on
+    if err.severity == HwErrorSeverity::Fatal {
+        RasAction::Panic
+    } else {
+        action
+    }
+}
+
+/// Takes `action` on the error `err` which interrupted `tf`.
+pub(crate) fn take_action(tf: &TrapFrame, action: RasAction, err: &HwError) {
+    match action {
+        RasAction::Log => {}
+        #[cfg(feature = "uspace")]
+        RasAction::KillTask
+            if err.from_user
+                && handle_trap!(
+                    USER_FAULT,
+                    tf,
+                    crate::trap::signal::SIGBUS,
+                    va!(err.vaddr.unwrap_or(0))
+                ) => {}
+        _ => panic!("{}:\n{:#x?}", err, tf),
+    }
+}
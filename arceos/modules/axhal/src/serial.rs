@@ -0,0 +1,197 @@
+//! Buffered serial ports, shared by the UART drivers of the platforms.
+//!
+//! The received bytes are moved into a ring buffer by the IRQ handler of the
+//! UART (with the `irq` feature), or when reading with the buffer empty, so
+//! that a burst of input is not lost between two reads. With the software
+//! flow control, XOFF is sent to the peer when the buffer is nearly full and
+//! XON once it is drained, and the XOFF and XON received stop and restart
+//! the output.
+
+/// Parity bit of the characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// The number of set bits is odd.
+    Odd,
+    /// The number of set bits is even.
+    Even,
+}
+
+/// Flow control of a serial port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    /// No flow control.
+    None,
+    /// Software flow control, with the XON and XOFF characters.
+    XonXoff,
+}
+
+/// Configuration of a serial port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// Baud rate, or `None` to keep the current one.
+    pub baud_rate: Option<u32>,
+    /// Number of data bits, from 5 to 8.
+    pub data_bits: u8,
+    /// Parity bit.
+    pub parity: Parity,
+    /// Number of stop bits, 1 or 2.
+    pub stop_bits: u8,
+    /// Flow control.
+    pub flow_control: FlowControl,
+}
+
+impl Default for SerialConfig {
+    /// 8 data bits, no parity, 1 stop bit (8N1), no flow control.
+    fn default() -> Self {
+        Self {
+            baud_rate: None,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+impl SerialConfig {
+    fn is_valid(&self) -> bool {
+        (5..=8).contains(&self.data_bits)
+            && (1..=2).contains(&self.stop_bits)
+            && self.baud_rate != Some(0)
+    }
+}
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+const RX_BUF_SIZE: usize = 1024;
+/// Number of buffered bytes from which XOFF is sent.
+const RX_HIGH_WATER: usize = RX_BUF_SIZE * 3 / 4;
+/// Number of buffered bytes below which XON is sent after XOFF.
+const RX_LOW_WATER: usize = RX_BUF_SIZE / 4;
+
+/// How long the output waits for XON after XOFF, so that a lost XON does not
+/// hang the kernel.
+const TX_STOP_TIMEOUT_NANOS: u64 = 1_000_000_000;
+
+/// The registers of a UART.
+pub(crate) trait Uart {
+    /// Reads a received byte, if any.
+    fn read_byte(&mut self) -> Option<u8>;
+    /// Writes a byte, when there is room for it.
+    fn write_byte(&mut self, c: u8);
+    /// Sets the baud rate and the frame format, returns `false` if they are
+    /// not supported.
+    fn configure(&mut self, config: &SerialConfig) -> bool;
+}
+
+/// A UART with a receive buffer and flow control.
+pub(crate) struct Serial<U> {
+    uart: U,
+    buf: [u8; RX_BUF_SIZE],
+    head: usize,
+    len: usize,
+    flow_control: FlowControl,
+    /// XOFF received, the output waits for XON.
+    tx_stopped: bool,
+    /// XOFF sent, the peer waits for XON.
+    rx_throttled: bool,
+}
+
+impl<U: Uart> Serial<U> {
+    pub const fn new(uart: U) -> Self {
+        Self {
+            uart,
+            buf: [0; RX_BUF_SIZE],
+            head: 0,
+            len: 0,
+            flow_control: FlowControl::None,
+            tx_stopped: false,
+            rx_throttled: false,
+        }
+    }
+
+    pub fn uart(&mut self) -> &mut U {
+        &mut self.uart
+    }
+
+    /// Moves the received bytes into the buffer.
+    pub fn receive(&mut self) {
+        while let Some(c) = self.uart.read_byte() {
+            self.push(c);
+        }
+    }
+
+    fn push(&mut self, c: u8) {
+        if self.flow_control == FlowControl::XonXoff && (c == XOFF || c == XON) {
+            self.tx_stopped = c == XOFF;
+            return;
+        }
+        if crate::console::handle_break(c) {
+            return;
+        }
+        if self.len == RX_BUF_SIZE {
+            // Overrun, the new bytes are dropped.
+            return;
+        }
+        self.buf[(self.head + self.len) % RX_BUF_SIZE] = c;
+        self.len += 1;
+        if self.flow_control == FlowControl::XonXoff
+            && !self.rx_throttled
+            && self.len >= RX_HIGH_WATER
+        {
+            self.uart.write_byte(XOFF);
+            self.rx_throttled = true;
+        }
+    }
+
+    /// Reads a byte from the buffer, or from the UART if it is empty.
+    pub fn getchar(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            self.receive();
+        }
+        if self.len == 0 {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUF_SIZE;
+        self.len -= 1;
+        if self.rx_throttled && self.len <= RX_LOW_WATER {
+            self.uart.write_byte(XON);
+            self.rx_throttled = false;
+        }
+        Some(c)
+    }
+
+    /// Writes a byte, after XON if the output is stopped.
+    pub fn putchar(&mut self, c: u8) {
+        if self.tx_stopped {
+            // Polling, the IRQ handler may wait for the lock held.
+            let deadline = crate::time::monotonic_time_nanos() + TX_STOP_TIMEOUT_NANOS;
+            while self.tx_stopped && crate::time::monotonic_time_nanos() < deadline {
+                self.receive();
+                core::hint::spin_loop();
+            }
+            self.tx_stopped = false;
+        }
+        self.uart.write_byte(c);
+    }
+
+    /// Changes the configuration, returns `false` if it is not supported.
+    pub fn set_config(&mut self, config: &SerialConfig) -> bool {
+        if !config.is_valid() || !self.uart.configure(config) {
+            return false;
+        }
+        self.flow_control = config.flow_control;
+        if config.flow_control == FlowControl::None {
+            if self.rx_throttled {
+                self.uart.write_byte(XON);
+            }
+            self.tx_stopped = false;
+            self.rx_throttled = false;
+        }
+        true
+    }
+}
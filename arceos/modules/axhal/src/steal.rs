@@ -0,0 +1,89 @@
+//! Steal time, with the `steal-time` feature.
+//!
+//! Under a hypervisor, a vCPU may be preempted by the host for other vCPUs or
+//! host tasks while the guest thinks it runs. The host reports the time each
+//! vCPU was runnable but not running, its steal time, in an area of the guest
+//! memory registered by [`init_percpu`]:
+//! - x86_64: the `MSR_KVM_STEAL_TIME` area of KVM, if the CPUID of KVM has
+//!   the `KVM_FEATURE_STEAL_TIME` feature.
+//! - riscv: the shared memory of the SBI Steal-time Accounting (STA)
+//!   extension.
+//! - aarch64: not reported, the stolen time area of the Arm PV time is not in
+//!   the linear mapping of the memory.
+//!
+//! The steal time of a CPU without the area reads as 0.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::cpu::{this_cpu_id, this_cpu_is_bsp};
+
+/// The area updated by the hypervisor, as little-endian words whose layout is
+/// given by the architecture.
+#[repr(C, align(64))]
+pub(crate) struct StealArea(pub(crate) [AtomicU64; 8]);
+
+/// The word of the byte telling whether the vCPU is preempted, the first one
+/// of it, on all the architectures.
+const PREEMPTED: usize = 2;
+
+static AREAS: [StealArea; axconfig::SMP] =
+    [const { StealArea([const { AtomicU64::new(0) }; 8]) }; axconfig::SMP];
+static REGISTERED: [AtomicBool; axconfig::SMP] = [const { AtomicBool::new(false) }; axconfig::SMP];
+
+/// Registers the steal time area of the current CPU to the hypervisor.
+///
+/// It is called on every CPU during the initialization, before the other
+/// functions are used.
+pub fn init_percpu() {
+    let cpu_id = this_cpu_id();
+    let registered = crate::arch::steal::register(&AREAS[cpu_id]);
+    REGISTERED[cpu_id].store(registered, Ordering::Release);
+    if this_cpu_is_bsp() && registered {
+        info!("steal time: reported by the hypervisor");
+    }
+}
+
+/// Whether the hypervisor reports the steal time of the CPU `cpu_id`.
+pub fn is_supported(cpu_id: usize) -> bool {
+    REGISTERED
+        .get(cpu_id)
+        .is_some_and(|r| r.load(Ordering::Acquire))
+}
+
+/// Reads the steal time in nanoseconds, consistent with the sequence number
+/// the hypervisor makes odd while it updates the area.
+fn read(area: &StealArea) -> u64 {
+    use crate::arch::steal::{SEQUENCE, STEAL};
+    loop {
+        let seq = area.0[SEQUENCE].load(Ordering::Acquire) as u32;
+        let steal = area.0[STEAL].load(Ordering::Acquire);
+        if seq & 1 == 0 && area.0[SEQUENCE].load(Ordering::Acquire) as u32 == seq {
+            return steal;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Returns the steal time of the CPU `cpu_id` since it was registered, or
+/// `None` if it is not reported.
+pub fn steal_time(cpu_id: usize) -> Option<Duration> {
+    is_supported(cpu_id).then(|| Duration::from_nanos(read(&AREAS[cpu_id])))
+}
+
+/// Returns the steal time of the current CPU in nanoseconds, 0 if it is not
+/// reported.
+pub fn this_cpu_steal_nanos() -> u64 {
+    let cpu_id = this_cpu_id();
+    if is_supported(cpu_id) {
+        read(&AREAS[cpu_id])
+    } else {
+        0
+    }
+}
+
+/// Whether the hypervisor reports the vCPU of `cpu_id` as preempted, e.g. to
+/// stop spinning on a lock held by it.
+pub fn is_preempted(cpu_id: usize) -> bool {
+    is_supported(cpu_id) && AREAS[cpu_id].0[PREEMPTED].load(Ordering::Relaxed) as u8 & 1 != 0
+}
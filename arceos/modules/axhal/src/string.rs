@@ -0,0 +1,352 @@
+//! Memory copy, fill and comparison routines.
+//!
+//! They are written per architecture, to be used as the `memcpy`, `memset`
+//! and `memcmp` symbols of the whole image (see `axlibc`): the compiler
+//! cannot turn their loops back into calls to these symbols. When the CPU
+//! offers a faster variant, it is selected with [`Dispatch`].
+//!
+//! Only the general-purpose registers are used, the FP/SIMD ones may hold
+//! the state of a task (see the `fp_simd` feature).
+
+use core::arch::asm;
+
+#[cfg(target_arch = "x86_64")]
+use crate::cpu::CpuFeatures;
+use crate::cpu::Dispatch;
+
+type CopyFn = unsafe fn(*mut u8, *const u8, usize);
+type FillFn = unsafe fn(*mut u8, u8, usize);
+
+#[cfg(target_arch = "x86_64")]
+static MEMCPY: Dispatch<CopyFn> = Dispatch::new(&[(CpuFeatures::ERMS, copy_erms)], copy_words);
+#[cfg(target_arch = "x86_64")]
+static MEMSET: Dispatch<FillFn> = Dispatch::new(&[(CpuFeatures::ERMS, fill_erms)], fill_words);
+
+#[cfg(not(target_arch = "x86_64"))]
+static MEMCPY: Dispatch<CopyFn> = Dispatch::new(&[], copy_words);
+#[cfg(not(target_arch = "x86_64"))]
+static MEMSET: Dispatch<FillFn> = Dispatch::new(&[], fill_words);
+
+/// Whether loads from misaligned addresses are allowed and fast.
+const FAST_UNALIGNED: bool = cfg!(target_arch = "x86_64");
+
+/// Copies `n` bytes from `src` to `dst`.
+///
+/// # Safety
+///
+/// Same as [`core::ptr::copy_nonoverlapping`].
+#[inline]
+pub unsafe fn memcpy(dst: *mut u8, src: *const u8, n: usize) {
+    (MEMCPY.get())(dst, src, n)
+}
+
+/// Sets `n` bytes at `dst` to `c`.
+///
+/// # Safety
+///
+/// Same as [`core::ptr::write_bytes`].
+#[inline]
+pub unsafe fn memset(dst: *mut u8, c: u8, n: usize) {
+    (MEMSET.get())(dst, c, n)
+}
+
+/// Compares `n` bytes at `a` and `b`, returns the difference of the first
+/// differing bytes, or 0 if all are equal.
+///
+/// # Safety
+///
+/// `a` and `b` must be valid for reads of `n` bytes.
+pub unsafe fn memcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    const WORD: usize = core::mem::size_of::<usize>();
+    let mut i = 0;
+    if FAST_UNALIGNED || (a as usize ^ b as usize) % WORD == 0 {
+        if !FAST_UNALIGNED {
+            while i < n && (a as usize + i) % WORD != 0 {
+                let (x, y) = (*a.add(i), *b.add(i));
+                if x != y {
+                    return x as i32 - y as i32;
+                }
+                i += 1;
+            }
+        }
+        // The differing word, if any, is compared by bytes below.
+        while i + WORD <= n
+            && a.add(i).cast::<usize>().read_unaligned()
+                == b.add(i).cast::<usize>().read_unaligned()
+        {
+            i += WORD;
+        }
+    }
+    while i < n {
+        let (x, y) = (*a.add(i), *b.add(i));
+        if x != y {
+            return x as i32 - y as i32;
+        }
+        i += 1;
+    }
+    0
+}
+
+/// `c` repeated in every byte of a word.
+#[inline]
+fn splat(c: u8) -> usize {
+    c as usize * (usize::MAX / 0xff)
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn copy_erms(dst: *mut u8, src: *const u8, n: usize) {
+    asm!(
+        "rep movsb",
+        inout("rcx") n => _,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn copy_words(dst: *mut u8, src: *const u8, n: usize) {
+    asm!(
+        "rep movsq",
+        "mov rcx, {rem}",
+        "rep movsb",
+        rem = in(reg) n & 7,
+        inout("rcx") n >> 3 => _,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn fill_erms(dst: *mut u8, c: u8, n: usize) {
+    asm!(
+        "rep stosb",
+        inout("rcx") n => _,
+        inout("rdi") dst => _,
+        in("al") c,
+        options(nostack, preserves_flags),
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn fill_words(dst: *mut u8, c: u8, n: usize) {
+    asm!(
+        "rep stosq",
+        "mov rcx, {rem}",
+        "rep stosb",
+        rem = in(reg) n & 7,
+        inout("rcx") n >> 3 => _,
+        inout("rdi") dst => _,
+        in("rax") splat(c),
+        options(nostack, preserves_flags),
+    );
+}
+
+// When `dst` and `src` have the same alignment: aligns them by bytes, copies
+// 64 bytes at a time, then words, then the last bytes. Otherwise, by bytes as
+// misaligned accesses may fault or be emulated.
+#[cfg(target_arch = "aarch64")]
+unsafe fn copy_words(dst: *mut u8, src: *const u8, n: usize) {
+    asm!(
+        "eor    x3, x0, x1",
+        "tst    x3, #7",
+        "b.ne   5f",
+        "1:",
+        "tst    x0, #7",
+        "b.eq   2f",
+        "cbz    x2, 6f",
+        "ldrb   w3, [x1], #1",
+        "strb   w3, [x0], #1",
+        "sub    x2, x2, #1",
+        "b      1b",
+        "2:",
+        "cmp    x2, #64",
+        "b.lo   3f",
+        "ldp    x4, x5, [x1]",
+        "ldp    x6, x7, [x1, #16]",
+        "ldp    x8, x9, [x1, #32]",
+        "ldp    x10, x11, [x1, #48]",
+        "stp    x4, x5, [x0]",
+        "stp    x6, x7, [x0, #16]",
+        "stp    x8, x9, [x0, #32]",
+        "stp    x10, x11, [x0, #48]",
+        "add    x1, x1, #64",
+        "add    x0, x0, #64",
+        "sub    x2, x2, #64",
+        "b      2b",
+        "3:",
+        "cmp    x2, #8",
+        "b.lo   5f",
+        "ldr    x3, [x1], #8",
+        "str    x3, [x0], #8",
+        "sub    x2, x2, #8",
+        "b      3b",
+        "5:",
+        "cbz    x2, 6f",
+        "ldrb   w3, [x1], #1",
+        "strb   w3, [x0], #1",
+        "sub    x2, x2, #1",
+        "b      5b",
+        "6:",
+        inout("x0") dst => _,
+        inout("x1") src => _,
+        inout("x2") n => _,
+        out("x3") _, out("x4") _, out("x5") _, out("x6") _, out("x7") _,
+        out("x8") _, out("x9") _, out("x10") _, out("x11") _,
+        options(nostack),
+    );
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn fill_words(dst: *mut u8, c: u8, n: usize) {
+    asm!(
+        "1:",
+        "tst    x0, #7",
+        "b.eq   2f",
+        "cbz    x2, 5f",
+        "strb   w1, [x0], #1",
+        "sub    x2, x2, #1",
+        "b      1b",
+        "2:",
+        "cmp    x2, #64",
+        "b.lo   3f",
+        "stp    x1, x1, [x0]",
+        "stp    x1, x1, [x0, #16]",
+        "stp    x1, x1, [x0, #32]",
+        "stp    x1, x1, [x0, #48]",
+        "add    x0, x0, #64",
+        "sub    x2, x2, #64",
+        "b      2b",
+        "3:",
+        "cmp    x2, #8",
+        "b.lo   4f",
+        "str    x1, [x0], #8",
+        "sub    x2, x2, #8",
+        "b      3b",
+        "4:",
+        "cbz    x2, 5f",
+        "strb   w1, [x0], #1",
+        "sub    x2, x2, #1",
+        "b      4b",
+        "5:",
+        inout("x0") dst => _,
+        in("x1") splat(c),
+        inout("x2") n => _,
+        options(nostack),
+    );
+}
+
+// Same as on aarch64, RISC-V does not guarantee fast misaligned accesses.
+#[cfg(target_arch = "riscv64")]
+unsafe fn copy_words(dst: *mut u8, src: *const u8, n: usize) {
+    asm!(
+        "xor    t0, a0, a1",
+        "andi   t0, t0, 7",
+        "bnez   t0, 5f",
+        "1:",
+        "andi   t0, a0, 7",
+        "beqz   t0, 2f",
+        "beqz   a2, 6f",
+        "lb     t0, 0(a1)",
+        "sb     t0, 0(a0)",
+        "addi   a1, a1, 1",
+        "addi   a0, a0, 1",
+        "addi   a2, a2, -1",
+        "j      1b",
+        "2:",
+        "li     t1, 64",
+        "bltu   a2, t1, 3f",
+        "ld     t0, 0(a1)",
+        "ld     t1, 8(a1)",
+        "ld     t2, 16(a1)",
+        "ld     t3, 24(a1)",
+        "ld     t4, 32(a1)",
+        "ld     t5, 40(a1)",
+        "ld     t6, 48(a1)",
+        "ld     a3, 56(a1)",
+        "sd     t0, 0(a0)",
+        "sd     t1, 8(a0)",
+        "sd     t2, 16(a0)",
+        "sd     t3, 24(a0)",
+        "sd     t4, 32(a0)",
+        "sd     t5, 40(a0)",
+        "sd     t6, 48(a0)",
+        "sd     a3, 56(a0)",
+        "addi   a1, a1, 64",
+        "addi   a0, a0, 64",
+        "addi   a2, a2, -64",
+        "j      2b",
+        "3:",
+        "li     t1, 8",
+        "bltu   a2, t1, 5f",
+        "ld     t0, 0(a1)",
+        "sd     t0, 0(a0)",
+        "addi   a1, a1, 8",
+        "addi   a0, a0, 8",
+        "addi   a2, a2, -8",
+        "j      3b",
+        "5:",
+        "beqz   a2, 6f",
+        "lb     t0, 0(a1)",
+        "sb     t0, 0(a0)",
+        "addi   a1, a1, 1",
+        "addi   a0, a0, 1",
+        "addi   a2, a2, -1",
+        "j      5b",
+        "6:",
+        inout("a0") dst => _,
+        inout("a1") src => _,
+        inout("a2") n => _,
+        out("a3") _, out("t0") _, out("t1") _, out("t2") _,
+        out("t3") _, out("t4") _, out("t5") _, out("t6") _,
+        options(nostack),
+    );
+}
+
+#[cfg(target_arch = "riscv64")]
+unsafe fn fill_words(dst: *mut u8, c: u8, n: usize) {
+    asm!(
+        "1:",
+        "andi   t0, a0, 7",
+        "beqz   t0, 2f",
+        "beqz   a2, 5f",
+        "sb     a1, 0(a0)",
+        "addi   a0, a0, 1",
+        "addi   a2, a2, -1",
+        "j      1b",
+        "2:",
+        "li     t0, 64",
+        "bltu   a2, t0, 3f",
+        "sd     a1, 0(a0)",
+        "sd     a1, 8(a0)",
+        "sd     a1, 16(a0)",
+        "sd     a1, 24(a0)",
+        "sd     a1, 32(a0)",
+        "sd     a1, 40(a0)",
+        "sd     a1, 48(a0)",
+        "sd     a1, 56(a0)",
+        "addi   a0, a0, 64",
+        "addi   a2, a2, -64",
+        "j      2b",
+        "3:",
+        "li     t0, 8",
+        "bltu   a2, t0, 4f",
+        "sd     a1, 0(a0)",
+        "addi   a0, a0, 8",
+        "addi   a2, a2, -8",
+        "j      3b",
+        "4:",
+        "beqz   a2, 5f",
+        "sb     a1, 0(a0)",
+        "addi   a0, a0, 1",
+        "addi   a2, a2, -1",
+        "j      4b",
+        "5:",
+        inout("a0") dst => _,
+        in("a1") splat(c),
+        inout("a2") n => _,
+        out("t0") _,
+        options(nostack),
+    );
+}
@@ -1,4 +1,16 @@
 //! Time-related operations.
+//!
+//! With the `virtual-time` feature, the monotonic and wall clocks do not
+//! follow the hardware counter, but a virtual clock starting at 0 that only
+//! moves forward when [`advance_virtual_time`] or [`advance_virtual_time_to`]
+//! is called, or during [`busy_wait`]. The task scheduler also fast-forwards
+//! it to the next timer deadline when all tasks are idle, so timer-heavy code
+//! runs deterministically and faster than real time. Interrupts and other
+//! users of [`current_ticks`] still see the hardware counter.
+//!
+//! The wall clock follows the monotonic clock from the epoch offset read at
+//! boot, it can be disciplined (by a PTP client, say) with
+//! [`step_wall_time`] and [`set_wall_time_freq`].
 
 pub use core::time::Duration;
 
@@ -10,9 +22,16 @@ pub type TimeValue = Duration;
 
 #[cfg(feature = "irq")]
 pub use crate::platform::irq::TIMER_IRQ_NUM;
-#[cfg(feature = "irq")]
+#[cfg(all(
+    feature = "irq",
+    not(feature = "virtual-time"),
+    not(feature = "irq-stats"),
+    not(feature = "timer-broadcast")
+))]
 pub use crate::platform::time::set_oneshot_timer;
-pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
+pub use crate::platform::time::{current_ticks, nanos_to_ticks, ticks_to_nanos};
+
+use kspin::SpinNoIrq;
 
 /// Number of milliseconds in a second.
 pub const MILLIS_PER_SEC: u64 = 1_000;
@@ -25,9 +44,92 @@ pub const NANOS_PER_MILLIS: u64 = 1_000_000;
 /// Number of nanoseconds in a microsecond.
 pub const NANOS_PER_MICROS: u64 = 1_000;
 
+/// The correction of the wall clock over the epoch offset of the platform.
+struct WallClockAdj {
+    /// The correction at the monotonic time `base_nanos`.
+    offset_nanos: i64,
+    base_nanos: u64,
+    /// How much faster than the monotonic clock the wall clock runs, in
+    /// parts per billion.
+    freq_ppb: i64,
+}
+
+impl WallClockAdj {
+    fn offset_at(&self, mono_nanos: u64) -> i64 {
+        let elapsed = mono_nanos.saturating_sub(self.base_nanos) as i128;
+        self.offset_nanos + (elapsed * self.freq_ppb as i128 / NANOS_PER_SEC as i128) as i64
+    }
+
+    /// Moves the base to `mono_nanos`, before changing the frequency.
+    fn rebase(&mut self, mono_nanos: u64) {
+        self.offset_nanos = self.offset_at(mono_nanos);
+        self.base_nanos = mono_nanos;
+    }
+}
+
+static WALL_CLOCK_ADJ: SpinNoIrq<WallClockAdj> = SpinNoIrq::new(WallClockAdj {
+    offset_nanos: 0,
+    base_nanos: 0,
+    freq_ppb: 0,
+});
+
+#[cfg(feature = "virtual-time")]
+static VIRTUAL_NANOS: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
 /// Returns nanoseconds elapsed since system boot.
 pub fn monotonic_time_nanos() -> u64 {
-    ticks_to_nanos(current_ticks())
+    #[cfg(feature = "virtual-time")]
+    {
+        VIRTUAL_NANOS.load(core::sync::atomic::Ordering::Acquire)
+    }
+    #[cfg(not(feature = "virtual-time"))]
+    {
+        ticks_to_nanos(current_ticks())
+    }
+}
+
+/// Moves the virtual clock forward by `dur`.
+#[cfg(feature = "virtual-time")]
+pub fn advance_virtual_time(dur: Duration) {
+    let nanos = dur.as_nanos().min(u64::MAX as u128) as u64;
+    VIRTUAL_NANOS.fetch_add(nanos, core::sync::atomic::Ordering::AcqRel);
+}
+
+/// Moves the virtual clock forward to the monotonic time `deadline`, does
+/// nothing if it is already past.
+#[cfg(feature = "virtual-time")]
+pub fn advance_virtual_time_to(deadline: TimeValue) {
+    let nanos = deadline.as_nanos().min(u64::MAX as u128) as u64;
+    VIRTUAL_NANOS.fetch_max(nanos, core::sync::atomic::Ordering::AcqRel);
+}
+
+/// Set a one-shot timer.
+///
+/// A timer interrupt will be triggered at the specified monotonic time
+/// deadline (in nanoseconds), which is recorded to measure its latency, or
+/// for the [timer broadcast](crate::broadcast).
+#[cfg(all(
+    feature = "irq",
+    not(feature = "virtual-time"),
+    any(feature = "irq-stats", feature = "timer-broadcast")
+))]
+pub fn set_oneshot_timer(deadline_ns: u64) {
+    #[cfg(feature = "irq-stats")]
+    crate::irq_stats::set_timer_deadline(deadline_ns);
+    #[cfg(feature = "timer-broadcast")]
+    crate::broadcast::set_deadline(deadline_ns);
+    crate::platform::time::set_oneshot_timer(deadline_ns);
+}
+
+/// Set a one-shot timer, firing when the virtual clock would reach
+/// `deadline_ns` if it followed the hardware counter.
+#[cfg(all(feature = "irq", feature = "virtual-time"))]
+pub fn set_oneshot_timer(deadline_ns: u64) {
+    let delta = deadline_ns.saturating_sub(monotonic_time_nanos());
+    let hw_deadline_ns = ticks_to_nanos(current_ticks()) + delta;
+    #[cfg(feature = "timer-broadcast")]
+    crate::broadcast::set_deadline(hw_deadline_ns);
+    crate::platform::time::set_oneshot_timer(hw_deadline_ns);
 }
 
 /// Returns the time elapsed since system boot in [`TimeValue`].
@@ -35,6 +137,28 @@ pub fn monotonic_time() -> TimeValue {
     TimeValue::from_nanos(monotonic_time_nanos())
 }
 
+/// Returns the epoch offset in nanoseconds (wall time offset to monotonic
+/// clock start), with the corrections made by [`step_wall_time`] and
+/// [`set_wall_time_freq`].
+pub fn epochoffset_nanos() -> u64 {
+    let adj = WALL_CLOCK_ADJ.lock().offset_at(monotonic_time_nanos());
+    crate::platform::time::epochoffset_nanos().saturating_add_signed(adj)
+}
+
+/// Steps the wall clock by `delta_nanos` (backward if negative). The
+/// monotonic clock is not affected.
+pub fn step_wall_time(delta_nanos: i64) {
+    WALL_CLOCK_ADJ.lock().offset_nanos += delta_nanos;
+}
+
+/// Makes the wall clock run faster than the monotonic clock by `ppb` parts
+/// per billion (slower if negative), and returns the previous value.
+pub fn set_wall_time_freq(ppb: i64) -> i64 {
+    let mut adj = WALL_CLOCK_ADJ.lock();
+    adj.rebase(monotonic_time_nanos());
+    core::mem::replace(&mut adj.freq_ppb, ppb)
+}
+
 /// Returns nanoseconds elapsed since epoch (also known as realtime).
 pub fn wall_time_nanos() -> u64 {
     monotonic_time_nanos() + epochoffset_nanos()
@@ -51,7 +175,12 @@ pub fn busy_wait(dur: Duration) {
 }
 
 /// Busy waiting until reaching the given deadline.
+///
+/// With the `virtual-time` feature, it advances the virtual clock to the
+/// deadline and returns immediately.
 pub fn busy_wait_until(deadline: TimeValue) {
+    #[cfg(feature = "virtual-time")]
+    advance_virtual_time_to(deadline.saturating_sub(TimeValue::from_nanos(epochoffset_nanos())));
     while wall_time() < deadline {
         core::hint::spin_loop();
     }
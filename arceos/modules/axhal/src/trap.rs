@@ -4,7 +4,6 @@ use linkme::distributed_slice as def_trap_handler;
 use memory_addr::VirtAddr;
 use page_table_entry::MappingFlags;
 
-#[cfg(feature = "uspace")]
 use crate::arch::TrapFrame;
 
 pub use linkme::distributed_slice as register_trap_handler;
@@ -22,6 +21,38 @@ pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 #[def_trap_handler]
 pub static SYSCALL: [fn(&TrapFrame, usize) -> isize];
 
+/// A slice of handler functions of the fatal traps from the user space, with
+/// the signal killing the task and the faulting address.
+///
+/// The handler does not return if it kills the task, the kernel panics if
+/// there is none. If it returns `true`, the trapping instruction is run
+/// again, e.g. once a debugger handled the trap.
+///
+/// The breakpoints of the user space are handed to it too, with `SIGTRAP`,
+/// they are skipped if there is no handler.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_FAULT: [fn(&TrapFrame, i32, VirtAddr) -> bool];
+
+/// A slice of handler functions of the hardware errors, choosing the action
+/// taken on each, see [`ras`](crate::ras).
+#[cfg(feature = "ras")]
+#[def_trap_handler]
+pub static HW_ERROR: [fn(&crate::ras::HwError) -> crate::ras::RasAction];
+
+/// The signals of the fatal user traps.
+#[cfg(feature = "uspace")]
+pub mod signal {
+    /// Illegal instruction.
+    pub const SIGILL: i32 = 4;
+    /// Breakpoint.
+    pub const SIGTRAP: i32 = 5;
+    /// Misaligned access.
+    pub const SIGBUS: i32 = 7;
+    /// Invalid memory access.
+    pub const SIGSEGV: i32 = 11;
+}
+
 #[allow(unused_macros)]
 macro_rules! handle_trap {
     ($trap:ident, $($args:tt)*) => {{
@@ -38,6 +69,37 @@ macro_rules! handle_trap {
     }}
 }
 
+/// The trap frame of the IRQ being handled on this CPU, or 0.
+#[percpu::def_percpu]
+static IRQ_FRAME: usize = 0;
+
+/// Calls the IRQ handler, with the trap frame of the interrupted code
+/// available by [`with_irq_trap_frame`].
+pub(crate) fn handle_irq(tf: &TrapFrame, irq: usize) -> bool {
+    let prev = IRQ_FRAME.read_current();
+    IRQ_FRAME.write_current(tf as *const _ as usize);
+    let handled = handle_trap!(IRQ, irq);
+    IRQ_FRAME.write_current(prev);
+    handled
+}
+
+/// Returns whether an IRQ handler is running on this CPU, where blocking is
+/// not allowed.
+pub fn in_irq() -> bool {
+    IRQ_FRAME.read_current() != 0
+}
+
+/// Calls `f` with the trap frame of the code interrupted by the IRQ being
+/// handled on this CPU, e.g. for a sampling profiler in the timer handler.
+///
+/// Returns `None` out of the IRQ handlers.
+pub fn with_irq_trap_frame<R>(f: impl FnOnce(&TrapFrame) -> R) -> Option<R> {
+    let tf = IRQ_FRAME.read_current() as *const TrapFrame;
+    // SAFETY: it is set while the IRQ handler runs on this CPU, the frame
+    // is on its stack.
+    unsafe { tf.as_ref() }.map(f)
+}
+
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
 pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
@@ -0,0 +1,60 @@
+//! The least recently used cache of the blocks of the image.
+
+use alloc::{boxed::Box, collections::BTreeMap};
+
+pub struct BlockCache {
+    capacity: usize,
+    /// The blocks, with the time they were last used.
+    blocks: BTreeMap<u64, (u64, Box<[u8]>)>,
+    /// The blocks by the time they were last used.
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.blocks.contains_key(&id)
+    }
+
+    /// Returns the block `id`, and marks it as the most recently used.
+    pub fn get(&mut self, id: u64) -> Option<&[u8]> {
+        let now = self.tick();
+        let (used, data) = self.blocks.get_mut(&id)?;
+        self.lru.remove(used);
+        self.lru.insert(now, id);
+        *used = now;
+        Some(data)
+    }
+
+    /// Inserts the block `id`, evicting the least recently used one if the
+    /// cache is full.
+    pub fn insert(&mut self, id: u64, data: Box<[u8]>) {
+        let now = self.tick();
+        if let Some((used, _)) = self.blocks.insert(id, (now, data)) {
+            self.lru.remove(&used);
+        } else if self.blocks.len() > self.capacity {
+            if let Some((_, victim)) = self.lru.pop_first() {
+                self.blocks.remove(&victim);
+            }
+        }
+        self.lru.insert(now, id);
+    }
+}
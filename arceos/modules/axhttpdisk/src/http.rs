@@ -0,0 +1,274 @@
+//! A minimal HTTP/1.1 client for the range requests (RFC 9110, 14).
+//!
+//! The connection is kept alive between the requests, and opened again if
+//! it is closed or broken.
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time, TimeValue};
+use axnet::TcpSocket;
+
+/// The attempts of a request, with a new connection before each retry.
+const MAX_ATTEMPTS: usize = 3;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The most bytes of the response headers.
+const MAX_HEADER_LEN: usize = 16 * 1024;
+
+/// A parsed `http://host[:port]/path` URL.
+#[derive(Debug, Clone)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        // an IPv6 address is in brackets
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']')?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().ok()?,
+            None => 80,
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            host: host.into(),
+            port,
+            path: path.into(),
+        })
+    }
+}
+
+/// Why a request did not complete.
+enum ReqError {
+    /// The connection is broken, the request can be sent again.
+    Conn(AxError),
+    /// The server answered with an error.
+    Failed(AxError),
+}
+
+impl From<AxError> for ReqError {
+    fn from(e: AxError) -> Self {
+        Self::Conn(e)
+    }
+}
+
+/// The body of a `206 Partial Content` response.
+pub struct RangeResponse {
+    pub data: Vec<u8>,
+    /// The size of the whole resource.
+    pub total: u64,
+}
+
+pub struct HttpClient {
+    url: HttpUrl,
+    addr: Option<SocketAddr>,
+    sock: Option<TcpSocket>,
+}
+
+fn send_all(sock: &TcpSocket, mut buf: &[u8], deadline: TimeValue) -> AxResult {
+    while !buf.is_empty() {
+        axnet::poll_interfaces();
+        match sock.send(buf) {
+            Ok(0) => return ax_err!(ConnectionReset, "HTTP connection closed"),
+            Ok(n) => buf = &buf[n..],
+            Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+            Err(AxError::WouldBlock) => return ax_err!(TimedOut, "HTTP send timed out"),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Receives some bytes at the end of `buf`, returns how many.
+fn recv_some(sock: &TcpSocket, buf: &mut Vec<u8>, deadline: TimeValue) -> AxResult<usize> {
+    let mut chunk = [0; 2048];
+    loop {
+        axnet::poll_interfaces();
+        match sock.recv(&mut chunk) {
+            Ok(0) => return ax_err!(ConnectionReset, "HTTP connection closed"),
+            Ok(n) => {
+                buf.extend_from_slice(&chunk[..n]);
+                return Ok(n);
+            }
+            Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+            Err(AxError::WouldBlock) => return ax_err!(TimedOut, "HTTP recv timed out"),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Parses `bytes <first>-<last>/<total>`.
+fn parse_content_range(value: &str) -> Option<(u64, u64, u64)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    Some((
+        first.trim().parse().ok()?,
+        last.trim().parse().ok()?,
+        total.trim().parse().ok()?,
+    ))
+}
+
+impl HttpClient {
+    pub fn new(url: HttpUrl) -> Self {
+        Self {
+            url,
+            addr: None,
+            sock: None,
+        }
+    }
+
+    /// Returns the connection, opening it if needed.
+    fn sock(&mut self) -> AxResult<&TcpSocket> {
+        if self.sock.is_none() {
+            let addr = match self.addr {
+                Some(addr) => addr,
+                None => {
+                    let ip = match self.url.host.parse::<IpAddr>() {
+                        Ok(ip) => ip,
+                        Err(_) => *axnet::dns_query(&self.url.host)?
+                            .first()
+                            .ok_or(AxError::NotFound)?,
+                    };
+                    let addr = SocketAddr::new(ip, self.url.port);
+                    self.addr = Some(addr);
+                    addr
+                }
+            };
+            let sock = TcpSocket::new();
+            sock.connect(addr)?;
+            sock.set_nonblocking(true);
+            self.sock = Some(sock);
+        }
+        Ok(self.sock.as_ref().unwrap())
+    }
+
+    /// Fetches `len` bytes of the resource from `start`, retrying on a new
+    /// connection if the former one is broken.
+    ///
+    /// Fewer bytes are returned at the end of the resource.
+    pub fn get_range(&mut self, start: u64, len: usize) -> AxResult<RangeResponse> {
+        for attempt in 0..MAX_ATTEMPTS {
+            match self.try_get_range(start, len) {
+                Ok(resp) => return Ok(resp),
+                Err(ReqError::Failed(e)) => {
+                    self.sock = None;
+                    return Err(e);
+                }
+                Err(ReqError::Conn(e)) => {
+                    warn!(
+                        "HTTP disk: connection error {:?} (attempt {}), reconnecting",
+                        e,
+                        attempt + 1
+                    );
+                    self.sock = None;
+                }
+            }
+        }
+        ax_err!(Io, "HTTP disk: request failed after retries")
+    }
+
+    fn try_get_range(&mut self, start: u64, len: usize) -> Result<RangeResponse, ReqError> {
+        let deadline = monotonic_time() + REQUEST_TIMEOUT;
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={}-{}\r\nUser-Agent: ArceOS\r\n\r\n",
+            self.url.path,
+            self.url.host,
+            start,
+            start + len as u64 - 1
+        );
+        let sock = self.sock()?;
+        send_all(sock, request.as_bytes(), deadline)?;
+
+        // the status line and the headers
+        let mut buf = Vec::new();
+        let header_len = loop {
+            if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+            if buf.len() > MAX_HEADER_LEN {
+                return Err(ReqError::Failed(AxError::InvalidData));
+            }
+            recv_some(sock, &mut buf, deadline)?;
+        };
+        let header = core::str::from_utf8(&buf[..header_len])
+            .map_err(|_| ReqError::Failed(AxError::InvalidData))?;
+        let mut lines = header.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or(ReqError::Failed(AxError::InvalidData))?;
+        let mut content_len = None;
+        let mut content_range = None;
+        let mut close = false;
+        for (key, value) in lines.filter_map(|line| line.split_once(':')) {
+            let value = value.trim();
+            if key.eq_ignore_ascii_case("Content-Length") {
+                content_len = value.parse::<usize>().ok();
+            } else if key.eq_ignore_ascii_case("Content-Range") {
+                content_range = parse_content_range(value);
+            } else if key.eq_ignore_ascii_case("Connection") {
+                close = value.eq_ignore_ascii_case("close");
+            } else if key.eq_ignore_ascii_case("Transfer-Encoding") {
+                warn!("HTTP disk: transfer encoding {} not supported", value);
+                return Err(ReqError::Failed(AxError::Unsupported));
+            }
+        }
+        match status {
+            206 => {}
+            200 => {
+                warn!("HTTP disk: the server does not support the range requests");
+                return Err(ReqError::Failed(AxError::Unsupported));
+            }
+            404 => return Err(ReqError::Failed(AxError::NotFound)),
+            416 => return Err(ReqError::Failed(AxError::InvalidInput)),
+            // the server is not available for now
+            500..=599 => return Err(ReqError::Conn(AxError::Io)),
+            _ => {
+                warn!("HTTP disk: status {}", status);
+                return Err(ReqError::Failed(AxError::Io));
+            }
+        }
+        let (Some(content_len), Some((first, last, total))) = (content_len, content_range) else {
+            return Err(ReqError::Failed(AxError::InvalidData));
+        };
+        if first != start || last + 1 != first + content_len as u64 {
+            return Err(ReqError::Failed(AxError::InvalidData));
+        }
+
+        // the body, part of which may have come with the headers
+        let mut data = Vec::with_capacity(content_len);
+        data.extend_from_slice(&buf[header_len..]);
+        while data.len() < content_len {
+            recv_some(sock, &mut data, deadline)?;
+        }
+        if data.len() != content_len {
+            // more bytes than announced, the connection is out of sync
+            return Err(ReqError::Conn(AxError::InvalidData));
+        }
+        if close {
+            self.sock = None;
+        }
+        Ok(RangeResponse { data, total })
+    }
+}
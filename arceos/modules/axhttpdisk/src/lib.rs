@@ -0,0 +1,209 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) HTTP disk module.
+//!
+//! It exposes a disk image served over HTTP as a read-only block device
+//! ([`HttpDisk`]), to be used by `axfs` as the disk of the root filesystem:
+//! the system boots from an image in an object storage, without copying it
+//! into the guest first.
+//!
+//! - The image is read in 4 KiB blocks by the range requests, on a kept
+//!   alive connection, and the consecutive missing blocks of a read are
+//!   fetched by one request.
+//! - The blocks are kept in a least recently used cache.
+//! - The writes fail, the filesystem must not be written.
+//!
+//! Only the plain HTTP is supported, the image is configured by the
+//! environment variables at build time, see [`HttpDiskConfig::from_env`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod cache;
+mod http;
+
+use alloc::{boxed::Box, vec};
+
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{AxError, AxResult};
+
+use self::cache::BlockCache;
+use self::http::HttpClient;
+
+pub use self::http::HttpUrl;
+
+/// The size of the blocks fetched and cached.
+const CACHE_BLOCK_SIZE: usize = 4096;
+/// The most blocks fetched by a request.
+const MAX_FETCH_BLOCKS: usize = 32;
+/// The default size of the cache, in KiB.
+const DEFAULT_CACHE_KB: usize = 4096;
+
+/// The block size of the exposed block device.
+const BLOCK_SIZE: usize = 512;
+
+/// The configuration of an HTTP disk.
+#[derive(Debug, Clone)]
+pub struct HttpDiskConfig {
+    /// The URL of the image.
+    pub url: HttpUrl,
+    /// The size of the cache, in bytes.
+    pub cache_size: usize,
+}
+
+impl HttpDiskConfig {
+    /// Reads the configuration from the environment variables at build
+    /// time, or `None` if `AX_HTTP_DISK_URL` is not set:
+    ///
+    /// - `AX_HTTP_DISK_URL`: the URL of the image, as
+    ///   `http://host[:port]/path`.
+    /// - `AX_HTTP_DISK_CACHE`: the size of the cache in KiB (4096 by
+    ///   default).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a variable is invalid.
+    pub fn from_env() -> Option<Self> {
+        // The empty variables are unset ones exported by the Makefile.
+        let var = |v: Option<&'static str>| v.filter(|v| !v.is_empty());
+        let url = var(option_env!("AX_HTTP_DISK_URL"))?;
+        let url = HttpUrl::parse(url).expect("invalid AX_HTTP_DISK_URL");
+        let cache_kb = var(option_env!("AX_HTTP_DISK_CACHE")).map_or(DEFAULT_CACHE_KB, |kb| {
+            kb.parse().expect("invalid AX_HTTP_DISK_CACHE")
+        });
+        Some(Self {
+            url,
+            cache_size: cache_kb * 1024,
+        })
+    }
+}
+
+/// A disk image served over HTTP, as a read-only block device of 512-byte
+/// blocks.
+pub struct HttpDisk {
+    client: HttpClient,
+    cache: BlockCache,
+    /// The size of the image in bytes.
+    size: u64,
+}
+
+impl HttpDisk {
+    /// Fetches the first block of the image of `config`, to learn its size.
+    pub fn connect(config: HttpDiskConfig) -> AxResult<Self> {
+        let mut client = HttpClient::new(config.url);
+        let first = client.get_range(0, CACHE_BLOCK_SIZE)?;
+        let mut cache = BlockCache::new(config.cache_size / CACHE_BLOCK_SIZE);
+        let mut block = vec![0; CACHE_BLOCK_SIZE];
+        block[..first.data.len()].copy_from_slice(&first.data);
+        cache.insert(0, block.into_boxed_slice());
+        Ok(Self {
+            client,
+            cache,
+            size: first.total,
+        })
+    }
+
+    /// Fetches `count` cache blocks from `first` into the cache.
+    fn fetch(&mut self, first: u64, count: u64) -> AxResult {
+        let start = first * CACHE_BLOCK_SIZE as u64;
+        let len = (count * CACHE_BLOCK_SIZE as u64).min(self.size - start) as usize;
+        let resp = self.client.get_range(start, len)?;
+        if resp.data.len() != len {
+            return Err(AxError::InvalidData);
+        }
+        for (i, chunk) in resp.data.chunks(CACHE_BLOCK_SIZE).enumerate() {
+            // the last block of the image is padded with zeros
+            let mut block = vec![0; CACHE_BLOCK_SIZE];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.cache
+                .insert(first + i as u64, block.into_boxed_slice());
+        }
+        Ok(())
+    }
+
+    /// Reads the bytes at `offset`, from the cache or the server.
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> AxResult {
+        let cbs = CACHE_BLOCK_SIZE as u64;
+        let end = offset + buf.len() as u64;
+        let last = end.div_ceil(cbs);
+        let mut pos = offset;
+        while pos < end {
+            let id = pos / cbs;
+            if !self.cache.contains(id) {
+                // the missing blocks of the read, in one request, which must
+                // not evict each other
+                let count = (id..last)
+                    .take(MAX_FETCH_BLOCKS.min(self.cache.capacity()))
+                    .take_while(|&id| !self.cache.contains(id))
+                    .count() as u64;
+                self.fetch(id, count)?;
+            }
+            let block = self.cache.get(id).ok_or(AxError::InvalidData)?;
+            let block_off = (pos - id * cbs) as usize;
+            let len = (CACHE_BLOCK_SIZE - block_off).min((end - pos) as usize);
+            let dst = (pos - offset) as usize;
+            buf[dst..dst + len].copy_from_slice(&block[block_off..block_off + len]);
+            pos += len as u64;
+        }
+        Ok(())
+    }
+}
+
+impl BaseDriverOps for HttpDisk {
+    fn device_name(&self) -> &str {
+        "http-disk"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+}
+
+impl BlockDriverOps for HttpDisk {
+    fn num_blocks(&self) -> u64 {
+        self.size / BLOCK_SIZE as u64
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let offset = block_id * BLOCK_SIZE as u64;
+        if buf.len() % BLOCK_SIZE != 0 || offset + buf.len() as u64 > self.size {
+            return Err(DevError::InvalidParam);
+        }
+        self.read(offset, buf).map_err(|_| DevError::Io)
+    }
+
+    fn write_block(&mut self, _block_id: u64, _buf: &[u8]) -> DevResult {
+        Err(DevError::Unsupported)
+    }
+
+    fn flush(&mut self) -> DevResult {
+        Ok(())
+    }
+}
+
+/// Connects to the image configured at build time, returns it as the block
+/// device for the filesystems.
+///
+/// # Panics
+///
+/// Panics if the image is not configured, or can not be read.
+pub fn init_http_disk() -> AxDeviceContainer<AxBlockDevice> {
+    info!("Initialize HTTP disk...");
+
+    let config = HttpDiskConfig::from_env().expect("AX_HTTP_DISK_URL not set");
+    info!(
+        "  fetch http://{}:{}{}, {} KiB of cache",
+        config.url.host,
+        config.url.port,
+        config.url.path,
+        config.cache_size / 1024
+    );
+    let disk = HttpDisk::connect(config).expect("HTTP disk not available");
+    info!("  {} bytes", disk.size);
+    AxDeviceContainer::from_one(Box::new(disk))
+}
@@ -0,0 +1,256 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) iSCSI initiator module.
+//!
+//! It logs in to an iSCSI target over TCP, and exposes a remote LUN as a
+//! block device ([`IscsiDisk`]), to be used by `axfs` as the disk of the
+//! root filesystem: the system is diskless, but its state is kept on the
+//! network storage.
+//!
+//! - The CHAP authentication (with MD5) is used if a secret is configured,
+//!   the mutual authentication is not supported.
+//! - The header and data digests (CRC32C) are used if the target agrees.
+//! - The session is kept alive by pinging the target (NOP-Out) when idle,
+//!   in a background task, which also answers the pings of the target.
+//! - When the connection is broken, the session is reinstated by a new
+//!   login and the command is retried.
+//!
+//! The target is configured by the environment variables at build time,
+//! see [`IscsiConfig::from_env`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod pdu;
+mod session;
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec};
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{AxError, AxResult};
+use axsync::Mutex;
+
+use self::session::{DataDir, Session};
+
+/// The default TCP port of the targets.
+const DEFAULT_PORT: u16 = 3260;
+/// The default keepalive interval.
+const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(10);
+
+/// The block size of the exposed block device.
+const BLOCK_SIZE: usize = 512;
+/// The most bytes read or written by a command.
+const MAX_TRANSFER: usize = 128 * 1024;
+
+/// The configuration of an iSCSI session.
+#[derive(Debug, Clone)]
+pub struct IscsiConfig {
+    /// The address of the target portal.
+    pub target: SocketAddr,
+    /// The iSCSI name (IQN) of the target.
+    pub target_name: String,
+    /// The iSCSI name of the initiator.
+    pub initiator_name: String,
+    /// The logical unit to use.
+    pub lun: u64,
+    /// The CHAP user name and secret, if the target requires them.
+    pub chap: Option<(String, String)>,
+    /// The idle time after which the target is pinged.
+    pub keepalive: Duration,
+}
+
+impl IscsiConfig {
+    /// Reads the configuration from the environment variables at build
+    /// time, or `None` if `AX_ISCSI_TARGET` is not set:
+    ///
+    /// - `AX_ISCSI_TARGET`: the address of the target, with the port 3260
+    ///   if not given.
+    /// - `AX_ISCSI_IQN`: the name of the target.
+    /// - `AX_ISCSI_INITIATOR`: the name of the initiator (optional).
+    /// - `AX_ISCSI_LUN`: the logical unit (0 by default).
+    /// - `AX_ISCSI_CHAP_USER`, `AX_ISCSI_CHAP_SECRET`: the CHAP credentials
+    ///   (optional).
+    /// - `AX_ISCSI_KEEPALIVE`: the keepalive interval in seconds (10 by
+    ///   default).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a variable is invalid.
+    pub fn from_env() -> Option<Self> {
+        // The empty variables are unset ones exported by the Makefile.
+        let var = |v: Option<&'static str>| v.filter(|v| !v.is_empty());
+        let target = var(option_env!("AX_ISCSI_TARGET"))?;
+        let target = target
+            .parse()
+            .or_else(|_| target.parse().map(|ip| SocketAddr::new(ip, DEFAULT_PORT)))
+            .expect("invalid AX_ISCSI_TARGET");
+        let target_name = var(option_env!("AX_ISCSI_IQN")).expect("AX_ISCSI_IQN not set");
+        let initiator_name =
+            var(option_env!("AX_ISCSI_INITIATOR")).unwrap_or("iqn.2024-01.org.arceos:initiator");
+        let lun = var(option_env!("AX_ISCSI_LUN"))
+            .map_or(0, |lun| lun.parse().expect("invalid AX_ISCSI_LUN"));
+        let chap = var(option_env!("AX_ISCSI_CHAP_SECRET")).map(|secret| {
+            let user = var(option_env!("AX_ISCSI_CHAP_USER")).unwrap_or(initiator_name);
+            (user.into(), secret.into())
+        });
+        let keepalive = var(option_env!("AX_ISCSI_KEEPALIVE")).map_or(DEFAULT_KEEPALIVE, |secs| {
+            Duration::from_secs(secs.parse().expect("invalid AX_ISCSI_KEEPALIVE"))
+        });
+        Some(Self {
+            target,
+            target_name: target_name.into(),
+            initiator_name: initiator_name.into(),
+            lun,
+            chap,
+            keepalive,
+        })
+    }
+}
+
+/// A LUN of an iSCSI target, as a block device of 512-byte blocks.
+///
+/// The blocks of the LUN may be larger, they are then read and written in
+/// whole.
+pub struct IscsiDisk {
+    session: Arc<Mutex<Session>>,
+    lun_block_size: usize,
+    lun_num_blocks: u64,
+}
+
+impl IscsiDisk {
+    /// Logs in to the target of `config`, and starts the keepalive task.
+    pub fn connect(config: IscsiConfig) -> AxResult<Self> {
+        let keepalive = config.keepalive;
+        let mut session = Session::connect(config)?;
+        let (lun_num_blocks, lun_block_size) = session.read_capacity()?;
+        let lun_block_size = lun_block_size as usize;
+        if lun_block_size % BLOCK_SIZE != 0 {
+            warn!("iSCSI: unsupported block size {}", lun_block_size);
+            return Err(AxError::Unsupported);
+        }
+
+        let session = Arc::new(Mutex::new(session));
+        let weak = Arc::downgrade(&session);
+        axtask::spawn(move || loop {
+            axtask::sleep(keepalive / 2);
+            let Some(session) = weak.upgrade() else {
+                break;
+            };
+            session.lock().keepalive();
+        });
+        Ok(Self {
+            session,
+            lun_block_size,
+            lun_num_blocks,
+        })
+    }
+
+    /// Reads or writes the bytes at `offset`, in the whole blocks of the LUN.
+    fn transfer(&self, offset: u64, len: usize, dir: DataDir) -> AxResult {
+        let bs = self.lun_block_size as u64;
+        let mut session = self.session.lock();
+        let first = offset / bs;
+        let last = (offset + len as u64).div_ceil(bs);
+        let num = (last - first) as u32;
+        if offset % bs == 0 && len as u64 % bs == 0 {
+            return session.read_write(first, num, dir);
+        }
+        match dir {
+            DataDir::Read(buf) => {
+                let mut tmp = vec![0; num as usize * self.lun_block_size];
+                session.read_write(first, num, DataDir::Read(&mut tmp))?;
+                let start = (offset - first * bs) as usize;
+                buf.copy_from_slice(&tmp[start..start + len]);
+                Ok(())
+            }
+            DataDir::Write(buf) => {
+                // read-modify-write the blocks partially written
+                let mut tmp = vec![0; num as usize * self.lun_block_size];
+                session.read_write(first, num, DataDir::Read(&mut tmp))?;
+                let start = (offset - first * bs) as usize;
+                tmp[start..start + len].copy_from_slice(buf);
+                session.read_write(first, num, DataDir::Write(&tmp))
+            }
+            DataDir::None => Ok(()),
+        }
+    }
+}
+
+impl BaseDriverOps for IscsiDisk {
+    fn device_name(&self) -> &str {
+        "iscsi"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+}
+
+impl BlockDriverOps for IscsiDisk {
+    fn num_blocks(&self) -> u64 {
+        self.lun_num_blocks * (self.lun_block_size / BLOCK_SIZE) as u64
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        if buf.len() % BLOCK_SIZE != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        let mut offset = block_id * BLOCK_SIZE as u64;
+        for chunk in buf.chunks_mut(MAX_TRANSFER) {
+            let len = chunk.len();
+            self.transfer(offset, len, DataDir::Read(chunk))
+                .map_err(|_| DevError::Io)?;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        if buf.len() % BLOCK_SIZE != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        let mut offset = block_id * BLOCK_SIZE as u64;
+        for chunk in buf.chunks(MAX_TRANSFER) {
+            self.transfer(offset, chunk.len(), DataDir::Write(chunk))
+                .map_err(|_| DevError::Io)?;
+            offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        self.session
+            .lock()
+            .synchronize_cache()
+            .map_err(|_| DevError::Io)
+    }
+}
+
+/// Logs in to the target configured at build time, returns its LUN as the
+/// block device for the filesystems.
+///
+/// # Panics
+///
+/// Panics if the target is not configured, or the login fails.
+pub fn init_iscsi() -> AxDeviceContainer<AxBlockDevice> {
+    info!("Initialize iSCSI initiator...");
+
+    let config = IscsiConfig::from_env().expect("AX_ISCSI_TARGET not set");
+    info!(
+        "  connect to {} ({}), LUN {}",
+        config.target_name, config.target, config.lun
+    );
+    let disk = IscsiDisk::connect(config).expect("iSCSI login failed");
+    info!(
+        "  {} blocks of {} bytes",
+        disk.lun_num_blocks, disk.lun_block_size
+    );
+    AxDeviceContainer::from_one(Box::new(disk))
+}
@@ -0,0 +1,150 @@
+//! The protocol data units of iSCSI (RFC 7143, section 11).
+
+use alloc::vec::Vec;
+
+/// The length of the basic header segment.
+pub const BHS_LEN: usize = 48;
+
+/// The task tag of the PDUs not answered (NOP-Out, NOP-In).
+pub const RESERVED_TAG: u32 = 0xffff_ffff;
+
+/// The opcodes of the PDUs, of the initiator then of the target.
+pub mod op {
+    pub const NOP_OUT: u8 = 0x00;
+    pub const SCSI_CMD: u8 = 0x01;
+    pub const LOGIN_REQ: u8 = 0x03;
+    pub const DATA_OUT: u8 = 0x05;
+    pub const LOGOUT_REQ: u8 = 0x06;
+
+    pub const NOP_IN: u8 = 0x20;
+    pub const SCSI_RESP: u8 = 0x21;
+    pub const LOGIN_RESP: u8 = 0x23;
+    pub const DATA_IN: u8 = 0x25;
+    pub const LOGOUT_RESP: u8 = 0x26;
+    pub const R2T: u8 = 0x31;
+    pub const ASYNC_MSG: u8 = 0x32;
+    pub const REJECT: u8 = 0x3f;
+}
+
+/// The immediate delivery bit of the opcode byte.
+pub const IMMEDIATE: u8 = 0x40;
+/// The final bit of the flags byte.
+pub const FINAL: u8 = 0x80;
+
+/// A PDU: the basic header segment and the data segment, without the
+/// padding and the digests.
+pub struct Pdu {
+    pub bhs: [u8; BHS_LEN],
+    pub data: Vec<u8>,
+}
+
+impl Pdu {
+    pub fn new(opcode: u8, flags: u8) -> Self {
+        let mut bhs = [0; BHS_LEN];
+        bhs[0] = opcode;
+        bhs[1] = flags;
+        Self {
+            bhs,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.bhs[0] & 0x3f
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.bhs[1]
+    }
+
+    /// The length of the additional header segments, in bytes.
+    pub fn ahs_len(&self) -> usize {
+        self.bhs[4] as usize * 4
+    }
+
+    pub fn data_len(&self) -> usize {
+        u32::from_be_bytes([0, self.bhs[5], self.bhs[6], self.bhs[7]]) as usize
+    }
+
+    /// Sets the data segment and its length in the header.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.bhs[5..8].copy_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        self.data = data;
+    }
+
+    pub fn u16(&self, off: usize) -> u16 {
+        u16::from_be_bytes(self.bhs[off..off + 2].try_into().unwrap())
+    }
+
+    pub fn u32(&self, off: usize) -> u32 {
+        u32::from_be_bytes(self.bhs[off..off + 4].try_into().unwrap())
+    }
+
+    pub fn set_u16(&mut self, off: usize, v: u16) {
+        self.bhs[off..off + 2].copy_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn set_u32(&mut self, off: usize, v: u32) {
+        self.bhs[off..off + 4].copy_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn set_u64(&mut self, off: usize, v: u64) {
+        self.bhs[off..off + 8].copy_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn itt(&self) -> u32 {
+        self.u32(16)
+    }
+
+    pub fn set_itt(&mut self, itt: u32) {
+        self.set_u32(16, itt)
+    }
+
+    /// Whether the PDU consumes a status sequence number, to acknowledge.
+    pub fn advances_stat_sn(&self) -> bool {
+        match self.opcode() {
+            op::SCSI_RESP | op::LOGIN_RESP | op::LOGOUT_RESP | op::ASYNC_MSG => true,
+            op::DATA_IN => self.flags() & 0x01 != 0,
+            op::NOP_IN => self.itt() != RESERVED_TAG,
+            _ => false,
+        }
+    }
+}
+
+/// Encodes the LUN in the single level format of SAM (peripheral device
+/// addressing up to 255, flat space addressing up to 16383).
+pub fn encode_lun(lun: u64) -> u64 {
+    if lun < 256 {
+        lun << 48
+    } else {
+        (0x4000 | (lun & 0x3fff)) << 48
+    }
+}
+
+/// Encodes the text key-value pairs of the login and text PDUs.
+pub fn encode_text(pairs: &[(&str, &str)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (key, value) in pairs {
+        data.extend_from_slice(key.as_bytes());
+        data.push(b'=');
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    }
+    data
+}
+
+/// Iterates over the text key-value pairs in `data`.
+pub fn parse_text(data: &[u8]) -> impl Iterator<Item = (&str, &str)> {
+    data.split(|&b| b == 0)
+        .filter_map(|kv| core::str::from_utf8(kv).ok()?.split_once('='))
+}
+
+/// Returns the value of `key` in the text `data`.
+pub fn text_value<'a>(data: &'a [u8], key: &str) -> Option<&'a str> {
+    parse_text(data).find(|(k, _)| *k == key).map(|(_, v)| v)
+}
+
+/// The length of the padding of a segment of `len` bytes.
+pub const fn pad_len(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
@@ -0,0 +1,732 @@
+//! The iSCSI session: the login, the SCSI commands and the recovery.
+//!
+//! The session has a single connection, and a single command outstanding at
+//! a time. As the error recovery level is 0, a broken connection is given up
+//! and the session reinstated by a new login with the same ISID, then the
+//! command is retried.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::time::Duration;
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::checksum::crc32c_update;
+use axhal::time::{monotonic_time, TimeValue};
+use axnet::TcpSocket;
+
+use crate::pdu::{self, op, Pdu, FINAL, IMMEDIATE, RESERVED_TAG};
+use crate::IscsiConfig;
+
+/// The data segment length we accept from the target.
+const MAX_RECV_DATA_LEN: usize = 256 * 1024;
+/// The data segment length of the target if it does not declare it.
+const DEFAULT_SEND_DATA_LEN: usize = 8192;
+
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+const CMD_TIMEOUT: Duration = Duration::from_secs(30);
+const NOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The attempts of a command, with a reconnection before each retry.
+const MAX_ATTEMPTS: usize = 4;
+/// The delay before logging in again (`DefaultTime2Wait`).
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// The login stages.
+const STAGE_SECURITY: u8 = 0;
+const STAGE_OPERATIONAL: u8 = 1;
+const STAGE_FULL_FEATURE: u8 = 3;
+
+/// The SCSI status codes.
+const STATUS_GOOD: u8 = 0x00;
+const STATUS_CHECK_CONDITION: u8 = 0x02;
+const STATUS_BUSY: u8 = 0x08;
+const STATUS_TASK_SET_FULL: u8 = 0x28;
+
+/// The sense key of the unit attention condition.
+const SENSE_UNIT_ATTENTION: u8 = 0x06;
+
+/// The parameters negotiated at the login.
+#[derive(Clone, Copy)]
+struct Params {
+    header_digest: bool,
+    data_digest: bool,
+    /// The `MaxRecvDataSegmentLength` of the target.
+    max_send_data: usize,
+}
+
+impl Params {
+    const LOGIN: Self = Self {
+        header_digest: false,
+        data_digest: false,
+        max_send_data: DEFAULT_SEND_DATA_LEN,
+    };
+}
+
+/// Why a command did not complete.
+enum CmdError {
+    /// The connection is broken, it must be reinstated.
+    Conn(AxError),
+    /// The target asks to issue the command again.
+    Retry,
+    /// The command failed.
+    Failed(AxError),
+}
+
+impl From<AxError> for CmdError {
+    fn from(e: AxError) -> Self {
+        Self::Conn(e)
+    }
+}
+
+/// The data transferred by a SCSI command.
+pub enum DataDir<'a> {
+    None,
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+pub struct Session {
+    config: IscsiConfig,
+    sock: Option<TcpSocket>,
+    isid: [u8; 6],
+    tsih: u16,
+    next_itt: u32,
+    cmd_sn: u32,
+    exp_stat_sn: u32,
+    max_cmd_sn: u32,
+    params: Params,
+    /// When the last PDU was received.
+    last_rx: TimeValue,
+}
+
+/// Whether `a` is before `b` in the serial number arithmetic (RFC 1982).
+fn sn_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn deadline(timeout: Duration) -> TimeValue {
+    monotonic_time() + timeout
+}
+
+fn digest(parts: &[&[u8]]) -> [u8; 4] {
+    let crc = parts.iter().fold(!0, |crc, part| crc32c_update(crc, part));
+    // the digests are sent least significant byte first
+    (!crc).to_le_bytes()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    let mut s = String::from("0x");
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl Session {
+    /// Logs in to the target of `config`.
+    pub fn connect(config: IscsiConfig) -> AxResult<Self> {
+        let mut isid = [0; 6];
+        // the random qualifier format
+        isid.copy_from_slice(&axhal::misc::random().to_be_bytes()[..6]);
+        isid[0] = 0x80 | (isid[0] & 0x3f);
+        let mut session = Self {
+            config,
+            sock: None,
+            isid,
+            tsih: 0,
+            next_itt: 1,
+            cmd_sn: 1,
+            exp_stat_sn: 0,
+            max_cmd_sn: 1,
+            params: Params::LOGIN,
+            last_rx: monotonic_time(),
+        };
+        session.login()?;
+        Ok(session)
+    }
+
+    fn alloc_itt(&mut self) -> u32 {
+        let itt = self.next_itt;
+        self.next_itt = match itt.wrapping_add(1) {
+            RESERVED_TAG => 1,
+            next => next,
+        };
+        itt
+    }
+
+    fn sock(&self) -> AxResult<&TcpSocket> {
+        self.sock.as_ref().ok_or(AxError::NotConnected)
+    }
+
+    fn send_all(&self, mut buf: &[u8], deadline: TimeValue) -> AxResult {
+        let sock = self.sock()?;
+        while !buf.is_empty() {
+            axnet::poll_interfaces();
+            match sock.send(buf) {
+                Ok(0) => return ax_err!(ConnectionReset, "iSCSI connection closed"),
+                Ok(n) => buf = &buf[n..],
+                Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+                Err(AxError::WouldBlock) => return ax_err!(TimedOut, "iSCSI send timed out"),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn recv_exact(&self, buf: &mut [u8], deadline: TimeValue) -> AxResult {
+        let sock = self.sock()?;
+        let mut len = 0;
+        while len < buf.len() {
+            axnet::poll_interfaces();
+            match sock.recv(&mut buf[len..]) {
+                Ok(0) => return ax_err!(ConnectionReset, "iSCSI connection closed"),
+                Ok(n) => len += n,
+                Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+                Err(AxError::WouldBlock) => return ax_err!(TimedOut, "iSCSI recv timed out"),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn send_pdu(&self, pdu: &Pdu) -> AxResult {
+        let pad = [0; 4];
+        let pad = &pad[..pdu::pad_len(pdu.data.len())];
+        let mut buf = Vec::with_capacity(pdu::BHS_LEN + pdu.data.len() + 12);
+        buf.extend_from_slice(&pdu.bhs);
+        if self.params.header_digest {
+            buf.extend_from_slice(&digest(&[&pdu.bhs]));
+        }
+        if !pdu.data.is_empty() {
+            buf.extend_from_slice(&pdu.data);
+            buf.extend_from_slice(pad);
+            if self.params.data_digest {
+                buf.extend_from_slice(&digest(&[&pdu.data, pad]));
+            }
+        }
+        self.send_all(&buf, deadline(CMD_TIMEOUT))
+    }
+
+    fn recv_pdu(&mut self, deadline: TimeValue) -> AxResult<Pdu> {
+        let mut pdu = Pdu::new(0, 0);
+        self.recv_exact(&mut pdu.bhs, deadline)?;
+        // the additional header segments are not used by the responses
+        let mut ahs = vec![0; pdu.ahs_len()];
+        self.recv_exact(&mut ahs, deadline)?;
+        if self.params.header_digest {
+            let mut expected = [0; 4];
+            self.recv_exact(&mut expected, deadline)?;
+            if digest(&[&pdu.bhs, &ahs]) != expected {
+                return ax_err!(InvalidData, "iSCSI header digest error");
+            }
+        }
+        let len = pdu.data_len();
+        if len > 0 {
+            let mut data = vec![0; len + pdu::pad_len(len)];
+            self.recv_exact(&mut data, deadline)?;
+            if self.params.data_digest {
+                let mut expected = [0; 4];
+                self.recv_exact(&mut expected, deadline)?;
+                if digest(&[&data]) != expected {
+                    return ax_err!(InvalidData, "iSCSI data digest error");
+                }
+            }
+            data.truncate(len);
+            pdu.data = data;
+        }
+        self.last_rx = monotonic_time();
+        self.update_sn(&pdu);
+        Ok(pdu)
+    }
+
+    fn update_sn(&mut self, pdu: &Pdu) {
+        if pdu.advances_stat_sn() {
+            self.exp_stat_sn = pdu.u32(24).wrapping_add(1);
+        }
+        // all the target PDUs carry the command window
+        let max_cmd_sn = pdu.u32(32);
+        if sn_lt(self.max_cmd_sn, max_cmd_sn) {
+            self.max_cmd_sn = max_cmd_sn;
+        }
+    }
+
+    /// Handles the PDUs not related to the current task, returns whether the
+    /// PDU has been consumed.
+    fn handle_unsolicited(&mut self, pdu: &Pdu) -> Result<bool, CmdError> {
+        match pdu.opcode() {
+            op::NOP_IN if pdu.itt() == RESERVED_TAG => {
+                // a ping from the target, answered if it asks to
+                let ttt = pdu.u32(20);
+                if ttt != RESERVED_TAG {
+                    let mut reply = Pdu::new(op::NOP_OUT | IMMEDIATE, FINAL);
+                    reply.bhs[8..16].copy_from_slice(&pdu.bhs[8..16]);
+                    reply.set_itt(RESERVED_TAG);
+                    reply.set_u32(20, ttt);
+                    reply.set_u32(24, self.cmd_sn);
+                    reply.set_u32(28, self.exp_stat_sn);
+                    self.send_pdu(&reply)?;
+                }
+                Ok(true)
+            }
+            op::ASYNC_MSG => {
+                let event = pdu.bhs[36];
+                warn!("iSCSI: asynchronous event {}", event);
+                match event {
+                    // the target requests a logout, or drops the connection
+                    1..=3 => Err(CmdError::Conn(AxError::ConnectionReset)),
+                    _ => Ok(true),
+                }
+            }
+            op::REJECT => {
+                warn!("iSCSI: PDU rejected, reason {:#x}", pdu.bhs[2]);
+                Err(CmdError::Failed(AxError::Io))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn login_pdu(&self, itt: u32, csg: u8, nsg: u8, transit: bool, text: Vec<u8>) -> Pdu {
+        let mut flags = (csg << 2) | nsg;
+        if transit {
+            flags |= FINAL;
+        }
+        let mut pdu = Pdu::new(op::LOGIN_REQ | IMMEDIATE, flags);
+        pdu.bhs[8..14].copy_from_slice(&self.isid);
+        pdu.set_u16(14, self.tsih);
+        pdu.set_itt(itt);
+        pdu.set_u32(24, self.cmd_sn);
+        pdu.set_u32(28, self.exp_stat_sn);
+        pdu.set_data(text);
+        pdu
+    }
+
+    /// Sends a login request, returns the response.
+    fn login_exchange(&mut self, req: Pdu) -> AxResult<Pdu> {
+        self.send_pdu(&req)?;
+        let resp = self.recv_pdu(deadline(LOGIN_TIMEOUT))?;
+        if resp.opcode() != op::LOGIN_RESP || resp.itt() != req.itt() {
+            return ax_err!(InvalidData, "iSCSI: unexpected login response");
+        }
+        match (resp.bhs[36], resp.bhs[37]) {
+            (0, _) => Ok(resp),
+            (2, 1) => ax_err!(PermissionDenied, "iSCSI: authentication failed"),
+            (2, 2) => ax_err!(PermissionDenied, "iSCSI: authorization failure"),
+            (2, 3) => ax_err!(NotFound, "iSCSI: target not found"),
+            (class, detail) => {
+                warn!("iSCSI: login failed, status {:#x}/{:#x}", class, detail);
+                Err(AxError::Io)
+            }
+        }
+    }
+
+    /// Opens the connection and logs in, with the CHAP authentication if
+    /// configured.
+    fn login(&mut self) -> AxResult {
+        let sock = TcpSocket::new();
+        sock.connect(self.config.target)?;
+        sock.set_nonblocking(true);
+        self.sock = Some(sock);
+        self.params = Params::LOGIN;
+        self.exp_stat_sn = 0;
+
+        let itt = self.alloc_itt();
+        let initiator = self.config.initiator_name.clone();
+        let target = self.config.target_name.clone();
+        let names = [
+            ("InitiatorName", initiator.as_str()),
+            ("TargetName", target.as_str()),
+            ("SessionType", "Normal"),
+        ];
+
+        let mut names_sent = false;
+        if let Some((user, secret)) = self.config.chap.clone() {
+            let mut text = names.to_vec();
+            text.push(("AuthMethod", "CHAP"));
+            let req = self.login_pdu(
+                itt,
+                STAGE_SECURITY,
+                STAGE_OPERATIONAL,
+                false,
+                pdu::encode_text(&text),
+            );
+            let resp = self.login_exchange(req)?;
+            if pdu::text_value(&resp.data, "AuthMethod") != Some("CHAP") {
+                return ax_err!(Unsupported, "iSCSI: CHAP not accepted by the target");
+            }
+
+            // MD5 only
+            let req = self.login_pdu(
+                itt,
+                STAGE_SECURITY,
+                STAGE_OPERATIONAL,
+                false,
+                pdu::encode_text(&[("CHAP_A", "5")]),
+            );
+            let resp = self.login_exchange(req)?;
+            let id = pdu::text_value(&resp.data, "CHAP_I").and_then(|id| id.parse::<u8>().ok());
+            let challenge = pdu::text_value(&resp.data, "CHAP_C").and_then(hex_decode);
+            let (Some(id), Some(challenge)) = (id, challenge) else {
+                return ax_err!(InvalidData, "iSCSI: invalid CHAP challenge");
+            };
+            let response = hex_encode(&axcrypto::md5::hash(&[
+                &[id],
+                secret.as_bytes(),
+                &challenge,
+            ]));
+            let text = [("CHAP_N", user.as_str()), ("CHAP_R", response.as_str())];
+            let req = self.login_pdu(
+                itt,
+                STAGE_SECURITY,
+                STAGE_OPERATIONAL,
+                true,
+                pdu::encode_text(&text),
+            );
+            let mut resp = self.login_exchange(req)?;
+            while resp.flags() & FINAL == 0 {
+                let req = self.login_pdu(itt, STAGE_SECURITY, STAGE_OPERATIONAL, true, Vec::new());
+                resp = self.login_exchange(req)?;
+            }
+            names_sent = true;
+        }
+
+        let max_recv = format!("{}", MAX_RECV_DATA_LEN);
+        let mut text = if names_sent {
+            Vec::new()
+        } else {
+            names.to_vec()
+        };
+        text.extend_from_slice(&[
+            ("HeaderDigest", "CRC32C,None"),
+            ("DataDigest", "CRC32C,None"),
+            ("MaxRecvDataSegmentLength", max_recv.as_str()),
+            // the data is always sent on R2T
+            ("InitialR2T", "Yes"),
+            ("ImmediateData", "No"),
+            ("MaxBurstLength", "262144"),
+            ("FirstBurstLength", "65536"),
+            ("MaxOutstandingR2T", "1"),
+            ("DataPDUInOrder", "Yes"),
+            ("DataSequenceInOrder", "Yes"),
+            ("DefaultTime2Wait", "2"),
+            ("DefaultTime2Retain", "0"),
+            ("ErrorRecoveryLevel", "0"),
+            ("MaxConnections", "1"),
+        ]);
+        let mut params = Params::LOGIN;
+        let mut text = pdu::encode_text(&text);
+        loop {
+            let req = self.login_pdu(itt, STAGE_OPERATIONAL, STAGE_FULL_FEATURE, true, text);
+            let resp = self.login_exchange(req)?;
+            for (key, value) in pdu::parse_text(&resp.data) {
+                match key {
+                    "HeaderDigest" => params.header_digest = value == "CRC32C",
+                    "DataDigest" => params.data_digest = value == "CRC32C",
+                    "MaxRecvDataSegmentLength" => {
+                        params.max_send_data = value.parse().unwrap_or(DEFAULT_SEND_DATA_LEN)
+                    }
+                    _ => {}
+                }
+            }
+            if resp.flags() & FINAL != 0 && resp.flags() & 0x03 == STAGE_FULL_FEATURE {
+                self.tsih = resp.u16(14);
+                self.cmd_sn = resp.u32(28);
+                self.max_cmd_sn = resp.u32(32);
+                break;
+            }
+            text = Vec::new();
+        }
+        // the digests start after the login
+        self.params = params;
+        info!(
+            "iSCSI: logged in to {} ({}), digests: header {}, data {}",
+            self.config.target_name, self.config.target, params.header_digest, params.data_digest
+        );
+        Ok(())
+    }
+
+    /// Reinstates the session after the connection is broken.
+    fn reconnect(&mut self) -> AxResult {
+        self.sock = None;
+        // a new session with the same ISID closes the old one
+        self.tsih = 0;
+        axtask::sleep(RECONNECT_DELAY);
+        self.login()
+    }
+
+    /// Sends a NOP-Out and waits for the NOP-In, for the target to tell it is
+    /// alive and to update the command window.
+    fn ping(&mut self) -> Result<(), CmdError> {
+        let itt = self.alloc_itt();
+        let mut pdu = Pdu::new(op::NOP_OUT | IMMEDIATE, FINAL);
+        pdu.set_itt(itt);
+        pdu.set_u32(20, RESERVED_TAG);
+        pdu.set_u32(24, self.cmd_sn);
+        pdu.set_u32(28, self.exp_stat_sn);
+        self.send_pdu(&pdu)?;
+        let deadline = deadline(NOP_TIMEOUT);
+        loop {
+            let resp = self.recv_pdu(deadline)?;
+            if self.handle_unsolicited(&resp)? {
+                continue;
+            }
+            if resp.opcode() == op::NOP_IN && resp.itt() == itt {
+                return Ok(());
+            }
+            warn!("iSCSI: unexpected PDU {:#x}", resp.opcode());
+        }
+    }
+
+    /// Pings the target if nothing was received for the keepalive interval,
+    /// and reconnects if it does not answer.
+    pub fn keepalive(&mut self) {
+        if monotonic_time() < self.last_rx + self.config.keepalive {
+            return;
+        }
+        match self.ping() {
+            Ok(()) | Err(CmdError::Failed(_)) | Err(CmdError::Retry) => {}
+            Err(CmdError::Conn(e)) => {
+                warn!("iSCSI: keepalive failed: {:?}, reconnecting", e);
+                if let Err(e) = self.reconnect() {
+                    warn!("iSCSI: reconnection failed: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Executes the SCSI command `cdb` on the LUN, reconnecting and retrying
+    /// it if the connection is broken.
+    pub fn execute(&mut self, cdb: &[u8; 16], mut dir: DataDir) -> AxResult {
+        for attempt in 0..MAX_ATTEMPTS {
+            if self.sock.is_none() {
+                if let Err(e) = self.reconnect() {
+                    warn!("iSCSI: reconnection failed: {:?}", e);
+                    continue;
+                }
+            }
+            match self.try_execute(cdb, &mut dir) {
+                Ok(()) => return Ok(()),
+                Err(CmdError::Retry) => {}
+                Err(CmdError::Failed(e)) => return Err(e),
+                Err(CmdError::Conn(e)) => {
+                    warn!(
+                        "iSCSI: connection error {:?} (attempt {}), reconnecting",
+                        e,
+                        attempt + 1
+                    );
+                    self.sock = None;
+                }
+            }
+        }
+        ax_err!(Io, "iSCSI: command failed after retries")
+    }
+
+    fn try_execute(&mut self, cdb: &[u8; 16], dir: &mut DataDir) -> Result<(), CmdError> {
+        let deadline = deadline(CMD_TIMEOUT);
+        // wait for the command window to open
+        while sn_lt(self.max_cmd_sn, self.cmd_sn) {
+            if monotonic_time() >= deadline {
+                return Err(CmdError::Conn(AxError::TimedOut));
+            }
+            self.ping()?;
+        }
+
+        let (flags, len) = match dir {
+            DataDir::None => (0, 0),
+            DataDir::Read(buf) => (0x40, buf.len()),
+            DataDir::Write(buf) => (0x20, buf.len()),
+        };
+        let itt = self.alloc_itt();
+        let lun = pdu::encode_lun(self.config.lun);
+        // the simple task attribute
+        let mut cmd = Pdu::new(op::SCSI_CMD, FINAL | flags | 0x01);
+        cmd.set_u64(8, lun);
+        cmd.set_itt(itt);
+        cmd.set_u32(20, len as u32);
+        cmd.set_u32(24, self.cmd_sn);
+        cmd.set_u32(28, self.exp_stat_sn);
+        cmd.bhs[32..48].copy_from_slice(cdb);
+        self.cmd_sn = self.cmd_sn.wrapping_add(1);
+        self.send_pdu(&cmd)?;
+
+        loop {
+            let pdu = self.recv_pdu(deadline)?;
+            if self.handle_unsolicited(&pdu)? {
+                continue;
+            }
+            if pdu.itt() != itt {
+                warn!("iSCSI: PDU {:#x} for unknown task", pdu.opcode());
+                continue;
+            }
+            match pdu.opcode() {
+                op::DATA_IN => {
+                    let DataDir::Read(buf) = &mut *dir else {
+                        return Err(CmdError::Conn(AxError::InvalidData));
+                    };
+                    let offset = pdu.u32(40) as usize;
+                    let Some(dst) = buf.get_mut(offset..offset + pdu.data.len()) else {
+                        return Err(CmdError::Conn(AxError::InvalidData));
+                    };
+                    dst.copy_from_slice(&pdu.data);
+                    if pdu.flags() & 0x01 != 0 {
+                        return self.check_status(pdu.bhs[3], &[]);
+                    }
+                }
+                op::R2T => {
+                    let DataDir::Write(buf) = &*dir else {
+                        return Err(CmdError::Conn(AxError::InvalidData));
+                    };
+                    let ttt = pdu.u32(20);
+                    let offset = pdu.u32(40) as usize;
+                    let len = pdu.u32(44) as usize;
+                    let Some(data) = buf.get(offset..offset + len) else {
+                        return Err(CmdError::Conn(AxError::InvalidData));
+                    };
+                    self.send_data_out(lun, itt, ttt, offset, data)?;
+                }
+                op::SCSI_RESP => {
+                    if pdu.bhs[2] != 0 {
+                        warn!("iSCSI: target failure {:#x}", pdu.bhs[2]);
+                        return Err(CmdError::Failed(AxError::Io));
+                    }
+                    return self.check_status(pdu.bhs[3], &pdu.data);
+                }
+                opcode => {
+                    warn!("iSCSI: unexpected PDU {:#x}", opcode);
+                    return Err(CmdError::Conn(AxError::InvalidData));
+                }
+            }
+        }
+    }
+
+    fn send_data_out(
+        &mut self,
+        lun: u64,
+        itt: u32,
+        ttt: u32,
+        offset: usize,
+        data: &[u8],
+    ) -> AxResult {
+        let max_len = self.params.max_send_data.max(512);
+        let num_pdus = data.len().div_ceil(max_len).max(1);
+        for (sn, chunk) in data.chunks(max_len).enumerate() {
+            let flags = if sn + 1 == num_pdus { FINAL } else { 0 };
+            let mut pdu = Pdu::new(op::DATA_OUT, flags);
+            pdu.set_u64(8, lun);
+            pdu.set_itt(itt);
+            pdu.set_u32(20, ttt);
+            pdu.set_u32(28, self.exp_stat_sn);
+            pdu.set_u32(36, sn as u32);
+            pdu.set_u32(40, (offset + sn * max_len) as u32);
+            pdu.set_data(chunk.to_vec());
+            self.send_pdu(&pdu)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the SCSI status, with the sense data of the response.
+    fn check_status(&self, status: u8, sense: &[u8]) -> Result<(), CmdError> {
+        match status {
+            STATUS_GOOD => Ok(()),
+            STATUS_BUSY | STATUS_TASK_SET_FULL => {
+                axtask::yield_now();
+                Err(CmdError::Retry)
+            }
+            STATUS_CHECK_CONDITION => {
+                // the data segment is the sense length and the sense data
+                let sense = sense.get(2..).unwrap_or(&[]);
+                let key = match sense.first().map(|code| code & 0x7f) {
+                    Some(0x70 | 0x71) => sense.get(2).map(|key| key & 0x0f),
+                    Some(0x72 | 0x73) => sense.get(1).map(|key| key & 0x0f),
+                    _ => None,
+                };
+                if key == Some(SENSE_UNIT_ATTENTION) {
+                    // reported once after a reset or a reconnection
+                    return Err(CmdError::Retry);
+                }
+                warn!("iSCSI: check condition, sense key {:?}", key);
+                Err(CmdError::Failed(AxError::Io))
+            }
+            _ => {
+                warn!("iSCSI: SCSI status {:#x}", status);
+                Err(CmdError::Failed(AxError::Io))
+            }
+        }
+    }
+
+    /// Returns the number of blocks and the block size of the LUN.
+    pub fn read_capacity(&mut self) -> AxResult<(u64, u32)> {
+        // SERVICE ACTION IN (16), READ CAPACITY (16)
+        let mut cdb = [0; 16];
+        cdb[0] = 0x9e;
+        cdb[1] = 0x10;
+        cdb[10..14].copy_from_slice(&32u32.to_be_bytes());
+        let mut data = [0; 32];
+        self.execute(&cdb, DataDir::Read(&mut data))?;
+        let last_lba = u64::from_be_bytes(data[..8].try_into().unwrap());
+        let block_size = u32::from_be_bytes(data[8..12].try_into().unwrap());
+        if block_size == 0 {
+            return Err(ax_err_type!(InvalidData, "iSCSI: invalid block size"));
+        }
+        Ok((last_lba + 1, block_size))
+    }
+
+    /// Reads or writes `num_blocks` blocks of the LUN from `lba`, by the
+    /// direction of `dir`.
+    pub fn read_write(&mut self, lba: u64, num_blocks: u32, dir: DataDir) -> AxResult {
+        // READ (16) or WRITE (16)
+        let mut cdb = [0; 16];
+        cdb[0] = match dir {
+            DataDir::Write(_) => 0x8a,
+            _ => 0x88,
+        };
+        cdb[2..10].copy_from_slice(&lba.to_be_bytes());
+        cdb[10..14].copy_from_slice(&num_blocks.to_be_bytes());
+        self.execute(&cdb, dir)
+    }
+
+    /// Flushes the write cache of the LUN.
+    pub fn synchronize_cache(&mut self) -> AxResult {
+        // SYNCHRONIZE CACHE (10), the whole LUN
+        let mut cdb = [0; 16];
+        cdb[0] = 0x35;
+        self.execute(&cdb, DataDir::None)
+    }
+
+    /// Logs out, and closes the connection.
+    pub fn logout(&mut self) {
+        if self.sock.is_none() {
+            return;
+        }
+        let itt = self.alloc_itt();
+        // close the session
+        let mut pdu = Pdu::new(op::LOGOUT_REQ | IMMEDIATE, FINAL);
+        pdu.set_itt(itt);
+        pdu.set_u32(24, self.cmd_sn);
+        pdu.set_u32(28, self.exp_stat_sn);
+        if self.send_pdu(&pdu).is_ok() {
+            let deadline = deadline(NOP_TIMEOUT);
+            while let Ok(resp) = self.recv_pdu(deadline) {
+                if resp.opcode() == op::LOGOUT_RESP {
+                    break;
+                }
+            }
+        }
+        self.sock = None;
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.logout();
+    }
+}
@@ -0,0 +1,184 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) loadable kernel modules.
+//!
+//! A module is a relocatable ELF object (`ET_REL`) for the architecture of
+//! the kernel, e.g. a driver added to a deployed image without rebuilding it.
+//! It is written as a `no_std` crate declaring itself with [`module!`]:
+//!
+//! ```ignore
+//! fn hello_init() -> axerrno::AxResult {
+//!     Ok(())
+//! }
+//!
+//! fn hello_exit() {}
+//!
+//! axkmod::module! {
+//!     name: "hello",
+//!     depends: ["greeter"],
+//!     init: hello_init,
+//!     exit: hello_exit,
+//! }
+//! ```
+//!
+//! and built as one object with the crates it uses but the kernel ones, e.g.
+//! with `ld -r`, by the toolchain of the kernel, without linker relaxation on
+//! RISC-V (`-C target-feature=-relax`).
+//!
+//! The undefined symbols of a module are resolved against the
+//! [symbols exported](export_symbol!) by the kernel, then by the modules
+//! loaded: a module exports its symbols with [`export_symbol!`] as the kernel
+//! does. A module depends on the modules whose symbols it uses and on the
+//! ones it declares, which are loaded first and cannot be unloaded while it
+//! is.
+//!
+//! The [`loader`] is enabled by the `loader` feature.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "loader")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "loader")]
+extern crate alloc;
+
+#[cfg(feature = "loader")]
+pub mod loader;
+pub mod reloc;
+
+#[cfg(test)]
+mod tests;
+
+#[doc(hidden)]
+pub use {axerrno, linkme};
+
+/// The section of the information of a module, `key=value` strings ended by
+/// a zero.
+pub const MODINFO_SECTION: &str = ".modinfo";
+/// The symbol of the initialization function of a module,
+/// `extern "C" fn() -> i32` returning 0 or the `errno` of its error.
+pub const INIT_SYMBOL: &str = "init_module";
+/// The symbol of the exit function of a module, `extern "C" fn()`.
+pub const EXIT_SYMBOL: &str = "cleanup_module";
+
+/// A symbol exported to the modules, see [`export_symbol!`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct KernelSymbol {
+    /// The name the modules refer to it by.
+    pub name: &'static str,
+    /// The address.
+    pub addr: *const (),
+}
+
+// SAFETY: the symbols are read only.
+unsafe impl Sync for KernelSymbol {}
+
+/// All the symbols exported by the crates of the kernel.
+#[linkme::distributed_slice]
+pub static KERNEL_SYMBOLS: [KernelSymbol];
+
+/// Returns the address of the symbol `name` exported by the kernel.
+pub fn find_symbol(name: &str) -> Option<*const ()> {
+    KERNEL_SYMBOLS
+        .iter()
+        .find(|sym| sym.name == name)
+        .map(|sym| sym.addr)
+}
+
+/// Exports a function, or a static with `static`, to the modules, by its name
+/// or by the given one.
+///
+/// The modules call the functions with the ABI they are declared with: an
+/// `extern "C"` one keeps working across toolchains.
+#[macro_export]
+macro_rules! export_symbol {
+    (static $var:ident) => {
+        $crate::export_symbol!(@export stringify!($var), core::ptr::addr_of!($var) as *const ());
+    };
+    ($func:ident) => {
+        $crate::export_symbol!(@export stringify!($func), $func as *const ());
+    };
+    ($name:literal, $func:path) => {
+        $crate::export_symbol!(@export $name, $func as *const ());
+    };
+    (@export $name:expr, $addr:expr) => {
+        const _: () = {
+            #[$crate::linkme::distributed_slice($crate::KERNEL_SYMBOLS)]
+            #[linkme(crate = $crate::linkme)]
+            static SYMBOL: $crate::KernelSymbol = $crate::KernelSymbol {
+                name: $name,
+                addr: $addr,
+            };
+        };
+    };
+}
+
+/// Returns the value of `key` in the information `modinfo` of a module.
+pub fn modinfo_get<'a>(modinfo: &'a [u8], key: &str) -> Option<&'a str> {
+    modinfo
+        .split(|&b| b == 0)
+        .filter_map(|entry| core::str::from_utf8(entry).ok())
+        .find_map(|entry| entry.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Returns the dependencies declared in the information `modinfo` of a
+/// module.
+pub fn modinfo_depends(modinfo: &[u8]) -> impl Iterator<Item = &str> {
+    modinfo_get(modinfo, "depends")
+        .unwrap_or("")
+        .split(',')
+        .filter(|dep| !dep.is_empty())
+}
+
+#[doc(hidden)]
+pub const fn modinfo_bytes<const N: usize>(info: &str) -> [u8; N] {
+    let mut bytes = [0; N];
+    let info = info.as_bytes();
+    let mut i = 0;
+    while i < N {
+        bytes[i] = info[i];
+        i += 1;
+    }
+    bytes
+}
+
+/// Declares a module: its name, the modules it depends on, and its
+/// initialization and exit functions, see the [crate documentation](crate).
+///
+/// The initialization function returns an `AxResult`, the module is unloaded
+/// if it fails.
+#[macro_export]
+macro_rules! module {
+    (
+        name: $name:literal,
+        $(depends: [$($dep:literal),* $(,)?],)?
+        init: $init:path
+        $(, exit: $exit:path)?
+        $(,)?
+    ) => {
+        const _: () = {
+            const INFO: &str = concat!(
+                "name=", $name, "\0"
+                $(, "depends=" $(, $dep, ",")*, "\0")?
+            );
+
+            #[used]
+            #[link_section = ".modinfo"]
+            static MODINFO: [u8; INFO.len()] = $crate::modinfo_bytes(INFO);
+
+            #[no_mangle]
+            extern "C" fn init_module() -> i32 {
+                match $init() {
+                    Ok(()) => 0,
+                    Err(e) => $crate::axerrno::LinuxError::from(e).code(),
+                }
+            }
+
+            $(
+                #[no_mangle]
+                extern "C" fn cleanup_module() {
+                    $exit()
+                }
+            )?
+        };
+    };
+}
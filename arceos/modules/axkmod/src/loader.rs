@@ -0,0 +1,622 @@
+//! Loading of the modules in the kernel.
+//!
+//! [`load`] reads a module from the filesystem, loads the modules it declares
+//! it depends on from the same directory first, as `<name>.ko`, links it and
+//! runs its initialization function. The sections of a module are laid out
+//! in new pages, the code ones mapped read-only and executable, the read-only
+//! data ones read-only, followed by its PLT and GOT. [`unload`] runs the exit
+//! function of a module no other one depends on and frees its pages. The
+//! initialization and exit functions run with the modules locked, they cannot
+//! load or unload modules.
+//!
+//! The `insmod`, `rmmod` and `lsmod` shell commands do the same.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axsync::Mutex;
+use elf::abi::{
+    ET_REL, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHN_ABS, SHN_COMMON, SHN_UNDEF, SHT_NOBITS,
+    SHT_REL, SHT_RELA, STB_LOCAL, STB_WEAK,
+};
+use elf::endian::AnyEndian;
+use elf::section::SectionHeader;
+use elf::symbol::Symbol;
+use elf::{ElfBytes, ParseError};
+
+use crate::reloc::{self, Machine, Reloc, GOT_ENTRY_LEN, PLT_ENTRY_LEN};
+use crate::{find_symbol, KernelSymbol, EXIT_SYMBOL, INIT_SYMBOL, MODINFO_SECTION};
+
+/// The section of the symbols exported by a module with `export_symbol!`.
+const EXPORT_SECTION: &str = "linkme_KERNEL_SYMBOLS";
+
+/// A module loaded.
+#[derive(Debug, Clone)]
+pub struct ModuleInfo {
+    /// The name.
+    pub name: String,
+    /// The start of its pages.
+    pub base: VirtAddr,
+    /// The bytes of its pages.
+    pub size: usize,
+    /// The names of the modules it depends on.
+    pub depends: Vec<String>,
+    /// The names of the modules depending on it.
+    pub users: Vec<String>,
+}
+
+struct Module {
+    name: String,
+    base: usize,
+    size: usize,
+    exports: Vec<(String, usize)>,
+    depends: Vec<String>,
+    exit: Option<usize>,
+}
+
+static MODULES: Mutex<Vec<Module>> = Mutex::new(Vec::new());
+
+fn invalid(err: ParseError) -> AxError {
+    warn!("kmod: invalid module: {:?}", err);
+    AxError::InvalidData
+}
+
+/// The parts of a module: text, read-only data and writable data.
+fn part_of(shdr: &SectionHeader) -> Option<usize> {
+    let flags = shdr.sh_flags as u32;
+    if flags & SHF_ALLOC == 0 || shdr.sh_size == 0 {
+        None
+    } else if flags & SHF_EXECINSTR != 0 {
+        Some(0)
+    } else if flags & SHF_WRITE == 0 {
+        Some(1)
+    } else {
+        Some(2)
+    }
+}
+
+/// The layout of the pages of a module.
+struct Layout {
+    /// The offsets of the allocated sections, by index.
+    sections: Vec<Option<usize>>,
+    /// The ends of the text and of the read-only data, and the size, all
+    /// page-aligned.
+    ends: [usize; 3],
+    plt: usize,
+    got: usize,
+    /// The PLT and GOT entries of the symbols, by index.
+    plt_slots: BTreeMap<u32, usize>,
+    got_slots: BTreeMap<u32, usize>,
+}
+
+/// A parsed module.
+struct Object<'a> {
+    file: ElfBytes<'a, AnyEndian>,
+    machine: Machine,
+    shdrs: Vec<SectionHeader>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Object<'a> {
+    fn parse(data: &'a [u8]) -> AxResult<Self> {
+        let file = ElfBytes::<AnyEndian>::minimal_parse(data).map_err(invalid)?;
+        if file.ehdr.e_type != ET_REL {
+            return ax_err!(InvalidData, "not a relocatable object");
+        }
+        let machine = Machine::from_elf(file.ehdr.e_machine);
+        if machine.is_none() || machine != Machine::native() {
+            return ax_err!(Unsupported, "module of another architecture");
+        }
+        let (shdrs, strtab) = file.section_headers_with_strtab().map_err(invalid)?;
+        let (Some(shdrs), Some(strtab)) = (shdrs, strtab) else {
+            return ax_err!(InvalidData, "module without sections");
+        };
+        let shdrs: Vec<_> = shdrs.iter().collect();
+        let names = shdrs
+            .iter()
+            .map(|shdr| strtab.get(shdr.sh_name as usize))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+        Ok(Self {
+            file,
+            machine: machine.unwrap(),
+            shdrs,
+            names,
+        })
+    }
+
+    fn section(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|&n| n == name)
+    }
+
+    fn modinfo(&self) -> AxResult<&'a [u8]> {
+        let Some(index) = self.section(MODINFO_SECTION) else {
+            return ax_err!(InvalidData, "module without .modinfo");
+        };
+        let (data, _) = self
+            .file
+            .section_data(&self.shdrs[index])
+            .map_err(invalid)?;
+        Ok(data)
+    }
+
+    /// Returns the relocation sections and the sections they apply to, the
+    /// allocated ones only.
+    fn rela_sections(&self) -> AxResult<Vec<(usize, usize)>> {
+        let mut relas = Vec::new();
+        for (index, shdr) in self.shdrs.iter().enumerate() {
+            if shdr.sh_type != SHT_RELA && shdr.sh_type != SHT_REL {
+                continue;
+            }
+            let target = shdr.sh_info as usize;
+            if self.shdrs.get(target).and_then(part_of).is_none() {
+                continue;
+            }
+            if shdr.sh_type == SHT_REL {
+                return ax_err!(Unsupported, "relocations without addends");
+            }
+            relas.push((index, target));
+        }
+        Ok(relas)
+    }
+
+    fn layout(&self) -> AxResult<Layout> {
+        let mut plt_slots = BTreeMap::new();
+        let mut got_slots = BTreeMap::new();
+        for (index, _) in self.rela_sections()? {
+            let relas = self
+                .file
+                .section_data_as_relas(&self.shdrs[index])
+                .map_err(invalid)?;
+            for rela in relas {
+                if self.machine.uses_plt(rela.r_type) {
+                    let len = plt_slots.len();
+                    plt_slots.entry(rela.r_sym).or_insert(len);
+                }
+                if self.machine.uses_got(rela.r_type) {
+                    let len = got_slots.len();
+                    got_slots.entry(rela.r_sym).or_insert(len);
+                }
+            }
+        }
+
+        let mut sections = alloc::vec![None; self.shdrs.len()];
+        let mut ends = [0; 3];
+        let (mut plt, mut got) = (0, 0);
+        let mut offset = 0usize;
+        for (part, end) in ends.iter_mut().enumerate() {
+            for (index, shdr) in self.shdrs.iter().enumerate() {
+                if part_of(shdr) == Some(part) {
+                    offset = offset.next_multiple_of(shdr.sh_addralign.max(1) as usize);
+                    sections[index] = Some(offset);
+                    offset += shdr.sh_size as usize;
+                }
+            }
+            if part == 0 {
+                plt = offset.next_multiple_of(PLT_ENTRY_LEN);
+                offset = plt + plt_slots.len() * PLT_ENTRY_LEN;
+            } else if part == 1 {
+                got = offset.next_multiple_of(GOT_ENTRY_LEN);
+                offset = got + got_slots.len() * GOT_ENTRY_LEN;
+            }
+            offset = offset.next_multiple_of(PAGE_SIZE_4K);
+            *end = offset;
+        }
+        if offset == 0 {
+            return ax_err!(InvalidData, "empty module");
+        }
+        Ok(Layout {
+            sections,
+            ends,
+            plt,
+            got,
+            plt_slots,
+            got_slots,
+        })
+    }
+}
+
+/// Links a module in its pages at `base`.
+struct Linker<'a, 'b> {
+    obj: &'b Object<'a>,
+    layout: &'b Layout,
+    base: usize,
+    modules: &'b [Module],
+    depends: Vec<String>,
+}
+
+impl Linker<'_, '_> {
+    fn section_addr(&self, index: usize) -> Option<usize> {
+        self.layout
+            .sections
+            .get(index)
+            .copied()
+            .flatten()
+            .map(|offset| self.base + offset)
+    }
+
+    /// Resolves an undefined symbol against the kernel, the modules loaded,
+    /// and the bounds of the sections of the module.
+    fn resolve_undefined(&mut self, name: &str, weak: bool) -> AxResult<u64> {
+        if let Some(addr) = find_symbol(name) {
+            return Ok(addr as u64);
+        }
+        for module in self.modules {
+            if let Some((_, addr)) = module.exports.iter().find(|(n, _)| n == name) {
+                if !self.depends.contains(&module.name) {
+                    self.depends.push(module.name.clone());
+                }
+                return Ok(*addr as u64);
+            }
+        }
+        let bound = match (name.strip_prefix("__start_"), name.strip_prefix("__stop_")) {
+            (Some(section), _) => Some((section, false)),
+            (_, Some(section)) => Some((section, true)),
+            _ => None,
+        };
+        if let Some((section, at_end)) = bound {
+            let index = self.obj.section(section);
+            if let Some(addr) = index.and_then(|index| self.section_addr(index)) {
+                let size = self.obj.shdrs[index.unwrap()].sh_size;
+                return Ok(addr as u64 + if at_end { size } else { 0 });
+            }
+        }
+        if weak {
+            return Ok(0);
+        }
+        warn!("kmod: unresolved symbol {}", name);
+        ax_err!(NotFound, "unresolved symbol")
+    }
+
+    fn resolve(&mut self, sym: &Symbol, name: &str) -> AxResult<u64> {
+        match sym.st_shndx {
+            SHN_UNDEF if name.is_empty() => Ok(0),
+            SHN_UNDEF => self.resolve_undefined(name, sym.st_bind() == STB_WEAK),
+            SHN_ABS => Ok(sym.st_value),
+            SHN_COMMON => ax_err!(Unsupported, "common symbols"),
+            index => match self.section_addr(index as usize) {
+                Some(addr) => Ok(addr as u64 + sym.st_value),
+                None => ax_err!(InvalidData, "symbol in a section not loaded"),
+            },
+        }
+    }
+
+    /// Lays the sections out in `image`, resolves the symbols and applies the
+    /// relocations.
+    fn link(&mut self, image: &mut [u8]) -> AxResult {
+        let obj = self.obj;
+        for (index, shdr) in obj.shdrs.iter().enumerate() {
+            let Some(offset) = self.layout.sections[index] else {
+                continue;
+            };
+            if shdr.sh_type != SHT_NOBITS {
+                let (data, _) = obj.file.section_data(shdr).map_err(invalid)?;
+                image[offset..offset + data.len()].copy_from_slice(data);
+            }
+        }
+
+        let Some((symtab, strtab)) = obj.file.symbol_table().map_err(invalid)? else {
+            return ax_err!(InvalidData, "module without symbols");
+        };
+        let mut values = BTreeMap::new();
+        for (index, target) in obj.rela_sections()? {
+            let section = self.layout.sections[target].unwrap();
+            let mut relocs = Vec::new();
+            let relas = obj
+                .file
+                .section_data_as_relas(&obj.shdrs[index])
+                .map_err(invalid)?;
+            for rela in relas {
+                let value = match values.get(&rela.r_sym) {
+                    Some(&value) => value,
+                    None => {
+                        let sym = symtab.get(rela.r_sym as usize).map_err(invalid)?;
+                        let name = strtab.get(sym.st_name as usize).map_err(invalid)?;
+                        let value = self.resolve(&sym, name)?;
+                        values.insert(rela.r_sym, value);
+                        value
+                    }
+                };
+                let slot = |slots: &BTreeMap<u32, usize>, start, len| {
+                    slots
+                        .get(&rela.r_sym)
+                        .map_or(0, |i| (self.base + start + i * len) as u64)
+                };
+                relocs.push(Reloc {
+                    r_type: rela.r_type,
+                    offset: section + rela.r_offset as usize,
+                    sym: value,
+                    addend: rela.r_addend,
+                    got: slot(&self.layout.got_slots, self.layout.got, GOT_ENTRY_LEN),
+                    plt: slot(&self.layout.plt_slots, self.layout.plt, PLT_ENTRY_LEN),
+                });
+            }
+            if let Err(e) = reloc::apply(obj.machine, image, self.base as u64, &relocs) {
+                warn!("kmod: cannot relocate {}: {:?}", obj.names[target], e);
+                return Err(e);
+            }
+        }
+
+        for (&sym, &slot) in &self.layout.got_slots {
+            let offset = self.layout.got + slot * GOT_ENTRY_LEN;
+            image[offset..offset + GOT_ENTRY_LEN].copy_from_slice(&values[&sym].to_le_bytes());
+        }
+        for (&sym, &slot) in &self.layout.plt_slots {
+            let offset = self.layout.plt + slot * PLT_ENTRY_LEN;
+            if let Some(entry) = obj.machine.plt_entry(values[&sym]) {
+                image[offset..offset + PLT_ENTRY_LEN].copy_from_slice(&entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the address of the global symbol `name` defined by the module.
+    fn find_defined(&self, name: &str) -> AxResult<Option<usize>> {
+        let Some((symtab, strtab)) = self.obj.file.symbol_table().map_err(invalid)? else {
+            return Ok(None);
+        };
+        for sym in symtab.iter() {
+            if sym.st_bind() == STB_LOCAL || sym.is_undefined() || sym.st_shndx == SHN_ABS {
+                continue;
+            }
+            if strtab.get(sym.st_name as usize).map_err(invalid)? == name {
+                return Ok(self
+                    .section_addr(sym.st_shndx as usize)
+                    .map(|addr| addr + sym.st_value as usize));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the symbols the module exports, from the section filled by
+    /// `export_symbol!`, relocated.
+    fn exports(&self) -> AxResult<Vec<(String, usize)>> {
+        let Some(index) = self.obj.section(EXPORT_SECTION) else {
+            return Ok(Vec::new());
+        };
+        let Some(addr) = self.section_addr(index) else {
+            return Ok(Vec::new());
+        };
+        let count = self.obj.shdrs[index].sh_size as usize / core::mem::size_of::<KernelSymbol>();
+        // SAFETY: the section is an array of `KernelSymbol` built by the same
+        // toolchain, relocated.
+        let symbols = unsafe { core::slice::from_raw_parts(addr as *const KernelSymbol, count) };
+        let mut exports = Vec::new();
+        for sym in symbols {
+            let exported = find_symbol(sym.name).is_some()
+                || self
+                    .modules
+                    .iter()
+                    .any(|m| m.exports.iter().any(|(n, _)| n == sym.name));
+            if exported {
+                warn!("kmod: symbol {} exported twice", sym.name);
+                return ax_err!(AlreadyExists, "symbol already exported");
+            }
+            exports.push((sym.name.to_string(), sym.addr as usize));
+        }
+        Ok(exports)
+    }
+}
+
+/// Gives the pages of a module back, writable as they were allocated.
+fn free_pages(base: usize, size: usize) {
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    if let Err(e) = axmm::kernel_aspace()
+        .lock()
+        .protect(VirtAddr::from(base), size, flags)
+    {
+        // Leak them rather than hand out read-only pages.
+        warn!("kmod: cannot free the pages at {:#x}: {:?}", base, e);
+        return;
+    }
+    axalloc::global_allocator().dealloc_pages(base, size / PAGE_SIZE_4K);
+}
+
+/// Loads the module `data`, whose dependencies are loaded, and returns its
+/// name.
+pub fn load_bytes(data: &[u8]) -> AxResult<String> {
+    let obj = Object::parse(data)?;
+    let modinfo = obj.modinfo()?;
+    let Some(name) = crate::modinfo_get(modinfo, "name").filter(|name| !name.is_empty()) else {
+        return ax_err!(InvalidData, "module without a name");
+    };
+
+    let mut modules = MODULES.lock();
+    if modules.iter().any(|m| m.name == name) {
+        return ax_err!(AlreadyExists, "module already loaded");
+    }
+    let mut depends = Vec::new();
+    for dep in crate::modinfo_depends(modinfo) {
+        if !modules.iter().any(|m| m.name == dep) {
+            warn!("kmod: {} depends on {}, not loaded", name, dep);
+            return ax_err!(NotFound, "dependency not loaded");
+        }
+        depends.push(dep.to_string());
+    }
+
+    let layout = obj.layout()?;
+    let size = layout.ends[2];
+    let base = axalloc::global_allocator()
+        .alloc_pages(size / PAGE_SIZE_4K, PAGE_SIZE_4K)
+        .map_err(|_| AxError::NoMemory)?;
+    // SAFETY: the pages are just allocated, for the module only.
+    let image = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, size) };
+    image.fill(0);
+    let mut linker = Linker {
+        obj: &obj,
+        layout: &layout,
+        base,
+        modules: &modules,
+        depends,
+    };
+    let linked = linker.link(image).and_then(|_| {
+        let init = linker.find_defined(INIT_SYMBOL)?;
+        let exit = linker.find_defined(EXIT_SYMBOL)?;
+        Ok((init, exit, linker.exports()?))
+    });
+    let (init, exit, exports) = match linked {
+        Ok(linked) => linked,
+        Err(e) => {
+            free_pages(base, size);
+            return Err(e);
+        }
+    };
+    let depends = linker.depends;
+
+    let [text_end, ro_end, _] = layout.ends;
+    let mut aspace = axmm::kernel_aspace().lock();
+    let mut protected = Ok(());
+    if text_end > 0 {
+        let flags = MappingFlags::READ | MappingFlags::EXECUTE;
+        protected = aspace.protect(VirtAddr::from(base), text_end, flags);
+    }
+    if protected.is_ok() && ro_end > text_end {
+        let start = VirtAddr::from(base + text_end);
+        protected = aspace.protect(start, ro_end - text_end, MappingFlags::READ);
+    }
+    drop(aspace);
+    if let Err(e) = protected {
+        free_pages(base, size);
+        return Err(e);
+    }
+    // The pages were written as data, the instruction cache of this CPU may
+    // hold stale lines of them.
+    #[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+    axhal::arch::flush_icache_all();
+
+    if let Some(init) = init {
+        // SAFETY: `init_module` is defined by `module!` with this signature.
+        let init = unsafe { core::mem::transmute::<usize, extern "C" fn() -> i32>(init) };
+        let code = init();
+        if code != 0 {
+            warn!("kmod: {} failed to initialize: errno {}", name, code);
+            free_pages(base, size);
+            return ax_err!(BadState, "module initialization failed");
+        }
+    }
+    info!(
+        "kmod: loaded {} at {:#x}, {} bytes, depends on {:?}",
+        name, base, size, depends
+    );
+    modules.push(Module {
+        name: name.to_string(),
+        base,
+        size,
+        exports,
+        depends,
+        exit,
+    });
+    Ok(name.to_string())
+}
+
+/// Loads the module at `path`, and first the modules it depends on not
+/// loaded from `<name>.ko` in its directory, and returns its name.
+pub fn load(path: &str) -> AxResult<String> {
+    load_with_deps(path, &mut Vec::new())
+}
+
+fn load_with_deps(path: &str, loading: &mut Vec<String>) -> AxResult<String> {
+    let data = axfs::api::read(path)?;
+    let obj = Object::parse(&data)?;
+    let modinfo = obj.modinfo()?;
+    let name = crate::modinfo_get(modinfo, "name").unwrap_or("");
+    if loading.iter().any(|n| n == name) {
+        warn!("kmod: circular dependency on {}", name);
+        return ax_err!(InvalidData, "circular dependency");
+    }
+    loading.push(name.to_string());
+    let dir = path.rsplit_once('/').map_or(".", |(dir, _)| dir);
+    for dep in crate::modinfo_depends(modinfo) {
+        if !is_loaded(dep) {
+            load_with_deps(&alloc::format!("{}/{}.ko", dir, dep), loading)?;
+        }
+    }
+    loading.pop();
+    load_bytes(&data)
+}
+
+/// Returns whether the module `name` is loaded.
+pub fn is_loaded(name: &str) -> bool {
+    MODULES.lock().iter().any(|m| m.name == name)
+}
+
+/// Runs the exit function of the module `name` and unloads it.
+///
+/// Fails if other modules depend on it, or if it has no exit function.
+pub fn unload(name: &str) -> AxResult {
+    let mut modules = MODULES.lock();
+    let Some(pos) = modules.iter().position(|m| m.name == name) else {
+        return ax_err!(NotFound, "module not loaded");
+    };
+    if let Some(user) = modules.iter().find(|m| m.depends.iter().any(|d| d == name)) {
+        warn!("kmod: {} is used by {}", name, user.name);
+        return ax_err!(ResourceBusy, "module in use");
+    }
+    let Some(exit) = modules[pos].exit else {
+        return ax_err!(Unsupported, "module without exit function");
+    };
+    // SAFETY: `cleanup_module` is defined by `module!` with this signature.
+    let exit = unsafe { core::mem::transmute::<usize, extern "C" fn()>(exit) };
+    exit();
+    let module = modules.remove(pos);
+    free_pages(module.base, module.size);
+    info!("kmod: unloaded {}", name);
+    Ok(())
+}
+
+/// Returns the modules loaded, in the order they were.
+pub fn modules() -> Vec<ModuleInfo> {
+    let modules = MODULES.lock();
+    modules
+        .iter()
+        .map(|m| ModuleInfo {
+            name: m.name.clone(),
+            base: VirtAddr::from(m.base),
+            size: m.size,
+            depends: m.depends.clone(),
+            users: modules
+                .iter()
+                .filter(|u| u.depends.contains(&m.name))
+                .map(|u| u.name.clone())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Prints the `len` bytes at `msg` on the console, for the modules.
+extern "C" fn kmod_print(msg: *const u8, len: usize) {
+    // SAFETY: the module passes a valid buffer.
+    axhal::console::write_bytes(unsafe { core::slice::from_raw_parts(msg, len) });
+}
+
+crate::export_symbol!(kmod_print);
+
+fn do_insmod(args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+    match load(args.trim()) {
+        Ok(name) => writeln!(out, "loaded {}", name),
+        Err(e) => writeln!(out, "insmod: {}: {:?}", args.trim(), e),
+    }
+}
+
+fn do_rmmod(args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+    match unload(args.trim()) {
+        Ok(()) => Ok(()),
+        Err(e) => writeln!(out, "rmmod: {}: {:?}", args.trim(), e),
+    }
+}
+
+fn do_lsmod(_args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+    writeln!(out, "{:<20} {:>8}  Used by", "Module", "Size")?;
+    for m in modules() {
+        writeln!(out, "{:<20} {:>8}  {}", m.name, m.size, m.users.join(","))?;
+    }
+    Ok(())
+}
+
+axcmd::register_cmd!("insmod", do_insmod, "load a kernel module");
+axcmd::register_cmd!("rmmod", do_rmmod, "unload a kernel module");
+axcmd::register_cmd!("lsmod", do_lsmod, "list the kernel modules loaded");
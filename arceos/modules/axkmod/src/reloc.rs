@@ -0,0 +1,520 @@
+//! The relocations of the modules, for x86_64, RISC-V and AArch64.
+//!
+//! The calls too far for their instructions go through an entry of the PLT
+//! of the module, a jump to the address in it, and the accesses to the GOT
+//! through the entry of the symbol, both laid out by the loader. The RISC-V
+//! calls reach 2 GiB away and need no PLT, but the linker relaxation of the
+//! objects is not supported.
+
+use axerrno::{ax_err, AxResult};
+
+/// The bytes of an entry of the PLT.
+pub const PLT_ENTRY_LEN: usize = 16;
+/// The bytes of an entry of the GOT.
+pub const GOT_ENTRY_LEN: usize = 8;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_GOTPCREL: u32 = 9;
+const R_X86_64_32: u32 = 10;
+const R_X86_64_32S: u32 = 11;
+const R_X86_64_PC64: u32 = 24;
+const R_X86_64_GOTPCRELX: u32 = 41;
+const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_32: u32 = 1;
+const R_RISCV_64: u32 = 2;
+const R_RISCV_BRANCH: u32 = 16;
+const R_RISCV_JAL: u32 = 17;
+const R_RISCV_CALL: u32 = 18;
+const R_RISCV_CALL_PLT: u32 = 19;
+const R_RISCV_GOT_HI20: u32 = 20;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+const R_RISCV_PCREL_LO12_S: u32 = 25;
+const R_RISCV_HI20: u32 = 26;
+const R_RISCV_LO12_I: u32 = 27;
+const R_RISCV_LO12_S: u32 = 28;
+const R_RISCV_ADD32: u32 = 35;
+const R_RISCV_ADD64: u32 = 36;
+const R_RISCV_SUB32: u32 = 39;
+const R_RISCV_SUB64: u32 = 40;
+const R_RISCV_RVC_BRANCH: u32 = 44;
+const R_RISCV_RVC_JUMP: u32 = 45;
+const R_RISCV_RELAX: u32 = 51;
+const R_RISCV_32_PCREL: u32 = 57;
+
+const R_AARCH64_NONE: u32 = 0;
+const R_AARCH64_NULL: u32 = 256;
+const R_AARCH64_ABS64: u32 = 257;
+const R_AARCH64_ABS32: u32 = 258;
+const R_AARCH64_PREL64: u32 = 260;
+const R_AARCH64_PREL32: u32 = 261;
+const R_AARCH64_ADR_PREL_LO21: u32 = 274;
+const R_AARCH64_ADR_PREL_PG_HI21: u32 = 275;
+const R_AARCH64_ADR_PREL_PG_HI21_NC: u32 = 276;
+const R_AARCH64_ADD_ABS_LO12_NC: u32 = 277;
+const R_AARCH64_LDST8_ABS_LO12_NC: u32 = 278;
+const R_AARCH64_TSTBR14: u32 = 279;
+const R_AARCH64_CONDBR19: u32 = 280;
+const R_AARCH64_JUMP26: u32 = 282;
+const R_AARCH64_CALL26: u32 = 283;
+const R_AARCH64_LDST16_ABS_LO12_NC: u32 = 284;
+const R_AARCH64_LDST32_ABS_LO12_NC: u32 = 285;
+const R_AARCH64_LDST64_ABS_LO12_NC: u32 = 286;
+const R_AARCH64_LDST128_ABS_LO12_NC: u32 = 299;
+const R_AARCH64_ADR_GOT_PAGE: u32 = 311;
+const R_AARCH64_LD64_GOT_LO12_NC: u32 = 312;
+
+/// An architecture of the modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    /// x86_64, `EM_X86_64`.
+    X86_64,
+    /// RISC-V 64-bit, `EM_RISCV`.
+    Riscv64,
+    /// AArch64, `EM_AARCH64`.
+    Aarch64,
+}
+
+impl Machine {
+    /// Returns the architecture of the `e_machine` of an ELF header.
+    pub const fn from_elf(e_machine: u16) -> Option<Self> {
+        match e_machine {
+            62 => Some(Self::X86_64),
+            243 => Some(Self::Riscv64),
+            183 => Some(Self::Aarch64),
+            _ => None,
+        }
+    }
+
+    /// Returns the architecture of the kernel.
+    pub const fn native() -> Option<Self> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Self::X86_64)
+        } else if cfg!(target_arch = "riscv64") {
+            Some(Self::Riscv64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Self::Aarch64)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether a relocation of type `r_type` refers to the GOT entry
+    /// of its symbol.
+    pub const fn uses_got(self, r_type: u32) -> bool {
+        match self {
+            Self::X86_64 => matches!(
+                r_type,
+                R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX
+            ),
+            Self::Riscv64 => r_type == R_RISCV_GOT_HI20,
+            Self::Aarch64 => matches!(r_type, R_AARCH64_ADR_GOT_PAGE | R_AARCH64_LD64_GOT_LO12_NC),
+        }
+    }
+
+    /// Returns whether a relocation of type `r_type` is a call which may go
+    /// through a PLT entry.
+    pub const fn uses_plt(self, r_type: u32) -> bool {
+        match self {
+            Self::X86_64 => r_type == R_X86_64_PLT32,
+            Self::Riscv64 => false,
+            Self::Aarch64 => matches!(r_type, R_AARCH64_JUMP26 | R_AARCH64_CALL26),
+        }
+    }
+
+    /// Returns the PLT entry jumping to `target`.
+    pub fn plt_entry(self, target: u64) -> Option<[u8; PLT_ENTRY_LEN]> {
+        let mut entry = [0; PLT_ENTRY_LEN];
+        match self {
+            Self::X86_64 => {
+                // jmp *0(%rip); .quad target; int3; int3
+                entry[..6].copy_from_slice(&[0xff, 0x25, 0, 0, 0, 0]);
+                entry[6..14].copy_from_slice(&target.to_le_bytes());
+                entry[14..].fill(0xcc);
+            }
+            Self::Aarch64 => {
+                // ldr x16, #8; br x16; .quad target
+                entry[..4].copy_from_slice(&0x5800_0050u32.to_le_bytes());
+                entry[4..8].copy_from_slice(&0xd61f_0200u32.to_le_bytes());
+                entry[8..].copy_from_slice(&target.to_le_bytes());
+            }
+            Self::Riscv64 => return None,
+        }
+        Some(entry)
+    }
+}
+
+/// A relocation, its symbol resolved.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reloc {
+    /// The type, of the architecture.
+    pub r_type: u32,
+    /// The offset of the place relocated in the image.
+    pub offset: usize,
+    /// The address of the symbol.
+    pub sym: u64,
+    /// The addend.
+    pub addend: i64,
+    /// The address of the GOT entry of the symbol, if it uses one.
+    pub got: u64,
+    /// The address of the PLT entry of the symbol, if it may use one.
+    pub plt: u64,
+}
+
+struct Image<'a> {
+    data: &'a mut [u8],
+    base: u64,
+}
+
+impl Image<'_> {
+    fn place(&self, r: &Reloc) -> u64 {
+        self.base.wrapping_add(r.offset as u64)
+    }
+
+    fn bytes<const N: usize>(&mut self, offset: usize) -> AxResult<&mut [u8; N]> {
+        match self.data.get_mut(offset..offset.wrapping_add(N)) {
+            Some(bytes) => Ok(bytes.try_into().unwrap()),
+            None => ax_err!(InvalidData, "relocation out of the module"),
+        }
+    }
+
+    fn read16(&mut self, offset: usize) -> AxResult<u16> {
+        Ok(u16::from_le_bytes(*self.bytes(offset)?))
+    }
+
+    fn read32(&mut self, offset: usize) -> AxResult<u32> {
+        Ok(u32::from_le_bytes(*self.bytes(offset)?))
+    }
+
+    fn read64(&mut self, offset: usize) -> AxResult<u64> {
+        Ok(u64::from_le_bytes(*self.bytes(offset)?))
+    }
+
+    fn write16(&mut self, offset: usize, value: u16) -> AxResult {
+        *self.bytes(offset)? = value.to_le_bytes();
+        Ok(())
+    }
+
+    fn write32(&mut self, offset: usize, value: u32) -> AxResult {
+        *self.bytes(offset)? = value.to_le_bytes();
+        Ok(())
+    }
+
+    fn write64(&mut self, offset: usize, value: u64) -> AxResult {
+        *self.bytes(offset)? = value.to_le_bytes();
+        Ok(())
+    }
+
+    /// Writes a signed 32-bit value, checking its range.
+    fn write_i32(&mut self, offset: usize, value: i64) -> AxResult {
+        if !fits(value, 32) {
+            return overflow();
+        }
+        self.write32(offset, value as u32)
+    }
+
+    /// Replaces the bits of `mask` of the instruction at `offset` with `bits`.
+    fn patch32(&mut self, offset: usize, mask: u32, bits: u32) -> AxResult {
+        let insn = self.read32(offset)?;
+        self.write32(offset, (insn & !mask) | (bits & mask))
+    }
+
+    fn patch16(&mut self, offset: usize, mask: u16, bits: u16) -> AxResult {
+        let insn = self.read16(offset)?;
+        self.write16(offset, (insn & !mask) | (bits & mask))
+    }
+}
+
+/// Returns whether `value` fits in `bits` bits, signed.
+fn fits(value: i64, bits: u32) -> bool {
+    let bound = 1i64 << (bits - 1);
+    (-bound..bound).contains(&value)
+}
+
+fn overflow() -> AxResult {
+    ax_err!(InvalidData, "relocation overflow")
+}
+
+fn unsupported() -> AxResult {
+    ax_err!(Unsupported, "unsupported relocation")
+}
+
+/// Applies `relocs` to `image`, loaded at `base`.
+pub fn apply(machine: Machine, image: &mut [u8], base: u64, relocs: &[Reloc]) -> AxResult {
+    let mut image = Image { data: image, base };
+    for (i, r) in relocs.iter().enumerate() {
+        match machine {
+            Machine::X86_64 => apply_x86_64(&mut image, r)?,
+            Machine::Riscv64 => apply_riscv64(&mut image, r, relocs, i)?,
+            Machine::Aarch64 => apply_aarch64(&mut image, r)?,
+        }
+    }
+    Ok(())
+}
+
+fn apply_x86_64(img: &mut Image, r: &Reloc) -> AxResult {
+    let p = img.place(r);
+    let s_a = r.sym.wrapping_add(r.addend as u64);
+    match r.r_type {
+        R_X86_64_NONE => Ok(()),
+        R_X86_64_64 => img.write64(r.offset, s_a),
+        R_X86_64_PC64 => img.write64(r.offset, s_a.wrapping_sub(p)),
+        R_X86_64_PC32 | R_X86_64_PLT32 => {
+            let mut value = s_a.wrapping_sub(p) as i64;
+            if !fits(value, 32) && r.plt != 0 {
+                value = r.plt.wrapping_add(r.addend as u64).wrapping_sub(p) as i64;
+            }
+            img.write_i32(r.offset, value)
+        }
+        R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+            let value = r.got.wrapping_add(r.addend as u64).wrapping_sub(p);
+            img.write_i32(r.offset, value as i64)
+        }
+        R_X86_64_32 => match u32::try_from(s_a) {
+            Ok(value) => img.write32(r.offset, value),
+            Err(_) => overflow(),
+        },
+        R_X86_64_32S => img.write_i32(r.offset, s_a as i64),
+        _ => unsupported(),
+    }
+}
+
+/// Returns the upper 20 bits of `value` for a `lui` or an `auipc`, rounded for
+/// the sign of the lower 12 bits.
+fn riscv_hi20(value: i64) -> AxResult<u32> {
+    let rounded = value.wrapping_add(0x800);
+    if !fits(rounded, 32) {
+        return ax_err!(InvalidData, "relocation overflow");
+    }
+    Ok((rounded as u32) & 0xffff_f000)
+}
+
+/// Returns the immediate of an I-type instruction holding `lo12`.
+fn riscv_itype(lo12: i64) -> u32 {
+    ((lo12 as u32) & 0xfff) << 20
+}
+
+/// Returns the immediate of an S-type instruction holding `lo12`.
+fn riscv_stype(lo12: i64) -> u32 {
+    let imm = lo12 as u32;
+    ((imm >> 5) & 0x7f) << 25 | (imm & 0x1f) << 7
+}
+
+const RISCV_ITYPE_MASK: u32 = 0xfff0_0000;
+const RISCV_STYPE_MASK: u32 = 0xfe00_0f80;
+
+/// Returns the value of the `auipc` a `%pcrel_lo` relocation refers to, by
+/// the relocation at it.
+fn riscv_pcrel_hi(img: &Image, relocs: &[Reloc], i: usize) -> AxResult<i64> {
+    let auipc = relocs[i].sym;
+    let (before, after) = relocs.split_at(i);
+    let hi = before.iter().rev().chain(after).find(|r| {
+        matches!(r.r_type, R_RISCV_PCREL_HI20 | R_RISCV_GOT_HI20) && img.place(r) == auipc
+    });
+    let Some(hi) = hi else {
+        return ax_err!(InvalidData, "%pcrel_lo without %pcrel_hi");
+    };
+    let target = match hi.r_type {
+        R_RISCV_GOT_HI20 => hi.got,
+        _ => hi.sym,
+    };
+    Ok(target.wrapping_add(hi.addend as u64).wrapping_sub(auipc) as i64)
+}
+
+fn apply_riscv64(img: &mut Image, r: &Reloc, relocs: &[Reloc], i: usize) -> AxResult {
+    let p = img.place(r);
+    let s_a = r.sym.wrapping_add(r.addend as u64);
+    let pcrel = s_a.wrapping_sub(p) as i64;
+    match r.r_type {
+        R_RISCV_NONE | R_RISCV_RELAX => Ok(()),
+        R_RISCV_64 => img.write64(r.offset, s_a),
+        R_RISCV_32 => match u32::try_from(s_a) {
+            Ok(value) => img.write32(r.offset, value),
+            Err(_) => overflow(),
+        },
+        R_RISCV_32_PCREL => img.write_i32(r.offset, pcrel),
+        R_RISCV_ADD32 => {
+            let value = img.read32(r.offset)?.wrapping_add(s_a as u32);
+            img.write32(r.offset, value)
+        }
+        R_RISCV_SUB32 => {
+            let value = img.read32(r.offset)?.wrapping_sub(s_a as u32);
+            img.write32(r.offset, value)
+        }
+        R_RISCV_ADD64 => {
+            let value = img.read64(r.offset)?.wrapping_add(s_a);
+            img.write64(r.offset, value)
+        }
+        R_RISCV_SUB64 => {
+            let value = img.read64(r.offset)?.wrapping_sub(s_a);
+            img.write64(r.offset, value)
+        }
+        R_RISCV_BRANCH => {
+            if !fits(pcrel, 13) || pcrel & 1 != 0 {
+                return overflow();
+            }
+            let imm = pcrel as u32;
+            let bits = (imm >> 12 & 1) << 31
+                | (imm >> 5 & 0x3f) << 25
+                | (imm >> 1 & 0xf) << 8
+                | (imm >> 11 & 1) << 7;
+            img.patch32(r.offset, 0xfe00_0f80, bits)
+        }
+        R_RISCV_JAL => {
+            if !fits(pcrel, 21) || pcrel & 1 != 0 {
+                return overflow();
+            }
+            let imm = pcrel as u32;
+            let bits = (imm >> 20 & 1) << 31
+                | (imm >> 1 & 0x3ff) << 21
+                | (imm >> 11 & 1) << 20
+                | (imm >> 12 & 0xff) << 12;
+            img.patch32(r.offset, 0xffff_f000, bits)
+        }
+        R_RISCV_CALL | R_RISCV_CALL_PLT => {
+            // auipc ra, %pcrel_hi(sym); jalr ra, %pcrel_lo(sym)(ra)
+            img.patch32(r.offset, 0xffff_f000, riscv_hi20(pcrel)?)?;
+            img.patch32(r.offset + 4, RISCV_ITYPE_MASK, riscv_itype(pcrel))
+        }
+        R_RISCV_PCREL_HI20 => img.patch32(r.offset, 0xffff_f000, riscv_hi20(pcrel)?),
+        R_RISCV_GOT_HI20 => {
+            let value = r.got.wrapping_add(r.addend as u64).wrapping_sub(p) as i64;
+            img.patch32(r.offset, 0xffff_f000, riscv_hi20(value)?)
+        }
+        R_RISCV_PCREL_LO12_I => {
+            let value = riscv_pcrel_hi(img, relocs, i)?;
+            img.patch32(r.offset, RISCV_ITYPE_MASK, riscv_itype(value))
+        }
+        R_RISCV_PCREL_LO12_S => {
+            let value = riscv_pcrel_hi(img, relocs, i)?;
+            img.patch32(r.offset, RISCV_STYPE_MASK, riscv_stype(value))
+        }
+        R_RISCV_HI20 => img.patch32(r.offset, 0xffff_f000, riscv_hi20(s_a as i64)?),
+        R_RISCV_LO12_I => img.patch32(r.offset, RISCV_ITYPE_MASK, riscv_itype(s_a as i64)),
+        R_RISCV_LO12_S => img.patch32(r.offset, RISCV_STYPE_MASK, riscv_stype(s_a as i64)),
+        R_RISCV_RVC_BRANCH => {
+            if !fits(pcrel, 9) || pcrel & 1 != 0 {
+                return overflow();
+            }
+            let imm = pcrel as u16;
+            let bits = (imm >> 8 & 1) << 12
+                | (imm >> 3 & 3) << 10
+                | (imm >> 6 & 3) << 5
+                | (imm >> 1 & 3) << 3
+                | (imm >> 5 & 1) << 2;
+            img.patch16(r.offset, 0x1c7c, bits)
+        }
+        R_RISCV_RVC_JUMP => {
+            if !fits(pcrel, 12) || pcrel & 1 != 0 {
+                return overflow();
+            }
+            let imm = pcrel as u16;
+            let bits = (imm >> 11 & 1) << 12
+                | (imm >> 4 & 1) << 11
+                | (imm >> 8 & 3) << 9
+                | (imm >> 10 & 1) << 8
+                | (imm >> 6 & 1) << 7
+                | (imm >> 7 & 1) << 6
+                | (imm >> 1 & 7) << 3
+                | (imm >> 5 & 1) << 2;
+            img.patch16(r.offset, 0x1ffc, bits)
+        }
+        _ => unsupported(),
+    }
+}
+
+const fn page(addr: u64) -> u64 {
+    addr & !0xfff
+}
+
+/// Returns the immediate of an `adr` or an `adrp` holding `imm`.
+fn aarch64_adr(imm: i64) -> u32 {
+    let imm = imm as u32;
+    (imm & 3) << 29 | (imm >> 2 & 0x7ffff) << 5
+}
+
+const AARCH64_ADR_MASK: u32 = 0x60ff_ffe0;
+const AARCH64_IMM12_MASK: u32 = 0x003f_fc00;
+
+fn apply_aarch64(img: &mut Image, r: &Reloc) -> AxResult {
+    let p = img.place(r);
+    let s_a = r.sym.wrapping_add(r.addend as u64);
+    let pcrel = s_a.wrapping_sub(p) as i64;
+    let lo12 = |shift: u32| ((s_a as u32 & 0xfff) >> shift) << 10;
+    match r.r_type {
+        R_AARCH64_NONE | R_AARCH64_NULL => Ok(()),
+        R_AARCH64_ABS64 => img.write64(r.offset, s_a),
+        R_AARCH64_PREL64 => img.write64(r.offset, pcrel as u64),
+        R_AARCH64_ABS32 => {
+            if !(-(1i64 << 31)..1i64 << 32).contains(&(s_a as i64)) {
+                return overflow();
+            }
+            img.write32(r.offset, s_a as u32)
+        }
+        R_AARCH64_PREL32 => {
+            if !(-(1i64 << 31)..1i64 << 32).contains(&pcrel) {
+                return overflow();
+            }
+            img.write32(r.offset, pcrel as u32)
+        }
+        R_AARCH64_ADR_PREL_LO21 => {
+            if !fits(pcrel, 21) {
+                return overflow();
+            }
+            img.patch32(r.offset, AARCH64_ADR_MASK, aarch64_adr(pcrel))
+        }
+        R_AARCH64_ADR_PREL_PG_HI21 | R_AARCH64_ADR_PREL_PG_HI21_NC => {
+            let pages = (page(s_a).wrapping_sub(page(p)) as i64) >> 12;
+            if r.r_type == R_AARCH64_ADR_PREL_PG_HI21 && !fits(pages, 21) {
+                return overflow();
+            }
+            img.patch32(r.offset, AARCH64_ADR_MASK, aarch64_adr(pages))
+        }
+        R_AARCH64_ADR_GOT_PAGE => {
+            let pages = (page(r.got).wrapping_sub(page(p)) as i64) >> 12;
+            if !fits(pages, 21) {
+                return overflow();
+            }
+            img.patch32(r.offset, AARCH64_ADR_MASK, aarch64_adr(pages))
+        }
+        R_AARCH64_ADD_ABS_LO12_NC | R_AARCH64_LDST8_ABS_LO12_NC => {
+            img.patch32(r.offset, AARCH64_IMM12_MASK, lo12(0))
+        }
+        R_AARCH64_LDST16_ABS_LO12_NC => img.patch32(r.offset, AARCH64_IMM12_MASK, lo12(1)),
+        R_AARCH64_LDST32_ABS_LO12_NC => img.patch32(r.offset, AARCH64_IMM12_MASK, lo12(2)),
+        R_AARCH64_LDST64_ABS_LO12_NC => img.patch32(r.offset, AARCH64_IMM12_MASK, lo12(3)),
+        R_AARCH64_LDST128_ABS_LO12_NC => img.patch32(r.offset, AARCH64_IMM12_MASK, lo12(4)),
+        R_AARCH64_LD64_GOT_LO12_NC => {
+            let bits = ((r.got as u32 & 0xfff) >> 3) << 10;
+            img.patch32(r.offset, AARCH64_IMM12_MASK, bits)
+        }
+        R_AARCH64_TSTBR14 => {
+            if !fits(pcrel, 16) {
+                return overflow();
+            }
+            img.patch32(r.offset, 0x0007_ffe0, ((pcrel >> 2) as u32 & 0x3fff) << 5)
+        }
+        R_AARCH64_CONDBR19 => {
+            if !fits(pcrel, 21) {
+                return overflow();
+            }
+            img.patch32(r.offset, 0x00ff_ffe0, ((pcrel >> 2) as u32 & 0x7ffff) << 5)
+        }
+        R_AARCH64_JUMP26 | R_AARCH64_CALL26 => {
+            let mut value = pcrel;
+            if !fits(value, 28) && r.plt != 0 {
+                value = r.plt.wrapping_sub(p) as i64;
+            }
+            if !fits(value, 28) {
+                return overflow();
+            }
+            img.patch32(r.offset, 0x03ff_ffff, (value >> 2) as u32)
+        }
+        _ => unsupported(),
+    }
+}
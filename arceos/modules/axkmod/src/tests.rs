@@ -0,0 +1,233 @@
+use crate::reloc::{apply, Machine, Reloc};
+use crate::{find_symbol, modinfo_depends, modinfo_get};
+
+fn exported(x: u32) -> u32 {
+    x + 1
+}
+
+crate::export_symbol!(exported);
+crate::export_symbol!("test_exported_alias", exported);
+
+static EXPORTED_VAR: u32 = 42;
+
+crate::export_symbol!(static EXPORTED_VAR);
+
+fn insns(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+fn words(image: &[u8]) -> Vec<u32> {
+    image
+        .chunks(4)
+        .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_export_symbol() {
+    assert_eq!(find_symbol("exported"), Some(exported as *const ()));
+    assert_eq!(
+        find_symbol("test_exported_alias"),
+        Some(exported as *const ())
+    );
+    assert_eq!(
+        find_symbol("EXPORTED_VAR"),
+        Some(&EXPORTED_VAR as *const u32 as *const ())
+    );
+    assert_eq!(find_symbol("not_exported"), None);
+}
+
+#[test]
+fn test_modinfo() {
+    let info = b"name=hello\0depends=greeter,console,\0";
+    assert_eq!(modinfo_get(info, "name"), Some("hello"));
+    assert_eq!(modinfo_get(info, "nam"), None);
+    assert_eq!(modinfo_get(info, "license"), None);
+    let deps: Vec<_> = modinfo_depends(info).collect();
+    assert_eq!(deps, ["greeter", "console"]);
+    assert_eq!(modinfo_depends(b"name=hello\0").count(), 0);
+}
+
+#[test]
+fn test_machine() {
+    assert_eq!(Machine::from_elf(62), Some(Machine::X86_64));
+    assert_eq!(Machine::from_elf(243), Some(Machine::Riscv64));
+    assert_eq!(Machine::from_elf(183), Some(Machine::Aarch64));
+    assert_eq!(Machine::from_elf(3), None);
+    assert!(Machine::Riscv64.plt_entry(0).is_none());
+
+    let entry = Machine::Aarch64.plt_entry(0x1122_3344_5566_7788).unwrap();
+    assert_eq!(words(&entry[..8]), [0x5800_0050, 0xd61f_0200]);
+    assert_eq!(&entry[8..], &0x1122_3344_5566_7788u64.to_le_bytes());
+}
+
+#[test]
+fn test_x86_64() {
+    let base = 0xffff_8000_0010_0000;
+    let mut image = [0u8; 16];
+    let relocs = [
+        // call sym: R_X86_64_PLT32
+        Reloc {
+            r_type: 4,
+            offset: 4,
+            sym: base + 0x1000,
+            addend: -4,
+            ..Default::default()
+        },
+        // .quad sym: R_X86_64_64
+        Reloc {
+            r_type: 1,
+            offset: 8,
+            sym: 0x1234,
+            addend: 8,
+            ..Default::default()
+        },
+    ];
+    apply(Machine::X86_64, &mut image, base, &relocs).unwrap();
+    assert_eq!(words(&image[4..8]), [0x1000 - 8]);
+    assert_eq!(&image[8..], &0x123cu64.to_le_bytes());
+
+    // Too far, through the PLT.
+    let mut call = Reloc {
+        r_type: 4,
+        offset: 4,
+        sym: 0x1000,
+        addend: -4,
+        ..Default::default()
+    };
+    assert!(apply(Machine::X86_64, &mut image, base, &[call]).is_err());
+    call.plt = base + 0x100;
+    apply(Machine::X86_64, &mut image, base, &[call]).unwrap();
+    assert_eq!(words(&image[4..8]), [0x100 - 8]);
+
+    // Out of the image.
+    call.offset = 14;
+    assert!(apply(Machine::X86_64, &mut image, base, &[call]).is_err());
+}
+
+#[test]
+fn test_riscv64() {
+    let base = 0x8020_0000;
+    // auipc ra, 0; jalr ra, 0(ra); auipc a0, 0; addi a0, a0, 0; beq zero, zero, 0;
+    // jal ra, 0
+    let mut image = insns(&[
+        0x0000_0097,
+        0x0000_80e7,
+        0x0000_0517,
+        0x0005_0513,
+        0x0000_0063,
+        0x0000_00ef,
+    ]);
+    let relocs = [
+        // R_RISCV_CALL_PLT
+        Reloc {
+            r_type: 19,
+            offset: 0,
+            sym: base + 0x1234,
+            ..Default::default()
+        },
+        // R_RISCV_PCREL_LO12_I, before its R_RISCV_PCREL_HI20
+        Reloc {
+            r_type: 24,
+            offset: 12,
+            sym: base + 8,
+            ..Default::default()
+        },
+        Reloc {
+            r_type: 23,
+            offset: 8,
+            sym: base + 0x1808,
+            ..Default::default()
+        },
+        // R_RISCV_BRANCH
+        Reloc {
+            r_type: 16,
+            offset: 16,
+            sym: base + 32,
+            ..Default::default()
+        },
+        // R_RISCV_JAL
+        Reloc {
+            r_type: 17,
+            offset: 20,
+            sym: base + 20 + 0x800,
+            ..Default::default()
+        },
+    ];
+    apply(Machine::Riscv64, &mut image, base, &relocs).unwrap();
+    assert_eq!(
+        words(&image),
+        [
+            0x0000_1097,
+            0x2340_80e7,
+            0x0000_2517,
+            0x8005_0513,
+            0x0000_0863,
+            0x0010_00ef,
+        ]
+    );
+
+    // %pcrel_lo without its %pcrel_hi.
+    assert!(apply(Machine::Riscv64, &mut image, base, &relocs[1..2]).is_err());
+    // Relaxation is not supported.
+    let align = Reloc {
+        r_type: 43,
+        ..Default::default()
+    };
+    assert!(apply(Machine::Riscv64, &mut image, base, &[align]).is_err());
+}
+
+#[test]
+fn test_aarch64() {
+    let base = 0x4008_0000;
+    // bl 0; adrp x0, 0; add x0, x0, #0; ldr x1, [x0]
+    let mut image = insns(&[0x9400_0000, 0x9000_0000, 0x9100_0000, 0xf940_0001]);
+    let data = base + 0x5678;
+    let relocs = [
+        // R_AARCH64_CALL26
+        Reloc {
+            r_type: 283,
+            offset: 0,
+            sym: base + 0x1000,
+            ..Default::default()
+        },
+        // R_AARCH64_ADR_PREL_PG_HI21
+        Reloc {
+            r_type: 275,
+            offset: 4,
+            sym: data,
+            ..Default::default()
+        },
+        // R_AARCH64_ADD_ABS_LO12_NC
+        Reloc {
+            r_type: 277,
+            offset: 8,
+            sym: data,
+            ..Default::default()
+        },
+        // R_AARCH64_LDST64_ABS_LO12_NC
+        Reloc {
+            r_type: 286,
+            offset: 12,
+            sym: data,
+            ..Default::default()
+        },
+    ];
+    apply(Machine::Aarch64, &mut image, base, &relocs).unwrap();
+    assert_eq!(
+        words(&image),
+        [0x9400_0400, 0xb000_0020, 0x9119_e000, 0xf943_3c01]
+    );
+
+    // Too far, through the PLT.
+    let mut call = Reloc {
+        r_type: 283,
+        offset: 0,
+        sym: base + (1 << 28),
+        ..Default::default()
+    };
+    assert!(apply(Machine::Aarch64, &mut image, base, &[call]).is_err());
+    call.plt = base + 0x100;
+    apply(Machine::Aarch64, &mut image, base, &[call]).unwrap();
+    assert_eq!(words(&image[..4]), [0x9400_0040]);
+}
@@ -0,0 +1,189 @@
+//! The format of the patch blobs.
+//!
+//! A blob holds the position-independent code of new implementations of
+//! [patch sites](crate::PatchSite), in little-endian:
+//!
+//! - A 16-byte header: the magic `AXLP`, the version `u16`, the number of
+//!   patches `u16`, the number of relocations `u16`, 2 reserved bytes, and
+//!   the bytes of code `u32`.
+//! - The patches, each the path of the function patched in [`NAME_LEN`]
+//!   bytes padded with zeros, the [`signature_hash`] of its signature `u64`,
+//!   the offset of its new implementation in the code `u32` and 4 reserved
+//!   bytes.
+//! - The relocations, each the offset in the code of the 8 bytes to write
+//!   `u32`, its kind `u32`, and the path of the function whose address is
+//!   written in [`NAME_LEN`] bytes. The only kind is [`RELOC_ABS64`], the
+//!   address of the original implementation of a patch site, so that a new
+//!   implementation can call it.
+//! - The code.
+//! - The HMAC-BLAKE2s of all the bytes before, keyed by the key of the build
+//!   given in `AX_LIVEPATCH_KEY`, the authenticity of the blob.
+//!
+//! A blob is only loaded if it is authenticated by the kernel it is built
+//! for: a kernel built without a key loads none.
+
+use axcrypto::blake2s;
+use axerrno::{ax_err, AxResult};
+
+/// The magic of a blob.
+pub const MAGIC: [u8; 4] = *b"AXLP";
+/// The version of the format.
+pub const VERSION: u16 = 1;
+/// The bytes of a path of function, padded with zeros.
+pub const NAME_LEN: usize = 64;
+/// The bytes of the authenticity tag.
+pub const TAG_LEN: usize = 32;
+/// The relocation writing the address of the original implementation of a
+/// patch site.
+pub const RELOC_ABS64: u32 = 0;
+
+const HEADER_LEN: usize = 16;
+const PATCH_LEN: usize = NAME_LEN + 16;
+const RELOC_LEN: usize = NAME_LEN + 8;
+
+/// Returns the key of the build, given in `AX_LIVEPATCH_KEY`.
+pub fn build_key() -> Option<&'static [u8]> {
+    option_env!("AX_LIVEPATCH_KEY")
+        .filter(|key| !key.is_empty())
+        .map(str::as_bytes)
+}
+
+/// Returns the hash of a signature of patch site, as in the blobs.
+pub fn signature_hash(signature: &str) -> u64 {
+    let digest = blake2s::hash(&[signature.as_bytes()]);
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+/// Returns the authenticity tag of a blob whose other bytes are `data`.
+pub fn seal(key: &[u8], data: &[u8]) -> [u8; TAG_LEN] {
+    blake2s::hmac(key, &[data])
+}
+
+fn read_u16(data: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(data[off..off + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(data[off..off + 4].try_into().unwrap())
+}
+
+fn read_name(data: &[u8]) -> AxResult<&str> {
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    match core::str::from_utf8(&data[..len]) {
+        Ok(name) if !name.is_empty() => Ok(name),
+        _ => ax_err!(InvalidData, "invalid function name in patch blob"),
+    }
+}
+
+/// A new implementation of a patch site in a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch<'a> {
+    /// The path of the function patched.
+    pub name: &'a str,
+    /// The [`signature_hash`] of its signature.
+    pub signature_hash: u64,
+    /// The offset of the new implementation in the code.
+    pub offset: usize,
+}
+
+/// A relocation of the code of a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reloc<'a> {
+    /// The offset in the code of the 8 bytes to write.
+    pub offset: usize,
+    /// The kind, [`RELOC_ABS64`].
+    pub kind: u32,
+    /// The path of the function whose address is written.
+    pub symbol: &'a str,
+}
+
+/// A parsed and authenticated patch blob.
+#[derive(Debug, Clone, Copy)]
+pub struct PatchBlob<'a> {
+    data: &'a [u8],
+    patches: usize,
+    relocs: usize,
+    code_len: usize,
+}
+
+impl<'a> PatchBlob<'a> {
+    /// Parses `data` and checks its authenticity with `key`.
+    pub fn parse(data: &'a [u8], key: &[u8]) -> AxResult<Self> {
+        if data.len() < HEADER_LEN + TAG_LEN || data[..4] != MAGIC {
+            return ax_err!(InvalidData, "not a patch blob");
+        }
+        let (body, tag) = data.split_at(data.len() - TAG_LEN);
+        if !axcrypto::ct_eq(&seal(key, body), tag) {
+            return ax_err!(PermissionDenied, "patch blob not authenticated");
+        }
+        if read_u16(data, 4) != VERSION {
+            return ax_err!(Unsupported, "unsupported patch blob version");
+        }
+        let blob = Self {
+            data: body,
+            patches: read_u16(data, 6) as usize,
+            relocs: read_u16(data, 8) as usize,
+            code_len: read_u32(data, 12) as usize,
+        };
+        if blob.patches == 0 {
+            return ax_err!(InvalidData, "empty patch blob");
+        }
+        if blob.code_offset() + blob.code_len != body.len() {
+            return ax_err!(InvalidData, "truncated patch blob");
+        }
+        for i in 0..blob.patches {
+            if blob.patch(i)?.offset >= blob.code_len {
+                return ax_err!(InvalidData, "patch out of the code");
+            }
+        }
+        for i in 0..blob.relocs {
+            let reloc = blob.reloc(i)?;
+            if reloc.kind != RELOC_ABS64 {
+                return ax_err!(Unsupported, "unsupported relocation");
+            }
+            if reloc.offset + 8 > blob.code_len {
+                return ax_err!(InvalidData, "relocation out of the code");
+            }
+        }
+        Ok(blob)
+    }
+
+    fn code_offset(&self) -> usize {
+        HEADER_LEN + self.patches * PATCH_LEN + self.relocs * RELOC_LEN
+    }
+
+    fn patch(&self, i: usize) -> AxResult<Patch<'a>> {
+        let entry = &self.data[HEADER_LEN + i * PATCH_LEN..][..PATCH_LEN];
+        Ok(Patch {
+            name: read_name(&entry[..NAME_LEN])?,
+            signature_hash: u64::from_le_bytes(entry[NAME_LEN..NAME_LEN + 8].try_into().unwrap()),
+            offset: read_u32(entry, NAME_LEN + 8) as usize,
+        })
+    }
+
+    fn reloc(&self, i: usize) -> AxResult<Reloc<'a>> {
+        let off = HEADER_LEN + self.patches * PATCH_LEN + i * RELOC_LEN;
+        let entry = &self.data[off..][..RELOC_LEN];
+        Ok(Reloc {
+            offset: read_u32(entry, 0) as usize,
+            kind: read_u32(entry, 4),
+            symbol: read_name(&entry[8..])?,
+        })
+    }
+
+    /// Returns the patches of the blob.
+    pub fn patches(&self) -> impl Iterator<Item = Patch<'a>> + '_ {
+        // The entries are checked by `parse`.
+        (0..self.patches).map(|i| self.patch(i).unwrap())
+    }
+
+    /// Returns the relocations of the code.
+    pub fn relocs(&self) -> impl Iterator<Item = Reloc<'a>> + '_ {
+        (0..self.relocs).map(|i| self.reloc(i).unwrap())
+    }
+
+    /// Returns the code, before relocation.
+    pub fn code(&self) -> &'a [u8] {
+        &self.data[self.code_offset()..]
+    }
+}
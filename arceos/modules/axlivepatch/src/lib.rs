@@ -0,0 +1,169 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) live patching of kernel
+//! functions.
+//!
+//! A function defined with [`patchable!`] gets a prologue checking a
+//! redirection, so that it can be replaced at runtime, e.g. by an emergency
+//! fix of an appliance which cannot be rebooted:
+//!
+//! ```ignore
+//! axlivepatch::patchable! {
+//!     /// Returns whether `len` is a valid length of a name.
+//!     pub fn check_name_len(len: usize) -> bool {
+//!         len <= 255
+//!     }
+//! }
+//! ```
+//!
+//! The prologue is a load of the redirection and a branch, and keeps
+//! the code of the kernel read-only: the redirection is a pointer in a
+//! [`PatchSite`], taken by all the calls starting after it is set, while the
+//! ones already running finish in the old implementation. The sites of all
+//! the crates linked in the kernel are collected in [`PATCH_SITES`] at link
+//! time, named by their path.
+//!
+//! The new implementations are loaded from a [patch blob](blob), whose
+//! authenticity is checked against the key of the build, by
+//! [`loader::apply`] with the `loader` feature. They must be built by the
+//! same toolchain as the kernel, as they are called with the Rust ABI.
+//!
+//! The functions taking generic parameters, `self` or patterns as arguments
+//! cannot be patchable.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "loader")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "loader")]
+extern crate alloc;
+
+pub mod blob;
+#[cfg(feature = "loader")]
+pub mod loader;
+
+#[cfg(test)]
+mod tests;
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+#[doc(hidden)]
+pub use linkme;
+
+/// A function which can be redirected, defined by [`patchable!`].
+pub struct PatchSite {
+    name: &'static str,
+    signature: &'static str,
+    original: *const (),
+    /// The redirection, null for the original function.
+    target: AtomicPtr<()>,
+}
+
+// SAFETY: the pointers are to code, the redirection is atomic.
+unsafe impl Sync for PatchSite {}
+
+impl core::fmt::Debug for PatchSite {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("PatchSite")
+            .field("name", &self.name)
+            .field("patched", &self.is_patched())
+            .finish()
+    }
+}
+
+impl PatchSite {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, signature: &'static str, original: *const ()) -> Self {
+        Self {
+            name,
+            signature,
+            original,
+            target: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    /// Returns the path of the function, e.g. `axfs::fops::check_name_len`.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the signature of the function, as written, e.g.
+    /// `fn(usize) -> bool`.
+    pub const fn signature(&self) -> &'static str {
+        self.signature
+    }
+
+    /// Returns the original implementation.
+    pub const fn original(&self) -> *const () {
+        self.original
+    }
+
+    /// Returns the redirection, or null if the original implementation runs.
+    #[inline]
+    pub fn target(&self) -> *const () {
+        self.target.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the function is redirected.
+    pub fn is_patched(&self) -> bool {
+        !self.target().is_null()
+    }
+
+    /// Redirects the calls of the function to `target`.
+    ///
+    /// # Safety
+    ///
+    /// `target` must be a function of the [signature](Self::signature) of the
+    /// site, with the Rust ABI, living as long as it is redirected to.
+    pub unsafe fn redirect(&self, target: *const ()) {
+        self.target.store(target as *mut (), Ordering::Release);
+    }
+
+    /// Gives the calls of the function back to the original implementation.
+    pub fn revert(&self) {
+        self.target.store(null_mut(), Ordering::Release);
+    }
+}
+
+/// All the patch sites of the crates.
+#[linkme::distributed_slice]
+pub static PATCH_SITES: [PatchSite];
+
+/// Returns the patch site of the function `name`.
+pub fn find_site(name: &str) -> Option<&'static PatchSite> {
+    PATCH_SITES.iter().find(|site| site.name == name)
+}
+
+/// Defines a function which can be redirected at runtime, see the
+/// [crate documentation](crate).
+#[macro_export]
+macro_rules! patchable {
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)? $body:block
+    ) => {
+        $(#[$attr])*
+        $vis fn $name($($arg: $ty),*) $(-> $ret)? {
+            fn original($($arg: $ty),*) $(-> $ret)? $body
+
+            #[$crate::linkme::distributed_slice($crate::PATCH_SITES)]
+            #[linkme(crate = $crate::linkme)]
+            static SITE: $crate::PatchSite = $crate::PatchSite::new(
+                concat!(module_path!(), "::", stringify!($name)),
+                stringify!(fn($($ty),*) $(-> $ret)?),
+                original as *const (),
+            );
+
+            let target = SITE.target();
+            if !target.is_null() {
+                // SAFETY: the redirection is a function of the signature of
+                // the site.
+                let f = unsafe {
+                    core::mem::transmute::<*const (), fn($($ty),*) $(-> $ret)?>(target)
+                };
+                return f($($arg),*);
+            }
+            original($($arg),*)
+        }
+    };
+}
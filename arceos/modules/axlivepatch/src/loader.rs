@@ -0,0 +1,193 @@
+//! Loading of the patch blobs in the kernel.
+//!
+//! [`apply`] copies the code of an authenticated blob to new pages, relocates
+//! it, maps it read-only and executable, and only then redirects its patch
+//! sites, all of them or none. A patch is reverted by [`revert`], which gives
+//! the sites back to their original implementations but keeps the code of the
+//! patch, as a CPU may still be running it.
+//!
+//! The blob is checked and its code loaded before taking the lock of the
+//! patches applied, which is only held to recheck that the sites are not
+//! patched yet and to redirect them.
+//!
+//! The `livepatch` shell command lists the patches applied.
+
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use kspin::SpinNoIrq;
+
+use crate::blob::{build_key, signature_hash, PatchBlob};
+use crate::{find_site, PatchSite};
+
+/// A patch applied.
+#[derive(Debug, Clone)]
+pub struct AppliedPatch {
+    /// The identifier, to [`revert`] it.
+    pub id: usize,
+    /// The sites redirected.
+    pub sites: Vec<&'static PatchSite>,
+    /// The code of the patch.
+    pub code: VirtAddr,
+    /// The bytes of the code.
+    pub code_len: usize,
+}
+
+struct Applied {
+    next_id: usize,
+    patches: Vec<AppliedPatch>,
+}
+
+static APPLIED: SpinNoIrq<Applied> = SpinNoIrq::new(Applied {
+    next_id: 1,
+    patches: Vec::new(),
+});
+
+/// Applies the patch blob `data`, authenticated by the key of the build, and
+/// returns its identifier.
+///
+/// Fails if a function patched is not a patch site of the kernel, has
+/// another signature, or is already patched.
+pub fn apply(data: &[u8]) -> AxResult<usize> {
+    let Some(key) = build_key() else {
+        return ax_err!(PermissionDenied, "kernel built without AX_LIVEPATCH_KEY");
+    };
+    let blob = PatchBlob::parse(data, key)?;
+
+    let mut sites = Vec::new();
+    for patch in blob.patches() {
+        let Some(site) = find_site(patch.name) else {
+            warn!("livepatch: no patch site {}", patch.name);
+            return ax_err!(NotFound, "no such patch site");
+        };
+        if signature_hash(site.signature()) != patch.signature_hash {
+            warn!("livepatch: {} is not `{}`", patch.name, site.signature());
+            return ax_err!(InvalidData, "signature mismatch");
+        }
+        if site.is_patched() || sites.iter().any(|s| core::ptr::eq(*s, site)) {
+            return ax_err!(AlreadyExists, "function already patched");
+        }
+        sites.push(site);
+    }
+    let mut relocs = Vec::new();
+    for reloc in blob.relocs() {
+        let Some(site) = find_site(reloc.symbol) else {
+            warn!("livepatch: no patch site {}", reloc.symbol);
+            return ax_err!(NotFound, "no such relocation symbol");
+        };
+        relocs.push((reloc.offset, site.original() as u64));
+    }
+
+    let code = blob.code();
+    let num_pages = code.len().div_ceil(PAGE_SIZE_4K);
+    let start = axalloc::global_allocator()
+        .alloc_pages(num_pages, PAGE_SIZE_4K)
+        .map_err(|_| AxError::NoMemory)?;
+    // SAFETY: the pages are just allocated, for the code only.
+    let text = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, code.len()) };
+    text.copy_from_slice(code);
+    for (offset, value) in relocs {
+        text[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    let code_addr = VirtAddr::from(start);
+    let flags = MappingFlags::READ | MappingFlags::EXECUTE;
+    if let Err(e) = axmm::kernel_aspace()
+        .lock()
+        .protect(code_addr, num_pages * PAGE_SIZE_4K, flags)
+    {
+        axalloc::global_allocator().dealloc_pages(start, num_pages);
+        return Err(e);
+    }
+    // The pages are new, no CPU has fetched them, but the instruction cache of
+    // this one may still hold the previous instructions at their addresses.
+    #[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+    axhal::arch::flush_icache_all();
+
+    let mut applied = APPLIED.lock();
+    // patched by another patch while the code was loaded
+    if sites.iter().any(|site| site.is_patched()) {
+        drop(applied);
+        free_code(code_addr, num_pages);
+        return ax_err!(AlreadyExists, "function already patched");
+    }
+    for (site, patch) in sites.iter().zip(blob.patches()) {
+        // SAFETY: the signature is checked, and the code is never freed.
+        unsafe { site.redirect((start + patch.offset) as *const ()) };
+    }
+    let id = applied.next_id;
+    applied.next_id += 1;
+    info!(
+        "livepatch: applied patch {} at {:#x}: {:?}",
+        id, start, sites
+    );
+    applied.patches.push(AppliedPatch {
+        id,
+        sites,
+        code: code_addr,
+        code_len: code.len(),
+    });
+    Ok(id)
+}
+
+/// Frees the `num_pages` pages of the code of a patch which was not applied,
+/// writable again.
+fn free_code(code: VirtAddr, num_pages: usize) {
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    match axmm::kernel_aspace()
+        .lock()
+        .protect(code, num_pages * PAGE_SIZE_4K, flags)
+    {
+        Ok(()) => axalloc::global_allocator().dealloc_pages(code.as_usize(), num_pages),
+        // leaked rather than given back read-only
+        Err(e) => warn!(
+            "livepatch: failed to unprotect {:#x}: {:?}",
+            code.as_usize(),
+            e
+        ),
+    }
+}
+
+/// Reverts the patch `id`, giving its sites back to their original
+/// implementations.
+pub fn revert(id: usize) -> AxResult {
+    let mut applied = APPLIED.lock();
+    let Some(pos) = applied.patches.iter().position(|p| p.id == id) else {
+        return ax_err!(NotFound, "no such patch");
+    };
+    let patch = applied.patches.remove(pos);
+    for site in &patch.sites {
+        site.revert();
+    }
+    // The code is leaked: the calls started before may not have returned.
+    info!("livepatch: reverted patch {}: {:?}", id, patch.sites);
+    Ok(())
+}
+
+/// Returns the patches applied.
+pub fn applied() -> Vec<AppliedPatch> {
+    APPLIED.lock().patches.clone()
+}
+
+fn do_livepatch(_args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+    let patches = applied();
+    if patches.is_empty() {
+        writeln!(out, "no patch applied")?;
+    }
+    for patch in patches {
+        writeln!(
+            out,
+            "patch {}: {} bytes at {:#x}",
+            patch.id,
+            patch.code_len,
+            patch.code.as_usize()
+        )?;
+        for site in patch.sites {
+            writeln!(out, "    {}: {}", site.name(), site.signature())?;
+        }
+    }
+    Ok(())
+}
+
+axcmd::register_cmd!("livepatch", do_livepatch, "list the live patches applied");
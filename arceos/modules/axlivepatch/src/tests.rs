@@ -0,0 +1,109 @@
+use crate::blob::{seal, signature_hash, PatchBlob, NAME_LEN, RELOC_ABS64, VERSION};
+use crate::{find_site, patchable};
+
+const KEY: &[u8] = b"test key";
+
+patchable! {
+    /// Doubles `x`.
+    fn double(x: u32) -> u32 {
+        x * 2
+    }
+}
+
+fn triple(x: u32) -> u32 {
+    x * 3
+}
+
+fn name(s: &str) -> [u8; NAME_LEN] {
+    let mut buf = [0; NAME_LEN];
+    buf[..s.len()].copy_from_slice(s.as_bytes());
+    buf
+}
+
+fn build(patches: &[(&str, &str, u32)], relocs: &[(u32, &str)], code: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    blob.extend_from_slice(b"AXLP");
+    blob.extend_from_slice(&VERSION.to_le_bytes());
+    blob.extend_from_slice(&(patches.len() as u16).to_le_bytes());
+    blob.extend_from_slice(&(relocs.len() as u16).to_le_bytes());
+    blob.extend_from_slice(&[0; 2]);
+    blob.extend_from_slice(&(code.len() as u32).to_le_bytes());
+    for (site, signature, offset) in patches {
+        blob.extend_from_slice(&name(site));
+        blob.extend_from_slice(&signature_hash(signature).to_le_bytes());
+        blob.extend_from_slice(&offset.to_le_bytes());
+        blob.extend_from_slice(&[0; 4]);
+    }
+    for (offset, symbol) in relocs {
+        blob.extend_from_slice(&offset.to_le_bytes());
+        blob.extend_from_slice(&RELOC_ABS64.to_le_bytes());
+        blob.extend_from_slice(&name(symbol));
+    }
+    blob.extend_from_slice(code);
+    let tag = seal(KEY, &blob);
+    blob.extend_from_slice(&tag);
+    blob
+}
+
+#[test]
+fn test_redirect() {
+    let site = find_site("axlivepatch::tests::double").unwrap();
+    assert_eq!(site.signature(), "fn(u32) -> u32");
+    assert!(!site.is_patched());
+    assert_eq!(double(5), 10);
+
+    unsafe { site.redirect(triple as *const ()) };
+    assert!(site.is_patched());
+    assert_eq!(double(5), 15);
+
+    site.revert();
+    assert!(!site.is_patched());
+    assert_eq!(double(5), 10);
+}
+
+#[test]
+fn test_parse() {
+    let code = [0x90u8; 24];
+    let data = build(
+        &[("axlivepatch::tests::double", "fn(u32) -> u32", 4)],
+        &[(16, "axlivepatch::tests::double")],
+        &code,
+    );
+    let blob = PatchBlob::parse(&data, KEY).unwrap();
+    let patches: Vec<_> = blob.patches().collect();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].name, "axlivepatch::tests::double");
+    assert_eq!(patches[0].signature_hash, signature_hash("fn(u32) -> u32"));
+    assert_eq!(patches[0].offset, 4);
+    let relocs: Vec<_> = blob.relocs().collect();
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].offset, 16);
+    assert_eq!(relocs[0].symbol, "axlivepatch::tests::double");
+    assert_eq!(blob.code(), &code);
+}
+
+#[test]
+fn test_authenticity() {
+    let data = build(&[("a::f", "fn()", 0)], &[], &[0xc3]);
+    assert!(PatchBlob::parse(&data, KEY).is_ok());
+    assert!(PatchBlob::parse(&data, b"another key").is_err());
+
+    let mut tampered = data.clone();
+    let code = tampered.len() - 33;
+    tampered[code] ^= 1;
+    assert!(PatchBlob::parse(&tampered, KEY).is_err());
+    assert!(PatchBlob::parse(&data[..data.len() - 1], KEY).is_err());
+}
+
+#[test]
+fn test_invalid() {
+    // No patch.
+    assert!(PatchBlob::parse(&build(&[], &[], &[0xc3]), KEY).is_err());
+    // Patch out of the code.
+    assert!(PatchBlob::parse(&build(&[("a::f", "fn()", 1)], &[], &[0xc3]), KEY).is_err());
+    // Relocation out of the code.
+    let data = build(&[("a::f", "fn()", 0)], &[(4, "a::f")], &[0; 8]);
+    assert!(PatchBlob::parse(&data, KEY).is_err());
+    // Empty name.
+    assert!(PatchBlob::parse(&build(&[("", "fn()", 0)], &[], &[0xc3]), KEY).is_err());
+}
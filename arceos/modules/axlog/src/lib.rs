@@ -25,6 +25,10 @@
 //!   optimized out to a no-op.
 //! - `log-level-warn`, `log-level-info`, `log-level-debug`, `log-level-trace`:
 //!   Similar to `log-level-error`.
+//! - `ring`: Write the log records into a lock-free ring once it is enabled,
+//!   to be printed by a flusher (see [`enable_ring`] and [`flush_ring`]).
+//! - `pstore`: Keep the console output in a RAM region surviving the warm
+//!   reboots, to read the log of the previous boot (see [`pstore_init`]).
 //!
 //! # Examples
 //!
@@ -53,6 +57,7 @@ extern crate log;
 use core::fmt::{self, Write};
 use core::str::FromStr;
 
+use kspin::SpinNoIrq;
 use log::{Level, LevelFilter, Log, Metadata, Record};
 
 #[cfg(not(feature = "std"))]
@@ -60,6 +65,21 @@ use crate_interface::call_interface;
 
 pub use log::{debug, error, info, trace, warn};
 
+#[cfg(feature = "ring")]
+mod ring;
+
+#[cfg(feature = "ring")]
+pub use self::ring::{disable_ring, enable_ring, flush_ring, ring_enabled};
+
+#[cfg(feature = "pstore")]
+mod pstore;
+
+#[cfg(feature = "pstore")]
+pub use self::pstore::{pstore_dump, pstore_init, pstore_previous, set_pstore_sink, PstoreSink};
+
+/// Serializes the output to the console.
+static CONSOLE_LOCK: SpinNoIrq<()> = SpinNoIrq::new(()); // TODO: more efficient
+
 /// Prints to the console.
 ///
 /// Equivalent to the [`ax_println!`] macro except that a newline is not printed at
@@ -131,6 +151,8 @@ struct Logger;
 
 impl Write for Logger {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(feature = "pstore")]
+        pstore::write(s);
         cfg_if::cfg_if! {
             if #[cfg(feature = "std")] {
                 std::print!("{}", s);
@@ -166,7 +188,7 @@ impl Log for Logger {
 
         cfg_if::cfg_if! {
             if #[cfg(feature = "std")] {
-                __print_impl(with_color!(
+                write_record(with_color!(
                     ColorCode::White,
                     "[{time} {path}:{line}] {args}\n",
                     time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
@@ -181,7 +203,7 @@ impl Log for Logger {
                 if let Some(cpu_id) = cpu_id {
                     if let Some(tid) = tid {
                         // show CPU ID and task ID
-                        __print_impl(with_color!(
+                        write_record(with_color!(
                             ColorCode::White,
                             "[{:>3}.{:06} {cpu_id}:{tid} {path}:{line}] {args}\n",
                             now.as_secs(),
@@ -194,7 +216,7 @@ impl Log for Logger {
                         ));
                     } else {
                         // show CPU ID only
-                        __print_impl(with_color!(
+                        write_record(with_color!(
                             ColorCode::White,
                             "[{:>3}.{:06} {cpu_id} {path}:{line}] {args}\n",
                             now.as_secs(),
@@ -207,7 +229,7 @@ impl Log for Logger {
                     }
                 } else {
                     // neither CPU ID nor task ID is shown
-                    __print_impl(with_color!(
+                    write_record(with_color!(
                         ColorCode::White,
                         "[{:>3}.{:06} {path}:{line}] {args}\n",
                         now.as_secs(),
@@ -224,12 +246,18 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
+/// Writes a formatted log record, into the ring if it is enabled.
+fn write_record(args: fmt::Arguments) {
+    #[cfg(feature = "ring")]
+    if ring::push(args) {
+        return;
+    }
+    __print_impl(args);
+}
+
 /// Prints the formatted string to the console.
 pub fn print_fmt(args: fmt::Arguments) -> fmt::Result {
-    use kspin::SpinNoIrq; // TODO: more efficient
-    static LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
-
-    let _guard = LOCK.lock();
+    let _guard = CONSOLE_LOCK.lock();
     Logger.write_fmt(args)
 }
 
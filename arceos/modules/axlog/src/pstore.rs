@@ -0,0 +1,199 @@
+//! The persistent store of the log, with the `pstore` feature.
+//!
+//! Once [`pstore_init`] attaches a RAM region kept across warm reboots, all
+//! the console output, including the log records printed from the ring, is
+//! also copied into it, the last bytes overwriting the first ones when it is
+//! full. The region holds two halves used by turns: attaching it at the next
+//! boot keeps the half written by the previous one, given by
+//! [`pstore_previous`], and writes into the other one.
+//!
+//! The region may be lost with the power, e.g. on a cold boot: its header is
+//! checked, and a region which is not valid is taken as empty. For the
+//! storages which do not survive without the kernel, e.g. a flash or disk
+//! sector, a sink set by [`set_pstore_sink`] is handed the log by
+//! [`pstore_dump`], to be called in a panic.
+
+use core::mem::size_of;
+
+use kspin::SpinNoIrq;
+
+/// The magic of a valid header, "AXPSTORE".
+const MAGIC: u64 = u64::from_le_bytes(*b"AXPSTORE");
+
+/// The header of the region, followed by the two halves.
+#[repr(C)]
+struct Header {
+    magic: u64,
+    /// The half written by the current boot.
+    current: u64,
+    /// The bytes written into each half since its boot started.
+    written: [u64; 2],
+    /// `MAGIC` XOR the other fields, to detect a region not written by us.
+    check: u64,
+}
+
+impl Header {
+    fn checksum(&self) -> u64 {
+        MAGIC ^ self.current ^ self.written[0] ^ self.written[1]
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.current < 2 && self.check == self.checksum()
+    }
+
+    fn seal(&mut self) {
+        self.check = self.checksum();
+    }
+}
+
+/// A sink of the log, handed its bytes in order with the offset of each
+/// chunk, e.g. to write them into a flash or disk sector.
+pub type PstoreSink = fn(offset: usize, data: &[u8]);
+
+struct Store {
+    header: *mut Header,
+    halves: [*mut u8; 2],
+    /// The bytes of a half.
+    capacity: usize,
+}
+
+// The region is only accessed with the lock of the store held.
+unsafe impl Send for Store {}
+
+impl Store {
+    fn header(&mut self) -> &mut Header {
+        // SAFETY: the region is attached by `pstore_init` for the whole run.
+        unsafe { &mut *self.header }
+    }
+
+    /// The bytes of the half `index`, the oldest ones first: at most two
+    /// chunks, as it wraps around.
+    fn chunks(&mut self, index: usize) -> [&'static [u8]; 2] {
+        let written = self.header().written[index] as usize;
+        let capacity = self.capacity;
+        // SAFETY: the half is `capacity` bytes of the region.
+        let half = unsafe { core::slice::from_raw_parts(self.halves[index], capacity) };
+        if written <= capacity {
+            [&half[..written], &[]]
+        } else {
+            let pos = written % capacity;
+            [&half[pos..], &half[..pos]]
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let capacity = self.capacity;
+        let current = self.header().current as usize;
+        let written = self.header().written[current] as usize;
+        // Only the last `capacity` bytes can be kept.
+        let skipped = bytes.len().saturating_sub(capacity);
+        let mut pos = (written + skipped) % capacity;
+        let mut rest = &bytes[skipped..];
+        // SAFETY: the half is `capacity` bytes of the region.
+        let half = unsafe { core::slice::from_raw_parts_mut(self.halves[current], capacity) };
+        while !rest.is_empty() {
+            let n = rest.len().min(capacity - pos);
+            half[pos..pos + n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            pos = (pos + n) % capacity;
+        }
+        let header = self.header();
+        header.written[current] = (written + bytes.len()) as u64;
+        header.seal();
+    }
+}
+
+static STORE: SpinNoIrq<Option<Store>> = SpinNoIrq::new(None);
+static SINK: SpinNoIrq<Option<PstoreSink>> = SpinNoIrq::new(None);
+/// The log of the previous boot, `None` if there is none.
+static PREVIOUS: SpinNoIrq<Option<[&'static [u8]; 2]>> = SpinNoIrq::new(None);
+
+/// Attaches the RAM region `[base, base + size)` as the persistent store of
+/// the log, and keeps the log of the previous boot in it if it is valid.
+///
+/// Returns the bytes kept of the log of the previous boot, 0 if there is
+/// none. A region too small to hold any log is not attached.
+///
+/// # Safety
+///
+/// The region must be reserved for the store for the whole run, mapped and
+/// writable, at the same physical address at each boot, and aligned to 8
+/// bytes.
+pub unsafe fn pstore_init(base: usize, size: usize) -> usize {
+    let Some(capacity) = size.checked_sub(size_of::<Header>()).map(|n| n / 2) else {
+        return 0;
+    };
+    if capacity == 0 {
+        return 0;
+    }
+    let data = (base + size_of::<Header>()) as *mut u8;
+    let mut store = Store {
+        header: base as *mut Header,
+        halves: [data, data.add(capacity)],
+        capacity,
+    };
+    let header = store.header();
+    let previous = if header.is_valid() {
+        let previous = header.current as usize;
+        header.current = 1 - header.current;
+        Some(previous)
+    } else {
+        header.magic = MAGIC;
+        header.current = 0;
+        header.written = [0; 2];
+        None
+    };
+    let current = header.current as usize;
+    header.written[current] = 0;
+    header.seal();
+    let chunks = previous.map(|index| store.chunks(index));
+    *PREVIOUS.lock() = chunks;
+    *STORE.lock() = Some(store);
+    chunks.map_or(0, |[a, b]| a.len() + b.len())
+}
+
+/// Returns the log of the previous boot, in two chunks to be read in order,
+/// or `None` if the region held none or is not attached.
+///
+/// The log is the console output, split anywhere: the first chunk may start
+/// in the middle of a character if the log wrapped around.
+pub fn pstore_previous() -> Option<[&'static [u8]; 2]> {
+    *PREVIOUS.lock()
+}
+
+/// Sets the sink the log of the current boot is handed to by [`pstore_dump`].
+pub fn set_pstore_sink(sink: PstoreSink) {
+    *SINK.lock() = Some(sink);
+}
+
+/// Hands the log of the current boot kept in the region to the sink, if one
+/// is set.
+///
+/// It is to be called in a panic, after the log ring is flushed. It does
+/// nothing if the store is locked, by the CPU panicking while writing into it.
+pub fn pstore_dump() {
+    let Some(sink) = *SINK.lock() else {
+        return;
+    };
+    let Some(mut store) = STORE.try_lock() else {
+        return;
+    };
+    let Some(store) = store.as_mut() else {
+        return;
+    };
+    let current = store.header().current as usize;
+    let mut offset = 0;
+    for chunk in store.chunks(current) {
+        if !chunk.is_empty() {
+            sink(offset, chunk);
+        }
+        offset += chunk.len();
+    }
+}
+
+/// Copies the console output into the region, if it is attached.
+pub(crate) fn write(s: &str) {
+    if let Some(store) = STORE.lock().as_mut() {
+        store.write(s.as_bytes());
+    }
+}
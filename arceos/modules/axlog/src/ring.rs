@@ -0,0 +1,230 @@
+//! The log ring, with the `ring` feature.
+//!
+//! Once [`enable_ring`] is called, the log records are formatted by the
+//! loggers into a bounded ring instead of being printed, and printed later
+//! by [`flush_ring`] from a single flusher, so the CPUs and the interrupt
+//! handlers logging at the same time do not wait for each other on the
+//! console lock.
+//!
+//! The ring is a multi-producer single-consumer queue of fixed slots in the
+//! way of the bounded queue of Dmitry Vyukov: a record takes some contiguous
+//! slots claimed by a single CAS on the head, so the writers never block, and
+//! the record is dropped if the ring is full. A writer which is preempted
+//! between claiming its slots and publishing them holds the flush of the
+//! records after it until it runs again, but never loses them.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The number of slots of the ring, a power of two.
+const NR_SLOTS: usize = 1024;
+/// The bytes of a record in a slot.
+const SLOT_DATA: usize = 120;
+/// The most bytes of a record, the rest is truncated.
+const MAX_RECORD: usize = 1024;
+/// The most slots printed for each holding of the console lock, as the
+/// interrupts are disabled while it is held.
+const FLUSH_BATCH: usize = 64;
+
+struct Slot {
+    /// `pos` when the slot is free for the writer of position `pos`, and
+    /// `pos + 1` once the data of position `pos` is published.
+    seq: AtomicUsize,
+    data: UnsafeCell<(u8, [u8; SLOT_DATA])>,
+}
+
+struct Ring {
+    slots: [Slot; NR_SLOTS],
+    /// The next position to be claimed by the writers.
+    head: AtomicUsize,
+    /// The next position to be printed, only moved by the flusher.
+    tail: AtomicUsize,
+    flushing: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+// The data of a slot is only accessed by the writer which claimed it until it
+// is published, and then by the flusher until it is freed.
+unsafe impl Sync for Ring {}
+
+static RING: Ring = Ring {
+    slots: {
+        let mut slots = [const {
+            Slot {
+                seq: AtomicUsize::new(0),
+                data: UnsafeCell::new((0, [0; SLOT_DATA])),
+            }
+        }; NR_SLOTS];
+        let mut i = 0;
+        while i < NR_SLOTS {
+            slots[i].seq = AtomicUsize::new(i);
+            i += 1;
+        }
+        slots
+    },
+    head: AtomicUsize::new(0),
+    tail: AtomicUsize::new(0),
+    flushing: AtomicBool::new(false),
+    dropped: AtomicUsize::new(0),
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A record being formatted, truncated to [`MAX_RECORD`] bytes.
+struct RecordBuf {
+    buf: [u8; MAX_RECORD],
+    len: usize,
+}
+
+impl Write for RecordBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = MAX_RECORD - self.len;
+        let mut n = s.len().min(room);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+impl RecordBuf {
+    fn as_str(&self) -> &str {
+        // SAFETY: only whole characters are written.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+/// Splits `s` into the chunks of the slots, at the character boundaries.
+fn chunks(mut s: &str) -> impl Iterator<Item = &str> {
+    core::iter::from_fn(move || {
+        if s.is_empty() {
+            return None;
+        }
+        let mut n = s.len().min(SLOT_DATA);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        let (chunk, rest) = s.split_at(n);
+        s = rest;
+        Some(chunk)
+    })
+}
+
+/// Claims `n` contiguous slots, returns the first position, or [`None`] if
+/// the ring is full.
+fn claim(n: usize) -> Option<usize> {
+    let mut pos = RING.head.load(Ordering::Relaxed);
+    loop {
+        // The flusher frees the slots in order, the others are free if the
+        // last one is.
+        let last = pos + n - 1;
+        let seq = RING.slots[last % NR_SLOTS].seq.load(Ordering::Acquire);
+        match (seq as isize).wrapping_sub(last as isize) {
+            0 => match RING.head.compare_exchange_weak(
+                pos,
+                pos + n,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(pos),
+                Err(head) => pos = head,
+            },
+            diff if diff < 0 => return None,
+            _ => pos = RING.head.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Writes a log record into the ring.
+///
+/// Returns `false` if the ring is not enabled and the record is to be printed
+/// directly.
+pub(crate) fn push(args: fmt::Arguments) -> bool {
+    if !ENABLED.load(Ordering::Acquire) {
+        return false;
+    }
+    let mut record = RecordBuf {
+        buf: [0; MAX_RECORD],
+        len: 0,
+    };
+    let _ = record.write_fmt(args);
+    let s = record.as_str();
+    if s.is_empty() {
+        return true;
+    }
+    let Some(pos) = claim(chunks(s).count()) else {
+        RING.dropped.fetch_add(1, Ordering::Relaxed);
+        return true;
+    };
+    for (i, chunk) in chunks(s).enumerate() {
+        let slot = &RING.slots[(pos + i) % NR_SLOTS];
+        // SAFETY: the slot is claimed by this writer.
+        let (len, data) = unsafe { &mut *slot.data.get() };
+        *len = chunk.len() as u8;
+        data[..chunk.len()].copy_from_slice(chunk.as_bytes());
+        slot.seq.store(pos + i + 1, Ordering::Release);
+    }
+    true
+}
+
+/// Prints the published records of the ring.
+///
+/// Only one caller flushes the ring at a time, the others return at once.
+/// Returns the number of bytes printed.
+pub fn flush_ring() -> usize {
+    if RING.flushing.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    let mut printed = 0;
+    let mut tail = RING.tail.load(Ordering::Relaxed);
+    'drain: loop {
+        let _guard = crate::CONSOLE_LOCK.lock();
+        for _ in 0..FLUSH_BATCH {
+            let slot = &RING.slots[tail % NR_SLOTS];
+            if slot.seq.load(Ordering::Acquire) != tail + 1 {
+                break 'drain;
+            }
+            // SAFETY: the slot is published, and only the flusher reads it.
+            let (len, data) = unsafe { &*slot.data.get() };
+            let len = *len as usize;
+            // SAFETY: the writers split the records at the char boundaries.
+            let _ =
+                crate::Logger.write_str(unsafe { core::str::from_utf8_unchecked(&data[..len]) });
+            printed += len;
+            slot.seq.store(tail + NR_SLOTS, Ordering::Release);
+            tail += 1;
+            RING.tail.store(tail, Ordering::Relaxed);
+        }
+    }
+    let dropped = RING.dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let _guard = crate::CONSOLE_LOCK.lock();
+        let _ = writeln!(crate::Logger, "[{} log records dropped]", dropped);
+    }
+    RING.flushing.store(false, Ordering::Release);
+    printed
+}
+
+/// Writes the log records into the ring from now on, to be printed by
+/// [`flush_ring`].
+pub fn enable_ring() {
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Prints the log records directly from now on, after flushing the ring.
+///
+/// It is to be called before a shutdown or in a panic, so the records in the
+/// ring are not lost. If the ring is being flushed by another CPU, that one
+/// prints them instead.
+pub fn disable_ring() {
+    ENABLED.store(false, Ordering::Release);
+    flush_ring();
+}
+
+/// Returns whether the log records are written into the ring.
+pub fn ring_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,83 @@
+//! Address space layout randomization (ASLR) for user address spaces.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::AddrSpace;
+
+/// Default load address of position-independent executables.
+const PIE_BASE: usize = 0x1000_0000;
+/// Reserved for the user stack below the stack top, mmap areas start below.
+const STACK_GAP: usize = 0x100_0000; // 16 MiB
+
+/// Number of random page bits of each base. With 4K pages, the PIE base
+/// varies in a 1 GiB window, mmap base in 4 GiB, stack top in 64 MiB, and
+/// heap start in 32 MiB.
+const PIE_RANDOM_BITS: u32 = 18;
+const MMAP_RANDOM_BITS: u32 = 20;
+const STACK_RANDOM_BITS: u32 = 14;
+const HEAP_RANDOM_BITS: u32 = 13;
+
+static ASLR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables ASLR for address spaces created afterwards.
+///
+/// It is enabled by default. Disabling it at boot makes user layouts
+/// reproducible, which is useful for debugging: sys_map does it by `aslr=off`
+/// on the command line, or by `ASLR=n` at build time.
+pub fn set_aslr_enabled(enabled: bool) {
+    info!("user ASLR {}", if enabled { "enabled" } else { "disabled" });
+    ASLR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether ASLR is enabled.
+pub fn aslr_enabled() -> bool {
+    ASLR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// A random page offset of `bits` bits, from the entropy pool with the
+/// `entropy` feature of axhal.
+fn random_pages(bits: u32) -> usize {
+    if aslr_enabled() {
+        let mut bytes = [0; size_of::<usize>()];
+        axhal::misc::random_bytes(&mut bytes);
+        (usize::from_le_bytes(bytes) & ((1 << bits) - 1)) * PAGE_SIZE_4K
+    } else {
+        0
+    }
+}
+
+/// The (possibly randomized) layout of a user address space.
+#[derive(Debug, Clone, Copy)]
+pub struct UserLayout {
+    /// Load bias of position-independent executables.
+    pub pie_base: VirtAddr,
+    /// Top of the user stack.
+    pub stack_top: VirtAddr,
+    /// Highest address of the mmap area, which grows downwards.
+    pub mmap_base: VirtAddr,
+    /// Gap between the end of the loaded image and the heap.
+    heap_offset: usize,
+}
+
+impl UserLayout {
+    /// Chooses a layout for the given user address space.
+    pub fn new(aspace: &AddrSpace) -> Self {
+        let stack_top = aspace.end() - random_pages(STACK_RANDOM_BITS);
+        let layout = Self {
+            pie_base: aspace.base() + PIE_BASE + random_pages(PIE_RANDOM_BITS),
+            stack_top,
+            mmap_base: stack_top - STACK_GAP - random_pages(MMAP_RANDOM_BITS),
+            heap_offset: random_pages(HEAP_RANDOM_BITS),
+        };
+        debug!("new user layout: {:#x?}", layout);
+        layout
+    }
+
+    /// Returns the start of the heap (the initial program break), given the
+    /// end of the loaded image.
+    pub fn heap_start(&self, image_end: VirtAddr) -> VirtAddr {
+        image_end.align_up_4k() + self.heap_offset
+    }
+}
@@ -19,6 +19,10 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    #[cfg(feature = "dirty-log")]
+    dirty_log: Option<crate::dirty::DirtyLog>,
+    #[cfg(feature = "idle-track")]
+    idle: crate::idle::IdleTracker,
 }
 
 impl AddrSpace {
@@ -42,6 +46,15 @@ impl AddrSpace {
         &self.pt
     }
 
+    pub(crate) fn parts(&self) -> (&MemorySet<Backend>, &PageTable) {
+        (&self.areas, &self.pt)
+    }
+
+    #[cfg(any(feature = "ksm", feature = "compaction"))]
+    pub(crate) fn parts_mut(&mut self) -> (&MemorySet<Backend>, &mut PageTable) {
+        (&self.areas, &mut self.pt)
+    }
+
     /// Returns the root physical address of the inner page table.
     pub const fn page_table_root(&self) -> PhysAddr {
         self.pt.root_paddr()
@@ -59,9 +72,99 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            #[cfg(feature = "dirty-log")]
+            dirty_log: None,
+            #[cfg(feature = "idle-track")]
+            idle: Default::default(),
         })
     }
 
+    /// Records that the mappings of the range changed, if logged.
+    #[allow(unused_variables)]
+    fn remapped(&mut self, start: VirtAddr, size: usize) {
+        #[cfg(feature = "dirty-log")]
+        if let Some(log) = &mut self.dirty_log {
+            log.remap(start, size);
+        }
+        #[cfg(feature = "idle-track")]
+        self.idle.remap(start, size);
+    }
+
+    /// Marks the pages of the range as dirty, if logged.
+    #[allow(unused_variables)]
+    fn mark_dirty(&self, start: VirtAddr, size: usize) {
+        #[cfg(feature = "dirty-log")]
+        if let Some(log) = &self.dirty_log {
+            log.mark(start, size);
+        }
+    }
+
+    /// Starts logging the pages written in the range, see [`dirty`](crate::dirty).
+    ///
+    /// Returns an error if the range is out of the address space or not
+    /// aligned, or if a range is already logged.
+    #[cfg(feature = "dirty-log")]
+    pub fn start_dirty_log(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        if self.dirty_log.is_some() {
+            return ax_err!(AlreadyExists, "dirty log already started");
+        }
+        let range = VirtAddrRange::from_start_size(start, size);
+        self.dirty_log = Some(crate::dirty::DirtyLog::new(range, &mut self.pt));
+        Ok(())
+    }
+
+    /// Stops logging the pages written, giving the write permission back to
+    /// the pages write-protected by the log.
+    #[cfg(feature = "dirty-log")]
+    pub fn stop_dirty_log(&mut self) {
+        if let Some(log) = self.dirty_log.take() {
+            log.stop(&mut self.pt);
+        }
+    }
+
+    /// Returns the pages written since the start of the log or the previous
+    /// call, and write-protects them again. Returns `None` if no range is
+    /// logged.
+    #[cfg(feature = "dirty-log")]
+    pub fn take_dirty_log(&mut self) -> Option<crate::dirty::DirtyBitmap> {
+        let log = self.dirty_log.as_mut()?;
+        Some(log.take(&mut self.pt))
+    }
+
+    /// Samples the idle pages of the address space, see [`idle`](crate::idle),
+    /// and returns its report.
+    #[cfg(feature = "idle-track")]
+    pub fn sample_idle(&mut self) -> crate::idle::IdleReport {
+        self.idle.sample(&self.areas, &mut self.pt)
+    }
+
+    /// Returns the report of the last sample of the idle pages.
+    #[cfg(feature = "idle-track")]
+    pub fn idle_report(&self) -> crate::idle::IdleReport {
+        self.idle.report()
+    }
+
+    /// Returns the bytes of `start..end` not accessed during the last
+    /// interval of the samples of the idle pages.
+    #[cfg(all(feature = "idle-track", feature = "maps"))]
+    pub(crate) fn idle_size(&self, start: VirtAddr, end: VirtAddr) -> usize {
+        self.idle.idle_size(start, end)
+    }
+
+    /// Returns the 4K pages not accessed for `min_samples` samples of the
+    /// idle pages at least (one at least), in ascending order: the first to
+    /// reclaim.
+    #[cfg(feature = "idle-track")]
+    pub fn idle_pages(&self, min_samples: u8) -> Vec<VirtAddr> {
+        self.idle.idle_pages(min_samples).collect()
+    }
+
     /// Copies page table mappings from another address space.
     ///
     /// It copies the page table entries only rather than the memory regions,
@@ -126,6 +229,35 @@ impl AddrSpace {
             )
             .map_err(paging_err_to_ax_err)?
             .flush_all();
+        self.remapped(start_vaddr, size);
+        Ok(())
+    }
+
+    /// Add a new linear mapping of frames shared with other address spaces,
+    /// as an area so that it is seen by `find_free_area`.
+    ///
+    /// The frames are not freed when it is unmapped.
+    #[cfg(feature = "ring")]
+    pub fn map_shared(
+        &mut self,
+        start: VirtAddr,
+        paddr: PhysAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !paddr.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let offset = start.as_usize().wrapping_sub(paddr.as_usize());
+        let area = MemoryArea::new(start, size, flags, Backend::new_linear(offset));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        self.remapped(start, size);
         Ok(())
     }
 
@@ -155,6 +287,7 @@ impl AddrSpace {
         self.areas
             .map(area, &mut self.pt, false)
             .map_err(mapping_err_to_ax_err)?;
+        self.remapped(start, size);
         Ok(())
     }
 
@@ -174,6 +307,17 @@ impl AddrSpace {
             .unmap_region(start, size, true)
             .map_err(paging_err_to_ax_err)?
             .ignore();
+        self.remapped(start, size);
+        Ok(())
+    }
+
+    /// Removes all the mappings of the address space, keeping its page table
+    /// with the mappings copied from other address spaces (the kernel ones).
+    pub fn clear(&mut self) -> AxResult {
+        self.areas
+            .clear(&mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
+        self.remapped(self.base(), self.size());
         Ok(())
     }
 
@@ -230,11 +374,51 @@ impl AddrSpace {
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
     pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
+        #[cfg(feature = "ksm")]
+        if crate::ksm::range_shared(&self.pt, start, buf.len()) {
+            return ax_err!(PermissionDenied, "write to merged pages");
+        }
+        self.mark_dirty(start, buf.len());
         self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
             core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
         })
     }
 
+    /// Translates `vaddr` to its address in the linear mapping, for an access
+    /// of `access` by the kernel, with the bytes from it to the end of its
+    /// page.
+    ///
+    /// The page is faulted in (e.g. allocated lazily, or unmerged before a
+    /// write) if it is not mapped for `access` yet.
+    fn translate_for(
+        &mut self,
+        vaddr: VirtAddr,
+        access: MappingFlags,
+    ) -> AxResult<(VirtAddr, usize)> {
+        let Some(area) = self.areas.find(vaddr) else {
+            return ax_err!(BadAddress, "address not mapped");
+        };
+        if !area.flags().contains(access) {
+            return ax_err!(PermissionDenied, "access not allowed by the area");
+        }
+        let query = |pt: &PageTable| match pt.query(vaddr) {
+            Ok((paddr, flags, page_size)) if flags.contains(access) => Some((paddr, page_size)),
+            _ => None,
+        };
+        let (paddr, page_size) = match query(&self.pt) {
+            Some(mapped) => mapped,
+            None if self.handle_page_fault(vaddr, access) => {
+                query(&self.pt).ok_or(AxError::BadAddress)?
+            }
+            None => return ax_err!(BadAddress, "page fault not handled"),
+        };
+        let page_size: usize = page_size.into();
+        Ok((
+            phys_to_virt(paddr),
+            page_size - vaddr.align_offset(page_size),
+        ))
+    }
+
     /// Updates mapping within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
@@ -251,6 +435,11 @@ impl AddrSpace {
             .protect_region(start, size, flags, true)
             .map_err(paging_err_to_ax_err)?
             .ignore();
+        self.remapped(start, size);
+        #[cfg(feature = "ksm")]
+        if flags.contains(MappingFlags::WRITE) {
+            crate::ksm::write_protect(&mut self.pt, start, size);
+        }
         Ok(())
     }
 
@@ -264,9 +453,31 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        #[cfg(feature = "dirty-log")]
+        if let Some(log) = &mut self.dirty_log {
+            if log.contains(vaddr) && log.handle_fault(&mut self.pt, vaddr, access_flags) {
+                return true;
+            }
+        }
+        #[cfg(feature = "idle-track")]
+        if self.idle.handle_fault(&mut self.pt, vaddr) {
+            return true;
+        }
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
+                // Already handled, e.g. after a migration of the page.
+                if let Ok((_, flags, _)) = self.pt.query(vaddr) {
+                    if flags.contains(access_flags) {
+                        return true;
+                    }
+                }
+                #[cfg(feature = "ksm")]
+                if access_flags.contains(MappingFlags::WRITE)
+                    && crate::ksm::handle_write_fault(&mut self.pt, vaddr, orig_flags)
+                {
+                    return true;
+                }
                 return area
                     .backend()
                     .handle_page_fault(vaddr, orig_flags, &mut self.pt);
@@ -293,6 +504,8 @@ impl AddrSpace {
                 return None;
             }
 
+            // Written through the linear mapping, without faults.
+            self.mark_dirty(vaddr, len);
             let mut start = vaddr;
             let end = start + len;
 
@@ -324,6 +537,38 @@ impl AddrSpace {
     }
 }
 
+/// Copies `len` bytes at `src_vaddr` in the address space `src` to
+/// `dst_vaddr` in `dst`, for the debuggers and the core dumps of the user
+/// processes.
+///
+/// The pages are faulted in as on the accesses of the processes, e.g.
+/// allocated lazily, so the copy sees their memory as they do. Returns
+/// `PermissionDenied` if an area of the source does not allow reading or one
+/// of the destination does not allow writing, and `BadAddress` if a page is
+/// not in an area or cannot be faulted in. The bytes before that page are
+/// copied.
+pub fn copy_between(
+    src: &mut AddrSpace,
+    src_vaddr: VirtAddr,
+    dst: &mut AddrSpace,
+    dst_vaddr: VirtAddr,
+    len: usize,
+) -> AxResult {
+    if !src.contains_range(src_vaddr, len) || !dst.contains_range(dst_vaddr, len) {
+        return ax_err!(InvalidInput, "address out of range");
+    }
+    let mut copied = 0;
+    while copied < len {
+        let (from, src_left) = src.translate_for(src_vaddr + copied, MappingFlags::READ)?;
+        let (to, dst_left) = dst.translate_for(dst_vaddr + copied, MappingFlags::WRITE)?;
+        let size = (len - copied).min(src_left).min(dst_left);
+        // The two pages can be the same frame, e.g. shared by both.
+        unsafe { core::ptr::copy(from.as_ptr(), to.as_mut_ptr(), size) };
+        copied += size;
+    }
+    Ok(())
+}
+
 impl fmt::Debug for AddrSpace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AddrSpace")
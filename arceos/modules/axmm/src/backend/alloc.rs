@@ -1,11 +1,19 @@
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
+#[cfg(feature = "compaction")]
+use memory_addr::MemoryAddr;
 use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
 use super::Backend;
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+pub(crate) fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+    // zeroed in the background, see `axalloc::scrub`
+    #[cfg(feature = "page-scrub")]
+    if zeroed {
+        let vaddr = VirtAddr::from(axalloc::scrub::alloc_zeroed_page().ok()?);
+        return Some(virt_to_phys(vaddr));
+    }
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
@@ -14,7 +22,13 @@ fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
+pub(crate) fn dealloc_frame(frame: PhysAddr) {
+    #[cfg(feature = "ksm")]
+    if !crate::ksm::release(frame) {
+        return;
+    }
+    #[cfg(feature = "compaction")]
+    crate::compact::rmap_remove(frame);
     let vaddr = phys_to_virt(frame);
     global_allocator().dealloc_pages(vaddr.as_usize(), 1);
 }
@@ -46,6 +60,8 @@ impl Backend {
                 if let Some(frame) = alloc_frame(true) {
                     if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
                         tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
+                        #[cfg(feature = "compaction")]
+                        crate::compact::rmap_insert(frame, pt.root_paddr(), addr);
                     } else {
                         return false;
                     }
@@ -98,6 +114,8 @@ impl Backend {
             // Allocate a physical frame lazily and map it to the fault address.
             // `vaddr` does not need to be aligned. It will be automatically
             // aligned during `pt.remap` regardless of the page size.
+            #[cfg(feature = "compaction")]
+            crate::compact::rmap_insert(frame, pt.root_paddr(), vaddr.align_down_4k());
             pt.remap(vaddr, frame, orig_flags)
                 .map(|(_, tlb)| tlb.flush())
                 .is_ok()
@@ -8,6 +8,9 @@ use memory_set::MappingBackend;
 mod alloc;
 mod linear;
 
+#[cfg(feature = "ksm")]
+pub(crate) use self::alloc::{alloc_frame, dealloc_frame};
+
 /// A unified enum type for different memory mapping backends.
 ///
 /// Currently, two backends are implemented:
@@ -24,7 +27,7 @@ pub enum Backend {
     /// constant, which is specified by `pa_va_offset`. For example, the virtual
     /// address `vaddr` is mapped to the physical address `vaddr - pa_va_offset`.
     Linear {
-        /// `vaddr - paddr`.
+        /// `vaddr - paddr`, wrapping if the virtual address is the lower one.
         pa_va_offset: usize,
     },
     /// Allocation mapping backend.
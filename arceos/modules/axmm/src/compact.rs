@@ -0,0 +1,155 @@
+//! Memory compaction.
+//!
+//! When an allocation of contiguous pages fails, the global allocator calls
+//! [`compact`] (installed by [`init`]) and retries. It migrates the movable
+//! pages out of the aligned windows holding the most of them, so that one of
+//! these windows may become free for huge pages or DMA buffers.
+//!
+//! The movable pages are the private frames of the allocation mappings of
+//! the [registered](crate::register_aspace) address spaces, found by a
+//! reverse map from frames to their mappings. The pages of unregistered or
+//! locked address spaces, and the shared frames of [`ksm`](crate::ksm),
+//! stay in place. There is no page cache yet, whose pages would be movable
+//! too.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use axalloc::global_allocator;
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::paging::{MappingFlags, PageSize};
+use kspin::SpinNoIrq;
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// Maximum number of windows evacuated by a compaction pass.
+const MAX_WINDOWS: usize = 4;
+
+/// Frames of allocation mappings, with the root of the page table and the
+/// virtual address mapping them.
+static RMAP: SpinNoIrq<BTreeMap<PhysAddr, (PhysAddr, VirtAddr)>> = SpinNoIrq::new(BTreeMap::new());
+
+pub(crate) fn rmap_insert(frame: PhysAddr, root: PhysAddr, vaddr: VirtAddr) {
+    RMAP.lock().insert(frame, (root, vaddr));
+}
+
+pub(crate) fn rmap_remove(frame: PhysAddr) {
+    RMAP.lock().remove(&frame);
+}
+
+/// Installs [`compact`] as the compaction hook of the global allocator.
+pub fn init() {
+    axalloc::set_compaction_hook(compact);
+}
+
+/// Migrates movable pages to make room for `num_pages` contiguous pages
+/// aligned to `align_pow2`, returns whether any page was migrated.
+pub fn compact(num_pages: usize, align_pow2: usize) -> bool {
+    // Not when called while the reverse map is being updated.
+    let Some(mut rmap) = RMAP.try_lock() else {
+        return false;
+    };
+    // Windows large enough and aligned, in the kernel virtual addresses of
+    // the allocator.
+    let stride = (num_pages * PAGE_SIZE_4K).next_multiple_of(align_pow2.max(PAGE_SIZE_4K));
+    let mut counts = BTreeMap::<usize, usize>::new();
+    for &frame in rmap.keys() {
+        #[cfg(feature = "ksm")]
+        if crate::ksm::is_shared(frame) {
+            continue;
+        }
+        *counts
+            .entry(phys_to_virt(frame).as_usize() / stride)
+            .or_default() += 1;
+    }
+    let mut windows: Vec<_> = counts.into_iter().collect();
+    windows.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+    let mut migrated = 0;
+    for &(index, _) in windows.iter().take(MAX_WINDOWS) {
+        let window = index * stride..(index + 1) * stride;
+        let frames: Vec<PhysAddr> = rmap
+            .range(virt_to_phys(window.start.into())..virt_to_phys(window.end.into()))
+            .map(|(&frame, _)| frame)
+            .collect();
+        // Free pages of the window got while allocating ones out of it.
+        let mut held = Vec::new();
+        for frame in frames {
+            if migrate(&mut rmap, frame, &window, &mut held) {
+                migrated += 1;
+            } else if held.len() > num_pages {
+                break;
+            }
+        }
+        for vaddr in held {
+            global_allocator().dealloc_pages(vaddr, 1);
+        }
+    }
+    debug!(
+        "compaction for {} pages: {} pages migrated",
+        num_pages, migrated
+    );
+    migrated > 0
+}
+
+/// Moves `frame` to a new frame out of `window`.
+fn migrate(
+    rmap: &mut BTreeMap<PhysAddr, (PhysAddr, VirtAddr)>,
+    frame: PhysAddr,
+    window: &Range<usize>,
+    held: &mut Vec<usize>,
+) -> bool {
+    #[cfg(feature = "ksm")]
+    if crate::ksm::is_shared(frame) {
+        return false;
+    }
+    let Some(&(root, vaddr)) = rmap.get(&frame) else {
+        return false;
+    };
+    let Some(aspace) = crate::registry::find(root) else {
+        return false;
+    };
+    let Some(mut aspace) = aspace.try_lock() else {
+        return false;
+    };
+    let new = loop {
+        let Ok(new) = global_allocator().alloc_pages(1, PAGE_SIZE_4K) else {
+            return false;
+        };
+        if !window.contains(&new) {
+            break virt_to_phys(new.into());
+        }
+        held.push(new);
+    };
+
+    let (_, pt) = aspace.parts_mut();
+    let moved = match pt.query(vaddr) {
+        Ok((paddr, flags, PageSize::Size4K)) if paddr == frame => {
+            // Stops writes during the copy.
+            if let Ok((_, tlb)) = pt.protect(vaddr, flags - MappingFlags::WRITE) {
+                tlb.flush();
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        phys_to_virt(frame).as_ptr(),
+                        phys_to_virt(new).as_mut_ptr(),
+                        PAGE_SIZE_4K,
+                    )
+                };
+                pt.remap(vaddr, new, flags)
+                    .map(|(_, tlb)| tlb.flush())
+                    .is_ok()
+            } else {
+                false
+            }
+        }
+        _ => false,
+    };
+    if moved {
+        rmap.remove(&frame);
+        rmap.insert(new, (root, vaddr));
+        global_allocator().dealloc_pages(phys_to_virt(frame).as_usize(), 1);
+    } else {
+        global_allocator().dealloc_pages(phys_to_virt(new).as_usize(), 1);
+    }
+    moved
+}
@@ -0,0 +1,283 @@
+//! Dirty page logging, for the live migration of the guests of a hypervisor.
+//!
+//! The guest physical memory of a virtual machine is an [`AddrSpace`] whose
+//! page table is the stage-2 (nested) one. To migrate it while it runs, the
+//! hypervisor copies all the memory, then repeatedly the pages written since
+//! the previous copy, until few are left and the guest is paused for the
+//! last copy.
+//!
+//! [`AddrSpace::start_dirty_log`] write-protects the writable pages of a
+//! range, and [`AddrSpace::handle_page_fault`] records the pages whose write
+//! faults on them as dirty before giving the write permission back, so that
+//! a page faults once per round. The page table API does not expose the
+//! dirty bits of the hardware, hence the write protection. The writes of the
+//! kernel through [`AddrSpace::write`] and the pages mapped, unmapped or
+//! protected again in the range are recorded as well.
+//! [`AddrSpace::take_dirty_log`] returns the [`DirtyBitmap`] of the pages
+//! dirtied since the previous call and write-protects them again.
+//!
+//! The hypervisor must flush the guest TLB (e.g. `hfence.gvma` on RISC-V)
+//! after starting the log and after taking it, as the stage-2 translations
+//! cached by the CPUs may still allow the writes.
+//!
+//! [`AddrSpace`]: crate::AddrSpace
+//! [`AddrSpace::start_dirty_log`]: crate::AddrSpace::start_dirty_log
+//! [`AddrSpace::handle_page_fault`]: crate::AddrSpace::handle_page_fault
+//! [`AddrSpace::write`]: crate::AddrSpace::write
+//! [`AddrSpace::take_dirty_log`]: crate::AddrSpace::take_dirty_log
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::paging::{MappingFlags, PageTable};
+use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+const BITS: usize = u64::BITS as usize;
+
+/// The state of the dirty page logging of a range of an address space.
+pub(crate) struct DirtyLog {
+    range: VirtAddrRange,
+    /// A bit per page, set by the writes. Atomic for the writes of the
+    /// kernel, which only borrow the address space.
+    dirty: Vec<AtomicU64>,
+    /// A bit per page write-protected by the log.
+    protected: Vec<u64>,
+}
+
+impl DirtyLog {
+    /// Starts logging `range`, whose writable pages of `pt` are
+    /// write-protected.
+    pub(crate) fn new(range: VirtAddrRange, pt: &mut PageTable) -> Self {
+        let words = (range.size() / PAGE_SIZE_4K).div_ceil(BITS);
+        let mut log = Self {
+            range,
+            dirty: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            protected: alloc::vec![0; words],
+        };
+        log.protect_range(pt, range.start, range.end, |_| true);
+        log
+    }
+
+    /// Returns whether `vaddr` is in the logged range.
+    pub(crate) fn contains(&self, vaddr: VirtAddr) -> bool {
+        self.range.contains(vaddr)
+    }
+
+    fn index(&self, vaddr: VirtAddr) -> usize {
+        (vaddr - self.range.start) / PAGE_SIZE_4K
+    }
+
+    /// Returns the indexes of the pages of the logged range overlapping
+    /// `start..end`.
+    fn indexes(&self, start: VirtAddr, end: VirtAddr) -> core::ops::Range<usize> {
+        let start = start.max(self.range.start).align_down_4k();
+        let end = end.min(self.range.end).align_up_4k();
+        if start >= end {
+            return 0..0;
+        }
+        self.index(start)..self.index(end)
+    }
+
+    /// Marks the pages overlapping `start..start + size` as dirty.
+    pub(crate) fn mark(&self, start: VirtAddr, size: usize) {
+        for i in self.indexes(start, start + size) {
+            self.dirty[i / BITS].fetch_or(1 << (i % BITS), Ordering::Relaxed);
+        }
+    }
+
+    /// Records that the mappings of `start..start + size` changed: the pages
+    /// are dirty, and no longer write-protected by the log.
+    pub(crate) fn remap(&mut self, start: VirtAddr, size: usize) {
+        self.mark(start, size);
+        for i in self.indexes(start, start + size) {
+            self.protected[i / BITS] &= !(1 << (i % BITS));
+        }
+    }
+
+    fn is_protected(&self, i: usize) -> bool {
+        self.protected[i / BITS] & (1 << (i % BITS)) != 0
+    }
+
+    /// Write-protects the writable pages of `start..end` selected by
+    /// `select`, given the index of their first page in the range.
+    fn protect_range(
+        &mut self,
+        pt: &mut PageTable,
+        start: VirtAddr,
+        end: VirtAddr,
+        select: impl Fn(usize) -> bool,
+    ) {
+        let mut vaddr = start;
+        while vaddr < end {
+            let Ok((_, flags, page_size)) = pt.query(vaddr) else {
+                vaddr += PAGE_SIZE_4K;
+                continue;
+            };
+            let page_end = vaddr.align_down(page_size) + page_size.into();
+            if flags.contains(MappingFlags::WRITE) && select(self.index(vaddr)) {
+                if let Ok((_, tlb)) = pt.protect(vaddr, flags - MappingFlags::WRITE) {
+                    tlb.flush();
+                    for i in self.indexes(vaddr, page_end) {
+                        self.protected[i / BITS] |= 1 << (i % BITS);
+                    }
+                }
+            }
+            vaddr = page_end;
+        }
+    }
+
+    /// Handles a fault at `vaddr` in the logged range, with the access
+    /// `access`.
+    ///
+    /// The page is dirty whatever the access, as the fault may map a new
+    /// page, writable. Returns `true` if it was a write to a page
+    /// write-protected by the log, which is writable again.
+    pub(crate) fn handle_fault(
+        &mut self,
+        pt: &mut PageTable,
+        vaddr: VirtAddr,
+        access: MappingFlags,
+    ) -> bool {
+        let Ok((_, flags, page_size)) = pt.query(vaddr) else {
+            self.mark(vaddr.align_down_4k(), PAGE_SIZE_4K);
+            return false;
+        };
+        let page_start = vaddr.align_down(page_size);
+        let page_size: usize = page_size.into();
+        self.mark(page_start, page_size);
+        if !access.contains(MappingFlags::WRITE) || !self.is_protected(self.index(vaddr)) {
+            return false;
+        }
+        self.remap(page_start, page_size);
+        // Merged since protected: unshared by the copy-on-write.
+        #[cfg(feature = "ksm")]
+        if crate::ksm::range_shared(pt, page_start, page_size) {
+            return false;
+        }
+        match pt.protect(vaddr, flags | MappingFlags::WRITE) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the pages dirtied since the previous call, and write-protects
+    /// them again.
+    pub(crate) fn take(&mut self, pt: &mut PageTable) -> DirtyBitmap {
+        let bits: Vec<u64> = self
+            .dirty
+            .iter()
+            .map(|word| word.swap(0, Ordering::Relaxed))
+            .collect();
+        let is_set = |i: usize| bits[i / BITS] & (1 << (i % BITS)) != 0;
+        self.protect_range(pt, self.range.start, self.range.end, is_set);
+        DirtyBitmap {
+            start: self.range.start,
+            pages: self.range.size() / PAGE_SIZE_4K,
+            bits,
+        }
+    }
+
+    /// Stops logging: gives the write permission back to the pages
+    /// write-protected by the log.
+    pub(crate) fn stop(self, pt: &mut PageTable) {
+        let mut vaddr = self.range.start;
+        while vaddr < self.range.end {
+            let i = self.index(vaddr);
+            match pt.query(vaddr) {
+                Ok((_, flags, page_size)) if self.is_protected(i) => {
+                    if let Ok((_, tlb)) = pt.protect(vaddr, flags | MappingFlags::WRITE) {
+                        tlb.flush();
+                    }
+                    vaddr = vaddr.align_down(page_size) + page_size.into();
+                }
+                _ => vaddr += PAGE_SIZE_4K,
+            }
+        }
+        #[cfg(feature = "ksm")]
+        crate::ksm::write_protect(pt, self.range.start, self.range.size());
+    }
+}
+
+/// The pages of a logged range dirtied during a round, returned by
+/// [`AddrSpace::take_dirty_log`].
+///
+/// [`AddrSpace::take_dirty_log`]: crate::AddrSpace::take_dirty_log
+#[derive(Debug, Clone)]
+pub struct DirtyBitmap {
+    start: VirtAddr,
+    pages: usize,
+    bits: Vec<u64>,
+}
+
+impl DirtyBitmap {
+    /// Returns the start of the logged range.
+    pub const fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    /// Returns the size of the logged range.
+    pub const fn size(&self) -> usize {
+        self.pages * PAGE_SIZE_4K
+    }
+
+    /// Returns the bits of the pages, the page `i` of the range being the bit
+    /// `i % 64` of the word `i / 64`, as the dirty bitmaps of KVM.
+    pub fn as_words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    /// Returns whether the 4K page at `vaddr` is dirty.
+    pub fn is_dirty(&self, vaddr: VirtAddr) -> bool {
+        if vaddr < self.start || vaddr >= self.start + self.size() {
+            return false;
+        }
+        let i = (vaddr - self.start) / PAGE_SIZE_4K;
+        self.bits[i / BITS] & (1 << (i % BITS)) != 0
+    }
+
+    /// Returns the number of dirty pages.
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns whether no page is dirty.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&w| w == 0)
+    }
+
+    /// Returns the addresses of the dirty 4K pages, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = VirtAddr> + '_ {
+        self.bits
+            .iter()
+            .enumerate()
+            .flat_map(|(word_idx, &word)| {
+                let mut word = word;
+                core::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(word_idx * BITS + bit)
+                })
+            })
+            .map(|i| self.start + i * PAGE_SIZE_4K)
+    }
+
+    /// Returns the runs of contiguous dirty pages, as their start and size,
+    /// in ascending order, to copy them by larger chunks.
+    pub fn runs(&self) -> impl Iterator<Item = (VirtAddr, usize)> + '_ {
+        let mut pages = self.iter().peekable();
+        core::iter::from_fn(move || {
+            let start = pages.next()?;
+            let mut end = start + PAGE_SIZE_4K;
+            while pages.next_if_eq(&end).is_some() {
+                end += PAGE_SIZE_4K;
+            }
+            Some((start, end - start))
+        })
+    }
+}
@@ -0,0 +1,206 @@
+//! Idle page tracking and working set estimation.
+//!
+//! The page table API does not expose the accessed bits of the hardware, so
+//! they are emulated as the dirty bits of [`dirty`](crate::dirty) are: a
+//! sample of an address space takes the user permission away from its
+//! resident anonymous pages, and [`AddrSpace::handle_page_fault`] gives it
+//! back to a page on its first user access since, marking it accessed. The
+//! pages stay mapped, so the kernel still reads and writes them, but these
+//! accesses are not counted.
+//!
+//! At each sample, the pages not accessed since the previous one age by one
+//! sample, the others are young again. The [`IdleReport`] of an address space
+//! tells its working set, the pages accessed during the last interval, and
+//! its idle and cold pages, not accessed for one and [`COLD_SAMPLES`]
+//! samples: the ones to reclaim first, or to give back to the hypervisor by
+//! a balloon driver, from [`AddrSpace::idle_pages`].
+//!
+//! The sampler runs over the [registered](crate::register_aspace) address
+//! spaces in a background task with [`start`], or once with [`sample_all`].
+//! A page faults once per interval at most, so the interval trades the
+//! precision for the overhead.
+//!
+//! [`AddrSpace::handle_page_fault`]: crate::AddrSpace::handle_page_fault
+//! [`AddrSpace::idle_pages`]: crate::AddrSpace::idle_pages
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::time::Duration;
+
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, VirtAddr, PAGE_SIZE_4K};
+use memory_set::MemorySet;
+
+use crate::backend::Backend;
+
+/// The samples a page stays idle to be cold.
+pub const COLD_SAMPLES: u8 = 4;
+
+/// The working set and the idle memory of an address space, or of all of
+/// them, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdleReport {
+    /// The anonymous memory resident, tracked.
+    pub resident: usize,
+    /// The memory accessed during the last interval.
+    pub working_set: usize,
+    /// The memory not accessed during the last interval.
+    pub idle: usize,
+    /// The memory not accessed for [`COLD_SAMPLES`] intervals.
+    pub cold: usize,
+}
+
+impl core::ops::AddAssign for IdleReport {
+    fn add_assign(&mut self, other: Self) {
+        self.resident += other.resident;
+        self.working_set += other.working_set;
+        self.idle += other.idle;
+        self.cold += other.cold;
+    }
+}
+
+/// The state of the idle page tracking of an address space.
+#[derive(Default)]
+pub(crate) struct IdleTracker {
+    /// The samples each tracked page is idle for, 0 if accessed since the
+    /// previous sample, by address.
+    ages: BTreeMap<VirtAddr, u8>,
+    /// The pages whose user permission is taken away by the tracker.
+    protected: BTreeSet<VirtAddr>,
+    report: IdleReport,
+}
+
+impl IdleTracker {
+    /// Ages the pages not accessed since the previous sample, and takes the
+    /// user permission away from the resident ones again.
+    pub(crate) fn sample(&mut self, areas: &MemorySet<Backend>, pt: &mut PageTable) -> IdleReport {
+        let mut ages = BTreeMap::new();
+        let mut protected = BTreeSet::new();
+        let mut report = IdleReport::default();
+        for area in areas.iter() {
+            if !matches!(area.backend(), Backend::Alloc { .. })
+                || !area.flags().contains(MappingFlags::USER)
+            {
+                continue;
+            }
+            for vaddr in PageIter4K::new(area.start(), area.end()).unwrap() {
+                let Ok((_, flags, PageSize::Size4K)) = pt.query(vaddr) else {
+                    continue;
+                };
+                // The lazy mappings not faulted in yet have no flags.
+                if flags.is_empty() {
+                    continue;
+                }
+                let age = if self.protected.contains(&vaddr) {
+                    self.ages.get(&vaddr).map_or(1, |age| age.saturating_add(1))
+                } else {
+                    0
+                };
+                report.resident += PAGE_SIZE_4K;
+                match age {
+                    0 => report.working_set += PAGE_SIZE_4K,
+                    _ => report.idle += PAGE_SIZE_4K,
+                }
+                if age >= COLD_SAMPLES {
+                    report.cold += PAGE_SIZE_4K;
+                }
+                ages.insert(vaddr, age);
+                if flags.contains(MappingFlags::USER) {
+                    match pt.protect(vaddr, flags - MappingFlags::USER) {
+                        Ok((_, tlb)) => tlb.flush(),
+                        Err(_) => continue,
+                    }
+                }
+                protected.insert(vaddr);
+            }
+        }
+        self.ages = ages;
+        self.protected = protected;
+        self.report = report;
+        report
+    }
+
+    /// Handles a fault at `vaddr`: returns `true` if the page had its user
+    /// permission taken away by the tracker, which is given back.
+    pub(crate) fn handle_fault(&mut self, pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+        let page = vaddr.align_down_4k();
+        if !self.protected.remove(&page) {
+            return false;
+        }
+        self.ages.insert(page, 0);
+        let Ok((_, flags, _)) = pt.query(page) else {
+            return false;
+        };
+        match pt.protect(page, flags | MappingFlags::USER) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Records that the mappings of `start..start + size` changed: the pages
+    /// are no longer tracked until the next sample.
+    pub(crate) fn remap(&mut self, start: VirtAddr, size: usize) {
+        let range = start.align_down_4k()..(start + size).align_up_4k();
+        self.ages.retain(|vaddr, _| !range.contains(vaddr));
+        self.protected.retain(|vaddr| !range.contains(vaddr));
+    }
+
+    /// Returns the report of the last sample.
+    pub(crate) fn report(&self) -> IdleReport {
+        self.report
+    }
+
+    /// Returns the bytes of `start..end` not accessed during the last
+    /// interval.
+    pub(crate) fn idle_size(&self, start: VirtAddr, end: VirtAddr) -> usize {
+        let idle = self.ages.range(start..end).filter(|(_, &age)| age > 0);
+        idle.count() * PAGE_SIZE_4K
+    }
+
+    /// Returns the pages idle for `min_samples` samples at least, in
+    /// ascending order.
+    pub(crate) fn idle_pages(&self, min_samples: u8) -> impl Iterator<Item = VirtAddr> + '_ {
+        self.ages
+            .iter()
+            .filter(move |(_, &age)| age >= min_samples.max(1))
+            .map(|(&vaddr, _)| vaddr)
+    }
+}
+
+/// The report of all the address spaces at the last sample.
+static TOTAL: SpinNoIrq<IdleReport> = SpinNoIrq::new(IdleReport {
+    resident: 0,
+    working_set: 0,
+    idle: 0,
+    cold: 0,
+});
+
+/// Returns the report of all the registered address spaces at the last
+/// sample.
+pub fn stats() -> IdleReport {
+    *TOTAL.lock()
+}
+
+/// Samples all the registered address spaces once, and returns the total
+/// report.
+pub fn sample_all() -> IdleReport {
+    let mut total = IdleReport::default();
+    for aspace in crate::registry::aspaces() {
+        total += aspace.lock().sample_idle();
+    }
+    *TOTAL.lock() = total;
+    total
+}
+
+/// Starts the background sampler, sampling all the registered address
+/// spaces every `interval`.
+pub fn start(interval: Duration) {
+    axtask::spawn(move || loop {
+        let report = sample_all();
+        debug!("idle: {:?}", report);
+        axtask::sleep(interval);
+    });
+}
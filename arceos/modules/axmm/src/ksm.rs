@@ -0,0 +1,261 @@
+//! Kernel same-page merging (KSM).
+//!
+//! A scanner walks the read-only pages of the allocation mappings of the
+//! [registered](crate::register_aspace) address spaces, and maps the pages with identical
+//! content to the same frame, freeing the others. The shared frames are kept
+//! read-only: a write fault on one gives a private copy to the faulting
+//! address space (copy-on-write), and writes through [`AddrSpace::write`]
+//! are refused.
+//!
+//! Like Linux, a content seen twice in the same round of a scan only makes
+//! the second page a shared frame, the other page is merged into it on the
+//! next round. Shared frames whose content hashes are equal but not their
+//! contents are not merged.
+//!
+//! The scanner runs in a background task with [`start`], or by rounds with
+//! [`scan_once`].
+
+use alloc::collections::BTreeMap;
+use core::time::Duration;
+
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::backend::{alloc_frame, dealloc_frame, Backend};
+use crate::AddrSpace;
+
+/// Statistics of the merging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KsmStats {
+    /// Number of shared frames.
+    pub pages_shared: usize,
+    /// Number of pages mapped to the shared frames, saved ones are
+    /// `pages_sharing - pages_shared`.
+    pub pages_sharing: usize,
+    /// Number of pages unshared by write faults.
+    pub cow_faults: u64,
+    /// Number of rounds over all registered address spaces.
+    pub full_scans: u64,
+}
+
+struct SharedFrame {
+    hash: u64,
+    mappings: usize,
+}
+
+struct Ksm {
+    /// Shared frames, by content hash.
+    stable: BTreeMap<u64, PhysAddr>,
+    frames: BTreeMap<PhysAddr, SharedFrame>,
+    /// Frames seen in the current round, by content hash.
+    unstable: BTreeMap<u64, PhysAddr>,
+    cow_faults: u64,
+    full_scans: u64,
+}
+
+static KSM: SpinNoIrq<Ksm> = SpinNoIrq::new(Ksm {
+    stable: BTreeMap::new(),
+    frames: BTreeMap::new(),
+    unstable: BTreeMap::new(),
+    cow_faults: 0,
+    full_scans: 0,
+});
+
+fn page_bytes(frame: PhysAddr) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), PAGE_SIZE_4K) }
+}
+
+/// FNV-1a over the words of the page.
+fn page_hash(frame: PhysAddr) -> u64 {
+    page_bytes(frame)
+        .chunks_exact(8)
+        .fold(0xcbf2_9ce4_8422_2325, |h, w| {
+            (h ^ u64::from_ne_bytes(w.try_into().unwrap())).wrapping_mul(0x100_0000_01b3)
+        })
+}
+
+/// Returns the statistics of the merging.
+pub fn stats() -> KsmStats {
+    let ksm = KSM.lock();
+    KsmStats {
+        pages_shared: ksm.frames.len(),
+        pages_sharing: ksm.frames.values().map(|f| f.mappings).sum(),
+        cow_faults: ksm.cow_faults,
+        full_scans: ksm.full_scans,
+    }
+}
+
+/// Scans all registered address spaces once, returns the number of pages
+/// merged.
+pub fn scan_once() -> usize {
+    let merged = crate::registry::aspaces()
+        .iter()
+        .map(|a| scan(&mut a.lock()))
+        .sum();
+    let mut ksm = KSM.lock();
+    ksm.unstable.clear();
+    ksm.full_scans += 1;
+    merged
+}
+
+/// Starts the background scanner, scanning all registered address spaces
+/// every `interval`.
+pub fn start(interval: Duration) {
+    axtask::spawn(move || loop {
+        let merged = scan_once();
+        if merged > 0 {
+            debug!("KSM: {} pages merged, {:?}", merged, stats());
+        }
+        axtask::sleep(interval);
+    });
+}
+
+/// Scans the address space, returns the number of pages merged.
+pub fn scan(aspace: &mut AddrSpace) -> usize {
+    let (areas, pt) = aspace.parts_mut();
+    let mut merged = 0;
+    for area in areas.iter() {
+        if !matches!(area.backend(), Backend::Alloc { .. }) {
+            continue;
+        }
+        for vaddr in PageIter4K::new(area.start(), area.end()).unwrap() {
+            let Ok((paddr, flags, PageSize::Size4K)) = pt.query(vaddr) else {
+                continue;
+            };
+            if flags.contains(MappingFlags::READ) && !flags.contains(MappingFlags::WRITE) {
+                merged += scan_page(pt, vaddr, paddr, flags) as usize;
+            }
+        }
+    }
+    merged
+}
+
+/// Merges the page at `vaddr`, mapped to `paddr`, returns whether it was
+/// mapped to a shared frame.
+fn scan_page(pt: &mut PageTable, vaddr: VirtAddr, paddr: PhysAddr, flags: MappingFlags) -> bool {
+    let mut ksm = KSM.lock();
+    if ksm.frames.contains_key(&paddr) {
+        return false;
+    }
+    let hash = page_hash(paddr);
+    if let Some(&frame) = ksm.stable.get(&hash) {
+        if page_bytes(frame) != page_bytes(paddr) {
+            return false;
+        }
+        if pt
+            .remap(vaddr, frame, flags)
+            .map(|(_, tlb)| tlb.flush())
+            .is_err()
+        {
+            return false;
+        }
+        ksm.frames.get_mut(&frame).unwrap().mappings += 1;
+        drop(ksm);
+        dealloc_frame(paddr);
+        return true;
+    }
+    match ksm.unstable.get(&hash) {
+        // The other frame may have been freed since, it is only compared.
+        Some(&other) if other != paddr && page_bytes(other) == page_bytes(paddr) => {
+            ksm.unstable.remove(&hash);
+            ksm.stable.insert(hash, paddr);
+            ksm.frames.insert(paddr, SharedFrame { hash, mappings: 1 });
+        }
+        _ => {
+            ksm.unstable.insert(hash, paddr);
+        }
+    }
+    false
+}
+
+/// Whether `frame` is a shared frame.
+#[cfg(feature = "compaction")]
+pub(crate) fn is_shared(frame: PhysAddr) -> bool {
+    KSM.lock().frames.contains_key(&frame)
+}
+
+/// Drops a mapping of `frame`, returns whether it is no longer used and must
+/// be freed.
+pub(crate) fn release(frame: PhysAddr) -> bool {
+    let mut ksm = KSM.lock();
+    let Some(shared) = ksm.frames.get_mut(&frame) else {
+        return true;
+    };
+    shared.mappings -= 1;
+    if shared.mappings > 0 {
+        return false;
+    }
+    let hash = shared.hash;
+    ksm.frames.remove(&frame);
+    ksm.stable.remove(&hash);
+    true
+}
+
+/// Handles a write fault at `vaddr`, returns `true` if it was on a shared
+/// frame, now replaced by a private one mapped with `flags`.
+pub(crate) fn handle_write_fault(pt: &mut PageTable, vaddr: VirtAddr, flags: MappingFlags) -> bool {
+    let Ok((frame, _, PageSize::Size4K)) = pt.query(vaddr.align_down_4k()) else {
+        return false;
+    };
+    let mut ksm = KSM.lock();
+    let Some(shared) = ksm.frames.get_mut(&frame) else {
+        return false;
+    };
+    let private = if shared.mappings == 1 {
+        // The last mapping keeps the frame.
+        let hash = shared.hash;
+        ksm.frames.remove(&frame);
+        ksm.stable.remove(&hash);
+        frame
+    } else {
+        let Some(copy) = alloc_frame(false) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(frame).as_ptr(),
+                phys_to_virt(copy).as_mut_ptr(),
+                PAGE_SIZE_4K,
+            )
+        };
+        shared.mappings -= 1;
+        copy
+    };
+    ksm.cow_faults += 1;
+    drop(ksm);
+    #[cfg(feature = "compaction")]
+    crate::compact::rmap_insert(private, pt.root_paddr(), vaddr.align_down_4k());
+    pt.remap(vaddr, private, flags)
+        .map(|(_, tlb)| tlb.flush())
+        .is_ok()
+}
+
+/// Removes the write permission of the shared frames in the range, after its
+/// flags were changed.
+pub(crate) fn write_protect(pt: &mut PageTable, start: VirtAddr, size: usize) {
+    let ksm = KSM.lock();
+    let end = (start + size).align_up_4k();
+    for vaddr in PageIter4K::new(start.align_down_4k(), end).unwrap() {
+        if let Ok((frame, flags, PageSize::Size4K)) = pt.query(vaddr) {
+            if flags.contains(MappingFlags::WRITE) && ksm.frames.contains_key(&frame) {
+                if let Ok((_, tlb)) = pt.protect(vaddr, flags - MappingFlags::WRITE) {
+                    tlb.flush();
+                }
+            }
+        }
+    }
+}
+
+/// Whether a page of the range is mapped to a shared frame.
+pub(crate) fn range_shared(pt: &PageTable, start: VirtAddr, size: usize) -> bool {
+    let ksm = KSM.lock();
+    let end = (start + size).align_up_4k();
+    PageIter4K::new(start.align_down_4k(), end)
+        .unwrap()
+        .any(|vaddr| {
+            pt.query(vaddr)
+                .is_ok_and(|(frame, ..)| ksm.frames.contains_key(&frame))
+        })
+}
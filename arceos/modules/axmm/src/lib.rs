@@ -1,4 +1,19 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
+//!
+//! # Cargo Features
+//!
+//! - `ksm`: Merge the identical read-only pages of address spaces, see
+//!   [`ksm`].
+//! - `compaction`: Migrate the pages of address spaces when contiguous pages
+//!   cannot be allocated, see [`compact`].
+//! - `maps`: List the memory maps of the address spaces registered by
+//!   [`register_process_aspace`], for `/proc/[pid]/maps`.
+//! - `ring`: Shared-memory rings, single-producer single-consumer channels
+//!   mapped in several address spaces, see [`ring`].
+//! - `dirty-log`: Log the pages written in a range of an address space, for
+//!   the live migration of the guests of a hypervisor, see [`dirty`].
+//! - `idle-track`: Track the idle pages of the address spaces, to estimate
+//!   their working sets, see [`idle`].
 
 #![no_std]
 
@@ -6,10 +21,43 @@
 extern crate log;
 extern crate alloc;
 
+mod aslr;
 mod aspace;
 mod backend;
-
-pub use self::aspace::AddrSpace;
+mod maps;
+
+#[cfg(feature = "compaction")]
+pub mod compact;
+#[cfg(feature = "dirty-log")]
+pub mod dirty;
+#[cfg(feature = "idle-track")]
+pub mod idle;
+#[cfg(feature = "ksm")]
+pub mod ksm;
+#[cfg(any(
+    feature = "ksm",
+    feature = "compaction",
+    feature = "maps",
+    feature = "idle-track"
+))]
+mod registry;
+#[cfg(feature = "ring")]
+pub mod ring;
+
+pub use self::aslr::{aslr_enabled, set_aslr_enabled, UserLayout};
+pub use self::aspace::{copy_between, AddrSpace};
+#[cfg(feature = "maps")]
+pub use self::maps::{proc_maps, proc_smaps, process_memory_map};
+pub use self::maps::{AreaBacking, AreaInfo};
+#[cfg(feature = "maps")]
+pub use self::registry::process_ids;
+#[cfg(any(
+    feature = "ksm",
+    feature = "compaction",
+    feature = "maps",
+    feature = "idle-track"
+))]
+pub use self::registry::{register_aspace, register_process_aspace};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -84,6 +132,8 @@ pub fn init_memory_management() {
     debug!("kernel address space init OK: {:#x?}", kernel_aspace);
     KERNEL_ASPACE.init_once(SpinNoIrq::new(kernel_aspace));
     axhal::paging::set_kernel_page_table_root(kernel_page_table_root());
+    #[cfg(feature = "compaction")]
+    compact::init();
 }
 
 /// Initializes kernel paging for secondary CPUs.
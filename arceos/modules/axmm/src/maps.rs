@@ -0,0 +1,164 @@
+//! Memory map introspection: the areas of the address spaces with their
+//! permissions, backends and resident sizes, listed in `/proc/[pid]/maps`
+//! and `/proc/[pid]/smaps` for the processes registered by
+//! [`register_process_aspace`](crate::register_process_aspace).
+
+#[cfg(feature = "maps")]
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "maps")]
+use core::fmt::Write;
+
+use axhal::paging::{MappingFlags, PageTable};
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::backend::Backend;
+use crate::AddrSpace;
+
+/// The object backing an area of an address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaBacking {
+    /// Mapped linearly to the physical memory starting at `paddr`.
+    Linear {
+        /// The physical address of the start of the area.
+        paddr: PhysAddr,
+    },
+    /// Anonymous memory from the global allocator, allocated when the area is
+    /// mapped if `populate`, or on the page faults otherwise.
+    Anonymous {
+        /// Whether the frames are allocated when the area is mapped.
+        populate: bool,
+    },
+}
+
+/// An area of an address space, as listed in `/proc/[pid]/maps`.
+#[derive(Debug, Clone, Copy)]
+pub struct AreaInfo {
+    /// The start address of the area.
+    pub start: VirtAddr,
+    /// The end address of the area, exclusive.
+    pub end: VirtAddr,
+    /// The permissions of the area.
+    pub flags: MappingFlags,
+    /// The object backing the area.
+    pub backing: AreaBacking,
+    /// The size of the area mapped to physical frames, in bytes.
+    pub rss: usize,
+}
+
+impl AreaInfo {
+    /// Returns the size of the area in bytes.
+    pub fn size(&self) -> usize {
+        self.end.as_usize() - self.start.as_usize()
+    }
+}
+
+impl fmt::Display for AreaInfo {
+    /// Formats the area as a line of `/proc/[pid]/maps`, without the line
+    /// break.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let perm = |flag, c| if self.flags.contains(flag) { c } else { '-' };
+        let (offset, name) = match self.backing {
+            AreaBacking::Linear { paddr } => (paddr.as_usize(), "[linear]"),
+            AreaBacking::Anonymous { .. } => (0, ""),
+        };
+        write!(
+            f,
+            "{:08x}-{:08x} {}{}{}p {:08x} 00:00 0",
+            self.start,
+            self.end,
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            offset
+        )?;
+        if !name.is_empty() {
+            write!(f, "{:>20}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl AddrSpace {
+    /// Returns the areas of the address space, ordered by their addresses.
+    ///
+    /// The linear mappings of [`AddrSpace::map_linear`] are not areas, so they
+    /// are not listed.
+    pub fn memory_map(&self) -> Vec<AreaInfo> {
+        let (areas, pt) = self.parts();
+        areas
+            .iter()
+            .map(|area| AreaInfo {
+                start: area.start(),
+                end: area.end(),
+                flags: area.flags(),
+                backing: match *area.backend() {
+                    Backend::Linear { pa_va_offset } => AreaBacking::Linear {
+                        paddr: PhysAddr::from(area.start().as_usize().wrapping_sub(pa_va_offset)),
+                    },
+                    Backend::Alloc { populate } => AreaBacking::Anonymous { populate },
+                },
+                rss: resident_size(pt, area.start(), area.end()),
+            })
+            .collect()
+    }
+}
+
+/// Returns the size of the range mapped to physical frames in the page table.
+/// The lazy mappings not faulted in yet have no flags.
+fn resident_size(pt: &PageTable, start: VirtAddr, end: VirtAddr) -> usize {
+    let mut rss = 0;
+    let mut vaddr = start;
+    while vaddr < end {
+        match pt.query(vaddr) {
+            Ok((_, flags, page_size)) => {
+                let page_size: usize = page_size.into();
+                let next = vaddr.align_down(page_size) + page_size;
+                if !flags.is_empty() {
+                    rss += next.min(end).as_usize() - vaddr.as_usize();
+                }
+                vaddr = next;
+            }
+            Err(_) => vaddr += PAGE_SIZE_4K,
+        }
+    }
+    rss
+}
+
+/// Returns the memory map of the process `pid`, see [`AddrSpace::memory_map`].
+#[cfg(feature = "maps")]
+pub fn process_memory_map(pid: u64) -> Option<Vec<AreaInfo>> {
+    Some(crate::registry::process_aspace(pid)?.lock().memory_map())
+}
+
+/// Generates `/proc/[pid]/maps`: a line per area of the process `pid`.
+#[cfg(feature = "maps")]
+pub fn proc_maps(pid: u64) -> Option<String> {
+    let mut out = String::new();
+    for area in process_memory_map(pid)? {
+        writeln!(out, "{}", area).ok();
+    }
+    Some(out)
+}
+
+/// Generates `/proc/[pid]/smaps`: the lines of `/proc/[pid]/maps`, each
+/// followed by the size and the resident size of the area, and its size idle
+/// at the last sample with the `idle-track` feature.
+#[cfg(feature = "maps")]
+pub fn proc_smaps(pid: u64) -> Option<String> {
+    let aspace = crate::registry::process_aspace(pid)?;
+    let aspace = aspace.lock();
+    let mut out = String::new();
+    for area in aspace.memory_map() {
+        writeln!(out, "{}", area).ok();
+        writeln!(out, "Size:           {:>8} kB", area.size() / 1024).ok();
+        writeln!(out, "Rss:            {:>8} kB", area.rss / 1024).ok();
+        #[cfg(feature = "idle-track")]
+        {
+            let idle = aspace.idle_size(area.start, area.end);
+            writeln!(out, "Idle:           {:>8} kB", idle / 1024).ok();
+        }
+    }
+    Some(out)
+}
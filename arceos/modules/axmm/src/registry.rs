@@ -0,0 +1,87 @@
+//! Address spaces known to the memory management, to work on their pages
+//! outside of their owners.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axsync::Mutex;
+use kspin::SpinNoIrq;
+use memory_addr::PhysAddr;
+
+use crate::AddrSpace;
+
+struct Registered {
+    /// The root of the page table.
+    #[cfg_attr(not(feature = "compaction"), allow(dead_code))]
+    root: PhysAddr,
+    /// The ID of the process owning the address space.
+    pid: Option<u64>,
+    aspace: Weak<Mutex<AddrSpace>>,
+}
+
+/// Registered address spaces.
+static ASPACES: SpinNoIrq<Vec<Registered>> = SpinNoIrq::new(Vec::new());
+
+fn register(pid: Option<u64>, aspace: &Arc<Mutex<AddrSpace>>) {
+    let root = aspace.lock().page_table_root();
+    let mut aspaces = ASPACES.lock();
+    aspaces.retain(|r| r.aspace.strong_count() > 0);
+    aspaces.push(Registered {
+        root,
+        pid,
+        aspace: Arc::downgrade(aspace),
+    });
+}
+
+/// Registers an address space, until it is dropped, so that its pages can be
+/// merged (with the `ksm` feature), migrated (with the `compaction`
+/// feature) or sampled for idle pages (with the `idle-track` feature).
+pub fn register_aspace(aspace: &Arc<Mutex<AddrSpace>>) {
+    register(None, aspace);
+}
+
+/// Registers the address space of the process `pid`, until it is dropped,
+/// like [`register_aspace`]. Its memory map is listed in `/proc/[pid]/maps`
+/// (with the `maps` feature).
+pub fn register_process_aspace(pid: u64, aspace: &Arc<Mutex<AddrSpace>>) {
+    register(Some(pid), aspace);
+}
+
+/// Returns the registered address spaces still alive.
+#[cfg(any(feature = "ksm", feature = "idle-track"))]
+pub(crate) fn aspaces() -> Vec<Arc<Mutex<AddrSpace>>> {
+    let mut aspaces = ASPACES.lock();
+    aspaces.retain(|r| r.aspace.strong_count() > 0);
+    aspaces.iter().filter_map(|r| r.aspace.upgrade()).collect()
+}
+
+/// Returns the registered address space whose page table root is `root`.
+#[cfg(feature = "compaction")]
+pub(crate) fn find(root: PhysAddr) -> Option<Arc<Mutex<AddrSpace>>> {
+    let aspaces = ASPACES.lock();
+    aspaces.iter().find(|r| r.root == root)?.aspace.upgrade()
+}
+
+/// Returns the IDs of the processes whose address spaces are registered and
+/// still alive, in ascending order.
+#[cfg(feature = "maps")]
+pub fn process_ids() -> Vec<u64> {
+    let mut aspaces = ASPACES.lock();
+    aspaces.retain(|r| r.aspace.strong_count() > 0);
+    let mut pids: Vec<u64> = aspaces.iter().filter_map(|r| r.pid).collect();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+/// Returns the registered address space of the process `pid`.
+#[cfg(feature = "maps")]
+pub(crate) fn process_aspace(pid: u64) -> Option<Arc<Mutex<AddrSpace>>> {
+    let aspaces = ASPACES.lock();
+    aspaces
+        .iter()
+        .rev()
+        .find(|r| r.pid == Some(pid))?
+        .aspace
+        .upgrade()
+}
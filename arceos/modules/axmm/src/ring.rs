@@ -0,0 +1,348 @@
+//! Shared-memory rings, a single-producer single-consumer IPC channel.
+//!
+//! A [`SharedRing`] holds its messages in frames mapped both in the kernel
+//! and in the address spaces it is mapped into by [`SharedRing::map_into`],
+//! so that two processes, or a process and a kernel service, exchange
+//! messages without a syscall per message. The kernel only mediates the
+//! setup and the sleeps, as a futex: a side finding the ring empty (or full)
+//! sets its waiting flag, checks the ring again, and sleeps by
+//! [`SharedRing::wait`] while the counter of the other side keeps the value
+//! it saw. The other side calls [`SharedRing::wake`] after moving its counter
+//! only if the flag is set.
+//!
+//! The layout shared with the user space is the [`RingHeader`] page followed
+//! by the data, a power of two number of bytes. The messages are written
+//! back to back, each as its length in a native-endian `u32` followed by the
+//! payload padded to 4 bytes, wrapping around the end of the data. `head`
+//! and `tail` count the bytes written and read, wrapping at `u32::MAX`.
+//!
+//! The rings are named by an ID, to be mapped by the peer: [`create`] makes a
+//! ring and [`get`] looks it up. A ring lives as long as it is referenced,
+//! e.g. by the processes it is mapped into, which must keep it until their
+//! address space is dropped.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axalloc::global_allocator;
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::paging::MappingFlags;
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::AddrSpace;
+
+/// The magic of [`RingHeader::magic`], "RING".
+pub const RING_MAGIC: u32 = u32::from_le_bytes(*b"RING");
+
+/// The largest data size of a ring.
+pub const MAX_RING_CAPACITY: usize = 1 << 30;
+
+/// The header of a ring, in the first page of its mapping.
+///
+/// The fields written by each side are in their own cache line: `head` and
+/// `producer_waiting` at offset 64 by the producer, `tail` and
+/// `consumer_waiting` at offset 128 by the consumer.
+#[repr(C)]
+pub struct RingHeader {
+    /// [`RING_MAGIC`].
+    pub magic: u32,
+    /// The bytes of the data, a power of two.
+    pub capacity: u32,
+    _pad0: [u32; 14],
+    /// The bytes written by the producer.
+    pub head: AtomicU32,
+    /// Non-zero while the producer waits for space.
+    pub producer_waiting: AtomicU32,
+    _pad1: [u32; 14],
+    /// The bytes read by the consumer.
+    pub tail: AtomicU32,
+    /// Non-zero while the consumer waits for data.
+    pub consumer_waiting: AtomicU32,
+    _pad2: [u32; 14],
+}
+
+/// What a side of a ring waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingEvent {
+    /// The consumer waits for data: `head` to move.
+    Data,
+    /// The producer waits for space: `tail` to move.
+    Space,
+}
+
+impl TryFrom<usize> for RingEvent {
+    type Error = AxError;
+
+    fn try_from(value: usize) -> AxResult<Self> {
+        match value {
+            0 => Ok(Self::Data),
+            1 => Ok(Self::Space),
+            _ => ax_err!(InvalidInput, "unknown ring event"),
+        }
+    }
+}
+
+/// A ring shared by a producer and a consumer, see the [module
+/// documentation](self).
+pub struct SharedRing {
+    id: u32,
+    /// The header page, followed by the data pages.
+    paddr: PhysAddr,
+    capacity: usize,
+    data_wq: WaitQueue,
+    space_wq: WaitQueue,
+}
+
+/// The rings by ID, kept until they are dropped.
+static RINGS: SpinNoIrq<BTreeMap<u32, Weak<SharedRing>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Creates a ring with `capacity` bytes of data, a power of two of at least a
+/// page, and registers it under a new ID.
+pub fn create(capacity: usize) -> AxResult<Arc<SharedRing>> {
+    if !capacity.is_power_of_two() || !(PAGE_SIZE_4K..=MAX_RING_CAPACITY).contains(&capacity) {
+        return ax_err!(InvalidInput, "invalid ring capacity");
+    }
+    let mut ring = SharedRing::new(capacity)?;
+    let mut rings = RINGS.lock();
+    rings.retain(|_, ring| ring.strong_count() > 0);
+    ring.id = (1..=u32::MAX)
+        .find(|id| !rings.contains_key(id))
+        .ok_or(AxError::NoMemory)?;
+    let ring = Arc::new(ring);
+    rings.insert(ring.id, Arc::downgrade(&ring));
+    Ok(ring)
+}
+
+/// Returns the ring of ID `id`, if it is still alive.
+pub fn get(id: u32) -> Option<Arc<SharedRing>> {
+    RINGS.lock().get(&id)?.upgrade()
+}
+
+impl SharedRing {
+    fn new(capacity: usize) -> AxResult<Self> {
+        let pages = 1 + capacity / PAGE_SIZE_4K;
+        let vaddr = global_allocator()
+            .alloc_pages(pages, PAGE_SIZE_4K)
+            .map_err(|_| AxError::NoMemory)?;
+        // SAFETY: the pages were just allocated for the ring.
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, pages * PAGE_SIZE_4K) };
+        let ring = Self {
+            id: 0,
+            paddr: virt_to_phys(VirtAddr::from(vaddr)),
+            capacity,
+            data_wq: WaitQueue::new(),
+            space_wq: WaitQueue::new(),
+        };
+        // SAFETY: the header page is zeroed and only ours yet.
+        unsafe {
+            let header = phys_to_virt(ring.paddr).as_mut_ptr() as *mut RingHeader;
+            (*header).magic = RING_MAGIC;
+            (*header).capacity = capacity as u32;
+        }
+        Ok(ring)
+    }
+
+    /// The ID of the ring, to look it up by [`get`].
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The bytes of the data.
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The bytes of the mapping: the header page and the data.
+    pub const fn size(&self) -> usize {
+        PAGE_SIZE_4K + self.capacity
+    }
+
+    /// The header, shared with the address spaces the ring is mapped into.
+    pub fn header(&self) -> &RingHeader {
+        // SAFETY: the header page lives as long as the ring, and the fields
+        // written by the peers are atomics.
+        unsafe { &*(phys_to_virt(self.paddr).as_ptr() as *const RingHeader) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        (phys_to_virt(self.paddr) + PAGE_SIZE_4K).as_mut_ptr()
+    }
+
+    fn counter(&self, event: RingEvent) -> &AtomicU32 {
+        match event {
+            RingEvent::Data => &self.header().head,
+            RingEvent::Space => &self.header().tail,
+        }
+    }
+
+    fn wait_queue(&self, event: RingEvent) -> &WaitQueue {
+        match event {
+            RingEvent::Data => &self.data_wq,
+            RingEvent::Space => &self.space_wq,
+        }
+    }
+
+    /// Maps the ring at `start` in `aspace`, with `flags`.
+    ///
+    /// The mapping is an area of `aspace`, backed by the frames of the ring,
+    /// which are not freed when it is unmapped: the owner of `aspace` must
+    /// keep the ring as long as it is mapped.
+    pub fn map_into(
+        &self,
+        aspace: &mut AddrSpace,
+        start: VirtAddr,
+        flags: MappingFlags,
+    ) -> AxResult {
+        aspace.map_shared(start, self.paddr, self.size(), flags)
+    }
+
+    /// Sleeps while the counter of `event` is `expected`, `head` for
+    /// [`RingEvent::Data`] and `tail` for [`RingEvent::Space`], until it is
+    /// woken by [`wake`](Self::wake). Returns at once if it differs.
+    pub fn wait(&self, event: RingEvent, expected: u32) {
+        let counter = self.counter(event);
+        self.wait_queue(event)
+            .wait_until(|| counter.load(Ordering::SeqCst) != expected);
+    }
+
+    /// Wakes the side waiting for `event`.
+    pub fn wake(&self, event: RingEvent) {
+        self.wait_queue(event).notify_all(true);
+    }
+
+    /// Copies `src` into the data at the byte index `pos`, wrapping around.
+    fn write_data(&self, pos: u32, src: &[u8]) {
+        let offset = pos as usize & (self.capacity - 1);
+        let first = src.len().min(self.capacity - offset);
+        // SAFETY: both parts are in the data, of `capacity` bytes.
+        unsafe {
+            let data = self.data();
+            core::ptr::copy_nonoverlapping(src.as_ptr(), data.add(offset), first);
+            core::ptr::copy_nonoverlapping(src[first..].as_ptr(), data, src.len() - first);
+        }
+    }
+
+    /// Copies the data at the byte index `pos` into `dst`, wrapping around.
+    fn read_data(&self, pos: u32, dst: &mut [u8]) {
+        let offset = pos as usize & (self.capacity - 1);
+        let first = dst.len().min(self.capacity - offset);
+        // SAFETY: both parts are in the data, of `capacity` bytes.
+        unsafe {
+            let data = self.data();
+            core::ptr::copy_nonoverlapping(data.add(offset), dst.as_mut_ptr(), first);
+            let rest = dst.len() - first;
+            core::ptr::copy_nonoverlapping(data, dst[first..].as_mut_ptr(), rest);
+        }
+    }
+
+    /// Sends `msg` as the producer, or fails with `WouldBlock` if the ring
+    /// is full.
+    pub fn try_send(&self, msg: &[u8]) -> AxResult {
+        let need = 4 + msg.len().next_multiple_of(4);
+        if need > self.capacity {
+            return ax_err!(InvalidInput, "message larger than the ring");
+        }
+        let header = self.header();
+        let head = header.head.load(Ordering::Relaxed);
+        let used = head.wrapping_sub(header.tail.load(Ordering::Acquire)) as usize;
+        if self.capacity - used.min(self.capacity) < need {
+            return Err(AxError::WouldBlock);
+        }
+        self.write_data(head, &(msg.len() as u32).to_ne_bytes());
+        self.write_data(head.wrapping_add(4), msg);
+        header
+            .head
+            .store(head.wrapping_add(need as u32), Ordering::SeqCst);
+        if header.consumer_waiting.load(Ordering::SeqCst) != 0 {
+            self.wake(RingEvent::Data);
+        }
+        Ok(())
+    }
+
+    /// Sends `msg` as the producer, sleeping while the ring is full.
+    pub fn send(&self, msg: &[u8]) -> AxResult {
+        let header = self.header();
+        loop {
+            match self.try_send(msg) {
+                Err(AxError::WouldBlock) => {}
+                res => return res,
+            }
+            header.producer_waiting.store(1, Ordering::SeqCst);
+            let tail = header.tail.load(Ordering::SeqCst);
+            let res = self.try_send(msg);
+            if res == Err(AxError::WouldBlock) {
+                self.wait(RingEvent::Space, tail);
+            }
+            header.producer_waiting.store(0, Ordering::SeqCst);
+            if res != Err(AxError::WouldBlock) {
+                return res;
+            }
+        }
+    }
+
+    /// Receives a message into `buf` as the consumer, returning its length,
+    /// or fails with `WouldBlock` if the ring is empty.
+    ///
+    /// A message larger than `buf` is left in the ring, and fails with
+    /// `InvalidInput`. A ring whose header was corrupted by the peer fails
+    /// with `BadState`.
+    pub fn try_recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let header = self.header();
+        let tail = header.tail.load(Ordering::Relaxed);
+        let used = header.head.load(Ordering::Acquire).wrapping_sub(tail) as usize;
+        if used == 0 {
+            return Err(AxError::WouldBlock);
+        }
+        let mut len = [0; 4];
+        self.read_data(tail, &mut len);
+        let len = u32::from_ne_bytes(len) as usize;
+        let need = 4 + len.next_multiple_of(4);
+        if used > self.capacity || need > used {
+            return ax_err!(BadState, "corrupted ring");
+        }
+        if len > buf.len() {
+            return ax_err!(InvalidInput, "message larger than the buffer");
+        }
+        self.read_data(tail.wrapping_add(4), &mut buf[..len]);
+        header
+            .tail
+            .store(tail.wrapping_add(need as u32), Ordering::SeqCst);
+        if header.producer_waiting.load(Ordering::SeqCst) != 0 {
+            self.wake(RingEvent::Space);
+        }
+        Ok(len)
+    }
+
+    /// Receives a message into `buf` as the consumer, sleeping while the ring
+    /// is empty.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let header = self.header();
+        loop {
+            match self.try_recv(buf) {
+                Err(AxError::WouldBlock) => {}
+                res => return res,
+            }
+            header.consumer_waiting.store(1, Ordering::SeqCst);
+            let head = header.head.load(Ordering::SeqCst);
+            let res = self.try_recv(buf);
+            if res == Err(AxError::WouldBlock) {
+                self.wait(RingEvent::Data, head);
+            }
+            header.consumer_waiting.store(0, Ordering::SeqCst);
+            if res != Err(AxError::WouldBlock) {
+                return res;
+            }
+        }
+    }
+}
+
+impl Drop for SharedRing {
+    fn drop(&mut self) {
+        let pages = 1 + self.capacity / PAGE_SIZE_4K;
+        global_allocator().dealloc_pages(phys_to_virt(self.paddr).as_usize(), pages);
+    }
+}
@@ -0,0 +1,232 @@
+//! Software bridge with MAC learning.
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::ptr::NonNull;
+
+use axdriver::prelude::*;
+use axdriver_net::{EthernetAddress, NetBufPtr};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+
+/// How long a learned address is kept without seeing it again, as Linux.
+const AGEING_TIME: u64 = 300 * NANOS_PER_SEC;
+/// The maximum number of learned addresses.
+const MAX_FDB_ENTRIES: usize = 1024;
+
+const ETH_HEADER_LEN: usize = 14;
+
+type MacAddr = [u8; 6];
+
+const fn is_multicast(mac: &MacAddr) -> bool {
+    mac[0] & 1 != 0
+}
+
+/// A learned address.
+struct FdbEntry {
+    mac: MacAddr,
+    port: usize,
+    last_seen: u64,
+}
+
+/// A software bridge joining the NICs as its ports.
+///
+/// It learns on which port each source address is, and forwards the frames
+/// received on a port to the port of their destination, or floods them to
+/// all the other ports if the destination is unknown, a broadcast or a
+/// multicast. The network stack sits on the bridge as one more port, with
+/// the address of the first NIC: it receives the frames to that address, the
+/// broadcasts and the multicasts, and its frames are forwarded as the others.
+///
+/// The frames are forwarded when the network stack is polled (see
+/// [`poll_interfaces`](crate::poll_interfaces)), a forwarded frame is copied
+/// to the buffers of the destination ports.
+pub struct Bridge {
+    ports: Vec<AxNetDevice>,
+    mac: MacAddr,
+    fdb: Vec<FdbEntry>,
+    /// The received buffers handed to the network stack, with their ports.
+    rx_bufs: Vec<(usize, usize)>,
+    /// The port to receive from first next time.
+    next_rx: usize,
+}
+
+impl Bridge {
+    /// Creates a bridge of the NICs `ports`, it must not be empty.
+    pub fn new(ports: Vec<AxNetDevice>) -> Self {
+        assert!(!ports.is_empty(), "No NIC device found!");
+        let mac = ports[0].mac_address().0;
+        Self {
+            ports,
+            mac,
+            fdb: Vec::new(),
+            rx_bufs: Vec::new(),
+            next_rx: 0,
+        }
+    }
+
+    /// Records that `mac` is on the port `port`.
+    fn learn(&mut self, mac: MacAddr, port: usize, now: u64) {
+        if is_multicast(&mac) {
+            return;
+        }
+        if let Some(entry) = self.fdb.iter_mut().find(|e| e.mac == mac) {
+            if entry.port != port {
+                debug!("bridge: {:02x?} moved to port {}", mac, port);
+            }
+            entry.port = port;
+            entry.last_seen = now;
+            return;
+        }
+        self.fdb.retain(|e| now - e.last_seen < AGEING_TIME);
+        if self.fdb.len() >= MAX_FDB_ENTRIES {
+            let oldest = (0..self.fdb.len())
+                .min_by_key(|&i| self.fdb[i].last_seen)
+                .unwrap();
+            self.fdb.swap_remove(oldest);
+        }
+        self.fdb.push(FdbEntry {
+            mac,
+            port,
+            last_seen: now,
+        });
+    }
+
+    /// Returns the port of the unicast address `mac`, if learned.
+    fn lookup(&self, mac: &MacAddr, now: u64) -> Option<usize> {
+        self.fdb
+            .iter()
+            .find(|e| e.mac == *mac && now - e.last_seen < AGEING_TIME)
+            .map(|e| e.port)
+    }
+
+    /// Sends `frame` to the port of its destination, or floods it to all the
+    /// ports but `from`.
+    fn forward(&mut self, frame: &[u8], from: Option<usize>, now: u64) {
+        let dst: MacAddr = frame[..6].try_into().unwrap();
+        let to = if is_multicast(&dst) {
+            None
+        } else {
+            self.lookup(&dst, now)
+        };
+        if to.is_some() && to == from {
+            return; // filtered, the destination is on the same segment
+        }
+        for (i, port) in self.ports.iter_mut().enumerate() {
+            if Some(i) == from || to.is_some_and(|to| to != i) || !port.can_transmit() {
+                continue;
+            }
+            let res = port.alloc_tx_buffer(frame.len()).and_then(|mut tx_buf| {
+                tx_buf.packet_mut().copy_from_slice(frame);
+                port.transmit(tx_buf)
+            });
+            if let Err(e) = res {
+                warn!("bridge: forwarding to port {} failed: {:?}", i, e);
+            }
+        }
+    }
+}
+
+impl BaseDriverOps for Bridge {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+
+    fn device_name(&self) -> &str {
+        "bridge"
+    }
+}
+
+impl NetDriverOps for Bridge {
+    fn mac_address(&self) -> EthernetAddress {
+        EthernetAddress(self.mac)
+    }
+
+    fn can_transmit(&self) -> bool {
+        self.ports.iter().any(|p| p.can_transmit())
+    }
+
+    fn can_receive(&self) -> bool {
+        self.ports.iter().any(|p| p.can_receive())
+    }
+
+    fn rx_queue_size(&self) -> usize {
+        self.ports[0].rx_queue_size()
+    }
+
+    fn tx_queue_size(&self) -> usize {
+        self.ports[0].tx_queue_size()
+    }
+
+    fn recycle_rx_buffer(&mut self, rx_buf: NetBufPtr) -> DevResult {
+        let raw_ptr = rx_buf.raw_ptr::<u8>() as usize;
+        let idx = self
+            .rx_bufs
+            .iter()
+            .position(|&(ptr, _)| ptr == raw_ptr)
+            .ok_or(DevError::InvalidParam)?;
+        let (_, port) = self.rx_bufs.swap_remove(idx);
+        self.ports[port].recycle_rx_buffer(rx_buf)
+    }
+
+    fn recycle_tx_buffers(&mut self) -> DevResult {
+        for port in self.ports.iter_mut() {
+            port.recycle_tx_buffers()?;
+        }
+        Ok(())
+    }
+
+    fn transmit(&mut self, tx_buf: NetBufPtr) -> DevResult {
+        // SAFETY: the buffer was allocated by `alloc_tx_buffer`.
+        let buf = unsafe {
+            Box::from_raw(core::ptr::slice_from_raw_parts_mut(
+                tx_buf.raw_ptr::<u8>(),
+                tx_buf.packet_len(),
+            ))
+        };
+        if buf.len() >= ETH_HEADER_LEN {
+            self.forward(&buf, None, monotonic_time_nanos());
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> DevResult<NetBufPtr> {
+        let num_ports = self.ports.len();
+        let mut idle_ports = 0;
+        while idle_ports < num_ports {
+            let i = self.next_rx;
+            self.next_rx = (i + 1) % num_ports;
+            let rx_buf = match self.ports[i].receive() {
+                Ok(rx_buf) => rx_buf,
+                Err(DevError::Again) => {
+                    idle_ports += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            idle_ports = 0;
+
+            let now = monotonic_time_nanos();
+            let frame = rx_buf.packet();
+            if frame.len() >= ETH_HEADER_LEN {
+                let dst: MacAddr = frame[..6].try_into().unwrap();
+                self.learn(frame[6..12].try_into().unwrap(), i, now);
+                if dst != self.mac {
+                    self.forward(frame, Some(i), now);
+                }
+                if dst == self.mac || is_multicast(&dst) {
+                    self.rx_bufs.push((rx_buf.raw_ptr::<u8>() as usize, i));
+                    return Ok(rx_buf);
+                }
+            }
+            self.ports[i].recycle_rx_buffer(rx_buf)?;
+        }
+        Err(DevError::Again)
+    }
+
+    fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr> {
+        // The destination port is known once the frame is written, it is
+        // copied to the buffers of the ports in `transmit`.
+        let buf = Box::into_raw(vec![0u8; size].into_boxed_slice()) as *mut u8;
+        let ptr = NonNull::new(buf).ok_or(DevError::NoMemory)?;
+        Ok(NetBufPtr::new(ptr, ptr, size))
+    }
+}
@@ -7,13 +7,29 @@
 //! # Organization
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
+//! - [`TcpInfo`]: RTT, retransmission and pacing metrics of a TCP connection.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
+//! - [`ZeroCopyUdpSocket`]: A UDP socket lending the RX buffers of the driver.
 //! - [`dns_query`]: Function for DNS query.
+//! - [`set_socket_mem_limit`]: The budget of the memory of the socket buffers.
+//! - [`PtpClient`]: A PTP slave clock disciplining the wall clock.
+//! - [`WgTunnel`]: A WireGuard tunnel to a peer, configured by [`WgConfig`].
+//! - [`XdpSocket`]: Raw frames through rings shared with the application,
+//!   bypassing the stack.
 //!
 //! # Cargo Features
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `vlan`: Put the network stack on the 802.1Q VLAN sub-interface given by
+//!   the `AX_VLAN` environment variable at build time.
+//! - `bridge`: Join all the NICs in a software bridge with MAC learning, the
+//!   network stack sits on the bridge.
+//! - `ptp`: Enable the PTP (IEEE 1588) slave clock ([`PtpClient`]).
+//! - `wireguard`: Enable the WireGuard tunnels ([`WgTunnel`]).
+//! - `xdp`: Enable the AF_XDP-style sockets ([`XdpSocket`]).
+//! - `ip-frag`: Fragment the IPv4 packets larger than the [`mtu`], and
+//!   reassemble the fragments received.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -24,6 +40,13 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(feature = "bridge")]
+mod bridge;
+#[cfg(feature = "vlan")]
+mod vlan;
+#[cfg(feature = "wireguard")]
+mod wireguard;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "smoltcp")] {
         mod smoltcp_impl;
@@ -31,18 +54,68 @@ cfg_if::cfg_if! {
     }
 }
 
+pub use self::net_impl::TcpInfo;
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
 pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{dns_query, mtu, poll_interfaces};
+pub use self::net_impl::{interface, interface_names, interfaces};
+pub use self::net_impl::{NetAttrFn, NetIfInfo, NetStats, SYS_CLASS_NET_ATTRS};
+pub use self::net_impl::{
+    set_lend_timeout, zero_copy_stats, RxPacket, ZeroCopyUdpSocket, MAX_LENT,
+};
+pub use self::net_impl::{set_socket_mem_limit, socket_mem_usage};
+#[cfg(feature = "ptp")]
+pub use self::net_impl::{PtpClient, PtpStatus};
+#[cfg(feature = "xdp")]
+pub use self::net_impl::{XdpConfig, XdpDesc, XdpFilter, XdpHeader, XdpRingHeader, XdpSocket};
+#[cfg(feature = "xdp")]
+pub use self::net_impl::{XdpStats, MAX_RING_SIZE, XDP_MAGIC};
+#[cfg(feature = "wireguard")]
+pub use self::wireguard::{WgConfig, WgTunnel};
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
+#[cfg(feature = "bridge")]
+type Port = bridge::Bridge;
+#[cfg(not(feature = "bridge"))]
+type Port = AxNetDevice;
+
+/// The device under the network stack.
+#[cfg(feature = "vlan")]
+type NetPort = vlan::Vlan;
+#[cfg(not(feature = "vlan"))]
+type NetPort = Port;
+
 /// Initializes the network subsystem by NIC devices.
 pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
     info!("Initialize network subsystem...");
 
-    let dev = net_devs.take_one().expect("No NIC device found!");
-    info!("  use NIC 0: {:?}", dev.device_name());
-    net_impl::init(dev);
+    #[cfg(not(feature = "bridge"))]
+    let (dev, max_mtu) = {
+        let dev = net_devs.take_one().expect("No NIC device found!");
+        info!("  use NIC 0: {:?}", dev.device_name());
+        let max_mtu = axdriver::net::max_mtu(dev.device_name());
+        (dev, max_mtu)
+    };
+    #[cfg(feature = "bridge")]
+    let (dev, max_mtu) = {
+        let mut ports = alloc::vec::Vec::new();
+        let mut max_mtu = usize::MAX;
+        while let Some(dev) = net_devs.take_one() {
+            info!("  bridge port {}: {:?}", ports.len(), dev.device_name());
+            max_mtu = max_mtu.min(axdriver::net::max_mtu(dev.device_name()));
+            ports.push(dev);
+        }
+        (bridge::Bridge::new(ports), max_mtu)
+    };
+    #[cfg(feature = "vlan")]
+    let dev = {
+        let vid = option_env!("AX_VLAN")
+            .and_then(|vid| vid.parse().ok())
+            .expect("invalid VLAN ID in AX_VLAN");
+        info!("  use VLAN {}", vid);
+        vlan::Vlan::new(dev, vid)
+    };
+    net_impl::init(dev, max_mtu);
 }
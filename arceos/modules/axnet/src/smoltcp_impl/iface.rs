@@ -0,0 +1,194 @@
+//! The information and the statistics of the network interfaces, read by the
+//! ethtool-like queries and the files of `/sys/class/net`.
+
+use alloc::{format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use smoltcp::wire::EthernetAddress;
+
+use super::{mtu, ETH0};
+
+/// The index of the interface, the only one.
+const IFINDEX: u32 = 1;
+
+/// The counters of the interface, since boot.
+struct Counters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    rx_errors: AtomicU64,
+    rx_dropped: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_errors: AtomicU64,
+}
+
+static COUNTERS: Counters = Counters {
+    rx_packets: AtomicU64::new(0),
+    rx_bytes: AtomicU64::new(0),
+    rx_errors: AtomicU64::new(0),
+    rx_dropped: AtomicU64::new(0),
+    tx_packets: AtomicU64::new(0),
+    tx_bytes: AtomicU64::new(0),
+    tx_errors: AtomicU64::new(0),
+};
+
+/// Counts a frame of `len` bytes received from the device, whatever takes it
+/// then.
+pub(super) fn count_rx(len: usize) {
+    COUNTERS.rx_packets.fetch_add(1, Ordering::Relaxed);
+    COUNTERS.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+}
+
+/// Counts a failed receive from the device.
+pub(super) fn count_rx_error() {
+    COUNTERS.rx_errors.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a frame received and dropped by the BPF hook.
+pub(super) fn count_rx_dropped() {
+    COUNTERS.rx_dropped.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a frame of `len` bytes given to the device.
+pub(super) fn count_tx(len: usize) {
+    COUNTERS.tx_packets.fetch_add(1, Ordering::Relaxed);
+    COUNTERS.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+}
+
+/// Counts a failed transmit to the device.
+pub(super) fn count_tx_error() {
+    COUNTERS.tx_errors.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The statistics of a network interface, since boot.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NetStats {
+    /// The frames received, including the ones dropped.
+    pub rx_packets: u64,
+    /// The bytes of the frames received.
+    pub rx_bytes: u64,
+    /// The receives failed.
+    pub rx_errors: u64,
+    /// The frames received and dropped by the BPF hook.
+    pub rx_dropped: u64,
+    /// The frames transmitted.
+    pub tx_packets: u64,
+    /// The bytes of the frames transmitted.
+    pub tx_bytes: u64,
+    /// The transmits failed.
+    pub tx_errors: u64,
+}
+
+impl NetStats {
+    /// Returns the counters with their names in Linux, e.g. `rx_bytes`.
+    pub fn counters(&self) -> [(&'static str, u64); 7] {
+        [
+            ("rx_packets", self.rx_packets),
+            ("rx_bytes", self.rx_bytes),
+            ("rx_errors", self.rx_errors),
+            ("rx_dropped", self.rx_dropped),
+            ("tx_packets", self.tx_packets),
+            ("tx_bytes", self.tx_bytes),
+            ("tx_errors", self.tx_errors),
+        ]
+    }
+}
+
+/// A network interface, up from its creation.
+#[derive(Debug, Clone)]
+pub struct NetIfInfo {
+    /// The name, e.g. `eth0`.
+    pub name: &'static str,
+    /// The index, from 1.
+    pub index: u32,
+    /// The MAC address.
+    pub mac: [u8; 6],
+    /// The MTU.
+    pub mtu: usize,
+    /// The link speed in Mb/s, `None` if it is unknown.
+    pub speed: Option<u32>,
+    /// The name of the device, e.g. `virtio-net`.
+    pub driver: String,
+    /// The statistics.
+    pub stats: NetStats,
+}
+
+/// Returns the network interfaces, none before the network is initialized.
+pub fn interfaces() -> Vec<NetIfInfo> {
+    let Some(eth0) = ETH0.get() else {
+        return Vec::new();
+    };
+    let c = &COUNTERS;
+    let stats = NetStats {
+        rx_packets: c.rx_packets.load(Ordering::Relaxed),
+        rx_bytes: c.rx_bytes.load(Ordering::Relaxed),
+        rx_errors: c.rx_errors.load(Ordering::Relaxed),
+        rx_dropped: c.rx_dropped.load(Ordering::Relaxed),
+        tx_packets: c.tx_packets.load(Ordering::Relaxed),
+        tx_bytes: c.tx_bytes.load(Ordering::Relaxed),
+        tx_errors: c.tx_errors.load(Ordering::Relaxed),
+    };
+    let info = NetIfInfo {
+        name: eth0.name,
+        index: IFINDEX,
+        mac: eth0.ether_addr.0,
+        mtu: mtu(),
+        speed: eth0.speed,
+        driver: eth0.driver.clone(),
+        stats,
+    };
+    alloc::vec![info]
+}
+
+/// Returns the network interface `name`.
+pub fn interface(name: &str) -> Option<NetIfInfo> {
+    interfaces().into_iter().find(|i| i.name == name)
+}
+
+/// Returns the names of the network interfaces.
+pub fn interface_names() -> Vec<String> {
+    interfaces().into_iter().map(|i| i.name.into()).collect()
+}
+
+fn counter(name: &str, counter: &str) -> Option<String> {
+    let stats = interface(name)?.stats;
+    let (_, value) = stats.counters().into_iter().find(|(c, _)| *c == counter)?;
+    Some(format!("{}\n", value))
+}
+
+/// The function generating an attribute file of an interface, `None` if it
+/// is gone.
+pub type NetAttrFn = fn(iface: &str) -> Option<String>;
+
+/// The attribute files of `/sys/class/net/<iface>` as in Linux, with the
+/// functions generating their contents, the counters in `statistics`.
+pub const SYS_CLASS_NET_ATTRS: &[(&str, NetAttrFn)] = &[
+    ("address", |n| {
+        Some(format!("{}\n", EthernetAddress(interface(n)?.mac)))
+    }),
+    ("addr_len", |n| interface(n).map(|_| "6\n".into())),
+    ("carrier", |n| interface(n).map(|_| "1\n".into())),
+    ("duplex", |n| {
+        let duplex = match interface(n)?.speed {
+            Some(_) => "full",
+            None => "unknown",
+        };
+        Some(format!("{}\n", duplex))
+    }),
+    ("ifindex", |n| Some(format!("{}\n", interface(n)?.index))),
+    ("mtu", |n| Some(format!("{}\n", interface(n)?.mtu))),
+    ("operstate", |n| interface(n).map(|_| "up\n".into())),
+    ("speed", |n| match interface(n)?.speed {
+        Some(speed) => Some(format!("{}\n", speed)),
+        None => Some("-1\n".into()),
+    }),
+    // ARPHRD_ETHER
+    ("type", |n| interface(n).map(|_| "1\n".into())),
+    ("statistics/rx_packets", |n| counter(n, "rx_packets")),
+    ("statistics/rx_bytes", |n| counter(n, "rx_bytes")),
+    ("statistics/rx_errors", |n| counter(n, "rx_errors")),
+    ("statistics/rx_dropped", |n| counter(n, "rx_dropped")),
+    ("statistics/tx_packets", |n| counter(n, "tx_packets")),
+    ("statistics/tx_bytes", |n| counter(n, "tx_bytes")),
+    ("statistics/tx_errors", |n| counter(n, "tx_errors")),
+];
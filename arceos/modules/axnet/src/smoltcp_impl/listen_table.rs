@@ -13,14 +13,17 @@ const PORT_NUM: usize = 65536;
 
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
+    /// The most connections queued, half-open or not accepted yet.
+    backlog: usize,
     syn_queue: VecDeque<SocketHandle>,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(listen_endpoint: IpListenEndpoint, backlog: usize) -> Self {
         Self {
             listen_endpoint,
-            syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            backlog,
+            syn_queue: VecDeque::with_capacity(backlog),
         }
     }
 
@@ -61,12 +64,15 @@ impl ListenTable {
         self.tcp[port as usize].lock().is_none()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> AxResult {
+    /// Listens on `listen_endpoint`, queueing at most `backlog` connections
+    /// (clamped to `1..=LISTEN_QUEUE_SIZE`).
+    pub fn listen(&self, listen_endpoint: IpListenEndpoint, backlog: usize) -> AxResult {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
+        let backlog = backlog.clamp(1, LISTEN_QUEUE_SIZE);
         let mut entry = self.tcp[port as usize].lock();
         if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
+            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint, backlog)));
             Ok(())
         } else {
             ax_err!(AddrInUse, "socket listen() failed")
@@ -121,12 +127,15 @@ impl ListenTable {
                 // not listening on this address
                 return;
             }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
+            if entry.syn_queue.len() >= entry.backlog {
                 // SYN queue is full, drop the packet
                 warn!("SYN queue overflow!");
                 return;
             }
-            let mut socket = SocketSetWrapper::new_tcp_socket();
+            let Some(mut socket) = SocketSetWrapper::new_tcp_socket() else {
+                warn!("socket memory exhausted, SYN dropped");
+                return;
+            };
             if socket.listen(entry.listen_endpoint).is_ok() {
                 let handle = sockets.add(socket);
                 debug!(
@@ -1,13 +1,23 @@
 mod addr;
 mod bench;
 mod dns;
+mod iface;
 mod listen_table;
+#[cfg(feature = "ptp")]
+mod ptp;
+mod sock_mem;
 mod tcp;
+mod tcp_info;
 mod udp;
+#[cfg(feature = "xdp")]
+mod xdp;
+mod zero_copy;
 
+use alloc::string::String;
 use alloc::vec;
 use core::cell::RefCell;
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
@@ -21,10 +31,24 @@ use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
 
 use self::listen_table::ListenTable;
+use crate::NetPort;
 
 pub use self::dns::dns_query;
+pub use self::iface::{interface, interface_names, interfaces};
+pub use self::iface::{NetAttrFn, NetIfInfo, NetStats, SYS_CLASS_NET_ATTRS};
+#[cfg(feature = "ptp")]
+pub use self::ptp::{PtpClient, PtpStatus};
+pub use self::sock_mem::{set_socket_mem_limit, socket_mem_usage};
 pub use self::tcp::TcpSocket;
+pub use self::tcp_info::TcpInfo;
 pub use self::udp::UdpSocket;
+#[cfg(feature = "xdp")]
+pub use self::xdp::{XdpConfig, XdpDesc, XdpFilter, XdpHeader, XdpRingHeader, XdpSocket, XdpStats};
+#[cfg(feature = "xdp")]
+pub use self::xdp::{MAX_RING_SIZE, XDP_MAGIC};
+pub use self::zero_copy::{
+    set_lend_timeout, zero_copy_stats, RxPacket, ZeroCopyUdpSocket, MAX_LENT,
+};
 
 macro_rules! env_or_default {
     ($key:literal) => {
@@ -40,7 +64,11 @@ const GATEWAY: &str = env_or_default!("AX_GW");
 const DNS_SEVER: &str = "8.8.8.8";
 const IP_PREFIX: u8 = 24;
 
-const STANDARD_MTU: usize = 1500;
+const STANDARD_MTU: usize = axdriver::net::STANDARD_MTU;
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// The MTU of the interface, set at init.
+static MTU: AtomicUsize = AtomicUsize::new(STANDARD_MTU);
 
 const RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 
@@ -57,12 +85,16 @@ static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
 struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
 struct DeviceWrapper {
-    inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    inner: RefCell<NetPort>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
 }
 
 struct InterfaceWrapper {
     name: &'static str,
     ether_addr: EthernetAddress,
+    /// The name of the device.
+    driver: String,
+    /// The link speed of the device in Mb/s, if known.
+    speed: Option<u32>,
     dev: Mutex<DeviceWrapper>,
     iface: Mutex<Interface>,
 }
@@ -72,20 +104,23 @@ impl<'a> SocketSetWrapper<'a> {
         Self(Mutex::new(SocketSet::new(vec![])))
     }
 
-    pub fn new_tcp_socket() -> socket::tcp::Socket<'a> {
-        let tcp_rx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]);
-        let tcp_tx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]);
-        socket::tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
+    /// Returns `None` if the socket memory is exhausted.
+    pub fn new_tcp_socket() -> Option<socket::tcp::Socket<'a>> {
+        let buf_len = sock_mem::charge(TCP_RX_BUF_LEN.max(TCP_TX_BUF_LEN), 2, false)?;
+        let tcp_rx_buffer = socket::tcp::SocketBuffer::new(vec![0; buf_len]);
+        let tcp_tx_buffer = socket::tcp::SocketBuffer::new(vec![0; buf_len]);
+        Some(socket::tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer))
     }
 
     pub fn new_udp_socket() -> socket::udp::Socket<'a> {
+        let buf_len = sock_mem::charge(UDP_RX_BUF_LEN.max(UDP_TX_BUF_LEN), 2, true).unwrap();
         let udp_rx_buffer = socket::udp::PacketBuffer::new(
             vec![socket::udp::PacketMetadata::EMPTY; 8],
-            vec![0; UDP_RX_BUF_LEN],
+            vec![0; buf_len],
         );
         let udp_tx_buffer = socket::udp::PacketBuffer::new(
             vec![socket::udp::PacketMetadata::EMPTY; 8],
-            vec![0; UDP_TX_BUF_LEN],
+            vec![0; buf_len],
         );
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
@@ -119,26 +154,57 @@ impl<'a> SocketSetWrapper<'a> {
         f(socket)
     }
 
+    /// Calls `f` with the two different sockets `a` and `b` at once, under one
+    /// lock of the set.
+    pub fn with_socket_pair_mut<T: AnySocket<'a>, R, F>(
+        &self,
+        a: SocketHandle,
+        b: SocketHandle,
+        f: F,
+    ) -> R
+    where
+        F: FnOnce(&mut T, &mut T) -> R,
+    {
+        assert_ne!(a, b);
+        let mut set = self.0.lock();
+        let (mut socket_a, mut socket_b) = (None, None);
+        for (handle, socket) in set.iter_mut() {
+            if handle == a {
+                socket_a = T::downcast_mut(socket);
+            } else if handle == b {
+                socket_b = T::downcast_mut(socket);
+            }
+        }
+        match (socket_a, socket_b) {
+            (Some(a), Some(b)) => f(a, b),
+            _ => panic!("handle does not refer to a valid socket of this type"),
+        }
+    }
+
     pub fn poll_interfaces(&self) {
         ETH0.poll(&self.0);
     }
 
     pub fn remove(&self, handle: SocketHandle) {
-        self.0.lock().remove(handle);
+        sock_mem::uncharge_socket(&self.0.lock().remove(handle));
         debug!("socket {}: destroyed", handle);
     }
 }
 
 impl InterfaceWrapper {
-    fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
+    fn new(name: &'static str, dev: NetPort, ether_addr: EthernetAddress) -> Self {
         let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
         config.random_seed = RANDOM_SEED;
 
+        let driver = String::from(dev.device_name());
+        let speed = axdriver::net::link_speed(&driver);
         let mut dev = DeviceWrapper::new(dev);
         let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
         Self {
             name,
             ether_addr,
+            driver,
+            speed,
             dev: Mutex::new(dev),
             iface,
         }
@@ -170,6 +236,19 @@ impl InterfaceWrapper {
         };
     }
 
+    #[cfg(feature = "ptp")]
+    pub fn join_multicast_group(&self, addr: IpAddress) {
+        let mut dev = self.dev.lock();
+        let mut iface = self.iface.lock();
+        let timestamp = Self::current_time();
+        if let Err(e) = iface.join_multicast_group(dev.deref_mut(), addr, timestamp) {
+            warn!(
+                "{}: joining multicast group {} failed: {:?}",
+                self.name, addr, e
+            );
+        }
+    }
+
     pub fn poll(&self, sockets: &Mutex<SocketSet>) {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
@@ -180,7 +259,7 @@ impl InterfaceWrapper {
 }
 
 impl DeviceWrapper {
-    fn new(inner: AxNetDevice) -> Self {
+    fn new(inner: NetPort) -> Self {
         Self {
             inner: RefCell::new(inner),
         }
@@ -197,18 +276,44 @@ impl Device for DeviceWrapper {
             warn!("recycle_tx_buffers failed: {:?}", e);
             return None;
         }
+        zero_copy::recycle(&mut dev);
+        #[cfg(feature = "xdp")]
+        xdp::transmit(&mut dev);
 
         if !dev.can_transmit() {
             return None;
         }
-        let rx_buf = match dev.receive() {
-            Ok(buf) => buf,
-            Err(err) => {
-                if !matches!(err, DevError::Again) {
-                    warn!("receive failed: {:?}", err);
+        let rx_buf = loop {
+            #[allow(unused_mut)]
+            let mut rx_buf = match dev.receive() {
+                Ok(buf) => buf,
+                Err(err) => {
+                    if !matches!(err, DevError::Again) {
+                        warn!("receive failed: {:?}", err);
+                        iface::count_rx_error();
+                    }
+                    return None;
+                }
+            };
+            iface::count_rx(rx_buf.packet_len());
+            #[cfg(feature = "bpf")]
+            if axbpf::run_hook(axbpf::HOOK_NET_RX, rx_buf.packet_mut()) == Some(0) {
+                trace!("RECV {} bytes dropped by BPF", rx_buf.packet_len());
+                iface::count_rx_dropped();
+                if let Err(e) = dev.recycle_rx_buffer(rx_buf) {
+                    warn!("recycle_rx_buffer failed: {:?}", e);
+                    return None;
                 }
-                return None;
+                continue;
             }
+            #[cfg(feature = "xdp")]
+            let Some(rx_buf) = xdp::steer(rx_buf, &mut dev) else {
+                continue;
+            };
+            let Some(rx_buf) = zero_copy::divert(rx_buf, &mut dev) else {
+                continue;
+            };
+            break rx_buf;
         };
         Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
     }
@@ -228,19 +333,21 @@ impl Device for DeviceWrapper {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1514;
+        caps.max_transmission_unit = ETHERNET_HEADER_LEN + mtu();
         caps.max_burst_size = None;
         caps.medium = Medium::Ethernet;
         caps
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
-struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
+struct AxNetRxToken<'a>(&'a RefCell<NetPort>, NetBufPtr);
+struct AxNetTxToken<'a>(&'a RefCell<NetPort>);
 
 impl<'a> RxToken for AxNetRxToken<'a> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
         snoop_tcp_packet(self.1.packet(), sockets).ok();
+        #[cfg(feature = "ptp")]
+        ptp::snoop_packet(self.1.packet()).ok();
     }
 
     fn consume<R, F>(self, f: F) -> R
@@ -268,7 +375,11 @@ impl<'a> TxToken for AxNetTxToken<'a> {
         let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+        tcp_info::snoop_transmit(tx_buf.packet()).ok();
+        #[cfg(feature = "ptp")]
+        ptp::snoop_packet(tx_buf.packet()).ok();
         dev.transmit(tx_buf).unwrap();
+        iface::count_tx(len);
         ret
     }
 }
@@ -288,6 +399,7 @@ fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smolt
             // create a socket for the first incoming TCP packet, as the later accept() returns.
             LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
         }
+        tcp_info::snoop_receive(dst_addr, src_addr, &tcp_packet);
     }
     Ok(())
 }
@@ -300,6 +412,16 @@ pub fn poll_interfaces() {
     SOCKET_SET.poll_interfaces();
 }
 
+/// Returns the MTU of the network interface.
+///
+/// It is the largest MTU of the device, or the one given by the `AX_MTU`
+/// environment variable at build time if smaller. The TCP connections send
+/// segments of it at most, and the larger UDP datagrams are fragmented with
+/// the `ip-frag` feature.
+pub fn mtu() -> usize {
+    MTU.load(Ordering::Relaxed)
+}
+
 /// Benchmark raw socket transmit bandwidth.
 pub fn bench_transmit() {
     ETH0.dev.lock().bench_transmit_bandwidth();
@@ -310,7 +432,38 @@ pub fn bench_receive() {
     ETH0.dev.lock().bench_receive_bandwidth();
 }
 
-pub(crate) fn init(net_dev: AxNetDevice) {
+/// The `ifconfig` command of the shell.
+fn do_ifconfig(_args: &str, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+    let Some(eth0) = ETH0.get() else {
+        return writeln!(out, "no network interface");
+    };
+    writeln!(
+        out,
+        "{}: ether {} mtu {}",
+        eth0.name(),
+        eth0.ethernet_address(),
+        mtu()
+    )?;
+    for cidr in eth0.iface.lock().ip_addrs() {
+        writeln!(out, "    inet {}", cidr)?;
+    }
+    let (used, limit) = socket_mem_usage();
+    writeln!(out, "    socket buffers {} of {} bytes", used, limit)
+}
+
+axcmd::register_cmd!("ifconfig", do_ifconfig, "show the network interfaces");
+
+pub(crate) fn init(net_dev: NetPort, max_mtu: usize) {
+    sock_mem::init();
+    let mtu = match option_env!("AX_MTU") {
+        Some(mtu) => mtu.parse().expect("invalid MTU in AX_MTU"),
+        None => max_mtu,
+    };
+    // The interface keeps the capabilities of the device it is created with.
+    MTU.store(
+        mtu.clamp(axdriver::net::MIN_MTU, max_mtu),
+        Ordering::Relaxed,
+    );
     let ether_addr = EthernetAddress(net_dev.mac_address().0);
     let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
 
@@ -327,4 +480,10 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     info!("  ether:    {}", ETH0.ethernet_address());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
+    info!("  mtu:      {}", mtu());
+    #[cfg(feature = "event")]
+    axevent::publish(axevent::KernelEvent::LinkChange {
+        iface: ETH0.name,
+        up: true,
+    });
 }
@@ -0,0 +1,507 @@
+//! PTP (IEEE 1588-2008) slave clock.
+//!
+//! A [`PtpClient`] is an ordinary clock in the slave state over UDP/IPv4. It
+//! picks the best master from the Announce messages, measures its offset
+//! from the master with the Sync/Follow_Up and Delay_Req/Delay_Resp
+//! exchanges (the end-to-end delay mechanism), and disciplines the wall clock
+//! of [`axhal::time`] with a PI servo.
+//!
+//! The NIC drivers do not report hardware timestamps, so the event messages
+//! are timestamped in software when their frames pass the device, not when
+//! the client reads them from its sockets, which keeps the scheduling delays
+//! out of the measurements.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{AxError, AxResult};
+use axhal::time::{monotonic_time_nanos, wall_time_nanos, NANOS_PER_MILLIS, NANOS_PER_SEC};
+use axsync::Mutex;
+use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, UdpPacket};
+
+use super::addr::from_core_ipaddr;
+use super::{UdpSocket, ETH0};
+
+const EVENT_PORT: u16 = 319;
+const GENERAL_PORT: u16 = 320;
+/// The primary multicast address of PTP over IPv4.
+const PRIMARY_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 1, 129);
+
+const MSG_SYNC: u8 = 0x0;
+const MSG_DELAY_REQ: u8 = 0x1;
+const MSG_FOLLOW_UP: u8 = 0x8;
+const MSG_DELAY_RESP: u8 = 0x9;
+const MSG_ANNOUNCE: u8 = 0xb;
+
+const HEADER_LEN: usize = 34;
+const FLAG_TWO_STEP: u16 = 0x0200;
+const FLAG_UTC_OFFSET_VALID: u16 = 0x0004;
+const FLAG_PTP_TIMESCALE: u16 = 0x0008;
+
+/// The interval between the Delay_Req messages.
+const DELAY_REQ_INTERVAL: u64 = NANOS_PER_SEC;
+/// The number of Announce intervals after which a master is forgotten.
+const ANNOUNCE_RECEIPT_TIMEOUT: u64 = 4;
+/// The number of path delay samples the minimum is taken over.
+const DELAY_SAMPLES: usize = 8;
+/// The offset above which the clock is stepped instead of slewed.
+const STEP_THRESHOLD: i64 = 100 * NANOS_PER_MILLIS as i64;
+/// The offset under which the clock is considered synchronized.
+const LOCK_THRESHOLD: i64 = NANOS_PER_MILLIS as i64;
+/// The maximum frequency correction, 500 ppm.
+const MAX_FREQ_PPB: i64 = 500_000;
+/// The number of event timestamps kept for the client.
+const MAX_TIMESTAMPS: usize = 16;
+
+/// The status of a [`PtpClient`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PtpStatus {
+    /// The clock identity of the master, `None` if there is none.
+    pub master: Option<[u8; 8]>,
+    /// The last measured offset of the wall clock from the master in
+    /// nanoseconds.
+    pub offset_ns: i64,
+    /// The mean path delay to the master in nanoseconds.
+    pub mean_path_delay_ns: i64,
+    /// The frequency correction of the wall clock in parts per billion.
+    pub freq_ppb: i64,
+    /// Whether the wall clock is synchronized to the master (within 1ms).
+    pub locked: bool,
+}
+
+type PortIdentity = [u8; 10];
+
+/// The common header of the PTP messages.
+struct Header {
+    msg_type: u8,
+    domain: u8,
+    flags: u16,
+    /// The correction field in nanoseconds.
+    correction: i64,
+    source: PortIdentity,
+    seq: u16,
+    log_interval: i8,
+}
+
+impl Header {
+    fn parse(msg: &[u8]) -> Option<Self> {
+        if msg.len() < HEADER_LEN || msg[1] & 0xf != 2 {
+            return None;
+        }
+        let correction = i64::from_be_bytes(msg[8..16].try_into().unwrap()) >> 16;
+        Some(Self {
+            msg_type: msg[0] & 0xf,
+            domain: msg[4],
+            flags: u16::from_be_bytes([msg[6], msg[7]]),
+            correction,
+            source: msg[20..30].try_into().unwrap(),
+            seq: u16::from_be_bytes([msg[30], msg[31]]),
+            log_interval: msg[33] as i8,
+        })
+    }
+}
+
+/// Returns the nanoseconds of the PTP timestamp at the start of `buf`.
+fn parse_timestamp(buf: &[u8]) -> Option<i64> {
+    let buf = buf.get(..10)?;
+    let mut secs = [0; 8];
+    secs[2..].copy_from_slice(&buf[..6]);
+    let nanos = u32::from_be_bytes(buf[6..10].try_into().unwrap());
+    Some(i64::from_be_bytes(secs) * NANOS_PER_SEC as i64 + nanos as i64)
+}
+
+/// Returns the Delay_Req message `seq` from `source`.
+fn delay_req(domain: u8, source: &PortIdentity, seq: u16) -> [u8; HEADER_LEN + 10] {
+    let mut msg = [0; HEADER_LEN + 10];
+    msg[0] = MSG_DELAY_REQ;
+    msg[1] = 2;
+    msg[2..4].copy_from_slice(&(msg.len() as u16).to_be_bytes());
+    msg[4] = domain;
+    msg[20..30].copy_from_slice(source);
+    msg[30..32].copy_from_slice(&seq.to_be_bytes());
+    msg[32] = 1; // control field of Delay_Req
+    msg[33] = 0x7f;
+    msg
+}
+
+/// A software timestamp of an event message.
+#[derive(Clone, Copy)]
+struct EventTimestamp {
+    msg_type: u8,
+    seq: u16,
+    source: PortIdentity,
+    wall_nanos: u64,
+}
+
+static SNOOPING: AtomicBool = AtomicBool::new(false);
+static TIMESTAMPS: Mutex<Vec<EventTimestamp>> = Mutex::new(Vec::new());
+
+/// Records the time the PTP event message in the frame `buf` passes the
+/// device, if a [`PtpClient`] is running.
+pub(super) fn snoop_packet(buf: &[u8]) -> Result<(), smoltcp::wire::Error> {
+    if !SNOOPING.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let wall_nanos = wall_time_nanos();
+    let ether_frame = EthernetFrame::new_checked(buf)?;
+    let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
+    if ipv4_packet.next_header() != IpProtocol::Udp {
+        return Ok(());
+    }
+    let udp_packet = UdpPacket::new_checked(ipv4_packet.payload())?;
+    if udp_packet.dst_port() != EVENT_PORT {
+        return Ok(());
+    }
+    if let Some(header) = Header::parse(udp_packet.payload()) {
+        if matches!(header.msg_type, MSG_SYNC | MSG_DELAY_REQ) {
+            let mut timestamps = TIMESTAMPS.lock();
+            if timestamps.len() >= MAX_TIMESTAMPS {
+                timestamps.remove(0);
+            }
+            timestamps.push(EventTimestamp {
+                msg_type: header.msg_type,
+                seq: header.seq,
+                source: header.source,
+                wall_nanos,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Takes the timestamp of the event message `seq` of `msg_type` from
+/// `source`.
+fn take_timestamp(msg_type: u8, seq: u16, source: &PortIdentity) -> Option<u64> {
+    let mut timestamps = TIMESTAMPS.lock();
+    let i = timestamps
+        .iter()
+        .position(|t| t.msg_type == msg_type && t.seq == seq && t.source == *source)?;
+    Some(timestamps.remove(i).wall_nanos)
+}
+
+/// A master heard from in the Announce messages.
+struct ForeignMaster {
+    port: PortIdentity,
+    /// The dataset compared to pick the best master, the lower the better:
+    /// priority1, clock quality, priority2, grandmaster identity and steps
+    /// removed.
+    rank: [u8; 16],
+    /// TAI - UTC in nanoseconds, 0 if the master is not on the PTP timescale.
+    utc_offset: i64,
+    last_seen: u64,
+    timeout: u64,
+}
+
+/// The PI servo disciplining the wall clock.
+#[derive(Default)]
+struct Servo {
+    /// The integral term, the frequency error of the local clock.
+    drift_ppb: i64,
+    freq_ppb: i64,
+    stepped: bool,
+}
+
+impl Servo {
+    /// Takes the offset `offset` of the wall clock from the master, and
+    /// returns whether the clock was stepped.
+    fn sample(&mut self, offset: i64) -> bool {
+        if !self.stepped || offset.abs() > STEP_THRESHOLD {
+            info!("PTP: stepping the wall clock by {}ns", -offset);
+            axhal::time::step_wall_time(-offset);
+            self.stepped = true;
+            return true;
+        }
+        // kp = 0.7, ki = 0.3, for a sample per second
+        self.drift_ppb = (self.drift_ppb + offset * 3 / 10).clamp(-MAX_FREQ_PPB, MAX_FREQ_PPB);
+        self.freq_ppb = -(offset * 7 / 10 + self.drift_ppb).clamp(-MAX_FREQ_PPB, MAX_FREQ_PPB);
+        axhal::time::set_wall_time_freq(self.freq_ppb);
+        false
+    }
+}
+
+struct PtpState {
+    domain: u8,
+    port: PortIdentity,
+    masters: Vec<ForeignMaster>,
+    master: Option<PortIdentity>,
+    /// The Sync waiting for its Follow_Up: sequence, receive time and
+    /// correction.
+    pending_sync: Option<(u16, u64, i64)>,
+    /// The origin and receive times of the last Sync.
+    last_sync: Option<(i64, u64)>,
+    /// The Delay_Req waiting for its Delay_Resp.
+    pending_delay_req: Option<u16>,
+    last_delay_req: Option<u64>,
+    next_seq: u16,
+    delays: Vec<i64>,
+    servo: Servo,
+    status: PtpStatus,
+}
+
+impl PtpState {
+    fn utc_offset(&self) -> i64 {
+        self.masters
+            .iter()
+            .find(|m| Some(m.port) == self.master)
+            .map_or(0, |m| m.utc_offset)
+    }
+
+    fn reset(&mut self) {
+        self.pending_sync = None;
+        self.last_sync = None;
+        self.pending_delay_req = None;
+        self.delays.clear();
+        self.status.master = self.master.map(|p| p[..8].try_into().unwrap());
+        self.status.locked = false;
+    }
+
+    fn on_announce(&mut self, header: &Header, msg: &[u8]) {
+        if msg.len() < HEADER_LEN + 30 {
+            return;
+        }
+        let body = &msg[HEADER_LEN..];
+        let mut rank = [0; 16];
+        rank[0] = body[13]; // priority1
+        rank[1..5].copy_from_slice(&body[14..18]); // clock quality
+        rank[5] = body[18]; // priority2
+        rank[6..14].copy_from_slice(&body[19..27]); // grandmaster identity
+        rank[14..16].copy_from_slice(&body[27..29]); // steps removed
+        let utc_offset = if header.flags & FLAG_PTP_TIMESCALE != 0 {
+            let offset = if header.flags & FLAG_UTC_OFFSET_VALID != 0 {
+                i16::from_be_bytes([body[10], body[11]]) as i64
+            } else {
+                37 // since 2017
+            };
+            offset * NANOS_PER_SEC as i64
+        } else {
+            0
+        };
+        let log_interval = header.log_interval.clamp(-8, 8);
+        let interval = if log_interval >= 0 {
+            NANOS_PER_SEC << log_interval
+        } else {
+            NANOS_PER_SEC >> -log_interval
+        };
+        let master = ForeignMaster {
+            port: header.source,
+            rank,
+            utc_offset,
+            last_seen: monotonic_time_nanos(),
+            timeout: ANNOUNCE_RECEIPT_TIMEOUT * interval,
+        };
+        match self.masters.iter_mut().find(|m| m.port == header.source) {
+            Some(m) => *m = master,
+            None => self.masters.push(master),
+        }
+        self.select_master();
+    }
+
+    /// Runs the best master clock algorithm over the masters heard from.
+    fn select_master(&mut self) {
+        let now = monotonic_time_nanos();
+        self.masters.retain(|m| now - m.last_seen < m.timeout);
+        let best = self.masters.iter().min_by_key(|m| m.rank).map(|m| m.port);
+        if best != self.master {
+            match best {
+                Some(port) => info!("PTP: new master {:02x?}", port),
+                None => warn!("PTP: master lost"),
+            }
+            self.master = best;
+            self.reset();
+        }
+    }
+
+    fn on_sync(&mut self, header: &Header, msg: &[u8]) {
+        let Some(t2) = take_timestamp(MSG_SYNC, header.seq, &header.source) else {
+            return;
+        };
+        if header.flags & FLAG_TWO_STEP != 0 {
+            self.pending_sync = Some((header.seq, t2, header.correction));
+        } else if let Some(t1) = parse_timestamp(&msg[HEADER_LEN..]) {
+            self.on_sync_times(t1 + header.correction, t2);
+        }
+    }
+
+    fn on_follow_up(&mut self, header: &Header, msg: &[u8]) {
+        let Some((seq, t2, correction)) = self.pending_sync else {
+            return;
+        };
+        if seq != header.seq {
+            return;
+        }
+        self.pending_sync = None;
+        if let Some(t1) = parse_timestamp(&msg[HEADER_LEN..]) {
+            self.on_sync_times(t1 + correction + header.correction, t2);
+        }
+    }
+
+    /// Takes the origin time `t1` (of the master) and the receive time `t2`
+    /// (of the wall clock) of a Sync.
+    fn on_sync_times(&mut self, t1: i64, t2: u64) {
+        let t1 = t1 - self.utc_offset();
+        self.last_sync = Some((t1, t2));
+        let Some(&delay) = self.delays.iter().min() else {
+            return;
+        };
+        let offset = t2 as i64 - (t1 + delay);
+        if self.servo.sample(offset) {
+            // The times taken before the step are off.
+            self.last_sync = None;
+            self.pending_delay_req = None;
+        }
+        self.status.offset_ns = offset;
+        self.status.mean_path_delay_ns = delay;
+        self.status.freq_ppb = self.servo.freq_ppb;
+        self.status.locked = offset.abs() < LOCK_THRESHOLD;
+    }
+
+    fn on_delay_resp(&mut self, header: &Header, msg: &[u8]) {
+        if msg.len() < HEADER_LEN + 20 || msg[HEADER_LEN + 10..HEADER_LEN + 20] != self.port {
+            return;
+        }
+        if self.pending_delay_req != Some(header.seq) {
+            return;
+        }
+        self.pending_delay_req = None;
+        let Some(t3) = take_timestamp(MSG_DELAY_REQ, header.seq, &self.port) else {
+            return;
+        };
+        let (Some(t4), Some((t1, t2))) = (parse_timestamp(&msg[HEADER_LEN..]), self.last_sync)
+        else {
+            return;
+        };
+        let t4 = t4 - header.correction - self.utc_offset();
+        let delay = ((t2 as i64 - t1) + (t4 - t3 as i64)) / 2;
+        if delay < 0 {
+            debug!("PTP: negative path delay {}ns", delay);
+            return;
+        }
+        if self.delays.len() >= DELAY_SAMPLES {
+            self.delays.remove(0);
+        }
+        self.delays.push(delay);
+    }
+}
+
+/// A PTP slave clock disciplining the wall clock.
+///
+/// It does nothing on its own: [`poll`](Self::poll) must be called
+/// regularly, from a dedicated task say, to handle the messages of the
+/// master.
+pub struct PtpClient {
+    event: UdpSocket,
+    general: UdpSocket,
+    state: Mutex<PtpState>,
+}
+
+impl PtpClient {
+    /// Creates a slave clock of the PTP domain `domain`, listening on the
+    /// PTP ports for the master.
+    pub fn new(domain: u8) -> AxResult<Self> {
+        ETH0.join_multicast_group(from_core_ipaddr(IpAddr::V4(PRIMARY_GROUP)));
+        let event = UdpSocket::new();
+        event.bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            EVENT_PORT,
+        ))?;
+        event.set_nonblocking(true);
+        let general = UdpSocket::new();
+        general.bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            GENERAL_PORT,
+        ))?;
+        general.set_nonblocking(true);
+
+        // The clock identity is the EUI-64 of the MAC address.
+        let mac = ETH0.ethernet_address().0;
+        let mut port = [0; 10];
+        port[..3].copy_from_slice(&mac[..3]);
+        port[3..5].copy_from_slice(&[0xff, 0xfe]);
+        port[5..8].copy_from_slice(&mac[3..]);
+        port[9] = 1; // port number
+        SNOOPING.store(true, Ordering::Relaxed);
+        Ok(Self {
+            event,
+            general,
+            state: Mutex::new(PtpState {
+                domain,
+                port,
+                masters: Vec::new(),
+                master: None,
+                pending_sync: None,
+                last_sync: None,
+                pending_delay_req: None,
+                last_delay_req: None,
+                next_seq: 0,
+                delays: Vec::new(),
+                servo: Servo::default(),
+                status: PtpStatus::default(),
+            }),
+        })
+    }
+
+    /// Returns the status of the clock.
+    pub fn status(&self) -> PtpStatus {
+        self.state.lock().status
+    }
+
+    /// Handles the messages received, and sends a Delay_Req if one is due.
+    pub fn poll(&self) -> AxResult {
+        super::poll_interfaces();
+        let mut buf = [0u8; 128];
+        let mut state = self.state.lock();
+        for socket in [&self.event, &self.general] {
+            loop {
+                let len = match socket.recv_from(&mut buf) {
+                    Ok((len, _)) => len,
+                    Err(AxError::WouldBlock) => break,
+                    Err(e) => return Err(e),
+                };
+                let msg = &buf[..len];
+                let Some(header) = Header::parse(msg) else {
+                    continue;
+                };
+                if header.domain != state.domain {
+                    continue;
+                }
+                if header.msg_type == MSG_ANNOUNCE {
+                    state.on_announce(&header, msg);
+                    continue;
+                }
+                if state.master != Some(header.source) {
+                    continue;
+                }
+                match header.msg_type {
+                    MSG_SYNC => state.on_sync(&header, msg),
+                    MSG_FOLLOW_UP => state.on_follow_up(&header, msg),
+                    MSG_DELAY_RESP => state.on_delay_resp(&header, msg),
+                    _ => {}
+                }
+            }
+        }
+        state.select_master();
+
+        let now = monotonic_time_nanos();
+        let due = state
+            .last_delay_req
+            .map_or(true, |t| now - t >= DELAY_REQ_INTERVAL);
+        if state.master.is_some() && state.last_sync.is_some() && due {
+            let seq = state.next_seq;
+            state.next_seq = seq.wrapping_add(1);
+            let msg = delay_req(state.domain, &state.port, seq);
+            self.event
+                .send_to(&msg, SocketAddr::new(IpAddr::V4(PRIMARY_GROUP), EVENT_PORT))?;
+            state.pending_delay_req = Some(seq);
+            state.last_delay_req = Some(now);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PtpClient {
+    fn drop(&mut self) {
+        SNOOPING.store(false, Ordering::Relaxed);
+        TIMESTAMPS.lock().clear();
+    }
+}
@@ -0,0 +1,82 @@
+//! The memory of the socket buffers, against a global budget.
+//!
+//! Like `tcp_mem` of Linux, the buffers of all the sockets are charged to a
+//! budget, set by the `AX_SOCK_MEM` environment variable at build time (in
+//! bytes) or with [`set_socket_mem_limit`]. Past three quarters of it, the
+//! memory is under pressure: the new sockets get buffers of a quarter of the
+//! size, so their windows are smaller. Past the budget, no more TCP socket
+//! is created, the incoming SYNs are dropped, and the new UDP sockets get the
+//! smallest buffers. The buffers of a socket are kept until it is destroyed.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use smoltcp::socket::Socket;
+
+const DEFAULT_LIMIT: usize = 64 * 1024 * 1024;
+/// The smallest buffer given under pressure.
+pub(crate) const MIN_BUF_LEN: usize = 4 * 1024;
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_LIMIT);
+
+pub(crate) fn init() {
+    if let Some(limit) = option_env!("AX_SOCK_MEM") {
+        set_socket_mem_limit(limit.parse().expect("invalid budget in AX_SOCK_MEM"));
+    }
+}
+
+/// Sets the budget of the socket buffers, in bytes.
+///
+/// The sockets with buffers already are not shrunk, a budget lower than the
+/// memory in use only applies to the new ones.
+pub fn set_socket_mem_limit(limit: usize) {
+    info!("socket memory budget: {} bytes", limit);
+    LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Returns the memory of the socket buffers in use and its budget, in
+/// bytes.
+pub fn socket_mem_usage() -> (usize, usize) {
+    (USED.load(Ordering::Relaxed), LIMIT.load(Ordering::Relaxed))
+}
+
+/// Charges `count` buffers of `len` bytes, or the smaller ones given under
+/// pressure, returns the length of a buffer.
+///
+/// Past the budget, it returns `None`, or charges the smallest buffers if
+/// `must` is true.
+pub(crate) fn charge(len: usize, count: usize, must: bool) -> Option<usize> {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let mut used = USED.load(Ordering::Relaxed);
+    loop {
+        let len = if used > limit / 4 * 3 {
+            (len / 4).max(MIN_BUF_LEN).min(len)
+        } else {
+            len
+        };
+        let len = match used.checked_add(len * count) {
+            Some(total) if total <= limit => len,
+            _ if must => MIN_BUF_LEN.min(len),
+            _ => return None,
+        };
+        match USED.compare_exchange_weak(
+            used,
+            used + len * count,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return Some(len),
+            Err(now) => used = now,
+        }
+    }
+}
+
+/// Releases the buffers of a destroyed socket.
+pub(crate) fn uncharge_socket(socket: &Socket) {
+    let len = match socket {
+        Socket::Tcp(socket) => socket.recv_capacity() + socket.send_capacity(),
+        Socket::Udp(socket) => socket.payload_recv_capacity() + socket.payload_send_capacity(),
+        _ => 0,
+    };
+    USED.fetch_sub(len, Ordering::Relaxed);
+}
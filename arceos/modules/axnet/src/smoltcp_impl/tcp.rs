@@ -4,14 +4,15 @@ use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
-use axsync::Mutex;
+use axsync::{spin_wait_until, Mutex};
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
-use super::{SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET};
+use super::tcp_info::{self, TcpInfo};
+use super::{SocketSetWrapper, ETH0, LISTEN_QUEUE_SIZE, LISTEN_TABLE, SOCKET_SET};
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -121,8 +122,12 @@ impl TcpSocket {
     pub fn connect(&self, remote_addr: SocketAddr) -> AxResult {
         self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
             // SAFETY: no other threads can read or write these fields.
-            let handle = unsafe { self.handle.get().read() }
-                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
+            let handle = match unsafe { self.handle.get().read() } {
+                Some(handle) => handle,
+                None => SOCKET_SET.add(SocketSetWrapper::new_tcp_socket().ok_or_else(|| {
+                    ax_err_type!(NoMemory, "socket connect() failed: socket memory exhausted")
+                })?),
+            };
 
             // TODO: check remote addr unreachable
             let remote_endpoint = from_core_sockaddr(remote_addr);
@@ -152,12 +157,15 @@ impl TcpSocket {
                 self.peer_addr.get().write(remote_endpoint);
                 self.handle.get().write(Some(handle));
             }
+            tcp_info::register(handle, local_endpoint, remote_endpoint);
             Ok(())
         })
         .unwrap_or_else(|_| ax_err!(AlreadyExists, "socket connect() failed: already connected"))?; // EISCONN
 
         // Here our state must be `CONNECTING`, and only one thread can run here.
         if self.is_nonblocking() {
+            // Send the SYN now rather than at the next poll.
+            SOCKET_SET.poll_interfaces();
             Err(AxError::WouldBlock)
         } else {
             self.block_on(|| {
@@ -204,12 +212,19 @@ impl TcpSocket {
     /// It's must be called after [`bind`](Self::bind) and before
     /// [`accept`](Self::accept).
     pub fn listen(&self) -> AxResult {
+        self.listen_with_backlog(LISTEN_QUEUE_SIZE)
+    }
+
+    /// Starts listening as [`listen`](Self::listen), with at most `backlog`
+    /// connections queued (half-open or not accepted yet), the SYNs past it
+    /// are dropped. The backlog is clamped to `1..=512`.
+    pub fn listen_with_backlog(&self, backlog: usize) -> AxResult {
         self.update_state(STATE_CLOSED, STATE_LISTENING, || {
             let bound_endpoint = self.bound_endpoint()?;
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint)?;
+            LISTEN_TABLE.listen(bound_endpoint, backlog)?;
             debug!("TCP socket listening on {}", bound_endpoint);
             Ok(())
         })
@@ -229,9 +244,15 @@ impl TcpSocket {
 
         // SAFETY: `self.local_addr` should be initialized after `bind()`.
         let local_port = unsafe { self.local_addr.get().read().port };
+        if self.is_nonblocking() {
+            // Take the handshakes completed by the packets received since
+            // the last poll, rather than failing until the next one.
+            SOCKET_SET.poll_interfaces();
+        }
         self.block_on(|| {
             let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
+            tcp_info::register(handle, local_addr, peer_addr);
             Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
         })
     }
@@ -302,6 +323,98 @@ impl TcpSocket {
         })
     }
 
+    /// Receives data from the socket without copying it out.
+    ///
+    /// The closure is called with the contiguous readable part of the receive
+    /// buffer and returns how many bytes it consumed, which may be none. The
+    /// consumed byte count is returned, or `None` if the connection is closed.
+    ///
+    /// The closure runs with the socket set locked: it must not use any
+    /// socket, this one included, and should only parse or copy the data,
+    /// leaving the work on it until `recv_with` returns.
+    pub fn recv_with<F>(&self, f: F) -> AxResult<Option<usize>>
+    where
+        F: FnOnce(&[u8]) -> usize,
+    {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, "socket recv_with() failed");
+        }
+
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        let mut f = Some(f);
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                if !socket.is_active() {
+                    // not open
+                    ax_err!(ConnectionRefused, "socket recv_with() failed")
+                } else if !socket.may_recv() {
+                    // connection closed
+                    Ok(None)
+                } else if socket.recv_queue() > 0 {
+                    // data available, hand the rx buffer to the consumer
+                    let f = f.take().unwrap();
+                    socket
+                        .recv(|buf| {
+                            let len = f(buf).min(buf.len());
+                            (len, Some(len))
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket recv_with() failed"))
+                } else {
+                    // no more data
+                    Err(AxError::WouldBlock)
+                }
+            })
+        })
+    }
+
+    /// Moves the received data straight to the transmit buffer of `dst`,
+    /// without copying it out, under one lock of the socket set.
+    ///
+    /// Returns the moved byte count, or `None` if this connection is closed.
+    /// It would block while there is no data or `dst` cannot take more.
+    pub fn splice_to(&self, dst: &TcpSocket) -> AxResult<Option<usize>> {
+        if self.is_connecting() || dst.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() || !dst.is_connected() {
+            return ax_err!(NotConnected, "socket splice_to() failed");
+        }
+
+        // SAFETY: `handle` should be initialized in the connected sockets.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        let dst_handle = unsafe { dst.handle.get().read().unwrap() };
+        self.block_on(|| {
+            let quota = tcp_info::pacing_quota(dst_handle);
+            SOCKET_SET.with_socket_pair_mut::<tcp::Socket, _, _>(handle, dst_handle, |src, dst| {
+                if !src.is_active() {
+                    // not open
+                    ax_err!(ConnectionRefused, "socket splice_to() failed")
+                } else if !src.may_recv() {
+                    // connection closed
+                    Ok(None)
+                } else if !dst.is_active() || !dst.may_send() {
+                    // closed by the remote of `dst`
+                    ax_err!(ConnectionReset, "socket splice_to() failed")
+                } else if src.recv_queue() > 0 && dst.can_send() && quota > 0 {
+                    // hand the rx buffer to the tx buffer of `dst`
+                    let len = src
+                        .recv(|buf| {
+                            let len = dst.send_slice(&buf[..buf.len().min(quota)]).unwrap_or(0);
+                            (len, len)
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket splice_to() failed"))?;
+                    tcp_info::on_queued(dst_handle, len);
+                    Ok(Some(len))
+                } else {
+                    // no more data, or the tx buffer of `dst` is full
+                    Err(AxError::WouldBlock)
+                }
+            })
+        })
+    }
+
     /// Transmits data in the given buffer.
     pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
         if self.is_connecting() {
@@ -313,25 +426,97 @@ impl TcpSocket {
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
         self.block_on(|| {
+            let quota = tcp_info::pacing_quota(handle);
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                 if !socket.is_active() || !socket.may_send() {
                     // closed by remote
                     ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
+                } else if socket.can_send() && quota > 0 {
+                    // connected, the tx buffer is not full, and the pacing allows
                     // TODO: use socket.send(|buf| {...})
                     let len = socket
-                        .send_slice(buf)
+                        .send_slice(&buf[..buf.len().min(quota)])
                         .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                    tcp_info::on_queued(handle, len);
                     Ok(len)
                 } else {
-                    // tx buffer is full
+                    // tx buffer is full, or waiting for the pacing
+                    Err(AxError::WouldBlock)
+                }
+            })
+        })
+    }
+
+    /// Transmits data written straight to the transmit buffer, without
+    /// copying it in.
+    ///
+    /// The closure is called with the contiguous writable part of the
+    /// transmit buffer and returns how many bytes it wrote there, which is
+    /// returned. Its errors are returned as is.
+    ///
+    /// The closure runs with the socket set locked: it must not use any
+    /// socket nor block (e.g. on disk I/O), but only fill the buffer.
+    pub fn send_with<F>(&self, f: F) -> AxResult<usize>
+    where
+        F: FnOnce(&mut [u8]) -> AxResult<usize>,
+    {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, "socket send_with() failed");
+        }
+
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        let mut f = Some(f);
+        self.block_on(|| {
+            let quota = tcp_info::pacing_quota(handle);
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                if !socket.is_active() || !socket.may_send() {
+                    // closed by remote
+                    ax_err!(ConnectionReset, "socket send_with() failed")
+                } else if socket.can_send() && quota > 0 {
+                    // connected, hand the tx buffer to the producer
+                    let f = f.take().unwrap();
+                    let res = socket
+                        .send(|buf| {
+                            let buf_len = buf.len().min(quota);
+                            match f(&mut buf[..buf_len]) {
+                                Ok(len) => {
+                                    let len = len.min(buf_len);
+                                    (len, Ok(len))
+                                }
+                                Err(e) => (0, Err(e)),
+                            }
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket send_with() failed"))?;
+                    if let Ok(len) = res {
+                        tcp_info::on_queued(handle, len);
+                    }
+                    res
+                } else {
+                    // tx buffer is full, or waiting for the pacing
                     Err(AxError::WouldBlock)
                 }
             })
         })
     }
 
+    /// Returns the RTT, retransmission and pacing metrics of the connection.
+    ///
+    /// They are all zero (and the pacing rate unlimited) if the socket is not
+    /// connected.
+    pub fn tcp_info(&self) -> TcpInfo {
+        match self.get_state() {
+            STATE_CONNECTING | STATE_CONNECTED => {
+                // SAFETY: `self.handle` should be initialized in a connected socket.
+                let handle = unsafe { self.handle.get().read().unwrap() };
+                tcp_info::info(handle).unwrap_or_default()
+            }
+            _ => TcpInfo::default(),
+        }
+    }
+
     /// Whether the socket is readable or writable.
     pub fn poll(&self) -> AxResult<PollState> {
         match self.get_state() {
@@ -455,7 +640,8 @@ impl TcpSocket {
         SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
             Ok(PollState {
                 readable: !socket.may_recv() || socket.can_recv(),
-                writable: !socket.may_send() || socket.can_send(),
+                writable: !socket.may_send()
+                    || (socket.can_send() && tcp_info::pacing_quota(handle) > 0),
             })
         })
     }
@@ -481,14 +667,19 @@ impl TcpSocket {
         if self.is_nonblocking() {
             f()
         } else {
-            loop {
+            // poll again at once, then less and less often
+            let mut res = None;
+            spin_wait_until(|| {
                 SOCKET_SET.poll_interfaces();
                 match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
-                    Err(e) => return Err(e),
+                    Err(AxError::WouldBlock) => false,
+                    r => {
+                        res = Some(r);
+                        true
+                    }
                 }
-            }
+            });
+            res.unwrap()
         }
     }
 }
@@ -498,6 +689,7 @@ impl Drop for TcpSocket {
         self.shutdown().ok();
         // Safe because we have mut reference to `self`.
         if let Some(handle) = unsafe { self.handle.get().read() } {
+            tcp_info::unregister(handle);
             SOCKET_SET.remove(handle);
         }
     }
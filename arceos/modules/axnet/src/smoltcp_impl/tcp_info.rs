@@ -0,0 +1,302 @@
+//! Per-connection TCP metrics and egress pacing.
+//!
+//! smoltcp keeps its RTT estimator to itself, so the segments of the
+//! connections are snooped on their way to and from the NIC. The transmitted
+//! segments give the retransmissions, and the ACKs of the timed segments give
+//! the RTT samples (RFC 6298, skipping the retransmitted segments as Karn's
+//! algorithm).
+//!
+//! The pacing rate is a gain over the delivery rate measured from the
+//! acknowledged bytes. The data of a paced socket enters its transmit buffer
+//! no faster than that rate, a quantum at a time, so the stack never sends
+//! the whole buffer in one burst.
+
+use alloc::vec::Vec;
+
+use axhal::time::{monotonic_time_nanos, NANOS_PER_MICROS, NANOS_PER_MILLIS, NANOS_PER_SEC};
+use axsync::Mutex;
+use smoltcp::iface::SocketHandle;
+use smoltcp::wire::{EthernetFrame, IpEndpoint, IpProtocol, Ipv4Packet, TcpPacket};
+
+/// The maximum segment size on the Ethernet.
+const MSS: u64 = 1460;
+/// The initial congestion window in segments (RFC 6928).
+const INIT_CWND: u64 = 10;
+/// The pacing rate over the delivery rate, which leaves room for the
+/// connection to speed up.
+const PACING_GAIN: u64 = 2;
+
+/// TCP metrics of a connection, as the `struct tcp_info` of Linux.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpInfo {
+    /// The smoothed RTT in microseconds, 0 before the first sample.
+    pub rtt_us: u32,
+    /// The RTT variation in microseconds.
+    pub rttvar_us: u32,
+    /// The minimum RTT in microseconds.
+    pub min_rtt_us: u32,
+    /// The number of segments sent, including the retransmitted ones.
+    pub segs_out: u32,
+    /// The number of segments received.
+    pub segs_in: u32,
+    /// The number of segments retransmitted.
+    pub total_retrans: u32,
+    /// The payload bytes sent, without the retransmissions.
+    pub bytes_sent: u64,
+    /// The payload bytes retransmitted.
+    pub bytes_retrans: u64,
+    /// The bytes acknowledged by the peer.
+    pub bytes_acked: u64,
+    /// The bytes sent but not acknowledged yet.
+    pub bytes_in_flight: u32,
+    /// The last measured delivery rate in bytes per second.
+    pub delivery_rate: u64,
+    /// The pacing rate in bytes per second, [`u64::MAX`] if the connection
+    /// is not paced (yet).
+    pub pacing_rate: u64,
+}
+
+impl Default for TcpInfo {
+    fn default() -> Self {
+        Self {
+            rtt_us: 0,
+            rttvar_us: 0,
+            min_rtt_us: 0,
+            segs_out: 0,
+            segs_in: 0,
+            total_retrans: 0,
+            bytes_sent: 0,
+            bytes_retrans: 0,
+            bytes_acked: 0,
+            bytes_in_flight: 0,
+            delivery_rate: 0,
+            pacing_rate: u64::MAX,
+        }
+    }
+}
+
+/// Whether the sequence number `a` is after `b`.
+const fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+struct ConnStats {
+    handle: SocketHandle,
+    local: IpEndpoint,
+    remote: IpEndpoint,
+    info: TcpInfo,
+    /// The sequence number after the last new byte sent, `None` before the
+    /// first segment.
+    snd_max: Option<u32>,
+    /// The first unacknowledged sequence number.
+    snd_una: u32,
+    /// The end sequence number of the timed segment, and when it was sent.
+    timed: Option<(u32, u64)>,
+    srtt_ns: u64,
+    rttvar_ns: u64,
+    min_rtt_ns: u64,
+    /// The start of the delivery rate interval, and the bytes acknowledged
+    /// in it.
+    interval_start: u64,
+    interval_acked: u64,
+    /// When the paced transmit buffer can take more data.
+    next_send: u64,
+}
+
+impl ConnStats {
+    fn new(handle: SocketHandle, local: IpEndpoint, remote: IpEndpoint) -> Self {
+        Self {
+            handle,
+            local,
+            remote,
+            info: TcpInfo::default(),
+            snd_max: None,
+            snd_una: 0,
+            timed: None,
+            srtt_ns: 0,
+            rttvar_ns: 0,
+            min_rtt_ns: u64::MAX,
+            interval_start: monotonic_time_nanos(),
+            interval_acked: 0,
+            next_send: 0,
+        }
+    }
+
+    fn on_transmit(&mut self, seq: u32, seg_len: u32, payload_len: u32, now: u64) {
+        self.info.segs_out = self.info.segs_out.wrapping_add(1);
+        if seg_len == 0 {
+            return; // pure ACK
+        }
+        let end = seq.wrapping_add(seg_len);
+        match self.snd_max {
+            Some(max) if !seq_after(end, max) => {
+                self.info.total_retrans = self.info.total_retrans.wrapping_add(1);
+                self.info.bytes_retrans += payload_len as u64;
+                if self
+                    .timed
+                    .is_some_and(|(timed_end, _)| seq_after(timed_end, seq))
+                {
+                    self.timed = None; // Karn's algorithm
+                }
+            }
+            max => {
+                let new = max.map_or(payload_len, |max| end.wrapping_sub(max));
+                if max.is_none() {
+                    self.snd_una = seq;
+                }
+                self.snd_max = Some(end);
+                self.info.bytes_sent += new.min(payload_len) as u64;
+                if self.timed.is_none() {
+                    self.timed = Some((end, now));
+                }
+            }
+        }
+    }
+
+    fn on_receive(&mut self, ack: Option<u32>, now: u64) {
+        self.info.segs_in = self.info.segs_in.wrapping_add(1);
+        let (Some(ack), Some(snd_max)) = (ack, self.snd_max) else {
+            return;
+        };
+        if seq_after(ack, self.snd_una) && !seq_after(ack, snd_max) {
+            let acked = ack.wrapping_sub(self.snd_una) as u64;
+            self.snd_una = ack;
+            self.info.bytes_acked += acked;
+            self.interval_acked += acked;
+        }
+        if let Some((_, sent)) = self.timed.filter(|&(end, _)| !seq_after(end, ack)) {
+            self.timed = None;
+            self.sample_rtt(now.saturating_sub(sent));
+        }
+        let elapsed = now.saturating_sub(self.interval_start);
+        if self.srtt_ns > 0 && elapsed >= self.srtt_ns {
+            self.info.delivery_rate = self.interval_acked * NANOS_PER_SEC / elapsed;
+            self.interval_start = now;
+            self.interval_acked = 0;
+            self.update_pacing_rate();
+        }
+    }
+
+    fn sample_rtt(&mut self, rtt: u64) {
+        let rtt = rtt.max(1);
+        if self.srtt_ns == 0 {
+            self.srtt_ns = rtt;
+            self.rttvar_ns = rtt / 2;
+            self.update_pacing_rate();
+        } else {
+            self.rttvar_ns = (3 * self.rttvar_ns + self.srtt_ns.abs_diff(rtt)) / 4;
+            self.srtt_ns = (7 * self.srtt_ns + rtt) / 8;
+        }
+        self.min_rtt_ns = self.min_rtt_ns.min(rtt);
+    }
+
+    fn update_pacing_rate(&mut self) {
+        // Never below the initial window per RTT, as after an idle period.
+        let floor = INIT_CWND * MSS * NANOS_PER_SEC / self.srtt_ns;
+        self.info.pacing_rate = PACING_GAIN * self.info.delivery_rate.max(floor);
+    }
+
+    fn info(&self) -> TcpInfo {
+        let to_us = |ns: u64| (ns / NANOS_PER_MICROS).min(u32::MAX as u64) as u32;
+        TcpInfo {
+            rtt_us: to_us(self.srtt_ns),
+            rttvar_us: to_us(self.rttvar_ns),
+            min_rtt_us: if self.min_rtt_ns == u64::MAX {
+                0
+            } else {
+                to_us(self.min_rtt_ns)
+            },
+            bytes_in_flight: self.snd_max.map_or(0, |max| max.wrapping_sub(self.snd_una)),
+            ..self.info
+        }
+    }
+}
+
+static TCP_STATS: Mutex<Vec<ConnStats>> = Mutex::new(Vec::new());
+
+fn with_conn(local: IpEndpoint, remote: IpEndpoint, f: impl FnOnce(&mut ConnStats)) {
+    let mut stats = TCP_STATS.lock();
+    if let Some(conn) = stats
+        .iter_mut()
+        .find(|c| c.local == local && c.remote == remote)
+    {
+        f(conn);
+    }
+}
+
+/// Starts tracking the connection of the socket `handle`.
+pub(crate) fn register(handle: SocketHandle, local: IpEndpoint, remote: IpEndpoint) {
+    let mut stats = TCP_STATS.lock();
+    stats.retain(|c| c.handle != handle);
+    stats.push(ConnStats::new(handle, local, remote));
+}
+
+/// Stops tracking the connection of the socket `handle`.
+pub(crate) fn unregister(handle: SocketHandle) {
+    TCP_STATS.lock().retain(|c| c.handle != handle);
+}
+
+/// Returns the metrics of the connection of the socket `handle`.
+pub(crate) fn info(handle: SocketHandle) -> Option<TcpInfo> {
+    let stats = TCP_STATS.lock();
+    stats.iter().find(|c| c.handle == handle).map(|c| c.info())
+}
+
+/// Returns how many bytes the socket `handle` can put in its transmit buffer
+/// now, 0 if it has to wait for the pacing.
+pub(crate) fn pacing_quota(handle: SocketHandle) -> usize {
+    let now = monotonic_time_nanos();
+    let stats = TCP_STATS.lock();
+    match stats.iter().find(|c| c.handle == handle) {
+        Some(c) if c.info.pacing_rate != u64::MAX => {
+            if now < c.next_send {
+                0
+            } else {
+                // About 1ms worth of data, at least 2 segments.
+                (c.info.pacing_rate / (NANOS_PER_SEC / NANOS_PER_MILLIS)).max(2 * MSS) as usize
+            }
+        }
+        _ => usize::MAX,
+    }
+}
+
+/// Records that `len` bytes were put in the transmit buffer of the socket
+/// `handle`, delaying the next ones by the pacing.
+pub(crate) fn on_queued(handle: SocketHandle, len: usize) {
+    let now = monotonic_time_nanos();
+    let mut stats = TCP_STATS.lock();
+    if let Some(c) = stats.iter_mut().find(|c| c.handle == handle) {
+        if c.info.pacing_rate != u64::MAX {
+            let delay = len as u64 * NANOS_PER_SEC / c.info.pacing_rate.max(1);
+            c.next_send = c.next_send.max(now) + delay;
+        }
+    }
+}
+
+/// Updates the metrics of the connection sending the frame `buf`.
+pub(super) fn snoop_transmit(buf: &[u8]) -> Result<(), smoltcp::wire::Error> {
+    let ether_frame = EthernetFrame::new_checked(buf)?;
+    let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
+
+    if ipv4_packet.next_header() == IpProtocol::Tcp {
+        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
+        let local = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
+        let remote = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
+        let payload_len = tcp_packet.payload().len() as u32;
+        let seg_len = payload_len + tcp_packet.syn() as u32 + tcp_packet.fin() as u32;
+        let seq = tcp_packet.seq_number().0 as u32;
+        let now = monotonic_time_nanos();
+        with_conn(local, remote, |c| {
+            c.on_transmit(seq, seg_len, payload_len, now)
+        });
+    }
+    Ok(())
+}
+
+/// Updates the metrics of the connection from `local` to `remote` receiving
+/// the segment `tcp_packet`.
+pub(super) fn snoop_receive(local: IpEndpoint, remote: IpEndpoint, tcp_packet: &TcpPacket<&[u8]>) {
+    let ack = tcp_packet.ack().then(|| tcp_packet.ack_number().0 as u32);
+    let now = monotonic_time_nanos();
+    with_conn(local, remote, |c| c.on_receive(ack, now));
+}
@@ -3,7 +3,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
-use axsync::Mutex;
+use axsync::{spin_wait_until, Mutex};
 use spin::RwLock;
 
 use smoltcp::iface::SocketHandle;
@@ -259,14 +259,19 @@ impl UdpSocket {
         if self.is_nonblocking() {
             f()
         } else {
-            loop {
+            // poll again at once, then less and less often
+            let mut res = None;
+            spin_wait_until(|| {
                 SOCKET_SET.poll_interfaces();
                 match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
-                    Err(e) => return Err(e),
+                    Err(AxError::WouldBlock) => false,
+                    r => {
+                        res = Some(r);
+                        true
+                    }
                 }
-            }
+            });
+            res.unwrap()
         }
     }
 }
@@ -0,0 +1,555 @@
+//! AF_XDP-style packet rings, bypassing the TCP/IP stack, with the `xdp`
+//! feature.
+//!
+//! An [`XdpSocket`] owns a region of pages, mappable in a user address space
+//! by [`XdpSocket::map_into`], holding a UMEM of `frame_count` frames and
+//! four single-producer single-consumer descriptor rings, as AF_XDP of
+//! Linux:
+//!
+//! - fill: the frames given by the application to receive into.
+//! - rx: the frames received, as [`XdpDesc`].
+//! - tx: the frames to transmit, as [`XdpDesc`].
+//! - completion: the frames transmitted, given back to the application.
+//!
+//! The socket is bound to the RX queue of the device, the only one, with an
+//! [`XdpFilter`] steering the frames it matches to its rx ring, the other
+//! frames going to the stack as usual. The frames are copied between the
+//! buffers of the driver and the UMEM at the polls of the device, so the
+//! application moves batches of frames without a syscall per frame: it only
+//! calls [`XdpSocket::kick`] to have the tx ring sent, and
+//! [`XdpSocket::wait_rx`] to sleep for frames.
+//!
+//! The layout shared is the [`XdpHeader`] page followed by the entries of
+//! the rings, at the offsets it gives: `u64` addresses in the fill and
+//! completion rings, [`XdpDesc`] in the rx and tx rings. The addresses are
+//! offsets in the UMEM; the fill ring may give any address in a frame, it
+//! is received at the start of the frame. The `producer` and `consumer`
+//! counters of the rings count the entries written and read, wrapping at
+//! `u32::MAX`.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use axalloc::global_allocator;
+use axdriver::prelude::*;
+use axdriver_net::NetBufPtr;
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::mem::{virt_to_phys, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use axsync::spin::SpinNoIrq;
+use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet};
+use smoltcp::wire::{TcpPacket, UdpPacket};
+
+use super::SOCKET_SET;
+use crate::NetPort;
+
+/// The magic of [`XdpHeader::magic`], "XDP0".
+pub const XDP_MAGIC: u32 = u32::from_le_bytes(*b"XDP0");
+
+/// The largest number of entries of a ring.
+pub const MAX_RING_SIZE: usize = 1 << 14;
+
+/// The largest region of a socket, the UMEM and the rings.
+const MAX_REGION_SIZE: usize = 1 << 30;
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// The counters of a ring, in [`XdpHeader`].
+///
+/// `producer` and `consumer` are in their own cache line.
+#[repr(C)]
+pub struct XdpRingHeader {
+    /// The entries written by the producer.
+    pub producer: AtomicU32,
+    _pad0: [u32; 15],
+    /// The entries read by the consumer.
+    pub consumer: AtomicU32,
+    _pad1: [u32; 15],
+}
+
+/// The header of the region of a socket, in its first page.
+///
+/// The offsets are from the start of the region, page-aligned.
+#[repr(C)]
+pub struct XdpHeader {
+    /// [`XDP_MAGIC`].
+    pub magic: u32,
+    /// The bytes of a frame of the UMEM, a power of two.
+    pub frame_size: u32,
+    /// The frames of the UMEM.
+    pub frame_count: u32,
+    /// The entries of each ring, a power of two.
+    pub ring_size: u32,
+    /// The offset of the entries of the fill ring.
+    pub fill_offset: u32,
+    /// The offset of the entries of the completion ring.
+    pub completion_offset: u32,
+    /// The offset of the entries of the rx ring.
+    pub rx_offset: u32,
+    /// The offset of the entries of the tx ring.
+    pub tx_offset: u32,
+    /// The offset of the UMEM.
+    pub umem_offset: u32,
+    _pad: [u32; 7],
+    /// The fill ring, produced by the application.
+    pub fill: XdpRingHeader,
+    /// The completion ring, produced by the kernel.
+    pub completion: XdpRingHeader,
+    /// The rx ring, produced by the kernel.
+    pub rx: XdpRingHeader,
+    /// The tx ring, produced by the application.
+    pub tx: XdpRingHeader,
+}
+
+/// A frame in the UMEM, in the rx and tx rings.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XdpDesc {
+    /// The offset of the frame in the UMEM.
+    pub addr: u64,
+    /// The bytes of the frame.
+    pub len: u32,
+    /// Unused, zero.
+    pub options: u32,
+}
+
+/// The frames steered to a socket, instead of the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XdpFilter {
+    /// All the frames: the stack gets none.
+    All,
+    /// The frames of an EtherType, e.g. `0x88b5` for local experiments.
+    EtherType(u16),
+    /// The IPv4 packets of an IP protocol number.
+    IpProtocol(u8),
+    /// The UDP datagrams to a port, over IPv4.
+    UdpPort(u16),
+    /// The TCP segments to a port, over IPv4.
+    TcpPort(u16),
+}
+
+impl XdpFilter {
+    fn matches(&self, frame: &[u8]) -> bool {
+        let Ok(ether_frame) = EthernetFrame::new_checked(frame) else {
+            return false;
+        };
+        let ethertype = ether_frame.ethertype();
+        match *self {
+            Self::All => return true,
+            Self::EtherType(ty) => return u16::from(ethertype) == ty,
+            _ => {}
+        }
+        if ethertype != EthernetProtocol::Ipv4 {
+            return false;
+        }
+        let Ok(ipv4_packet) = Ipv4Packet::new_checked(ether_frame.payload()) else {
+            return false;
+        };
+        let proto = ipv4_packet.next_header();
+        match *self {
+            Self::IpProtocol(p) => u8::from(proto) == p,
+            // The later fragments have no port, they go to the stack.
+            _ if ipv4_packet.frag_offset() != 0 => false,
+            Self::UdpPort(port) => {
+                proto == IpProtocol::Udp
+                    && UdpPacket::new_checked(ipv4_packet.payload())
+                        .is_ok_and(|udp| udp.dst_port() == port)
+            }
+            Self::TcpPort(port) => {
+                proto == IpProtocol::Tcp
+                    && TcpPacket::new_checked(ipv4_packet.payload())
+                        .is_ok_and(|tcp| tcp.dst_port() == port)
+            }
+            Self::All | Self::EtherType(_) => unreachable!(),
+        }
+    }
+}
+
+/// The sizes of the region of a socket.
+#[derive(Debug, Clone, Copy)]
+pub struct XdpConfig {
+    /// The bytes of a frame of the UMEM, a power of two of 2048 to 4096,
+    /// holding a frame of the [`mtu`](super::mtu).
+    pub frame_size: usize,
+    /// The frames of the UMEM.
+    pub frame_count: usize,
+    /// The entries of each ring, a power of two up to [`MAX_RING_SIZE`].
+    pub ring_size: usize,
+}
+
+impl Default for XdpConfig {
+    fn default() -> Self {
+        Self {
+            frame_size: 2048,
+            frame_count: 1024,
+            ring_size: 512,
+        }
+    }
+}
+
+/// The statistics of a socket.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XdpStats {
+    /// The frames put in the rx ring.
+    pub rx_packets: u64,
+    /// The frames steered to the socket but dropped: the fill ring empty,
+    /// the rx ring full, or the frame larger than a frame of the UMEM.
+    pub rx_dropped: u64,
+    /// The frames of the tx ring transmitted.
+    pub tx_packets: u64,
+    /// The descriptors of the application out of the UMEM, skipped.
+    pub invalid_descs: u64,
+}
+
+#[derive(Clone, Copy)]
+enum RingId {
+    Fill,
+    Completion,
+    Rx,
+    Tx,
+}
+
+/// The pages of a socket, freed when the last reference is dropped.
+struct XdpRegion {
+    vaddr: usize,
+    pages: usize,
+    config: XdpConfig,
+    filter: XdpFilter,
+    rx_packets: AtomicU64,
+    rx_dropped: AtomicU64,
+    tx_packets: AtomicU64,
+    invalid_descs: AtomicU64,
+}
+
+/// The sockets bound, the first matching a frame gets it.
+static SOCKETS: SpinNoIrq<Vec<Arc<XdpRegion>>> = SpinNoIrq::new(Vec::new());
+
+impl XdpRegion {
+    fn new(config: XdpConfig, filter: XdpFilter) -> AxResult<Self> {
+        let XdpConfig {
+            frame_size,
+            frame_count,
+            ring_size,
+        } = config;
+        if !frame_size.is_power_of_two()
+            || !(2048..=PAGE_SIZE_4K).contains(&frame_size)
+            || frame_size < super::mtu() + ETHERNET_HEADER_LEN
+        {
+            return ax_err!(InvalidInput, "invalid XDP frame size");
+        }
+        if !ring_size.is_power_of_two() || ring_size > MAX_RING_SIZE {
+            return ax_err!(InvalidInput, "invalid XDP ring size");
+        }
+        let addr_ring = (ring_size * 8).next_multiple_of(PAGE_SIZE_4K);
+        let desc_ring =
+            (ring_size * core::mem::size_of::<XdpDesc>()).next_multiple_of(PAGE_SIZE_4K);
+        let umem_offset = PAGE_SIZE_4K + 2 * (addr_ring + desc_ring);
+        let size = frame_count
+            .checked_mul(frame_size)
+            .and_then(|umem| umem.checked_add(umem_offset))
+            .filter(|&size| frame_count > 0 && size <= MAX_REGION_SIZE)
+            .ok_or(AxError::InvalidInput)?;
+
+        let pages = size.div_ceil(PAGE_SIZE_4K);
+        let vaddr = global_allocator()
+            .alloc_pages(pages, PAGE_SIZE_4K)
+            .map_err(|_| AxError::NoMemory)?;
+        // SAFETY: the pages were just allocated for the region.
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, pages * PAGE_SIZE_4K) };
+        // SAFETY: the header page is zeroed and only ours yet.
+        unsafe {
+            let header = &mut *(vaddr as *mut XdpHeader);
+            header.magic = XDP_MAGIC;
+            header.frame_size = frame_size as u32;
+            header.frame_count = frame_count as u32;
+            header.ring_size = ring_size as u32;
+            header.fill_offset = PAGE_SIZE_4K as u32;
+            header.completion_offset = (PAGE_SIZE_4K + addr_ring) as u32;
+            header.rx_offset = (PAGE_SIZE_4K + 2 * addr_ring) as u32;
+            header.tx_offset = (PAGE_SIZE_4K + 2 * addr_ring + desc_ring) as u32;
+            header.umem_offset = umem_offset as u32;
+        }
+        Ok(Self {
+            vaddr,
+            pages,
+            config,
+            filter,
+            rx_packets: AtomicU64::new(0),
+            rx_dropped: AtomicU64::new(0),
+            tx_packets: AtomicU64::new(0),
+            invalid_descs: AtomicU64::new(0),
+        })
+    }
+
+    fn header(&self) -> &XdpHeader {
+        // SAFETY: the header page lives as long as the region, and the fields
+        // written by the application are atomics.
+        unsafe { &*(self.vaddr as *const XdpHeader) }
+    }
+
+    fn size(&self) -> usize {
+        self.pages * PAGE_SIZE_4K
+    }
+
+    /// The counters and the entries of a ring.
+    fn ring<T>(&self, id: RingId) -> (&XdpRingHeader, *mut T) {
+        let header = self.header();
+        let (ring, offset) = match id {
+            RingId::Fill => (&header.fill, header.fill_offset),
+            RingId::Completion => (&header.completion, header.completion_offset),
+            RingId::Rx => (&header.rx, header.rx_offset),
+            RingId::Tx => (&header.tx, header.tx_offset),
+        };
+        (ring, (self.vaddr + offset as usize) as *mut T)
+    }
+
+    /// The entries the kernel may write in a ring it produces.
+    fn free(&self, id: RingId) -> usize {
+        let (ring, _) = self.ring::<u64>(id);
+        let used = ring
+            .producer
+            .load(Ordering::Relaxed)
+            .wrapping_sub(ring.consumer.load(Ordering::Acquire)) as usize;
+        self.config.ring_size.saturating_sub(used)
+    }
+
+    /// Writes an entry in a ring the kernel produces, if it is not full.
+    fn produce<T: Copy>(&self, id: RingId, entry: T) -> bool {
+        if self.free(id) == 0 {
+            return false;
+        }
+        let (ring, entries) = self.ring::<T>(id);
+        let prod = ring.producer.load(Ordering::Relaxed);
+        let idx = prod as usize & (self.config.ring_size - 1);
+        // SAFETY: the index is in the ring, not read by the application until
+        // the producer moves.
+        unsafe { entries.add(idx).write_volatile(entry) };
+        ring.producer.store(prod.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Reads the next entry of a ring the kernel consumes, without taking it.
+    fn peek<T: Copy>(&self, id: RingId) -> Option<T> {
+        let (ring, entries) = self.ring::<T>(id);
+        let cons = ring.consumer.load(Ordering::Relaxed);
+        if ring.producer.load(Ordering::Acquire) == cons {
+            return None;
+        }
+        let idx = cons as usize & (self.config.ring_size - 1);
+        // SAFETY: the index is in the ring, written by the application before
+        // the producer moved.
+        Some(unsafe { entries.add(idx).read_volatile() })
+    }
+
+    /// Takes the entry read by [`peek`](Self::peek).
+    fn advance(&self, id: RingId) {
+        let (ring, _) = self.ring::<u64>(id);
+        ring.consumer.fetch_add(1, Ordering::Release);
+    }
+
+    /// The UMEM bytes of a descriptor, if it is within one of its frames.
+    fn frame(&self, addr: u64, len: usize) -> Option<&mut [u8]> {
+        let frame_size = self.config.frame_size;
+        let addr = usize::try_from(addr).ok()?;
+        if addr / frame_size >= self.config.frame_count || addr % frame_size + len > frame_size {
+            self.invalid_descs.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let start = self.vaddr + self.header().umem_offset as usize + addr;
+        // SAFETY: the bytes are in the UMEM, owned by the kernel until the
+        // descriptor is given back.
+        Some(unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) })
+    }
+
+    /// Copies a frame received into the UMEM, and puts it in the rx ring.
+    fn receive(&self, packet: &[u8]) {
+        if packet.len() > self.config.frame_size || self.free(RingId::Rx) == 0 {
+            self.rx_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let Some(addr) = self.peek::<u64>(RingId::Fill) else {
+            self.rx_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        self.advance(RingId::Fill);
+        // The frame is received at its start.
+        let addr = addr & !(self.config.frame_size as u64 - 1);
+        let Some(frame) = self.frame(addr, packet.len()) else {
+            self.rx_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        frame.copy_from_slice(packet);
+        let desc = XdpDesc {
+            addr,
+            len: packet.len() as u32,
+            options: 0,
+        };
+        self.produce(RingId::Rx, desc);
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Transmits the frames of the tx ring, while the device and the
+    /// completion ring have room.
+    fn transmit(&self, dev: &mut NetPort) {
+        while self.free(RingId::Completion) > 0 && dev.can_transmit() {
+            let Some(desc) = self.peek::<XdpDesc>(RingId::Tx) else {
+                break;
+            };
+            let Some(frame) = self.frame(desc.addr, desc.len as usize) else {
+                self.advance(RingId::Tx);
+                continue;
+            };
+            let mut tx_buf = match dev.alloc_tx_buffer(frame.len()) {
+                Ok(buf) => buf,
+                Err(e) => {
+                    warn!("XDP alloc_tx_buffer failed: {:?}", e);
+                    break;
+                }
+            };
+            tx_buf.packet_mut().copy_from_slice(frame);
+            self.advance(RingId::Tx);
+            match dev.transmit(tx_buf) {
+                Ok(()) => super::iface::count_tx(frame.len()),
+                Err(e) => {
+                    warn!("XDP transmit failed: {:?}", e);
+                    super::iface::count_tx_error();
+                }
+            }
+            self.produce(RingId::Completion, desc.addr);
+            self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for XdpRegion {
+    fn drop(&mut self) {
+        global_allocator().dealloc_pages(self.vaddr, self.pages);
+    }
+}
+
+/// A socket sending and receiving raw frames through shared rings, see the
+/// [module documentation](self).
+pub struct XdpSocket {
+    region: Arc<XdpRegion>,
+}
+
+impl XdpSocket {
+    /// Allocates the region of `config`, and steers the frames matching
+    /// `filter` to it.
+    ///
+    /// Returns [`Err(AddrInUse)`](AxError::AddrInUse) if another socket has
+    /// the same filter.
+    pub fn bind(filter: XdpFilter, config: XdpConfig) -> AxResult<Self> {
+        let region = Arc::new(XdpRegion::new(config, filter)?);
+        let mut sockets = SOCKETS.lock();
+        if sockets.iter().any(|s| s.filter == filter) {
+            return ax_err!(AddrInUse, "XDP socket bind() failed");
+        }
+        sockets.push(region.clone());
+        debug!("XDP socket {:?}: bound, {} bytes", filter, region.size());
+        Ok(Self { region })
+    }
+
+    /// Returns the filter of the socket.
+    pub fn filter(&self) -> XdpFilter {
+        self.region.filter
+    }
+
+    /// Returns the sizes of the region.
+    pub fn config(&self) -> XdpConfig {
+        self.region.config
+    }
+
+    /// The header of the region, shared with the address spaces it is
+    /// mapped into.
+    pub fn header(&self) -> &XdpHeader {
+        self.region.header()
+    }
+
+    /// The region in the kernel address space, starting with the header.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.region.vaddr as *mut u8
+    }
+
+    /// The bytes of the region, whole pages.
+    pub fn size(&self) -> usize {
+        self.region.size()
+    }
+
+    /// The physical address of the region, contiguous.
+    pub fn paddr(&self) -> PhysAddr {
+        virt_to_phys(VirtAddr::from(self.region.vaddr))
+    }
+
+    /// Maps the region at `start` in `aspace`, with `flags`.
+    ///
+    /// The frames are not freed when it is unmapped: the owner of `aspace`
+    /// must keep the socket as long as it is mapped.
+    pub fn map_into(
+        &self,
+        aspace: &mut AddrSpace,
+        start: VirtAddr,
+        flags: MappingFlags,
+    ) -> AxResult {
+        aspace.map_shared(start, self.paddr(), self.size(), flags)
+    }
+
+    /// Transmits the frames of the tx ring, and receives the frames pending
+    /// into the rx ring.
+    pub fn kick(&self) {
+        SOCKET_SET.poll_interfaces();
+    }
+
+    /// Sleeps until the rx ring is not empty.
+    pub fn wait_rx(&self) {
+        let rx = &self.region.header().rx;
+        loop {
+            self.kick();
+            if rx.producer.load(Ordering::Acquire) != rx.consumer.load(Ordering::Acquire) {
+                return;
+            }
+            axtask::yield_now();
+        }
+    }
+
+    /// Returns the statistics of the socket.
+    pub fn stats(&self) -> XdpStats {
+        let region = &self.region;
+        XdpStats {
+            rx_packets: region.rx_packets.load(Ordering::Relaxed),
+            rx_dropped: region.rx_dropped.load(Ordering::Relaxed),
+            tx_packets: region.tx_packets.load(Ordering::Relaxed),
+            invalid_descs: region.invalid_descs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for XdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().retain(|s| !Arc::ptr_eq(s, &self.region));
+        debug!("XDP socket {:?}: unbound", self.region.filter);
+    }
+}
+
+/// Transmits the tx rings of the sockets, from the poll of the device.
+pub(super) fn transmit(dev: &mut NetPort) {
+    for region in SOCKETS.lock().iter() {
+        region.transmit(dev);
+    }
+}
+
+/// Takes the buffer if a socket has a filter matching its frame, or gives
+/// it back.
+pub(super) fn steer(rx_buf: NetBufPtr, dev: &mut NetPort) -> Option<NetBufPtr> {
+    let sockets = SOCKETS.lock();
+    let Some(region) = sockets.iter().find(|s| s.filter.matches(rx_buf.packet())) else {
+        return Some(rx_buf);
+    };
+    region.receive(rx_buf.packet());
+    drop(sockets);
+    if let Err(e) = dev.recycle_rx_buffer(rx_buf) {
+        warn!("recycle_rx_buffer failed: {:?}", e);
+    }
+    None
+}
@@ -0,0 +1,319 @@
+//! Zero-copy receive of UDP datagrams, lending the RX buffers of the driver.
+//!
+//! The datagrams to the port of a [`ZeroCopyUdpSocket`] are taken before the
+//! TCP/IP stack, and [`ZeroCopyUdpSocket::recv_ref`] lends the buffer of the
+//! driver holding one as an [`RxPacket`], until it is released. The buffers
+//! lent are out of the RX ring of the device: held for longer than the
+//! [lend timeout](set_lend_timeout), or past [`MAX_LENT`] buffers lent, the
+//! oldest ones are copied out into the heap and given back to the driver,
+//! the packets still reading the same bytes, so a slow application does not
+//! starve the device.
+//!
+//! The IPv4 datagrams only are taken, not the fragmented ones, with their
+//! checksums checked.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::net::SocketAddr;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axdriver::prelude::*;
+use axdriver_net::NetBufPtr;
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::monotonic_time_nanos;
+use axsync::spin::SpinNoIrq;
+use axsync::Mutex;
+use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpAddress, IpEndpoint, IpProtocol};
+use smoltcp::wire::{Ipv4Packet, UdpPacket};
+
+use super::addr::into_core_sockaddr;
+use super::SOCKET_SET;
+use crate::NetPort;
+
+/// The most buffers lent at once, queued or received.
+pub const MAX_LENT: usize = 64;
+/// The datagrams queued on a socket, the next ones are dropped.
+const QUEUE_LEN: usize = 32;
+const DEFAULT_LEND_TIMEOUT: Duration = Duration::from_millis(10);
+
+static LEND_TIMEOUT_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_LEND_TIMEOUT.as_nanos() as u64);
+static COPIED: AtomicUsize = AtomicUsize::new(0);
+
+/// The sockets by port.
+static SOCKETS: SpinNoIrq<BTreeMap<u16, Arc<SocketQueue>>> = SpinNoIrq::new(BTreeMap::new());
+/// The buffers lent, oldest first.
+static LENT: SpinNoIrq<VecDeque<Weak<LentBuf>>> = SpinNoIrq::new(VecDeque::new());
+/// The buffers released, given back to the driver at the next poll.
+static RELEASED: SpinNoIrq<Vec<RxBuf>> = SpinNoIrq::new(Vec::new());
+
+/// A buffer of the driver, moved between the tasks.
+struct RxBuf(NetBufPtr);
+
+unsafe impl Send for RxBuf {}
+
+enum LentData {
+    Lent(RxBuf),
+    Copied(Vec<u8>),
+    Released,
+}
+
+struct LentBuf {
+    data: Mutex<LentData>,
+    /// The time it was taken from the driver, in nanoseconds.
+    since: u64,
+}
+
+impl LentBuf {
+    /// Copies the packet out and releases the buffer of the driver, unless
+    /// it is being read.
+    fn copy_out(&self) -> bool {
+        let Some(mut data) = self.data.try_lock() else {
+            return false;
+        };
+        if let LentData::Lent(buf) = &*data {
+            let copy = buf.0.packet().to_vec();
+            if let LentData::Lent(buf) = core::mem::replace(&mut *data, LentData::Copied(copy)) {
+                RELEASED.lock().push(buf);
+            }
+            COPIED.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+
+    fn release(&self) {
+        if let LentData::Lent(buf) = core::mem::replace(&mut *self.data.lock(), LentData::Released)
+        {
+            RELEASED.lock().push(buf);
+        }
+    }
+}
+
+struct SocketQueue {
+    packets: SpinNoIrq<VecDeque<RxPacket>>,
+}
+
+/// A UDP datagram received in the buffer of the driver, lent until dropped
+/// or [released](RxPacket::release).
+pub struct RxPacket {
+    buf: Arc<LentBuf>,
+    source: SocketAddr,
+    /// The UDP payload in the Ethernet frame.
+    payload: Range<usize>,
+}
+
+impl RxPacket {
+    /// Returns the address the datagram was sent from.
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// Returns the length of the payload.
+    pub fn len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Whether the payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    /// Calls `f` with the payload of the datagram, in the buffer of the
+    /// driver unless it was copied out.
+    ///
+    /// The packet is not copied out while `f` runs.
+    pub fn with_payload<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        match &*self.buf.data.lock() {
+            LentData::Lent(buf) => f(&buf.0.packet()[self.payload.clone()]),
+            LentData::Copied(copy) => f(&copy[self.payload.clone()]),
+            LentData::Released => unreachable!(),
+        }
+    }
+
+    /// Whether the packet was copied out of the buffer of the driver, being
+    /// held for too long.
+    pub fn is_copied(&self) -> bool {
+        matches!(&*self.buf.data.lock(), LentData::Copied(_))
+    }
+
+    /// Gives the buffer back to the driver, the same as dropping the packet.
+    pub fn release(self) {}
+}
+
+impl Drop for RxPacket {
+    fn drop(&mut self) {
+        self.buf.release();
+    }
+}
+
+/// A UDP socket receiving the datagrams to a port without copying them, see
+/// the [module documentation](self).
+pub struct ZeroCopyUdpSocket {
+    port: u16,
+    queue: Arc<SocketQueue>,
+    nonblock: AtomicBool,
+}
+
+impl ZeroCopyUdpSocket {
+    /// Takes the datagrams to `port`, from the UDP sockets bound to it too.
+    ///
+    /// Returns [`Err(AddrInUse)`](axerrno::AxError::AddrInUse) if another
+    /// zero-copy socket has the port.
+    pub fn bind(port: u16) -> AxResult<Self> {
+        let queue = Arc::new(SocketQueue {
+            packets: SpinNoIrq::new(VecDeque::new()),
+        });
+        let mut sockets = SOCKETS.lock();
+        if sockets.contains_key(&port) {
+            return ax_err!(AddrInUse, "zero-copy socket bind() failed");
+        }
+        sockets.insert(port, queue.clone());
+        debug!("zero-copy socket on port {}: created", port);
+        Ok(Self {
+            port,
+            queue,
+            nonblock: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the port of the socket.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this socket into or out of nonblocking mode, where
+    /// [`recv_ref`](Self::recv_ref) returns
+    /// [`Err(WouldBlock)`](axerrno::AxError::WouldBlock) without datagram.
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Receives a datagram, lending the buffer of the driver holding it.
+    pub fn recv_ref(&self) -> AxResult<RxPacket> {
+        loop {
+            SOCKET_SET.poll_interfaces();
+            if let Some(packet) = self.queue.packets.lock().pop_front() {
+                return Ok(packet);
+            }
+            if self.is_nonblocking() {
+                return Err(AxError::WouldBlock);
+            }
+            axtask::yield_now();
+        }
+    }
+}
+
+impl Drop for ZeroCopyUdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().remove(&self.port);
+        // The packets are released unlocked.
+        let packets = core::mem::take(&mut *self.queue.packets.lock());
+        drop(packets);
+        debug!("zero-copy socket on port {}: destroyed", self.port);
+    }
+}
+
+/// Sets how long a buffer may be lent before being copied out.
+pub fn set_lend_timeout(timeout: Duration) {
+    LEND_TIMEOUT_NANOS.store(timeout.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Returns the buffers lent now, and the ones copied out since boot.
+pub fn zero_copy_stats() -> (usize, usize) {
+    let lent = LENT.lock().iter().filter(|b| b.strong_count() > 0).count();
+    (lent, COPIED.load(Ordering::Relaxed))
+}
+
+/// Gives the buffers released back to the driver, and copies out the ones
+/// lent for too long, or the oldest ones past [`MAX_LENT`], from the poll of
+/// the device.
+pub(super) fn recycle(dev: &mut NetPort) {
+    let now = monotonic_time_nanos();
+    let timeout = LEND_TIMEOUT_NANOS.load(Ordering::Relaxed);
+    {
+        let mut lent = LENT.lock();
+        lent.retain(|b| b.strong_count() > 0);
+        let mut excess = lent.len().saturating_sub(MAX_LENT);
+        for buf in lent.iter().filter_map(Weak::upgrade) {
+            if excess == 0 && now.saturating_sub(buf.since) < timeout {
+                break;
+            }
+            if buf.copy_out() {
+                excess = excess.saturating_sub(1);
+            }
+        }
+    }
+    for buf in core::mem::take(&mut *RELEASED.lock()) {
+        if let Err(e) = dev.recycle_rx_buffer(buf.0) {
+            warn!("recycle_rx_buffer failed: {:?}", e);
+        }
+    }
+}
+
+/// Takes the buffer if it holds a datagram to a zero-copy socket, or gives
+/// it back.
+pub(super) fn divert(rx_buf: NetBufPtr, dev: &mut NetPort) -> Option<NetBufPtr> {
+    let Some((port, source, payload)) = parse_udp(rx_buf.packet()) else {
+        return Some(rx_buf);
+    };
+    let Some(queue) = SOCKETS.lock().get(&port).cloned() else {
+        return Some(rx_buf);
+    };
+    let mut packets = queue.packets.lock();
+    if packets.len() >= QUEUE_LEN {
+        trace!("zero-copy socket on port {}: queue full", port);
+        if let Err(e) = dev.recycle_rx_buffer(rx_buf) {
+            warn!("recycle_rx_buffer failed: {:?}", e);
+        }
+        return None;
+    }
+    let buf = Arc::new(LentBuf {
+        data: Mutex::new(LentData::Lent(RxBuf(rx_buf))),
+        since: monotonic_time_nanos(),
+    });
+    LENT.lock().push_back(Arc::downgrade(&buf));
+    packets.push_back(RxPacket {
+        buf,
+        source,
+        payload,
+    });
+    None
+}
+
+/// Returns the destination port, the source and the payload of the UDP
+/// datagram in the frame, if it is one, unfragmented and with the right
+/// checksums.
+fn parse_udp(frame: &[u8]) -> Option<(u16, SocketAddr, Range<usize>)> {
+    const ETHERNET_HEADER_LEN: usize = 14;
+    const UDP_HEADER_LEN: usize = 8;
+
+    let ether_frame = EthernetFrame::new_checked(frame).ok()?;
+    if ether_frame.ethertype() != EthernetProtocol::Ipv4 {
+        return None;
+    }
+    let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload()).ok()?;
+    if ipv4_packet.next_header() != IpProtocol::Udp
+        || ipv4_packet.more_frags()
+        || ipv4_packet.frag_offset() != 0
+        || !ipv4_packet.verify_checksum()
+    {
+        return None;
+    }
+    let udp_packet = UdpPacket::new_checked(ipv4_packet.payload()).ok()?;
+    let src_addr = IpAddress::Ipv4(ipv4_packet.src_addr());
+    let dst_addr = IpAddress::Ipv4(ipv4_packet.dst_addr());
+    if !udp_packet.verify_checksum(&src_addr, &dst_addr) {
+        return None;
+    }
+    let source = into_core_sockaddr(IpEndpoint::new(src_addr, udp_packet.src_port()));
+    let start = ETHERNET_HEADER_LEN + ipv4_packet.header_len() as usize + UDP_HEADER_LEN;
+    let len = udp_packet.len() as usize - UDP_HEADER_LEN;
+    Some((udp_packet.dst_port(), source, start..start + len))
+}
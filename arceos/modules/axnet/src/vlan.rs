@@ -0,0 +1,120 @@
+//! 802.1Q VLAN sub-interface.
+
+use core::ptr::NonNull;
+
+use axdriver::prelude::*;
+use axdriver_net::{EthernetAddress, NetBufPtr};
+
+use crate::Port;
+
+/// The tag protocol identifier of 802.1Q.
+const TPID_8021Q: u16 = 0x8100;
+/// The length of the 802.1Q tag.
+const TAG_LEN: usize = 4;
+/// The length of the destination and source MAC addresses.
+const ADDRS_LEN: usize = 12;
+
+/// A VLAN sub-interface of a port.
+///
+/// The frames it sends are tagged with its VLAN ID, and it only receives the
+/// frames tagged with it, the other frames of the port are dropped. The
+/// buffers it hands out are the ones of the port, starting after the room for
+/// the tag.
+pub struct Vlan {
+    dev: Port,
+    vid: u16,
+}
+
+impl Vlan {
+    /// Creates the sub-interface of the VLAN `vid` on `dev`.
+    pub fn new(dev: Port, vid: u16) -> Self {
+        assert!((1..0xfff).contains(&vid), "invalid VLAN ID {}", vid);
+        Self { dev, vid }
+    }
+}
+
+/// Skips the room of the tag at the start of the buffer of the port.
+fn skip_tag(mut buf: NetBufPtr) -> NetBufPtr {
+    let raw_ptr = NonNull::new(buf.raw_ptr::<u8>()).unwrap();
+    let data = NonNull::new(buf.packet_mut()[TAG_LEN..].as_mut_ptr()).unwrap();
+    NetBufPtr::new(raw_ptr, data, buf.packet_len() - TAG_LEN)
+}
+
+/// Restores the buffer of the port, with the room of the tag.
+fn restore_tag(mut buf: NetBufPtr) -> NetBufPtr {
+    let raw_ptr = NonNull::new(buf.raw_ptr::<u8>()).unwrap();
+    // SAFETY: the buffer was made by `skip_tag`, the tag room is before it.
+    let data = unsafe { NonNull::new_unchecked(buf.packet_mut().as_mut_ptr().sub(TAG_LEN)) };
+    NetBufPtr::new(raw_ptr, data, buf.packet_len() + TAG_LEN)
+}
+
+impl BaseDriverOps for Vlan {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+
+    fn device_name(&self) -> &str {
+        self.dev.device_name()
+    }
+}
+
+impl NetDriverOps for Vlan {
+    fn mac_address(&self) -> EthernetAddress {
+        self.dev.mac_address()
+    }
+
+    fn can_transmit(&self) -> bool {
+        self.dev.can_transmit()
+    }
+
+    fn can_receive(&self) -> bool {
+        self.dev.can_receive()
+    }
+
+    fn rx_queue_size(&self) -> usize {
+        self.dev.rx_queue_size()
+    }
+
+    fn tx_queue_size(&self) -> usize {
+        self.dev.tx_queue_size()
+    }
+
+    fn recycle_rx_buffer(&mut self, rx_buf: NetBufPtr) -> DevResult {
+        self.dev.recycle_rx_buffer(restore_tag(rx_buf))
+    }
+
+    fn recycle_tx_buffers(&mut self) -> DevResult {
+        self.dev.recycle_tx_buffers()
+    }
+
+    fn transmit(&mut self, tx_buf: NetBufPtr) -> DevResult {
+        let mut tx_buf = restore_tag(tx_buf);
+        let frame = tx_buf.packet_mut();
+        frame.copy_within(TAG_LEN..TAG_LEN + ADDRS_LEN, 0);
+        frame[ADDRS_LEN..ADDRS_LEN + 2].copy_from_slice(&TPID_8021Q.to_be_bytes());
+        // priority 0, drop eligible 0
+        frame[ADDRS_LEN + 2..ADDRS_LEN + 4].copy_from_slice(&self.vid.to_be_bytes());
+        self.dev.transmit(tx_buf)
+    }
+
+    fn receive(&mut self) -> DevResult<NetBufPtr> {
+        loop {
+            let mut rx_buf = self.dev.receive()?;
+            let frame = rx_buf.packet_mut();
+            let tag = |i: usize| u16::from_be_bytes([frame[i], frame[i + 1]]);
+            if frame.len() >= ADDRS_LEN + TAG_LEN + 2
+                && tag(ADDRS_LEN) == TPID_8021Q
+                && tag(ADDRS_LEN + 2) & 0xfff == self.vid
+            {
+                frame.copy_within(0..ADDRS_LEN, TAG_LEN);
+                return Ok(skip_tag(rx_buf));
+            }
+            trace!("VLAN {}: drop a frame of another VLAN", self.vid);
+            self.dev.recycle_rx_buffer(rx_buf)?;
+        }
+    }
+
+    fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr> {
+        self.dev.alloc_tx_buffer(size + TAG_LEN).map(skip_tag)
+    }
+}
@@ -0,0 +1,517 @@
+//! WireGuard tunnel.
+//!
+//! A [`WgTunnel`] is a point-to-point interface to a single peer: the IP
+//! packets given to it are encrypted and sent to the peer over UDP, and the
+//! packets from the peer come out of it. It follows the WireGuard protocol:
+//! a `Noise_IKpsk2` handshake establishes the session keys, which are renewed
+//! every 2 minutes, and the transport messages are protected against replays.
+//!
+//! Cookie replies (the DoS mitigation under load) are not supported, the
+//! received ones are ignored. The ephemeral keys come from
+//! [`axhal::misc::random`].
+
+mod noise;
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::net::{Ipv4Addr, SocketAddr};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::time::{monotonic_time_nanos, wall_time, NANOS_PER_SEC};
+use axio::PollState;
+use axsync::Mutex;
+
+use self::noise::{Initiation, Key, Session, StaticKeys, Timestamp};
+use crate::{poll_interfaces, UdpSocket};
+
+const REKEY_AFTER_MESSAGES: u64 = 1 << 60;
+const REKEY_AFTER_TIME: u64 = 120 * NANOS_PER_SEC;
+const REJECT_AFTER_TIME: u64 = 180 * NANOS_PER_SEC;
+const REKEY_ATTEMPT_TIME: u64 = 90 * NANOS_PER_SEC;
+const REKEY_TIMEOUT: u64 = 5 * NANOS_PER_SEC;
+const KEEPALIVE_TIMEOUT: u64 = 10 * NANOS_PER_SEC;
+
+/// The maximum size of the packets in the tunnel, the default of `wg-quick`.
+pub const MTU: usize = 1420;
+/// The default UDP port of WireGuard.
+pub const DEFAULT_PORT: u16 = 51820;
+/// The maximum number of received packets waiting for [`WgTunnel::recv`].
+const RX_QUEUE_LEN: usize = 64;
+
+/// The configuration of a WireGuard interface.
+#[derive(Clone)]
+pub struct WgConfig {
+    /// The private key of the interface.
+    pub private_key: [u8; 32],
+    /// The public key of the peer.
+    pub peer_public_key: [u8; 32],
+    /// The pre-shared key, all zeros if there is none.
+    pub preshared_key: [u8; 32],
+    /// The address of the peer, learned from its first handshake if `None`.
+    pub endpoint: Option<SocketAddr>,
+    /// The UDP port to listen on.
+    pub listen_port: u16,
+    /// The interval of the keepalives to the peer in seconds, 0 to disable.
+    pub persistent_keepalive: u64,
+}
+
+impl WgConfig {
+    /// Returns the configuration given by the environment variables at build
+    /// time, or `None` if `AX_WG_KEY` is not set.
+    ///
+    /// The keys are base64-encoded, as the `wg` tool prints them:
+    ///
+    /// - `AX_WG_KEY`: the private key of the interface.
+    /// - `AX_WG_PEER`: the public key of the peer.
+    /// - `AX_WG_PSK`: the pre-shared key (optional).
+    /// - `AX_WG_ENDPOINT`: the address and port of the peer (optional).
+    /// - `AX_WG_PORT`: the port to listen on (51820 by default).
+    /// - `AX_WG_KEEPALIVE`: the persistent keepalive interval in seconds
+    ///   (optional).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a variable is invalid.
+    pub fn from_env() -> Option<Self> {
+        // The empty variables are unset ones exported by the Makefile.
+        let var = |v: Option<&'static str>| v.filter(|v| !v.is_empty());
+        let private_key = parse_key(var(option_env!("AX_WG_KEY"))?).expect("invalid AX_WG_KEY");
+        let peer_public_key = var(option_env!("AX_WG_PEER"))
+            .and_then(parse_key)
+            .expect("invalid AX_WG_PEER");
+        let preshared_key = var(option_env!("AX_WG_PSK"))
+            .map_or(Some([0; 32]), parse_key)
+            .expect("invalid AX_WG_PSK");
+        let endpoint = var(option_env!("AX_WG_ENDPOINT"))
+            .map(|addr| addr.parse().expect("invalid AX_WG_ENDPOINT"));
+        let listen_port = var(option_env!("AX_WG_PORT")).map_or(DEFAULT_PORT, |port| {
+            port.parse().expect("invalid AX_WG_PORT")
+        });
+        let persistent_keepalive = var(option_env!("AX_WG_KEEPALIVE"))
+            .map_or(0, |secs| secs.parse().expect("invalid AX_WG_KEEPALIVE"));
+        Some(Self {
+            private_key,
+            peer_public_key,
+            preshared_key,
+            endpoint,
+            listen_port,
+            persistent_keepalive,
+        })
+    }
+}
+
+/// Decodes a base64-encoded key.
+fn parse_key(s: &str) -> Option<[u8; 32]> {
+    let s = s.as_bytes();
+    if s.len() != 44 || s[43] != b'=' {
+        return None;
+    }
+    let mut key = [0; 32];
+    let (mut bits, mut num_bits, mut len) = (0u32, 0, 0);
+    for &c in &s[..43] {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | v as u32;
+        num_bits += 6;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            key[len] = (bits >> num_bits) as u8;
+            len += 1;
+        }
+    }
+    Some(key)
+}
+
+fn random_index() -> u32 {
+    axhal::misc::random() as u32
+}
+
+fn random_key() -> Key {
+    let mut key = [0; 32];
+    key[..16].copy_from_slice(&axhal::misc::random().to_le_bytes());
+    key[16..].copy_from_slice(&axhal::misc::random().to_le_bytes());
+    key
+}
+
+/// Returns the current TAI64N timestamp.
+fn tai64n() -> Timestamp {
+    let now = wall_time();
+    let mut timestamp = [0; 12];
+    timestamp[..8].copy_from_slice(&(0x4000_0000_0000_000a + now.as_secs()).to_be_bytes());
+    timestamp[8..].copy_from_slice(&now.subsec_nanos().to_be_bytes());
+    timestamp
+}
+
+/// Returns the length of the IP packet `padded` without the padding.
+fn packet_len(padded: &[u8]) -> Option<usize> {
+    let len = match padded[0] >> 4 {
+        4 if padded.len() >= 20 => u16::from_be_bytes([padded[2], padded[3]]) as usize,
+        6 if padded.len() >= 40 => 40 + u16::from_be_bytes([padded[4], padded[5]]) as usize,
+        _ => return None,
+    };
+    (len <= padded.len()).then_some(len)
+}
+
+/// The state of the peer.
+struct Peer {
+    endpoint: Option<SocketAddr>,
+    /// The handshake initiation sent, and when.
+    initiation: Option<(Initiation, u64)>,
+    /// When the first initiation of the handshakes in progress was sent.
+    attempt_start: Option<u64>,
+    current: Option<Session>,
+    /// The session before the current one, for the packets still in flight.
+    previous: Option<Session>,
+    /// The session established as the responder, used once the peer sends
+    /// data on it.
+    next: Option<Session>,
+    /// The greatest timestamp of the initiations received.
+    last_timestamp: Timestamp,
+    /// When a packet was last sent, and a non-keepalive one received.
+    last_sent: u64,
+    last_received: u64,
+    rx_queue: VecDeque<Vec<u8>>,
+}
+
+impl Peer {
+    fn session_mut(&mut self, local_index: u32) -> Option<&mut Session> {
+        [&mut self.current, &mut self.previous, &mut self.next]
+            .into_iter()
+            .flatten()
+            .find(|s| s.local_index == local_index)
+    }
+
+    /// Drops the sessions with expired keys.
+    fn expire_sessions(&mut self, now: u64) {
+        for session in [&mut self.current, &mut self.previous, &mut self.next] {
+            if session
+                .as_ref()
+                .is_some_and(|s| now - s.created >= REJECT_AFTER_TIME)
+            {
+                *session = None;
+            }
+        }
+    }
+}
+
+/// A WireGuard interface with a single peer.
+pub struct WgTunnel {
+    socket: UdpSocket,
+    keys: StaticKeys,
+    persistent_keepalive: u64,
+    nonblock: AtomicBool,
+    peer: Mutex<Peer>,
+}
+
+impl WgTunnel {
+    /// Creates the interface of `config`, listening on its UDP port.
+    pub fn new(config: &WgConfig) -> AxResult<Self> {
+        let socket = UdpSocket::new();
+        socket.bind(SocketAddr::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            config.listen_port,
+        ))?;
+        socket.set_nonblocking(true);
+        Ok(Self {
+            socket,
+            keys: StaticKeys::new(
+                config.private_key,
+                config.peer_public_key,
+                config.preshared_key,
+            ),
+            persistent_keepalive: config.persistent_keepalive * NANOS_PER_SEC,
+            nonblock: AtomicBool::new(false),
+            peer: Mutex::new(Peer {
+                endpoint: config.endpoint,
+                initiation: None,
+                attempt_start: None,
+                current: None,
+                previous: None,
+                next: None,
+                last_timestamp: [0; 12],
+                last_sent: 0,
+                last_received: 0,
+                rx_queue: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Returns the public key of the interface.
+    pub fn public_key(&self) -> [u8; 32] {
+        *self.keys.public_key()
+    }
+
+    /// Returns the local UDP address.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns the current address of the peer, if known.
+    pub fn peer_endpoint(&self) -> Option<SocketAddr> {
+        self.peer.lock().endpoint
+    }
+
+    /// Returns whether the interface is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves the interface into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Sends the IP packet `packet` to the peer, making a handshake first if
+    /// there is no session. On success, returns the length of the packet.
+    ///
+    /// Returns [`Err(TimedOut)`](AxError::TimedOut) if the peer did not
+    /// respond to the handshake in 90 seconds.
+    pub fn send(&self, packet: &[u8]) -> AxResult<usize> {
+        if packet.is_empty() || packet.len() > MTU {
+            return ax_err!(InvalidInput, "WireGuard send() failed: invalid packet size");
+        }
+        self.block_on(|| {
+            self.process_incoming()?;
+            let mut peer = self.peer.lock();
+            let now = monotonic_time_nanos();
+            peer.expire_sessions(now);
+            if peer.current.is_some() {
+                if self.send_packet(&mut peer, packet, now)? {
+                    // Rekeying is best-effort while the session is valid.
+                    self.initiate(&mut peer, now).ok();
+                }
+                Ok(packet.len())
+            } else {
+                self.initiate(&mut peer, now)?;
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    /// Receives an IP packet from the peer. On success, returns the number of
+    /// bytes read, the rest of the packet is discarded if `buf` is too short.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.block_on(|| {
+            self.process_incoming()?;
+            let packet = self
+                .peer
+                .lock()
+                .rx_queue
+                .pop_front()
+                .ok_or(AxError::WouldBlock)?;
+            let len = packet.len().min(buf.len());
+            buf[..len].copy_from_slice(&packet[..len]);
+            Ok(len)
+        })
+    }
+
+    /// Whether a packet can be received, or sent without a handshake.
+    pub fn poll(&self) -> AxResult<PollState> {
+        poll_interfaces();
+        self.process_incoming()?;
+        let peer = self.peer.lock();
+        Ok(PollState {
+            readable: !peer.rx_queue.is_empty(),
+            writable: peer.current.is_some(),
+        })
+    }
+
+    /// Sends the keepalives and the handshakes due.
+    ///
+    /// It must be called periodically, every second say, for the interface
+    /// to keep the session when it sends nothing.
+    pub fn update_timers(&self) -> AxResult {
+        poll_interfaces();
+        self.process_incoming()?;
+        let mut peer = self.peer.lock();
+        let now = monotonic_time_nanos();
+        peer.expire_sessions(now);
+        let Some(session) = peer.current.as_ref() else {
+            if peer.initiation.is_some() {
+                self.initiate(&mut peer, now)?; // retry
+            }
+            return Ok(());
+        };
+        if session.initiator && now - session.created >= REKEY_AFTER_TIME {
+            self.initiate(&mut peer, now).ok();
+        }
+        let passive =
+            peer.last_received > peer.last_sent && now - peer.last_received >= KEEPALIVE_TIMEOUT;
+        let persistent =
+            self.persistent_keepalive > 0 && now - peer.last_sent >= self.persistent_keepalive;
+        if passive || persistent {
+            self.send_packet(&mut peer, &[], now)?;
+        }
+        Ok(())
+    }
+}
+
+/// Private methods
+impl WgTunnel {
+    /// Sends `packet` on the current session, and returns whether the session
+    /// needs rekeying.
+    fn send_packet(&self, peer: &mut Peer, packet: &[u8], now: u64) -> AxResult<bool> {
+        let endpoint = peer.endpoint.ok_or(AxError::NotConnected)?;
+        let session = peer.current.as_mut().ok_or(AxError::WouldBlock)?;
+        let Some(msg) = session.encrypt(packet) else {
+            peer.current = None; // the keys are worn out
+            return Err(AxError::WouldBlock);
+        };
+        let rekey = session.send_counter() >= REKEY_AFTER_MESSAGES
+            || (session.initiator && now - session.created >= REKEY_AFTER_TIME);
+        self.socket.send_to(&msg, endpoint)?;
+        peer.last_sent = now;
+        Ok(rekey)
+    }
+
+    /// Sends a handshake initiation, unless one was sent less than
+    /// `REKEY_TIMEOUT` ago.
+    fn initiate(&self, peer: &mut Peer, now: u64) -> AxResult {
+        if peer
+            .initiation
+            .as_ref()
+            .is_some_and(|&(_, sent)| now - sent < REKEY_TIMEOUT)
+        {
+            return Ok(());
+        }
+        let start = *peer.attempt_start.get_or_insert(now);
+        if now - start >= REKEY_ATTEMPT_TIME {
+            peer.initiation = None;
+            peer.attempt_start = None;
+            return ax_err!(TimedOut, "WireGuard handshake timed out");
+        }
+        let endpoint = peer
+            .endpoint
+            .ok_or_else(|| ax_err_type!(NotConnected, "WireGuard peer endpoint unknown"))?;
+
+        let (init, msg) =
+            noise::create_initiation(&self.keys, random_index(), random_key(), &tai64n());
+        self.socket.send_to(&msg, endpoint)?;
+        debug!("WireGuard: handshake initiation sent to {}", endpoint);
+        peer.initiation = Some((init, now));
+        Ok(())
+    }
+
+    /// Handles the messages received on the UDP socket.
+    fn process_incoming(&self) -> AxResult {
+        let mut buf = [0u8; noise::DATA_HEADER_LEN + MTU + 32];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(res) => res,
+                Err(AxError::WouldBlock) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let msg = &mut buf[..len];
+            if len < 4 || msg[1..4] != [0; 3] {
+                continue;
+            }
+            let mut peer = self.peer.lock();
+            match msg[0] {
+                noise::MSG_INITIATION => self.handle_initiation(&mut peer, msg, from),
+                noise::MSG_RESPONSE => self.handle_response(&mut peer, msg, from),
+                noise::MSG_DATA => self.handle_data(&mut peer, msg, from),
+                noise::MSG_COOKIE_REPLY => debug!("WireGuard: cookie reply ignored"),
+                ty => trace!("WireGuard: unknown message type {}", ty),
+            }
+        }
+    }
+
+    fn handle_initiation(&self, peer: &mut Peer, msg: &[u8], from: SocketAddr) {
+        let Some(init) = noise::consume_initiation(&self.keys, msg) else {
+            debug!("WireGuard: invalid handshake initiation from {}", from);
+            return;
+        };
+        if init.timestamp <= peer.last_timestamp {
+            debug!("WireGuard: replayed handshake initiation from {}", from);
+            return;
+        }
+        peer.last_timestamp = init.timestamp;
+        let (session, msg) = noise::create_response(&self.keys, init, random_index(), random_key());
+        if let Err(e) = self.socket.send_to(&msg, from) {
+            warn!("WireGuard: sending handshake response failed: {:?}", e);
+            return;
+        }
+        debug!("WireGuard: handshake response sent to {}", from);
+        peer.endpoint = Some(from);
+        peer.next = Some(session);
+    }
+
+    fn handle_response(&self, peer: &mut Peer, msg: &[u8], from: SocketAddr) {
+        let Some((init, _)) = peer.initiation.as_ref() else {
+            return;
+        };
+        let Some(session) = noise::consume_response(&self.keys, init, msg) else {
+            debug!("WireGuard: invalid handshake response from {}", from);
+            return;
+        };
+        debug!("WireGuard: session established with {}", from);
+        peer.endpoint = Some(from);
+        peer.initiation = None;
+        peer.attempt_start = None;
+        peer.previous = peer.current.replace(session);
+        // Confirm the session to the responder.
+        let now = monotonic_time_nanos();
+        if let Err(e) = self.send_packet(peer, &[], now) {
+            warn!("WireGuard: sending keepalive failed: {:?}", e);
+        }
+    }
+
+    fn handle_data(&self, peer: &mut Peer, msg: &mut [u8], from: SocketAddr) {
+        if msg.len() < 8 {
+            return;
+        }
+        let index = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+        let now = monotonic_time_nanos();
+        peer.expire_sessions(now);
+        let Some(session) = peer.session_mut(index) else {
+            trace!("WireGuard: data for unknown session {:#x}", index);
+            return;
+        };
+        let Some(padded) = session.decrypt(msg) else {
+            debug!("WireGuard: invalid data message from {}", from);
+            return;
+        };
+        if peer.next.as_ref().is_some_and(|s| s.local_index == index) {
+            // The initiator uses the new session, so do we.
+            peer.previous = peer.current.take();
+            peer.current = peer.next.take();
+            peer.attempt_start = None;
+        }
+        peer.endpoint = Some(from);
+
+        if padded.is_empty() {
+            return; // keepalive
+        }
+        let Some(len) = packet_len(padded) else {
+            debug!("WireGuard: invalid packet from {}", from);
+            return;
+        };
+        peer.last_received = now;
+        if peer.rx_queue.len() >= RX_QUEUE_LEN {
+            warn!("WireGuard: receive queue full, packet dropped");
+            return;
+        }
+        peer.rx_queue.push_back(padded[..len].to_vec());
+    }
+
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        loop {
+            poll_interfaces();
+            match f() {
+                Err(AxError::WouldBlock) if !self.is_nonblocking() => axtask::yield_now(),
+                res => return res,
+            }
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! The `Noise_IKpsk2` handshake and the transport messages of WireGuard.
+
+use alloc::{vec, vec::Vec};
+
+use axcrypto::blake2s::{self, hash, kdf};
+use axcrypto::chacha20poly1305::{self as aead, TAG_LEN};
+use axcrypto::{ct_eq, x25519};
+use axhal::time::monotonic_time_nanos;
+
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+
+pub const MSG_INITIATION: u8 = 1;
+pub const MSG_RESPONSE: u8 = 2;
+pub const MSG_COOKIE_REPLY: u8 = 3;
+pub const MSG_DATA: u8 = 4;
+
+pub const INITIATION_LEN: usize = 148;
+pub const RESPONSE_LEN: usize = 92;
+pub const DATA_HEADER_LEN: usize = 16;
+
+/// The number of messages after which the transport keys are not used.
+pub const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+/// The number of counters before the greatest one received that are
+/// accepted out of order.
+const REPLAY_WINDOW: u64 = 2048;
+
+pub type Key = [u8; 32];
+/// A TAI64N timestamp.
+pub type Timestamp = [u8; 12];
+
+/// The static keys of the interface and its peer.
+pub struct StaticKeys {
+    private: Key,
+    public: Key,
+    peer_public: Key,
+    psk: Key,
+    /// The key of the mac1 of the messages to the peer.
+    peer_mac1_key: Key,
+    /// The key of the mac1 of the messages from the peer.
+    mac1_key: Key,
+}
+
+impl StaticKeys {
+    pub fn new(private: Key, peer_public: Key, psk: Key) -> Self {
+        let public = x25519::public_key(&private);
+        Self {
+            private,
+            public,
+            peer_public,
+            psk,
+            peer_mac1_key: hash(&[LABEL_MAC1, &peer_public]),
+            mac1_key: hash(&[LABEL_MAC1, &public]),
+        }
+    }
+
+    pub fn public_key(&self) -> &Key {
+        &self.public
+    }
+}
+
+fn nonce(counter: u64) -> [u8; aead::NONCE_LEN] {
+    let mut nonce = [0; aead::NONCE_LEN];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+/// Encrypts `buf` but its last [`TAG_LEN`] bytes, where the tag is written.
+fn seal(key: &Key, counter: u64, aad: &[u8], buf: &mut [u8]) {
+    let (data, tag) = buf.split_at_mut(buf.len() - TAG_LEN);
+    tag.copy_from_slice(&aead::seal(key, &nonce(counter), aad, data));
+}
+
+/// Decrypts `buf` sealed by [`seal`].
+fn open(key: &Key, counter: u64, aad: &[u8], buf: &mut [u8]) -> Option<()> {
+    let (data, tag) = buf.split_at_mut(buf.len() - TAG_LEN);
+    aead::open(key, &nonce(counter), aad, data, (&*tag).try_into().unwrap()).ok()
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// Writes the mac1 of `msg`, the mac2 is left zero as no cookie is used.
+fn add_mac1(key: &Key, msg: &mut [u8]) {
+    let n = msg.len() - 32;
+    let mac = blake2s::mac(key, &msg[..n]);
+    msg[n..n + 16].copy_from_slice(&mac);
+}
+
+fn check_mac1(key: &Key, msg: &[u8]) -> bool {
+    let n = msg.len() - 32;
+    ct_eq(&blake2s::mac(key, &msg[..n]), &msg[n..n + 16])
+}
+
+/// The chaining key and the hash of a handshake.
+#[derive(Clone)]
+struct SymmetricState {
+    ck: Key,
+    h: Key,
+}
+
+impl SymmetricState {
+    fn new(responder_public: &Key) -> Self {
+        let ck = hash(&[CONSTRUCTION]);
+        let h = hash(&[&ck, IDENTIFIER]);
+        Self {
+            ck,
+            h: hash(&[&h, responder_public]),
+        }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.h = hash(&[&self.h, data]);
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        [self.ck] = kdf(&self.ck, input);
+    }
+
+    /// Mixes `input` in the chaining key and returns the key derived with it.
+    fn mix_key2(&mut self, input: &[u8]) -> Key {
+        let [ck, key] = kdf(&self.ck, input);
+        self.ck = ck;
+        key
+    }
+
+    /// Mixes the pre-shared key, as `psk2`.
+    fn mix_psk(&mut self, psk: &Key) -> Key {
+        let [ck, tau, key] = kdf(&self.ck, psk);
+        self.ck = ck;
+        self.mix_hash(&tau);
+        key
+    }
+
+    fn encrypt(&mut self, key: &Key, buf: &mut [u8]) {
+        seal(key, 0, &self.h, buf);
+        self.mix_hash(buf);
+    }
+
+    fn decrypt(&mut self, key: &Key, buf: &mut [u8]) -> Option<()> {
+        let h = hash(&[&self.h, buf]);
+        open(key, 0, &self.h, buf)?;
+        self.h = h;
+        Some(())
+    }
+
+    /// Returns the transport keys of the initiator, to send and to receive.
+    fn split(&self) -> (Key, Key) {
+        let [k1, k2] = kdf(&self.ck, &[]);
+        (k1, k2)
+    }
+}
+
+/// A handshake initiation sent, waiting for the response.
+pub struct Initiation {
+    pub local_index: u32,
+    state: SymmetricState,
+    ephemeral: Key,
+}
+
+/// Returns the handshake initiation message to the peer, with the sender
+/// index `local_index` and the ephemeral private key `ephemeral`.
+pub fn create_initiation(
+    keys: &StaticKeys,
+    local_index: u32,
+    ephemeral: Key,
+    timestamp: &Timestamp,
+) -> (Initiation, [u8; INITIATION_LEN]) {
+    let mut msg = [0; INITIATION_LEN];
+    msg[0] = MSG_INITIATION;
+    msg[4..8].copy_from_slice(&local_index.to_le_bytes());
+
+    let mut state = SymmetricState::new(&keys.peer_public);
+    let e_pub = x25519::public_key(&ephemeral);
+    msg[8..40].copy_from_slice(&e_pub);
+    state.mix_key(&e_pub);
+    state.mix_hash(&e_pub);
+
+    let key = state.mix_key2(&x25519::x25519(&ephemeral, &keys.peer_public));
+    msg[40..72].copy_from_slice(&keys.public);
+    state.encrypt(&key, &mut msg[40..88]);
+
+    let key = state.mix_key2(&x25519::x25519(&keys.private, &keys.peer_public));
+    msg[88..100].copy_from_slice(timestamp);
+    state.encrypt(&key, &mut msg[88..116]);
+
+    add_mac1(&keys.peer_mac1_key, &mut msg);
+    let init = Initiation {
+        local_index,
+        state,
+        ephemeral,
+    };
+    (init, msg)
+}
+
+/// A handshake initiation received from the peer.
+pub struct ReceivedInitiation {
+    pub remote_index: u32,
+    pub timestamp: Timestamp,
+    state: SymmetricState,
+    remote_ephemeral: Key,
+}
+
+/// Checks and decrypts the handshake initiation message `msg`, which must be
+/// from the peer.
+pub fn consume_initiation(keys: &StaticKeys, msg: &[u8]) -> Option<ReceivedInitiation> {
+    if msg.len() != INITIATION_LEN || !check_mac1(&keys.mac1_key, msg) {
+        return None;
+    }
+    let mut msg: [u8; INITIATION_LEN] = msg.try_into().unwrap();
+
+    let mut state = SymmetricState::new(&keys.public);
+    let e_pub: Key = msg[8..40].try_into().unwrap();
+    state.mix_key(&e_pub);
+    state.mix_hash(&e_pub);
+
+    let key = state.mix_key2(&x25519::x25519(&keys.private, &e_pub));
+    state.decrypt(&key, &mut msg[40..88])?;
+    if !ct_eq(&msg[40..72], &keys.peer_public) {
+        return None; // not the peer
+    }
+
+    let key = state.mix_key2(&x25519::x25519(&keys.private, &keys.peer_public));
+    state.decrypt(&key, &mut msg[88..116])?;
+    Some(ReceivedInitiation {
+        remote_index: le32(&msg[4..]),
+        timestamp: msg[88..100].try_into().unwrap(),
+        state,
+        remote_ephemeral: e_pub,
+    })
+}
+
+/// Returns the handshake response message to `init`, and the session it
+/// establishes.
+pub fn create_response(
+    keys: &StaticKeys,
+    init: ReceivedInitiation,
+    local_index: u32,
+    ephemeral: Key,
+) -> (Session, [u8; RESPONSE_LEN]) {
+    let mut msg = [0; RESPONSE_LEN];
+    msg[0] = MSG_RESPONSE;
+    msg[4..8].copy_from_slice(&local_index.to_le_bytes());
+    msg[8..12].copy_from_slice(&init.remote_index.to_le_bytes());
+
+    let mut state = init.state;
+    let e_pub = x25519::public_key(&ephemeral);
+    msg[12..44].copy_from_slice(&e_pub);
+    state.mix_key(&e_pub);
+    state.mix_hash(&e_pub);
+    state.mix_key(&x25519::x25519(&ephemeral, &init.remote_ephemeral));
+    state.mix_key(&x25519::x25519(&ephemeral, &keys.peer_public));
+    let key = state.mix_psk(&keys.psk);
+    state.encrypt(&key, &mut msg[44..60]);
+
+    add_mac1(&keys.peer_mac1_key, &mut msg);
+    let (recv_key, send_key) = state.split();
+    let session = Session::new(local_index, init.remote_index, send_key, recv_key, false);
+    (session, msg)
+}
+
+/// Checks the handshake response message `msg` to `init`, and returns the
+/// session it establishes.
+pub fn consume_response(keys: &StaticKeys, init: &Initiation, msg: &[u8]) -> Option<Session> {
+    if msg.len() != RESPONSE_LEN
+        || le32(&msg[8..]) != init.local_index
+        || !check_mac1(&keys.mac1_key, msg)
+    {
+        return None;
+    }
+    let mut msg: [u8; RESPONSE_LEN] = msg.try_into().unwrap();
+
+    let mut state = init.state.clone();
+    let e_pub: Key = msg[12..44].try_into().unwrap();
+    state.mix_key(&e_pub);
+    state.mix_hash(&e_pub);
+    state.mix_key(&x25519::x25519(&init.ephemeral, &e_pub));
+    state.mix_key(&x25519::x25519(&keys.private, &e_pub));
+    let key = state.mix_psk(&keys.psk);
+    state.decrypt(&key, &mut msg[44..60])?;
+
+    let (send_key, recv_key) = state.split();
+    let session = Session::new(init.local_index, le32(&msg[4..]), send_key, recv_key, true);
+    Some(session)
+}
+
+/// The sliding window of the counters received (RFC 6479).
+struct ReplayWindow {
+    bitmap: [u64; (REPLAY_WINDOW / 64) as usize],
+    /// The counter after the greatest one received.
+    next: u64,
+}
+
+impl ReplayWindow {
+    const fn new() -> Self {
+        Self {
+            bitmap: [0; (REPLAY_WINDOW / 64) as usize],
+            next: 0,
+        }
+    }
+
+    fn bit(counter: u64) -> (usize, u64) {
+        let i = counter % REPLAY_WINDOW;
+        ((i / 64) as usize, 1 << (i % 64))
+    }
+
+    /// Whether `counter` was neither received nor is too old.
+    fn check(&self, counter: u64) -> bool {
+        if counter >= REJECT_AFTER_MESSAGES {
+            return false;
+        }
+        if counter >= self.next {
+            return true;
+        }
+        let (word, mask) = Self::bit(counter);
+        self.next - counter <= REPLAY_WINDOW && self.bitmap[word] & mask == 0
+    }
+
+    /// Records that `counter` was received, it must pass [`Self::check`].
+    fn update(&mut self, counter: u64) {
+        if counter >= self.next {
+            if counter - self.next >= REPLAY_WINDOW {
+                self.bitmap = [0; (REPLAY_WINDOW / 64) as usize];
+            } else {
+                for c in self.next..counter {
+                    let (word, mask) = Self::bit(c);
+                    self.bitmap[word] &= !mask;
+                }
+            }
+            self.next = counter + 1;
+        }
+        let (word, mask) = Self::bit(counter);
+        self.bitmap[word] |= mask;
+    }
+}
+
+/// A secure session with the peer, established by a handshake.
+pub struct Session {
+    pub local_index: u32,
+    remote_index: u32,
+    send_key: Key,
+    recv_key: Key,
+    send_counter: u64,
+    replay: ReplayWindow,
+    /// Whether the handshake was initiated by us.
+    pub initiator: bool,
+    /// When the session was established.
+    pub created: u64,
+}
+
+impl Session {
+    fn new(
+        local_index: u32,
+        remote_index: u32,
+        send_key: Key,
+        recv_key: Key,
+        initiator: bool,
+    ) -> Self {
+        Self {
+            local_index,
+            remote_index,
+            send_key,
+            recv_key,
+            send_counter: 0,
+            replay: ReplayWindow::new(),
+            initiator,
+            created: monotonic_time_nanos(),
+        }
+    }
+
+    pub fn send_counter(&self) -> u64 {
+        self.send_counter
+    }
+
+    /// Returns the transport data message of `packet`, or `None` if the keys
+    /// are worn out.
+    pub fn encrypt(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        if self.send_counter >= REJECT_AFTER_MESSAGES {
+            return None;
+        }
+        let counter = self.send_counter;
+        self.send_counter += 1;
+
+        let padded_len = packet.len().next_multiple_of(16);
+        let mut msg = vec![0; DATA_HEADER_LEN + padded_len + TAG_LEN];
+        msg[0] = MSG_DATA;
+        msg[4..8].copy_from_slice(&self.remote_index.to_le_bytes());
+        msg[8..16].copy_from_slice(&counter.to_le_bytes());
+        msg[DATA_HEADER_LEN..DATA_HEADER_LEN + packet.len()].copy_from_slice(packet);
+        seal(&self.send_key, counter, &[], &mut msg[DATA_HEADER_LEN..]);
+        Some(msg)
+    }
+
+    /// Decrypts the transport data message `msg` in place, and returns the
+    /// packet in it (with the padding).
+    pub fn decrypt<'a>(&mut self, msg: &'a mut [u8]) -> Option<&'a [u8]> {
+        if msg.len() < DATA_HEADER_LEN + TAG_LEN {
+            return None;
+        }
+        let counter = u64::from_le_bytes(msg[8..16].try_into().unwrap());
+        if !self.replay.check(counter) {
+            return None;
+        }
+        open(&self.recv_key, counter, &[], &mut msg[DATA_HEADER_LEN..])?;
+        self.replay.update(counter);
+        let end = msg.len() - TAG_LEN;
+        Some(&msg[DATA_HEADER_LEN..end])
+    }
+}
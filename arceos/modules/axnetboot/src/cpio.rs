@@ -0,0 +1,115 @@
+//! Unpacking of the `newc` cpio archives, made by `cpio -H newc -o` as the
+//! Linux initramfs.
+//!
+//! Each entry is a header of 110 ASCII bytes (the magic and 13 fields of 8
+//! hexadecimal digits), the name and the data, each padded to 4 bytes, until
+//! the `TRAILER!!!` entry. The directories and the regular files are created,
+//! with their modification times. The other entries (symbolic links, device
+//! nodes) are skipped.
+
+use alloc::format;
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult};
+
+const MAGIC: &[u8] = b"070701";
+/// The magic of the archives with the checksums of the data, not checked.
+const MAGIC_CRC: &[u8] = b"070702";
+const HEADER_LEN: usize = 110;
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// The header fields, in the order of the archive.
+#[derive(Clone, Copy)]
+enum Field {
+    Mode = 1,
+    Mtime = 5,
+    FileSize = 6,
+    NameSize = 11,
+}
+
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.starts_with(MAGIC_CRC)
+}
+
+fn field(header: &[u8], field: Field) -> AxResult<u32> {
+    let start = MAGIC.len() + field as usize * 8;
+    let digits =
+        core::str::from_utf8(&header[start..start + 8]).map_err(|_| AxError::InvalidData)?;
+    u32::from_str_radix(digits, 16).map_err(|_| AxError::InvalidData)
+}
+
+/// Returns the entry name as a path relative to the target directory, or
+/// `None` for the root itself. The names going out of it are rejected.
+fn relative_path(name: &str) -> AxResult<Option<&str>> {
+    let name = name.trim_start_matches("./").trim_start_matches('/');
+    if name.split('/').any(|c| c == "..") {
+        warn!("cpio: entry {:?} outside of the target directory", name);
+        return Err(AxError::InvalidData);
+    }
+    Ok(Some(name).filter(|n| !n.is_empty() && *n != "."))
+}
+
+/// Unpacks the archive `data` into `dir`, returns the number of files
+/// written.
+pub fn unpack(data: &[u8], dir: &str) -> AxResult<usize> {
+    let dir = dir.trim_end_matches('/');
+    let mut files = 0;
+    let mut pos = 0;
+    loop {
+        let header = data
+            .get(pos..pos + HEADER_LEN)
+            .ok_or(AxError::InvalidData)?;
+        if !is_archive(header) {
+            return Err(AxError::InvalidData);
+        }
+        let mode = field(header, Field::Mode)?;
+        let mtime = field(header, Field::Mtime)?;
+        let file_size = field(header, Field::FileSize)? as usize;
+        let name_size = field(header, Field::NameSize)? as usize;
+
+        let name_start = pos + HEADER_LEN;
+        // the name size includes the terminating NUL
+        let name = data
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .ok_or(AxError::InvalidData)?;
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let file_data = data
+            .get(data_start..data_start + file_size)
+            .ok_or(AxError::InvalidData)?;
+        pos = (data_start + file_size).next_multiple_of(4);
+        if name.as_bytes() == TRAILER {
+            return Ok(files);
+        }
+
+        let Some(rel) = relative_path(name)? else {
+            continue;
+        };
+        let path = format!("{}/{}", dir, rel);
+        match mode & S_IFMT {
+            S_IFDIR => crate::create_dirs(&path)?,
+            S_IFREG => {
+                // the archives need not list the parent directories
+                if let Some((parent, _)) = path.rsplit_once('/') {
+                    if !parent.is_empty() {
+                        crate::create_dirs(parent)?;
+                    }
+                }
+                axfs::api::write(&path, file_data)?;
+                files += 1;
+            }
+            kind => {
+                debug!("cpio: {} of type {:#o} skipped", path, kind);
+                continue;
+            }
+        }
+        let mtime = Some(Duration::from_secs(mtime as u64));
+        if let Err(e) = axfs::api::set_times(&path, None, mtime) {
+            debug!("cpio: failed to set the times of {}: {:?}", path, e);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! A minimal HTTP/1.1 client fetching a whole resource by a GET request.
+//!
+//! The body is delimited by `Content-Length`, or by the end of the connection
+//! if there is none. The chunked transfer coding is not supported.
+
+use alloc::{format, vec::Vec};
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time, TimeValue};
+use axnet::TcpSocket;
+
+/// The longest time without any progress of the transfer.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The most bytes of the response headers.
+const MAX_HEADER_LEN: usize = 16 * 1024;
+
+fn send_all(sock: &TcpSocket, mut buf: &[u8]) -> AxResult {
+    let deadline = monotonic_time() + IDLE_TIMEOUT;
+    while !buf.is_empty() {
+        axnet::poll_interfaces();
+        match sock.send(buf) {
+            Ok(0) => return ax_err!(ConnectionReset, "HTTP connection closed"),
+            Ok(n) => buf = &buf[n..],
+            Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+            Err(AxError::WouldBlock) => return ax_err!(TimedOut, "HTTP send timed out"),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Receives some bytes at the end of `buf`, returns how many, 0 if the
+/// connection is closed.
+fn recv_some(sock: &TcpSocket, buf: &mut Vec<u8>) -> AxResult<usize> {
+    let deadline: TimeValue = monotonic_time() + IDLE_TIMEOUT;
+    let mut chunk = [0; 4096];
+    loop {
+        axnet::poll_interfaces();
+        match sock.recv(&mut chunk) {
+            Ok(n) => {
+                buf.extend_from_slice(&chunk[..n]);
+                return Ok(n);
+            }
+            Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+            Err(AxError::WouldBlock) => return ax_err!(TimedOut, "HTTP recv timed out"),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fetches `path` from the HTTP server `host` at `addr`.
+pub fn fetch(addr: SocketAddr, host: &str, path: &str) -> AxResult<Vec<u8>> {
+    let sock = TcpSocket::new();
+    sock.connect(addr)?;
+    sock.set_nonblocking(true);
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ArceOS\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    send_all(&sock, request.as_bytes())?;
+
+    // the status line and the headers
+    let mut buf = Vec::new();
+    let header_len = loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_HEADER_LEN {
+            return Err(AxError::InvalidData);
+        }
+        if recv_some(&sock, &mut buf)? == 0 {
+            return ax_err!(ConnectionReset, "HTTP connection closed");
+        }
+    };
+    let header = core::str::from_utf8(&buf[..header_len]).map_err(|_| AxError::InvalidData)?;
+    let mut lines = header.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or(AxError::InvalidData)?;
+    let mut content_len = None;
+    for (key, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = value.trim();
+        if key.eq_ignore_ascii_case("Content-Length") {
+            content_len = Some(value.parse::<usize>().map_err(|_| AxError::InvalidData)?);
+        } else if key.eq_ignore_ascii_case("Transfer-Encoding") {
+            warn!("HTTP: transfer encoding {} not supported", value);
+            return Err(AxError::Unsupported);
+        }
+    }
+    match status {
+        200 => {}
+        404 => return Err(AxError::NotFound),
+        _ => {
+            warn!("HTTP: status {}", status);
+            return Err(AxError::Io);
+        }
+    }
+
+    // the body, part of which may have come with the headers
+    let mut data = Vec::with_capacity(content_len.unwrap_or(0));
+    data.extend_from_slice(&buf[header_len..]);
+    drop(buf);
+    loop {
+        if content_len.is_some_and(|len| data.len() >= len) {
+            break;
+        }
+        if recv_some(&sock, &mut data)? == 0 {
+            if content_len.is_some() {
+                return ax_err!(ConnectionReset, "HTTP connection closed in the body");
+            }
+            break;
+        }
+    }
+    if content_len.is_some_and(|len| data.len() != len) {
+        return Err(AxError::InvalidData);
+    }
+    sock.shutdown().ok();
+    Ok(data)
+}
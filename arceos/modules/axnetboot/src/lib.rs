@@ -0,0 +1,216 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) network boot module.
+//!
+//! It fetches the application bundle over the network at boot, once the NIC
+//! is up, and puts it into the filesystem before the application starts: the
+//! application and its files are served by the development host instead of
+//! being baked into the disk image, so a new build is tried without writing
+//! the image again.
+//!
+//! - The bundle is fetched by TFTP (RFC 1350, with the block size and
+//!   transfer size options of RFC 2348 and RFC 2349) or by a plain HTTP GET.
+//! - A bundle in the `newc` cpio format (the format of the Linux initramfs)
+//!   is unpacked into the target directory, any other file is written into
+//!   it under the last component of the URL path.
+//!
+//! The bundle is configured by the environment variables at build time, see
+//! [`NetbootConfig::from_env`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod cpio;
+mod http;
+mod tftp;
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::{IpAddr, SocketAddr};
+
+use axerrno::{AxError, AxResult};
+
+/// The protocol a bundle is fetched with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// `tftp://`, port 69 by default.
+    Tftp,
+    /// `http://`, port 80 by default.
+    Http,
+}
+
+/// A parsed `tftp://host[:port]/path` or `http://host[:port]/path` URL.
+#[derive(Debug, Clone)]
+pub struct BootUrl {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl BootUrl {
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = if let Some(rest) = url.strip_prefix("tftp://") {
+            (Scheme::Tftp, rest)
+        } else {
+            (Scheme::Http, url.strip_prefix("http://")?)
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        // an IPv6 address is in brackets
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']')?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match (port, scheme) {
+            (Some(port), _) => port.parse().ok()?,
+            (None, Scheme::Tftp) => 69,
+            (None, Scheme::Http) => 80,
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            scheme,
+            host: host.into(),
+            port,
+            path: path.into(),
+        })
+    }
+
+    /// Returns the last component of the path, the name of a bundle which is
+    /// not an archive.
+    pub fn file_name(&self) -> Option<&str> {
+        self.path.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+
+    /// Resolves the host, by DNS if it is not an IP address.
+    fn socket_addr(&self) -> AxResult<SocketAddr> {
+        let ip = match self.host.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => *axnet::dns_query(&self.host)?
+                .first()
+                .ok_or(AxError::NotFound)?,
+        };
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
+
+/// The configuration of the network boot.
+#[derive(Debug, Clone)]
+pub struct NetbootConfig {
+    /// The URL of the bundle.
+    pub url: BootUrl,
+    /// The directory the bundle is put into.
+    pub dir: String,
+}
+
+impl NetbootConfig {
+    /// Reads the configuration from the environment variables at build
+    /// time, or `None` if `AX_NETBOOT_URL` is not set:
+    ///
+    /// - `AX_NETBOOT_URL`: the URL of the bundle, as
+    ///   `tftp://host[:port]/path` or `http://host[:port]/path`.
+    /// - `AX_NETBOOT_DIR`: the directory the bundle is put into (`/` by
+    ///   default), created if missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a variable is invalid.
+    pub fn from_env() -> Option<Self> {
+        // The empty variables are unset ones exported by the Makefile.
+        let var = |v: Option<&'static str>| v.filter(|v| !v.is_empty());
+        let url = var(option_env!("AX_NETBOOT_URL"))?;
+        let url = BootUrl::parse(url).expect("invalid AX_NETBOOT_URL");
+        let dir = var(option_env!("AX_NETBOOT_DIR")).unwrap_or("/");
+        assert!(dir.starts_with('/'), "AX_NETBOOT_DIR must be absolute");
+        Some(Self {
+            url,
+            dir: dir.into(),
+        })
+    }
+}
+
+/// Creates the directory `path` and its missing parents.
+fn create_dirs(path: &str) -> AxResult {
+    let mut end = 0;
+    for component in path.split('/') {
+        end += component.len() + 1;
+        if component.is_empty() {
+            continue;
+        }
+        match axfs::api::create_dir(&path[..end - 1]) {
+            Ok(()) | Err(AxError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the whole resource at `url`.
+pub fn fetch(url: &BootUrl) -> AxResult<Vec<u8>> {
+    let addr = url.socket_addr()?;
+    match url.scheme {
+        Scheme::Tftp => tftp::fetch(addr, &url.path),
+        Scheme::Http => http::fetch(addr, &url.host, &url.path),
+    }
+}
+
+/// Puts the fetched `bundle` into `dir`, unpacking it if it is a cpio
+/// archive, returns the number of files written.
+pub fn install(url: &BootUrl, bundle: &[u8], dir: &str) -> AxResult<usize> {
+    create_dirs(dir)?;
+    if cpio::is_archive(bundle) {
+        return cpio::unpack(bundle, dir);
+    }
+    if bundle.starts_with(&[0x1f, 0x8b]) {
+        warn!("netboot: the bundle is gzip-compressed, written as is");
+    }
+    let name = url.file_name().ok_or(AxError::InvalidInput)?;
+    axfs::api::write(&format!("{}/{}", dir.trim_end_matches('/'), name), bundle)?;
+    Ok(1)
+}
+
+/// Fetches the bundle configured at build time and puts it into the
+/// filesystem.
+///
+/// # Panics
+///
+/// Panics if the bundle is not configured, or can not be fetched or
+/// installed.
+pub fn init_netboot() {
+    info!("Initialize network boot...");
+
+    let config = NetbootConfig::from_env().expect("AX_NETBOOT_URL not set");
+    let url = &config.url;
+    info!(
+        "  fetch {}://{}:{}{} into {}",
+        match url.scheme {
+            Scheme::Tftp => "tftp",
+            Scheme::Http => "http",
+        },
+        url.host,
+        url.port,
+        url.path,
+        config.dir
+    );
+    let start = axhal::time::monotonic_time();
+    let bundle = fetch(url).expect("netboot bundle not available");
+    let elapsed = axhal::time::monotonic_time() - start;
+    info!(
+        "  {} bytes in {}.{:03}s",
+        bundle.len(),
+        elapsed.as_secs(),
+        elapsed.subsec_millis()
+    );
+    let files = install(url, &bundle, &config.dir).expect("failed to install the netboot bundle");
+    info!("  {} files installed", files);
+}
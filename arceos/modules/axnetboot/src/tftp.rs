@@ -0,0 +1,222 @@
+//! A minimal TFTP client for the read requests (RFC 1350), asking for the
+//! block size and transfer size options (RFC 2347, RFC 2348, RFC 2349).
+//!
+//! The server answers from a port of its own (the transfer ID), the packets
+//! from elsewhere are ignored. The last packet sent is sent again when no
+//! answer comes in time.
+
+use alloc::{format, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time, TimeValue};
+use axnet::UdpSocket;
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+const OP_OACK: u16 = 6;
+
+/// The block size of RFC 1350, if the server does not take the option.
+const DEFAULT_BLOCK_SIZE: usize = 512;
+/// The block size asked for, the most data in an Ethernet frame without IP
+/// fragmentation.
+const BLOCK_SIZE: usize = 1428;
+const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(1);
+/// The most times a packet is sent before giving up.
+const MAX_RETRIES: usize = 8;
+
+fn be16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Builds the read request of `path` in the octet mode, with the options.
+fn read_request(path: &str) -> Vec<u8> {
+    let mut req = Vec::new();
+    req.extend_from_slice(&OP_RRQ.to_be_bytes());
+    // the servers take the path relative to their root
+    for field in [
+        path.trim_start_matches('/').as_bytes(),
+        b"octet",
+        b"blksize",
+        format!("{}", BLOCK_SIZE).as_bytes(),
+        b"tsize",
+        b"0",
+    ] {
+        req.extend_from_slice(field);
+        req.push(0);
+    }
+    req
+}
+
+fn ack(block: u16) -> [u8; 4] {
+    let mut pkt = [0; 4];
+    pkt[..2].copy_from_slice(&OP_ACK.to_be_bytes());
+    pkt[2..].copy_from_slice(&block.to_be_bytes());
+    pkt
+}
+
+/// Parses the options acknowledged by an OACK packet, returns the block size
+/// and the transfer size.
+fn parse_oack(opts: &[u8]) -> AxResult<(usize, Option<usize>)> {
+    let mut fields = opts.split(|&b| b == 0);
+    let (mut block_size, mut tsize) = (DEFAULT_BLOCK_SIZE, None);
+    while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+        let value = core::str::from_utf8(value)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .ok_or(AxError::InvalidData)?;
+        if name.eq_ignore_ascii_case(b"blksize") {
+            // the server may only lower the block size
+            if !(8..=BLOCK_SIZE).contains(&value) {
+                return Err(AxError::InvalidData);
+            }
+            block_size = value;
+        } else if name.eq_ignore_ascii_case(b"tsize") {
+            tsize = Some(value);
+        }
+    }
+    Ok((block_size, tsize))
+}
+
+/// Logs the error packet of the server, returns the error it maps to.
+fn server_error(pkt: &[u8]) -> AxError {
+    let code = if pkt.len() >= 4 { be16(pkt, 2) } else { 0 };
+    let msg = pkt.get(4..).unwrap_or_default();
+    let msg = msg.split(|&b| b == 0).next().unwrap_or_default();
+    warn!(
+        "TFTP: error {} from the server: {}",
+        code,
+        core::str::from_utf8(msg).unwrap_or("?")
+    );
+    match code {
+        1 => AxError::NotFound,
+        2 => AxError::PermissionDenied,
+        _ => AxError::Io,
+    }
+}
+
+struct Transfer {
+    sock: UdpSocket,
+    server: SocketAddr,
+    /// The transfer ID of the server, once it answered.
+    peer: Option<SocketAddr>,
+    /// The last packet sent, sent again on timeouts.
+    last_sent: Vec<u8>,
+}
+
+impl Transfer {
+    fn send(&mut self, pkt: &[u8]) -> AxResult {
+        self.last_sent.clear();
+        self.last_sent.extend_from_slice(pkt);
+        self.sock
+            .send_to(pkt, self.peer.unwrap_or(self.server))
+            .map(|_| ())
+    }
+
+    /// Receives a packet of the server into `buf`, sending the last packet
+    /// again on the timeouts, returns its length.
+    fn recv(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        let mut deadline: TimeValue = monotonic_time() + RETRANSMIT_TIMEOUT;
+        let mut retries = 0;
+        loop {
+            axnet::poll_interfaces();
+            match self.sock.recv_from(buf) {
+                Ok((len, from)) => {
+                    // the server answers the request from its transfer ID
+                    match self.peer {
+                        None if from.ip() == self.server.ip() => self.peer = Some(from),
+                        Some(peer) if peer == from => {}
+                        _ => {
+                            debug!("TFTP: packet from unknown {} ignored", from);
+                            continue;
+                        }
+                    }
+                    if len < 4 {
+                        return Err(AxError::InvalidData);
+                    }
+                    return Ok(len);
+                }
+                Err(AxError::WouldBlock) if monotonic_time() < deadline => axtask::yield_now(),
+                Err(AxError::WouldBlock) => {
+                    retries += 1;
+                    if retries == MAX_RETRIES {
+                        return ax_err!(TimedOut, "TFTP: no answer from the server");
+                    }
+                    debug!("TFTP: timeout, sending again (retry {})", retries);
+                    let pkt = core::mem::take(&mut self.last_sent);
+                    self.sock.send_to(&pkt, self.peer.unwrap_or(self.server))?;
+                    self.last_sent = pkt;
+                    deadline = monotonic_time() + RETRANSMIT_TIMEOUT;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Fetches the file `path` from the TFTP server at `server`.
+pub fn fetch(server: SocketAddr, path: &str) -> AxResult<Vec<u8>> {
+    let sock = UdpSocket::new();
+    let local = match server.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    sock.bind(SocketAddr::new(local, 0))?;
+    sock.set_nonblocking(true);
+    let mut xfer = Transfer {
+        sock,
+        server,
+        peer: None,
+        last_sent: Vec::new(),
+    };
+    xfer.send(&read_request(path))?;
+
+    let mut buf = [0; 4 + BLOCK_SIZE];
+    let mut data = Vec::new();
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    // the block acknowledged last, 0 for the options
+    let mut acked: u16 = 0;
+    loop {
+        let len = xfer.recv(&mut buf)?;
+        let pkt = &buf[..len];
+        match be16(pkt, 0) {
+            // sent again if our acknowledgment was lost
+            OP_OACK if acked == 0 && data.is_empty() => {
+                let (size, tsize) = parse_oack(&pkt[2..])?;
+                block_size = size;
+                if let Some(tsize) = tsize {
+                    debug!("TFTP: {} bytes, blocks of {}", tsize, block_size);
+                    data.reserve_exact(tsize);
+                }
+                xfer.send(&ack(0))?;
+            }
+            OP_DATA => {
+                let block = be16(pkt, 2);
+                let payload = &pkt[4..];
+                if block == acked.wrapping_add(1) {
+                    if payload.len() > block_size {
+                        return Err(AxError::InvalidData);
+                    }
+                    data.extend_from_slice(payload);
+                    acked = block;
+                    xfer.send(&ack(block))?;
+                    // a short block ends the transfer
+                    if payload.len() < block_size {
+                        return Ok(data);
+                    }
+                } else if block == acked && !data.is_empty() {
+                    // our acknowledgment was lost
+                    xfer.send(&ack(block))?;
+                }
+            }
+            OP_ERROR => return Err(server_error(pkt)),
+            op => {
+                warn!("TFTP: unexpected packet {}", op);
+                return Err(AxError::InvalidData);
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::{format, vec::Vec};
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axerrno::AxResult;
+use axhal::time::monotonic_time_nanos;
+use axnet::TcpSocket;
+use kspin::SpinNoIrq;
+
+use crate::BackendStats;
+
+/// The most bytes of a health check response read for its status line.
+const MAX_STATUS_LINE: usize = 256;
+
+pub(crate) struct Backend {
+    pub addr: SocketAddr,
+    healthy: AtomicBool,
+    /// The monotonic time of the last check, in nanoseconds.
+    last_check: AtomicU64,
+    active: AtomicUsize,
+    served: AtomicU64,
+    pool: SpinNoIrq<Vec<TcpSocket>>,
+}
+
+/// Counts a connection relayed to a backend while alive.
+pub(crate) struct ActiveGuard<'a>(&'a Backend);
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Backend {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            healthy: AtomicBool::new(true),
+            last_check: AtomicU64::new(0),
+            active: AtomicUsize::new(0),
+            served: AtomicU64::new(0),
+            pool: SpinNoIrq::new(Vec::new()),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Whether the last check is older than `interval`.
+    pub fn check_due(&self, interval: Duration) -> bool {
+        let last = self.last_check.load(Ordering::Relaxed);
+        monotonic_time_nanos().saturating_sub(last) >= interval.as_nanos() as u64
+    }
+
+    pub fn start(&self) -> ActiveGuard<'_> {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        self.served.fetch_add(1, Ordering::Relaxed);
+        ActiveGuard(self)
+    }
+
+    pub fn stats(&self) -> BackendStats {
+        BackendStats {
+            addr: self.addr,
+            healthy: self.is_healthy(),
+            active: self.active(),
+            served: self.served.load(Ordering::Relaxed),
+            pooled: self.pool.lock().len(),
+        }
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        self.last_check
+            .store(monotonic_time_nanos(), Ordering::Relaxed);
+        if self.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            info!(
+                "proxy backend {} is {}",
+                self.addr,
+                if healthy { "up" } else { "down" }
+            );
+        }
+    }
+
+    /// Checks the backend, with a `GET` of `path` if any, and returns
+    /// whether it is up.
+    pub fn check(&self, path: Option<&str>) -> bool {
+        let healthy = self.probe(path).unwrap_or(false);
+        if !healthy {
+            // The pooled connections are likely broken too.
+            let pool = core::mem::take(&mut *self.pool.lock());
+            drop(pool);
+        }
+        self.set_healthy(healthy);
+        healthy
+    }
+
+    fn probe(&self, path: Option<&str>) -> AxResult<bool> {
+        let sock = TcpSocket::new();
+        sock.connect(self.addr)?;
+        let Some(path) = path else {
+            sock.shutdown().ok();
+            return Ok(true);
+        };
+        let req = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            path, self.addr
+        );
+        let mut sent = 0;
+        while sent < req.len() {
+            sent += sock.send(&req.as_bytes()[sent..])?;
+        }
+        let mut buf = [0; MAX_STATUS_LINE];
+        let mut len = 0;
+        while len < buf.len() && !buf[..len].contains(&b'\n') {
+            match sock.recv(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        sock.shutdown().ok();
+        // e.g. "HTTP/1.1 200 OK"
+        let status = core::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|line| line.strip_prefix("HTTP/1."))
+            .and_then(|line| line.get(2..5))
+            .and_then(|code| code.parse::<u16>().ok());
+        Ok(status.is_some_and(|code| (200..300).contains(&code)))
+    }
+
+    /// Opens connections until `size` are idle in the pool.
+    pub fn fill_pool(&self, size: usize) {
+        while self.pool.lock().len() < size {
+            let sock = TcpSocket::new();
+            if let Err(e) = sock.connect(self.addr) {
+                warn!("proxy backend {}: pool connect failed: {:?}", self.addr, e);
+                self.set_healthy(false);
+                return;
+            }
+            self.pool.lock().push(sock);
+        }
+    }
+
+    /// Returns a connection to the backend, a pooled one if any is still
+    /// open, marking the backend down if it cannot be reached.
+    pub fn connect(&self) -> AxResult<TcpSocket> {
+        loop {
+            let Some(sock) = self.pool.lock().pop() else {
+                break;
+            };
+            // An idle connection is readable only if closed by the backend.
+            if sock.poll().is_ok_and(|state| !state.readable) {
+                return Ok(sock);
+            }
+        }
+        let sock = TcpSocket::new();
+        if let Err(e) = sock.connect(self.addr) {
+            self.set_healthy(false);
+            return Err(e);
+        }
+        Ok(sock)
+    }
+}
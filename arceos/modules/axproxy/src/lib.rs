@@ -0,0 +1,250 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) reverse proxy and load
+//! balancer.
+//!
+//! A [`Proxy`] accepts the TCP connections on a port, e.g. of HTTP clients,
+//! and relays each one to a backend picked by its [`Balance`] policy:
+//!
+//! ```ignore
+//! let mut proxy = axproxy::Proxy::new(&["10.0.2.2:8001".parse()?, "10.0.2.2:8002".parse()?]);
+//! proxy.balance(Balance::LeastConnections).health_check("/healthz");
+//! proxy.serve("0.0.0.0:80".parse()?)?;
+//! ```
+//!
+//! # Health checks
+//!
+//! A backend failing a connection is taken out of the rotation, and checked
+//! again every check interval until it answers: it accepts a connection, or
+//! with [`Proxy::health_check`], answers a `GET` of the path with a `2xx`
+//! status. With the `multitask` feature a task checks all the backends every
+//! interval, otherwise a backend down is checked again when picked.
+//!
+//! # Connection pool
+//!
+//! With [`Proxy::pool_size`], connections to each backend are opened ahead
+//! of the clients, so that a client does not wait for the handshake with
+//! the backend. A pooled connection is used for one client only, the ones
+//! closed by the backend while idle are discarded.
+//!
+//! # Cargo Features
+//!
+//! - `multitask`: Relay every accepted connection in a separate task, and
+//!   check the backends in the background. If it is not enabled, connections
+//!   are relayed one after another.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod backend;
+mod relay;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axnet::TcpSocket;
+
+use self::backend::Backend;
+
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How a [`Proxy`] picks the backend of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balance {
+    /// Each backend up in turn.
+    RoundRobin,
+    /// The backend up relaying the fewest connections.
+    LeastConnections,
+}
+
+/// The state of a backend, see [`Proxy::stats`].
+#[derive(Debug, Clone)]
+pub struct BackendStats {
+    /// The address of the backend.
+    pub addr: SocketAddr,
+    /// Whether it passed its last check.
+    pub healthy: bool,
+    /// The connections relayed to it now.
+    pub active: usize,
+    /// The connections relayed to it since the start.
+    pub served: u64,
+    /// The idle connections in its pool.
+    pub pooled: usize,
+}
+
+/// A reverse proxy relaying the connections to a set of backends.
+pub struct Proxy {
+    backends: Vec<Backend>,
+    balance: Balance,
+    health_path: Option<String>,
+    check_interval: Duration,
+    pool_size: usize,
+    next: AtomicUsize,
+}
+
+impl Proxy {
+    /// Creates a proxy to `backends`, picked in round robin, with no pool.
+    pub fn new(backends: &[SocketAddr]) -> Self {
+        Self {
+            backends: backends.iter().map(|&addr| Backend::new(addr)).collect(),
+            balance: Balance::RoundRobin,
+            health_path: None,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            pool_size: 0,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the policy picking the backends.
+    pub fn balance(&mut self, balance: Balance) -> &mut Self {
+        self.balance = balance;
+        self
+    }
+
+    /// Checks the backends with a `GET` of `path`, instead of a connection
+    /// only.
+    pub fn health_check(&mut self, path: &str) -> &mut Self {
+        self.health_path = Some(path.into());
+        self
+    }
+
+    /// Sets the interval of the health checks, 5 seconds by default.
+    pub fn check_interval(&mut self, interval: Duration) -> &mut Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Keeps up to `size` idle connections open to each backend.
+    pub fn pool_size(&mut self, size: usize) -> &mut Self {
+        self.pool_size = size;
+        self
+    }
+
+    /// Returns the state of each backend.
+    pub fn stats(&self) -> Vec<BackendStats> {
+        self.backends.iter().map(Backend::stats).collect()
+    }
+
+    /// Listens on `addr` and relays the incoming connections forever.
+    ///
+    /// Only returns if there is no backend, or if binding or listening
+    /// fails.
+    pub fn serve(self, addr: SocketAddr) -> AxResult {
+        if self.backends.is_empty() {
+            return ax_err!(InvalidInput, "proxy without backends");
+        }
+        let listener = TcpSocket::new();
+        listener.bind(addr)?;
+        listener.listen()?;
+        info!(
+            "proxy listening on {}, {} backends",
+            listener.local_addr()?,
+            self.backends.len()
+        );
+
+        let proxy = Arc::new(self);
+        proxy.check_backends();
+        #[cfg(feature = "multitask")]
+        {
+            let proxy = proxy.clone();
+            axtask::spawn(move || loop {
+                proxy.check_backends();
+                axtask::sleep(proxy.check_interval);
+            });
+        }
+        loop {
+            let conn = match listener.accept() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("proxy accept failed: {:?}", e);
+                    continue;
+                }
+            };
+            debug!("proxy connection from {:?}", conn.peer_addr());
+            #[cfg(feature = "multitask")]
+            {
+                let proxy = proxy.clone();
+                axtask::spawn(move || proxy.serve_connection(conn));
+            }
+            #[cfg(not(feature = "multitask"))]
+            proxy.serve_connection(conn);
+        }
+    }
+
+    /// Relays an accepted connection to a backend until either side closes
+    /// it.
+    pub fn serve_connection(&self, client: TcpSocket) {
+        // Each backend up is tried once before giving up.
+        for _ in 0..self.backends.len() {
+            let Some(backend) = self.pick() else {
+                break;
+            };
+            let conn = match backend.connect() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("proxy backend {} is down: {:?}", backend.addr, e);
+                    continue;
+                }
+            };
+            let _active = backend.start();
+            match relay::relay(&client, &conn) {
+                Ok((up, down)) => debug!(
+                    "proxy {:?} <-> {}: {} bytes up, {} bytes down",
+                    client.peer_addr(),
+                    backend.addr,
+                    up,
+                    down
+                ),
+                Err(e) => debug!("proxy relay to {} failed: {:?}", backend.addr, e),
+            }
+            conn.shutdown().ok();
+            client.shutdown().ok();
+            #[cfg(not(feature = "multitask"))]
+            backend.fill_pool(self.pool_size);
+            return;
+        }
+        warn!("proxy: no backend up for {:?}", client.peer_addr());
+        client.shutdown().ok();
+    }
+
+    /// Checks the backends down, or all of them with a health path, and
+    /// fills their pools.
+    pub fn check_backends(&self) {
+        for backend in &self.backends {
+            if !backend.is_healthy() || self.health_path.is_some() {
+                backend.check(self.health_path.as_deref());
+            }
+            if backend.is_healthy() {
+                backend.fill_pool(self.pool_size);
+            }
+        }
+    }
+
+    /// Picks the backend of a new connection among the ones up, after
+    /// checking again the ones down for the check interval.
+    fn pick(&self) -> Option<&Backend> {
+        let n = self.backends.len();
+        let up = |backend: &&Backend| {
+            backend.is_healthy()
+                || (backend.check_due(self.check_interval)
+                    && backend.check(self.health_path.as_deref()))
+        };
+        match self.balance {
+            Balance::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..n)
+                    .map(|i| &self.backends[(start + i) % n])
+                    .find(|backend| up(backend))
+            }
+            Balance::LeastConnections => self
+                .backends
+                .iter()
+                .filter(up)
+                .min_by_key(|backend| backend.active()),
+        }
+    }
+}
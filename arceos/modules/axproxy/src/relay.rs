@@ -0,0 +1,70 @@
+//! Relaying the bytes between two connections.
+//!
+//! Both sockets are non-blocking, a single task moves the bytes in both
+//! directions and yields when neither side is ready. The bytes go from the
+//! receive buffer of a socket to the transmit buffer of the other by
+//! [`TcpSocket::splice_to`], without a buffer of the relay in between. The
+//! relay ends once a side closes its connection, the other side is then
+//! closed too (after sending the bytes received from it): a half-closed
+//! connection is not kept.
+
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult};
+use axhal::time::monotonic_time;
+use axnet::TcpSocket;
+
+/// The time without any byte relayed after which the relay ends.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The state of a direction.
+struct Pipe {
+    eof: bool,
+    total: u64,
+}
+
+impl Pipe {
+    const fn new() -> Self {
+        Self {
+            eof: false,
+            total: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.eof
+    }
+
+    /// Moves the bytes from `src` to `dst`, returns whether any moved.
+    fn pump(&mut self, src: &TcpSocket, dst: &TcpSocket) -> AxResult<bool> {
+        match src.splice_to(dst) {
+            Ok(Some(n)) => self.total += n as u64,
+            Ok(None) => self.eof = true,
+            Err(AxError::WouldBlock) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        Ok(true)
+    }
+}
+
+/// Relays between `client` and `backend` until either closes, returns the
+/// bytes sent to the backend and to the client.
+pub(crate) fn relay(client: &TcpSocket, backend: &TcpSocket) -> AxResult<(u64, u64)> {
+    client.set_nonblocking(true);
+    backend.set_nonblocking(true);
+    let mut up = Pipe::new();
+    let mut down = Pipe::new();
+    let mut last_progress = monotonic_time();
+    while !up.is_done() && !down.is_done() {
+        let progress = up.pump(client, backend)? | down.pump(backend, client)?;
+        if progress {
+            last_progress = monotonic_time();
+        } else if monotonic_time() - last_progress >= IDLE_TIMEOUT {
+            return Err(AxError::TimedOut);
+        } else {
+            axnet::poll_interfaces();
+            axtask::yield_now();
+        }
+    }
+    Ok((up.total, down.total))
+}
@@ -0,0 +1,271 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) record and replay of
+//! nondeterministic events.
+//!
+//! When [recording](start_recording), the kernel logs the sources of
+//! nondeterminism it observes: interrupt arrivals, scheduling decisions and
+//! random values, each stamped with a logical timestamp (a global sequence
+//! number). When [replaying](start_replay) such a log, random values are
+//! returned from the log, the scheduler picks the recorded next task if it
+//! is ready, and interrupt arrivals are checked against the log.
+//!
+//! Interrupts cannot be injected at the exact recorded point, so replay is
+//! best effort: the first event that does not match the log is reported as
+//! a [`Divergence`], which is where to look for the heisenbug.
+//!
+//! Events are matched per CPU and per kind, so the interleaving between CPUs
+//! does not need to be identical for the replay to proceed.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use kspin::SpinNoIrq;
+
+/// A nondeterministic event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayEvent {
+    /// An interrupt arrived.
+    Irq(usize),
+    /// The scheduler switched from task `prev` to task `next` (by task ID).
+    Schedule {
+        /// The previous task.
+        prev: u64,
+        /// The next task.
+        next: u64,
+    },
+    /// A random value was generated.
+    Random(u128),
+}
+
+impl ReplayEvent {
+    const fn kind(&self) -> u8 {
+        match self {
+            Self::Irq(_) => 0,
+            Self::Schedule { .. } => 1,
+            Self::Random(_) => 2,
+        }
+    }
+}
+
+/// A recorded event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// Logical timestamp.
+    pub time: u64,
+    /// The CPU on which the event happened.
+    pub cpu: usize,
+    /// The event.
+    pub event: ReplayEvent,
+}
+
+/// The first event that did not match the log during a replay.
+#[derive(Debug, Clone, Copy)]
+pub struct Divergence {
+    /// The recorded event, `None` if the log was exhausted.
+    pub expected: Option<Record>,
+    /// The event that happened instead.
+    pub actual: Record,
+}
+
+/// The recorder mode.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Neither recording nor replaying.
+    Off = 0,
+    /// Recording events.
+    Record = 1,
+    /// Replaying a log.
+    Replay = 2,
+}
+
+struct State {
+    log: Vec<Record>,
+    capacity: usize,
+    overflow: u64,
+    next_time: u64,
+    /// Next position in `log` to look at, per `(cpu, kind)` stream.
+    cursors: BTreeMap<(usize, u8), usize>,
+    divergence: Option<Divergence>,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Off as u8);
+
+static STATE: SpinNoIrq<State> = SpinNoIrq::new(State {
+    log: Vec::new(),
+    capacity: 0,
+    overflow: 0,
+    next_time: 0,
+    cursors: BTreeMap::new(),
+    divergence: None,
+});
+
+impl State {
+    fn reset(&mut self, log: Vec<Record>, capacity: usize) {
+        self.log = log;
+        self.capacity = capacity;
+        self.overflow = 0;
+        self.next_time = 0;
+        self.cursors.clear();
+        self.divergence = None;
+    }
+
+    fn record(&mut self, cpu: usize, event: ReplayEvent) {
+        let time = self.next_time;
+        self.next_time += 1;
+        // The capacity was reserved when recording started, this never
+        // allocates.
+        if self.log.len() < self.capacity {
+            self.log.push(Record { time, cpu, event });
+        } else {
+            self.overflow += 1;
+        }
+    }
+
+    /// Peeks the next recorded event of kind `kind` on `cpu`.
+    fn peek(&self, cpu: usize, kind: u8) -> Option<(usize, Record)> {
+        let start = self.cursors.get(&(cpu, kind)).copied().unwrap_or(0);
+        self.log[start..]
+            .iter()
+            .position(|r| r.cpu == cpu && r.event.kind() == kind)
+            .map(|i| (start + i, self.log[start + i]))
+    }
+
+    /// Matches `event` against the log, returns the recorded event.
+    fn replay(&mut self, cpu: usize, event: ReplayEvent, check: bool) -> Option<Record> {
+        let time = self.next_time;
+        self.next_time += 1;
+        let kind = event.kind();
+        let expected = self.peek(cpu, kind);
+        if let Some((idx, _)) = expected {
+            self.cursors.insert((cpu, kind), idx + 1);
+        }
+        let expected = expected.map(|(_, r)| r);
+        let matched = expected.is_some_and(|r| !check || r.event == event);
+        if !matched && self.divergence.is_none() {
+            let actual = Record { time, cpu, event };
+            warn!(
+                "replay diverged at time {}: expected {:?}, got {:?}",
+                time, expected, actual
+            );
+            self.divergence = Some(Divergence { expected, actual });
+        }
+        expected
+    }
+}
+
+/// Returns the current mode.
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Acquire) {
+        1 => Mode::Record,
+        2 => Mode::Replay,
+        _ => Mode::Off,
+    }
+}
+
+/// Starts recording, keeping at most `capacity` events.
+pub fn start_recording(capacity: usize) {
+    info!("replay: start recording");
+    STATE.lock().reset(Vec::with_capacity(capacity), capacity);
+    MODE.store(Mode::Record as u8, Ordering::Release);
+}
+
+/// Stops recording and returns the log.
+///
+/// Events that did not fit in the capacity are lost, and the log cannot be
+/// fully replayed; their number is logged.
+pub fn stop_recording() -> Vec<Record> {
+    MODE.store(Mode::Off as u8, Ordering::Release);
+    let mut state = STATE.lock();
+    if state.overflow > 0 {
+        warn!("replay: {} events not recorded", state.overflow);
+    }
+    info!("replay: {} events recorded", state.log.len());
+    core::mem::take(&mut state.log)
+}
+
+/// Starts replaying `log`.
+pub fn start_replay(log: Vec<Record>) {
+    info!("replay: start replaying {} events", log.len());
+    STATE.lock().reset(log, 0);
+    MODE.store(Mode::Replay as u8, Ordering::Release);
+}
+
+/// Stops replaying, returns the first divergence if any.
+pub fn stop_replay() -> Option<Divergence> {
+    MODE.store(Mode::Off as u8, Ordering::Release);
+    let mut state = STATE.lock();
+    state.log = Vec::new();
+    state.divergence.take()
+}
+
+/// Returns the first divergence of the ongoing replay.
+pub fn divergence() -> Option<Divergence> {
+    STATE.lock().divergence
+}
+
+/// Called on every interrupt arrival.
+pub fn on_irq(cpu: usize, irq: usize) {
+    match mode() {
+        Mode::Off => {}
+        Mode::Record => STATE.lock().record(cpu, ReplayEvent::Irq(irq)),
+        Mode::Replay => {
+            STATE.lock().replay(cpu, ReplayEvent::Irq(irq), true);
+        }
+    }
+}
+
+/// Called by the scheduler before picking the next task on `cpu`.
+///
+/// Returns the ID of the task to pick when replaying.
+pub fn replay_next_task(cpu: usize) -> Option<u64> {
+    if mode() != Mode::Replay {
+        return None;
+    }
+    match STATE.lock().peek(cpu, 1)?.1.event {
+        ReplayEvent::Schedule { next, .. } => Some(next),
+        _ => None,
+    }
+}
+
+/// Called by the scheduler after switching from task `prev` to task `next`.
+pub fn on_schedule(cpu: usize, prev: u64, next: u64) {
+    let event = ReplayEvent::Schedule { prev, next };
+    match mode() {
+        Mode::Off => {}
+        Mode::Record => STATE.lock().record(cpu, event),
+        Mode::Replay => {
+            STATE.lock().replay(cpu, event, true);
+        }
+    }
+}
+
+/// Generates a random value with `gen`, or returns the recorded one when
+/// replaying.
+pub fn random(cpu: usize, gen: impl FnOnce() -> u128) -> u128 {
+    match mode() {
+        Mode::Off => gen(),
+        Mode::Record => {
+            let value = gen();
+            STATE.lock().record(cpu, ReplayEvent::Random(value));
+            value
+        }
+        Mode::Replay => {
+            let value = gen();
+            let mut state = STATE.lock();
+            match state.replay(cpu, ReplayEvent::Random(value), false) {
+                Some(Record {
+                    event: ReplayEvent::Random(recorded),
+                    ..
+                }) => recorded,
+                _ => value,
+            }
+        }
+    }
+}
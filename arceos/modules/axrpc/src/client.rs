@@ -0,0 +1,134 @@
+use core::marker::PhantomData;
+use core::net::SocketAddr;
+
+use axerrno::AxResult;
+use axnet::TcpSocket;
+use prost::Message;
+
+use crate::frame::{Frame, FrameKind};
+use crate::{Code, Status};
+
+/// An RPC client bound to one server connection.
+///
+/// Calls on the same client are issued one at a time.
+pub struct RpcClient {
+    sock: TcpSocket,
+    next_id: u32,
+}
+
+impl RpcClient {
+    /// Connects to the RPC server at `addr`.
+    pub fn connect(addr: SocketAddr) -> AxResult<Self> {
+        let sock = TcpSocket::new();
+        sock.connect(addr)?;
+        Ok(Self { sock, next_id: 1 })
+    }
+
+    /// Issues a unary call and waits for the response.
+    pub fn call<Req, Resp>(&mut self, method: &str, req: &Req) -> Result<Resp, Status>
+    where
+        Req: Message,
+        Resp: Message + Default,
+    {
+        let mut stream = self.call_server_streaming::<Req, Resp>(method, req)?;
+        let resp = match stream.next() {
+            Some(resp) => resp?,
+            None => return Err(Status::internal("missing response")),
+        };
+        match stream.next() {
+            None => Ok(resp),
+            Some(Ok(_)) => Err(Status::internal("unexpected extra response")),
+            Some(Err(status)) => Err(status),
+        }
+    }
+
+    /// Issues a server-streaming call, the responses can be read from the
+    /// returned [`Streaming`] iterator.
+    pub fn call_server_streaming<Req, Resp>(
+        &mut self,
+        method: &str,
+        req: &Req,
+    ) -> Result<Streaming<'_, Resp>, Status>
+    where
+        Req: Message,
+        Resp: Message + Default,
+    {
+        let call_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let frame = Frame::call(call_id, method, &req.encode_to_vec())?;
+        #[cfg(feature = "trace")]
+        let frame = match axtrace::current() {
+            Some(context) => frame.with_trace_context(&alloc::format!("{}", context)),
+            None => frame,
+        };
+        frame.write_to(&self.sock)?;
+        Ok(Streaming {
+            client: self,
+            call_id,
+            finished: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Closes the connection.
+    pub fn close(self) -> AxResult {
+        self.sock.shutdown()
+    }
+}
+
+/// The response stream of a server-streaming call.
+///
+/// It yields every response message, and finally an `Err` if the call did
+/// not end with [`Code::Ok`].
+pub struct Streaming<'a, Resp> {
+    client: &'a mut RpcClient,
+    call_id: u32,
+    finished: bool,
+    _marker: PhantomData<Resp>,
+}
+
+impl<Resp: Message + Default> Streaming<'_, Resp> {
+    fn next_frame(&mut self) -> Result<Option<Resp>, Status> {
+        loop {
+            let frame = Frame::read_from(&self.client.sock)?
+                .ok_or_else(|| Status::new(Code::Unavailable, "connection closed"))?;
+            if frame.call_id != self.call_id {
+                // Leftover frames of an abandoned call.
+                continue;
+            }
+            return match frame.kind {
+                FrameKind::Data => Ok(Some(Resp::decode(frame.payload.as_slice())?)),
+                FrameKind::End => {
+                    let status = frame.parse_end();
+                    if status.code() == Code::Ok {
+                        Ok(None)
+                    } else {
+                        Err(status)
+                    }
+                }
+                FrameKind::Call => Err(Status::internal("unexpected call frame")),
+            };
+        }
+    }
+}
+
+impl<Resp: Message + Default> Iterator for Streaming<'_, Resp> {
+    type Item = Result<Resp, Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_frame() {
+            Ok(Some(resp)) => Some(Ok(resp)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(status) => {
+                self.finished = true;
+                Some(Err(status))
+            }
+        }
+    }
+}
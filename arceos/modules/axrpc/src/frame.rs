@@ -0,0 +1,244 @@
+//! Wire framing of the RPC protocol.
+//!
+//! Every frame starts with a fixed 10-byte header, followed by `len` bytes of
+//! payload. All integers are big-endian.
+//!
+//! ```text
+//! +--------+--------+-------------+-------------+-----------------+
+//! | kind:1 | flag:1 | call_id:4   | len:4       | payload: len    |
+//! +--------+--------+-------------+-------------+-----------------+
+//! ```
+//!
+//! A call is made of one [`FrameKind::Call`] frame from the client, whose
+//! payload is `method_len:2 | method | request`, answered by zero or more
+//! [`FrameKind::Data`] frames (one message each) and a final
+//! [`FrameKind::End`] frame carrying `code:4 | message`.
+//!
+//! A call frame with the [`FLAG_TRACE_CONTEXT`] flag propagates the trace of
+//! the caller: its payload starts with `ctx_len:1 | traceparent`, the [W3C
+//! trace context] of the client span, before the method.
+//!
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
+
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxResult};
+use axnet::TcpSocket;
+
+use crate::{Code, Status};
+
+/// Size of the frame header in bytes.
+pub const HEADER_LEN: usize = 10;
+
+/// Maximum payload length of a single frame (4 MiB).
+pub const MAX_PAYLOAD_LEN: usize = 4 << 20;
+
+/// Flag of the call frames carrying the trace context of the caller.
+pub const FLAG_TRACE_CONTEXT: u8 = 0x01;
+
+/// Frame types.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FrameKind {
+    /// Starts a call: method path and the encoded request.
+    Call = 1,
+    /// One encoded response message.
+    Data = 2,
+    /// Terminates a call with the final status.
+    End = 3,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, ()> {
+        match kind {
+            1 => Ok(Self::Call),
+            2 => Ok(Self::Data),
+            3 => Ok(Self::End),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A decoded frame.
+#[derive(Debug)]
+pub struct Frame {
+    /// Frame type.
+    pub kind: FrameKind,
+    /// Flags, [`FLAG_TRACE_CONTEXT`] or 0.
+    pub flags: u8,
+    /// Identifier of the call this frame belongs to.
+    pub call_id: u32,
+    /// Raw payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Builds a [`FrameKind::Call`] frame.
+    ///
+    /// Fails with [`InvalidInput`](axerrno::AxError::InvalidInput) if the
+    /// method path is longer than [`u16::MAX`] bytes.
+    pub fn call(call_id: u32, method: &str, request: &[u8]) -> AxResult<Self> {
+        let Ok(method_len) = u16::try_from(method.len()) else {
+            return ax_err!(InvalidInput, "RPC method path too long");
+        };
+        let mut payload = Vec::with_capacity(2 + method.len() + request.len());
+        payload.extend_from_slice(&method_len.to_be_bytes());
+        payload.extend_from_slice(method.as_bytes());
+        payload.extend_from_slice(request);
+        Ok(Self::new(FrameKind::Call, call_id, payload))
+    }
+
+    /// Adds the `traceparent` value of the caller to a [`FrameKind::Call`]
+    /// frame.
+    pub fn with_trace_context(mut self, traceparent: &str) -> Self {
+        debug_assert!(self.kind == FrameKind::Call && self.flags & FLAG_TRACE_CONTEXT == 0);
+        let len = traceparent.len().min(u8::MAX as usize);
+        let mut payload = Vec::with_capacity(1 + len + self.payload.len());
+        payload.push(len as u8);
+        payload.extend_from_slice(&traceparent.as_bytes()[..len]);
+        payload.append(&mut self.payload);
+        self.payload = payload;
+        self.flags |= FLAG_TRACE_CONTEXT;
+        self
+    }
+
+    /// Returns the `traceparent` value of the caller carried by the frame, if
+    /// any.
+    pub fn trace_context(&self) -> Option<&str> {
+        if self.flags & FLAG_TRACE_CONTEXT == 0 {
+            return None;
+        }
+        let len = *self.payload.first()? as usize;
+        let value = self.payload.get(1..1 + len)?;
+        core::str::from_utf8(value).ok()
+    }
+
+    /// Builds a [`FrameKind::Data`] frame.
+    pub fn data(call_id: u32, message: Vec<u8>) -> Self {
+        Self::new(FrameKind::Data, call_id, message)
+    }
+
+    /// Builds a [`FrameKind::End`] frame from the final status.
+    pub fn end(call_id: u32, status: &Status) -> Self {
+        let mut payload = Vec::with_capacity(4 + status.message().len());
+        payload.extend_from_slice(&(status.code() as u32).to_be_bytes());
+        payload.extend_from_slice(status.message().as_bytes());
+        Self::new(FrameKind::End, call_id, payload)
+    }
+
+    const fn new(kind: FrameKind, call_id: u32, payload: Vec<u8>) -> Self {
+        Self {
+            kind,
+            flags: 0,
+            call_id,
+            payload,
+        }
+    }
+
+    /// Splits the payload of a [`FrameKind::Call`] frame into the method path
+    /// and the encoded request.
+    pub fn parse_call(&self) -> Result<(&str, &[u8]), Status> {
+        let bad = || Status::internal("malformed call frame");
+        let mut payload = &self.payload[..];
+        if self.flags & FLAG_TRACE_CONTEXT != 0 {
+            let ctx_len = *payload.first().ok_or_else(bad)? as usize;
+            payload = payload.get(1 + ctx_len..).ok_or_else(bad)?;
+        }
+        if payload.len() < 2 {
+            return Err(bad());
+        }
+        let method_len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        let rest = &payload[2..];
+        if rest.len() < method_len {
+            return Err(bad());
+        }
+        let method = core::str::from_utf8(&rest[..method_len]).map_err(|_| bad())?;
+        Ok((method, &rest[method_len..]))
+    }
+
+    /// Decodes the status carried by a [`FrameKind::End`] frame.
+    pub fn parse_end(&self) -> Status {
+        if self.payload.len() < 4 {
+            return Status::internal("malformed end frame");
+        }
+        let code = u32::from_be_bytes(self.payload[..4].try_into().unwrap());
+        let message = alloc::string::String::from_utf8_lossy(&self.payload[4..]);
+        Status::new(Code::from(code), message)
+    }
+
+    /// Encodes the header of the frame.
+    pub(crate) fn header(&self) -> AxResult<[u8; HEADER_LEN]> {
+        if self.payload.len() > MAX_PAYLOAD_LEN {
+            return ax_err!(InvalidInput, "RPC frame too large");
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header[0] = self.kind as u8;
+        header[1] = self.flags;
+        header[2..6].copy_from_slice(&self.call_id.to_be_bytes());
+        header[6..10].copy_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        Ok(header)
+    }
+
+    /// Decodes a frame header, returns the frame without its payload and the
+    /// payload length.
+    pub(crate) fn parse_header(header: &[u8; HEADER_LEN]) -> AxResult<(Self, usize)> {
+        let kind = match FrameKind::try_from(header[0]) {
+            Ok(kind) => kind,
+            Err(_) => return ax_err!(InvalidData, "unknown RPC frame kind"),
+        };
+        let call_id = u32::from_be_bytes(header[2..6].try_into().unwrap());
+        let len = u32::from_be_bytes(header[6..10].try_into().unwrap()) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return ax_err!(InvalidData, "RPC frame too large");
+        }
+        let mut frame = Self::new(kind, call_id, Vec::new());
+        frame.flags = header[1];
+        Ok((frame, len))
+    }
+
+    /// Writes the frame to the socket.
+    pub fn write_to(&self, sock: &TcpSocket) -> AxResult {
+        write_all(sock, &self.header()?)?;
+        write_all(sock, &self.payload)
+    }
+
+    /// Reads one frame from the socket.
+    ///
+    /// Returns `Ok(None)` if the peer closed the connection cleanly before
+    /// the first byte of the header.
+    pub fn read_from(sock: &TcpSocket) -> AxResult<Option<Self>> {
+        let mut header = [0u8; HEADER_LEN];
+        let n = sock.recv(&mut header)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        read_exact(sock, &mut header[n..])?;
+
+        let (mut frame, len) = Self::parse_header(&header)?;
+        frame.payload = alloc::vec![0; len];
+        read_exact(sock, &mut frame.payload)?;
+        Ok(Some(frame))
+    }
+}
+
+fn read_exact(sock: &TcpSocket, mut buf: &mut [u8]) -> AxResult {
+    while !buf.is_empty() {
+        match sock.recv(buf)? {
+            0 => return ax_err!(UnexpectedEof, "RPC connection closed"),
+            n => buf = &mut buf[n..],
+        }
+    }
+    Ok(())
+}
+
+fn write_all(sock: &TcpSocket, mut buf: &[u8]) -> AxResult {
+    while !buf.is_empty() {
+        match sock.send(buf)? {
+            0 => return ax_err!(WriteZero),
+            n => buf = &buf[n..],
+        }
+    }
+    Ok(())
+}
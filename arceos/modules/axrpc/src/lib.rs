@@ -0,0 +1,45 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) lightweight RPC framework.
+//!
+//! It provides unary and server-streaming remote procedure calls with
+//! [protobuf] encoded messages (via the `no_std` build of [prost]), carried
+//! over a simple length-prefixed framing on top of [`axnet::TcpSocket`].
+//! The framing is small enough to be re-implemented by host-side services
+//! in a few dozen lines, see [`frame`] for the exact wire layout.
+//!
+//! # Organization
+//!
+//! - [`RpcServer`]: Registers method handlers and serves incoming calls.
+//! - [`RpcClient`]: Connects to a server and issues calls.
+//! - [`Status`]: The gRPC-style status returned by every failed call.
+//!
+//! # Cargo Features
+//!
+//! - `multitask`: Serve every accepted connection in a separate task. If it is
+//!   not enabled, connections are served one after another.
+//! - `trace`: Propagate the trace context of the caller in the call frames,
+//!   and serve every call in a span of `axtrace`, a child of the caller's.
+//!
+//! [protobuf]: https://protobuf.dev/programming-guides/encoding/
+//! [prost]: https://github.com/tokio-rs/prost
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod client;
+mod server;
+mod status;
+
+pub mod frame;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::client::{RpcClient, Streaming};
+pub use self::server::{ResponseSink, RpcServer};
+pub use self::status::{Code, Status};
+
+#[doc(no_inline)]
+pub use prost::Message;
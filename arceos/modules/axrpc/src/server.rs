@@ -0,0 +1,167 @@
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc};
+use core::net::SocketAddr;
+
+use axerrno::AxResult;
+use axnet::TcpSocket;
+use prost::Message;
+
+use crate::frame::{Frame, FrameKind};
+use crate::{Code, Status};
+
+type UnaryHandler = dyn Fn(&[u8]) -> Result<alloc::vec::Vec<u8>, Status> + Send + Sync;
+type StreamingHandler = dyn Fn(&[u8], &mut ResponseSink) -> Result<(), Status> + Send + Sync;
+
+enum Handler {
+    Unary(Box<UnaryHandler>),
+    ServerStreaming(Box<StreamingHandler>),
+}
+
+/// The sending half of a server-streaming call, passed to the handler.
+pub struct ResponseSink<'a> {
+    sock: &'a TcpSocket,
+    call_id: u32,
+}
+
+impl ResponseSink<'_> {
+    /// Sends one response message to the client.
+    pub fn send<M: Message>(&mut self, message: &M) -> Result<(), Status> {
+        Frame::data(self.call_id, message.encode_to_vec())
+            .write_to(self.sock)
+            .map_err(Status::from)
+    }
+}
+
+/// An RPC server holding the registered method handlers.
+///
+/// Methods are addressed by their full path, conventionally
+/// `"package.Service/Method"` as in gRPC.
+#[derive(Default)]
+pub struct RpcServer {
+    handlers: BTreeMap<String, Handler>,
+}
+
+impl RpcServer {
+    /// Creates a server with no methods registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a unary method: one request, one response.
+    pub fn add_unary<Req, Resp, F>(&mut self, method: &str, f: F) -> &mut Self
+    where
+        Req: Message + Default,
+        Resp: Message,
+        F: Fn(Req) -> Result<Resp, Status> + Send + Sync + 'static,
+    {
+        let handler = move |req: &[u8]| Ok(f(Req::decode(req)?)?.encode_to_vec());
+        self.handlers
+            .insert(method.into(), Handler::Unary(Box::new(handler)));
+        self
+    }
+
+    /// Registers a server-streaming method: one request, any number of
+    /// responses sent through the [`ResponseSink`].
+    pub fn add_server_streaming<Req, F>(&mut self, method: &str, f: F) -> &mut Self
+    where
+        Req: Message + Default,
+        F: Fn(Req, &mut ResponseSink) -> Result<(), Status> + Send + Sync + 'static,
+    {
+        let handler = move |req: &[u8], sink: &mut ResponseSink| f(Req::decode(req)?, sink);
+        self.handlers
+            .insert(method.into(), Handler::ServerStreaming(Box::new(handler)));
+        self
+    }
+
+    /// Listens on `addr` and serves incoming connections forever.
+    ///
+    /// Only returns if binding or listening fails.
+    pub fn serve(self, addr: SocketAddr) -> AxResult {
+        let listener = TcpSocket::new();
+        listener.bind(addr)?;
+        listener.listen()?;
+        info!("RPC server listening on {}", listener.local_addr()?);
+
+        let server = Arc::new(self);
+        loop {
+            let conn = match listener.accept() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("RPC accept failed: {:?}", e);
+                    continue;
+                }
+            };
+            debug!("RPC connection from {:?}", conn.peer_addr());
+            #[cfg(feature = "multitask")]
+            {
+                let server = server.clone();
+                axtask::spawn(move || server.serve_connection(conn));
+            }
+            #[cfg(not(feature = "multitask"))]
+            server.serve_connection(conn);
+        }
+    }
+
+    /// Serves calls on an established connection until it is closed.
+    pub fn serve_connection(&self, conn: TcpSocket) {
+        loop {
+            match Frame::read_from(&conn) {
+                Ok(Some(frame)) => {
+                    if let Err(e) = self.handle_frame(&conn, &frame) {
+                        warn!("RPC connection error: {:?}", e);
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("RPC connection error: {:?}", e);
+                    break;
+                }
+            }
+        }
+        conn.shutdown().ok();
+    }
+
+    fn handle_frame(&self, conn: &TcpSocket, frame: &Frame) -> AxResult {
+        if frame.kind != FrameKind::Call {
+            let status = Status::internal("expected a call frame");
+            return Frame::end(frame.call_id, &status).write_to(conn);
+        }
+        let result = frame.parse_call().and_then(|(method, req)| {
+            debug!("RPC call #{}: {}", frame.call_id, method);
+            #[cfg(feature = "trace")]
+            let mut span = {
+                let parent = frame.trace_context().and_then(axtrace::TraceContext::parse);
+                let mut span = axtrace::Span::start_remote(method, parent);
+                span.set_attribute("rpc.system", "axrpc");
+                span.set_attribute("rpc.method", method);
+                span
+            };
+            let result = match self.handlers.get(method) {
+                Some(Handler::Unary(f)) => f(req).and_then(|resp| {
+                    Frame::data(frame.call_id, resp)
+                        .write_to(conn)
+                        .map_err(Status::from)
+                }),
+                Some(Handler::ServerStreaming(f)) => {
+                    let mut sink = ResponseSink {
+                        sock: conn,
+                        call_id: frame.call_id,
+                    };
+                    f(req, &mut sink)
+                }
+                None => Err(Status::new(
+                    Code::Unimplemented,
+                    alloc::format!("unknown method {}", method),
+                )),
+            };
+            #[cfg(feature = "trace")]
+            if let Err(status) = &result {
+                span.set_attribute("rpc.grpc.status_code", status.code() as i64);
+                span.set_error(status.message());
+            }
+            result
+        });
+        let status = result.err().unwrap_or_else(|| Status::new(Code::Ok, ""));
+        Frame::end(frame.call_id, &status).write_to(conn)
+    }
+}
@@ -0,0 +1,121 @@
+use alloc::string::String;
+use core::fmt;
+
+use axerrno::AxError;
+
+/// Status codes, numerically compatible with the [gRPC status codes].
+///
+/// [gRPC status codes]: https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Code {
+    /// Not an error, returned on success.
+    Ok = 0,
+    /// The operation was cancelled.
+    Cancelled = 1,
+    /// Unknown error.
+    Unknown = 2,
+    /// The client specified an invalid argument.
+    InvalidArgument = 3,
+    /// The deadline expired before the operation could complete.
+    DeadlineExceeded = 4,
+    /// Some requested entity was not found.
+    NotFound = 5,
+    /// The entity that a client attempted to create already exists.
+    AlreadyExists = 6,
+    /// The caller does not have permission to execute the operation.
+    PermissionDenied = 7,
+    /// Some resource has been exhausted.
+    ResourceExhausted = 8,
+    /// The method is not implemented or not registered on the server.
+    Unimplemented = 12,
+    /// Internal errors, e.g. a malformed frame.
+    Internal = 13,
+    /// The service is currently unavailable, e.g. the connection is broken.
+    Unavailable = 14,
+    /// Unrecoverable data loss or corruption.
+    DataLoss = 15,
+}
+
+impl From<u32> for Code {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => Self::Ok,
+            1 => Self::Cancelled,
+            3 => Self::InvalidArgument,
+            4 => Self::DeadlineExceeded,
+            5 => Self::NotFound,
+            6 => Self::AlreadyExists,
+            7 => Self::PermissionDenied,
+            8 => Self::ResourceExhausted,
+            12 => Self::Unimplemented,
+            13 => Self::Internal,
+            14 => Self::Unavailable,
+            15 => Self::DataLoss,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The result status of a remote call: a [`Code`] with a human-readable
+/// message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Status {
+    code: Code,
+    message: String,
+}
+
+impl Status {
+    /// Creates a new status with the given code and message.
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the status code.
+    pub const fn code(&self) -> Code {
+        self.code
+    }
+
+    /// Returns the status message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn internal(message: impl Into<String>) -> Self {
+        Self::new(Code::Internal, message)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl From<AxError> for Status {
+    fn from(err: AxError) -> Self {
+        let code = match err {
+            AxError::InvalidInput | AxError::InvalidData => Code::InvalidArgument,
+            AxError::NotFound => Code::NotFound,
+            AxError::AlreadyExists => Code::AlreadyExists,
+            AxError::PermissionDenied => Code::PermissionDenied,
+            AxError::NoMemory => Code::ResourceExhausted,
+            AxError::Unsupported => Code::Unimplemented,
+            AxError::ConnectionRefused
+            | AxError::ConnectionReset
+            | AxError::NotConnected
+            | AxError::UnexpectedEof => Code::Unavailable,
+            _ => Code::Unknown,
+        };
+        Self::new(code, alloc::format!("{:?}", err))
+    }
+}
+
+impl From<prost::DecodeError> for Status {
+    fn from(err: prost::DecodeError) -> Self {
+        Self::new(Code::InvalidArgument, alloc::format!("{}", err))
+    }
+}
@@ -0,0 +1,125 @@
+use axerrno::AxError;
+
+use crate::frame::{Frame, FrameKind, FLAG_TRACE_CONTEXT, HEADER_LEN, MAX_PAYLOAD_LEN};
+use crate::{Code, Status};
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+/// Encodes the frame and decodes it back.
+fn round_trip(frame: &Frame) -> Frame {
+    let (mut decoded, len) = Frame::parse_header(&frame.header().unwrap()).unwrap();
+    assert_eq!(len, frame.payload.len());
+    decoded.payload = frame.payload.clone();
+    decoded
+}
+
+#[test]
+fn test_call() {
+    let frame = Frame::call(7, "/kv.Store/Get", b"\x0a\x01k").unwrap();
+    let decoded = round_trip(&frame);
+    assert_eq!(decoded.kind, FrameKind::Call);
+    assert_eq!(decoded.call_id, 7);
+    assert_eq!(decoded.flags, 0);
+    assert_eq!(decoded.trace_context(), None);
+    assert_eq!(
+        decoded.parse_call(),
+        Ok(("/kv.Store/Get", &b"\x0a\x01k"[..]))
+    );
+
+    let frame = Frame::call(u32::MAX, "", b"").unwrap();
+    assert_eq!(round_trip(&frame).call_id, u32::MAX);
+    assert_eq!(frame.parse_call(), Ok(("", &b""[..])));
+}
+
+#[test]
+fn test_call_method_len() {
+    let method = "m".repeat(u16::MAX as usize);
+    let frame = Frame::call(1, &method, b"req").unwrap();
+    assert_eq!(frame.parse_call(), Ok((method.as_str(), &b"req"[..])));
+
+    // Refused rather than truncated to 16 bits.
+    let method = "m".repeat(u16::MAX as usize + 1);
+    assert_eq!(
+        Frame::call(1, &method, b"req").unwrap_err(),
+        AxError::InvalidInput
+    );
+}
+
+#[test]
+fn test_trace_context() {
+    let frame = Frame::call(3, "/svc/Echo", b"hi")
+        .unwrap()
+        .with_trace_context(TRACEPARENT);
+    let decoded = round_trip(&frame);
+    assert_eq!(decoded.flags, FLAG_TRACE_CONTEXT);
+    assert_eq!(decoded.trace_context(), Some(TRACEPARENT));
+    assert_eq!(decoded.parse_call(), Ok(("/svc/Echo", &b"hi"[..])));
+}
+
+#[test]
+fn test_data_and_end() {
+    let frame = round_trip(&Frame::data(5, b"message".to_vec()));
+    assert_eq!(frame.kind, FrameKind::Data);
+    assert_eq!(frame.payload, b"message");
+
+    let status = Status::new(Code::NotFound, "no such key");
+    let frame = round_trip(&Frame::end(5, &status));
+    assert_eq!(frame.kind, FrameKind::End);
+    assert_eq!(frame.parse_end(), status);
+    let ok = Status::new(Code::Ok, "");
+    assert_eq!(round_trip(&Frame::end(5, &ok)).parse_end(), ok);
+}
+
+#[test]
+fn test_truncated_call() {
+    let frame = Frame::call(1, "/svc/Echo", b"")
+        .unwrap()
+        .with_trace_context(TRACEPARENT);
+    // The request may be empty, not the method.
+    for len in 0..frame.payload.len() {
+        let mut truncated = Frame::call(1, "", b"").unwrap();
+        truncated.flags = frame.flags;
+        truncated.payload = frame.payload[..len].to_vec();
+        let err = truncated.parse_call().unwrap_err();
+        assert_eq!(err.code(), Code::Internal);
+        if len <= TRACEPARENT.len() {
+            assert_eq!(truncated.trace_context(), None);
+        }
+    }
+
+    // Method not in UTF-8.
+    let mut frame = Frame::call(1, "ab", b"").unwrap();
+    frame.payload[2] = 0xff;
+    assert_eq!(frame.parse_call().unwrap_err().code(), Code::Internal);
+}
+
+#[test]
+fn test_truncated_end() {
+    for len in 0..4 {
+        let frame = Frame::data(1, alloc::vec![0; len]);
+        assert_eq!(frame.parse_end().code(), Code::Internal);
+    }
+    // Unknown status code.
+    let frame = Frame::data(1, b"\x00\x00\x00\x63oops".to_vec());
+    assert_eq!(frame.parse_end(), Status::new(Code::Unknown, "oops"));
+}
+
+#[test]
+fn test_bad_header() {
+    let mut header = Frame::data(1, b"x".to_vec()).header().unwrap();
+    assert_eq!(header.len(), HEADER_LEN);
+    header[0] = 0;
+    assert_eq!(
+        Frame::parse_header(&header).unwrap_err(),
+        AxError::InvalidData
+    );
+    header[0] = FrameKind::Data as u8;
+    header[6..10].copy_from_slice(&(MAX_PAYLOAD_LEN as u32 + 1).to_be_bytes());
+    assert_eq!(
+        Frame::parse_header(&header).unwrap_err(),
+        AxError::InvalidData
+    );
+
+    let frame = Frame::data(1, alloc::vec![0; MAX_PAYLOAD_LEN + 1]);
+    assert_eq!(frame.header().unwrap_err(), AxError::InvalidInput);
+}
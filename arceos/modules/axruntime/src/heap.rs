@@ -0,0 +1,149 @@
+//! Placement of the heap arenas.
+//!
+//! `AX_HEAP_ARENAS` is a comma-separated list of `name:size[@paddr]` (e.g.
+//! `dma:16M@0x80000000,node1:256M`), the sizes in bytes with an optional
+//! `K`, `M` or `G` suffix. Each arena is carved out of the free memory
+//! regions before the rest is given to the global heap: at `paddr` if given,
+//! otherwise from the top of the largest free range.
+
+use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags, PhysAddr, PAGE_SIZE_4K};
+
+/// The most free ranges, after splitting the regions around the arenas.
+const MAX_RANGES: usize = 32;
+
+/// The free physical memory ranges left for the global heap.
+pub(crate) struct FreeRanges {
+    ranges: [(usize, usize); MAX_RANGES],
+    len: usize,
+}
+
+impl FreeRanges {
+    fn push(&mut self, start: usize, size: usize) {
+        if size == 0 {
+            return;
+        }
+        if self.len == MAX_RANGES {
+            warn!(
+                "too many free memory ranges, [PA:{:#x}, +{:#x}) unused",
+                start, size
+            );
+            return;
+        }
+        self.ranges[self.len] = (start, size);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, index: usize) -> (usize, usize) {
+        let range = self.ranges[index];
+        self.len -= 1;
+        self.ranges[index] = self.ranges[self.len];
+        range
+    }
+
+    /// Returns the ranges, the start physical address and the size of each.
+    pub fn iter(&self) -> impl Iterator<Item = (PhysAddr, usize)> + '_ {
+        self.ranges[..self.len]
+            .iter()
+            .map(|&(start, size)| (PhysAddr::from(start), size))
+    }
+
+    /// Takes `[start, start + size)`, returns `false` if it is not free.
+    fn take_at(&mut self, start: usize, size: usize) -> bool {
+        let Some(end) = start.checked_add(size) else {
+            return false;
+        };
+        let Some(index) = self.ranges[..self.len]
+            .iter()
+            .position(|&(s, n)| s <= start && end <= s + n)
+        else {
+            return false;
+        };
+        let (s, n) = self.remove(index);
+        self.push(s, start - s);
+        self.push(end, s + n - end);
+        true
+    }
+
+    /// Takes `size` bytes from the top of the largest range, returns the
+    /// start of them.
+    fn take_top(&mut self, size: usize) -> Option<usize> {
+        let index = (0..self.len).max_by_key(|&i| self.ranges[i].1)?;
+        let (s, n) = self.ranges[index];
+        let start = (s + n).checked_sub(size)? & !(PAGE_SIZE_4K - 1);
+        if start < s {
+            return None;
+        }
+        self.take_at(start, size).then_some(start)
+    }
+}
+
+fn parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 10),
+        b'M' | b'm' => (&s[..s.len() - 1], 20),
+        b'G' | b'g' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n = match digits.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    n.checked_mul(1 << shift)
+}
+
+/// Parses `name:size[@paddr]`, the size rounded up to the pages.
+fn parse_arena(spec: &'static str) -> Option<(&'static str, usize, Option<usize>)> {
+    let (name, rest) = spec.split_once(':')?;
+    let (size, paddr) = match rest.split_once('@') {
+        Some((size, paddr)) => (size, Some(parse_size(paddr)?)),
+        None => (rest, None),
+    };
+    let size = parse_size(size)?.checked_next_multiple_of(PAGE_SIZE_4K)?;
+    if name.is_empty() || paddr.is_some_and(|p| p % PAGE_SIZE_4K != 0) {
+        return None;
+    }
+    Some((name, size, paddr))
+}
+
+/// Adds the heap arenas of `AX_HEAP_ARENAS`, returns the free memory left
+/// for the global heap.
+pub(crate) fn place_arenas() -> FreeRanges {
+    let mut free = FreeRanges {
+        ranges: [(0, 0); MAX_RANGES],
+        len: 0,
+    };
+    for r in memory_regions() {
+        if r.flags.contains(MemRegionFlags::FREE) {
+            free.push(r.paddr.as_usize(), crate::heap_size(&r));
+        }
+    }
+    let specs = option_env!("AX_HEAP_ARENAS").unwrap_or("");
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((name, size, paddr)) = parse_arena(spec) else {
+            warn!("invalid heap arena {:?}", spec);
+            continue;
+        };
+        let start = match paddr {
+            Some(paddr) => free.take_at(paddr, size).then_some(paddr),
+            None => free.take_top(size),
+        };
+        let Some(start) = start else {
+            warn!("no free memory for heap arena {:?}", spec);
+            continue;
+        };
+        let vaddr = phys_to_virt(start.into()).as_usize();
+        match axalloc::add_arena(name, vaddr, size) {
+            Ok(_) => info!(
+                "  heap arena {}: [PA:{:#x}, PA:{:#x})",
+                name,
+                start,
+                start + size
+            ),
+            Err(e) => {
+                warn!("failed to add heap arena {:?}: {:?}", spec, e);
+                free.push(start, size);
+            }
+        }
+    }
+    free
+}
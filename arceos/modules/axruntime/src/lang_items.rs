@@ -1,7 +1,66 @@
+//! The panic handler.
+//!
+//! The system goes down by the policy given by `panic=` on the command line,
+//! or by `AX_PANIC` at build time:
+//!
+//! - `poweroff` (default): Power off.
+//! - `halt`: Stop the CPU, for the host to inspect the guest.
+//! - `reboot`: Reboot.
+//! - `dump-reboot`: Report the tasks, then reboot keeping the memory, so that
+//!   the report is in the log of the previous boot with `pstore`.
+//!
+//! The host is told the guest failed in all cases, see
+//! [`axhal::misc::FailureReset`].
+
+use axhal::misc::FailureReset;
 use core::panic::PanicInfo;
 
+/// The policy if there is none on the command line.
+const DEFAULT_POLICY: &str = match option_env!("AX_PANIC") {
+    Some(policy) => policy,
+    None => "poweroff",
+};
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    #[cfg(feature = "log-ring")]
+    axlog::disable_ring();
+    report(info);
+    let policy = axhal::cmdline::args()
+        .find_map(|arg| arg.strip_prefix("panic="))
+        .unwrap_or(DEFAULT_POLICY);
+    let reset = match policy {
+        "halt" => FailureReset::Halt,
+        "reboot" => FailureReset::ColdReboot,
+        "dump-reboot" => {
+            dump();
+            FailureReset::WarmReboot
+        }
+        _ => FailureReset::PowerOff,
+    };
+    #[cfg(feature = "pstore")]
+    axlog::pstore_dump();
+    axhal::misc::fail(reset)
+}
+
+fn report(info: &PanicInfo) {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        error!("{} {}", curr.id_name_fmt(), info);
+        return;
+    }
     error!("{}", info);
-    axhal::misc::terminate()
+}
+
+fn dump() {
+    #[cfg(feature = "multitask")]
+    {
+        error!("tasks:");
+        let listed = axtask::try_for_each_task(|task| {
+            error!("  {} {}", task.id_name_fmt(), task.state_name());
+        });
+        if !listed {
+            error!("  (locked)");
+        }
+    }
 }
@@ -6,13 +6,52 @@
 //! # Cargo Features
 //!
 //! - `alloc`: Enable global memory allocator.
+//! - `page-color`: Allocate the pages of each task from its cache colors.
+//! - `heap-quota`: Charge the heap allocations of each task to the quota of
+//!   the task or of its job.
+//! - `page-scrub`: Zero the freed pages in a background task of the lowest
+//!   priority, for the allocations of the user and guest memory.
 //! - `paging`: Enable page table manipulation support.
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
+//! - `nohz`: Stop the timer tick of the isolated CPUs while a task runs.
+//! - `timer-broadcast`: Wake the idle secondary CPUs by the timer of the
+//!   primary CPU, see [`axhal::broadcast`].
+//! - `log-ring`: Write the log records into a lock-free ring, printed by a
+//!   flusher task.
+//! - `pstore`: Keep the log in a RAM region at the top of the physical memory,
+//!   surviving the warm reboots, to read the log of the previous boot with
+//!   `axlog::pstore_previous`.
+//! - `irq-guard`: Report blocking in the IRQ handlers, including the
+//!   allocations which would shrink the caches.
+//! - `pmu`: Count the cycles, instructions, cache and branch misses by the
+//!   hardware performance counters, per task with `multitask`.
+//! - `ras`: Decode and report the hardware errors instead of panicking on
+//!   all of them.
+//! - `steal-time`: Read the steal time reported by the hypervisor, and charge
+//!   the stolen timer ticks to no task with `multitask`.
+//! - `entropy`: Seed the entropy pool from its sources at boot, see
+//!   [`axhal::entropy`].
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fs`: Enable filesystem support.
-//! - `net`: Enable networking support.
+//! - `maps`: List the memory maps of the registered processes in
+//!   `/proc/[pid]/maps`.
+//! - `snapshot`: Save the state of the subsystems and of the application to a
+//!   file, restored at the next boot for a warm start, see [`snapshot`].
+//! - `net`: Enable networking support, with the network interfaces in
+//!   `/sys/class/net` along with `fs`.
+//! - `iscsi`: Use a LUN of an iSCSI target as the disk of the filesystems.
+//! - `httpdisk`: Use a disk image served over HTTP as the (read-only) disk of
+//!   the filesystems.
+//! - `netboot`: Fetch the application bundle over TFTP or HTTP at boot, and
+//!   put it into the filesystem.
+//! - `trace`: Export the spans of `axtrace` to the OTLP/HTTP collector at
+//!   `AX_OTLP_ENDPOINT`.
 //! - `display`: Enable graphics support.
+//! - `sound`: Enable sound support.
+//! - `vsock`: Enable the VM sockets support.
+//! - `selftest`: Run the self-tests of the subsystems before `main`, and
+//!   print their summary, see [`axselftest`].
 //!
 //! All the features are optional and disabled by default.
 
@@ -21,12 +60,22 @@
 
 #[macro_use]
 extern crate axlog;
+#[cfg(any(feature = "snapshot", feature = "selftest"))]
+extern crate alloc;
 
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
+#[cfg(feature = "alloc")]
+mod heap;
 #[cfg(feature = "smp")]
 mod mp;
+#[cfg(feature = "pstore")]
+mod pstore;
+#[cfg(feature = "selftest")]
+mod selftest;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
 
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
@@ -102,6 +151,8 @@ fn is_init_ok() -> bool {
 /// and the secondary CPUs call [`rust_main_secondary`].
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
+    #[cfg(feature = "pstore")]
+    pstore::init();
     ax_println!("{}", LOGO);
     ax_println!(
         "\
@@ -128,7 +179,14 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     axlog::init();
     axlog::set_max_level(option_env!("AX_LOG").unwrap_or("")); // no effect if set `log-level-*` features
     info!("Logging is enabled.");
+    #[cfg(feature = "pstore")]
+    pstore::report();
     info!("Primary CPU {} started, dtb = {:#x}.", cpu_id, dtb);
+    axcounter::set_cpu_id_hook(axhal::cpu::this_cpu_id);
+    info!("CPU features: {:?}", axhal::cpu::features());
+    if let Some(con) = axhal::earlycon::current() {
+        info!("Early console: {:x?}", con);
+    }
 
     info!("Found physcial memory regions:");
     for r in axhal::mem::memory_regions() {
@@ -149,23 +207,82 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
     info!("Initialize platform devices...");
     axhal::platform_init();
+    #[cfg(feature = "pmu")]
+    axhal::pmu::init_percpu();
+    #[cfg(feature = "ras")]
+    axhal::ras::init_percpu();
+    #[cfg(feature = "steal-time")]
+    axhal::steal::init_percpu();
+    #[cfg(feature = "entropy")]
+    axhal::entropy::init();
 
     #[cfg(feature = "multitask")]
-    axtask::init_scheduler();
+    {
+        axtask::init_scheduler();
+        axhal::console::set_break_handler(on_console_break);
+    }
+
+    #[cfg(feature = "log-ring")]
+    start_log_flusher();
+    #[cfg(feature = "page-scrub")]
+    start_page_scrubber();
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(all(feature = "irq-guard", feature = "alloc"))]
+    axalloc::set_may_block_hook(axtask::may_block);
+    #[cfg(feature = "page-color")]
+    axalloc::color::set_color_hook(|| {
+        axtask::current_may_uninit().map_or(0, |curr| curr.page_colors())
+    });
+    #[cfg(feature = "heap-quota")]
+    axalloc::quota::set_quota_hook(axtask::current_heap_quota);
+
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "sound",
+        feature = "vsock"
+    ))]
     {
+        axdriver::params::parse_cmdline(axhal::cmdline::get());
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
 
-        #[cfg(feature = "fs")]
+        #[cfg(all(feature = "fs", not(any(feature = "iscsi", feature = "httpdisk"))))]
         axfs::init_filesystems(all_devices.block);
+        #[cfg(feature = "maps")]
+        axfs::fops::set_pid_source(axfs::fops::PidSource {
+            pids: axmm::process_ids,
+            files: &[("maps", axmm::proc_maps), ("smaps", axmm::proc_smaps)],
+        });
 
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
+        #[cfg(all(feature = "fs", feature = "net"))]
+        axfs::fops::set_net_source(axfs::fops::NetSource {
+            ifaces: axnet::interface_names,
+            files: axnet::SYS_CLASS_NET_ATTRS,
+        });
+        // the root disk is on the network
+        #[cfg(feature = "iscsi")]
+        axfs::init_filesystems(axiscsi::init_iscsi());
+        #[cfg(all(feature = "httpdisk", not(feature = "iscsi")))]
+        axfs::init_filesystems(axhttpdisk::init_http_disk());
+        #[cfg(feature = "netboot")]
+        axnetboot::init_netboot();
+        #[cfg(feature = "trace")]
+        axtrace::init_exporter();
+        #[cfg(feature = "snapshot")]
+        snapshot::restore_at_boot();
 
         #[cfg(feature = "display")]
         axdisplay::init_display(all_devices.display);
+
+        #[cfg(feature = "sound")]
+        axsound::init_sound(all_devices.sound);
+
+        #[cfg(feature = "vsock")]
+        axvsock::init_vsock(all_devices.vsock);
     }
 
     #[cfg(feature = "smp")]
@@ -190,8 +307,13 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         core::hint::spin_loop();
     }
 
+    #[cfg(feature = "selftest")]
+    selftest::run();
+
     unsafe { main() };
 
+    #[cfg(feature = "log-ring")]
+    axlog::disable_ring();
     #[cfg(feature = "multitask")]
     axtask::exit(0);
     #[cfg(not(feature = "multitask"))]
@@ -201,30 +323,92 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     }
 }
 
+/// Spawns the task printing the log ring, and writes the log records into it
+/// from now on.
+#[cfg(feature = "log-ring")]
+fn start_log_flusher() {
+    const FLUSH_INTERVAL: core::time::Duration = core::time::Duration::from_millis(10);
+
+    info!("Initialize log ring...");
+    axtask::spawn_raw(
+        || loop {
+            axlog::flush_ring();
+            axtask::sleep(FLUSH_INTERVAL);
+        },
+        "log-flusher".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+    axlog::enable_ring();
+}
+
+/// Spawns the task zeroing the freed pages, see `axalloc::scrub`. It runs at
+/// the lowest priority, and sleeps while there is nothing to zero.
+#[cfg(feature = "page-scrub")]
+fn start_page_scrubber() {
+    const SCRUB_INTERVAL: core::time::Duration = core::time::Duration::from_millis(10);
+    /// The lowest nice value of CFS, the other schedulers have no priority.
+    const IDLE_PRIORITY: isize = 19;
+
+    info!("Initialize page scrubber...");
+    axtask::spawn_raw(
+        || {
+            axtask::set_priority(IDLE_PRIORITY);
+            loop {
+                while axalloc::scrub::scrub_step() {
+                    axtask::yield_now();
+                }
+                axtask::sleep(SCRUB_INTERVAL);
+            }
+        },
+        "page-scrubber".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+/// Requests the foreground task to cancel on Ctrl-C. If it did not take the
+/// previous request yet, it does not respond and the system is shut down.
+#[cfg(feature = "multitask")]
+fn on_console_break() -> bool {
+    let Some(task) = axtask::foreground() else {
+        return false;
+    };
+    if task.is_cancel_requested() {
+        warn!(
+            "{} does not respond to Ctrl-C, shutting down",
+            task.id_name()
+        );
+        axhal::misc::terminate();
+    }
+    info!("Ctrl-C: cancel {}", task.id_name());
+    task.request_cancel();
+    true
+}
+
 #[cfg(feature = "alloc")]
 fn init_allocator() {
-    use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+    use axhal::mem::phys_to_virt;
 
     info!("Initialize global memory allocator...");
     info!("  use {} allocator.", axalloc::global_allocator().name());
 
+    let free = heap::place_arenas();
     let mut max_region_size = 0;
     let mut max_region_paddr = 0.into();
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.size > max_region_size {
-            max_region_size = r.size;
-            max_region_paddr = r.paddr;
+    for (paddr, size) in free.iter() {
+        if size > max_region_size {
+            max_region_size = size;
+            max_region_paddr = paddr;
         }
     }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr == max_region_paddr {
-            axalloc::global_init(phys_to_virt(r.paddr).as_usize(), r.size);
+    for (paddr, size) in free.iter() {
+        if paddr == max_region_paddr {
+            axalloc::global_init(phys_to_virt(paddr).as_usize(), size);
             break;
         }
     }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr != max_region_paddr {
-            axalloc::global_add_memory(phys_to_virt(r.paddr).as_usize(), r.size)
+    for (paddr, size) in free.iter() {
+        if paddr != max_region_paddr {
+            axalloc::global_add_memory(phys_to_virt(paddr).as_usize(), size)
                 .expect("add heap memory region failed");
         }
     }
@@ -240,23 +424,57 @@ fn init_allocator() {
     let mut max_region_size = 0;
     let mut max_region_paddr = 0.into();
     for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.size > max_region_size {
-            max_region_size = r.size;
+        if r.flags.contains(MemRegionFlags::FREE) && heap_size(&r) > max_region_size {
+            max_region_size = heap_size(&r);
             max_region_paddr = r.paddr;
         }
     }
     for r in memory_regions() {
         if r.flags.contains(MemRegionFlags::FREE) && r.paddr == max_region_paddr {
-            alt_axalloc::global_init(phys_to_virt(r.paddr).as_usize(), r.size);
+            alt_axalloc::global_init(phys_to_virt(r.paddr).as_usize(), heap_size(&r));
             break;
         }
     }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr != max_region_paddr {
-            alt_axalloc::global_add_memory(phys_to_virt(r.paddr).as_usize(), r.size)
-                .expect("add heap memory region failed");
-        }
-    }
+    // The other free regions are only added once the heap is exhausted.
+    alt_axalloc::global_allocator().set_extend_hook(extend_heap);
+    alt_axalloc::global_allocator()
+        .set_low_watermark(alt_axalloc::Watermark::Percent(10), Some(warn_low_heap))
+        .unwrap();
+}
+
+/// Warns that the early heap is almost exhausted, from the allocator.
+#[cfg(feature = "alt_alloc")]
+fn warn_low_heap(available: usize, total: usize) {
+    warn!("early heap low: {} of {} bytes free", available, total);
+}
+
+/// Hands the free regions but the largest one (the initial heap) to the
+/// early allocator, one at each call.
+#[cfg(feature = "alt_alloc")]
+fn extend_heap(_needed: usize) -> Option<(usize, usize)> {
+    use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_REGION: AtomicUsize = AtomicUsize::new(0);
+    let free = || memory_regions().filter(|r| r.flags.contains(MemRegionFlags::FREE));
+    // the first of the largest ones, as in `init_allocator`
+    let largest = free()
+        .reduce(|a, b| if heap_size(&b) > heap_size(&a) { b } else { a })?
+        .paddr;
+    let r = free()
+        .filter(|r| r.paddr != largest)
+        .nth(NEXT_REGION.fetch_add(1, Ordering::Relaxed))?;
+    Some((phys_to_virt(r.paddr).as_usize(), heap_size(&r)))
+}
+
+/// Returns the bytes of the free region `r` given to the heap, without the
+/// pstore region.
+#[cfg(any(feature = "alloc", feature = "alt_alloc"))]
+pub(crate) fn heap_size(r: &axhal::mem::MemRegion) -> usize {
+    #[cfg(feature = "pstore")]
+    return pstore::heap_size(r.paddr.as_usize(), r.size);
+    #[cfg(not(feature = "pstore"))]
+    r.size
 }
 
 #[cfg(feature = "irq")]
@@ -274,7 +492,8 @@ fn init_interrupt() {
         let now_ns = axhal::time::monotonic_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
         let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
-        if now_ns >= deadline {
+        // The virtual clock stands still between ticks, do not catch up.
+        if now_ns >= deadline || cfg!(feature = "virtual-time") {
             deadline = now_ns + PERIODIC_INTERVAL_NANOS;
         }
         unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
@@ -282,9 +501,20 @@ fn init_interrupt() {
     }
 
     axhal::irq::register_handler(TIMER_IRQ_NUM, || {
-        update_timer();
-        #[cfg(feature = "multitask")]
-        axtask::on_timer_tick();
+        #[cfg(feature = "nohz")]
+        {
+            axtask::on_timer_tick();
+            // Stopped on isolated CPUs, the scheduler restarts it.
+            if axtask::tick_needed() {
+                update_timer();
+            }
+        }
+        #[cfg(not(feature = "nohz"))]
+        {
+            update_timer();
+            #[cfg(feature = "multitask")]
+            axtask::on_timer_tick();
+        }
     });
 
     // Enable IRQs before starting app
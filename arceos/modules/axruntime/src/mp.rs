@@ -39,6 +39,12 @@ pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
     axmm::init_memory_management_secondary();
 
     axhal::platform_init_secondary();
+    #[cfg(feature = "pmu")]
+    axhal::pmu::init_percpu();
+    #[cfg(feature = "ras")]
+    axhal::ras::init_percpu();
+    #[cfg(feature = "steal-time")]
+    axhal::steal::init_percpu();
 
     #[cfg(feature = "multitask")]
     axtask::init_scheduler_secondary();
@@ -60,6 +66,9 @@ pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
     axtask::run_idle();
     #[cfg(not(feature = "multitask"))]
     loop {
+        #[cfg(feature = "timer-broadcast")]
+        axhal::broadcast::idle();
+        #[cfg(not(feature = "timer-broadcast"))]
         axhal::arch::wait_for_irqs();
     }
 }
@@ -0,0 +1,66 @@
+//! The persistent store of the log, see `axlog::pstore_init`.
+//!
+//! The region is the last `AX_PSTORE_KB` KiB (64 by default) of the physical
+//! memory, so that it is at the same address at each boot, and it is kept out
+//! of the heap. Each half of it holds the log of a boot.
+
+use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags, PAGE_SIZE_4K};
+
+/// The KiB of the region by default.
+const DEFAULT_KB: usize = 64;
+
+/// Returns the physical address and the size of the region, `None` if it is
+/// not in the free memory.
+fn region() -> Option<(usize, usize)> {
+    let kb = option_env!("AX_PSTORE_KB").map_or(Some(DEFAULT_KB), |kb| kb.parse().ok())?;
+    let size = kb
+        .checked_mul(1024)?
+        .checked_next_multiple_of(PAGE_SIZE_4K)?;
+    let end = axconfig::PHYS_MEMORY_END & !(PAGE_SIZE_4K - 1);
+    let start = end.checked_sub(size)?;
+    memory_regions()
+        .any(|r| {
+            let paddr = r.paddr.as_usize();
+            r.flags.contains(MemRegionFlags::FREE) && paddr <= start && end <= paddr + r.size
+        })
+        .then_some((start, size))
+}
+
+/// Attaches the region, before anything is printed.
+pub(crate) fn init() {
+    if let Some((paddr, size)) = region() {
+        // SAFETY: the region is free memory, kept out of the heap, at the
+        // same address at each boot.
+        unsafe { axlog::pstore_init(phys_to_virt(paddr.into()).as_usize(), size) };
+    }
+}
+
+/// Logs the region and the log kept of the previous boot, once the logger is
+/// initialized.
+pub(crate) fn report() {
+    let Some((paddr, size)) = region() else {
+        warn!("no free memory for the pstore region");
+        return;
+    };
+    let previous = axlog::pstore_previous().map_or(0, |[a, b]| a.len() + b.len());
+    info!(
+        "pstore: [PA:{:#x}, PA:{:#x}), {} bytes of log of the previous boot",
+        paddr,
+        paddr + size,
+        previous
+    );
+}
+
+/// Returns the bytes of the free range `[paddr, paddr + size)` left for the
+/// heap, below the region.
+#[cfg(any(feature = "alloc", feature = "alt_alloc"))]
+pub(crate) fn heap_size(paddr: usize, size: usize) -> usize {
+    let Some((start, _)) = region() else {
+        return size;
+    };
+    if paddr >= start {
+        0
+    } else {
+        size.min(start - paddr)
+    }
+}
@@ -0,0 +1,180 @@
+//! The built-in self-tests, run at boot before `main` with the other
+//! registered ones, see [`axselftest`].
+//!
+//! They check the subsystems enabled: the allocator, the timer, the
+//! filesystem on the ramfs of `/tmp`, and the TCP stack with an echo to the
+//! server given by the `AX_SELFTEST_ECHO` environment variable at build time
+//! (e.g. `10.0.2.2:7`), as the network stack has no loopback interface.
+
+use axselftest::{fail, register_selftest, Outcome};
+
+/// Runs the self-tests and prints their summary.
+pub(crate) fn run() {
+    ax_println!("Running self-tests...");
+    let results = axselftest::run_all(|test, outcome| match outcome {
+        Outcome::Passed => ax_println!("  {:<12} ok", test.name),
+        Outcome::Skipped(why) => ax_println!("  {:<12} skipped: {}", test.name, why),
+        Outcome::Failed(what) => ax_println!("  {:<12} FAILED: {}", test.name, what),
+    });
+    let count = |f: fn(&Outcome) -> bool| results.iter().filter(|(_, o)| f(o)).count();
+    let failed = count(|o| matches!(o, Outcome::Failed(_)));
+    ax_println!(
+        "self-tests: {} passed, {} failed, {} skipped\n",
+        count(|o| *o == Outcome::Passed),
+        failed,
+        count(|o| matches!(o, Outcome::Skipped(_))),
+    );
+    if failed > 0 {
+        warn!("{} self-test(s) failed", failed);
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn test_allocator() -> Outcome {
+    use alloc::vec::Vec;
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+
+    const SIZES: [usize; 7] = [1, 8, 63, 64, 4095, 4096, 65536];
+    const ALIGNS: [usize; 4] = [1, 8, 64, 4096];
+
+    let allocator = axalloc::global_allocator();
+    let mut blocks: Vec<(NonNull<u8>, Layout, u8)> = Vec::new();
+    for (i, (&size, &align)) in SIZES
+        .iter()
+        .flat_map(|size| ALIGNS.iter().map(move |align| (size, align)))
+        .enumerate()
+    {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let Ok(ptr) = allocator.alloc(layout) else {
+            fail!("no memory for {:?}", layout);
+        };
+        if ptr.as_ptr() as usize % align != 0 {
+            fail!("{:?} at {:p} is misaligned", layout, ptr);
+        }
+        // The blocks are filled while the others are live, an overlap shows
+        // up as a wrong byte.
+        let byte = i as u8;
+        unsafe { ptr.as_ptr().write_bytes(byte, size) };
+        blocks.push((ptr, layout, byte));
+    }
+    for &(ptr, layout, byte) in &blocks {
+        let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+        if data.iter().any(|&b| b != byte) {
+            fail!("{:?} at {:p} was overwritten", layout, ptr);
+        }
+    }
+    for (ptr, layout, _) in blocks {
+        allocator.dealloc(ptr, layout);
+    }
+
+    let Ok(pages) = allocator.alloc_pages(4, 4 * 4096) else {
+        fail!("no memory for 4 pages");
+    };
+    allocator.dealloc_pages(pages, 4);
+    if pages % (4 * 4096) != 0 {
+        fail!("pages at {:#x} are misaligned", pages);
+    }
+    Outcome::Passed
+}
+
+#[cfg(feature = "alloc")]
+register_selftest!("allocator", test_allocator);
+
+fn test_timer() -> Outcome {
+    use axhal::time::monotonic_time;
+    use core::time::Duration;
+
+    const DUR: Duration = Duration::from_millis(20);
+    const TOLERANCE: Duration = Duration::from_millis(20);
+
+    let check = |what: &str, elapsed: Duration| {
+        if elapsed < DUR || elapsed > DUR + TOLERANCE {
+            Err(alloc::format!("{} of {:?} took {:?}", what, DUR, elapsed))
+        } else {
+            Ok(())
+        }
+    };
+    let start = monotonic_time();
+    axhal::time::busy_wait(DUR);
+    if let Err(e) = check("busy wait", monotonic_time() - start) {
+        return Outcome::Failed(e);
+    }
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    {
+        let start = monotonic_time();
+        axtask::sleep(DUR);
+        if let Err(e) = check("sleep", monotonic_time() - start) {
+            return Outcome::Failed(e);
+        }
+    }
+    Outcome::Passed
+}
+
+register_selftest!("timer", test_timer);
+
+#[cfg(feature = "fs")]
+fn test_fs() -> Outcome {
+    const PATH: &str = "/tmp/.selftest";
+
+    let data: alloc::vec::Vec<u8> = (0..10000u32).map(|i| (i * 7) as u8).collect();
+    if let Err(e) = axfs::api::write(PATH, &data) {
+        fail!("writing {}: {:?}", PATH, e);
+    }
+    let read = axfs::api::read(PATH);
+    axfs::api::remove_file(PATH).ok();
+    match read {
+        Ok(read) if read == data => Outcome::Passed,
+        Ok(read) => fail!(
+            "read {} bytes back, not the {} written",
+            read.len(),
+            data.len()
+        ),
+        Err(e) => fail!("reading {}: {:?}", PATH, e),
+    }
+}
+
+#[cfg(feature = "fs")]
+register_selftest!("fs", test_fs);
+
+#[cfg(feature = "net")]
+fn test_tcp_echo() -> Outcome {
+    use axnet::TcpSocket;
+
+    const MESSAGE: &[u8] = b"ArceOS self-test";
+
+    let Some(addr) = option_env!("AX_SELFTEST_ECHO") else {
+        return Outcome::Skipped("no echo server in AX_SELFTEST_ECHO");
+    };
+    let Ok(addr) = addr.parse() else {
+        fail!("invalid echo server {:?}", addr);
+    };
+    let sock = TcpSocket::new();
+    if let Err(e) = sock.connect(addr) {
+        fail!("connecting to {}: {:?}", addr, e);
+    }
+    let mut sent = 0;
+    while sent < MESSAGE.len() {
+        match sock.send(&MESSAGE[sent..]) {
+            Ok(n) => sent += n,
+            Err(e) => fail!("sending: {:?}", e),
+        }
+    }
+    let mut buf = [0; MESSAGE.len()];
+    let mut received = 0;
+    while received < buf.len() {
+        match sock.recv(&mut buf[received..]) {
+            Ok(0) => fail!("closed after {} bytes", received),
+            Ok(n) => received += n,
+            Err(e) => fail!("receiving: {:?}", e),
+        }
+    }
+    sock.shutdown().ok();
+    if buf != MESSAGE {
+        fail!("echoed {:?}", buf);
+    }
+    Outcome::Passed
+}
+
+#[cfg(feature = "net")]
+register_selftest!("tcp-echo", test_tcp_echo);
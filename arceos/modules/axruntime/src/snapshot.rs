@@ -0,0 +1,379 @@
+//! Checkpoints of the state of the kernel, restored at boot for warm starts.
+//!
+//! A serverless function spends most of its cold start initializing: loading
+//! its models, filling its caches, writing its scratch files. [`checkpoint`]
+//! writes the state of the subsystems and of the application to a file once
+//! it is initialized, and the next boot restores it from that file before
+//! `main`, which checks [`restored`] to skip the initialization.
+//!
+//! The state is made of the [`SnapshotSection`]s registered in
+//! [`SNAPSHOT_SECTIONS`], each saved as bytes by a subsystem or by the
+//! application and given back to it at the restore:
+//!
+//! ```ignore
+//! use axruntime::snapshot::{register_snapshot_section, SnapshotSection, SNAPSHOT_SECTIONS};
+//!
+//! #[register_snapshot_section(SNAPSHOT_SECTIONS)]
+//! static CACHE: SnapshotSection = SnapshotSection::new("app.cache", save_cache, restore_cache);
+//! ```
+//!
+//! The built-in sections are:
+//!
+//! - `files`: the directories and the regular files under `/tmp`, the RAM
+//!   filesystem, with their permission bits and their access and
+//!   modification times;
+//! - `params`: the values of the driver parameters settable at run time, see
+//!   [`axdriver::params`].
+//!
+//! The tasks, the address spaces, the sockets and the state of the allocators
+//! are not saved: they are made of pointers into the memory of the previous
+//! boot, which is not saved, and of the states of the devices and of the
+//! peers. The saved state of the early allocator (`save_state` of
+//! `bump_allocator`) is no exception, it describes the allocations in that
+//! memory and is only restorable on the same memory, across a suspend. The
+//! application saves the data it needs to rebuild them.
+//!
+//! The image is restored from the file given by the argument
+//! `snapshot=<path>` of the command line, `/snapshot.img` by default, if it
+//! exists; `snapshot=off` boots cold. It is a sequence of little-endian
+//! 64-bit words: [`SNAPSHOT_MAGIC`], [`SNAPSHOT_VERSION`], the fingerprint of
+//! the kernel build and the number of sections, then each section as the
+//! length of its name, its name, the length of its data and its data, padded
+//! to a word, and last a checksum of all the words before. An image of
+//! another build, truncated or corrupted, is not restored.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axfs::api::Permissions;
+use core::time::Duration;
+
+pub use linkme::distributed_slice as register_snapshot_section;
+
+/// All the sections of the snapshots.
+#[linkme::distributed_slice]
+pub static SNAPSHOT_SECTIONS: [SnapshotSection];
+
+/// The magic number of the images, "AXSNAPSH".
+pub const SNAPSHOT_MAGIC: u64 = u64::from_le_bytes(*b"AXSNAPSH");
+
+/// The version of the layout of the images.
+pub const SNAPSHOT_VERSION: u64 = 2;
+
+/// The image restored by default.
+const DEFAULT_PATH: &str = "/snapshot.img";
+
+/// The directory saved by the `files` section.
+const FILES_ROOT: &str = "/tmp";
+
+static RESTORED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// A part of the state saved in the snapshots, see the [module
+/// documentation](self).
+pub struct SnapshotSection {
+    name: &'static str,
+    save: fn(&mut Vec<u8>) -> AxResult,
+    restore: fn(&[u8]) -> AxResult,
+}
+
+impl SnapshotSection {
+    /// Creates the section `name`, whose state is appended to a buffer by
+    /// `save` and restored from its bytes by `restore`.
+    ///
+    /// The names starting with `app.` are left to the application.
+    pub const fn new(
+        name: &'static str,
+        save: fn(&mut Vec<u8>) -> AxResult,
+        restore: fn(&[u8]) -> AxResult,
+    ) -> Self {
+        Self {
+            name,
+            save,
+            restore,
+        }
+    }
+
+    /// Returns the name of the section.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Returns whether the state was restored from a snapshot at boot.
+pub fn restored() -> bool {
+    RESTORED.load(core::sync::atomic::Ordering::Acquire)
+}
+
+/// The FNV-1a hash of `bytes`, continued from `hash`.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Identifies the kernel build: the pointers saved by the sections (the
+/// functions of the application, say) are only valid in the same image.
+fn fingerprint() -> u64 {
+    let hash = fnv1a(
+        FNV_OFFSET,
+        option_env!("AX_PLATFORM").unwrap_or("").as_bytes(),
+    );
+    let hash = fnv1a(hash, env!("CARGO_PKG_VERSION").as_bytes());
+    fnv1a(hash, &(crate::rust_main as usize).to_le_bytes())
+}
+
+fn push_word(image: &mut Vec<u8>, word: u64) {
+    image.extend_from_slice(&word.to_le_bytes());
+}
+
+fn push_bytes(image: &mut Vec<u8>, bytes: &[u8]) {
+    push_word(image, bytes.len() as u64);
+    image.extend_from_slice(bytes);
+    image.resize(image.len().next_multiple_of(8), 0);
+}
+
+/// Reads the words and the byte arrays of an image.
+struct Reader<'a> {
+    image: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn word(&mut self) -> AxResult<u64> {
+        let bytes = self
+            .image
+            .get(self.pos..self.pos + 8)
+            .ok_or(AxError::InvalidData)?;
+        self.pos += 8;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> AxResult<&'a [u8]> {
+        let len = usize::try_from(self.word()?).map_err(|_| AxError::InvalidData)?;
+        let end = self.pos.checked_add(len).ok_or(AxError::InvalidData)?;
+        let bytes = self.image.get(self.pos..end).ok_or(AxError::InvalidData)?;
+        self.pos = end.next_multiple_of(8);
+        Ok(bytes)
+    }
+}
+
+/// Saves all the sections in an image.
+pub fn save() -> AxResult<Vec<u8>> {
+    let mut image = Vec::new();
+    push_word(&mut image, SNAPSHOT_MAGIC);
+    push_word(&mut image, SNAPSHOT_VERSION);
+    push_word(&mut image, fingerprint());
+    push_word(&mut image, SNAPSHOT_SECTIONS.len() as u64);
+    let mut data = Vec::new();
+    for section in SNAPSHOT_SECTIONS {
+        data.clear();
+        (section.save)(&mut data).inspect_err(|err| {
+            warn!("snapshot: failed to save {}: {:?}", section.name, err);
+        })?;
+        push_bytes(&mut image, section.name.as_bytes());
+        push_bytes(&mut image, &data);
+    }
+    let checksum = fnv1a(FNV_OFFSET, &image);
+    push_word(&mut image, checksum);
+    Ok(image)
+}
+
+/// Checks `image` and returns its sections, as their names and data.
+fn parse(image: &[u8]) -> AxResult<Vec<(&str, &[u8])>> {
+    let Some(body_len) = image.len().checked_sub(8).filter(|len| len % 8 == 0) else {
+        return ax_err!(InvalidData, "truncated snapshot");
+    };
+    let mut reader = Reader { image, pos: 0 };
+    if reader.word()? != SNAPSHOT_MAGIC {
+        return ax_err!(InvalidData, "not a snapshot");
+    }
+    if reader.word()? != SNAPSHOT_VERSION {
+        return ax_err!(Unsupported, "unknown snapshot version");
+    }
+    if reader.word()? != fingerprint() {
+        return ax_err!(Unsupported, "snapshot of another kernel build");
+    }
+    let checksum = u64::from_le_bytes(image[body_len..].try_into().unwrap());
+    if fnv1a(FNV_OFFSET, &image[..body_len]) != checksum {
+        return ax_err!(InvalidData, "corrupted snapshot");
+    }
+    let count = reader.word()?;
+    let mut sections = Vec::new();
+    for _ in 0..count {
+        let name = core::str::from_utf8(reader.bytes()?).map_err(|_| AxError::InvalidData)?;
+        sections.push((name, reader.bytes()?));
+    }
+    if reader.pos != body_len {
+        return ax_err!(InvalidData, "inconsistent snapshot");
+    }
+    Ok(sections)
+}
+
+/// Restores the sections from `image`.
+///
+/// The sections of the image are checked before any is restored. The ones
+/// not registered are ignored, and the registered ones missing from the
+/// image are not restored.
+pub fn restore(image: &[u8]) -> AxResult {
+    let sections = parse(image)?;
+    for (name, data) in sections {
+        let Some(section) = SNAPSHOT_SECTIONS.iter().find(|s| s.name == name) else {
+            warn!("snapshot: unknown section {}", name);
+            continue;
+        };
+        (section.restore)(data).inspect_err(|err| {
+            warn!("snapshot: failed to restore {}: {:?}", name, err);
+        })?;
+    }
+    RESTORED.store(true, core::sync::atomic::Ordering::Release);
+    Ok(())
+}
+
+/// Saves all the sections in the image file `path`.
+///
+/// The image is written beside it first, then renamed, so that a crash
+/// leaves the previous image.
+pub fn checkpoint(path: &str) -> AxResult {
+    let image = save()?;
+    let tmp = alloc::format!("{}.tmp", path);
+    axfs::api::write(&tmp, &image)?;
+    axfs::api::rename(&tmp, path)?;
+    info!("snapshot: {} bytes saved to {}", image.len(), path);
+    Ok(())
+}
+
+/// Restores the image given on the command line, if any, once the
+/// filesystems are initialized.
+pub(crate) fn restore_at_boot() {
+    let path = axhal::cmdline::args()
+        .find_map(|arg| arg.strip_prefix("snapshot="))
+        .unwrap_or(DEFAULT_PATH);
+    if path == "off" {
+        return;
+    }
+    let Ok(image) = axfs::api::read(path) else {
+        return;
+    };
+    let start = axhal::time::monotonic_time();
+    match restore(&image) {
+        Ok(()) => info!(
+            "snapshot: restored from {} in {:?}",
+            path,
+            axhal::time::monotonic_time() - start
+        ),
+        Err(err) => warn!("snapshot: not restored from {}: {:?}", path, err),
+    }
+}
+
+#[linkme::distributed_slice(SNAPSHOT_SECTIONS)]
+static FILES: SnapshotSection = SnapshotSection::new("files", save_files, restore_files);
+
+#[linkme::distributed_slice(SNAPSHOT_SECTIONS)]
+static PARAMS: SnapshotSection = SnapshotSection::new("params", save_params, restore_params);
+
+/// The kinds of the entries of the `files` section.
+const ENTRY_DIR: u64 = 0;
+const ENTRY_FILE: u64 = 1;
+
+/// Saves the entries under `dir`, as their kind, their path, their
+/// permission bits, their access and modification times in nanoseconds and
+/// the data of the files, the directories before their entries.
+fn save_dir(dir: &str, data: &mut Vec<u8>) -> AxResult {
+    for entry in axfs::api::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let ty = entry.file_type();
+        let kind = if ty.is_dir() {
+            ENTRY_DIR
+        } else if ty.is_file() {
+            ENTRY_FILE
+        } else {
+            continue;
+        };
+        // before the data is read, which may update the access time
+        let perm = axfs::api::metadata(&path)?.permissions();
+        let times = axfs::api::times(&path)?;
+        push_word(data, kind);
+        push_bytes(data, path.as_bytes());
+        push_word(data, perm.bits() as u64);
+        push_word(data, times.atime.as_nanos() as u64);
+        push_word(data, times.mtime.as_nanos() as u64);
+        if kind == ENTRY_DIR {
+            save_dir(&path, data)?;
+        } else {
+            push_bytes(data, &axfs::api::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+fn save_files(data: &mut Vec<u8>) -> AxResult {
+    match axfs::api::metadata(FILES_ROOT) {
+        Ok(_) => save_dir(FILES_ROOT, data),
+        Err(AxError::NotFound) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn restore_files(data: &[u8]) -> AxResult {
+    let mut reader = Reader {
+        image: data,
+        pos: 0,
+    };
+    // the times of a directory change as its entries are restored
+    let mut attrs = Vec::new();
+    while reader.pos < data.len() {
+        let kind = reader.word()?;
+        let path = core::str::from_utf8(reader.bytes()?).map_err(|_| AxError::InvalidData)?;
+        if !path.starts_with(FILES_ROOT) {
+            return ax_err!(InvalidData, "file out of the saved directory");
+        }
+        let perm = Permissions::from_bits(reader.word()? as u16).ok_or(AxError::InvalidData)?;
+        let atime = Duration::from_nanos(reader.word()?);
+        let mtime = Duration::from_nanos(reader.word()?);
+        match kind {
+            ENTRY_DIR => axfs::api::create_dir_all(path)?,
+            ENTRY_FILE => axfs::api::write(path, reader.bytes()?)?,
+            _ => return ax_err!(InvalidData, "unknown file kind"),
+        }
+        attrs.push((path, perm, atime, mtime));
+    }
+    for (path, perm, atime, mtime) in attrs.into_iter().rev() {
+        axfs::api::set_permissions(path, perm)?;
+        axfs::api::set_times(path, Some(atime), Some(mtime))?;
+    }
+    Ok(())
+}
+
+/// Saves the parameters as lines `<driver>.<name>=<value>`.
+fn save_params(data: &mut Vec<u8>) -> AxResult {
+    let mut lines = String::new();
+    for param in axdriver::params::params().filter(|p| !p.is_boot_only()) {
+        lines += &alloc::format!("{}.{}={}\n", param.driver(), param.name(), param.get());
+    }
+    data.extend_from_slice(lines.as_bytes());
+    Ok(())
+}
+
+fn restore_params(data: &[u8]) -> AxResult {
+    let lines = core::str::from_utf8(data).map_err(|_| AxError::InvalidData)?;
+    for line in lines.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            return ax_err!(InvalidData, "invalid parameter line");
+        };
+        let param = key
+            .split_once('.')
+            .and_then(|(driver, name)| axdriver::params::find(driver, name));
+        // Of a driver no longer built in.
+        let Some(param) = param else {
+            continue;
+        };
+        if param.set_str(value).is_err() {
+            warn!("snapshot: invalid driver parameter {}", line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) built-in self-tests
+//! registered by any crate.
+//!
+//! A subsystem adds a test of itself, run at boot before the application
+//! with the `selftest` feature of the runtime, with [`register_selftest!`]:
+//!
+//! ```ignore
+//! fn test_timer() -> axselftest::Outcome {
+//!     axselftest::Outcome::Passed
+//! }
+//!
+//! axselftest::register_selftest!("timer", test_timer);
+//! ```
+//!
+//! The tests of all the crates linked in the kernel are collected in
+//! [`SELF_TESTS`] at link time, [`run_all`] runs them in order of their
+//! names, so that the bring-up logs of two boards compare line by line.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+#[doc(hidden)]
+pub use linkme;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::format;
+}
+
+/// The result of a self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The subsystem works.
+    Passed,
+    /// The test could not run, e.g. without the device it needs, and why.
+    Skipped(&'static str),
+    /// The subsystem does not work, and what went wrong.
+    Failed(String),
+}
+
+/// A self-test of a subsystem, see [`register_selftest!`].
+pub struct SelfTest {
+    /// The name printed in the summary.
+    pub name: &'static str,
+    /// The function run.
+    pub func: fn() -> Outcome,
+}
+
+/// All the self-tests registered by the crates.
+#[linkme::distributed_slice]
+pub static SELF_TESTS: [SelfTest];
+
+/// Registers the self-test `name` running `func`.
+#[macro_export]
+macro_rules! register_selftest {
+    ($name:literal, $func:path) => {
+        const _: () = {
+            #[$crate::linkme::distributed_slice($crate::SELF_TESTS)]
+            #[linkme(crate = $crate::linkme)]
+            static TEST: $crate::SelfTest = $crate::SelfTest {
+                name: $name,
+                func: $func,
+            };
+        };
+    };
+}
+
+/// Returns [`Outcome::Failed`] with the formatted message from the test
+/// function.
+#[macro_export]
+macro_rules! fail {
+    ($($arg:tt)+) => {
+        return $crate::Outcome::Failed($crate::__private::format!($($arg)+))
+    };
+}
+
+/// Runs all the registered self-tests in order of their names, calling
+/// `report` after each one.
+pub fn run_all(mut report: impl FnMut(&SelfTest, &Outcome)) -> Vec<(&'static str, Outcome)> {
+    let mut tests: Vec<&'static SelfTest> = SELF_TESTS.iter().collect();
+    tests.sort_by_key(|test| test.name);
+    tests
+        .into_iter()
+        .map(|test| {
+            let outcome = (test.func)();
+            report(test, &outcome);
+            (test.name, outcome)
+        })
+        .collect()
+}
@@ -0,0 +1,114 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) sound module.
+//!
+//! Currently only supports the PCM playback on the output streams of the main
+//! sound device.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+
+#[doc(no_inline)]
+pub use axdriver::sound::{PcmFormat, PcmParams};
+
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{AxError, AxResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+
+static MAIN_SOUND: LazyInit<Mutex<AxSoundDevice>> = LazyInit::new();
+
+/// Initializes the sound subsystem by underlayer devices.
+pub fn init_sound(mut sound_devs: AxDeviceContainer<AxSoundDevice>) {
+    info!("Initialize sound subsystem...");
+
+    let dev = sound_devs.take_one().expect("No sound device found!");
+    info!(
+        "  use sound device 0: {:?}, {} output streams",
+        dev.device_name(),
+        dev.num_output_streams()
+    );
+    MAIN_SOUND.init_once(Mutex::new(dev));
+}
+
+const fn as_ax_err(e: DevError) -> AxError {
+    match e {
+        DevError::AlreadyExists => AxError::AlreadyExists,
+        DevError::Again => AxError::WouldBlock,
+        DevError::BadState => AxError::BadState,
+        DevError::InvalidParam => AxError::InvalidInput,
+        DevError::Io => AxError::Io,
+        DevError::NoMemory => AxError::NoMemory,
+        DevError::ResourceBusy => AxError::ResourceBusy,
+        DevError::Unsupported => AxError::Unsupported,
+    }
+}
+
+/// Returns the number of output streams of the sound device.
+pub fn num_output_streams() -> usize {
+    MAIN_SOUND.lock().num_output_streams()
+}
+
+/// A PCM stream playing on an output stream of the sound device.
+///
+/// When dropped, the playback is stopped without waiting for the queued
+/// frames, see [`PcmStream::drain`].
+pub struct PcmStream {
+    stream: usize,
+    params: PcmParams,
+}
+
+impl PcmStream {
+    /// Opens the output stream `stream` with `params`.
+    pub fn open(stream: usize, params: PcmParams) -> AxResult<Self> {
+        MAIN_SOUND.lock().open(stream, &params).map_err(as_ax_err)?;
+        Ok(Self { stream, params })
+    }
+
+    /// Returns the parameters of the stream.
+    pub fn params(&self) -> &PcmParams {
+        &self.params
+    }
+
+    /// Queues the frames of `buf` for the playback, waiting for queued frames
+    /// being played to make room for them.
+    ///
+    /// Returns the number of bytes queued, the length of `buf` without the
+    /// last partial frame.
+    pub fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
+        let len = buf.len() - buf.len() % self.params.frame_bytes();
+        let mut written = 0;
+        while written < len {
+            match MAIN_SOUND.lock().write(self.stream, &buf[written..len]) {
+                Ok(n) => written += n,
+                Err(DevError::Again) => {}
+                Err(e) => return Err(as_ax_err(e)),
+            }
+            if written < len {
+                axtask::yield_now();
+            }
+        }
+        Ok(written)
+    }
+
+    /// Waits until all the queued frames have been played.
+    pub fn drain(&mut self) -> AxResult {
+        while MAIN_SOUND
+            .lock()
+            .pending_bytes(self.stream)
+            .map_err(as_ax_err)?
+            > 0
+        {
+            axtask::yield_now();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PcmStream {
+    fn drop(&mut self) {
+        if let Err(e) = MAIN_SOUND.lock().close(self.stream) {
+            warn!("failed to close PCM stream {}: {:?}", self.stream, e);
+        }
+    }
+}
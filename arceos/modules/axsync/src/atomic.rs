@@ -0,0 +1,86 @@
+//! Atomics and memory barriers, with the orderings of the primitives of the
+//! crate.
+//!
+//! The atomic types are the ones of [`core::sync::atomic`], or of [loom] in
+//! the builds with `--cfg loom`, which check the primitives under all the
+//! interleavings and the reorderings allowed by the C++ memory model, like
+//! a weakly-ordered CPU does. The lock-free code of the crate uses them
+//! through this module, and [`spin_loop`] in its busy waits, so that it is
+//! model checked:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test -p axsync --release loom
+//! ```
+//!
+//! # Barriers
+//!
+//! The fences, and the instructions they are lowered to:
+//!
+//! | Fence          | Orders                        | RISC-V         | AArch64     | x86_64   |
+//! |----------------|-------------------------------|----------------|-------------|----------|
+//! | [`smp_mb`]     | all accesses                  | `fence rw, rw` | `dmb ish`   | `mfence` |
+//! | [`smp_rmb`]    | loads before, all after       | `fence r, rw`  | `dmb ishld` | none     |
+//! | [`smp_wmb`]    | all before, stores after      | `fence rw, w`  | `dmb ish`   | none     |
+//!
+//! They order the accesses of this CPU as seen by the other CPUs only, not by
+//! the devices: the MMIO and DMA accesses are ordered by the drivers.
+//!
+//! # Orderings of the primitives
+//!
+//! | Primitive                  | Operation                | Ordering                 |
+//! |----------------------------|--------------------------|--------------------------|
+//! | [`spin`] lock              | lock, `compare_exchange` | `Acquire` / `Relaxed`    |
+//! |                            | spin on the lock, `load` | `Relaxed`                |
+//! |                            | unlock, `store`          | `Release`                |
+//! | [`Mutex`](crate::Mutex)    | lock, `compare_exchange` | `Acquire` / `Relaxed`    |
+//! |                            | unlock, `swap`           | `Release`                |
+//! | lock statistics            | all                      | `Relaxed`, no ordering   |
+//!
+//! The unlock with `Release` and the next lock with `Acquire` order the
+//! critical sections of the successive holders, the failed attempts and the
+//! spins synchronize with nothing.
+//!
+//! [loom]: https://docs.rs/loom
+
+#[cfg(not(loom))]
+pub use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicI64, AtomicIsize, AtomicU32, AtomicU64, AtomicUsize,
+};
+#[cfg(loom)]
+pub use loom::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicI64, AtomicIsize, AtomicU32, AtomicU64, AtomicUsize,
+};
+
+pub use core::sync::atomic::Ordering;
+
+#[cfg(not(loom))]
+use core::sync::atomic::fence;
+#[cfg(loom)]
+use loom::sync::atomic::fence;
+
+#[cfg(not(loom))]
+pub use core::hint::spin_loop;
+#[cfg(loom)]
+pub use loom::hint::spin_loop;
+
+/// A full barrier: the accesses before it are seen by the other CPUs before
+/// the ones after it.
+#[inline(always)]
+pub fn smp_mb() {
+    fence(Ordering::SeqCst);
+}
+
+/// A read barrier: the loads before it are done before the accesses after
+/// it, e.g. reading the data after the flag telling it is ready.
+#[inline(always)]
+pub fn smp_rmb() {
+    fence(Ordering::Acquire);
+}
+
+/// A write barrier: the accesses before it are seen by the other CPUs before
+/// the stores after it, e.g. writing the data before the flag telling it is
+/// ready.
+#[inline(always)]
+pub fn smp_wmb() {
+    fence(Ordering::Release);
+}
@@ -0,0 +1,171 @@
+//! Scheduler-aware spin-wait helpers.
+
+use core::cell::Cell;
+use core::time::Duration;
+
+use kernel_guard::NoPreempt;
+
+/// Up to `2^SPIN_LIMIT` spin-loop hints are issued by a single step.
+const SPIN_LIMIT: u32 = 6;
+/// After this many steps, [`Backoff::snooze`] stops spinning and yields.
+const YIELD_LIMIT: u32 = 10;
+/// How long [`spin_wait_until`] sleeps per round once the backoff is
+/// completed.
+const SLEEP_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Exponential backoff for spin-wait loops.
+///
+/// Each step doubles the number of [`core::hint::spin_loop`] hints, up to a
+/// limit. [`snooze`](Backoff::snooze) additionally gives up the CPU once
+/// spinning is unlikely to help, and [`is_completed`](Backoff::is_completed)
+/// tells the caller it should block instead.
+///
+/// # Examples
+///
+/// ```ignore
+/// let backoff = Backoff::new();
+/// while !ready.load(Ordering::Acquire) {
+///     backoff.snooze();
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Backoff {
+    step: Cell<u32>,
+}
+
+impl Backoff {
+    /// Creates a new `Backoff`.
+    pub const fn new() -> Self {
+        Self { step: Cell::new(0) }
+    }
+
+    /// Resets the backoff to its initial state.
+    pub fn reset(&self) {
+        self.step.set(0);
+    }
+
+    /// Backs off in a lock-free loop, i.e. when another CPU is expected to
+    /// make progress very soon. Never yields.
+    pub fn spin(&self) {
+        for _ in 0..1 << self.step.get().min(SPIN_LIMIT) {
+            core::hint::spin_loop();
+        }
+        if self.step.get() <= SPIN_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// Backs off in a blocking loop, i.e. when waiting for another task.
+    ///
+    /// It spins first, then calls [`axtask::yield_now`] to let other tasks
+    /// run on this CPU.
+    pub fn snooze(&self) {
+        if self.step.get() <= SPIN_LIMIT {
+            for _ in 0..1 << self.step.get() {
+                core::hint::spin_loop();
+            }
+        } else {
+            axtask::yield_now();
+        }
+        if self.step.get() <= YIELD_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// Returns `true` if spinning and yielding have been exhausted, and the
+    /// caller should rather sleep or block on a wait queue.
+    pub fn is_completed(&self) -> bool {
+        self.step.get() > YIELD_LIMIT
+    }
+}
+
+/// Waits until `condition` returns `true`.
+///
+/// It spins for a short while, then yields the CPU, and finally sleeps
+/// between polls, so long waits do not burn a whole core.
+pub fn spin_wait_until<F: FnMut() -> bool>(mut condition: F) {
+    let backoff = Backoff::new();
+    while !condition() {
+        if backoff.is_completed() {
+            crate::spin::might_sleep();
+            axtask::sleep(SLEEP_INTERVAL);
+        } else {
+            backoff.snooze();
+        }
+    }
+}
+
+/// Waits until `condition` returns `true` or `timeout` expires.
+///
+/// Returns `false` on timeout.
+pub fn spin_wait_timeout<F: FnMut() -> bool>(mut condition: F, timeout: Duration) -> bool {
+    let deadline = axhal::time::wall_time() + timeout;
+    let backoff = Backoff::new();
+    while !condition() {
+        let now = axhal::time::wall_time();
+        if now >= deadline {
+            return false;
+        }
+        if backoff.is_completed() {
+            crate::spin::might_sleep();
+            axtask::sleep(SLEEP_INTERVAL.min(deadline - now));
+        } else {
+            backoff.snooze();
+        }
+    }
+    true
+}
+
+/// Spins on `try_acquire` with preemption disabled, for tiny critical
+/// sections that must not be scheduled out once entered.
+///
+/// On success, the value and the guard are returned, and preemption stays
+/// disabled until the guard is dropped. While waiting, preemption is briefly
+/// re-enabled between rounds once spinning is exhausted, so the holder on
+/// the same CPU can make progress.
+pub fn spin_wait_no_preempt<T, F>(mut try_acquire: F) -> (T, NoPreempt)
+where
+    F: FnMut() -> Option<T>,
+{
+    let backoff = Backoff::new();
+    loop {
+        let guard = NoPreempt::new();
+        if let Some(val) = try_acquire() {
+            return (val, guard);
+        }
+        if backoff.step.get() > SPIN_LIMIT {
+            drop(guard);
+            axtask::yield_now();
+        } else {
+            backoff.spin();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spin_saturates() {
+        let backoff = Backoff::new();
+        for _ in 0..SPIN_LIMIT * 2 {
+            backoff.spin();
+        }
+        assert_eq!(backoff.step.get(), SPIN_LIMIT + 1);
+        assert!(!backoff.is_completed());
+        backoff.reset();
+        assert_eq!(backoff.step.get(), 0);
+    }
+
+    #[test]
+    fn no_preempt_acquire() {
+        let mut tries = 0;
+        let (val, guard) = spin_wait_no_preempt(|| {
+            tries += 1;
+            (tries == 3).then_some(tries)
+        });
+        assert_eq!(val, 3);
+        drop(guard);
+    }
+}
@@ -3,18 +3,28 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
-//! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
+//! - [`Backoff`]: Exponential backoff for spin-wait loops, with the
+//!   [`spin_wait_until`] family of bounded spin-waits built on it.
+//! - mod [`spin`]: Spinlocks with local IRQs and/or preemption disabled.
+//! - mod [`atomic`]: Atomics and barriers, model checked with `--cfg loom`.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
 //!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
 //!   feature is enabled by default.
+//! - `lockstat`: Collect contention and hold-time statistics for every
+//!   spinlock, see [`spin::LockStats`]. Meant for debug builds.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
-pub use kspin as spin;
+pub mod atomic;
+pub mod spin;
+
+mod backoff;
+
+pub use self::backoff::{spin_wait_no_preempt, spin_wait_timeout, spin_wait_until, Backoff};
 
 #[cfg(feature = "multitask")]
 mod mutex;
@@ -25,4 +35,4 @@ pub use self::mutex::{Mutex, MutexGuard};
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
-pub use kspin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+pub use self::spin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
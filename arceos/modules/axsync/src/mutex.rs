@@ -5,7 +5,7 @@ use core::fmt;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
 
-use axtask::{current, WaitQueue};
+use axtask::{current, CancellationToken, Cancelled, WaitQueue};
 
 /// A mutual exclusion primitive useful for protecting shared data, similar to
 /// [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
@@ -69,6 +69,8 @@ impl<T: ?Sized> Mutex<T> {
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
     pub fn lock(&self) -> MutexGuard<T> {
+        crate::spin::might_sleep();
+        axtask::may_block("locking a Mutex");
         let current_id = current().id().as_u64();
         loop {
             // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
@@ -98,6 +100,26 @@ impl<T: ?Sized> Mutex<T> {
         }
     }
 
+    /// Locks the [`Mutex`] like [`Mutex::lock`], unless `token` is cancelled
+    /// while waiting for it, then [`Cancelled`] is returned.
+    pub fn lock_cancellable(&self, token: &CancellationToken) -> Result<MutexGuard<T>, Cancelled> {
+        crate::spin::might_sleep();
+        axtask::may_block("locking a Mutex");
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Ok(guard);
+            }
+            assert_ne!(
+                self.owner_id.load(Ordering::Relaxed),
+                current().id().as_u64(),
+                "{} tried to acquire mutex it already owns.",
+                current().id_name()
+            );
+            self.wq
+                .wait_until_cancellable(token, || !self.is_locked())?;
+        }
+    }
+
     /// Try to lock this [`Mutex`], returning a lock guard if successful.
     #[inline(always)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
@@ -201,9 +223,10 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
 mod tests {
     use crate::Mutex;
     use axtask as thread;
-    use std::sync::Once;
+    use std::sync::{Mutex as StdMutex, Once};
 
     static INIT: Once = Once::new();
+    static SERIAL: StdMutex<()> = StdMutex::new(());
 
     fn may_interrupt() {
         // simulate interrupts
@@ -214,6 +237,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
+        let _lock = SERIAL.lock();
         INIT.call_once(thread::init_scheduler);
 
         const NUM_TASKS: u32 = 10;
@@ -249,4 +273,22 @@ mod tests {
         assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
         println!("Mutex test OK");
     }
+
+    #[test]
+    fn lock_cancellable() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        static M: Mutex<u32> = Mutex::new(0);
+        let token = thread::CancellationToken::new();
+        let guard = M.lock();
+        let t = token.clone();
+        let task = thread::spawn(move || assert!(M.lock_cancellable(&t).is_err()));
+        thread::yield_now();
+        token.cancel();
+        assert_eq!(task.join(), Some(0));
+        drop(guard);
+        // A free mutex is locked even with the token cancelled.
+        assert!(M.lock_cancellable(&token).is_ok());
+    }
 }
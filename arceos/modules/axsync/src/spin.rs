@@ -0,0 +1,424 @@
+//! Spinlocks with local IRQs and/or preemption disabled while held.
+//!
+//! All flavors share the same [`BaseSpinLock`] implementation and guard API,
+//! and only differ in the [`kernel_guard`] guard taken around the critical
+//! section:
+//!
+//! - [`SpinRaw`]: Nothing is disabled. Must not be used in IRQ context.
+//! - [`SpinNoPreempt`]: Kernel preemption is disabled.
+//! - [`SpinNoIrq`]: Both preemption and local IRQs are disabled.
+//!
+//! Sleeping (e.g. locking a [`Mutex`](crate::Mutex)) while holding a
+//! [`SpinNoPreempt`] or [`SpinNoIrq`] lock is a bug. In debug builds it is
+//! detected by [`might_sleep`]. With the `lockstat` feature, every lock also
+//! records contention and hold-time statistics, see [`LockStats`].
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "lockstat")]
+use core::sync::atomic::AtomicU64;
+
+use crate::atomic::{spin_loop, AtomicBool, Ordering};
+
+use kernel_guard::{BaseGuard, NoOp, NoPreempt, NoPreemptIrqSave};
+
+/// A [`BaseGuard`] usable by spinlocks.
+pub trait SpinGuard: BaseGuard {
+    /// Whether the holder is pinned to the current CPU (i.e. it cannot be
+    /// scheduled out) while the guard is held.
+    const PINNED: bool;
+}
+
+impl SpinGuard for NoOp {
+    const PINNED: bool = false;
+}
+
+impl SpinGuard for NoPreempt {
+    const PINNED: bool = true;
+}
+
+impl SpinGuard for NoPreemptIrqSave {
+    const PINNED: bool = true;
+}
+
+/// A spinlock that does nothing around the critical section.
+pub type SpinRaw<T> = BaseSpinLock<NoOp, T>;
+/// A guard of [`SpinRaw`].
+pub type SpinRawGuard<'a, T> = BaseSpinLockGuard<'a, NoOp, T>;
+
+/// A spinlock that disables kernel preemption while held.
+pub type SpinNoPreempt<T> = BaseSpinLock<NoPreempt, T>;
+/// A guard of [`SpinNoPreempt`].
+pub type SpinNoPreemptGuard<'a, T> = BaseSpinLockGuard<'a, NoPreempt, T>;
+
+/// A spinlock that disables kernel preemption and local IRQs while held.
+pub type SpinNoIrq<T> = BaseSpinLock<NoPreemptIrqSave, T>;
+/// A guard of [`SpinNoIrq`].
+pub type SpinNoIrqGuard<'a, T> = BaseSpinLockGuard<'a, NoPreemptIrqSave, T>;
+
+#[cfg(debug_assertions)]
+static HELD_PINNED: [core::sync::atomic::AtomicUsize; axconfig::SMP] =
+    [const { core::sync::atomic::AtomicUsize::new(0) }; axconfig::SMP];
+
+/// Asserts that the caller is allowed to sleep, i.e. it does not hold any
+/// [`SpinNoPreempt`] or [`SpinNoIrq`] lock on this CPU.
+///
+/// It is a no-op in release builds.
+#[inline]
+pub fn might_sleep() {
+    #[cfg(debug_assertions)]
+    {
+        let held = HELD_PINNED[axhal::cpu::this_cpu_id()].load(Ordering::Relaxed);
+        assert_eq!(held, 0, "sleeping while holding {} spinlock(s)", held);
+    }
+}
+
+/// Contention and hold-time statistics of a spinlock.
+///
+/// Hold times are measured in hardware ticks, see
+/// [`axhal::time::current_ticks`].
+#[cfg(feature = "lockstat")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LockStats {
+    /// Number of successful acquisitions.
+    pub acquisitions: u64,
+    /// Number of acquisitions that found the lock already held.
+    pub contended: u64,
+    /// Total number of spin iterations spent waiting.
+    pub spins: u64,
+    /// Total time the lock was held.
+    pub total_hold_ticks: u64,
+    /// Longest time the lock was held at once.
+    pub max_hold_ticks: u64,
+}
+
+#[cfg(feature = "lockstat")]
+struct StatCounters {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    spins: AtomicU64,
+    total_hold_ticks: AtomicU64,
+    max_hold_ticks: AtomicU64,
+}
+
+#[cfg(feature = "lockstat")]
+impl StatCounters {
+    const fn new() -> Self {
+        Self {
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            spins: AtomicU64::new(0),
+            total_hold_ticks: AtomicU64::new(0),
+            max_hold_ticks: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A spinlock, with the guard `G` taken while it is held.
+pub struct BaseSpinLock<G: SpinGuard, T: ?Sized> {
+    _phantom: PhantomData<G>,
+    lock: AtomicBool,
+    #[cfg(feature = "lockstat")]
+    stats: StatCounters,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides mutable data access to a [`BaseSpinLock`].
+///
+/// The lock is released when the guard falls out of scope. It is `!Send`,
+/// as the critical section belongs to the current CPU.
+#[must_use = "if unused the lock will immediately unlock"]
+pub struct BaseSpinLockGuard<'a, G: SpinGuard, T: ?Sized + 'a> {
+    _not_send: PhantomData<*const ()>,
+    state: G::State,
+    lock: &'a BaseSpinLock<G, T>,
+    #[cfg(feature = "lockstat")]
+    acquired_at: u64,
+}
+
+// Same unsafe impls as `std::sync::Mutex`.
+unsafe impl<G: SpinGuard, T: ?Sized + Send> Sync for BaseSpinLock<G, T> {}
+unsafe impl<G: SpinGuard, T: ?Sized + Send> Send for BaseSpinLock<G, T> {}
+unsafe impl<G: SpinGuard, T: ?Sized + Sync> Sync for BaseSpinLockGuard<'_, G, T> {}
+
+impl<G: SpinGuard, T> BaseSpinLock<G, T> {
+    /// Creates a new spinlock wrapping the supplied data.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            _phantom: PhantomData,
+            lock: AtomicBool::new(false),
+            #[cfg(feature = "lockstat")]
+            stats: StatCounters::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Creates a new spinlock wrapping the supplied data.
+    ///
+    /// It is not `const` in the model checked builds, the atomics of loom
+    /// are not.
+    #[cfg(loom)]
+    pub fn new(data: T) -> Self {
+        Self {
+            _phantom: PhantomData,
+            lock: AtomicBool::new(false),
+            #[cfg(feature = "lockstat")]
+            stats: StatCounters::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this lock and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized> BaseSpinLock<G, T> {
+    /// Locks the spinlock and returns a guard that permits access to the
+    /// inner data.
+    ///
+    /// The lock will be released when the guard falls out of scope.
+    #[inline(always)]
+    pub fn lock(&self) -> BaseSpinLockGuard<G, T> {
+        let state = G::acquire();
+        let mut spins = 0u64;
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Wait until the lock looks unlocked before retrying.
+            while self.is_locked() {
+                spin_loop();
+                spins += 1;
+            }
+        }
+        self.acquired(state, spins)
+    }
+
+    /// Tries to lock this spinlock, returning a guard if successful.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<BaseSpinLockGuard<G, T>> {
+        let state = G::acquire();
+        if self
+            .lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(self.acquired(state, 0))
+        } else {
+            G::release(state);
+            None
+        }
+    }
+
+    /// Returns `true` if the lock is currently held.
+    ///
+    /// The result is only a heuristic, and should not be used for
+    /// synchronization.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.lock.load(Ordering::Relaxed)
+    }
+
+    /// Force unlock this spinlock.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the lock is not held by the current
+    /// thread. However, this can be useful in some instances for exposing
+    /// the lock to FFI that doesn't know how to deal with RAII.
+    #[inline(always)]
+    pub unsafe fn force_unlock(&self) {
+        self.lock.store(false, Ordering::Release);
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the lock mutably, no actual locking needs to
+    /// take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Returns the statistics collected so far.
+    #[cfg(feature = "lockstat")]
+    pub fn stats(&self) -> LockStats {
+        let s = &self.stats;
+        LockStats {
+            acquisitions: s.acquisitions.load(Ordering::Relaxed),
+            contended: s.contended.load(Ordering::Relaxed),
+            spins: s.spins.load(Ordering::Relaxed),
+            total_hold_ticks: s.total_hold_ticks.load(Ordering::Relaxed),
+            max_hold_ticks: s.max_hold_ticks.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets all statistics to zero.
+    #[cfg(feature = "lockstat")]
+    pub fn reset_stats(&self) {
+        let s = &self.stats;
+        s.acquisitions.store(0, Ordering::Relaxed);
+        s.contended.store(0, Ordering::Relaxed);
+        s.spins.store(0, Ordering::Relaxed);
+        s.total_hold_ticks.store(0, Ordering::Relaxed);
+        s.max_hold_ticks.store(0, Ordering::Relaxed);
+    }
+
+    #[allow(unused_variables)]
+    fn acquired(&self, state: G::State, spins: u64) -> BaseSpinLockGuard<G, T> {
+        #[cfg(debug_assertions)]
+        if G::PINNED {
+            HELD_PINNED[axhal::cpu::this_cpu_id()].fetch_add(1, Ordering::Relaxed);
+        }
+        #[cfg(feature = "lockstat")]
+        {
+            let s = &self.stats;
+            s.acquisitions.fetch_add(1, Ordering::Relaxed);
+            if spins > 0 {
+                s.contended.fetch_add(1, Ordering::Relaxed);
+                s.spins.fetch_add(spins, Ordering::Relaxed);
+            }
+        }
+        BaseSpinLockGuard {
+            _not_send: PhantomData,
+            state,
+            lock: self,
+            #[cfg(feature = "lockstat")]
+            acquired_at: axhal::time::current_ticks(),
+        }
+    }
+}
+
+impl<G: SpinGuard, T: Default> Default for BaseSpinLock<G, T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized + fmt::Debug> fmt::Debug for BaseSpinLock<G, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "SpinLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "SpinLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized> Deref for BaseSpinLockGuard<'_, G, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only we are referencing data
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized> DerefMut for BaseSpinLockGuard<'_, G, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        // We know statically that only we are referencing data
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized + fmt::Debug> fmt::Debug for BaseSpinLockGuard<'_, G, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<G: SpinGuard, T: ?Sized> Drop for BaseSpinLockGuard<'_, G, T> {
+    /// The dropping of the guard will release the lock it was created from.
+    #[inline(always)]
+    fn drop(&mut self) {
+        #[cfg(feature = "lockstat")]
+        {
+            let held = axhal::time::current_ticks().saturating_sub(self.acquired_at);
+            let s = &self.lock.stats;
+            s.total_hold_ticks.fetch_add(held, Ordering::Relaxed);
+            s.max_hold_ticks.fetch_max(held, Ordering::Relaxed);
+        }
+        #[cfg(debug_assertions)]
+        if G::PINNED {
+            HELD_PINNED[axhal::cpu::this_cpu_id()].fetch_sub(1, Ordering::Relaxed);
+        }
+        self.lock.lock.store(false, Ordering::Release);
+        G::release(self.state);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::SpinRaw;
+
+    /// A counter protected by a separate lock, so that loom checks the
+    /// accesses to it are ordered by the lock.
+    struct Shared {
+        lock: SpinRaw<()>,
+        value: UnsafeCell<usize>,
+    }
+
+    unsafe impl Sync for Shared {}
+
+    impl Shared {
+        fn inc(&self) {
+            let _guard = self.lock.lock();
+            self.value.with_mut(|v| unsafe { *v += 1 });
+        }
+    }
+
+    #[test]
+    fn loom_spin_lock() {
+        loom::model(|| {
+            let shared = Arc::new(Shared {
+                lock: SpinRaw::new(()),
+                value: UnsafeCell::new(0),
+            });
+            let threads: [_; 2] = core::array::from_fn(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.inc())
+            });
+            shared.inc();
+            for t in threads {
+                t.join().unwrap();
+            }
+            let _guard = shared.lock.lock();
+            assert_eq!(shared.value.with(|v| unsafe { *v }), 3);
+        });
+    }
+
+    #[test]
+    fn loom_spin_try_lock() {
+        loom::model(|| {
+            let shared = Arc::new(Shared {
+                lock: SpinRaw::new(()),
+                value: UnsafeCell::new(0),
+            });
+            let other = shared.clone();
+            let t = thread::spawn(move || {
+                if let Some(_guard) = other.lock.try_lock() {
+                    other.value.with_mut(|v| unsafe { *v += 1 });
+                }
+            });
+            shared.inc();
+            t.join().unwrap();
+            let value = shared.value.with(|v| unsafe { *v });
+            assert!(value == 1 || value == 2);
+        });
+    }
+}
@@ -1,11 +1,19 @@
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+};
+use kspin::SpinNoIrq;
 
 pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::cancel::{CancellationToken, Cancelled, DropGuard};
+#[cfg(feature = "trace")]
+pub use crate::task::TraceSpan;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, FsContext, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
@@ -14,20 +22,7 @@ pub use crate::wait_queue::WaitQueue;
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
-        const MAX_TIME_SLICE: usize = 5;
-        pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
-    } else if #[cfg(feature = "sched_cfs")] {
-        pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
-    } else {
-        // If no scheduler features are set, use FIFO as the default.
-        pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
-    }
-}
+pub(crate) type AxTask = TaskInner;
 
 #[cfg(feature = "preempt")]
 struct KernelGuardIfImpl;
@@ -71,7 +66,7 @@ pub fn init_scheduler() {
     #[cfg(feature = "irq")]
     crate::timers::init();
 
-    info!("  use {} scheduler.", Scheduler::scheduler_name());
+    info!("  use {} scheduler.", crate::sched_policy());
 }
 
 /// Initializes the task scheduler for secondary CPUs.
@@ -82,10 +77,20 @@ pub fn init_scheduler_secondary() {
 /// Handles periodic timer ticks for the task manager.
 ///
 /// For example, advance scheduler states, checks timed events, etc.
+///
+/// With the `nohz` feature, the isolated CPUs leave the timers to the other
+/// CPUs, see [`tick_needed`](crate::tick_needed).
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
+    #[cfg(feature = "nohz")]
+    if !crate::nohz::this_cpu_isolated() {
+        crate::timers::check_events();
+    }
+    #[cfg(not(feature = "nohz"))]
     crate::timers::check_events();
+    #[cfg(feature = "profile")]
+    crate::profile::sample();
     RUN_QUEUE.lock().scheduler_timer_tick();
 }
 
@@ -132,6 +137,46 @@ pub fn set_priority(prio: isize) -> bool {
     RUN_QUEUE.lock().set_current_priority(prio)
 }
 
+static FOREGROUND: SpinNoIrq<Option<Weak<AxTask>>> = SpinNoIrq::new(None);
+
+/// Sets the foreground task, the one the console break (Ctrl-C) requests to
+/// cancel, see [`TaskInner::request_cancel`].
+///
+/// With no foreground task (the default), the break character is read from
+/// the console like the others.
+pub fn set_foreground(task: Option<&AxTaskRef>) {
+    *FOREGROUND.lock() = task.map(Arc::downgrade);
+}
+
+/// Returns the foreground task, if it is still alive.
+pub fn foreground() -> Option<AxTaskRef> {
+    FOREGROUND.lock().as_ref().and_then(Weak::upgrade)
+}
+
+/// Checks that the current task may block for `what`, e.g. before locking a
+/// sleeping lock.
+///
+/// With the `irq-guard` feature, blocking in an IRQ handler is reported, and
+/// it returns `false` if the report does not panic, for the caller to fail
+/// instead of blocking if it can. It always returns `true` otherwise.
+#[inline]
+pub fn may_block(_what: &str) -> bool {
+    #[cfg(feature = "irq-guard")]
+    return crate::irq_guard::check(_what);
+    #[cfg(not(feature = "irq-guard"))]
+    true
+}
+
+/// Gets the quota the current heap allocation is charged to: the one of the
+/// current task, none in the IRQ handlers, see [`axalloc::quota`].
+#[cfg(feature = "heap-quota")]
+pub fn current_heap_quota() -> Option<Arc<axalloc::quota::HeapQuota>> {
+    if axhal::trap::in_irq() {
+        return None;
+    }
+    current_may_uninit().and_then(|curr| curr.heap_quota().cloned())
+}
+
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
@@ -155,6 +200,29 @@ pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
 
+/// Current task is going to sleep for the given duration, or until `token`
+/// is cancelled, then [`Cancelled`] is returned.
+///
+/// If the feature `irq` is not enabled, it uses busy-wait instead.
+pub fn sleep_cancellable(
+    dur: core::time::Duration,
+    token: &CancellationToken,
+) -> Result<(), Cancelled> {
+    #[cfg(feature = "irq")]
+    return WaitQueue::new()
+        .wait_timeout_until_cancellable(dur, token, || false)
+        .map(|_| ());
+    #[cfg(not(feature = "irq"))]
+    {
+        let deadline = axhal::time::wall_time() + dur;
+        while axhal::time::wall_time() < deadline {
+            token.check()?;
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+}
+
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
     RUN_QUEUE.lock().exit_current(exit_code)
@@ -162,12 +230,23 @@ pub fn exit(exit_code: i32) -> ! {
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`]. With the
+/// `virtual-time` feature, the virtual clock jumps to the next timer deadline
+/// instead of waiting for it, once all the CPUs are idle.
 pub fn run_idle() -> ! {
     loop {
         yield_now();
+        #[cfg(all(feature = "irq", feature = "virtual-time"))]
+        if crate::timers::enter_idle() && crate::timers::advance_to_next_deadline() {
+            crate::timers::exit_idle();
+            continue;
+        }
         debug!("idle task: waiting for IRQs...");
-        #[cfg(feature = "irq")]
+        #[cfg(all(feature = "irq", feature = "timer-broadcast"))]
+        axhal::broadcast::idle();
+        #[cfg(all(feature = "irq", not(feature = "timer-broadcast")))]
         axhal::arch::wait_for_irqs();
+        #[cfg(all(feature = "irq", feature = "virtual-time"))]
+        crate::timers::exit_idle();
     }
 }
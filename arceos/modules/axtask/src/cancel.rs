@@ -0,0 +1,170 @@
+//! Cancellation tokens, waking up the blocking waits to cancel.
+//!
+//! A task asked to stop with [`CancellationToken::cancel`] is not
+//! interrupted: its cancellable waits return [`Cancelled`], and it returns
+//! from its function with the error, dropping what it holds (guards,
+//! buffers) on the way, instead of exiting with them still held.
+//!
+//! The tokens form trees: one made with [`CancellationToken::child_token`]
+//! is cancelled with its parent, not the other way around. Each task has a
+//! token of its own, [`TaskInner::cancel_token`], cancelled by
+//! [`TaskInner::request_cancel`], e.g. when its [`Job`] is terminated. A
+//! [`DropGuard`] cancels its token when dropped, e.g. to stop the helper
+//! tasks of a scope when it is left early.
+//!
+//! [`TaskInner::cancel_token`]: crate::TaskInner::cancel_token
+//! [`TaskInner::request_cancel`]: crate::TaskInner::request_cancel
+//! [`Job`]: crate::Job
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::{AxTaskRef, RUN_QUEUE};
+
+/// The error of a wait whose token was cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+struct TokenInner {
+    cancelled: AtomicBool,
+    /// The tasks blocked in a wait cancellable by the token.
+    waiters: SpinNoIrq<Vec<AxTaskRef>>,
+    children: SpinNoIrq<Vec<Weak<TokenInner>>>,
+}
+
+impl TokenInner {
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        {
+            // The waiters check the token with `RUN_QUEUE` locked before
+            // blocking, none of them misses the cancellation.
+            let mut rq = RUN_QUEUE.lock();
+            for task in self.waiters.lock().drain(..) {
+                rq.unblock_task(task, true);
+            }
+        }
+        let children = core::mem::take(&mut *self.children.lock());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// A token to ask the tasks holding it to stop, see the
+/// [module documentation](self).
+///
+/// The clones of a token are the same token.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+impl CancellationToken {
+    /// Creates a token not cancelled.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(TokenInner {
+                cancelled: AtomicBool::new(false),
+                waiters: SpinNoIrq::new(Vec::new()),
+                children: SpinNoIrq::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Creates a token cancelled with this one, at once if it already is.
+    pub fn child_token(&self) -> Self {
+        let child = Self::new();
+        {
+            let mut children = self.inner.children.lock();
+            children.retain(|c| c.strong_count() > 0);
+            children.push(Arc::downgrade(&child.inner));
+        }
+        if self.is_cancelled() {
+            child.cancel();
+        }
+        child
+    }
+
+    /// Cancels the token and its children, waking up the tasks waiting with
+    /// them. Cancelling a cancelled token does nothing.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns [`Cancelled`] if the token was cancelled, e.g. to return
+    /// early with `?` between the steps of a long computation.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns a guard cancelling the token when dropped.
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard { token: Some(self) }
+    }
+
+    /// Adds the current task to the waiters, `RUN_QUEUE` must be locked.
+    pub(crate) fn add_waiter(&self, task: AxTaskRef) {
+        self.inner.waiters.lock().push(task);
+    }
+
+    /// Removes the current task from the waiters, once woken up.
+    pub(crate) fn remove_waiter(&self, task: &AxTaskRef) {
+        self.inner.waiters.lock().retain(|t| !Arc::ptr_eq(t, task));
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A guard cancelling its token when dropped, see
+/// [`CancellationToken::drop_guard`].
+pub struct DropGuard {
+    token: Option<CancellationToken>,
+}
+
+impl DropGuard {
+    /// Returns the token without cancelling it.
+    pub fn disarm(mut self) -> CancellationToken {
+        self.token.take().unwrap()
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! SMT-aware scheduling domains, with the `sched_smt` feature.
+//!
+//! The domains of a CPU are the groups of CPUs sharing its core (its SMT
+//! siblings), its cluster and its package, from [`axhal::cpu::topology`]. As
+//! the run queue is shared by all CPUs, they balance the load on the pick:
+//! a CPU whose sibling runs a task defers the next task once, running its
+//! idle task instead, while a core has all its hardware threads idle, so
+//! that the idle core picks it on its next tick and the tasks spread across
+//! the physical cores before sharing them.
+//!
+//! A task made SMT exclusive with [`TaskInner::set_smt_exclusive`] keeps its
+//! core to itself: its siblings only run their idle task while it runs, and
+//! with the `preempt` feature, the tasks they run are preempted on their
+//! next tick.
+//!
+//! [`TaskInner::set_smt_exclusive`]: crate::TaskInner::set_smt_exclusive
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use axhal::cpu::CpuTopology;
+
+use crate::TaskInner;
+
+/// The CPU has not started scheduling yet.
+const OFFLINE: u8 = 0;
+/// The CPU runs its idle task.
+const IDLE: u8 = 1;
+/// The CPU runs a task.
+const BUSY: u8 = 2;
+/// The CPU runs an SMT exclusive task.
+const EXCLUSIVE: u8 = 3;
+
+static CPU_STATE: [AtomicU8; axconfig::SMP] = [const { AtomicU8::new(OFFLINE) }; axconfig::SMP];
+
+fn topology(cpu_id: usize) -> CpuTopology {
+    axhal::cpu::topology(cpu_id).unwrap()
+}
+
+fn state(cpu_id: usize) -> u8 {
+    CPU_STATE[cpu_id].load(Ordering::Relaxed)
+}
+
+/// The other hardware threads of the core of the CPU `cpu_id`.
+fn siblings(cpu_id: usize) -> impl Iterator<Item = usize> {
+    let core = topology(cpu_id);
+    (0..axconfig::SMP).filter(move |&id| id != cpu_id && topology(id).is_smt_sibling(&core))
+}
+
+/// Returns the scheduling domains of the CPU `cpu_id`, from the smallest:
+/// the CPUs of its core, of its cluster and of its package, or `None` if
+/// there is no such CPU.
+///
+/// The levels of a single CPU, or with the same CPUs as the level below, are
+/// left out.
+pub fn sched_domains(cpu_id: usize) -> Option<Vec<Vec<usize>>> {
+    let this = axhal::cpu::topology(cpu_id)?;
+    let levels: [fn(&CpuTopology, &CpuTopology) -> bool; 3] = [
+        |a, b| a.core == b.core,
+        |a, b| a.cluster == b.cluster,
+        |a, b| a.package == b.package,
+    ];
+    let mut domains: Vec<Vec<usize>> = Vec::new();
+    for same in levels {
+        let cpus: Vec<usize> = (0..axconfig::SMP)
+            .filter(|&id| same(&this, &topology(id)))
+            .collect();
+        if cpus.len() > 1 && domains.last() != Some(&cpus) {
+            domains.push(cpus);
+        }
+    }
+    Some(domains)
+}
+
+/// Records the task the current CPU switches to.
+pub(crate) fn on_switch(next: &TaskInner) {
+    let state = if next.is_idle() {
+        IDLE
+    } else if next.is_smt_exclusive() {
+        EXCLUSIVE
+    } else {
+        BUSY
+    };
+    CPU_STATE[axhal::cpu::this_cpu_id()].store(state, Ordering::Relaxed);
+}
+
+/// Whether a sibling of the CPU `cpu_id` runs an SMT exclusive task.
+pub(crate) fn sibling_exclusive(cpu_id: usize) -> bool {
+    siblings(cpu_id).any(|id| state(id) == EXCLUSIVE)
+}
+
+/// Whether the CPU `cpu_id` leaves its next task to an idle core: a sibling
+/// of it runs a task, and another core has all its started CPUs idle.
+pub(crate) fn should_spread(cpu_id: usize) -> bool {
+    if !siblings(cpu_id).any(|id| state(id) != IDLE && state(id) != OFFLINE) {
+        return false;
+    }
+    let this_core = topology(cpu_id).core;
+    (0..axconfig::SMP).any(|id| {
+        let core = topology(id).core;
+        core != this_core
+            && state(id) == IDLE
+            && !is_isolated(id)
+            && (0..axconfig::SMP)
+                .filter(|&other| topology(other).core == core)
+                .all(|other| state(other) != BUSY && state(other) != EXCLUSIVE)
+    })
+}
+
+fn is_isolated(_cpu_id: usize) -> bool {
+    #[cfg(feature = "nohz")]
+    return crate::nohz::is_cpu_isolated(_cpu_id);
+    #[cfg(not(feature = "nohz"))]
+    false
+}
@@ -0,0 +1,44 @@
+//! Reports of blocking in the IRQ handlers, with the `irq-guard` feature.
+//!
+//! Blocking the current task in an IRQ handler (waiting on a [`WaitQueue`],
+//! sleeping, locking a sleeping lock, or an allocation shrinking the caches)
+//! switches away from the interrupted code while it holds its locks, and
+//! hangs the CPU sooner or later. It is checked by [`may_block`], and
+//! reported with the backtrace of the handler: it panics by default, or just
+//! warns if built with `AX_IRQ_GUARD=warn`, to find all of them in one run.
+//!
+//! [`WaitQueue`]: crate::WaitQueue
+//! [`may_block`]: crate::may_block
+
+use log::Level;
+
+/// The most frames of the backtrace.
+const MAX_DEPTH: usize = 16;
+
+/// Returns `false` after reporting `what` if it is called in an IRQ handler.
+pub(crate) fn check(what: &str) -> bool {
+    if !axhal::trap::in_irq() {
+        return true;
+    }
+    let warn_only = option_env!("AX_IRQ_GUARD") == Some("warn");
+    let level = if warn_only { Level::Warn } else { Level::Error };
+    let cpu = axhal::cpu::this_cpu_id();
+    log!(level, "{} in interrupt context on CPU {}", what, cpu);
+
+    // the IRQ handlers run on the stack of the interrupted task
+    let marker = 0u8;
+    let low = &marker as *const u8 as usize;
+    let top = crate::current()
+        .kernel_stack_top()
+        .map_or(0, |top| top.as_usize());
+    let mut pcs = [0; MAX_DEPTH];
+    let n = crate::unwind::walk(crate::unwind::current_fp(), low, top, &mut pcs);
+    for (i, pc) in pcs[..n].iter().enumerate() {
+        log!(level, "  #{:<2} {:#x}", i, pc);
+    }
+
+    if !warn_only {
+        panic!("{} in interrupt context", what);
+    }
+    false
+}
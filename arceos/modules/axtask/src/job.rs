@@ -0,0 +1,298 @@
+//! Jobs: groups of tasks terminated together, with aggregated usage.
+//!
+//! A task belongs to the job of the task which spawned it, or to the one it
+//! was spawned in with [`Job::spawn`]. A job created by a task of another
+//! job is nested in it: terminating a job terminates its nested jobs, and the
+//! usage of a job counts the tasks of its nested jobs.
+//!
+//! The tasks are not interrupted when their job is terminated: like with
+//! Ctrl-C, their cancellation is requested, and they poll it with
+//! [`TaskInner::take_cancel_request`] and return, or their waits cancellable
+//! by [`TaskInner::cancel_token`] return [`Cancelled`](crate::Cancelled).
+//! The tasks spawned in a terminated job have their cancellation requested
+//! from the start.
+//!
+//! With the `heap-quota` feature, the heap allocations of the tasks spawned
+//! in a job are limited by its [quota](Job::set_heap_quota).
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::{AxRunQueue, AxTask, AxTaskRef, TaskInner, WaitQueue};
+
+/// The reference type of a job.
+pub type JobRef = Arc<Job>;
+
+/// The resource usage of a job and of its nested jobs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JobUsage {
+    /// The tasks alive.
+    pub live_tasks: usize,
+    /// The tasks spawned in the job since it was created.
+    pub spawned_tasks: usize,
+    /// The tasks which exited.
+    pub exited_tasks: usize,
+    /// The timer ticks the tasks ran for.
+    pub cpu_ticks: u64,
+}
+
+struct JobInner {
+    /// The tasks alive of the job, by ID, without the ones of the nested
+    /// jobs.
+    members: BTreeMap<u64, Weak<AxTask>>,
+    children: Vec<Weak<Job>>,
+    /// The ID of the first task spawned in the job.
+    root: Option<u64>,
+    /// The exit code of the job, once it was terminated or its root exited.
+    exit_code: Option<i32>,
+    terminated: bool,
+}
+
+/// A group of tasks, see the [module documentation](self).
+pub struct Job {
+    id: u64,
+    name: String,
+    parent: Option<JobRef>,
+    inner: SpinNoIrq<JobInner>,
+    kill_on_root_exit: AtomicBool,
+    live_tasks: AtomicUsize,
+    spawned_tasks: AtomicUsize,
+    exited_tasks: AtomicUsize,
+    cpu_ticks: AtomicU64,
+    wait_for_exit: WaitQueue,
+    #[cfg(feature = "heap-quota")]
+    heap_quota: SpinNoIrq<Option<Arc<axalloc::quota::HeapQuota>>>,
+}
+
+impl Job {
+    /// Creates a job, nested in the job of the current task if it has one.
+    pub fn new(name: String) -> JobRef {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let parent = current_job();
+        let terminated = parent.as_ref().and_then(|parent| parent.terminated_code());
+        let job = Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name,
+            parent,
+            inner: SpinNoIrq::new(JobInner {
+                members: BTreeMap::new(),
+                children: Vec::new(),
+                root: None,
+                exit_code: terminated,
+                terminated: terminated.is_some(),
+            }),
+            kill_on_root_exit: AtomicBool::new(false),
+            live_tasks: AtomicUsize::new(0),
+            spawned_tasks: AtomicUsize::new(0),
+            exited_tasks: AtomicUsize::new(0),
+            cpu_ticks: AtomicU64::new(0),
+            wait_for_exit: WaitQueue::new(),
+            #[cfg(feature = "heap-quota")]
+            heap_quota: SpinNoIrq::new(None),
+        });
+        if let Some(parent) = &job.parent {
+            let mut inner = parent.inner.lock();
+            inner.children.retain(|child| child.strong_count() > 0);
+            inner.children.push(Arc::downgrade(&job));
+        }
+        job
+    }
+
+    /// Gets the ID of the job.
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Gets the name of the job.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the job this one is nested in.
+    pub fn parent(&self) -> Option<&JobRef> {
+        self.parent.as_ref()
+    }
+
+    /// Sets whether the exit of the root of the job, the first task spawned
+    /// in it, terminates the job with its exit code. It is not the default.
+    pub fn set_kill_on_root_exit(&self, kill: bool) {
+        self.kill_on_root_exit.store(kill, Ordering::Release);
+    }
+
+    /// Spawns a task in the job, with the default stack size
+    /// [`axconfig::TASK_STACK_SIZE`].
+    pub fn spawn<F>(self: &Arc<Self>, f: F, name: String) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut task = TaskInner::new(f, name, axconfig::TASK_STACK_SIZE);
+        task.set_job(Some(self.clone()));
+        #[cfg(feature = "heap-quota")]
+        if let Some(quota) = self.heap_quota() {
+            task.set_heap_quota(Some(quota));
+        }
+        crate::spawn_task(task)
+    }
+
+    /// Gets the quota of the heap allocations of the tasks spawned in the
+    /// job, see [`Job::set_heap_quota`].
+    #[cfg(feature = "heap-quota")]
+    pub fn heap_quota(&self) -> Option<Arc<axalloc::quota::HeapQuota>> {
+        self.heap_quota.lock().clone()
+    }
+
+    /// Limits the heap allocations of the tasks spawned in the job from now
+    /// on, with [`Job::spawn`], to `limit` bytes in all, or sets the limit if
+    /// the job has a quota already.
+    ///
+    /// The quota is nested in the one of the current task, so that the job
+    /// cannot allocate more than its creator.
+    #[cfg(feature = "heap-quota")]
+    pub fn set_heap_quota(&self, limit: usize) {
+        if let Some(quota) = self.heap_quota() {
+            quota.set_limit(limit);
+            return;
+        }
+        // The quota is created unlocked, charged to the current task.
+        let quota = axalloc::quota::HeapQuota::new(limit, crate::current_heap_quota());
+        self.heap_quota.lock().get_or_insert(quota);
+    }
+
+    /// Returns the tasks alive of the job, ordered by their IDs, without the
+    /// ones of the nested jobs.
+    pub fn members(&self) -> Vec<AxTaskRef> {
+        // The tasks upgraded must not be dropped with the lock held.
+        let members = self
+            .inner
+            .lock()
+            .members
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+        members
+    }
+
+    /// Returns the resource usage of the job and of its nested jobs.
+    pub fn usage(&self) -> JobUsage {
+        JobUsage {
+            live_tasks: self.live_tasks.load(Ordering::Acquire),
+            spawned_tasks: self.spawned_tasks.load(Ordering::Acquire),
+            exited_tasks: self.exited_tasks.load(Ordering::Acquire),
+            cpu_ticks: self.cpu_ticks.load(Ordering::Acquire),
+        }
+    }
+
+    /// Whether the job was terminated.
+    pub fn is_terminated(&self) -> bool {
+        self.inner.lock().terminated
+    }
+
+    /// Terminates the job and its nested jobs with `exit_code`, requesting
+    /// the cancellation of their tasks.
+    ///
+    /// Terminating a terminated job does nothing.
+    pub fn terminate(&self, exit_code: i32) {
+        let (members, children) = {
+            let mut inner = self.inner.lock();
+            if inner.terminated {
+                return;
+            }
+            inner.terminated = true;
+            inner.exit_code = Some(exit_code);
+            let members: Vec<_> = inner.members.values().filter_map(Weak::upgrade).collect();
+            let children: Vec<_> = inner.children.iter().filter_map(Weak::upgrade).collect();
+            (members, children)
+        };
+        debug!("job terminate: {} ({} tasks)", self, members.len());
+        for task in members {
+            task.request_cancel();
+        }
+        for child in children {
+            child.terminate(exit_code);
+        }
+    }
+
+    /// Waits for the tasks of the job and of its nested jobs to exit, and
+    /// returns the exit code of the job: the one it was terminated with, or
+    /// the one of its root, 0 if it has none.
+    ///
+    /// It returns immediately if no task was spawned in the job.
+    pub fn wait(&self) -> i32 {
+        self.wait_for_exit
+            .wait_until(|| self.live_tasks.load(Ordering::Acquire) == 0);
+        self.inner.lock().exit_code.unwrap_or(0)
+    }
+
+    fn terminated_code(&self) -> Option<i32> {
+        let inner = self.inner.lock();
+        inner.terminated.then_some(inner.exit_code.unwrap_or(0))
+    }
+
+    /// The job and the ones it is nested in.
+    fn ancestors(&self) -> impl Iterator<Item = &Job> {
+        core::iter::successors(Some(self), |job| job.parent.as_deref())
+    }
+
+    pub(crate) fn add_member(&self, task: &AxTaskRef) {
+        let terminated = {
+            let mut inner = self.inner.lock();
+            inner.root.get_or_insert(task.id().as_u64());
+            inner
+                .members
+                .insert(task.id().as_u64(), Arc::downgrade(task));
+            inner.terminated
+        };
+        if terminated {
+            task.request_cancel();
+        }
+        for job in self.ancestors() {
+            job.spawned_tasks.fetch_add(1, Ordering::AcqRel);
+            job.live_tasks.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    pub(crate) fn on_member_exit(&self, task: &AxTaskRef, exit_code: i32, rq: &mut AxRunQueue) {
+        let kill = {
+            let mut inner = self.inner.lock();
+            inner.members.remove(&task.id().as_u64());
+            let is_root = inner.root == Some(task.id().as_u64());
+            if is_root && !inner.terminated {
+                inner.exit_code = Some(exit_code);
+            }
+            is_root && self.kill_on_root_exit.load(Ordering::Acquire)
+        };
+        if kill {
+            self.terminate(exit_code);
+        }
+        for job in self.ancestors() {
+            job.exited_tasks.fetch_add(1, Ordering::AcqRel);
+            if job.live_tasks.fetch_sub(1, Ordering::AcqRel) == 1 {
+                job.wait_for_exit.notify_all_locked(false, rq);
+            }
+        }
+    }
+
+    #[cfg(feature = "irq")]
+    pub(crate) fn account_tick(&self) {
+        for job in self.ancestors() {
+            job.cpu_ticks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Job({}, {:?})", self.id, self.name)
+    }
+}
+
+/// Gets the job of the current task.
+pub fn current_job() -> Option<JobRef> {
+    crate::current_may_uninit().and_then(|curr| curr.job().cloned())
+}
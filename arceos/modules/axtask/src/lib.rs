@@ -1,8 +1,12 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) task management module.
 //!
 //! This module provides primitives for task management, including task
-//! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
-//! is configurable by cargo features.
+//! creation, scheduling, sleeping, termination, etc. The scheduling policy
+//! is chosen at boot by cargo features, and can be switched at runtime, see
+//! [`set_sched_policy`]. Tasks can be grouped in [`Job`]s, terminated
+//! together, and asked to stop with [`CancellationToken`]s, which wake up
+//! their cancellable waits. The CPU-bound helper work is offloaded to the thread pools of
+//! [`pool`].
 //!
 //! # Cargo Features
 //!
@@ -10,20 +14,53 @@
 //!   management and scheduling is used, as well as more task-related APIs.
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
-//!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    APIs can be used, such as [`sleep`], [`sleep_until`],
+//!    [`WaitQueue::wait_timeout`] and the timer callbacks of [`set_timer`].
 //! - `preempt`: Enable preemptive scheduling.
-//! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
-//!   `multitask` feature if it is enabled. This feature is enabled by default,
-//!   and it can be overriden by other scheduler features.
-//! - `sched_rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
-//!   the `multitask` and `preempt` features if it is enabled.
-//! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
-//!   the `multitask` and `preempt` features if it is enabled.
+//! - `virtual-time`: Use the virtual clock of [`axhal::time`], which the idle
+//!   task fast-forwards to the next timer deadline when all the CPUs are idle.
+//! - `replay`: Record scheduling decisions with [`axreplay`], and follow the
+//!   recorded ones when replaying.
+//! - `bpf`: Run the scheduler tracepoints of [`axbpf`] on enqueue, dequeue
+//!   and pick, see [`run_queue_stats`].
+//! - `trace`: Keep the span of a distributed trace each task works for,
+//!   tagging its scheduler tracepoints, see [`TaskInner::set_trace_span`].
+//! - `sched_fifo`: Boot with the FIFO cooperative scheduler (`fifo`). It also
+//!   enables the `multitask` feature if it is enabled. This feature is enabled
+//!   by default, and it can be overriden by other scheduler features.
+//! - `sched_rr`: Boot with the Round-robin preemptive scheduler (`rr`). It
+//!   also enables the `multitask` and `preempt` features if it is enabled.
+//! - `sched_cfs`: Boot with the [Completely Fair Scheduler][1] (`cfs`). It
+//!   also enables the the `multitask` and `preempt` features if it is enabled.
+//! - `sched_boost`: Run the tasks waking up from waits before the other ready
+//!   tasks, unless they ran for long since they last woke up. It reduces the
+//!   latency of the interactive and I/O-bound tasks on busy CPUs, with any
+//!   scheduler.
+//! - `sched_smt`: Spread the tasks across the physical cores before their SMT
+//!   siblings, and let tasks keep their core to themselves, see
+//!   [`sched_domains`] and [`TaskInner::set_smt_exclusive`].
+//! - `sched_debug`: Check the invariants of the scheduler on every operation
+//!   (task state transitions, queues of the tasks, order of the timers), and
+//!   panic with a report of the task on violation. For debugging only.
+//! - `profile`: Sample the profiled tasks on the timer ticks, for per-task
+//!   on-CPU flamegraphs, see [`start_profiling`].
+//! - `irq-guard`: Report blocking in the IRQ handlers with a backtrace, see
+//!   [`may_block`]. For debugging only.
+//! - `page-color`: Keep a mask of the cache colors of the pages of each task,
+//!   see [`TaskInner::set_page_colors`].
+//! - `heap-quota`: Charge the heap allocations of the tasks to the quotas of
+//!   the tasks or of their jobs, see [`TaskInner::set_heap_quota`] and
+//!   [`Job::set_heap_quota`].
+//! - `nohz`: Allow isolating CPUs, running without the periodic timer tick
+//!   nor the kernel work, see [`set_cpu_isolated`].
+//! - `timer-broadcast`: Wait in the idle task with the local timer handed
+//!   over to the primary CPU, see [`axhal::broadcast`].
+//! - `pmu`: Count the events of the hardware performance counters per task,
+//!   see [`TaskInner::pmu_counters`].
+//! - `steal-time`: Charge the timer ticks stolen by the hypervisor to no
+//!   task, see [`stolen_ticks`].
 //!
-//! [1]: scheduler::FifoScheduler
-//! [2]: scheduler::RRScheduler
-//! [3]: scheduler::CFScheduler
+//! [1]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -42,18 +79,64 @@ cfg_if::cfg_if! {
         extern crate log;
         extern crate alloc;
 
+        mod job;
+        mod registry;
         mod run_queue;
+        mod sched;
+        #[cfg(feature = "sched_debug")]
+        mod sched_debug;
+        mod stats;
         mod task;
         mod task_ext;
         mod api;
+        mod cancel;
         mod wait_queue;
+        #[doc(cfg(feature = "multitask"))]
+        pub mod pool;
 
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "nohz")]
+        mod nohz;
+        #[cfg(feature = "profile")]
+        mod profile;
+        #[cfg(feature = "pmu")]
+        mod pmu;
+        #[cfg(feature = "steal-time")]
+        mod steal;
+        #[cfg(feature = "sched_smt")]
+        mod domains;
+        #[cfg(feature = "irq-guard")]
+        mod irq_guard;
+        #[cfg(any(feature = "profile", feature = "irq-guard"))]
+        mod unwind;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
+        #[doc(cfg(feature = "multitask"))]
+        pub use self::registry::{tasks, try_for_each_task};
+        #[doc(cfg(feature = "multitask"))]
+        pub use self::job::{current_job, Job, JobRef, JobUsage};
+        #[doc(cfg(feature = "multitask"))]
+        pub use self::sched::{
+            register_sched_policy, sched_policies, sched_policy, set_sched_policy, SchedPolicy,
+            MAX_TIME_SLICE,
+        };
+        #[cfg(feature = "nohz")]
+        pub use self::nohz::{is_cpu_isolated, set_cpu_isolated, tick_needed};
+        #[cfg(feature = "profile")]
+        pub use self::profile::{
+            profile_snapshot, start_profiling, stop_profiling, TaskProfile,
+        };
         pub use self::api::{sleep, sleep_until, yield_now};
+        #[cfg(feature = "irq")]
+        pub use self::timers::{cancel_timer, set_timer, TimerId};
+        #[doc(cfg(feature = "multitask"))]
+        pub use self::stats::{run_queue_stats, RunQueueStats};
+        #[cfg(feature = "steal-time")]
+        pub use self::steal::stolen_ticks;
+        #[cfg(feature = "sched_smt")]
+        pub use self::domains::sched_domains;
     } else {
         mod api_s;
         pub use self::api_s::{sleep, sleep_until, yield_now};
@@ -0,0 +1,70 @@
+//! Isolated CPUs, running without the periodic timer tick (full dynticks).
+//!
+//! An isolated CPU stops its tick while a task runs on it and no other task
+//! is ready, so that the task is never interrupted, and restarts it when it
+//! reschedules. It does not check the timers, which the other CPUs do, and
+//! does not run the unbound kernel work such as the `gc` task.
+//!
+//! As the run queue is shared by all CPUs, the tasks becoming ready while the
+//! tick is stopped are run by the other CPUs. The primary CPU can't be
+//! isolated, so that some CPU keeps the housekeeping.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static ISOLATED: [AtomicBool; axconfig::SMP] = [const { AtomicBool::new(false) }; axconfig::SMP];
+static TICK_STOPPED: [AtomicBool; axconfig::SMP] =
+    [const { AtomicBool::new(false) }; axconfig::SMP];
+
+const TICK_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// Isolates the CPU `cpu_id`, or stops isolating it.
+///
+/// Returns `false` if there is no such CPU, or if it is the primary CPU.
+pub fn set_cpu_isolated(cpu_id: usize, isolated: bool) -> bool {
+    if cpu_id == 0 || cpu_id >= axconfig::SMP {
+        return false;
+    }
+    info!("CPU {} isolated: {}", cpu_id, isolated);
+    ISOLATED[cpu_id].store(isolated, Ordering::Release);
+    true
+}
+
+/// Whether the CPU `cpu_id` is isolated.
+pub fn is_cpu_isolated(cpu_id: usize) -> bool {
+    ISOLATED
+        .get(cpu_id)
+        .is_some_and(|i| i.load(Ordering::Acquire))
+}
+
+/// Whether this CPU is isolated.
+pub(crate) fn this_cpu_isolated() -> bool {
+    is_cpu_isolated(axhal::cpu::this_cpu_id())
+}
+
+/// Whether this CPU needs its next timer tick, or stops it.
+///
+/// Called at the end of the timer tick, the tick is stopped if this CPU is
+/// isolated, runs a task, and no other task is ready.
+pub fn tick_needed() -> bool {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    if !is_cpu_isolated(cpu_id) || crate::current().is_idle() {
+        return true;
+    }
+    if crate::stats::nr_ready() > 0 {
+        return true;
+    }
+    if !TICK_STOPPED[cpu_id].swap(true, Ordering::Relaxed) {
+        debug!("CPU {}: timer tick stopped", cpu_id);
+    }
+    false
+}
+
+/// Restarts the timer tick of this CPU if it was stopped, when it
+/// reschedules.
+pub(crate) fn restart_tick() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    if TICK_STOPPED[cpu_id].swap(false, Ordering::Relaxed) {
+        debug!("CPU {}: timer tick restarted", cpu_id);
+        axhal::time::set_oneshot_timer(axhal::time::monotonic_time_nanos() + TICK_NANOS);
+    }
+}
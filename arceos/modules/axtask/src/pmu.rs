@@ -0,0 +1,65 @@
+//! Per-task hardware performance counters, with the `pmu` feature.
+//!
+//! The counters of [`axhal::pmu`] count for the CPU. They are read on every
+//! context switch, and the counts since a task was switched in are added to
+//! it when it is switched out, so each task has the counts of the events
+//! while it ran, see [`TaskInner::pmu_counters`].
+//!
+//! [`TaskInner::pmu_counters`]: crate::TaskInner::pmu_counters
+
+use axhal::pmu::PmuCounters;
+use kspin::SpinNoIrq;
+
+struct State {
+    /// The counts until the task was last switched in.
+    total: PmuCounters,
+    /// The CPU and its counters when the task was switched in, if it runs.
+    running: Option<(usize, PmuCounters)>,
+}
+
+/// The counters of a task.
+pub(crate) struct TaskPmu(SpinNoIrq<State>);
+
+impl TaskPmu {
+    pub const fn new() -> Self {
+        Self(SpinNoIrq::new(State {
+            total: PmuCounters::ZERO,
+            running: None,
+        }))
+    }
+
+    /// The task is switched in on the current CPU, whose counters are `now`.
+    pub fn switch_in(&self, now: &PmuCounters) {
+        self.0.lock().running = Some((axhal::cpu::this_cpu_id(), *now));
+    }
+
+    /// The task is switched out of the current CPU, whose counters are `now`.
+    pub fn switch_out(&self, now: &PmuCounters) {
+        let mut state = self.0.lock();
+        if let Some((_, since)) = state.running.take() {
+            state.total.accumulate(&now.since(&since));
+        }
+    }
+
+    /// The counts of the task, including its current run if it runs on the
+    /// current CPU.
+    pub fn counters(&self) -> PmuCounters {
+        let state = self.0.lock();
+        let mut counters = state.total;
+        // the lock disables the IRQs, the current CPU does not change
+        match state.running {
+            Some((cpu_id, since)) if cpu_id == axhal::cpu::this_cpu_id() => {
+                counters.accumulate(&axhal::pmu::read_all().since(&since));
+            }
+            _ => {}
+        }
+        counters
+    }
+}
+
+/// Switches the counters from `prev` to `next`, on a context switch.
+pub(crate) fn switch(prev: &TaskPmu, next: &TaskPmu) {
+    let now = axhal::pmu::read_all();
+    prev.switch_out(&now);
+    next.switch_in(&now);
+}
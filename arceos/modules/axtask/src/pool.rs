@@ -0,0 +1,232 @@
+//! Thread pools, for the CPU-bound helper work of the kernel.
+//!
+//! The subsystems hand their heavy computations (compression, checksums,
+//! crypto) to a [`ThreadPool`] instead of running them in the tasks which
+//! must stay responsive, like the reactor of an async executor or the tasks
+//! serving the interrupts. The work is queued and run in order by at most
+//! `size` worker tasks, spawned the first time they are needed and sleeping
+//! when the queue is empty. The workers belong to no [`Job`](crate::Job):
+//! terminating the job of a submitter does not stop them.
+//!
+//! [`spawn_blocking`] submits to the shared pool of the kernel, with a
+//! worker per CPU. A subsystem may have a pool of its own as a `static`.
+//!
+//! The result of a work is got from its [`BlockingHandle`], by blocking in
+//! [`BlockingHandle::join`] or by awaiting it, as it is a [`Future`]. A work
+//! may not wait for another work of its pool, which may be queued behind it.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use kspin::SpinNoIrq;
+
+use crate::{TaskInner, WaitQueue};
+
+type Work = Box<dyn FnOnce() + Send>;
+
+/// The shared pool of [`spawn_blocking`].
+static BLOCKING_POOL: ThreadPool = ThreadPool::new("blocking", axconfig::SMP);
+
+struct PoolInner {
+    queue: VecDeque<Work>,
+    /// The workers spawned.
+    workers: usize,
+    /// The workers running a work.
+    busy: usize,
+}
+
+/// A pool of worker tasks running the works submitted, see the [module
+/// documentation](self).
+pub struct ThreadPool {
+    name: &'static str,
+    size: usize,
+    inner: SpinNoIrq<PoolInner>,
+    work_ready: WaitQueue,
+}
+
+impl ThreadPool {
+    /// Creates a pool of at most `size` workers, named `<name>-<index>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub const fn new(name: &'static str, size: usize) -> Self {
+        assert!(size > 0, "empty thread pool");
+        Self {
+            name,
+            size,
+            inner: SpinNoIrq::new(PoolInner {
+                queue: VecDeque::new(),
+                workers: 0,
+                busy: 0,
+            }),
+            work_ready: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the name of the pool.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the largest number of workers of the pool.
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of workers spawned.
+    pub fn workers(&self) -> usize {
+        self.inner.lock().workers
+    }
+
+    /// Returns the number of works queued, not yet picked by a worker.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().queue.len()
+    }
+
+    /// Queues `f` to run in a worker of the pool, and returns the handle of
+    /// its result.
+    ///
+    /// A worker is spawned if all the ones spawned are busy and the pool is
+    /// not full.
+    pub fn spawn<F, T>(&'static self, f: F) -> BlockingHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = Arc::new(Slot {
+            state: SpinNoIrq::new(SlotState {
+                result: None,
+                waker: None,
+            }),
+            done: WaitQueue::new(),
+        });
+        let work_slot = slot.clone();
+        let work = Box::new(move || work_slot.complete(f()));
+
+        let new_worker = {
+            let mut inner = self.inner.lock();
+            inner.queue.push_back(work);
+            let idle = inner.workers - inner.busy;
+            if inner.workers < self.size && inner.queue.len() > idle {
+                inner.workers += 1;
+                Some(inner.workers - 1)
+            } else {
+                None
+            }
+        };
+        if let Some(index) = new_worker {
+            let name = format!("{}-{}", self.name, index);
+            let mut task =
+                TaskInner::new(move || self.run_worker(), name, axconfig::TASK_STACK_SIZE);
+            task.set_job(None);
+            crate::spawn_task(task);
+        }
+        self.work_ready.notify_one(true);
+        BlockingHandle { slot }
+    }
+
+    fn run_worker(&self) {
+        loop {
+            self.work_ready
+                .wait_until(|| !self.inner.lock().queue.is_empty());
+            let work = {
+                let mut inner = self.inner.lock();
+                let work = inner.queue.pop_front();
+                if work.is_some() {
+                    inner.busy += 1;
+                }
+                work
+            };
+            // Another worker may have taken it first.
+            if let Some(work) = work {
+                work();
+                self.inner.lock().busy -= 1;
+            }
+        }
+    }
+}
+
+/// Runs `f` in the shared pool of the kernel, with a worker per CPU, and
+/// returns the handle of its result.
+pub fn spawn_blocking<F, T>(f: F) -> BlockingHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    BLOCKING_POOL.spawn(f)
+}
+
+struct SlotState<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+struct Slot<T> {
+    state: SpinNoIrq<SlotState<T>>,
+    done: WaitQueue,
+}
+
+impl<T> Slot<T> {
+    fn complete(&self, value: T) {
+        let waker = {
+            let mut state = self.state.lock();
+            state.result = Some(value);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        self.done.notify_all(true);
+    }
+}
+
+/// The handle of the result of a work submitted to a [`ThreadPool`].
+///
+/// Dropping it does not cancel the work, whose result is dropped.
+pub struct BlockingHandle<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> BlockingHandle<T> {
+    /// Returns whether the work finished.
+    pub fn is_finished(&self) -> bool {
+        self.slot.state.lock().result.is_some()
+    }
+
+    /// Returns the result of the work if it finished, or gives the handle
+    /// back.
+    pub fn try_join(self) -> Result<T, Self> {
+        let result = self.slot.state.lock().result.take();
+        result.ok_or(self)
+    }
+
+    /// Blocks the current task until the work finishes, and returns its
+    /// result.
+    pub fn join(self) -> T {
+        self.slot
+            .done
+            .wait_until(|| self.slot.state.lock().result.is_some());
+        self.slot.state.lock().result.take().unwrap()
+    }
+}
+
+impl<T> Future for BlockingHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.slot.state.lock();
+        match state.result.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-task on-CPU profiles, with the `profile` feature.
+//!
+//! Every timer tick samples the task running on the CPU if it is profiled:
+//! the interrupted program counter, and the return addresses of the kernel
+//! stack walked by the frame pointers. The kernel must be built with
+//! `-C force-frame-pointers=yes` for the walk, only the interrupted program
+//! counter is sampled otherwise, as for the user code.
+//!
+//! The samples are counted per stack, and [`TaskProfile::folded`] gives them
+//! in the folded format of the flamegraph tools (`flamegraph.pl`,
+//! `inferno-flamegraph`), the addresses to be resolved with `addr2line` on
+//! the kernel ELF.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axhal::arch::TrapFrame;
+use kspin::SpinNoIrq;
+
+/// The most frames of a sample.
+const MAX_DEPTH: usize = 32;
+/// The most distinct stacks of a profile, the samples of the other ones are
+/// dropped.
+const MAX_STACKS: usize = 4096;
+
+/// The on-CPU samples of a task.
+#[derive(Debug, Clone)]
+pub struct TaskProfile {
+    id: u64,
+    name: String,
+    /// The sampled stacks, outermost frame first, and their counts.
+    stacks: BTreeMap<Vec<usize>, u64>,
+    samples: u64,
+    dropped: u64,
+}
+
+impl TaskProfile {
+    /// The ID of the profiled task.
+    pub fn task_id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of samples, including the dropped ones.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// The number of samples dropped as there were too many distinct stacks.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns the sampled stacks (outermost frame first) and their counts.
+    pub fn stacks(&self) -> impl Iterator<Item = (&[usize], u64)> {
+        self.stacks.iter().map(|(stack, &n)| (stack.as_slice(), n))
+    }
+
+    /// Returns the stacks in the folded format, a line per stack with the
+    /// task as the root frame, e.g. `worker[5];0xffffffc080201234;... 42`.
+    ///
+    /// The profiles of several tasks can be concatenated for one flamegraph.
+    pub fn folded(&self) -> String {
+        let root: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c == ';' || c.is_whitespace() {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let mut out = String::new();
+        for (stack, n) in self.stacks() {
+            let _ = write!(out, "{}[{}]", root, self.id);
+            for pc in stack {
+                let _ = write!(out, ";{:#x}", pc);
+            }
+            let _ = writeln!(out, " {}", n);
+        }
+        out
+    }
+
+    fn add(&mut self, mut pcs: Vec<usize>) {
+        self.samples += 1;
+        pcs.reverse();
+        if let Some(n) = self.stacks.get_mut(&pcs) {
+            *n += 1;
+        } else if self.stacks.len() < MAX_STACKS {
+            self.stacks.insert(pcs, 1);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+static PROFILES: SpinNoIrq<BTreeMap<u64, TaskProfile>> = SpinNoIrq::new(BTreeMap::new());
+
+/// The number of profiled tasks, to skip the sampling if there is none.
+static NR_PROFILED: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts profiling the task `id`.
+///
+/// Returns `false` if there is no such task, or it is already profiled.
+pub fn start_profiling(id: u64) -> bool {
+    let Some(task) = crate::tasks().into_iter().find(|t| t.id().as_u64() == id) else {
+        return false;
+    };
+    let mut profiles = PROFILES.lock();
+    if profiles.contains_key(&id) {
+        return false;
+    }
+    profiles.insert(
+        id,
+        TaskProfile {
+            id,
+            name: task.name(),
+            stacks: BTreeMap::new(),
+            samples: 0,
+            dropped: 0,
+        },
+    );
+    NR_PROFILED.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// Stops profiling the task `id`, returns its profile.
+///
+/// The profile of an exited task is kept until it is stopped.
+pub fn stop_profiling(id: u64) -> Option<TaskProfile> {
+    let profile = PROFILES.lock().remove(&id)?;
+    NR_PROFILED.fetch_sub(1, Ordering::Relaxed);
+    Some(profile)
+}
+
+/// Returns the profile of the task `id` so far, and keeps profiling it.
+pub fn profile_snapshot(id: u64) -> Option<TaskProfile> {
+    PROFILES.lock().get(&id).cloned()
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        fn frame_regs(tf: &TrapFrame) -> (usize, usize) {
+            (tf.sepc, tf.regs.s0)
+        }
+    } else if #[cfg(target_arch = "x86_64")] {
+        fn frame_regs(tf: &TrapFrame) -> (usize, usize) {
+            (tf.rip as usize, tf.rbp as usize)
+        }
+    } else if #[cfg(target_arch = "aarch64")] {
+        fn frame_regs(tf: &TrapFrame) -> (usize, usize) {
+            (tf.elr as usize, tf.r[29] as usize)
+        }
+    }
+}
+
+/// Walks the frames interrupted by `tf` in the kernel stack below
+/// `stack_top`, returns the program counters, innermost first.
+fn walk(tf: &TrapFrame, stack_top: usize) -> Vec<usize> {
+    let (pc, fp) = frame_regs(tf);
+    let mut pcs = [0; MAX_DEPTH];
+    pcs[0] = pc;
+    // the interrupted frames are above the trap frame
+    let low = tf as *const _ as usize;
+    let n = crate::unwind::walk(fp, low, stack_top, &mut pcs[1..]);
+    pcs[..n + 1].to_vec()
+}
+
+/// Samples the current task on a timer tick.
+pub(crate) fn sample() {
+    if NR_PROFILED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let curr = crate::current();
+    let id = curr.id().as_u64();
+    if !PROFILES.lock().contains_key(&id) {
+        return;
+    }
+    // the tasks on the boot stacks have the interrupted program counter only
+    let top = curr.kernel_stack_top().map_or(0, |top| top.as_usize());
+    let Some(pcs) = axhal::trap::with_irq_trap_frame(|tf| walk(tf, top)) else {
+        return;
+    };
+    if let Some(profile) = PROFILES.lock().get_mut(&id) {
+        profile.add(pcs);
+    }
+}
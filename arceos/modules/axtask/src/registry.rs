@@ -0,0 +1,71 @@
+//! The names of the tasks, unique among them, and the list of the tasks.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use kspin::SpinNoIrq;
+
+use crate::{AxTask, AxTaskRef, TaskId};
+
+static NAMES: SpinNoIrq<BTreeSet<String>> = SpinNoIrq::new(BTreeSet::new());
+
+static TASKS: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Reserves `name`, or `name` with the first free suffix `-2`, `-3`... if it
+/// is used by another task, and returns it.
+///
+/// The empty name, for unnamed tasks, is not reserved.
+pub(crate) fn reserve_name(name: String) -> String {
+    if name.is_empty() {
+        return name;
+    }
+    let mut names = NAMES.lock();
+    let name = if names.contains(&name) {
+        (2..)
+            .map(|n| alloc::format!("{}-{}", name, n))
+            .find(|s| !names.contains(s))
+            .unwrap()
+    } else {
+        name
+    };
+    names.insert(name.clone());
+    name
+}
+
+/// Releases a name reserved by [`reserve_name`].
+pub(crate) fn release_name(name: &str) {
+    NAMES.lock().remove(name);
+}
+
+pub(crate) fn register(task: &AxTaskRef) {
+    TASKS
+        .lock()
+        .insert(task.id().as_u64(), AxTaskRef::downgrade(task));
+}
+
+pub(crate) fn unregister(id: TaskId) {
+    TASKS.lock().remove(&id.as_u64());
+}
+
+/// Returns the tasks alive, ordered by their IDs.
+pub fn tasks() -> Vec<AxTaskRef> {
+    // The tasks upgraded must not be dropped with the lock held.
+    let tasks = TASKS.lock().values().filter_map(Weak::upgrade).collect();
+    tasks
+}
+
+/// Calls `f` on the tasks alive, ordered by their IDs, without allocating,
+/// for the reports of a panic. Returns `false` if the list is locked.
+///
+/// The references to the tasks are leaked, as the system is going down.
+pub fn try_for_each_task(mut f: impl FnMut(&AxTaskRef)) -> bool {
+    let Some(tasks) = TASKS.try_lock() else {
+        return false;
+    };
+    for task in tasks.values().filter_map(Weak::upgrade) {
+        f(&task);
+        core::mem::forget(task);
+    }
+    true
+}
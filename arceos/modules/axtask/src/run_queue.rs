@@ -1,11 +1,12 @@
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
-use scheduler::BaseScheduler;
 
+use crate::sched::SchedPolicy;
 use crate::task::{CurrentTask, TaskState};
-use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
+use crate::{AxTaskRef, TaskInner, WaitQueue};
 
 // TODO: per-CPU
 pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
@@ -18,31 +19,89 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// A waking task is boosted if it ran for less timer ticks since it last
+/// woke up.
+#[cfg(feature = "sched_boost")]
+const BOOST_MAX_RUN_TICKS: usize = 2;
+
+/// Maximum number of boosted tasks picked in a row while other tasks are
+/// ready, so that they can't starve them.
+#[cfg(feature = "sched_boost")]
+const MAX_BOOST_STREAK: usize = 8;
+
 pub(crate) struct AxRunQueue {
-    scheduler: Scheduler,
+    scheduler: Box<dyn SchedPolicy>,
+    policy_name: &'static str,
+    /// Woken up tasks to run before the ones of the scheduler.
+    #[cfg(feature = "sched_boost")]
+    boosted: VecDeque<AxTaskRef>,
+    #[cfg(feature = "sched_boost")]
+    boost_streak: usize,
 }
 
 impl AxRunQueue {
     pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE).into_arc();
-        let mut scheduler = Scheduler::new();
+        let mut gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE);
+        gc_task.set_housekeeping();
+        let gc_task = gc_task.into_arc();
+        let (policy_name, mut scheduler) = crate::sched::init();
+        crate::stats::on_enqueue(&gc_task);
         scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+        SpinNoIrq::new(Self {
+            scheduler,
+            policy_name,
+            #[cfg(feature = "sched_boost")]
+            boosted: VecDeque::new(),
+            #[cfg(feature = "sched_boost")]
+            boost_streak: 0,
+        })
+    }
+
+    pub fn policy_name(&self) -> &'static str {
+        self.policy_name
+    }
+
+    /// Switches to another scheduling policy, moving the ready tasks to it.
+    pub fn set_policy(&mut self, name: &'static str, mut policy: Box<dyn SchedPolicy>) {
+        while let Some(task) = self.scheduler.pick_next_task() {
+            policy.add_task(task);
+        }
+        self.scheduler = policy;
+        self.policy_name = name;
     }
 
     pub fn add_task(&mut self, task: AxTaskRef) {
         debug!("task spawn: {}", task.id_name());
         assert!(task.is_ready());
+        crate::stats::on_enqueue(&task);
         self.scheduler.add_task(task);
     }
 
     #[cfg(feature = "irq")]
     pub fn scheduler_timer_tick(&mut self) {
         let curr = crate::current();
+        #[cfg(feature = "steal-time")]
+        if crate::steal::tick_stolen() {
+            if !curr.is_idle() {
+                curr.add_stolen_tick();
+            }
+            return;
+        }
+        #[cfg(feature = "sched_boost")]
+        if !curr.is_idle() {
+            curr.add_run_tick();
+        }
+        if let Some(job) = curr.job() {
+            job.account_tick();
+        }
         if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+        #[cfg(all(feature = "sched_smt", feature = "preempt"))]
+        if !curr.is_idle() && crate::domains::sibling_exclusive(axhal::cpu::this_cpu_id()) {
+            curr.set_preempt_pending(true);
+        }
     }
 
     pub fn yield_current(&mut self) {
@@ -86,12 +145,21 @@ impl AxRunQueue {
         debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
         assert!(curr.is_running());
         assert!(!curr.is_idle());
+        #[cfg(feature = "event")]
+        axevent::publish(axevent::KernelEvent::TaskExit {
+            task_id: curr.id().as_u64(),
+            exit_code,
+        });
         if curr.is_init() {
             EXITED_TASKS.lock().clear();
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
+            crate::stats::on_dequeue(curr.as_task_ref());
             curr.notify_exit(exit_code, self);
+            if let Some(job) = curr.job() {
+                job.on_member_exit(curr.as_task_ref(), exit_code, self);
+            }
             EXITED_TASKS.lock().push_back(curr.clone());
             WAIT_FOR_EXIT.notify_one_locked(false, self);
             self.resched(false);
@@ -105,6 +173,7 @@ impl AxRunQueue {
     {
         let curr = crate::current();
         debug!("task block: {}", curr.id_name());
+        crate::may_block("waiting");
         assert!(curr.is_running());
         assert!(!curr.is_idle());
 
@@ -113,6 +182,7 @@ impl AxRunQueue {
         assert!(curr.can_preempt(1));
 
         curr.set_state(TaskState::Blocked);
+        crate::stats::on_dequeue(curr.as_task_ref());
         wait_queue_push(curr.clone());
         self.resched(false);
     }
@@ -121,6 +191,15 @@ impl AxRunQueue {
         debug!("task unblock: {}", task.id_name());
         if task.is_blocked() {
             task.set_state(TaskState::Ready);
+            crate::stats::on_enqueue(&task);
+            #[cfg(feature = "sched_boost")]
+            if task.take_run_ticks() < BOOST_MAX_RUN_TICKS && !task.is_housekeeping() {
+                trace!("task boost: {}", task.id_name());
+                self.boosted.push_back(task);
+                #[cfg(feature = "preempt")]
+                crate::current().set_preempt_pending(true);
+                return;
+            }
             self.scheduler.add_task(task); // TODO: priority
             if resched {
                 #[cfg(feature = "preempt")]
@@ -133,6 +212,7 @@ impl AxRunQueue {
     pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
         let curr = crate::current();
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
+        crate::may_block("sleeping");
         assert!(curr.is_running());
         assert!(!curr.is_idle());
 
@@ -140,6 +220,7 @@ impl AxRunQueue {
         if now < deadline {
             crate::timers::set_alarm_wakeup(deadline, curr.clone());
             curr.set_state(TaskState::Blocked);
+            crate::stats::on_dequeue(curr.as_task_ref());
             self.resched(false);
         }
     }
@@ -153,14 +234,126 @@ impl AxRunQueue {
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
             if !prev.is_idle() {
+                crate::stats::on_enqueue(prev.as_task_ref());
                 self.scheduler.put_prev_task(prev.clone(), preempt);
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
+        #[cfg(feature = "nohz")]
+        crate::nohz::restart_tick();
+        let next = self.pick_next_task();
+        #[cfg(feature = "replay")]
+        axreplay::on_schedule(
+            axhal::cpu::this_cpu_id(),
+            prev.id().as_u64(),
+            next.id().as_u64(),
+        );
+        self.switch_to(prev, next);
+    }
+
+    fn pick_next_task(&mut self) -> AxTaskRef {
+        let next = self.select_next_task();
+        crate::stats::on_pick(&next);
+        next
+    }
+
+    fn select_next_task(&mut self) -> AxTaskRef {
+        #[cfg(feature = "replay")]
+        if let Some(id) = axreplay::replay_next_task(axhal::cpu::this_cpu_id()) {
+            if let Some(task) = self.pick_task_by_id(id) {
+                return task;
+            }
+        }
+        #[cfg(feature = "sched_boost")]
+        if let Some(task) = self.pick_boosted_task() {
+            return task;
+        }
+        #[cfg(feature = "nohz")]
+        let next = self.pick_isolated_task();
+        #[cfg(not(feature = "nohz"))]
+        let next = self.scheduler.pick_next_task();
+        #[cfg(feature = "sched_smt")]
+        let next = self.place_smt_task(next);
+        next.unwrap_or_else(|| unsafe {
             // Safety: IRQs must be disabled at this time.
             IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
-        self.switch_to(prev, next);
+        })
+    }
+
+    /// Picks the first boosted task, or a task of the scheduler after too many
+    /// boosted ones in a row.
+    #[cfg(feature = "sched_boost")]
+    fn pick_boosted_task(&mut self) -> Option<AxTaskRef> {
+        if self.boosted.is_empty() {
+            self.boost_streak = 0;
+            return None;
+        }
+        if self.boost_streak >= MAX_BOOST_STREAK {
+            if let Some(task) = self.scheduler.pick_next_task() {
+                self.boost_streak = 0;
+                return Some(task);
+            }
+        }
+        self.boost_streak += 1;
+        self.boosted.pop_front()
+    }
+
+    /// Picks the next task of the scheduler, but the housekeeping ones on an
+    /// isolated CPU.
+    #[cfg(feature = "nohz")]
+    fn pick_isolated_task(&mut self) -> Option<AxTaskRef> {
+        let task = self.scheduler.pick_next_task()?;
+        if !task.is_housekeeping() || !crate::nohz::this_cpu_isolated() {
+            return Some(task);
+        }
+        // Only the `gc` task is housekeeping, the next one is not.
+        let next = self.scheduler.pick_next_task();
+        self.scheduler.put_prev_task(task, true);
+        next
+    }
+
+    /// Puts the next task back and runs the idle task instead, once for an
+    /// idle core to pick it, or while an SMT sibling of this CPU runs an
+    /// exclusive task, see [`crate::domains`].
+    #[cfg(feature = "sched_smt")]
+    fn place_smt_task(&mut self, next: Option<AxTaskRef>) -> Option<AxTaskRef> {
+        let task = next?;
+        let cpu_id = axhal::cpu::this_cpu_id();
+        if !crate::domains::sibling_exclusive(cpu_id) {
+            if task.set_spread_deferred(false) || !crate::domains::should_spread(cpu_id) {
+                return Some(task);
+            }
+            task.set_spread_deferred(true);
+        }
+        self.scheduler.put_prev_task(task, true);
+        None
+    }
+
+    /// Picks the ready task with the given ID, by rotating the ready queue
+    /// until it comes first. Returns `None` if it is not ready.
+    #[cfg(feature = "replay")]
+    fn pick_task_by_id(&mut self, id: u64) -> Option<AxTaskRef> {
+        // Safety: IRQs must be disabled at this time.
+        let idle = unsafe { IDLE_TASK.current_ref_raw().get_unchecked() };
+        if idle.id().as_u64() == id {
+            return Some(idle.clone());
+        }
+        #[cfg(feature = "sched_boost")]
+        if let Some(index) = self.boosted.iter().position(|t| t.id().as_u64() == id) {
+            return self.boosted.remove(index);
+        }
+        let first = self.scheduler.pick_next_task()?;
+        let mut task = first.clone();
+        loop {
+            if task.id().as_u64() == id {
+                return Some(task);
+            }
+            self.scheduler.put_prev_task(task, false);
+            task = self.scheduler.pick_next_task()?;
+            if Arc::ptr_eq(&task, &first) {
+                self.scheduler.put_prev_task(task, false);
+                return None;
+            }
+        }
     }
 
     fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
@@ -172,9 +365,14 @@ impl AxRunQueue {
         #[cfg(feature = "preempt")]
         next_task.set_preempt_pending(false);
         next_task.set_state(TaskState::Running);
+        #[cfg(feature = "sched_smt")]
+        crate::domains::on_switch(&next_task);
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        crate::stats::on_switch();
+        #[cfg(feature = "pmu")]
+        crate::pmu::switch(prev_task.pmu(), next_task.pmu());
 
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
@@ -224,17 +422,29 @@ pub(crate) fn init() {
     // Put the subsequent execution into the `main` task.
     let main_task = TaskInner::new_init("main".into()).into_arc();
     main_task.set_state(TaskState::Running);
+    #[cfg(feature = "pmu")]
+    main_task.pmu().switch_in(&axhal::pmu::read_all());
+    #[cfg(feature = "sched_smt")]
+    crate::domains::on_switch(&main_task);
     unsafe { CurrentTask::init_current(main_task) };
 
     RUN_QUEUE.init_once(AxRunQueue::new());
+    #[cfg(all(feature = "irq", feature = "virtual-time"))]
+    crate::timers::cpu_online();
 }
 
 pub(crate) fn init_secondary() {
     // Put the subsequent execution into the `idle` task.
     let idle_task = TaskInner::new_init("idle".into()).into_arc();
     idle_task.set_state(TaskState::Running);
+    #[cfg(feature = "pmu")]
+    idle_task.pmu().switch_in(&axhal::pmu::read_all());
     IDLE_TASK.with_current(|i| {
         i.init_once(idle_task.clone());
     });
+    #[cfg(feature = "sched_smt")]
+    crate::domains::on_switch(&idle_task);
     unsafe { CurrentTask::init_current(idle_task) }
+    #[cfg(all(feature = "irq", feature = "virtual-time"))]
+    crate::timers::cpu_online();
 }
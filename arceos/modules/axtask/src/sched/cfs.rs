@@ -0,0 +1,91 @@
+//! The Completely Fair Scheduler policy.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::Ordering;
+
+use super::SchedPolicy;
+use crate::AxTaskRef;
+
+/// The weight of a nice value of 0.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// The weights of the nice values from -20 to 19, each one about 1.25 times
+/// the next one, as in Linux.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// The virtual runtime of a tick of a task of nice 0.
+const TICK_VRUNTIME: u64 = 1024;
+
+/// Runs the ready task which ran the least, by virtual runtime: the timer
+/// ticks the tasks ran for, weighted by their nice values.
+///
+/// A task added to the queue starts with the least virtual runtime of the
+/// queue, so that it does not run for long to catch up with the others.
+pub(super) struct CfsPolicy {
+    /// The ready tasks, by virtual runtime and order of queuing.
+    ready: BTreeMap<(u64, u64), AxTaskRef>,
+    /// The least virtual runtime of the queue, never decreasing.
+    min_vruntime: u64,
+    next_seq: u64,
+}
+
+impl CfsPolicy {
+    pub(super) const fn new() -> Self {
+        Self {
+            ready: BTreeMap::new(),
+            min_vruntime: 0,
+            next_seq: 0,
+        }
+    }
+
+    fn insert(&mut self, task: AxTaskRef) {
+        let vruntime = task.sched_entity().vruntime.load(Ordering::Acquire);
+        self.ready.insert((vruntime, self.next_seq), task);
+        self.next_seq += 1;
+        if let Some((&(first, _), _)) = self.ready.first_key_value() {
+            self.min_vruntime = self.min_vruntime.max(first);
+        }
+    }
+}
+
+impl SchedPolicy for CfsPolicy {
+    fn add_task(&mut self, task: AxTaskRef) {
+        let vruntime = &task.sched_entity().vruntime;
+        vruntime.store(self.min_vruntime, Ordering::Release);
+        self.insert(task);
+    }
+
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        self.ready.pop_first().map(|(_, task)| task)
+    }
+
+    fn put_prev_task(&mut self, prev: AxTaskRef, _preempt: bool) {
+        // It may come from another policy, with an old virtual runtime.
+        let vruntime = &prev.sched_entity().vruntime;
+        vruntime.fetch_max(self.min_vruntime, Ordering::AcqRel);
+        self.insert(prev);
+    }
+
+    fn task_tick(&mut self, current: &AxTaskRef) -> bool {
+        let entity = current.sched_entity();
+        let nice = entity.nice.load(Ordering::Acquire);
+        let weight = NICE_TO_WEIGHT[(nice + 20) as usize];
+        let delta = TICK_VRUNTIME * NICE_0_WEIGHT / weight;
+        let vruntime = entity.vruntime.fetch_add(delta, Ordering::AcqRel) + delta;
+        self.ready
+            .first_key_value()
+            .is_some_and(|(&(first, _), _)| vruntime > first)
+    }
+
+    fn set_priority(&mut self, current: &AxTaskRef, prio: isize) -> bool {
+        if !(-20..=19).contains(&prio) {
+            return false;
+        }
+        current.sched_entity().nice.store(prio, Ordering::Release);
+        true
+    }
+}
@@ -0,0 +1,42 @@
+//! The FIFO cooperative policy.
+
+use alloc::collections::VecDeque;
+
+use super::SchedPolicy;
+use crate::AxTaskRef;
+
+/// Runs the ready tasks in the order they became ready, until they yield or
+/// block.
+pub(super) struct FifoPolicy {
+    ready: VecDeque<AxTaskRef>,
+}
+
+impl FifoPolicy {
+    pub(super) const fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl SchedPolicy for FifoPolicy {
+    fn add_task(&mut self, task: AxTaskRef) {
+        self.ready.push_back(task);
+    }
+
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        self.ready.pop_front()
+    }
+
+    fn put_prev_task(&mut self, prev: AxTaskRef, _preempt: bool) {
+        self.ready.push_back(prev);
+    }
+
+    fn task_tick(&mut self, _current: &AxTaskRef) -> bool {
+        false
+    }
+
+    fn set_priority(&mut self, _current: &AxTaskRef, _prio: isize) -> bool {
+        false
+    }
+}
@@ -0,0 +1,155 @@
+//! Scheduling policies, switchable at runtime.
+//!
+//! A policy implements [`SchedPolicy`]: it holds the ready tasks, and decides
+//! which one runs next. The policies are registered by name with
+//! [`register_sched_policy`], the built-in ones at boot:
+//!
+//! - `fifo`: the FIFO cooperative scheduler;
+//! - `rr`: the round-robin preemptive scheduler, with time slices of
+//!   [`MAX_TIME_SLICE`] ticks;
+//! - `cfs`: the [Completely Fair Scheduler], the priority being the nice
+//!   value.
+//!
+//! The run queue starts with the one of the `sched_*` feature (`fifo` by
+//! default), and [`set_sched_policy`] switches to another one: the ready
+//! tasks move to it, and the other tasks join it once they are put back in
+//! the run queue (spawned, woken up, yielding or preempted). The nice values
+//! are kept in the tasks, so they carry over when switching back to `cfs`.
+//!
+//! The preemptive policies only preempt with the `preempt` feature, without
+//! it they run as cooperative ones.
+//!
+//! [Completely Fair Scheduler]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
+
+mod cfs;
+mod fifo;
+mod rr;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicIsize, AtomicU64};
+
+use kspin::SpinNoIrq;
+
+use crate::{AxTaskRef, RUN_QUEUE};
+
+pub use self::rr::MAX_TIME_SLICE;
+
+/// A scheduling policy, the queue of the ready tasks.
+///
+/// The methods are called with the run queue locked and the IRQs disabled,
+/// they must not block nor lock the run queue. The running task, the idle
+/// task and the blocked ones are not in the queue.
+pub trait SchedPolicy: Send {
+    /// Adds a task which became ready, spawned or woken up.
+    fn add_task(&mut self, task: AxTaskRef);
+
+    /// Takes the next task to run, `None` if there is no ready task.
+    fn pick_next_task(&mut self) -> Option<AxTaskRef>;
+
+    /// Puts back the task which was running, `preempt` if it was preempted
+    /// rather than yielding.
+    fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool);
+
+    /// Accounts a timer tick to the running task, returns whether it must be
+    /// preempted.
+    fn task_tick(&mut self, current: &AxTaskRef) -> bool;
+
+    /// Sets the priority of the running task, returns `false` if the policy
+    /// has none or it is out of its range.
+    fn set_priority(&mut self, current: &AxTaskRef, prio: isize) -> bool;
+}
+
+/// The per-task state of the built-in policies.
+pub(crate) struct SchedEntity {
+    /// The ticks left of the time slice, for `rr`.
+    time_slice: AtomicIsize,
+    /// The virtual runtime, for `cfs`.
+    vruntime: AtomicU64,
+    /// The nice value, for `cfs`.
+    nice: AtomicIsize,
+}
+
+impl SchedEntity {
+    pub(crate) const fn new() -> Self {
+        Self {
+            time_slice: AtomicIsize::new(MAX_TIME_SLICE),
+            vruntime: AtomicU64::new(0),
+            nice: AtomicIsize::new(0),
+        }
+    }
+}
+
+/// A registered policy.
+struct PolicyEntry {
+    name: &'static str,
+    new: fn() -> Box<dyn SchedPolicy>,
+}
+
+static POLICIES: SpinNoIrq<Vec<PolicyEntry>> = SpinNoIrq::new(Vec::new());
+
+/// The policy the run queue starts with.
+const DEFAULT_POLICY: &str = if cfg!(feature = "sched_rr") {
+    "rr"
+} else if cfg!(feature = "sched_cfs") {
+    "cfs"
+} else {
+    "fifo"
+};
+
+/// Registers the built-in policies, returns the default one.
+pub(crate) fn init() -> (&'static str, Box<dyn SchedPolicy>) {
+    register_sched_policy("fifo", || Box::new(fifo::FifoPolicy::new()));
+    register_sched_policy("rr", || Box::new(rr::RRPolicy::new()));
+    register_sched_policy("cfs", || Box::new(cfs::CfsPolicy::new()));
+    (DEFAULT_POLICY, new_policy(DEFAULT_POLICY).unwrap())
+}
+
+fn new_policy(name: &str) -> Option<Box<dyn SchedPolicy>> {
+    let policies = POLICIES.lock();
+    let entry = policies.iter().find(|entry| entry.name == name)?;
+    Some((entry.new)())
+}
+
+/// Registers the scheduling policy `name`, created by `new` each time it is
+/// switched to.
+///
+/// Returns `false` if there is already a policy of that name.
+pub fn register_sched_policy(name: &'static str, new: fn() -> Box<dyn SchedPolicy>) -> bool {
+    let mut policies = POLICIES.lock();
+    if policies.iter().any(|entry| entry.name == name) {
+        return false;
+    }
+    policies.push(PolicyEntry { name, new });
+    true
+}
+
+/// Returns the names of the registered scheduling policies, in the order
+/// they were registered.
+pub fn sched_policies() -> Vec<&'static str> {
+    POLICIES.lock().iter().map(|entry| entry.name).collect()
+}
+
+/// Returns the name of the active scheduling policy.
+pub fn sched_policy() -> &'static str {
+    RUN_QUEUE.lock().policy_name()
+}
+
+/// Switches to the scheduling policy `name`, returns `false` if it is not
+/// registered.
+///
+/// Switching to the active policy does nothing.
+pub fn set_sched_policy(name: &str) -> bool {
+    let Some(name) = sched_policies()
+        .into_iter()
+        .find(|&registered| registered == name)
+    else {
+        return false;
+    };
+    if sched_policy() != name {
+        let policy = new_policy(name).unwrap();
+        info!("switch to the {} scheduling policy", name);
+        RUN_QUEUE.lock().set_policy(name, policy);
+    }
+    true
+}
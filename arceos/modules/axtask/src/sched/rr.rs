@@ -0,0 +1,61 @@
+//! The round-robin preemptive policy.
+
+use alloc::collections::VecDeque;
+use core::sync::atomic::Ordering;
+
+use super::SchedPolicy;
+use crate::AxTaskRef;
+
+/// The timer ticks of a time slice.
+pub const MAX_TIME_SLICE: isize = 5;
+
+/// Runs the ready tasks in turn, each for a time slice.
+///
+/// A task preempted before the end of its time slice runs first the next
+/// time, for the rest of it.
+pub(super) struct RRPolicy {
+    ready: VecDeque<AxTaskRef>,
+}
+
+impl RRPolicy {
+    pub(super) const fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+fn reset_time_slice(task: &AxTaskRef) {
+    let entity = task.sched_entity();
+    entity.time_slice.store(MAX_TIME_SLICE, Ordering::Release);
+}
+
+impl SchedPolicy for RRPolicy {
+    fn add_task(&mut self, task: AxTaskRef) {
+        reset_time_slice(&task);
+        self.ready.push_back(task);
+    }
+
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        self.ready.pop_front()
+    }
+
+    fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool) {
+        let time_slice = prev.sched_entity().time_slice.load(Ordering::Acquire);
+        if time_slice > 0 && preempt {
+            self.ready.push_front(prev);
+        } else {
+            reset_time_slice(&prev);
+            self.ready.push_back(prev);
+        }
+    }
+
+    fn task_tick(&mut self, current: &AxTaskRef) -> bool {
+        let entity = current.sched_entity();
+        entity.time_slice.fetch_sub(1, Ordering::Release) <= 1
+    }
+
+    fn set_priority(&mut self, _current: &AxTaskRef, _prio: isize) -> bool {
+        false
+    }
+}
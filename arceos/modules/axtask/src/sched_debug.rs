@@ -0,0 +1,115 @@
+//! Integrity checks of the scheduler, with the `sched_debug` feature.
+//!
+//! The state transitions of the tasks, the queues they are in and the order
+//! of the expired timers are checked on every operation, and a violation
+//! panics with a report of the task, before the bug corrupts anything else.
+
+use core::fmt;
+
+#[cfg(feature = "irq")]
+use axhal::time::TimeValue;
+
+use crate::stats::NO_CPU;
+use crate::task::{TaskInner, TaskState};
+
+fn violation(task: &TaskInner, what: fmt::Arguments) -> ! {
+    #[cfg(feature = "irq")]
+    let in_timer_list = task.in_timer_list();
+    #[cfg(not(feature = "irq"))]
+    let in_timer_list = false;
+    let rq_cpu = Some(task.rq_cpu()).filter(|&cpu| cpu != NO_CPU);
+    panic!(
+        "scheduler integrity violation on CPU {}: {}\n    \
+         task: {}, state={:?}, idle={}, in_wait_queue={}, in_timer_list={}, \
+         ready on CPU {:?}",
+        axhal::cpu::this_cpu_id(),
+        what,
+        task.id_name_fmt(),
+        task.state(),
+        task.is_idle(),
+        task.in_wait_queue(),
+        in_timer_list,
+        rq_cpu,
+    );
+}
+
+pub(crate) fn check_transition(task: &TaskInner, from: TaskState, to: TaskState) {
+    use TaskState::*;
+    if !matches!(
+        (from, to),
+        (Ready, Running) | (Running, Ready | Blocked | Exited) | (Blocked, Ready)
+    ) {
+        violation(
+            task,
+            format_args!("invalid transition {:?} -> {:?}", from, to),
+        );
+    }
+}
+
+/// Checks a task put in the ready queue, `prev_rq_cpu` is the CPU it was
+/// accounted to before.
+pub(crate) fn on_enqueue(task: &TaskInner, prev_rq_cpu: usize) {
+    if prev_rq_cpu != NO_CPU {
+        violation(
+            task,
+            format_args!("enqueued again, already ready on CPU {}", prev_rq_cpu),
+        );
+    }
+    if task.is_idle() || !task.is_ready() {
+        violation(task, format_args!("enqueued in the ready queue"));
+    }
+}
+
+/// Checks a task leaving the CPU without being put in the ready queue.
+pub(crate) fn on_dequeue(task: &TaskInner) {
+    if !matches!(task.state(), TaskState::Blocked | TaskState::Exited) {
+        violation(task, format_args!("dequeued but still runnable"));
+    }
+    if task.rq_cpu() != NO_CPU {
+        violation(task, format_args!("dequeued but still in the ready queue"));
+    }
+    if task.in_wait_queue() {
+        violation(task, format_args!("dequeued but already in a wait queue"));
+    }
+}
+
+/// Checks a task picked to run, `prev_rq_cpu` is the CPU it was accounted to.
+pub(crate) fn on_pick(task: &TaskInner, prev_rq_cpu: usize) {
+    if !task.is_ready() {
+        violation(task, format_args!("picked but not ready"));
+    }
+    if !task.is_idle() && prev_rq_cpu == NO_CPU {
+        violation(task, format_args!("picked but not in the ready queue"));
+    }
+}
+
+/// Checks a task before its wakeup timer is set.
+#[cfg(feature = "irq")]
+pub(crate) fn on_set_alarm(task: &TaskInner) {
+    if task.in_timer_list() {
+        violation(task, format_args!("wakeup timer set twice"));
+    }
+}
+
+/// Checks the wakeup timer of a task expired, with the deadline of the next
+/// timer of the list.
+#[cfg(feature = "irq")]
+pub(crate) fn on_expire(
+    task: &TaskInner,
+    deadline: TimeValue,
+    next: Option<TimeValue>,
+    now: TimeValue,
+) {
+    if deadline > now || next.is_some_and(|next| next < deadline) {
+        violation(
+            task,
+            format_args!(
+                "timer expired out of order: deadline={:?}, next={:?}, now={:?}",
+                deadline, next, now
+            ),
+        );
+    }
+    if !task.in_timer_list() {
+        violation(task, format_args!("cancelled wakeup timer expired"));
+    }
+}
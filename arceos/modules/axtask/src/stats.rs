@@ -0,0 +1,173 @@
+//! Per-CPU run queue statistics and scheduler tracepoints.
+//!
+//! All CPUs share one run queue for now, so a ready task is accounted to the
+//! CPU that enqueued it. A CPU picking a task enqueued by another CPU steals
+//! it, and a task picked by another CPU than the one it last ran on migrates.
+//!
+//! With the `bpf` feature, the events also run the programs attached to the
+//! [`axbpf::HOOK_SCHED_ENQUEUE`], [`axbpf::HOOK_SCHED_DEQUEUE`] and
+//! [`axbpf::HOOK_SCHED_PICK`] tracepoints.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axcounter::Gauge;
+
+use crate::AxTaskRef;
+
+/// No CPU, for a task that never ran or is not ready.
+pub(crate) const NO_CPU: usize = usize::MAX;
+
+/// Statistics of the run queue of a CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunQueueStats {
+    /// Number of ready tasks enqueued by the CPU and not picked yet.
+    pub nr_ready: usize,
+    /// Number of tasks enqueued, i.e. spawned, woken up or preempted.
+    pub nr_enqueued: u64,
+    /// Number of tasks that blocked, slept or exited.
+    pub nr_dequeued: u64,
+    /// Number of ready tasks picked to run, excluding the idle task.
+    pub nr_picked: u64,
+    /// Number of picked tasks enqueued by another CPU.
+    pub nr_steals: u64,
+    /// Number of picked tasks that last ran on another CPU.
+    pub nr_migrations: u64,
+    /// Number of context switches.
+    pub nr_switches: u64,
+}
+
+struct CpuStats {
+    nr_ready: AtomicUsize,
+    nr_enqueued: AtomicU64,
+    nr_dequeued: AtomicU64,
+    nr_picked: AtomicU64,
+    nr_steals: AtomicU64,
+    nr_migrations: AtomicU64,
+    nr_switches: AtomicU64,
+}
+
+impl CpuStats {
+    const fn new() -> Self {
+        Self {
+            nr_ready: AtomicUsize::new(0),
+            nr_enqueued: AtomicU64::new(0),
+            nr_dequeued: AtomicU64::new(0),
+            nr_picked: AtomicU64::new(0),
+            nr_steals: AtomicU64::new(0),
+            nr_migrations: AtomicU64::new(0),
+            nr_switches: AtomicU64::new(0),
+        }
+    }
+}
+
+static STATS: [CpuStats; axconfig::SMP] = [const { CpuStats::new() }; axconfig::SMP];
+
+/// The ready tasks of all the CPUs, read at once rather than summing the
+/// `nr_ready` of the CPUs while they change.
+static NR_READY: Gauge = Gauge::new();
+
+/// Returns the number of the ready tasks, of all the CPUs.
+pub(crate) fn nr_ready() -> usize {
+    NR_READY.get()
+}
+
+/// Returns the run queue statistics of the CPU `cpu_id`, or `None` if there
+/// is no such CPU.
+pub fn run_queue_stats(cpu_id: usize) -> Option<RunQueueStats> {
+    let s = STATS.get(cpu_id)?;
+    Some(RunQueueStats {
+        nr_ready: s.nr_ready.load(Ordering::Relaxed),
+        nr_enqueued: s.nr_enqueued.load(Ordering::Relaxed),
+        nr_dequeued: s.nr_dequeued.load(Ordering::Relaxed),
+        nr_picked: s.nr_picked.load(Ordering::Relaxed),
+        nr_steals: s.nr_steals.load(Ordering::Relaxed),
+        nr_migrations: s.nr_migrations.load(Ordering::Relaxed),
+        nr_switches: s.nr_switches.load(Ordering::Relaxed),
+    })
+}
+
+fn this_cpu() -> (usize, &'static CpuStats) {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    (cpu_id, &STATS[cpu_id])
+}
+
+// The following ones are called with the run queue locked.
+
+pub(crate) fn on_enqueue(task: &AxTaskRef) {
+    let (cpu_id, stats) = this_cpu();
+    let _prev_rq_cpu = task.set_rq_cpu(cpu_id);
+    #[cfg(feature = "sched_debug")]
+    crate::sched_debug::on_enqueue(task, _prev_rq_cpu);
+    stats.nr_enqueued.fetch_add(1, Ordering::Relaxed);
+    let nr_ready = stats.nr_ready.fetch_add(1, Ordering::Relaxed) + 1;
+    NR_READY.inc();
+    tracepoint(Event::Enqueue, cpu_id, task, nr_ready);
+}
+
+pub(crate) fn on_dequeue(task: &AxTaskRef) {
+    #[cfg(feature = "sched_debug")]
+    crate::sched_debug::on_dequeue(task);
+    let (cpu_id, stats) = this_cpu();
+    stats.nr_dequeued.fetch_add(1, Ordering::Relaxed);
+    let nr_ready = stats.nr_ready.load(Ordering::Relaxed);
+    tracepoint(Event::Dequeue, cpu_id, task, nr_ready);
+}
+
+pub(crate) fn on_pick(task: &AxTaskRef) {
+    let (cpu_id, stats) = this_cpu();
+    let rq_cpu = task.set_rq_cpu(NO_CPU);
+    #[cfg(feature = "sched_debug")]
+    crate::sched_debug::on_pick(task, rq_cpu);
+    if rq_cpu != NO_CPU {
+        stats.nr_picked.fetch_add(1, Ordering::Relaxed);
+        STATS[rq_cpu].nr_ready.fetch_sub(1, Ordering::Relaxed);
+        NR_READY.dec();
+        if rq_cpu != cpu_id {
+            stats.nr_steals.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    let nr_ready = stats.nr_ready.load(Ordering::Relaxed);
+    tracepoint(Event::Pick, cpu_id, task, nr_ready);
+    let last_cpu = task.set_cpu(cpu_id);
+    if last_cpu != NO_CPU && last_cpu != cpu_id {
+        stats.nr_migrations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn on_switch() {
+    this_cpu().1.nr_switches.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy)]
+enum Event {
+    Enqueue,
+    Dequeue,
+    Pick,
+}
+
+#[cfg(feature = "bpf")]
+fn tracepoint(event: Event, cpu_id: usize, task: &AxTaskRef, nr_ready: usize) {
+    let point = match event {
+        Event::Enqueue => axbpf::HOOK_SCHED_ENQUEUE,
+        Event::Dequeue => axbpf::HOOK_SCHED_DEQUEUE,
+        Event::Pick => axbpf::HOOK_SCHED_PICK,
+    };
+    let mut data = axbpf::SchedData {
+        cpu: cpu_id as u32,
+        task_cpu: task.cpu() as u32,
+        task_id: task.id().as_u64(),
+        nr_ready: nr_ready as u64,
+        time_ns: axhal::time::monotonic_time_nanos(),
+        ..axbpf::SchedData::default()
+    };
+    #[cfg(feature = "trace")]
+    if let Some(span) = task.trace_span() {
+        data.trace_id = [(span.trace_id >> 64) as u64, span.trace_id as u64];
+        data.span_id = span.span_id;
+    }
+    axbpf::run_hook(point, data.as_bytes_mut());
+}
+
+#[cfg(not(feature = "bpf"))]
+#[inline(always)]
+fn tracepoint(_event: Event, _cpu_id: usize, _task: &AxTaskRef, _nr_ready: usize) {}
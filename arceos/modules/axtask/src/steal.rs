@@ -0,0 +1,58 @@
+//! Steal time accounting, with the `steal-time` feature.
+//!
+//! The ticks are charged to the task they interrupt, in its time slice, its
+//! virtual runtime and the CPU time of its job. Under a hypervisor, the
+//! vCPU may not have run for most of the tick: the host ran something else,
+//! as reported by [`axhal::steal`]. The steal time of a CPU since its
+//! previous tick is added up, and a tick is stolen when a full tick period
+//! of it is pending: it is then charged to no task nor job, and counted in
+//! [`stolen_ticks`] and [`TaskInner::stolen_ticks`] instead, so that the
+//! utilization of the tasks and the scheduling decisions do not count the
+//! preemptions of the host.
+//!
+//! [`TaskInner::stolen_ticks`]: crate::TaskInner::stolen_ticks
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const TICK_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+struct CpuSteal {
+    /// The steal time read on the previous tick.
+    last: AtomicU64,
+    /// The steal time not accounted as stolen ticks yet.
+    pending: AtomicU64,
+    stolen_ticks: AtomicU64,
+}
+
+static CPUS: [CpuSteal; axconfig::SMP] = [const {
+    CpuSteal {
+        last: AtomicU64::new(0),
+        pending: AtomicU64::new(0),
+        stolen_ticks: AtomicU64::new(0),
+    }
+}; axconfig::SMP];
+
+/// Returns the number of the ticks stolen on the CPU `cpu_id`, or `None` if
+/// there is no such CPU.
+pub fn stolen_ticks(cpu_id: usize) -> Option<u64> {
+    CPUS.get(cpu_id)
+        .map(|c| c.stolen_ticks.load(Ordering::Relaxed))
+}
+
+/// Accounts the steal time on a timer tick of the current CPU, returns
+/// whether the tick is stolen.
+pub(crate) fn tick_stolen() -> bool {
+    let cpu = &CPUS[axhal::cpu::this_cpu_id()];
+    let now = axhal::steal::this_cpu_steal_nanos();
+    let last = cpu.last.swap(now, Ordering::Relaxed);
+    // Only this CPU updates its counters, on its ticks.
+    let pending = cpu.pending.load(Ordering::Relaxed) + now.saturating_sub(last);
+    let stolen = pending >= TICK_NANOS;
+    if stolen {
+        cpu.stolen_ticks.fetch_add(1, Ordering::Relaxed);
+        cpu.pending.store(pending - TICK_NANOS, Ordering::Relaxed);
+    } else {
+        cpu.pending.store(pending, Ordering::Relaxed);
+    }
+    stolen
+}
@@ -1,24 +1,36 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
 use axhal::arch::TaskContext;
+use kspin::SpinNoIrq;
 use memory_addr::{align_up_4k, VirtAddr};
 
+use crate::sched::SchedEntity;
 use crate::task_ext::AxTaskExt;
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxRunQueue, AxTask, AxTaskRef, CancellationToken, JobRef, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
 
+/// The span of a distributed trace a task works for, tagging its scheduler
+/// tracepoints, see [`TaskInner::set_trace_span`].
+#[cfg(feature = "trace")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TraceSpan {
+    /// The ID of the trace.
+    pub trace_id: u128,
+    /// The ID of the span in the trace.
+    pub span_id: u64,
+    /// The W3C trace flags, bit 0 set if the trace is sampled.
+    pub flags: u8,
+}
+
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -29,12 +41,33 @@ pub(crate) enum TaskState {
     Exited = 4,
 }
 
+/// The filesystem context of a task, inherited by the tasks it spawns.
+#[derive(Debug, Clone)]
+pub struct FsContext {
+    /// The current directory, an absolute path ending with `/`.
+    pub cwd: String,
+    /// The file mode creation mask.
+    pub umask: u32,
+}
+
+impl Default for FsContext {
+    fn default() -> Self {
+        Self {
+            cwd: String::from("/"),
+            umask: 0o022,
+        }
+    }
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
-    name: String,
+    name: SpinNoIrq<String>,
+    tag: SpinNoIrq<Option<String>>,
     is_idle: bool,
     is_init: bool,
+    /// Kernel work not bound to a CPU, kept off the isolated CPUs.
+    is_housekeeping: bool,
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
@@ -43,6 +76,18 @@ pub struct TaskInner {
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// The CPU the task last ran on.
+    cpu: AtomicUsize,
+    /// The CPU the ready task is accounted to.
+    rq_cpu: AtomicUsize,
+    /// Timer ticks the task ran for since it last woke up.
+    #[cfg(feature = "sched_boost")]
+    run_ticks: AtomicUsize,
+
+    /// The state of the task in the scheduling policies.
+    sched: SchedEntity,
+    job: Option<JobRef>,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
@@ -50,10 +95,30 @@ pub struct TaskInner {
 
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
+    cancel_requested: AtomicBool,
+    cancel_token: CancellationToken,
+    /// The mask of the cache colors of the pages allocated by the task.
+    #[cfg(feature = "page-color")]
+    page_colors: AtomicU64,
+    /// The quota the heap allocations of the task are charged to.
+    #[cfg(feature = "heap-quota")]
+    heap_quota: Option<Arc<axalloc::quota::HeapQuota>>,
+    #[cfg(feature = "pmu")]
+    pmu: crate::pmu::TaskPmu,
+    #[cfg(feature = "steal-time")]
+    stolen_ticks: AtomicU64,
+    #[cfg(feature = "sched_smt")]
+    smt_exclusive: AtomicBool,
+    /// Whether the task was left to an idle core on its last pick.
+    #[cfg(feature = "sched_smt")]
+    spread_deferred: AtomicBool,
+    #[cfg(feature = "trace")]
+    trace_span: SpinNoIrq<Option<TraceSpan>>,
 
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
+    fs_context: SpinNoIrq<FsContext>,
 
     #[cfg(feature = "tls")]
     tls: TlsArea,
@@ -105,9 +170,6 @@ impl TaskInner {
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
-        if t.name == "idle" {
-            t.is_idle = true;
-        }
         t
     }
 
@@ -116,14 +178,58 @@ impl TaskInner {
         self.id
     }
 
+    /// Returns the filesystem context of the task: its current directory and
+    /// file mode creation mask.
+    pub fn fs_context(&self) -> &SpinNoIrq<FsContext> {
+        &self.fs_context
+    }
+
     /// Gets the name of the task.
-    pub fn name(&self) -> &str {
-        self.name.as_str()
+    pub fn name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    /// Renames the task, returns the name given to it.
+    ///
+    /// The names are unique: if another task has the same name, a suffix
+    /// `-2`, `-3`... is added to it.
+    pub fn set_name(&self, name: String) -> String {
+        let mut curr = self.name.lock();
+        crate::registry::release_name(&curr);
+        *curr = crate::registry::reserve_name(name);
+        curr.clone()
+    }
+
+    /// Gets the tag of the task, a free text describing it.
+    pub fn tag(&self) -> Option<String> {
+        self.tag.lock().clone()
+    }
+
+    /// Sets or clears the tag of the task.
+    pub fn set_tag(&self, tag: Option<String>) {
+        *self.tag.lock() = tag;
     }
 
     /// Get a combined string of the task ID and name.
     pub fn id_name(&self) -> alloc::string::String {
-        alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
+        alloc::format!("Task({}, {:?})", self.id.as_u64(), *self.name.lock())
+    }
+
+    /// Formats the task ID and name like [`TaskInner::id_name`], without
+    /// allocating nor waiting for a renaming, e.g. in a panic.
+    pub fn id_name_fmt(&self) -> impl fmt::Display + '_ {
+        IdName(self)
+    }
+
+    /// Gets the state of the task: `"running"`, `"ready"`, `"blocked"` or
+    /// `"exited"`.
+    pub fn state_name(&self) -> &'static str {
+        match self.state() {
+            TaskState::Running => "running",
+            TaskState::Ready => "ready",
+            TaskState::Blocked => "blocked",
+            TaskState::Exited => "exited",
+        }
     }
 
     /// Wait for the task to exit, and return the exit code.
@@ -135,6 +241,124 @@ impl TaskInner {
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Requests the task to stop what it is doing, e.g. on Ctrl-C.
+    ///
+    /// The task is not interrupted: it polls the request with
+    /// [`TaskInner::take_cancel_request`], and cleans up before returning.
+    /// Its [`cancel_token`](TaskInner::cancel_token) is cancelled, which
+    /// wakes up its cancellable waits.
+    pub fn request_cancel(&self) {
+        self.cancel_requested.store(true, Ordering::Release);
+        self.cancel_token.cancel();
+    }
+
+    /// Gets the token of the task, cancelled for good on the first
+    /// [`request_cancel`](TaskInner::request_cancel).
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel_token
+    }
+
+    /// Whether a cancellation was requested and not taken yet.
+    pub fn is_cancel_requested(&self) -> bool {
+        self.cancel_requested.load(Ordering::Acquire)
+    }
+
+    /// Clears the cancellation request, returns whether there was one.
+    pub fn take_cancel_request(&self) -> bool {
+        self.cancel_requested.swap(false, Ordering::AcqRel)
+    }
+
+    /// Gets the job of the task, the one of the task which spawned it by
+    /// default.
+    pub fn job(&self) -> Option<&JobRef> {
+        self.job.as_ref()
+    }
+
+    /// Sets the job of the task, before spawning it.
+    pub fn set_job(&mut self, job: Option<JobRef>) {
+        self.job = job;
+    }
+
+    /// Gets the mask of the cache colors of the pages allocated by the task,
+    /// 0 for any color.
+    #[cfg(feature = "page-color")]
+    pub fn page_colors(&self) -> u64 {
+        self.page_colors.load(Ordering::Relaxed)
+    }
+
+    /// Sets the mask of the cache colors of the pages allocated by the task
+    /// from now on, inherited by the tasks it spawns.
+    ///
+    /// The tasks given disjoint masks do not evict the cache lines of each
+    /// other from the last level cache.
+    #[cfg(feature = "page-color")]
+    pub fn set_page_colors(&self, mask: u64) {
+        self.page_colors.store(mask, Ordering::Relaxed);
+    }
+
+    /// Gets the quota the heap allocations of the task are charged to, see
+    /// [`axalloc::quota`].
+    #[cfg(feature = "heap-quota")]
+    pub fn heap_quota(&self) -> Option<&Arc<axalloc::quota::HeapQuota>> {
+        self.heap_quota.as_ref()
+    }
+
+    /// Sets the quota of the heap allocations of the task, before spawning
+    /// it. By default, it is the one of the task which spawned it.
+    #[cfg(feature = "heap-quota")]
+    pub fn set_heap_quota(&mut self, quota: Option<Arc<axalloc::quota::HeapQuota>>) {
+        self.heap_quota = quota;
+    }
+
+    /// Returns the counts of the hardware performance counters while the
+    /// task ran, see [`axhal::pmu`].
+    ///
+    /// For the task running on another CPU, they are the counts until it was
+    /// last switched in.
+    #[cfg(feature = "pmu")]
+    pub fn pmu_counters(&self) -> axhal::pmu::PmuCounters {
+        self.pmu.counters()
+    }
+
+    /// Returns the number of the timer ticks stolen by the hypervisor while
+    /// the task ran, not charged to it, see [`axhal::steal`].
+    #[cfg(feature = "steal-time")]
+    pub fn stolen_ticks(&self) -> u64 {
+        self.stolen_ticks.load(Ordering::Relaxed)
+    }
+
+    /// Makes the task keep its physical core to itself while it runs, the
+    /// SMT siblings of its CPU running no other task, or stops it.
+    ///
+    /// It takes effect on the next switch to the task.
+    #[cfg(feature = "sched_smt")]
+    pub fn set_smt_exclusive(&self, exclusive: bool) {
+        self.smt_exclusive.store(exclusive, Ordering::Relaxed);
+    }
+
+    /// Whether the task keeps its physical core to itself, see
+    /// [`TaskInner::set_smt_exclusive`].
+    #[cfg(feature = "sched_smt")]
+    pub fn is_smt_exclusive(&self) -> bool {
+        self.smt_exclusive.load(Ordering::Relaxed)
+    }
+
+    /// Returns the span of a distributed trace the task works for, if any.
+    #[cfg(feature = "trace")]
+    pub fn trace_span(&self) -> Option<TraceSpan> {
+        *self.trace_span.lock()
+    }
+
+    /// Sets the span of a distributed trace the task works for, returning
+    /// the previous one.
+    ///
+    /// The scheduler tracepoints of the task are tagged with it. The tasks
+    /// spawned start with the one of their parent.
+    #[cfg(feature = "trace")]
+    pub fn set_trace_span(&self, span: Option<TraceSpan>) -> Option<TraceSpan> {
+        core::mem::replace(&mut *self.trace_span.lock(), span)
+    }
+
     /// Returns the pointer to the user-defined task extended data.
     ///
     /// # Safety
@@ -166,23 +390,55 @@ impl TaskInner {
     fn new_common(id: TaskId, name: String) -> Self {
         Self {
             id,
-            name,
-            is_idle: false,
+            is_idle: name == "idle",
+            name: SpinNoIrq::new(crate::registry::reserve_name(name)),
+            tag: SpinNoIrq::new(None),
             is_init: false,
+            is_housekeeping: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            cpu: AtomicUsize::new(crate::stats::NO_CPU),
+            rq_cpu: AtomicUsize::new(crate::stats::NO_CPU),
+            #[cfg(feature = "sched_boost")]
+            run_ticks: AtomicUsize::new(0),
+            sched: SchedEntity::new(),
+            job: crate::current_may_uninit().and_then(|curr| curr.job().cloned()),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            cancel_requested: AtomicBool::new(false),
+            cancel_token: CancellationToken::new(),
+            #[cfg(feature = "page-color")]
+            page_colors: AtomicU64::new(
+                crate::current_may_uninit().map_or(0, |curr| curr.page_colors()),
+            ),
+            #[cfg(feature = "heap-quota")]
+            heap_quota: crate::current_may_uninit().and_then(|curr| curr.heap_quota().cloned()),
+            #[cfg(feature = "pmu")]
+            pmu: crate::pmu::TaskPmu::new(),
+            #[cfg(feature = "steal-time")]
+            stolen_ticks: AtomicU64::new(0),
+            #[cfg(feature = "sched_smt")]
+            smt_exclusive: AtomicBool::new(false),
+            #[cfg(feature = "sched_smt")]
+            spread_deferred: AtomicBool::new(false),
+            #[cfg(feature = "trace")]
+            trace_span: SpinNoIrq::new(
+                crate::current_may_uninit().and_then(|curr| curr.trace_span()),
+            ),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
+            fs_context: SpinNoIrq::new(
+                crate::current_may_uninit()
+                    .map_or_else(FsContext::default, |curr| curr.fs_context().lock().clone()),
+            ),
             #[cfg(feature = "tls")]
             tls: TlsArea::alloc(),
         }
@@ -199,14 +455,16 @@ impl TaskInner {
     pub(crate) fn new_init(name: String) -> Self {
         let mut t = Self::new_common(TaskId::new(), name);
         t.is_init = true;
-        if t.name == "idle" {
-            t.is_idle = true;
-        }
         t
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let task = Arc::new(self);
+        crate::registry::register(&task);
+        if let Some(job) = &task.job {
+            job.add_member(&task);
+        }
+        task
     }
 
     #[inline]
@@ -216,6 +474,12 @@ impl TaskInner {
 
     #[inline]
     pub(crate) fn set_state(&self, state: TaskState) {
+        #[cfg(feature = "sched_debug")]
+        {
+            let from = self.state.swap(state as u8, Ordering::AcqRel).into();
+            crate::sched_debug::check_transition(self, from, state);
+        }
+        #[cfg(not(feature = "sched_debug"))]
         self.state.store(state as u8, Ordering::Release)
     }
 
@@ -244,6 +508,22 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    #[allow(dead_code)]
+    pub(crate) const fn is_housekeeping(&self) -> bool {
+        self.is_housekeeping
+    }
+
+    #[inline]
+    pub(crate) fn set_housekeeping(&mut self) {
+        self.is_housekeeping = true;
+    }
+
+    #[inline]
+    pub(crate) const fn sched_entity(&self) -> &SchedEntity {
+        &self.sched
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -266,6 +546,63 @@ impl TaskInner {
         self.in_timer_list.store(in_timer_list, Ordering::Release);
     }
 
+    #[inline]
+    #[cfg(feature = "bpf")]
+    pub(crate) fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Relaxed)
+    }
+
+    /// Sets the CPU the task runs on, returns the previous one.
+    #[inline]
+    pub(crate) fn set_cpu(&self, cpu_id: usize) -> usize {
+        self.cpu.swap(cpu_id, Ordering::Relaxed)
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_debug")]
+    pub(crate) fn rq_cpu(&self) -> usize {
+        self.rq_cpu.load(Ordering::Relaxed)
+    }
+
+    /// Sets the CPU the task is accounted to, returns the previous one.
+    #[inline]
+    pub(crate) fn set_rq_cpu(&self, cpu_id: usize) -> usize {
+        self.rq_cpu.swap(cpu_id, Ordering::Relaxed)
+    }
+
+    #[inline]
+    #[cfg(feature = "pmu")]
+    pub(crate) fn pmu(&self) -> &crate::pmu::TaskPmu {
+        &self.pmu
+    }
+
+    #[inline]
+    #[cfg(feature = "steal-time")]
+    pub(crate) fn add_stolen_tick(&self) {
+        self.stolen_ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sets whether the task was left to an idle core, returns the previous
+    /// value.
+    #[inline]
+    #[cfg(feature = "sched_smt")]
+    pub(crate) fn set_spread_deferred(&self, deferred: bool) -> bool {
+        self.spread_deferred.swap(deferred, Ordering::Relaxed)
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_boost")]
+    pub(crate) fn add_run_tick(&self) {
+        self.run_ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Resets the timer ticks the task ran for, returns them.
+    #[inline]
+    #[cfg(feature = "sched_boost")]
+    pub(crate) fn take_run_ticks(&self) -> usize {
+        self.run_ticks.swap(0, Ordering::Relaxed)
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn set_preempt_pending(&self, pending: bool) {
@@ -334,7 +671,7 @@ impl fmt::Debug for TaskInner {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("TaskInner")
             .field("id", &self.id)
-            .field("name", &self.name)
+            .field("name", &*self.name.lock())
             .field("state", &self.state())
             .finish()
     }
@@ -343,6 +680,19 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        crate::registry::unregister(self.id);
+        crate::registry::release_name(self.name.get_mut());
+    }
+}
+
+struct IdName<'a>(&'a TaskInner);
+
+impl fmt::Display for IdName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.name.try_lock() {
+            Some(name) => write!(f, "Task({}, {:?})", self.0.id.as_u64(), *name),
+            None => write!(f, "Task({}, <renaming>)", self.0.id.as_u64()),
+        }
     }
 }
 
@@ -128,3 +128,259 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_fs_context_inherit() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let old = current().fs_context().lock().clone();
+    current().fs_context().lock().cwd = "/tmp/".into();
+    let task = axtask::spawn_raw(
+        || {
+            let mut ctx = current().fs_context().lock();
+            assert_eq!(ctx.cwd, "/tmp/");
+            ctx.cwd = "/".into();
+            ctx.umask = 0o077;
+        },
+        "fs".into(),
+        0x1000,
+    );
+    task.join();
+    let ctx = current().fs_context().lock().clone();
+    assert_eq!(ctx.cwd, "/tmp/");
+    assert_eq!(ctx.umask, old.umask);
+    *current().fs_context().lock() = old;
+}
+
+#[test]
+fn test_run_queue_stats() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let before = crate::run_queue_stats(0).unwrap();
+    let task = axtask::spawn_raw(axtask::yield_now, "stats".into(), 0x1000);
+    task.join();
+    let after = crate::run_queue_stats(0).unwrap();
+    // Spawned, and `yield_now` puts it back once.
+    assert!(after.nr_enqueued >= before.nr_enqueued + 2);
+    assert!(after.nr_picked >= before.nr_picked + 2);
+    assert!(after.nr_dequeued > before.nr_dequeued);
+    assert!(after.nr_switches > before.nr_switches);
+    assert_eq!(after.nr_steals, 0);
+    assert_eq!(after.nr_migrations, 0);
+    assert!(crate::run_queue_stats(axconfig::SMP).is_none());
+}
+
+#[test]
+fn test_task_names() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let a = axtask::spawn_raw(axtask::yield_now, "worker".into(), 0x1000);
+    let b = axtask::spawn_raw(axtask::yield_now, "worker".into(), 0x1000);
+    assert_eq!(a.name(), "worker");
+    assert_eq!(b.name(), "worker-2");
+    assert_eq!(b.set_name("wörker".into()), "wörker");
+    b.set_tag(Some("io".into()));
+    assert_eq!(b.tag().as_deref(), Some("io"));
+    let listed = crate::tasks();
+    assert!(listed.iter().any(|t| t.name() == "wörker"));
+    assert!(listed
+        .windows(2)
+        .all(|w| w[0].id().as_u64() < w[1].id().as_u64()));
+    drop(listed);
+    a.join();
+    b.join();
+}
+
+#[test]
+fn test_sched_policy_switch() {
+    use std::collections::VecDeque;
+
+    use crate::{AxTaskRef, SchedPolicy};
+
+    /// Runs the last ready task first.
+    struct Lifo(VecDeque<AxTaskRef>);
+
+    impl SchedPolicy for Lifo {
+        fn add_task(&mut self, task: AxTaskRef) {
+            self.0.push_front(task);
+        }
+        fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+            self.0.pop_front()
+        }
+        fn put_prev_task(&mut self, prev: AxTaskRef, _preempt: bool) {
+            self.0.push_back(prev);
+        }
+        fn task_tick(&mut self, _current: &AxTaskRef) -> bool {
+            false
+        }
+        fn set_priority(&mut self, _current: &AxTaskRef, _prio: isize) -> bool {
+            false
+        }
+    }
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    fn new_lifo() -> Box<dyn SchedPolicy> {
+        Box::new(Lifo(VecDeque::new()))
+    }
+
+    assert!(crate::register_sched_policy("lifo", new_lifo));
+    assert!(!crate::register_sched_policy("lifo", new_lifo));
+    assert!(crate::sched_policies().contains(&"lifo"));
+    assert!(!crate::set_sched_policy("nope"));
+    assert_eq!(crate::sched_policy(), "fifo");
+
+    assert!(crate::set_sched_policy("lifo"));
+    assert_eq!(crate::sched_policy(), "lifo");
+
+    const NUM_TASKS: usize = 3;
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|i| {
+            axtask::spawn_raw(
+                move || ORDER.lock().unwrap().push(i),
+                format!("L{}", i),
+                0x1000,
+            )
+        })
+        .collect();
+    for task in tasks {
+        task.join();
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [2, 1, 0]);
+
+    assert!(crate::set_sched_policy("fifo"));
+    assert_eq!(crate::sched_policy(), "fifo");
+}
+
+#[test]
+fn test_job_termination() {
+    use crate::{Job, JobRef};
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    fn wait_cancel() {
+        while !current().take_cancel_request() {
+            axtask::yield_now();
+        }
+    }
+
+    // The exit of the root tears down the job.
+    let job = Job::new("service".into());
+    job.set_kill_on_root_exit(true);
+    let job2 = job.clone();
+    job.spawn(
+        move || {
+            let child = axtask::spawn(wait_cancel);
+            assert!(JobRef::ptr_eq(child.job().unwrap(), &job2));
+            axtask::exit(3);
+        },
+        "root".into(),
+    );
+    assert_eq!(job.wait(), 3);
+    assert!(job.is_terminated());
+    let usage = job.usage();
+    assert_eq!(usage.live_tasks, 0);
+    assert_eq!(usage.spawned_tasks, 2);
+    assert_eq!(usage.exited_tasks, 2);
+
+    // Terminating a job terminates the nested ones.
+    let parent = Job::new("parent".into());
+    parent.spawn(
+        || {
+            let nested = Job::new("nested".into());
+            nested.spawn(wait_cancel, "nested".into());
+            wait_cancel();
+        },
+        "parent".into(),
+    );
+    while parent.usage().live_tasks < 2 {
+        axtask::yield_now();
+    }
+    assert_eq!(parent.members().len(), 1);
+    parent.terminate(-1);
+    assert_eq!(parent.wait(), -1);
+    assert_eq!(parent.usage().exited_tasks, 2);
+    assert!(crate::current_job().is_none());
+}
+
+#[test]
+fn test_spawn_blocking() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::pool::{spawn_blocking, ThreadPool};
+
+    const NUM_WORKS: usize = 16;
+    let handles: Vec<_> = (0..NUM_WORKS)
+        .map(|i| {
+            spawn_blocking(move || {
+                axtask::yield_now();
+                (0..=i as u64).sum::<u64>()
+            })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join(), (i * (i + 1) / 2) as u64);
+    }
+
+    // The workers are reused, up to the size of the pool.
+    static POOL: ThreadPool = ThreadPool::new("test-pool", 2);
+    for _ in 0..3 {
+        let handles: Vec<_> = (0..8).map(|i| POOL.spawn(move || i * 2)).collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join()).collect();
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(POOL.workers() <= POOL.size());
+    }
+    assert_eq!(POOL.pending(), 0);
+
+    let handle = POOL.spawn(|| current().job().is_none());
+    assert!(handle.join());
+}
+
+#[test]
+fn test_cancellation_token() {
+    use crate::{CancellationToken, Cancelled};
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+
+    // A wait is woken up by the cancellation of the parent of its token.
+    let parent = CancellationToken::new();
+    let child = parent.child_token();
+    let token = child.clone();
+    let task = axtask::spawn(move || {
+        assert_eq!(WQ.wait_until_cancellable(&token, || false), Err(Cancelled));
+    });
+    axtask::yield_now();
+    assert!(!child.is_cancelled());
+    parent.cancel();
+    assert_eq!(task.join(), Some(0));
+    assert!(child.is_cancelled());
+    assert!(parent.child_token().is_cancelled());
+    assert_eq!(child.check(), Err(Cancelled));
+
+    // The guard cancels when dropped, unless disarmed.
+    let token = CancellationToken::new();
+    drop(token.clone().drop_guard());
+    assert!(token.is_cancelled());
+    let token = CancellationToken::new();
+    token.clone().drop_guard().disarm();
+    assert!(!token.is_cancelled());
+
+    // The token of a task is cancelled by its cancellation request.
+    let task = axtask::spawn(|| {
+        let token = current().cancel_token().clone();
+        assert_eq!(WQ.wait_until_cancellable(&token, || false), Err(Cancelled));
+    });
+    axtask::yield_now();
+    task.request_cancel();
+    assert_eq!(task.join(), Some(0));
+}
@@ -1,4 +1,9 @@
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+#[cfg(feature = "virtual-time")]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use axhal::time::wall_time;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
@@ -7,34 +12,124 @@ use timer_list::{TimeValue, TimerEvent, TimerList};
 use crate::{AxTaskRef, RUN_QUEUE};
 
 // TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskTimerEvent>>> = LazyInit::new();
+
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The CPUs online, and those of them waiting in the idle task. The virtual
+/// clock only jumps when all of them are idle: the tasks of a busy CPU would
+/// see their clock race ahead otherwise.
+#[cfg(feature = "virtual-time")]
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "virtual-time")]
+static IDLE_CPUS: AtomicUsize = AtomicUsize::new(0);
 
-struct TaskWakeupEvent(AxTaskRef);
+/// The identifier of a timer callback set by [`set_timer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+enum TaskTimerEvent {
+    Wakeup(AxTaskRef),
+    Callback(TimerId, Box<dyn FnOnce(TimeValue) + Send>),
+}
 
-impl TimerEvent for TaskWakeupEvent {
-    fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+impl TimerEvent for TaskTimerEvent {
+    fn callback(self, now: TimeValue) {
+        match self {
+            Self::Wakeup(task) => {
+                let mut rq = RUN_QUEUE.lock();
+                task.set_in_timer_list(false);
+                rq.unblock_task(task, true);
+            }
+            Self::Callback(_, callback) => callback(now),
+        }
     }
 }
 
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
     let mut timers = TIMER_LIST.lock();
+    #[cfg(feature = "sched_debug")]
+    crate::sched_debug::on_set_alarm(&task);
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    timers.set(deadline, TaskTimerEvent::Wakeup(task));
 }
 
 pub fn cancel_alarm(task: &AxTaskRef) {
     let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    timers.cancel(|e| matches!(e, TaskTimerEvent::Wakeup(t) if Arc::ptr_eq(t, task)));
+}
+
+/// Calls `callback` with the current time once the wall time reaches
+/// `deadline`.
+///
+/// The callback runs in the timer interrupt handler (or in the idle task
+/// with the `virtual-time` feature), so it must not block. It can set other
+/// timers, e.g. to re-arm a periodic one.
+pub fn set_timer<F>(deadline: TimeValue, callback: F) -> TimerId
+where
+    F: FnOnce(TimeValue) + Send + 'static,
+{
+    let id = TimerId(NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed));
+    TIMER_LIST
+        .lock()
+        .set(deadline, TaskTimerEvent::Callback(id, Box::new(callback)));
+    id
+}
+
+/// Cancels the timer callback `id` if it has not run yet.
+pub fn cancel_timer(id: TimerId) {
+    TIMER_LIST
+        .lock()
+        .cancel(|e| matches!(e, TaskTimerEvent::Callback(t, _) if *t == id));
+}
+
+/// Counts the current CPU as online, once its scheduler is initialized.
+#[cfg(feature = "virtual-time")]
+pub(crate) fn cpu_online() {
+    ONLINE_CPUS.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Counts the current CPU as idle until [`exit_idle`], returns whether all
+/// the CPUs online are idle now.
+#[cfg(feature = "virtual-time")]
+pub(crate) fn enter_idle() -> bool {
+    IDLE_CPUS.fetch_add(1, Ordering::AcqRel) + 1 == ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// Counts the current CPU as busy again, after [`enter_idle`].
+#[cfg(feature = "virtual-time")]
+pub(crate) fn exit_idle() {
+    IDLE_CPUS.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// Fast-forwards the virtual clock to the earliest timer deadline and fires
+/// the expired timers. Returns `false` if there is no timer.
+///
+/// Only the last CPU to become idle calls it, see [`enter_idle`].
+#[cfg(feature = "virtual-time")]
+pub fn advance_to_next_deadline() -> bool {
+    let Some(deadline) = TIMER_LIST.lock().next_deadline() else {
+        return false;
+    };
+    let epoch = TimeValue::from_nanos(axhal::time::epochoffset_nanos());
+    axhal::time::advance_virtual_time_to(deadline.saturating_sub(epoch));
+    check_events();
+    true
 }
 
 pub fn check_events() {
     loop {
         let now = wall_time();
-        let event = TIMER_LIST.lock().expire_one(now);
+        let event = {
+            let mut timers = TIMER_LIST.lock();
+            let event = timers.expire_one(now);
+            #[cfg(feature = "sched_debug")]
+            if let Some((deadline, TaskTimerEvent::Wakeup(task))) = &event {
+                crate::sched_debug::on_expire(task, *deadline, timers.next_deadline(), now);
+            }
+            event
+        };
         if let Some((_deadline, event)) = event {
             event.callback(now);
         } else {
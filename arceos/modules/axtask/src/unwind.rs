@@ -0,0 +1,64 @@
+//! Walks of the kernel stack by the frame pointers, the kernel being built
+//! with `-C force-frame-pointers=yes`.
+
+use core::mem::size_of;
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        /// The offset from the frame pointer of the frame record, the former
+        /// frame pointer and the return address.
+        const RECORD_OFFSET: isize = -2 * size_of::<usize>() as isize;
+
+        /// Returns the frame pointer of the calling function.
+        #[cfg(feature = "irq-guard")]
+        #[inline(always)]
+        pub(crate) fn current_fp() -> usize {
+            let fp;
+            unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+            fp
+        }
+    } else if #[cfg(target_arch = "x86_64")] {
+        const RECORD_OFFSET: isize = 0;
+
+        #[cfg(feature = "irq-guard")]
+        #[inline(always)]
+        pub(crate) fn current_fp() -> usize {
+            let fp;
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+            fp
+        }
+    } else if #[cfg(target_arch = "aarch64")] {
+        const RECORD_OFFSET: isize = 0;
+
+        #[cfg(feature = "irq-guard")]
+        #[inline(always)]
+        pub(crate) fn current_fp() -> usize {
+            let fp;
+            unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+            fp
+        }
+    }
+}
+
+/// Walks the frames from the frame pointer `fp`, the frame records being in
+/// `[low, stack_top)`. Fills `pcs` with the return addresses, innermost
+/// first, returns their number.
+pub(crate) fn walk(mut fp: usize, mut low: usize, stack_top: usize, pcs: &mut [usize]) -> usize {
+    let mut n = 0;
+    while n < pcs.len() && fp % size_of::<usize>() == 0 {
+        let record = fp.wrapping_add_signed(RECORD_OFFSET);
+        if record < low || record + 2 * size_of::<usize>() > stack_top {
+            break;
+        }
+        // SAFETY: the record is in the kernel stack.
+        let [prev_fp, ret] = unsafe { (record as *const [usize; 2]).read() };
+        if ret == 0 {
+            break;
+        }
+        pcs[n] = ret;
+        n += 1;
+        low = record + 2 * size_of::<usize>();
+        fp = prev_fp;
+    }
+    n
+}
@@ -2,7 +2,7 @@ use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use kspin::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::{AxRunQueue, AxTaskRef, CancellationToken, Cancelled, CurrentTask, RUN_QUEUE};
 
 /// A queue to store sleeping tasks.
 ///
@@ -94,6 +94,38 @@ impl WaitQueue {
         self.cancel_events(crate::current());
     }
 
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or `token` is cancelled.
+    ///
+    /// Returns [`Cancelled`] if the token was cancelled before the condition
+    /// became true.
+    pub fn wait_until_cancellable<F>(
+        &self,
+        token: &CancellationToken,
+        condition: F,
+    ) -> Result<(), Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let res = loop {
+            let mut rq = RUN_QUEUE.lock();
+            if condition() {
+                break Ok(());
+            } else if token.is_cancelled() {
+                break Err(Cancelled);
+            }
+            rq.block_current(|task| {
+                task.set_in_wait_queue(true);
+                token.add_waiter(task.clone());
+                self.queue.lock().push_back(task);
+            });
+            token.remove_waiter(curr.as_task_ref());
+        };
+        self.cancel_events(curr);
+        res
+    }
+
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
     #[cfg(feature = "irq")]
@@ -151,6 +183,47 @@ impl WaitQueue {
         timeout
     }
 
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, the given duration has elapsed, or `token` is
+    /// cancelled.
+    ///
+    /// Returns whether the duration elapsed, or [`Cancelled`] if the token was
+    /// cancelled before.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_cancellable<F>(
+        &self,
+        dur: core::time::Duration,
+        token: &CancellationToken,
+        condition: F,
+    ) -> Result<bool, Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        crate::timers::set_alarm_wakeup(deadline, curr.clone());
+
+        let mut res = Ok(true);
+        while axhal::time::wall_time() < deadline {
+            let mut rq = RUN_QUEUE.lock();
+            if condition() {
+                res = Ok(false);
+                break;
+            } else if token.is_cancelled() {
+                res = Err(Cancelled);
+                break;
+            }
+            rq.block_current(|task| {
+                task.set_in_wait_queue(true);
+                token.add_waiter(task.clone());
+                self.queue.lock().push_back(task);
+            });
+            token.remove_waiter(curr.as_task_ref());
+        }
+        self.cancel_events(curr);
+        res
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
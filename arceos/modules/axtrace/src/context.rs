@@ -0,0 +1,110 @@
+//! The [W3C trace context], carried by the `traceparent` header.
+//!
+//! ```text
+//! traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01
+//!              |  |                                |                |
+//!              |  trace-id: 16 bytes               parent-id: 8     flags
+//!              version
+//! ```
+//!
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
+
+use core::fmt;
+
+/// The name of the header carrying the trace context.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// The length of a `traceparent` value of version `00`.
+pub const TRACEPARENT_LEN: usize = 55;
+
+/// The trace flag of the sampled traces, whose spans are recorded.
+pub const FLAG_SAMPLED: u8 = 0x01;
+
+/// The trace context of a span: the trace it belongs to, and its ID.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TraceContext {
+    /// The ID of the trace, never 0.
+    pub trace_id: u128,
+    /// The ID of the span, never 0.
+    pub span_id: u64,
+    /// The trace flags, see [`FLAG_SAMPLED`].
+    pub flags: u8,
+}
+
+/// Parses the lowercase hexadecimal `hex`.
+fn parse_hex(hex: &[u8]) -> Option<u128> {
+    hex.iter().try_fold(0u128, |n, &c| {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            _ => return None,
+        };
+        Some(n << 4 | digit as u128)
+    })
+}
+
+impl TraceContext {
+    /// Parses a `traceparent` value.
+    ///
+    /// The values of the versions after `00` are read as the version `00`
+    /// ones they start with, as the specification asks.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim_matches([' ', '\t']).as_bytes();
+        if value.len() < TRACEPARENT_LEN {
+            return None;
+        }
+        let version = parse_hex(&value[..2])?;
+        let rest_ok = match version {
+            0 => value.len() == TRACEPARENT_LEN,
+            0xff => false,
+            _ => value.len() == TRACEPARENT_LEN || value[TRACEPARENT_LEN] == b'-',
+        };
+        if !rest_ok || value[2] != b'-' || value[35] != b'-' || value[52] != b'-' {
+            return None;
+        }
+        let trace_id = parse_hex(&value[3..35])?;
+        let span_id = parse_hex(&value[36..52])? as u64;
+        let flags = parse_hex(&value[53..55])? as u8;
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            flags,
+        })
+    }
+
+    /// Extracts the trace context from the head of an HTTP/1.x request,
+    /// the request line and the header lines, if it has a valid one.
+    pub fn extract_http(head: &str) -> Option<Self> {
+        head.split("\r\n")
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(TRACEPARENT))
+            .and_then(|(_, value)| Self::parse(value))
+    }
+
+    /// Returns the `traceparent` header line of an HTTP/1.x request, ended
+    /// by CRLF, to propagate the context.
+    pub fn http_header(&self) -> alloc::string::String {
+        alloc::format!("{}: {}\r\n", TRACEPARENT, self)
+    }
+
+    /// Whether the spans of the trace are recorded.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+}
+
+/// Formats the `traceparent` value of version `00`.
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
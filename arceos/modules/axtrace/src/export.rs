@@ -0,0 +1,137 @@
+//! The exporter of the spans to an OTLP/HTTP collector.
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axnet::TcpSocket;
+
+use crate::otlp::{encode_json, TRACES_PATH};
+use crate::span::take_ended;
+
+/// The interval of the exports of the exporter started at boot.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// The most bytes of the status line read.
+const MAX_STATUS_LEN: usize = 1024;
+
+/// Ships the spans ended to an OTLP/HTTP collector, as JSON.
+pub struct Exporter {
+    addr: SocketAddr,
+    path: String,
+    service: String,
+}
+
+fn write_all(sock: &TcpSocket, mut buf: &[u8]) -> AxResult {
+    while !buf.is_empty() {
+        match sock.send(buf)? {
+            0 => return ax_err!(WriteZero),
+            n => buf = &buf[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Reads the status code of the response.
+fn read_status(sock: &TcpSocket) -> AxResult<u16> {
+    let mut line = Vec::new();
+    let mut chunk = [0; 256];
+    while !line.windows(2).any(|w| w == b"\r\n") {
+        if line.len() > MAX_STATUS_LEN {
+            return ax_err!(InvalidData, "OTLP status line too long");
+        }
+        match sock.recv(&mut chunk)? {
+            0 => return ax_err!(UnexpectedEof, "OTLP connection closed"),
+            n => line.extend_from_slice(&chunk[..n]),
+        }
+    }
+    // HTTP/1.1 200 OK
+    let status = core::str::from_utf8(&line)
+        .ok()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok());
+    match status {
+        Some(status) => Ok(status),
+        None => ax_err!(InvalidData, "bad OTLP status line"),
+    }
+}
+
+impl Exporter {
+    /// Creates an exporter to the collector at `addr`, the spans from the
+    /// service named `service`.
+    pub fn new(addr: SocketAddr, service: &str) -> Self {
+        Self {
+            addr,
+            path: TRACES_PATH.into(),
+            service: service.into(),
+        }
+    }
+
+    /// Sets the path of the requests, [`TRACES_PATH`] by default.
+    pub fn path(&mut self, path: &str) -> &mut Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Exports the spans ended, returns how many.
+    ///
+    /// The spans are lost if the collector cannot be reached or refuses
+    /// them.
+    pub fn export(&self) -> AxResult<usize> {
+        let spans = take_ended();
+        if spans.is_empty() {
+            return Ok(0);
+        }
+        let body = encode_json(&self.service, &spans);
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.addr,
+            body.len()
+        );
+        let sock = TcpSocket::new();
+        sock.connect(self.addr)?;
+        write_all(&sock, head.as_bytes())?;
+        write_all(&sock, body.as_bytes())?;
+        let status = read_status(&sock);
+        sock.shutdown().ok();
+        match status? {
+            200..=299 => Ok(spans.len()),
+            status => {
+                warn!("OTLP collector {} answered {}", self.addr, status);
+                ax_err!(BadState, "OTLP export refused")
+            }
+        }
+    }
+
+    /// Exports the spans ended every `interval`, forever, in a new task.
+    pub fn spawn(self, interval: Duration) {
+        axtask::spawn(move || loop {
+            axtask::sleep(interval);
+            match self.export() {
+                Ok(0) => {}
+                Ok(n) => debug!("exported {} spans to {}", n, self.addr),
+                Err(e) => warn!("exporting spans to {} failed: {:?}", self.addr, e),
+            }
+        });
+    }
+}
+
+/// Starts exporting the spans to the collector at the address given by the
+/// `AX_OTLP_ENDPOINT` environment variable at build time, e.g.
+/// `10.0.2.2:4318`, if any.
+///
+/// The spans are from the service named by `AX_OTLP_SERVICE`, `arceos` by
+/// default.
+pub fn init_exporter() {
+    let Some(endpoint) = option_env!("AX_OTLP_ENDPOINT") else {
+        return;
+    };
+    let addr = endpoint
+        .parse()
+        .expect("invalid address in AX_OTLP_ENDPOINT");
+    let service = option_env!("AX_OTLP_SERVICE").unwrap_or("arceos");
+    info!("exporting the spans of {} to {}", service, addr);
+    Exporter::new(addr, service).spawn(EXPORT_INTERVAL);
+}
@@ -0,0 +1,48 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) distributed tracing.
+//!
+//! The services of the unikernel join the traces of the requests they
+//! handle, as the [W3C trace context] propagates them: a handler extracts
+//! the context of the client from the `traceparent` header of the request
+//! with [`TraceContext::extract_http`], or from an `axrpc` call frame, and
+//! starts a [`Span`] child of it. The span is the active span of its task
+//! until it ends: the scheduler tracepoints of the task are tagged with it,
+//! and the spans started meanwhile are its children, so that the requests
+//! sent to other services propagate it with [`current`].
+//!
+//! The spans of the sampled traces are queued when they end, and shipped to
+//! an [OTLP] collector by OTLP/HTTP, in JSON, by the [`Exporter`], so that
+//! the services appear in the existing tracing backends.
+//!
+//! # Cargo Features
+//!
+//! - `span`: the spans of the tasks, [`Span`].
+//! - `export`: the [`Exporter`] of the spans, by TCP.
+//!
+//! Without any, only the trace context and the OTLP encoding are built.
+//!
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
+//! [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+#[cfg(feature = "export")]
+#[macro_use]
+extern crate log;
+
+mod context;
+#[cfg(feature = "export")]
+mod export;
+pub mod otlp;
+#[cfg(feature = "span")]
+mod span;
+
+#[cfg(test)]
+mod tests;
+
+pub use context::{TraceContext, FLAG_SAMPLED, TRACEPARENT, TRACEPARENT_LEN};
+#[cfg(feature = "export")]
+pub use export::{init_exporter, Exporter};
+pub use otlp::{SpanData, SpanKind, Value};
+#[cfg(feature = "span")]
+pub use span::{current, dropped_spans, take_ended, Span, MAX_QUEUED};
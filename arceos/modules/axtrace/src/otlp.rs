@@ -0,0 +1,174 @@
+//! The spans of the [OTLP] data model, and their JSON encoding sent to the
+//! collectors by OTLP/HTTP, in a `POST` to `/v1/traces`.
+//!
+//! [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::TraceContext;
+
+/// The path of the OTLP/HTTP requests exporting spans.
+pub const TRACES_PATH: &str = "/v1/traces";
+
+/// The role of a span in its trace.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SpanKind {
+    /// An operation inside the service.
+    Internal = 1,
+    /// The handling of a request from a remote client.
+    Server = 2,
+    /// A request to a remote server.
+    Client = 3,
+}
+
+/// The value of an attribute of a span.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A string.
+    Str(String),
+    /// A signed integer.
+    Int(i64),
+    /// A boolean.
+    Bool(bool),
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::Str(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::Str(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Self::Int(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+/// A finished span.
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    /// The context of the span.
+    pub context: TraceContext,
+    /// The ID of the parent span, `None` for the root of a trace.
+    pub parent_span_id: Option<u64>,
+    /// The name of the operation.
+    pub name: String,
+    /// The role of the span.
+    pub kind: SpanKind,
+    /// The wall time of the start, in nanoseconds since the Unix epoch.
+    pub start_ns: u64,
+    /// The wall time of the end, in nanoseconds since the Unix epoch.
+    pub end_ns: u64,
+    /// The attributes, e.g. `("rpc.method", "Echo".into())`.
+    pub attributes: Vec<(String, Value)>,
+    /// The message of the error the operation failed with, if any.
+    pub error: Option<String>,
+}
+
+/// Writes `s` as a JSON string.
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes the OTLP key-value list `attributes`.
+fn write_attributes<'a>(out: &mut String, attributes: impl Iterator<Item = (&'a str, &'a Value)>) {
+    out.push('[');
+    for (i, (key, value)) in attributes.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"key\":");
+        write_str(out, key);
+        out.push_str(",\"value\":{");
+        // The 64-bit integers are strings in the JSON encoding of protobuf.
+        match value {
+            Value::Str(s) => {
+                out.push_str("\"stringValue\":");
+                write_str(out, s);
+            }
+            Value::Int(n) => {
+                let _ = write!(out, "\"intValue\":\"{}\"", n);
+            }
+            Value::Bool(b) => {
+                let _ = write!(out, "\"boolValue\":{}", b);
+            }
+        }
+        out.push_str("}}");
+    }
+    out.push(']');
+}
+
+fn write_span(out: &mut String, span: &SpanData) {
+    // The IDs are hexadecimal in OTLP/JSON, not base64.
+    let _ = write!(
+        out,
+        "{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\"",
+        span.context.trace_id, span.context.span_id
+    );
+    if let Some(parent) = span.parent_span_id {
+        let _ = write!(out, ",\"parentSpanId\":\"{:016x}\"", parent);
+    }
+    out.push_str(",\"name\":");
+    write_str(out, &span.name);
+    let _ = write!(
+        out,
+        ",\"kind\":{},\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":",
+        span.kind as u8, span.start_ns, span.end_ns
+    );
+    write_attributes(out, span.attributes.iter().map(|(k, v)| (k.as_str(), v)));
+    match &span.error {
+        // STATUS_CODE_ERROR
+        Some(message) => {
+            out.push_str(",\"status\":{\"code\":2,\"message\":");
+            write_str(out, message);
+            out.push_str("}}");
+        }
+        None => out.push_str(",\"status\":{}}"),
+    }
+}
+
+/// Encodes `spans` of the service named `service` as the JSON body of an
+/// OTLP/HTTP export request.
+pub fn encode_json(service: &str, spans: &[SpanData]) -> String {
+    let mut out = String::from("{\"resourceSpans\":[{\"resource\":{\"attributes\":");
+    let service = Value::from(service);
+    write_attributes(&mut out, [("service.name", &service)].into_iter());
+    out.push_str("},\"scopeSpans\":[{\"scope\":{\"name\":\"axtrace\"},\"spans\":[");
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_span(&mut out, span);
+    }
+    out.push_str("]}]}]}");
+    out
+}
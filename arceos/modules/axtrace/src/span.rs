@@ -0,0 +1,158 @@
+//! The spans of the tasks, queued for the exporter when they end.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::time::wall_time_nanos;
+use axtask::TraceSpan;
+use kspin::SpinNoIrq;
+
+use crate::otlp::{SpanData, SpanKind, Value};
+use crate::{TraceContext, FLAG_SAMPLED};
+
+/// The most spans ended and not exported yet, the oldest ones are dropped.
+pub const MAX_QUEUED: usize = 1024;
+
+static QUEUE: SpinNoIrq<VecDeque<SpanData>> = SpinNoIrq::new(VecDeque::new());
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a random nonzero ID.
+fn random_id() -> u128 {
+    loop {
+        let id = axhal::misc::random();
+        if id as u64 != 0 {
+            return id;
+        }
+    }
+}
+
+/// Returns the context of the active span of the current task, if any.
+pub fn current() -> Option<TraceContext> {
+    let span = axtask::current_may_uninit()?.trace_span()?;
+    Some(TraceContext {
+        trace_id: span.trace_id,
+        span_id: span.span_id,
+        flags: span.flags,
+    })
+}
+
+/// Takes the sampled spans ended, oldest first, to export them.
+pub fn take_ended() -> Vec<SpanData> {
+    QUEUE.lock().drain(..).collect()
+}
+
+/// Returns the number of spans dropped since boot, ended while the queue
+/// was full.
+pub fn dropped_spans() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// A span of the current task, its active span until it is dropped.
+///
+/// The scheduler tracepoints of the task are tagged with the active span,
+/// and the spans started meanwhile are its children, also in the tasks the
+/// task spawns. It ends when dropped, and it is queued for the exporter if
+/// its trace is sampled.
+pub struct Span {
+    data: SpanData,
+    /// The span active before.
+    prev: Option<TraceSpan>,
+    /// Ended on the task it started on.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Span {
+    /// Starts a span, a child of the active span of the current task, or the
+    /// root of a new sampled trace.
+    pub fn start(name: &str) -> Self {
+        Self::start_child(name, SpanKind::Internal, current())
+    }
+
+    /// Starts a span handling a request from a remote client, a child of the
+    /// client span `parent`, e.g. extracted with
+    /// [`TraceContext::extract_http`], or the root of a new sampled trace.
+    pub fn start_remote(name: &str, parent: Option<TraceContext>) -> Self {
+        Self::start_child(name, SpanKind::Server, parent)
+    }
+
+    /// Starts a span of `kind`, a child of `parent`, or a root if `None`.
+    pub fn start_child(name: &str, kind: SpanKind, parent: Option<TraceContext>) -> Self {
+        let context = match parent {
+            Some(parent) => TraceContext {
+                span_id: random_id() as u64,
+                ..parent
+            },
+            None => TraceContext {
+                trace_id: random_id(),
+                span_id: random_id() as u64,
+                flags: FLAG_SAMPLED,
+            },
+        };
+        let prev = axtask::current().set_trace_span(Some(TraceSpan {
+            trace_id: context.trace_id,
+            span_id: context.span_id,
+            flags: context.flags,
+        }));
+        Self {
+            data: SpanData {
+                context,
+                parent_span_id: parent.map(|p| p.span_id),
+                name: String::from(name),
+                kind,
+                start_ns: wall_time_nanos(),
+                end_ns: 0,
+                attributes: Vec::new(),
+                error: None,
+            },
+            prev,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Returns the context of the span, to propagate it.
+    pub fn context(&self) -> TraceContext {
+        self.data.context
+    }
+
+    /// Sets the attribute `key`.
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<Value>) {
+        let value = value.into();
+        match self.data.attributes.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.data.attributes.push((key.into(), value)),
+        }
+    }
+
+    /// Marks the operation as failed with the error `message`.
+    pub fn set_error(&mut self, message: &str) {
+        self.data.error = Some(message.into());
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        axtask::current().set_trace_span(self.prev);
+        if !self.data.context.is_sampled() {
+            return;
+        }
+        let span = SpanData {
+            context: self.data.context,
+            parent_span_id: self.data.parent_span_id,
+            name: core::mem::take(&mut self.data.name),
+            kind: self.data.kind,
+            start_ns: self.data.start_ns,
+            end_ns: wall_time_nanos(),
+            attributes: core::mem::take(&mut self.data.attributes),
+            error: self.data.error.take(),
+        };
+        let mut queue = QUEUE.lock();
+        if queue.len() >= MAX_QUEUED {
+            queue.pop_front();
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(span);
+    }
+}
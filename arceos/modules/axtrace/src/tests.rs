@@ -0,0 +1,129 @@
+use crate::otlp::encode_json;
+use crate::{SpanData, SpanKind, TraceContext, FLAG_SAMPLED};
+
+const VALUE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+fn context() -> TraceContext {
+    TraceContext {
+        trace_id: 0x4bf92f3577b34da6a3ce929d0e0e4736,
+        span_id: 0x00f067aa0ba902b7,
+        flags: FLAG_SAMPLED,
+    }
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(TraceContext::parse(VALUE), Some(context()));
+    assert_eq!(
+        TraceContext::parse(&format!(" {}\t", VALUE)),
+        Some(context())
+    );
+    assert_eq!(context().to_string(), VALUE);
+    assert!(context().is_sampled());
+
+    let unsampled = TraceContext::parse(&VALUE.replace("-01", "-00")).unwrap();
+    assert!(!unsampled.is_sampled());
+    assert_eq!(unsampled.to_string(), VALUE.replace("-01", "-00"));
+}
+
+#[test]
+fn test_parse_invalid() {
+    // Uppercase.
+    assert_eq!(TraceContext::parse(&VALUE.to_uppercase()), None);
+    // Zero IDs.
+    assert_eq!(
+        TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+        None
+    );
+    assert_eq!(
+        TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01"),
+        None
+    );
+    // Bad version, lengths and separators.
+    assert_eq!(TraceContext::parse(&VALUE.replacen("00", "ff", 1)), None);
+    assert_eq!(TraceContext::parse(&VALUE[..54]), None);
+    assert_eq!(TraceContext::parse(&format!("{}-00", VALUE)), None);
+    assert_eq!(TraceContext::parse(&VALUE.replace('-', "_")), None);
+    assert_eq!(TraceContext::parse(""), None);
+}
+
+#[test]
+fn test_parse_future_version() {
+    let value = VALUE.replacen("00", "cc", 1);
+    assert_eq!(TraceContext::parse(&value), Some(context()));
+    assert_eq!(
+        TraceContext::parse(&format!("{}-what-the-future-holds", value)),
+        Some(context())
+    );
+    assert_eq!(TraceContext::parse(&format!("{}x", value)), None);
+}
+
+#[test]
+fn test_extract_http() {
+    let head = format!(
+        "GET /items HTTP/1.1\r\nHost: example\r\nTraceParent: {}\r\n\r\n",
+        VALUE
+    );
+    assert_eq!(TraceContext::extract_http(&head), Some(context()));
+    assert_eq!(
+        TraceContext::extract_http("GET / HTTP/1.1\r\nHost: example\r\n\r\n"),
+        None
+    );
+    // Not in the body, nor a malformed one.
+    let head = format!("POST / HTTP/1.1\r\n\r\ntraceparent: {}\r\n", VALUE);
+    assert_eq!(TraceContext::extract_http(&head), None);
+    assert_eq!(
+        TraceContext::extract_http("GET / HTTP/1.1\r\ntraceparent: 00-xyz\r\n\r\n"),
+        None
+    );
+
+    assert_eq!(
+        context().http_header(),
+        format!("traceparent: {}\r\n", VALUE)
+    );
+}
+
+#[test]
+fn test_encode_json() {
+    let span = SpanData {
+        context: context(),
+        parent_span_id: Some(0x1122334455667788),
+        name: "Echo \"hi\"\n".into(),
+        kind: SpanKind::Server,
+        start_ns: 1000,
+        end_ns: 2000,
+        attributes: vec![
+            ("rpc.method".into(), "Echo".into()),
+            ("rpc.size".into(), 42i64.into()),
+            ("cached".into(), false.into()),
+        ],
+        error: Some("not found".into()),
+    };
+    let root = SpanData {
+        parent_span_id: None,
+        attributes: vec![],
+        error: None,
+        kind: SpanKind::Internal,
+        ..span.clone()
+    };
+    assert_eq!(
+        encode_json("svc", &[span, root]),
+        concat!(
+            r#"{"resourceSpans":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"svc"}}]},"#,
+            r#""scopeSpans":[{"scope":{"name":"axtrace"},"spans":["#,
+            r#"{"traceId":"4bf92f3577b34da6a3ce929d0e0e4736","spanId":"00f067aa0ba902b7","parentSpanId":"1122334455667788","#,
+            r#""name":"Echo \"hi\"\n","kind":2,"startTimeUnixNano":"1000","endTimeUnixNano":"2000","attributes":["#,
+            r#"{"key":"rpc.method","value":{"stringValue":"Echo"}},"#,
+            r#"{"key":"rpc.size","value":{"intValue":"42"}},"#,
+            r#"{"key":"cached","value":{"boolValue":false}}],"#,
+            r#""status":{"code":2,"message":"not found"}},"#,
+            r#"{"traceId":"4bf92f3577b34da6a3ce929d0e0e4736","spanId":"00f067aa0ba902b7","#,
+            r#""name":"Echo \"hi\"\n","kind":1,"startTimeUnixNano":"1000","endTimeUnixNano":"2000","attributes":[],"#,
+            r#""status":{}}]}]}]}"#,
+        )
+    );
+    assert_eq!(
+        encode_json("a\u{1}b", &[]),
+        r#"{"resourceSpans":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"a\u0001b"}}]},"scopeSpans":[{"scope":{"name":"axtrace"},"spans":[]}]}]}"#
+    );
+}
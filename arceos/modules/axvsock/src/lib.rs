@@ -0,0 +1,160 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) VM sockets (vsock) module.
+//!
+//! It provides stream sockets ([`VsockSocket`]) on the vsock device, to talk
+//! to the host or to the hypervisor daemons without the IP network. Like the
+//! TCP sockets, an endpoint is addressed by a context ID (CID) and a port.
+//!
+//! Only the main vsock device is used, and it is polled on the socket
+//! operations.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod socket;
+
+pub use self::socket::VsockSocket;
+#[doc(no_inline)]
+pub use axdriver::vsock::VsockAddr;
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use axdriver::vsock::{VsockConnId, VsockEvent};
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{ax_err, AxError, AxResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+
+/// The CID to bind to any address of the guest.
+pub const VMADDR_CID_ANY: u64 = u32::MAX as u64;
+/// The CID of the hypervisor.
+pub const VMADDR_CID_HYPERVISOR: u64 = 0;
+/// The CID of the host.
+pub const VMADDR_CID_HOST: u64 = 2;
+/// The port to bind to any free port.
+pub const VMADDR_PORT_ANY: u32 = u32::MAX;
+
+/// The first port allocated to the sockets not bound to a port.
+const EPHEMERAL_PORT_START: u32 = 49152;
+
+/// The state of a connection known to the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnState {
+    Connecting,
+    Connected,
+    /// Closed or reset by the peer, the received bytes can still be read.
+    Closed,
+}
+
+struct VsockStack {
+    dev: AxVsockDevice,
+    conns: BTreeMap<VsockConnId, ConnState>,
+    /// The accepted connections not taken yet, of the listening ports.
+    listeners: BTreeMap<u32, VecDeque<VsockConnId>>,
+    /// The local ports bound by the sockets.
+    ports: BTreeSet<u32>,
+    next_port: u32,
+}
+
+static VSOCK: LazyInit<Mutex<VsockStack>> = LazyInit::new();
+
+/// Initializes the vsock subsystem by underlayer devices.
+pub fn init_vsock(mut vsock_devs: AxDeviceContainer<AxVsockDevice>) {
+    info!("Initialize vsock subsystem...");
+
+    let dev = vsock_devs.take_one().expect("No vsock device found!");
+    info!(
+        "  use vsock device 0: {:?}, guest CID {}",
+        dev.device_name(),
+        dev.guest_cid()
+    );
+    VSOCK.init_once(Mutex::new(VsockStack {
+        dev,
+        conns: BTreeMap::new(),
+        listeners: BTreeMap::new(),
+        ports: BTreeSet::new(),
+        next_port: EPHEMERAL_PORT_START,
+    }));
+}
+
+/// Returns the CID of the guest.
+pub fn guest_cid() -> u64 {
+    VSOCK.lock().dev.guest_cid()
+}
+
+const fn as_ax_err(e: DevError) -> AxError {
+    match e {
+        DevError::AlreadyExists => AxError::AlreadyExists,
+        DevError::Again => AxError::WouldBlock,
+        DevError::BadState => AxError::BadState,
+        DevError::InvalidParam => AxError::InvalidInput,
+        DevError::Io => AxError::Io,
+        DevError::NoMemory => AxError::NoMemory,
+        DevError::ResourceBusy => AxError::ResourceBusy,
+        DevError::Unsupported => AxError::Unsupported,
+    }
+}
+
+impl VsockStack {
+    /// Processes all the pending events of the device.
+    fn poll(&mut self) {
+        loop {
+            let event = match self.dev.poll_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => return,
+                Err(e) => {
+                    warn!("failed to poll the vsock device: {:?}", e);
+                    return;
+                }
+            };
+            match event {
+                VsockEvent::ConnectionRequest(conn) => {
+                    if let Some(queue) = self.listeners.get_mut(&conn.local_port) {
+                        debug!("vsock: accepted connection {:?}", conn);
+                        queue.push_back(conn);
+                        self.conns.insert(conn, ConnState::Connected);
+                    }
+                }
+                VsockEvent::Connected(conn) => {
+                    if let Some(state) = self.conns.get_mut(&conn) {
+                        *state = ConnState::Connected;
+                    }
+                }
+                VsockEvent::Disconnected(conn) => {
+                    if let Some(state) = self.conns.get_mut(&conn) {
+                        *state = ConnState::Closed;
+                    }
+                }
+                VsockEvent::Received(..) | VsockEvent::CreditUpdate(_) => {}
+            }
+        }
+    }
+
+    /// Binds `port`, or a free port if it is [`VMADDR_PORT_ANY`].
+    fn bind_port(&mut self, port: u32) -> AxResult<u32> {
+        if port != VMADDR_PORT_ANY {
+            if !self.ports.insert(port) {
+                return ax_err!(AddrInUse, "vsock port in use");
+            }
+            return Ok(port);
+        }
+        for _ in EPHEMERAL_PORT_START..VMADDR_PORT_ANY {
+            let port = self.next_port;
+            self.next_port = if port == VMADDR_PORT_ANY - 1 {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+            if self.ports.insert(port) {
+                return Ok(port);
+            }
+        }
+        ax_err!(AddrInUse, "no free vsock port")
+    }
+
+    fn unbind_port(&mut self, port: u32) {
+        self.ports.remove(&port);
+    }
+}
@@ -0,0 +1,355 @@
+use axdriver::prelude::*;
+use axdriver::vsock::{VsockAddr, VsockConnId};
+use axerrno::{ax_err, AxError, AxResult};
+use axio::PollState;
+
+use crate::{as_ax_err, ConnState, VMADDR_CID_ANY, VMADDR_PORT_ANY, VSOCK};
+
+/// The bytes sent to the device at a time.
+const MAX_SEND_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Listening,
+    Connecting(VsockConnId),
+    Connected(VsockConnId),
+    Closed,
+}
+
+/// A stream socket of the VM sockets.
+///
+/// Like the TCP socket, it either listens on a port and accepts the
+/// connections to it, or connects to a listening peer. The operations block
+/// unless it is set to non-blocking with [`VsockSocket::set_nonblocking`].
+pub struct VsockSocket {
+    state: State,
+    /// The port bound by the socket, the accepted sockets share the port of
+    /// the listening socket but do not own it.
+    bound_port: Option<u32>,
+    local_port: Option<u32>,
+    nonblock: bool,
+}
+
+impl VsockSocket {
+    /// Creates a new socket, not bound to a port.
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            bound_port: None,
+            local_port: None,
+            nonblock: false,
+        }
+    }
+
+    /// Returns the local address, with the port [`VMADDR_PORT_ANY`] if it is
+    /// not bound yet.
+    pub fn local_addr(&self) -> AxResult<VsockAddr> {
+        Ok(VsockAddr {
+            cid: crate::guest_cid(),
+            port: self.local_port.unwrap_or(VMADDR_PORT_ANY),
+        })
+    }
+
+    /// Returns the address of the peer, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<VsockAddr> {
+        match self.state {
+            State::Connecting(conn) | State::Connected(conn) => Ok(conn.peer),
+            _ => Err(AxError::NotConnected),
+        }
+    }
+
+    /// Returns whether this socket is in non-blocking mode.
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock
+    }
+
+    /// Moves this socket into or out of non-blocking mode.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblock = nonblocking;
+    }
+
+    /// Binds the socket to `addr`, whose CID is the one of the guest or
+    /// [`VMADDR_CID_ANY`], and whose port may be [`VMADDR_PORT_ANY`].
+    pub fn bind(&mut self, addr: VsockAddr) -> AxResult {
+        if self.state != State::Idle || self.local_port.is_some() {
+            return ax_err!(InvalidInput, "socket bind() failed: already bound");
+        }
+        if addr.cid != VMADDR_CID_ANY && addr.cid != crate::guest_cid() {
+            return ax_err!(InvalidInput, "socket bind() failed: not a local CID");
+        }
+        let port = VSOCK.lock().bind_port(addr.port)?;
+        self.bound_port = Some(port);
+        self.local_port = Some(port);
+        Ok(())
+    }
+
+    /// Starts listening on the bound port, or a free port if not bound.
+    pub fn listen(&mut self) -> AxResult {
+        match self.state {
+            State::Idle => {}
+            State::Listening => return Ok(()),
+            _ => return ax_err!(InvalidInput, "socket listen() failed: connected"),
+        }
+        let mut vsock = VSOCK.lock();
+        let port = match self.local_port {
+            Some(port) => port,
+            None => {
+                let port = vsock.bind_port(VMADDR_PORT_ANY)?;
+                self.bound_port = Some(port);
+                self.local_port = Some(port);
+                port
+            }
+        };
+        vsock.listeners.insert(port, Default::default());
+        vsock.dev.listen(port);
+        self.state = State::Listening;
+        Ok(())
+    }
+
+    /// Accepts a connection to the listening port, returns the socket of the
+    /// connection.
+    pub fn accept(&mut self) -> AxResult<VsockSocket> {
+        if self.state != State::Listening {
+            return ax_err!(InvalidInput, "socket accept() failed: not listen");
+        }
+        let port = self.local_port.unwrap();
+        self.block_on(|| {
+            let mut vsock = VSOCK.lock();
+            vsock.poll();
+            let conn = vsock
+                .listeners
+                .get_mut(&port)
+                .and_then(|queue| queue.pop_front())
+                .ok_or(AxError::WouldBlock)?;
+            Ok(VsockSocket {
+                state: State::Connected(conn),
+                bound_port: None,
+                local_port: Some(port),
+                nonblock: false,
+            })
+        })
+    }
+
+    /// Connects to the listening peer at `addr`.
+    pub fn connect(&mut self, addr: VsockAddr) -> AxResult {
+        let conn = match self.state {
+            State::Idle => {
+                let mut vsock = VSOCK.lock();
+                let port = match self.local_port {
+                    Some(port) => port,
+                    None => {
+                        let port = vsock.bind_port(VMADDR_PORT_ANY)?;
+                        self.bound_port = Some(port);
+                        self.local_port = Some(port);
+                        port
+                    }
+                };
+                let conn = VsockConnId {
+                    peer: addr,
+                    local_port: port,
+                };
+                vsock.dev.connect(addr, port).map_err(as_ax_err)?;
+                vsock.conns.insert(conn, ConnState::Connecting);
+                self.state = State::Connecting(conn);
+                conn
+            }
+            State::Connecting(conn) if conn.peer == addr => conn,
+            State::Connected(_) => return ax_err!(AlreadyExists, "socket connect() failed"),
+            _ => return ax_err!(InvalidInput, "socket connect() failed"),
+        };
+
+        self.block_on(|| {
+            let mut vsock = VSOCK.lock();
+            vsock.poll();
+            match vsock.conns.get(&conn) {
+                Some(ConnState::Connecting) => Err(AxError::WouldBlock),
+                Some(ConnState::Connected) => Ok(true),
+                _ => {
+                    vsock.conns.remove(&conn);
+                    Ok(false)
+                }
+            }
+        })
+        .and_then(|connected| {
+            if connected {
+                self.state = State::Connected(conn);
+                Ok(())
+            } else {
+                self.state = State::Idle;
+                ax_err!(ConnectionRefused, "socket connect() failed")
+            }
+        })
+    }
+
+    /// Sends the bytes in `buf`, returns the number of bytes sent.
+    ///
+    /// All the bytes are sent unless the socket is non-blocking.
+    pub fn send(&mut self, buf: &[u8]) -> AxResult<usize> {
+        self.update_state();
+        let conn = match self.state {
+            State::Connected(conn) => conn,
+            State::Connecting(_) if self.nonblock => return Err(AxError::WouldBlock),
+            _ => return ax_err!(NotConnected, "socket send() failed"),
+        };
+        let mut sent = 0;
+        while sent < buf.len() {
+            let res = self.block_on(|| {
+                let mut vsock = VSOCK.lock();
+                vsock.poll();
+                if vsock.conns.get(&conn) != Some(&ConnState::Connected) {
+                    return ax_err!(ConnectionReset, "socket send() failed");
+                }
+                let len = (buf.len() - sent).min(MAX_SEND_SIZE);
+                vsock
+                    .dev
+                    .send(conn, &buf[sent..sent + len])
+                    .map_err(as_ax_err)
+            });
+            match res {
+                Ok(n) => sent += n,
+                Err(_) if sent > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Receives bytes into `buf`, returns the number of bytes received, which
+    /// is 0 if the peer has closed the connection.
+    pub fn recv(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        self.update_state();
+        let conn = match self.state {
+            State::Connected(conn) => conn,
+            State::Connecting(_) if self.nonblock => return Err(AxError::WouldBlock),
+            State::Closed => return Ok(0),
+            _ => return ax_err!(NotConnected, "socket recv() failed"),
+        };
+        self.block_on(|| {
+            let mut vsock = VSOCK.lock();
+            vsock.poll();
+            let closed = vsock.conns.get(&conn) != Some(&ConnState::Connected);
+            match vsock.dev.recv(conn, buf) {
+                Ok(n) if n > 0 => Ok(n),
+                // the connection is forgotten by the device once the
+                // received bytes are taken after the peer closes it
+                Ok(_) | Err(DevError::BadState) if closed => Ok(0),
+                Ok(_) => Err(AxError::WouldBlock),
+                Err(e) => Err(as_ax_err(e)),
+            }
+        })
+    }
+
+    /// Closes the connection, or stops listening.
+    pub fn shutdown(&mut self) -> AxResult {
+        let mut vsock = VSOCK.lock();
+        match self.state {
+            State::Connecting(conn) | State::Connected(conn) => {
+                if vsock.conns.remove(&conn) == Some(ConnState::Closed) {
+                    // may have been forgotten by the device already
+                    vsock.dev.abort(conn).ok();
+                } else {
+                    vsock.dev.shutdown(conn).map_err(as_ax_err)?;
+                }
+            }
+            State::Listening => {
+                let port = self.local_port.unwrap();
+                vsock.dev.unlisten(port);
+                for conn in vsock.listeners.remove(&port).unwrap_or_default() {
+                    vsock.conns.remove(&conn);
+                    vsock.dev.abort(conn).ok();
+                }
+            }
+            State::Idle | State::Closed => {}
+        }
+        self.state = State::Closed;
+        Ok(())
+    }
+
+    /// Checks whether the socket is readable or writable.
+    pub fn poll(&mut self) -> AxResult<PollState> {
+        self.update_state();
+        let mut vsock = VSOCK.lock();
+        vsock.poll();
+        Ok(match self.state {
+            State::Listening => PollState {
+                readable: vsock
+                    .listeners
+                    .get(&self.local_port.unwrap())
+                    .is_some_and(|queue| !queue.is_empty()),
+                writable: false,
+            },
+            State::Connecting(conn) | State::Connected(conn) => {
+                match vsock.conns.get(&conn).copied() {
+                    Some(ConnState::Connecting) => PollState {
+                        readable: false,
+                        writable: false,
+                    },
+                    Some(ConnState::Connected) => PollState {
+                        readable: vsock.dev.recv_avail(conn).is_ok_and(|n| n > 0),
+                        writable: true,
+                    },
+                    _ => PollState {
+                        readable: true,
+                        writable: true,
+                    },
+                }
+            }
+            State::Idle => PollState {
+                readable: false,
+                writable: false,
+            },
+            State::Closed => PollState {
+                readable: true,
+                writable: true,
+            },
+        })
+    }
+
+    /// Completes a non-blocking connect once the peer has accepted it.
+    fn update_state(&mut self) {
+        if let State::Connecting(conn) = self.state {
+            let mut vsock = VSOCK.lock();
+            vsock.poll();
+            if vsock.conns.get(&conn) == Some(&ConnState::Connected) {
+                self.state = State::Connected(conn);
+            }
+        }
+    }
+
+    /// Runs `f` until it does not return
+    /// [`Err(WouldBlock)`](AxError::WouldBlock), unless the socket is
+    /// non-blocking.
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.nonblock {
+            f()
+        } else {
+            loop {
+                match f() {
+                    Ok(t) => return Ok(t),
+                    Err(AxError::WouldBlock) => axtask::yield_now(),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+impl Default for VsockSocket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for VsockSocket {
+    fn drop(&mut self) {
+        self.shutdown().ok();
+        if let Some(port) = self.bound_port {
+            VSOCK.lock().unbind_port(port);
+        }
+    }
+}
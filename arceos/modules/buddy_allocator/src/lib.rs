@@ -0,0 +1,395 @@
+#![cfg_attr(not(test), no_std)]
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+
+#[cfg(test)]
+mod tests;
+
+/// Buddy page allocator
+/// Use it as the formal page allocator once the kernel is up.
+///
+/// The free pages are kept in blocks of 2^order pages aligned to their size,
+/// in a free list per order:
+/// - Allocation takes a block of the smallest order large enough, and splits
+///   it in halves down to that order, the upper halves going back to the
+///   free lists. The pages beyond the requested number are freed at once.
+/// - Free merges the block with its buddy (the other half of the block of
+///   the next order) as long as the buddy is a free block of the same order.
+///
+/// Up to `MAX_REGIONS` disjoint regions can be used, the first one by
+/// `init` and the others by `add_memory`. Each region keeps the orders of
+/// its free blocks in a byte map at its start, one byte per page, and the
+/// free lists are linked through the free blocks themselves: unlike the
+/// bitmap page allocator, it writes into the memory it manages.
+///
+/// The alignment `align_pow2` of `alloc_pages` is in bytes, as for the other
+/// page allocators of `allocator`: the block order is raised to it if needed.
+///
+/// `add_memory_reserved` adds a region with some of its pages already
+/// allocated, e.g. the live allocations of the early allocator taking the
+/// whole memory over: they are freed later by `dealloc_pages`. The byte map
+/// is then put in the first pages not reserved.
+///
+pub struct BuddyPageAllocator<const PAGE_SIZE: usize = 4096> {
+    // 内存区域
+    regions: [Region; MAX_REGIONS],
+    // 内存区域数量
+    region_count: usize,
+    // 各阶空闲链表的头块地址，0 表示为空
+    free_lists: [usize; NR_ORDERS],
+    // 可分配的总页数
+    total_pages: usize,
+    // 已分配的页数
+    used_pages: usize,
+}
+
+/// 最多管理的内存区域数量
+const MAX_REGIONS: usize = 8;
+
+/// 阶数，最大的块为 2^(NR_ORDERS - 1) 页
+pub const NR_ORDERS: usize = 20;
+
+/// 阶表中非空闲块首页的标记
+const NOT_FREE: u8 = u8::MAX;
+
+/// 一个内存区域，其中的若干页存放阶表，通常在区域开头
+#[derive(Clone, Copy)]
+struct Region {
+    // 区域起始地址（页对齐），阶表从它开始逐页记录
+    base: usize,
+    // 阶表地址
+    table: usize,
+    // 可分配页的起始地址
+    start: usize,
+    // 区域结束地址（页对齐）
+    end: usize,
+}
+
+impl Region {
+    const EMPTY: Self = Self {
+        base: 0,
+        table: 0,
+        start: 0,
+        end: 0,
+    };
+
+    fn contains(&self, pos: usize) -> bool {
+        (self.start..self.end).contains(&pos)
+    }
+
+    /// `pos` 处页在阶表中的项
+    fn order_entry(&self, pos: usize, page_size: usize) -> *mut u8 {
+        (self.table + (pos - self.base) / page_size) as *mut u8
+    }
+}
+
+/// 空闲块开头的双向链表节点
+struct FreeNode {
+    next: usize,
+    prev: usize,
+}
+
+impl<const PAGE_SIZE: usize> BuddyPageAllocator<PAGE_SIZE> {
+    /// 创建一个新的伙伴页分配器
+    pub const fn new() -> Self {
+        Self {
+            regions: [Region::EMPTY; MAX_REGIONS],
+            region_count: 0,
+            free_lists: [0; NR_ORDERS],
+            total_pages: 0,
+            used_pages: 0,
+        }
+    }
+
+    fn regions(&self) -> &[Region] {
+        &self.regions[..self.region_count]
+    }
+
+    fn region_of(&self, pos: usize) -> Option<Region> {
+        self.regions()
+            .iter()
+            .find(|region| region.contains(pos))
+            .copied()
+    }
+
+    const fn block_size(order: usize) -> usize {
+        PAGE_SIZE << order
+    }
+
+    /// 能容纳 `num_pages` 页的最小阶
+    fn order_of(num_pages: usize) -> usize {
+        num_pages.next_power_of_two().trailing_zeros() as usize
+    }
+
+    /// 各阶空闲块的数量
+    pub fn free_blocks(&self) -> [usize; NR_ORDERS] {
+        let mut counts = [0; NR_ORDERS];
+        for (order, count) in counts.iter_mut().enumerate() {
+            let mut pos = self.free_lists[order];
+            while pos != 0 {
+                *count += 1;
+                pos = unsafe { (*(pos as *const FreeNode)).next };
+            }
+        }
+        counts
+    }
+
+    /// 将 `pos` 处的 `order` 阶块放入空闲链表
+    fn push_free(&mut self, region: &Region, pos: usize, order: usize) {
+        let head = self.free_lists[order];
+        // SAFETY: the free blocks belong to the allocator.
+        unsafe {
+            (pos as *mut FreeNode).write(FreeNode {
+                next: head,
+                prev: 0,
+            });
+            if head != 0 {
+                (*(head as *mut FreeNode)).prev = pos;
+            }
+            *region.order_entry(pos, PAGE_SIZE) = order as u8;
+        }
+        self.free_lists[order] = pos;
+    }
+
+    /// 将 `pos` 处的 `order` 阶块移出空闲链表
+    fn remove_free(&mut self, region: &Region, pos: usize, order: usize) {
+        // SAFETY: `pos` is a free block of `order`.
+        unsafe {
+            let FreeNode { next, prev } = (pos as *const FreeNode).read();
+            if prev == 0 {
+                self.free_lists[order] = next;
+            } else {
+                (*(prev as *mut FreeNode)).next = next;
+            }
+            if next != 0 {
+                (*(next as *mut FreeNode)).prev = prev;
+            }
+            *region.order_entry(pos, PAGE_SIZE) = NOT_FREE;
+        }
+    }
+
+    /// `pos` 处是否为 `order` 阶空闲块的首页
+    fn is_free_block(region: &Region, pos: usize, order: usize) -> bool {
+        region.contains(pos) && unsafe { *region.order_entry(pos, PAGE_SIZE) } == order as u8
+    }
+
+    /// 释放 `pos` 处的 `order` 阶块，与空闲的伙伴逐阶合并
+    fn free_block(&mut self, region: &Region, mut pos: usize, mut order: usize) {
+        while order + 1 < NR_ORDERS {
+            let buddy = pos ^ Self::block_size(order);
+            if !Self::is_free_block(region, buddy, order) {
+                break;
+            }
+            self.remove_free(region, buddy, order);
+            pos = pos.min(buddy);
+            order += 1;
+        }
+        self.push_free(region, pos, order);
+    }
+
+    /// 将 `pos` 处的页从所在的空闲块中取出，逐阶拆分该块，返回该页是否空闲
+    fn claim_page(&mut self, region: &Region, pos: usize) -> bool {
+        let Some((mut block, mut order)) = (0..NR_ORDERS)
+            .map(|order| (pos & !(Self::block_size(order) - 1), order))
+            .find(|&(block, order)| Self::is_free_block(region, block, order))
+        else {
+            return false;
+        };
+        self.remove_free(region, block, order);
+        // 不含该页的一半放回空闲链表
+        while order > 0 {
+            order -= 1;
+            let half = Self::block_size(order);
+            if pos < block + half {
+                self.push_free(region, block + half, order);
+            } else {
+                self.push_free(region, block, order);
+                block += half;
+            }
+        }
+        true
+    }
+
+    /// 加入一块内存区域，其中与 `reserved` 的范围 `(start, size)` 重叠的页
+    /// 标记为已分配，之后可由 `dealloc_pages` 释放
+    ///
+    /// 保留的范围可以相互重叠，也可以超出区域。阶表放在区域中第一段足够大、
+    /// 不与它们重叠的位置，没有这样的位置时返回 [`AllocError::NoMemory`]。
+    pub fn add_memory_reserved<I>(&mut self, start: usize, size: usize, reserved: I) -> AllocResult
+    where
+        I: IntoIterator<Item = (usize, usize)> + Clone,
+    {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        let base = start
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(AllocError::InvalidParam)?;
+        let end = end & !(PAGE_SIZE - 1);
+        // 阶表每页一个字节，之后至少还要有一页可分配
+        let num_pages = end.saturating_sub(base) / PAGE_SIZE;
+        let table_pages = num_pages.div_ceil(PAGE_SIZE);
+        if num_pages <= table_pages {
+            return Err(AllocError::InvalidParam);
+        }
+        if self
+            .regions()
+            .iter()
+            .any(|region| start < region.end && region.base < end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        // 区域列表已满
+        if self.region_count == MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        // 跳过与阶表重叠的保留范围
+        let table_size = table_pages * PAGE_SIZE;
+        let mut table = base;
+        while let Some((lo, len)) = reserved
+            .clone()
+            .into_iter()
+            .find(|&(lo, len)| lo < table + table_size && table < lo.saturating_add(len))
+        {
+            table = lo
+                .saturating_add(len)
+                .checked_next_multiple_of(PAGE_SIZE)
+                .ok_or(AllocError::NoMemory)?;
+            if table.saturating_add(table_size) > end {
+                return Err(AllocError::NoMemory);
+            }
+        }
+        let region = Region {
+            base,
+            table,
+            start: if table == base {
+                base + table_size
+            } else {
+                base
+            },
+            end,
+        };
+        // SAFETY: the table is in the region given to the allocator.
+        unsafe { core::ptr::write_bytes(table as *mut u8, NOT_FREE, num_pages) };
+        self.regions[self.region_count] = region;
+        self.region_count += 1;
+        // 阶表两侧的页先全部释放，再取出保留的页
+        self.free_range(&region, base, (table - base) / PAGE_SIZE);
+        self.free_range(
+            &region,
+            table + table_size,
+            (end - table - table_size) / PAGE_SIZE,
+        );
+        let mut claimed = 0;
+        for (lo, len) in reserved {
+            let hi = lo
+                .saturating_add(len)
+                .saturating_add(PAGE_SIZE - 1)
+                .min(end)
+                & !(PAGE_SIZE - 1);
+            let lo = (lo & !(PAGE_SIZE - 1)).max(region.start);
+            for pos in (lo..hi).step_by(PAGE_SIZE) {
+                if self.claim_page(&region, pos) {
+                    claimed += 1;
+                }
+            }
+        }
+        self.total_pages += num_pages - table_pages;
+        self.used_pages += claimed;
+        Ok(())
+    }
+
+    /// 释放区域内的页范围 `[pos, pos + num_pages * PAGE_SIZE)`，拆分为对齐的块
+    fn free_range(&mut self, region: &Region, mut pos: usize, mut num_pages: usize) {
+        while num_pages > 0 {
+            let align_order = (pos / PAGE_SIZE).trailing_zeros() as usize;
+            let size_order = num_pages.ilog2() as usize;
+            let order = align_order.min(size_order).min(NR_ORDERS - 1);
+            self.free_block(region, pos, order);
+            pos += Self::block_size(order);
+            num_pages -= 1 << order;
+        }
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for BuddyPageAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    /// Initialize the allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        self.region_count = 0;
+        self.free_lists = [0; NR_ORDERS];
+        self.total_pages = 0;
+        self.used_pages = 0;
+        self.add_memory(start, size)
+            .expect("invalid region of the buddy allocator");
+    }
+
+    /// Add a free memory region to the allocator.
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.add_memory_reserved(start, size, core::iter::empty())
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if num_pages == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidParam);
+        }
+        let align_order = Self::order_of(align_pow2.div_ceil(PAGE_SIZE));
+        let order = Self::order_of(num_pages).max(align_order);
+        if order >= NR_ORDERS {
+            return Err(AllocError::NoMemory);
+        }
+        // 最小的足够大的空闲块
+        let Some(mut cur) = (order..NR_ORDERS).find(|&o| self.free_lists[o] != 0) else {
+            return Err(AllocError::NoMemory);
+        };
+        let pos = self.free_lists[cur];
+        let region = self.region_of(pos).unwrap();
+        self.remove_free(&region, pos, cur);
+        // 逐阶拆分，上半块放回空闲链表
+        while cur > order {
+            cur -= 1;
+            self.push_free(&region, pos + Self::block_size(cur), cur);
+        }
+        // 块中多出的页立即释放
+        self.free_range(
+            &region,
+            pos + num_pages * PAGE_SIZE,
+            (1 << order) - num_pages,
+        );
+        self.used_pages += num_pages;
+        Ok(pos)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        let Some(region) = self.region_of(pos) else {
+            panic!("buddy allocator: pages at {:#x} not allocated", pos);
+        };
+        assert!(
+            pos % PAGE_SIZE == 0 && pos + num_pages * PAGE_SIZE <= region.end,
+            "buddy allocator: invalid pages [{:#x}, +{})",
+            pos,
+            num_pages
+        );
+        self.free_range(&region, pos, num_pages);
+        self.used_pages -= num_pages;
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
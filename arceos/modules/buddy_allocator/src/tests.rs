@@ -0,0 +1,198 @@
+use allocator::{AllocError, BaseAllocator, PageAllocator};
+use core::alloc::Layout;
+
+use crate::{BuddyPageAllocator, NR_ORDERS};
+
+const PAGE: usize = 4096;
+const PAGES: usize = 64;
+
+/// 按 `PAGES` 页对齐的一块内存，块的地址因此可预知
+struct Memory {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Memory {
+    fn new(pages: usize) -> Self {
+        let layout = Layout::from_size_align(pages * PAGE, PAGES * PAGE).unwrap();
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        assert!(!ptr.is_null());
+        Self { ptr, layout }
+    }
+
+    fn start(&self) -> usize {
+        self.ptr as usize
+    }
+
+    fn page(&self, idx: usize) -> usize {
+        self.start() + idx * PAGE
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+fn allocator(mem: &Memory) -> BuddyPageAllocator {
+    let mut alloc = BuddyPageAllocator::new();
+    alloc.init(mem.start(), mem.layout.size());
+    alloc
+}
+
+fn orders(counts: &[usize]) -> [usize; NR_ORDERS] {
+    let mut blocks = [0; NR_ORDERS];
+    blocks[..counts.len()].copy_from_slice(counts);
+    blocks
+}
+
+/// 第 0 页为阶表，其余 63 页的空闲块
+const INITIAL: [usize; 6] = [1, 1, 1, 1, 1, 1];
+
+#[test]
+fn test_init() {
+    let mem = Memory::new(PAGES);
+    let alloc = allocator(&mem);
+    assert_eq!(alloc.total_pages(), PAGES - 1);
+    assert_eq!(alloc.available_pages(), PAGES - 1);
+    assert_eq!(alloc.free_blocks(), orders(&INITIAL));
+}
+
+#[test]
+fn test_split_and_merge() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    assert_eq!(alloc.alloc_pages(1, PAGE), Ok(mem.page(1)));
+    // 拆分 2 阶块，另一半放回空闲链表
+    assert_eq!(alloc.alloc_pages(1, PAGE), Ok(mem.page(2)));
+    assert_eq!(alloc.free_blocks(), orders(&[1, 0, 1, 1, 1, 1]));
+
+    // 与空闲的伙伴合并，阶表所在的页不参与合并
+    alloc.dealloc_pages(mem.page(2), 1);
+    assert_eq!(alloc.free_blocks(), orders(&[0, 1, 1, 1, 1, 1]));
+    alloc.dealloc_pages(mem.page(1), 1);
+    assert_eq!(alloc.free_blocks(), orders(&INITIAL));
+    assert_eq!(alloc.used_pages(), 0);
+}
+
+#[test]
+fn test_extra_pages() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    // 3 页取自 4 页的块，多出的一页立即释放
+    assert_eq!(alloc.alloc_pages(3, PAGE), Ok(mem.page(4)));
+    assert_eq!(alloc.used_pages(), 3);
+    assert_eq!(alloc.free_blocks(), orders(&[2, 1, 0, 1, 1, 1]));
+    // 后放回的块先分配出去
+    assert_eq!(alloc.alloc_pages(1, PAGE), Ok(mem.page(7)));
+    assert_eq!(alloc.alloc_pages(1, PAGE), Ok(mem.page(1)));
+
+    alloc.dealloc_pages(mem.page(7), 1);
+    alloc.dealloc_pages(mem.page(4), 3);
+    alloc.dealloc_pages(mem.page(1), 1);
+    assert_eq!(alloc.free_blocks(), orders(&INITIAL));
+}
+
+#[test]
+fn test_alignment() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    let pos = alloc.alloc_pages(1, 16 * PAGE).unwrap();
+    assert_eq!(pos, mem.page(16));
+    assert_eq!(alloc.used_pages(), 1);
+    assert_eq!(
+        alloc.alloc_pages(1, 3 * PAGE),
+        Err(AllocError::InvalidParam)
+    );
+    alloc.dealloc_pages(pos, 1);
+    assert_eq!(alloc.free_blocks(), orders(&INITIAL));
+}
+
+#[test]
+fn test_exhaustion() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    let mut pages: Vec<_> = (1..PAGES)
+        .map(|_| alloc.alloc_pages(1, PAGE).unwrap())
+        .collect();
+    assert_eq!(alloc.available_pages(), 0);
+    assert_eq!(alloc.free_blocks(), orders(&[]));
+    assert_eq!(alloc.alloc_pages(1, PAGE), Err(AllocError::NoMemory));
+    assert_eq!(alloc.alloc_pages(0, PAGE), Err(AllocError::InvalidParam));
+    assert_eq!(
+        alloc.alloc_pages(1 << NR_ORDERS, PAGE),
+        Err(AllocError::NoMemory)
+    );
+
+    // 乱序释放后合并回原来的块
+    pages.sort_unstable_by_key(|&pos| (pos / PAGE).reverse_bits());
+    for pos in pages {
+        alloc.dealloc_pages(pos, 1);
+    }
+    assert_eq!(alloc.free_blocks(), orders(&INITIAL));
+    assert_eq!(alloc.alloc_pages(32, PAGE), Ok(mem.page(32)));
+    assert_eq!(alloc.alloc_pages(32, PAGE), Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_multi_region() {
+    let mem = Memory::new(PAGES);
+    let more = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    assert_eq!(
+        alloc.add_memory(mem.page(8), 8 * PAGE),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        alloc.add_memory(more.start(), PAGE),
+        Err(AllocError::InvalidParam)
+    );
+    alloc.add_memory(more.start(), PAGES * PAGE).unwrap();
+    assert_eq!(alloc.total_pages(), 2 * (PAGES - 1));
+
+    let a = alloc.alloc_pages(32, PAGE).unwrap();
+    let b = alloc.alloc_pages(32, PAGE).unwrap();
+    assert_ne!(a / (PAGES * PAGE), b / (PAGES * PAGE));
+    assert_eq!(alloc.alloc_pages(32, PAGE), Err(AllocError::NoMemory));
+    alloc.dealloc_pages(a, 32);
+    alloc.dealloc_pages(b, 32);
+    assert_eq!(alloc.used_pages(), 0);
+}
+
+#[test]
+fn test_add_memory_reserved() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = BuddyPageAllocator::<PAGE>::new();
+    // 保留的页中不能放阶表，它移到其后的第一页
+    let reserved = [(mem.page(0), 2 * PAGE), (mem.page(40) + 8, 100)];
+    alloc
+        .add_memory_reserved(mem.start(), PAGES * PAGE, reserved)
+        .unwrap();
+    assert_eq!(alloc.total_pages(), PAGES - 1);
+    assert_eq!(alloc.used_pages(), 3);
+    // 取出保留页时拆下的最后一块是它的伙伴
+    assert_eq!(alloc.alloc_pages(1, PAGE), Ok(mem.page(41)));
+    assert_eq!(alloc.free_blocks(), orders(&[1, 1, 2, 2, 2]));
+
+    alloc.dealloc_pages(mem.page(0), 2);
+    alloc.dealloc_pages(mem.page(40), 1);
+    alloc.dealloc_pages(mem.page(41), 1);
+    assert_eq!(alloc.used_pages(), 0);
+    assert_eq!(alloc.free_blocks(), orders(&[1, 1, 1, 1, 1, 1]));
+
+    // 没有放阶表的位置
+    let more = Memory::new(PAGES);
+    assert_eq!(
+        alloc.add_memory_reserved(more.start(), PAGES * PAGE, [(more.start(), PAGES * PAGE)]),
+        Err(AllocError::NoMemory)
+    );
+}
+
+#[test]
+#[should_panic(expected = "not allocated")]
+fn test_foreign_pages() {
+    let mem = Memory::new(PAGES);
+    let mut alloc = allocator(&mem);
+    alloc.dealloc_pages(PAGE, 1);
+}
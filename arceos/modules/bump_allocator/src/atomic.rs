@@ -0,0 +1,427 @@
+//! A lock-free variant of the early allocator for the multi-hart early boot.
+//!
+//! The secondary harts allocate their stacks and boot structures before the
+//! spinlocks can be trusted, so [`AtomicEarlyAllocator`] is shared by them
+//! without any lock: its cursors of the bytes and the pages are atomics
+//! moved by compare-exchange loops, and all its methods take `&self`. The traits of
+//! the allocators are implemented for `&AtomicEarlyAllocator`, and
+//! [`GlobalAlloc`] for the allocator itself.
+//!
+//! It manages a single region, as the original bump allocator did:
+//!
+//! - The bytes are allocated forward and counted; the bytes area is reset
+//!   once the count goes down to zero. The bytes cursor is packed with an
+//!   epoch bumped by each reset, so a free which read the cursor before
+//!   another reset cannot reset it again once the area has grown back to the
+//!   same position: a reset racing with an allocation is skipped, and done at
+//!   a later free. The region is limited to 4 GiB to fit the packed word.
+//! - The pages are allocated backward, and only freeing the lowest pages
+//!   moves `page_pos` back up. The other frees and the gaps left by the
+//!   alignment are not reused.
+//!
+//! Each allocation moves its cursor first and checks the other one after:
+//! two allocations colliding in the middle see each other, and the one which
+//! overlaps fails, so both may fail when the memory is nearly exhausted.
+//! `init` must be done before the region is shared.
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
+
+/// 字节游标中偏移的掩码，高 32 位为重置的代数
+const OFFSET_MASK: u64 = u32::MAX as u64;
+
+/// 字节游标相对 `start` 的偏移
+const fn offset_of(bytes: u64) -> usize {
+    (bytes & OFFSET_MASK) as usize
+}
+
+/// 代数不变，偏移改为 `offset`
+const fn with_offset(bytes: u64, offset: usize) -> u64 {
+    (bytes & !OFFSET_MASK) | offset as u64
+}
+
+/// 重置后的字节游标：偏移为零，代数加一
+const fn next_epoch(bytes: u64) -> u64 {
+    (bytes >> 32).wrapping_add(1) << 32
+}
+
+/// 无锁的早期分配器，可在多个 hart 间共享
+///
+/// [ bytes-used | avail-area | pages-used ]
+/// |            | -->    <-- |            |
+/// start     byte_pos    page_pos       end
+pub struct AtomicEarlyAllocator<const PAGE_SIZE: usize = 4096> {
+    // 内存区域起始地址
+    start: AtomicUsize,
+    // 内存区域结束地址
+    end: AtomicUsize,
+    // 字节分配当前位置相对 start 的偏移（低 32 位）和重置的代数（高 32 位）
+    bytes: AtomicU64,
+    // 页分配当前位置
+    page_pos: AtomicUsize,
+    // 字节分配计数器
+    count: AtomicUsize,
+    // 当前分配的页数
+    live_pages: AtomicUsize,
+}
+
+impl<const PAGE_SIZE: usize> AtomicEarlyAllocator<PAGE_SIZE> {
+    /// 创建一个空的分配器，可用于 `static` 的初始化
+    pub const fn new() -> Self {
+        Self {
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            page_pos: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            live_pages: AtomicUsize::new(0),
+        }
+    }
+
+    /// 以 `[start, start + size)` 初始化分配器，须在其他 hart 使用它之前调用
+    ///
+    /// 区域至多使用 4 GiB
+    pub fn init(&self, start: usize, size: usize) {
+        let end = start.saturating_add(size.min(u32::MAX as usize));
+        self.start.store(start, SeqCst);
+        self.end.store(end, SeqCst);
+        self.bytes.store(0, SeqCst);
+        self.page_pos.store(end, SeqCst);
+        self.count.store(0, SeqCst);
+        self.live_pages.store(0, SeqCst);
+    }
+
+    /// 分配字节
+    pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let size = layout.size();
+        let align = layout.align();
+        if size == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        // 先计数，持有计数时字节区域不会被重置
+        self.count.fetch_add(1, SeqCst);
+        let start = self.start.load(SeqCst);
+        let mut bytes = self.bytes.load(SeqCst);
+        // 溢出时视为空间不足
+        while let Some((aligned_pos, new_pos)) = (start + offset_of(bytes))
+            .checked_add(align - 1)
+            .map(|pos| pos & !(align - 1))
+            .and_then(|aligned| Some((aligned, aligned.checked_add(size)?)))
+        {
+            if new_pos > self.page_pos.load(SeqCst) {
+                break;
+            }
+            let new_bytes = with_offset(bytes, new_pos - start);
+            match self
+                .bytes
+                .compare_exchange_weak(bytes, new_bytes, SeqCst, SeqCst)
+            {
+                Ok(_) => {
+                    // 页分配可能同时越过了新的位置
+                    if new_pos <= self.page_pos.load(SeqCst) {
+                        return NonNull::new(aligned_pos as *mut u8)
+                            .ok_or(AllocError::InvalidParam);
+                    }
+                    // 之后没有新的分配时退回，否则留作间隙
+                    let _ = self
+                        .bytes
+                        .compare_exchange(new_bytes, bytes, SeqCst, SeqCst);
+                    break;
+                }
+                Err(current) => bytes = current,
+            }
+        }
+        self.count.fetch_sub(1, SeqCst);
+        Err(AllocError::NoMemory)
+    }
+
+    /// 释放字节，所有分配都释放后重置字节区域
+    pub fn dealloc(&self, _pos: NonNull<u8>, _layout: Layout) {
+        // 在减少计数之前读取，之后的分配或重置会使重置失败
+        let bytes = self.bytes.load(SeqCst);
+        if self
+            .count
+            .fetch_update(SeqCst, SeqCst, |count| count.checked_sub(1))
+            == Ok(1)
+        {
+            self.reset(bytes);
+        }
+    }
+
+    /// 字节游标仍为 `bytes` 时重置字节区域
+    ///
+    /// 其间的重置增加了代数，即使区域又增长到同一位置也会失败
+    fn reset(&self, bytes: u64) {
+        let _ = self
+            .bytes
+            .compare_exchange(bytes, next_epoch(bytes), SeqCst, SeqCst);
+    }
+
+    /// 字节分配当前位置
+    fn byte_pos(&self) -> usize {
+        self.start.load(SeqCst) + offset_of(self.bytes.load(SeqCst))
+    }
+
+    /// 分配 `num_pages` 个连续的页，按 `1 << align_pow2` 页对齐
+    pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let align = u32::try_from(align_pow2)
+            .ok()
+            .and_then(|shift| 1usize.checked_shl(shift))
+            .and_then(|pages| pages.checked_mul(PAGE_SIZE))
+            .ok_or(AllocError::InvalidParam)?;
+        let size = num_pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(AllocError::InvalidParam)?;
+        let mut pos = self.page_pos.load(SeqCst);
+        while let Some(new_pos) = pos.checked_sub(size).map(|pos| pos & !(align - 1)) {
+            if new_pos < self.byte_pos() {
+                break;
+            }
+            match self
+                .page_pos
+                .compare_exchange_weak(pos, new_pos, SeqCst, SeqCst)
+            {
+                Ok(_) => {
+                    // 字节分配可能同时越过了新的位置
+                    if self.byte_pos() <= new_pos {
+                        self.live_pages.fetch_add(num_pages, SeqCst);
+                        return Ok(new_pos);
+                    }
+                    let _ = self.page_pos.compare_exchange(new_pos, pos, SeqCst, SeqCst);
+                    break;
+                }
+                Err(current) => pos = current,
+            }
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    /// 释放 `pos` 处的 `num_pages` 个页，只有最低处的页会被回收
+    pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
+        let Some(end) = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| pos.checked_add(size))
+        else {
+            return;
+        };
+        if num_pages == 0 || pos < self.page_pos.load(SeqCst) || end > self.end.load(SeqCst) {
+            return;
+        }
+        let _ = self.live_pages.fetch_update(SeqCst, SeqCst, |pages| {
+            Some(pages.saturating_sub(num_pages))
+        });
+        let _ = self.page_pos.compare_exchange(pos, end, SeqCst, SeqCst);
+    }
+
+    /// 总字节数
+    pub fn total_bytes(&self) -> usize {
+        self.end.load(SeqCst) - self.start.load(SeqCst)
+    }
+
+    /// 已使用的字节数，包括不再复用的间隙和页
+    pub fn used_bytes(&self) -> usize {
+        self.total_bytes() - self.available_bytes()
+    }
+
+    /// 剩余可用的字节数
+    pub fn available_bytes(&self) -> usize {
+        self.page_pos.load(SeqCst).saturating_sub(self.byte_pos())
+    }
+
+    /// 总页数
+    pub fn total_pages(&self) -> usize {
+        self.total_bytes() / PAGE_SIZE
+    }
+
+    /// 当前分配的页数
+    pub fn used_pages(&self) -> usize {
+        self.live_pages.load(SeqCst)
+    }
+
+    /// 剩余可用的页数
+    pub fn available_pages(&self) -> usize {
+        self.available_bytes() / PAGE_SIZE
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for AtomicEarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for &AtomicEarlyAllocator<PAGE_SIZE> {
+    /// Initialize the allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        AtomicEarlyAllocator::init(self, start, size)
+    }
+
+    /// Add a free memory region to the allocator, which is not supported:
+    /// it manages a single region.
+    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
+        Err(AllocError::InvalidParam)
+    }
+}
+
+impl<const PAGE_SIZE: usize> ByteAllocator for &AtomicEarlyAllocator<PAGE_SIZE> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        AtomicEarlyAllocator::alloc(self, layout)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        AtomicEarlyAllocator::dealloc(self, pos, layout)
+    }
+
+    fn total_bytes(&self) -> usize {
+        AtomicEarlyAllocator::total_bytes(self)
+    }
+
+    fn used_bytes(&self) -> usize {
+        AtomicEarlyAllocator::used_bytes(self)
+    }
+
+    fn available_bytes(&self) -> usize {
+        AtomicEarlyAllocator::available_bytes(self)
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for &AtomicEarlyAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        AtomicEarlyAllocator::alloc_pages(self, num_pages, align_pow2)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        AtomicEarlyAllocator::dealloc_pages(self, pos, num_pages)
+    }
+
+    fn total_pages(&self) -> usize {
+        AtomicEarlyAllocator::total_pages(self)
+    }
+
+    fn used_pages(&self) -> usize {
+        AtomicEarlyAllocator::used_pages(self)
+    }
+
+    fn available_pages(&self) -> usize {
+        AtomicEarlyAllocator::available_pages(self)
+    }
+}
+
+unsafe impl<const PAGE_SIZE: usize> GlobalAlloc for AtomicEarlyAllocator<PAGE_SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match AtomicEarlyAllocator::alloc(self, layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let pos = NonNull::new(ptr).expect("dealloc null ptr");
+        AtomicEarlyAllocator::dealloc(self, pos, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{layout, Memory, PAGE};
+
+    #[test]
+    fn test_bytes_and_pages() {
+        let mem = Memory::new(16);
+        let alloc = AtomicEarlyAllocator::<PAGE>::new();
+        alloc.init(mem.start(), mem.size());
+        assert_eq!(alloc.total_pages(), 16);
+
+        let a = alloc.alloc(layout(24)).unwrap();
+        let b = alloc.alloc(layout(8)).unwrap();
+        assert_eq!(a.as_ptr() as usize, mem.start());
+        assert_eq!(b.as_ptr() as usize, mem.start() + 24);
+        let p = alloc.alloc_pages(1, 0).unwrap();
+        let q = alloc.alloc_pages(1, 1).unwrap();
+        assert_eq!(p, mem.end() - PAGE);
+        assert_eq!(q, (p - PAGE) & !(2 * PAGE - 1));
+        assert_eq!(alloc.used_pages(), 2);
+
+        // 只有最低处的页被回收，其余的释放和对齐的间隙不再复用
+        alloc.dealloc_pages(p, 1);
+        assert_eq!(alloc.available_bytes(), q - (mem.start() + 32));
+        alloc.dealloc_pages(q, 1);
+        assert_eq!(alloc.used_pages(), 0);
+        assert_eq!(alloc.available_bytes(), q + PAGE - (mem.start() + 32));
+
+        alloc.dealloc(a, layout(24));
+        alloc.dealloc(b, layout(8));
+        assert_eq!(alloc.alloc(layout(8)).unwrap(), a);
+        assert_eq!(alloc.alloc(layout(0)), Err(AllocError::InvalidParam));
+        assert_eq!(alloc.alloc_pages(16, 0), Err(AllocError::NoMemory));
+    }
+
+    #[test]
+    fn test_concurrent() {
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 64;
+        let mem = Memory::new(64);
+        let alloc = AtomicEarlyAllocator::<PAGE>::new();
+        alloc.init(mem.start(), mem.size());
+
+        let mut ranges: Vec<(usize, usize)> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut ranges = Vec::new();
+                        for _ in 0..ROUNDS {
+                            let pos = alloc.alloc(layout(40)).unwrap().as_ptr() as usize;
+                            ranges.push((pos, pos + 40));
+                        }
+                        for _ in 0..ROUNDS / 8 {
+                            let pos = alloc.alloc_pages(1, 0).unwrap();
+                            ranges.push((pos, pos + PAGE));
+                        }
+                        ranges
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        // 所有分配互不重叠，且都在区域之内
+        ranges.sort_unstable();
+        assert!(ranges.windows(2).all(|pair| pair[0].1 <= pair[1].0));
+        assert!(ranges
+            .iter()
+            .all(|&(start, end)| mem.start() <= start && end <= mem.end()));
+        assert_eq!(alloc.used_pages(), THREADS * ROUNDS / 8);
+    }
+
+    #[test]
+    fn test_stale_reset() {
+        let mem = Memory::new(4);
+        let alloc = AtomicEarlyAllocator::<PAGE>::new();
+        alloc.init(mem.start(), mem.size());
+
+        // 第一个 hart 释放 a：已读取游标并减少计数，尚未重置
+        let a = alloc.alloc(layout(8)).unwrap();
+        let bytes = alloc.bytes.load(SeqCst);
+        alloc.count.fetch_sub(1, SeqCst);
+        // 其他 hart 分配并释放，重置了字节区域，之后的分配回到同一位置
+        let b = alloc.alloc(layout(8)).unwrap();
+        alloc.dealloc(b, layout(8));
+        assert_eq!(alloc.alloc(layout(8)).unwrap(), a);
+        assert_eq!(offset_of(alloc.bytes.load(SeqCst)), offset_of(bytes));
+
+        // 过期的重置失败，仍在使用的分配不会被覆盖
+        alloc.reset(bytes);
+        let c = alloc.alloc(layout(8)).unwrap();
+        assert_eq!(c.as_ptr() as usize, mem.start() + 8);
+    }
+}
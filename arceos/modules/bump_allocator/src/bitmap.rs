@@ -0,0 +1,234 @@
+//! Page ownership bitmap, stored at the end of a memory region.
+//!
+//! Bit `i` is set if the `i`-th page from `base` is allocated. With it, any
+//! page range can be freed, and the freed pages (and the gaps left by
+//! alignment) are reused by the later allocations.
+
+use allocator::{AllocError, AllocResult};
+
+use crate::state::{StateReader, StateWriter};
+use crate::AllocConstraints;
+
+/// 一个内存区域的页位图
+#[derive(Clone, Copy)]
+pub(crate) struct PageBitmap {
+    // 位图所在地址
+    addr: usize,
+    // 第 0 位对应的页地址
+    base: usize,
+    // 位图覆盖的页数
+    num_pages: usize,
+    page_size: usize,
+}
+
+impl PageBitmap {
+    pub const EMPTY: Self = Self {
+        addr: 0,
+        base: 0,
+        num_pages: 0,
+        page_size: 1,
+    };
+
+    /// 在区域 `[start, end)` 的末尾放置位图，返回位图和可分配区域的新结束地址
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid writable memory.
+    pub unsafe fn reserve(start: usize, end: usize, page_size: usize) -> (Self, usize) {
+        let base = (start + page_size - 1) & !(page_size - 1);
+        let max_pages = end.saturating_sub(base) / page_size;
+        let bytes = max_pages.div_ceil(8);
+        let addr = end - bytes;
+        core::ptr::write_bytes(addr as *mut u8, 0, bytes);
+        // 位图之下的整页才可分配
+        let new_end = if bytes == 0 {
+            end
+        } else {
+            addr & !(page_size - 1)
+        };
+        let num_pages = new_end.saturating_sub(base) / page_size;
+        let bitmap = Self {
+            addr,
+            base,
+            num_pages,
+            page_size,
+        };
+        (bitmap, new_end)
+    }
+
+    /// 只覆盖 `end` 之下的页的同一位图，用于分割区域
+    pub fn truncate(&self, end: usize) -> Self {
+        Self {
+            num_pages: self
+                .num_pages
+                .min(end.saturating_sub(self.base) / self.page_size),
+            ..*self
+        }
+    }
+
+    /// 写入位图的描述和内容
+    pub fn save(&self, w: &mut StateWriter) {
+        w.word(self.addr);
+        w.word(self.base);
+        w.word(self.num_pages);
+        w.word(self.page_size);
+        let (start, end) = self.storage();
+        // 位图在 `reserve` 留出的内存中
+        let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+        w.bytes(bytes);
+    }
+
+    /// 读取 [`save`](Self::save) 写入的位图，`write_back` 时将内容写回内存
+    ///
+    /// # Safety
+    ///
+    /// The memory of the bitmap must be valid writable memory.
+    pub unsafe fn load(r: &mut StateReader) -> AllocResult<Self> {
+        let bitmap = Self {
+            addr: r.word()?,
+            base: r.word()?,
+            num_pages: r.word()?,
+            page_size: r.word()?,
+        };
+        let bytes = r.bytes()?;
+        if !bitmap.page_size.is_power_of_two() || bytes.len() != bitmap.num_pages.div_ceil(8) {
+            return Err(AllocError::InvalidParam);
+        }
+        if r.write_back && !bytes.is_empty() {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), bitmap.addr as *mut u8, bytes.len());
+        }
+        Ok(bitmap)
+    }
+
+    /// 位图本身占用的内存 `(start, end)`
+    pub fn storage(&self) -> (usize, usize) {
+        (self.addr, self.addr + self.num_pages.div_ceil(8))
+    }
+
+    fn index(&self, pos: usize) -> usize {
+        (pos - self.base) / self.page_size
+    }
+
+    fn byte(&self, idx: usize) -> *mut u8 {
+        (self.addr + idx / 8) as *mut u8
+    }
+
+    /// 地址 `pos` 处的页是否已分配
+    pub fn is_used(&self, pos: usize) -> bool {
+        let idx = self.index(pos);
+        idx < self.num_pages && unsafe { *self.byte(idx) } & (1 << (idx % 8)) != 0
+    }
+
+    /// 标记 `[pos, pos + size)` 中的页为已分配或空闲
+    pub fn set(&mut self, pos: usize, size: usize, used: bool) {
+        let first = self.index(pos);
+        let last = (first + size / self.page_size).min(self.num_pages);
+        for idx in first..last {
+            let byte = unsafe { &mut *self.byte(idx) };
+            if used {
+                *byte |= 1 << (idx % 8);
+            } else {
+                *byte &= !(1 << (idx % 8));
+            }
+        }
+    }
+
+    /// 在 `[lo, end)` 中从高往低查找 `size` 字节满足约束的空闲页
+    pub fn find_free(
+        &self,
+        lo: usize,
+        size: usize,
+        constraints: &AllocConstraints,
+    ) -> Option<usize> {
+        let end = self.base + self.num_pages * self.page_size;
+        let mut cand = constraints.fit(end, size)?;
+        while cand >= lo {
+            // 找到候选范围内最低的已分配页，下一个候选须在它之下
+            match (cand..cand + size)
+                .step_by(self.page_size)
+                .find(|&pos| self.is_used(pos))
+            {
+                None => return Some(cand),
+                Some(used) => cand = constraints.fit(used, size)?,
+            }
+        }
+        None
+    }
+
+    /// `[lo, end)` 中空闲的字节数
+    pub fn free_bytes(&self, lo: usize) -> usize {
+        let end = self.base + self.num_pages * self.page_size;
+        (lo..end)
+            .step_by(self.page_size)
+            .filter(|&pos| !self.is_used(pos))
+            .count()
+            * self.page_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, Memory, PAGE};
+    use allocator::PageAllocator;
+
+    #[test]
+    fn test_bitmap() {
+        let mem = Memory::new(8);
+        let (mut bitmap, end) = unsafe { PageBitmap::reserve(mem.start(), mem.end(), PAGE) };
+        // 一个字节的位图占去最后一页
+        assert_eq!(end, mem.end() - PAGE);
+        assert_eq!(bitmap.storage(), (mem.end() - 1, mem.end()));
+        assert_eq!(bitmap.free_bytes(mem.start()), 7 * PAGE);
+
+        let base = mem.start();
+        bitmap.set(base + 2 * PAGE, 3 * PAGE, true);
+        assert!(!bitmap.is_used(base + PAGE));
+        assert!(bitmap.is_used(base + 2 * PAGE) && bitmap.is_used(base + 4 * PAGE));
+        assert!(!bitmap.is_used(base + 5 * PAGE));
+        assert_eq!(bitmap.free_bytes(base), 4 * PAGE);
+
+        // 从高往低查找
+        let any = AllocConstraints::NONE.resolve(PAGE, PAGE).unwrap();
+        assert_eq!(
+            bitmap.find_free(base, 2 * PAGE, &any),
+            Some(base + 5 * PAGE)
+        );
+        assert_eq!(bitmap.find_free(base, 3 * PAGE, &any), None);
+        // 越过已分配的页，找到之下的空闲页
+        bitmap.set(base + 3 * PAGE, PAGE, false);
+        bitmap.set(base + 5 * PAGE, 2 * PAGE, true);
+        assert_eq!(bitmap.find_free(base, 2 * PAGE, &any), Some(base));
+        assert_eq!(bitmap.find_free(base + PAGE, 2 * PAGE, &any), None);
+
+        let truncated = bitmap.truncate(base + 4 * PAGE);
+        assert!(!truncated.is_used(base + 4 * PAGE));
+        assert!(truncated.is_used(base + 2 * PAGE));
+    }
+
+    #[test]
+    fn test_free_any_range() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let avail = alloc.available_pages();
+        let a = alloc.alloc_pages(2, 0).unwrap();
+        let b = alloc.alloc_pages(3, 0).unwrap();
+        let c = alloc.alloc_pages(1, 0).unwrap();
+
+        // 中间释放的页被之后的分配复用
+        alloc.dealloc_pages(b, 3);
+        assert_eq!(alloc.used_pages(), 3);
+        assert_eq!(alloc.snapshot().regions()[0].hole_bytes, 3 * PAGE);
+        assert_eq!(alloc.alloc_pages(2, 0), Ok(b + PAGE));
+        assert_eq!(alloc.alloc_pages(1, 0), Ok(b));
+        assert_eq!(alloc.snapshot().regions()[0].page_pos, c);
+
+        alloc.dealloc_pages(b + PAGE, 2);
+        alloc.dealloc_pages(a, 2);
+        alloc.dealloc_pages(b, 1);
+        assert_eq!(alloc.snapshot().regions()[0].page_pos, c);
+        alloc.dealloc_pages(c, 1);
+        assert_eq!(alloc.used_pages(), 0);
+        assert_eq!(alloc.available_pages(), avail);
+    }
+}
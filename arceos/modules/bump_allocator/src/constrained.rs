@@ -0,0 +1,176 @@
+//! Page allocations constrained for the DMA devices.
+//!
+//! [`AllocConstraints`] limits where the pages of an allocation can be: below
+//! an address ceiling, e.g. 4 GiB for the 32-bit DMA, aligned to what the
+//! device requires, and without crossing a boundary, e.g. the 64 KiB of the
+//! ISA DMA. The addresses are the ones managed by the allocator: the linear
+//! mapping keeps the order of the physical addresses, so a physical ceiling
+//! is converted by it.
+//!
+//! In the early allocator, a ceiling below `p_pos` leaves the pages above it
+//! unused until the allocation is freed, as for the alignment gaps.
+//!
+//! [`ConstrainedPageAllocator`] is implemented by the page allocators
+//! honoring them. It only depends on [`PageAllocator`], so the other page
+//! allocators can implement it too, with `NoMemory` when no free range meets
+//! the constraints.
+
+use allocator::{AllocError, AllocResult, PageAllocator};
+
+/// 页分配的地址约束
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocConstraints {
+    /// 分配的页须全部低于该地址，`usize::MAX` 时不限
+    pub max_addr: usize,
+    /// 起始地址的对齐字节数，为 2 的幂，小于 `PAGE_SIZE` 时按 `PAGE_SIZE` 对齐
+    pub align: usize,
+    /// 分配不能跨越该字节数的整数倍，为 2 的幂，0 时不限
+    pub boundary: usize,
+}
+
+impl AllocConstraints {
+    /// 没有约束
+    pub const NONE: Self = Self {
+        max_addr: usize::MAX,
+        align: 0,
+        boundary: 0,
+    };
+
+    /// 只要求按 `align` 字节对齐
+    pub const fn aligned(align: usize) -> Self {
+        Self {
+            align,
+            ..Self::NONE
+        }
+    }
+
+    /// 检查 `size` 字节的分配的约束，返回对齐不小于 `page_size` 的约束
+    pub(crate) fn resolve(self, size: usize, page_size: usize) -> AllocResult<Self> {
+        let align = self.align.max(page_size);
+        if !align.is_power_of_two()
+            || (self.boundary != 0 && (!self.boundary.is_power_of_two() || size > self.boundary))
+        {
+            return Err(AllocError::InvalidParam);
+        }
+        Ok(Self { align, ..self })
+    }
+
+    /// 结束地址不超过 `top` 的 `size` 字节中，满足约束的最高起始地址
+    ///
+    /// 须先由 `resolve` 检查。
+    pub(crate) fn fit(&self, top: usize, size: usize) -> Option<usize> {
+        let top = top.min(self.max_addr);
+        let start = top.checked_sub(size)? & !(self.align - 1);
+        if self.boundary == 0 || start / self.boundary == (start + size - 1) / self.boundary {
+            return Some(start);
+        }
+        // 结束于跨越的边界处，对齐后不会越过上一个边界
+        let end = (start + size) & !(self.boundary - 1);
+        Some(end.checked_sub(size)? & !(self.align - 1))
+    }
+}
+
+impl Default for AllocConstraints {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// 可按地址约束分配页的页分配器
+pub trait ConstrainedPageAllocator: PageAllocator {
+    /// 分配 `num_pages` 个满足 `constraints` 的连续页，返回起始地址
+    ///
+    /// 没有满足约束的空闲范围时返回 [`AllocError::NoMemory`]，约束本身无效
+    /// （对齐或边界不是 2 的幂、分配大于边界）时返回
+    /// [`AllocError::InvalidParam`]。
+    fn alloc_pages_constrained(
+        &mut self,
+        num_pages: usize,
+        constraints: AllocConstraints,
+    ) -> AllocResult<usize>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, Memory, PAGE};
+
+    #[test]
+    fn test_resolve() {
+        let none = AllocConstraints::NONE.resolve(PAGE, PAGE).unwrap();
+        assert_eq!(none.align, PAGE);
+        assert_eq!(
+            AllocConstraints::aligned(4 * PAGE)
+                .resolve(PAGE, PAGE)
+                .unwrap()
+                .align,
+            4 * PAGE
+        );
+        assert_eq!(
+            AllocConstraints::aligned(3 * PAGE).resolve(PAGE, PAGE),
+            Err(AllocError::InvalidParam)
+        );
+        let boundary = AllocConstraints {
+            boundary: 2 * PAGE,
+            ..AllocConstraints::NONE
+        };
+        assert!(boundary.resolve(2 * PAGE, PAGE).is_ok());
+        assert_eq!(
+            boundary.resolve(3 * PAGE, PAGE),
+            Err(AllocError::InvalidParam)
+        );
+    }
+
+    #[test]
+    fn test_fit() {
+        let c = AllocConstraints {
+            max_addr: 0x10_0000,
+            boundary: 0x4000,
+            ..AllocConstraints::NONE
+        }
+        .resolve(0x2000, PAGE)
+        .unwrap();
+        assert_eq!(c.fit(0x20_0000, 0x2000), Some(0xf_e000));
+        // 跨越边界时结束于边界
+        assert_eq!(c.fit(0xf_d000, 0x2000), Some(0xf_a000));
+        assert_eq!(c.fit(0x1000, 0x2000), None);
+    }
+
+    #[test]
+    fn test_alloc_pages_constrained() {
+        let mem = Memory::new(64);
+        let mut alloc = allocator(&mem);
+        let ceiling = mem.start() + 16 * PAGE;
+        let low = AllocConstraints {
+            max_addr: ceiling,
+            ..AllocConstraints::NONE
+        };
+        let pos = alloc.alloc_pages_constrained(2, low).unwrap();
+        assert!(pos + 2 * PAGE <= ceiling);
+
+        let aligned = alloc
+            .alloc_pages_constrained(1, AllocConstraints::aligned(4 * PAGE))
+            .unwrap();
+        assert_eq!(aligned % (4 * PAGE), 0);
+
+        let boundary = AllocConstraints {
+            boundary: 4 * PAGE,
+            ..AllocConstraints::NONE
+        };
+        let pos = alloc.alloc_pages_constrained(3, boundary).unwrap();
+        assert_eq!(pos / (4 * PAGE), (pos + 3 * PAGE - 1) / (4 * PAGE));
+
+        let below = AllocConstraints {
+            max_addr: mem.start(),
+            ..AllocConstraints::NONE
+        };
+        assert_eq!(
+            alloc.alloc_pages_constrained(1, below),
+            Err(AllocError::NoMemory)
+        );
+        assert_eq!(
+            alloc.alloc_pages_constrained(5, boundary),
+            Err(AllocError::InvalidParam)
+        );
+    }
+}
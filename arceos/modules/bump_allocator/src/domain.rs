@@ -0,0 +1,232 @@
+//! Allocation domains: per-subsystem accounting and budgets.
+//!
+//! `alloc_for` and `alloc_pages_for` allocate on behalf of an [`AllocDomain`],
+//! e.g. the page tables or the device tree parser, and `dealloc_for` and
+//! `dealloc_pages_for` free with the same domain, as the layout is given
+//! back. Each domain counts its live bytes and pages, its peak and the
+//! allocations refused by its budget, given by [`EarlyAllocator::domain_stats`],
+//! so it is known which subsystem eats the early heap.
+//!
+//! `set_domain_budget` caps the bytes a domain holds, its pages counted as
+//! `PAGE_SIZE` bytes each: an allocation which would exceed it fails with
+//! `NoMemory`. The other allocations are not counted in any domain.
+//!
+//! With the `tracking` feature, the allocations of a domain are recorded
+//! with its name as their tag.
+//!
+//! [`EarlyAllocator::domain_stats`]: crate::EarlyAllocator::domain_stats
+
+use core::fmt;
+
+/// 分配域的数量
+pub const NR_DOMAINS: usize = 6;
+
+/// 分配所属的子系统
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocDomain {
+    /// 页表
+    PageTables,
+    /// 设备树的解析
+    DeviceTree,
+    /// 驱动的探测和初始化
+    Drivers,
+    /// 任务的栈和控制块
+    Tasks,
+    /// 文件系统
+    Fs,
+    /// 其他子系统
+    Other,
+}
+
+impl AllocDomain {
+    /// 所有的分配域，按编号顺序
+    pub const ALL: [Self; NR_DOMAINS] = [
+        Self::PageTables,
+        Self::DeviceTree,
+        Self::Drivers,
+        Self::Tasks,
+        Self::Fs,
+        Self::Other,
+    ];
+
+    /// 分配域的名字
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::PageTables => "page-tables",
+            Self::DeviceTree => "device-tree",
+            Self::Drivers => "drivers",
+            Self::Tasks => "tasks",
+            Self::Fs => "fs",
+            Self::Other => "other",
+        }
+    }
+
+    pub(crate) const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for AllocDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 一个分配域的使用统计，由 [`EarlyAllocator::domain_stats`] 返回
+///
+/// [`EarlyAllocator::domain_stats`]: crate::EarlyAllocator::domain_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DomainStats {
+    /// 存活的字节分配的字节数
+    pub live_bytes: usize,
+    /// 存活的页数
+    pub live_pages: usize,
+    /// 存活的字节数与页的字节数之和的峰值
+    pub peak_bytes: usize,
+    /// 成功的分配次数
+    pub allocs: usize,
+    /// 因超出预算被拒绝的分配次数
+    pub over_budget: usize,
+    /// 预算的字节数，`None` 表示不限
+    pub budget: Option<usize>,
+}
+
+impl DomainStats {
+    pub(crate) const EMPTY: Self = Self {
+        live_bytes: 0,
+        live_pages: 0,
+        peak_bytes: 0,
+        allocs: 0,
+        over_budget: 0,
+        budget: None,
+    };
+
+    /// 清零用量和计数，保留预算，用于 `init` 重新开始
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            budget: self.budget,
+            ..Self::EMPTY
+        };
+    }
+
+    /// 存活的字节数，页按 `page_size` 字节计
+    pub fn held_bytes(&self, page_size: usize) -> usize {
+        self.live_pages
+            .saturating_mul(page_size)
+            .saturating_add(self.live_bytes)
+    }
+
+    /// 再分配 `size` 字节是否不超出预算，超出时计数
+    pub(crate) fn admit(&mut self, size: usize, page_size: usize) -> bool {
+        let within = match self.budget {
+            Some(budget) => self.held_bytes(page_size).saturating_add(size) <= budget,
+            None => true,
+        };
+        if !within {
+            self.over_budget += 1;
+        }
+        within
+    }
+
+    /// 记录一次分配
+    pub(crate) fn on_alloc(&mut self, bytes: usize, pages: usize, page_size: usize) {
+        self.allocs += 1;
+        self.live_bytes += bytes;
+        self.live_pages += pages;
+        self.peak_bytes = self.peak_bytes.max(self.held_bytes(page_size));
+    }
+
+    /// 记录一次释放
+    pub(crate) fn on_free(&mut self, bytes: usize, pages: usize) {
+        self.live_bytes = self.live_bytes.saturating_sub(bytes);
+        self.live_pages = self.live_pages.saturating_sub(pages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{AllocError, BaseAllocator, PageAllocator};
+
+    #[test]
+    fn test_accounting() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let bytes = alloc
+            .alloc_for(AllocDomain::DeviceTree, layout(100))
+            .unwrap();
+        let pages = alloc
+            .alloc_pages_for(AllocDomain::DeviceTree, 2, 0)
+            .unwrap();
+        alloc.alloc_pages_for(AllocDomain::Tasks, 1, 0).unwrap();
+
+        let stats = alloc.domain_stats(AllocDomain::DeviceTree);
+        assert_eq!(
+            (stats.live_bytes, stats.live_pages, stats.allocs),
+            (100, 2, 2)
+        );
+        assert_eq!(stats.held_bytes(PAGE), 2 * PAGE + 100);
+        assert_eq!(alloc.domain_stats(AllocDomain::Tasks).live_pages, 1);
+        assert_eq!(alloc.domain_stats(AllocDomain::Fs), DomainStats::EMPTY);
+
+        alloc.dealloc_for(AllocDomain::DeviceTree, bytes, layout(100));
+        alloc.dealloc_pages_for(AllocDomain::DeviceTree, pages, 2);
+        let stats = alloc.domain_stats(AllocDomain::DeviceTree);
+        assert_eq!((stats.live_bytes, stats.live_pages), (0, 0));
+        assert_eq!(stats.peak_bytes, 2 * PAGE + 100);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let domain = AllocDomain::Drivers;
+        alloc.set_domain_budget(domain, Some(PAGE + 64));
+        let pos = alloc.alloc_pages_for(domain, 1, 0).unwrap();
+        alloc.alloc_for(domain, layout(64)).unwrap();
+        assert_eq!(
+            alloc.alloc_for(domain, layout(1)),
+            Err(AllocError::NoMemory)
+        );
+        assert_eq!(
+            alloc.alloc_pages_for(domain, 1, 0),
+            Err(AllocError::NoMemory)
+        );
+        let stats = alloc.domain_stats(domain);
+        assert_eq!((stats.over_budget, stats.budget), (2, Some(PAGE + 64)));
+        // 其他的分配不计入
+        alloc.alloc_pages(1, 0).unwrap();
+
+        alloc.dealloc_pages_for(domain, pos, 1);
+        alloc.alloc_for(domain, layout(PAGE)).unwrap();
+        alloc.set_domain_budget(domain, None);
+        alloc.alloc_pages_for(domain, 4, 0).unwrap();
+    }
+
+    #[test]
+    fn test_init_resets() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let domain = AllocDomain::Drivers;
+        alloc.set_domain_budget(domain, Some(2 * PAGE));
+        alloc.alloc_pages_for(domain, 2, 0).unwrap();
+        assert!(alloc.alloc_for(domain, layout(8)).is_err());
+
+        // 重新初始化后之前的分配不再计入，预算保留
+        alloc.init(mem.start(), mem.size());
+        let stats = alloc.domain_stats(domain);
+        assert_eq!((stats.live_pages, stats.allocs), (0, 0));
+        assert_eq!((stats.over_budget, stats.budget), (0, Some(2 * PAGE)));
+        alloc.alloc_pages_for(domain, 2, 0).unwrap();
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(AllocDomain::ALL.len(), NR_DOMAINS);
+        for (i, domain) in AllocDomain::ALL.iter().enumerate() {
+            assert_eq!(domain.index(), i);
+        }
+        assert_eq!(AllocDomain::PageTables.to_string(), "page-tables");
+    }
+}
@@ -0,0 +1,273 @@
+//! The early allocator as the `#[global_allocator]`, with the `global`
+//! feature.
+//!
+//! [`GlobalEarlyAllocator`] holds an [`EarlyAllocator`] behind a spinlock
+//! which disables the interrupts, so `alloc::vec::Vec` and the other
+//! collections work in the very early code, before `axalloc` is initialized:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static EARLY: GlobalEarlyAllocator = GlobalEarlyAllocator::new();
+//!
+//! EARLY.init(heap_start, heap_size);
+//! ```
+//!
+//! The failed allocations return a null pointer, as the [`GlobalAlloc`]
+//! contract requires, and the callers report them by
+//! `alloc::alloc::handle_alloc_error`.
+
+use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use kspin::{SpinNoIrq, SpinNoIrqGuard};
+
+use crate::{
+    AllocConstraints, AllocDomain, AllocStats, ConstrainedPageAllocator, DomainStats,
+    EarlyAllocator, ExtendHook, Handoff, HugePageAllocator, NodeStats, Snapshot, Watermark,
+    WatermarkHook,
+};
+
+/// 可作为 `#[global_allocator]` 的早期分配器
+pub struct GlobalEarlyAllocator<const PAGE_SIZE: usize = 4096> {
+    inner: SpinNoIrq<EarlyAllocator<PAGE_SIZE>>,
+}
+
+impl<const PAGE_SIZE: usize> GlobalEarlyAllocator<PAGE_SIZE> {
+    /// 创建一个空的分配器，可用于 `static` 的初始化
+    pub const fn new() -> Self {
+        Self {
+            inner: SpinNoIrq::new(EarlyAllocator::new()),
+        }
+    }
+
+    /// 以 `[start, start + size)` 初始化分配器，由 `axruntime` 在首次分配前调用
+    pub fn init(&self, start: usize, size: usize) {
+        self.inner.lock().init(start, size);
+    }
+
+    /// 加入一块空闲内存区域
+    pub fn add_memory(&self, start: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory(start, size)
+    }
+
+    /// 加入第 `node` 个 NUMA 节点上的一块空闲内存区域
+    pub fn add_memory_on(&self, node: usize, start: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory_on(node, start, size)
+    }
+
+    /// 保留 `[start, start + size)`，之后的分配都不会使用其中的地址
+    pub fn reserve(&self, start: usize, size: usize) -> AllocResult {
+        self.inner.lock().reserve(start, size)
+    }
+
+    /// 设置内存耗尽时扩展堆的回调，它在持有锁时调用，不能再分配
+    pub fn set_extend_hook(&self, hook: ExtendHook) {
+        self.inner.lock().set_extend_hook(hook)
+    }
+
+    /// 设置低内存水位线，见 [`EarlyAllocator::set_low_watermark`]，回调在持有锁
+    /// 时调用，不能再分配
+    pub fn set_low_watermark(&self, mark: Watermark, hook: Option<WatermarkHook>) -> AllocResult {
+        self.inner.lock().set_low_watermark(mark, hook)
+    }
+
+    /// 取走低于水位线的通知，见 [`EarlyAllocator::take_low_watermark`]
+    pub fn take_low_watermark(&self) -> bool {
+        self.inner.lock().take_low_watermark()
+    }
+
+    /// 分配 `num_pages` 个连续的页
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages(num_pages, align_pow2)
+    }
+
+    /// 分配 `num_pages` 个连续的页，优先使用第 `node` 个节点
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_on(
+        &self,
+        node: usize,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_on(node, num_pages, align_pow2)
+    }
+
+    /// 分配 `num_pages` 个清零的连续页
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_zeroed(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_zeroed(num_pages, align_pow2)
+    }
+
+    /// 分配 `base` 处的 `num_pages` 个页，见 [`EarlyAllocator::alloc_pages_at`]
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_at(&self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_at(base, num_pages)
+    }
+
+    /// 释放 `pos` 处的 `num_pages` 个页
+    pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
+        self.inner.lock().dealloc_pages(pos, num_pages)
+    }
+
+    /// 为分配域 `domain` 分配字节，见 [`EarlyAllocator::alloc_for`]
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_for(&self, domain: AllocDomain, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.inner.lock().alloc_for(domain, layout)
+    }
+
+    /// 释放为分配域 `domain` 分配的字节
+    pub fn dealloc_for(&self, domain: AllocDomain, pos: NonNull<u8>, layout: Layout) {
+        self.inner.lock().dealloc_for(domain, pos, layout)
+    }
+
+    /// 为分配域 `domain` 分配页，见 [`EarlyAllocator::alloc_pages_for`]
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_for(
+        &self,
+        domain: AllocDomain,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_for(domain, num_pages, align_pow2)
+    }
+
+    /// 释放为分配域 `domain` 分配的页
+    pub fn dealloc_pages_for(&self, domain: AllocDomain, pos: usize, num_pages: usize) {
+        self.inner.lock().dealloc_pages_for(domain, pos, num_pages)
+    }
+
+    /// 设置分配域 `domain` 的预算字节数，见 [`EarlyAllocator::set_domain_budget`]
+    pub fn set_domain_budget(&self, domain: AllocDomain, budget: Option<usize>) {
+        self.inner.lock().set_domain_budget(domain, budget)
+    }
+
+    /// 分配 `num` 个连续的 `page_size` 大小的页，见 [`HugePageAllocator`]
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_sized(&self, num: usize, page_size: usize) -> AllocResult<usize> {
+        self.inner.lock().alloc_pages_sized(num, page_size)
+    }
+
+    /// 分配 `num_pages` 个满足 `constraints` 的连续页
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_constrained(
+        &self,
+        num_pages: usize,
+        constraints: AllocConstraints,
+    ) -> AllocResult<usize> {
+        self.inner
+            .lock()
+            .alloc_pages_constrained(num_pages, constraints)
+    }
+
+    /// 释放 `pos` 处的 `num` 个 `page_size` 大小的页
+    pub fn dealloc_pages_sized(&self, pos: usize, num: usize, page_size: usize) {
+        self.inner.lock().dealloc_pages_sized(pos, num, page_size)
+    }
+
+    /// 封存分配器并移交剩余的内存，见 [`EarlyAllocator::take_remaining`]
+    pub fn take_remaining(&self) -> Handoff {
+        self.inner.lock().take_remaining()
+    }
+
+    /// 冻结分配器，之后只接受释放，见 [`EarlyAllocator::freeze`]
+    pub fn freeze(&self) {
+        self.inner.lock().freeze()
+    }
+
+    /// 自 `init` 以来的分配统计
+    pub fn stats(&self) -> AllocStats {
+        self.inner.lock().stats()
+    }
+
+    /// 第 `node` 个节点的使用统计
+    pub fn node_stats(&self, node: usize) -> NodeStats {
+        self.inner.lock().node_stats(node)
+    }
+
+    /// 分配域 `domain` 的使用统计
+    pub fn domain_stats(&self, domain: AllocDomain) -> DomainStats {
+        self.inner.lock().domain_stats(domain)
+    }
+
+    /// 当前布局的快照，见 [`EarlyAllocator::snapshot`]
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.lock().snapshot()
+    }
+
+    /// 设置释放时是否清零，见 [`EarlyAllocator::set_zero_on_free`]
+    pub fn set_zero_on_free(&self, enabled: bool) {
+        self.inner.lock().set_zero_on_free(enabled)
+    }
+
+    /// 锁住内部的分配器，用于其他操作
+    pub fn lock(&self) -> SpinNoIrqGuard<'_, EarlyAllocator<PAGE_SIZE>> {
+        self.inner.lock()
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for GlobalEarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const PAGE_SIZE: usize> GlobalAlloc for GlobalEarlyAllocator<PAGE_SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.inner.lock().alloc(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let pos = NonNull::new(ptr).expect("dealloc null ptr");
+        self.inner.lock().dealloc(pos, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let pos = NonNull::new(ptr).expect("realloc null ptr");
+        match self.inner.lock().realloc(pos, layout, new_size) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Memory, PAGE};
+
+    #[test]
+    fn test_global_alloc() {
+        let mem = Memory::new(16);
+        let global = GlobalEarlyAllocator::<PAGE>::new();
+        global.init(mem.start(), mem.size());
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        unsafe {
+            let a = GlobalAlloc::alloc(&global, layout);
+            assert!(!a.is_null());
+            a.write_bytes(0x11, 32);
+            // 最近的一次分配原地扩展
+            let b = GlobalAlloc::realloc(&global, a, layout, 64);
+            assert_eq!(a, b);
+            assert_eq!(*b.add(31), 0x11);
+            let huge = Layout::from_size_align(32 * PAGE, 8).unwrap();
+            assert!(GlobalAlloc::alloc(&global, huge).is_null());
+            GlobalAlloc::dealloc(&global, b, Layout::from_size_align(64, 8).unwrap());
+        }
+        assert_eq!(global.lock().live_byte_allocs(), 0);
+        assert_eq!(global.stats().failed_byte_allocs, 1);
+
+        let pos = global.alloc_pages(1, 0).unwrap();
+        assert_eq!(global.snapshot().live_pages, 1);
+        global.dealloc_pages(pos, 1);
+        global.freeze();
+        assert!(global.alloc_pages(1, 0).is_err());
+    }
+}
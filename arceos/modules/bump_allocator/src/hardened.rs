@@ -0,0 +1,213 @@
+//! Shadow records of the live allocations, with the `hardened` feature.
+//!
+//! Each live byte allocation and page allocation is recorded by its address
+//! and size, in tables kept apart from the allocated memory. A free must
+//! match a record: the double frees, the frees of never allocated pointers
+//! and the frees with a wrong size are rejected and counted in
+//! [`AllocStats::invalid_frees`] instead of corrupting the allocator.
+//!
+//! Each allocated range is also checked to lie in the free window of its
+//! region, and the allocator panics if it does not.
+//!
+//! The tables have a fixed capacity, the allocations fail when they are full.
+//!
+//! [`AllocStats::invalid_frees`]: crate::AllocStats::invalid_frees
+
+use allocator::AllocResult;
+
+use crate::state::{StateReader, StateWriter};
+
+/// 最多记录的存活字节分配数量
+pub const MAX_BYTE_RECORDS: usize = 256;
+/// 最多记录的存活页分配数量
+pub const MAX_PAGE_RECORDS: usize = 64;
+
+/// 一组存活分配的记录 `(pos, size)`，无序
+#[derive(Clone, Copy)]
+pub struct Records<const N: usize> {
+    records: [(usize, usize); N],
+    len: usize,
+}
+
+impl<const N: usize> Records<N> {
+    pub const EMPTY: Self = Self {
+        records: [(0, 0); N],
+        len: 0,
+    };
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// 记录一个分配，调用者需先检查 [`is_full`](Self::is_full)
+    pub fn insert(&mut self, pos: usize, size: usize) {
+        self.records[self.len] = (pos, size);
+        self.len += 1;
+    }
+
+    fn position(&self, pos: usize, size: usize) -> Option<usize> {
+        self.records[..self.len]
+            .iter()
+            .position(|&record| record == (pos, size))
+    }
+
+    /// 是否有 `pos` 处 `size` 大小的记录
+    pub fn contains(&self, pos: usize, size: usize) -> bool {
+        self.position(pos, size).is_some()
+    }
+
+    /// 删除 `pos` 处 `size` 大小的记录，没有时返回 `false`
+    pub fn remove(&mut self, pos: usize, size: usize) -> bool {
+        let Some(i) = self.position(pos, size) else {
+            return false;
+        };
+        self.len -= 1;
+        self.records[i] = self.records[self.len];
+        true
+    }
+
+    /// 将 `pos` 处 `size` 大小的记录改为 `new_size`
+    pub fn resize(&mut self, pos: usize, size: usize, new_size: usize) {
+        if let Some(i) = self.position(pos, size) {
+            self.records[i].1 = new_size;
+        }
+    }
+
+    /// 写入所有记录
+    pub fn save(&self, w: &mut StateWriter) {
+        w.word(self.len);
+        for &(pos, size) in &self.records[..self.len] {
+            w.word(pos);
+            w.word(size);
+        }
+    }
+
+    /// 读取 [`save`](Self::save) 写入的记录
+    pub fn load(r: &mut StateReader) -> AllocResult<Self> {
+        let mut records = Self::EMPTY;
+        records.len = r.len(N)?;
+        for record in &mut records.records[..records.len] {
+            *record = (r.word()?, r.word()?);
+        }
+        Ok(records)
+    }
+
+    /// 删除地址在 `[start, end)` 中的记录
+    pub fn remove_within(&mut self, start: usize, end: usize) {
+        let mut i = 0;
+        while i < self.len {
+            if (start..end).contains(&self.records[i].0) {
+                self.len -= 1;
+                self.records[i] = self.records[self.len];
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// 存活的字节分配和页分配的影子记录
+pub struct Shadow {
+    /// 字节分配 `(pos, size)`
+    pub bytes: Records<MAX_BYTE_RECORDS>,
+    /// 页分配 `(pos, num_pages)`
+    pub pages: Records<MAX_PAGE_RECORDS>,
+}
+
+impl Shadow {
+    pub const EMPTY: Self = Self {
+        bytes: Records::EMPTY,
+        pages: Records::EMPTY,
+    };
+
+    pub fn save(&self, w: &mut StateWriter) {
+        self.bytes.save(w);
+        self.pages.save(w);
+    }
+
+    pub fn load(r: &mut StateReader) -> AllocResult<Self> {
+        Ok(Self {
+            bytes: Records::load(r)?,
+            pages: Records::load(r)?,
+        })
+    }
+}
+
+/// 检查分配的范围 `[pos, end)` 在区域的可用范围 `[start, end)` 之内，越界时
+/// panic
+pub fn check_range(pos: usize, end: usize, area_start: usize, area_end: usize) {
+    assert!(
+        area_start <= pos && pos < end && end <= area_end,
+        "early allocator: allocation [{:#x}, {:#x}) out of [{:#x}, {:#x})",
+        pos,
+        end,
+        area_start,
+        area_end
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory};
+    use allocator::{ByteAllocator, PageAllocator};
+
+    #[test]
+    fn test_records() {
+        let mut records = Records::<2>::EMPTY;
+        records.insert(0x1000, 16);
+        records.insert(0x2000, 32);
+        assert!(records.is_full());
+        assert!(records.contains(0x2000, 32));
+        assert!(!records.contains(0x2000, 16));
+        records.resize(0x1000, 16, 64);
+        assert!(records.contains(0x1000, 64));
+        assert!(!records.remove(0x1000, 16));
+        assert!(records.remove(0x1000, 64));
+        assert!(!records.is_full());
+        records.insert(0x3000, 8);
+        records.remove_within(0x2000, 0x3000);
+        assert!(!records.contains(0x2000, 32));
+        assert!(records.contains(0x3000, 8));
+    }
+
+    #[test]
+    fn test_invalid_frees() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let a = alloc.alloc(layout(16)).unwrap();
+        let b = alloc.alloc(layout(16)).unwrap();
+        let pos = alloc.alloc_pages(2, 0).unwrap();
+
+        // 大小不符、重复释放和未分配的地址都被忽略
+        alloc.dealloc(a, layout(8));
+        alloc.dealloc(a, layout(16));
+        alloc.dealloc(a, layout(16));
+        alloc.dealloc(
+            core::ptr::NonNull::new(0x10 as *mut u8).unwrap(),
+            layout(16),
+        );
+        assert_eq!(alloc.live_byte_allocs(), 1);
+        alloc.dealloc_pages(pos, 1);
+        alloc.dealloc_pages(pos, 2);
+        alloc.dealloc_pages(pos, 2);
+        assert_eq!(alloc.used_pages(), 0);
+        assert_eq!(alloc.stats().invalid_frees, 5);
+        assert_eq!(
+            unsafe { alloc.realloc(a, layout(16), 32) },
+            Err(allocator::AllocError::InvalidParam)
+        );
+        alloc.dealloc(b, layout(16));
+        assert_eq!(alloc.live_byte_allocs(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of")]
+    fn test_check_range() {
+        check_range(0x1000, 0x2000, 0x1000, 0x1800);
+    }
+}
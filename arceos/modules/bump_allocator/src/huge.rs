@@ -0,0 +1,189 @@
+//! Pages larger than `PAGE_SIZE`, e.g. the 2 MiB and 1 GiB blocks of the
+//! riscv64 kernel linear map.
+//!
+//! [`HugePageAllocator`] extends [`PageAllocator`] with the allocations of
+//! `num` pages of a given size, aligned to that size. Such an allocation is
+//! made of `num * page_size / PAGE_SIZE` base pages, so `used_pages` and
+//! `total_pages` still count the base pages whatever the sizes mixed, and
+//! [`HugePageAllocator::used_pages_sized`] counts the live pages of each
+//! size.
+
+use allocator::{AllocError, AllocResult, PageAllocator};
+
+use crate::state::{StateReader, StateWriter};
+
+/// 最多同时统计的大页大小的种类数，`PAGE_SIZE` 本身不占用
+pub const MAX_PAGE_SIZES: usize = 4;
+
+/// 可分配多种大小的页的页分配器
+///
+/// 页大小 `page_size` 须为 `PAGE_SIZE` 乘以 2 的幂，分配的地址按它对齐。
+pub trait HugePageAllocator: PageAllocator {
+    /// 分配 `num` 个连续的 `page_size` 大小的页，返回起始地址
+    fn alloc_pages_sized(&mut self, num: usize, page_size: usize) -> AllocResult<usize>;
+
+    /// 释放 `pos` 处由 [`alloc_pages_sized`] 分配的 `num` 个 `page_size`
+    /// 大小的页
+    ///
+    /// [`alloc_pages_sized`]: Self::alloc_pages_sized
+    fn dealloc_pages_sized(&mut self, pos: usize, num: usize, page_size: usize);
+
+    /// 当前分配的 `page_size` 大小的页数，`PAGE_SIZE` 时为不属于大页的基本页数
+    fn used_pages_sized(&self, page_size: usize) -> usize;
+
+    /// 还可以分配多少个 `page_size` 大小的页（分别对齐后，不能同时分配）
+    fn available_pages_sized(&self, page_size: usize) -> usize;
+}
+
+/// `page_size` 相当于多少个基本页，不是 `base` 乘以 2 的幂时返回错误
+pub(crate) fn page_ratio(page_size: usize, base: usize) -> AllocResult<usize> {
+    let ratio = page_size / base;
+    if !ratio.is_power_of_two() || ratio * base != page_size {
+        return Err(AllocError::InvalidParam);
+    }
+    Ok(ratio)
+}
+
+/// 各种大页的存活页数
+#[derive(Clone, Copy)]
+pub(crate) struct SizedPages {
+    // `(page_size, live)`
+    sizes: [(usize, usize); MAX_PAGE_SIZES],
+    // 大小的种类数
+    len: usize,
+}
+
+impl SizedPages {
+    pub const EMPTY: Self = Self {
+        sizes: [(0, 0); MAX_PAGE_SIZES],
+        len: 0,
+    };
+
+    /// `page_size` 的存活页数
+    pub fn live(&self, page_size: usize) -> usize {
+        self.sizes[..self.len]
+            .iter()
+            .find(|&&(size, _)| size == page_size)
+            .map_or(0, |&(_, live)| live)
+    }
+
+    /// 所有大页占用的基本页数
+    pub fn base_pages(&self, base: usize) -> usize {
+        self.sizes[..self.len]
+            .iter()
+            .map(|&(size, live)| live * (size / base))
+            .sum()
+    }
+
+    /// 是否还能统计 `page_size`，种类已满且不含它时不能
+    pub fn has_room(&self, page_size: usize) -> bool {
+        self.len < MAX_PAGE_SIZES
+            || self.sizes[..self.len]
+                .iter()
+                .any(|&(size, _)| size == page_size)
+    }
+
+    /// 增加 `num` 个 `page_size` 的页，须先由 `has_room` 检查
+    pub fn add(&mut self, page_size: usize, num: usize) {
+        if let Some(entry) = self.sizes[..self.len]
+            .iter_mut()
+            .find(|(size, _)| *size == page_size)
+        {
+            entry.1 += num;
+            return;
+        }
+        self.sizes[self.len] = (page_size, num);
+        self.len += 1;
+    }
+
+    /// 写入各种大页的存活页数
+    pub fn save(&self, w: &mut StateWriter) {
+        w.word(self.len);
+        for &(size, live) in &self.sizes[..self.len] {
+            w.word(size);
+            w.word(live);
+        }
+    }
+
+    /// 读取 [`save`](Self::save) 写入的存活页数
+    pub fn load(r: &mut StateReader) -> AllocResult<Self> {
+        let mut sized = Self::EMPTY;
+        sized.len = r.len(MAX_PAGE_SIZES)?;
+        for entry in &mut sized.sizes[..sized.len] {
+            *entry = (r.word()?, r.word()?);
+        }
+        Ok(sized)
+    }
+
+    /// 减少 `num` 个 `page_size` 的页，减到 0 时腾出该种类
+    pub fn sub(&mut self, page_size: usize, num: usize) {
+        let Some(i) = self.sizes[..self.len]
+            .iter()
+            .position(|&(size, _)| size == page_size)
+        else {
+            return;
+        };
+        self.sizes[i].1 = self.sizes[i].1.saturating_sub(num);
+        if self.sizes[i].1 == 0 {
+            self.len -= 1;
+            self.sizes[i] = self.sizes[self.len];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, Memory, PAGE};
+
+    #[test]
+    fn test_page_ratio() {
+        assert_eq!(page_ratio(PAGE, PAGE), Ok(1));
+        assert_eq!(page_ratio(0x20_0000, PAGE), Ok(512));
+        assert_eq!(page_ratio(3 * PAGE, PAGE), Err(AllocError::InvalidParam));
+        assert_eq!(page_ratio(PAGE / 2, PAGE), Err(AllocError::InvalidParam));
+    }
+
+    #[test]
+    fn test_sized_pages() {
+        let mut sized = SizedPages::EMPTY;
+        for i in 1..=MAX_PAGE_SIZES {
+            assert!(sized.has_room(PAGE << i));
+            sized.add(PAGE << i, i);
+        }
+        assert!(!sized.has_room(PAGE << 8));
+        assert!(sized.has_room(PAGE << 1));
+        assert_eq!(sized.live(PAGE << 2), 2);
+        assert_eq!(sized.base_pages(PAGE), 2 + 2 * 4 + 3 * 8 + 4 * 16);
+        sized.sub(PAGE << 1, 1);
+        assert_eq!(sized.live(PAGE << 1), 0);
+        assert!(sized.has_room(PAGE << 8));
+    }
+
+    #[test]
+    fn test_alloc_pages_sized() {
+        let mem = Memory::new(64);
+        let mut alloc = allocator(&mem);
+        let size = 8 * PAGE;
+        let available = alloc.available_pages_sized(size);
+        assert!(available >= 5);
+
+        let base = alloc.alloc_pages(1, 0).unwrap();
+        let pos = alloc.alloc_pages_sized(2, size).unwrap();
+        assert_eq!(pos % size, 0);
+        assert_eq!(alloc.used_pages(), 17);
+        assert_eq!(alloc.used_pages_sized(size), 2);
+        assert_eq!(alloc.used_pages_sized(PAGE), 1);
+        assert_eq!(alloc.used_pages_sized(3 * PAGE), 0);
+        assert!(alloc.available_pages_sized(size) < available);
+        assert_eq!(
+            alloc.alloc_pages_sized(1, 3 * PAGE),
+            Err(AllocError::InvalidParam)
+        );
+
+        alloc.dealloc_pages_sized(pos, 2, size);
+        assert_eq!(alloc.used_pages_sized(size), 0);
+        alloc.dealloc_pages_sized(base, 1, PAGE);
+        assert_eq!(alloc.used_pages(), 0);
+    }
+}
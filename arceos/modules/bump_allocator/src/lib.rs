@@ -1,12 +1,54 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+
+mod atomic;
+#[cfg(feature = "page-bitmap")]
+mod bitmap;
+mod constrained;
+mod domain;
+#[cfg(feature = "global")]
+mod global;
+#[cfg(feature = "hardened")]
+mod hardened;
+mod huge;
+mod migrate;
+mod numa;
+#[cfg(feature = "debug-poison")]
+mod poison;
+mod pool;
+mod snapshot;
+mod state;
+mod stats;
+#[cfg(feature = "tracking")]
+mod track;
+mod watermark;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::atomic::AtomicEarlyAllocator;
+pub use self::constrained::{AllocConstraints, ConstrainedPageAllocator};
+pub use self::domain::{AllocDomain, DomainStats, NR_DOMAINS};
+#[cfg(feature = "global")]
+pub use self::global::GlobalEarlyAllocator;
+pub use self::huge::{HugePageAllocator, MAX_PAGE_SIZES};
+pub use self::migrate::{LiveKind, LiveRange};
+pub use self::numa::{NodeStats, MAX_NODES};
+pub use self::pool::ObjectPool;
+pub use self::snapshot::{RegionLayout, Snapshot};
+pub use self::state::{STATE_MAGIC, STATE_VERSION};
+pub use self::stats::{AllocStats, NR_SIZE_CLASSES};
+#[cfg(feature = "tracking")]
+pub use self::track::{AllocKind, AllocSite, LiveAlloc, MAX_TRACK_RECORDS};
+pub use self::watermark::{Watermark, WatermarkHook};
 
 use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
 use core::alloc::Layout;
+use core::fmt;
 use core::ptr::NonNull;
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
-/// This is a double-end memory range:
+/// Each memory region is a double-end memory range:
 /// - Alloc bytes forward
 /// - Alloc pages backward
 ///
@@ -14,11 +56,95 @@ use core::ptr::NonNull;
 /// |            | -->    <-- |            |
 /// start       b_pos        p_pos       end
 ///
-/// For bytes area, 'count' records number of allocations.
+/// Up to `MAX_REGIONS` disjoint regions can be used, the first one by
+/// `init` and the others by `add_memory`. An allocation is taken from the
+/// first region with enough room, so it spills into the next regions when
+/// the former ones are exhausted.
+///
+/// For bytes area, 'count' records number of allocations (of all regions).
 /// When it goes down to ZERO, free bytes-used area.
-/// For pages area, it will never be freed!
+/// For pages area, it is a stack: freeing the most recent allocation moves
+/// 'p_pos' back up. Other frees (and the gaps left by alignment) are kept
+/// as holes, and given back once they become contiguous with 'p_pos'.
+///
+/// With the `page-bitmap` feature, each region keeps a bitmap of its pages
+/// at its end instead: any pages can be freed and reused. The address
+/// computations never wrap around: an allocation which would overflow fails.
 ///
+/// The other features are described in their modules: the poisoning, the
+/// shadow records and the tracking of the allocations, the NUMA nodes, the
+/// allocation domains, the watermark, the constrained and huge pages, the
+/// migration to the formal allocators, the statistics, the snapshots and
+/// the saved state.
 pub struct EarlyAllocator<const PAGE_SIZE: usize = 4096> {
+    // 内存区域
+    regions: [Region; MAX_REGIONS],
+    // 内存区域数量
+    region_count: usize,
+    // 字节分配计数器
+    alloc_count: usize,
+    // 页区域中 p_pos 之上已释放的空洞
+    #[cfg(not(feature = "page-bitmap"))]
+    holes: [PageRange; MAX_PAGE_HOLES],
+    // 空洞数量
+    #[cfg(not(feature = "page-bitmap"))]
+    hole_count: usize,
+    // 是否已将剩余内存移交给正式分配器
+    sealed: bool,
+    // 是否已冻结，冻结后只接受释放
+    frozen: bool,
+    // 释放时是否清零被释放的内存
+    zero_on_free: bool,
+    // 内存耗尽时获取新区域的回调
+    extend_hook: Option<ExtendHook>,
+    // 低内存水位线
+    low_watermark: watermark::LowWatermark,
+    // 已移交的字节数
+    handed_bytes: usize,
+    // 当前分配的页数
+    live_pages: usize,
+    // 分配统计
+    stats: AllocStats,
+    // 各种大页的存活页数
+    sized: huge::SizedPages,
+    // 各节点的分配计数
+    node_counters: [numa::NodeCounters; MAX_NODES],
+    // 各分配域的统计和预算
+    domains: [DomainStats; NR_DOMAINS],
+    // 保留的地址范围，`init` 不清除
+    reserved: [PageRange; MAX_RESERVED],
+    // 保留范围数量
+    reserved_count: usize,
+    // 存活分配的影子记录
+    #[cfg(feature = "hardened")]
+    shadow: hardened::Shadow,
+    // 存活分配及其调用位置的记录
+    #[cfg(feature = "tracking")]
+    tracker: track::Tracker,
+}
+
+/// 最多管理的内存区域数量，包括被保留范围分割出的区域
+const MAX_REGIONS: usize = 16;
+
+/// 最多保留的地址范围数量
+pub const MAX_RESERVED: usize = 8;
+
+/// 内存耗尽时扩展堆的回调，参数为所需的字节数，返回新区域的 `(start, size)`
+pub type ExtendHook = fn(needed: usize) -> Option<(usize, usize)>;
+
+/// 每个字节分配前后的哨兵大小
+#[cfg(feature = "debug-poison")]
+const GUARD: usize = poison::WORD;
+#[cfg(not(feature = "debug-poison"))]
+const GUARD: usize = 0;
+
+/// 最多记录的页空洞数量，超出时该空洞将无法回收
+#[cfg(not(feature = "page-bitmap"))]
+const MAX_PAGE_HOLES: usize = 32;
+
+/// 一个双端分配的内存区域
+#[derive(Clone, Copy)]
+struct Region {
     // 内存区域起始地址
     start: usize,
     // 内存区域结束地址
@@ -27,132 +153,1491 @@ pub struct EarlyAllocator<const PAGE_SIZE: usize = 4096> {
     byte_pos: usize,
     // 页分配当前位置
     page_pos: usize,
-    // 字节分配计数器
+    // 所在的 NUMA 节点
+    node: usize,
+    // 页位图
+    #[cfg(feature = "page-bitmap")]
+    bitmap: bitmap::PageBitmap,
+}
+
+impl Region {
+    const EMPTY: Self = Self::new(0, 0);
+
+    const fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            byte_pos: start,
+            page_pos: end,
+            node: 0,
+            #[cfg(feature = "page-bitmap")]
+            bitmap: bitmap::PageBitmap::EMPTY,
+        }
+    }
+
+    /// 剩余可用的字节数
+    const fn avail(&self) -> usize {
+        self.page_pos - self.byte_pos
+    }
+}
+
+/// 字节区域的一个检查点，由 [`EarlyAllocator::checkpoint`] 创建
+///
+/// 回滚到它时，之后的字节分配全部作废
+#[derive(Clone, Copy)]
+pub struct BumpMark {
+    // 各区域的字节分配位置
+    byte_pos: [usize; MAX_REGIONS],
+    // 字节分配计数
     alloc_count: usize,
 }
 
+/// 早期分配器移交给正式分配器的剩余内存，由 [`EarlyAllocator::take_remaining`]
+/// 返回
+#[derive(Clone, Copy)]
+pub struct Handoff {
+    // 各区域的空闲窗口 `(start, size)`
+    ranges: [(usize, usize); MAX_REGIONS],
+    // 各空闲窗口所在的节点
+    nodes: [usize; MAX_REGIONS],
+    // 空闲窗口数量
+    range_count: usize,
+    /// 仍存活的字节分配个数
+    pub live_byte_allocs: usize,
+    /// 字节区域已使用的字节数
+    pub live_bytes: usize,
+    /// 仍被分配的页数
+    pub live_pages: usize,
+}
+
+impl Handoff {
+    /// 空闲的内存范围 `(start, size)`，可直接交给后继分配器的 `add_memory`
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges[..self.range_count]
+    }
+
+    /// 各空闲范围所在的 NUMA 节点，与 [`ranges`] 一一对应
+    ///
+    /// [`ranges`]: Self::ranges
+    pub fn nodes(&self) -> &[usize] {
+        &self.nodes[..self.range_count]
+    }
+
+    /// 空闲范围的总字节数
+    pub fn free_bytes(&self) -> usize {
+        self.ranges().iter().map(|&(_, size)| size).sum()
+    }
+}
+
+/// 一段地址范围 `[start, end)`
+#[derive(Clone, Copy)]
+struct PageRange {
+    start: usize,
+    end: usize,
+}
+
+impl PageRange {
+    const EMPTY: Self = Self { start: 0, end: 0 };
+}
+
 impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
     /// 创建一个新的早期内存分配器
     pub const fn new() -> Self {
         Self {
-            start: 0,
-            end: 0,
-            byte_pos: 0,
-            page_pos: 0,
+            regions: [Region::EMPTY; MAX_REGIONS],
+            region_count: 0,
             alloc_count: 0,
+            #[cfg(not(feature = "page-bitmap"))]
+            holes: [PageRange::EMPTY; MAX_PAGE_HOLES],
+            #[cfg(not(feature = "page-bitmap"))]
+            hole_count: 0,
+            sealed: false,
+            frozen: false,
+            zero_on_free: false,
+            extend_hook: None,
+            low_watermark: watermark::LowWatermark::EMPTY,
+            handed_bytes: 0,
+            live_pages: 0,
+            stats: AllocStats::EMPTY,
+            sized: huge::SizedPages::EMPTY,
+            node_counters: [numa::NodeCounters::EMPTY; MAX_NODES],
+            domains: [DomainStats::EMPTY; NR_DOMAINS],
+            reserved: [PageRange::EMPTY; MAX_RESERVED],
+            reserved_count: 0,
+            #[cfg(feature = "hardened")]
+            shadow: hardened::Shadow::EMPTY,
+            #[cfg(feature = "tracking")]
+            tracker: track::Tracker::EMPTY,
         }
     }
-}
 
-impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
-    /// Initialize the allocator with a free memory region.
-    fn init(&mut self, start: usize, size: usize) {
-        self.start = start;
-        self.end = start + size;
-        self.byte_pos = start;
-        self.page_pos = self.end;
-        self.alloc_count = 0;
+    fn regions(&self) -> &[Region] {
+        &self.regions[..self.region_count]
     }
 
-    /// Add a free memory region to the allocator.
-    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
-        // 早期分配器不支持添加新的内存区域
-        Err(AllocError::NoMemory)
+    /// 各区域字节区域已使用的字节数
+    fn byte_area_used(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|region| region.byte_pos - region.start)
+            .sum()
     }
-}
 
-impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
-    /// Allocate memory with the given size (in bytes) and alignment.
-    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+    /// 自 `init` 以来的分配统计
+    pub fn stats(&self) -> AllocStats {
+        self.stats
+    }
+
+    /// 当前布局的快照：各区域的位置、保留范围和存活的分配数
+    pub fn snapshot(&self) -> Snapshot {
+        let regions = self.regions().iter().map(|region| RegionLayout {
+            start: region.start,
+            byte_pos: region.byte_pos,
+            page_pos: region.page_pos,
+            end: region.end,
+            node: region.node,
+            hole_bytes: self.region_hole_bytes(region),
+        });
+        Snapshot::new(
+            regions,
+            self.reserved(),
+            self.alloc_count,
+            self.used_pages(),
+            self.sealed,
+            self.frozen,
+        )
+    }
+
+    /// 记录字节区域的当前状态，用于一批临时分配之后的 [`rollback`]
+    ///
+    /// [`rollback`]: Self::rollback
+    pub fn checkpoint(&self) -> BumpMark {
+        let mut byte_pos = [0; MAX_REGIONS];
+        for (pos, region) in byte_pos.iter_mut().zip(self.regions()) {
+            *pos = region.byte_pos;
+        }
+        BumpMark {
+            byte_pos,
+            alloc_count: self.alloc_count,
+        }
+    }
+
+    /// 将字节区域恢复到 `mark` 的状态，一次性丢弃之后的所有字节分配
+    ///
+    /// 这些分配不能再被使用，也不必再释放。检查点之前的分配若已全部释放
+    /// （字节区域已被重置），则保持重置后的状态。
+    pub fn rollback(&mut self, mark: BumpMark) {
+        self.alloc_count = self.alloc_count.min(mark.alloc_count);
+        // 封存后字节区域之后的窗口已移交，丢弃的字节不再复用
+        if self.sealed {
+            return;
+        }
+        let regions = &mut self.regions[..self.region_count];
+        for (region, &pos) in regions.iter_mut().zip(&mark.byte_pos) {
+            // 检查点之后加入的区域仍为 0，不会越过起始地址
+            let pos = region.byte_pos.min(pos.max(region.start));
+            #[cfg(feature = "hardened")]
+            self.shadow.bytes.remove_within(pos, region.byte_pos);
+            #[cfg(feature = "tracking")]
+            self.tracker.remove_bytes_within(pos, region.byte_pos);
+            #[cfg(feature = "debug-poison")]
+            unsafe {
+                poison::fill(pos, region.byte_pos, poison::FREED)
+            };
+            if self.zero_on_free {
+                unsafe { zero(pos, region.byte_pos) };
+            }
+            region.byte_pos = pos;
+        }
+    }
+
+    /// 封存分配器，将各区域的空闲窗口 `[byte_pos, page_pos)` 移交出去
+    ///
+    /// 之后的分配全部失败，已有的分配仍可释放，但释放的内存不再被复用。
+    /// 再次调用时返回空的范围。
+    pub fn take_remaining(&mut self) -> Handoff {
+        let mut handoff = Handoff {
+            ranges: [(0, 0); MAX_REGIONS],
+            nodes: [0; MAX_REGIONS],
+            range_count: 0,
+            live_byte_allocs: self.alloc_count,
+            live_bytes: self.byte_area_used(),
+            live_pages: self.used_pages(),
+        };
+        if self.sealed {
+            return handoff;
+        }
+        for region in self.regions[..self.region_count].iter_mut() {
+            let size = region.avail();
+            if size > 0 {
+                handoff.ranges[handoff.range_count] = (region.byte_pos, size);
+                handoff.nodes[handoff.range_count] = region.node;
+                handoff.range_count += 1;
+            }
+            // 窗口已不属于本分配器
+            region.byte_pos = region.page_pos;
+            self.handed_bytes += size;
+        }
+        self.sealed = true;
+        handoff
+    }
+
+    /// 是否已由 [`take_remaining`] 封存
+    ///
+    /// [`take_remaining`]: Self::take_remaining
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    /// 冻结分配器：之后的分配全部失败，释放仍然有效
+    ///
+    /// 与 [`take_remaining`] 不同，空闲窗口不移交，由 [`live_ranges`] 列出
+    /// 仍被使用的范围，正式分配器接管全部内存时将它们标记为已分配。
+    ///
+    /// [`take_remaining`]: Self::take_remaining
+    /// [`live_ranges`]: Self::live_ranges
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// 是否已由 [`freeze`] 冻结
+    ///
+    /// [`freeze`]: Self::freeze
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// 仍被使用的范围，按页向外对齐：各区域的字节区域、被分配的页（不含
+    /// 空洞）、记录表和页位图，以及保留的范围
+    ///
+    /// 其余的内存都是空闲的。对齐后相邻的范围可能共用首尾的页。在 [`freeze`]
+    /// 之后调用，它们才不会再增加。
+    ///
+    /// [`freeze`]: Self::freeze
+    pub fn live_ranges(&self) -> impl Iterator<Item = LiveRange> + '_ {
+        let bytes = self
+            .regions()
+            .iter()
+            .filter(|region| region.byte_pos > region.start)
+            .map(|region| (LiveKind::Bytes, region.start, region.byte_pos));
+        let pages = self.regions().iter().flat_map(move |region| {
+            self.page_runs(region)
+                .map(|(start, end)| (LiveKind::Pages, start, end))
+        });
+        let metadata = self
+            .metadata()
+            .map(|(start, end)| (LiveKind::Metadata, start, end));
+        let reserved = self.reserved[..self.reserved_count]
+            .iter()
+            .map(|range| (LiveKind::Reserved, range.start, range.end));
+        bytes
+            .chain(pages)
+            .chain(metadata)
+            .chain(reserved)
+            .map(|(kind, start, end)| LiveRange::new(kind, start, end, PAGE_SIZE))
+    }
+
+    /// 仍存活的字节分配个数，降为 0 时各区域的字节区域不再被使用
+    pub fn live_byte_allocs(&self) -> usize {
+        self.alloc_count
+    }
+
+    /// `pos` 是否在某个区域的字节区域内，即是否为本分配器的字节分配
+    pub fn owns_bytes(&self, pos: usize) -> bool {
+        self.regions()
+            .iter()
+            .any(|region| (region.start..region.byte_pos).contains(&pos))
+    }
+
+    /// 分配字节，记录的调用位置为标签 `tag`
+    #[cfg(feature = "tracking")]
+    pub fn alloc_tagged(&mut self, layout: Layout, tag: &'static str) -> AllocResult<NonNull<u8>> {
+        let pos = self.alloc(layout)?;
+        self.tracker
+            .retag(AllocKind::Bytes, pos.as_ptr() as usize, tag);
+        Ok(pos)
+    }
+
+    /// 分配页，记录的调用位置为标签 `tag`
+    #[cfg(feature = "tracking")]
+    pub fn alloc_pages_tagged(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+        tag: &'static str,
+    ) -> AllocResult<usize> {
+        let pos = self.alloc_pages(num_pages, align_pow2)?;
+        self.tracker.retag(AllocKind::Pages, pos, tag);
+        Ok(pos)
+    }
+
+    /// 列出仍存活的分配及其调用位置，未记录的分配见 [`untracked_allocs`]
+    ///
+    /// [`untracked_allocs`]: Self::untracked_allocs
+    #[cfg(feature = "tracking")]
+    pub fn report_leaks(&self) -> impl Iterator<Item = &LiveAlloc> {
+        self.tracker.records().iter()
+    }
+
+    /// 因记录表已满而未被记录的分配次数
+    #[cfg(feature = "tracking")]
+    pub fn untracked_allocs(&self) -> usize {
+        self.tracker.untracked()
+    }
+
+    /// 为分配域 `domain` 分配字节，超出它的预算时返回 [`AllocError::NoMemory`]
+    ///
+    /// 须由 [`dealloc_for`] 以同一个域释放。
+    ///
+    /// [`dealloc_for`]: Self::dealloc_for
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_for(&mut self, domain: AllocDomain, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if !self.domains[domain.index()].admit(layout.size(), PAGE_SIZE) {
+            return Err(AllocError::NoMemory);
+        }
+        let pos = self.alloc(layout)?;
+        self.domains[domain.index()].on_alloc(layout.size(), 0, PAGE_SIZE);
+        #[cfg(feature = "tracking")]
+        self.tracker
+            .retag(AllocKind::Bytes, pos.as_ptr() as usize, domain.name());
+        Ok(pos)
+    }
+
+    /// 释放由 [`alloc_for`] 为分配域 `domain` 分配的字节
+    ///
+    /// [`alloc_for`]: Self::alloc_for
+    pub fn dealloc_for(&mut self, domain: AllocDomain, pos: NonNull<u8>, layout: Layout) {
+        let live = self.alloc_count;
+        self.dealloc(pos, layout);
+        // 被忽略的释放不减少计数
+        if self.alloc_count < live {
+            self.domains[domain.index()].on_free(layout.size(), 0);
+        }
+    }
+
+    /// 为分配域 `domain` 分配页，超出它的预算时返回 [`AllocError::NoMemory`]
+    ///
+    /// 须由 [`dealloc_pages_for`] 以同一个域释放。
+    ///
+    /// [`dealloc_pages_for`]: Self::dealloc_pages_for
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_for(
+        &mut self,
+        domain: AllocDomain,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        let size = num_pages.saturating_mul(PAGE_SIZE);
+        if !self.domains[domain.index()].admit(size, PAGE_SIZE) {
+            return Err(AllocError::NoMemory);
+        }
+        let pos = self.alloc_pages(num_pages, align_pow2)?;
+        self.domains[domain.index()].on_alloc(0, num_pages, PAGE_SIZE);
+        #[cfg(feature = "tracking")]
+        self.tracker.retag(AllocKind::Pages, pos, domain.name());
+        Ok(pos)
+    }
+
+    /// 释放由 [`alloc_pages_for`] 为分配域 `domain` 分配的页
+    ///
+    /// [`alloc_pages_for`]: Self::alloc_pages_for
+    pub fn dealloc_pages_for(&mut self, domain: AllocDomain, pos: usize, num_pages: usize) {
+        let live_pages = self.live_pages;
+        self.dealloc_pages(pos, num_pages);
+        if self.live_pages < live_pages {
+            self.domains[domain.index()].on_free(0, num_pages);
+        }
+    }
+
+    /// 设置分配域 `domain` 的预算字节数，页按 `PAGE_SIZE` 字节计，`None` 表示
+    /// 不限
+    ///
+    /// 已超出新预算的域保留已有的分配，之后的分配失败。
+    pub fn set_domain_budget(&mut self, domain: AllocDomain, budget: Option<usize>) {
+        self.domains[domain.index()].budget = budget;
+    }
+
+    /// 分配域 `domain` 的使用统计
+    pub fn domain_stats(&self, domain: AllocDomain) -> DomainStats {
+        self.domains[domain.index()]
+    }
+
+    /// 加入第 `node` 个 NUMA 节点上的一块空闲内存区域
+    pub fn add_memory_on(&mut self, node: usize, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        if size == 0 || node >= MAX_NODES {
+            return Err(AllocError::InvalidParam);
+        }
+        if self
+            .regions()
+            .iter()
+            .any(|region| start < region.end && region.start < end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        // 除去保留的范围后，每一段成为一个区域
+        let mut pieces = 0;
+        let mut pos = start;
+        while let Some((_, piece_end)) = self.next_piece(pos, end) {
+            pieces += 1;
+            pos = piece_end;
+        }
+        // 区域列表已满
+        if self.region_count + pieces > MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        let mut pos = start;
+        while let Some((piece_start, piece_end)) = self.next_piece(pos, end) {
+            self.regions[self.region_count] = Region {
+                node,
+                ..Self::new_region(piece_start, piece_end)
+            };
+            self.region_count += 1;
+            pos = piece_end;
+        }
+        Ok(())
+    }
+
+    /// 设置内存耗尽时扩展堆的回调
+    ///
+    /// 分配因空间不足失败时，以所需的字节数（分配的大小加上对齐，不含页位图
+    /// 等元数据）调用 `hook`，它返回的区域加入节点 0 后重试分配，直到分配
+    /// 成功、`hook` 返回 `None` 或区域无法加入。`hook` 在分配器内部调用，
+    /// 不能再从这个分配器分配。`init` 不清除它，封存或冻结后不再调用它。
+    pub fn set_extend_hook(&mut self, hook: ExtendHook) {
+        self.extend_hook = Some(hook);
+    }
+
+    /// 调用扩展回调加入一个至少能容纳 `needed` 字节的区域，返回是否加入
+    fn extend(&mut self, needed: usize) -> bool {
+        if self.sealed || self.frozen {
+            return false;
+        }
+        let Some((start, size)) = self.extend_hook.and_then(|hook| hook(needed)) else {
+            return false;
+        };
+        self.add_memory_on(0, start, size).is_ok()
+    }
+
+    /// 设置低内存水位线
+    ///
+    /// 每次分配成功后检查空闲字节数（即 `available_bytes`），刚低于 `mark`
+    /// 时调用 `hook` 并设置通知标志，回到水位线之上后再次生效。`hook` 在
+    /// 分配器内部调用，不能再从这个分配器分配。百分比超过 100 时返回
+    /// [`AllocError::InvalidParam`]。`init` 不清除它，封存或冻结后不再检查。
+    pub fn set_low_watermark(
+        &mut self,
+        mark: Watermark,
+        hook: Option<WatermarkHook>,
+    ) -> AllocResult {
+        self.low_watermark.set(mark, hook)
+    }
+
+    /// 清除低内存水位线
+    pub fn clear_low_watermark(&mut self) {
+        self.low_watermark.clear();
+    }
+
+    /// 当前的空闲字节数是否低于水位线，未设置时为 `false`
+    pub fn is_below_watermark(&self) -> bool {
+        self.low_watermark
+            .mark()
+            .is_some_and(|mark| self.available_bytes() < mark.threshold(self.total_bytes()))
+    }
+
+    /// 取走低于水位线的通知，返回自上次调用以来是否低于过水位线
+    pub fn take_low_watermark(&mut self) -> bool {
+        self.low_watermark.take()
+    }
+
+    /// 分配后检查水位线
+    fn check_watermark(&mut self) {
+        let (available, total) = (self.available_bytes(), self.total_bytes());
+        self.low_watermark.check(available, total);
+    }
+
+    /// 保留 `[start, start + size)`，之后的分配都不会使用其中的地址
+    ///
+    /// 在 `init` 之前保留时，`init` 和 `add_memory` 加入的区域除去保留的
+    /// 范围。在之后保留时，范围须在各区域的空闲窗口 `[byte_pos, page_pos)`
+    /// 之内，区域在它两侧分割为两个区域；与已分配的内存、位图或记录表重叠时
+    /// 返回 [`AllocError::MemoryOverlap`]。保留不能撤销，`init` 也不清除。
+    pub fn reserve(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        if size == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.reserved_count == MAX_RESERVED {
+            return Err(AllocError::NoMemory);
+        }
+        // 区域之外的元数据
+        #[cfg(feature = "tracking")]
+        if overlaps(self.tracker.table(), start, end) {
+            return Err(AllocError::MemoryOverlap);
+        }
+        #[cfg(feature = "page-bitmap")]
+        if self
+            .regions()
+            .iter()
+            .any(|region| overlaps(region.bitmap.storage(), start, end))
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        // 先检查所有重叠的区域，都能分割时才修改
+        let mut splits = 0;
+        for region in self.regions() {
+            let (lo, hi) = (start.max(region.start), end.min(region.end));
+            if lo >= hi {
+                continue;
+            }
+            if lo < region.byte_pos || hi > region.page_pos {
+                return Err(AllocError::MemoryOverlap);
+            }
+            if hi < region.end {
+                splits += 1;
+            }
+        }
+        if self.region_count + splits > MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        for idx in 0..self.region_count {
+            let region = self.regions[idx];
+            let (lo, hi) = (start.max(region.start), end.min(region.end));
+            if lo >= hi {
+                continue;
+            }
+            // 之上的部分加到末尾，不改变已有区域的下标（检查点按下标记录）
+            if hi < region.end {
+                self.regions[self.region_count] = Region {
+                    start: hi,
+                    byte_pos: hi,
+                    ..region
+                };
+                self.region_count += 1;
+            }
+            // 之下的部分留在原位，可能为空
+            self.regions[idx] = Region {
+                end: lo,
+                page_pos: lo,
+                #[cfg(feature = "page-bitmap")]
+                bitmap: region.bitmap.truncate(lo),
+                ..region
+            };
+        }
+        self.reserved[self.reserved_count] = PageRange { start, end };
+        self.reserved_count += 1;
+        Ok(())
+    }
+
+    /// 保留的地址范围 `(start, size)`
+    pub fn reserved(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.reserved[..self.reserved_count]
+            .iter()
+            .map(|range| (range.start, range.end - range.start))
+    }
+
+    /// 分配字节，优先使用第 `node` 个节点上的区域，不足时使用其他节点
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_on(&mut self, node: usize, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if node >= MAX_NODES {
+            return Err(AllocError::InvalidParam);
+        }
+        self.alloc_on_node(layout, Some(node))
+    }
+
+    /// 分配页，优先使用第 `node` 个节点上的区域，不足时使用其他节点
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_on(
+        &mut self,
+        node: usize,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        if node >= MAX_NODES {
+            return Err(AllocError::InvalidParam);
+        }
+        let constraints = AllocConstraints::aligned(Self::page_align(align_pow2)?);
+        self.alloc_pages_on_node(num_pages, constraints, Some(node))
+    }
+
+    /// 设置释放时是否清零被释放的内存，包括 `rollback` 丢弃的字节分配和
+    /// 原地收缩时多出的部分
+    ///
+    /// 清零在 `debug-poison` 的毒化之后进行，一个启动阶段留下的内容（如密钥）
+    /// 不会泄露给之后的分配。[`alloc_zeroed`] 和 [`alloc_pages_zeroed`] 总是
+    /// 返回清零的内存。
+    ///
+    /// [`alloc_zeroed`]: Self::alloc_zeroed
+    /// [`alloc_pages_zeroed`]: Self::alloc_pages_zeroed
+    pub fn set_zero_on_free(&mut self, enabled: bool) {
+        self.zero_on_free = enabled;
+    }
+
+    /// 释放时是否清零被释放的内存
+    pub fn zero_on_free(&self) -> bool {
+        self.zero_on_free
+    }
+
+    /// 分配清零的字节
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_zeroed(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let ptr = self.alloc_on_node(layout, None)?;
+        unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0, layout.size()) };
+        Ok(ptr)
+    }
+
+    /// 分配清零的页，`align_pow2` 同 [`PageAllocator::alloc_pages`]
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_zeroed(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        let pos = self.alloc_pages(num_pages, align_pow2)?;
+        unsafe { core::ptr::write_bytes(pos as *mut u8, 0, num_pages * PAGE_SIZE) };
+        Ok(pos)
+    }
+
+    /// 分配 `base` 处的 `num_pages` 个页，返回 `base`，用于须位于固定地址的
+    /// 页表、固件交接结构等
+    ///
+    /// 范围须在一个区域中且仍然空闲：在空闲窗口 `[byte_pos, page_pos)` 中的
+    /// 部分使 page_pos 下移到 `base`，其上的间隙记为空洞；page_pos 之上的部分
+    /// 须为已释放的页。与已分配的内存、保留的范围或元数据重叠时返回
+    /// [`AllocError::MemoryOverlap`]，不在任何区域中时返回
+    /// [`AllocError::NoMemory`]，`base` 未按页对齐时返回
+    /// [`AllocError::InvalidParam`]。
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub fn alloc_pages_at(&mut self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        if num_pages == 0 || base & (PAGE_SIZE - 1) != 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let end = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| base.checked_add(size))
+            .ok_or(AllocError::InvalidParam)?;
+        if self.sealed || self.frozen {
+            self.stats.failed_page_allocs += 1;
+            return Err(AllocError::NoMemory);
+        }
+        #[cfg(feature = "hardened")]
+        if self.shadow.pages.is_full() {
+            self.stats.failed_page_allocs += 1;
+            return Err(AllocError::NoMemory);
+        }
+        if let Err(err) = self.alloc_pages_at_in(base, end) {
+            self.stats.failed_page_allocs += 1;
+            return Err(err);
+        }
+        self.record_pages(base, num_pages, None);
+        Ok(base)
+    }
+
+    /// 第 `node` 个节点的使用统计
+    pub fn node_stats(&self, node: usize) -> NodeStats {
+        let mut stats = NodeStats::default();
+        for region in self.regions().iter().filter(|region| region.node == node) {
+            stats.regions += 1;
+            stats.total_bytes += region.end - region.start;
+            stats.used_bytes += region.byte_pos - region.start;
+            stats.used_pages +=
+                (region.end - region.page_pos - self.region_hole_bytes(region)) / PAGE_SIZE;
+            stats.available_bytes += region.avail();
+        }
+        if let Some(counters) = self.node_counters.get(node) {
+            stats.byte_allocs = counters.byte_allocs;
+            stats.page_allocs = counters.page_allocs;
+            stats.fallbacks = counters.fallbacks;
+        }
+        stats
+    }
+
+    /// 将字节分配 `pos` 的大小调整为 `new_size`，返回新的地址
+    ///
+    /// 它是所在区域最近的一次分配（紧邻 byte_pos）且空间足够时，原地扩展或
+    /// 收缩；否则分配一块新的内存，复制内容后释放原分配。
+    ///
+    /// # Safety
+    ///
+    /// `pos` must be a live allocation of `layout` from this allocator.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    pub unsafe fn realloc(
+        &mut self,
+        pos: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> AllocResult<NonNull<u8>> {
+        if new_size == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.sealed || self.frozen {
+            self.stats.failed_byte_allocs += 1;
+            return Err(AllocError::NoMemory);
+        }
+        let start = pos.as_ptr() as usize;
+        #[cfg(feature = "hardened")]
+        if !self.shadow.bytes.contains(start, layout.size()) {
+            self.stats.invalid_frees += 1;
+            return Err(AllocError::InvalidParam);
+        }
+        let old_end = start.checked_add(layout.size() + GUARD);
+        if let Some(region) = self.regions[..self.region_count]
+            .iter_mut()
+            .find(|region| region.start <= start && Some(region.byte_pos) == old_end)
+        {
+            if let Some(new_end) = new_size
+                .checked_add(GUARD)
+                .and_then(|size| start.checked_add(size))
+            {
+                if new_end <= region.page_pos {
+                    #[cfg(feature = "debug-poison")]
+                    {
+                        let old_size = layout.size();
+                        poison::check(start, old_size);
+                        if new_size > old_size {
+                            poison::fill(start + old_size, start + new_size, poison::ALLOC);
+                        } else {
+                            poison::fill(start + new_size, region.byte_pos, poison::FREED);
+                        }
+                        poison::set_tail(start, new_size);
+                    }
+                    if self.zero_on_free {
+                        zero(new_end, region.byte_pos);
+                    }
+                    region.byte_pos = new_end;
+                    #[cfg(feature = "hardened")]
+                    self.shadow.bytes.resize(start, layout.size(), new_size);
+                    #[cfg(feature = "tracking")]
+                    self.tracker.resize(start, new_size);
+                    let used = self.byte_area_used();
+                    self.stats.on_byte_grow(used);
+                    self.check_watermark();
+                    return Ok(pos);
+                }
+            }
+        }
+
+        let new_layout = Layout::from_size_align(new_size, layout.align())
+            .map_err(|_| AllocError::InvalidParam)?;
+        // 优先留在原分配所在的节点
+        let new_pos = self.alloc_on_node(new_layout, self.node_of(start))?;
+        core::ptr::copy_nonoverlapping(pos.as_ptr(), new_pos.as_ptr(), layout.size().min(new_size));
+        // 新分配已计数，释放原分配不会重置字节区域
+        self.dealloc(pos, layout);
+        Ok(new_pos)
+    }
+
+    /// 分配字节，`node` 上的区域优先
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn alloc_on_node(&mut self, layout: Layout, node: Option<usize>) -> AllocResult<NonNull<u8>> {
+        let mut result = self.alloc_bytes(layout, node);
+        // 空间不足时扩展堆后重试
+        while matches!(result, Err(AllocError::NoMemory))
+            && self.extend(layout.size().saturating_add(layout.align() + 2 * GUARD))
+        {
+            result = self.alloc_bytes(layout, node);
+        }
+        match result {
+            Ok(pos) => {
+                let used = self.byte_area_used();
+                self.stats.on_byte_alloc(layout.size(), used);
+                #[cfg(feature = "tracking")]
+                self.tracker.insert(
+                    AllocKind::Bytes,
+                    pos.as_ptr() as usize,
+                    layout.size(),
+                    AllocSite::Caller(core::panic::Location::caller()),
+                );
+                self.count_on_node(pos.as_ptr() as usize, node, false);
+                self.check_watermark();
+            }
+            Err(AllocError::NoMemory) => self.stats.failed_byte_allocs += 1,
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// 分配页，`node` 上的区域优先
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn alloc_pages_on_node(
+        &mut self,
+        num_pages: usize,
+        constraints: AllocConstraints,
+        node: Option<usize>,
+    ) -> AllocResult<usize> {
+        if num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.sealed || self.frozen {
+            self.stats.failed_page_allocs += 1;
+            return Err(AllocError::NoMemory);
+        }
+
+        #[cfg(feature = "hardened")]
+        if self.shadow.pages.is_full() {
+            self.stats.failed_page_allocs += 1;
+            return Err(AllocError::NoMemory);
+        }
+
+        let bytes_size = num_pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(AllocError::NoMemory)?;
+        let constraints = constraints.resolve(bytes_size, PAGE_SIZE)?;
+
+        // 依次尝试各个区域，当前区域耗尽时溢出到后续区域
+        let mut found = self
+            .region_order(node)
+            .find_map(|idx| self.alloc_pages_in(idx, bytes_size, &constraints));
+        // 都耗尽时扩展堆后重试
+        while found.is_none() && self.extend(bytes_size.saturating_add(constraints.align)) {
+            found = self
+                .region_order(node)
+                .find_map(|idx| self.alloc_pages_in(idx, bytes_size, &constraints));
+        }
+        let Some(pos) = found else {
+            self.stats.failed_page_allocs += 1;
+            return Err(AllocError::NoMemory);
+        };
+        self.record_pages(pos, num_pages, node);
+        Ok(pos)
+    }
+
+    /// 记录 `pos` 处分配的 `num_pages` 个页，请求的节点为 `node`
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn record_pages(&mut self, pos: usize, num_pages: usize, node: Option<usize>) {
+        #[cfg(feature = "hardened")]
+        self.shadow.pages.insert(pos, num_pages);
+        #[cfg(feature = "tracking")]
+        self.tracker.insert(
+            AllocKind::Pages,
+            pos,
+            num_pages * PAGE_SIZE,
+            AllocSite::Caller(core::panic::Location::caller()),
+        );
+        self.live_pages += num_pages;
+        self.stats.on_page_alloc(self.live_pages);
+        self.count_on_node(pos, node, true);
+        self.check_watermark();
+    }
+
+    /// 页对齐的字节数，`align_pow2` 表示页对齐的幂，例如 0 表示 1 页对齐，1 表示 2 页对齐...
+    fn page_align(align_pow2: usize) -> AllocResult<usize> {
+        u32::try_from(align_pow2)
+            .ok()
+            .and_then(|shift| 1usize.checked_shl(shift))
+            .and_then(|pages| pages.checked_mul(PAGE_SIZE))
+            .ok_or(AllocError::InvalidParam)
+    }
+
+    /// 区域的尝试顺序：`node` 上的区域在前，其余的在后，各自保持原顺序
+    fn region_order(&self, node: Option<usize>) -> impl Iterator<Item = usize> {
+        let mut nodes = [0; MAX_REGIONS];
+        for (node, region) in nodes.iter_mut().zip(self.regions()) {
+            *node = region.node;
+        }
+        let count = self.region_count;
+        let preferred = move |idx: &usize| Some(nodes[*idx]) == node;
+        (0..count)
+            .filter(preferred)
+            .chain((0..count).filter(move |idx| !preferred(idx)))
+    }
+
+    /// 地址 `pos` 所在区域的节点
+    fn node_of(&self, pos: usize) -> Option<usize> {
+        self.regions()
+            .iter()
+            .find(|region| region.start <= pos && pos < region.end)
+            .map(|region| region.node)
+    }
+
+    /// 为 `pos` 处的分配计数，请求的节点为 `requested`
+    fn count_on_node(&mut self, pos: usize, requested: Option<usize>, pages: bool) {
+        let Some(node) = self.node_of(pos) else {
+            return;
+        };
+        let counters = &mut self.node_counters[node];
+        if pages {
+            counters.page_allocs += 1;
+        } else {
+            counters.byte_allocs += 1;
+        }
+        if let Some(requested) = requested.filter(|&requested| requested != node) {
+            self.node_counters[requested].fallbacks += 1;
+        }
+    }
+
+    /// 区域 `region` 中被分配的页的连续范围 `(start, end)`，按地址顺序
+    #[cfg(feature = "page-bitmap")]
+    fn page_runs<'a>(&'a self, region: &'a Region) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut pos = region.page_pos;
+        core::iter::from_fn(move || {
+            while pos < region.end && !region.bitmap.is_used(pos) {
+                pos += PAGE_SIZE;
+            }
+            if pos >= region.end {
+                return None;
+            }
+            let start = pos;
+            while pos < region.end && region.bitmap.is_used(pos) {
+                pos += PAGE_SIZE;
+            }
+            Some((start, pos.min(region.end)))
+        })
+    }
+
+    /// 区域 `region` 中被分配的页的连续范围 `(start, end)`，即 page_pos 之上
+    /// 除去空洞的部分，按地址顺序
+    #[cfg(not(feature = "page-bitmap"))]
+    fn page_runs<'a>(&'a self, region: &'a Region) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut pos = region.page_pos;
+        core::iter::from_fn(move || {
+            while pos < region.end {
+                let next_hole = self.holes[..self.hole_count]
+                    .iter()
+                    .filter(|hole| pos <= hole.start && hole.end <= region.end)
+                    .min_by_key(|hole| hole.start);
+                let start = pos;
+                match next_hole {
+                    Some(hole) if hole.start == pos => pos = hole.end,
+                    Some(hole) => {
+                        pos = hole.end;
+                        return Some((start, hole.start));
+                    }
+                    None => {
+                        pos = region.end;
+                        return Some((start, region.end));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// 区域之外的元数据 `(start, end)`：记录表和各页位图，分割出的区域共用
+    /// 的位图只列出一次
+    fn metadata(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        #[cfg(feature = "tracking")]
+        let table = Some(self.tracker.table()).filter(|(start, end)| start < end);
+        #[cfg(not(feature = "tracking"))]
+        let table = None;
+        #[cfg(feature = "page-bitmap")]
+        let bitmaps = self.regions().iter().enumerate().filter_map(|(i, region)| {
+            let (start, _) = region.bitmap.storage();
+            let regions = self.regions();
+            if regions[..i].iter().any(|r| r.bitmap.storage().0 == start) {
+                return None;
+            }
+            // 截断的位图覆盖的页较少，取最大的范围
+            let end = regions[i..]
+                .iter()
+                .map(|r| r.bitmap.storage())
+                .filter(|&(s, _)| s == start)
+                .map(|(_, e)| e)
+                .max()?;
+            (start < end).then_some((start, end))
+        });
+        #[cfg(not(feature = "page-bitmap"))]
+        let bitmaps = core::iter::empty();
+        table.into_iter().chain(bitmaps)
+    }
+
+    /// `[pos, end)` 中不被保留的第一段
+    fn next_piece(&self, mut pos: usize, end: usize) -> Option<(usize, usize)> {
+        while pos < end {
+            let next = self.reserved[..self.reserved_count]
+                .iter()
+                .filter(|range| pos < range.end && range.start < end)
+                .min_by_key(|range| range.start);
+            match next {
+                Some(range) if range.start <= pos => pos = range.end,
+                Some(range) => return Some((pos, range.start)),
+                None => return Some((pos, end)),
+            }
+        }
+        None
+    }
+
+    /// 创建内存区域 `[start, end)`，启用页位图时将其放在区域末尾
+    fn new_region(start: usize, end: usize) -> Region {
+        #[cfg(feature = "page-bitmap")]
+        {
+            let (bitmap, end) = unsafe { bitmap::PageBitmap::reserve(start, end, PAGE_SIZE) };
+            Region {
+                bitmap,
+                ..Region::new(start, end)
+            }
+        }
+        #[cfg(not(feature = "page-bitmap"))]
+        Region::new(start, end)
+    }
+
+    /// 记录一个空洞，与相邻的空洞合并
+    #[cfg(not(feature = "page-bitmap"))]
+    fn add_hole(&mut self, start: usize, end: usize) {
+        let (mut start, mut end) = (start, end);
+        let mut i = 0;
+        while i < self.hole_count {
+            let hole = self.holes[i];
+            if hole.end == start || hole.start == end {
+                start = start.min(hole.start);
+                end = end.max(hole.end);
+                self.remove_hole(i);
+            } else {
+                i += 1;
+            }
+        }
+        if self.hole_count < MAX_PAGE_HOLES {
+            self.holes[self.hole_count] = PageRange { start, end };
+            self.hole_count += 1;
+        }
+    }
+
+    /// 从空洞中取出 `[start, end)`，它须在一个空洞之内，两侧剩余的部分仍为
+    /// 空洞
+    #[cfg(not(feature = "page-bitmap"))]
+    fn take_hole(&mut self, start: usize, end: usize) -> AllocResult {
+        let idx = (0..self.hole_count)
+            .find(|&i| self.holes[i].start <= start && end <= self.holes[i].end)
+            .ok_or(AllocError::MemoryOverlap)?;
+        let hole = self.holes[idx];
+        // 从中间取出时空洞多出一个
+        if hole.start < start && end < hole.end && self.hole_count == MAX_PAGE_HOLES {
+            return Err(AllocError::NoMemory);
+        }
+        self.remove_hole(idx);
+        for (start, end) in [(hole.start, start), (end, hole.end)] {
+            if start < end {
+                self.holes[self.hole_count] = PageRange { start, end };
+                self.hole_count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "page-bitmap"))]
+    fn remove_hole(&mut self, idx: usize) {
+        self.hole_count -= 1;
+        self.holes[idx] = self.holes[self.hole_count];
+    }
+
+    /// 回收与第 `idx` 个区域的 page_pos 相邻的空洞
+    #[cfg(not(feature = "page-bitmap"))]
+    fn reclaim_holes(&mut self, idx: usize) {
+        let region = &mut self.regions[idx];
+        while let Some(i) = (0..self.hole_count).find(|&i| self.holes[i].start == region.page_pos) {
+            region.page_pos = self.holes[i].end;
+            self.hole_count -= 1;
+            self.holes[i] = self.holes[self.hole_count];
+        }
+    }
+
+    /// 空洞（page_pos 之上的空闲页）的总字节数
+    #[cfg(feature = "page-bitmap")]
+    fn hole_bytes(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|region| region.bitmap.free_bytes(region.page_pos))
+            .sum()
+    }
+
+    /// 空洞的总字节数
+    #[cfg(not(feature = "page-bitmap"))]
+    fn hole_bytes(&self) -> usize {
+        self.holes[..self.hole_count]
+            .iter()
+            .map(|hole| hole.end - hole.start)
+            .sum()
+    }
+
+    /// 区域 `region` 中空洞的字节数
+    #[cfg(feature = "page-bitmap")]
+    fn region_hole_bytes(&self, region: &Region) -> usize {
+        region.bitmap.free_bytes(region.page_pos)
+    }
+
+    /// 区域 `region` 中空洞的字节数
+    #[cfg(not(feature = "page-bitmap"))]
+    fn region_hole_bytes(&self, region: &Region) -> usize {
+        self.holes[..self.hole_count]
+            .iter()
+            .filter(|hole| region.page_pos <= hole.start && hole.end <= region.end)
+            .map(|hole| hole.end - hole.start)
+            .sum()
+    }
+
+    /// 在第一个空间足够的区域中分配字节，`node` 上的区域优先
+    fn alloc_bytes(&mut self, layout: Layout, node: Option<usize>) -> AllocResult<NonNull<u8>> {
         let size = layout.size();
         let align = layout.align();
 
         if size == 0 {
             return Err(AllocError::InvalidParam);
         }
+        if self.sealed || self.frozen {
+            return Err(AllocError::NoMemory);
+        }
+        #[cfg(feature = "hardened")]
+        if self.shadow.bytes.is_full() {
+            return Err(AllocError::NoMemory);
+        }
+
+        // 依次尝试各个区域，当前区域耗尽时溢出到后续区域
+        for idx in self.region_order(node) {
+            let region = &mut self.regions[idx];
+            // 计算对齐后的位置，前后留出哨兵的空间，溢出时视为空间不足
+            let Some(aligned_pos) = region
+                .byte_pos
+                .checked_add(GUARD + align - 1)
+                .map(|pos| pos & !(align - 1))
+            else {
+                continue;
+            };
+            let Some(new_pos) = aligned_pos.checked_add(size + GUARD) else {
+                continue;
+            };
+
+            // 检查是否有足够空间
+            if new_pos > region.page_pos {
+                continue;
+            }
+            #[cfg(feature = "hardened")]
+            {
+                hardened::check_range(
+                    aligned_pos - GUARD,
+                    new_pos,
+                    region.byte_pos,
+                    region.page_pos,
+                );
+                self.shadow.bytes.insert(aligned_pos, size);
+            }
+
+            // 更新分配计数和位置指针
+            region.byte_pos = new_pos;
+            self.alloc_count += 1;
+            #[cfg(feature = "debug-poison")]
+            unsafe {
+                poison::arm(aligned_pos, size)
+            };
+
+            // 返回分配的内存区域
+            return NonNull::new(aligned_pos as *mut u8).ok_or(AllocError::InvalidParam);
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    /// 在第 `idx` 个区域中分配满足约束的页，返回页起始地址
+    fn alloc_pages_in(
+        &mut self,
+        idx: usize,
+        bytes_size: usize,
+        constraints: &AllocConstraints,
+    ) -> Option<usize> {
+        let region = self.regions[idx];
+        // 优先复用 page_pos 之上已释放的页
+        #[cfg(feature = "page-bitmap")]
+        if let Some(pos) = region
+            .bitmap
+            .find_free(region.page_pos, bytes_size, constraints)
+        {
+            #[cfg(feature = "hardened")]
+            hardened::check_range(pos, pos + bytes_size, region.page_pos, region.end);
+            self.regions[idx].bitmap.set(pos, bytes_size, true);
+            return Some(pos);
+        }
 
-        // 计算对齐后的位置
-        let aligned_pos = (self.byte_pos + align - 1) & !(align - 1);
-        let new_pos = aligned_pos + size;
+        // 计算满足约束的页起始位置（向下对齐）
+        let aligned_pos = constraints.fit(region.page_pos, bytes_size)?;
 
         // 检查是否有足够空间
-        if new_pos > self.page_pos {
+        if aligned_pos < region.byte_pos {
+            return None;
+        }
+        #[cfg(feature = "hardened")]
+        hardened::check_range(
+            aligned_pos,
+            aligned_pos + bytes_size,
+            region.byte_pos,
+            region.page_pos,
+        );
+
+        // 对齐留下的间隙记为空洞，释放本次分配时一并回收
+        #[cfg(not(feature = "page-bitmap"))]
+        {
+            let alloc_end = aligned_pos + bytes_size;
+            if alloc_end < region.page_pos {
+                self.add_hole(alloc_end, region.page_pos);
+            }
+        }
+        // 间隙的位保持空闲，可被之后的分配复用
+        #[cfg(feature = "page-bitmap")]
+        self.regions[idx].bitmap.set(aligned_pos, bytes_size, true);
+
+        // 更新页分配位置
+        self.regions[idx].page_pos = aligned_pos;
+        Some(aligned_pos)
+    }
+
+    /// 分配 `[base, end)` 处的页，见 [`EarlyAllocator::alloc_pages_at`]
+    fn alloc_pages_at_in(&mut self, base: usize, end: usize) -> AllocResult {
+        let reserved = self.reserved[..self.reserved_count]
+            .iter()
+            .map(|range| (range.start, range.end));
+        let used = self
+            .metadata()
+            .chain(reserved)
+            .any(|range| overlaps(range, base, end));
+        if used {
+            return Err(AllocError::MemoryOverlap);
+        }
+        let Some(idx) = self
+            .regions()
+            .iter()
+            .position(|region| region.start <= base && end <= region.end)
+        else {
+            // 跨越区域的边界
+            if self
+                .regions()
+                .iter()
+                .any(|region| overlaps((region.start, region.end), base, end))
+            {
+                return Err(AllocError::MemoryOverlap);
+            }
             return Err(AllocError::NoMemory);
+        };
+        let region = self.regions[idx];
+        if base < region.byte_pos {
+            return Err(AllocError::MemoryOverlap);
         }
+        #[cfg(feature = "hardened")]
+        hardened::check_range(base, end, region.byte_pos, region.end);
 
-        // 更新分配计数和位置指针
-        self.byte_pos = new_pos;
-        self.alloc_count += 1;
+        // page_pos 之上的部分须为已释放的页
+        let lo = base.max(region.page_pos);
+        if lo < end {
+            #[cfg(feature = "page-bitmap")]
+            {
+                if (lo..end)
+                    .step_by(PAGE_SIZE)
+                    .any(|pos| region.bitmap.is_used(pos))
+                {
+                    return Err(AllocError::MemoryOverlap);
+                }
+                self.regions[idx].bitmap.set(lo, end - lo, true);
+            }
+            #[cfg(not(feature = "page-bitmap"))]
+            self.take_hole(lo, end)?;
+        }
+        // 空闲窗口中的部分，page_pos 下移，之上的间隙与对齐的一样处理
+        if base < region.page_pos {
+            let hi = end.min(region.page_pos);
+            #[cfg(not(feature = "page-bitmap"))]
+            if hi < region.page_pos {
+                self.add_hole(hi, region.page_pos);
+            }
+            #[cfg(feature = "page-bitmap")]
+            self.regions[idx].bitmap.set(base, hi - base, true);
+            self.regions[idx].page_pos = base;
+        }
+        Ok(())
+    }
+}
 
-        // 返回分配的内存区域
-        NonNull::new(aligned_pos as *mut u8).ok_or(AllocError::InvalidParam)
+impl<const PAGE_SIZE: usize> fmt::Debug for EarlyAllocator<PAGE_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.snapshot(), f)
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
+    /// Initialize the allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        let end = start.saturating_add(size);
+        #[cfg(feature = "tracking")]
+        {
+            self.tracker = track::Tracker::EMPTY;
+        }
+        // 除去保留的范围，记录表放在第一段的起始处
+        self.region_count = 0;
+        let mut pos = start;
+        while let Some((piece_start, piece_end)) = self.next_piece(pos, end) {
+            if self.region_count == MAX_REGIONS {
+                break;
+            }
+            #[cfg(feature = "tracking")]
+            let piece_start = if self.region_count == 0 {
+                let (tracker, piece_start) = track::Tracker::reserve(piece_start, piece_end);
+                self.tracker = tracker;
+                piece_start
+            } else {
+                piece_start
+            };
+            self.regions[self.region_count] = Self::new_region(piece_start, piece_end);
+            self.region_count += 1;
+            pos = piece_end;
+        }
+        self.alloc_count = 0;
+        #[cfg(not(feature = "page-bitmap"))]
+        {
+            self.hole_count = 0;
+        }
+        self.sealed = false;
+        self.frozen = false;
+        self.handed_bytes = 0;
+        self.live_pages = 0;
+        self.stats = AllocStats::EMPTY;
+        self.sized = huge::SizedPages::EMPTY;
+        self.node_counters = [numa::NodeCounters::EMPTY; MAX_NODES];
+        for domain in &mut self.domains {
+            domain.reset();
+        }
+        #[cfg(feature = "hardened")]
+        {
+            self.shadow = hardened::Shadow::EMPTY;
+        }
+    }
+
+    /// Add a free memory region to the allocator.
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.add_memory_on(0, start, size)
+    }
+}
+
+impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
+    /// Allocate memory with the given size (in bytes) and alignment.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.alloc_on_node(layout, None)
     }
 
     /// Deallocate memory at the given position, size, and alignment.
     fn dealloc(&mut self, _pos: NonNull<u8>, _layout: Layout) {
+        // 不是存活的分配，忽略
+        #[cfg(feature = "hardened")]
+        if !self
+            .shadow
+            .bytes
+            .remove(_pos.as_ptr() as usize, _layout.size())
+        {
+            self.stats.invalid_frees += 1;
+            return;
+        }
+        #[cfg(feature = "tracking")]
+        self.tracker
+            .remove(AllocKind::Bytes, _pos.as_ptr() as usize);
+        #[cfg(feature = "debug-poison")]
+        unsafe {
+            poison::free(_pos.as_ptr() as usize, _layout.size())
+        };
+        if self.zero_on_free {
+            let start = _pos.as_ptr() as usize;
+            unsafe { zero(start, start + _layout.size()) };
+        }
+
         // 减少分配计数
         if self.alloc_count > 0 {
             self.alloc_count -= 1;
         }
 
-        // 只有当所有分配都释放时，才重置字节分配指针
-        if self.alloc_count == 0 {
-            self.byte_pos = self.start;
+        // 只有当所有分配都释放时，才重置所有区域的字节分配指针，封存后
+        // 字节区域之后的窗口已移交，不再重置
+        if self.alloc_count == 0 && !self.sealed {
+            #[cfg(feature = "hardened")]
+            self.shadow.bytes.clear();
+            #[cfg(feature = "tracking")]
+            self.tracker.clear_bytes();
+            for region in self.regions[..self.region_count].iter_mut() {
+                #[cfg(feature = "debug-poison")]
+                unsafe {
+                    poison::fill(region.start, region.byte_pos, poison::FREED)
+                };
+                // 哨兵和对齐的间隙也一并清零
+                if self.zero_on_free {
+                    unsafe { zero(region.start, region.byte_pos) };
+                }
+                region.byte_pos = region.start;
+            }
         }
         // 注意：我们不会释放单个内存块，而是等到所有块都释放时才重置指针
     }
 
     /// Returns total memory size in bytes.
     fn total_bytes(&self) -> usize {
-        self.end - self.start
+        self.regions()
+            .iter()
+            .map(|region| region.end - region.start)
+            .sum::<usize>()
+            - self.handed_bytes
     }
 
     /// Returns allocated memory size in bytes.
     fn used_bytes(&self) -> usize {
-        let bytes_used = self.byte_pos - self.start;
-        let pages_used = self.end - self.page_pos;
-        bytes_used + pages_used
+        self.total_bytes() - self.available_bytes() - self.hole_bytes()
     }
 
     /// Returns available memory size in bytes.
     fn available_bytes(&self) -> usize {
-        self.page_pos - self.byte_pos
+        self.regions().iter().map(Region::avail).sum()
     }
 }
 
 impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
     const PAGE_SIZE: usize = PAGE_SIZE;
     /// Allocate contiguous memory pages with given count and alignment.
+    #[cfg_attr(feature = "tracking", track_caller)]
     fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        if num_pages == 0 {
-            return Err(AllocError::InvalidParam);
-        }
-
-        let page_size = PAGE_SIZE;
-        let bytes_size = num_pages * page_size;
-
-        // 计算对齐要求，align_pow2表示页对齐的幂，例如0表示1页对齐，1表示2页对齐...
-        let align = page_size << align_pow2;
-
-        // 计算对齐后的页起始位置（向下对齐）
-        let aligned_pos = (self.page_pos - bytes_size) & !(align - 1);
-
-        // 检查是否有足够空间
-        if aligned_pos < self.byte_pos {
-            return Err(AllocError::NoMemory);
-        }
-
-        // 更新页分配位置
-        self.page_pos = aligned_pos;
-
-        // 返回分配的页地址
-        Ok(aligned_pos)
+        let constraints = AllocConstraints::aligned(Self::page_align(align_pow2)?);
+        self.alloc_pages_on_node(num_pages, constraints, None)
     }
 
     /// Deallocate contiguous memory pages with given position and count.
-    fn dealloc_pages(&mut self, _pos: usize, _num_pages: usize) {
-        // 页分配不支持释放，这是设计决定
-        // 早期分配器中分配的页面预计会在整个系统生命周期内使用
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        #[cfg(feature = "hardened")]
+        if !self.shadow.pages.remove(pos, num_pages) {
+            self.stats.invalid_frees += 1;
+            return;
+        }
+        let Some(end) = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| pos.checked_add(size))
+        else {
+            return;
+        };
+        let Some(idx) = self
+            .regions()
+            .iter()
+            .position(|region| region.page_pos <= pos && end <= region.end)
+        else {
+            return;
+        };
+        if num_pages == 0 {
+            return;
+        }
+        self.live_pages = self.live_pages.saturating_sub(num_pages);
+        #[cfg(feature = "tracking")]
+        self.tracker.remove(AllocKind::Pages, pos);
+        if self.zero_on_free {
+            unsafe { zero(pos, end) };
+        }
+        #[cfg(feature = "page-bitmap")]
+        {
+            // 清除对应的位，page_pos 越过其上所有空闲页回退
+            let region = &mut self.regions[idx];
+            region.bitmap.set(pos, end - pos, false);
+            while region.page_pos < region.end && !region.bitmap.is_used(region.page_pos) {
+                region.page_pos += PAGE_SIZE;
+            }
+            // 分割出的区域的结束地址可能未对齐
+            region.page_pos = region.page_pos.min(region.end);
+        }
+        #[cfg(not(feature = "page-bitmap"))]
+        if pos == self.regions[idx].page_pos {
+            // 释放最近一次分配，page_pos 回退，并回收随之相邻的空洞
+            self.regions[idx].page_pos = end;
+            self.reclaim_holes(idx);
+        } else {
+            // 乱序释放，先记为空洞
+            self.add_hole(pos, end);
+        }
     }
 
     /// Returns the total number of memory pages.
@@ -162,11 +1647,104 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
 
     /// Returns the number of allocated memory pages.
     fn used_pages(&self) -> usize {
-        (self.end - self.page_pos) / Self::PAGE_SIZE
+        let pages_used: usize = self
+            .regions()
+            .iter()
+            .map(|region| region.end - region.page_pos)
+            .sum();
+        (pages_used - self.hole_bytes()) / Self::PAGE_SIZE
     }
 
     /// Returns the number of available memory pages.
     fn available_pages(&self) -> usize {
-        (self.page_pos - self.byte_pos) / Self::PAGE_SIZE
+        self.regions()
+            .iter()
+            .map(|region| region.avail() / Self::PAGE_SIZE)
+            .sum()
     }
 }
+
+impl<const PAGE_SIZE: usize> ConstrainedPageAllocator for EarlyAllocator<PAGE_SIZE> {
+    /// Allocate contiguous memory pages meeting the given constraints.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn alloc_pages_constrained(
+        &mut self,
+        num_pages: usize,
+        constraints: AllocConstraints,
+    ) -> AllocResult<usize> {
+        self.alloc_pages_on_node(num_pages, constraints, None)
+    }
+}
+
+impl<const PAGE_SIZE: usize> HugePageAllocator for EarlyAllocator<PAGE_SIZE> {
+    /// Allocate `num` contiguous pages of `page_size`, aligned to it.
+    #[cfg_attr(feature = "tracking", track_caller)]
+    fn alloc_pages_sized(&mut self, num: usize, page_size: usize) -> AllocResult<usize> {
+        let ratio = huge::page_ratio(page_size, PAGE_SIZE)?;
+        let num_pages = num.checked_mul(ratio).ok_or(AllocError::NoMemory)?;
+        if ratio > 1 && !self.sized.has_room(page_size) {
+            return Err(AllocError::InvalidParam);
+        }
+        let pos = self.alloc_pages(num_pages, ratio.trailing_zeros() as usize)?;
+        if ratio > 1 {
+            self.sized.add(page_size, num);
+        }
+        Ok(pos)
+    }
+
+    /// Deallocate `num` contiguous pages of `page_size` at the given position.
+    fn dealloc_pages_sized(&mut self, pos: usize, num: usize, page_size: usize) {
+        let Ok(ratio) = huge::page_ratio(page_size, PAGE_SIZE) else {
+            return;
+        };
+        let Some(num_pages) = num.checked_mul(ratio) else {
+            return;
+        };
+        let live_pages = self.live_pages;
+        self.dealloc_pages(pos, num_pages);
+        // 被忽略的释放不减少计数
+        if ratio > 1 && self.live_pages < live_pages {
+            self.sized.sub(page_size, num);
+        }
+    }
+
+    /// Returns the number of allocated pages of `page_size`.
+    fn used_pages_sized(&self, page_size: usize) -> usize {
+        match huge::page_ratio(page_size, PAGE_SIZE) {
+            Ok(1) => self.used_pages() - self.sized.base_pages(PAGE_SIZE),
+            Ok(_) => self.sized.live(page_size),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the number of pages of `page_size` which can be allocated.
+    fn available_pages_sized(&self, page_size: usize) -> usize {
+        if huge::page_ratio(page_size, PAGE_SIZE).is_err() {
+            return 0;
+        }
+        self.regions()
+            .iter()
+            .map(|region| {
+                let end = region.page_pos & !(page_size - 1);
+                let start = region.byte_pos.checked_next_multiple_of(page_size);
+                start.map_or(0, |start| end.saturating_sub(start) / page_size)
+            })
+            .sum()
+    }
+}
+
+/// 清零 `[start, end)`
+///
+/// # Safety
+///
+/// The range must be valid writable memory.
+unsafe fn zero(start: usize, end: usize) {
+    if start < end {
+        core::ptr::write_bytes(start as *mut u8, 0, end - start);
+    }
+}
+
+/// `(lo, hi)` 与 `[start, end)` 是否重叠
+fn overlaps((lo, hi): (usize, usize), start: usize, end: usize) -> bool {
+    start < hi && lo < end
+}
@@ -0,0 +1,117 @@
+//! Migration of the live allocations to the allocators taking over.
+//!
+//! `take_remaining` only hands the free windows of the regions over, the
+//! rest of the early memory is lost to the allocators taking over even once
+//! its allocations are freed. The two-phase switch instead lets them manage
+//! the whole memory given to the early allocator:
+//!
+//! 1. `freeze` makes the allocations fail from then on, while the frees are
+//!    still honored, so the live allocations cannot change but shrink;
+//! 2. `live_ranges` lists the page-aligned [`LiveRange`]s still in use. The
+//!    final page allocator adds the memory with them marked as allocated
+//!    (e.g. `BuddyPageAllocator::add_memory_reserved`).
+//!
+//! The live page allocations then belong to the final page allocator, and
+//! are freed to it. The byte allocations are still freed to the early
+//! allocator (`owns_bytes` tells them apart): the byte areas and the
+//! metadata are given to the final page allocator once `live_byte_allocs`
+//! drops to 0. The reserved ranges are never given.
+
+/// 存活范围的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveKind {
+    /// 区域的字节区域 `[start, byte_pos)`，其中的分配仍由早期分配器释放
+    Bytes,
+    /// 被分配的页，之后由正式的页分配器释放
+    Pages,
+    /// 早期分配器的记录表或页位图
+    Metadata,
+    /// 保留的地址范围
+    Reserved,
+}
+
+/// 冻结后仍被使用的一段内存，由 [`EarlyAllocator::live_ranges`] 列出
+///
+/// [`EarlyAllocator::live_ranges`]: crate::EarlyAllocator::live_ranges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveRange {
+    /// 范围的种类
+    pub kind: LiveKind,
+    /// 起始地址，按页对齐
+    pub start: usize,
+    /// 字节数，为页大小的整数倍
+    pub size: usize,
+}
+
+impl LiveRange {
+    /// `[start, end)` 向外按 `page_size` 对齐后的范围
+    pub(crate) fn new(kind: LiveKind, start: usize, end: usize, page_size: usize) -> Self {
+        let start = start & !(page_size - 1);
+        let end = end.saturating_add(page_size - 1) & !(page_size - 1);
+        Self {
+            kind,
+            start,
+            size: end - start,
+        }
+    }
+
+    /// 结束地址
+    pub fn end(&self) -> usize {
+        self.start + self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{AllocError, ByteAllocator, PageAllocator};
+
+    #[test]
+    fn test_live_range() {
+        let range = LiveRange::new(LiveKind::Bytes, 0x1010, 0x2001, PAGE);
+        assert_eq!(
+            (range.start, range.size, range.end()),
+            (0x1000, 0x2000, 0x3000)
+        );
+        let range = LiveRange::new(LiveKind::Pages, 0x3000, 0x4000, PAGE);
+        assert_eq!((range.start, range.end()), (0x3000, 0x4000));
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let bytes = alloc.alloc(layout(64)).unwrap();
+        let page = alloc.alloc_pages(1, 0).unwrap();
+        let other = alloc.alloc_pages(2, 0).unwrap();
+        alloc.freeze();
+        assert!(alloc.is_frozen());
+        assert_eq!(alloc.alloc(layout(8)), Err(AllocError::NoMemory));
+        assert_eq!(alloc.alloc_pages(1, 0), Err(AllocError::NoMemory));
+
+        let ranges: Vec<_> = alloc.live_ranges().collect();
+        let pos = bytes.as_ptr() as usize;
+        assert!(ranges
+            .iter()
+            .any(|r| r.kind == LiveKind::Bytes && r.start <= pos && pos < r.end()));
+        let pages: Vec<_> = ranges
+            .iter()
+            .filter(|r| r.kind == LiveKind::Pages)
+            .map(|r| (r.start, r.end()))
+            .collect();
+        assert_eq!(pages, [(other, page + PAGE)]);
+
+        // 释放仍然有效，空洞不在存活的范围中
+        alloc.dealloc_pages(page, 1);
+        alloc.dealloc(bytes, layout(64));
+        assert_eq!(alloc.live_byte_allocs(), 0);
+        let kinds: Vec<_> = alloc
+            .live_ranges()
+            .filter(|r| r.kind != LiveKind::Metadata)
+            .map(|r| (r.kind, r.start, r.end()))
+            .collect();
+        assert_eq!(kinds, [(LiveKind::Pages, other, other + 2 * PAGE)]);
+        assert!(!alloc.owns_bytes(bytes.as_ptr() as usize));
+    }
+}
@@ -0,0 +1,92 @@
+//! NUMA nodes of the memory regions.
+//!
+//! Each region belongs to a node: the one given to `add_memory_on`, and 0 for
+//! the region of `init` and those of `add_memory`. `alloc_on` and
+//! `alloc_pages_on` try the regions of the requested node first, in their
+//! order, and fall back to the regions of the other nodes. The fallbacks are
+//! counted in [`NodeStats::fallbacks`], with the usage of each node.
+
+/// 最多支持的节点数量，节点号为 `0..MAX_NODES`
+pub const MAX_NODES: usize = 4;
+
+/// 一个节点的使用统计，由 [`EarlyAllocator::node_stats`] 返回
+///
+/// [`EarlyAllocator::node_stats`]: crate::EarlyAllocator::node_stats
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeStats {
+    /// 节点上的区域数量
+    pub regions: usize,
+    /// 节点上区域的总字节数
+    pub total_bytes: usize,
+    /// 字节区域已使用的字节数
+    pub used_bytes: usize,
+    /// 被分配的页数
+    pub used_pages: usize,
+    /// 剩余可用的字节数
+    pub available_bytes: usize,
+    /// 自 `init` 以来在该节点上成功的字节分配次数
+    pub byte_allocs: usize,
+    /// 自 `init` 以来在该节点上成功的页分配次数
+    pub page_allocs: usize,
+    /// 请求该节点、但由其他节点满足的分配次数
+    pub fallbacks: usize,
+}
+
+/// 一个节点的分配计数
+#[derive(Clone, Copy)]
+pub(crate) struct NodeCounters {
+    pub byte_allocs: usize,
+    pub page_allocs: usize,
+    pub fallbacks: usize,
+}
+
+impl NodeCounters {
+    pub const EMPTY: Self = Self {
+        byte_allocs: 0,
+        page_allocs: 0,
+        fallbacks: 0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use crate::MAX_NODES;
+    use allocator::{AllocError, PageAllocator};
+
+    #[test]
+    fn test_node_preference() {
+        let mem = Memory::new(8);
+        let far = Memory::new(8);
+        let mut alloc = allocator(&mem);
+        alloc.add_memory_on(1, far.start(), far.size()).unwrap();
+        assert_eq!(
+            alloc.add_memory_on(MAX_NODES, far.end() + PAGE, PAGE),
+            Err(AllocError::InvalidParam)
+        );
+
+        let on_far = |pos: usize| (far.start()..far.end()).contains(&pos);
+        let pos = alloc.alloc_on(1, layout(64)).unwrap();
+        assert!(on_far(pos.as_ptr() as usize));
+        assert!(!on_far(alloc.alloc_pages(1, 0).unwrap()));
+        let pages: Vec<_> = (0..alloc.node_stats(1).available_bytes / PAGE)
+            .map(|_| alloc.alloc_pages_on(1, 1, 0).unwrap())
+            .collect();
+        assert!(pages.iter().all(|&pos| on_far(pos)));
+
+        // 节点 1 耗尽后由节点 0 满足
+        assert!(!on_far(alloc.alloc_pages_on(1, 1, 0).unwrap()));
+        let stats = alloc.node_stats(1);
+        assert_eq!(stats.regions, 1);
+        assert_eq!(stats.byte_allocs, 1);
+        assert_eq!(stats.page_allocs, pages.len());
+        assert_eq!(stats.used_pages, pages.len());
+        assert_eq!(stats.fallbacks, 1);
+        let stats = alloc.node_stats(0);
+        assert_eq!((stats.page_allocs, stats.fallbacks), (2, 0));
+        assert_eq!(
+            alloc.alloc_on(MAX_NODES, layout(8)),
+            Err(AllocError::InvalidParam)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Poisoning and canaries of the byte allocations, with the `debug-poison`
+//! feature.
+//!
+//! A new allocation is filled with [`ALLOC`], and a freed one (or the whole
+//! byte area when it is reset) with [`FREED`], so the use of uninitialized
+//! or freed memory shows up as these patterns. A canary word is put before
+//! and after each allocation and checked when it is freed.
+
+use core::ptr;
+
+/// 新分配内存的填充字节
+pub const ALLOC: u8 = 0xAA;
+/// 已释放内存的填充字节
+pub const FREED: u8 = 0xDD;
+
+/// 哨兵字的大小，分配前后各一个
+pub const WORD: usize = core::mem::size_of::<usize>();
+
+const HEAD: usize = 0xC0DE_CAFE_C0DE_CAFE_u64 as usize;
+const TAIL: usize = 0xF00D_FACE_F00D_FACE_u64 as usize;
+/// 释放后的前哨兵，用于发现重复释放
+const HEAD_FREED: usize = usize::from_ne_bytes([FREED; WORD]);
+
+/// 填充 `[start, end)`
+///
+/// # Safety
+///
+/// The range must be valid writable memory.
+pub unsafe fn fill(start: usize, end: usize, byte: u8) {
+    if start < end {
+        ptr::write_bytes(start as *mut u8, byte, end - start);
+    }
+}
+
+/// 初始化 `pos` 处 `size` 字节的新分配及其前后哨兵
+///
+/// # Safety
+///
+/// `[pos - WORD, pos + size + WORD)` must be valid writable memory.
+pub unsafe fn arm(pos: usize, size: usize) {
+    ptr::write_unaligned((pos - WORD) as *mut usize, HEAD);
+    fill(pos, pos + size, ALLOC);
+    set_tail(pos, size);
+}
+
+/// 将 `pos` 处分配的后哨兵移到 `size` 字节之后
+///
+/// # Safety
+///
+/// `[pos + size, pos + size + WORD)` must be valid writable memory.
+pub unsafe fn set_tail(pos: usize, size: usize) {
+    ptr::write_unaligned((pos + size) as *mut usize, TAIL);
+}
+
+/// 检查 `pos` 处 `size` 字节分配的前后哨兵，被破坏时 panic
+///
+/// # Safety
+///
+/// `pos` must be an allocation of this allocator.
+pub unsafe fn check(pos: usize, size: usize) {
+    match ptr::read_unaligned((pos - WORD) as *const usize) {
+        HEAD => {}
+        HEAD_FREED => panic!("early allocator: allocation at {:#x} freed twice", pos),
+        _ => panic!(
+            "early allocator: canary before the allocation at {:#x} corrupted",
+            pos
+        ),
+    }
+    if ptr::read_unaligned((pos + size) as *const usize) != TAIL {
+        panic!(
+            "early allocator: canary after the allocation at {:#x} ({} bytes) corrupted",
+            pos, size
+        );
+    }
+}
+
+/// 检查并毒化 `pos` 处被释放的 `size` 字节分配
+///
+/// # Safety
+///
+/// `pos` must be a live allocation of this allocator.
+pub unsafe fn free(pos: usize, size: usize) {
+    check(pos, size);
+    ptr::write_unaligned((pos - WORD) as *mut usize, HEAD_FREED);
+    fill(pos, pos + size, FREED);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory};
+    use allocator::ByteAllocator;
+
+    fn buffer() -> [usize; 8] {
+        [0; 8]
+    }
+
+    #[test]
+    fn test_arm_and_free() {
+        let mut buf = buffer();
+        let pos = buf.as_mut_ptr() as usize + WORD;
+        unsafe {
+            arm(pos, 16);
+            assert!((pos..pos + 16).all(|addr| *(addr as *const u8) == ALLOC));
+            check(pos, 16);
+            free(pos, 16);
+            assert!((pos..pos + 16).all(|addr| *(addr as *const u8) == FREED));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn test_double_free() {
+        let mut buf = buffer();
+        let pos = buf.as_mut_ptr() as usize + WORD;
+        unsafe {
+            arm(pos, 16);
+            free(pos, 16);
+            free(pos, 16);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "canary after")]
+    fn test_overflow() {
+        let mut buf = buffer();
+        let pos = buf.as_mut_ptr() as usize + WORD;
+        unsafe {
+            arm(pos, 16);
+            *((pos + 16) as *mut u8) = 0;
+            check(pos, 16);
+        }
+    }
+
+    #[test]
+    fn test_allocator_poison() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let a = alloc.alloc(layout(32)).unwrap();
+        let b = alloc.alloc(layout(8)).unwrap();
+        let data = unsafe { core::slice::from_raw_parts(a.as_ptr(), 32) };
+        assert!(data.iter().all(|&byte| byte == ALLOC));
+        alloc.dealloc(a, layout(32));
+        let data = unsafe { core::slice::from_raw_parts(a.as_ptr(), 32) };
+        assert!(data.iter().all(|&byte| byte == FREED));
+        // 重置时整个字节区域被毒化，包括哨兵
+        alloc.dealloc(b, layout(8));
+        let tail =
+            unsafe { core::slice::from_raw_parts((b.as_ptr() as usize + 8) as *const u8, WORD) };
+        assert!(tail.iter().all(|&byte| byte == FREED));
+    }
+}
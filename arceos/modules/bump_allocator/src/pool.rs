@@ -0,0 +1,266 @@
+//! A typed object pool on the pages of any page allocator.
+//!
+//! [`ObjectPool`] holds slots of a type `T`, e.g. the per-CPU data or the IRQ
+//! descriptors, so its users do not compute the addresses over the pages
+//! themselves. The slots are carved out of chunks of pages taken from a
+//! [`PageAllocator`] by `grow`, or by `alloc_or_grow` when the pool is full.
+//! Each slot is aligned for `T`, and a freed slot is reused by the next
+//! allocation of the pool.
+//!
+//! The chunks are linked by a header at their start, and the free slots by a
+//! pointer stored in them, so the pool needs no memory besides its pages and
+//! `new` is a `const fn`. The chunks only go back to the page allocator all
+//! together, by `release` once no slot is in use, and must be given back to
+//! the allocator they came from.
+//!
+//! The slots are raw memory: the pool neither initializes nor drops the
+//! values in them.
+
+use allocator::{AllocError, AllocResult, PageAllocator};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+/// 一块页的头部，位于其起始处
+struct ChunkHeader {
+    // 下一块
+    next: *mut ChunkHeader,
+    // 页数
+    pages: usize,
+    // 槽的个数
+    slots: usize,
+}
+
+/// 空闲的槽，存放下一个空闲槽的指针
+struct FreeSlot {
+    next: *mut FreeSlot,
+}
+
+/// 固定类型 `T` 的对象池
+///
+/// 从页分配器取页，切成按 `T` 对齐的槽分配，释放的槽留在池中复用。
+pub struct ObjectPool<T> {
+    // 每次增长取的页数
+    chunk_pages: usize,
+    // 块的链表
+    chunks: *mut ChunkHeader,
+    // 空闲槽的链表
+    free: *mut FreeSlot,
+    // 槽的总数
+    capacity: usize,
+    // 已分配的槽数
+    in_use: usize,
+    _marker: PhantomData<T>,
+}
+
+// 池拥有它的页，槽中的 `T` 随指针交给使用者
+unsafe impl<T: Send> Send for ObjectPool<T> {}
+
+impl<T> ObjectPool<T> {
+    /// 槽的对齐字节数
+    const SLOT_ALIGN: usize = if align_of::<T>() > align_of::<FreeSlot>() {
+        align_of::<T>()
+    } else {
+        align_of::<FreeSlot>()
+    };
+
+    /// 槽的字节数，能放下 `T` 或空闲槽的指针，且为对齐的整数倍
+    const SLOT_SIZE: usize = {
+        let size = if size_of::<T>() > size_of::<FreeSlot>() {
+            size_of::<T>()
+        } else {
+            size_of::<FreeSlot>()
+        };
+        (size + Self::SLOT_ALIGN - 1) & !(Self::SLOT_ALIGN - 1)
+    };
+
+    /// 第一个槽相对块起始的偏移
+    const FIRST_SLOT: usize =
+        (size_of::<ChunkHeader>() + Self::SLOT_ALIGN - 1) & !(Self::SLOT_ALIGN - 1);
+
+    /// 创建空的对象池，每次增长取 `chunk_pages` 页（至少 1 页）
+    pub const fn new(chunk_pages: usize) -> Self {
+        Self {
+            chunk_pages: if chunk_pages == 0 { 1 } else { chunk_pages },
+            chunks: ptr::null_mut(),
+            free: ptr::null_mut(),
+            capacity: 0,
+            in_use: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 槽的总数
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 已分配的槽数
+    pub const fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// 空闲的槽数
+    pub const fn available(&self) -> usize {
+        self.capacity - self.in_use
+    }
+
+    /// 从 `pages` 取一块页加入池中，返回新增的槽数
+    ///
+    /// 一块放不下一个槽，或 `T` 的对齐大于页大小时返回 `InvalidParam`。
+    pub fn grow<A: PageAllocator>(&mut self, pages: &mut A) -> AllocResult<usize> {
+        let size = self
+            .chunk_pages
+            .checked_mul(A::PAGE_SIZE)
+            .ok_or(AllocError::InvalidParam)?;
+        if Self::SLOT_ALIGN > A::PAGE_SIZE || size < Self::FIRST_SLOT + Self::SLOT_SIZE {
+            return Err(AllocError::InvalidParam);
+        }
+        // 对齐的幂为 0，即按 1 页对齐，如同 `EarlyAllocator`
+        let start = pages.alloc_pages(self.chunk_pages, 0)?;
+        let slots = (size - Self::FIRST_SLOT) / Self::SLOT_SIZE;
+        let chunk = start as *mut ChunkHeader;
+        // SAFETY: 这些页刚分配给池，按页对齐
+        unsafe {
+            chunk.write(ChunkHeader {
+                next: self.chunks,
+                pages: self.chunk_pages,
+                slots,
+            });
+            // 倒序压入，使低地址的槽先分配
+            for i in (0..slots).rev() {
+                let slot = (start + Self::FIRST_SLOT + i * Self::SLOT_SIZE) as *mut FreeSlot;
+                slot.write(FreeSlot { next: self.free });
+                self.free = slot;
+            }
+        }
+        self.chunks = chunk;
+        self.capacity += slots;
+        Ok(slots)
+    }
+
+    /// 分配一个槽，池满时返回 `NoMemory`
+    pub fn alloc(&mut self) -> AllocResult<NonNull<T>> {
+        let slot = NonNull::new(self.free).ok_or(AllocError::NoMemory)?;
+        // SAFETY: 空闲链表中的槽都在池的页中
+        self.free = unsafe { slot.as_ref().next };
+        self.in_use += 1;
+        Ok(slot.cast())
+    }
+
+    /// 分配一个槽，池满时先从 `pages` 增长
+    pub fn alloc_or_grow<A: PageAllocator>(&mut self, pages: &mut A) -> AllocResult<NonNull<T>> {
+        if self.free.is_null() {
+            self.grow(pages)?;
+        }
+        self.alloc()
+    }
+
+    /// 释放 `ptr` 处的槽，供之后的分配复用，不析构其中的值
+    ///
+    /// # Safety
+    ///
+    /// `ptr` 须是本池的 [`alloc`] 分配且尚未释放的槽。
+    ///
+    /// [`alloc`]: Self::alloc
+    pub unsafe fn free(&mut self, ptr: NonNull<T>) {
+        debug_assert!(self.contains(ptr), "not a slot of the pool");
+        let slot = ptr.cast::<FreeSlot>().as_ptr();
+        slot.write(FreeSlot { next: self.free });
+        self.free = slot;
+        self.in_use -= 1;
+    }
+
+    /// `ptr` 是否指向池中的一个槽
+    pub fn contains(&self, ptr: NonNull<T>) -> bool {
+        let addr = ptr.as_ptr() as usize;
+        let mut chunk = self.chunks;
+        while !chunk.is_null() {
+            // SAFETY: 块的链表中的块都属于池
+            let header = unsafe { &*chunk };
+            let first = chunk as usize + Self::FIRST_SLOT;
+            let end = first + header.slots * Self::SLOT_SIZE;
+            if (first..end).contains(&addr) {
+                return (addr - first) % Self::SLOT_SIZE == 0;
+            }
+            chunk = header.next;
+        }
+        false
+    }
+
+    /// 把所有的页还给 `pages`，池变为空的
+    ///
+    /// 还有槽在使用时返回 `InvalidParam`。`pages` 须是池增长时的页分配器。
+    pub fn release<A: PageAllocator>(&mut self, pages: &mut A) -> AllocResult {
+        if self.in_use != 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        while !self.chunks.is_null() {
+            // SAFETY: 块的链表中的块都属于池
+            let (next, num) = unsafe { ((*self.chunks).next, (*self.chunks).pages) };
+            pages.dealloc_pages(self.chunks as usize, num);
+            self.chunks = next;
+        }
+        self.free = ptr::null_mut();
+        self.capacity = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, Memory, PAGE};
+
+    #[repr(align(64))]
+    struct Aligned {
+        _data: [u8; 100],
+    }
+
+    #[test]
+    fn test_alloc_and_reuse() {
+        let mem = Memory::new(16);
+        let mut pages = allocator(&mem);
+        let mut pool = ObjectPool::<Aligned>::new(1);
+        assert_eq!(pool.alloc().err(), Some(AllocError::NoMemory));
+
+        let a = pool.alloc_or_grow(&mut pages).unwrap();
+        let slots = pool.capacity();
+        assert_eq!(slots, (PAGE - 64) / 128);
+        assert_eq!(pages.used_pages(), 1);
+        let b = pool.alloc().unwrap();
+        assert_eq!(a.as_ptr() as usize % 64, 0);
+        assert_eq!(b.as_ptr() as usize, a.as_ptr() as usize + 128);
+        assert!(pool.contains(b));
+        assert!(!pool.contains(NonNull::new((b.as_ptr() as usize + 8) as *mut Aligned).unwrap()));
+
+        // 释放的槽最先被复用
+        unsafe { pool.free(a) };
+        assert_eq!(pool.alloc().unwrap(), a);
+        for _ in 2..slots {
+            pool.alloc().unwrap();
+        }
+        assert_eq!(pool.available(), 0);
+        pool.alloc_or_grow(&mut pages).unwrap();
+        assert_eq!(pool.capacity(), 2 * slots);
+        assert_eq!(pool.in_use(), slots + 1);
+        assert_eq!(pages.used_pages(), 2);
+    }
+
+    #[test]
+    fn test_release() {
+        let mem = Memory::new(16);
+        let mut pages = allocator(&mem);
+        let mut pool = ObjectPool::<u64>::new(2);
+        assert_eq!(pool.grow(&mut pages), Ok((2 * PAGE - 24) / 8));
+        let slot = pool.alloc().unwrap();
+        assert_eq!(pool.release(&mut pages), Err(AllocError::InvalidParam));
+        unsafe { pool.free(slot) };
+        pool.release(&mut pages).unwrap();
+        assert_eq!(pool.capacity(), 0);
+        assert_eq!(pages.used_pages(), 0);
+
+        let mut big = ObjectPool::<[u8; 2 * 4096]>::new(1);
+        assert_eq!(big.grow(&mut pages), Err(AllocError::InvalidParam));
+    }
+}
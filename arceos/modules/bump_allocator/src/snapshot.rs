@@ -0,0 +1,234 @@
+//! Introspection of the layout of the early allocator.
+//!
+//! [`Snapshot`] copies the positions of each region (`start`, `byte_pos`,
+//! `page_pos`, `end`), the reserved ranges and the live allocation counts,
+//! so they can be walked or printed without holding the allocator, e.g. by a
+//! shell command or a panic handler after an allocation failed. It is
+//! printed as the diagram of the regions with the real addresses:
+//!
+//! ```text
+//! region 0 (node 0): 4096 bytes used, 2088960 available, 4096 bytes in pages
+//! [ bytes-used        | avail-area        | pages-used        ]
+//! |                   | -->           <-- |                   |
+//! 0x80200000          0x80201000          0x803ff000          0x80400000
+//! start               b_pos               p_pos               end
+//! ```
+//!
+//! `EarlyAllocator` is printed the same by `Debug`.
+
+use core::fmt;
+
+use crate::{MAX_REGIONS, MAX_RESERVED};
+
+/// 一个内存区域的布局
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegionLayout {
+    /// 区域起始地址
+    pub start: usize,
+    /// 字节分配当前位置，`[start, byte_pos)` 为字节区域
+    pub byte_pos: usize,
+    /// 页分配当前位置，`[page_pos, end)` 为页区域
+    pub page_pos: usize,
+    /// 区域结束地址
+    pub end: usize,
+    /// 所在的 NUMA 节点
+    pub node: usize,
+    /// 页区域中已释放、尚未回收的字节数
+    pub hole_bytes: usize,
+}
+
+impl RegionLayout {
+    /// 字节区域已使用的字节数
+    pub fn used_bytes(&self) -> usize {
+        self.byte_pos - self.start
+    }
+
+    /// 剩余可用的字节数 `[byte_pos, page_pos)`
+    pub fn avail_bytes(&self) -> usize {
+        self.page_pos - self.byte_pos
+    }
+
+    /// 页区域中被分配的字节数，不含空洞
+    pub fn page_bytes(&self) -> usize {
+        self.end - self.page_pos - self.hole_bytes
+    }
+}
+
+/// 早期分配器的布局快照，由 [`EarlyAllocator::snapshot`] 返回
+///
+/// [`EarlyAllocator::snapshot`]: crate::EarlyAllocator::snapshot
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    // 各区域的布局
+    regions: [RegionLayout; MAX_REGIONS],
+    // 区域数量
+    region_count: usize,
+    // 保留的地址范围 `(start, size)`
+    reserved: [(usize, usize); MAX_RESERVED],
+    // 保留范围数量
+    reserved_count: usize,
+    /// 存活的字节分配个数
+    pub live_byte_allocs: usize,
+    /// 存活的页数
+    pub live_pages: usize,
+    /// 是否已由 `take_remaining` 封存
+    pub sealed: bool,
+    /// 是否已由 `freeze` 冻结
+    pub frozen: bool,
+}
+
+impl Snapshot {
+    pub(crate) fn new(
+        regions: impl Iterator<Item = RegionLayout>,
+        reserved: impl Iterator<Item = (usize, usize)>,
+        live_byte_allocs: usize,
+        live_pages: usize,
+        sealed: bool,
+        frozen: bool,
+    ) -> Self {
+        let mut snapshot = Self {
+            regions: [RegionLayout::default(); MAX_REGIONS],
+            region_count: 0,
+            reserved: [(0, 0); MAX_RESERVED],
+            reserved_count: 0,
+            live_byte_allocs,
+            live_pages,
+            sealed,
+            frozen,
+        };
+        for (slot, region) in snapshot.regions.iter_mut().zip(regions) {
+            *slot = region;
+            snapshot.region_count += 1;
+        }
+        for (slot, range) in snapshot.reserved.iter_mut().zip(reserved) {
+            *slot = range;
+            snapshot.reserved_count += 1;
+        }
+        snapshot
+    }
+
+    /// 各区域的布局，按区域的顺序（即分配时尝试的顺序）
+    pub fn regions(&self) -> &[RegionLayout] {
+        &self.regions[..self.region_count]
+    }
+
+    /// 保留的地址范围 `(start, size)`
+    pub fn reserved(&self) -> &[(usize, usize)] {
+        &self.reserved[..self.reserved_count]
+    }
+}
+
+impl<'a> IntoIterator for &'a Snapshot {
+    type Item = &'a RegionLayout;
+    type IntoIter = core::slice::Iter<'a, RegionLayout>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.regions().iter()
+    }
+}
+
+impl fmt::Display for RegionLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[ bytes-used        | avail-area        | pages-used        ]\n")?;
+        f.write_str("|                   | -->           <-- |                   |\n")?;
+        writeln!(
+            f,
+            "{:<#20x}{:<#20x}{:<#20x}{:#x}",
+            self.start, self.byte_pos, self.page_pos, self.end
+        )?;
+        f.write_str("start               b_pos               p_pos               end")
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "early allocator: {} regions, {} live byte allocations, {} live pages{}{}",
+            self.region_count,
+            self.live_byte_allocs,
+            self.live_pages,
+            if self.sealed { ", sealed" } else { "" },
+            if self.frozen { ", frozen" } else { "" }
+        )?;
+        for (i, region) in self.regions().iter().enumerate() {
+            write!(
+                f,
+                "region {} (node {}): {} bytes used, {} available, {} bytes in pages",
+                i,
+                region.node,
+                region.used_bytes(),
+                region.avail_bytes(),
+                region.page_bytes()
+            )?;
+            if region.hole_bytes > 0 {
+                write!(f, ", {} bytes in holes", region.hole_bytes)?;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}", region)?;
+        }
+        for &(start, size) in self.reserved() {
+            writeln!(f, "reserved: [{:#x}, {:#x})", start, start + size)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{ByteAllocator, PageAllocator};
+
+    #[test]
+    fn test_snapshot() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        alloc.alloc(layout(100)).unwrap();
+        let a = alloc.alloc_pages(1, 0).unwrap();
+        let b = alloc.alloc_pages(1, 0).unwrap();
+        alloc.dealloc_pages(a, 1);
+        alloc.reserve(b - 2 * PAGE, PAGE).unwrap();
+        alloc.freeze();
+
+        let snapshot = alloc.snapshot();
+        assert_eq!((snapshot.live_byte_allocs, snapshot.live_pages), (1, 1));
+        assert!(snapshot.frozen && !snapshot.sealed);
+        assert_eq!(snapshot.reserved(), &[(b - 2 * PAGE, PAGE)]);
+        assert_eq!(snapshot.into_iter().count(), 2);
+        // 保留的范围之上的部分成为另一个区域，带着其中的页
+        let low = snapshot.regions()[0];
+        assert!(low.used_bytes() >= 100);
+        assert_eq!((low.page_pos, low.end), (b - 2 * PAGE, b - 2 * PAGE));
+        assert_eq!(low.page_bytes(), 0);
+        let high = snapshot.regions()[1];
+        assert_eq!((high.start, high.page_pos), (b - PAGE, b));
+        assert_eq!(high.avail_bytes(), PAGE);
+        assert_eq!((high.page_bytes(), high.hole_bytes), (PAGE, PAGE));
+
+        let text = snapshot.to_string();
+        assert!(text.starts_with(
+            "early allocator: 2 regions, 1 live byte allocations, 1 live pages, frozen\n"
+        ));
+        assert!(text.contains(&format!("reserved: [{:#x}, {:#x})", b - 2 * PAGE, b - PAGE)));
+    }
+
+    #[test]
+    fn test_region_display() {
+        let region = RegionLayout {
+            start: 0x8020_0000,
+            byte_pos: 0x8020_1000,
+            page_pos: 0x803f_f000,
+            end: 0x8040_0000,
+            node: 0,
+            hole_bytes: 0,
+        };
+        assert_eq!(
+            region.to_string(),
+            "[ bytes-used        | avail-area        | pages-used        ]\n\
+             |                   | -->           <-- |                   |\n\
+             0x80200000          0x80201000          0x803ff000          0x80400000\n\
+             start               b_pos               p_pos               end"
+        );
+    }
+}
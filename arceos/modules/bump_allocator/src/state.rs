@@ -0,0 +1,468 @@
+//! Saving and restoring the whole state of the early allocator.
+//!
+//! `save_state` serializes the regions, the cursors, the holes, the counters
+//! and the statistics, and with the features enabled, the page bitmaps, the
+//! shadow records and the tracking table, into a buffer given by the caller.
+//! `restore_state` builds an equivalent allocator from it, e.g. to resume
+//! after a suspend, or to replay a test from the state before a failure.
+//!
+//! The layout is a sequence of little-endian 64-bit words:
+//!
+//! - a header: [`STATE_MAGIC`], [`STATE_VERSION`], the page size, the mask
+//!   of the features changing the state, and the size of the whole state in
+//!   bytes;
+//! - the fields, in a fixed order. An array is written as its length
+//!   followed by its items, a `bool` as 0 or 1, an `Option` as 0, or 1
+//!   followed by the value, and raw bytes as their length followed by them,
+//!   padded to a word.
+//!
+//! A new field is added by bumping the version; `restore_state` rejects the
+//! versions it does not know, the states of another page size or of other
+//! features, and the truncated or inconsistent ones.
+//!
+//! The state describes the memory at its addresses, without the data of the
+//! allocations: it is restored on the same memory, whose metadata (the page
+//! bitmaps and the tracking table) is written back from the state. The
+//! extension and watermark hooks are functions, which are not saved: they
+//! are to be set again. The call sites of the tracking table are saved as
+//! pointers, only valid in the same kernel image.
+
+use allocator::{AllocError, AllocResult};
+
+#[cfg(not(feature = "page-bitmap"))]
+use crate::MAX_PAGE_HOLES;
+use crate::{
+    huge, numa, watermark, DomainStats, EarlyAllocator, PageRange, Region, MAX_NODES, MAX_REGIONS,
+    MAX_RESERVED, NR_DOMAINS, NR_SIZE_CLASSES,
+};
+
+/// 状态的魔数，"BUMPSTAT"
+pub const STATE_MAGIC: u64 = u64::from_le_bytes(*b"BUMPSTAT");
+
+/// 当前的状态格式版本
+pub const STATE_VERSION: u64 = 1;
+
+/// 头部的字数，最后一个字为状态的总字节数
+const HEADER_WORDS: usize = 5;
+
+/// 改变状态内容的特性的掩码
+const FEATURES: u64 = cfg!(feature = "page-bitmap") as u64
+    | (cfg!(feature = "hardened") as u64) << 1
+    | (cfg!(feature = "tracking") as u64) << 2
+    | (cfg!(feature = "debug-poison") as u64) << 3;
+
+/// 按字写入状态，超出缓冲区的部分只计数
+pub(crate) struct StateWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> StateWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn raw(&mut self, value: u64) {
+        if let Some(dst) = self.buf.get_mut(self.len..self.len + 8) {
+            dst.copy_from_slice(&value.to_le_bytes());
+        }
+        self.len += 8;
+    }
+
+    pub fn word(&mut self, value: usize) {
+        self.raw(value as u64);
+    }
+
+    pub fn flag(&mut self, value: bool) {
+        self.raw(value as u64);
+    }
+
+    pub fn option(&mut self, value: Option<usize>) {
+        match value {
+            Some(value) => {
+                self.raw(1);
+                self.word(value);
+            }
+            None => self.raw(0),
+        }
+    }
+
+    /// 写入长度和按字补齐的字节
+    #[cfg_attr(not(feature = "page-bitmap"), allow(dead_code))]
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.word(data.len());
+        for chunk in data.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.raw(u64::from_le_bytes(word));
+        }
+    }
+}
+
+/// 按字读取状态，越界或取值无效时返回 [`AllocError::InvalidParam`]
+pub(crate) struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// 是否将元数据写回内存，先完整检查一遍状态再写
+    #[cfg_attr(
+        not(any(feature = "page-bitmap", feature = "tracking")),
+        allow(dead_code)
+    )]
+    pub write_back: bool,
+}
+
+impl<'a> StateReader<'a> {
+    fn raw(&mut self) -> AllocResult<u64> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + 8)
+            .ok_or(AllocError::InvalidParam)?;
+        self.pos += 8;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn word(&mut self) -> AllocResult<usize> {
+        usize::try_from(self.raw()?).map_err(|_| AllocError::InvalidParam)
+    }
+
+    pub fn flag(&mut self) -> AllocResult<bool> {
+        match self.raw()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(AllocError::InvalidParam),
+        }
+    }
+
+    pub fn option(&mut self) -> AllocResult<Option<usize>> {
+        match self.flag()? {
+            true => self.word().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    /// 读取数组长度，超过 `max` 时出错
+    pub fn len(&mut self, max: usize) -> AllocResult<usize> {
+        let len = self.word()?;
+        if len > max {
+            return Err(AllocError::InvalidParam);
+        }
+        Ok(len)
+    }
+
+    /// 读取 [`StateWriter::bytes`] 写入的字节
+    #[cfg_attr(not(feature = "page-bitmap"), allow(dead_code))]
+    pub fn bytes(&mut self) -> AllocResult<&'a [u8]> {
+        let len = self.word()?;
+        let padded = len
+            .checked_next_multiple_of(8)
+            .ok_or(AllocError::InvalidParam)?;
+        let end = self
+            .pos
+            .checked_add(padded)
+            .ok_or(AllocError::InvalidParam)?;
+        let data = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .filter(|_| end <= self.buf.len())
+            .ok_or(AllocError::InvalidParam)?;
+        self.pos = end;
+        Ok(data)
+    }
+}
+
+impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
+    /// 将分配器的全部状态写入 `buf`，返回状态的字节数
+    ///
+    /// 只有 `buf` 不小于返回值时状态才被完整写入，可先以空缓冲区调用得到所需
+    /// 的大小。状态为小端的 64 位字，以 [`STATE_MAGIC`] 和 [`STATE_VERSION`]
+    /// 开头。
+    pub fn save_state(&self, buf: &mut [u8]) -> usize {
+        let mut w = StateWriter::new(buf);
+        w.raw(STATE_MAGIC);
+        w.raw(STATE_VERSION);
+        w.word(PAGE_SIZE);
+        w.raw(FEATURES);
+        // 总字节数，最后回填
+        w.raw(0);
+
+        w.word(self.region_count);
+        for region in self.regions() {
+            w.word(region.start);
+            w.word(region.end);
+            w.word(region.byte_pos);
+            w.word(region.page_pos);
+            w.word(region.node);
+            #[cfg(feature = "page-bitmap")]
+            region.bitmap.save(&mut w);
+        }
+        w.word(self.alloc_count);
+        #[cfg(not(feature = "page-bitmap"))]
+        {
+            w.word(self.hole_count);
+            for hole in &self.holes[..self.hole_count] {
+                w.word(hole.start);
+                w.word(hole.end);
+            }
+        }
+        w.flag(self.sealed);
+        w.flag(self.frozen);
+        w.flag(self.zero_on_free);
+        self.low_watermark.save(&mut w);
+        w.word(self.handed_bytes);
+        w.word(self.live_pages);
+
+        let stats = &self.stats;
+        w.word(stats.peak_bytes);
+        w.word(stats.peak_pages);
+        w.word(stats.byte_allocs);
+        w.word(stats.page_allocs);
+        w.word(NR_SIZE_CLASSES);
+        for &count in &stats.size_classes {
+            w.word(count);
+        }
+        w.word(stats.failed_byte_allocs);
+        w.word(stats.failed_page_allocs);
+        w.word(stats.invalid_frees);
+
+        self.sized.save(&mut w);
+        w.word(MAX_NODES);
+        for counters in &self.node_counters {
+            w.word(counters.byte_allocs);
+            w.word(counters.page_allocs);
+            w.word(counters.fallbacks);
+        }
+        w.word(NR_DOMAINS);
+        for domain in &self.domains {
+            w.word(domain.live_bytes);
+            w.word(domain.live_pages);
+            w.word(domain.peak_bytes);
+            w.word(domain.allocs);
+            w.word(domain.over_budget);
+            w.option(domain.budget);
+        }
+        w.word(self.reserved_count);
+        for range in &self.reserved[..self.reserved_count] {
+            w.word(range.start);
+            w.word(range.end);
+        }
+        #[cfg(feature = "hardened")]
+        self.shadow.save(&mut w);
+        #[cfg(feature = "tracking")]
+        self.tracker.save(&mut w);
+
+        let len = w.len;
+        if len <= buf.len() {
+            let total = (HEADER_WORDS - 1) * 8;
+            buf[total..total + 8].copy_from_slice(&(len as u64).to_le_bytes());
+        }
+        len
+    }
+
+    /// 由 [`save_state`] 写入的状态重建分配器，扩展和水位线的回调为空
+    ///
+    /// 版本、页大小或特性不符，或状态被截断、取值无效时返回
+    /// [`AllocError::InvalidParam`]。
+    ///
+    /// # Safety
+    ///
+    /// `buf` must be a state saved by [`save_state`] in the same kernel
+    /// image, and the memory of its regions must still be valid for the
+    /// allocator: the page bitmaps and the tracking table are written back
+    /// to it, and the allocations of the state are assumed to be live.
+    ///
+    /// [`save_state`]: Self::save_state
+    pub unsafe fn restore_state(buf: &[u8]) -> AllocResult<Self> {
+        Self::read_state(buf, false)?;
+        Self::read_state(buf, true)
+    }
+
+    unsafe fn read_state(buf: &[u8], write_back: bool) -> AllocResult<Self> {
+        let mut r = StateReader {
+            buf,
+            pos: 0,
+            write_back,
+        };
+        if r.raw()? != STATE_MAGIC
+            || r.raw()? != STATE_VERSION
+            || r.word()? != PAGE_SIZE
+            || r.raw()? != FEATURES
+        {
+            return Err(AllocError::InvalidParam);
+        }
+        let total = r.word()?;
+        if total > buf.len() {
+            return Err(AllocError::InvalidParam);
+        }
+        r.buf = &buf[..total];
+
+        let mut alloc = Self::new();
+        alloc.region_count = r.len(MAX_REGIONS)?;
+        for i in 0..alloc.region_count {
+            let (start, end) = (r.word()?, r.word()?);
+            let region = Region {
+                byte_pos: r.word()?,
+                page_pos: r.word()?,
+                node: r.word()?,
+                #[cfg(feature = "page-bitmap")]
+                bitmap: crate::bitmap::PageBitmap::load(&mut r)?,
+                ..Region::new(start, end)
+            };
+            if !(region.start <= region.byte_pos
+                && region.byte_pos <= region.page_pos
+                && region.page_pos <= region.end
+                && region.node < MAX_NODES)
+            {
+                return Err(AllocError::InvalidParam);
+            }
+            alloc.regions[i] = region;
+        }
+        alloc.alloc_count = r.word()?;
+        #[cfg(not(feature = "page-bitmap"))]
+        {
+            alloc.hole_count = r.len(MAX_PAGE_HOLES)?;
+            for hole in &mut alloc.holes[..alloc.hole_count] {
+                *hole = PageRange {
+                    start: r.word()?,
+                    end: r.word()?,
+                };
+            }
+        }
+        alloc.sealed = r.flag()?;
+        alloc.frozen = r.flag()?;
+        alloc.zero_on_free = r.flag()?;
+        alloc.low_watermark = watermark::LowWatermark::load(&mut r)?;
+        alloc.handed_bytes = r.word()?;
+        alloc.live_pages = r.word()?;
+
+        let stats = &mut alloc.stats;
+        stats.peak_bytes = r.word()?;
+        stats.peak_pages = r.word()?;
+        stats.byte_allocs = r.word()?;
+        stats.page_allocs = r.word()?;
+        if r.word()? != NR_SIZE_CLASSES {
+            return Err(AllocError::InvalidParam);
+        }
+        for count in &mut stats.size_classes {
+            *count = r.word()?;
+        }
+        stats.failed_byte_allocs = r.word()?;
+        stats.failed_page_allocs = r.word()?;
+        stats.invalid_frees = r.word()?;
+
+        alloc.sized = huge::SizedPages::load(&mut r)?;
+        if r.word()? != MAX_NODES {
+            return Err(AllocError::InvalidParam);
+        }
+        for counters in &mut alloc.node_counters {
+            *counters = numa::NodeCounters {
+                byte_allocs: r.word()?,
+                page_allocs: r.word()?,
+                fallbacks: r.word()?,
+            };
+        }
+        if r.word()? != NR_DOMAINS {
+            return Err(AllocError::InvalidParam);
+        }
+        for domain in &mut alloc.domains {
+            *domain = DomainStats {
+                live_bytes: r.word()?,
+                live_pages: r.word()?,
+                peak_bytes: r.word()?,
+                allocs: r.word()?,
+                over_budget: r.word()?,
+                budget: r.option()?,
+            };
+        }
+        alloc.reserved_count = r.len(MAX_RESERVED)?;
+        for range in &mut alloc.reserved[..alloc.reserved_count] {
+            *range = PageRange {
+                start: r.word()?,
+                end: r.word()?,
+            };
+        }
+        #[cfg(feature = "hardened")]
+        {
+            alloc.shadow = crate::hardened::Shadow::load(&mut r)?;
+        }
+        #[cfg(feature = "tracking")]
+        {
+            alloc.tracker = crate::track::Tracker::load(&mut r)?;
+        }
+        if r.pos != total {
+            return Err(AllocError::InvalidParam);
+        }
+        Ok(alloc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use crate::{AllocDomain, Watermark};
+    use allocator::{ByteAllocator, PageAllocator};
+
+    fn save(alloc: &EarlyAllocator) -> Vec<u8> {
+        let mut buf = vec![0; alloc.save_state(&mut [])];
+        assert_eq!(alloc.save_state(&mut buf), buf.len());
+        buf
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        alloc.alloc(layout(40)).unwrap();
+        let a = alloc.alloc_pages(1, 0).unwrap();
+        alloc.alloc_pages(2, 0).unwrap();
+        alloc.dealloc_pages(a, 1);
+        alloc.set_domain_budget(AllocDomain::Fs, Some(PAGE));
+        alloc
+            .set_low_watermark(Watermark::Percent(10), None)
+            .unwrap();
+        let buf = save(&alloc);
+        assert_eq!(&buf[..8], &STATE_MAGIC.to_le_bytes());
+        let snapshot = alloc.snapshot();
+        // 恢复后的分配与原分配器在保存之后的分配相同，元数据被写回
+        let bytes = alloc.alloc(layout(8));
+        let pages = alloc.alloc_pages(1, 0);
+
+        let mut restored = unsafe { EarlyAllocator::<PAGE>::restore_state(&buf) }.unwrap();
+        assert_eq!(restored.snapshot().regions(), snapshot.regions());
+        assert_eq!(restored.live_byte_allocs(), 1);
+        assert_eq!(restored.used_pages(), 2);
+        assert_eq!(restored.stats().page_allocs, 2);
+        assert_eq!(restored.domain_stats(AllocDomain::Fs).budget, Some(PAGE));
+        assert_eq!(save(&restored), buf);
+        assert_eq!(restored.alloc(layout(8)), bytes);
+        assert_eq!(restored.alloc_pages(1, 0), pages);
+    }
+
+    #[test]
+    fn test_rejected() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        alloc.alloc(layout(40)).unwrap();
+        let buf = save(&alloc);
+        let restore = |buf: &[u8]| unsafe { EarlyAllocator::<PAGE>::restore_state(buf) }.err();
+
+        let mut bad = buf.clone();
+        bad[8..16].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(restore(&bad), Some(AllocError::InvalidParam));
+        bad = buf.clone();
+        bad[0] ^= 1;
+        assert_eq!(restore(&bad), Some(AllocError::InvalidParam));
+        assert_eq!(
+            restore(&buf[..buf.len() - 8]),
+            Some(AllocError::InvalidParam)
+        );
+        assert_eq!(restore(&buf[..16]), Some(AllocError::InvalidParam));
+        assert!(unsafe { EarlyAllocator::<8192>::restore_state(&buf) }.is_err());
+        // 区域的位置不一致
+        bad = buf.clone();
+        let byte_pos = HEADER_WORDS * 8 + 3 * 8;
+        bad[byte_pos..byte_pos + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(restore(&bad), Some(AllocError::InvalidParam));
+        assert_eq!(restore(&buf), None);
+    }
+}
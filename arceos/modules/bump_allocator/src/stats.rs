@@ -0,0 +1,122 @@
+//! 早期分配器的统计：峰值、字节分配的大小分布和失败次数
+
+/// 字节分配大小分布的档数
+pub const NR_SIZE_CLASSES: usize = 16;
+
+/// 最小一档的上限，之后每档翻倍，最后一档不设上限
+const MIN_CLASS_SIZE: usize = 8;
+
+/// 早期分配器的统计，由 [`EarlyAllocator::stats`] 返回
+///
+/// 自 `init` 以来累计，`rollback` 和字节区域的重置不影响它们。
+///
+/// [`EarlyAllocator::stats`]: crate::EarlyAllocator::stats
+#[derive(Debug, Clone, Copy)]
+pub struct AllocStats {
+    /// 字节区域使用的峰值字节数，包括对齐的间隙
+    pub peak_bytes: usize,
+    /// 同时被分配的页数的峰值
+    pub peak_pages: usize,
+    /// 成功的字节分配次数
+    pub byte_allocs: usize,
+    /// 成功的页分配次数
+    pub page_allocs: usize,
+    /// 字节分配的大小分布，第 `i` 档的上限见 [`size_class_limit`]
+    ///
+    /// [`size_class_limit`]: Self::size_class_limit
+    pub size_classes: [usize; NR_SIZE_CLASSES],
+    /// 因内存不足失败的字节分配次数
+    pub failed_byte_allocs: usize,
+    /// 因内存不足失败的页分配次数
+    pub failed_page_allocs: usize,
+    /// 被拒绝的无效释放次数：重复释放、释放未分配的地址或大小不符，仅在
+    /// `hardened` 特性下检测
+    pub invalid_frees: usize,
+}
+
+impl AllocStats {
+    pub(crate) const EMPTY: Self = Self {
+        peak_bytes: 0,
+        peak_pages: 0,
+        byte_allocs: 0,
+        page_allocs: 0,
+        size_classes: [0; NR_SIZE_CLASSES],
+        failed_byte_allocs: 0,
+        failed_page_allocs: 0,
+        invalid_frees: 0,
+    };
+
+    /// 第 `class` 档分配大小的上限（含），最后一档返回 [`None`]
+    pub fn size_class_limit(class: usize) -> Option<usize> {
+        (class + 1 < NR_SIZE_CLASSES).then(|| MIN_CLASS_SIZE << class)
+    }
+
+    /// 大小为 `size` 的分配所在的档
+    fn size_class(size: usize) -> usize {
+        let class = size
+            .div_ceil(MIN_CLASS_SIZE)
+            .next_power_of_two()
+            .trailing_zeros();
+        (class as usize).min(NR_SIZE_CLASSES - 1)
+    }
+
+    /// 记录一次 `size` 字节的分配，字节区域已使用 `used` 字节
+    pub(crate) fn on_byte_alloc(&mut self, size: usize, used: usize) {
+        self.byte_allocs += 1;
+        self.size_classes[Self::size_class(size)] += 1;
+        self.on_byte_grow(used);
+    }
+
+    /// 字节区域增长到已使用 `used` 字节
+    pub(crate) fn on_byte_grow(&mut self, used: usize) {
+        self.peak_bytes = self.peak_bytes.max(used);
+    }
+
+    /// 记录一次页分配，当前共分配了 `live_pages` 页
+    pub(crate) fn on_page_alloc(&mut self, live_pages: usize) {
+        self.page_allocs += 1;
+        self.peak_pages = self.peak_pages.max(live_pages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{ByteAllocator, PageAllocator};
+
+    #[test]
+    fn test_size_classes() {
+        assert_eq!(AllocStats::size_class_limit(0), Some(8));
+        assert_eq!(AllocStats::size_class_limit(3), Some(64));
+        assert_eq!(AllocStats::size_class_limit(NR_SIZE_CLASSES - 1), None);
+        for (size, class) in [(1, 0), (8, 0), (9, 1), (16, 1), (17, 2), (64, 3), (65, 4)] {
+            assert_eq!(AllocStats::size_class(size), class);
+        }
+        assert_eq!(AllocStats::size_class(usize::MAX / 2), NR_SIZE_CLASSES - 1);
+    }
+
+    #[test]
+    fn test_counts() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let a = alloc.alloc(layout(8)).unwrap();
+        let b = alloc.alloc(layout(60)).unwrap();
+        alloc.dealloc(a, layout(8));
+        alloc.dealloc(b, layout(60));
+        let pos = alloc.alloc_pages(2, 0).unwrap();
+        alloc.dealloc_pages(pos, 2);
+        alloc.alloc_pages(1, 0).unwrap();
+        assert!(alloc.alloc(layout(32 * PAGE)).is_err());
+        assert!(alloc.alloc_pages(32, 0).is_err());
+
+        // 峰值不随释放下降
+        let stats = alloc.stats();
+        assert!(stats.peak_bytes >= 68);
+        assert_eq!((stats.byte_allocs, stats.page_allocs), (2, 2));
+        assert_eq!(stats.peak_pages, 2);
+        assert_eq!((stats.size_classes[0], stats.size_classes[3]), (1, 1));
+        assert_eq!((stats.failed_byte_allocs, stats.failed_page_allocs), (1, 1));
+        assert_eq!(stats.invalid_frees, 0);
+    }
+}
@@ -0,0 +1,393 @@
+use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::EarlyAllocator;
+
+pub(crate) const PAGE: usize = 4096;
+
+/// 测试用的一块按页对齐的内存
+pub(crate) struct Memory {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Memory {
+    pub fn new(pages: usize) -> Self {
+        let layout = Layout::from_size_align(pages * PAGE, PAGE).unwrap();
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        assert!(!ptr.is_null());
+        Self { ptr, layout }
+    }
+
+    pub fn start(&self) -> usize {
+        self.ptr as usize
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn end(&self) -> usize {
+        self.start() + self.size()
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// 以整块 `mem` 初始化的分配器
+pub(crate) fn allocator(mem: &Memory) -> EarlyAllocator {
+    let mut alloc = EarlyAllocator::new();
+    alloc.init(mem.start(), mem.size());
+    alloc
+}
+
+pub(crate) fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size, 8).unwrap()
+}
+
+#[test]
+fn test_bytes_reset() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    let avail = alloc.available_bytes();
+    let start = alloc.snapshot().regions()[0].start;
+
+    let a = alloc.alloc(layout(24)).unwrap();
+    let b = alloc
+        .alloc(Layout::from_size_align(64, 64).unwrap())
+        .unwrap();
+    let c = alloc.alloc(layout(8)).unwrap();
+    assert!(a < b && b < c);
+    assert_eq!(b.as_ptr() as usize % 64, 0);
+    assert_eq!(alloc.live_byte_allocs(), 3);
+    assert!(alloc.used_bytes() >= 96);
+    assert!(alloc.owns_bytes(b.as_ptr() as usize));
+
+    // 释放的顺序不影响，最后一次释放时重置
+    alloc.dealloc(b, Layout::from_size_align(64, 64).unwrap());
+    alloc.dealloc(a, layout(24));
+    assert!(alloc.snapshot().regions()[0].byte_pos > start);
+    alloc.dealloc(c, layout(8));
+    assert_eq!(alloc.live_byte_allocs(), 0);
+    assert_eq!(alloc.snapshot().regions()[0].byte_pos, start);
+    assert_eq!(alloc.available_bytes(), avail);
+    assert_eq!(alloc.alloc(layout(24)).unwrap(), a);
+    assert_eq!(alloc.alloc(layout(0)), Err(AllocError::InvalidParam));
+}
+
+#[test]
+fn test_pages_lifo() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    let avail = alloc.available_pages();
+
+    let a = alloc.alloc_pages(1, 0).unwrap();
+    let b = alloc.alloc_pages(2, 0).unwrap();
+    assert!(a + PAGE <= mem.end());
+    assert_eq!(b, a - 2 * PAGE);
+    assert_eq!(alloc.used_pages(), 3);
+    assert_eq!(alloc.available_pages(), avail - 3);
+
+    alloc.dealloc_pages(b, 2);
+    assert_eq!(alloc.snapshot().regions()[0].page_pos, a);
+    alloc.dealloc_pages(a, 1);
+    assert_eq!(alloc.used_pages(), 0);
+    assert_eq!(alloc.available_pages(), avail);
+    assert_eq!(alloc.alloc_pages(0, 0), Err(AllocError::InvalidParam));
+}
+
+#[cfg(not(feature = "page-bitmap"))]
+#[test]
+fn test_page_holes() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    let avail = alloc.available_pages();
+
+    let a = alloc.alloc_pages(1, 0).unwrap();
+    let b = alloc.alloc_pages(1, 0).unwrap();
+    let c = alloc.alloc_pages(1, 0).unwrap();
+
+    // 乱序释放的页成为空洞，相邻的空洞合并，不被复用
+    alloc.dealloc_pages(b, 1);
+    alloc.dealloc_pages(a, 1);
+    let region = alloc.snapshot().regions()[0];
+    assert_eq!(region.page_pos, c);
+    assert_eq!(region.hole_bytes, 2 * PAGE);
+    assert_eq!(alloc.used_pages(), 1);
+    assert_eq!(alloc.alloc_pages(1, 0).unwrap(), c - PAGE);
+    alloc.dealloc_pages(c - PAGE, 1);
+
+    // 释放最低处的页时一并回收相邻的空洞
+    alloc.dealloc_pages(c, 1);
+    let region = alloc.snapshot().regions()[0];
+    assert_eq!(region.page_pos, region.end);
+    assert_eq!(region.hole_bytes, 0);
+    assert_eq!(alloc.available_pages(), avail);
+}
+
+#[test]
+fn test_alignment_gap() {
+    let mem = Memory::new(32);
+    let mut alloc = allocator(&mem);
+    let avail = alloc.available_pages();
+
+    let a = alloc.alloc_pages(1, 0).unwrap();
+    let b = alloc.alloc_pages(1, 3).unwrap();
+    assert_eq!(b % (8 * PAGE), 0);
+    assert!(b + PAGE <= a);
+    assert_eq!(alloc.used_pages(), 2);
+
+    alloc.dealloc_pages(b, 1);
+    alloc.dealloc_pages(a, 1);
+    assert_eq!(alloc.used_pages(), 0);
+    assert_eq!(alloc.available_pages(), avail);
+}
+
+#[test]
+fn test_multi_region() {
+    let mem = Memory::new(4);
+    let more = Memory::new(8);
+    let mut alloc = allocator(&mem);
+    assert_eq!(
+        alloc.add_memory(mem.start() + PAGE, PAGE),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        alloc.add_memory(more.start(), 0),
+        Err(AllocError::InvalidParam)
+    );
+    alloc.add_memory(more.start(), more.size()).unwrap();
+    assert_eq!(alloc.snapshot().regions().len(), 2);
+
+    // 第一个区域耗尽后溢出到第二个区域
+    let first = alloc.snapshot().regions()[0].avail_bytes() / PAGE;
+    let pages: Vec<_> = (0..alloc.available_pages())
+        .map(|_| alloc.alloc_pages(1, 0).unwrap())
+        .collect();
+    assert!(pages[..first].iter().all(|&pos| pos < mem.end()));
+    assert!(pages[first..]
+        .iter()
+        .all(|&pos| (more.start()..more.end()).contains(&pos)));
+    assert_eq!(alloc.alloc_pages(1, 0), Err(AllocError::NoMemory));
+    assert_eq!(alloc.stats().failed_page_allocs, 1);
+
+    for &pos in pages.iter().rev() {
+        alloc.dealloc_pages(pos, 1);
+    }
+    // 放不进第一个区域的字节分配
+    let big = alloc.alloc(layout(6 * PAGE)).unwrap();
+    assert!((more.start()..more.end()).contains(&(big.as_ptr() as usize)));
+}
+
+#[test]
+fn test_checkpoint_rollback() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+
+    let a = alloc.alloc(layout(32)).unwrap();
+    let mark = alloc.checkpoint();
+    let b = alloc.alloc(layout(100)).unwrap();
+    alloc.alloc(layout(200)).unwrap();
+    alloc.rollback(mark);
+    assert_eq!(alloc.live_byte_allocs(), 1);
+    assert_eq!(alloc.alloc(layout(100)).unwrap(), b);
+
+    // 检查点之前的分配都已释放时，保持重置后的状态
+    let mark = alloc.checkpoint();
+    alloc.dealloc(b, layout(100));
+    alloc.dealloc(a, layout(32));
+    alloc.rollback(mark);
+    assert_eq!(alloc.live_byte_allocs(), 0);
+    assert_eq!(alloc.alloc(layout(32)).unwrap(), a);
+}
+
+#[test]
+fn test_realloc() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+
+    let a = alloc.alloc(layout(16)).unwrap();
+    unsafe { a.as_ptr().write_bytes(0x5a, 16) };
+    // 最近的一次分配原地扩展和收缩
+    let grown = unsafe { alloc.realloc(a, layout(16), 64) }.unwrap();
+    assert_eq!(grown, a);
+    let shrunk = unsafe { alloc.realloc(a, layout(64), 32) }.unwrap();
+    assert_eq!(shrunk, a);
+
+    // 之后有其他分配时移动并复制
+    let b = alloc.alloc(layout(8)).unwrap();
+    let moved = unsafe { alloc.realloc(a, layout(32), 128) }.unwrap();
+    assert!(moved > b);
+    let data = unsafe { core::slice::from_raw_parts(moved.as_ptr(), 16) };
+    assert!(data.iter().all(|&byte| byte == 0x5a));
+    assert_eq!(alloc.live_byte_allocs(), 2);
+    assert_eq!(
+        unsafe { alloc.realloc(moved, layout(128), 0) },
+        Err(AllocError::InvalidParam)
+    );
+}
+
+#[test]
+fn test_take_remaining() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    let bytes = alloc.alloc(layout(64)).unwrap();
+    let page = alloc.alloc_pages(1, 0).unwrap();
+    let avail = alloc.available_bytes();
+
+    let handoff = alloc.take_remaining();
+    assert!(alloc.is_sealed());
+    assert_eq!(handoff.ranges().len(), 1);
+    assert_eq!(handoff.nodes(), &[0]);
+    assert_eq!(handoff.free_bytes(), avail);
+    assert_eq!(handoff.live_byte_allocs, 1);
+    assert_eq!(handoff.live_pages, 1);
+    assert_eq!(handoff.ranges()[0].0 + avail, page);
+
+    // 不再分配，已有的分配仍可释放
+    assert_eq!(alloc.alloc(layout(8)), Err(AllocError::NoMemory));
+    assert_eq!(alloc.alloc_pages(1, 0), Err(AllocError::NoMemory));
+    assert_eq!(alloc.available_bytes(), 0);
+    alloc.dealloc(bytes, layout(64));
+    alloc.dealloc_pages(page, 1);
+    assert_eq!(alloc.used_pages(), 0);
+    assert!(alloc.take_remaining().ranges().is_empty());
+}
+
+#[test]
+fn test_reserve() {
+    let mem = Memory::new(16);
+    let mut alloc = EarlyAllocator::<PAGE>::new();
+    let hole = mem.start() + 8 * PAGE;
+    alloc.reserve(hole, 2 * PAGE).unwrap();
+    alloc.init(mem.start(), mem.size());
+    let snapshot = alloc.snapshot();
+    assert_eq!(snapshot.regions().len(), 2);
+    assert!(snapshot.regions()[0].end <= hole);
+    assert_eq!(snapshot.regions()[1].start, hole + 2 * PAGE);
+    assert_eq!(snapshot.reserved(), &[(hole, 2 * PAGE)]);
+
+    let pages: Vec<_> = (0..alloc.available_pages())
+        .map(|_| alloc.alloc_pages(1, 0).unwrap())
+        .collect();
+    assert!(pages
+        .iter()
+        .all(|&pos| pos + PAGE <= hole || pos >= hole + 2 * PAGE));
+
+    // 初始化之后只能保留空闲窗口中的范围
+    assert_eq!(
+        alloc.reserve(pages[0], PAGE),
+        Err(AllocError::MemoryOverlap)
+    );
+    for &pos in pages.iter().rev() {
+        alloc.dealloc_pages(pos, 1);
+    }
+    let window = alloc.snapshot().regions()[1];
+    let mid = window.page_pos - 2 * PAGE;
+    alloc.reserve(mid, PAGE).unwrap();
+    assert_eq!(alloc.snapshot().regions().len(), 3);
+    assert_eq!(alloc.reserved().count(), 2);
+    assert_eq!(alloc.reserve(mid, 0), Err(AllocError::InvalidParam));
+}
+
+#[test]
+fn test_alloc_pages_at() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    let avail = alloc.available_pages();
+    let base = alloc.snapshot().regions()[0].page_pos - 4 * PAGE;
+
+    assert_eq!(alloc.alloc_pages_at(base, 2), Ok(base));
+    assert_eq!(
+        alloc.alloc_pages_at(base, 1),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        alloc.alloc_pages_at(base + 1, 1),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(
+        alloc.alloc_pages_at(mem.end() + 16 * PAGE, 1),
+        Err(AllocError::NoMemory)
+    );
+    assert_eq!(alloc.used_pages(), 2);
+    // 之上的间隙不被下移的 page_pos 覆盖
+    let next = alloc.alloc_pages(1, 0).unwrap();
+    assert!(next < base || next >= base + 2 * PAGE);
+
+    alloc.dealloc_pages(next, 1);
+    alloc.dealloc_pages(base, 2);
+    assert_eq!(alloc.available_pages(), avail);
+}
+
+#[test]
+fn test_zero_on_free() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    alloc.set_zero_on_free(true);
+    assert!(alloc.zero_on_free());
+
+    let a = alloc.alloc(layout(64)).unwrap();
+    let data = unsafe { core::slice::from_raw_parts_mut(a.as_ptr(), 64) };
+    data.fill(0xff);
+    alloc.dealloc(a, layout(64));
+    assert!(data.iter().all(|&byte| byte == 0));
+
+    let pos = alloc.alloc_pages(1, 0).unwrap();
+    let page = unsafe { core::slice::from_raw_parts_mut(pos as *mut u8, PAGE) };
+    page.fill(0xff);
+    alloc.dealloc_pages(pos, 1);
+    assert!(page.iter().all(|&byte| byte == 0));
+
+    alloc.set_zero_on_free(false);
+    let pos = alloc.alloc_pages(1, 0).unwrap();
+    unsafe { (pos as *mut u8).write_bytes(0xff, PAGE) };
+    alloc.dealloc_pages(pos, 1);
+    let pos = alloc.alloc_pages_zeroed(1, 0).unwrap();
+    let page = unsafe { core::slice::from_raw_parts(pos as *const u8, PAGE) };
+    assert!(page.iter().all(|&byte| byte == 0));
+    let a = alloc.alloc_zeroed(layout(32)).unwrap();
+    let data = unsafe { core::slice::from_raw_parts(a.as_ptr(), 32) };
+    assert!(data.iter().all(|&byte| byte == 0));
+}
+
+/// 扩展回调给出的区域起始地址，0 表示没有
+static EXTRA: AtomicUsize = AtomicUsize::new(0);
+
+fn extend(needed: usize) -> Option<(usize, usize)> {
+    assert!(needed >= 8 * PAGE);
+    match EXTRA.swap(0, Ordering::SeqCst) {
+        0 => None,
+        start => Some((start, 16 * PAGE)),
+    }
+}
+
+#[test]
+fn test_extend_hook() {
+    let mem = Memory::new(4);
+    let more = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    alloc.set_extend_hook(extend);
+
+    EXTRA.store(more.start(), Ordering::SeqCst);
+    let pos = alloc.alloc_pages(8, 0).unwrap();
+    assert!((more.start()..more.end()).contains(&pos));
+    assert_eq!(alloc.snapshot().regions().len(), 2);
+    // 回调没有新的区域时失败
+    assert_eq!(alloc.alloc_pages(9, 0), Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_debug() {
+    let mem = Memory::new(16);
+    let mut alloc = allocator(&mem);
+    alloc.alloc_pages(1, 0).unwrap();
+    let text = format!("{:?}", alloc);
+    assert!(text.starts_with("early allocator: 1 regions, 0 live byte allocations, 1 live pages"));
+    assert!(text.contains("start               b_pos               p_pos               end"));
+}
@@ -0,0 +1,351 @@
+//! Allocation tracking, with the `tracking` feature.
+//!
+//! Each live byte allocation and page allocation is recorded with its call
+//! site, in a table carved out of the start of the first region by `init`,
+//! so no heap is needed. [`EarlyAllocator::report_leaks`] lists the
+//! allocations still live, e.g. when the formal allocators take over.
+//!
+//! The call site is the caller of `alloc`, `realloc` or `alloc_pages`, by
+//! `#[track_caller]`, or the tag given to `alloc_tagged` or
+//! `alloc_pages_tagged`. When the table is full the allocations still
+//! succeed, but are only counted by [`EarlyAllocator::untracked_allocs`].
+//!
+//! [`EarlyAllocator::report_leaks`]: crate::EarlyAllocator::report_leaks
+//! [`EarlyAllocator::untracked_allocs`]: crate::EarlyAllocator::untracked_allocs
+
+use allocator::{AllocError, AllocResult};
+use core::fmt;
+use core::mem::{align_of, size_of};
+use core::panic::Location;
+
+use crate::state::{StateReader, StateWriter};
+
+/// 最多记录的存活分配数量，且记录表至多占第一个区域的 1/8
+pub const MAX_TRACK_RECORDS: usize = 512;
+
+/// 分配的调用位置
+#[derive(Debug, Clone, Copy)]
+pub enum AllocSite {
+    /// 由 `#[track_caller]` 得到的调用者
+    Caller(&'static Location<'static>),
+    /// 调用者给出的标签
+    Tag(&'static str),
+}
+
+impl fmt::Display for AllocSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Caller(location) => write!(f, "{}", location),
+            Self::Tag(tag) => f.write_str(tag),
+        }
+    }
+}
+
+/// 存活分配的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocKind {
+    /// 字节分配
+    Bytes,
+    /// 页分配
+    Pages,
+}
+
+/// 一个存活的分配，由 [`EarlyAllocator::report_leaks`] 列出
+///
+/// [`EarlyAllocator::report_leaks`]: crate::EarlyAllocator::report_leaks
+#[derive(Debug, Clone, Copy)]
+pub struct LiveAlloc {
+    /// 字节分配或页分配
+    pub kind: AllocKind,
+    /// 起始地址
+    pub pos: usize,
+    /// 字节数，页分配为页数乘以页大小
+    pub size: usize,
+    /// 调用位置
+    pub site: AllocSite,
+}
+
+impl fmt::Display for LiveAlloc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AllocKind::Bytes => "bytes",
+            AllocKind::Pages => "pages",
+        };
+        write!(
+            f,
+            "{} [{:#x}, {:#x}) from {}",
+            kind,
+            self.pos,
+            self.pos + self.size,
+            self.site
+        )
+    }
+}
+
+/// 存活分配的记录表，位于第一个区域的起始处
+pub struct Tracker {
+    // 记录表所在地址
+    addr: usize,
+    // 可容纳的记录数
+    capacity: usize,
+    // 记录数
+    len: usize,
+    // 因记录表已满而未记录的分配次数
+    untracked: usize,
+}
+
+impl Tracker {
+    pub const EMPTY: Self = Self {
+        addr: 0,
+        capacity: 0,
+        len: 0,
+        untracked: 0,
+    };
+
+    /// 在区域 `[start, end)` 的起始处放置记录表，返回记录表和可分配区域的
+    /// 新起始地址
+    pub fn reserve(start: usize, end: usize) -> (Self, usize) {
+        let Some(addr) = start.checked_next_multiple_of(align_of::<LiveAlloc>()) else {
+            return (Self::EMPTY, start);
+        };
+        let capacity =
+            (end.saturating_sub(addr) / 8 / size_of::<LiveAlloc>()).min(MAX_TRACK_RECORDS);
+        let tracker = Self {
+            addr,
+            capacity,
+            ..Self::EMPTY
+        };
+        if capacity == 0 {
+            return (tracker, start);
+        }
+        (tracker, addr + capacity * size_of::<LiveAlloc>())
+    }
+
+    /// 记录表占用的内存 `(start, end)`
+    pub fn table(&self) -> (usize, usize) {
+        (
+            self.addr,
+            self.addr + self.capacity * size_of::<LiveAlloc>(),
+        )
+    }
+
+    pub fn records(&self) -> &[LiveAlloc] {
+        if self.len == 0 {
+            return &[];
+        }
+        // 记录表在 `reserve` 留出的内存中，前 `len` 项已写入
+        unsafe { core::slice::from_raw_parts(self.addr as *const LiveAlloc, self.len) }
+    }
+
+    fn records_mut(&mut self) -> &mut [LiveAlloc] {
+        if self.len == 0 {
+            return &mut [];
+        }
+        unsafe { core::slice::from_raw_parts_mut(self.addr as *mut LiveAlloc, self.len) }
+    }
+
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// 记录一个分配，记录表已满时只计数
+    pub fn insert(&mut self, kind: AllocKind, pos: usize, size: usize, site: AllocSite) {
+        if self.len == self.capacity {
+            self.untracked += 1;
+            return;
+        }
+        let record = LiveAlloc {
+            kind,
+            pos,
+            size,
+            site,
+        };
+        unsafe { (self.addr as *mut LiveAlloc).add(self.len).write(record) };
+        self.len += 1;
+    }
+
+    fn position(&self, kind: AllocKind, pos: usize) -> Option<usize> {
+        self.records()
+            .iter()
+            .position(|record| record.kind == kind && record.pos == pos)
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        let last = self.len - 1;
+        self.records_mut().swap(i, last);
+        self.len = last;
+    }
+
+    /// 删除 `pos` 处的记录，没有时（未记录的分配）忽略
+    pub fn remove(&mut self, kind: AllocKind, pos: usize) {
+        if let Some(i) = self.position(kind, pos) {
+            self.swap_remove(i);
+        }
+    }
+
+    /// 将 `pos` 处字节分配的记录改为 `size` 字节
+    pub fn resize(&mut self, pos: usize, size: usize) {
+        if let Some(i) = self.position(AllocKind::Bytes, pos) {
+            self.records_mut()[i].size = size;
+        }
+    }
+
+    /// 将 `pos` 处分配的调用位置改为标签 `tag`
+    pub fn retag(&mut self, kind: AllocKind, pos: usize, tag: &'static str) {
+        if let Some(i) = self.position(kind, pos) {
+            self.records_mut()[i].site = AllocSite::Tag(tag);
+        }
+    }
+
+    /// 删除地址在 `[start, end)` 中的字节分配的记录
+    pub fn remove_bytes_within(&mut self, start: usize, end: usize) {
+        let mut i = 0;
+        while i < self.len {
+            let record = self.records()[i];
+            if record.kind == AllocKind::Bytes && (start..end).contains(&record.pos) {
+                self.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// 写入记录表的描述和所有记录，调用位置写为指针
+    pub fn save(&self, w: &mut StateWriter) {
+        w.word(self.addr);
+        w.word(self.capacity);
+        w.word(self.untracked);
+        w.word(self.len);
+        for record in self.records() {
+            w.word(record.kind as usize);
+            w.word(record.pos);
+            w.word(record.size);
+            match record.site {
+                AllocSite::Caller(location) => {
+                    w.word(0);
+                    w.word(location as *const Location as usize);
+                    w.word(0);
+                }
+                AllocSite::Tag(tag) => {
+                    w.word(1);
+                    w.word(tag.as_ptr() as usize);
+                    w.word(tag.len());
+                }
+            }
+        }
+    }
+
+    /// 读取 [`save`](Self::save) 写入的记录表，`write_back` 时将记录写回内存
+    ///
+    /// # Safety
+    ///
+    /// The call sites must be the pointers saved in the same kernel image.
+    pub unsafe fn load(r: &mut StateReader) -> AllocResult<Self> {
+        let tracker = Self {
+            addr: r.word()?,
+            capacity: r.word()?,
+            untracked: r.word()?,
+            len: 0,
+        };
+        if tracker.capacity > MAX_TRACK_RECORDS || tracker.addr % align_of::<LiveAlloc>() != 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let len = r.len(tracker.capacity)?;
+        for i in 0..len {
+            let kind = match r.word()? {
+                0 => AllocKind::Bytes,
+                1 => AllocKind::Pages,
+                _ => return Err(AllocError::InvalidParam),
+            };
+            let (pos, size) = (r.word()?, r.word()?);
+            let (tag, ptr, ptr_len) = (r.word()?, r.word()?, r.word()?);
+            if ptr == 0 {
+                return Err(AllocError::InvalidParam);
+            }
+            let site = match tag {
+                0 => AllocSite::Caller(&*(ptr as *const Location)),
+                1 => AllocSite::Tag(core::str::from_utf8_unchecked(core::slice::from_raw_parts(
+                    ptr as *const u8,
+                    ptr_len,
+                ))),
+                _ => return Err(AllocError::InvalidParam),
+            };
+            if r.write_back {
+                let record = LiveAlloc {
+                    kind,
+                    pos,
+                    size,
+                    site,
+                };
+                (tracker.addr as *mut LiveAlloc).add(i).write(record);
+            }
+        }
+        Ok(Self { len, ..tracker })
+    }
+
+    /// 删除所有字节分配的记录，字节区域重置时调用
+    pub fn clear_bytes(&mut self) {
+        self.remove_bytes_within(0, usize::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{ByteAllocator, PageAllocator};
+
+    #[test]
+    fn test_report_leaks() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let a = alloc.alloc(layout(32)).unwrap();
+        let b = alloc.alloc_tagged(layout(16), "fdt").unwrap();
+        let pos = alloc.alloc_pages_tagged(1, 0, "page-table").unwrap();
+        alloc.alloc_pages(2, 0).unwrap();
+
+        let leaks: Vec<_> = alloc.report_leaks().copied().collect();
+        assert_eq!(leaks.len(), 4);
+        let site = |pos: usize| leaks.iter().find(|leak| leak.pos == pos).unwrap().site;
+        assert!(
+            matches!(site(a.as_ptr() as usize), AllocSite::Caller(l) if l.file().ends_with("track.rs"))
+        );
+        assert!(matches!(site(b.as_ptr() as usize), AllocSite::Tag("fdt")));
+        let page = leaks.iter().find(|leak| leak.pos == pos).unwrap();
+        assert_eq!((page.kind, page.size), (AllocKind::Pages, PAGE));
+        assert_eq!(
+            page.to_string(),
+            format!("pages [{:#x}, {:#x}) from page-table", pos, pos + PAGE)
+        );
+
+        alloc.dealloc_pages(pos, 1);
+        alloc.dealloc(a, layout(32));
+        assert_eq!(alloc.report_leaks().count(), 2);
+        // 字节区域重置时清除所有字节分配的记录
+        alloc.dealloc(b, layout(16));
+        assert!(alloc
+            .report_leaks()
+            .all(|leak| leak.kind == AllocKind::Pages));
+        assert_eq!(alloc.untracked_allocs(), 0);
+    }
+
+    #[test]
+    fn test_table_full() {
+        let mem = Memory::new(1);
+        let table = 2 * size_of::<LiveAlloc>();
+        let (mut tracker, start) = Tracker::reserve(mem.start(), mem.start() + 8 * table);
+        assert_eq!(start, mem.start() + table);
+        assert_eq!(tracker.table(), (mem.start(), start));
+
+        tracker.insert(AllocKind::Bytes, 0x1000, 8, AllocSite::Tag("a"));
+        tracker.insert(AllocKind::Pages, 0x1000, PAGE, AllocSite::Tag("b"));
+        tracker.insert(AllocKind::Bytes, 0x2000, 8, AllocSite::Tag("c"));
+        assert_eq!(tracker.untracked(), 1);
+        tracker.resize(0x1000, 24);
+        assert_eq!(tracker.records()[0].size, 24);
+        tracker.remove(AllocKind::Bytes, 0x2000);
+        tracker.clear_bytes();
+        assert_eq!(tracker.records().len(), 1);
+        assert_eq!(tracker.records()[0].kind, AllocKind::Pages);
+    }
+}
@@ -0,0 +1,195 @@
+//! Low-memory watermark of the early allocator.
+//!
+//! `set_low_watermark` sets a threshold of the free bytes, the windows
+//! `[byte_pos, p_pos)` of the regions, as a number of bytes or a percentage
+//! of the total memory. The allocations check it once they succeed: when the
+//! free bytes drop below it, the hook is called and a flag is raised, polled
+//! by `take_low_watermark`. It is armed again once the free bytes are back
+//! above it at a later allocation, e.g. after frees or `add_memory`.
+//!
+//! It warns before the byte and page cursors collide, so a warning can be
+//! logged or the formal allocators can take over while there is room left.
+
+use allocator::{AllocError, AllocResult};
+
+use crate::state::{StateReader, StateWriter};
+
+/// 低内存水位线，空闲字节数低于它时通知
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watermark {
+    /// 空闲字节数
+    Bytes(usize),
+    /// 总字节数的百分比，不超过 100
+    Percent(u8),
+}
+
+impl Watermark {
+    /// 总字节数为 `total` 时的阈值字节数
+    pub fn threshold(self, total: usize) -> usize {
+        match self {
+            Self::Bytes(bytes) => bytes,
+            Self::Percent(percent) => (total as u128 * percent as u128 / 100) as usize,
+        }
+    }
+}
+
+/// 空闲字节数低于水位线时的回调，参数为空闲字节数和总字节数
+pub type WatermarkHook = fn(available: usize, total: usize);
+
+/// 水位线及其状态
+pub(crate) struct LowWatermark {
+    // 水位线，`None` 表示未设置
+    mark: Option<Watermark>,
+    // 低于水位线时的回调
+    hook: Option<WatermarkHook>,
+    // 上次检查时是否低于水位线
+    below: bool,
+    // 低于水位线后是否尚未被 `take` 取走
+    pending: bool,
+}
+
+impl LowWatermark {
+    pub(crate) const EMPTY: Self = Self {
+        mark: None,
+        hook: None,
+        below: false,
+        pending: false,
+    };
+
+    pub(crate) fn set(&mut self, mark: Watermark, hook: Option<WatermarkHook>) -> AllocResult {
+        if matches!(mark, Watermark::Percent(percent) if percent > 100) {
+            return Err(AllocError::InvalidParam);
+        }
+        *self = Self {
+            mark: Some(mark),
+            hook,
+            ..Self::EMPTY
+        };
+        Ok(())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::EMPTY;
+    }
+
+    pub(crate) fn mark(&self) -> Option<Watermark> {
+        self.mark
+    }
+
+    /// 根据当前的空闲字节数更新状态，刚低于水位线时调用回调
+    pub(crate) fn check(&mut self, available: usize, total: usize) {
+        let Some(mark) = self.mark else {
+            return;
+        };
+        let below = available < mark.threshold(total);
+        if below && !self.below {
+            self.pending = true;
+            if let Some(hook) = self.hook {
+                hook(available, total);
+            }
+        }
+        self.below = below;
+    }
+
+    /// 取走低于水位线的通知
+    pub(crate) fn take(&mut self) -> bool {
+        core::mem::take(&mut self.pending)
+    }
+
+    /// 写入水位线及其状态，不含回调
+    pub(crate) fn save(&self, w: &mut StateWriter) {
+        match self.mark {
+            None => w.word(0),
+            Some(Watermark::Bytes(bytes)) => {
+                w.word(1);
+                w.word(bytes);
+            }
+            Some(Watermark::Percent(percent)) => {
+                w.word(2);
+                w.word(percent as usize);
+            }
+        }
+        w.flag(self.below);
+        w.flag(self.pending);
+    }
+
+    /// 读取 [`save`](Self::save) 写入的水位线，回调为空
+    pub(crate) fn load(r: &mut StateReader) -> AllocResult<Self> {
+        let mark = match r.word()? {
+            0 => None,
+            1 => Some(Watermark::Bytes(r.word()?)),
+            2 => match u8::try_from(r.word()?) {
+                Ok(percent) if percent <= 100 => Some(Watermark::Percent(percent)),
+                _ => return Err(AllocError::InvalidParam),
+            },
+            _ => return Err(AllocError::InvalidParam),
+        };
+        Ok(Self {
+            mark,
+            hook: None,
+            below: r.flag()?,
+            pending: r.flag()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{allocator, layout, Memory, PAGE};
+    use allocator::{ByteAllocator, PageAllocator};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static CROSSED: AtomicUsize = AtomicUsize::new(0);
+
+    fn hook(available: usize, total: usize) {
+        assert!(available < total);
+        CROSSED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_threshold() {
+        assert_eq!(Watermark::Bytes(100).threshold(1000), 100);
+        assert_eq!(Watermark::Percent(25).threshold(1000), 250);
+        assert_eq!(Watermark::Percent(100).threshold(usize::MAX), usize::MAX);
+        let mut mark = LowWatermark::EMPTY;
+        assert_eq!(
+            mark.set(Watermark::Percent(101), None),
+            Err(AllocError::InvalidParam)
+        );
+        assert_eq!(mark.mark(), None);
+    }
+
+    #[test]
+    fn test_low_watermark() {
+        let mem = Memory::new(16);
+        let mut alloc = allocator(&mem);
+        let mark = alloc.available_bytes() - 2 * PAGE;
+        alloc
+            .set_low_watermark(Watermark::Bytes(mark), Some(hook))
+            .unwrap();
+
+        let a = alloc.alloc_pages(1, 0).unwrap();
+        assert!(!alloc.is_below_watermark());
+        let b = alloc.alloc_pages(2, 0).unwrap();
+        assert!(alloc.is_below_watermark());
+        assert_eq!(CROSSED.load(Ordering::SeqCst), 1);
+        // 仍低于水位线时不再通知
+        let c = alloc.alloc(layout(8)).unwrap();
+        assert_eq!(CROSSED.load(Ordering::SeqCst), 1);
+        assert!(alloc.take_low_watermark());
+        assert!(!alloc.take_low_watermark());
+
+        // 回到水位线之上的一次分配后再次生效
+        alloc.dealloc_pages(b, 2);
+        alloc.dealloc(c, layout(8));
+        alloc.alloc(layout(8)).unwrap();
+        alloc.alloc_pages(2, 0).unwrap();
+        assert_eq!(CROSSED.load(Ordering::SeqCst), 2);
+        assert!(alloc.take_low_watermark());
+
+        alloc.clear_low_watermark();
+        assert!(!alloc.is_below_watermark());
+        alloc.dealloc_pages(a, 1);
+    }
+}
@@ -0,0 +1,207 @@
+#![cfg_attr(not(test), no_std)]
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+#[cfg(test)]
+mod tests;
+
+/// 最多记录的第一个分配器的内存区域数量
+pub const MAX_FIRST_REGIONS: usize = 8;
+
+/// Chained fallback allocator
+/// Use it to keep an allocator as the fast path in front of another one, e.g.
+/// the early bump region for the tiny allocations after the formal allocator
+/// comes online.
+///
+/// Each allocation is tried by the first allocator `A`, then by the second
+/// one `B` if `A` fails with [`AllocError::NoMemory`]. The other errors are
+/// returned at once.
+///
+/// The chain records the memory regions of `A`, given by `init_first` and
+/// `add_first_memory`, to route each free back to the allocator owning the
+/// address: in a region of `A` to `A`, anywhere else to `B`. The memory of
+/// `init` and `add_memory` is given to `B`, which may also be initialized
+/// before being chained.
+///
+pub struct ChainAllocator<A, B> {
+    // 优先尝试的分配器
+    first: A,
+    // 后备的分配器
+    second: B,
+    // 第一个分配器的内存区域 [start, end)
+    first_regions: [(usize, usize); MAX_FIRST_REGIONS],
+    // 区域数量
+    first_region_count: usize,
+}
+
+impl<A, B> ChainAllocator<A, B> {
+    /// 创建先尝试 `first`、再尝试 `second` 的分配器
+    pub const fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            first_regions: [(0, 0); MAX_FIRST_REGIONS],
+            first_region_count: 0,
+        }
+    }
+
+    /// 第一个分配器
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// 第一个分配器，可变引用
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// 后备的分配器
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// 后备的分配器，可变引用
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    /// 拆分为两个分配器
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    /// `pos` 是否属于第一个分配器
+    pub fn owned_by_first(&self, pos: usize) -> bool {
+        self.first_regions[..self.first_region_count]
+            .iter()
+            .any(|&(start, end)| (start..end).contains(&pos))
+    }
+
+    /// 检查能否记录区域 `[start, start + size)`，返回区域结束地址
+    fn check_first_region(&self, start: usize, size: usize) -> AllocResult<usize> {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        if self.first_regions[..self.first_region_count]
+            .iter()
+            .any(|&(s, e)| start < e && s < end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        // 区域列表已满
+        if self.first_region_count == MAX_FIRST_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        Ok(end)
+    }
+
+    /// 记录第一个分配器的内存区域，不交给分配器
+    ///
+    /// 用于链接之前已初始化的第一个分配器。
+    pub fn claim_first_region(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = self.check_first_region(start, size)?;
+        self.first_regions[self.first_region_count] = (start, end);
+        self.first_region_count += 1;
+        Ok(())
+    }
+}
+
+impl<A: BaseAllocator, B> ChainAllocator<A, B> {
+    /// 以内存区域初始化第一个分配器，并记录该区域
+    pub fn init_first(&mut self, start: usize, size: usize) {
+        self.first_region_count = 0;
+        self.first.init(start, size);
+        self.claim_first_region(start, size)
+            .expect("invalid region of the first allocator");
+    }
+
+    /// 将内存区域加入第一个分配器，并记录该区域
+    pub fn add_first_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        // 先检查能否记录，以免区域交给分配器后无法找回
+        self.check_first_region(start, size)?;
+        self.first.add_memory(start, size)?;
+        self.claim_first_region(start, size)
+    }
+}
+
+/// 先尝试 `first`，`NoMemory` 时再尝试 `second`
+fn chain<T>(first: AllocResult<T>, second: impl FnOnce() -> AllocResult<T>) -> AllocResult<T> {
+    match first {
+        Err(AllocError::NoMemory) => second(),
+        result => result,
+    }
+}
+
+impl<A: BaseAllocator, B: BaseAllocator> BaseAllocator for ChainAllocator<A, B> {
+    /// Initialize the second allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        self.second.init(start, size);
+    }
+
+    /// Add a free memory region to the second allocator.
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.second.add_memory(start, size)
+    }
+}
+
+impl<A: ByteAllocator, B: ByteAllocator> ByteAllocator for ChainAllocator<A, B> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        chain(self.first.alloc(layout), || self.second.alloc(layout))
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        if self.owned_by_first(pos.as_ptr() as usize) {
+            self.first.dealloc(pos, layout)
+        } else {
+            self.second.dealloc(pos, layout)
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.first.total_bytes() + self.second.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.first.used_bytes() + self.second.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.first.available_bytes() + self.second.available_bytes()
+    }
+}
+
+impl<A: PageAllocator, B: PageAllocator> PageAllocator for ChainAllocator<A, B> {
+    const PAGE_SIZE: usize = {
+        assert!(
+            A::PAGE_SIZE == B::PAGE_SIZE,
+            "the chained page allocators must have the same page size"
+        );
+        A::PAGE_SIZE
+    };
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        chain(self.first.alloc_pages(num_pages, align_pow2), || {
+            self.second.alloc_pages(num_pages, align_pow2)
+        })
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        if self.owned_by_first(pos) {
+            self.first.dealloc_pages(pos, num_pages)
+        } else {
+            self.second.dealloc_pages(pos, num_pages)
+        }
+    }
+
+    fn total_pages(&self) -> usize {
+        self.first.total_pages() + self.second.total_pages()
+    }
+
+    fn used_pages(&self) -> usize {
+        self.first.used_pages() + self.second.used_pages()
+    }
+
+    fn available_pages(&self) -> usize {
+        self.first.available_pages() + self.second.available_pages()
+    }
+}
@@ -0,0 +1,224 @@
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::{ChainAllocator, MAX_FIRST_REGIONS};
+
+const PAGE: usize = 4096;
+
+/// 按页顺序分配的假分配器，只记录地址，不访问内存
+#[derive(Default)]
+struct Mock {
+    regions: Vec<(usize, usize)>,
+    next: usize,
+    used: usize,
+    // 设置时所有分配都以此失败
+    error: Option<AllocError>,
+    freed: Vec<usize>,
+}
+
+impl Mock {
+    fn with_region(start: usize, size: usize) -> Self {
+        let mut mock = Self::default();
+        mock.init(start, size);
+        mock
+    }
+
+    fn total(&self) -> usize {
+        self.regions
+            .iter()
+            .map(|&(start, end)| (end - start) / PAGE)
+            .sum()
+    }
+
+    fn take(&mut self, num_pages: usize) -> AllocResult<usize> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        for &(start, end) in &self.regions {
+            let pos = self.next.max(start);
+            if pos + num_pages * PAGE <= end {
+                self.next = pos + num_pages * PAGE;
+                self.used += num_pages;
+                return Ok(pos);
+            }
+        }
+        Err(AllocError::NoMemory)
+    }
+}
+
+impl BaseAllocator for Mock {
+    fn init(&mut self, start: usize, size: usize) {
+        self.regions = vec![(start, start + size)];
+        self.next = start;
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.regions.push((start, start + size));
+        Ok(())
+    }
+}
+
+impl ByteAllocator for Mock {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let pos = self.take(layout.size().div_ceil(PAGE))?;
+        Ok(NonNull::new(pos as *mut u8).unwrap())
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        self.freed.push(pos.as_ptr() as usize);
+        self.used -= layout.size().div_ceil(PAGE);
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total() * PAGE
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used * PAGE
+    }
+
+    fn available_bytes(&self) -> usize {
+        (self.total() - self.used) * PAGE
+    }
+}
+
+impl PageAllocator for Mock {
+    const PAGE_SIZE: usize = PAGE;
+
+    fn alloc_pages(&mut self, num_pages: usize, _align_pow2: usize) -> AllocResult<usize> {
+        self.take(num_pages)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        self.freed.push(pos);
+        self.used -= num_pages;
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total()
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total() - self.used
+    }
+}
+
+const FIRST: usize = 0x10_0000;
+const SECOND: usize = 0x20_0000;
+
+fn chain() -> ChainAllocator<Mock, Mock> {
+    let mut chain = ChainAllocator::new(Mock::default(), Mock::default());
+    chain.init_first(FIRST, 2 * PAGE);
+    chain.init(SECOND, 8 * PAGE);
+    chain
+}
+
+#[test]
+fn test_fallback() {
+    let mut chain = chain();
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(FIRST));
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(FIRST + PAGE));
+    // 第一个分配器耗尽后由后备的分配器分配
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(SECOND));
+    assert_eq!(chain.first().used_pages(), 2);
+    assert_eq!(chain.second().used_pages(), 1);
+
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let ptr = chain.alloc(layout).unwrap();
+    assert_eq!(ptr.as_ptr() as usize, SECOND + PAGE);
+    assert_eq!(chain.alloc_pages(16, PAGE), Err(AllocError::NoMemory));
+
+    assert_eq!(chain.total_pages(), 10);
+    assert_eq!(chain.used_pages(), 4);
+    assert_eq!(chain.available_bytes(), 6 * PAGE);
+}
+
+#[test]
+fn test_other_errors() {
+    let mut chain = chain();
+    // 不是 NoMemory 的错误直接返回，不再尝试后备的分配器
+    chain.first_mut().error = Some(AllocError::InvalidParam);
+    assert_eq!(chain.alloc_pages(1, PAGE), Err(AllocError::InvalidParam));
+    let layout = Layout::from_size_align(8, 8).unwrap();
+    assert_eq!(chain.alloc(layout), Err(AllocError::InvalidParam));
+    assert_eq!(chain.second().used_pages(), 0);
+
+    chain.first_mut().error = Some(AllocError::NoMemory);
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(SECOND));
+}
+
+#[test]
+fn test_dealloc_routing() {
+    let mut chain = chain();
+    let more = FIRST + 0x8_0000;
+    chain.add_first_memory(more, PAGE).unwrap();
+    let layout = Layout::from_size_align(PAGE, PAGE).unwrap();
+    let ptr = chain.alloc(layout).unwrap();
+    assert_eq!(ptr.as_ptr() as usize, FIRST);
+    let pages: Vec<_> = (0..4)
+        .map(|_| chain.alloc_pages(1, PAGE).unwrap())
+        .collect();
+    assert_eq!(pages[..2], [FIRST + PAGE, more]);
+
+    // 每次释放交给地址所在区域的分配器
+    for &pos in pages.iter().rev() {
+        chain.dealloc_pages(pos, 1);
+    }
+    chain.dealloc(ptr, layout);
+    assert_eq!(chain.first().freed, [more, FIRST + PAGE, FIRST]);
+    assert_eq!(chain.second().freed, [SECOND + PAGE, SECOND]);
+    assert!(chain.owned_by_first(more + PAGE - 1));
+    assert!(!chain.owned_by_first(more + PAGE));
+}
+
+#[test]
+fn test_first_regions() {
+    let mut chain = chain();
+    // 重叠的区域不交给第一个分配器
+    assert_eq!(
+        chain.add_first_memory(FIRST + PAGE, 2 * PAGE),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(chain.first().regions.len(), 1);
+    assert_eq!(
+        chain.claim_first_region(usize::MAX, 2),
+        Err(AllocError::InvalidParam)
+    );
+
+    for i in 1..MAX_FIRST_REGIONS {
+        chain
+            .claim_first_region(FIRST + i * 0x1_0000, PAGE)
+            .unwrap();
+    }
+    assert_eq!(
+        chain.add_first_memory(0x1000_0000, PAGE),
+        Err(AllocError::NoMemory)
+    );
+    assert_eq!(chain.first().regions.len(), 1);
+
+    // 重新初始化时清空记录的区域
+    chain.init_first(FIRST + 0x1_0000, PAGE);
+    assert!(!chain.owned_by_first(FIRST));
+    assert!(chain.owned_by_first(FIRST + 0x1_0000));
+}
+
+#[test]
+fn test_initialized_first() {
+    // 已初始化的第一个分配器只记录其区域
+    let mut chain = ChainAllocator::new(
+        Mock::with_region(FIRST, PAGE),
+        Mock::with_region(SECOND, PAGE),
+    );
+    chain.claim_first_region(FIRST, PAGE).unwrap();
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(FIRST));
+    assert_eq!(chain.alloc_pages(1, PAGE), Ok(SECOND));
+    chain.dealloc_pages(FIRST, 1);
+    let (first, second) = chain.into_inner();
+    assert_eq!(first.freed, [FIRST]);
+    assert_eq!(second.used_pages(), 1);
+}
@@ -0,0 +1,245 @@
+#![cfg_attr(not(test), no_std)]
+
+use allocator::{AllocError, AllocResult, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kspin::{SpinNoIrq, SpinNoIrqGuard};
+
+#[cfg(test)]
+mod tests;
+
+/// 最小的大小类
+const MIN_CLASS_SHIFT: usize = 3;
+
+/// 大小类数量：8, 16, ..., 1024 字节
+pub const NR_SIZE_CLASSES: usize = 8;
+
+/// 最大的缓存大小类，更大的分配直接使用后备分配器
+pub const MAX_CACHED_SIZE: usize = 1 << (MIN_CLASS_SHIFT + NR_SIZE_CLASSES - 1);
+
+/// 每个 CPU 的每个大小类最多缓存的块数
+pub const MAGAZINE_SIZE: usize = 32;
+
+/// 每次从后备分配器补充、或归还后备分配器的块数
+const BATCH: usize = MAGAZINE_SIZE / 2;
+
+/// `class` 大小类的块在后备分配器中的布局
+fn class_layout(class: usize) -> Layout {
+    let size = 1 << (MIN_CLASS_SHIFT + class);
+    // 大小为 2 的幂，且不超过 MAX_CACHED_SIZE
+    unsafe { Layout::from_size_align_unchecked(size, size) }
+}
+
+/// `layout` 的大小类，过大时为 `None`
+fn class_of(layout: &Layout) -> Option<usize> {
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(1 << MIN_CLASS_SHIFT)
+        .next_power_of_two();
+    (size <= MAX_CACHED_SIZE).then(|| size.trailing_zeros() as usize - MIN_CLASS_SHIFT)
+}
+
+/// 一个大小类的空闲块，后进先出
+struct Magazine {
+    // 块的地址
+    blocks: [usize; MAGAZINE_SIZE],
+    // 块数
+    len: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            blocks: [0; MAGAZINE_SIZE],
+            len: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.blocks[self.len])
+    }
+
+    /// 将最早放入的 `count` 个块归还后备分配器
+    fn drain<A: ByteAllocator>(&mut self, class: usize, count: usize, backing: &mut A) {
+        let count = count.min(self.len);
+        for &pos in &self.blocks[..count] {
+            // 块来自后备分配器，地址非空
+            let pos = unsafe { NonNull::new_unchecked(pos as *mut u8) };
+            backing.dealloc(pos, class_layout(class));
+        }
+        self.blocks.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+}
+
+/// 一个 CPU 的缓存，按缓存行对齐，避免 CPU 间的伪共享
+#[repr(align(64))]
+struct CpuCache {
+    magazines: SpinNoIrq<[Magazine; NR_SIZE_CLASSES]>,
+}
+
+/// Per-CPU caching allocator
+/// Use it in front of a shared byte allocator on SMP, to take the frequent
+/// small allocations and frees off its lock.
+///
+/// The sizes up to [`MAX_CACHED_SIZE`] are rounded up to a power of two size
+/// class. Each CPU keeps a small stack of free blocks per class (a magazine
+/// of at most [`MAGAZINE_SIZE`] blocks), taken by its allocations and filled
+/// by its frees without touching the backing allocator `A`:
+/// - An allocation from an empty magazine refills it with a batch of blocks
+///   of the class from `A`, under a single lock of `A`.
+/// - A free into a full magazine returns a batch of its blocks to `A` first.
+///
+/// Larger allocations go to `A` directly. When `A` runs out of memory, the
+/// blocks cached by all the CPUs are returned to it and the allocation is
+/// tried again.
+///
+/// The methods take the ID of the calling CPU and `&self`: each cache has its
+/// own lock, taken by other CPUs only by [`flush`](Self::flush), so a
+/// wrong ID costs contention but is safe. The IDs are taken modulo
+/// `MAX_CPUS`. A CPU going offline should [`flush`](Self::flush) its cache,
+/// whose blocks are unusable otherwise.
+///
+/// `A` is initialized by [`backing`](Self::backing), and must not be used
+/// through it for the blocks of this allocator.
+///
+pub struct PerCpuAllocator<A: ByteAllocator, const MAX_CPUS: usize = 16> {
+    // 后备的共享分配器
+    backing: SpinNoIrq<A>,
+    // 各 CPU 的缓存
+    caches: [CpuCache; MAX_CPUS],
+    // 所有缓存中的字节数
+    cached_bytes: AtomicUsize,
+}
+
+impl<A: ByteAllocator, const MAX_CPUS: usize> PerCpuAllocator<A, MAX_CPUS> {
+    /// 创建一个以 `backing` 为后备分配器的分配器
+    pub const fn new(backing: A) -> Self {
+        Self {
+            backing: SpinNoIrq::new(backing),
+            caches: [const {
+                CpuCache {
+                    magazines: SpinNoIrq::new([const { Magazine::new() }; NR_SIZE_CLASSES]),
+                }
+            }; MAX_CPUS],
+            cached_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// 锁住后备的分配器，用于初始化、加入内存
+    ///
+    /// 持有时不能调用本分配器的其他方法。
+    pub fn backing(&self) -> SpinNoIrqGuard<'_, A> {
+        self.backing.lock()
+    }
+
+    fn cache(&self, cpu_id: usize) -> &CpuCache {
+        &self.caches[cpu_id % MAX_CPUS]
+    }
+
+    fn try_alloc(&self, cpu_id: usize, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let Some(class) = class_of(&layout) else {
+            return self.backing.lock().alloc(layout);
+        };
+        let block_size = class_layout(class).size();
+        let mut magazines = self.cache(cpu_id).magazines.lock();
+        let magazine = &mut magazines[class];
+        if magazine.len == 0 {
+            // 补充一批块，至少得到一块
+            let mut backing = self.backing.lock();
+            while magazine.len < BATCH {
+                match backing.alloc(class_layout(class)) {
+                    Ok(pos) => {
+                        magazine.blocks[magazine.len] = pos.as_ptr() as usize;
+                        magazine.len += 1;
+                    }
+                    Err(_) if magazine.len > 0 => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.cached_bytes
+                .fetch_add(magazine.len * block_size, Ordering::Relaxed);
+        }
+        let pos = magazine.pop().unwrap();
+        self.cached_bytes.fetch_sub(block_size, Ordering::Relaxed);
+        Ok(unsafe { NonNull::new_unchecked(pos as *mut u8) })
+    }
+
+    /// 在 CPU `cpu_id` 上分配
+    pub fn alloc(&self, cpu_id: usize, layout: Layout) -> AllocResult<NonNull<u8>> {
+        match self.try_alloc(cpu_id, layout) {
+            Err(AllocError::NoMemory) if self.cached_bytes() > 0 => {
+                // 内存可能在各 CPU 的缓存中
+                self.flush_all();
+                self.try_alloc(cpu_id, layout)
+            }
+            result => result,
+        }
+    }
+
+    /// 在 CPU `cpu_id` 上释放
+    pub fn dealloc(&self, cpu_id: usize, pos: NonNull<u8>, layout: Layout) {
+        let Some(class) = class_of(&layout) else {
+            return self.backing.lock().dealloc(pos, layout);
+        };
+        let block_size = class_layout(class).size();
+        let mut magazines = self.cache(cpu_id).magazines.lock();
+        let magazine = &mut magazines[class];
+        if magazine.len == MAGAZINE_SIZE {
+            magazine.drain(class, BATCH, &mut *self.backing.lock());
+            self.cached_bytes
+                .fetch_sub(BATCH * block_size, Ordering::Relaxed);
+        }
+        magazine.blocks[magazine.len] = pos.as_ptr() as usize;
+        magazine.len += 1;
+        self.cached_bytes.fetch_add(block_size, Ordering::Relaxed);
+    }
+
+    /// 将 CPU `cpu_id` 缓存的块全部归还后备分配器，返回归还的字节数
+    ///
+    /// 用于 CPU 下线、关机前。
+    pub fn flush(&self, cpu_id: usize) -> usize {
+        let mut magazines = self.cache(cpu_id).magazines.lock();
+        let mut backing = self.backing.lock();
+        let mut bytes = 0;
+        for (class, magazine) in magazines.iter_mut().enumerate() {
+            bytes += magazine.len * class_layout(class).size();
+            magazine.drain(class, MAGAZINE_SIZE, &mut *backing);
+        }
+        self.cached_bytes.fetch_sub(bytes, Ordering::Relaxed);
+        bytes
+    }
+
+    /// 将所有 CPU 缓存的块归还后备分配器，返回归还的字节数
+    pub fn flush_all(&self) -> usize {
+        (0..MAX_CPUS).map(|cpu_id| self.flush(cpu_id)).sum()
+    }
+
+    /// 各 CPU 缓存中的字节数
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes.load(Ordering::Relaxed)
+    }
+
+    /// 后备分配器的总字节数
+    pub fn total_bytes(&self) -> usize {
+        self.backing.lock().total_bytes()
+    }
+
+    /// 已分配的字节数，按后备分配器的统计，减去缓存中的块
+    ///
+    /// 缓存中的块按大小类计，后备分配器的额外开销（如块头）仍计入已分配。
+    pub fn used_bytes(&self) -> usize {
+        self.backing
+            .lock()
+            .used_bytes()
+            .saturating_sub(self.cached_bytes())
+    }
+
+    /// 可用的字节数，含缓存中的块
+    pub fn available_bytes(&self) -> usize {
+        self.backing.lock().available_bytes() + self.cached_bytes()
+    }
+}
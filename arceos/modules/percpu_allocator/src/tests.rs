@@ -0,0 +1,193 @@
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use std::collections::BTreeMap;
+
+use crate::{PerCpuAllocator, BATCH, MAGAZINE_SIZE, MAX_CACHED_SIZE};
+
+/// 记录调用次数的假后备分配器，只分配地址，不访问内存
+struct Mock {
+    // 最多分配的字节数
+    capacity: usize,
+    next: usize,
+    // 已分配的块及其大小
+    live: BTreeMap<usize, usize>,
+    allocs: usize,
+    deallocs: usize,
+}
+
+impl Mock {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next: 0x1000_0000,
+            live: BTreeMap::new(),
+            allocs: 0,
+            deallocs: 0,
+        }
+    }
+}
+
+impl BaseAllocator for Mock {
+    fn init(&mut self, _start: usize, _size: usize) {}
+
+    fn add_memory(&mut self, _start: usize, size: usize) -> AllocResult {
+        self.capacity += size;
+        Ok(())
+    }
+}
+
+impl ByteAllocator for Mock {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if self.used_bytes() + layout.size() > self.capacity {
+            return Err(AllocError::NoMemory);
+        }
+        let pos = self.next.next_multiple_of(layout.align());
+        self.next = pos + layout.size();
+        self.live.insert(pos, layout.size());
+        self.allocs += 1;
+        Ok(NonNull::new(pos as *mut u8).unwrap())
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let size = self.live.remove(&(pos.as_ptr() as usize));
+        assert_eq!(size, Some(layout.size()));
+        self.deallocs += 1;
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.capacity
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.live.values().sum()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.capacity - self.used_bytes()
+    }
+}
+
+fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size, 8).unwrap()
+}
+
+#[test]
+fn test_fill() {
+    let percpu = PerCpuAllocator::<_, 4>::new(Mock::new(1 << 20));
+    percpu.alloc(0, layout(10)).unwrap();
+    // 一次补充一批块，之后的分配不经过后备分配器
+    assert_eq!(percpu.backing().allocs, BATCH);
+    assert_eq!(percpu.cached_bytes(), (BATCH - 1) * 16);
+    let ptrs: Vec<_> = (1..BATCH)
+        .map(|_| percpu.alloc(0, layout(16)).unwrap())
+        .collect();
+    assert_eq!(percpu.backing().allocs, BATCH);
+    assert_eq!(percpu.cached_bytes(), 0);
+    assert_eq!(percpu.used_bytes(), BATCH * 16);
+
+    // 其他 CPU 与其他大小类各有自己的缓存
+    percpu.alloc(1, layout(16)).unwrap();
+    percpu.alloc(0, layout(17)).unwrap();
+    assert_eq!(percpu.backing().allocs, 3 * BATCH);
+    // 本 CPU 的缓存用完后再补充
+    percpu.alloc(0, layout(16)).unwrap();
+    assert_eq!(percpu.backing().allocs, 4 * BATCH);
+
+    // 释放的块放回本 CPU 的缓存，后进先出
+    percpu.dealloc(0, ptrs[0], layout(16));
+    assert_eq!(percpu.backing().deallocs, 0);
+    assert_eq!(percpu.alloc(0, layout(16)), Ok(ptrs[0]));
+}
+
+#[test]
+fn test_full_magazine() {
+    let percpu = PerCpuAllocator::<_, 4>::new(Mock::new(1 << 20));
+    let ptrs: Vec<_> = (0..MAGAZINE_SIZE + 1)
+        .map(|_| percpu.alloc(0, layout(64)).unwrap())
+        .collect();
+    assert_eq!(percpu.backing().allocs, MAGAZINE_SIZE + BATCH);
+    assert_eq!(percpu.cached_bytes(), (BATCH - 1) * 64);
+
+    // 在另一个 CPU 上释放，缓存满时将最早的一批块归还后备分配器
+    for &ptr in &ptrs {
+        percpu.dealloc(1, ptr, layout(64));
+    }
+    assert_eq!(percpu.backing().deallocs, BATCH);
+    assert!(!percpu
+        .backing()
+        .live
+        .contains_key(&(ptrs[0].as_ptr() as usize)));
+    assert_eq!(
+        percpu.cached_bytes(),
+        (BATCH - 1 + MAGAZINE_SIZE + 1 - BATCH) * 64
+    );
+    assert_eq!(percpu.used_bytes(), 0);
+}
+
+#[test]
+fn test_flush() {
+    let percpu = PerCpuAllocator::<_, 4>::new(Mock::new(1 << 20));
+    let a = percpu.alloc(2, layout(8)).unwrap();
+    let b = percpu.alloc(2, layout(1024)).unwrap();
+    percpu.dealloc(2, a, layout(8));
+    let cached = percpu.cached_bytes();
+    assert_eq!(cached, BATCH * 8 + (BATCH - 1) * 1024);
+    assert_eq!(percpu.available_bytes(), (1 << 20) - 1024);
+
+    assert_eq!(percpu.flush(1), 0);
+    assert_eq!(percpu.flush(6), cached);
+    assert_eq!(percpu.cached_bytes(), 0);
+    assert_eq!(percpu.backing().live.len(), 1);
+    assert_eq!(
+        percpu.backing().live.get(&(b.as_ptr() as usize)),
+        Some(&1024)
+    );
+    percpu.dealloc(2, b, layout(1024));
+    assert_eq!(percpu.flush_all(), 1024);
+    assert_eq!(percpu.backing().used_bytes(), 0);
+}
+
+#[test]
+fn test_large() {
+    let percpu = PerCpuAllocator::<_, 4>::new(Mock::new(1 << 20));
+    let large = layout(MAX_CACHED_SIZE + 1);
+    let ptr = percpu.alloc(0, large).unwrap();
+    assert_eq!(percpu.backing().allocs, 1);
+    assert_eq!(percpu.cached_bytes(), 0);
+    percpu.dealloc(0, ptr, large);
+    assert_eq!(percpu.backing().deallocs, 1);
+    // 大小按对齐取大小类
+    percpu
+        .alloc(0, Layout::from_size_align(8, MAX_CACHED_SIZE).unwrap())
+        .unwrap();
+    assert_eq!(percpu.cached_bytes(), (BATCH - 1) * MAX_CACHED_SIZE);
+}
+
+#[test]
+fn test_no_memory() {
+    let percpu = PerCpuAllocator::<_, 4>::new(Mock::new(BATCH * 256));
+    // CPU 1 的缓存占满后备分配器
+    percpu.alloc(1, layout(256)).unwrap();
+    assert_eq!(percpu.backing().available_bytes(), 0);
+
+    // 后备分配器耗尽时归还各 CPU 缓存的块再试
+    percpu.alloc(0, layout(128)).unwrap();
+    assert_eq!(percpu.cached_bytes(), (BATCH - 1) * 128);
+    assert_eq!(percpu.backing().live.len(), 1 + BATCH);
+
+    // 部分补充也能分配
+    let left = percpu.backing().available_bytes() / 256;
+    assert!(left > 1 && left < BATCH);
+    percpu.alloc(2, layout(256)).unwrap();
+    assert_eq!(percpu.backing().available_bytes(), 0);
+    assert_eq!(percpu.cached_bytes(), (BATCH - 1) * 128 + (left - 1) * 256);
+
+    // 归还缓存后仍不够时失败
+    assert_eq!(
+        percpu.alloc(0, layout(BATCH * 256)),
+        Err(AllocError::NoMemory)
+    );
+    assert_eq!(percpu.cached_bytes(), 0);
+    assert_eq!(percpu.backing().live.len(), 3);
+}
@@ -0,0 +1,230 @@
+use allocator::{AllocError, AllocResult, PageAllocator};
+use core::ptr::NonNull;
+
+/// 每个缓存最多保留的空 slab 数量，多出的归还页分配器
+const MAX_EMPTY_SLABS: usize = 1;
+
+/// 每个 slab 为一页，开头为 slab 头
+#[repr(C)]
+struct SlabHeader {
+    // 同一链表中的下一个 slab，0 表示没有
+    next: usize,
+    // 同一链表中的上一个 slab，0 表示没有
+    prev: usize,
+    // 空闲对象链表的头对象地址，0 表示为空
+    free: usize,
+    // 尚未使用过的对象的起始地址
+    bump: usize,
+    // 已分配的对象数
+    in_use: usize,
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<SlabHeader>();
+
+fn header(slab: usize) -> *mut SlabHeader {
+    slab as *mut SlabHeader
+}
+
+/// 将 `slab` 放入 `head` 链表的头部
+fn push(head: &mut usize, slab: usize) {
+    // SAFETY: the slabs are pages owned by the cache.
+    unsafe {
+        (*header(slab)).next = *head;
+        (*header(slab)).prev = 0;
+        if *head != 0 {
+            (*header(*head)).prev = slab;
+        }
+    }
+    *head = slab;
+}
+
+/// 将 `slab` 移出 `head` 链表
+fn unlink(head: &mut usize, slab: usize) {
+    // SAFETY: `slab` is in the list of `head`.
+    unsafe {
+        let SlabHeader { next, prev, .. } = header(slab).read();
+        if prev == 0 {
+            *head = next;
+        } else {
+            (*header(prev)).next = next;
+        }
+        if next != 0 {
+            (*header(next)).prev = prev;
+        }
+    }
+}
+
+/// A cache of the objects of one size, in slabs of one page each.
+///
+/// The slabs are kept in three lists: the partial ones (with both allocated
+/// and free objects), the full ones, and a few empty ones. Allocation takes an
+/// object from the first partial slab, and free finds the slab of an object by
+/// aligning its address down to the page, so both take constant time besides
+/// getting or releasing a page.
+pub(crate) struct Cache {
+    // 对象大小，为 `align` 的倍数
+    size: usize,
+    // 对象对齐
+    align: usize,
+    // 部分使用的 slab 链表
+    partial: usize,
+    // 已满的 slab 链表
+    full: usize,
+    // 空 slab 链表
+    empty: usize,
+    // 空 slab 数量
+    nr_empty: usize,
+    // slab 总数
+    nr_slabs: usize,
+    // 已分配的对象数
+    in_use: usize,
+}
+
+impl Cache {
+    /// `size` 须为 `align` 的倍数，且不小于 `usize` 的大小
+    pub const fn new(size: usize, align: usize) -> Self {
+        Self {
+            size,
+            align,
+            partial: 0,
+            full: 0,
+            empty: 0,
+            nr_empty: 0,
+            nr_slabs: 0,
+            in_use: 0,
+        }
+    }
+
+    /// 第一个对象在 slab 中的偏移
+    const fn first_offset(&self) -> usize {
+        HEADER_SIZE.next_multiple_of(self.align)
+    }
+
+    /// 每页 slab 的对象数，对象放不下时为 0
+    pub const fn objects_per_slab(&self, page_size: usize) -> usize {
+        page_size.saturating_sub(self.first_offset()) / self.size
+    }
+
+    pub const fn object_size(&self) -> usize {
+        self.size
+    }
+
+    /// 已分配的对象数
+    pub const fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// 空闲对象数，包括尚未使用过的
+    pub const fn free_objects(&self, page_size: usize) -> usize {
+        self.nr_slabs * self.objects_per_slab(page_size) - self.in_use
+    }
+
+    /// slab 占用的页数
+    pub const fn nr_slabs(&self) -> usize {
+        self.nr_slabs
+    }
+
+    /// 取一个空 slab，没有时从页分配器分配一页
+    fn take_slab<P: PageAllocator>(&mut self, pages: &mut P) -> AllocResult<usize> {
+        let slab = if self.empty != 0 {
+            let slab = self.empty;
+            unlink(&mut self.empty, slab);
+            self.nr_empty -= 1;
+            slab
+        } else {
+            let slab = pages.alloc_pages(1, P::PAGE_SIZE)?;
+            self.nr_slabs += 1;
+            slab
+        };
+        // SAFETY: the slab is an unused page owned by the cache.
+        unsafe {
+            header(slab).write(SlabHeader {
+                next: 0,
+                prev: 0,
+                free: 0,
+                bump: slab + self.first_offset(),
+                in_use: 0,
+            })
+        };
+        Ok(slab)
+    }
+
+    fn release_slab<P: PageAllocator>(&mut self, pages: &mut P, slab: usize) {
+        pages.dealloc_pages(slab, 1);
+        self.nr_slabs -= 1;
+    }
+
+    pub fn alloc<P: PageAllocator>(&mut self, pages: &mut P) -> AllocResult<NonNull<u8>> {
+        let capacity = self.objects_per_slab(P::PAGE_SIZE);
+        if capacity == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.partial == 0 {
+            let slab = self.take_slab(pages)?;
+            push(&mut self.partial, slab);
+        }
+        let slab = self.partial;
+        // SAFETY: the partial slab has a free object, either in the free list
+        // or never used.
+        let obj = unsafe {
+            let h = header(slab);
+            let obj = if (*h).free != 0 {
+                let obj = (*h).free;
+                (*h).free = *(obj as *const usize);
+                obj
+            } else {
+                let obj = (*h).bump;
+                (*h).bump += self.size;
+                obj
+            };
+            (*h).in_use += 1;
+            if (*h).in_use == capacity {
+                unlink(&mut self.partial, slab);
+                push(&mut self.full, slab);
+            }
+            obj
+        };
+        self.in_use += 1;
+        Ok(unsafe { NonNull::new_unchecked(obj as *mut u8) })
+    }
+
+    /// # Safety
+    ///
+    /// `obj` must be an object allocated from this cache with the same page
+    /// allocator.
+    pub unsafe fn dealloc<P: PageAllocator>(&mut self, pages: &mut P, obj: NonNull<u8>) {
+        let obj = obj.as_ptr() as usize;
+        let slab = obj & !(P::PAGE_SIZE - 1);
+        let capacity = self.objects_per_slab(P::PAGE_SIZE);
+        let h = header(slab);
+        *(obj as *mut usize) = (*h).free;
+        (*h).free = obj;
+        if (*h).in_use == capacity {
+            unlink(&mut self.full, slab);
+            push(&mut self.partial, slab);
+        }
+        (*h).in_use -= 1;
+        self.in_use -= 1;
+        if (*h).in_use == 0 {
+            unlink(&mut self.partial, slab);
+            if self.nr_empty < MAX_EMPTY_SLABS {
+                push(&mut self.empty, slab);
+                self.nr_empty += 1;
+            } else {
+                self.release_slab(pages, slab);
+            }
+        }
+    }
+
+    /// 将所有空 slab 归还页分配器，返回归还的页数
+    pub fn shrink<P: PageAllocator>(&mut self, pages: &mut P) -> usize {
+        let released = self.nr_empty;
+        while self.empty != 0 {
+            let slab = self.empty;
+            unlink(&mut self.empty, slab);
+            self.release_slab(pages, slab);
+        }
+        self.nr_empty = 0;
+        released
+    }
+}
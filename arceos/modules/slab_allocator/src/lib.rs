@@ -0,0 +1,285 @@
+#![cfg_attr(not(test), no_std)]
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+mod cache;
+
+#[cfg(test)]
+mod tests;
+
+use self::cache::Cache;
+
+/// 最小的大小类
+const MIN_CLASS_SHIFT: usize = 3;
+
+/// 大小类数量：8, 16, ..., 1024 字节
+pub const NR_SIZE_CLASSES: usize = 8;
+
+/// 最大的大小类，更大的分配直接使用页
+pub const MAX_SLAB_SIZE: usize = 1 << (MIN_CLASS_SHIFT + NR_SIZE_CLASSES - 1);
+
+/// Slab byte allocator
+/// Use it as the byte allocator for the many small, fixed-size kernel objects.
+///
+/// The sizes up to [`MAX_SLAB_SIZE`] are rounded up to a power of two size
+/// class, each with its own cache of slabs. A slab is a page taken from the
+/// page allocator `P`, with a header at its start and the objects of its
+/// class after it, aligned to their size:
+/// - Allocation takes an object from a partial slab of the class, getting a
+///   new slab when there is none, and moves the slab to the full list when it
+///   has no object left.
+/// - Free finds the slab by aligning the address down to the page, and moves
+///   it back to the partial list, or to the empty ones once all its objects
+///   are freed. An empty slab is kept per class, the others are returned to
+///   `P` at once.
+///
+/// Larger allocations take whole pages from `P` directly.
+///
+/// The memory of `init` and `add_memory` is given to `P`, whose alignment of
+/// `alloc_pages` is in bytes, as for the page allocators of `allocator`.
+///
+pub struct SlabByteAllocator<P: PageAllocator> {
+    // 提供 slab 与大块分配的页分配器
+    pages: P,
+    // 各大小类的缓存
+    caches: [Cache; NR_SIZE_CLASSES],
+    // 直接分配为页的字节数
+    large_bytes: usize,
+    // 已分配对象占用的字节数（按大小类计）
+    object_bytes: usize,
+}
+
+impl<P: PageAllocator> SlabByteAllocator<P> {
+    /// 创建一个使用页分配器 `pages` 的 slab 分配器
+    pub const fn new(pages: P) -> Self {
+        let mut caches = [const { Cache::new(0, 1) }; NR_SIZE_CLASSES];
+        let mut i = 0;
+        while i < NR_SIZE_CLASSES {
+            let size = 1 << (MIN_CLASS_SHIFT + i);
+            caches[i] = Cache::new(size, size);
+            i += 1;
+        }
+        Self {
+            pages,
+            caches,
+            large_bytes: 0,
+            object_bytes: 0,
+        }
+    }
+
+    /// 底层的页分配器
+    pub fn pages(&self) -> &P {
+        &self.pages
+    }
+
+    /// `layout` 的大小类，过大时为 `None`
+    fn class_of(layout: &Layout) -> Option<usize> {
+        let size = layout
+            .size()
+            .max(layout.align())
+            .max(1 << MIN_CLASS_SHIFT)
+            .next_power_of_two();
+        (size <= MAX_SLAB_SIZE).then(|| size.trailing_zeros() as usize - MIN_CLASS_SHIFT)
+    }
+
+    fn large_pages(layout: &Layout) -> usize {
+        layout.size().div_ceil(P::PAGE_SIZE)
+    }
+
+    /// 将各大小类的空 slab 归还页分配器，返回归还的页数
+    pub fn shrink(&mut self) -> usize {
+        let Self { pages, caches, .. } = self;
+        caches.iter_mut().map(|cache| cache.shrink(pages)).sum()
+    }
+
+    /// 各大小类已分配的对象数
+    pub fn objects_in_use(&self) -> [usize; NR_SIZE_CLASSES] {
+        core::array::from_fn(|i| self.caches[i].in_use())
+    }
+
+    /// slab 占用的总页数
+    pub fn slab_pages(&self) -> usize {
+        self.caches.iter().map(Cache::nr_slabs).sum()
+    }
+}
+
+impl<P: PageAllocator + Default> Default for SlabByteAllocator<P> {
+    fn default() -> Self {
+        Self::new(P::default())
+    }
+}
+
+impl<P: PageAllocator> BaseAllocator for SlabByteAllocator<P> {
+    /// Initialize the allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        self.pages.init(start, size);
+    }
+
+    /// Add a free memory region to the allocator.
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.pages.add_memory(start, size)
+    }
+}
+
+impl<P: PageAllocator> ByteAllocator for SlabByteAllocator<P> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        match Self::class_of(&layout) {
+            Some(class) => {
+                let cache = &mut self.caches[class];
+                let ptr = cache.alloc(&mut self.pages)?;
+                self.object_bytes += cache.object_size();
+                Ok(ptr)
+            }
+            None => {
+                let num_pages = Self::large_pages(&layout);
+                let pos = self
+                    .pages
+                    .alloc_pages(num_pages, layout.align().max(P::PAGE_SIZE))?;
+                self.large_bytes += num_pages * P::PAGE_SIZE;
+                NonNull::new(pos as *mut u8).ok_or(AllocError::NoMemory)
+            }
+        }
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        match Self::class_of(&layout) {
+            Some(class) => {
+                let cache = &mut self.caches[class];
+                // SAFETY: `pos` was allocated with the same layout, so from the
+                // same cache.
+                unsafe { cache.dealloc(&mut self.pages, pos) };
+                self.object_bytes -= cache.object_size();
+            }
+            None => {
+                let num_pages = Self::large_pages(&layout);
+                self.pages.dealloc_pages(pos.as_ptr() as usize, num_pages);
+                self.large_bytes -= num_pages * P::PAGE_SIZE;
+            }
+        }
+    }
+
+    /// The allocated bytes and the available ones.
+    fn total_bytes(&self) -> usize {
+        self.used_bytes() + self.available_bytes()
+    }
+
+    /// The bytes of the allocated objects, rounded up to their classes, and of
+    /// the large allocations, rounded up to the pages.
+    fn used_bytes(&self) -> usize {
+        self.object_bytes + self.large_bytes
+    }
+
+    /// The free pages of the page allocator and the free objects of the slabs.
+    fn available_bytes(&self) -> usize {
+        let free_objects: usize = self
+            .caches
+            .iter()
+            .map(|cache| cache.free_objects(P::PAGE_SIZE) * cache.object_size())
+            .sum();
+        self.pages.available_pages() * P::PAGE_SIZE + free_objects
+    }
+}
+
+/// A cache of the objects of type `T`.
+///
+/// Like a size class of [`SlabByteAllocator`], the objects are kept in slabs
+/// of one page each, but of the exact size and alignment of `T`. The pages are
+/// taken from and returned to the page allocator given to each call, which
+/// must be the same one for the whole life of the cache.
+///
+/// Dropping the cache leaks its slabs, [`SlabCache::shrink`] it first once
+/// all the objects are freed.
+pub struct SlabCache<T> {
+    cache: Cache,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: the cache only holds the addresses of the slabs and objects, which
+// are not accessed but through its `&mut self` methods.
+unsafe impl<T: Send> Send for SlabCache<T> {}
+
+impl<T> SlabCache<T> {
+    const ALIGN: usize = {
+        let align = core::mem::align_of::<T>();
+        if align > core::mem::align_of::<usize>() {
+            align
+        } else {
+            core::mem::align_of::<usize>()
+        }
+    };
+
+    const SIZE: usize = {
+        let size = core::mem::size_of::<T>();
+        let size = if size > core::mem::size_of::<usize>() {
+            size
+        } else {
+            core::mem::size_of::<usize>()
+        };
+        size.next_multiple_of(Self::ALIGN)
+    };
+
+    /// Creates an empty cache.
+    pub const fn new() -> Self {
+        Self {
+            cache: Cache::new(Self::SIZE, Self::ALIGN),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of objects in a slab of a page of `page_size`
+    /// bytes, 0 if `T` does not fit in.
+    pub const fn objects_per_slab(page_size: usize) -> usize {
+        Cache::new(Self::SIZE, Self::ALIGN).objects_per_slab(page_size)
+    }
+
+    /// Returns the number of the allocated objects.
+    pub const fn len(&self) -> usize {
+        self.cache.in_use()
+    }
+
+    /// Returns `true` if no object is allocated.
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of pages taken by the slabs.
+    pub const fn slab_pages(&self) -> usize {
+        self.cache.nr_slabs()
+    }
+
+    /// Moves `value` into a new object.
+    ///
+    /// Fails with [`AllocError::InvalidParam`] if `T` does not fit in a page
+    /// of `P`, and with [`AllocError::NoMemory`] if no page is left.
+    pub fn alloc<P: PageAllocator>(&mut self, pages: &mut P, value: T) -> AllocResult<NonNull<T>> {
+        let ptr = self.cache.alloc(pages)?.cast::<T>();
+        // SAFETY: the object is free, and of the size and alignment of `T`.
+        unsafe { ptr.as_ptr().write(value) };
+        Ok(ptr)
+    }
+
+    /// Drops the object at `ptr` and frees it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be an object allocated from this cache with `pages`, and not
+    /// be used afterwards.
+    pub unsafe fn free<P: PageAllocator>(&mut self, pages: &mut P, ptr: NonNull<T>) {
+        ptr.as_ptr().drop_in_place();
+        self.cache.dealloc(pages, ptr.cast());
+    }
+
+    /// Returns the empty slabs to `pages`, returns the number of them.
+    pub fn shrink<P: PageAllocator>(&mut self, pages: &mut P) -> usize {
+        self.cache.shrink(pages)
+    }
+}
+
+impl<T> Default for SlabCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,231 @@
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::cell::Cell;
+use std::rc::Rc;
+
+use crate::{SlabByteAllocator, SlabCache, MAX_SLAB_SIZE, NR_SIZE_CLASSES};
+
+const PAGE: usize = 4096;
+
+/// 从宿主堆分配页的页分配器，最多 `limit` 页
+struct HostPages {
+    limit: usize,
+    used: usize,
+}
+
+impl HostPages {
+    fn new(limit: usize) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    fn layout(num_pages: usize) -> Layout {
+        Layout::from_size_align(num_pages * PAGE, PAGE).unwrap()
+    }
+}
+
+impl BaseAllocator for HostPages {
+    fn init(&mut self, _start: usize, _size: usize) {}
+
+    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
+        Err(AllocError::InvalidParam)
+    }
+}
+
+impl PageAllocator for HostPages {
+    const PAGE_SIZE: usize = PAGE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        assert_eq!(align_pow2, PAGE);
+        if self.used + num_pages > self.limit {
+            return Err(AllocError::NoMemory);
+        }
+        self.used += num_pages;
+        Ok(unsafe { std::alloc::alloc(Self::layout(num_pages)) } as usize)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        self.used -= num_pages;
+        unsafe { std::alloc::dealloc(pos as *mut u8, Self::layout(num_pages)) };
+    }
+
+    fn total_pages(&self) -> usize {
+        self.limit
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used
+    }
+
+    fn available_pages(&self) -> usize {
+        self.limit - self.used
+    }
+}
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+fn slab_of(ptr: core::ptr::NonNull<u8>) -> usize {
+    ptr.as_ptr() as usize & !(PAGE - 1)
+}
+
+#[test]
+fn test_size_classes() {
+    let mut slab = SlabByteAllocator::new(HostPages::new(64));
+    // 大小与对齐向上取到 2 的幂，最小为 8
+    let cases = [
+        (layout(1, 1), 0),
+        (layout(8, 8), 0),
+        (layout(9, 1), 1),
+        (layout(8, 64), 3),
+        (layout(100, 4), 4),
+        (layout(MAX_SLAB_SIZE, 8), NR_SIZE_CLASSES - 1),
+    ];
+    let mut ptrs = Vec::new();
+    for (layout, class) in cases {
+        let before = slab.objects_in_use()[class];
+        let ptr = slab.alloc(layout).unwrap();
+        assert_eq!(ptr.as_ptr() as usize % layout.align(), 0);
+        assert_eq!(slab.objects_in_use()[class], before + 1);
+        ptrs.push((ptr, layout));
+    }
+    // 同一大小类的对象在同一个 slab 中
+    assert_eq!(slab_of(ptrs[0].0), slab_of(ptrs[1].0));
+    assert_eq!(slab.slab_pages(), 5);
+    assert_eq!(slab.used_bytes(), 8 + 8 + 16 + 64 + 128 + MAX_SLAB_SIZE);
+
+    for (ptr, layout) in ptrs {
+        slab.dealloc(ptr, layout);
+    }
+    assert_eq!(slab.objects_in_use(), [0; NR_SIZE_CLASSES]);
+    assert_eq!(slab.used_bytes(), 0);
+    assert_eq!(slab.shrink(), 5);
+    assert_eq!(slab.total_bytes(), 64 * PAGE);
+}
+
+#[test]
+fn test_large() {
+    let mut slab = SlabByteAllocator::new(HostPages::new(64));
+    let layout = layout(MAX_SLAB_SIZE + 1, 8);
+    let ptr = slab.alloc(layout).unwrap();
+    assert_eq!(ptr.as_ptr() as usize % PAGE, 0);
+    assert_eq!(slab.slab_pages(), 0);
+    assert_eq!(slab.pages().used_pages(), 1);
+    assert_eq!(slab.used_bytes(), PAGE);
+
+    let big = Layout::from_size_align(3 * PAGE + 1, 8).unwrap();
+    let ptr2 = slab.alloc(big).unwrap();
+    assert_eq!(slab.pages().used_pages(), 5);
+    slab.dealloc(ptr, layout);
+    slab.dealloc(ptr2, big);
+    assert_eq!(slab.pages().used_pages(), 0);
+    assert_eq!(slab.used_bytes(), 0);
+}
+
+#[test]
+fn test_slab_lifecycle() {
+    let mut slab = SlabByteAllocator::new(HostPages::new(64));
+    let layout = layout(MAX_SLAB_SIZE, 8);
+    // slab 头之后放得下 3 个对象
+    let ptrs: Vec<_> = (0..4).map(|_| slab.alloc(layout).unwrap()).collect();
+    assert_eq!(slab.slab_pages(), 2);
+    assert_eq!(slab_of(ptrs[0]), slab_of(ptrs[2]));
+    assert_ne!(slab_of(ptrs[2]), slab_of(ptrs[3]));
+    assert_eq!(slab.available_bytes(), 62 * PAGE + 2 * MAX_SLAB_SIZE);
+
+    // 释放的对象先被重用
+    slab.dealloc(ptrs[1], layout);
+    assert_eq!(slab.alloc(layout), Ok(ptrs[1]));
+
+    // 保留一个空 slab，其余归还页分配器
+    for &ptr in &ptrs {
+        slab.dealloc(ptr, layout);
+    }
+    assert_eq!(slab.slab_pages(), 1);
+    assert_eq!(slab.pages().used_pages(), 1);
+    assert_eq!(slab.shrink(), 1);
+    assert_eq!(slab.slab_pages(), 0);
+    assert_eq!(slab.pages().used_pages(), 0);
+}
+
+#[test]
+fn test_no_memory() {
+    let mut slab = SlabByteAllocator::new(HostPages::new(1));
+    let ptr = slab.alloc(layout(16, 8)).unwrap();
+    assert_eq!(slab.alloc(layout(32, 8)), Err(AllocError::NoMemory));
+    assert_eq!(
+        slab.alloc(layout(MAX_SLAB_SIZE + 1, 8)),
+        Err(AllocError::NoMemory)
+    );
+    slab.dealloc(ptr, layout(16, 8));
+    assert_eq!(slab.shrink(), 1);
+    assert!(slab.alloc(layout(32, 8)).is_ok());
+}
+
+struct Node {
+    value: u64,
+    dropped: Rc<Cell<usize>>,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.dropped.set(self.dropped.get() + 1);
+    }
+}
+
+#[test]
+fn test_slab_cache() {
+    let mut pages = HostPages::new(64);
+    let mut cache = SlabCache::<Node>::new();
+    let dropped = Rc::new(Cell::new(0));
+    let per_slab = SlabCache::<Node>::objects_per_slab(PAGE);
+    assert!(per_slab > 1);
+    assert!(cache.is_empty());
+
+    let nodes: Vec<_> = (0..per_slab as u64 + 1)
+        .map(|value| {
+            let node = Node {
+                value,
+                dropped: dropped.clone(),
+            };
+            cache.alloc(&mut pages, node).unwrap()
+        })
+        .collect();
+    assert_eq!(cache.len(), per_slab + 1);
+    assert_eq!(cache.slab_pages(), 2);
+    for (i, node) in nodes.iter().enumerate() {
+        assert_eq!(node.as_ptr() as usize % core::mem::align_of::<Node>(), 0);
+        assert_eq!(unsafe { node.as_ref() }.value, i as u64);
+    }
+
+    // 释放时析构对象，其位置由下一个对象重用
+    unsafe { cache.free(&mut pages, nodes[1]) };
+    assert_eq!(dropped.get(), 1);
+    let node = Node {
+        value: 42,
+        dropped: dropped.clone(),
+    };
+    let reused = cache.alloc(&mut pages, node).unwrap();
+    assert_eq!(reused, nodes[1]);
+    assert_eq!(unsafe { reused.as_ref() }.value, 42);
+
+    for &node in &nodes {
+        unsafe { cache.free(&mut pages, node) };
+    }
+    assert_eq!(dropped.get(), per_slab + 2);
+    assert!(cache.is_empty());
+    assert_eq!(cache.shrink(&mut pages), 1);
+    assert_eq!(pages.used_pages(), 0);
+}
+
+#[test]
+fn test_slab_cache_too_large() {
+    let mut pages = HostPages::new(64);
+    let mut cache = SlabCache::<[u8; PAGE]>::new();
+    assert_eq!(SlabCache::<[u8; PAGE]>::objects_per_slab(PAGE), 0);
+    assert_eq!(
+        cache.alloc(&mut pages, [0; PAGE]),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(pages.used_pages(), 0);
+}
@@ -0,0 +1,375 @@
+#![cfg_attr(not(test), no_std)]
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+#[cfg(test)]
+mod tests;
+
+/// 块大小与地址的粒度，也是分配的最小对齐
+const GRANULARITY: usize = 2 * core::mem::size_of::<usize>();
+
+/// 块头大小，块头之后为用户数据
+const HEADER_SIZE: usize = core::mem::size_of::<BlockHeader>();
+
+/// 最小的块：块头与空闲链表指针
+const MIN_BLOCK_SIZE: usize = core::mem::size_of::<FreeBlock>();
+
+/// 块大小中的空闲标记（块大小为粒度的倍数，低位不用）
+const FREE: usize = 1;
+
+/// 每个块开头的块头
+#[repr(C)]
+struct BlockHeader {
+    // 物理上的前一个块，0 表示为区域的第一个块
+    prev_phys: usize,
+    // 块大小（包括块头），低位为标记
+    size: usize,
+}
+
+/// 空闲块，链表指针在用户数据处
+#[repr(C)]
+struct FreeBlock {
+    header: BlockHeader,
+    // 同一链表中的下一个空闲块，0 表示没有
+    next_free: usize,
+    // 同一链表中的上一个空闲块，0 表示没有
+    prev_free: usize,
+}
+
+fn block(pos: usize) -> *mut FreeBlock {
+    pos as *mut FreeBlock
+}
+
+/// TLSF (two-level segregated fit) byte allocator
+/// Use it for the latency-sensitive workloads, both allocation and free take
+/// constant time in the worst case.
+///
+/// The free blocks are kept in `FL_COUNT` x `SL_COUNT` free lists: the first
+/// level splits the sizes in powers of two, the second level splits each of
+/// them linearly in `SL_COUNT` ranges. A bitmap of the non-empty lists per
+/// level finds a free block large enough with two bit scans:
+/// - Allocation rounds the size up to the next list boundary, so that any
+///   block of the list found fits, takes the first block, and splits the
+///   rest off into a new free block.
+/// - Free merges the block with its physical neighbours at once if they are
+///   free, which each block header links to.
+///
+/// `FL_COUNT` and `SL_COUNT` are the widths of the two indexes, the largest
+/// block is about `2^(FL_COUNT + log2(SL_COUNT) + log2(2 *
+/// size_of::<usize>()) - 1)` bytes: the larger regions are cut in several
+/// blocks, which are never merged. `SL_COUNT` must be a power of two, at
+/// most `usize::BITS`.
+///
+/// Any number of regions can be added by `add_memory`, each ending with a
+/// sentinel block header. Like the buddy page allocator, it writes into the
+/// memory it manages.
+///
+pub struct TlsfByteAllocator<const FL_COUNT: usize = 24, const SL_COUNT: usize = 16> {
+    // 非空的一级链表的位图
+    fl_bitmap: usize,
+    // 各一级下非空的二级链表的位图
+    sl_bitmaps: [usize; FL_COUNT],
+    // 各链表的头块地址，0 表示为空
+    free_lists: [[usize; SL_COUNT]; FL_COUNT],
+    // 可分配的总字节数
+    total_bytes: usize,
+    // 已分配块的字节数（包括块头）
+    used_bytes: usize,
+}
+
+impl<const FL_COUNT: usize, const SL_COUNT: usize> TlsfByteAllocator<FL_COUNT, SL_COUNT> {
+    /// 二级索引的位数
+    const SL_SHIFT: u32 = SL_COUNT.trailing_zeros();
+
+    /// 一级索引 0 的所有块均小于此大小，按粒度线性划分
+    const SMALL_BLOCK_SIZE: usize = GRANULARITY << Self::SL_SHIFT;
+
+    /// 一级索引 0 之外的块大小的最低位数
+    const FL_SHIFT: u32 = Self::SMALL_BLOCK_SIZE.trailing_zeros();
+
+    /// 最大的块大小
+    const MAX_BLOCK_SIZE: usize = {
+        assert!(
+            SL_COUNT.is_power_of_two() && SL_COUNT <= usize::BITS as usize,
+            "SL_COUNT must be a power of two, at most usize::BITS"
+        );
+        assert!(
+            FL_COUNT > 0 && FL_COUNT <= usize::BITS as usize,
+            "FL_COUNT must be in 1..=usize::BITS"
+        );
+        assert!(
+            Self::FL_SHIFT as usize + FL_COUNT <= usize::BITS as usize,
+            "the first level index is too wide"
+        );
+        (1 << (Self::FL_SHIFT as usize + FL_COUNT - 1)) - GRANULARITY
+    };
+
+    /// 创建一个新的 TLSF 分配器
+    pub const fn new() -> Self {
+        let _ = Self::MAX_BLOCK_SIZE;
+        Self {
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            free_lists: [[0; SL_COUNT]; FL_COUNT],
+            total_bytes: 0,
+            used_bytes: 0,
+        }
+    }
+
+    /// 大小为 `size` 的块所在的链表
+    fn mapping(size: usize) -> (usize, usize) {
+        if size < Self::SMALL_BLOCK_SIZE {
+            (0, size / GRANULARITY)
+        } else {
+            let fl = size.ilog2();
+            let sl = (size >> (fl - Self::SL_SHIFT)) ^ SL_COUNT;
+            ((fl - Self::FL_SHIFT + 1) as usize, sl)
+        }
+    }
+
+    /// 其中任意块都能容纳 `size` 字节的第一个链表
+    fn mapping_search(size: usize) -> Option<(usize, usize)> {
+        let size = if size < Self::SMALL_BLOCK_SIZE {
+            size
+        } else {
+            size.checked_add((1 << (size.ilog2() - Self::SL_SHIFT)) - 1)?
+        };
+        (size <= Self::MAX_BLOCK_SIZE).then(|| Self::mapping(size))
+    }
+
+    /// 从 `(fl, sl)` 起第一个非空的链表
+    fn find_list(&self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        let sl_map = self.sl_bitmaps[fl] & (!0 << sl);
+        if sl_map != 0 {
+            return Some((fl, sl_map.trailing_zeros() as usize));
+        }
+        let fl_map = self.fl_bitmap & 1usize.checked_shl(fl as u32 + 1).map_or(0, |b| !(b - 1));
+        if fl_map == 0 {
+            return None;
+        }
+        let fl = fl_map.trailing_zeros() as usize;
+        Some((fl, self.sl_bitmaps[fl].trailing_zeros() as usize))
+    }
+
+    /// 将 `pos` 处大小为 `size` 的块标记为空闲，放入链表
+    fn insert_free(&mut self, pos: usize, size: usize) {
+        let (fl, sl) = Self::mapping(size);
+        let head = self.free_lists[fl][sl];
+        // SAFETY: the free blocks belong to the allocator.
+        unsafe {
+            let b = block(pos);
+            (*b).header.size = size | FREE;
+            (*b).next_free = head;
+            (*b).prev_free = 0;
+            if head != 0 {
+                (*block(head)).prev_free = pos;
+            }
+        }
+        self.free_lists[fl][sl] = pos;
+        self.sl_bitmaps[fl] |= 1 << sl;
+        self.fl_bitmap |= 1 << fl;
+    }
+
+    /// 将 `pos` 处的空闲块移出链表，返回其大小
+    fn remove_free(&mut self, pos: usize) -> usize {
+        // SAFETY: `pos` is a free block.
+        unsafe {
+            let b = block(pos);
+            let size = (*b).header.size & !FREE;
+            let (next, prev) = ((*b).next_free, (*b).prev_free);
+            let (fl, sl) = Self::mapping(size);
+            if prev == 0 {
+                self.free_lists[fl][sl] = next;
+                if next == 0 {
+                    self.sl_bitmaps[fl] &= !(1 << sl);
+                    if self.sl_bitmaps[fl] == 0 {
+                        self.fl_bitmap &= !(1 << fl);
+                    }
+                }
+            } else {
+                (*block(prev)).next_free = next;
+            }
+            if next != 0 {
+                (*block(next)).prev_free = prev;
+            }
+            (*b).header.size = size;
+            size
+        }
+    }
+
+    /// `pos` 处块的大小
+    fn size_of(pos: usize) -> usize {
+        unsafe { (*block(pos)).header.size & !FREE }
+    }
+
+    fn is_free(pos: usize) -> bool {
+        unsafe { (*block(pos)).header.size & FREE != 0 }
+    }
+
+    fn set_prev_phys(pos: usize, prev: usize) {
+        unsafe { (*block(pos)).header.prev_phys = prev }
+    }
+
+    /// 将 `pos` 处已移出链表、大小为 `size` 的块从 `at` 处切开，返回后一块
+    fn split(pos: usize, size: usize, at: usize) -> usize {
+        let rest = pos + at;
+        // SAFETY: both blocks are within the block of `pos`.
+        unsafe {
+            (*block(pos)).header.size = at;
+            (*block(rest)).header = BlockHeader {
+                prev_phys: pos,
+                size: size - at,
+            };
+        }
+        Self::set_prev_phys(rest + size - at, rest);
+        rest
+    }
+
+    /// 释放 `pos` 处大小为 `size` 的块，与空闲的相邻块合并
+    fn free_block(&mut self, mut pos: usize, mut size: usize) {
+        let prev = unsafe { (*block(pos)).header.prev_phys };
+        if prev != 0 && Self::is_free(prev) && Self::size_of(prev) + size <= Self::MAX_BLOCK_SIZE {
+            size += self.remove_free(prev);
+            pos = prev;
+        }
+        let next = pos + size;
+        if Self::is_free(next) && size + Self::size_of(next) <= Self::MAX_BLOCK_SIZE {
+            size += self.remove_free(next);
+        }
+        Self::set_prev_phys(pos + size, pos);
+        unsafe { (*block(pos)).header.size = size };
+        self.insert_free(pos, size);
+    }
+}
+
+impl<const FL_COUNT: usize, const SL_COUNT: usize> Default
+    for TlsfByteAllocator<FL_COUNT, SL_COUNT>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const FL_COUNT: usize, const SL_COUNT: usize> BaseAllocator
+    for TlsfByteAllocator<FL_COUNT, SL_COUNT>
+{
+    /// Initialize the allocator with a free memory region.
+    fn init(&mut self, start: usize, size: usize) {
+        *self = Self::new();
+        self.add_memory(start, size)
+            .expect("invalid region of the TLSF allocator");
+    }
+
+    /// Add a free memory region to the allocator.
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        let start = start
+            .checked_next_multiple_of(GRANULARITY)
+            .ok_or(AllocError::InvalidParam)?;
+        let end = end & !(GRANULARITY - 1);
+        // 区域末尾为哨兵块头，标记为已分配，不会被合并
+        if end.saturating_sub(start) < MIN_BLOCK_SIZE + HEADER_SIZE {
+            return Err(AllocError::InvalidParam);
+        }
+        let sentinel = end - HEADER_SIZE;
+        // 超过最大块大小的区域切成多个块
+        let (mut pos, mut prev) = (start, 0);
+        while pos < sentinel {
+            let mut size = (sentinel - pos).min(Self::MAX_BLOCK_SIZE);
+            if sentinel - pos - size < MIN_BLOCK_SIZE {
+                size = sentinel - pos;
+                if size > Self::MAX_BLOCK_SIZE {
+                    size -= MIN_BLOCK_SIZE;
+                }
+            }
+            // SAFETY: the region is given to the allocator.
+            unsafe {
+                (*block(pos)).header = BlockHeader {
+                    prev_phys: prev,
+                    size,
+                }
+            };
+            self.insert_free(pos, size);
+            prev = pos;
+            pos += size;
+        }
+        unsafe {
+            (*block(sentinel)).header = BlockHeader {
+                prev_phys: prev,
+                size: 0,
+            }
+        };
+        self.total_bytes += sentinel - start;
+        Ok(())
+    }
+}
+
+impl<const FL_COUNT: usize, const SL_COUNT: usize> ByteAllocator
+    for TlsfByteAllocator<FL_COUNT, SL_COUNT>
+{
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let size = layout
+            .size()
+            .checked_next_multiple_of(GRANULARITY)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .ok_or(AllocError::NoMemory)?
+            .max(MIN_BLOCK_SIZE);
+        let align = layout.align();
+        // 对齐要求更高时，多找的空间用于切出前面的空闲块
+        let search = if align <= GRANULARITY {
+            size
+        } else {
+            size.checked_add(align)
+                .and_then(|size| size.checked_add(MIN_BLOCK_SIZE))
+                .ok_or(AllocError::NoMemory)?
+        };
+        let (fl, sl) = Self::mapping_search(search).ok_or(AllocError::NoMemory)?;
+        let (fl, sl) = self.find_list(fl, sl).ok_or(AllocError::NoMemory)?;
+        let mut pos = self.free_lists[fl][sl];
+        let mut block_size = self.remove_free(pos);
+
+        if align > GRANULARITY {
+            let data = pos + HEADER_SIZE;
+            let mut aligned = data.next_multiple_of(align);
+            if aligned != data && aligned - data < MIN_BLOCK_SIZE {
+                aligned = (data + MIN_BLOCK_SIZE).next_multiple_of(align);
+            }
+            if aligned != data {
+                let lead = aligned - data;
+                let rest = Self::split(pos, block_size, lead);
+                self.free_block(pos, lead);
+                pos = rest;
+                block_size -= lead;
+            }
+        }
+        if block_size - size >= MIN_BLOCK_SIZE {
+            let rest = Self::split(pos, block_size, size);
+            self.free_block(rest, block_size - size);
+            block_size = size;
+        }
+        self.used_bytes += block_size;
+        Ok(unsafe { NonNull::new_unchecked((pos + HEADER_SIZE) as *mut u8) })
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, _layout: Layout) {
+        let pos = pos.as_ptr() as usize - HEADER_SIZE;
+        let size = Self::size_of(pos);
+        debug_assert!(!Self::is_free(pos), "TLSF allocator: double free");
+        self.used_bytes -= size;
+        self.free_block(pos, size);
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.total_bytes - self.used_bytes
+    }
+}
@@ -0,0 +1,220 @@
+use allocator::{AllocError, BaseAllocator, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::{TlsfByteAllocator, HEADER_SIZE};
+
+const PAGE: usize = 4096;
+
+/// 页对齐的一块内存
+struct Memory {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Memory {
+    fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, PAGE).unwrap();
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        assert!(!ptr.is_null());
+        Self { ptr, layout }
+    }
+
+    fn start(&self) -> usize {
+        self.ptr as usize
+    }
+
+    fn size(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// 各链表中的空闲块 `(地址, 大小)`，按地址排序
+fn free_blocks<const FL: usize, const SL: usize>(
+    tlsf: &TlsfByteAllocator<FL, SL>,
+) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    for (fl, lists) in tlsf.free_lists.iter().enumerate() {
+        for (sl, &head) in lists.iter().enumerate() {
+            assert_eq!(head != 0, tlsf.sl_bitmaps[fl] & (1 << sl) != 0);
+            let mut pos = head;
+            while pos != 0 {
+                blocks.push((pos, TlsfByteAllocator::<FL, SL>::size_of(pos)));
+                pos = unsafe { (*crate::block(pos)).next_free };
+            }
+        }
+        assert_eq!(tlsf.sl_bitmaps[fl] != 0, tlsf.fl_bitmap & (1 << fl) != 0);
+    }
+    blocks.sort_unstable();
+    blocks
+}
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+/// 分配到耗尽再全部释放，检查对齐、数据与空闲块的合并
+fn exercise<const FL: usize, const SL: usize>() {
+    let max = TlsfByteAllocator::<FL, SL>::MAX_BLOCK_SIZE;
+    let mem = Memory::new(16 * PAGE);
+    let mut tlsf = TlsfByteAllocator::<FL, SL>::new();
+    tlsf.init(mem.start(), mem.size());
+    let total = mem.size() - HEADER_SIZE;
+    assert_eq!(tlsf.total_bytes(), total);
+
+    // 超过最大块大小的区域切成多个块
+    let blocks = free_blocks(&tlsf);
+    assert_eq!(blocks.iter().map(|&(_, size)| size).sum::<usize>(), total);
+    assert!(blocks.iter().all(|&(_, size)| size <= max));
+    assert_eq!(blocks.len(), total.div_ceil(max));
+
+    let layouts = [
+        layout(1, 1),
+        layout(24, 8),
+        layout(max / 4, 16),
+        layout(8, 64),
+    ];
+    let mut ptrs: Vec<(NonNull<u8>, Layout)> = Vec::new();
+    loop {
+        let before = ptrs.len();
+        for layout in layouts {
+            if let Ok(ptr) = tlsf.alloc(layout) {
+                assert_eq!(ptr.as_ptr() as usize % layout.align(), 0);
+                let fill = ptrs.len() as u8;
+                unsafe { ptr.as_ptr().write_bytes(fill, layout.size()) };
+                ptrs.push((ptr, layout));
+            }
+        }
+        if ptrs.len() == before {
+            break;
+        }
+    }
+    assert!(ptrs.len() > 16);
+    assert_eq!(tlsf.used_bytes() + tlsf.available_bytes(), total);
+    assert_eq!(tlsf.alloc(layout(max, 1)), Err(AllocError::NoMemory));
+
+    for (i, &(ptr, layout)) in ptrs.iter().enumerate() {
+        let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+        assert!(data.iter().all(|&b| b == i as u8));
+    }
+    // 先释放隔一个的块，再释放其余的
+    for (ptr, layout) in ptrs.iter().step_by(2).chain(ptrs.iter().skip(1).step_by(2)) {
+        tlsf.dealloc(*ptr, *layout);
+    }
+    assert_eq!(tlsf.used_bytes(), 0);
+
+    // 相邻的空闲块只在合并后超过最大块大小时才分开
+    let blocks = free_blocks(&tlsf);
+    assert_eq!(blocks.iter().map(|&(_, size)| size).sum::<usize>(), total);
+    for pair in blocks.windows(2) {
+        let ((a, a_size), (b, b_size)) = (pair[0], pair[1]);
+        assert!(a_size <= max);
+        assert!(a + a_size < b || a_size + b_size > max);
+    }
+}
+
+#[test]
+fn test_configs() {
+    exercise::<24, 16>();
+    exercise::<16, 4>();
+    exercise::<8, 32>();
+    exercise::<6, 64>();
+    exercise::<4, 2>();
+    exercise::<12, 1>();
+}
+
+#[test]
+fn test_split_and_merge() {
+    let mem = Memory::new(PAGE);
+    let start = mem.start();
+    let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+    tlsf.init(start, PAGE);
+    let total = PAGE - HEADER_SIZE;
+    assert_eq!(free_blocks(&tlsf), [(start, total)]);
+
+    // 每个块切下 64 字节与块头，余下的仍为一个空闲块
+    let size = 64 + HEADER_SIZE;
+    let ptrs: Vec<_> = (0..3).map(|_| tlsf.alloc(layout(64, 8)).unwrap()).collect();
+    for (i, ptr) in ptrs.iter().enumerate() {
+        assert_eq!(ptr.as_ptr() as usize, start + i * size + HEADER_SIZE);
+    }
+    assert_eq!(free_blocks(&tlsf), [(start + 3 * size, total - 3 * size)]);
+    assert_eq!(tlsf.used_bytes(), 3 * size);
+
+    // 相邻块都已分配，不合并
+    tlsf.dealloc(ptrs[1], layout(64, 8));
+    assert_eq!(
+        free_blocks(&tlsf),
+        [(start + size, size), (start + 3 * size, total - 3 * size)]
+    );
+    // 与后一个空闲块合并
+    tlsf.dealloc(ptrs[0], layout(64, 8));
+    assert_eq!(
+        free_blocks(&tlsf),
+        [(start, 2 * size), (start + 3 * size, total - 3 * size)]
+    );
+    // 与前后两个空闲块合并
+    tlsf.dealloc(ptrs[2], layout(64, 8));
+    assert_eq!(free_blocks(&tlsf), [(start, total)]);
+    assert_eq!(tlsf.used_bytes(), 0);
+
+    // 分配的大小向上取到链表的边界，接近整块时找不到链表
+    let ptr = tlsf.alloc(layout(total - HEADER_SIZE - 8, 8));
+    assert_eq!(ptr, Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_alignment() {
+    let mem = Memory::new(PAGE);
+    let start = mem.start();
+    let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+    tlsf.init(start, PAGE);
+    let total = PAGE - HEADER_SIZE;
+
+    // 对齐前的空间切成前面的空闲块
+    let ptr = tlsf.alloc(layout(64, 256)).unwrap();
+    assert_eq!(ptr.as_ptr() as usize, start + 256);
+    let pos = ptr.as_ptr() as usize - HEADER_SIZE;
+    assert_eq!(
+        free_blocks(&tlsf),
+        [
+            (start, pos - start),
+            (
+                pos + 64 + HEADER_SIZE,
+                start + total - pos - 64 - HEADER_SIZE
+            )
+        ]
+    );
+    tlsf.dealloc(ptr, layout(64, 256));
+    assert_eq!(free_blocks(&tlsf), [(start, total)]);
+}
+
+#[test]
+fn test_regions() {
+    let mem = Memory::new(PAGE);
+    let more = Memory::new(PAGE);
+    let mut tlsf = TlsfByteAllocator::<24, 16>::new();
+    tlsf.init(mem.start(), PAGE);
+    assert_eq!(
+        tlsf.add_memory(more.start(), HEADER_SIZE + 8),
+        Err(AllocError::InvalidParam)
+    );
+    tlsf.add_memory(more.start() + 1, PAGE - 1).unwrap();
+    assert_eq!(tlsf.total_bytes(), 2 * (PAGE - HEADER_SIZE) - 16);
+
+    // 两个区域的块不会合并
+    let a = tlsf.alloc(layout(2048, 8)).unwrap();
+    let b = tlsf.alloc(layout(2048, 8)).unwrap();
+    assert_ne!(a.as_ptr() as usize / PAGE, b.as_ptr() as usize / PAGE);
+    assert_eq!(tlsf.alloc(layout(2048, 8)), Err(AllocError::NoMemory));
+    tlsf.dealloc(a, layout(2048, 8));
+    tlsf.dealloc(b, layout(2048, 8));
+    assert_eq!(free_blocks(&tlsf).len(), 2);
+    assert_eq!(tlsf.available_bytes(), tlsf.total_bytes());
+}
@@ -2,6 +2,12 @@
 
 include scripts/make/cargo.mk
 
+ifeq ($(COMPRESS), y)
+  ifeq ($(filter $(ARCH),riscv64 aarch64),)
+    $(error "COMPRESS" is only supported on riscv64 and aarch64)
+  endif
+endif
+
 ifeq ($(APP_TYPE), c)
   include scripts/make/build_c.mk
 else
@@ -41,7 +47,15 @@ endif
 $(OUT_DIR):
 	$(call run_cmd,mkdir,-p $@)
 
+ifeq ($(COMPRESS), y)
+$(OUT_BIN): _cargo_build $(OUT_ELF)
+	$(call run_cmd,$(OBJCOPY),$(OUT_ELF) --strip-all -O binary $(OUT_RAW_BIN))
+	@printf "    $(GREEN_C)Compressing$(END_C) kernel image: $(OUT_RAW_BIN)\n"
+	$(call cargo_build_bootstub,$(OUT_RAW_BIN))
+	$(call run_cmd,$(OBJCOPY),$(BOOTSTUB_ELF) --strip-all -O binary $@)
+else
 $(OUT_BIN): _cargo_build $(OUT_ELF)
 	$(call run_cmd,$(OBJCOPY),$(OUT_ELF) --strip-all -O binary $@)
+endif
 
 .PHONY: _cargo_build
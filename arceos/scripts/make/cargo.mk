@@ -28,6 +28,11 @@ define cargo_build
   $(call run_cmd,cargo -C $(1) build,$(build_args) --features "$(strip $(2))")
 endef
 
+# The boot stub has its own linker script, not the one of the kernel.
+define cargo_build_bootstub
+  $(call run_cmd,AX_BOOTSTUB_KERNEL=$(abspath $(1)) RUSTFLAGS="" cargo -C tools/bootstub build,$(build_args))
+endef
+
 clippy_args := -A clippy::new_without_default
 
 define cargo_clippy
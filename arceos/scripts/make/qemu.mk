@@ -53,6 +53,12 @@ ifneq ($(VFIO_PCI),)
   QEMU := sudo $(QEMU)
 endif
 
+ifeq ($(PVPANIC), y)
+  ifeq ($(ARCH), x86_64)
+    qemu_args-y += -device pvpanic
+  endif
+endif
+
 ifeq ($(NET_DUMP), y)
   qemu_args-$(NET) += -object filter-dump,id=dump0,netdev=net0,file=netdump.pcap
 endif
@@ -61,6 +67,13 @@ qemu_args-$(GRAPHIC) += \
   -device virtio-gpu-$(vdev-suffix) -vga none \
   -serial mon:stdio
 
+qemu_args-$(SOUND) += \
+  -device virtio-sound-$(vdev-suffix),audiodev=snd0 \
+  -audiodev wav,id=snd0,path=sound.wav
+
+qemu_args-$(VSOCK) += \
+  -device vhost-vsock-$(vdev-suffix),guest-cid=$(VSOCK_CID)
+
 ifeq ($(GRAPHIC), n)
   qemu_args-y += -nographic
 endif
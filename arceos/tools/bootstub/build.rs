@@ -0,0 +1,141 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+/// The room of the stub code, data, BSS and stack besides the payload.
+const STUB_ROOM: usize = 0x10_0000;
+/// The alignment of the address the stub is relocated to.
+const STUB_ALIGN: usize = 0x20_0000;
+
+const MIN_MATCH: usize = 4;
+/// The last literals of a block, which no match may cover.
+const LAST_LITERALS: usize = 5;
+/// The distance of the last match start from the end of a block.
+const MF_LIMIT: usize = 12;
+const HASH_BITS: u32 = 16;
+
+fn push_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Appends a sequence of `literals` followed by the match `m` (the offset
+/// back and the length), which the last sequence has not.
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], m: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_code = m.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((lit_len.min(15) as u8) << 4) | match_code.min(15) as u8);
+    if lit_len >= 15 {
+        push_len(out, lit_len - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = m {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_code >= 15 {
+            push_len(out, match_code - 15);
+        }
+    }
+}
+
+/// Compresses `src` into a single LZ4 block, greedily.
+fn lz4_compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() / 2);
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let read = |i: usize| u32::from_le_bytes(src[i..i + 4].try_into().unwrap());
+    let limit = src.len().saturating_sub(MF_LIMIT);
+    let (mut anchor, mut i) = (0, 0);
+    while i < limit {
+        let hash = (read(i).wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+        // the positions are stored plus one, 0 for none
+        let candidate = table[hash].checked_sub(1);
+        table[hash] = i + 1;
+        match candidate {
+            Some(c) if i - c <= u16::MAX as usize && read(c) == read(i) => {
+                let max_len = src.len() - LAST_LITERALS - i;
+                let mut len = MIN_MATCH;
+                while len < max_len && src[c + len] == src[i + len] {
+                    len += 1;
+                }
+                push_sequence(&mut out, &src[anchor..i], Some((i - c, len)));
+                i += len;
+                anchor = i;
+            }
+            _ => i += 1,
+        }
+    }
+    push_sequence(&mut out, &src[anchor..], None);
+    out
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_BOOTSTUB_KERNEL");
+    let kernel_path = std::env::var("AX_BOOTSTUB_KERNEL")
+        .expect("AX_BOOTSTUB_KERNEL must be the path of the raw kernel image");
+    println!("cargo:rerun-if-changed={}", kernel_path);
+    let kernel = std::fs::read(&kernel_path).expect("failed to read the kernel image");
+    let payload = lz4_compress(&kernel);
+
+    let kernel_base = axconfig::KERNEL_BASE_PADDR;
+    let stub_size = payload.len() + STUB_ROOM;
+    let stub_base = axconfig::PHYS_MEMORY_END
+        .checked_sub(stub_size)
+        .expect("the compressed kernel is larger than the memory")
+        & !(STUB_ALIGN - 1);
+    // The bootloader loads the stub at the kernel base, the decompressed
+    // kernel and the loaded stub must both be below the relocated stub.
+    assert!(
+        kernel_base + kernel.len().max(stub_size) <= stub_base,
+        "no room for the boot stub: kernel at {:#x} ({:#x} bytes), stub at {:#x}",
+        kernel_base,
+        kernel.len(),
+        stub_base
+    );
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("kernel.lz4"), &payload).unwrap();
+    let mut layout = String::new();
+    writeln!(layout, "const KERNEL_BASE: usize = {:#x};", kernel_base).unwrap();
+    writeln!(layout, "const KERNEL_SIZE: usize = {:#x};", kernel.len()).unwrap();
+    std::fs::write(out_dir.join("layout.rs"), layout).unwrap();
+
+    let ld_script = format!(
+        "\
+ENTRY(_start)
+SECTIONS
+{{
+    . = {stub_base:#x};
+    .text : {{
+        *(.text.boot)
+        *(.text .text.*)
+    }}
+    .rodata : {{
+        *(.rodata .rodata.*)
+        *(.srodata .srodata.*)
+    }}
+    .data : {{
+        *(.data .data.*)
+        *(.sdata .sdata.*)
+    }}
+    . = ALIGN(8);
+    _stub_image_end = .;
+    .bss (NOLOAD) : ALIGN(16) {{
+        _sbss = .;
+        *(.bss.stack)
+        *(.bss .bss.*)
+        *(.sbss .sbss.*)
+        *(COMMON)
+        . = ALIGN(8);
+        _ebss = .;
+    }}
+    /DISCARD/ : {{
+        *(.eh_frame .eh_frame_hdr .comment)
+    }}
+}}
+"
+    );
+    let ld_path = out_dir.join("linker.lds");
+    std::fs::write(&ld_path, ld_script).unwrap();
+    println!("cargo:rustc-link-arg=-T{}", ld_path.display());
+}
@@ -0,0 +1,154 @@
+//! The position-independent entry, relocating the stub, and the jump to the
+//! kernel.
+//!
+//! The entry runs at the load address until it has copied the image to the
+//! link address, so it only uses PC-relative addressing and loads the link
+//! addresses from literals.
+
+/// The stack of the stub.
+const STACK_SIZE: usize = 0x4000;
+
+#[cfg(target_arch = "riscv64")]
+core::arch::global_asm!(
+    "
+    .section .text.boot
+    .globl _start
+_start:
+    mv      s0, a0
+    mv      s1, a1
+    lla     t0, _start
+    ld      t1, .Llink
+    ld      t2, .Lend
+    ld      t3, .Lentry
+1:
+    ld      t4, 0(t0)
+    sd      t4, 0(t1)
+    addi    t0, t0, 8
+    addi    t1, t1, 8
+    bltu    t1, t2, 1b
+    fence.i
+    jr      t3
+
+    .balign 8
+.Llink:
+    .quad   _start
+.Lend:
+    .quad   _stub_image_end
+.Lentry:
+    .quad   2f
+
+    .section .text
+2:
+    la      sp, {stack} + {stack_size}
+    la      t0, _sbss
+    la      t1, _ebss
+3:
+    bgeu    t0, t1, 4f
+    sd      zero, 0(t0)
+    addi    t0, t0, 8
+    j       3b
+4:
+    mv      a0, s0
+    mv      a1, s1
+    call    stub_main
+    ",
+    stack = sym STACK,
+    stack_size = const STACK_SIZE,
+);
+
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    "
+    .section .text.boot
+    .globl _start
+_start:
+    mov     x19, x0
+    mov     x20, x1
+    adr     x9, _start
+    ldr     x10, .Llink
+    ldr     x11, .Lend
+    ldr     x12, .Lentry
+1:
+    ldr     x13, [x9], #8
+    str     x13, [x10], #8
+    cmp     x10, x11
+    b.lo    1b
+    dsb     sy
+    ic      iallu
+    dsb     sy
+    isb
+    br      x12
+
+    .balign 8
+.Llink:
+    .quad   _start
+.Lend:
+    .quad   _stub_image_end
+.Lentry:
+    .quad   2f
+
+    .section .text
+2:
+    adrp    x9, {stack}
+    add     x9, x9, :lo12:{stack}
+    add     x9, x9, {stack_size}
+    mov     sp, x9
+    adrp    x9, _sbss
+    add     x9, x9, :lo12:_sbss
+    adrp    x10, _ebss
+    add     x10, x10, :lo12:_ebss
+3:
+    cmp     x9, x10
+    b.hs    4f
+    str     xzr, [x9], #8
+    b       3b
+4:
+    mov     x0, x19
+    mov     x1, x20
+    bl      stub_main
+    ",
+    stack = sym STACK,
+    stack_size = const STACK_SIZE,
+);
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "aarch64")))]
+compile_error!("the boot stub supports riscv64 and aarch64 only");
+
+#[link_section = ".bss.stack"]
+static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+/// Jumps to the decompressed kernel at `entry` with the boot arguments.
+///
+/// # Safety
+///
+/// The kernel image must be at `entry`.
+pub unsafe fn enter_kernel(entry: usize, arg0: usize, arg1: usize) -> ! {
+    #[cfg(target_arch = "riscv64")]
+    core::arch::asm!(
+        "fence.i",
+        "jr {entry}",
+        entry = in(reg) entry,
+        in("a0") arg0,
+        in("a1") arg1,
+        options(noreturn),
+    );
+    #[cfg(target_arch = "aarch64")]
+    core::arch::asm!(
+        "dsb sy",
+        "ic iallu",
+        "dsb sy",
+        "isb",
+        "br {entry}",
+        entry = in(reg) entry,
+        in("x0") arg0,
+        in("x1") arg1,
+        options(noreturn),
+    );
+}
+
+/// Stops the boot CPU, when the payload is corrupted.
+pub fn halt() -> ! {
+    loop {
+        unsafe { core::arch::asm!("wfi") };
+    }
+}
@@ -0,0 +1,50 @@
+//! LZ4 block decompression.
+
+fn read_len(src: &[u8], i: &mut usize, mut len: usize) -> Option<usize> {
+    loop {
+        let b = *src.get(*i)?;
+        *i += 1;
+        len = len.checked_add(b as usize)?;
+        if b != 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// Decompresses the LZ4 block `src` into `dst`, returns the decompressed
+/// length, or [`None`] if the block is corrupted or larger than `dst`.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let (mut i, mut o) = (0usize, 0usize);
+    loop {
+        let token = *src.get(i)?;
+        i += 1;
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len = read_len(src, &mut i, lit_len)?;
+        }
+        let literals = src.get(i..i.checked_add(lit_len)?)?;
+        dst.get_mut(o..o.checked_add(lit_len)?)?
+            .copy_from_slice(literals);
+        i += lit_len;
+        o += lit_len;
+        // the last sequence has no match
+        if i == src.len() {
+            return Some(o);
+        }
+        let offset = u16::from_le_bytes([*src.get(i)?, *src.get(i + 1)?]) as usize;
+        i += 2;
+        let mut match_len = (token & 15) as usize;
+        if match_len == 15 {
+            match_len = read_len(src, &mut i, match_len)?;
+        }
+        match_len += 4;
+        if offset == 0 || offset > o || match_len > dst.len() - o {
+            return None;
+        }
+        // the match may overlap the bytes it produces
+        for k in o..o + match_len {
+            dst[k] = dst[k - offset];
+        }
+        o += match_len;
+    }
+}
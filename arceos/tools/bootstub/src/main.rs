@@ -0,0 +1,33 @@
+//! Self-decompressing boot stub of the ArceOS kernel images.
+//!
+//! The stub is loaded at the base address of the kernel, copies itself to
+//! the top of the physical memory (where it is linked), decompresses the
+//! kernel image embedded in it to the base address, then jumps to the
+//! kernel with the boot arguments it was given. See the README for the build.
+
+#![no_std]
+#![no_main]
+
+mod boot;
+mod lz4;
+
+include!(concat!(env!("OUT_DIR"), "/layout.rs"));
+
+/// The kernel image compressed into an LZ4 block by the build script.
+static PAYLOAD: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/kernel.lz4"));
+
+/// Called by the relocated boot code with the boot arguments.
+#[no_mangle]
+extern "C" fn stub_main(arg0: usize, arg1: usize) -> ! {
+    // SAFETY: the memory below the relocated stub is not used by it.
+    let dst = unsafe { core::slice::from_raw_parts_mut(KERNEL_BASE as *mut u8, KERNEL_SIZE) };
+    match lz4::decompress(PAYLOAD, dst) {
+        Some(KERNEL_SIZE) => unsafe { boot::enter_kernel(KERNEL_BASE, arg0, arg1) },
+        _ => boot::halt(),
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    boot::halt()
+}
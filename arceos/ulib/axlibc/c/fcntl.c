@@ -1,3 +1,4 @@
+#include <errno.h>
 #include <fcntl.h>
 #include <stdarg.h>
 #include <stdio.h>
@@ -39,6 +40,23 @@ int open(const char *filename, int flags, ...)
     return ax_open(filename, flags, mode);
 }
 
+// TODO: remove this function in future work
+int ax_openat(int dirfd, const char *filename, int flags, mode_t mode);
+
+int openat(int dirfd, const char *filename, int flags, ...)
+{
+    mode_t mode = 0;
+
+    if ((flags & O_CREAT) || (flags & O_TMPFILE) == O_TMPFILE) {
+        va_list ap;
+        va_start(ap, flags);
+        mode = va_arg(ap, mode_t);
+        va_end(ap);
+    }
+
+    return ax_openat(dirfd, filename, flags, mode);
+}
+
 // TODO
 int posix_fadvise(int __fd, unsigned long __offset, unsigned long __len, int __advise)
 {
@@ -53,4 +71,11 @@ int sync_file_range(int fd, off_t pos, off_t len, unsigned flags)
     return 0;
 }
 
+int posix_fallocate(int fd, off_t offset, off_t len)
+{
+    if (fallocate(fd, 0, offset, len) < 0)
+        return errno;
+    return 0;
+}
+
 #endif // AX_CONFIG_FS
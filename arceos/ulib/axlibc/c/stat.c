@@ -1,3 +1,4 @@
+#include <fcntl.h>
 #include <stdio.h>
 #include <sys/stat.h>
 #include <sys/types.h>
@@ -9,19 +10,14 @@ int fchmod(int fd, mode_t mode)
     return 0;
 }
 
-// TODO:
+#ifdef AX_CONFIG_FS
+
 int mkdir(const char *path, mode_t mode)
 {
-    unimplemented();
-    return 0;
+    return mkdirat(AT_FDCWD, path, mode);
 }
 
-// TODO
-int chmod(const char *path, mode_t mode)
-{
-    unimplemented();
-    return 0;
-}
+#else
 
 // TODO
 mode_t umask(mode_t mask)
@@ -30,8 +26,10 @@ mode_t umask(mode_t mask)
     return 0;
 }
 
+#endif // AX_CONFIG_FS
+
 // TODO
-int fstatat(int fd, const char *restrict path, struct stat *restrict st, int flag)
+int chmod(const char *path, mode_t mode)
 {
     unimplemented();
     return 0;
@@ -43,48 +43,6 @@ void *memchr(const void *src, int c, size_t n)
     return n ? (void *)s : 0;
 }
 
-void *memset(void *dest, int c, size_t n)
-{
-    unsigned char *s = dest;
-    size_t k;
-
-    /* Fill head and tail with minimal branching. Each
-     * conditional ensures that all the subsequently used
-     * offsets are well-defined and in the dest region. */
-
-    if (!n)
-        return dest;
-    s[0] = c;
-    s[n - 1] = c;
-    if (n <= 2)
-        return dest;
-    s[1] = c;
-    s[2] = c;
-    s[n - 2] = c;
-    s[n - 3] = c;
-    if (n <= 6)
-        return dest;
-    s[3] = c;
-    s[n - 4] = c;
-    if (n <= 8)
-        return dest;
-
-    /* Advance pointer to align it at a 4-byte boundary,
-     * and truncate n to a multiple of 4. The previous code
-     * already took care of any head/tail that get cut off
-     * by the alignment. */
-
-    k = -(uintptr_t)s & 3;
-    s += k;
-    n -= k;
-    n &= -4;
-
-    /* Pure C fallback with no aliasing violations. */
-    for (; n; n--, s++) *s = c;
-
-    return dest;
-}
-
 char *strcpy(char *restrict d, const char *restrict s)
 {
     for (; (*d = *s); s++, d++)
@@ -234,14 +192,6 @@ int strerror_r(int err, char *buf, size_t buflen)
     return 0;
 }
 
-void *memcpy(void *restrict dest, const void *restrict src, size_t n)
-{
-    unsigned char *d = dest;
-    const unsigned char *s = src;
-    for (; n; n--) *d++ = *s++;
-    return dest;
-}
-
 void *memmove(void *dest, const void *src, size_t n)
 {
     char *d = dest;
@@ -261,14 +211,6 @@ void *memmove(void *dest, const void *src, size_t n)
     return dest;
 }
 
-int memcmp(const void *vl, const void *vr, size_t n)
-{
-    const unsigned char *l = vl, *r = vr;
-    for (; n && *l == *r; n--, l++, r++)
-        ;
-    return n ? *l - *r : 0;
-}
-
 int strcasecmp(const char *_l, const char *_r)
 {
     const unsigned char *l = (void *)_l, *r = (void *)_r;
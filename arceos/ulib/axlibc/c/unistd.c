@@ -68,20 +68,20 @@ ssize_t readlink(const char *path, char *buf, size_t bufsiz)
     return 0;
 }
 
-// TODO:
+#ifdef AX_CONFIG_FS
+
 int unlink(const char *pathname)
 {
-    unimplemented();
-    return 0;
+    return unlinkat(AT_FDCWD, pathname, 0);
 }
 
-// TODO:
 int rmdir(const char *pathname)
 {
-    unimplemented();
-    return 0;
+    return unlinkat(AT_FDCWD, pathname, AT_REMOVEDIR);
 }
 
+#endif // AX_CONFIG_FS
+
 // TODO:
 int fsync(int fd)
 {
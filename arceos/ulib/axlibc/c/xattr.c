@@ -0,0 +1,27 @@
+#include <sys/xattr.h>
+
+#ifdef AX_CONFIG_FS
+
+// There are no symbolic links, the `l*` variants are the same as the others.
+
+ssize_t lgetxattr(const char *path, const char *name, void *value, size_t size)
+{
+    return getxattr(path, name, value, size);
+}
+
+ssize_t llistxattr(const char *path, char *list, size_t size)
+{
+    return listxattr(path, list, size);
+}
+
+int lsetxattr(const char *path, const char *name, const void *value, size_t size, int flags)
+{
+    return setxattr(path, name, value, size, flags);
+}
+
+int lremovexattr(const char *path, const char *name)
+{
+    return removexattr(path, name);
+}
+
+#endif // AX_CONFIG_FS
@@ -0,0 +1,82 @@
+#ifndef _LINUX_ETHTOOL_H
+#define _LINUX_ETHTOOL_H
+
+#include <stdint.h>
+
+#define ETHTOOL_GSET     0x00000001
+#define ETHTOOL_GDRVINFO 0x00000003
+#define ETHTOOL_GLINK    0x0000000a
+#define ETHTOOL_GSTRINGS 0x0000001b
+#define ETHTOOL_GSTATS   0x0000001d
+
+#define ETH_GSTRING_LEN 32
+#define ETH_SS_STATS    1
+
+#define SPEED_UNKNOWN  -1
+#define DUPLEX_HALF    0x00
+#define DUPLEX_FULL    0x01
+#define DUPLEX_UNKNOWN 0xff
+
+#define PORT_TP    0x00
+#define PORT_FIBRE 0x03
+#define PORT_OTHER 0xff
+
+#define XCVR_INTERNAL 0x00
+
+#define AUTONEG_DISABLE 0x00
+#define AUTONEG_ENABLE  0x01
+
+struct ethtool_cmd {
+    uint32_t cmd;
+    uint32_t supported;
+    uint32_t advertising;
+    uint16_t speed;
+    uint8_t duplex;
+    uint8_t port;
+    uint8_t phy_address;
+    uint8_t transceiver;
+    uint8_t autoneg;
+    uint8_t mdio_support;
+    uint32_t maxtxpkt;
+    uint32_t maxrxpkt;
+    uint16_t speed_hi;
+    uint8_t eth_tp_mdix;
+    uint8_t eth_tp_mdix_ctrl;
+    uint32_t lp_advertising;
+    uint32_t reserved[2];
+};
+
+struct ethtool_drvinfo {
+    uint32_t cmd;
+    char driver[32];
+    char version[32];
+    char fw_version[32];
+    char bus_info[32];
+    char erom_version[32];
+    char reserved2[12];
+    uint32_t n_priv_flags;
+    uint32_t n_stats;
+    uint32_t testinfo_len;
+    uint32_t eedump_len;
+    uint32_t regdump_len;
+};
+
+struct ethtool_value {
+    uint32_t cmd;
+    uint32_t data;
+};
+
+struct ethtool_gstrings {
+    uint32_t cmd;
+    uint32_t string_set;
+    uint32_t len;
+    uint8_t data[];
+};
+
+struct ethtool_stats {
+    uint32_t cmd;
+    uint32_t n_stats;
+    uint64_t data[];
+};
+
+#endif // _LINUX_ETHTOOL_H
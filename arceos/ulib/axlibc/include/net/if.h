@@ -0,0 +1,59 @@
+#ifndef _NET_IF_H
+#define _NET_IF_H
+
+#include <sys/socket.h>
+
+#define IFNAMSIZ 16
+
+#define IFF_UP          0x1
+#define IFF_BROADCAST   0x2
+#define IFF_DEBUG       0x4
+#define IFF_LOOPBACK    0x8
+#define IFF_POINTOPOINT 0x10
+#define IFF_NOTRAILERS  0x20
+#define IFF_RUNNING     0x40
+#define IFF_NOARP       0x80
+#define IFF_PROMISC     0x100
+#define IFF_ALLMULTI    0x200
+#define IFF_MULTICAST   0x1000
+#define IFF_LOWER_UP    0x10000
+
+struct ifmap {
+    unsigned long int mem_start;
+    unsigned long int mem_end;
+    unsigned short int base_addr;
+    unsigned char irq;
+    unsigned char dma;
+    unsigned char port;
+};
+
+struct ifreq {
+    union {
+        char ifrn_name[IFNAMSIZ];
+    } ifr_ifrn;
+    union {
+        struct sockaddr ifru_addr;
+        struct sockaddr ifru_dstaddr;
+        struct sockaddr ifru_broadaddr;
+        struct sockaddr ifru_netmask;
+        struct sockaddr ifru_hwaddr;
+        short int ifru_flags;
+        int ifru_ivalue;
+        int ifru_mtu;
+        struct ifmap ifru_map;
+        char ifru_slave[IFNAMSIZ];
+        char ifru_newname[IFNAMSIZ];
+        char *ifru_data;
+    } ifr_ifru;
+};
+
+#define ifr_name    ifr_ifrn.ifrn_name
+#define ifr_hwaddr  ifr_ifru.ifru_hwaddr
+#define ifr_addr    ifr_ifru.ifru_addr
+#define ifr_flags   ifr_ifru.ifru_flags
+#define ifr_ifindex ifr_ifru.ifru_ivalue
+#define ifr_mtu     ifr_ifru.ifru_mtu
+#define ifr_map     ifr_ifru.ifru_map
+#define ifr_data    ifr_ifru.ifru_data
+
+#endif // _NET_IF_H
@@ -60,6 +60,13 @@
 #define TIOCGISO7816 0x80285442
 #define TIOCSISO7816 0xc0285443
 
+#define SIOCGIFNAME   0x8910
+#define SIOCGIFFLAGS  0x8913
+#define SIOCGIFMTU    0x8921
+#define SIOCGIFHWADDR 0x8927
+#define SIOCGIFINDEX  0x8933
+#define SIOCETHTOOL   0x8946
+
 int ioctl(int, int, ...);
 
 #endif // __SYS_IOCTL_H__
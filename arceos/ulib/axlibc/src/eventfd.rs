@@ -0,0 +1,35 @@
+use core::ffi::{c_int, c_uint, c_void};
+
+use arceos_posix_api::{sys_eventfd, sys_read, sys_write};
+
+use crate::utils::e;
+
+/// Create a file descriptor for event notification
+///
+/// Return the new file descriptor if succeed
+#[no_mangle]
+pub unsafe extern "C" fn eventfd(initval: c_uint, flags: c_int) -> c_int {
+    e(sys_eventfd(initval, flags))
+}
+
+/// Read the counter of an eventfd into `value`
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn eventfd_read(fd: c_int, value: *mut u64) -> c_int {
+    match e(sys_read(fd, value as *mut c_void, 8) as _) {
+        8 => 0,
+        _ => -1,
+    }
+}
+
+/// Add `value` to the counter of an eventfd
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn eventfd_write(fd: c_int, value: u64) -> c_int {
+    match e(sys_write(fd, &value as *const u64 as *const c_void, 8) as _) {
+        8 => 0,
+        _ => -1,
+    }
+}
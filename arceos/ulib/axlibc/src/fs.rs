@@ -1,7 +1,14 @@
-use core::ffi::{c_char, c_int};
+use axerrno::LinuxError;
+use core::ffi::{c_char, c_int, c_void};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_fallocate, sys_fchdir, sys_fstat, sys_fstatat, sys_getcwd, sys_linkat, sys_lseek,
+    sys_lstat, sys_mkdirat, sys_open, sys_openat, sys_posix_fadvise, sys_rename, sys_renameat,
+    sys_stat, sys_umask, sys_unlinkat, sys_utimensat,
+};
+use arceos_posix_api::{
+    sys_fgetxattr, sys_flistxattr, sys_fremovexattr, sys_fsetxattr, sys_getxattr, sys_listxattr,
+    sys_removexattr, sys_setxattr,
 };
 
 use crate::{ctypes, utils::e};
@@ -19,6 +26,20 @@ pub unsafe extern "C" fn ax_open(
     e(sys_open(filename, flags, mode))
 }
 
+/// Open a file by `filename` relative to the directory `dirfd` and insert it
+/// into the file descriptor table.
+///
+/// Return its index in the file table (`fd`).
+#[no_mangle]
+pub unsafe extern "C" fn ax_openat(
+    dirfd: c_int,
+    filename: *const c_char,
+    flags: c_int,
+    mode: ctypes::mode_t,
+) -> c_int {
+    e(sys_openat(dirfd, filename, flags, mode))
+}
+
 /// Set the position of the file indicated by `fd`.
 ///
 /// Return its position after seek.
@@ -27,6 +48,32 @@ pub unsafe extern "C" fn lseek(fd: c_int, offset: ctypes::off_t, whence: c_int)
     e(sys_lseek(fd, offset, whence) as _) as _
 }
 
+/// Manipulate the storage of `len` bytes from `offset` of the file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fallocate(
+    fd: c_int,
+    mode: c_int,
+    offset: ctypes::off_t,
+    len: ctypes::off_t,
+) -> c_int {
+    e(sys_fallocate(fd, mode, offset, len))
+}
+
+/// Give the access pattern of `len` bytes from `offset` of the file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn posix_fadvise(
+    fd: c_int,
+    offset: ctypes::off_t,
+    len: ctypes::off_t,
+    advice: c_int,
+) -> c_int {
+    e(sys_posix_fadvise(fd, offset, len, advice))
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -65,3 +112,193 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Rename `old` relative to the directory `olddirfd` to `new` relative to the
+/// directory `newdirfd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn renameat(
+    olddirfd: c_int,
+    old: *const c_char,
+    newdirfd: c_int,
+    new: *const c_char,
+) -> c_int {
+    e(sys_renameat(olddirfd, old, newdirfd, new))
+}
+
+/// Get the file metadata by `path` relative to the directory `dirfd` and
+/// write into `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fstatat(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut ctypes::stat,
+    flags: c_int,
+) -> c_int {
+    e(sys_fstatat(dirfd, path, buf, flags))
+}
+
+/// Create a directory by `path` relative to the directory `dirfd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn mkdirat(dirfd: c_int, path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    e(sys_mkdirat(dirfd, path, mode))
+}
+
+/// Set the file mode creation mask to `mask & 0o777`.
+///
+/// Return the previous mask.
+#[no_mangle]
+pub unsafe extern "C" fn umask(mask: ctypes::mode_t) -> ctypes::mode_t {
+    sys_umask(mask)
+}
+
+/// Remove a file, or a directory with `AT_REMOVEDIR`, by `path` relative to
+/// the directory `dirfd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    e(sys_unlinkat(dirfd, path, flags))
+}
+
+/// Create a hard link. Not supported by the filesystems.
+#[no_mangle]
+pub unsafe extern "C" fn linkat(
+    olddirfd: c_int,
+    old: *const c_char,
+    newdirfd: c_int,
+    new: *const c_char,
+    flags: c_int,
+) -> c_int {
+    e(sys_linkat(olddirfd, old, newdirfd, new, flags))
+}
+
+/// Change the current directory to the directory referred to by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fchdir(fd: c_int) -> c_int {
+    e(sys_fchdir(fd))
+}
+
+/// Set the access and modification times of the file by `path` relative to
+/// the directory `dirfd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const ctypes::timespec,
+    flags: c_int,
+) -> c_int {
+    if path.is_null() {
+        // Only `futimens` operates on `dirfd` itself.
+        return e((LinuxError::EFAULT as c_int).wrapping_neg());
+    }
+    e(sys_utimensat(dirfd, path, times, flags))
+}
+
+/// Set the access and modification times of the file indicated by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn futimens(fd: c_int, times: *const ctypes::timespec) -> c_int {
+    e(sys_utimensat(fd, core::ptr::null(), times, 0))
+}
+
+/// Get the value of the extended attribute `name` of the file by `path`.
+///
+/// Return the size of the value.
+#[no_mangle]
+pub unsafe extern "C" fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_getxattr(path, name, value as _, size) as _) as _
+}
+
+/// Get the value of the extended attribute `name` of the file indicated by
+/// `fd`.
+///
+/// Return the size of the value.
+#[no_mangle]
+pub unsafe extern "C" fn fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_fgetxattr(fd, name, value as _, size) as _) as _
+}
+
+/// Set the extended attribute `name` of the file by `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_setxattr(path, name, value as _, size, flags))
+}
+
+/// Set the extended attribute `name` of the file indicated by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fsetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_fsetxattr(fd, name, value as _, size, flags))
+}
+
+/// List the extended attribute names of the file by `path`.
+///
+/// Return the size of the list.
+#[no_mangle]
+pub unsafe extern "C" fn listxattr(
+    path: *const c_char,
+    list: *mut c_char,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_listxattr(path, list, size) as _) as _
+}
+
+/// List the extended attribute names of the file indicated by `fd`.
+///
+/// Return the size of the list.
+#[no_mangle]
+pub unsafe extern "C" fn flistxattr(fd: c_int, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    e(sys_flistxattr(fd, list, size) as _) as _
+}
+
+/// Remove the extended attribute `name` of the file by `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    e(sys_removexattr(path, name))
+}
+
+/// Remove the extended attribute `name` of the file indicated by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fremovexattr(fd: c_int, name: *const c_char) -> c_int {
+    e(sys_fremovexattr(fd, name))
+}
@@ -15,15 +15,25 @@
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `net`: Enable networking support.
+//!     - `vsock`: Enable the VM sockets (`AF_VSOCK`) to talk to the host.
 //! - Lib C functions
 //!     - `fd`: Enable file descriptor table.
 //!     - `pipe`: Enable pipe support.
 //!     - `select`: Enable synchronous I/O multiplexing ([select]) support.
 //!     - `epoll`: Enable event polling ([epoll]) support.
+//!     - `eventfd`: Enable event notification file descriptors ([eventfd]).
+//!     - `timerfd`: Enable timers notifying via file descriptors ([timerfd]).
+//!     - `signalfd`: Enable accepting signals via file descriptors ([signalfd]).
+//!     - `timer`: Enable the POSIX timers ([timer_create]), notifying by signals
+//!       via file descriptors or by callbacks.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [select]: https://man7.org/linux/man-pages/man2/select.2.html
 //! [epoll]: https://man7.org/linux/man-pages/man7/epoll.7.html
+//! [eventfd]: https://man7.org/linux/man-pages/man2/eventfd.2.html
+//! [timerfd]: https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+//! [signalfd]: https://man7.org/linux/man-pages/man2/signalfd.2.html
+//! [timer_create]: https://man7.org/linux/man-pages/man2/timer_create.2.html
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
 #![feature(doc_cfg)]
@@ -49,6 +59,8 @@ mod ctypes {
 #[macro_use]
 mod utils;
 
+#[cfg(feature = "eventfd")]
+mod eventfd;
 #[cfg(feature = "fd")]
 mod fd_ops;
 #[cfg(feature = "fs")]
@@ -57,16 +69,22 @@ mod fs;
 mod io_mpx;
 #[cfg(feature = "alloc")]
 mod malloc;
-#[cfg(feature = "net")]
+#[cfg(any(feature = "net", feature = "vsock"))]
 mod net;
 #[cfg(feature = "pipe")]
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "signalfd")]
+mod signalfd;
 #[cfg(feature = "alloc")]
 mod strftime;
 #[cfg(feature = "fp_simd")]
 mod strtod;
+#[cfg(feature = "timer")]
+mod timer;
+#[cfg(feature = "timerfd")]
+mod timerfd;
 
 mod errno;
 mod io;
@@ -74,6 +92,8 @@ mod mktime;
 mod rand;
 mod resource;
 mod setjmp;
+#[cfg(not(test))]
+mod string;
 mod sys;
 mod time;
 mod unistd;
@@ -87,6 +107,8 @@ pub use self::mktime::mktime;
 pub use self::rand::{rand, random, srand};
 pub use self::resource::{getrlimit, setrlimit};
 pub use self::setjmp::{longjmp, setjmp};
+#[cfg(not(test))]
+pub use self::string::{memcmp, memcpy, memset};
 pub use self::sys::sysconf;
 pub use self::time::{clock_gettime, nanosleep};
 pub use self::unistd::{abort, exit, getpid};
@@ -100,13 +122,24 @@ pub use self::strftime::strftime;
 pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, ax_openat, fchdir, fstat, fstatat, futimens, getcwd, linkat, lseek, lstat, mkdirat,
+    rename, renameat, stat, unlinkat, utimensat,
+};
+#[cfg(feature = "fs")]
+pub use self::fs::{
+    fgetxattr, flistxattr, fremovexattr, fsetxattr, getxattr, listxattr, removexattr, setxattr,
+};
 
-#[cfg(feature = "net")]
+#[cfg(all(feature = "fs", feature = "net"))]
+pub use self::net::sendfile;
+#[cfg(any(feature = "net", feature = "vsock"))]
 pub use self::net::{
-    accept, bind, connect, freeaddrinfo, getaddrinfo, getpeername, getsockname, listen, recv,
-    recvfrom, send, sendto, shutdown, socket,
+    accept, bind, connect, getpeername, getsockname, listen, recv, recvfrom, send, sendto,
+    shutdown, socket,
 };
+#[cfg(feature = "net")]
+pub use self::net::{freeaddrinfo, getaddrinfo, getsockopt};
 
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
@@ -116,6 +149,15 @@ pub use self::pthread::{pthread_mutex_init, pthread_mutex_lock, pthread_mutex_un
 #[cfg(feature = "pipe")]
 pub use self::pipe::pipe;
 
+#[cfg(feature = "eventfd")]
+pub use self::eventfd::{eventfd, eventfd_read, eventfd_write};
+#[cfg(feature = "signalfd")]
+pub use self::signalfd::signalfd;
+#[cfg(feature = "timer")]
+pub use self::timer::{timer_create, timer_delete, timer_getoverrun, timer_gettime, timer_settime};
+#[cfg(feature = "timerfd")]
+pub use self::timerfd::{timerfd_create, timerfd_gettime, timerfd_settime};
+
 #[cfg(feature = "select")]
 pub use self::io_mpx::select;
 #[cfg(feature = "epoll")]
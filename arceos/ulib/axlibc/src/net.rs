@@ -1,9 +1,12 @@
 use arceos_posix_api::{
-    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getpeername,
-    sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
-    sys_socket,
+    sys_accept, sys_bind, sys_connect, sys_getpeername, sys_getsockname, sys_listen, sys_recv,
+    sys_recvfrom, sys_send, sys_sendto, sys_shutdown, sys_socket,
 };
-use core::ffi::{c_char, c_int, c_void};
+#[cfg(feature = "net")]
+use arceos_posix_api::{sys_freeaddrinfo, sys_getaddrinfo, sys_getsockopt};
+#[cfg(feature = "net")]
+use core::ffi::c_char;
+use core::ffi::{c_int, c_void};
 
 use crate::{ctypes, utils::e};
 
@@ -70,6 +73,21 @@ pub unsafe extern "C" fn send(
     e(sys_send(socket_fd, buf_ptr, len, flag) as _) as _
 }
 
+/// Transfer data from a file to a TCP socket, without copying it through a
+/// user buffer.
+///
+/// Return the number of bytes sent if success.
+#[cfg(all(feature = "fs", feature = "net"))]
+#[no_mangle]
+pub unsafe extern "C" fn sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: ctypes::size_t,
+) -> ctypes::ssize_t {
+    e(arceos_posix_api::sys_sendfile(out_fd, in_fd, offset, count) as _) as _
+}
+
 /// Receive a message on a socket and get its source address.
 ///
 /// Return the number of bytes received if success.
@@ -138,6 +156,7 @@ pub unsafe extern "C" fn shutdown(
 /// Query addresses for a domain name.
 ///
 /// Return address number if success.
+#[cfg(feature = "net")]
 #[no_mangle]
 pub unsafe extern "C" fn getaddrinfo(
     nodename: *const c_char,
@@ -154,6 +173,7 @@ pub unsafe extern "C" fn getaddrinfo(
 }
 
 /// Free queried `addrinfo` struct
+#[cfg(feature = "net")]
 #[no_mangle]
 pub unsafe extern "C" fn freeaddrinfo(res: *mut ctypes::addrinfo) {
     sys_freeaddrinfo(res);
@@ -178,3 +198,18 @@ pub unsafe extern "C" fn getpeername(
 ) -> c_int {
     e(sys_getpeername(sock_fd, addr, addrlen))
 }
+
+/// Get options on a socket.
+///
+/// Return 0 if success.
+#[cfg(feature = "net")]
+#[no_mangle]
+pub unsafe extern "C" fn getsockopt(
+    sock_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+) -> c_int {
+    e(sys_getsockopt(sock_fd, level, optname, optval, optlen))
+}
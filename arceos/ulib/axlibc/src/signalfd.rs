@@ -0,0 +1,13 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::sys_signalfd;
+
+use crate::{ctypes, utils::e};
+
+/// Create a file descriptor for accepting signals
+///
+/// Return the file descriptor if succeed
+#[no_mangle]
+pub unsafe extern "C" fn signalfd(fd: c_int, mask: *const ctypes::sigset_t, flags: c_int) -> c_int {
+    e(sys_signalfd(fd, mask, flags))
+}
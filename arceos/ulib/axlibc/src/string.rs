@@ -0,0 +1,23 @@
+//! Memory routines of `string.h`, used by the whole image.
+
+use core::ffi::{c_int, c_void};
+
+/// Copies `n` bytes from `src` to `dest`, which must not overlap.
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(dest: *mut c_void, src: *const c_void, n: usize) -> *mut c_void {
+    axhal::string::memcpy(dest as _, src as _, n);
+    dest
+}
+
+/// Fills `n` bytes at `dest` with the byte `c`.
+#[no_mangle]
+pub unsafe extern "C" fn memset(dest: *mut c_void, c: c_int, n: usize) -> *mut c_void {
+    axhal::string::memset(dest as _, c as u8, n);
+    dest
+}
+
+/// Compares `n` bytes at `vl` and `vr`.
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(vl: *const c_void, vr: *const c_void, n: usize) -> c_int {
+    axhal::string::memcmp(vl as _, vr as _, n)
+}
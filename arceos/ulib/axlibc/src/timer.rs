@@ -0,0 +1,57 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{
+    sys_timer_create, sys_timer_delete, sys_timer_getoverrun, sys_timer_gettime, sys_timer_settime,
+};
+
+use crate::{ctypes, utils::e};
+
+/// Create a timer notifying its expirations as `sevp` asks
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *mut ctypes::sigevent,
+    timerid: *mut ctypes::timer_t,
+) -> c_int {
+    e(sys_timer_create(clockid, sevp, timerid))
+}
+
+/// Arm or disarm a timer
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timer_settime(
+    timerid: ctypes::timer_t,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_settime(timerid, flags, new_value, old_value))
+}
+
+/// Get the time until the next expiration of a timer
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timer_gettime(
+    timerid: ctypes::timer_t,
+    curr_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_gettime(timerid, curr_value))
+}
+
+/// Get the overrun count of the last notification delivered by a timer
+#[no_mangle]
+pub unsafe extern "C" fn timer_getoverrun(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_getoverrun(timerid))
+}
+
+/// Delete a timer
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timer_delete(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_delete(timerid))
+}
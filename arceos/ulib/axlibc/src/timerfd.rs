@@ -0,0 +1,34 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{sys_timerfd_create, sys_timerfd_gettime, sys_timerfd_settime};
+
+use crate::{ctypes, utils::e};
+
+/// Create a timer that notifies via a file descriptor
+///
+/// Return the new file descriptor if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timerfd_create(clockid: ctypes::clockid_t, flags: c_int) -> c_int {
+    e(sys_timerfd_create(clockid, flags))
+}
+
+/// Arm or disarm the timer of a file descriptor
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timerfd_settime(
+    fd: c_int,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timerfd_settime(fd, flags, new_value, old_value))
+}
+
+/// Get the time until the next expiration of the timer of a file descriptor
+///
+/// Return 0 if succeed
+#[no_mangle]
+pub unsafe extern "C" fn timerfd_gettime(fd: c_int, curr_value: *mut ctypes::itimerspec) -> c_int {
+    e(sys_timerfd_gettime(fd, curr_value))
+}
@@ -15,34 +15,52 @@
 //!     - `fp_simd`: Enable floating point and SIMD support.
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support.
+//!     - `irq-stats`: Record the durations and latencies of the IRQ handlers.
+//!     - `timer-broadcast`: Wake the idle CPUs by the timer of the primary CPU, for the boards
+//!       whose local timers stop in idle.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
 //!     - `alloc-tlsf`: Use the TLSF allocator.
 //!     - `alloc-slab`: Use the slab allocator.
 //!     - `alloc-buddy`: Use the buddy system allocator.
+//!     - `alloc-page-buddy`: Use the buddy page allocator, which coalesces the freed pages.
+//!     - `page-color`: Allocate the pages of each task from its cache colors, to partition the
+//!       last level cache.
+//!     - `page-scrub`: Zero the freed pages in the background, for the user and guest memory.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
-//!     - `sched_fifo`: Use the FIFO cooperative scheduler.
-//!     - `sched_rr`: Use the Round-robin preemptive scheduler.
-//!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_fifo`: Boot with the FIFO cooperative scheduler.
+//!     - `sched_rr`: Boot with the Round-robin preemptive scheduler.
+//!     - `sched_cfs`: Boot with the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_boost`: Run the tasks waking up from I/O waits first, for a lower latency.
+//!     - `sched_debug`: Check the invariants of the scheduler on every operation.
+//!     - `sched_profile`: Sample the profiled tasks on the timer ticks, for per-task flamegraphs.
+//!     - `irq-guard`: Report blocking in the IRQ handlers with a backtrace, for debugging.
+//!     - `nohz`: Allow isolating CPUs from the timer tick and the kernel work.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
 //!     - `net`: Enable networking support.
 //!     - `dns`: Enable DNS lookup support.
 //!     - `display`: Enable graphics support.
+//!     - `display-terminal`: Show the console output on the display.
+//!     - `sound`: Enable sound support.
+//!     - `entropy`: Seed the random numbers from the entropy pool.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
 //!     - `driver-ramdisk`: Use the RAM disk to emulate the block device.
 //!     - `driver-ixgbe`: Enable the Intel 82599 10Gbit NIC driver.
 //!     - `driver-bcm2835-sdhci`: Enable the BCM2835 SDHCI driver (Raspberry Pi SD card).
+//!     - `driver-sdhci`: Enable the driver of the standard SD host controllers.
 //! - Logging
 //!     - `log-level-off`: Disable all logging.
 //!     - `log-level-error`, `log-level-warn`, `log-level-info`, `log-level-debug`,
 //!       `log-level-trace`: Keep logging only at the specified level or higher.
+//!     - `log-ring`: Write the log records into a lock-free ring, printed by a flusher task.
+//!     - `pstore`: Keep the log in RAM across warm reboots, to read the log of the previous boot.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 
@@ -43,6 +43,18 @@ impl TcpStream {
     pub fn shutdown(&self) -> io::Result<()> {
         api::ax_tcp_shutdown(&self.0)
     }
+
+    /// Reads data straight out of the socket receive buffer, without copying.
+    ///
+    /// `f` is given the currently readable bytes and returns how many of them
+    /// it consumed. Returns the number of consumed bytes, or `None` at EOF.
+    ///
+    /// `f` runs with the sockets locked: it must not use any socket, this one
+    /// included, and should only parse or copy the bytes, leaving the work on
+    /// them until `recv_with` returns.
+    pub fn recv_with<F: FnMut(&[u8]) -> usize>(&mut self, mut f: F) -> io::Result<Option<usize>> {
+        api::ax_tcp_recv_with(&self.0, &mut f)
+    }
 }
 
 impl Read for TcpStream {
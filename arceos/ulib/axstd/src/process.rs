@@ -8,3 +8,22 @@
 pub fn exit(_exit_code: i32) -> ! {
     arceos_api::sys::ax_terminate();
 }
+
+/// Makes the current thread the foreground one, or no longer the foreground
+/// one if `catch` is false.
+///
+/// Ctrl-C on the console then requests the foreground thread to stop what it
+/// is doing, which it checks with [`interrupted`] to clean up and return. A
+/// second Ctrl-C before it does shuts down the whole system. Without a
+/// foreground thread, Ctrl-C is read from the standard input.
+#[cfg(feature = "multitask")]
+pub fn catch_interrupt(catch: bool) {
+    arceos_api::task::ax_set_current_foreground(catch);
+}
+
+/// Returns whether Ctrl-C was pressed for the current thread since the last
+/// call, see [`catch_interrupt`].
+#[cfg(feature = "multitask")]
+pub fn interrupted() -> bool {
+    arceos_api::task::ax_take_cancel_request()
+}